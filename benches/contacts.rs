@@ -0,0 +1,171 @@
+//! Benchmarks for building the contact list: grouping rows under their contacts, scoring each
+//! contact with `ContactResponse::new`, and serializing the response.
+//!
+//! Run with `cargo bench`; `cargo bench -- serialize` runs one group.
+
+use criterion::{BatchSize, BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use personal_crm::models::{
+    Contact, ContactDetails, ContactResponse, Interaction, Occasion, OccasionType, Tag,
+};
+use personal_crm::timezone::LocalDates;
+use std::hint::black_box;
+use time::{Date, Duration, Month, OffsetDateTime};
+
+const SIZES: [usize; 3] = [100, 1_000, 10_000];
+
+/// Interactions logged with each contact
+const INTERACTIONS_PER_CONTACT: usize = 6;
+
+/// Rows as the repository loads them for `n` contacts
+struct Rows {
+    contacts: Vec<Contact>,
+    tags: Vec<(i32, Tag)>,
+    interactions: Vec<Interaction>,
+    occasions: Vec<Occasion>,
+}
+
+fn rows(n: usize) -> Rows {
+    let now = OffsetDateTime::now_utc();
+    let tags: Vec<Tag> = ["Family", "Friends", "Work", "College"]
+        .iter()
+        .enumerate()
+        .map(|(i, name)| Tag {
+            tag_id: i as i32 + 1,
+            name: name.to_string(),
+            color: Some("#2563eb".to_string()),
+            details: None,
+        })
+        .collect();
+
+    let mut rows = Rows {
+        contacts: Vec::with_capacity(n),
+        tags: Vec::new(),
+        interactions: Vec::with_capacity(n * INTERACTIONS_PER_CONTACT),
+        occasions: Vec::new(),
+    };
+    for i in 0..n {
+        let contact_id = i as i32 + 1;
+        rows.contacts.push(Contact {
+            contact_id,
+            first_name: Some(format!("First{}", i)),
+            last_name: Some(format!("Last{}", i)),
+            email: Some(format!("contact{}@example.com", i)),
+            phone: (i % 2 == 0).then(|| "+1 555 010 0000".to_string()),
+            short_note: (i % 3 == 0).then(|| "Met at a conference".to_string()),
+            desired_frequency_days: (i % 4 == 0).then_some(30),
+            ..Default::default()
+        });
+        for t in 0..i % 3 {
+            rows.tags
+                .push((contact_id, tags[(i + t) % tags.len()].clone()));
+        }
+        for k in 0..INTERACTIONS_PER_CONTACT {
+            rows.interactions.push(Interaction {
+                interaction_id: (i * INTERACTIONS_PER_CONTACT + k) as i32 + 1,
+                contact_id: Some(contact_id),
+                interaction_date: now - Duration::days(((i * 7 + k * 37) % 730) as i64),
+                notes: Some("Caught up over coffee".to_string()),
+                follow_up_priority: None,
+                interaction_type: Some("call".to_string()),
+                // Every tenth interaction is a group one with the next contact
+                contact_ids: if k == 0 && i % 10 == 0 && i + 1 < n {
+                    vec![contact_id + 1]
+                } else {
+                    Vec::new()
+                },
+                tag_ids: Vec::new(),
+            });
+        }
+        if i % 3 != 2 {
+            let month = Month::try_from((i % 12) as u8 + 1).unwrap();
+            rows.occasions.push(Occasion {
+                occasion_id: contact_id,
+                contact_id: Some(contact_id),
+                name: "Birthday".to_string(),
+                date: Date::from_calendar_date(1990, month, (i % 28) as u8 + 1).unwrap(),
+                recurring: Some(true),
+                recurring_interval: None,
+                details: None,
+                occasion_type: OccasionType::Birthday,
+                birth_year: Some(1990),
+                remind_days_before: None,
+                upcoming_age: None,
+                interaction_id: None,
+            });
+        }
+    }
+    rows
+}
+
+fn details(n: usize) -> Vec<ContactDetails> {
+    let rows = rows(n);
+    ContactDetails::group(rows.contacts, rows.tags, rows.interactions, rows.occasions)
+}
+
+fn score(details: Vec<ContactDetails>, dates: &LocalDates) -> Vec<ContactResponse> {
+    details
+        .into_iter()
+        .map(|d| ContactResponse::new(d.contact, d.tags, d.interactions, d.occasions, dates))
+        .collect()
+}
+
+fn bench_group(c: &mut Criterion) {
+    let mut group = c.benchmark_group("contact_details_group");
+    for n in SIZES {
+        let rows = rows(n);
+        group.throughput(Throughput::Elements(n as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(n), &rows, |b, rows| {
+            b.iter_batched(
+                || {
+                    (
+                        rows.contacts.clone(),
+                        rows.tags.clone(),
+                        rows.interactions.clone(),
+                        rows.occasions.clone(),
+                    )
+                },
+                |(contacts, tags, interactions, occasions)| {
+                    ContactDetails::group(contacts, tags, interactions, occasions)
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn bench_score(c: &mut Criterion) {
+    // A named timezone so every interaction date goes through the conversion
+    let dates = LocalDates::new(Some("Europe/Berlin"));
+    let mut group = c.benchmark_group("contact_response_new");
+    for n in SIZES {
+        let details = details(n);
+        group.throughput(Throughput::Elements(n as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(n), &details, |b, details| {
+            b.iter_batched(
+                || details.clone(),
+                |details| score(details, &dates),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn bench_serialize(c: &mut Criterion) {
+    let dates = LocalDates::utc();
+    let mut group = c.benchmark_group("serialize_contacts");
+    for n in SIZES {
+        let response = score(details(n), &dates);
+        // Reported as bytes per second, so the response size shows up as throughput
+        let size = serde_json::to_vec(&response).unwrap().len();
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(n), &response, |b, response| {
+            b.iter(|| serde_json::to_vec(black_box(response)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_group, bench_score, bench_serialize);
+criterion_main!(benches);
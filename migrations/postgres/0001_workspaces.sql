@@ -0,0 +1,63 @@
+-- Moves a database from before workspaces onto them. Every user gets a default "Personal"
+-- workspace and their contacts, tags, interactions and occasions move into it, then contact
+-- emails and tag names become unique per workspace rather than across the database. Each step
+-- checks what is already there, so databases created with workspaces are left as they are.
+
+DO $$
+DECLARE
+    t TEXT;
+BEGIN
+    IF NOT EXISTS (
+        SELECT 1 FROM information_schema.columns
+        WHERE table_schema = current_schema() AND table_name = 'contacts' AND column_name = 'user_id'
+    ) THEN
+        RETURN;
+    END IF;
+
+    CREATE TABLE IF NOT EXISTS workspaces (
+        workspace_id SERIAL PRIMARY KEY,
+        user_id INT NOT NULL,
+        FOREIGN KEY (user_id) REFERENCES users(user_id) ON DELETE CASCADE,
+        name VARCHAR(100) NOT NULL,
+        is_default BOOLEAN NOT NULL DEFAULT FALSE,
+        UNIQUE (user_id, name),
+        created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+        updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+    );
+    CREATE UNIQUE INDEX IF NOT EXISTS idx_workspaces_default ON workspaces(user_id) WHERE is_default;
+
+    INSERT INTO workspaces (user_id, name, is_default)
+    SELECT u.user_id, 'Personal', TRUE FROM users u
+    WHERE NOT EXISTS (SELECT 1 FROM workspaces w WHERE w.user_id = u.user_id AND w.is_default);
+
+    FOREACH t IN ARRAY ARRAY['contacts', 'tags', 'interactions', 'occasions'] LOOP
+        IF EXISTS (
+            SELECT 1 FROM information_schema.columns
+            WHERE table_schema = current_schema() AND table_name = t AND column_name = 'user_id'
+        ) THEN
+            EXECUTE format(
+                'ALTER TABLE %I ADD COLUMN IF NOT EXISTS workspace_id INT
+                    REFERENCES workspaces(workspace_id) ON DELETE CASCADE', t);
+            EXECUTE format(
+                'UPDATE %I t SET workspace_id = w.workspace_id FROM workspaces w
+                 WHERE w.user_id = t.user_id AND w.is_default', t);
+            EXECUTE format(
+                'ALTER TABLE %I ALTER COLUMN workspace_id SET NOT NULL, DROP COLUMN user_id', t);
+        END IF;
+    END LOOP;
+END $$;
+
+DO $$
+BEGIN
+    IF EXISTS (SELECT 1 FROM pg_constraint
+               WHERE conname = 'contacts_email_key' AND conrelid = to_regclass('contacts')) THEN
+        ALTER TABLE contacts DROP CONSTRAINT contacts_email_key;
+        ALTER TABLE contacts ADD CONSTRAINT contacts_workspace_id_email_key
+            UNIQUE (workspace_id, email);
+    END IF;
+    IF EXISTS (SELECT 1 FROM pg_constraint
+               WHERE conname = 'tags_name_key' AND conrelid = to_regclass('tags')) THEN
+        ALTER TABLE tags DROP CONSTRAINT tags_name_key;
+        ALTER TABLE tags ADD CONSTRAINT tags_workspace_id_name_key UNIQUE (workspace_id, name);
+    END IF;
+END $$;
@@ -0,0 +1,26 @@
+-- Interaction dates and export push times were stored without a time zone; they were written in
+-- UTC, so that is what they are read as. Users get a time zone to show dates in. Columns that
+-- already have their new type are left as they are.
+
+DO $$
+BEGIN
+    IF EXISTS (
+        SELECT 1 FROM information_schema.columns
+        WHERE table_schema = current_schema() AND table_name = 'interactions'
+            AND column_name = 'interaction_date' AND data_type = 'timestamp without time zone'
+    ) THEN
+        ALTER TABLE interactions ALTER COLUMN interaction_date TYPE TIMESTAMPTZ
+            USING interaction_date AT TIME ZONE 'UTC';
+    END IF;
+    IF EXISTS (
+        SELECT 1 FROM information_schema.columns
+        WHERE table_schema = current_schema() AND table_name = 'export_schedules'
+            AND column_name = 'last_pushed_at' AND data_type = 'timestamp without time zone'
+    ) THEN
+        ALTER TABLE export_schedules ALTER COLUMN last_pushed_at TYPE TIMESTAMPTZ
+            USING last_pushed_at AT TIME ZONE 'UTC';
+    END IF;
+    IF to_regclass('user_preferences') IS NOT NULL THEN
+        ALTER TABLE user_preferences ADD COLUMN IF NOT EXISTS timezone VARCHAR(64);
+    END IF;
+END $$;
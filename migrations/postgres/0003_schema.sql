@@ -0,0 +1,916 @@
+-- The whole schema. New databases get every table from here; databases created before a
+-- column was added get it from the ALTER TABLE after its table. Databases set up from
+-- schema.sql before there were migrations have no record of what they already have, so every
+-- statement must be safe to run on a database that has it.
+
+CREATE TABLE IF NOT EXISTS users (
+    user_id SERIAL PRIMARY KEY,
+    auth0_id VARCHAR(100) UNIQUE NOT NULL,
+    name VARCHAR(100) NOT NULL,
+    email VARCHAR(100) UNIQUE NOT NULL,
+    -- Admins can list and deactivate users; see also ADMIN_AUTH0_IDS
+    is_admin BOOLEAN NOT NULL DEFAULT FALSE,
+    -- Deactivated users are refused on every request until reactivated
+    deactivated_at TIMESTAMP,
+    -- Set by DELETE /account, which also deactivates the user; the user and everything they own
+    -- are deleted once it passes, unless restored first
+    delete_after TIMESTAMPTZ,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP 
+);
+
+ALTER TABLE users
+    ADD COLUMN IF NOT EXISTS is_admin BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN IF NOT EXISTS deactivated_at TIMESTAMP,
+    ADD COLUMN IF NOT EXISTS delete_after TIMESTAMPTZ;
+
+-- A user's separate address books, such as "Personal" and "Work". Contacts, tags, interaction
+-- types, saved filters and everything hanging off them belong to exactly one workspace. Every
+-- user has one default workspace, created with the user.
+CREATE TABLE IF NOT EXISTS workspaces (
+    workspace_id SERIAL PRIMARY KEY,
+    user_id INT NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users(user_id) ON DELETE CASCADE,
+    name VARCHAR(100) NOT NULL,
+    is_default BOOLEAN NOT NULL DEFAULT FALSE,
+    UNIQUE (user_id, name),
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_workspaces_default ON workspaces(user_id) WHERE is_default;
+
+CREATE TABLE IF NOT EXISTS contacts (
+    contact_id SERIAL PRIMARY KEY,
+    workspace_id INT NOT NULL,
+    FOREIGN KEY (workspace_id) REFERENCES workspaces(workspace_id) ON DELETE CASCADE,
+    first_name VARCHAR(50),
+    last_name VARCHAR(50),
+    email VARCHAR(100),
+    UNIQUE (workspace_id, email),
+    phone VARCHAR(20),
+    -- phone in E.164, set by the application when it parses as a valid number
+    phone_e164 VARCHAR(16),
+    -- Up to 255 characters, checked by the API. Both notes are stored encrypted when
+    -- NOTES_ENCRYPTION_KEY is set.
+    short_note TEXT,
+    notes TEXT,
+    avatar_url TEXT,
+    -- Archived contacts keep their history but drop out of lists and suggestions
+    archived BOOLEAN NOT NULL DEFAULT FALSE,
+    -- How often the user wants to be in touch, overriding the cadence inferred from history
+    desired_frequency_days INT CHECK (desired_frequency_days > 0),
+    -- How the user met the contact: {"date": "YYYY-MM-DD", "place": ..., "context": ...}
+    met_at JSONB CHECK (jsonb_typeof(met_at) = 'object'),
+    introduced_by_contact_id INT,
+    FOREIGN KEY (introduced_by_contact_id) REFERENCES contacts(contact_id) ON DELETE SET NULL,
+    -- Where the contact lives, and its coordinates when a geocoder found them
+    location VARCHAR(255),
+    latitude DOUBLE PRECISION CHECK (latitude BETWEEN -90 AND 90),
+    longitude DOUBLE PRECISION CHECK (longitude BETWEEN -180 AND 180),
+    CHECK ((latitude IS NULL) = (longitude IS NULL)),
+    -- When the latest interaction the contact took part in happened, kept by the
+    -- refresh_last_interaction triggers for the contact list's summaries
+    last_interaction_at TIMESTAMPTZ,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP 
+);
+
+ALTER TABLE contacts
+    ALTER COLUMN short_note TYPE TEXT,
+    ADD COLUMN IF NOT EXISTS phone_e164 VARCHAR(16),
+    ADD COLUMN IF NOT EXISTS avatar_url TEXT,
+    ADD COLUMN IF NOT EXISTS archived BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN IF NOT EXISTS desired_frequency_days INT CHECK (desired_frequency_days > 0),
+    ADD COLUMN IF NOT EXISTS met_at JSONB CHECK (jsonb_typeof(met_at) = 'object'),
+    ADD COLUMN IF NOT EXISTS introduced_by_contact_id INT
+        REFERENCES contacts(contact_id) ON DELETE SET NULL,
+    ADD COLUMN IF NOT EXISTS location VARCHAR(255),
+    ADD COLUMN IF NOT EXISTS latitude DOUBLE PRECISION CHECK (latitude BETWEEN -90 AND 90),
+    ADD COLUMN IF NOT EXISTS longitude DOUBLE PRECISION CHECK (longitude BETWEEN -180 AND 180),
+    ADD COLUMN IF NOT EXISTS last_interaction_at TIMESTAMPTZ;
+
+DO $$ BEGIN
+    ALTER TABLE contacts ADD CONSTRAINT contacts_check
+        CHECK ((latitude IS NULL) = (longitude IS NULL));
+EXCEPTION WHEN duplicate_object THEN NULL;
+END $$;
+
+CREATE INDEX IF NOT EXISTS idx_contacts_phone_e164 ON contacts(workspace_id, phone_e164);
+-- Who each contact introduced, for /contacts/{id}/introductions
+CREATE INDEX IF NOT EXISTS idx_contacts_introduced_by ON contacts(introduced_by_contact_id);
+-- Bounding box searches for /contacts/nearby
+CREATE INDEX IF NOT EXISTS idx_contacts_coordinates
+    ON contacts(workspace_id, latitude, longitude) WHERE latitude IS NOT NULL;
+-- Name prefix searches for /contacts/autocomplete
+CREATE INDEX IF NOT EXISTS idx_contacts_first_name_prefix
+    ON contacts(workspace_id, lower(first_name) text_pattern_ops);
+CREATE INDEX IF NOT EXISTS idx_contacts_last_name_prefix
+    ON contacts(workspace_id, lower(last_name) text_pattern_ops);
+-- Full-text search of notes for /interactions/search
+CREATE INDEX IF NOT EXISTS idx_contacts_notes_search
+    ON contacts USING GIN (to_tsvector('english', COALESCE(notes, '')));
+
+-- Edits to a contact's fields, one row per field changed, written by the application when it
+-- updates or archives the contact. Values are stored as text; NULL when the field was empty.
+CREATE TABLE IF NOT EXISTS contact_changes (
+    change_id SERIAL PRIMARY KEY,
+    contact_id INT NOT NULL,
+    FOREIGN KEY (contact_id) REFERENCES contacts(contact_id) ON DELETE CASCADE,
+    field VARCHAR(50) NOT NULL,
+    old_value TEXT,
+    new_value TEXT,
+    changed_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- A contact's history, paged newest first by (changed_at, change_id)
+CREATE INDEX IF NOT EXISTS idx_contact_changes_history
+    ON contact_changes(contact_id, changed_at DESC, change_id DESC);
+
+CREATE TABLE IF NOT EXISTS tags (
+    tag_id SERIAL PRIMARY KEY,
+    workspace_id INT NOT NULL,
+    FOREIGN KEY (workspace_id) REFERENCES workspaces(workspace_id) ON DELETE CASCADE,
+    name VARCHAR(50) NOT NULL,
+    UNIQUE (workspace_id, name),
+    details TEXT,
+    color VARCHAR(20),
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP 
+);
+
+CREATE TABLE IF NOT EXISTS contact_tags (
+    contact_id INT NOT NULL,
+    tag_id INT NOT NULL,
+    PRIMARY KEY (contact_id, tag_id),
+    FOREIGN KEY (contact_id) REFERENCES contacts(contact_id) ON DELETE CASCADE,
+    FOREIGN KEY (tag_id) REFERENCES tags(tag_id) ON DELETE CASCADE
+);
+
+DO $$ BEGIN
+    CREATE TYPE interaction_kind AS ENUM ('call', 'email', 'meeting', 'message');
+EXCEPTION WHEN duplicate_object THEN NULL;
+END $$;
+
+-- Interaction types a user added on top of the built-in kinds
+CREATE TABLE IF NOT EXISTS interaction_types (
+    type_id SERIAL PRIMARY KEY,
+    workspace_id INT NOT NULL,
+    FOREIGN KEY (workspace_id) REFERENCES workspaces(workspace_id) ON DELETE CASCADE,
+    name VARCHAR(50) NOT NULL,
+    UNIQUE (workspace_id, name),
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+-- An interaction has a built-in kind, a custom type, or neither. Deleting a custom type
+-- leaves its interactions untyped. contact_id is NULL when the contact was deleted and the
+-- interaction kept, unassigned (CONTACT_DELETE_BEHAVIOR=orphan); deletes cascade otherwise.
+CREATE TABLE IF NOT EXISTS interactions (
+    interaction_id SERIAL PRIMARY KEY,
+    workspace_id INT NOT NULL,
+    contact_id INT,
+    FOREIGN KEY (workspace_id) REFERENCES workspaces(workspace_id) ON DELETE CASCADE,
+    FOREIGN KEY (contact_id) REFERENCES contacts(contact_id) ON DELETE CASCADE,
+    interaction_date TIMESTAMPTZ NOT NULL,
+    notes TEXT,
+    followup_priority INT,
+    interaction_type interaction_kind,
+    custom_type_id INT,
+    FOREIGN KEY (custom_type_id) REFERENCES interaction_types(type_id) ON DELETE SET NULL,
+    CHECK (interaction_type IS NULL OR custom_type_id IS NULL),
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP 
+);
+
+ALTER TABLE interactions
+    ALTER COLUMN contact_id DROP NOT NULL,
+    ADD COLUMN IF NOT EXISTS interaction_type interaction_kind,
+    ADD COLUMN IF NOT EXISTS custom_type_id INT
+        REFERENCES interaction_types(type_id) ON DELETE SET NULL;
+
+DO $$ BEGIN
+    ALTER TABLE interactions ADD CONSTRAINT interactions_check
+        CHECK (interaction_type IS NULL OR custom_type_id IS NULL);
+EXCEPTION WHEN duplicate_object THEN NULL;
+END $$;
+
+-- A contact's timeline, paged newest first by (interaction_date, interaction_id)
+CREATE INDEX IF NOT EXISTS idx_interactions_timeline
+    ON interactions(contact_id, interaction_date DESC, interaction_id DESC);
+-- Full-text search of notes for /interactions/search
+CREATE INDEX IF NOT EXISTS idx_interactions_notes_search
+    ON interactions USING GIN (to_tsvector('english', COALESCE(notes, '')));
+
+-- Contacts who took part in an interaction besides its own contact_id
+CREATE TABLE IF NOT EXISTS interaction_participants (
+    interaction_id INT NOT NULL,
+    contact_id INT NOT NULL,
+    PRIMARY KEY (interaction_id, contact_id),
+    FOREIGN KEY (interaction_id) REFERENCES interactions(interaction_id) ON DELETE CASCADE,
+    FOREIGN KEY (contact_id) REFERENCES contacts(contact_id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_interaction_participants_contact
+    ON interaction_participants(contact_id);
+
+-- Tags on interactions, from the same tags as contacts carry
+CREATE TABLE IF NOT EXISTS interaction_tags (
+    interaction_id INT NOT NULL,
+    tag_id INT NOT NULL,
+    PRIMARY KEY (interaction_id, tag_id),
+    FOREIGN KEY (interaction_id) REFERENCES interactions(interaction_id) ON DELETE CASCADE,
+    FOREIGN KEY (tag_id) REFERENCES tags(tag_id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_interaction_tags_tag ON interaction_tags(tag_id);
+
+-- Where an attachment's transcription stands; attachments not sent for transcription have none
+DO $$ BEGIN
+    CREATE TYPE transcription_status AS ENUM ('pending', 'done', 'failed');
+EXCEPTION WHEN duplicate_object THEN NULL;
+END $$;
+
+-- Files attached to interactions, such as photos from a meetup or a voice memo. Audio sent
+-- for transcription stays pending until the transcription job appends its transcript to the
+-- interaction's notes; see `attachments`
+CREATE TABLE IF NOT EXISTS interaction_attachments (
+    attachment_id SERIAL PRIMARY KEY,
+    workspace_id INT NOT NULL,
+    interaction_id INT NOT NULL,
+    FOREIGN KEY (workspace_id) REFERENCES workspaces(workspace_id) ON DELETE CASCADE,
+    FOREIGN KEY (interaction_id) REFERENCES interactions(interaction_id) ON DELETE CASCADE,
+    file_name VARCHAR(255) NOT NULL,
+    content_type VARCHAR(100) NOT NULL,
+    size_bytes INT NOT NULL,
+    data BYTEA NOT NULL,
+    transcription transcription_status,
+    transcription_attempts INT NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_interaction_attachments_interaction
+    ON interaction_attachments(interaction_id);
+CREATE INDEX IF NOT EXISTS idx_interaction_attachments_pending
+    ON interaction_attachments(attachment_id) WHERE transcription = 'pending';
+
+DO $$ BEGIN
+    CREATE TYPE occasion_kind AS ENUM ('birthday', 'anniversary', 'custom');
+EXCEPTION WHEN duplicate_object THEN NULL;
+END $$;
+
+-- Birthdays repeat every year whatever `recurring` says; birth_year is only set on birthdays.
+-- Like interactions, occasions are kept unassigned, with a NULL contact_id, when their contact
+-- is deleted with CONTACT_DELETE_BEHAVIOR=orphan.
+CREATE TABLE IF NOT EXISTS occasions (
+    occasion_id SERIAL PRIMARY KEY,
+    workspace_id INT NOT NULL,
+    contact_id INT,
+    FOREIGN KEY (workspace_id) REFERENCES workspaces(workspace_id) ON DELETE CASCADE,
+    FOREIGN KEY (contact_id) REFERENCES contacts(contact_id) ON DELETE CASCADE,
+    name VARCHAR(100) NOT NULL,
+    date DATE NOT NULL,
+    recurring BOOLEAN DEFAULT FALSE,
+    recurring_interval INT,
+    details TEXT,
+    occasion_type occasion_kind NOT NULL DEFAULT 'custom',
+    birth_year INT,
+    CHECK (birth_year IS NULL OR occasion_type = 'birthday'),
+    -- Days ahead of the occasion to remind, e.g. {14,3,0}; NULL uses the user's default
+    remind_days_before INT[],
+    -- Set on follow-ups created together with an interaction, which take them along when deleted
+    interaction_id INT,
+    FOREIGN KEY (interaction_id) REFERENCES interactions(interaction_id) ON DELETE CASCADE,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP 
+);
+
+ALTER TABLE occasions
+    ALTER COLUMN contact_id DROP NOT NULL,
+    ADD COLUMN IF NOT EXISTS occasion_type occasion_kind NOT NULL DEFAULT 'custom',
+    ADD COLUMN IF NOT EXISTS birth_year INT,
+    ADD COLUMN IF NOT EXISTS remind_days_before INT[],
+    ADD COLUMN IF NOT EXISTS interaction_id INT
+        REFERENCES interactions(interaction_id) ON DELETE CASCADE;
+
+DO $$ BEGIN
+    ALTER TABLE occasions ADD CONSTRAINT occasions_check
+        CHECK (birth_year IS NULL OR occasion_type = 'birthday');
+EXCEPTION WHEN duplicate_object THEN NULL;
+END $$;
+
+DO $$ BEGIN
+    CREATE TYPE social_platform AS ENUM (
+        'linkedin', 'twitter', 'github', 'instagram', 'facebook', 'mastodon', 'website', 'other'
+    );
+EXCEPTION WHEN duplicate_object THEN NULL;
+END $$;
+
+-- A contact's profiles on other sites, such as LinkedIn or GitHub
+CREATE TABLE IF NOT EXISTS social_profiles (
+    social_profile_id SERIAL PRIMARY KEY,
+    workspace_id INT NOT NULL,
+    contact_id INT NOT NULL,
+    FOREIGN KEY (workspace_id) REFERENCES workspaces(workspace_id) ON DELETE CASCADE,
+    FOREIGN KEY (contact_id) REFERENCES contacts(contact_id) ON DELETE CASCADE,
+    platform social_platform NOT NULL,
+    url TEXT NOT NULL,
+    UNIQUE (contact_id, url),
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+DO $$ BEGIN
+    CREATE TYPE gift_status AS ENUM ('idea', 'purchased', 'given');
+EXCEPTION WHEN duplicate_object THEN NULL;
+END $$;
+
+-- Gifts thought of for a contact, optionally for one of their occasions
+CREATE TABLE IF NOT EXISTS gift_ideas (
+    gift_idea_id SERIAL PRIMARY KEY,
+    workspace_id INT NOT NULL,
+    contact_id INT NOT NULL,
+    FOREIGN KEY (workspace_id) REFERENCES workspaces(workspace_id) ON DELETE CASCADE,
+    FOREIGN KEY (contact_id) REFERENCES contacts(contact_id) ON DELETE CASCADE,
+    occasion_id INT,
+    FOREIGN KEY (occasion_id) REFERENCES occasions(occasion_id) ON DELETE SET NULL,
+    idea TEXT NOT NULL,
+    status gift_status NOT NULL DEFAULT 'idea',
+    price_cents INT CHECK (price_cents >= 0),
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_gift_ideas_contact ON gift_ideas(contact_id);
+
+-- Named rosters of contacts, kept in the order the user gives them
+CREATE TABLE IF NOT EXISTS contact_groups (
+    group_id SERIAL PRIMARY KEY,
+    workspace_id INT NOT NULL,
+    FOREIGN KEY (workspace_id) REFERENCES workspaces(workspace_id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    description TEXT,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS contact_group_members (
+    group_id INT NOT NULL,
+    contact_id INT NOT NULL,
+    FOREIGN KEY (group_id) REFERENCES contact_groups(group_id) ON DELETE CASCADE,
+    FOREIGN KEY (contact_id) REFERENCES contacts(contact_id) ON DELETE CASCADE,
+    -- Members are listed by position, ties by contact_id
+    position INT NOT NULL,
+    PRIMARY KEY (group_id, contact_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_contact_group_members_contact ON contact_group_members(contact_id);
+
+-- Contacts suggested by the weekly reconnect rotation, keyed by the Monday of the week
+CREATE TABLE IF NOT EXISTS reconnect_picks (
+    workspace_id INT NOT NULL,
+    contact_id INT NOT NULL,
+    week DATE NOT NULL,
+    PRIMARY KEY (workspace_id, week, contact_id),
+    FOREIGN KEY (workspace_id) REFERENCES workspaces(workspace_id) ON DELETE CASCADE,
+    FOREIGN KEY (contact_id) REFERENCES contacts(contact_id) ON DELETE CASCADE
+);
+
+-- When avatar enrichment may next look a contact's email up, after finding nothing or failing
+CREATE TABLE IF NOT EXISTS avatar_lookups (
+    contact_id INT PRIMARY KEY,
+    workspace_id INT NOT NULL,
+    next_lookup_at TIMESTAMPTZ NOT NULL,
+    FOREIGN KEY (workspace_id) REFERENCES workspaces(workspace_id) ON DELETE CASCADE,
+    FOREIGN KEY (contact_id) REFERENCES contacts(contact_id) ON DELETE CASCADE
+);
+
+-- Contacts a user took out of the suggestions: snoozed until a date, or dismissed until the next
+-- interaction with them when snoozed_until is NULL
+CREATE TABLE IF NOT EXISTS suggestion_snoozes (
+    user_id INT NOT NULL,
+    contact_id INT NOT NULL,
+    snoozed_until DATE,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (user_id, contact_id),
+    FOREIGN KEY (user_id) REFERENCES users(user_id) ON DELETE CASCADE,
+    FOREIGN KEY (contact_id) REFERENCES contacts(contact_id) ON DELETE CASCADE
+);
+
+-- Reminder settings: holiday calendar, weekdays (1 = Monday) without reminders, the tags
+-- that get holiday greetings, and the days ahead of occasions to remind when they don't say.
+-- Deleted tags are ignored when the preferences are read.
+CREATE TABLE IF NOT EXISTS user_preferences (
+    user_id INT PRIMARY KEY,
+    FOREIGN KEY (user_id) REFERENCES users(user_id) ON DELETE CASCADE,
+    country VARCHAR(2),
+    quiet_weekdays INT[] NOT NULL DEFAULT '{}',
+    greeting_tag_ids INT[] NOT NULL DEFAULT '{}',
+    remind_days_before INT[] NOT NULL DEFAULT '{0}',
+    timezone VARCHAR(64),
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Account-wide settings shown by /me. The timezone lives in user_preferences.
+CREATE TABLE IF NOT EXISTS user_settings (
+    user_id INT PRIMARY KEY,
+    FOREIGN KEY (user_id) REFERENCES users(user_id) ON DELETE CASCADE,
+    email_notifications BOOLEAN NOT NULL DEFAULT TRUE,
+    push_notifications BOOLEAN NOT NULL DEFAULT FALSE,
+    -- Opt-in weekly review email, and the Monday of the week it was last sent in
+    weekly_review_email BOOLEAN NOT NULL DEFAULT FALSE,
+    weekly_review_sent_on DATE,
+    -- The user's day that due reminders were last pushed for
+    push_reminders_sent_on DATE,
+    default_reminder_cadence_days INT NOT NULL DEFAULT 30,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Scheduled pushes of a workspace's export archive to a webhook, signed with `secret`
+CREATE TABLE IF NOT EXISTS export_schedules (
+    workspace_id INT PRIMARY KEY,
+    FOREIGN KEY (workspace_id) REFERENCES workspaces(workspace_id) ON DELETE CASCADE,
+    url TEXT NOT NULL,
+    interval_hours INT NOT NULL,
+    secret VARCHAR(64) NOT NULL,
+    last_pushed_at TIMESTAMPTZ,
+    last_status TEXT,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Each workspace's secret address for logging emails: mail sent or BCC'd to
+-- `<token>@INBOUND_EMAIL_DOMAIN` reaches /webhooks/email through the mail provider and is logged
+-- as an interaction with the workspace's contacts among its sender and recipients
+CREATE TABLE IF NOT EXISTS inbound_email_addresses (
+    workspace_id INT PRIMARY KEY,
+    FOREIGN KEY (workspace_id) REFERENCES workspaces(workspace_id) ON DELETE CASCADE,
+    token VARCHAR(64) UNIQUE NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+DO $$ BEGIN
+    CREATE TYPE hook_event AS ENUM ('new_contact', 'new_interaction', 'upcoming_occasion');
+EXCEPTION WHEN duplicate_object THEN NULL;
+END $$;
+
+-- URLs subscribed to a workspace's events, as Zapier's REST hooks do
+CREATE TABLE IF NOT EXISTS rest_hooks (
+    hook_id SERIAL PRIMARY KEY,
+    workspace_id INT NOT NULL,
+    FOREIGN KEY (workspace_id) REFERENCES workspaces(workspace_id) ON DELETE CASCADE,
+    event hook_event NOT NULL,
+    target_url TEXT NOT NULL,
+    -- The user's day upcoming occasions were last sent for
+    delivered_on DATE,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_rest_hooks_workspace_id ON rest_hooks(workspace_id);
+
+-- Unauthenticated links to a read-only card of one contact, until they expire or are revoked
+CREATE TABLE IF NOT EXISTS contact_links (
+    link_id SERIAL PRIMARY KEY,
+    workspace_id INT NOT NULL,
+    FOREIGN KEY (workspace_id) REFERENCES workspaces(workspace_id) ON DELETE CASCADE,
+    contact_id INT NOT NULL,
+    FOREIGN KEY (contact_id) REFERENCES contacts(contact_id) ON DELETE CASCADE,
+    token VARCHAR(64) UNIQUE NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_contact_links_contact_id ON contact_links(contact_id);
+
+-- Free text captured on the go, until it is turned into an interaction or discarded
+CREATE TABLE IF NOT EXISTS inbox_captures (
+    capture_id SERIAL PRIMARY KEY,
+    workspace_id INT NOT NULL,
+    FOREIGN KEY (workspace_id) REFERENCES workspaces(workspace_id) ON DELETE CASCADE,
+    text TEXT NOT NULL,
+    captured_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_inbox_captures_workspace_id ON inbox_captures(workspace_id);
+
+-- Each contact's predicted priority, refreshed in the background. A score only stands while the
+-- contact's updated_at (moved by any interaction, participant or occasion change), the user's
+-- timezone and their local date are still the ones it was computed with; readers fall back to
+-- contact_priority() otherwise.
+CREATE TABLE IF NOT EXISTS contact_scores (
+    contact_id INT PRIMARY KEY,
+    FOREIGN KEY (contact_id) REFERENCES contacts(contact_id) ON DELETE CASCADE,
+    priority DOUBLE PRECISION,
+    computed_on DATE NOT NULL,
+    timezone TEXT NOT NULL,
+    contact_updated_at TIMESTAMP
+);
+
+-- Named contact queries ("haven't talked in 3 months"), evaluated when read. `query` holds
+-- the filter conditions as JSON.
+CREATE TABLE IF NOT EXISTS saved_filters (
+    filter_id SERIAL PRIMARY KEY,
+    workspace_id INT NOT NULL,
+    FOREIGN KEY (workspace_id) REFERENCES workspaces(workspace_id) ON DELETE CASCADE,
+    name VARCHAR(100) NOT NULL,
+    query JSONB NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+DO $$ BEGIN
+    CREATE TYPE goal_period AS ENUM ('week', 'month');
+EXCEPTION WHEN duplicate_object THEN NULL;
+END $$;
+
+-- How many interactions the user wants each week or month, overall or with one contact
+CREATE TABLE IF NOT EXISTS goals (
+    goal_id SERIAL PRIMARY KEY,
+    workspace_id INT NOT NULL,
+    FOREIGN KEY (workspace_id) REFERENCES workspaces(workspace_id) ON DELETE CASCADE,
+    name VARCHAR(100) NOT NULL,
+    -- Counts every interaction when NULL
+    contact_id INT,
+    FOREIGN KEY (contact_id) REFERENCES contacts(contact_id) ON DELETE CASCADE,
+    -- A built-in or custom type name; counts every type when NULL
+    interaction_type VARCHAR(50),
+    target_count INT NOT NULL CHECK (target_count > 0),
+    period goal_period NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+DO $$ BEGIN
+    CREATE TYPE share_permission AS ENUM ('read', 'write');
+EXCEPTION WHEN duplicate_object THEN NULL;
+END $$;
+
+-- Contacts a user shares with another user: every contact carrying `tag_id`, or the single
+-- contact `contact_id`. The grantee can read them, and with 'write' also edit them and log
+-- interactions and occasions, which are stored under the owner.
+CREATE TABLE IF NOT EXISTS shares (
+    share_id SERIAL PRIMARY KEY,
+    owner_id INT NOT NULL,
+    FOREIGN KEY (owner_id) REFERENCES users(user_id) ON DELETE CASCADE,
+    grantee_id INT NOT NULL,
+    FOREIGN KEY (grantee_id) REFERENCES users(user_id) ON DELETE CASCADE,
+    tag_id INT,
+    FOREIGN KEY (tag_id) REFERENCES tags(tag_id) ON DELETE CASCADE,
+    contact_id INT,
+    FOREIGN KEY (contact_id) REFERENCES contacts(contact_id) ON DELETE CASCADE,
+    permission share_permission NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    CHECK ((tag_id IS NULL) <> (contact_id IS NULL)),
+    CHECK (owner_id <> grantee_id),
+    UNIQUE NULLS NOT DISTINCT (owner_id, grantee_id, tag_id, contact_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_shares_grantee ON shares(grantee_id);
+
+-- Create requests sent with an Idempotency-Key header, so a retry gets the first response back
+-- instead of creating a duplicate. `fingerprint` hashes the request the key was first used
+-- with; `status` is NULL while that request is still running. Keys expire after 24 hours.
+CREATE TABLE IF NOT EXISTS idempotency_keys (
+    user_id INT NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users(user_id) ON DELETE CASCADE,
+    idempotency_key VARCHAR(255) NOT NULL,
+    fingerprint VARCHAR(64) NOT NULL,
+    status SMALLINT,
+    content_type TEXT,
+    location TEXT,
+    body BYTEA,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (user_id, idempotency_key)
+);
+
+-- Which replica runs each background job until when, so that only one of them runs it per
+-- interval; see `jobs`
+CREATE TABLE IF NOT EXISTS job_leases (
+    name TEXT PRIMARY KEY,
+    holder TEXT NOT NULL,
+    locked_until TIMESTAMPTZ NOT NULL
+);
+
+-- Browsers and devices subscribed to a user's Web Push notifications, with the keys their
+-- messages are encrypted to; see `push`
+CREATE TABLE IF NOT EXISTS push_subscriptions (
+    subscription_id SERIAL PRIMARY KEY,
+    user_id INT NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users(user_id) ON DELETE CASCADE,
+    endpoint TEXT NOT NULL,
+    p256dh TEXT NOT NULL,
+    auth TEXT NOT NULL,
+    device_name VARCHAR(100),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (user_id, endpoint)
+);
+
+DO $$ BEGIN
+    CREATE TYPE delivery_status AS ENUM ('pending', 'delivered', 'failed');
+EXCEPTION WHEN duplicate_object THEN NULL;
+END $$;
+
+-- Webhooks and emails owed, written in the same transaction as the change that owes them and
+-- sent by the outbox dispatcher, with retries; see `outbox`. Emails have no hook_id, and push
+-- notifications have a push_subscription_id instead.
+CREATE TABLE IF NOT EXISTS outbox (
+    delivery_id SERIAL PRIMARY KEY,
+    workspace_id INT NOT NULL,
+    FOREIGN KEY (workspace_id) REFERENCES workspaces(workspace_id) ON DELETE CASCADE,
+    hook_id INT,
+    FOREIGN KEY (hook_id) REFERENCES rest_hooks(hook_id) ON DELETE CASCADE,
+    push_subscription_id INT,
+    FOREIGN KEY (push_subscription_id) REFERENCES push_subscriptions(subscription_id) ON DELETE CASCADE,
+    -- New contacts or interactions, rendered when sent
+    entity_ids INT[] NOT NULL DEFAULT '{}',
+    -- Rendered upcoming occasion items, the email or the push notification
+    payload JSONB,
+    status delivery_status NOT NULL DEFAULT 'pending',
+    attempts INT NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    last_response_status INT,
+    last_error TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    finished_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_outbox_pending ON outbox(next_attempt_at) WHERE status = 'pending';
+CREATE INDEX IF NOT EXISTS idx_outbox_hook_id ON outbox(hook_id);
+CREATE INDEX IF NOT EXISTS idx_outbox_push_subscription_id ON outbox(push_subscription_id);
+
+CREATE OR REPLACE FUNCTION update_updated_at_column()
+RETURNS TRIGGER AS $$
+BEGIN
+    NEW.updated_at = CURRENT_TIMESTAMP;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE TRIGGER update_users_updated_at
+    BEFORE UPDATE ON users
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
+
+CREATE OR REPLACE TRIGGER update_workspaces_updated_at
+    BEFORE UPDATE ON workspaces
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
+
+CREATE OR REPLACE TRIGGER update_contacts_updated_at
+    BEFORE UPDATE ON contacts
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
+
+CREATE OR REPLACE TRIGGER update_tags_updated_at
+    BEFORE UPDATE ON tags
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
+
+CREATE OR REPLACE TRIGGER update_interaction_types_updated_at
+    BEFORE UPDATE ON interaction_types
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
+
+CREATE OR REPLACE TRIGGER update_interactions_updated_at
+    BEFORE UPDATE ON interactions
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
+
+CREATE OR REPLACE TRIGGER update_occasions_updated_at
+    BEFORE UPDATE ON occasions
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
+
+CREATE OR REPLACE TRIGGER update_social_profiles_updated_at
+    BEFORE UPDATE ON social_profiles
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
+
+CREATE OR REPLACE TRIGGER update_gift_ideas_updated_at
+    BEFORE UPDATE ON gift_ideas
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
+
+CREATE OR REPLACE TRIGGER update_contact_groups_updated_at
+    BEFORE UPDATE ON contact_groups
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
+
+CREATE OR REPLACE TRIGGER update_user_preferences_updated_at
+    BEFORE UPDATE ON user_preferences
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
+
+CREATE OR REPLACE TRIGGER update_user_settings_updated_at
+    BEFORE UPDATE ON user_settings
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
+
+CREATE OR REPLACE TRIGGER update_export_schedules_updated_at
+    BEFORE UPDATE ON export_schedules
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
+
+CREATE OR REPLACE TRIGGER update_saved_filters_updated_at
+    BEFORE UPDATE ON saved_filters
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
+
+CREATE OR REPLACE TRIGGER update_goals_updated_at
+    BEFORE UPDATE ON goals
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
+
+-- A contact's tags, interactions and occasions are part of the contact as the API returns it,
+-- so changing them moves the contact's updated_at (and its Last-Modified header) as well
+CREATE OR REPLACE FUNCTION touch_contact()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP IN ('UPDATE', 'DELETE') THEN
+        UPDATE contacts SET updated_at = CURRENT_TIMESTAMP WHERE contact_id = OLD.contact_id;
+    END IF;
+    IF TG_OP IN ('INSERT', 'UPDATE') THEN
+        UPDATE contacts SET updated_at = CURRENT_TIMESTAMP WHERE contact_id = NEW.contact_id;
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE TRIGGER touch_contact_on_contact_tags
+    AFTER INSERT OR UPDATE OR DELETE ON contact_tags
+    FOR EACH ROW
+    EXECUTE FUNCTION touch_contact();
+
+CREATE OR REPLACE TRIGGER touch_contact_on_interactions
+    AFTER INSERT OR UPDATE OR DELETE ON interactions
+    FOR EACH ROW
+    EXECUTE FUNCTION touch_contact();
+
+CREATE OR REPLACE TRIGGER touch_contact_on_interaction_participants
+    AFTER INSERT OR UPDATE OR DELETE ON interaction_participants
+    FOR EACH ROW
+    EXECUTE FUNCTION touch_contact();
+
+CREATE OR REPLACE TRIGGER touch_contact_on_occasions
+    AFTER INSERT OR UPDATE OR DELETE ON occasions
+    FOR EACH ROW
+    EXECUTE FUNCTION touch_contact();
+
+-- Renaming or recoloring a tag changes every contact that carries it
+CREATE OR REPLACE FUNCTION touch_tagged_contacts()
+RETURNS TRIGGER AS $$
+BEGIN
+    UPDATE contacts SET updated_at = CURRENT_TIMESTAMP
+    WHERE contact_id IN (SELECT contact_id FROM contact_tags WHERE tag_id = NEW.tag_id);
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE TRIGGER touch_contacts_on_tags
+    AFTER UPDATE ON tags
+    FOR EACH ROW
+    EXECUTE FUNCTION touch_tagged_contacts();
+
+-- An interaction's tags are part of it, so tagging it changes every contact it involves
+CREATE OR REPLACE FUNCTION touch_interaction_contacts()
+RETURNS TRIGGER AS $$
+DECLARE
+    changed INT := CASE WHEN TG_OP = 'DELETE' THEN OLD.interaction_id ELSE NEW.interaction_id END;
+BEGIN
+    UPDATE contacts SET updated_at = CURRENT_TIMESTAMP
+    WHERE contact_id IN (SELECT contact_id FROM interactions WHERE interaction_id = changed
+                         UNION
+                         SELECT contact_id FROM interaction_participants
+                         WHERE interaction_id = changed);
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE TRIGGER touch_contacts_on_interaction_tags
+    AFTER INSERT OR DELETE ON interaction_tags
+    FOR EACH ROW
+    EXECUTE FUNCTION touch_interaction_contacts();
+
+-- When the latest interaction contact `c_id` took part in happened
+CREATE OR REPLACE FUNCTION contact_last_interaction(c_id INT)
+RETURNS TIMESTAMPTZ AS $$
+    SELECT MAX(i.interaction_date)
+    FROM interactions i
+    WHERE i.contact_id = c_id
+       OR i.interaction_id IN (SELECT interaction_id FROM interaction_participants
+                               WHERE contact_id = c_id);
+$$ LANGUAGE sql STABLE;
+
+-- Keep contacts.last_interaction_at up to date for the contacts an interaction involves, before
+-- and after the change, including its other participants when its date moves
+CREATE OR REPLACE FUNCTION refresh_last_interaction()
+RETURNS TRIGGER AS $$
+DECLARE
+    old_contact INT := CASE WHEN TG_OP = 'INSERT' THEN NULL ELSE OLD.contact_id END;
+    new_contact INT := CASE WHEN TG_OP = 'DELETE' THEN NULL ELSE NEW.contact_id END;
+    changed INT := CASE WHEN TG_OP = 'DELETE' THEN OLD.interaction_id ELSE NEW.interaction_id END;
+BEGIN
+    UPDATE contacts SET last_interaction_at = contact_last_interaction(contact_id)
+    WHERE contact_id IN (old_contact, new_contact)
+       OR contact_id IN (SELECT contact_id FROM interaction_participants
+                         WHERE interaction_id = changed);
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE TRIGGER refresh_last_interaction_on_interactions
+    AFTER INSERT OR UPDATE OF contact_id, interaction_date OR DELETE ON interactions
+    FOR EACH ROW
+    EXECUTE FUNCTION refresh_last_interaction();
+
+CREATE OR REPLACE TRIGGER refresh_last_interaction_on_interaction_participants
+    AFTER INSERT OR UPDATE OR DELETE ON interaction_participants
+    FOR EACH ROW
+    EXECUTE FUNCTION refresh_last_interaction();
+
+-- Contacts from before last_interaction_at, without moving their updated_at
+ALTER TABLE contacts DISABLE TRIGGER update_contacts_updated_at;
+UPDATE contacts SET last_interaction_at = contact_last_interaction(contact_id)
+WHERE last_interaction_at IS NULL;
+ALTER TABLE contacts ENABLE TRIGGER update_contacts_updated_at;
+
+-- The predicted contact priority, computed in the database so contact lists can be sorted and
+-- paged by it. Mirrors ContactResponse::new and reminders::next_occurrence; keep them in step.
+
+-- `d`'s month and day in `y`; February 29 falls on the 28th in other years
+CREATE OR REPLACE FUNCTION date_in_year(d DATE, y INT)
+RETURNS DATE AS $$
+    SELECT CASE
+        WHEN EXTRACT(MONTH FROM d) = 2 AND EXTRACT(DAY FROM d) = 29
+             AND NOT ((y % 4 = 0 AND y % 100 <> 0) OR y % 400 = 0)
+            THEN make_date(y, 2, 28)
+        ELSE make_date(y, EXTRACT(MONTH FROM d)::INT, EXTRACT(DAY FROM d)::INT)
+    END;
+$$ LANGUAGE sql IMMUTABLE;
+
+-- The next time an occasion falls on or after `today`, NULL if it is over
+CREATE OR REPLACE FUNCTION occasion_next_occurrence(
+    d DATE, recurring BOOLEAN, recurring_interval INT, kind occasion_kind, today DATE
+)
+RETURNS DATE AS $$
+DECLARE
+    step INT;
+    start_year INT := EXTRACT(YEAR FROM d)::INT;
+    y INT;
+BEGIN
+    IF kind <> 'birthday' AND recurring IS DISTINCT FROM TRUE THEN
+        RETURN CASE WHEN d >= today THEN d END;
+    END IF;
+    step := CASE WHEN kind <> 'birthday' AND recurring_interval > 0 THEN recurring_interval ELSE 1 END;
+    y := start_year
+        + (GREATEST(EXTRACT(YEAR FROM today)::INT - start_year, 0) + step - 1) / step * step;
+    IF date_in_year(d, y) < today THEN
+        y := y + step;
+    END IF;
+    RETURN date_in_year(d, y);
+END;
+$$ LANGUAGE plpgsql IMMUTABLE;
+
+-- Interaction days are read in timezone `tz`; NULL when there is nothing to go on
+CREATE OR REPLACE FUNCTION contact_priority(
+    c_id INT, desired_frequency_days INT, today DATE, tz TEXT
+)
+RETURNS DOUBLE PRECISION AS $$
+    WITH days AS (
+        SELECT (i.interaction_date AT TIME ZONE tz)::DATE AS day
+        FROM interactions i
+        WHERE i.contact_id = c_id
+           OR i.interaction_id IN (SELECT interaction_id FROM interaction_participants
+                                   WHERE contact_id = c_id)
+    ), history AS (
+        SELECT COUNT(*) AS n, MIN(day) AS first_day, MAX(day) AS last_day FROM days
+    ), closest AS (
+        SELECT MIN(occasion_next_occurrence(o.date, o.recurring, o.recurring_interval,
+                                            o.occasion_type, today)) - today AS days
+        FROM occasions o
+        WHERE o.contact_id = c_id
+    ), parts AS (
+        SELECT
+            CASE
+                WHEN desired_frequency_days IS NOT NULL AND h.n >= 1
+                    THEN (today - h.last_day - desired_frequency_days)::DOUBLE PRECISION
+                WHEN h.n >= 2
+                    THEN (today - h.last_day) - (h.last_day - h.first_day)::DOUBLE PRECISION / (h.n - 1)
+            END AS interaction_score,
+            CASE
+                WHEN c.days IS NULL THEN NULL
+                WHEN c.days < 7 THEN 10.0
+                WHEN c.days < 30 THEN 5.0
+                WHEN c.days < 90 THEN 1.0
+                ELSE 0.0
+            END AS occasion_score
+        FROM history h, closest c
+    )
+    SELECT CASE
+        WHEN interaction_score IS NULL AND occasion_score IS NULL THEN NULL
+        ELSE COALESCE(interaction_score, 0) + COALESCE(occasion_score, 0)
+    END
+    FROM parts;
+$$ LANGUAGE sql STABLE;
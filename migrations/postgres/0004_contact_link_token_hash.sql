@@ -0,0 +1,6 @@
+-- Contact links keep only the SHA-256 of their token, so a leaked database or backup opens
+-- no one's card. Existing tokens are hashed in place, so links already handed out keep working.
+
+UPDATE contact_links SET token = encode(sha256(convert_to(token, 'UTF8')), 'hex');
+ALTER TABLE contact_links RENAME COLUMN token TO token_hash;
+ALTER TABLE contact_links RENAME CONSTRAINT contact_links_token_key TO contact_links_token_hash_key;
@@ -0,0 +1,721 @@
+-- The Postgres schema for SQLite. Databases from before there were migrations have no record
+-- of what they already have, so every statement must be safe to run on one that has it. Keep
+-- it in step with the Postgres migrations.
+--
+-- Where SQLite has no equivalent type:
+-- - timestamps are UTC text, "YYYY-MM-DD HH:MM:SS.ffffff", so they sort as they compare
+-- - enums are text limited by a CHECK
+-- - INT[] columns hold JSON arrays and JSONB columns JSON text
+-- - SERIAL is INTEGER PRIMARY KEY AUTOINCREMENT, which never reuses an id either
+--
+-- Full-text search, contact priorities and the other functions at the end of the Postgres
+-- schema are computed by `SqliteRepository` instead.
+
+CREATE TABLE IF NOT EXISTS users (
+    user_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    auth0_id VARCHAR(100) UNIQUE NOT NULL,
+    name VARCHAR(100) NOT NULL,
+    email VARCHAR(100) UNIQUE NOT NULL,
+    is_admin BOOLEAN NOT NULL DEFAULT FALSE,
+    deactivated_at TEXT,
+    delete_after TEXT,
+    created_at TEXT DEFAULT (strftime('%Y-%m-%d %H:%M:%f000', 'now')),
+    updated_at TEXT DEFAULT (strftime('%Y-%m-%d %H:%M:%f000', 'now'))
+);
+
+CREATE TABLE IF NOT EXISTS workspaces (
+    workspace_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INT NOT NULL,
+    name VARCHAR(100) NOT NULL,
+    is_default BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TEXT DEFAULT (strftime('%Y-%m-%d %H:%M:%f000', 'now')),
+    updated_at TEXT DEFAULT (strftime('%Y-%m-%d %H:%M:%f000', 'now')),
+    UNIQUE (user_id, name),
+    FOREIGN KEY (user_id) REFERENCES users(user_id) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_workspaces_default ON workspaces(user_id) WHERE is_default;
+
+CREATE TABLE IF NOT EXISTS contacts (
+    contact_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    workspace_id INT NOT NULL,
+    first_name VARCHAR(50),
+    last_name VARCHAR(50),
+    email VARCHAR(100),
+    phone VARCHAR(20),
+    phone_e164 VARCHAR(16),
+    short_note TEXT,
+    notes TEXT,
+    avatar_url TEXT,
+    archived BOOLEAN NOT NULL DEFAULT FALSE,
+    desired_frequency_days INT CHECK (desired_frequency_days > 0),
+    met_at TEXT CHECK (json_type(met_at) = 'object'),
+    introduced_by_contact_id INT,
+    location VARCHAR(255),
+    latitude REAL CHECK (latitude BETWEEN -90 AND 90),
+    longitude REAL CHECK (longitude BETWEEN -180 AND 180),
+    last_interaction_at TEXT,
+    created_at TEXT DEFAULT (strftime('%Y-%m-%d %H:%M:%f000', 'now')),
+    updated_at TEXT DEFAULT (strftime('%Y-%m-%d %H:%M:%f000', 'now')),
+    CHECK ((latitude IS NULL) = (longitude IS NULL)),
+    UNIQUE (workspace_id, email),
+    FOREIGN KEY (workspace_id) REFERENCES workspaces(workspace_id) ON DELETE CASCADE,
+    FOREIGN KEY (introduced_by_contact_id) REFERENCES contacts(contact_id) ON DELETE SET NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_contacts_workspace_id ON contacts(workspace_id);
+CREATE INDEX IF NOT EXISTS idx_contacts_phone_e164 ON contacts(workspace_id, phone_e164);
+CREATE INDEX IF NOT EXISTS idx_contacts_introduced_by ON contacts(introduced_by_contact_id);
+CREATE INDEX IF NOT EXISTS idx_contacts_coordinates
+    ON contacts(workspace_id, latitude, longitude) WHERE latitude IS NOT NULL;
+
+CREATE TABLE IF NOT EXISTS contact_changes (
+    change_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    contact_id INT NOT NULL,
+    field VARCHAR(50) NOT NULL,
+    old_value TEXT,
+    new_value TEXT,
+    changed_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f000', 'now')),
+    FOREIGN KEY (contact_id) REFERENCES contacts(contact_id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_contact_changes_history
+    ON contact_changes(contact_id, changed_at DESC, change_id DESC);
+
+CREATE TABLE IF NOT EXISTS tags (
+    tag_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    workspace_id INT NOT NULL,
+    name VARCHAR(50) NOT NULL,
+    details TEXT,
+    color VARCHAR(20),
+    created_at TEXT DEFAULT (strftime('%Y-%m-%d %H:%M:%f000', 'now')),
+    updated_at TEXT DEFAULT (strftime('%Y-%m-%d %H:%M:%f000', 'now')),
+    UNIQUE (workspace_id, name),
+    FOREIGN KEY (workspace_id) REFERENCES workspaces(workspace_id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS contact_tags (
+    contact_id INT NOT NULL,
+    tag_id INT NOT NULL,
+    PRIMARY KEY (contact_id, tag_id),
+    FOREIGN KEY (contact_id) REFERENCES contacts(contact_id) ON DELETE CASCADE,
+    FOREIGN KEY (tag_id) REFERENCES tags(tag_id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_contact_tags_tag ON contact_tags(tag_id);
+
+CREATE TABLE IF NOT EXISTS interaction_types (
+    type_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    workspace_id INT NOT NULL,
+    name VARCHAR(50) NOT NULL,
+    created_at TEXT DEFAULT (strftime('%Y-%m-%d %H:%M:%f000', 'now')),
+    updated_at TEXT DEFAULT (strftime('%Y-%m-%d %H:%M:%f000', 'now')),
+    UNIQUE (workspace_id, name),
+    FOREIGN KEY (workspace_id) REFERENCES workspaces(workspace_id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS interactions (
+    interaction_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    workspace_id INT NOT NULL,
+    contact_id INT,
+    interaction_date TEXT NOT NULL,
+    notes TEXT,
+    followup_priority INT,
+    interaction_type TEXT CHECK (interaction_type IN ('call', 'email', 'meeting', 'message')),
+    custom_type_id INT,
+    created_at TEXT DEFAULT (strftime('%Y-%m-%d %H:%M:%f000', 'now')),
+    updated_at TEXT DEFAULT (strftime('%Y-%m-%d %H:%M:%f000', 'now')),
+    CHECK (interaction_type IS NULL OR custom_type_id IS NULL),
+    FOREIGN KEY (workspace_id) REFERENCES workspaces(workspace_id) ON DELETE CASCADE,
+    FOREIGN KEY (contact_id) REFERENCES contacts(contact_id) ON DELETE CASCADE,
+    FOREIGN KEY (custom_type_id) REFERENCES interaction_types(type_id) ON DELETE SET NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_interactions_workspace_id ON interactions(workspace_id);
+CREATE INDEX IF NOT EXISTS idx_interactions_timeline
+    ON interactions(contact_id, interaction_date DESC, interaction_id DESC);
+
+CREATE TABLE IF NOT EXISTS interaction_participants (
+    interaction_id INT NOT NULL,
+    contact_id INT NOT NULL,
+    PRIMARY KEY (interaction_id, contact_id),
+    FOREIGN KEY (interaction_id) REFERENCES interactions(interaction_id) ON DELETE CASCADE,
+    FOREIGN KEY (contact_id) REFERENCES contacts(contact_id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_interaction_participants_contact
+    ON interaction_participants(contact_id);
+
+CREATE TABLE IF NOT EXISTS interaction_tags (
+    interaction_id INT NOT NULL,
+    tag_id INT NOT NULL,
+    PRIMARY KEY (interaction_id, tag_id),
+    FOREIGN KEY (interaction_id) REFERENCES interactions(interaction_id) ON DELETE CASCADE,
+    FOREIGN KEY (tag_id) REFERENCES tags(tag_id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_interaction_tags_tag ON interaction_tags(tag_id);
+
+CREATE TABLE IF NOT EXISTS interaction_attachments (
+    attachment_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    workspace_id INT NOT NULL,
+    interaction_id INT NOT NULL,
+    file_name VARCHAR(255) NOT NULL,
+    content_type VARCHAR(100) NOT NULL,
+    size_bytes INT NOT NULL,
+    data BLOB NOT NULL,
+    transcription TEXT CHECK (transcription IN ('pending', 'done', 'failed')),
+    transcription_attempts INT NOT NULL DEFAULT 0,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f000', 'now')),
+    FOREIGN KEY (workspace_id) REFERENCES workspaces(workspace_id) ON DELETE CASCADE,
+    FOREIGN KEY (interaction_id) REFERENCES interactions(interaction_id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_interaction_attachments_interaction
+    ON interaction_attachments(interaction_id);
+CREATE INDEX IF NOT EXISTS idx_interaction_attachments_pending
+    ON interaction_attachments(attachment_id) WHERE transcription = 'pending';
+
+CREATE TABLE IF NOT EXISTS occasions (
+    occasion_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    workspace_id INT NOT NULL,
+    contact_id INT,
+    name VARCHAR(100) NOT NULL,
+    date TEXT NOT NULL,
+    recurring BOOLEAN DEFAULT FALSE,
+    recurring_interval INT,
+    details TEXT,
+    occasion_type TEXT NOT NULL DEFAULT 'custom'
+        CHECK (occasion_type IN ('birthday', 'anniversary', 'custom')),
+    birth_year INT,
+    remind_days_before TEXT,
+    interaction_id INT,
+    created_at TEXT DEFAULT (strftime('%Y-%m-%d %H:%M:%f000', 'now')),
+    updated_at TEXT DEFAULT (strftime('%Y-%m-%d %H:%M:%f000', 'now')),
+    CHECK (birth_year IS NULL OR occasion_type = 'birthday'),
+    FOREIGN KEY (workspace_id) REFERENCES workspaces(workspace_id) ON DELETE CASCADE,
+    FOREIGN KEY (contact_id) REFERENCES contacts(contact_id) ON DELETE CASCADE,
+    FOREIGN KEY (interaction_id) REFERENCES interactions(interaction_id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_occasions_contact_id ON occasions(contact_id);
+
+CREATE TABLE IF NOT EXISTS social_profiles (
+    social_profile_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    workspace_id INT NOT NULL,
+    contact_id INT NOT NULL,
+    platform TEXT NOT NULL CHECK (platform IN (
+        'linkedin', 'twitter', 'github', 'instagram', 'facebook', 'mastodon', 'website', 'other'
+    )),
+    url TEXT NOT NULL,
+    created_at TEXT DEFAULT (strftime('%Y-%m-%d %H:%M:%f000', 'now')),
+    updated_at TEXT DEFAULT (strftime('%Y-%m-%d %H:%M:%f000', 'now')),
+    UNIQUE (contact_id, url),
+    FOREIGN KEY (workspace_id) REFERENCES workspaces(workspace_id) ON DELETE CASCADE,
+    FOREIGN KEY (contact_id) REFERENCES contacts(contact_id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS gift_ideas (
+    gift_idea_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    workspace_id INT NOT NULL,
+    contact_id INT NOT NULL,
+    occasion_id INT,
+    idea TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'idea' CHECK (status IN ('idea', 'purchased', 'given')),
+    price_cents INT CHECK (price_cents >= 0),
+    created_at TEXT DEFAULT (strftime('%Y-%m-%d %H:%M:%f000', 'now')),
+    updated_at TEXT DEFAULT (strftime('%Y-%m-%d %H:%M:%f000', 'now')),
+    FOREIGN KEY (workspace_id) REFERENCES workspaces(workspace_id) ON DELETE CASCADE,
+    FOREIGN KEY (contact_id) REFERENCES contacts(contact_id) ON DELETE CASCADE,
+    FOREIGN KEY (occasion_id) REFERENCES occasions(occasion_id) ON DELETE SET NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_gift_ideas_contact ON gift_ideas(contact_id);
+
+CREATE TABLE IF NOT EXISTS contact_groups (
+    group_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    workspace_id INT NOT NULL,
+    name TEXT NOT NULL,
+    description TEXT,
+    created_at TEXT DEFAULT (strftime('%Y-%m-%d %H:%M:%f000', 'now')),
+    updated_at TEXT DEFAULT (strftime('%Y-%m-%d %H:%M:%f000', 'now')),
+    FOREIGN KEY (workspace_id) REFERENCES workspaces(workspace_id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS contact_group_members (
+    group_id INT NOT NULL,
+    contact_id INT NOT NULL,
+    position INT NOT NULL,
+    PRIMARY KEY (group_id, contact_id),
+    FOREIGN KEY (group_id) REFERENCES contact_groups(group_id) ON DELETE CASCADE,
+    FOREIGN KEY (contact_id) REFERENCES contacts(contact_id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_contact_group_members_contact ON contact_group_members(contact_id);
+
+CREATE TABLE IF NOT EXISTS reconnect_picks (
+    workspace_id INT NOT NULL,
+    contact_id INT NOT NULL,
+    week TEXT NOT NULL,
+    PRIMARY KEY (workspace_id, week, contact_id),
+    FOREIGN KEY (workspace_id) REFERENCES workspaces(workspace_id) ON DELETE CASCADE,
+    FOREIGN KEY (contact_id) REFERENCES contacts(contact_id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS avatar_lookups (
+    contact_id INTEGER PRIMARY KEY,
+    workspace_id INT NOT NULL,
+    next_lookup_at TEXT NOT NULL,
+    FOREIGN KEY (workspace_id) REFERENCES workspaces(workspace_id) ON DELETE CASCADE,
+    FOREIGN KEY (contact_id) REFERENCES contacts(contact_id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS suggestion_snoozes (
+    user_id INT NOT NULL,
+    contact_id INT NOT NULL,
+    snoozed_until TEXT,
+    created_at TEXT DEFAULT (strftime('%Y-%m-%d %H:%M:%f000', 'now')),
+    PRIMARY KEY (user_id, contact_id),
+    FOREIGN KEY (user_id) REFERENCES users(user_id) ON DELETE CASCADE,
+    FOREIGN KEY (contact_id) REFERENCES contacts(contact_id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS user_preferences (
+    user_id INTEGER PRIMARY KEY,
+    country VARCHAR(2),
+    quiet_weekdays TEXT NOT NULL DEFAULT '[]',
+    greeting_tag_ids TEXT NOT NULL DEFAULT '[]',
+    remind_days_before TEXT NOT NULL DEFAULT '[0]',
+    timezone VARCHAR(64),
+    created_at TEXT DEFAULT (strftime('%Y-%m-%d %H:%M:%f000', 'now')),
+    updated_at TEXT DEFAULT (strftime('%Y-%m-%d %H:%M:%f000', 'now')),
+    FOREIGN KEY (user_id) REFERENCES users(user_id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS user_settings (
+    user_id INTEGER PRIMARY KEY,
+    email_notifications BOOLEAN NOT NULL DEFAULT TRUE,
+    push_notifications BOOLEAN NOT NULL DEFAULT FALSE,
+    weekly_review_email BOOLEAN NOT NULL DEFAULT FALSE,
+    weekly_review_sent_on TEXT,
+    push_reminders_sent_on TEXT,
+    default_reminder_cadence_days INT NOT NULL DEFAULT 30,
+    created_at TEXT DEFAULT (strftime('%Y-%m-%d %H:%M:%f000', 'now')),
+    updated_at TEXT DEFAULT (strftime('%Y-%m-%d %H:%M:%f000', 'now')),
+    FOREIGN KEY (user_id) REFERENCES users(user_id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS export_schedules (
+    workspace_id INTEGER PRIMARY KEY,
+    url TEXT NOT NULL,
+    interval_hours INT NOT NULL,
+    secret VARCHAR(64) NOT NULL,
+    last_pushed_at TEXT,
+    last_status TEXT,
+    created_at TEXT DEFAULT (strftime('%Y-%m-%d %H:%M:%f000', 'now')),
+    updated_at TEXT DEFAULT (strftime('%Y-%m-%d %H:%M:%f000', 'now')),
+    FOREIGN KEY (workspace_id) REFERENCES workspaces(workspace_id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS inbound_email_addresses (
+    workspace_id INTEGER PRIMARY KEY,
+    token VARCHAR(64) UNIQUE NOT NULL,
+    created_at TEXT DEFAULT (strftime('%Y-%m-%d %H:%M:%f000', 'now')),
+    FOREIGN KEY (workspace_id) REFERENCES workspaces(workspace_id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS rest_hooks (
+    hook_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    workspace_id INT NOT NULL,
+    event TEXT NOT NULL CHECK (event IN ('new_contact', 'new_interaction', 'upcoming_occasion')),
+    target_url TEXT NOT NULL,
+    delivered_on TEXT,
+    created_at TEXT DEFAULT (strftime('%Y-%m-%d %H:%M:%f000', 'now')),
+    FOREIGN KEY (workspace_id) REFERENCES workspaces(workspace_id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_rest_hooks_workspace_id ON rest_hooks(workspace_id);
+
+CREATE TABLE IF NOT EXISTS contact_links (
+    link_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    workspace_id INT NOT NULL,
+    contact_id INT NOT NULL,
+    token VARCHAR(64) UNIQUE NOT NULL,
+    expires_at TEXT NOT NULL,
+    created_at TEXT DEFAULT (strftime('%Y-%m-%d %H:%M:%f000', 'now')),
+    FOREIGN KEY (workspace_id) REFERENCES workspaces(workspace_id) ON DELETE CASCADE,
+    FOREIGN KEY (contact_id) REFERENCES contacts(contact_id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_contact_links_contact_id ON contact_links(contact_id);
+
+CREATE TABLE IF NOT EXISTS inbox_captures (
+    capture_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    workspace_id INT NOT NULL,
+    text TEXT NOT NULL,
+    captured_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f000', 'now')),
+    FOREIGN KEY (workspace_id) REFERENCES workspaces(workspace_id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_inbox_captures_workspace_id ON inbox_captures(workspace_id);
+
+CREATE TABLE IF NOT EXISTS saved_filters (
+    filter_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    workspace_id INT NOT NULL,
+    name VARCHAR(100) NOT NULL,
+    query TEXT NOT NULL,
+    created_at TEXT DEFAULT (strftime('%Y-%m-%d %H:%M:%f000', 'now')),
+    updated_at TEXT DEFAULT (strftime('%Y-%m-%d %H:%M:%f000', 'now')),
+    FOREIGN KEY (workspace_id) REFERENCES workspaces(workspace_id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS goals (
+    goal_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    workspace_id INT NOT NULL,
+    name VARCHAR(100) NOT NULL,
+    contact_id INT,
+    interaction_type VARCHAR(50),
+    target_count INT NOT NULL CHECK (target_count > 0),
+    period TEXT NOT NULL CHECK (period IN ('week', 'month')),
+    created_at TEXT DEFAULT (strftime('%Y-%m-%d %H:%M:%f000', 'now')),
+    updated_at TEXT DEFAULT (strftime('%Y-%m-%d %H:%M:%f000', 'now')),
+    FOREIGN KEY (workspace_id) REFERENCES workspaces(workspace_id) ON DELETE CASCADE,
+    FOREIGN KEY (contact_id) REFERENCES contacts(contact_id) ON DELETE CASCADE
+);
+
+-- NULLs are distinct in SQLite's UNIQUE constraints, so the index stands in for
+-- UNIQUE NULLS NOT DISTINCT
+CREATE TABLE IF NOT EXISTS shares (
+    share_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    owner_id INT NOT NULL,
+    grantee_id INT NOT NULL,
+    tag_id INT,
+    contact_id INT,
+    permission TEXT NOT NULL CHECK (permission IN ('read', 'write')),
+    created_at TEXT DEFAULT (strftime('%Y-%m-%d %H:%M:%f000', 'now')),
+    CHECK ((tag_id IS NULL) <> (contact_id IS NULL)),
+    CHECK (owner_id <> grantee_id),
+    FOREIGN KEY (owner_id) REFERENCES users(user_id) ON DELETE CASCADE,
+    FOREIGN KEY (grantee_id) REFERENCES users(user_id) ON DELETE CASCADE,
+    FOREIGN KEY (tag_id) REFERENCES tags(tag_id) ON DELETE CASCADE,
+    FOREIGN KEY (contact_id) REFERENCES contacts(contact_id) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_shares_unique
+    ON shares(owner_id, grantee_id, IFNULL(tag_id, 0), IFNULL(contact_id, 0));
+CREATE INDEX IF NOT EXISTS idx_shares_grantee ON shares(grantee_id);
+
+CREATE TABLE IF NOT EXISTS idempotency_keys (
+    user_id INT NOT NULL,
+    idempotency_key VARCHAR(255) NOT NULL,
+    fingerprint VARCHAR(64) NOT NULL,
+    status SMALLINT,
+    content_type TEXT,
+    location TEXT,
+    body BLOB,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f000', 'now')),
+    PRIMARY KEY (user_id, idempotency_key),
+    FOREIGN KEY (user_id) REFERENCES users(user_id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS job_leases (
+    name TEXT PRIMARY KEY,
+    holder TEXT NOT NULL,
+    locked_until TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS push_subscriptions (
+    subscription_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INT NOT NULL,
+    endpoint TEXT NOT NULL,
+    p256dh TEXT NOT NULL,
+    auth TEXT NOT NULL,
+    device_name VARCHAR(100),
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f000', 'now')),
+    UNIQUE (user_id, endpoint),
+    FOREIGN KEY (user_id) REFERENCES users(user_id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS outbox (
+    delivery_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    workspace_id INT NOT NULL,
+    hook_id INT,
+    push_subscription_id INT,
+    entity_ids TEXT NOT NULL DEFAULT '[]',
+    payload TEXT,
+    status TEXT NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'delivered', 'failed')),
+    attempts INT NOT NULL DEFAULT 0,
+    next_attempt_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f000', 'now')),
+    last_response_status INT,
+    last_error TEXT,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f000', 'now')),
+    finished_at TEXT,
+    FOREIGN KEY (workspace_id) REFERENCES workspaces(workspace_id) ON DELETE CASCADE,
+    FOREIGN KEY (hook_id) REFERENCES rest_hooks(hook_id) ON DELETE CASCADE,
+    FOREIGN KEY (push_subscription_id) REFERENCES push_subscriptions(subscription_id)
+        ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_outbox_pending ON outbox(next_attempt_at) WHERE status = 'pending';
+CREATE INDEX IF NOT EXISTS idx_outbox_hook_id ON outbox(hook_id);
+CREATE INDEX IF NOT EXISTS idx_outbox_push_subscription_id ON outbox(push_subscription_id);
+
+-- updated_at, unless the statement set it itself
+CREATE TRIGGER IF NOT EXISTS update_users_updated_at AFTER UPDATE ON users
+FOR EACH ROW WHEN NEW.updated_at IS OLD.updated_at
+BEGIN
+    UPDATE users SET updated_at = strftime('%Y-%m-%d %H:%M:%f000', 'now')
+    WHERE user_id = NEW.user_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS update_workspaces_updated_at AFTER UPDATE ON workspaces
+FOR EACH ROW WHEN NEW.updated_at IS OLD.updated_at
+BEGIN
+    UPDATE workspaces SET updated_at = strftime('%Y-%m-%d %H:%M:%f000', 'now')
+    WHERE workspace_id = NEW.workspace_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS update_contacts_updated_at AFTER UPDATE ON contacts
+FOR EACH ROW WHEN NEW.updated_at IS OLD.updated_at
+BEGIN
+    UPDATE contacts SET updated_at = strftime('%Y-%m-%d %H:%M:%f000', 'now')
+    WHERE contact_id = NEW.contact_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS update_tags_updated_at AFTER UPDATE ON tags
+FOR EACH ROW WHEN NEW.updated_at IS OLD.updated_at
+BEGIN
+    UPDATE tags SET updated_at = strftime('%Y-%m-%d %H:%M:%f000', 'now')
+    WHERE tag_id = NEW.tag_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS update_interaction_types_updated_at AFTER UPDATE ON interaction_types
+FOR EACH ROW WHEN NEW.updated_at IS OLD.updated_at
+BEGIN
+    UPDATE interaction_types SET updated_at = strftime('%Y-%m-%d %H:%M:%f000', 'now')
+    WHERE type_id = NEW.type_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS update_interactions_updated_at AFTER UPDATE ON interactions
+FOR EACH ROW WHEN NEW.updated_at IS OLD.updated_at
+BEGIN
+    UPDATE interactions SET updated_at = strftime('%Y-%m-%d %H:%M:%f000', 'now')
+    WHERE interaction_id = NEW.interaction_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS update_occasions_updated_at AFTER UPDATE ON occasions
+FOR EACH ROW WHEN NEW.updated_at IS OLD.updated_at
+BEGIN
+    UPDATE occasions SET updated_at = strftime('%Y-%m-%d %H:%M:%f000', 'now')
+    WHERE occasion_id = NEW.occasion_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS update_social_profiles_updated_at AFTER UPDATE ON social_profiles
+FOR EACH ROW WHEN NEW.updated_at IS OLD.updated_at
+BEGIN
+    UPDATE social_profiles SET updated_at = strftime('%Y-%m-%d %H:%M:%f000', 'now')
+    WHERE social_profile_id = NEW.social_profile_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS update_gift_ideas_updated_at AFTER UPDATE ON gift_ideas
+FOR EACH ROW WHEN NEW.updated_at IS OLD.updated_at
+BEGIN
+    UPDATE gift_ideas SET updated_at = strftime('%Y-%m-%d %H:%M:%f000', 'now')
+    WHERE gift_idea_id = NEW.gift_idea_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS update_contact_groups_updated_at AFTER UPDATE ON contact_groups
+FOR EACH ROW WHEN NEW.updated_at IS OLD.updated_at
+BEGIN
+    UPDATE contact_groups SET updated_at = strftime('%Y-%m-%d %H:%M:%f000', 'now')
+    WHERE group_id = NEW.group_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS update_user_preferences_updated_at AFTER UPDATE ON user_preferences
+FOR EACH ROW WHEN NEW.updated_at IS OLD.updated_at
+BEGIN
+    UPDATE user_preferences SET updated_at = strftime('%Y-%m-%d %H:%M:%f000', 'now')
+    WHERE user_id = NEW.user_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS update_user_settings_updated_at AFTER UPDATE ON user_settings
+FOR EACH ROW WHEN NEW.updated_at IS OLD.updated_at
+BEGIN
+    UPDATE user_settings SET updated_at = strftime('%Y-%m-%d %H:%M:%f000', 'now')
+    WHERE user_id = NEW.user_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS update_export_schedules_updated_at AFTER UPDATE ON export_schedules
+FOR EACH ROW WHEN NEW.updated_at IS OLD.updated_at
+BEGIN
+    UPDATE export_schedules SET updated_at = strftime('%Y-%m-%d %H:%M:%f000', 'now')
+    WHERE workspace_id = NEW.workspace_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS update_saved_filters_updated_at AFTER UPDATE ON saved_filters
+FOR EACH ROW WHEN NEW.updated_at IS OLD.updated_at
+BEGIN
+    UPDATE saved_filters SET updated_at = strftime('%Y-%m-%d %H:%M:%f000', 'now')
+    WHERE filter_id = NEW.filter_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS update_goals_updated_at AFTER UPDATE ON goals
+FOR EACH ROW WHEN NEW.updated_at IS OLD.updated_at
+BEGIN
+    UPDATE goals SET updated_at = strftime('%Y-%m-%d %H:%M:%f000', 'now')
+    WHERE goal_id = NEW.goal_id;
+END;
+
+-- A contact's tags, interactions and occasions are part of the contact, as in the Postgres
+-- touch_contact
+CREATE TRIGGER IF NOT EXISTS touch_contact_on_contact_tags_insert AFTER INSERT ON contact_tags
+BEGIN
+    UPDATE contacts SET updated_at = strftime('%Y-%m-%d %H:%M:%f000', 'now')
+    WHERE contact_id = NEW.contact_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS touch_contact_on_contact_tags_delete AFTER DELETE ON contact_tags
+BEGIN
+    UPDATE contacts SET updated_at = strftime('%Y-%m-%d %H:%M:%f000', 'now')
+    WHERE contact_id = OLD.contact_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS touch_contact_on_interactions_insert AFTER INSERT ON interactions
+BEGIN
+    UPDATE contacts SET updated_at = strftime('%Y-%m-%d %H:%M:%f000', 'now')
+    WHERE contact_id = NEW.contact_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS touch_contact_on_interactions_update AFTER UPDATE ON interactions
+BEGIN
+    UPDATE contacts SET updated_at = strftime('%Y-%m-%d %H:%M:%f000', 'now')
+    WHERE contact_id IN (OLD.contact_id, NEW.contact_id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS touch_contact_on_interactions_delete AFTER DELETE ON interactions
+BEGIN
+    UPDATE contacts SET updated_at = strftime('%Y-%m-%d %H:%M:%f000', 'now')
+    WHERE contact_id = OLD.contact_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS touch_contact_on_interaction_participants_insert
+AFTER INSERT ON interaction_participants
+BEGIN
+    UPDATE contacts SET updated_at = strftime('%Y-%m-%d %H:%M:%f000', 'now')
+    WHERE contact_id = NEW.contact_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS touch_contact_on_interaction_participants_delete
+AFTER DELETE ON interaction_participants
+BEGIN
+    UPDATE contacts SET updated_at = strftime('%Y-%m-%d %H:%M:%f000', 'now')
+    WHERE contact_id = OLD.contact_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS touch_contact_on_occasions_insert AFTER INSERT ON occasions
+BEGIN
+    UPDATE contacts SET updated_at = strftime('%Y-%m-%d %H:%M:%f000', 'now')
+    WHERE contact_id = NEW.contact_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS touch_contact_on_occasions_update AFTER UPDATE ON occasions
+BEGIN
+    UPDATE contacts SET updated_at = strftime('%Y-%m-%d %H:%M:%f000', 'now')
+    WHERE contact_id IN (OLD.contact_id, NEW.contact_id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS touch_contact_on_occasions_delete AFTER DELETE ON occasions
+BEGIN
+    UPDATE contacts SET updated_at = strftime('%Y-%m-%d %H:%M:%f000', 'now')
+    WHERE contact_id = OLD.contact_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS touch_contacts_on_tags AFTER UPDATE ON tags
+BEGIN
+    UPDATE contacts SET updated_at = strftime('%Y-%m-%d %H:%M:%f000', 'now')
+    WHERE contact_id IN (SELECT contact_id FROM contact_tags WHERE tag_id = NEW.tag_id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS touch_contacts_on_interaction_tags_insert
+AFTER INSERT ON interaction_tags
+BEGIN
+    UPDATE contacts SET updated_at = strftime('%Y-%m-%d %H:%M:%f000', 'now')
+    WHERE contact_id IN (SELECT contact_id FROM interactions
+                         WHERE interaction_id = NEW.interaction_id
+                         UNION
+                         SELECT contact_id FROM interaction_participants
+                         WHERE interaction_id = NEW.interaction_id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS touch_contacts_on_interaction_tags_delete
+AFTER DELETE ON interaction_tags
+BEGIN
+    UPDATE contacts SET updated_at = strftime('%Y-%m-%d %H:%M:%f000', 'now')
+    WHERE contact_id IN (SELECT contact_id FROM interactions
+                         WHERE interaction_id = OLD.interaction_id
+                         UNION
+                         SELECT contact_id FROM interaction_participants
+                         WHERE interaction_id = OLD.interaction_id);
+END;
+
+-- contacts.last_interaction_at, as the Postgres refresh_last_interaction keeps it
+CREATE TRIGGER IF NOT EXISTS refresh_last_interaction_on_interactions_insert
+AFTER INSERT ON interactions
+BEGIN
+    UPDATE contacts SET last_interaction_at = (
+        SELECT MAX(i.interaction_date) FROM interactions i
+        WHERE i.contact_id = contacts.contact_id
+           OR i.interaction_id IN (SELECT interaction_id FROM interaction_participants p
+                                   WHERE p.contact_id = contacts.contact_id))
+    WHERE contact_id = NEW.contact_id
+       OR contact_id IN (SELECT contact_id FROM interaction_participants
+                         WHERE interaction_id = NEW.interaction_id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS refresh_last_interaction_on_interactions_update
+AFTER UPDATE OF contact_id, interaction_date ON interactions
+BEGIN
+    UPDATE contacts SET last_interaction_at = (
+        SELECT MAX(i.interaction_date) FROM interactions i
+        WHERE i.contact_id = contacts.contact_id
+           OR i.interaction_id IN (SELECT interaction_id FROM interaction_participants p
+                                   WHERE p.contact_id = contacts.contact_id))
+    WHERE contact_id IN (OLD.contact_id, NEW.contact_id)
+       OR contact_id IN (SELECT contact_id FROM interaction_participants
+                         WHERE interaction_id = NEW.interaction_id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS refresh_last_interaction_on_interactions_delete
+AFTER DELETE ON interactions
+BEGIN
+    UPDATE contacts SET last_interaction_at = (
+        SELECT MAX(i.interaction_date) FROM interactions i
+        WHERE i.contact_id = contacts.contact_id
+           OR i.interaction_id IN (SELECT interaction_id FROM interaction_participants p
+                                   WHERE p.contact_id = contacts.contact_id))
+    WHERE contact_id = OLD.contact_id
+       OR contact_id IN (SELECT contact_id FROM interaction_participants
+                         WHERE interaction_id = OLD.interaction_id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS refresh_last_interaction_on_interaction_participants_insert
+AFTER INSERT ON interaction_participants
+BEGIN
+    UPDATE contacts SET last_interaction_at = (
+        SELECT MAX(i.interaction_date) FROM interactions i
+        WHERE i.contact_id = contacts.contact_id
+           OR i.interaction_id IN (SELECT interaction_id FROM interaction_participants p
+                                   WHERE p.contact_id = contacts.contact_id))
+    WHERE contact_id = NEW.contact_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS refresh_last_interaction_on_interaction_participants_delete
+AFTER DELETE ON interaction_participants
+BEGIN
+    UPDATE contacts SET last_interaction_at = (
+        SELECT MAX(i.interaction_date) FROM interactions i
+        WHERE i.contact_id = contacts.contact_id
+           OR i.interaction_id IN (SELECT interaction_id FROM interaction_participants p
+                                   WHERE p.contact_id = contacts.contact_id))
+    WHERE contact_id = OLD.contact_id;
+END;
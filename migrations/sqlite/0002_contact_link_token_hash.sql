@@ -0,0 +1,6 @@
+-- Contact links keep only the SHA-256 of their token, so a leaked database file opens no one's
+-- card. SQLite can't hash the existing tokens, so those links are dropped and have to be made
+-- again.
+
+DELETE FROM contact_links;
+ALTER TABLE contact_links RENAME COLUMN token TO token_hash;
@@ -1,102 +0,0 @@
-CREATE TABLE IF NOT EXISTS users (
-    user_id SERIAL PRIMARY KEY,
-    auth0_id VARCHAR(100) UNIQUE NOT NULL,
-    name VARCHAR(100) NOT NULL,
-    email VARCHAR(100) UNIQUE NOT NULL,
-    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
-    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP 
-);
-
-CREATE TABLE IF NOT EXISTS contacts (
-    contact_id SERIAL PRIMARY KEY,
-    user_id INT NOT NULL,
-    FOREIGN KEY (user_id) REFERENCES users(user_id) ON DELETE CASCADE,
-    first_name VARCHAR(50),
-    last_name VARCHAR(50),
-    email VARCHAR(100) UNIQUE,
-    phone VARCHAR(20),
-    short_note VARCHAR(255),
-    notes TEXT,
-    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
-    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP 
-);
-
-CREATE TABLE IF NOT EXISTS tags (
-    tag_id SERIAL PRIMARY KEY,
-    user_id INT NOT NULL,
-    FOREIGN KEY (user_id) REFERENCES users(user_id) ON DELETE CASCADE,
-    name VARCHAR(50) UNIQUE NOT NULL,
-    details TEXT,
-    color VARCHAR(20),
-    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
-    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP 
-);
-
-CREATE TABLE IF NOT EXISTS contact_tags (
-    contact_id INT NOT NULL,
-    tag_id INT NOT NULL,
-    PRIMARY KEY (contact_id, tag_id),
-    FOREIGN KEY (contact_id) REFERENCES contacts(contact_id) ON DELETE CASCADE,
-    FOREIGN KEY (tag_id) REFERENCES tags(tag_id) ON DELETE CASCADE
-);
-
-CREATE TABLE IF NOT EXISTS interactions (
-    interaction_id SERIAL PRIMARY KEY,
-    user_id INT NOT NULL,
-    contact_id INT NOT NULL,
-    FOREIGN KEY (user_id) REFERENCES users(user_id) ON DELETE CASCADE,
-    FOREIGN KEY (contact_id) REFERENCES contacts(contact_id) ON DELETE CASCADE,
-    interaction_date TIMESTAMP NOT NULL,
-    notes TEXT,
-    followup_priority INT,
-    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
-    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP 
-);
-
-CREATE TABLE IF NOT EXISTS occasions (
-    occasion_id SERIAL PRIMARY KEY,
-    user_id INT NOT NULL,
-    contact_id INT NOT NULL,
-    FOREIGN KEY (user_id) REFERENCES users(user_id) ON DELETE CASCADE,
-    FOREIGN KEY (contact_id) REFERENCES contacts(contact_id) ON DELETE CASCADE,
-    name VARCHAR(100) NOT NULL,
-    date DATE NOT NULL,
-    recurring BOOLEAN DEFAULT FALSE,
-    recurring_interval INT,
-    details TEXT,
-    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
-    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP 
-);
-
-CREATE OR REPLACE FUNCTION update_updated_at_column()
-RETURNS TRIGGER AS $$
-BEGIN
-    NEW.updated_at = CURRENT_TIMESTAMP;
-    RETURN NEW;
-END;
-$$ LANGUAGE plpgsql;
-
-CREATE TRIGGER update_users_updated_at
-    BEFORE UPDATE ON users
-    FOR EACH ROW
-    EXECUTE FUNCTION update_updated_at_column();
-
-CREATE TRIGGER update_contacts_updated_at
-    BEFORE UPDATE ON contacts
-    FOR EACH ROW
-    EXECUTE FUNCTION update_updated_at_column();
-
-CREATE TRIGGER update_tags_updated_at
-    BEFORE UPDATE ON tags
-    FOR EACH ROW
-    EXECUTE FUNCTION update_updated_at_column();
-
-CREATE TRIGGER update_interactions_updated_at
-    BEFORE UPDATE ON interactions
-    FOR EACH ROW
-    EXECUTE FUNCTION update_updated_at_column();
-
-CREATE TRIGGER update_occasions_updated_at
-    BEFORE UPDATE ON occasions
-    FOR EACH ROW
-    EXECUTE FUNCTION update_updated_at_column();
\ No newline at end of file
@@ -0,0 +1,302 @@
+//! Account handlers
+//!
+//! `DELETE /account` deactivates the account and deletes it once ACCOUNT_DELETION_GRACE_DAYS
+//! have passed, so a mistaken or malicious deletion can be undone with `POST /account/restore`
+//! until then. The deletion itself is done by a background task.
+
+use crate::AuthUser;
+use crate::events::{self, Action, Entity, EventBus};
+use crate::jobs::Job;
+use crate::models::{UpdateProfileRequest, UserProfile, datetime_format};
+use crate::reauth::{self, Reauth};
+use crate::repository::{self, RepoResult, Repository};
+use crate::timezone;
+use crate::user_cache::UserCache;
+use actix_web::{
+    Error, FromRequest, HttpRequest, HttpResponse, Responder, delete, get, patch, post, web,
+};
+use async_trait::async_trait;
+use serde::Serialize;
+use std::time::Duration as StdDuration;
+use time::{Duration, OffsetDateTime};
+
+/// Days a deleted account can be restored, unless ACCOUNT_DELETION_GRACE_DAYS says otherwise
+pub const DEFAULT_DELETION_GRACE_DAYS: i64 = 30;
+
+/// How often the background task deletes accounts whose grace period is over
+pub const PURGE_INTERVAL: StdDuration = StdDuration::from_secs(60 * 60);
+
+/// Longest name or email the users table accepts
+const MAX_FIELD_LEN: usize = 100;
+
+/// Longest default reminder cadence, in days
+const MAX_CADENCE_DAYS: i32 = 365;
+
+#[derive(Debug, Clone, Copy)]
+pub struct DeletionGrace {
+    /// 0 deletes accounts at once
+    pub days: i64,
+}
+
+impl Default for DeletionGrace {
+    fn default() -> Self {
+        DeletionGrace {
+            days: DEFAULT_DELETION_GRACE_DAYS,
+        }
+    }
+}
+
+impl DeletionGrace {
+    /// Read ACCOUNT_DELETION_GRACE_DAYS, falling back to the default when it is unset,
+    /// unparsable or negative
+    pub fn from_env() -> Self {
+        std::env::var("ACCOUNT_DELETION_GRACE_DAYS")
+            .ok()
+            .and_then(|v| v.parse::<i64>().ok())
+            .filter(|days| *days >= 0)
+            .map_or_else(DeletionGrace::default, |days| DeletionGrace { days })
+    }
+
+    /// When an account deleted at `now` goes; None when it goes at once
+    pub fn delete_after(&self, now: OffsetDateTime) -> Option<OffsetDateTime> {
+        (self.days > 0).then(|| now + Duration::days(self.days))
+    }
+}
+
+/// The app's setting, or the default when none is registered
+pub fn configured(grace: Option<&web::Data<DeletionGrace>>) -> DeletionGrace {
+    grace.map_or_else(DeletionGrace::default, |grace| *grace.get_ref())
+}
+
+#[derive(Debug, Serialize)]
+pub struct ScheduledDeletion {
+    #[serde(with = "datetime_format")]
+    pub delete_after: OffsetDateTime,
+}
+
+/// Delete every account whose grace period is over at `now`, returning how many were deleted
+pub async fn purge_due(repo: &dyn Repository, now: OffsetDateTime) -> RepoResult<usize> {
+    Ok(repo.purge_deleted_users(now).await?.len())
+}
+
+/// Deletes due accounts every `PURGE_INTERVAL`
+pub struct PurgeDeletedAccounts;
+
+#[async_trait]
+impl Job for PurgeDeletedAccounts {
+    fn name(&self) -> &'static str {
+        "purge-deleted-accounts"
+    }
+
+    fn interval(&self) -> StdDuration {
+        PURGE_INTERVAL
+    }
+
+    async fn run(&self, repo: &dyn Repository) -> RepoResult<()> {
+        purge_due(repo, OffsetDateTime::now_utc()).await.map(|_| ())
+    }
+}
+
+/// Apply submitted changes to the current profile. Names are trimmed; errors are the JSON
+/// body of the 400 response.
+fn apply(
+    mut profile: UserProfile,
+    changes: UpdateProfileRequest,
+) -> Result<UserProfile, serde_json::Value> {
+    if let Some(name) = changes.name {
+        let name = name.trim();
+        if name.is_empty() || name.chars().count() > MAX_FIELD_LEN {
+            return Err(serde_json::json!({
+                "error": format!("Name must be 1 to {} characters", MAX_FIELD_LEN)
+            }));
+        }
+        profile.name = name.to_string();
+    }
+    if let Some(email) = changes.email {
+        let email = email.trim();
+        if !email.contains('@') || email.chars().count() > MAX_FIELD_LEN {
+            return Err(serde_json::json!({
+                "error": "Invalid email address"
+            }));
+        }
+        profile.email = email.to_string();
+    }
+    if let Some(timezone) = changes.timezone {
+        if let Some(name) = &timezone
+            && !timezone::is_known(name)
+        {
+            return Err(serde_json::json!({
+                "error": "Unknown timezone; use an IANA name such as \"Europe/Berlin\""
+            }));
+        }
+        profile.timezone = timezone;
+    }
+    if let Some(notifications) = changes.notifications {
+        profile.notifications = notifications;
+    }
+    if let Some(days) = changes.default_reminder_cadence_days {
+        if !(1..=MAX_CADENCE_DAYS).contains(&days) {
+            return Err(serde_json::json!({
+                "error": format!("Default reminder cadence must be 1 to {} days", MAX_CADENCE_DAYS)
+            }));
+        }
+        profile.default_reminder_cadence_days = days;
+    }
+    Ok(profile)
+}
+
+/// The authenticated user's name, email and settings
+#[get("/me")]
+pub async fn get_me(repo: web::Data<dyn Repository>, auth_user: AuthUser) -> impl Responder {
+    match repo.get_profile(auth_user.user_id).await {
+        Ok(Some(profile)) => HttpResponse::Ok().json(profile),
+        Ok(None) => HttpResponse::NotFound().body("User not found"),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to fetch profile")
+        }
+    }
+}
+
+/// Change any of the authenticated user's name, email and settings
+#[patch("/me")]
+pub async fn update_me(
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    users: Option<web::Data<UserCache>>,
+    auth_user: AuthUser,
+    changes: web::Json<UpdateProfileRequest>,
+) -> impl Responder {
+    let profile = match repo.get_profile(auth_user.user_id).await {
+        Ok(Some(profile)) => profile,
+        Ok(None) => return HttpResponse::NotFound().body("User not found"),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            return HttpResponse::InternalServerError().body("Failed to fetch profile");
+        }
+    };
+    let profile = match apply(profile, changes.into_inner()) {
+        Ok(profile) => profile,
+        Err(error) => return HttpResponse::BadRequest().json(error),
+    };
+
+    match repo.save_profile(&profile).await {
+        Ok(()) => {
+            if let Some(users) = users {
+                users.forget(&auth_user.auth0_id).await;
+            }
+            events::publish(
+                bus.as_ref(),
+                auth_user.user_id,
+                Entity::Account,
+                Action::Updated,
+                Vec::new(),
+            );
+            HttpResponse::Ok().json(profile)
+        }
+        Err(e) if repository::is_unique_violation(&e) => {
+            HttpResponse::Conflict().json(serde_json::json!({
+                "error": "Email is already in use"
+            }))
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to update profile")
+        }
+    }
+}
+
+/// The user behind a request, even if their account is scheduled for deletion, so they can
+/// restore it
+pub struct RestoringUser(pub AuthUser);
+
+impl FromRequest for RestoringUser {
+    type Error = Error;
+    type Future = std::pin::Pin<Box<dyn std::future::Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut actix_web::dev::Payload) -> Self::Future {
+        let user = crate::authenticate(req, true);
+        Box::pin(async move { Ok(RestoringUser(user.await?)) })
+    }
+}
+
+/// Delete the authenticated user's account and all associated data after the grace period,
+/// deactivating it until then; with no grace period it is deleted at once. Needs a recent
+/// sign-in.
+#[delete("/account")]
+pub async fn delete_account(
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    users: Option<web::Data<UserCache>>,
+    reauth: Option<web::Data<Reauth>>,
+    grace: Option<web::Data<DeletionGrace>>,
+    auth_user: AuthUser,
+) -> impl Responder {
+    if let Some(response) = reauth::configured(reauth.as_ref()).reject_stale(&auth_user) {
+        return response;
+    }
+
+    let delete_after = configured(grace.as_ref()).delete_after(OffsetDateTime::now_utc());
+    let result = match delete_after {
+        Some(delete_after) => {
+            repo.schedule_user_deletion(auth_user.user_id, delete_after)
+                .await
+        }
+        None => repo.delete_user(auth_user.user_id).await,
+    };
+    match result {
+        Ok(()) => {
+            if let Some(users) = users {
+                users.forget(&auth_user.auth0_id).await;
+            }
+            events::publish(
+                bus.as_ref(),
+                auth_user.user_id,
+                Entity::Account,
+                Action::Deleted,
+                Vec::new(),
+            );
+            match delete_after {
+                Some(delete_after) => {
+                    HttpResponse::Accepted().json(ScheduledDeletion { delete_after })
+                }
+                None => HttpResponse::NoContent().finish(),
+            }
+        }
+        Err(e) => {
+            eprintln!("Failed to delete account: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to delete account")
+        }
+    }
+}
+
+/// Cancel the account's scheduled deletion and reactivate it. Accounts that aren't scheduled
+/// for deletion are left as they are.
+#[post("/account/restore")]
+pub async fn restore_account(
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    users: Option<web::Data<UserCache>>,
+    RestoringUser(auth_user): RestoringUser,
+) -> impl Responder {
+    match repo.restore_user(auth_user.user_id).await {
+        Ok(false) => HttpResponse::NoContent().finish(),
+        Ok(true) => {
+            if let Some(users) = users {
+                users.forget(&auth_user.auth0_id).await;
+            }
+            events::publish(
+                bus.as_ref(),
+                auth_user.user_id,
+                Entity::Account,
+                Action::Updated,
+                Vec::new(),
+            );
+            HttpResponse::NoContent().finish()
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to restore account")
+        }
+    }
+}
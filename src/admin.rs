@@ -0,0 +1,134 @@
+//! Instance administration for self-hosted deployments shared by a few people.
+//!
+//! Admins are the users flagged `is_admin` in the users table and those whose Auth0 subject is
+//! listed in ADMIN_AUTH0_IDS (comma separated). They sign in like everyone else and can list
+//! the accounts, see instance-wide counts and deactivate users, who are then refused on every
+//! request until reactivated. /admin/maintenance is separate and uses ADMIN_TOKEN instead.
+
+use crate::AuthUser;
+use crate::repository::Repository;
+use crate::user_cache::UserCache;
+use actix_web::error::ErrorForbidden;
+use actix_web::{Error, FromRequest, HttpRequest, HttpResponse, Responder, get, post, web};
+use std::collections::HashSet;
+
+/// Auth0 subjects granted the admin role by configuration
+#[derive(Debug, Default)]
+pub struct Admins {
+    auth0_ids: HashSet<String>,
+}
+
+impl Admins {
+    pub fn new(auth0_ids: impl IntoIterator<Item = String>) -> Self {
+        Admins {
+            auth0_ids: auth0_ids.into_iter().filter(|id| !id.is_empty()).collect(),
+        }
+    }
+
+    /// Read ADMIN_AUTH0_IDS
+    pub fn from_env() -> Self {
+        let ids = std::env::var("ADMIN_AUTH0_IDS").unwrap_or_default();
+        Admins::new(ids.split(',').map(|id| id.trim().to_string()))
+    }
+
+    pub fn includes(&self, user: &AuthUser) -> bool {
+        user.is_admin || self.auth0_ids.contains(&user.auth0_id)
+    }
+}
+
+/// An authenticated user with the admin role; anyone else gets 403
+pub struct AdminUser(pub AuthUser);
+
+impl FromRequest for AdminUser {
+    type Error = Error;
+    type Future = std::pin::Pin<Box<dyn std::future::Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut actix_web::dev::Payload) -> Self::Future {
+        let user = AuthUser::from_request(req, payload);
+        let admins = req.app_data::<web::Data<Admins>>().cloned();
+
+        Box::pin(async move {
+            let user = user.await?;
+            let is_admin = match &admins {
+                Some(admins) => admins.includes(&user),
+                None => user.is_admin,
+            };
+            if !is_admin {
+                return Err(ErrorForbidden("Admin role required"));
+            }
+            Ok(AdminUser(user))
+        })
+    }
+}
+
+/// Every account, by user id
+#[get("/admin/users")]
+pub async fn list_users(repo: web::Data<dyn Repository>, _admin: AdminUser) -> impl Responder {
+    match repo.list_accounts().await {
+        Ok(accounts) => HttpResponse::Ok().json(accounts),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to fetch users")
+        }
+    }
+}
+
+#[get("/admin/stats")]
+pub async fn instance_stats(repo: web::Data<dyn Repository>, _admin: AdminUser) -> impl Responder {
+    match repo.instance_stats().await {
+        Ok(stats) => HttpResponse::Ok().json(stats),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to fetch stats")
+        }
+    }
+}
+
+async fn set_deactivated(
+    repo: &dyn Repository,
+    users: Option<web::Data<UserCache>>,
+    user_id: i32,
+    deactivated: bool,
+) -> HttpResponse {
+    match repo.set_deactivated(user_id, deactivated).await {
+        Ok(Some(auth0_id)) => {
+            // Cached users would keep getting in until their entry expired
+            if let Some(users) = users {
+                users.forget(&auth0_id).await;
+            }
+            HttpResponse::NoContent().finish()
+        }
+        Ok(None) => HttpResponse::NotFound().body("User not found"),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to update user")
+        }
+    }
+}
+
+/// Refuse every further request from the user. Their data is kept.
+#[post("/admin/users/{id}/deactivate")]
+pub async fn deactivate_user(
+    repo: web::Data<dyn Repository>,
+    users: Option<web::Data<UserCache>>,
+    admin: AdminUser,
+    user_id: web::Path<i32>,
+) -> impl Responder {
+    let user_id = user_id.into_inner();
+    if user_id == admin.0.user_id {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Admins cannot deactivate themselves"
+        }));
+    }
+    set_deactivated(repo.get_ref(), users, user_id, true).await
+}
+
+#[post("/admin/users/{id}/reactivate")]
+pub async fn reactivate_user(
+    repo: web::Data<dyn Repository>,
+    users: Option<web::Data<UserCache>>,
+    _admin: AdminUser,
+    user_id: web::Path<i32>,
+) -> impl Responder {
+    set_deactivated(repo.get_ref(), users, user_id.into_inner(), false).await
+}
@@ -0,0 +1,271 @@
+//! Interaction attachment handlers.
+//!
+//! Files are uploaded as the raw request body, with their type in Content-Type and their name in
+//! `?file_name=`, and stored in the database next to the interaction. Voice memos uploaded with
+//! `?transcribe=true` are transcribed into the interaction's notes in the background; see
+//! `transcription`.
+
+use crate::AuthUser;
+use crate::events::{self, Action, Entity, EventBus};
+use crate::limits::{self, Limits};
+use crate::models::NewAttachment;
+use crate::mutations;
+use crate::quotas::{self, Quotas};
+use crate::repository::Repository;
+use crate::routes::{Owned, OwnedInteraction, Resource, ensure_owned};
+use crate::transcription::{self, Transcription};
+use actix_web::http::header::{
+    self, Charset, ContentDisposition, DispositionParam, DispositionType, ExtendedValue,
+};
+use actix_web::{HttpRequest, HttpResponse, Responder, delete, get, mime, post, web};
+use futures::StreamExt;
+use serde::Deserialize;
+
+/// Longest file name and content type the interaction_attachments table accepts
+const MAX_FILE_NAME_LEN: usize = 255;
+const MAX_CONTENT_TYPE_LEN: usize = 100;
+
+/// Name given to uploads that don't say theirs
+const DEFAULT_FILE_NAME: &str = "attachment";
+
+/// The file name to store: the last component of what the client sent, without control
+/// characters, or `DEFAULT_FILE_NAME`
+pub fn file_name(requested: Option<&str>) -> String {
+    let name: String = requested
+        .unwrap_or_default()
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_FILE_NAME_LEN)
+        .collect();
+    match name.trim() {
+        "" | "." | ".." => DEFAULT_FILE_NAME.to_string(),
+        name => name.to_string(),
+    }
+}
+
+#[get("/interactions/{id}/attachments")]
+pub async fn list_attachments(
+    repo: web::Data<dyn Repository>,
+    auth_user: AuthUser,
+    Owned(interaction): OwnedInteraction,
+) -> impl Responder {
+    match repo
+        .list_attachments(auth_user.workspace_id, interaction.interaction_id)
+        .await
+    {
+        Ok(attachments) => HttpResponse::Ok().json(attachments),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to fetch attachments")
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct UploadQuery {
+    file_name: Option<String>,
+    /// Transcribe the audio into the interaction's notes
+    #[serde(default)]
+    transcribe: bool,
+}
+
+/// Attach the request body to an interaction. Files over MAX_ATTACHMENT_BYTES get 413, and
+/// files that would take the user over QUOTA_MAX_ATTACHMENT_BYTES get 402.
+/// `?transcribe=true` needs an audio/* file and a configured transcriber.
+#[allow(clippy::too_many_arguments)]
+#[post("/interactions/{id}/attachments")]
+pub async fn upload_attachment(
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    limits: Option<web::Data<Limits>>,
+    quotas: Option<web::Data<Quotas>>,
+    transcription: Option<web::Data<Transcription>>,
+    auth_user: AuthUser,
+    Owned(interaction): OwnedInteraction,
+    query: web::Query<UploadQuery>,
+    req: HttpRequest,
+    mut payload: web::Payload,
+) -> impl Responder {
+    let id = interaction.interaction_id;
+
+    let content_type = match req.headers().get(header::CONTENT_TYPE) {
+        None => mime::APPLICATION_OCTET_STREAM.to_string(),
+        Some(value) => match value
+            .to_str()
+            .ok()
+            .and_then(|v| v.parse::<mime::Mime>().ok())
+        {
+            Some(mime) if mime.as_ref().len() <= MAX_CONTENT_TYPE_LEN => mime.to_string(),
+            _ => {
+                return HttpResponse::BadRequest().json(serde_json::json!({
+                    "error": format!(
+                        "Content-Type must be a media type of at most {} characters",
+                        MAX_CONTENT_TYPE_LEN
+                    )
+                }));
+            }
+        },
+    };
+    if query.transcribe {
+        if transcription.is_none() {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Transcription is not configured on this server"
+            }));
+        }
+        if !transcription::is_audio(&content_type) {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Only audio/* files can be transcribed"
+            }));
+        }
+    }
+
+    let limit = limits::configured(limits.as_ref()).max_attachment_bytes;
+    let mut data = Vec::new();
+    while let Some(chunk) = payload.next().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                return HttpResponse::BadRequest().json(serde_json::json!({
+                    "error": format!("Failed to read the upload: {}", e)
+                }));
+            }
+        };
+        if data.len() + chunk.len() > limit {
+            return limits::payload_too_large(limit);
+        }
+        data.extend_from_slice(&chunk);
+    }
+    if data.is_empty() {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "The uploaded file is empty"
+        }));
+    }
+    if let Some(response) = quotas::configured(quotas.as_ref())
+        .reject_attachment(repo.get_ref(), &auth_user, data.len())
+        .await
+    {
+        return response;
+    }
+
+    let attachment = NewAttachment {
+        file_name: file_name(query.file_name.as_deref()),
+        content_type,
+        data,
+        transcribe: query.transcribe,
+    };
+    match repo
+        .create_attachment(auth_user.workspace_id, id, &attachment)
+        .await
+    {
+        Ok(attachment_id) => {
+            events::publish(
+                bus.as_ref(),
+                auth_user.user_id,
+                Entity::Interaction,
+                Action::Updated,
+                vec![id],
+            );
+            let saved =
+                repo.list_attachments(auth_user.workspace_id, id)
+                    .await
+                    .map(|attachments| {
+                        attachments
+                            .into_iter()
+                            .find(|a| a.attachment_id == attachment_id)
+                    });
+            // The attachment lives at /attachments/{id}, under the same scope as this route
+            let scope = req
+                .path()
+                .rsplit_once("/interactions/")
+                .map_or("", |(scope, _)| scope);
+            mutations::created_in(&format!("{}/attachments", scope), attachment_id, saved)
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to upload attachment")
+        }
+    }
+}
+
+/// Download an attachment's file, with the type and name it was uploaded with
+#[get("/attachments/{id}")]
+pub async fn download_attachment(
+    repo: web::Data<dyn Repository>,
+    auth_user: AuthUser,
+    attachment_id: web::Path<i32>,
+) -> impl Responder {
+    let id = attachment_id.into_inner();
+
+    if let Err(response) = ensure_owned(repo.get_ref(), &auth_user, Resource::Attachment, id).await
+    {
+        return response;
+    }
+
+    match repo.attachment_file(auth_user.workspace_id, id).await {
+        Ok(Some(file)) => {
+            // Clients that don't read filename* get the name with non-ASCII characters replaced
+            let mut parameters = vec![DispositionParam::Filename(
+                file.file_name
+                    .chars()
+                    .map(|c| if c.is_ascii() { c } else { '_' })
+                    .collect(),
+            )];
+            if !file.file_name.is_ascii() {
+                parameters.push(DispositionParam::FilenameExt(ExtendedValue {
+                    charset: Charset::Ext("UTF-8".to_string()),
+                    language_tag: None,
+                    value: file.file_name.into_bytes(),
+                }));
+            }
+            HttpResponse::Ok()
+                .insert_header(ContentDisposition {
+                    disposition: DispositionType::Attachment,
+                    parameters,
+                })
+                .insert_header((header::CONTENT_TYPE, file.content_type))
+                .body(file.data)
+        }
+        Ok(None) => HttpResponse::NotFound().body("Attachment not found"),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to fetch attachment")
+        }
+    }
+}
+
+/// Delete an attachment. A transcript already in the interaction's notes stays there.
+#[delete("/attachments/{id}")]
+pub async fn delete_attachment(
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    auth_user: AuthUser,
+    attachment_id: web::Path<i32>,
+) -> impl Responder {
+    let id = attachment_id.into_inner();
+
+    if let Err(response) = ensure_owned(repo.get_ref(), &auth_user, Resource::Attachment, id).await
+    {
+        return response;
+    }
+
+    match repo.delete_attachment(auth_user.workspace_id, id).await {
+        Ok(Some(interaction_id)) => {
+            events::publish(
+                bus.as_ref(),
+                auth_user.user_id,
+                Entity::Interaction,
+                Action::Updated,
+                vec![interaction_id],
+            );
+            HttpResponse::Ok().body("Attachment deleted successfully")
+        }
+        Ok(None) => HttpResponse::NotFound().body("Attachment not found"),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to delete attachment")
+        }
+    }
+}
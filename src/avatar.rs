@@ -0,0 +1,105 @@
+//! Gravatar fallback for contacts without an uploaded avatar.
+//! Lookups happen server-side so clients get a final URL (or nothing) instead of probing
+//! Gravatar themselves, and results are cached so each address is checked at most once a day.
+
+use crate::models::{AvatarSource, ContactResponse};
+use futures::stream::{self, StreamExt};
+use moka::future::Cache;
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+
+/// Gravatar lookups running at once while building a contact list
+const CONCURRENT_LOOKUPS: usize = 8;
+
+/// Gravatar's key for an email address: SHA-256 of the trimmed, lowercased address
+pub fn gravatar_hash(email: &str) -> String {
+    format!("{:x}", Sha256::digest(email.trim().to_lowercase()))
+}
+
+pub struct GravatarResolver {
+    client: reqwest::Client,
+    /// hash -> avatar URL, or None when the address has no Gravatar
+    cache: Cache<String, Option<String>>,
+}
+
+impl GravatarResolver {
+    pub fn new() -> Self {
+        GravatarResolver {
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(2))
+                .build()
+                .expect("Failed to build HTTP client"),
+            cache: Cache::builder()
+                .max_capacity(100_000)
+                .time_to_live(Duration::from_secs(24 * 3600))
+                .build(),
+        }
+    }
+
+    /// The resolver if `GRAVATAR_FALLBACK` is set to `true`. Off by default because it sends
+    /// hashed contact emails to a third party.
+    pub fn from_env() -> Option<Self> {
+        let enabled = std::env::var("GRAVATAR_FALLBACK")
+            .map(|v| v.eq_ignore_ascii_case("true"))
+            .unwrap_or(false);
+        enabled.then(GravatarResolver::new)
+    }
+
+    /// Gravatar URL for `email`, if one exists. Failed lookups are not cached.
+    pub async fn lookup(&self, email: &str) -> Option<String> {
+        self.try_lookup(email).await.unwrap_or_else(|e| {
+            eprintln!("Gravatar lookup failed: {}", e);
+            None
+        })
+    }
+
+    /// Like `lookup`, telling a failed lookup apart from an address without a Gravatar
+    pub async fn try_lookup(&self, email: &str) -> Result<Option<String>, String> {
+        let hash = gravatar_hash(email);
+        if let Some(cached) = self.cache.get(&hash).await {
+            return Ok(cached);
+        }
+
+        let url = format!("https://gravatar.com/avatar/{}", hash);
+        let response = self
+            .client
+            .head(format!("{}?d=404", url))
+            .send()
+            .await
+            .map_err(|e| format!("Request failed: {:?}", e))?;
+
+        let found = if response.status().is_success() {
+            Some(url)
+        } else if response.status() == reqwest::StatusCode::NOT_FOUND {
+            None
+        } else {
+            return Err(format!("Returned status {}", response.status()));
+        };
+        self.cache.insert(hash, found.clone()).await;
+        Ok(found)
+    }
+
+    /// Fill in a Gravatar for each contact that has an email but no uploaded avatar
+    pub async fn fill(&self, contacts: &mut [ContactResponse]) {
+        let pending: Vec<&mut ContactResponse> = contacts
+            .iter_mut()
+            .filter(|c| c.avatar_url.is_none() && c.contact.email.is_some())
+            .collect();
+
+        stream::iter(pending)
+            .for_each_concurrent(CONCURRENT_LOOKUPS, |response| async move {
+                let email = response.contact.email.as_deref().unwrap_or_default();
+                if let Some(url) = self.lookup(email).await {
+                    response.avatar_url = Some(url);
+                    response.avatar_source = Some(AvatarSource::Gravatar);
+                }
+            })
+            .await;
+    }
+}
+
+impl Default for GravatarResolver {
+    fn default() -> Self {
+        GravatarResolver::new()
+    }
+}
@@ -0,0 +1,45 @@
+//! Exponential backoff after failures of an upstream service
+
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    /// Consecutive failures since the last success
+    failures: u32,
+    until: Option<Instant>,
+}
+
+impl Backoff {
+    pub const fn new(initial: Duration, max: Duration) -> Self {
+        Backoff {
+            initial,
+            max,
+            failures: 0,
+            until: None,
+        }
+    }
+
+    /// Time left before the service should be tried again, None if it can be tried now
+    pub fn remaining(&self, now: Instant) -> Option<Duration> {
+        self.until
+            .map(|until| until.saturating_duration_since(now))
+            .filter(|left| !left.is_zero())
+    }
+
+    /// Record a failure at `now`, doubling the wait after each one up to the maximum
+    pub fn failed(&mut self, now: Instant) {
+        let wait = self
+            .initial
+            .saturating_mul(2u32.saturating_pow(self.failures))
+            .min(self.max);
+        self.failures = self.failures.saturating_add(1);
+        self.until = Some(now + wait);
+    }
+
+    pub fn succeeded(&mut self) {
+        self.failures = 0;
+        self.until = None;
+    }
+}
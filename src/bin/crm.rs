@@ -0,0 +1,23 @@
+//! `crm`: add contacts, log interactions, see who is due and export from the terminal. See
+//! `personal_crm::cli`.
+
+use personal_crm::cli::{Client, Command};
+
+#[tokio::main]
+async fn main() {
+    let command = Command::from_args(std::env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(2);
+    });
+    let client = Client::from_env().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(2);
+    });
+    match client.run(command).await {
+        Ok(output) => println!("{}", output),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
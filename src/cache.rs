@@ -0,0 +1,192 @@
+//! Where the token, JWKS and user caches keep their entries.
+//!
+//! By default each server process keeps its own in memory. With REDIS_URL set they live in
+//! Redis instead, so replicas behind a load balancer share them: a token validated by one is
+//! trusted by the others, and a user forgotten after an account change is forgotten by all of
+//! them. Redis being unavailable makes lookups miss, so requests fall back to Auth0 and the
+//! database rather than failing.
+
+use async_trait::async_trait;
+use moka::Expiry;
+use redis::AsyncCommands;
+use redis::aio::{ConnectionManager, ConnectionManagerConfig};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::marker::PhantomData;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+/// Longest a Redis connection or command may take before the lookup counts as a miss
+const REDIS_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Entries with a time to live, as strings
+#[async_trait]
+pub trait CacheStore: Send + Sync {
+    async fn get(&self, key: &str) -> Option<String>;
+    async fn insert(&self, key: &str, value: String, ttl: Duration);
+    async fn remove(&self, key: &str);
+}
+
+/// Keeps each entry for the time to live it was inserted with
+struct EntryExpiry;
+
+impl Expiry<String, (String, Duration)> for EntryExpiry {
+    fn expire_after_create(
+        &self,
+        _key: &String,
+        (_, ttl): &(String, Duration),
+        _created_at: Instant,
+    ) -> Option<Duration> {
+        Some(*ttl)
+    }
+}
+
+/// This process's memory, the default
+pub struct MemoryStore {
+    entries: moka::future::Cache<String, (String, Duration)>,
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        MemoryStore {
+            entries: moka::future::Cache::builder()
+                .expire_after(EntryExpiry)
+                .max_capacity(25_000)
+                .build(),
+        }
+    }
+}
+
+#[async_trait]
+impl CacheStore for MemoryStore {
+    async fn get(&self, key: &str) -> Option<String> {
+        self.entries.get(key).await.map(|(value, _)| value)
+    }
+
+    async fn insert(&self, key: &str, value: String, ttl: Duration) {
+        self.entries.insert(key.to_string(), (value, ttl)).await;
+    }
+
+    async fn remove(&self, key: &str) {
+        self.entries.invalidate(key).await;
+    }
+}
+
+/// A Redis server shared by every replica
+pub struct RedisStore {
+    connection: ConnectionManager,
+}
+
+impl RedisStore {
+    pub async fn connect(url: &str) -> Result<Self, String> {
+        let client =
+            redis::Client::open(url).map_err(|e| format!("REDIS_URL is not valid: {}", e))?;
+        let config = ConnectionManagerConfig::new()
+            .set_connection_timeout(Some(REDIS_TIMEOUT))
+            .set_response_timeout(Some(REDIS_TIMEOUT));
+        let connection = ConnectionManager::new_with_config(client, config)
+            .await
+            .map_err(|e| format!("Failed to connect to Redis: {}", e))?;
+        Ok(RedisStore { connection })
+    }
+
+    /// The store for REDIS_URL; None when it is unset
+    pub async fn from_env() -> Result<Option<Self>, String> {
+        match std::env::var("REDIS_URL") {
+            Ok(url) if !url.trim().is_empty() => RedisStore::connect(url.trim()).await.map(Some),
+            _ => Ok(None),
+        }
+    }
+}
+
+#[async_trait]
+impl CacheStore for RedisStore {
+    async fn get(&self, key: &str) -> Option<String> {
+        let mut connection = self.connection.clone();
+        connection
+            .get(key)
+            .await
+            .inspect_err(|e| eprintln!("Redis GET failed: {}", e))
+            .ok()
+            .flatten()
+    }
+
+    async fn insert(&self, key: &str, value: String, ttl: Duration) {
+        let millis = ttl.as_millis().clamp(1, u64::MAX as u128) as u64;
+        let mut connection = self.connection.clone();
+        if let Err(e) = connection.pset_ex::<_, _, ()>(key, value, millis).await {
+            eprintln!("Redis SET failed: {}", e);
+        }
+    }
+
+    async fn remove(&self, key: &str) {
+        let mut connection = self.connection.clone();
+        if let Err(e) = connection.del::<_, usize>(key).await {
+            eprintln!("Redis DEL failed: {}", e);
+        }
+    }
+}
+
+static STORE: OnceLock<Box<dyn CacheStore>> = OnceLock::new();
+
+/// Keep the caches in `store` from now on. Must be called before the first lookup; fails
+/// once a store is in use.
+pub fn install(store: Box<dyn CacheStore>) -> Result<(), String> {
+    STORE
+        .set(store)
+        .map_err(|_| "A cache store is already in use".to_string())
+}
+
+/// The installed store, else a `MemoryStore`
+pub fn store() -> &'static dyn CacheStore {
+    STORE
+        .get_or_init(|| Box::new(MemoryStore::default()))
+        .as_ref()
+}
+
+/// Values of one kind in the installed store, as JSON under keys starting with `prefix`
+pub struct SharedCache<V> {
+    prefix: &'static str,
+    ttl: Duration,
+    values: PhantomData<fn() -> V>,
+}
+
+impl<V: Serialize + DeserializeOwned> SharedCache<V> {
+    /// `ttl` is how long entries are kept unless `insert_for` says otherwise
+    pub const fn new(prefix: &'static str, ttl: Duration) -> Self {
+        SharedCache {
+            prefix,
+            ttl,
+            values: PhantomData,
+        }
+    }
+
+    fn key(&self, key: &str) -> String {
+        format!("crm:{}:{}", self.prefix, key)
+    }
+
+    /// Entries that no longer parse, say after an upgrade, are misses
+    pub async fn get(&self, key: &str) -> Option<V> {
+        let json = store().get(&self.key(key)).await?;
+        serde_json::from_str(&json).ok()
+    }
+
+    pub async fn insert(&self, key: &str, value: &V) {
+        self.insert_for(key, value, self.ttl).await;
+    }
+
+    /// Keep the entry for `ttl` rather than the cache's own; nothing is kept for zero
+    pub async fn insert_for(&self, key: &str, value: &V, ttl: Duration) {
+        if ttl.is_zero() {
+            return;
+        }
+        let Ok(json) = serde_json::to_string(value) else {
+            return;
+        };
+        store().insert(&self.key(key), json, ttl).await;
+    }
+
+    pub async fn remove(&self, key: &str) {
+        store().remove(&self.key(key)).await;
+    }
+}
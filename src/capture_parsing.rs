@@ -0,0 +1,514 @@
+//! Suggestions for resolving inbox captures.
+//!
+//! Reads "lunch w/ Priya yesterday" and proposes the contact, date, type and notes to resolve
+//! the capture with; nothing changes until the user resolves it. Suggestions come from simple
+//! rules unless `CAPTURE_PARSER_URL` points at an OpenAI-compatible chat completions API, hosted
+//! or local (Ollama, llama.cpp), in which case a model reads the text and the rules only step
+//! in when it fails. Other providers plug in by implementing `CaptureParser`.
+
+use crate::AuthUser;
+use crate::inbox::MAX_CAPTURE_CHARS;
+use crate::interactions::BUILTIN_INTERACTION_TYPES;
+use crate::models::{CaptureSuggestion, Contact};
+use crate::repository::Repository;
+use crate::timezone::LocalDates;
+use actix_web::{HttpResponse, Responder, get, web};
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::time::Duration as StdDuration;
+use time::macros::format_description;
+use time::{Date, Duration, Weekday};
+
+/// Most contacts named in a prompt
+pub const MAX_PROMPT_CONTACTS: usize = 50;
+
+/// Oldest date a suggestion may have, in days before the capture
+const MAX_AGE_DAYS: i64 = 366;
+
+const ISO_DATE: &[time::format_description::BorrowedFormatItem<'static>] =
+    format_description!("[year]-[month]-[day]");
+
+/// Words that give away a built-in interaction type
+const TYPE_KEYWORDS: &[(&str, &str)] = &[
+    ("called", "call"),
+    ("phoned", "call"),
+    ("rang", "call"),
+    ("emailed", "email"),
+    ("texted", "message"),
+    ("messaged", "message"),
+    ("dm", "message"),
+    ("whatsapp", "message"),
+    ("met", "meeting"),
+    ("lunch", "meeting"),
+    ("dinner", "meeting"),
+    ("breakfast", "meeting"),
+    ("coffee", "meeting"),
+    ("drinks", "meeting"),
+];
+
+/// What a parser knows besides the text
+pub struct ParseContext {
+    /// The user's calendar day the text was captured on; "yesterday" counts back from it
+    pub captured_on: Date,
+    /// The workspace's contacts, any of which the text may be about
+    pub contacts: Vec<Contact>,
+    /// Built-in and custom interaction type names
+    pub interaction_types: Vec<String>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ParsedCapture {
+    pub contact_id: Option<i32>,
+    pub date: Option<Date>,
+    pub notes: Option<String>,
+    pub interaction_type: Option<String>,
+}
+
+#[async_trait]
+pub trait CaptureParser: Send + Sync {
+    /// Reported as the suggestion's `source`
+    fn name(&self) -> &'static str;
+
+    /// None when the text couldn't be read, e.g. because the provider is down
+    async fn parse(&self, text: &str, context: &ParseContext) -> Option<ParsedCapture>;
+}
+
+/// Lowercased runs of letters and digits
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+fn contains_words(haystack: &[String], needle: &[String]) -> bool {
+    !needle.is_empty() && haystack.windows(needle.len()).any(|w| w == needle)
+}
+
+fn full_name(contact: &Contact) -> String {
+    [&contact.first_name, &contact.last_name]
+        .into_iter()
+        .flatten()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The one contact named in full, or failing that the one whose first name is used. Several
+/// matches are left for the user to pick from.
+fn match_contact(text: &[String], contacts: &[Contact]) -> Option<i32> {
+    let only = |ids: Vec<i32>| match ids[..] {
+        [id] => Some(id),
+        _ => None,
+    };
+    let full: Vec<i32> = contacts
+        .iter()
+        .filter(|c| contains_words(text, &words(&full_name(c))))
+        .map(|c| c.contact_id)
+        .collect();
+    if !full.is_empty() {
+        return only(full);
+    }
+    only(
+        contacts
+            .iter()
+            .filter(|c| {
+                c.first_name
+                    .as_ref()
+                    .is_some_and(|first| contains_words(text, &words(first)))
+            })
+            .map(|c| c.contact_id)
+            .collect(),
+    )
+}
+
+fn weekday(word: &str) -> Option<Weekday> {
+    Some(match word {
+        "monday" => Weekday::Monday,
+        "tuesday" => Weekday::Tuesday,
+        "wednesday" => Weekday::Wednesday,
+        "thursday" => Weekday::Thursday,
+        "friday" => Weekday::Friday,
+        "saturday" => Weekday::Saturday,
+        "sunday" => Weekday::Sunday,
+        _ => return None,
+    })
+}
+
+/// The latest `weekday` on or, when `before` is set, before `day`
+fn latest(weekday: Weekday, day: Date, before: bool) -> Date {
+    let mut date = if before { day - Duration::days(1) } else { day };
+    while date.weekday() != weekday {
+        date -= Duration::days(1);
+    }
+    date
+}
+
+/// The first date the text mentions: an ISO date, "today", "yesterday", "last night",
+/// "3 days ago", "2 weeks ago", "friday" or "last friday"
+fn match_date(raw: &str, text: &[String], today: Date) -> Option<Date> {
+    let iso = raw.split_whitespace().find_map(|token| {
+        let token = token.trim_matches(|c: char| !c.is_ascii_digit());
+        Date::parse(token, ISO_DATE).ok()
+    });
+    if iso.is_some() {
+        return iso;
+    }
+
+    for (i, word) in text.iter().enumerate() {
+        let previous = i.checked_sub(1).map(|p| text[p].as_str());
+        match word.as_str() {
+            "today" | "tonight" => return Some(today),
+            "yesterday" => return Some(today - Duration::days(1)),
+            "night" if previous == Some("last") => return Some(today - Duration::days(1)),
+            "ago" if i >= 2 => {
+                let Ok(n) = text[i - 2].parse::<i64>() else {
+                    continue;
+                };
+                match text[i - 1].as_str() {
+                    "day" | "days" => return Some(today - Duration::days(n.min(MAX_AGE_DAYS))),
+                    "week" | "weeks" => {
+                        return Some(today - Duration::days(n.saturating_mul(7).min(MAX_AGE_DAYS)));
+                    }
+                    _ => {}
+                }
+            }
+            _ => {
+                if let Some(weekday) = weekday(word) {
+                    return Some(latest(weekday, today, previous == Some("last")));
+                }
+            }
+        }
+    }
+    None
+}
+
+/// A type named in the text, such as a custom "Dinner", or else one a keyword implies
+fn match_type(text: &[String], interaction_types: &[String]) -> Option<String> {
+    if let Some(named) = interaction_types
+        .iter()
+        .find(|t| contains_words(text, &words(t)))
+    {
+        return Some(named.clone());
+    }
+    TYPE_KEYWORDS
+        .iter()
+        .find(|(keyword, _)| text.iter().any(|w| w == keyword))
+        .map(|(_, t)| t.to_string())
+}
+
+/// Keyword and name matching, always available
+pub struct RuleParser;
+
+impl RuleParser {
+    pub fn parse_text(text: &str, context: &ParseContext) -> ParsedCapture {
+        let lowered = words(text);
+        ParsedCapture {
+            contact_id: match_contact(&lowered, &context.contacts),
+            date: match_date(text, &lowered, context.captured_on),
+            notes: Some(text.trim().to_string()),
+            interaction_type: match_type(&lowered, &context.interaction_types),
+        }
+    }
+}
+
+#[async_trait]
+impl CaptureParser for RuleParser {
+    fn name(&self) -> &'static str {
+        "rules"
+    }
+
+    async fn parse(&self, text: &str, context: &ParseContext) -> Option<ParsedCapture> {
+        Some(RuleParser::parse_text(text, context))
+    }
+}
+
+/// Contacts worth naming in a prompt: those with a name word that starts like a word of the
+/// text, so "Pri" finds Priya without sending the whole address book
+pub fn candidates<'a>(text: &str, contacts: &'a [Contact]) -> Vec<&'a Contact> {
+    let text = words(text);
+    let alike = |a: &str, b: &str| {
+        a == b
+            || (a.chars().count() >= 3
+                && b.chars().count() >= 3
+                && a.chars().take(3).eq(b.chars().take(3)))
+    };
+    contacts
+        .iter()
+        .filter(|c| {
+            words(&full_name(c))
+                .iter()
+                .any(|name| text.iter().any(|w| alike(w, name)))
+        })
+        .take(MAX_PROMPT_CONTACTS)
+        .collect()
+}
+
+const SYSTEM_PROMPT: &str = "You turn a short note about keeping in touch with someone into \
+an entry for a personal CRM. Reply with only a JSON object with these keys: \"contact_id\", \
+the id of the contact from the list the note is about, or null if none fits; \"date\", the day \
+it happened as YYYY-MM-DD, or null if the note doesn't say; \"interaction_type\", one of the \
+listed types, or null; \"notes\", what to remember from the note in a sentence or two.";
+
+/// The user message: the day, the candidate contacts and types, and the text
+pub fn prompt(text: &str, context: &ParseContext) -> String {
+    let contacts: Vec<String> = candidates(text, &context.contacts)
+        .into_iter()
+        .map(|c| format!("{}: {}", c.contact_id, full_name(c)))
+        .collect();
+    format!(
+        "Today is {} ({}).\nContacts:\n{}\nInteraction types: {}\n\nNote: {}",
+        context.captured_on,
+        context.captured_on.weekday(),
+        contacts.join("\n"),
+        context.interaction_types.join(", "),
+        text.trim()
+    )
+}
+
+#[derive(Deserialize)]
+struct Completion {
+    choices: Vec<Choice>,
+}
+
+#[derive(Deserialize)]
+struct Choice {
+    message: Message,
+}
+
+#[derive(Deserialize)]
+struct Message {
+    content: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Answer {
+    contact_id: Option<i32>,
+    date: Option<String>,
+    interaction_type: Option<String>,
+    notes: Option<String>,
+}
+
+/// A model behind an OpenAI-compatible chat completions endpoint
+pub struct LlmParser {
+    client: reqwest::Client,
+    url: String,
+    model: String,
+    api_key: Option<String>,
+}
+
+impl LlmParser {
+    pub fn new(url: String, model: String, api_key: Option<String>) -> Self {
+        LlmParser {
+            client: reqwest::Client::builder()
+                .timeout(StdDuration::from_secs(10))
+                .build()
+                .expect("Failed to build HTTP client"),
+            url,
+            model,
+            api_key,
+        }
+    }
+
+    /// The parser if CAPTURE_PARSER_URL and CAPTURE_PARSER_MODEL are set, with
+    /// CAPTURE_PARSER_API_KEY sent as a bearer token when there is one. Off by default because
+    /// capture text and the names of matching contacts go to the provider.
+    pub fn from_env() -> Option<Self> {
+        let url = std::env::var("CAPTURE_PARSER_URL").ok()?;
+        let Ok(model) = std::env::var("CAPTURE_PARSER_MODEL") else {
+            eprintln!("CAPTURE_PARSER_URL is set without CAPTURE_PARSER_MODEL; using rules");
+            return None;
+        };
+        Some(LlmParser::new(
+            url,
+            model,
+            std::env::var("CAPTURE_PARSER_API_KEY").ok(),
+        ))
+    }
+}
+
+#[async_trait]
+impl CaptureParser for LlmParser {
+    fn name(&self) -> &'static str {
+        "llm"
+    }
+
+    async fn parse(&self, text: &str, context: &ParseContext) -> Option<ParsedCapture> {
+        let body = serde_json::json!({
+            "model": self.model,
+            "temperature": 0,
+            "response_format": { "type": "json_object" },
+            "messages": [
+                { "role": "system", "content": SYSTEM_PROMPT },
+                { "role": "user", "content": prompt(text, context) }
+            ]
+        });
+        let mut request = self.client.post(&self.url).json(&body);
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+        let response = match request.send().await {
+            Ok(response) => response,
+            Err(e) => {
+                eprintln!("Capture parser request failed: {:?}", e);
+                return None;
+            }
+        };
+        if !response.status().is_success() {
+            eprintln!("Capture parser returned status {}", response.status());
+            return None;
+        }
+        let completion: Completion = match response.json().await {
+            Ok(completion) => completion,
+            Err(e) => {
+                eprintln!("Capture parser response unreadable: {:?}", e);
+                return None;
+            }
+        };
+
+        let content = completion.choices.into_iter().next()?.message.content?;
+        // Some models wrap the object in a code fence
+        let start = content.find('{')?;
+        let end = content.rfind('}')?;
+        let answer: Answer = match serde_json::from_str(content.get(start..=end)?) {
+            Ok(answer) => answer,
+            Err(e) => {
+                eprintln!("Capture parser answer unreadable: {:?}", e);
+                return None;
+            }
+        };
+        Some(ParsedCapture {
+            contact_id: answer.contact_id,
+            date: answer.date.and_then(|d| Date::parse(&d, ISO_DATE).ok()),
+            notes: answer.notes,
+            interaction_type: answer.interaction_type,
+        })
+    }
+}
+
+/// Drop what the workspace couldn't resolve the capture with: unknown contacts and types, and
+/// dates after the capture or long before it
+pub fn checked(parsed: ParsedCapture, context: &ParseContext) -> ParsedCapture {
+    let oldest = context.captured_on - Duration::days(MAX_AGE_DAYS);
+    ParsedCapture {
+        contact_id: parsed
+            .contact_id
+            .filter(|id| context.contacts.iter().any(|c| c.contact_id == *id)),
+        date: parsed
+            .date
+            .filter(|d| (oldest..=context.captured_on).contains(d)),
+        notes: parsed
+            .notes
+            .map(|n| n.trim().to_string())
+            .filter(|n| !n.is_empty() && n.chars().count() <= MAX_CAPTURE_CHARS),
+        interaction_type: parsed
+            .interaction_type
+            .filter(|t| context.interaction_types.contains(t)),
+    }
+}
+
+/// The configured parser, with the rules as a fallback
+#[derive(Default)]
+pub struct CaptureParsing {
+    parser: Option<Box<dyn CaptureParser>>,
+}
+
+impl CaptureParsing {
+    pub fn new(parser: Box<dyn CaptureParser>) -> Self {
+        CaptureParsing {
+            parser: Some(parser),
+        }
+    }
+
+    /// A model when `LlmParser::from_env` configures one, otherwise just the rules
+    pub fn from_env() -> Self {
+        CaptureParsing {
+            parser: LlmParser::from_env().map(|p| Box::new(p) as Box<dyn CaptureParser>),
+        }
+    }
+
+    /// The suggestion and the name of the parser that made it
+    pub async fn parse(&self, text: &str, context: &ParseContext) -> (&'static str, ParsedCapture) {
+        if let Some(parser) = &self.parser
+            && let Some(parsed) = parser.parse(text, context).await
+        {
+            return (parser.name(), checked(parsed, context));
+        }
+        (
+            RuleParser.name(),
+            checked(RuleParser::parse_text(text, context), context),
+        )
+    }
+}
+
+/// How the capture could be resolved; resolve it with `POST /inbox/{id}/resolve` once the user
+/// has checked the suggestion
+#[get("/inbox/{id}/suggestion")]
+pub async fn suggest_resolution(
+    repo: web::Data<dyn Repository>,
+    parsing: Option<web::Data<CaptureParsing>>,
+    auth_user: AuthUser,
+    capture_id: web::Path<i32>,
+) -> impl Responder {
+    let id = capture_id.into_inner();
+
+    let capture = match repo.get_capture(auth_user.workspace_id, id).await {
+        Ok(Some(capture)) => capture,
+        Ok(None) => return HttpResponse::NotFound().body("Capture not found"),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            return HttpResponse::InternalServerError().body("Failed to fetch capture");
+        }
+    };
+    let contacts = match repo.list_contacts(auth_user.workspace_id).await {
+        Ok(contacts) => contacts,
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            return HttpResponse::InternalServerError().body("Failed to fetch contacts");
+        }
+    };
+    let custom = match repo.list_interaction_types(auth_user.workspace_id).await {
+        Ok(custom) => custom,
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            return HttpResponse::InternalServerError().body("Failed to fetch interaction types");
+        }
+    };
+    let dates = match LocalDates::for_user(repo.get_ref(), auth_user.user_id).await {
+        Ok(dates) => dates,
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            return HttpResponse::InternalServerError().body("Failed to fetch preferences");
+        }
+    };
+
+    let captured_on = dates.date_of(capture.captured_at);
+    let context = ParseContext {
+        captured_on,
+        contacts,
+        interaction_types: BUILTIN_INTERACTION_TYPES
+            .iter()
+            .map(|t| t.to_string())
+            .chain(custom.into_iter().map(|t| t.name))
+            .collect(),
+    };
+    let (source, parsed) = match &parsing {
+        Some(parsing) => parsing.parse(&capture.text, &context).await,
+        None => {
+            CaptureParsing::default()
+                .parse(&capture.text, &context)
+                .await
+        }
+    };
+
+    HttpResponse::Ok().json(CaptureSuggestion {
+        contact_id: parsed.contact_id,
+        // The capture's time of day, moved to the day the text mentions
+        interaction_date: parsed
+            .date
+            .map(|day| capture.captured_at - (captured_on - day)),
+        notes: parsed.notes,
+        interaction_type: parsed.interaction_type,
+        source: source.to_string(),
+    })
+}
@@ -0,0 +1,504 @@
+//! The `crm` command line client (`src/bin/crm.rs`).
+//!
+//! Talks to the HTTP API like any other client, with the token in `CRM_API_KEY` as its bearer
+//! token and the server at `CRM_URL`:
+//!
+//! - `crm add-contact "Alice Zhang" [--email E] [--phone P] [--note N] [--every DAYS]`
+//! - `crm log "coffee with Alice" [--contact ID] [--type TYPE]` saves the text to the inbox and
+//!   resolves it into an interaction with the contact, or the one the server suggests; without
+//!   either it stays in the inbox to sort out later
+//! - `crm due [--days N]` lists the contacts past their desired frequency and the reminders due
+//!   in the next N days (default 7)
+//! - `crm export [--output FILE]` writes the workspace's JSON archive to the file or stdout
+
+use crate::models::{
+    Capture, CaptureSuggestion, Contact, ContactResponse, NewCaptureRequest, NewContactRequest,
+    Reminder, ResolveCaptureRequest,
+};
+use reqwest::{Method, StatusCode};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+
+/// Server used when `CRM_URL` is not set
+pub const DEFAULT_URL: &str = "http://localhost:3000";
+
+/// Days of reminders `crm due` shows unless `--days` says otherwise
+pub const DEFAULT_DUE_DAYS: i64 = 7;
+
+pub const USAGE: &str = "Usage:
+  crm add-contact NAME [--email E] [--phone P] [--note N] [--every DAYS]
+  crm log TEXT [--contact ID] [--type TYPE]
+  crm due [--days N]
+  crm export [--output FILE]
+
+CRM_API_KEY must hold an API token; CRM_URL is the server (default http://localhost:3000).";
+
+#[derive(Debug)]
+pub enum Command {
+    AddContact(Box<NewContactRequest>),
+    Log {
+        text: String,
+        contact_id: Option<i32>,
+        interaction_type: Option<String>,
+    },
+    Due {
+        days: i64,
+    },
+    Export {
+        output: Option<String>,
+    },
+}
+
+/// The arguments of a command: at most one positional argument and `--flag value` pairs
+struct Arguments {
+    positional: Option<String>,
+    flags: Vec<(String, String)>,
+}
+
+/// Split `args` into the one positional argument, named `what` in errors, and the flags
+fn arguments<I: Iterator<Item = String>>(mut args: I, what: &str) -> Result<Arguments, String> {
+    let mut positional = None;
+    let mut flags = Vec::new();
+    while let Some(arg) = args.next() {
+        if arg.starts_with("--") {
+            let value = args
+                .next()
+                .ok_or_else(|| format!("{} expects a value", arg))?;
+            flags.push((arg, value));
+        } else if positional.is_none() {
+            positional = Some(arg);
+        } else {
+            return Err(format!("Only one {} is expected; quote it", what));
+        }
+    }
+    Ok(Arguments { positional, flags })
+}
+
+fn number<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{} expects a number", flag))
+}
+
+impl Command {
+    /// Parse the arguments after the program name
+    pub fn from_args<I: Iterator<Item = String>>(mut args: I) -> Result<Command, String> {
+        let command = args.next().ok_or_else(|| USAGE.to_string())?;
+        match command.as_str() {
+            "add-contact" => {
+                let Arguments {
+                    positional: name,
+                    flags,
+                } = arguments(args, "name")?;
+                let name = name.unwrap_or_default();
+                let mut parts = name.split_whitespace();
+                let first_name = parts
+                    .next()
+                    .ok_or_else(|| "add-contact expects a name".to_string())?;
+                let last_name = parts.collect::<Vec<&str>>().join(" ");
+                let mut contact = NewContactRequest {
+                    first_name: Some(first_name.to_string()),
+                    last_name: (!last_name.is_empty()).then_some(last_name),
+                    ..Default::default()
+                };
+                for (flag, value) in flags {
+                    match flag.as_str() {
+                        "--email" => contact.email = Some(value),
+                        "--phone" => contact.phone = Some(value),
+                        "--note" => contact.short_note = Some(value),
+                        "--every" => contact.desired_frequency_days = Some(number(&flag, &value)?),
+                        other => return Err(format!("Unknown argument: {}", other)),
+                    }
+                }
+                Ok(Command::AddContact(Box::new(contact)))
+            }
+            "log" => {
+                let Arguments {
+                    positional: text,
+                    flags,
+                } = arguments(args, "text")?;
+                let text = text
+                    .filter(|text| !text.trim().is_empty())
+                    .ok_or_else(|| "log expects the text to log".to_string())?;
+                let mut contact_id = None;
+                let mut interaction_type = None;
+                for (flag, value) in flags {
+                    match flag.as_str() {
+                        "--contact" => contact_id = Some(number(&flag, &value)?),
+                        "--type" => interaction_type = Some(value),
+                        other => return Err(format!("Unknown argument: {}", other)),
+                    }
+                }
+                Ok(Command::Log {
+                    text,
+                    contact_id,
+                    interaction_type,
+                })
+            }
+            "due" => {
+                let Arguments {
+                    positional: extra,
+                    flags,
+                } = arguments(args, "argument")?;
+                if let Some(extra) = extra {
+                    return Err(format!("Unknown argument: {}", extra));
+                }
+                let mut days = DEFAULT_DUE_DAYS;
+                for (flag, value) in flags {
+                    match flag.as_str() {
+                        "--days" => days = number(&flag, &value)?,
+                        other => return Err(format!("Unknown argument: {}", other)),
+                    }
+                }
+                Ok(Command::Due { days })
+            }
+            "export" => {
+                let Arguments {
+                    positional: extra,
+                    flags,
+                } = arguments(args, "argument")?;
+                if let Some(extra) = extra {
+                    return Err(format!("Unknown argument: {}", extra));
+                }
+                let mut output = None;
+                for (flag, value) in flags {
+                    match flag.as_str() {
+                        "--output" => output = Some(value),
+                        other => return Err(format!("Unknown argument: {}", other)),
+                    }
+                }
+                Ok(Command::Export { output })
+            }
+            "help" | "--help" | "-h" => Err(USAGE.to_string()),
+            other => Err(format!("Unknown command: {}\n\n{}", other, USAGE)),
+        }
+    }
+}
+
+/// The contact fields `crm due` asks for
+#[derive(Debug, Clone, Deserialize)]
+pub struct ContactName {
+    pub contact_id: i32,
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+}
+
+/// A contact in the list `crm due` asks for
+#[derive(Debug, Clone, Deserialize)]
+pub struct DueContact {
+    pub contact: ContactName,
+    #[serde(default)]
+    pub overdue_days: Option<i64>,
+}
+
+fn display_name(first_name: Option<&str>, last_name: Option<&str>, contact_id: i32) -> String {
+    let name = [first_name, last_name]
+        .into_iter()
+        .flatten()
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<&str>>()
+        .join(" ");
+    if name.is_empty() {
+        format!("Contact {}", contact_id)
+    } else {
+        name
+    }
+}
+
+/// What `crm due` prints: overdue contacts, most overdue first, then the reminders in date order
+pub fn due_report(contacts: &[DueContact], reminders: &[Reminder]) -> String {
+    let names: HashMap<i32, String> = contacts
+        .iter()
+        .map(|DueContact { contact: c, .. }| {
+            let name = display_name(
+                c.first_name.as_deref(),
+                c.last_name.as_deref(),
+                c.contact_id,
+            );
+            (c.contact_id, name)
+        })
+        .collect();
+    let mut overdue: Vec<(&DueContact, i64)> = contacts
+        .iter()
+        .filter_map(|c| {
+            c.overdue_days
+                .filter(|&days| days >= 0)
+                .map(|days| (c, days))
+        })
+        .collect();
+    overdue.sort_by_key(|(c, days)| (-days, c.contact.contact_id));
+
+    let mut lines = Vec::new();
+    if overdue.is_empty() {
+        lines.push("Nobody is due for contact".to_string());
+    } else {
+        lines.push("Due for contact:".to_string());
+        for (contact, days) in overdue {
+            let when = match days {
+                0 => "due today".to_string(),
+                1 => "1 day overdue".to_string(),
+                n => format!("{} days overdue", n),
+            };
+            lines.push(format!(
+                "  {}  ({})",
+                names[&contact.contact.contact_id], when
+            ));
+        }
+    }
+    if reminders.is_empty() {
+        lines.push("No reminders coming up".to_string());
+    } else {
+        lines.push("Reminders:".to_string());
+        for reminder in reminders {
+            let who = reminder
+                .contact_ids
+                .iter()
+                .map(|id| {
+                    names
+                        .get(id)
+                        .cloned()
+                        .unwrap_or_else(|| format!("Contact {}", id))
+                })
+                .collect::<Vec<String>>();
+            let mut line = format!("  {}  {}", reminder.remind_on, reminder.name);
+            if !who.is_empty() {
+                line.push_str(&format!(" ({})", who.join(", ")));
+            }
+            if reminder.date != reminder.remind_on {
+                line.push_str(&format!(" on {}", reminder.date));
+            }
+            lines.push(line);
+        }
+    }
+    lines.join("\n")
+}
+
+/// The server's `error` message, else its plain-text body
+fn failure(status: StatusCode, body: &str) -> String {
+    let message = serde_json::from_str::<serde_json::Value>(body)
+        .ok()
+        .and_then(|json| json["error"].as_str().map(str::to_string))
+        .unwrap_or_else(|| body.trim().to_string());
+    let hint = if status == StatusCode::UNAUTHORIZED {
+        "; check CRM_API_KEY"
+    } else {
+        ""
+    };
+    if message.is_empty() {
+        format!("The server answered {}{}", status, hint)
+    } else {
+        format!("The server answered {}: {}{}", status, message, hint)
+    }
+}
+
+pub struct Client {
+    http: reqwest::Client,
+    base_url: String,
+    api_key: String,
+}
+
+impl Client {
+    pub fn new(base_url: &str, api_key: String) -> Self {
+        Client {
+            http: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key,
+        }
+    }
+
+    /// The client for CRM_URL with the token in CRM_API_KEY
+    pub fn from_env() -> Result<Self, String> {
+        let api_key = std::env::var("CRM_API_KEY")
+            .ok()
+            .filter(|key| !key.trim().is_empty())
+            .ok_or_else(|| "CRM_API_KEY must be set to an API token".to_string())?;
+        let base_url = std::env::var("CRM_URL").unwrap_or_else(|_| DEFAULT_URL.to_string());
+        Ok(Client::new(&base_url, api_key.trim().to_string()))
+    }
+
+    /// Send a request to `/v1{path}`, returning the status and body
+    async fn send(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<serde_json::Value>,
+    ) -> Result<(StatusCode, String), String> {
+        let mut request = self
+            .http
+            .request(method, format!("{}/v1{}", self.base_url, path))
+            .bearer_auth(&self.api_key);
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|e| format!("Could not reach {}: {}", self.base_url, e))?;
+        let status = response.status();
+        let body = response
+            .text()
+            .await
+            .map_err(|e| format!("Could not read the response: {}", e))?;
+        Ok((status, body))
+    }
+
+    /// Send a request and parse a successful response as JSON
+    async fn call<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<serde_json::Value>,
+    ) -> Result<T, String> {
+        let (status, body) = self.send(method, path, body).await?;
+        if !status.is_success() {
+            return Err(failure(status, &body));
+        }
+        serde_json::from_str(&body).map_err(|e| format!("Unexpected response: {}", e))
+    }
+
+    /// Run the command, returning what to print
+    pub async fn run(&self, command: Command) -> Result<String, String> {
+        match command {
+            Command::AddContact(contact) => self.add_contact(&contact).await,
+            Command::Log {
+                text,
+                contact_id,
+                interaction_type,
+            } => self.log(&text, contact_id, interaction_type).await,
+            Command::Due { days } => self.due(days).await,
+            Command::Export { output } => self.export(output.as_deref()).await,
+        }
+    }
+
+    async fn add_contact(&self, contact: &NewContactRequest) -> Result<String, String> {
+        let body = serde_json::to_value(contact).map_err(|e| e.to_string())?;
+        let created: Contact = self.call(Method::POST, "/contacts", Some(body)).await?;
+        Ok(format!(
+            "Added {} (contact {})",
+            display_name(
+                created.first_name.as_deref(),
+                created.last_name.as_deref(),
+                created.contact_id
+            ),
+            created.contact_id
+        ))
+    }
+
+    async fn log(
+        &self,
+        text: &str,
+        contact_id: Option<i32>,
+        interaction_type: Option<String>,
+    ) -> Result<String, String> {
+        let capture = NewCaptureRequest {
+            text: text.to_string(),
+        };
+        let body = serde_json::to_value(&capture).map_err(|e| e.to_string())?;
+        let capture: Capture = self.call(Method::POST, "/inbox", Some(body)).await?;
+
+        let resolution = match contact_id {
+            Some(contact_id) => Some(ResolveCaptureRequest {
+                contact_id,
+                interaction_date: None,
+                notes: None,
+                interaction_type: interaction_type.clone(),
+                contact_ids: Vec::new(),
+            }),
+            None => self.suggest(capture.capture_id).await?.and_then(|s| {
+                Some(ResolveCaptureRequest {
+                    contact_id: s.contact_id?,
+                    interaction_date: s.interaction_date,
+                    notes: None,
+                    interaction_type: interaction_type.clone().or(s.interaction_type),
+                    contact_ids: Vec::new(),
+                })
+            }),
+        };
+        let Some(resolution) = resolution else {
+            return Ok(format!(
+                "Saved to the inbox as capture {}; no contact recognised, so resolve it later \
+                 or log again with --contact",
+                capture.capture_id
+            ));
+        };
+
+        let contact_id = resolution.contact_id;
+        let body = serde_json::to_value(&resolution).map_err(|e| e.to_string())?;
+        let path = format!("/inbox/{}/resolve", capture.capture_id);
+        if let Err(e) = self
+            .call::<serde_json::Value>(Method::POST, &path, Some(body))
+            .await
+        {
+            return Err(format!(
+                "{}\nThe text is kept in the inbox as capture {}",
+                e, capture.capture_id
+            ));
+        }
+        let ContactResponse { contact, .. } = self
+            .call(Method::GET, &format!("/contacts/{}", contact_id), None)
+            .await?;
+        Ok(format!(
+            "Logged with {}",
+            display_name(
+                contact.first_name.as_deref(),
+                contact.last_name.as_deref(),
+                contact_id
+            )
+        ))
+    }
+
+    /// The server's suggestion for resolving a capture, None when it has no suggestions
+    async fn suggest(&self, capture_id: i32) -> Result<Option<CaptureSuggestion>, String> {
+        let path = format!("/inbox/{}/suggestion", capture_id);
+        let (status, body) = self.send(Method::GET, &path, None).await?;
+        // Servers built without capture parsing don't have the route
+        if status == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !status.is_success() {
+            return Err(failure(status, &body));
+        }
+        serde_json::from_str(&body)
+            .map(Some)
+            .map_err(|e| format!("Unexpected response: {}", e))
+    }
+
+    async fn due(&self, days: i64) -> Result<String, String> {
+        let contacts: Vec<DueContact> = self
+            .call(
+                Method::GET,
+                "/contacts?sort=priority&fields=contact_id,first_name,last_name,overdue_days",
+                None,
+            )
+            .await?;
+        let reminders: Vec<Reminder> = self
+            .call(
+                Method::GET,
+                &format!("/reminders/upcoming?days={}", days),
+                None,
+            )
+            .await?;
+        Ok(due_report(&contacts, &reminders))
+    }
+
+    async fn export(&self, output: Option<&str>) -> Result<String, String> {
+        let (status, body) = self.send(Method::GET, "/export", None).await?;
+        if !status.is_success() {
+            let mut message = failure(status, &body);
+            if body.contains("reauth_required") {
+                message.push_str("; exports need a token from a recent sign-in");
+            }
+            return Err(message);
+        }
+        match output {
+            None => Ok(body),
+            Some(path) => {
+                std::fs::write(path, &body)
+                    .map_err(|e| format!("Could not write {}: {}", path, e))?;
+                Ok(format!("Wrote the export to {}", path))
+            }
+        }
+    }
+}
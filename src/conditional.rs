@@ -0,0 +1,76 @@
+//! Conditional GET support so clients can revalidate cached responses instead of
+//! re-downloading them.
+//!
+//! Every response gets a strong ETag hashed from its body. When the newest change behind the
+//! body is known it is also sent as Last-Modified. If-None-Match takes precedence over
+//! If-Modified-Since, as RFC 9110 requires.
+
+use crate::streaming;
+use actix_web::http::header::{
+    ContentType, ETag, EntityTag, HttpDate, IfModifiedSince, IfNoneMatch, LastModified,
+};
+use actix_web::{HttpMessage, HttpRequest, HttpResponse};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::time::{Duration, SystemTime};
+use time::PrimitiveDateTime;
+
+/// HTTP dates have whole-second precision
+fn to_http_date(timestamp: PrimitiveDateTime) -> HttpDate {
+    let secs = timestamp.assume_utc().unix_timestamp().max(0) as u64;
+    HttpDate::from(SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+/// Whether the client's cached copy (described by the request's validators) is still current
+fn not_modified(req: &HttpRequest, etag: &EntityTag, last_modified: Option<HttpDate>) -> bool {
+    if let Some(if_none_match) = req.get_header::<IfNoneMatch>() {
+        return match if_none_match {
+            IfNoneMatch::Any => true,
+            IfNoneMatch::Items(tags) => tags.iter().any(|tag| tag.weak_eq(etag)),
+        };
+    }
+    match (req.get_header::<IfModifiedSince>(), last_modified) {
+        (Some(IfModifiedSince(since)), Some(modified)) => {
+            SystemTime::from(modified) <= SystemTime::from(since)
+        }
+        _ => false,
+    }
+}
+
+/// Stream `body` as JSON with validators, or answer 304 Not Modified when the client already
+/// has it. `last_modified` is the latest change behind the body, if known. The ETag is hashed
+/// from a first serialization pass, so the JSON is never held in memory whole.
+pub fn json_response<T: Serialize + Send + 'static>(
+    req: &HttpRequest,
+    body: T,
+    last_modified: Option<PrimitiveDateTime>,
+) -> HttpResponse {
+    let digest = match streaming::json_digest::<_, Sha256>(&body) {
+        Ok(digest) => digest,
+        Err(e) => {
+            eprintln!("Failed to serialize response: {:?}", e);
+            return HttpResponse::InternalServerError().body("Failed to serialize response");
+        }
+    };
+    let etag = EntityTag::new_strong(format!("{:x}", digest.finalize()));
+    let last_modified = last_modified.map(to_http_date);
+
+    let not_modified = not_modified(req, &etag, last_modified);
+
+    let mut response = if not_modified {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::Ok()
+    };
+    response.insert_header(ETag(etag));
+    if let Some(modified) = last_modified {
+        response.insert_header(LastModified(modified));
+    }
+
+    if not_modified {
+        return response.finish();
+    }
+    response
+        .content_type(ContentType::json())
+        .streaming(streaming::json_stream(body))
+}
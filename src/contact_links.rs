@@ -0,0 +1,323 @@
+//! Public links to a contact's card.
+//!
+//! `POST /contacts/{id}/share` makes a link that anyone holding it can open without signing in,
+//! until it expires or is revoked with `DELETE /share-links/{id}`. The link's token is random
+//! and unguessable, and is checked against the stored links on every view, so a revoked link
+//! stops working at once. Only the token's SHA-256 is stored, so the URL is shown once, when
+//! the link is made. The card shows the contact's name, how to reach them and where they
+//! live, never the notes the user keeps on them. It is JSON, or an HTML page for browsers.
+
+use crate::AuthUser;
+use crate::inbound_email::generate_token;
+use crate::models::{CardProfile, Contact, ContactCard, ContactLink, SocialProfile};
+use crate::mutations;
+use crate::repository::{RepoResult, Repository};
+use crate::routes::{Owned, OwnedContact, Resource, ensure_owned};
+use actix_web::http::header;
+use actix_web::{HttpRequest, HttpResponse, Responder, delete, get, post, web};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use time::{Duration, OffsetDateTime};
+
+/// How long a link lasts when the request doesn't say
+pub const DEFAULT_LINK_HOURS: i64 = 7 * 24;
+
+/// Longest a link may last
+pub const MAX_LINK_HOURS: i64 = 30 * 24;
+
+#[derive(Debug, Deserialize)]
+pub struct NewLinkQuery {
+    pub expires_in_hours: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CardQuery {
+    /// `json` or `html`; without it the Accept header decides
+    pub format: Option<String>,
+}
+
+/// The policy for the HTML card: the global one, but letting the avatar load over HTTPS
+const CARD_CONTENT_SECURITY_POLICY: &str =
+    "default-src 'none'; img-src https:; frame-ancestors 'none'";
+
+/// What is stored for a link's token, and looked up when the link is opened
+pub fn token_hash(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// The new link with its token and full URL, on the host the request came in through
+fn with_token(req: &HttpRequest, mut link: ContactLink, token: String) -> ContactLink {
+    let info = req.connection_info();
+    link.url = Some(format!(
+        "{}://{}/public/contacts/{}",
+        info.scheme(),
+        info.host(),
+        token
+    ));
+    link.token = Some(token);
+    link
+}
+
+pub fn contact_card(contact: Contact, social_profiles: Vec<SocialProfile>) -> ContactCard {
+    ContactCard {
+        first_name: contact.first_name,
+        last_name: contact.last_name,
+        email: contact.email,
+        phone: contact.phone,
+        avatar_url: contact.avatar_url,
+        location: contact.location,
+        social_profiles: social_profiles
+            .into_iter()
+            .map(|p| CardProfile {
+                platform: p.platform,
+                url: p.url,
+            })
+            .collect(),
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Only web URLs become links, so a stored `javascript:` URL stays inert
+fn is_web_url(url: &str) -> bool {
+    reqwest::Url::parse(url).is_ok_and(|url| matches!(url.scheme(), "http" | "https"))
+}
+
+/// Only HTTPS avatars are shown, the only images the card's policy lets load
+fn is_https_url(url: &str) -> bool {
+    reqwest::Url::parse(url).is_ok_and(|url| url.scheme() == "https")
+}
+
+/// The card as a standalone page, every value escaped
+pub fn render_card_html(card: &ContactCard) -> String {
+    let name = [card.first_name.as_deref(), card.last_name.as_deref()]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(" ");
+    let name = escape_html(&name);
+    let mut body = String::new();
+    if let Some(avatar_url) = card.avatar_url.as_deref().filter(|u| is_https_url(u)) {
+        body.push_str(&format!(
+            "<img src=\"{}\" alt=\"\" width=\"96\" height=\"96\">\n",
+            escape_html(avatar_url)
+        ));
+    }
+    body.push_str(&format!("<h1>{}</h1>\n<dl>\n", name));
+    if let Some(email) = &card.email {
+        let email = escape_html(email);
+        body.push_str(&format!(
+            "<dt>Email</dt><dd><a href=\"mailto:{}\">{}</a></dd>\n",
+            email, email
+        ));
+    }
+    if let Some(phone) = &card.phone {
+        let phone = escape_html(phone);
+        body.push_str(&format!(
+            "<dt>Phone</dt><dd><a href=\"tel:{}\">{}</a></dd>\n",
+            phone, phone
+        ));
+    }
+    if let Some(location) = &card.location {
+        body.push_str(&format!(
+            "<dt>Location</dt><dd>{}</dd>\n",
+            escape_html(location)
+        ));
+    }
+    body.push_str("</dl>\n");
+    let profiles: Vec<&CardProfile> = card
+        .social_profiles
+        .iter()
+        .filter(|p| is_web_url(&p.url))
+        .collect();
+    if !profiles.is_empty() {
+        body.push_str("<ul>\n");
+        for profile in profiles {
+            let url = escape_html(&profile.url);
+            body.push_str(&format!(
+                "<li><a href=\"{}\" rel=\"noopener noreferrer\">{}</a></li>\n",
+                url, url
+            ));
+        }
+        body.push_str("</ul>\n");
+    }
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <meta name=\"robots\" content=\"noindex\">\n<title>{}</title>\n</head>\n<body>\n{}</body>\n</html>\n",
+        name, body
+    )
+}
+
+/// Whether the caller asked for the HTML page rather than JSON
+fn wants_html(req: &HttpRequest, query: &CardQuery) -> bool {
+    match query.format.as_deref() {
+        Some(format) => format.eq_ignore_ascii_case("html"),
+        None => req
+            .headers()
+            .get(header::ACCEPT)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|accept| accept.contains("text/html")),
+    }
+}
+
+/// The card a token opens, if it is still valid
+async fn card_for_token(repo: &dyn Repository, token: &str) -> RepoResult<Option<ContactCard>> {
+    let now = OffsetDateTime::now_utc();
+    let Some((workspace_id, contact_id)) =
+        repo.contact_link_target(&token_hash(token), now).await?
+    else {
+        return Ok(None);
+    };
+    let Some(contact) = repo.get_contact(workspace_id, contact_id).await? else {
+        return Ok(None);
+    };
+    let social_profiles = repo.social_profiles_for_contacts(&[contact_id]).await?;
+    Ok(Some(contact_card(contact, social_profiles)))
+}
+
+/// Make a link to the contact's card, lasting `expires_in_hours` (default a week, at most 30
+/// days). Only for the user's own contacts, not ones shared with them. The response is the
+/// only one carrying the link's token and URL.
+#[post("/contacts/{id}/share")]
+pub async fn create_contact_link(
+    repo: web::Data<dyn Repository>,
+    auth_user: AuthUser,
+    req: HttpRequest,
+    Owned(contact): OwnedContact,
+    query: web::Query<NewLinkQuery>,
+) -> impl Responder {
+    let hours = query.expires_in_hours.unwrap_or(DEFAULT_LINK_HOURS);
+    if !(1..=MAX_LINK_HOURS).contains(&hours) {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("expires_in_hours must be 1 to {}", MAX_LINK_HOURS)
+        }));
+    }
+
+    let expires_at = OffsetDateTime::now_utc() + Duration::hours(hours);
+    let token = generate_token();
+    match repo
+        .create_contact_link(
+            auth_user.workspace_id,
+            contact.contact_id,
+            &token_hash(&token),
+            expires_at,
+        )
+        .await
+    {
+        Ok(link) => {
+            // The link lives at /share-links/{id}, under the same scope as this route
+            let scope = req
+                .path()
+                .rsplit_once("/contacts/")
+                .map_or("", |(scope, _)| scope);
+            let link_id = link.link_id;
+            mutations::created_in(
+                &format!("{}/share-links", scope),
+                link_id,
+                Ok(Some(with_token(&req, link, token))),
+            )
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to create link")
+        }
+    }
+}
+
+/// The contact's links that have not expired, without their tokens
+#[get("/contacts/{id}/share-links")]
+pub async fn list_contact_links(
+    repo: web::Data<dyn Repository>,
+    auth_user: AuthUser,
+    Owned(contact): OwnedContact,
+) -> impl Responder {
+    match repo
+        .contact_links(
+            auth_user.workspace_id,
+            contact.contact_id,
+            OffsetDateTime::now_utc(),
+        )
+        .await
+    {
+        Ok(links) => HttpResponse::Ok().json(links),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to fetch links")
+        }
+    }
+}
+
+/// Revoke a link
+#[delete("/share-links/{id}")]
+pub async fn delete_contact_link(
+    repo: web::Data<dyn Repository>,
+    auth_user: AuthUser,
+    link_id: web::Path<i32>,
+) -> impl Responder {
+    let id = link_id.into_inner();
+
+    // Verify the link belongs to the user
+    if let Err(response) = ensure_owned(repo.get_ref(), &auth_user, Resource::ContactLink, id).await
+    {
+        return response;
+    }
+
+    match repo.delete_contact_link(auth_user.workspace_id, id).await {
+        Ok(false) => HttpResponse::NotFound().body("Link not found"),
+        Ok(true) => HttpResponse::Ok().body("Link revoked successfully"),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to revoke link")
+        }
+    }
+}
+
+/// The card behind a link, for anyone holding it. Unknown, expired and revoked links are all
+/// reported alike.
+#[get("/public/contacts/{token}")]
+pub async fn view_contact_card(
+    repo: web::Data<dyn Repository>,
+    req: HttpRequest,
+    token: web::Path<String>,
+    query: web::Query<CardQuery>,
+) -> impl Responder {
+    let card = match card_for_token(repo.get_ref(), &token).await {
+        Ok(Some(card)) => card,
+        Ok(None) => return HttpResponse::NotFound().body("Link not found or expired"),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            return HttpResponse::InternalServerError().body("Failed to fetch contact");
+        }
+    };
+
+    // Revocation must take effect at once, so nothing along the way may keep a copy
+    let mut response = HttpResponse::Ok();
+    response
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .insert_header(("X-Robots-Tag", "noindex"));
+    if wants_html(&req, &query) {
+        response
+            .insert_header((
+                header::CONTENT_SECURITY_POLICY,
+                CARD_CONTENT_SECURITY_POLICY,
+            ))
+            .content_type("text/html; charset=utf-8")
+            .body(render_card_html(&card))
+    } else {
+        response.json(card)
+    }
+}
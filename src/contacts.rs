@@ -0,0 +1,1368 @@
+//! Contact handlers
+
+use crate::AuthUser;
+use crate::avatar::GravatarResolver;
+use crate::conditional;
+use crate::dedupe::{self, DedupePolicy, Plan};
+use crate::deletion::{self, DeletePolicies};
+use crate::events::{self, Action, Entity, EventBus};
+use crate::fieldsets::{FieldMask, Masked};
+use crate::geocoding::{self, BoundingBox, Geocoding};
+use crate::limits::{self, Limits};
+use crate::models::{
+    Contact, ContactAccess, ContactChecksum, ContactMatch, ContactResponse, ContactSummary,
+    Coordinates, NearbyContact, NewContactRequest, SharePermission, Tag,
+};
+use crate::mutations;
+use crate::phone;
+use crate::quotas::{self, Quotas};
+use crate::reauth::{self, Reauth};
+use crate::repository::{self, RepoResult, Repository};
+use crate::routes::{Owned, OwnedContact, ensure_contact_access, skipped_ids};
+use crate::timezone::LocalDates;
+use actix_web::guard::GuardContext;
+use actix_web::http::header::ContentType;
+use actix_web::{HttpRequest, HttpResponse, Responder, delete, get, patch, post, web};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+
+/// Content type of RFC 6902 JSON Patch bodies
+pub const JSON_PATCH: &str = "application/json-patch+json";
+
+/// Longest desired frequency, in days
+const MAX_FREQUENCY_DAYS: i32 = 365;
+
+/// Longest `met_at` place or context
+const MAX_MET_AT_LENGTH: usize = 255;
+
+/// Longest location
+const MAX_LOCATION_LENGTH: usize = 255;
+
+/// Longest short note. The column is unbounded, since encrypted notes take more room.
+const MAX_SHORT_NOTE_LENGTH: usize = 255;
+
+/// Check the fields the database does not constrain enough on its own. Errors are the JSON
+/// body of the 400 response.
+pub(crate) fn validate(contact: &NewContactRequest) -> Result<(), serde_json::Value> {
+    if contact
+        .desired_frequency_days
+        .is_some_and(|days| !(1..=MAX_FREQUENCY_DAYS).contains(&days))
+    {
+        return Err(serde_json::json!({
+            "error": format!("Desired frequency must be 1 to {} days", MAX_FREQUENCY_DAYS)
+        }));
+    }
+    if let Some(met_at) = &contact.met_at {
+        let too_long = |text: &Option<String>| {
+            text.as_ref()
+                .is_some_and(|text| text.chars().count() > MAX_MET_AT_LENGTH)
+        };
+        if too_long(&met_at.place) || too_long(&met_at.context) {
+            return Err(serde_json::json!({
+                "error": format!("met_at place and context must be up to {} characters", MAX_MET_AT_LENGTH)
+            }));
+        }
+    }
+    if contact
+        .location
+        .as_ref()
+        .is_some_and(|location| location.chars().count() > MAX_LOCATION_LENGTH)
+    {
+        return Err(serde_json::json!({
+            "error": format!("location must be up to {} characters", MAX_LOCATION_LENGTH)
+        }));
+    }
+    if contact
+        .short_note
+        .as_ref()
+        .is_some_and(|note| note.chars().count() > MAX_SHORT_NOTE_LENGTH)
+    {
+        return Err(serde_json::json!({
+            "error": format!("short_note must be up to {} characters", MAX_SHORT_NOTE_LENGTH)
+        }));
+    }
+    Ok(())
+}
+
+/// Geocode the locations of contacts just saved, when a geocoder is configured. Contacts are
+/// left without coordinates if this fails; the save itself has succeeded.
+pub(crate) async fn locate(
+    geocoding: Option<&web::Data<Geocoding>>,
+    repo: &dyn Repository,
+    workspace_id: i32,
+    contacts: impl IntoIterator<Item = (i32, &NewContactRequest)>,
+) {
+    let Some(geocoding) = geocoding else {
+        return;
+    };
+    let locations = contacts
+        .into_iter()
+        .filter_map(|(contact_id, c)| Some((contact_id, c.location.clone()?)))
+        .collect();
+    if let Err(e) = geocoding
+        .locate_contacts(repo, workspace_id, locations)
+        .await
+    {
+        eprintln!("Database error storing coordinates: {:?}", e);
+    }
+}
+
+/// Check that `introduced_by_contact_id` is another contact in the workspace. `contact_id` is
+/// the contact being updated, None for a new one.
+async fn check_introducer(
+    repo: &dyn Repository,
+    workspace_id: i32,
+    contact_id: Option<i32>,
+    contact: &NewContactRequest,
+) -> RepoResult<Result<(), serde_json::Value>> {
+    let Some(introducer) = contact.introduced_by_contact_id else {
+        return Ok(Ok(()));
+    };
+    if Some(introducer) != contact_id && repo.owns_contact(workspace_id, introducer).await? {
+        return Ok(Ok(()));
+    }
+    Ok(Err(serde_json::json!({
+        "error": "introduced_by_contact_id must be another contact in the workspace"
+    })))
+}
+
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum ContactSort {
+    /// By last name, then first name
+    #[default]
+    Name,
+    /// Highest predicted_contact_priority first
+    Priority,
+}
+
+#[derive(Deserialize)]
+struct ListContactsQuery {
+    /// Also list archived contacts
+    #[serde(default)]
+    include_archived: bool,
+    #[serde(default)]
+    sort: ContactSort,
+    /// Page size; all contacts when left out
+    limit: Option<u32>,
+    #[serde(default)]
+    offset: u32,
+    /// Comma-separated fields to send for each contact; all of them when left out
+    fields: Option<String>,
+    /// Comma-separated extras to send; `summary` is the only one
+    include: Option<String>,
+}
+
+/// Responses carry an ETag for If-None-Match revalidation. There is no Last-Modified here:
+/// deleting a contact leaves no timestamp behind to compare against.
+/// `?sort=priority` is sorted and paged by the repository, so only the page is loaded; contacts
+/// the user snoozed or dismissed come last.
+/// `?fields=` sends only the named fields, and skips loading interactions and occasions when
+/// none of the named fields need them. `?include=summary` adds each contact's last interaction
+/// and next occasion, in place of its interactions and occasions unless `?fields=` names them.
+#[get("/contacts")]
+pub async fn list_contacts(
+    req: HttpRequest,
+    repo: web::Data<dyn Repository>,
+    gravatar: Option<web::Data<GravatarResolver>>,
+    auth_user: AuthUser,
+    query: web::Query<ListContactsQuery>,
+) -> impl Responder {
+    let mask = match query.fields.as_deref().map(FieldMask::parse) {
+        None => None,
+        Some(Ok(mask)) if !mask.is_empty() => Some(mask),
+        Some(Ok(_)) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "fields must name at least one field"
+            }));
+        }
+        Some(Err(field)) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": format!("Unknown field {}", field)
+            }));
+        }
+    };
+    let mut with_summary = false;
+    for include in query
+        .include
+        .iter()
+        .flat_map(|i| i.split(','))
+        .map(str::trim)
+    {
+        match include {
+            "summary" => with_summary = true,
+            "" => {}
+            other => {
+                return HttpResponse::BadRequest().json(serde_json::json!({
+                    "error": format!("Unknown include {}", other)
+                }));
+            }
+        }
+    }
+    let mask = match mask {
+        None if with_summary => Some(FieldMask::summary()),
+        mask => mask,
+    };
+
+    let dates = LocalDates::for_user(repo.get_ref(), auth_user.user_id)
+        .await
+        .unwrap_or_default();
+
+    // Get contacts for the user
+    let contacts = match query.sort {
+        ContactSort::Name => repo.list_contacts(auth_user.workspace_id).await.map(|c| {
+            c.into_iter()
+                .filter(|contact| query.include_archived || !contact.archived)
+                .skip(query.offset as usize)
+                .take(query.limit.map_or(usize::MAX, |n| n as usize))
+                .collect::<Vec<Contact>>()
+        }),
+        ContactSort::Priority => {
+            match repo
+                .snoozed_contacts(auth_user.user_id, dates.today())
+                .await
+            {
+                Ok(snoozed) => {
+                    repo.contacts_by_priority(
+                        auth_user.workspace_id,
+                        &dates,
+                        query.include_archived,
+                        &snoozed,
+                        query.limit.map(i64::from),
+                        query.offset.into(),
+                    )
+                    .await
+                }
+                Err(e) => Err(e),
+            }
+        }
+    };
+    let contacts = match contacts {
+        Ok(contacts) => contacts,
+        Err(e) => {
+            eprintln!(
+                "Database error fetching contacts for user {}: {:?}",
+                auth_user.user_id, e
+            );
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to fetch contacts",
+                "details": format!("{:?}", e)
+            }));
+        }
+    };
+
+    if contacts.is_empty() {
+        return conditional::json_response(&req, Vec::<ContactResponse>::new(), None);
+    }
+
+    let loaded = if mask.is_none_or(FieldMask::needs_details) {
+        contact_responses(repo.get_ref(), auth_user.workspace_id, &contacts, &dates).await
+    } else {
+        tagged_contact_responses(repo.get_ref(), contacts, &dates).await
+    };
+    let mut response = match loaded {
+        Ok(response) => response,
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            return HttpResponse::InternalServerError().body("Failed to fetch contacts");
+        }
+    };
+
+    if with_summary {
+        let contact_ids: Vec<i32> = response.iter().map(|r| r.contact.contact_id).collect();
+        let summaries = match repo
+            .contact_summaries(auth_user.workspace_id, &contact_ids, dates.today())
+            .await
+        {
+            Ok(summaries) => summaries,
+            Err(e) => {
+                eprintln!("Database error: {:?}", e);
+                return HttpResponse::InternalServerError().body("Failed to fetch contacts");
+            }
+        };
+        let mut summaries: HashMap<i32, ContactSummary> = summaries.into_iter().collect();
+        for response in &mut response {
+            response.summary = summaries.remove(&response.contact.contact_id);
+        }
+    }
+
+    if let Some(gravatar) = gravatar
+        && mask.is_none_or(FieldMask::needs_avatar)
+    {
+        gravatar.fill(&mut response).await;
+    }
+
+    match mask {
+        None => conditional::json_response(&req, response, None),
+        Some(mask) => {
+            let masked: Vec<Masked> = response
+                .into_iter()
+                .map(|response| Masked { response, mask })
+                .collect();
+            conditional::json_response(&req, masked, None)
+        }
+    }
+}
+
+/// Responses with the contacts' tags but no interactions or occasions, for field masks that
+/// leave those out
+async fn tagged_contact_responses(
+    repo: &dyn Repository,
+    contacts: Vec<Contact>,
+    dates: &LocalDates,
+) -> RepoResult<Vec<ContactResponse>> {
+    let contact_ids: Vec<i32> = contacts.iter().map(|c| c.contact_id).collect();
+    let mut tags: HashMap<i32, Vec<Tag>> = HashMap::new();
+    for (contact_id, tag) in repo.tags_for_contacts(&contact_ids).await? {
+        tags.entry(contact_id).or_default().push(tag);
+    }
+    Ok(contacts
+        .into_iter()
+        .map(|contact| {
+            let mut tags = tags.remove(&contact.contact_id).unwrap_or_default();
+            // In the order contact_details gives them
+            tags.sort_by_key(|tag| tag.tag_id);
+            ContactResponse::new(contact, tags, Vec::new(), Vec::new(), dates)
+        })
+        .collect())
+}
+
+/// Load the contacts' tags, interactions and occasions, keeping the contacts' order
+pub(crate) async fn contact_responses(
+    repo: &dyn Repository,
+    workspace_id: i32,
+    contacts: &[Contact],
+    dates: &LocalDates,
+) -> RepoResult<Vec<ContactResponse>> {
+    let contact_ids: Vec<i32> = contacts.iter().map(|c| c.contact_id).collect();
+    let details = repo.contact_details(workspace_id, &contact_ids).await?;
+    Ok(details
+        .into_iter()
+        .map(|d| ContactResponse::new(d.contact, d.tags, d.interactions, d.occasions, dates))
+        .collect())
+}
+
+#[derive(Serialize)]
+struct ChecksumResponse {
+    global_hash: String,
+    count: usize,
+    contacts: Vec<ContactChecksum>,
+}
+
+/// Content hashes for every contact (including its tags, interactions and occasions)
+/// so sync clients can detect divergence and re-fetch only mismatched records
+#[get("/contacts/checksum")]
+pub async fn contacts_checksum(
+    repo: web::Data<dyn Repository>,
+    auth_user: AuthUser,
+) -> impl Responder {
+    match repo.contact_checksums(auth_user.workspace_id).await {
+        Ok(contacts) => {
+            let mut hasher = Sha256::new();
+            for contact in &contacts {
+                hasher.update(format!("{}:{}\n", contact.contact_id, contact.hash));
+            }
+            HttpResponse::Ok().json(ChecksumResponse {
+                global_hash: format!("{:x}", hasher.finalize()),
+                count: contacts.len(),
+                contacts,
+            })
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to compute checksums")
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct LookupQuery {
+    phone: String,
+}
+
+/// Contacts whose phone number is `?phone=`, however either was formatted (caller ID). Numbers
+/// without a country code are read in PHONE_DEFAULT_REGION.
+#[get("/contacts/lookup")]
+pub async fn lookup_contacts(
+    repo: web::Data<dyn Repository>,
+    auth_user: AuthUser,
+    query: web::Query<LookupQuery>,
+) -> impl Responder {
+    let Some(number) = phone::normalize(&query.phone) else {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "phone is not a valid phone number"
+        }));
+    };
+
+    match repo
+        .contacts_by_phone(auth_user.workspace_id, &number)
+        .await
+    {
+        Ok(contacts) => HttpResponse::Ok().json(contacts),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to look up contacts")
+        }
+    }
+}
+
+/// Most contacts one autocomplete request returns
+const MAX_AUTOCOMPLETE_LIMIT: u32 = 25;
+
+#[derive(Deserialize)]
+struct AutocompleteQuery {
+    #[serde(default)]
+    q: String,
+    #[serde(default = "default_autocomplete_limit")]
+    limit: u32,
+}
+
+fn default_autocomplete_limit() -> u32 {
+    10
+}
+
+/// Unarchived contacts for pickers as the user types, by name: every word of `?q=` must start
+/// the first or last name, so "al tu" finds Alan Turing. Only the uploaded photo is sent as the
+/// avatar; Gravatar lookups would cost more than the whole search.
+#[get("/contacts/autocomplete")]
+pub async fn autocomplete_contacts(
+    repo: web::Data<dyn Repository>,
+    auth_user: AuthUser,
+    query: web::Query<AutocompleteQuery>,
+) -> impl Responder {
+    if !(1..=MAX_AUTOCOMPLETE_LIMIT).contains(&query.limit) {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("limit must be 1 to {}", MAX_AUTOCOMPLETE_LIMIT)
+        }));
+    }
+    let words: Vec<String> = query.q.split_whitespace().map(str::to_lowercase).collect();
+    if words.is_empty() {
+        return HttpResponse::Ok().json(Vec::<ContactMatch>::new());
+    }
+
+    match repo
+        .autocomplete_contacts(auth_user.workspace_id, &words, query.limit.into())
+        .await
+    {
+        Ok(matches) => HttpResponse::Ok().json(matches),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to search contacts")
+        }
+    }
+}
+
+/// Widest `/contacts/nearby` search, in kilometres
+const MAX_RADIUS_KM: f64 = 500.0;
+
+#[derive(Deserialize)]
+struct NearbyQuery {
+    lat: f64,
+    lng: f64,
+    #[serde(default = "default_radius_km")]
+    radius_km: f64,
+}
+
+fn default_radius_km() -> f64 {
+    25.0
+}
+
+/// Unarchived contacts living within `?radius_km=` of `?lat=` and `?lng=`, nearest first, for
+/// seeing who to meet up with on a trip. Only contacts whose location was geocoded are found.
+#[get("/contacts/nearby")]
+pub async fn nearby_contacts(
+    repo: web::Data<dyn Repository>,
+    auth_user: AuthUser,
+    query: web::Query<NearbyQuery>,
+) -> impl Responder {
+    if !(-90.0..=90.0).contains(&query.lat) || !(-180.0..=180.0).contains(&query.lng) {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "lat must be -90 to 90 and lng -180 to 180"
+        }));
+    }
+    if !(query.radius_km > 0.0 && query.radius_km <= MAX_RADIUS_KM) {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("radius_km must be above 0 and up to {}", MAX_RADIUS_KM)
+        }));
+    }
+    let center = Coordinates {
+        latitude: query.lat,
+        longitude: query.lng,
+    };
+
+    let contacts = match repo
+        .contacts_in_box(
+            auth_user.workspace_id,
+            &BoundingBox::around(center, query.radius_km),
+        )
+        .await
+    {
+        Ok(contacts) => contacts,
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            return HttpResponse::InternalServerError().body("Failed to find nearby contacts");
+        }
+    };
+    // The box's corners lie beyond the radius
+    let mut nearby: Vec<NearbyContact> = contacts
+        .into_iter()
+        .filter_map(|contact| {
+            let position = Coordinates {
+                latitude: contact.latitude?,
+                longitude: contact.longitude?,
+            };
+            let distance_km = geocoding::distance_km(center, position);
+            (distance_km <= query.radius_km).then_some(NearbyContact {
+                contact,
+                distance_km,
+            })
+        })
+        .collect();
+    nearby.sort_by(|a, b| {
+        a.distance_km
+            .total_cmp(&b.distance_km)
+            .then(a.contact.contact_id.cmp(&b.contact.contact_id))
+    });
+    HttpResponse::Ok().json(nearby)
+}
+
+#[post("/contacts")]
+pub async fn create_contact(
+    req: HttpRequest,
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    geocoding: Option<web::Data<Geocoding>>,
+    auth_user: AuthUser,
+    quotas: Option<web::Data<Quotas>>,
+    new_contact: web::Json<NewContactRequest>,
+) -> impl Responder {
+    if let Err(error) = validate(&new_contact) {
+        return HttpResponse::BadRequest().json(error);
+    }
+    if let Some(response) = quotas::configured(quotas.as_ref())
+        .reject_contacts(repo.get_ref(), &auth_user, 1)
+        .await
+    {
+        return response;
+    }
+    match check_introducer(repo.get_ref(), auth_user.workspace_id, None, &new_contact).await {
+        Ok(Ok(())) => {}
+        Ok(Err(error)) => return HttpResponse::BadRequest().json(error),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            return HttpResponse::InternalServerError().body("Failed to create contact");
+        }
+    }
+
+    match repo
+        .create_contact(auth_user.workspace_id, &new_contact)
+        .await
+    {
+        Ok(contact_id) => {
+            locate(
+                geocoding.as_ref(),
+                repo.get_ref(),
+                auth_user.workspace_id,
+                [(contact_id, &*new_contact)],
+            )
+            .await;
+            events::publish(
+                bus.as_ref(),
+                auth_user.user_id,
+                Entity::Contact,
+                Action::Created,
+                vec![contact_id],
+            );
+            mutations::created(
+                &req,
+                contact_id,
+                repo.get_contact(auth_user.workspace_id, contact_id).await,
+            )
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to create contact")
+        }
+    }
+}
+
+/// What saving `plan` would do, for `?dry_run=true`: how many contacts would be created and
+/// updated, what would happen to each record, which records' emails are taken, by existing
+/// contacts or earlier records, and which would fail otherwise. `errors` are the failures found
+/// before saving. Nothing is kept.
+pub(crate) async fn preview_create(
+    repo: &dyn Repository,
+    workspace_id: i32,
+    plan: &Plan,
+    atomic: bool,
+    mut errors: Vec<serde_json::Value>,
+) -> RepoResult<serde_json::Value> {
+    let results = repo.check_contacts(workspace_id, &plan.contacts).await?;
+    let mut would_create = 0;
+    let mut duplicates = Vec::new();
+    let mut failed = false;
+    for ((index, contact), result) in plan.indexes.iter().zip(&plan.contacts).zip(results) {
+        match result {
+            Ok(()) => would_create += 1,
+            Err(e) if repository::is_unique_violation(&e) => {
+                failed = true;
+                duplicates.push(serde_json::json!({"index": index, "email": contact.email}));
+            }
+            Err(e) => {
+                failed = true;
+                errors.push(serde_json::json!({"index": index, "error": format!("{:?}", e)}));
+            }
+        }
+    }
+    errors.sort_by_key(|e| e["index"].as_u64());
+    let mut would_update = plan.updates.len();
+    // An atomic batch with a failing row rolls back entirely, before any update
+    if atomic && failed {
+        would_create = 0;
+        would_update = 0;
+    }
+
+    Ok(serde_json::json!({
+        "dry_run": true,
+        "created_count": would_create,
+        "updated_count": would_update,
+        "rows": plan.report(None, &[]),
+        "duplicates": duplicates,
+        "errors": errors,
+        "message": format!("Would create {} contacts", would_create)
+    }))
+}
+
+#[derive(Deserialize)]
+struct BulkCreateQuery {
+    /// Roll back the whole batch if any contact fails
+    #[serde(default)]
+    atomic: bool,
+    /// Report what would be created without saving anything
+    #[serde(default)]
+    dry_run: bool,
+    /// What to do with contacts that match existing ones
+    #[serde(default)]
+    dedupe: DedupePolicy,
+}
+
+/// Create many contacts with one INSERT in a single transaction.
+/// `?atomic=true` creates all of them or none; by default failing rows are reported and skipped.
+/// `?dedupe=` skips, overwrites or merges into contacts that already exist instead of creating
+/// them again, reporting what happened to each one under `rows`. `?dry_run=true` reports what
+/// would be created, and which emails are already taken, without saving anything.
+#[allow(clippy::too_many_arguments)]
+#[post("/contacts/bulk")]
+pub async fn create_contacts_bulk(
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    geocoding: Option<web::Data<Geocoding>>,
+    auth_user: AuthUser,
+    limits: Option<web::Data<Limits>>,
+    quotas: Option<web::Data<Quotas>>,
+    query: web::Query<BulkCreateQuery>,
+    new_contacts: web::Json<Vec<NewContactRequest>>,
+) -> impl Responder {
+    if let Some(response) = limits::configured(limits.as_ref()).reject_bulk(new_contacts.len()) {
+        return response;
+    }
+
+    for (index, contact) in new_contacts.iter().enumerate() {
+        let checked =
+            match check_introducer(repo.get_ref(), auth_user.workspace_id, None, contact).await {
+                Ok(checked) => validate(contact).and(checked),
+                Err(e) => {
+                    eprintln!("Database error: {:?}", e);
+                    return HttpResponse::InternalServerError().body("Failed to create contacts");
+                }
+            };
+        if let Err(mut error) = checked {
+            error["index"] = index.into();
+            return HttpResponse::BadRequest().json(error);
+        }
+    }
+
+    let existing =
+        match dedupe::existing_contacts(repo.get_ref(), auth_user.workspace_id, query.dedupe).await
+        {
+            Ok(existing) => existing,
+            Err(e) => {
+                eprintln!("Database error: {:?}", e);
+                return HttpResponse::InternalServerError().body("Failed to create contacts");
+            }
+        };
+    let plan = Plan::new(
+        query.dedupe,
+        &existing,
+        new_contacts.into_inner().into_iter().enumerate(),
+    );
+
+    if let Some(response) = quotas::configured(quotas.as_ref())
+        .reject_contacts(repo.get_ref(), &auth_user, plan.contacts.len())
+        .await
+    {
+        return response;
+    }
+
+    if query.dry_run {
+        return match preview_create(
+            repo.get_ref(),
+            auth_user.workspace_id,
+            &plan,
+            query.atomic,
+            Vec::new(),
+        )
+        .await
+        {
+            Ok(preview) => HttpResponse::Ok().json(preview),
+            Err(e) => {
+                eprintln!("Database error: {:?}", e);
+                HttpResponse::InternalServerError().body("Failed to check contacts")
+            }
+        };
+    }
+
+    let results = match repo
+        .create_contacts(auth_user.workspace_id, &plan.contacts, query.atomic)
+        .await
+    {
+        Ok(results) => results,
+        Err(e) => {
+            eprintln!("Database error creating contacts: {:?}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to create contacts",
+                "details": format!("{:?}", e)
+            }));
+        }
+    };
+
+    let mut created_ids = Vec::new();
+    let mut saved = Vec::new();
+    let mut errors = Vec::new();
+
+    for (index, result) in plan.indexes.iter().zip(results) {
+        match result {
+            Ok(contact_id) => {
+                created_ids.push(contact_id);
+                saved.push(Some(contact_id));
+            }
+            Err(e) => {
+                eprintln!("Database error creating contact {}: {:?}", index, e);
+                saved.push(None);
+                errors.push(serde_json::json!({
+                    "index": index,
+                    "error": format!("{:?}", e)
+                }));
+            }
+        }
+    }
+    let (updated_ids, failed_ids) = dedupe::save_updates(
+        repo.get_ref(),
+        auth_user.workspace_id,
+        &plan.updates,
+        &mut errors,
+    )
+    .await;
+    errors.sort_by_key(|e| e["index"].as_u64());
+
+    let created = plan
+        .contacts
+        .iter()
+        .zip(&saved)
+        .filter_map(|(contact, contact_id)| Some(((*contact_id)?, contact)));
+    let updated = plan
+        .updates
+        .iter()
+        .filter(|u| !failed_ids.contains(&u.contact_id))
+        .map(|u| (u.contact_id, &u.contact));
+    locate(
+        geocoding.as_ref(),
+        repo.get_ref(),
+        auth_user.workspace_id,
+        created.chain(updated),
+    )
+    .await;
+    publish_saved(&bus, &auth_user, &created_ids, &updated_ids);
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "created_contact_ids": created_ids,
+        "updated_contact_ids": updated_ids,
+        "rows": plan.report(Some(&saved), &failed_ids),
+        "errors": errors,
+        "message": format!("Created {} contacts", created_ids.len())
+    }))
+}
+
+/// Announce the contacts an import created and updated
+pub(crate) fn publish_saved(
+    bus: &Option<web::Data<EventBus>>,
+    auth_user: &AuthUser,
+    created_ids: &[i32],
+    updated_ids: &[i32],
+) {
+    for (action, ids) in [
+        (Action::Created, created_ids),
+        (Action::Updated, updated_ids),
+    ] {
+        if !ids.is_empty() {
+            events::publish(
+                bus.as_ref(),
+                auth_user.user_id,
+                Entity::Contact,
+                action,
+                ids.to_vec(),
+            );
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct DeleteQuery {
+    /// Delete the contact's history with it even under CONTACT_DELETE_BEHAVIOR=block
+    #[serde(default)]
+    force: bool,
+}
+
+/// Delete a contact. Its interactions and occasions go with it, are kept unassigned, or stop
+/// the delete with 409, as CONTACT_DELETE_BEHAVIOR says.
+#[delete("/contacts/{id}")]
+pub async fn delete_contact(
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    policies: Option<web::Data<DeletePolicies>>,
+    auth_user: AuthUser,
+    contact_id: web::Path<i32>,
+    query: web::Query<DeleteQuery>,
+) -> impl Responder {
+    let id = contact_id.into_inner();
+    let keep_history = match deletion::configured(policies.as_ref())
+        .contacts
+        .keep_history(repo.get_ref(), auth_user.workspace_id, &[id], query.force)
+        .await
+    {
+        Ok(keep_history) => keep_history,
+        Err(response) => return response,
+    };
+
+    match repo
+        .delete_contact(auth_user.workspace_id, id, keep_history)
+        .await
+    {
+        Ok(false) => HttpResponse::NotFound().body("Contact not found"),
+        Ok(true) => {
+            events::publish(
+                bus.as_ref(),
+                auth_user.user_id,
+                Entity::Contact,
+                Action::Deleted,
+                vec![id],
+            );
+            HttpResponse::Ok().body("Contact deleted successfully")
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to delete contact")
+        }
+    }
+}
+
+/// Validate and save `updated_contact` over contact `id`, which `access` allows writing
+async fn save_contact(
+    repo: &dyn Repository,
+    bus: Option<&web::Data<EventBus>>,
+    geocoding: Option<&web::Data<Geocoding>>,
+    access: &ContactAccess,
+    id: i32,
+    updated_contact: &NewContactRequest,
+) -> HttpResponse {
+    if let Err(error) = validate(updated_contact) {
+        return HttpResponse::BadRequest().json(error);
+    }
+    match check_introducer(repo, access.workspace_id, Some(id), updated_contact).await {
+        Ok(Ok(())) => {}
+        Ok(Err(error)) => return HttpResponse::BadRequest().json(error),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            return HttpResponse::InternalServerError().body("Failed to update contact");
+        }
+    }
+
+    match repo
+        .update_contact(access.workspace_id, id, updated_contact)
+        .await
+    {
+        Ok(false) => HttpResponse::NotFound().body("Contact not found"),
+        Ok(true) => {
+            locate(
+                geocoding,
+                repo,
+                access.workspace_id,
+                [(id, updated_contact)],
+            )
+            .await;
+            events::publish(
+                bus,
+                access.owner_id,
+                Entity::Contact,
+                Action::Updated,
+                vec![id],
+            );
+            mutations::updated(repo.get_contact(access.workspace_id, id).await)
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to update contact")
+        }
+    }
+}
+
+/// Replace the contact's fields with the ones sent; fields left out are cleared
+#[patch("/contacts/{id}")]
+pub async fn update_contact(
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    geocoding: Option<web::Data<Geocoding>>,
+    auth_user: AuthUser,
+    contact_id: web::Path<i32>,
+    updated_contact: web::Json<NewContactRequest>,
+) -> impl Responder {
+    let id = contact_id.into_inner();
+    // The user's own contact or one shared with them for writing
+    let access =
+        match ensure_contact_access(repo.get_ref(), &auth_user, id, SharePermission::Write).await {
+            Ok(access) => access,
+            Err(response) => return response,
+        };
+    save_contact(
+        repo.get_ref(),
+        bus.as_ref(),
+        geocoding.as_ref(),
+        &access,
+        id,
+        &updated_contact,
+    )
+    .await
+}
+
+/// Requests with a JSON Patch body, for `patch_contact`
+fn is_json_patch(ctx: &GuardContext) -> bool {
+    ctx.header::<ContentType>()
+        .is_some_and(|content_type| content_type.essence_str() == JSON_PATCH)
+}
+
+/// Apply an RFC 6902 JSON Patch to the contact's fields as `PATCH /contacts/{id}` with a JSON
+/// body takes them, so a client can change one field without sending the others. The
+/// operations apply all or nothing; a failed `test` gets 409 with the contact's fields as
+/// saved, anything else that can't apply 422. The result is validated and saved like a whole
+/// update.
+#[patch("/contacts/{id}", guard = "is_json_patch")]
+pub async fn patch_contact(
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    geocoding: Option<web::Data<Geocoding>>,
+    auth_user: AuthUser,
+    contact_id: web::Path<i32>,
+    patch: web::Json<json_patch::Patch>,
+) -> impl Responder {
+    let id = contact_id.into_inner();
+    let access =
+        match ensure_contact_access(repo.get_ref(), &auth_user, id, SharePermission::Write).await {
+            Ok(access) => access,
+            Err(response) => return response,
+        };
+    let saved = match repo.get_contact(access.workspace_id, id).await {
+        Ok(Some(contact)) => NewContactRequest::from(&contact),
+        Ok(None) => return HttpResponse::NotFound().body("Contact not found"),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            return HttpResponse::InternalServerError().body("Failed to fetch contact");
+        }
+    };
+    let updated_contact = match apply_patch(&saved, &patch) {
+        Ok(contact) => contact,
+        Err(PatchFailure::Conflict(error)) => {
+            return HttpResponse::Conflict().json(serde_json::json!({
+                "error": error,
+                "contact": saved
+            }));
+        }
+        Err(PatchFailure::Invalid(error)) => {
+            return HttpResponse::UnprocessableEntity().json(serde_json::json!({ "error": error }));
+        }
+    };
+    save_contact(
+        repo.get_ref(),
+        bus.as_ref(),
+        geocoding.as_ref(),
+        &access,
+        id,
+        &updated_contact,
+    )
+    .await
+}
+
+/// Why a JSON Patch could not be applied, as the error message
+pub enum PatchFailure {
+    /// A `test` operation found another value
+    Conflict(String),
+    /// A path that doesn't exist, a field contacts don't have or a value of the wrong type
+    Invalid(String),
+}
+
+/// Apply `patch` to the contact's fields, keeping to the fields a contact has
+pub fn apply_patch(
+    contact: &NewContactRequest,
+    patch: &json_patch::Patch,
+) -> Result<NewContactRequest, PatchFailure> {
+    let mut document = serde_json::to_value(contact).expect("contact fields serialize");
+    json_patch::patch(&mut document, patch).map_err(|e| {
+        let error = format!("Operation {} on \"{}\": {}", e.operation, e.path, e.kind);
+        match e.kind {
+            json_patch::PatchErrorKind::TestFailed => PatchFailure::Conflict(error),
+            _ => PatchFailure::Invalid(error),
+        }
+    })?;
+    // Removing a field clears it, but the document can't gain one
+    let fields = serde_json::to_value(contact).expect("contact fields serialize");
+    let fields = fields.as_object().expect("contact fields are an object");
+    if let Some(object) = document.as_object()
+        && let Some(unknown) = object.keys().find(|key| !fields.contains_key(*key))
+    {
+        return Err(PatchFailure::Invalid(format!("Unknown field {}", unknown)));
+    }
+    serde_json::from_value(document).map_err(|e| PatchFailure::Invalid(e.to_string()))
+}
+
+/// Archive or unarchive a contact; its tags, interactions and occasions are kept either way
+async fn set_archived(
+    repo: &dyn Repository,
+    bus: Option<&web::Data<EventBus>>,
+    auth_user: &AuthUser,
+    contact_id: i32,
+    archived: bool,
+) -> HttpResponse {
+    match repo
+        .set_contact_archived(auth_user.workspace_id, contact_id, archived)
+        .await
+    {
+        Ok(false) => HttpResponse::NotFound().body("Contact not found"),
+        Ok(true) => {
+            events::publish(
+                bus,
+                auth_user.user_id,
+                Entity::Contact,
+                Action::Updated,
+                vec![contact_id],
+            );
+            mutations::updated(repo.get_contact(auth_user.workspace_id, contact_id).await)
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to update contact")
+        }
+    }
+}
+
+#[post("/contacts/{id}/archive")]
+pub async fn archive_contact(
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    auth_user: AuthUser,
+    contact_id: web::Path<i32>,
+) -> impl Responder {
+    set_archived(
+        repo.get_ref(),
+        bus.as_ref(),
+        &auth_user,
+        contact_id.into_inner(),
+        true,
+    )
+    .await
+}
+
+#[post("/contacts/{id}/unarchive")]
+pub async fn unarchive_contact(
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    auth_user: AuthUser,
+    contact_id: web::Path<i32>,
+) -> impl Responder {
+    set_archived(
+        repo.get_ref(),
+        bus.as_ref(),
+        &auth_user,
+        contact_id.into_inner(),
+        false,
+    )
+    .await
+}
+
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum InteractionOrder {
+    /// Oldest first
+    #[default]
+    Asc,
+    /// Newest first
+    Desc,
+}
+
+#[derive(Deserialize)]
+struct GetContactQuery {
+    /// Comma-separated lists to send, of `interactions` and `occasions`; both when left out
+    include: Option<String>,
+    /// Most interactions to send, taken in `interactions_order`; all of them when left out
+    interactions_limit: Option<u32>,
+    #[serde(default)]
+    interactions_order: InteractionOrder,
+}
+
+/// Responses carry an ETag and Last-Modified for conditional requests.
+/// `?include=` sends only the named lists, so `?include=` alone sends neither.
+/// Interactions are sent by date in `?interactions_order=`, up to `?interactions_limit=`;
+/// predicted_contact_priority, overdue_days and health still count all of them.
+#[get("/contacts/{id}")]
+pub async fn get_contact(
+    req: HttpRequest,
+    repo: web::Data<dyn Repository>,
+    gravatar: Option<web::Data<GravatarResolver>>,
+    auth_user: AuthUser,
+    contact_id: web::Path<i32>,
+    query: web::Query<GetContactQuery>,
+) -> impl Responder {
+    let id = contact_id.into_inner();
+
+    let mut mask = FieldMask::all();
+    if let Some(include) = &query.include {
+        let (mut interactions, mut occasions) = (false, false);
+        for include in include.split(',').map(str::trim) {
+            match include {
+                "interactions" => interactions = true,
+                "occasions" => occasions = true,
+                "" => {}
+                other => {
+                    return HttpResponse::BadRequest().json(serde_json::json!({
+                        "error": format!("Unknown include {}", other)
+                    }));
+                }
+            }
+        }
+        if !interactions {
+            mask = mask.without("interactions");
+        }
+        if !occasions {
+            mask = mask.without("occasions");
+        }
+    }
+
+    // The user's own contact or one shared with them
+    let access =
+        match ensure_contact_access(repo.get_ref(), &auth_user, id, SharePermission::Read).await {
+            Ok(access) => access,
+            Err(response) => return response,
+        };
+    let details = match repo.contact_details(access.workspace_id, &[id]).await {
+        Ok(details) => details.into_iter().next(),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            return HttpResponse::InternalServerError().body("Failed to fetch contact");
+        }
+    };
+    let Some(details) = details else {
+        return HttpResponse::NotFound().body("Contact not found");
+    };
+
+    let dates = LocalDates::for_user(repo.get_ref(), auth_user.user_id)
+        .await
+        .unwrap_or_default();
+    let updated_at = details.contact.updated_at;
+    let mut response = [ContactResponse::new(
+        details.contact,
+        details.tags,
+        details.interactions,
+        details.occasions,
+        &dates,
+    )];
+    if let Some(gravatar) = gravatar {
+        gravatar.fill(&mut response).await;
+    }
+    let [mut response] = response;
+
+    let interactions = &mut response.interactions;
+    interactions.sort_by_key(|i| (i.interaction_date, i.interaction_id));
+    if let InteractionOrder::Desc = query.interactions_order {
+        interactions.reverse();
+    }
+    if let Some(limit) = query.interactions_limit {
+        interactions.truncate(limit as usize);
+    }
+
+    conditional::json_response(&req, Masked { response, mask }, updated_at)
+}
+
+/// The contacts `{id}` introduced the user to ("who did I meet through Dana?"), by name.
+/// Only for the user's own contacts: the list can name contacts a share leaves out.
+#[get("/contacts/{id}/introductions")]
+pub async fn contact_introductions(
+    repo: web::Data<dyn Repository>,
+    auth_user: AuthUser,
+    Owned(contact): OwnedContact,
+) -> impl Responder {
+    match repo
+        .contacts_introduced_by(auth_user.workspace_id, contact.contact_id)
+        .await
+    {
+        Ok(contacts) => HttpResponse::Ok().json(contacts),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to fetch introductions")
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct BulkDeleteRequest {
+    contact_ids: Vec<i32>,
+}
+
+#[derive(Deserialize)]
+struct BulkDeleteQuery {
+    /// Report what would be deleted without deleting anything
+    #[serde(default)]
+    dry_run: bool,
+    /// As for deleting one contact
+    #[serde(default)]
+    force: bool,
+}
+
+/// Delete many contacts at once. Needs a recent sign-in. `?dry_run=true` lists the contacts
+/// that would be deleted, with the interactions and occasions that would go with them or be
+/// kept unassigned, and deletes nothing. Under CONTACT_DELETE_BEHAVIOR=block one contact with
+/// history refuses the whole request.
+#[allow(clippy::too_many_arguments)]
+#[post("/contacts/bulk-delete")]
+pub async fn bulk_delete_contacts(
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    auth_user: AuthUser,
+    limits: Option<web::Data<Limits>>,
+    reauth: Option<web::Data<Reauth>>,
+    policies: Option<web::Data<DeletePolicies>>,
+    query: web::Query<BulkDeleteQuery>,
+    request: web::Json<BulkDeleteRequest>,
+) -> impl Responder {
+    if let Some(response) = reauth::configured(reauth.as_ref()).reject_stale(&auth_user) {
+        return response;
+    }
+    if let Some(response) =
+        limits::configured(limits.as_ref()).reject_bulk(request.contact_ids.len())
+    {
+        return response;
+    }
+    let keep_history = match deletion::configured(policies.as_ref())
+        .contacts
+        .keep_history(
+            repo.get_ref(),
+            auth_user.workspace_id,
+            &request.contact_ids,
+            query.force,
+        )
+        .await
+    {
+        Ok(keep_history) => keep_history,
+        Err(response) => return response,
+    };
+    if query.dry_run {
+        return preview_delete(
+            repo.get_ref(),
+            auth_user.workspace_id,
+            &request.contact_ids,
+            keep_history,
+        )
+        .await;
+    }
+    let deleted = match repo
+        .delete_contacts(auth_user.workspace_id, &request.contact_ids, keep_history)
+        .await
+    {
+        Ok(deleted) => deleted,
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            return HttpResponse::InternalServerError().body("Failed to delete contacts");
+        }
+    };
+
+    if !deleted.is_empty() {
+        events::publish(
+            bus.as_ref(),
+            auth_user.user_id,
+            Entity::Contact,
+            Action::Deleted,
+            deleted.clone(),
+        );
+    }
+
+    let skipped = skipped_ids(&request.contact_ids, &deleted);
+    let errors: Vec<_> = skipped
+        .iter()
+        .map(|id| serde_json::json!({"contact_id": id, "error": "Contact not found"}))
+        .collect();
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "deleted_count": deleted.len(),
+        "skipped_ids": skipped,
+        "errors": errors,
+        "message": format!("Deleted {} contacts", deleted.len())
+    }))
+}
+
+/// What a bulk delete of `contact_ids` would do, for `?dry_run=true`
+async fn preview_delete(
+    repo: &dyn Repository,
+    workspace_id: i32,
+    contact_ids: &[i32],
+    keep_history: bool,
+) -> HttpResponse {
+    let mut details = match repo.contact_details(workspace_id, contact_ids).await {
+        Ok(details) => details,
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            return HttpResponse::InternalServerError().body("Failed to check contacts");
+        }
+    };
+    let mut seen = HashSet::new();
+    details.retain(|d| seen.insert(d.contact.contact_id));
+    let deleted: Vec<i32> = details.iter().map(|d| d.contact.contact_id).collect();
+    // Interactions are deleted with their main contact; others only lose a participant
+    let interactions: HashSet<i32> = details
+        .iter()
+        .flat_map(|d| &d.interactions)
+        .filter(|i| i.contact_id.is_some_and(|c| deleted.contains(&c)))
+        .map(|i| i.interaction_id)
+        .collect();
+    let occasions: usize = details.iter().map(|d| d.occasions.len()).sum();
+
+    let skipped = skipped_ids(contact_ids, &deleted);
+    let errors: Vec<_> = skipped
+        .iter()
+        .map(|id| serde_json::json!({"contact_id": id, "error": "Contact not found"}))
+        .collect();
+
+    // History kept unassigned is counted apart from what is deleted
+    let gone = |count: usize| if keep_history { 0 } else { count };
+    let kept = |count: usize| if keep_history { count } else { 0 };
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "dry_run": true,
+        "deleted_count": deleted.len(),
+        "deleted_ids": deleted,
+        "interaction_count": gone(interactions.len()),
+        "occasion_count": gone(occasions),
+        "unassigned_interaction_count": kept(interactions.len()),
+        "unassigned_occasion_count": kept(occasions),
+        "skipped_ids": skipped,
+        "errors": errors,
+        "message": format!("Would delete {} contacts", deleted.len())
+    }))
+}
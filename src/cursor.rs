@@ -0,0 +1,59 @@
+//! Keyset pagination for collections listed newest first by (timestamp, id).
+//!
+//! A page ends with a `Link: <...&after=CURSOR>; rel="next"` header when it is full; the cursor
+//! is the position of the page's last row, and the next page starts strictly after it. Rows
+//! inserted meanwhile never shift later pages the way offset paging does, and each page is an
+//! index range scan however deep it is.
+
+use actix_web::HttpRequest;
+use time::OffsetDateTime;
+
+/// Largest page a client may ask for
+pub const MAX_PAGE_SIZE: u32 = 500;
+
+/// Position of a row in (timestamp, id) order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Cursor {
+    pub at: OffsetDateTime,
+    pub id: i32,
+}
+
+impl Cursor {
+    pub fn new(at: OffsetDateTime, id: i32) -> Self {
+        Cursor { at, id }
+    }
+
+    /// 40 hex digits: the timestamp in nanoseconds, then the id. Clients should treat it as
+    /// opaque.
+    pub fn encode(&self) -> String {
+        format!(
+            "{:032x}{:08x}",
+            self.at.unix_timestamp_nanos() as u128,
+            self.id as u32
+        )
+    }
+
+    /// None for anything `encode` did not produce
+    pub fn decode(cursor: &str) -> Option<Cursor> {
+        if cursor.len() != 40 || !cursor.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        let nanos = u128::from_str_radix(&cursor[..32], 16).ok()? as i128;
+        let id = u32::from_str_radix(&cursor[32..], 16).ok()? as i32;
+        let at = OffsetDateTime::from_unix_timestamp_nanos(nanos).ok()?;
+        Some(Cursor { at, id })
+    }
+}
+
+/// The `Link` header value pointing at the page after `cursor`: the request's own URL with
+/// its `after` parameter replaced
+pub fn next_page_link(req: &HttpRequest, cursor: &Cursor) -> String {
+    let after = format!("after={}", cursor.encode());
+    let query: Vec<&str> = req
+        .query_string()
+        .split('&')
+        .filter(|pair| !pair.is_empty() && !pair.starts_with("after="))
+        .chain([after.as_str()])
+        .collect();
+    format!("<{}?{}>; rel=\"next\"", req.path(), query.join("&"))
+}
@@ -0,0 +1,148 @@
+//! Connection pool settings, startup connection retry, migrations and read replica routing
+
+use crate::maintenance::is_read;
+use actix_web::Error;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use sqlx::PgPool;
+use sqlx::migrate::Migrator;
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use std::future::Future;
+use std::time::Duration;
+
+/// The Postgres schema, applied in order on startup. Migrations that already ran are recorded in
+/// `_sqlx_migrations` and skipped; a released migration is never edited, since its checksum is
+/// recorded too, so schema changes go in a new file.
+pub static MIGRATOR: Migrator = sqlx::migrate!("./migrations/postgres");
+
+/// Longest wait between startup connection attempts
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone)]
+pub struct DbConfig {
+    pub max_connections: u32,
+    /// Connections kept open even when idle
+    pub min_connections: u32,
+    /// How long a request waits for a free connection before failing
+    pub acquire_timeout: Duration,
+    /// Statements running longer are cancelled by the server; None for no limit
+    pub statement_timeout: Option<Duration>,
+    /// Tries at startup before giving up, waiting twice as long after each failure
+    pub connect_attempts: u32,
+    /// Wait after the first failed attempt
+    pub initial_backoff: Duration,
+}
+
+impl Default for DbConfig {
+    fn default() -> Self {
+        DbConfig {
+            max_connections: 10,
+            min_connections: 0,
+            acquire_timeout: Duration::from_secs(30),
+            statement_timeout: Some(Duration::from_secs(30)),
+            connect_attempts: 5,
+            initial_backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+impl DbConfig {
+    /// Read DB_MAX_CONNECTIONS, DB_MIN_CONNECTIONS, DB_ACQUIRE_TIMEOUT_SECS,
+    /// DB_STATEMENT_TIMEOUT_MS (0 for no limit) and DB_CONNECT_ATTEMPTS, falling back to the
+    /// defaults for anything unset or unparsable
+    pub fn from_env() -> Self {
+        let defaults = DbConfig::default();
+        let statement_timeout_ms = env_or(
+            "DB_STATEMENT_TIMEOUT_MS",
+            defaults
+                .statement_timeout
+                .map_or(0, |t| t.as_millis() as u64),
+        );
+        DbConfig {
+            max_connections: env_or("DB_MAX_CONNECTIONS", defaults.max_connections),
+            min_connections: env_or("DB_MIN_CONNECTIONS", defaults.min_connections),
+            acquire_timeout: Duration::from_secs(env_or(
+                "DB_ACQUIRE_TIMEOUT_SECS",
+                defaults.acquire_timeout.as_secs(),
+            )),
+            statement_timeout: Some(Duration::from_millis(statement_timeout_ms))
+                .filter(|t| !t.is_zero()),
+            connect_attempts: env_or("DB_CONNECT_ATTEMPTS", defaults.connect_attempts),
+            initial_backoff: defaults.initial_backoff,
+        }
+    }
+
+    pub fn pool_options(&self) -> PgPoolOptions {
+        PgPoolOptions::new()
+            .max_connections(self.max_connections)
+            .min_connections(self.min_connections.min(self.max_connections))
+            .acquire_timeout(self.acquire_timeout)
+    }
+
+    /// Connect with these settings, retrying with exponential backoff while the database is
+    /// unreachable. Returns the last error once every attempt has failed.
+    pub async fn connect(&self, options: PgConnectOptions) -> Result<PgPool, sqlx::Error> {
+        let options = match self.statement_timeout {
+            Some(timeout) => {
+                options.options([("statement_timeout", timeout.as_millis().to_string())])
+            }
+            None => options,
+        };
+        let attempts = self.connect_attempts.max(1);
+        let mut backoff = self.initial_backoff;
+        let mut attempt = 1;
+        loop {
+            match self.pool_options().connect_with(options.clone()).await {
+                Ok(pool) => return Ok(pool),
+                Err(e) if attempt < attempts => {
+                    eprintln!(
+                        "Database connection attempt {} of {} failed, retrying in {:?}: {}",
+                        attempt, attempts, backoff, e
+                    );
+                    actix_web::rt::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+fn env_or<T: std::str::FromStr>(key: &str, default: T) -> T {
+    std::env::var(key)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+tokio::task_local! {
+    /// Set while serving a request that never changes data
+    static READ_REQUEST: ();
+}
+
+/// Middleware letting GET, HEAD and OPTIONS requests read from the replica pool, when one is
+/// configured. Everything else, including background jobs, stays on the primary, so reading
+/// back a write never races replication lag.
+pub async fn replica_reads(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    if is_read(req.method()) {
+        as_read_request(next.call(req)).await
+    } else {
+        next.call(req).await
+    }
+}
+
+/// Run `future` as part of a read request, so its list, get and search queries may go to the
+/// replica
+pub async fn as_read_request<F: Future>(future: F) -> F::Output {
+    READ_REQUEST.scope((), future).await
+}
+
+/// Whether the current task is serving a read request
+pub fn in_read_request() -> bool {
+    READ_REQUEST.try_with(|_| ()).is_ok()
+}
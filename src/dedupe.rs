@@ -0,0 +1,394 @@
+//! What imports do with records that match contacts the workspace already has.
+//!
+//! `POST /contacts/bulk` and `POST /contacts/import/json` take `?dedupe=` with one of:
+//!
+//! - `create_duplicate` (the default) creates every record, as imports always have
+//! - `skip` leaves matched contacts alone and drops the record
+//! - `overwrite` replaces the matched contact's fields with the ones the record has
+//! - `merge` only fills in the fields the matched contact is missing
+//!
+//! A record matches a contact by email (case-insensitively), then by phone number (in E.164
+//! form), then by first and last name (case-insensitively). A phone or name match is not taken
+//! when both have an email and the emails differ, and a name match is not taken when both have
+//! a phone number and the numbers differ: they are different people. Earlier records in the
+//! same request count as contacts, so a file listing someone twice creates them once.
+
+use crate::models::{Contact, NewContactRequest};
+use crate::phone;
+use crate::repository::{RepoResult, Repository};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DedupePolicy {
+    #[default]
+    CreateDuplicate,
+    Skip,
+    Overwrite,
+    Merge,
+}
+
+/// Which of a record's fields matched the contact
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchedBy {
+    Email,
+    Phone,
+    Name,
+}
+
+/// What an import did with one record
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RowAction {
+    Created,
+    Skipped,
+    Overwritten,
+    Merged,
+    Failed,
+}
+
+/// One record's line in an import's `rows`
+#[derive(Debug, Serialize)]
+pub struct RowReport {
+    pub index: usize,
+    pub action: RowAction,
+    /// The contact the record created or matched; None when it failed, or in a dry run for
+    /// contacts that would be created
+    pub contact_id: Option<i32>,
+    pub matched_by: Option<MatchedBy>,
+}
+
+impl RowReport {
+    pub fn failed(index: usize) -> Self {
+        RowReport {
+            index,
+            action: RowAction::Failed,
+            contact_id: None,
+            matched_by: None,
+        }
+    }
+}
+
+/// An existing contact, or the contact at this position in `Plan::contacts`
+#[derive(Debug, Clone, Copy)]
+enum Target {
+    Existing(i32),
+    New(usize),
+}
+
+#[derive(Debug)]
+struct Row {
+    index: usize,
+    action: RowAction,
+    target: Target,
+    matched_by: Option<MatchedBy>,
+}
+
+/// An existing contact to save with the fields of the records that matched it
+#[derive(Debug)]
+pub struct Update {
+    pub contact_id: i32,
+    /// The first record that matched the contact
+    pub index: usize,
+    pub contact: NewContactRequest,
+}
+
+/// Lowercase email, E.164 phone number and lowercase full name of a contact or record
+#[derive(Debug, Default)]
+struct Keys {
+    email: Option<String>,
+    phone: Option<String>,
+    name: Option<String>,
+}
+
+impl Keys {
+    fn of(
+        email: Option<&str>,
+        phone: Option<&str>,
+        first: Option<&str>,
+        last: Option<&str>,
+    ) -> Keys {
+        let part = |s: Option<&str>| s.map(str::trim).unwrap_or_default().to_lowercase();
+        let name = format!("{} {}", part(first), part(last));
+        Keys {
+            email: email
+                .map(|e| e.trim().to_lowercase())
+                .filter(|e| !e.is_empty()),
+            phone: phone::e164(phone),
+            name: Some(name.trim().to_string()).filter(|n| !n.is_empty()),
+        }
+    }
+
+    fn of_record(contact: &NewContactRequest) -> Keys {
+        Keys::of(
+            contact.email.as_deref(),
+            contact.phone.as_deref(),
+            contact.first_name.as_deref(),
+            contact.last_name.as_deref(),
+        )
+    }
+
+    /// Whether both have a value and the values differ
+    fn differ(mine: &Option<String>, theirs: &Option<String>) -> bool {
+        matches!((mine, theirs), (Some(a), Some(b)) if a != b)
+    }
+}
+
+/// Contacts to match records against, by each of their keys
+#[derive(Debug, Default)]
+struct Candidates {
+    keys: Vec<(Target, Keys)>,
+    by_email: HashMap<String, usize>,
+    by_phone: HashMap<String, usize>,
+    by_name: HashMap<String, Vec<usize>>,
+}
+
+impl Candidates {
+    fn add(&mut self, target: Target, keys: Keys) {
+        let position = self.keys.len();
+        if let Some(email) = &keys.email {
+            self.by_email.entry(email.clone()).or_insert(position);
+        }
+        if let Some(phone) = &keys.phone {
+            self.by_phone.entry(phone.clone()).or_insert(position);
+        }
+        if let Some(name) = &keys.name {
+            self.by_name.entry(name.clone()).or_default().push(position);
+        }
+        self.keys.push((target, keys));
+    }
+
+    fn find(&self, record: &Keys) -> Option<(Target, MatchedBy)> {
+        let found = |position: &usize| self.keys[*position].0;
+        if let Some(target) = record.email.as_ref().and_then(|e| self.by_email.get(e)) {
+            return Some((found(target), MatchedBy::Email));
+        }
+        if let Some(position) = record.phone.as_ref().and_then(|p| self.by_phone.get(p))
+            && !Keys::differ(&record.email, &self.keys[*position].1.email)
+        {
+            return Some((found(position), MatchedBy::Phone));
+        }
+        record
+            .name
+            .as_ref()
+            .and_then(|n| self.by_name.get(n))
+            .into_iter()
+            .flatten()
+            .find(|position| {
+                let keys = &self.keys[**position].1;
+                !Keys::differ(&record.email, &keys.email)
+                    && !Keys::differ(&record.phone, &keys.phone)
+            })
+            .map(|position| (found(position), MatchedBy::Name))
+    }
+}
+
+/// Copy the fields `record` has into `contact`; with `overwrite` false, only into the fields
+/// `contact` is missing
+fn combine(contact: &mut NewContactRequest, record: NewContactRequest, overwrite: bool) {
+    fn field<T>(slot: &mut Option<T>, value: Option<T>, overwrite: bool) {
+        if value.is_some() && (overwrite || slot.is_none()) {
+            *slot = value;
+        }
+    }
+    field(&mut contact.first_name, record.first_name, overwrite);
+    field(&mut contact.last_name, record.last_name, overwrite);
+    field(&mut contact.email, record.email, overwrite);
+    field(&mut contact.phone, record.phone, overwrite);
+    field(&mut contact.short_note, record.short_note, overwrite);
+    field(&mut contact.notes, record.notes, overwrite);
+    field(&mut contact.avatar_url, record.avatar_url, overwrite);
+    field(
+        &mut contact.desired_frequency_days,
+        record.desired_frequency_days,
+        overwrite,
+    );
+    field(&mut contact.met_at, record.met_at, overwrite);
+    field(
+        &mut contact.introduced_by_contact_id,
+        record.introduced_by_contact_id,
+        overwrite,
+    );
+    field(&mut contact.location, record.location, overwrite);
+}
+
+/// What an import will save: the contacts to create, the existing contacts to update and what
+/// happens to each record
+#[derive(Debug, Default)]
+pub struct Plan {
+    /// Contacts to create
+    pub contacts: Vec<NewContactRequest>,
+    /// The index of the record each of `contacts` comes from
+    pub indexes: Vec<usize>,
+    pub updates: Vec<Update>,
+    rows: Vec<Row>,
+}
+
+impl Plan {
+    /// Match `records`, given with their indexes in the request, against `existing` contacts
+    /// and each other. `existing` is not looked at under `create_duplicate`.
+    pub fn new(
+        policy: DedupePolicy,
+        existing: &[Contact],
+        records: impl IntoIterator<Item = (usize, NewContactRequest)>,
+    ) -> Plan {
+        let mut plan = Plan::default();
+        let mut candidates = Candidates::default();
+        let mut saved: HashMap<i32, &Contact> = HashMap::new();
+        if policy != DedupePolicy::CreateDuplicate {
+            for contact in existing {
+                let keys = Keys::of(
+                    contact.email.as_deref(),
+                    contact.phone.as_deref(),
+                    contact.first_name.as_deref(),
+                    contact.last_name.as_deref(),
+                );
+                candidates.add(Target::Existing(contact.contact_id), keys);
+                saved.insert(contact.contact_id, contact);
+            }
+        }
+
+        for (index, record) in records {
+            let keys = Keys::of_record(&record);
+            let found = match policy {
+                DedupePolicy::CreateDuplicate => None,
+                _ => candidates.find(&keys),
+            };
+            let Some((target, matched_by)) = found else {
+                let target = Target::New(plan.contacts.len());
+                plan.contacts.push(record);
+                plan.indexes.push(index);
+                plan.rows.push(Row {
+                    index,
+                    action: RowAction::Created,
+                    target,
+                    matched_by: None,
+                });
+                if policy != DedupePolicy::CreateDuplicate {
+                    candidates.add(target, keys);
+                }
+                continue;
+            };
+
+            let action = match policy {
+                DedupePolicy::Overwrite => RowAction::Overwritten,
+                DedupePolicy::Merge => RowAction::Merged,
+                _ => RowAction::Skipped,
+            };
+            if action != RowAction::Skipped {
+                let contact = match target {
+                    Target::New(position) => &mut plan.contacts[position],
+                    Target::Existing(contact_id) => {
+                        let position =
+                            match plan.updates.iter().position(|u| u.contact_id == contact_id) {
+                                Some(position) => position,
+                                None => {
+                                    plan.updates.push(Update {
+                                        contact_id,
+                                        index,
+                                        contact: NewContactRequest::from(saved[&contact_id]),
+                                    });
+                                    plan.updates.len() - 1
+                                }
+                            };
+                        &mut plan.updates[position].contact
+                    }
+                };
+                combine(contact, record, action == RowAction::Overwritten);
+            }
+            plan.rows.push(Row {
+                index,
+                action,
+                target,
+                matched_by: Some(matched_by),
+            });
+        }
+        plan
+    }
+
+    /// What happened to each record, given the ids `contacts` were created with (None for
+    /// those that failed) and the updates that failed. Records that went into a contact that
+    /// failed to save are reported as failed. `created` is None for a dry run, where nothing
+    /// fails and new contacts have no id yet.
+    pub fn report(
+        &self,
+        created: Option<&[Option<i32>]>,
+        failed_updates: &[i32],
+    ) -> Vec<RowReport> {
+        self.rows
+            .iter()
+            .map(|row| {
+                let contact_id = match (row.target, created) {
+                    (Target::Existing(contact_id), _) if failed_updates.contains(&contact_id) => {
+                        return RowReport::failed(row.index);
+                    }
+                    (Target::Existing(contact_id), _) => Some(contact_id),
+                    (Target::New(_), None) => None,
+                    (Target::New(position), Some(created)) => match created[position] {
+                        Some(contact_id) => Some(contact_id),
+                        None => return RowReport::failed(row.index),
+                    },
+                };
+                RowReport {
+                    index: row.index,
+                    action: row.action,
+                    contact_id,
+                    matched_by: row.matched_by,
+                }
+            })
+            .collect()
+    }
+}
+
+/// The workspace's contacts to match records against; none are needed under `create_duplicate`
+pub async fn existing_contacts(
+    repo: &dyn Repository,
+    workspace_id: i32,
+    policy: DedupePolicy,
+) -> RepoResult<Vec<Contact>> {
+    match policy {
+        DedupePolicy::CreateDuplicate => Ok(Vec::new()),
+        _ => repo.list_contacts(workspace_id).await,
+    }
+}
+
+/// Save `updates`, returning the ids of the contacts updated and of those that failed. Failures
+/// are added to `errors` under the index of their first record.
+pub async fn save_updates(
+    repo: &dyn Repository,
+    workspace_id: i32,
+    updates: &[Update],
+    errors: &mut Vec<serde_json::Value>,
+) -> (Vec<i32>, Vec<i32>) {
+    let mut updated = Vec::new();
+    let mut failed = Vec::new();
+    for update in updates {
+        match repo
+            .update_contact(workspace_id, update.contact_id, &update.contact)
+            .await
+        {
+            Ok(true) => updated.push(update.contact_id),
+            Ok(false) => {
+                failed.push(update.contact_id);
+                errors.push(serde_json::json!({
+                    "index": update.index,
+                    "error": "Contact not found"
+                }));
+            }
+            Err(e) => {
+                eprintln!(
+                    "Database error updating contact {}: {:?}",
+                    update.contact_id, e
+                );
+                failed.push(update.contact_id);
+                errors.push(serde_json::json!({
+                    "index": update.index,
+                    "error": format!("{:?}", e)
+                }));
+            }
+        }
+    }
+    (updated, failed)
+}
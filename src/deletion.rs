@@ -0,0 +1,131 @@
+//! What deleting a contact does to its history.
+//!
+//! CONTACT_DELETE_BEHAVIOR is one of:
+//! - `cascade`, the default: the contact's interactions and occasions are deleted with it
+//! - `orphan`: they are kept without a contact, listed by `GET /unassigned` until an update
+//!   gives them one
+//! - `block`: deleting a contact with any gets 409, listing what it has, unless the request
+//!   says `?force=true`, which cascades
+//!
+//! Interactions the contact only took part in lose the participant either way.
+
+use crate::AuthUser;
+use crate::repository::Repository;
+use actix_web::{HttpResponse, Responder, get, web};
+use std::str::FromStr;
+
+/// `code` of the 409 sent for deletes refused under `block`
+pub const HAS_HISTORY: &str = "has_history";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DeleteBehavior {
+    #[default]
+    Cascade,
+    Orphan,
+    Block,
+}
+
+impl FromStr for DeleteBehavior {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "cascade" => Ok(DeleteBehavior::Cascade),
+            "orphan" => Ok(DeleteBehavior::Orphan),
+            "block" => Ok(DeleteBehavior::Block),
+            other => Err(format!(
+                "Unknown delete behavior {:?}: expected cascade, orphan or block",
+                other
+            )),
+        }
+    }
+}
+
+impl DeleteBehavior {
+    /// Whether deleting `contact_ids` keeps their history, or the response refusing the delete:
+    /// 409 under `Block` for contacts with history, unless `force`
+    pub async fn keep_history(
+        self,
+        repo: &dyn Repository,
+        workspace_id: i32,
+        contact_ids: &[i32],
+        force: bool,
+    ) -> Result<bool, HttpResponse> {
+        match self {
+            DeleteBehavior::Cascade => Ok(false),
+            DeleteBehavior::Orphan => Ok(true),
+            DeleteBehavior::Block if force => Ok(false),
+            DeleteBehavior::Block => {
+                let details = match repo.contact_details(workspace_id, contact_ids).await {
+                    Ok(details) => details,
+                    Err(e) => {
+                        eprintln!("Database error: {:?}", e);
+                        return Err(
+                            HttpResponse::InternalServerError().body("Failed to check contacts")
+                        );
+                    }
+                };
+                let blocked: Vec<_> = details
+                    .iter()
+                    .filter(|d| !d.interactions.is_empty() || !d.occasions.is_empty())
+                    .map(|d| {
+                        serde_json::json!({
+                            "contact_id": d.contact.contact_id,
+                            "interaction_count": d.interactions.len(),
+                            "occasion_count": d.occasions.len()
+                        })
+                    })
+                    .collect();
+                if blocked.is_empty() {
+                    return Ok(false);
+                }
+                Err(HttpResponse::Conflict().json(serde_json::json!({
+                    "error": "Contacts with interactions or occasions can't be deleted; \
+                              archive them instead, or delete with ?force=true to delete \
+                              their history too",
+                    "code": HAS_HISTORY,
+                    "contacts": blocked
+                })))
+            }
+        }
+    }
+}
+
+/// Delete behavior for each kind of record with history hanging off it
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DeletePolicies {
+    pub contacts: DeleteBehavior,
+}
+
+impl DeletePolicies {
+    /// Read CONTACT_DELETE_BEHAVIOR, cascading when unset
+    pub fn from_env() -> Result<Self, String> {
+        let contacts = match std::env::var("CONTACT_DELETE_BEHAVIOR") {
+            Ok(value) => value
+                .parse()
+                .map_err(|e| format!("CONTACT_DELETE_BEHAVIOR: {}", e))?,
+            Err(_) => DeleteBehavior::default(),
+        };
+        Ok(DeletePolicies { contacts })
+    }
+}
+
+/// The app's delete policies, or the defaults when none are registered
+pub fn configured(policies: Option<&web::Data<DeletePolicies>>) -> DeletePolicies {
+    policies.map_or_else(DeletePolicies::default, |policies| *policies.get_ref())
+}
+
+/// Interactions and occasions kept from deleted contacts, oldest first
+#[get("/unassigned")]
+pub async fn list_unassigned(
+    repo: web::Data<dyn Repository>,
+    auth_user: AuthUser,
+) -> impl Responder {
+    match repo.list_unassigned(auth_user.workspace_id).await {
+        Ok(unassigned) => HttpResponse::Ok().json(unassigned),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to fetch unassigned history")
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use actix_web::body::{self, BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::http::header::{self, HeaderName, HeaderValue, HttpDate};
+use actix_web::middleware::Next;
+use actix_web::{Error, web};
+use std::time::{Duration, SystemTime};
+use time::Date;
+
+const DEPRECATION: HeaderName = HeaderName::from_static("deprecation");
+const SUNSET: HeaderName = HeaderName::from_static("sunset");
+
+/// Metadata for an endpoint that is slated for removal
+#[derive(Debug, Clone)]
+pub struct DeprecatedRoute {
+    pub method: Method,
+    /// Route pattern as registered, including the version scope (e.g. "/v1/contacts/{id}")
+    pub pattern: &'static str,
+    /// When the endpoint was deprecated
+    pub deprecated_at: Date,
+    /// When the endpoint will stop working, if scheduled
+    pub sunset_at: Option<Date>,
+    /// Path of the endpoint clients should migrate to
+    pub successor: Option<&'static str>,
+    pub message: &'static str,
+}
+
+/// Endpoints currently slated for removal. Add an entry here when a route is deprecated;
+/// the middleware takes care of the headers and response warning.
+pub const DEPRECATED_ROUTES: &[DeprecatedRoute] = &[];
+
+/// Route metadata registry consulted by the deprecation middleware
+#[derive(Debug, Clone)]
+pub struct DeprecationRegistry {
+    routes: Vec<DeprecatedRoute>,
+}
+
+impl Default for DeprecationRegistry {
+    fn default() -> Self {
+        DeprecationRegistry::new(DEPRECATED_ROUTES.to_vec())
+    }
+}
+
+impl DeprecationRegistry {
+    pub fn new(routes: Vec<DeprecatedRoute>) -> Self {
+        DeprecationRegistry { routes }
+    }
+
+    pub fn routes(&self) -> &[DeprecatedRoute] {
+        &self.routes
+    }
+
+    pub fn lookup(&self, method: &Method, pattern: &str) -> Option<&DeprecatedRoute> {
+        self.routes
+            .iter()
+            .find(|r| r.method == *method && r.pattern == pattern)
+    }
+}
+
+fn unix_seconds(date: Date) -> i64 {
+    date.midnight().assume_utc().unix_timestamp()
+}
+
+fn http_date(date: Date) -> String {
+    let secs = unix_seconds(date).max(0) as u64;
+    HttpDate::from(SystemTime::UNIX_EPOCH + Duration::from_secs(secs)).to_string()
+}
+
+fn warning_text(route: &DeprecatedRoute) -> String {
+    let mut warning = format!("{} {} is deprecated", route.method, route.pattern);
+    if let Some(sunset) = route.sunset_at {
+        warning.push_str(&format!(" and will be removed on {}", sunset));
+    }
+    if let Some(successor) = route.successor {
+        warning.push_str(&format!("; use {} instead", successor));
+    }
+    if !route.message.is_empty() {
+        warning.push_str(&format!(". {}", route.message));
+    }
+    warning
+}
+
+/// Middleware attaching Deprecation (RFC 9745), Sunset (RFC 8594) and successor Link headers
+/// to deprecated endpoints, plus a `deprecation_warning` field on JSON object responses.
+/// Requires a `web::Data<DeprecationRegistry>` in app data.
+pub async fn deprecation(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let route = req
+        .app_data::<web::Data<DeprecationRegistry>>()
+        .zip(req.match_pattern())
+        .and_then(|(registry, pattern)| registry.lookup(req.method(), &pattern).cloned());
+
+    let res = next.call(req).await?;
+    let route = match route {
+        Some(route) => route,
+        None => return Ok(res.map_into_boxed_body()),
+    };
+
+    let is_json = res
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"));
+
+    let (req, mut res) = res.into_parts();
+    let headers = res.headers_mut();
+    headers.insert(
+        DEPRECATION,
+        HeaderValue::from_str(&format!("@{}", unix_seconds(route.deprecated_at))).unwrap(),
+    );
+    if let Some(sunset) = route.sunset_at {
+        headers.insert(SUNSET, HeaderValue::from_str(&http_date(sunset)).unwrap());
+    }
+    if let Some(successor) = route.successor
+        && let Ok(link) =
+            HeaderValue::from_str(&format!("<{}>; rel=\"successor-version\"", successor))
+    {
+        headers.insert(header::LINK, link);
+    }
+
+    if !is_json {
+        return Ok(ServiceResponse::new(req, res.map_into_boxed_body()));
+    }
+
+    // Add the warning to JSON object bodies; arrays and other payloads only get the headers
+    let (res, body) = res.into_parts();
+    let bytes = body::to_bytes(body).await.unwrap_or_default();
+    let bytes = match serde_json::from_slice::<serde_json::Value>(&bytes) {
+        Ok(serde_json::Value::Object(mut object)) => {
+            object.insert(
+                "deprecation_warning".to_string(),
+                serde_json::Value::String(warning_text(&route)),
+            );
+            serde_json::to_vec(&object).unwrap_or_else(|_| bytes.to_vec())
+        }
+        _ => bytes.to_vec(),
+    };
+
+    let mut res = res.set_body(BoxBody::new(bytes));
+    res.headers_mut().remove(header::CONTENT_LENGTH);
+    Ok(ServiceResponse::new(req, res))
+}
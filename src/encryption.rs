@@ -0,0 +1,119 @@
+//! Application-layer encryption of contacts' notes.
+//!
+//! With NOTES_ENCRYPTION_KEY set, `PgRepository` encrypts `short_note` and `notes` (and their
+//! values in the contact's history) with AES-256-GCM before writing them, and decrypts them as
+//! they are read, so database backups never hold them in plaintext. Encrypted values are
+//! stored as `enc:v1:` followed by the base64 of a random nonce and the ciphertext. Values
+//! without that prefix are read as they are, which keeps rows written before the key was set
+//! working until `personal-crm encrypt-notes` encrypts them.
+
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+
+/// Marks a stored value as encrypted; the version allows changing the scheme later
+pub const ENCRYPTED_PREFIX: &str = "enc:v1:";
+
+const NONCE_LEN: usize = 12;
+
+/// The contact fields, as named in the history, that are stored encrypted
+pub const ENCRYPTED_FIELDS: &[&str] = &["short_note", "notes"];
+
+#[derive(Clone)]
+pub struct NotesCipher {
+    cipher: Aes256Gcm,
+}
+
+// Never print the key
+impl std::fmt::Debug for NotesCipher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("NotesCipher")
+    }
+}
+
+impl NotesCipher {
+    /// A cipher for a 256-bit key
+    pub fn new(key: &[u8]) -> Result<Self, String> {
+        let cipher = Aes256Gcm::new_from_slice(key)
+            .map_err(|_| format!("Key must be 32 bytes, got {}", key.len()))?;
+        Ok(NotesCipher { cipher })
+    }
+
+    /// The cipher for NOTES_ENCRYPTION_KEY, the base64 of 32 random bytes (e.g. from
+    /// `openssl rand -base64 32`, or put there by a KMS or secrets manager); None when unset
+    pub fn from_env() -> Result<Option<Self>, String> {
+        let Some(key) = std::env::var("NOTES_ENCRYPTION_KEY")
+            .ok()
+            .filter(|k| !k.trim().is_empty())
+        else {
+            return Ok(None);
+        };
+        let key = STANDARD
+            .decode(key.trim())
+            .map_err(|e| format!("NOTES_ENCRYPTION_KEY is not base64: {}", e))?;
+        NotesCipher::new(&key)
+            .map(Some)
+            .map_err(|e| format!("NOTES_ENCRYPTION_KEY: {}", e))
+    }
+
+    /// The stored form of `plaintext`. Every call uses a fresh nonce, so equal notes don't
+    /// encrypt alike.
+    pub fn encrypt(&self, plaintext: &str) -> String {
+        let mut nonce = [0u8; NONCE_LEN];
+        getrandom::fill(&mut nonce).expect("Failed to read random bytes");
+        let ciphertext = self
+            .cipher
+            .encrypt(Nonce::from_slice(&nonce), plaintext.as_bytes())
+            .expect("AES-GCM encryption failed");
+        let mut sealed = nonce.to_vec();
+        sealed.extend(ciphertext);
+        format!("{}{}", ENCRYPTED_PREFIX, STANDARD.encode(sealed))
+    }
+
+    /// The plaintext of a stored value; values that were never encrypted are returned as
+    /// they are
+    pub fn decrypt(&self, stored: &str) -> Result<String, String> {
+        let Some(encoded) = stored.strip_prefix(ENCRYPTED_PREFIX) else {
+            return Ok(stored.to_string());
+        };
+        let sealed = STANDARD
+            .decode(encoded)
+            .map_err(|e| format!("Encrypted note is not base64: {}", e))?;
+        if sealed.len() < NONCE_LEN {
+            return Err("Encrypted note is truncated".to_string());
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let plaintext = self
+            .cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| "Encrypted note does not match NOTES_ENCRYPTION_KEY".to_string())?;
+        String::from_utf8(plaintext).map_err(|e| format!("Decrypted note is not UTF-8: {}", e))
+    }
+}
+
+pub fn is_encrypted(stored: &str) -> bool {
+    stored.starts_with(ENCRYPTED_PREFIX)
+}
+
+/// `value` as it is to be stored: encrypted with the cipher, else as it is
+pub fn seal(cipher: Option<&NotesCipher>, value: Option<&str>) -> Option<String> {
+    value.map(|v| match cipher {
+        Some(cipher) => cipher.encrypt(v),
+        None => v.to_string(),
+    })
+}
+
+/// The plaintext of a stored value. Encrypted values can't be read without the cipher.
+pub fn open(
+    cipher: Option<&NotesCipher>,
+    stored: Option<String>,
+) -> Result<Option<String>, String> {
+    match (stored, cipher) {
+        (Some(stored), Some(cipher)) => cipher.decrypt(&stored).map(Some),
+        (Some(stored), None) if is_encrypted(&stored) => {
+            Err("Notes are encrypted but NOTES_ENCRYPTION_KEY is not set".to_string())
+        }
+        (stored, _) => Ok(stored),
+    }
+}
@@ -0,0 +1,345 @@
+//! Contact photos from Gravatar or an enrichment API, stored as the contact's avatar.
+//!
+//! With `AVATAR_ENRICHMENT=true` the `avatar_enrichment` job looks up the contacts that have an
+//! email but no avatar, at most `ENRICHMENT_PER_MINUTE` (default 20) a minute so the providers
+//! aren't flooded, and stores the first avatar found. Gravatar is asked first, then the API at
+//! `ENRICHMENT_URL` when one is set: a Clearbit-style person lookup called as
+//! `GET ENRICHMENT_URL?email=...`, with `ENRICHMENT_API_KEY` as a bearer token and the avatar
+//! read from the JSON at `ENRICHMENT_AVATAR_POINTER` (default `/avatar`). Other providers plug
+//! in by implementing `AvatarFinder`.
+//!
+//! A contact nothing was found for is looked up again after `LOOKUP_AGAIN_AFTER`, one whose
+//! lookup failed after `RETRY_FAILED_AFTER`. `POST /contacts/{id}/enrich` looks one contact up
+//! straight away. Off by default because contact emails, hashed for Gravatar, go to the
+//! providers.
+
+use crate::AuthUser;
+use crate::avatar::GravatarResolver;
+use crate::events::{self, Action, Entity, EventBus};
+use crate::jobs::Job;
+use crate::models::PendingAvatarLookup;
+use crate::mutations;
+use crate::repository::{RepoResult, Repository};
+use crate::routes::{Owned, OwnedContact};
+use actix_web::{HttpResponse, Responder, post, web};
+use async_trait::async_trait;
+use reqwest::Url;
+use std::sync::Arc;
+use std::time::Duration;
+use time::OffsetDateTime;
+
+/// How often the job picks up contacts to look up
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Contacts looked up per run unless `ENRICHMENT_PER_MINUTE` says otherwise
+pub const DEFAULT_BATCH_SIZE: i64 = 20;
+
+/// Wait before looking a contact up again when no provider had an avatar
+pub const LOOKUP_AGAIN_AFTER: time::Duration = time::Duration::days(30);
+
+/// Wait before looking a contact up again when a provider failed
+pub const RETRY_FAILED_AFTER: time::Duration = time::Duration::days(1);
+
+#[async_trait]
+pub trait AvatarFinder: Send + Sync {
+    /// The URL of an avatar for `email`, None when the provider has none, or why the lookup
+    /// failed
+    async fn find(&self, email: &str) -> Result<Option<String>, String>;
+}
+
+#[async_trait]
+impl AvatarFinder for GravatarResolver {
+    async fn find(&self, email: &str) -> Result<Option<String>, String> {
+        self.try_lookup(email).await
+    }
+}
+
+/// A Clearbit-style person lookup by email
+pub struct EnrichmentApi {
+    client: reqwest::Client,
+    url: String,
+    api_key: Option<String>,
+    /// JSON pointer to the avatar URL in the response
+    avatar_pointer: String,
+}
+
+impl EnrichmentApi {
+    pub fn new(url: String, api_key: Option<String>, avatar_pointer: String) -> Self {
+        EnrichmentApi {
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(5))
+                .build()
+                .expect("Failed to build HTTP client"),
+            url,
+            api_key,
+            avatar_pointer,
+        }
+    }
+
+    /// The API if ENRICHMENT_URL is set
+    pub fn from_env() -> Option<Self> {
+        let url = std::env::var("ENRICHMENT_URL").ok()?;
+        Some(EnrichmentApi::new(
+            url,
+            std::env::var("ENRICHMENT_API_KEY").ok(),
+            std::env::var("ENRICHMENT_AVATAR_POINTER").unwrap_or_else(|_| "/avatar".to_string()),
+        ))
+    }
+}
+
+/// The http or https URL at `pointer` in an enrichment API's response. Anything else, such as
+/// a `javascript:` URL, is ignored, since clients put the avatar straight into an `<img>`.
+pub fn avatar_at(body: &serde_json::Value, pointer: &str) -> Option<String> {
+    let avatar = body.pointer(pointer)?.as_str()?.trim();
+    let url = Url::parse(avatar).ok()?;
+    matches!(url.scheme(), "http" | "https").then(|| avatar.to_string())
+}
+
+#[async_trait]
+impl AvatarFinder for EnrichmentApi {
+    async fn find(&self, email: &str) -> Result<Option<String>, String> {
+        let url = Url::parse_with_params(&self.url, [("email", email.trim())])
+            .map_err(|e| format!("ENRICHMENT_URL is not a valid URL: {:?}", e))?;
+        let mut request = self.client.get(url);
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|e| format!("Request failed: {:?}", e))?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !response.status().is_success() {
+            return Err(format!("Returned status {}", response.status()));
+        }
+        let body: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| format!("Unreadable response: {:?}", e))?;
+        Ok(avatar_at(&body, &self.avatar_pointer))
+    }
+}
+
+/// What looking up a contact's avatar came to
+#[derive(Debug, Clone, PartialEq)]
+pub enum LookupOutcome {
+    Stored,
+    NotFound,
+    /// Found, but the contact got an avatar or another email during the lookup
+    Superseded,
+    Failed(String),
+}
+
+/// The configured providers. Registered as app data for `POST /contacts/{id}/enrich`, and as
+/// the job that works through the contacts without an avatar.
+#[derive(Clone)]
+pub struct Enrichment {
+    finders: Vec<Arc<dyn AvatarFinder>>,
+    batch_size: i64,
+}
+
+impl Enrichment {
+    /// Enrichment asking `finders` in order
+    pub fn new(finders: Vec<Arc<dyn AvatarFinder>>) -> Self {
+        Enrichment {
+            finders,
+            batch_size: DEFAULT_BATCH_SIZE,
+        }
+    }
+
+    /// Look up at most `batch_size` contacts per run
+    pub fn with_batch_size(mut self, batch_size: i64) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// Enrichment from Gravatar and `EnrichmentApi::from_env` if AVATAR_ENRICHMENT is `true`
+    pub fn from_env() -> Option<Self> {
+        let enabled = std::env::var("AVATAR_ENRICHMENT")
+            .map(|v| v.eq_ignore_ascii_case("true"))
+            .unwrap_or(false);
+        if !enabled {
+            if std::env::var("ENRICHMENT_URL").is_ok() {
+                eprintln!("ENRICHMENT_URL is set without AVATAR_ENRICHMENT=true; not enriching");
+            }
+            return None;
+        }
+        let mut finders: Vec<Arc<dyn AvatarFinder>> = vec![Arc::new(GravatarResolver::new())];
+        if let Some(api) = EnrichmentApi::from_env() {
+            finders.push(Arc::new(api));
+        }
+        let enrichment = Enrichment::new(finders);
+        match std::env::var("ENRICHMENT_PER_MINUTE").map(|v| v.parse::<i64>()) {
+            Ok(Ok(per_minute)) if per_minute > 0 => Some(enrichment.with_batch_size(per_minute)),
+            Ok(_) => {
+                eprintln!("ENRICHMENT_PER_MINUTE must be a positive number; using the default");
+                Some(enrichment)
+            }
+            Err(_) => Some(enrichment),
+        }
+    }
+
+    /// The first avatar any finder has for `email`. Fails only when none found one and a
+    /// finder failed, since that one might have had it.
+    pub async fn find(&self, email: &str) -> Result<Option<String>, String> {
+        let mut failure = None;
+        for finder in &self.finders {
+            match finder.find(email).await {
+                Ok(Some(avatar_url)) => return Ok(Some(avatar_url)),
+                Ok(None) => {}
+                Err(e) => failure = Some(e),
+            }
+        }
+        match failure {
+            Some(e) => Err(e),
+            None => Ok(None),
+        }
+    }
+
+    /// Look up the contact's avatar, store it if found and record the lookup
+    pub async fn enrich(
+        &self,
+        repo: &dyn Repository,
+        contact: &PendingAvatarLookup,
+        now: OffsetDateTime,
+    ) -> RepoResult<LookupOutcome> {
+        let (avatar_url, next_lookup_at) = match self.find(&contact.email).await {
+            Ok(avatar_url) => (avatar_url, now + LOOKUP_AGAIN_AFTER),
+            Err(e) => {
+                repo.record_avatar_lookup(
+                    contact.workspace_id,
+                    contact.contact_id,
+                    &contact.email,
+                    None,
+                    now + RETRY_FAILED_AFTER,
+                )
+                .await?;
+                return Ok(LookupOutcome::Failed(e));
+            }
+        };
+        let stored = repo
+            .record_avatar_lookup(
+                contact.workspace_id,
+                contact.contact_id,
+                &contact.email,
+                avatar_url.as_deref(),
+                next_lookup_at,
+            )
+            .await?;
+        Ok(match (avatar_url, stored) {
+            (None, _) => LookupOutcome::NotFound,
+            (Some(_), true) => LookupOutcome::Stored,
+            (Some(_), false) => LookupOutcome::Superseded,
+        })
+    }
+
+    /// Look up a batch of contacts due for it, returning how many avatars were stored
+    pub async fn enrich_pending(
+        &self,
+        repo: &dyn Repository,
+        now: OffsetDateTime,
+    ) -> RepoResult<usize> {
+        let mut stored = 0;
+        for contact in repo.pending_avatar_lookups(now, self.batch_size).await? {
+            match self.enrich(repo, &contact, now).await? {
+                LookupOutcome::Stored => stored += 1,
+                LookupOutcome::Failed(e) => eprintln!(
+                    "Looking up an avatar for contact {} failed: {}",
+                    contact.contact_id, e
+                ),
+                LookupOutcome::NotFound | LookupOutcome::Superseded => {}
+            }
+        }
+        Ok(stored)
+    }
+}
+
+#[async_trait]
+impl Job for Enrichment {
+    fn name(&self) -> &'static str {
+        "avatar_enrichment"
+    }
+
+    fn interval(&self) -> Duration {
+        CHECK_INTERVAL
+    }
+
+    async fn run(&self, repo: &dyn Repository) -> RepoResult<()> {
+        self.enrich_pending(repo, OffsetDateTime::now_utc())
+            .await
+            .map(|_| ())
+    }
+}
+
+/// Look up the contact's avatar now rather than waiting for the job, returning the contact
+/// with the avatar stored
+#[post("/contacts/{id}/enrich")]
+pub async fn enrich_contact(
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    enrichment: Option<web::Data<Enrichment>>,
+    auth_user: AuthUser,
+    Owned(contact): OwnedContact,
+) -> impl Responder {
+    let Some(enrichment) = enrichment else {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Avatar enrichment is not configured on this server"
+        }));
+    };
+    let Some(email) = contact.email.filter(|email| !email.is_empty()) else {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "The contact has no email to look up"
+        }));
+    };
+    if contact.avatar_url.is_some() {
+        return HttpResponse::Conflict().json(serde_json::json!({
+            "error": "The contact already has an avatar"
+        }));
+    }
+
+    let pending = PendingAvatarLookup {
+        contact_id: contact.contact_id,
+        workspace_id: auth_user.workspace_id,
+        email,
+    };
+    let outcome = match enrichment
+        .enrich(repo.get_ref(), &pending, OffsetDateTime::now_utc())
+        .await
+    {
+        Ok(outcome) => outcome,
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            return HttpResponse::InternalServerError().body("Failed to enrich contact");
+        }
+    };
+    match outcome {
+        LookupOutcome::Stored => events::publish(
+            bus.as_ref(),
+            auth_user.user_id,
+            Entity::Contact,
+            Action::Updated,
+            vec![pending.contact_id],
+        ),
+        LookupOutcome::Superseded => {}
+        LookupOutcome::NotFound => {
+            return HttpResponse::NotFound().json(serde_json::json!({
+                "error": "No avatar found for the contact's email"
+            }));
+        }
+        LookupOutcome::Failed(e) => {
+            eprintln!(
+                "Looking up an avatar for contact {} failed: {}",
+                pending.contact_id, e
+            );
+            return HttpResponse::BadGateway().json(serde_json::json!({
+                "error": "The avatar lookup failed; try again later"
+            }));
+        }
+    }
+    mutations::updated(
+        repo.get_contact(auth_user.workspace_id, pending.contact_id)
+            .await,
+    )
+}
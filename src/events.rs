@@ -0,0 +1,149 @@
+//! Live change notifications over server-sent events.
+//!
+//! Mutation handlers publish a `ChangeEvent` to an in-process broadcast channel after each
+//! successful write; `GET /events` streams the caller's own events so other open clients can
+//! refetch what changed instead of polling. Events only reach subscribers connected to the
+//! same instance, and a subscriber that falls behind gets a `resync` event telling it to
+//! refetch everything.
+
+use crate::AuthUser;
+use actix_web::http::header::{CACHE_CONTROL, ContentEncoding};
+use actix_web::rt::time::{Interval, interval};
+use actix_web::web::Bytes;
+use actix_web::{HttpResponse, Responder, get, web};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::sync::broadcast::{self, Receiver, Sender, error::RecvError};
+
+/// Events buffered per subscriber before it is considered lagging
+const CAPACITY: usize = 1024;
+
+/// How often an idle stream sends a comment, so proxies don't close it
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Entity {
+    Contact,
+    Tag,
+    Interaction,
+    Capture,
+    InteractionType,
+    Occasion,
+    SocialProfile,
+    GiftIdea,
+    Group,
+    Preferences,
+    SavedFilter,
+    Goal,
+    Account,
+    Share,
+    Workspace,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Created,
+    Updated,
+    Deleted,
+}
+
+/// What changed: tagging a contact is an update of the contact
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChangeEvent {
+    pub entity: Entity,
+    pub action: Action,
+    pub ids: Vec<i32>,
+}
+
+pub struct EventBus {
+    sender: Sender<(i32, ChangeEvent)>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        EventBus {
+            sender: broadcast::channel(CAPACITY).0,
+        }
+    }
+}
+
+impl EventBus {
+    /// Send an event to the user's subscribers; nothing happens if there are none
+    pub fn publish(&self, user_id: i32, event: ChangeEvent) {
+        let _ = self.sender.send((user_id, event));
+    }
+
+    pub fn subscribe(&self) -> Receiver<(i32, ChangeEvent)> {
+        self.sender.subscribe()
+    }
+}
+
+/// Publish from a handler. Takes the optional app data handlers receive, so apps without
+/// live updates configured need no bus.
+pub fn publish(
+    bus: Option<&web::Data<EventBus>>,
+    user_id: i32,
+    entity: Entity,
+    action: Action,
+    ids: Vec<i32>,
+) {
+    if let Some(bus) = bus {
+        bus.publish(
+            user_id,
+            ChangeEvent {
+                entity,
+                action,
+                ids,
+            },
+        );
+    }
+}
+
+/// The next chunk of a user's stream: one of their events, a resync after lagging, or a
+/// keepalive comment. `None` once the bus is gone.
+async fn next_chunk(
+    user_id: i32,
+    events: &mut Receiver<(i32, ChangeEvent)>,
+    keepalive: &mut Interval,
+) -> Option<Bytes> {
+    loop {
+        tokio::select! {
+            received = events.recv() => match received {
+                Ok((owner, event)) if owner == user_id => {
+                    let data = serde_json::to_string(&event).ok()?;
+                    return Some(Bytes::from(format!("event: change\ndata: {}\n\n", data)));
+                }
+                Ok(_) => continue,
+                Err(RecvError::Lagged(_)) => {
+                    return Some(Bytes::from_static(b"event: resync\ndata: {}\n\n"));
+                }
+                Err(RecvError::Closed) => return None,
+            },
+            _ = keepalive.tick() => return Some(Bytes::from_static(b": keepalive\n\n")),
+        }
+    }
+}
+
+/// Stream the caller's change events as `text/event-stream`
+#[get("/events")]
+pub async fn event_stream(bus: Option<web::Data<EventBus>>, auth_user: AuthUser) -> impl Responder {
+    let Some(bus) = bus else {
+        return HttpResponse::ServiceUnavailable().body("Live updates are not enabled");
+    };
+
+    let user_id = auth_user.user_id;
+    let state = (bus.subscribe(), interval(KEEPALIVE_INTERVAL));
+    let stream = futures::stream::unfold(state, move |(mut events, mut keepalive)| async move {
+        let chunk = next_chunk(user_id, &mut events, &mut keepalive).await?;
+        Some((Ok::<_, actix_web::Error>(chunk), (events, keepalive)))
+    });
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((CACHE_CONTROL, "no-cache"))
+        // Compressors hold small writes back, which would delay events
+        .insert_header(ContentEncoding::Identity)
+        .streaming(stream)
+}
@@ -0,0 +1,1441 @@
+//! Canonical request and response payloads for client developers.
+//! Every example is built from the DTOs the handlers actually use, so a field added to a
+//! model shows up here (and in its schema) without anyone having to remember the docs.
+
+use crate::avatar::gravatar_hash;
+use crate::import::{ImportMapping, JsonImportRequest};
+use crate::interactions::BUILTIN_INTERACTION_TYPES;
+#[cfg(feature = "capture-parsing")]
+use crate::models::CaptureSuggestion;
+use crate::models::{
+    AccountUsage, Attachment, AvatarSource, Capture, Contact, ContactChange, ContactLink,
+    ContactMatch, ContactResponse, ContactTag, CreatedInteraction, CustomInteractionType,
+    DecliningContact, DeliveryStatus, ExportArchive, ExportSchedule, ExportScheduleRequest,
+    FilterQuery, GiftIdea, GiftStatus, Goal, GoalPeriod, GoalProgress, Granularity, Group,
+    GroupDigest, GroupInteractionRequest, GroupMembersRequest, HealthTrend, HookDelivery,
+    HookEvent, InboundEmailAddress, Interaction, InteractionTypesResponse, LinkPreview, MetAt,
+    NearbyContact, NewCaptureRequest, NewContactRequest, NewGiftIdeaRequest, NewGoalRequest,
+    NewGroupRequest, NewInteractionRequest, NewInteractionTypeRequest, NewOccasionRequest,
+    NewPushSubscriptionRequest, NewRestHookRequest, NewSavedFilterRequest, NewShareRequest,
+    NewSocialProfileRequest, NewTagRequest, NoteMatch, NoteSource, NotificationSettings, Occasion,
+    OccasionType, Preferences, PushKeys, PushSubscription, QuickAddOccasion, QuickAddParse,
+    QuickAddRequest, QuickAddResponse, ReconnectPick, ReconnectResponse, RelationshipHealth,
+    Reminder, ReminderKind, ResolveCaptureRequest, RestHook, SavedFilter, Share, SharePermission,
+    SharedContact, SharesResponse, SocialPlatform, SocialProfile, StatsMetric, SuggestionSnooze,
+    Tag, TagArchive, TagExport, TagImportReport, TagPalette, TagResponse, TagUsage, TimeSeries,
+    TimeSeriesPoint, TranscriptionStatus, TriggerItem, Unassigned, UpcomingOccasion,
+    UpdateProfileRequest, Usage, UsageLimits, UserProfile, WeeklyReview,
+};
+use crate::tags::PALETTE;
+use crate::zapier;
+use actix_web::http::Method;
+use actix_web::{HttpResponse, Responder, get, web};
+use schemars::{JsonSchema, Schema, schema_for};
+use serde::Serialize;
+use serde_json::Value;
+use time::macros::{date, datetime};
+
+/// A JSON body together with the schema of the type it was serialized from
+#[derive(Debug, Serialize)]
+pub struct Payload {
+    pub example: Value,
+    pub schema: Schema,
+}
+
+impl Payload {
+    fn of<T: Serialize + JsonSchema>(value: &T) -> Self {
+        Payload {
+            example: serde_json::to_value(value).expect("example payloads serialize"),
+            schema: schema_for!(T),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Example {
+    /// Handler name, used as the lookup key in /api/examples/{endpoint}
+    pub endpoint: &'static str,
+    #[serde(serialize_with = "serialize_method")]
+    pub method: Method,
+    /// Route pattern as listed in `routes::ROUTES`
+    pub pattern: &'static str,
+    pub request: Option<Payload>,
+    pub response: Option<Payload>,
+}
+
+fn serialize_method<S: serde::Serializer>(
+    method: &Method,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(method.as_str())
+}
+
+fn example(
+    endpoint: &'static str,
+    method: Method,
+    pattern: &'static str,
+    request: Option<Payload>,
+    response: Option<Payload>,
+) -> Example {
+    Example {
+        endpoint,
+        method,
+        pattern,
+        request,
+        response,
+    }
+}
+
+fn sample_new_contact() -> NewContactRequest {
+    NewContactRequest {
+        first_name: Some("Ada".to_string()),
+        last_name: Some("Lovelace".to_string()),
+        email: Some("ada@example.com".to_string()),
+        phone: Some("(415) 555-0100".to_string()),
+        short_note: Some("Met at the analytical engine meetup".to_string()),
+        notes: Some("Interested in collaborating on the next paper".to_string()),
+        avatar_url: Some("https://example.com/photos/ada.jpg".to_string()),
+        desired_frequency_days: Some(30),
+        met_at: Some(MetAt {
+            date: Some(date!(2023 - 06 - 10)),
+            place: Some("London".to_string()),
+            context: Some("Charles's soirée".to_string()),
+        }),
+        introduced_by_contact_id: Some(17),
+        location: Some("London".to_string()),
+    }
+}
+
+fn sample_preferences() -> Preferences {
+    Preferences {
+        country: Some("US".to_string()),
+        quiet_weekdays: vec![6, 7],
+        greeting_tag_ids: vec![7],
+        timezone: Some("America/New_York".to_string()),
+        remind_days_before: vec![0, 7],
+    }
+}
+
+fn sample_profile() -> UserProfile {
+    UserProfile {
+        user_id: 1,
+        name: "Grace Hopper".to_string(),
+        email: "grace@example.com".to_string(),
+        timezone: Some("America/New_York".to_string()),
+        notifications: NotificationSettings {
+            email: true,
+            push: false,
+            weekly_review: true,
+        },
+        default_reminder_cadence_days: 30,
+    }
+}
+
+fn sample_contact() -> Contact {
+    let new = sample_new_contact();
+    Contact {
+        contact_id: 42,
+        first_name: new.first_name,
+        last_name: new.last_name,
+        email: new.email,
+        phone: new.phone,
+        phone_e164: Some("+14155550100".to_string()),
+        short_note: new.short_note,
+        notes: new.notes,
+        avatar_url: new.avatar_url,
+        archived: false,
+        desired_frequency_days: new.desired_frequency_days,
+        met_at: new.met_at,
+        introduced_by_contact_id: new.introduced_by_contact_id,
+        location: new.location,
+        latitude: Some(51.5073),
+        longitude: Some(-0.1277),
+        updated_at: None,
+    }
+}
+
+fn sample_tag_archive() -> TagArchive {
+    let tag = sample_tag();
+    TagArchive {
+        tags: vec![TagExport {
+            name: tag.name,
+            color: tag.color,
+            details: tag.details,
+            contact_emails: vec![
+                "ada@example.com".to_string(),
+                "charles@analytical.org".to_string(),
+            ],
+        }],
+    }
+}
+
+fn sample_new_tag() -> NewTagRequest {
+    NewTagRequest {
+        name: "friends".to_string(),
+        color: Some("#4caf50".to_string()),
+        details: Some("People to catch up with outside work".to_string()),
+    }
+}
+
+fn sample_tag() -> Tag {
+    let new = sample_new_tag();
+    Tag {
+        tag_id: 7,
+        name: new.name,
+        color: new.color,
+        details: new.details,
+    }
+}
+
+fn sample_new_interaction() -> NewInteractionRequest {
+    NewInteractionRequest {
+        contact_id: 42,
+        interaction_date: datetime!(2024-03-14 18:30:00 UTC),
+        notes: Some("Coffee downtown".to_string()),
+        follow_up_priority: Some(2),
+        interaction_type: Some("meeting".to_string()),
+        contact_ids: vec![43],
+        follow_up_in_days: None,
+    }
+}
+
+fn sample_interaction() -> Interaction {
+    let new = sample_new_interaction();
+    Interaction {
+        interaction_id: 3,
+        contact_id: Some(new.contact_id),
+        interaction_date: new.interaction_date,
+        notes: new.notes,
+        follow_up_priority: new.follow_up_priority,
+        interaction_type: new.interaction_type,
+        contact_ids: new.contact_ids,
+        tag_ids: vec![7],
+    }
+}
+
+fn sample_new_occasion() -> NewOccasionRequest {
+    NewOccasionRequest {
+        contact_id: 42,
+        name: "Birthday".to_string(),
+        date: date!(2024 - 12 - 10),
+        recurring: true,
+        recurring_interval: Some(365),
+        details: Some("Likes fountain pens".to_string()),
+        occasion_type: OccasionType::Birthday,
+        birth_year: Some(1990),
+        remind_days_before: Some(vec![0, 3, 14]),
+    }
+}
+
+fn sample_occasion() -> Occasion {
+    let new = sample_new_occasion();
+    Occasion {
+        occasion_id: 5,
+        contact_id: Some(new.contact_id),
+        name: new.name,
+        date: new.date,
+        recurring: Some(new.recurring),
+        recurring_interval: new.recurring_interval,
+        details: new.details,
+        occasion_type: new.occasion_type,
+        birth_year: new.birth_year,
+        remind_days_before: new.remind_days_before,
+        upcoming_age: Some(35),
+        interaction_id: None,
+    }
+}
+
+fn sample_contact_link() -> ContactLink {
+    ContactLink {
+        link_id: 6,
+        contact_id: 42,
+        token: Some("9b1d4f7e2c8a6035e1f4d2b7c9a08e36".to_string()),
+        expires_at: datetime!(2024-03-22 09:30:00 UTC),
+        url: Some(
+            "https://crm.example.com/public/contacts/9b1d4f7e2c8a6035e1f4d2b7c9a08e36".to_string(),
+        ),
+    }
+}
+
+fn sample_rest_hook() -> RestHook {
+    RestHook {
+        hook_id: 4,
+        user_id: 0,
+        workspace_id: 0,
+        event: HookEvent::NewContact,
+        target_url: "https://hooks.zapier.com/hooks/standard/1234567/abcdef/".to_string(),
+        delivered_on: None,
+    }
+}
+
+fn sample_push_subscription() -> PushSubscription {
+    PushSubscription {
+        subscription_id: 3,
+        user_id: 0,
+        endpoint: "https://fcm.googleapis.com/fcm/send/dQw4w9WgXcQ:APA91bH2".to_string(),
+        p256dh: String::new(),
+        auth: String::new(),
+        device_name: Some("Pixel 8".to_string()),
+        created_at: datetime!(2024-03-15 09:30:00 UTC),
+    }
+}
+
+fn sample_new_social_profile() -> NewSocialProfileRequest {
+    NewSocialProfileRequest {
+        contact_id: 42,
+        platform: SocialPlatform::Github,
+        url: "https://github.com/ada".to_string(),
+    }
+}
+
+fn sample_social_profile() -> SocialProfile {
+    let new = sample_new_social_profile();
+    SocialProfile {
+        social_profile_id: 6,
+        contact_id: new.contact_id,
+        platform: new.platform,
+        url: new.url,
+        preview: Some(LinkPreview {
+            title: Some("ada - Overview".to_string()),
+            image_url: Some("https://avatars.githubusercontent.com/u/1".to_string()),
+        }),
+    }
+}
+
+fn sample_new_gift_idea() -> NewGiftIdeaRequest {
+    NewGiftIdeaRequest {
+        contact_id: 42,
+        occasion_id: Some(5),
+        idea: "Fountain pen".to_string(),
+        status: GiftStatus::Idea,
+        price_cents: Some(4500),
+    }
+}
+
+fn sample_gift_idea() -> GiftIdea {
+    let new = sample_new_gift_idea();
+    GiftIdea {
+        gift_idea_id: 8,
+        contact_id: new.contact_id,
+        occasion_id: new.occasion_id,
+        idea: new.idea,
+        status: new.status,
+        price_cents: new.price_cents,
+    }
+}
+
+fn sample_new_group() -> NewGroupRequest {
+    NewGroupRequest {
+        name: "Book club".to_string(),
+        description: Some("First Thursday of the month".to_string()),
+    }
+}
+
+fn sample_group() -> Group {
+    let new = sample_new_group();
+    Group {
+        group_id: 6,
+        name: new.name,
+        description: new.description,
+        contact_ids: vec![42, 17],
+    }
+}
+
+fn sample_new_filter() -> NewSavedFilterRequest {
+    NewSavedFilterRequest {
+        name: "Friends to catch up with".to_string(),
+        query: FilterQuery {
+            all_tag_ids: vec![7],
+            any_tag_ids: Vec::new(),
+            last_interaction_older_than_days: Some(90),
+            upcoming_occasion_within_days: None,
+        },
+    }
+}
+
+fn sample_filter() -> SavedFilter {
+    let new = sample_new_filter();
+    SavedFilter {
+        filter_id: 11,
+        name: new.name,
+        query: new.query,
+    }
+}
+
+fn sample_capture() -> Capture {
+    Capture {
+        capture_id: 8,
+        text: "lunch w/ Priya yesterday, she's moving to Austin".to_string(),
+        captured_at: datetime!(2024-03-12 13:45:00 UTC),
+    }
+}
+
+fn sample_new_goal() -> NewGoalRequest {
+    NewGoalRequest {
+        name: "Call Mom".to_string(),
+        contact_id: Some(42),
+        interaction_type: Some("call".to_string()),
+        target_count: 1,
+        period: GoalPeriod::Week,
+    }
+}
+
+fn sample_goal() -> Goal {
+    let new = sample_new_goal();
+    Goal {
+        goal_id: 5,
+        name: new.name,
+        contact_id: new.contact_id,
+        interaction_type: new.interaction_type,
+        target_count: new.target_count,
+        period: new.period,
+    }
+}
+
+fn sample_health() -> RelationshipHealth {
+    RelationshipHealth {
+        score: 82,
+        trend: Some(HealthTrend::Steady),
+        gap_growth: Some(1.1),
+    }
+}
+
+fn sample_contact_response() -> ContactResponse {
+    ContactResponse {
+        contact: sample_contact(),
+        tags: vec![sample_tag()],
+        interactions: vec![sample_interaction()],
+        occasions: vec![sample_occasion()],
+        predicted_contact_priority: Some(0.5),
+        overdue_days: Some(-4),
+        avatar_url: Some("https://example.com/photos/ada.jpg".to_string()),
+        avatar_source: Some(AvatarSource::Uploaded),
+        health: Some(sample_health()),
+        summary: None,
+    }
+}
+
+/// Every endpoint with a typed JSON request or response body
+pub fn examples() -> Vec<Example> {
+    vec![
+        example(
+            "list_contacts",
+            Method::GET,
+            "/v1/contacts",
+            None,
+            Some(Payload::of(&vec![sample_contact_response()])),
+        ),
+        example(
+            "lookup_contacts",
+            Method::GET,
+            "/v1/contacts/lookup",
+            None,
+            Some(Payload::of(&vec![sample_contact()])),
+        ),
+        example(
+            "autocomplete_contacts",
+            Method::GET,
+            "/v1/contacts/autocomplete",
+            None,
+            Some(Payload::of(&vec![ContactMatch {
+                contact_id: 42,
+                display_name: "Ada Lovelace".to_string(),
+                avatar_url: Some("https://example.com/photos/ada.jpg".to_string()),
+            }])),
+        ),
+        example(
+            "search_notes",
+            Method::GET,
+            "/v1/interactions/search",
+            None,
+            Some(Payload::of(&vec![NoteMatch {
+                source: NoteSource::Interaction,
+                interaction_id: Some(3),
+                interaction_date: Some(datetime!(2024-03-14 18:30:00 UTC)),
+                contact: Some(ContactMatch {
+                    contact_id: 42,
+                    display_name: "Ada Lovelace".to_string(),
+                    avatar_url: None,
+                }),
+                snippet: "Her dog is called <mark>Biscuit</mark>".to_string(),
+            }])),
+        ),
+        example(
+            "nearby_contacts",
+            Method::GET,
+            "/v1/contacts/nearby",
+            None,
+            Some(Payload::of(&vec![NearbyContact {
+                contact: sample_contact(),
+                distance_km: 3.2,
+            }])),
+        ),
+        example(
+            "declining_contacts",
+            Method::GET,
+            "/v1/contacts/declining",
+            None,
+            Some(Payload::of(&vec![DecliningContact {
+                contact: sample_contact(),
+                health: RelationshipHealth {
+                    score: 41,
+                    trend: Some(HealthTrend::Declining),
+                    gap_growth: Some(2.4),
+                },
+            }])),
+        ),
+        example(
+            "get_contact",
+            Method::GET,
+            "/v1/contacts/{id}",
+            None,
+            Some(Payload::of(&sample_contact_response())),
+        ),
+        example(
+            "contact_interactions",
+            Method::GET,
+            "/v1/contacts/{id}/interactions",
+            None,
+            Some(Payload::of(&vec![sample_interaction()])),
+        ),
+        example(
+            "list_attachments",
+            Method::GET,
+            "/v1/interactions/{id}/attachments",
+            None,
+            Some(Payload::of(&vec![Attachment {
+                attachment_id: 8,
+                interaction_id: 3,
+                file_name: "voice-memo.m4a".to_string(),
+                content_type: "audio/mp4".to_string(),
+                size_bytes: 482_113,
+                transcription: Some(TranscriptionStatus::Done),
+                created_at: datetime!(2024-03-15 18:05:00 UTC),
+            }])),
+        ),
+        example(
+            "contact_history",
+            Method::GET,
+            "/v1/contacts/{id}/history",
+            None,
+            Some(Payload::of(&vec![ContactChange {
+                change_id: 17,
+                field: "phone".to_string(),
+                before: Some("+1 415 555 0123".to_string()),
+                after: Some("+1 415 555 0199".to_string()),
+                changed_at: datetime!(2024-03-12 13:45:00 UTC),
+            }])),
+        ),
+        example(
+            "create_contact_link",
+            Method::POST,
+            "/v1/contacts/{id}/share",
+            None,
+            Some(Payload::of(&sample_contact_link())),
+        ),
+        example(
+            "list_contact_links",
+            Method::GET,
+            "/v1/contacts/{id}/share-links",
+            None,
+            Some(Payload::of(&vec![ContactLink {
+                token: None,
+                url: None,
+                ..sample_contact_link()
+            }])),
+        ),
+        example(
+            "contact_introductions",
+            Method::GET,
+            "/v1/contacts/{id}/introductions",
+            None,
+            Some(Payload::of(&vec![Contact {
+                contact_id: 43,
+                first_name: Some("Mary".to_string()),
+                last_name: Some("Somerville".to_string()),
+                email: None,
+                phone: None,
+                phone_e164: None,
+                short_note: None,
+                notes: None,
+                avatar_url: None,
+                met_at: None,
+                introduced_by_contact_id: Some(42),
+                ..sample_contact()
+            }])),
+        ),
+        example(
+            "create_contact",
+            Method::POST,
+            "/v1/contacts",
+            Some(Payload::of(&sample_new_contact())),
+            Some(Payload::of(&sample_contact())),
+        ),
+        example(
+            "create_contacts_bulk",
+            Method::POST,
+            "/v1/contacts/bulk",
+            Some(Payload::of(&vec![sample_new_contact()])),
+            None,
+        ),
+        example(
+            "import_json",
+            Method::POST,
+            "/v1/contacts/import/json",
+            Some(Payload::of(&JsonImportRequest {
+                mapping: ImportMapping {
+                    records: ".people".to_string(),
+                    fields: [
+                        ("first_name", ".name.given"),
+                        ("last_name", ".name.family"),
+                        ("email", ".emails[0] | ascii_downcase"),
+                        ("notes", ".bio // .summary"),
+                    ]
+                    .into_iter()
+                    .map(|(field, expr)| (field.to_string(), expr.to_string()))
+                    .collect(),
+                },
+                data: serde_json::json!({
+                    "people": [{
+                        "name": { "given": "Ada", "family": "Lovelace" },
+                        "emails": ["Ada@Example.com"],
+                        "summary": "Met at the analytical engine meetup"
+                    }]
+                }),
+            })),
+            None,
+        ),
+        example(
+            "quick_add",
+            Method::POST,
+            "/v1/quick-add",
+            Some(Payload::of(&QuickAddRequest {
+                text: "Ada Lovelace ada@example.com #friends @London birthday:1815-12-10 every:30"
+                    .to_string(),
+            })),
+            Some(Payload::of(&QuickAddResponse {
+                parsed: QuickAddParse {
+                    first_name: Some("Ada".to_string()),
+                    last_name: Some("Lovelace".to_string()),
+                    email: Some("ada@example.com".to_string()),
+                    phone: None,
+                    location: Some("London".to_string()),
+                    short_note: None,
+                    desired_frequency_days: Some(30),
+                    tags: vec!["friends".to_string()],
+                    occasions: vec![QuickAddOccasion {
+                        occasion_type: OccasionType::Birthday,
+                        date: date!(1815 - 12 - 10),
+                        birth_year: Some(1815),
+                    }],
+                },
+                contact: Some(Contact {
+                    phone: None,
+                    phone_e164: None,
+                    short_note: None,
+                    notes: None,
+                    avatar_url: None,
+                    met_at: None,
+                    introduced_by_contact_id: None,
+                    ..sample_contact()
+                }),
+                tag_ids: vec![7],
+                new_tags: Vec::new(),
+                occasion_ids: vec![5],
+                dry_run: false,
+            })),
+        ),
+        example(
+            "update_contact",
+            Method::PATCH,
+            "/v1/contacts/{id}",
+            Some(Payload::of(&sample_new_contact())),
+            Some(Payload::of(&sample_contact())),
+        ),
+        example(
+            "patch_contact",
+            Method::PATCH,
+            "/v1/contacts/{id}",
+            Some(Payload::of(
+                &serde_json::from_value::<json_patch::Patch>(serde_json::json!([
+                    { "op": "test", "path": "/email", "value": "ada@example.com" },
+                    { "op": "replace", "path": "/email", "value": "ada@analytical.org" },
+                    { "op": "remove", "path": "/phone" }
+                ]))
+                .expect("example patch parses"),
+            )),
+            Some(Payload::of(&Contact {
+                email: Some("ada@analytical.org".to_string()),
+                phone: None,
+                phone_e164: None,
+                ..sample_contact()
+            })),
+        ),
+        example(
+            "enrich_contact",
+            Method::POST,
+            "/v1/contacts/{id}/enrich",
+            None,
+            Some(Payload::of(&Contact {
+                avatar_url: Some(format!(
+                    "https://gravatar.com/avatar/{}",
+                    gravatar_hash("ada@example.com")
+                )),
+                ..sample_contact()
+            })),
+        ),
+        example(
+            "list_tags",
+            Method::GET,
+            "/v1/tags",
+            None,
+            Some(Payload::of(&TagResponse {
+                tags: vec![TagUsage {
+                    tag: sample_tag(),
+                    contact_count: 12,
+                }],
+            })),
+        ),
+        example(
+            "tag_palette",
+            Method::GET,
+            "/v1/tags/palette",
+            None,
+            Some(Payload::of(&TagPalette {
+                colors: PALETTE.iter().map(|c| c.to_string()).collect(),
+            })),
+        ),
+        example(
+            "create_tag",
+            Method::POST,
+            "/v1/tags",
+            Some(Payload::of(&sample_new_tag())),
+            Some(Payload::of(&sample_tag())),
+        ),
+        example(
+            "update_tag",
+            Method::PATCH,
+            "/v1/tags/{id}",
+            Some(Payload::of(&sample_new_tag())),
+            Some(Payload::of(&sample_tag())),
+        ),
+        example(
+            "export_tags",
+            Method::GET,
+            "/v1/tags/export",
+            None,
+            Some(Payload::of(&sample_tag_archive())),
+        ),
+        example(
+            "import_tags",
+            Method::POST,
+            "/v1/tags/import",
+            Some(Payload::of(&sample_tag_archive())),
+            Some(Payload::of(&TagImportReport {
+                created_tag_ids: vec![7],
+                existing_tag_ids: Vec::new(),
+                new_tags: vec![sample_tag().name],
+                tagged_count: 1,
+                unmatched_emails: vec!["charles@analytical.org".to_string()],
+                dry_run: false,
+            })),
+        ),
+        example(
+            "reconnect_this_week",
+            Method::GET,
+            "/v1/reconnect/this-week",
+            None,
+            Some(Payload::of(&ReconnectResponse {
+                week_of: date!(2024 - 03 - 11),
+                contacts: vec![ReconnectPick {
+                    contact: sample_contact(),
+                    tags: vec![sample_tag()],
+                    days_since_last_interaction: Some(45),
+                }],
+            })),
+        ),
+        example(
+            "snooze_contact",
+            Method::POST,
+            "/v1/contacts/{id}/snooze",
+            None,
+            Some(Payload::of(&SuggestionSnooze {
+                contact_id: 42,
+                snoozed_until: Some(date!(2024 - 03 - 27)),
+            })),
+        ),
+        example(
+            "dismiss_suggestion",
+            Method::POST,
+            "/v1/contacts/{id}/dismiss-suggestion",
+            None,
+            Some(Payload::of(&SuggestionSnooze {
+                contact_id: 42,
+                snoozed_until: None,
+            })),
+        ),
+        example(
+            "list_filters",
+            Method::GET,
+            "/v1/filters",
+            None,
+            Some(Payload::of(&vec![sample_filter()])),
+        ),
+        example(
+            "create_filter",
+            Method::POST,
+            "/v1/filters",
+            Some(Payload::of(&sample_new_filter())),
+            Some(Payload::of(&sample_filter())),
+        ),
+        example(
+            "update_filter",
+            Method::PATCH,
+            "/v1/filters/{id}",
+            Some(Payload::of(&sample_new_filter())),
+            Some(Payload::of(&sample_filter())),
+        ),
+        example(
+            "filter_contacts",
+            Method::GET,
+            "/v1/filters/{id}/contacts",
+            None,
+            Some(Payload::of(&vec![sample_contact_response()])),
+        ),
+        example(
+            "list_goals",
+            Method::GET,
+            "/v1/goals",
+            None,
+            Some(Payload::of(&vec![sample_goal()])),
+        ),
+        example(
+            "create_goal",
+            Method::POST,
+            "/v1/goals",
+            Some(Payload::of(&sample_new_goal())),
+            Some(Payload::of(&sample_goal())),
+        ),
+        example(
+            "goals_progress",
+            Method::GET,
+            "/v1/goals/progress",
+            None,
+            Some(Payload::of(&vec![GoalProgress {
+                goal: sample_goal(),
+                period_start: date!(2024 - 03 - 11),
+                count: 0,
+                completed: false,
+                streak: 6,
+                best_streak: 9,
+            }])),
+        ),
+        example(
+            "update_goal",
+            Method::PATCH,
+            "/v1/goals/{id}",
+            Some(Payload::of(&sample_new_goal())),
+            Some(Payload::of(&sample_goal())),
+        ),
+        example(
+            "get_preferences",
+            Method::GET,
+            "/v1/preferences",
+            None,
+            Some(Payload::of(&sample_preferences())),
+        ),
+        example(
+            "update_preferences",
+            Method::PUT,
+            "/v1/preferences",
+            Some(Payload::of(&sample_preferences())),
+            Some(Payload::of(&sample_preferences())),
+        ),
+        example(
+            "upcoming_reminders",
+            Method::GET,
+            "/v1/reminders/upcoming",
+            None,
+            Some(Payload::of(&vec![
+                Reminder {
+                    kind: ReminderKind::Occasion,
+                    remind_on: date!(2024 - 12 - 19),
+                    date: date!(2024 - 12 - 22),
+                    name: "Birthday".to_string(),
+                    days_before: 3,
+                    occasion_id: Some(5),
+                    contact_ids: vec![42],
+                    gift_ideas: vec![sample_gift_idea()],
+                },
+                Reminder {
+                    kind: ReminderKind::HolidayGreeting,
+                    remind_on: date!(2024 - 12 - 20),
+                    date: date!(2024 - 12 - 25),
+                    name: "Send holiday greetings: Christmas Day".to_string(),
+                    days_before: 3,
+                    occasion_id: None,
+                    contact_ids: vec![42],
+                    gift_ideas: Vec::new(),
+                },
+            ])),
+        ),
+        example(
+            "weekly_review",
+            Method::GET,
+            "/v1/review/weekly",
+            None,
+            Some(Payload::of(&WeeklyReview {
+                from: date!(2024 - 03 - 04),
+                to: date!(2024 - 03 - 10),
+                timezone: "America/New_York".to_string(),
+                interactions_logged: 6,
+                new_contacts: vec![sample_contact()],
+                upcoming_occasions: vec![UpcomingOccasion {
+                    occurs_on: date!(2024 - 03 - 14),
+                    occasion: sample_occasion(),
+                }],
+                reconnect: vec![ReconnectPick {
+                    contact: sample_contact(),
+                    tags: vec![sample_tag()],
+                    days_since_last_interaction: Some(45),
+                }],
+            })),
+        ),
+        example(
+            "subscribe_push",
+            Method::POST,
+            "/v1/push/subscriptions",
+            Some(Payload::of(&NewPushSubscriptionRequest {
+                endpoint: sample_push_subscription().endpoint,
+                keys: PushKeys {
+                    p256dh: "BNcRdreALRFXTkOOUHK1EtK2wtaz5Ry4YfYCA_0QTpQtUbVlUls0VJXg7A8u-Ts1XbjhazAkj7I99e8QcYP7DkM".to_string(),
+                    auth: "tBHItJI5svbpez7KI4CCXg".to_string(),
+                },
+                device_name: Some("Pixel 8".to_string()),
+            })),
+            Some(Payload::of(&sample_push_subscription())),
+        ),
+        example(
+            "list_push_subscriptions",
+            Method::GET,
+            "/v1/push/subscriptions",
+            None,
+            Some(Payload::of(&vec![sample_push_subscription()])),
+        ),
+        example(
+            "get_timeseries",
+            Method::GET,
+            "/v1/stats/timeseries",
+            None,
+            Some(Payload::of(&TimeSeries {
+                metric: StatsMetric::Interactions,
+                granularity: Granularity::Week,
+                from: date!(2024 - 02 - 19),
+                to: date!(2024 - 03 - 06),
+                timezone: "America/New_York".to_string(),
+                points: vec![
+                    TimeSeriesPoint {
+                        bucket: date!(2024 - 02 - 19),
+                        count: 4,
+                    },
+                    TimeSeriesPoint {
+                        bucket: date!(2024 - 02 - 26),
+                        count: 0,
+                    },
+                    TimeSeriesPoint {
+                        bucket: date!(2024 - 03 - 04),
+                        count: 2,
+                    },
+                ],
+            })),
+        ),
+        example(
+            "export_archive",
+            Method::GET,
+            "/v1/export",
+            None,
+            Some(Payload::of(&ExportArchive {
+                exported_at: datetime!(2024-03-15 02:00:00 UTC),
+                contacts: vec![sample_contact()],
+                tags: vec![sample_tag()],
+                contact_tags: vec![ContactTag {
+                    contact_id: 42,
+                    tag_id: 7,
+                }],
+                interactions: vec![sample_interaction()],
+                occasions: vec![sample_occasion()],
+                social_profiles: vec![SocialProfile {
+                    preview: None,
+                    ..sample_social_profile()
+                }],
+                gift_ideas: vec![sample_gift_idea()],
+                groups: vec![sample_group()],
+                preferences: sample_preferences(),
+            })),
+        ),
+        example(
+            "get_export_schedule",
+            Method::GET,
+            "/v1/export/schedule",
+            None,
+            Some(Payload::of(&ExportSchedule {
+                user_id: 0,
+                workspace_id: 0,
+                url: "https://backup.example.com/crm".to_string(),
+                interval_hours: 24,
+                secret: String::new(),
+                last_pushed_at: Some(datetime!(2024-03-15 02:00:00 UTC)),
+                last_status: Some("200 OK".to_string()),
+            })),
+        ),
+        example(
+            "update_export_schedule",
+            Method::PUT,
+            "/v1/export/schedule",
+            Some(Payload::of(&ExportScheduleRequest {
+                url: "https://backup.example.com/crm".to_string(),
+                interval_hours: 24,
+            })),
+            None,
+        ),
+        example(
+            "get_inbound_email",
+            Method::GET,
+            "/v1/inbound-email",
+            None,
+            Some(Payload::of(&InboundEmailAddress {
+                token: "5f0c2e9a7b41d8e3a6c94f1b2d7e0a58".to_string(),
+                address: Some("5f0c2e9a7b41d8e3a6c94f1b2d7e0a58@in.example.com".to_string()),
+            })),
+        ),
+        example(
+            "list_hooks",
+            Method::GET,
+            "/v1/hooks",
+            None,
+            Some(Payload::of(&vec![sample_rest_hook()])),
+        ),
+        example(
+            "subscribe_hook",
+            Method::POST,
+            "/v1/hooks",
+            Some(Payload::of(&NewRestHookRequest {
+                event: HookEvent::NewContact,
+                target_url: sample_rest_hook().target_url,
+            })),
+            None,
+        ),
+        example(
+            "list_deliveries",
+            Method::GET,
+            "/v1/webhooks/{id}/deliveries",
+            None,
+            Some(Payload::of(&vec![
+                HookDelivery {
+                    delivery_id: 31,
+                    status: DeliveryStatus::Pending,
+                    attempts: 2,
+                    last_response_status: Some(503),
+                    last_error: Some("Target answered 503 Service Unavailable".to_string()),
+                    created_at: datetime!(2024-03-14 18:30:02 UTC),
+                    next_attempt_at: datetime!(2024-03-14 18:32:10 UTC),
+                    finished_at: None,
+                },
+                HookDelivery {
+                    delivery_id: 30,
+                    status: DeliveryStatus::Delivered,
+                    attempts: 1,
+                    last_response_status: None,
+                    last_error: None,
+                    created_at: datetime!(2024-03-13 09:12:40 UTC),
+                    next_attempt_at: datetime!(2024-03-13 09:12:40 UTC),
+                    finished_at: Some(datetime!(2024-03-13 09:12:41 UTC)),
+                },
+            ])),
+        ),
+        example(
+            "new_contact_trigger",
+            Method::GET,
+            "/v1/triggers/new-contact",
+            None,
+            Some(Payload::of(&zapier::contact_items(vec![sample_contact()]))),
+        ),
+        example(
+            "new_interaction_trigger",
+            Method::GET,
+            "/v1/triggers/new-interaction",
+            None,
+            Some(Payload::of(&zapier::interaction_items(vec![
+                sample_interaction(),
+            ]))),
+        ),
+        example(
+            "upcoming_occasion_trigger",
+            Method::GET,
+            "/v1/triggers/upcoming-occasion",
+            None,
+            Some(Payload::of(&vec![TriggerItem {
+                id: "5-2024-12-10".to_string(),
+                item: UpcomingOccasion {
+                    occurs_on: date!(2024 - 12 - 10),
+                    occasion: sample_occasion(),
+                },
+            }])),
+        ),
+        example(
+            "get_me",
+            Method::GET,
+            "/v1/me",
+            None,
+            Some(Payload::of(&sample_profile())),
+        ),
+        example(
+            "update_me",
+            Method::PATCH,
+            "/v1/me",
+            Some(Payload::of(&UpdateProfileRequest {
+                timezone: Some(Some("Europe/London".to_string())),
+                default_reminder_cadence_days: Some(14),
+                ..UpdateProfileRequest::default()
+            })),
+            Some(Payload::of(&UserProfile {
+                timezone: Some("Europe/London".to_string()),
+                default_reminder_cadence_days: 14,
+                ..sample_profile()
+            })),
+        ),
+        example(
+            "get_usage",
+            Method::GET,
+            "/v1/account/usage",
+            None,
+            Some(Payload::of(&AccountUsage {
+                usage: Usage {
+                    workspaces: 2,
+                    contacts: 184,
+                    interactions: 1290,
+                    tags: 12,
+                    attachment_bytes: 48_312_904,
+                },
+                limits: UsageLimits {
+                    contacts: Some(1000),
+                    attachment_bytes: Some(1_073_741_824),
+                },
+            })),
+        ),
+        example(
+            "create_interaction",
+            Method::POST,
+            "/v1/interactions",
+            Some(Payload::of(&NewInteractionRequest {
+                follow_up_in_days: Some(14),
+                ..sample_new_interaction()
+            })),
+            Some(Payload::of(&CreatedInteraction {
+                interaction: sample_interaction(),
+                follow_up_occasion_id: Some(12),
+            })),
+        ),
+        example(
+            "update_interaction",
+            Method::PATCH,
+            "/v1/interactions/{id}",
+            Some(Payload::of(&sample_new_interaction())),
+            Some(Payload::of(&sample_interaction())),
+        ),
+        example(
+            "list_captures",
+            Method::GET,
+            "/v1/inbox",
+            None,
+            Some(Payload::of(&vec![sample_capture()])),
+        ),
+        example(
+            "create_capture",
+            Method::POST,
+            "/v1/inbox",
+            Some(Payload::of(&NewCaptureRequest {
+                text: "lunch w/ Priya yesterday, she's moving to Austin".to_string(),
+            })),
+            Some(Payload::of(&sample_capture())),
+        ),
+        example(
+            "resolve_capture",
+            Method::POST,
+            "/v1/inbox/{id}/resolve",
+            Some(Payload::of(&ResolveCaptureRequest {
+                contact_id: 42,
+                interaction_date: Some(datetime!(2024-03-11 12:30:00 UTC)),
+                notes: Some("Lunch; she's moving to Austin in May".to_string()),
+                interaction_type: Some("meeting".to_string()),
+                contact_ids: Vec::new(),
+            })),
+            None,
+        ),
+        #[cfg(feature = "capture-parsing")]
+        example(
+            "suggest_resolution",
+            Method::GET,
+            "/v1/inbox/{id}/suggestion",
+            None,
+            Some(Payload::of(&CaptureSuggestion {
+                contact_id: Some(42),
+                interaction_date: Some(datetime!(2024-03-11 13:45:00 UTC)),
+                notes: Some("Lunch; she's moving to Austin".to_string()),
+                interaction_type: Some("meeting".to_string()),
+                source: "llm".to_string(),
+            })),
+        ),
+        example(
+            "list_interaction_types",
+            Method::GET,
+            "/v1/interaction-types",
+            None,
+            Some(Payload::of(&InteractionTypesResponse {
+                builtin: BUILTIN_INTERACTION_TYPES
+                    .iter()
+                    .map(|t| t.to_string())
+                    .collect(),
+                custom: vec![CustomInteractionType {
+                    type_id: 2,
+                    name: "Dinner".to_string(),
+                }],
+            })),
+        ),
+        example(
+            "create_interaction_type",
+            Method::POST,
+            "/v1/interaction-types",
+            Some(Payload::of(&NewInteractionTypeRequest {
+                name: "Dinner".to_string(),
+            })),
+            None,
+        ),
+        example(
+            "create_occasion",
+            Method::POST,
+            "/v1/occasions",
+            Some(Payload::of(&sample_new_occasion())),
+            Some(Payload::of(&sample_occasion())),
+        ),
+        example(
+            "update_occasion",
+            Method::PATCH,
+            "/v1/occasions/{id}",
+            Some(Payload::of(&sample_new_occasion())),
+            Some(Payload::of(&sample_occasion())),
+        ),
+        example(
+            "list_unassigned",
+            Method::GET,
+            "/v1/unassigned",
+            None,
+            Some(Payload::of(&Unassigned {
+                interactions: vec![Interaction {
+                    contact_id: None,
+                    ..sample_interaction()
+                }],
+                occasions: vec![Occasion {
+                    contact_id: None,
+                    ..sample_occasion()
+                }],
+            })),
+        ),
+        example(
+            "list_social_profiles",
+            Method::GET,
+            "/v1/contacts/{id}/social-profiles",
+            None,
+            Some(Payload::of(&vec![sample_social_profile()])),
+        ),
+        example(
+            "create_social_profile",
+            Method::POST,
+            "/v1/social-profiles",
+            Some(Payload::of(&sample_new_social_profile())),
+            Some(Payload::of(&sample_social_profile())),
+        ),
+        example(
+            "update_social_profile",
+            Method::PATCH,
+            "/v1/social-profiles/{id}",
+            Some(Payload::of(&sample_new_social_profile())),
+            Some(Payload::of(&sample_social_profile())),
+        ),
+        example(
+            "list_gift_ideas",
+            Method::GET,
+            "/v1/contacts/{id}/gift-ideas",
+            None,
+            Some(Payload::of(&vec![sample_gift_idea()])),
+        ),
+        example(
+            "create_gift_idea",
+            Method::POST,
+            "/v1/gift-ideas",
+            Some(Payload::of(&sample_new_gift_idea())),
+            Some(Payload::of(&sample_gift_idea())),
+        ),
+        example(
+            "update_gift_idea",
+            Method::PATCH,
+            "/v1/gift-ideas/{id}",
+            Some(Payload::of(&sample_new_gift_idea())),
+            Some(Payload::of(&sample_gift_idea())),
+        ),
+        example(
+            "list_groups",
+            Method::GET,
+            "/v1/groups",
+            None,
+            Some(Payload::of(&vec![sample_group()])),
+        ),
+        example(
+            "create_group",
+            Method::POST,
+            "/v1/groups",
+            Some(Payload::of(&sample_new_group())),
+            Some(Payload::of(&Group {
+                contact_ids: Vec::new(),
+                ..sample_group()
+            })),
+        ),
+        example(
+            "get_group",
+            Method::GET,
+            "/v1/groups/{id}",
+            None,
+            Some(Payload::of(&sample_group())),
+        ),
+        example(
+            "update_group",
+            Method::PATCH,
+            "/v1/groups/{id}",
+            Some(Payload::of(&sample_new_group())),
+            Some(Payload::of(&sample_group())),
+        ),
+        example(
+            "group_contacts",
+            Method::GET,
+            "/v1/groups/{id}/contacts",
+            None,
+            Some(Payload::of(&vec![sample_contact()])),
+        ),
+        example(
+            "set_group_contacts",
+            Method::PUT,
+            "/v1/groups/{id}/contacts",
+            Some(Payload::of(&GroupMembersRequest {
+                contact_ids: vec![42, 17],
+            })),
+            Some(Payload::of(&sample_group())),
+        ),
+        example(
+            "add_group_contact",
+            Method::POST,
+            "/v1/groups/{group_id}/contacts/{contact_id}",
+            None,
+            Some(Payload::of(&sample_group())),
+        ),
+        example(
+            "remove_group_contact",
+            Method::DELETE,
+            "/v1/groups/{group_id}/contacts/{contact_id}",
+            None,
+            Some(Payload::of(&Group {
+                contact_ids: vec![42],
+                ..sample_group()
+            })),
+        ),
+        example(
+            "log_group_interaction",
+            Method::POST,
+            "/v1/groups/{id}/interactions",
+            Some(Payload::of(&GroupInteractionRequest {
+                interaction_date: datetime!(2024-03-14 19:00:00 UTC),
+                notes: Some("Dinner party at Ada's".to_string()),
+                follow_up_priority: None,
+                interaction_type: Some("meeting".to_string()),
+            })),
+            Some(Payload::of(&Interaction {
+                interaction_date: datetime!(2024-03-14 19:00:00 UTC),
+                notes: Some("Dinner party at Ada's".to_string()),
+                follow_up_priority: None,
+                contact_ids: vec![17],
+                tag_ids: Vec::new(),
+                ..sample_interaction()
+            })),
+        ),
+        example(
+            "send_group_digest",
+            Method::POST,
+            "/v1/groups/{id}/digest",
+            None,
+            Some(Payload::of(&GroupDigest {
+                to: "grace@example.com".to_string(),
+                subject: "Group digest: Book club".to_string(),
+                text: "Book club, 2 members:\nFirst Thursday of the month\n\n\
+                       - Ada Lovelace: last in touch 12 days ago, next occasion 2024-12-25\n\
+                       - Charles Babbage: not in touch yet\n"
+                    .to_string(),
+            })),
+        ),
+        example(
+            "list_shares",
+            Method::GET,
+            "/v1/shares",
+            None,
+            Some(Payload::of(&SharesResponse {
+                given: vec![Share {
+                    share_id: 4,
+                    owner_id: 1,
+                    owner_email: "ada@example.com".to_string(),
+                    grantee_id: 2,
+                    grantee_email: "charles@example.com".to_string(),
+                    tag_id: Some(7),
+                    contact_id: None,
+                    permission: SharePermission::Read,
+                }],
+                received: vec![],
+            })),
+        ),
+        example(
+            "create_share",
+            Method::POST,
+            "/v1/shares",
+            Some(Payload::of(&NewShareRequest {
+                email: "charles@example.com".to_string(),
+                tag_id: Some(7),
+                contact_ids: vec![],
+                permission: SharePermission::Read,
+            })),
+            None,
+        ),
+        example(
+            "shared_contacts",
+            Method::GET,
+            "/v1/shares/contacts",
+            None,
+            Some(Payload::of(&vec![SharedContact {
+                owner_id: 1,
+                owner_name: "Ada Lovelace".to_string(),
+                owner_email: "ada@example.com".to_string(),
+                permission: SharePermission::Write,
+                contact: sample_contact_response(),
+            }])),
+        ),
+    ]
+}
+
+/// Request/response examples for one endpoint, looked up by handler name
+#[get("/api/examples/{endpoint}")]
+pub async fn get_example(endpoint: web::Path<String>) -> impl Responder {
+    let mut all = examples();
+    match all.iter().position(|e| e.endpoint == endpoint.as_str()) {
+        Some(index) => HttpResponse::Ok().json(all.swap_remove(index)),
+        None => HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Unknown endpoint",
+            "endpoints": all.iter().map(|e| e.endpoint).collect::<Vec<_>>()
+        })),
+    }
+}
@@ -0,0 +1,296 @@
+//! Export archive download and scheduled pushes to a user's webhook.
+//!
+//! A schedule POSTs the same archive `GET /export` returns to the user's URL every
+//! `interval_hours`, so self-hosters can keep off-site backups without polling. Each push is
+//! signed: `X-CRM-Signature` is `sha256=` followed by the hex HMAC-SHA256, keyed with the
+//! schedule's secret, of `X-CRM-Timestamp`, a `.`, and the raw body. Receivers should
+//! recompute it and reject stale timestamps.
+
+use crate::AuthUser;
+use crate::jobs::Job;
+use crate::link_preview::{PublicResolver, is_fetchable};
+use crate::models::{
+    ContactTag, ExportArchive, ExportSchedule, ExportScheduleRequest, SavedExportSchedule,
+};
+use crate::mutations;
+use crate::reauth::{self, Reauth};
+use crate::repository::{RepoResult, Repository};
+use crate::streaming;
+use actix_web::http::header::{ContentDisposition, ContentType, DispositionParam, DispositionType};
+use actix_web::{HttpResponse, Responder, delete, get, put, web};
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use reqwest::redirect::Policy;
+use sha2::Sha256;
+use std::time::Duration;
+use time::OffsetDateTime;
+
+/// Allowed push intervals, in hours
+pub const MIN_INTERVAL_HOURS: i32 = 1;
+pub const MAX_INTERVAL_HOURS: i32 = 24 * 30;
+
+/// How often the background task looks for due schedules
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+const PUSH_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Everything in the workspace, with the preferences of the user owning it
+pub async fn build_archive(
+    repo: &dyn Repository,
+    user_id: i32,
+    workspace_id: i32,
+    exported_at: OffsetDateTime,
+) -> RepoResult<ExportArchive> {
+    let contacts = repo.list_contacts(workspace_id).await?;
+    let contact_ids: Vec<i32> = contacts.iter().map(|c| c.contact_id).collect();
+    let contact_tags = repo
+        .tags_for_contacts(&contact_ids)
+        .await?
+        .into_iter()
+        .map(|(contact_id, tag)| ContactTag {
+            contact_id,
+            tag_id: tag.tag_id,
+        })
+        .collect();
+
+    Ok(ExportArchive {
+        exported_at,
+        tags: repo.list_tags(workspace_id).await?,
+        contact_tags,
+        interactions: repo.interactions_for_contacts(&contact_ids).await?,
+        occasions: repo.occasions_for_contacts(&contact_ids).await?,
+        social_profiles: repo.social_profiles_for_contacts(&contact_ids).await?,
+        gift_ideas: repo.gift_ideas_for_contacts(&contact_ids).await?,
+        groups: repo.list_groups(workspace_id).await?,
+        preferences: repo.get_preferences(user_id).await?,
+        contacts,
+    })
+}
+
+/// Value of the `X-CRM-Signature` header for a push
+pub fn signature(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    format!("sha256={:x}", mac.finalize().into_bytes())
+}
+
+/// A new random signing secret, hex encoded
+pub fn generate_secret() -> String {
+    let mut bytes = [0u8; 32];
+    getrandom::fill(&mut bytes).expect("Failed to read random bytes");
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Pushes due archives to their webhooks
+pub struct ExportPusher {
+    client: reqwest::Client,
+}
+
+impl Default for ExportPusher {
+    fn default() -> Self {
+        ExportPusher {
+            client: reqwest::Client::builder()
+                .timeout(PUSH_TIMEOUT)
+                // Redirects could lead to an address the resolver never sees
+                .redirect(Policy::none())
+                .dns_resolver(PublicResolver)
+                .build()
+                .expect("Failed to build HTTP client"),
+        }
+    }
+}
+
+impl ExportPusher {
+    /// POST the workspace's archive to the user's URL, returning the status to record
+    async fn push(&self, repo: &dyn Repository, schedule: &ExportSchedule) -> String {
+        let archive = match build_archive(
+            repo,
+            schedule.user_id,
+            schedule.workspace_id,
+            OffsetDateTime::now_utc(),
+        )
+        .await
+        {
+            Ok(archive) => archive,
+            Err(e) => {
+                eprintln!("Database error: {:?}", e);
+                return "Failed to build export".to_string();
+            }
+        };
+        let body = match serde_json::to_vec(&archive) {
+            Ok(body) => body,
+            Err(e) => return format!("Failed to serialize export: {}", e),
+        };
+        let timestamp = OffsetDateTime::now_utc().unix_timestamp();
+
+        let result = self
+            .client
+            .post(&schedule.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header("X-CRM-Timestamp", timestamp.to_string())
+            .header(
+                "X-CRM-Signature",
+                signature(&schedule.secret, timestamp, &body),
+            )
+            .body(body)
+            .send()
+            .await;
+        match result {
+            Ok(response) => response.status().to_string(),
+            Err(e) => format!("Push failed: {}", e),
+        }
+    }
+
+    /// Push every schedule due at `now` and record the outcome, returning how many were pushed
+    pub async fn run_due(&self, repo: &dyn Repository, now: OffsetDateTime) -> RepoResult<usize> {
+        let due = repo.due_export_schedules(now).await?;
+        for schedule in &due {
+            let status = self.push(repo, schedule).await;
+            repo.record_export_push(schedule.workspace_id, now, &status)
+                .await?;
+        }
+        Ok(due.len())
+    }
+}
+
+/// Checks for due schedules every `CHECK_INTERVAL`
+#[async_trait]
+impl Job for ExportPusher {
+    fn name(&self) -> &'static str {
+        "export-pushes"
+    }
+
+    fn interval(&self) -> Duration {
+        CHECK_INTERVAL
+    }
+
+    async fn run(&self, repo: &dyn Repository) -> RepoResult<()> {
+        self.run_due(repo, OffsetDateTime::now_utc())
+            .await
+            .map(|_| ())
+    }
+}
+
+/// Download everything in the workspace as one JSON file, streamed as it is serialized. Needs a
+/// recent sign-in.
+#[get("/export")]
+pub async fn export_archive(
+    repo: web::Data<dyn Repository>,
+    reauth: Option<web::Data<Reauth>>,
+    auth_user: AuthUser,
+) -> impl Responder {
+    if let Some(response) = reauth::configured(reauth.as_ref()).reject_stale(&auth_user) {
+        return response;
+    }
+    match build_archive(
+        repo.get_ref(),
+        auth_user.user_id,
+        auth_user.workspace_id,
+        OffsetDateTime::now_utc(),
+    )
+    .await
+    {
+        Ok(archive) => HttpResponse::Ok()
+            .insert_header(ContentDisposition {
+                disposition: DispositionType::Attachment,
+                parameters: vec![DispositionParam::Filename(
+                    "personal-crm-export.json".to_string(),
+                )],
+            })
+            .content_type(ContentType::json())
+            .streaming(streaming::json_stream(archive)),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to export data")
+        }
+    }
+}
+
+#[get("/export/schedule")]
+pub async fn get_export_schedule(
+    repo: web::Data<dyn Repository>,
+    auth_user: AuthUser,
+) -> impl Responder {
+    match repo.export_schedule(auth_user.workspace_id).await {
+        Ok(Some(schedule)) => HttpResponse::Ok().json(schedule),
+        Ok(None) => HttpResponse::NotFound().body("Export schedule not found"),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to fetch export schedule")
+        }
+    }
+}
+
+/// Create or replace the schedule. A new signing secret is generated every time and is only
+/// returned here. Needs a recent sign-in, since the archive then goes wherever the URL says.
+#[put("/export/schedule")]
+pub async fn update_export_schedule(
+    repo: web::Data<dyn Repository>,
+    reauth: Option<web::Data<Reauth>>,
+    auth_user: AuthUser,
+    request: web::Json<ExportScheduleRequest>,
+) -> impl Responder {
+    if let Some(response) = reauth::configured(reauth.as_ref()).reject_stale(&auth_user) {
+        return response;
+    }
+    if !reqwest::Url::parse(&request.url).is_ok_and(|url| is_fetchable(&url)) {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "url must be an https URL on a public host name"
+        }));
+    }
+    if !(MIN_INTERVAL_HOURS..=MAX_INTERVAL_HOURS).contains(&request.interval_hours) {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!(
+                "interval_hours must be between {} and {}",
+                MIN_INTERVAL_HOURS, MAX_INTERVAL_HOURS
+            )
+        }));
+    }
+
+    let request = request.into_inner();
+    let schedule = ExportSchedule {
+        user_id: auth_user.user_id,
+        workspace_id: auth_user.workspace_id,
+        url: request.url,
+        interval_hours: request.interval_hours,
+        secret: generate_secret(),
+        last_pushed_at: None,
+        last_status: None,
+    };
+    match repo.save_export_schedule(&schedule).await {
+        Ok(()) => {
+            let saved = repo
+                .export_schedule(auth_user.workspace_id)
+                .await
+                .map(|saved| {
+                    saved.map(|saved| SavedExportSchedule {
+                        schedule: saved,
+                        secret: schedule.secret,
+                    })
+                });
+            mutations::updated(saved)
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to save export schedule")
+        }
+    }
+}
+
+#[delete("/export/schedule")]
+pub async fn delete_export_schedule(
+    repo: web::Data<dyn Repository>,
+    auth_user: AuthUser,
+) -> impl Responder {
+    match repo.delete_export_schedule(auth_user.workspace_id).await {
+        Ok(false) => HttpResponse::NotFound().body("Export schedule not found"),
+        Ok(true) => HttpResponse::Ok().body("Export schedule deleted successfully"),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to delete export schedule")
+        }
+    }
+}
@@ -0,0 +1,201 @@
+//! Sparse fieldsets for the contact list.
+//!
+//! `GET /contacts?fields=contact_id,first_name,last_name,tags` sends each contact with only the
+//! named fields, in the usual shape: contact fields stay under `contact`, the rest sit beside it.
+//! `avatar_url` names both the uploaded photo and the avatar to display. Masked contacts are
+//! serialized straight from the `ContactResponse`, so a masked list streams like a full one.
+//!
+//! `?include=summary` adds each contact's `summary` and, unless `?fields=` says otherwise,
+//! leaves out the interactions and occasions and the fields computed from them.
+//!
+//! `GET /contacts/{id}?include=` masks the same way, leaving out whichever of `interactions`
+//! and `occasions` it doesn't name.
+
+use crate::models::{Contact, ContactResponse};
+use serde::ser::{Serialize, SerializeMap, Serializer};
+
+/// Fields of `Contact`, sent under `contact`
+pub const CONTACT_FIELDS: &[&str] = &[
+    "contact_id",
+    "first_name",
+    "last_name",
+    "email",
+    "phone",
+    "phone_e164",
+    "short_note",
+    "notes",
+    "avatar_url",
+    "archived",
+    "desired_frequency_days",
+    "met_at",
+    "introduced_by_contact_id",
+    "location",
+    "latitude",
+    "longitude",
+];
+
+/// Fields of `ContactResponse` beside `contact`, apart from `avatar_url`
+pub const RESPONSE_FIELDS: &[&str] = &[
+    "tags",
+    "interactions",
+    "occasions",
+    "predicted_contact_priority",
+    "overdue_days",
+    "health",
+    "avatar_source",
+];
+
+/// Fields that need the contact's interactions and occasions loaded
+const DETAIL_FIELDS: &[&str] = &[
+    "interactions",
+    "occasions",
+    "predicted_contact_priority",
+    "overdue_days",
+    "health",
+];
+
+fn all_fields() -> impl Iterator<Item = &'static str> {
+    CONTACT_FIELDS.iter().chain(RESPONSE_FIELDS).copied()
+}
+
+/// The fields picked with `?fields=`, a bit per entry of `CONTACT_FIELDS` then
+/// `RESPONSE_FIELDS`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FieldMask(u32);
+
+impl FieldMask {
+    /// Parse a comma-separated list of field names. The error names the first unknown field.
+    pub fn parse(fields: &str) -> Result<FieldMask, String> {
+        let mut mask = 0;
+        for field in fields.split(',').map(str::trim).filter(|f| !f.is_empty()) {
+            let index = all_fields()
+                .position(|name| name == field)
+                .ok_or_else(|| field.to_string())?;
+            mask |= 1 << index;
+        }
+        Ok(FieldMask(mask))
+    }
+
+    /// Every field but those that need interactions and occasions, which `?include=summary`
+    /// sends in place of them
+    pub fn summary() -> FieldMask {
+        let mask = all_fields()
+            .enumerate()
+            .filter(|(_, field)| !DETAIL_FIELDS.contains(field))
+            .fold(0, |mask, (index, _)| mask | 1 << index);
+        FieldMask(mask)
+    }
+
+    /// Every field
+    pub fn all() -> FieldMask {
+        FieldMask((1 << all_fields().count()) - 1)
+    }
+
+    /// The mask with `field` left out
+    pub fn without(self, field: &str) -> FieldMask {
+        match all_fields().position(|name| name == field) {
+            Some(index) => FieldMask(self.0 & !(1 << index)),
+            None => self,
+        }
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn contains(self, field: &str) -> bool {
+        all_fields()
+            .position(|name| name == field)
+            .is_some_and(|index| self.0 & (1 << index) != 0)
+    }
+
+    /// Whether the fields need interactions and occasions, rather than just the contacts and
+    /// their tags
+    pub fn needs_details(self) -> bool {
+        DETAIL_FIELDS.iter().any(|field| self.contains(field))
+    }
+
+    /// Whether the fields show the avatar, so Gravatars are worth looking up
+    pub fn needs_avatar(self) -> bool {
+        self.contains("avatar_url") || self.contains("avatar_source")
+    }
+
+    fn any_contact_field(self) -> bool {
+        CONTACT_FIELDS.iter().any(|field| self.contains(field))
+    }
+}
+
+/// Serialize each listed field of `$value` that is in the mask
+macro_rules! masked_entries {
+    ($map:ident, $mask:expr, $value:expr, $($field:ident),+ $(,)?) => {
+        $(
+            if $mask.contains(stringify!($field)) {
+                $map.serialize_entry(stringify!($field), &$value.$field)?;
+            }
+        )+
+    };
+}
+
+struct MaskedContact<'a>(&'a Contact, FieldMask);
+
+impl Serialize for MaskedContact<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let MaskedContact(contact, mask) = self;
+        let mut map = serializer.serialize_map(None)?;
+        masked_entries!(
+            map,
+            mask,
+            contact,
+            contact_id,
+            first_name,
+            last_name,
+            email,
+            phone,
+            phone_e164,
+            short_note,
+            notes,
+            avatar_url,
+            archived,
+            desired_frequency_days,
+            met_at,
+            introduced_by_contact_id,
+            location,
+            latitude,
+            longitude,
+        );
+        map.end()
+    }
+}
+
+/// A contact response that serializes only the fields in its mask
+pub struct Masked {
+    pub response: ContactResponse,
+    pub mask: FieldMask,
+}
+
+impl Serialize for Masked {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let Masked { response, mask } = self;
+        let mut map = serializer.serialize_map(None)?;
+        if mask.any_contact_field() {
+            map.serialize_entry("contact", &MaskedContact(&response.contact, *mask))?;
+        }
+        masked_entries!(
+            map,
+            mask,
+            response,
+            tags,
+            interactions,
+            occasions,
+            predicted_contact_priority,
+            overdue_days,
+            health,
+            avatar_url,
+            avatar_source,
+        );
+        if let Some(summary) = &response.summary {
+            map.serialize_entry("summary", summary)?;
+        }
+        map.end()
+    }
+}
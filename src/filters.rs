@@ -0,0 +1,265 @@
+//! Saved filters: named contact queries evaluated server-side each time they are read
+
+use crate::AuthUser;
+use crate::avatar::GravatarResolver;
+use crate::conditional;
+use crate::contacts::contact_responses;
+use crate::events::{self, Action, Entity, EventBus};
+use crate::models::{ContactResponse, FilterQuery, NewSavedFilterRequest};
+use crate::mutations;
+use crate::reminders::next_occurrence;
+use crate::repository::Repository;
+use crate::routes::{Resource, ensure_owned};
+use crate::timezone::LocalDates;
+use actix_web::{HttpRequest, HttpResponse, Responder, delete, get, patch, post, web};
+use time::Duration;
+
+/// Longest name the saved_filters table accepts
+const MAX_NAME_LEN: usize = 100;
+
+/// Whether a contact meets every condition set in the query, as of the user's today
+pub fn matches(query: &FilterQuery, contact: &ContactResponse, dates: &LocalDates) -> bool {
+    let today = dates.today();
+    let has_tag = |tag_id: &i32| contact.tags.iter().any(|t| t.tag_id == *tag_id);
+    if !query.all_tag_ids.iter().all(has_tag) {
+        return false;
+    }
+    if !query.any_tag_ids.is_empty() && !query.any_tag_ids.iter().any(has_tag) {
+        return false;
+    }
+    if let Some(days) = query.last_interaction_older_than_days {
+        let cutoff = today - Duration::days(days.into());
+        if contact
+            .interactions
+            .iter()
+            .any(|i| dates.date_of(i.interaction_date) > cutoff)
+        {
+            return false;
+        }
+    }
+    if let Some(days) = query.upcoming_occasion_within_days {
+        let until = today + Duration::days(days.into());
+        if !contact
+            .occasions
+            .iter()
+            .filter_map(|o| next_occurrence(o, today))
+            .any(|date| date <= until)
+        {
+            return false;
+        }
+    }
+    true
+}
+
+/// Trim the name and check the day counts. Errors are the JSON body of the 400 response.
+fn validate(mut filter: NewSavedFilterRequest) -> Result<NewSavedFilterRequest, serde_json::Value> {
+    filter.name = filter.name.trim().to_string();
+    if filter.name.is_empty() || filter.name.chars().count() > MAX_NAME_LEN {
+        return Err(serde_json::json!({
+            "error": format!("Name must be 1 to {} characters", MAX_NAME_LEN)
+        }));
+    }
+    let query = &filter.query;
+    if [
+        query.last_interaction_older_than_days,
+        query.upcoming_occasion_within_days,
+    ]
+    .into_iter()
+    .flatten()
+    .any(|days| days < 0)
+    {
+        return Err(serde_json::json!({
+            "error": "Day counts must not be negative"
+        }));
+    }
+    Ok(filter)
+}
+
+/// Validate a submitted filter and check that its tags belong to the request's workspace
+async fn checked(
+    repo: &dyn Repository,
+    auth_user: &AuthUser,
+    filter: NewSavedFilterRequest,
+) -> Result<NewSavedFilterRequest, HttpResponse> {
+    let filter = validate(filter).map_err(|error| HttpResponse::BadRequest().json(error))?;
+    for &tag_id in filter
+        .query
+        .all_tag_ids
+        .iter()
+        .chain(&filter.query.any_tag_ids)
+    {
+        ensure_owned(repo, auth_user, Resource::Tag, tag_id).await?;
+    }
+    Ok(filter)
+}
+
+#[get("/filters")]
+pub async fn list_filters(repo: web::Data<dyn Repository>, auth_user: AuthUser) -> impl Responder {
+    match repo.list_filters(auth_user.workspace_id).await {
+        Ok(filters) => HttpResponse::Ok().json(filters),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to fetch filters")
+        }
+    }
+}
+
+#[post("/filters")]
+pub async fn create_filter(
+    req: HttpRequest,
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    auth_user: AuthUser,
+    new_filter: web::Json<NewSavedFilterRequest>,
+) -> impl Responder {
+    let filter = match checked(repo.get_ref(), &auth_user, new_filter.into_inner()).await {
+        Ok(filter) => filter,
+        Err(response) => return response,
+    };
+
+    match repo.create_filter(auth_user.workspace_id, &filter).await {
+        Ok(filter_id) => {
+            events::publish(
+                bus.as_ref(),
+                auth_user.user_id,
+                Entity::SavedFilter,
+                Action::Created,
+                vec![filter_id],
+            );
+            mutations::created(
+                &req,
+                filter_id,
+                repo.get_filter(auth_user.workspace_id, filter_id).await,
+            )
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to create filter")
+        }
+    }
+}
+
+#[patch("/filters/{id}")]
+pub async fn update_filter(
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    auth_user: AuthUser,
+    filter_id: web::Path<i32>,
+    updated_filter: web::Json<NewSavedFilterRequest>,
+) -> impl Responder {
+    let id = filter_id.into_inner();
+
+    // Verify the filter belongs to the user
+    if let Err(response) = ensure_owned(repo.get_ref(), &auth_user, Resource::Filter, id).await {
+        return response;
+    }
+    let filter = match checked(repo.get_ref(), &auth_user, updated_filter.into_inner()).await {
+        Ok(filter) => filter,
+        Err(response) => return response,
+    };
+
+    match repo
+        .update_filter(auth_user.workspace_id, id, &filter)
+        .await
+    {
+        Ok(false) => HttpResponse::NotFound().body("Filter not found"),
+        Ok(true) => {
+            events::publish(
+                bus.as_ref(),
+                auth_user.user_id,
+                Entity::SavedFilter,
+                Action::Updated,
+                vec![id],
+            );
+            mutations::updated(repo.get_filter(auth_user.workspace_id, id).await)
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to update filter")
+        }
+    }
+}
+
+#[delete("/filters/{id}")]
+pub async fn delete_filter(
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    auth_user: AuthUser,
+    filter_id: web::Path<i32>,
+) -> impl Responder {
+    let id = filter_id.into_inner();
+
+    match repo.delete_filter(auth_user.workspace_id, id).await {
+        Ok(false) => HttpResponse::NotFound().body("Filter not found"),
+        Ok(true) => {
+            events::publish(
+                bus.as_ref(),
+                auth_user.user_id,
+                Entity::SavedFilter,
+                Action::Deleted,
+                vec![id],
+            );
+            HttpResponse::Ok().body("Filter deleted successfully")
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to delete filter")
+        }
+    }
+}
+
+/// The contacts currently matching a saved filter, in the same shape and order as
+/// GET /contacts. Tags deleted since the filter was saved match no one, and neither do
+/// archived contacts.
+#[get("/filters/{id}/contacts")]
+pub async fn filter_contacts(
+    req: HttpRequest,
+    repo: web::Data<dyn Repository>,
+    gravatar: Option<web::Data<GravatarResolver>>,
+    auth_user: AuthUser,
+    filter_id: web::Path<i32>,
+) -> impl Responder {
+    let filter = match repo
+        .get_filter(auth_user.workspace_id, filter_id.into_inner())
+        .await
+    {
+        Ok(Some(filter)) => filter,
+        Ok(None) => return HttpResponse::NotFound().body("Filter not found"),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            return HttpResponse::InternalServerError().body("Failed to fetch filter");
+        }
+    };
+    let contacts = match repo.list_contacts(auth_user.workspace_id).await {
+        Ok(mut contacts) => {
+            contacts.retain(|c| !c.archived);
+            contacts
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            return HttpResponse::InternalServerError().body("Failed to fetch contacts");
+        }
+    };
+
+    let dates = match LocalDates::for_user(repo.get_ref(), auth_user.user_id).await {
+        Ok(dates) => dates,
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            return HttpResponse::InternalServerError().body("Failed to fetch preferences");
+        }
+    };
+    let mut response =
+        match contact_responses(repo.get_ref(), auth_user.workspace_id, &contacts, &dates).await {
+            Ok(response) => response,
+            Err(e) => {
+                eprintln!("Database error: {:?}", e);
+                return HttpResponse::InternalServerError().body("Failed to fetch contacts");
+            }
+        };
+    response.retain(|contact| matches(&filter.query, contact, &dates));
+    if let Some(gravatar) = gravatar {
+        gravatar.fill(&mut response).await;
+    }
+
+    conditional::json_response(&req, response, None)
+}
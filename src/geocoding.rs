@@ -0,0 +1,226 @@
+//! Coordinates for contact locations, for finding who lives near a place.
+//!
+//! A contact's `location` ("Lisbon", "Brooklyn, NY") is geocoded when it is saved, if
+//! `GEOCODER_URL` points at a Nominatim API, hosted or self-run. Other providers plug in by
+//! implementing `Geocoder`. Without a geocoder locations are kept as text and contacts have no
+//! coordinates.
+
+use crate::models::Coordinates;
+use crate::repository::{RepoResult, Repository};
+use async_trait::async_trait;
+use futures::stream::{self, StreamExt, TryStreamExt};
+use moka::future::Cache;
+use reqwest::Url;
+use std::time::Duration;
+
+/// Mean radius of the Earth
+pub const EARTH_RADIUS_KM: f64 = 6371.0088;
+
+/// Lookups running at once while geocoding a batch of contacts
+const CONCURRENT_LOOKUPS: usize = 4;
+
+#[async_trait]
+pub trait Geocoder: Send + Sync {
+    /// None when nothing matched or the lookup failed
+    async fn geocode(&self, location: &str) -> Option<Coordinates>;
+}
+
+/// Great-circle distance between two points, by the haversine formula
+pub fn distance_km(a: Coordinates, b: Coordinates) -> f64 {
+    let (lat_a, lat_b) = (a.latitude.to_radians(), b.latitude.to_radians());
+    let half_lat = (lat_b - lat_a) / 2.0;
+    let half_lng = (b.longitude - a.longitude).to_radians() / 2.0;
+    let h = half_lat.sin().powi(2) + lat_a.cos() * lat_b.cos() * half_lng.sin().powi(2);
+    2.0 * EARTH_RADIUS_KM * h.sqrt().min(1.0).asin()
+}
+
+/// A latitude and longitude range. `min_longitude` is greater than `max_longitude` when the
+/// box crosses the antimeridian.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    pub min_latitude: f64,
+    pub max_latitude: f64,
+    pub min_longitude: f64,
+    pub max_longitude: f64,
+}
+
+impl BoundingBox {
+    /// The smallest box holding every point within `radius_km` of `center`, so the database
+    /// can narrow a search before exact distances are worked out
+    pub fn around(center: Coordinates, radius_km: f64) -> Self {
+        let angle = radius_km / EARTH_RADIUS_KM;
+        let min_latitude = center.latitude - angle.to_degrees();
+        let max_latitude = center.latitude + angle.to_degrees();
+        // Circles over a pole take in every longitude
+        if min_latitude <= -90.0 || max_latitude >= 90.0 || angle >= std::f64::consts::FRAC_PI_2 {
+            return BoundingBox {
+                min_latitude: min_latitude.max(-90.0),
+                max_latitude: max_latitude.min(90.0),
+                min_longitude: -180.0,
+                max_longitude: 180.0,
+            };
+        }
+        let spread = (angle.sin() / center.latitude.to_radians().cos())
+            .min(1.0)
+            .asin()
+            .to_degrees();
+        let wrap = |longitude: f64| {
+            if longitude < -180.0 {
+                longitude + 360.0
+            } else if longitude > 180.0 {
+                longitude - 360.0
+            } else {
+                longitude
+            }
+        };
+        BoundingBox {
+            min_latitude,
+            max_latitude,
+            min_longitude: wrap(center.longitude - spread),
+            max_longitude: wrap(center.longitude + spread),
+        }
+    }
+
+    pub fn contains(&self, point: Coordinates) -> bool {
+        let longitude_inside = if self.min_longitude <= self.max_longitude {
+            (self.min_longitude..=self.max_longitude).contains(&point.longitude)
+        } else {
+            point.longitude >= self.min_longitude || point.longitude <= self.max_longitude
+        };
+        (self.min_latitude..=self.max_latitude).contains(&point.latitude) && longitude_inside
+    }
+}
+
+/// The first result of a Nominatim search, whose coordinates are strings
+pub fn parse_nominatim(body: &serde_json::Value) -> Option<Coordinates> {
+    let first = body.as_array()?.first()?;
+    let coordinate = |key: &str| first[key].as_str()?.parse::<f64>().ok();
+    Some(Coordinates {
+        latitude: coordinate("lat")?,
+        longitude: coordinate("lon")?,
+    })
+}
+
+/// Nominatim's `/search`, as run by OpenStreetMap or self-hosted
+pub struct NominatimGeocoder {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+impl NominatimGeocoder {
+    pub fn new(base_url: String) -> Self {
+        NominatimGeocoder {
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(2))
+                // Nominatim's usage policy asks clients to identify themselves
+                .user_agent(concat!("personal-crm/", env!("CARGO_PKG_VERSION")))
+                .build()
+                .expect("Failed to build HTTP client"),
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// The geocoder if GEOCODER_URL is set. Off by default because contact locations go to the
+    /// provider.
+    pub fn from_env() -> Option<Self> {
+        std::env::var("GEOCODER_URL")
+            .ok()
+            .map(NominatimGeocoder::new)
+    }
+}
+
+#[async_trait]
+impl Geocoder for NominatimGeocoder {
+    async fn geocode(&self, location: &str) -> Option<Coordinates> {
+        let url = Url::parse_with_params(
+            &format!("{}/search", self.base_url),
+            [("q", location), ("format", "jsonv2"), ("limit", "1")],
+        );
+        let url = match url {
+            Ok(url) => url,
+            Err(e) => {
+                eprintln!("GEOCODER_URL is not a valid URL: {:?}", e);
+                return None;
+            }
+        };
+        let response = match self.client.get(url).send().await {
+            Ok(response) if response.status().is_success() => response,
+            Ok(response) => {
+                eprintln!("Geocoding returned status {}", response.status());
+                return None;
+            }
+            Err(e) => {
+                eprintln!("Geocoding failed: {:?}", e);
+                return None;
+            }
+        };
+        match response.json::<serde_json::Value>().await {
+            Ok(body) => parse_nominatim(&body),
+            Err(e) => {
+                eprintln!("Geocoding returned an unreadable body: {:?}", e);
+                None
+            }
+        }
+    }
+}
+
+/// The configured geocoder, with places it found cached for a day
+pub struct Geocoding {
+    geocoder: Box<dyn Geocoder>,
+    /// Lowercased location -> coordinates
+    cache: Cache<String, Coordinates>,
+}
+
+impl Geocoding {
+    pub fn new(geocoder: Box<dyn Geocoder>) -> Self {
+        Geocoding {
+            geocoder,
+            cache: Cache::builder()
+                .max_capacity(100_000)
+                .time_to_live(Duration::from_secs(24 * 3600))
+                .build(),
+        }
+    }
+
+    /// Geocoding when `NominatimGeocoder::from_env` configures a geocoder
+    pub fn from_env() -> Option<Self> {
+        NominatimGeocoder::from_env().map(|g| Geocoding::new(Box::new(g)))
+    }
+
+    /// Coordinates for `location`. Places that weren't found are looked up again next time.
+    pub async fn locate(&self, location: &str) -> Option<Coordinates> {
+        let location = location.trim();
+        if location.is_empty() {
+            return None;
+        }
+        let key = location.to_lowercase();
+        if let Some(cached) = self.cache.get(&key).await {
+            return Some(cached);
+        }
+        let found = self.geocoder.geocode(location).await?;
+        self.cache.insert(key, found).await;
+        Some(found)
+    }
+
+    /// Geocode each contact's location and store the coordinates found. A contact whose
+    /// location changed in the meantime keeps none.
+    pub async fn locate_contacts(
+        &self,
+        repo: &dyn Repository,
+        workspace_id: i32,
+        locations: Vec<(i32, String)>,
+    ) -> RepoResult<()> {
+        stream::iter(locations)
+            .map(|(contact_id, location)| async move {
+                let Some(coordinates) = self.locate(&location).await else {
+                    return Ok(());
+                };
+                repo.set_contact_coordinates(workspace_id, contact_id, &location, coordinates)
+                    .await
+                    .map(|_| ())
+            })
+            .buffer_unordered(CONCURRENT_LOOKUPS)
+            .try_collect()
+            .await
+    }
+}
@@ -0,0 +1,238 @@
+//! Gift idea handlers.
+//!
+//! Ideas live on a contact and may name one of the contact's occasions. Ideas not yet given
+//! are listed on that occasion's upcoming reminders, along with the contact's ideas for no
+//! occasion in particular.
+
+use crate::AuthUser;
+use crate::events::{self, Action, Entity, EventBus};
+use crate::models::{NewGiftIdeaRequest, SharePermission};
+use crate::mutations;
+use crate::repository::{RepoResult, Repository};
+use crate::routes::{Resource, ensure_contact_access, ensure_owned};
+use actix_web::{HttpRequest, HttpResponse, Responder, delete, get, patch, post, web};
+
+/// Longest idea text accepted
+pub const MAX_IDEA_LENGTH: usize = 500;
+
+/// Check the idea text and price. Errors are the JSON body of the 400 response.
+fn validate(gift_idea: &NewGiftIdeaRequest) -> Result<(), serde_json::Value> {
+    let idea = gift_idea.idea.trim();
+    if idea.is_empty() || idea.chars().count() > MAX_IDEA_LENGTH {
+        return Err(serde_json::json!({
+            "error": format!("idea must be between 1 and {} characters", MAX_IDEA_LENGTH)
+        }));
+    }
+    if gift_idea.price_cents.is_some_and(|price| price < 0) {
+        return Err(serde_json::json!({ "error": "price_cents must not be negative" }));
+    }
+    Ok(())
+}
+
+/// Whether the idea's occasion, if it names one, is one of `contact_id`'s
+async fn occasion_matches(
+    repo: &dyn Repository,
+    contact_id: i32,
+    gift_idea: &NewGiftIdeaRequest,
+) -> RepoResult<bool> {
+    let Some(occasion_id) = gift_idea.occasion_id else {
+        return Ok(true);
+    };
+    Ok(repo
+        .occasions_for_contacts(&[contact_id])
+        .await?
+        .iter()
+        .any(|o| o.occasion_id == occasion_id))
+}
+
+fn occasion_mismatch() -> HttpResponse {
+    HttpResponse::BadRequest().json(serde_json::json!({
+        "error": "occasion_id must be one of the contact's occasions"
+    }))
+}
+
+/// A contact's gift ideas, oldest first
+#[get("/contacts/{id}/gift-ideas")]
+pub async fn list_gift_ideas(
+    repo: web::Data<dyn Repository>,
+    auth_user: AuthUser,
+    contact_id: web::Path<i32>,
+) -> impl Responder {
+    let id = contact_id.into_inner();
+
+    // The user's own contact or one shared with them
+    if let Err(response) =
+        ensure_contact_access(repo.get_ref(), &auth_user, id, SharePermission::Read).await
+    {
+        return response;
+    }
+
+    match repo.gift_ideas_for_contacts(&[id]).await {
+        Ok(gift_ideas) => HttpResponse::Ok().json(gift_ideas),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to fetch gift ideas")
+        }
+    }
+}
+
+#[post("/gift-ideas")]
+pub async fn create_gift_idea(
+    req: HttpRequest,
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    auth_user: AuthUser,
+    new_gift_idea: web::Json<NewGiftIdeaRequest>,
+) -> impl Responder {
+    if let Err(error) = validate(&new_gift_idea) {
+        return HttpResponse::BadRequest().json(error);
+    }
+
+    // The contact is the user's own or shared with them for writing; the idea goes in the
+    // contact's workspace
+    let access = match ensure_contact_access(
+        repo.get_ref(),
+        &auth_user,
+        new_gift_idea.contact_id,
+        SharePermission::Write,
+    )
+    .await
+    {
+        Ok(access) => access,
+        Err(response) => return response,
+    };
+
+    match occasion_matches(repo.get_ref(), new_gift_idea.contact_id, &new_gift_idea).await {
+        Ok(true) => {}
+        Ok(false) => return occasion_mismatch(),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            return HttpResponse::InternalServerError().body("Failed to create gift idea");
+        }
+    }
+
+    match repo
+        .create_gift_idea(access.workspace_id, &new_gift_idea)
+        .await
+    {
+        Ok(gift_idea_id) => {
+            events::publish(
+                bus.as_ref(),
+                access.owner_id,
+                Entity::GiftIdea,
+                Action::Created,
+                vec![gift_idea_id],
+            );
+            mutations::created(
+                &req,
+                gift_idea_id,
+                repo.get_gift_idea(access.workspace_id, gift_idea_id).await,
+            )
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to create gift idea")
+        }
+    }
+}
+
+/// Replace an idea's text, occasion, status and price. The idea stays on its contact.
+#[patch("/gift-ideas/{id}")]
+pub async fn update_gift_idea(
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    auth_user: AuthUser,
+    gift_idea_id: web::Path<i32>,
+    updated_gift_idea: web::Json<NewGiftIdeaRequest>,
+) -> impl Responder {
+    let id = gift_idea_id.into_inner();
+    if let Err(error) = validate(&updated_gift_idea) {
+        return HttpResponse::BadRequest().json(error);
+    }
+
+    // Verify the idea belongs to the user
+    if let Err(response) = ensure_owned(repo.get_ref(), &auth_user, Resource::GiftIdea, id).await {
+        return response;
+    }
+
+    // The occasion must be one of the contact the idea is on
+    let contact_id = match repo
+        .gift_ideas_for_contacts(&[updated_gift_idea.contact_id])
+        .await
+    {
+        Ok(gift_ideas) => gift_ideas
+            .iter()
+            .find(|g| g.gift_idea_id == id)
+            .map(|g| g.contact_id),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            return HttpResponse::InternalServerError().body("Failed to update gift idea");
+        }
+    };
+    let Some(contact_id) = contact_id else {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "contact_id must be the contact the gift idea is on"
+        }));
+    };
+    match occasion_matches(repo.get_ref(), contact_id, &updated_gift_idea).await {
+        Ok(true) => {}
+        Ok(false) => return occasion_mismatch(),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            return HttpResponse::InternalServerError().body("Failed to update gift idea");
+        }
+    }
+
+    match repo
+        .update_gift_idea(auth_user.workspace_id, id, &updated_gift_idea)
+        .await
+    {
+        Ok(_) => {
+            events::publish(
+                bus.as_ref(),
+                auth_user.user_id,
+                Entity::GiftIdea,
+                Action::Updated,
+                vec![id],
+            );
+            mutations::updated(repo.get_gift_idea(auth_user.workspace_id, id).await)
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to update gift idea")
+        }
+    }
+}
+
+#[delete("/gift-ideas/{id}")]
+pub async fn delete_gift_idea(
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    auth_user: AuthUser,
+    gift_idea_id: web::Path<i32>,
+) -> impl Responder {
+    let id = gift_idea_id.into_inner();
+
+    // Verify the idea belongs to the user
+    if let Err(response) = ensure_owned(repo.get_ref(), &auth_user, Resource::GiftIdea, id).await {
+        return response;
+    }
+
+    match repo.delete_gift_idea(auth_user.workspace_id, id).await {
+        Ok(false) => HttpResponse::NotFound().body("Gift idea not found"),
+        Ok(true) => {
+            events::publish(
+                bus.as_ref(),
+                auth_user.user_id,
+                Entity::GiftIdea,
+                Action::Deleted,
+                vec![id],
+            );
+            HttpResponse::Ok().body("Gift idea deleted successfully")
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to delete gift idea")
+        }
+    }
+}
@@ -0,0 +1,302 @@
+//! Interaction goals and streaks.
+//!
+//! A goal asks for a number of interactions every week (Monday to Sunday) or calendar month,
+//! with anyone or with one contact, optionally of one type. Progress is counted from the
+//! interactions themselves on the user's calendar days, so backdated and deleted interactions
+//! count as they should, and goals added later pick up the streak their history already earned.
+
+use crate::AuthUser;
+use crate::events::{self, Action, Entity, EventBus};
+use crate::interactions::check_interaction_type;
+use crate::models::{Goal, GoalPeriod, GoalProgress, Interaction, NewGoalRequest};
+use crate::mutations;
+use crate::reconnect::week_start;
+use crate::repository::Repository;
+use crate::routes::{Resource, ensure_owned};
+use crate::timezone::LocalDates;
+use actix_web::{HttpRequest, HttpResponse, Responder, delete, get, patch, post, web};
+use std::collections::HashMap;
+use time::{Date, Duration, Time};
+
+/// Longest name the goals table accepts
+const MAX_NAME_LEN: usize = 100;
+
+/// Largest target count per period
+const MAX_TARGET_COUNT: i32 = 100;
+
+/// How far back interactions are read for streaks
+const HISTORY_WEEKS: i64 = 104;
+
+/// First day of the period containing `date`
+pub fn period_start(period: GoalPeriod, date: Date) -> Date {
+    match period {
+        GoalPeriod::Week => week_start(date),
+        GoalPeriod::Month => date.replace_day(1).expect("every month has a first day"),
+    }
+}
+
+/// First day of the period before the one starting on `start`
+fn previous_start(period: GoalPeriod, start: Date) -> Date {
+    period_start(period, start - Duration::days(1))
+}
+
+/// Whether an interaction counts towards the goal
+fn counts_towards(goal: &Goal, interaction: &Interaction) -> bool {
+    goal.contact_id
+        .is_none_or(|contact_id| interaction.participants().any(|c| c == contact_id))
+        && goal
+            .interaction_type
+            .as_ref()
+            .is_none_or(|t| interaction.interaction_type.as_ref() == Some(t))
+}
+
+/// The goal's count for the period containing `today` and its streaks, from the interactions
+/// of the last `HISTORY_WEEKS`
+pub fn progress(
+    goal: &Goal,
+    interactions: &[Interaction],
+    dates: &LocalDates,
+    today: Date,
+) -> GoalProgress {
+    let current = period_start(goal.period, today);
+    let oldest = period_start(goal.period, today - Duration::weeks(HISTORY_WEEKS));
+
+    let mut counts: HashMap<Date, i32> = HashMap::new();
+    for interaction in interactions.iter().filter(|i| counts_towards(goal, i)) {
+        let day = dates.date_of(interaction.interaction_date);
+        if day >= oldest && day <= today {
+            *counts.entry(period_start(goal.period, day)).or_default() += 1;
+        }
+    }
+    let met = |start: &Date| counts.get(start).is_some_and(|n| *n >= goal.target_count);
+
+    // Newest first
+    let mut starts = vec![current];
+    while let Some(&last) = starts.last().filter(|s| **s > oldest) {
+        starts.push(previous_start(goal.period, last));
+    }
+
+    let count = counts.get(&current).copied().unwrap_or_default();
+    let completed = count >= goal.target_count;
+    // A period still under way doesn't break the streak until it is over
+    let streak = starts
+        .iter()
+        .skip(usize::from(!completed))
+        .take_while(|s| met(s))
+        .count();
+    let mut best_streak = 0;
+    let mut run = 0;
+    for start in starts.iter().rev() {
+        if met(start) {
+            run += 1;
+            best_streak = best_streak.max(run);
+        } else if *start != current {
+            run = 0;
+        }
+    }
+
+    GoalProgress {
+        goal: goal.clone(),
+        period_start: current,
+        count,
+        completed,
+        streak: streak as i32,
+        best_streak,
+    }
+}
+
+/// Trim the name and check the target. Errors are the JSON body of the 400 response.
+fn validate(mut goal: NewGoalRequest) -> Result<NewGoalRequest, serde_json::Value> {
+    goal.name = goal.name.trim().to_string();
+    if goal.name.is_empty() || goal.name.chars().count() > MAX_NAME_LEN {
+        return Err(serde_json::json!({
+            "error": format!("Name must be 1 to {} characters", MAX_NAME_LEN)
+        }));
+    }
+    if !(1..=MAX_TARGET_COUNT).contains(&goal.target_count) {
+        return Err(serde_json::json!({
+            "error": format!("target_count must be 1 to {}", MAX_TARGET_COUNT)
+        }));
+    }
+    Ok(goal)
+}
+
+/// Validate a submitted goal and check that its contact and type belong to the request's
+/// workspace
+async fn checked(
+    repo: &dyn Repository,
+    auth_user: &AuthUser,
+    goal: NewGoalRequest,
+) -> Result<NewGoalRequest, HttpResponse> {
+    let goal = validate(goal).map_err(|error| HttpResponse::BadRequest().json(error))?;
+    if let Some(contact_id) = goal.contact_id {
+        ensure_owned(repo, auth_user, Resource::Contact, contact_id).await?;
+    }
+    if let Some(response) = check_interaction_type(
+        repo,
+        auth_user.workspace_id,
+        goal.interaction_type.as_deref(),
+    )
+    .await
+    {
+        return Err(response);
+    }
+    Ok(goal)
+}
+
+#[get("/goals")]
+pub async fn list_goals(repo: web::Data<dyn Repository>, auth_user: AuthUser) -> impl Responder {
+    match repo.list_goals(auth_user.workspace_id).await {
+        Ok(goals) => HttpResponse::Ok().json(goals),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to fetch goals")
+        }
+    }
+}
+
+/// Every goal with its count for the current period and its streaks, in the user's timezone
+#[get("/goals/progress")]
+pub async fn goals_progress(
+    repo: web::Data<dyn Repository>,
+    auth_user: AuthUser,
+) -> impl Responder {
+    let goals = match repo.list_goals(auth_user.workspace_id).await {
+        Ok(goals) => goals,
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            return HttpResponse::InternalServerError().body("Failed to fetch goals");
+        }
+    };
+    let dates = match LocalDates::for_user(repo.get_ref(), auth_user.user_id).await {
+        Ok(dates) => dates,
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            return HttpResponse::InternalServerError().body("Failed to fetch preferences");
+        }
+    };
+    let today = dates.today();
+
+    // A day early, since the user's midnight can fall on the UTC day before
+    let since = (today - Duration::weeks(HISTORY_WEEKS) - Duration::days(1))
+        .with_time(Time::MIDNIGHT)
+        .assume_utc();
+    let interactions = if goals.is_empty() {
+        Vec::new()
+    } else {
+        match repo.interactions_since(auth_user.workspace_id, since).await {
+            Ok(interactions) => interactions,
+            Err(e) => {
+                eprintln!("Database error: {:?}", e);
+                return HttpResponse::InternalServerError().body("Failed to fetch interactions");
+            }
+        }
+    };
+
+    let progress: Vec<GoalProgress> = goals
+        .iter()
+        .map(|goal| progress(goal, &interactions, &dates, today))
+        .collect();
+    HttpResponse::Ok().json(progress)
+}
+
+#[post("/goals")]
+pub async fn create_goal(
+    req: HttpRequest,
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    auth_user: AuthUser,
+    new_goal: web::Json<NewGoalRequest>,
+) -> impl Responder {
+    let goal = match checked(repo.get_ref(), &auth_user, new_goal.into_inner()).await {
+        Ok(goal) => goal,
+        Err(response) => return response,
+    };
+
+    match repo.create_goal(auth_user.workspace_id, &goal).await {
+        Ok(goal_id) => {
+            events::publish(
+                bus.as_ref(),
+                auth_user.user_id,
+                Entity::Goal,
+                Action::Created,
+                vec![goal_id],
+            );
+            mutations::created(
+                &req,
+                goal_id,
+                repo.get_goal(auth_user.workspace_id, goal_id).await,
+            )
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to create goal")
+        }
+    }
+}
+
+#[patch("/goals/{id}")]
+pub async fn update_goal(
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    auth_user: AuthUser,
+    goal_id: web::Path<i32>,
+    updated_goal: web::Json<NewGoalRequest>,
+) -> impl Responder {
+    let id = goal_id.into_inner();
+
+    // Verify the goal belongs to the user
+    if let Err(response) = ensure_owned(repo.get_ref(), &auth_user, Resource::Goal, id).await {
+        return response;
+    }
+    let goal = match checked(repo.get_ref(), &auth_user, updated_goal.into_inner()).await {
+        Ok(goal) => goal,
+        Err(response) => return response,
+    };
+
+    match repo.update_goal(auth_user.workspace_id, id, &goal).await {
+        Ok(false) => HttpResponse::NotFound().body("Goal not found"),
+        Ok(true) => {
+            events::publish(
+                bus.as_ref(),
+                auth_user.user_id,
+                Entity::Goal,
+                Action::Updated,
+                vec![id],
+            );
+            mutations::updated(repo.get_goal(auth_user.workspace_id, id).await)
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to update goal")
+        }
+    }
+}
+
+#[delete("/goals/{id}")]
+pub async fn delete_goal(
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    auth_user: AuthUser,
+    goal_id: web::Path<i32>,
+) -> impl Responder {
+    let id = goal_id.into_inner();
+
+    match repo.delete_goal(auth_user.workspace_id, id).await {
+        Ok(false) => HttpResponse::NotFound().body("Goal not found"),
+        Ok(true) => {
+            events::publish(
+                bus.as_ref(),
+                auth_user.user_id,
+                Entity::Goal,
+                Action::Deleted,
+                vec![id],
+            );
+            HttpResponse::Ok().body("Goal deleted successfully")
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to delete goal")
+        }
+    }
+}
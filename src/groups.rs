@@ -0,0 +1,463 @@
+//! Contact group handlers.
+//!
+//! A group is a roster, such as a book club, rather than a label: its members are an explicit
+//! list kept in the order the user gives, replaced whole with `PUT /groups/{id}/contacts` or
+//! changed one contact at a time. `POST /groups/{id}/interactions` logs one interaction with
+//! every member taking part. `POST /groups/{id}/digest` emails the user where they stand with
+//! each member through the `outbox`; emails need SMTP_URL, see `mail`.
+
+use crate::AuthUser;
+use crate::events::{self, Action, Entity, EventBus};
+use crate::interactions::check_interaction_type;
+use crate::limits::{self, Limits};
+use crate::mail::Email;
+use crate::models::{
+    Contact, ContactSummary, Group, GroupDigest, GroupInteractionRequest, GroupMembersRequest,
+    Interaction, NewGroupRequest, NewInteractionRequest,
+};
+use crate::mutations;
+use crate::repository::{RepoResult, Repository};
+use crate::review::{contact_name, plural};
+use crate::routes::{Owned, OwnedContact, OwnedGroup, OwnedRow};
+use crate::timezone::LocalDates;
+use actix_web::{HttpRequest, HttpResponse, Responder, delete, get, patch, post, put, web};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+pub const MAX_NAME_LEN: usize = 100;
+pub const MAX_DESCRIPTION_LEN: usize = 1000;
+
+/// Trim the name and drop a blank description. Errors are the JSON body of the 400 response.
+fn validate(mut group: NewGroupRequest) -> Result<NewGroupRequest, serde_json::Value> {
+    group.name = group.name.trim().to_string();
+    if group.name.is_empty() || group.name.chars().count() > MAX_NAME_LEN {
+        return Err(serde_json::json!({
+            "error": format!("Name must be 1 to {} characters", MAX_NAME_LEN)
+        }));
+    }
+    group.description = group
+        .description
+        .map(|d| d.trim().to_string())
+        .filter(|d| !d.is_empty());
+    if group
+        .description
+        .as_ref()
+        .is_some_and(|d| d.chars().count() > MAX_DESCRIPTION_LEN)
+    {
+        return Err(serde_json::json!({
+            "error": format!("Description must be at most {} characters", MAX_DESCRIPTION_LEN)
+        }));
+    }
+    Ok(group)
+}
+
+/// The group's members, in its order
+async fn members(
+    repo: &dyn Repository,
+    workspace_id: i32,
+    group: &Group,
+) -> RepoResult<Vec<Contact>> {
+    let mut contacts: HashMap<i32, Contact> = repo
+        .list_contacts(workspace_id)
+        .await?
+        .into_iter()
+        .map(|c| (c.contact_id, c))
+        .collect();
+    Ok(group
+        .contact_ids
+        .iter()
+        .filter_map(|id| contacts.remove(id))
+        .collect())
+}
+
+/// The digest as a plain text email to `to`: each member in the group's order, with when the
+/// user was last in touch and the member's next occasion
+pub fn render_digest(
+    to: &str,
+    group: &Group,
+    members: &[Contact],
+    summaries: &HashMap<i32, ContactSummary>,
+    dates: &LocalDates,
+) -> Email {
+    let today = dates.today();
+    let mut text = format!(
+        "{}, {}:\n",
+        group.name,
+        plural(members.len(), "member", "members")
+    );
+    if let Some(description) = &group.description {
+        let _ = writeln!(text, "{}", description);
+    }
+    text.push('\n');
+    if members.is_empty() {
+        text.push_str("- Nobody yet\n");
+    }
+    for contact in members {
+        let summary = summaries.get(&contact.contact_id);
+        let last = match summary.and_then(|s| s.last_interaction_at) {
+            Some(at) => {
+                let days = (today - dates.date_of(at)).whole_days().max(0);
+                format!("last in touch {} ago", plural(days as usize, "day", "days"))
+            }
+            None => "not in touch yet".to_string(),
+        };
+        let _ = write!(text, "- {}: {}", contact_name(contact), last);
+        if let Some(on) = summary.and_then(|s| s.next_occasion_at) {
+            let _ = write!(text, ", next occasion {}", on);
+        }
+        text.push('\n');
+    }
+
+    Email {
+        to: to.to_string(),
+        subject: format!("Group digest: {}", group.name),
+        text,
+    }
+}
+
+/// The workspace's groups by name, each with its `contact_ids` in order
+#[get("/groups")]
+pub async fn list_groups(repo: web::Data<dyn Repository>, auth_user: AuthUser) -> impl Responder {
+    match repo.list_groups(auth_user.workspace_id).await {
+        Ok(groups) => HttpResponse::Ok().json(groups),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to fetch groups")
+        }
+    }
+}
+
+#[post("/groups")]
+pub async fn create_group(
+    req: HttpRequest,
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    auth_user: AuthUser,
+    new_group: web::Json<NewGroupRequest>,
+) -> impl Responder {
+    let new_group = match validate(new_group.into_inner()) {
+        Ok(group) => group,
+        Err(error) => return HttpResponse::BadRequest().json(error),
+    };
+
+    match repo.create_group(auth_user.workspace_id, &new_group).await {
+        Ok(group_id) => {
+            events::publish(
+                bus.as_ref(),
+                auth_user.user_id,
+                Entity::Group,
+                Action::Created,
+                vec![group_id],
+            );
+            mutations::created(
+                &req,
+                group_id,
+                repo.get_group(auth_user.workspace_id, group_id).await,
+            )
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to create group")
+        }
+    }
+}
+
+#[get("/groups/{id}")]
+pub async fn get_group(Owned(group): OwnedGroup) -> impl Responder {
+    HttpResponse::Ok().json(group)
+}
+
+/// Rename the group or change its description; the members stay as they are
+#[patch("/groups/{id}")]
+pub async fn update_group(
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    auth_user: AuthUser,
+    group_id: web::Path<i32>,
+    updated_group: web::Json<NewGroupRequest>,
+) -> impl Responder {
+    let id = group_id.into_inner();
+    let updated_group = match validate(updated_group.into_inner()) {
+        Ok(group) => group,
+        Err(error) => return HttpResponse::BadRequest().json(error),
+    };
+
+    match repo
+        .update_group(auth_user.workspace_id, id, &updated_group)
+        .await
+    {
+        Ok(false) => HttpResponse::NotFound().body("Group not found"),
+        Ok(true) => {
+            events::publish(
+                bus.as_ref(),
+                auth_user.user_id,
+                Entity::Group,
+                Action::Updated,
+                vec![id],
+            );
+            mutations::updated(repo.get_group(auth_user.workspace_id, id).await)
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to update group")
+        }
+    }
+}
+
+/// Delete the group; its members are kept as contacts
+#[delete("/groups/{id}")]
+pub async fn delete_group(
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    auth_user: AuthUser,
+    group_id: web::Path<i32>,
+) -> impl Responder {
+    let id = group_id.into_inner();
+
+    match repo.delete_group(auth_user.workspace_id, id).await {
+        Ok(false) => HttpResponse::NotFound().body("Group not found"),
+        Ok(true) => {
+            events::publish(
+                bus.as_ref(),
+                auth_user.user_id,
+                Entity::Group,
+                Action::Deleted,
+                vec![id],
+            );
+            HttpResponse::Ok().body("Group deleted successfully")
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to delete group")
+        }
+    }
+}
+
+/// The group's members, in its order
+#[get("/groups/{id}/contacts")]
+pub async fn group_contacts(
+    repo: web::Data<dyn Repository>,
+    auth_user: AuthUser,
+    Owned(group): OwnedGroup,
+) -> impl Responder {
+    match members(repo.get_ref(), auth_user.workspace_id, &group).await {
+        Ok(contacts) => HttpResponse::Ok().json(contacts),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to fetch group contacts")
+        }
+    }
+}
+
+/// Replace the group's members with `contact_ids`, in that order. Repeated ids count once, at
+/// their first place; an id that isn't one of the workspace's contacts changes nothing and
+/// gets 404.
+#[put("/groups/{id}/contacts")]
+pub async fn set_group_contacts(
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    auth_user: AuthUser,
+    limits: Option<web::Data<Limits>>,
+    Owned(group): OwnedGroup,
+    request: web::Json<GroupMembersRequest>,
+) -> impl Responder {
+    if let Some(response) =
+        limits::configured(limits.as_ref()).reject_bulk(request.contact_ids.len())
+    {
+        return response;
+    }
+    let mut seen = HashSet::new();
+    let contact_ids: Vec<i32> = request
+        .contact_ids
+        .iter()
+        .copied()
+        .filter(|id| seen.insert(*id))
+        .collect();
+
+    match repo
+        .set_group_members(auth_user.workspace_id, group.group_id, &contact_ids)
+        .await
+    {
+        Ok(false) => HttpResponse::NotFound().body("Contact not found"),
+        Ok(true) => {
+            events::publish(
+                bus.as_ref(),
+                auth_user.user_id,
+                Entity::Group,
+                Action::Updated,
+                vec![group.group_id],
+            );
+            mutations::updated(repo.get_group(auth_user.workspace_id, group.group_id).await)
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to update group contacts")
+        }
+    }
+}
+
+/// Add a contact after the group's last member. A contact already in the group keeps its place.
+#[post("/groups/{group_id}/contacts/{contact_id}")]
+pub async fn add_group_contact(
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    auth_user: AuthUser,
+    Owned(group): OwnedGroup,
+    Owned(contact): OwnedContact,
+) -> impl Responder {
+    match repo
+        .add_group_member(group.group_id, contact.contact_id)
+        .await
+    {
+        Ok(added) => {
+            if added {
+                events::publish(
+                    bus.as_ref(),
+                    auth_user.user_id,
+                    Entity::Group,
+                    Action::Updated,
+                    vec![group.group_id],
+                );
+            }
+            mutations::updated(repo.get_group(auth_user.workspace_id, group.group_id).await)
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to add contact to group")
+        }
+    }
+}
+
+#[delete("/groups/{group_id}/contacts/{contact_id}")]
+pub async fn remove_group_contact(
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    auth_user: AuthUser,
+    Owned(group): OwnedGroup,
+    Owned(contact): OwnedContact,
+) -> impl Responder {
+    match repo
+        .remove_group_member(group.group_id, contact.contact_id)
+        .await
+    {
+        Ok(false) => HttpResponse::NotFound().body("Contact is not in the group"),
+        Ok(true) => {
+            events::publish(
+                bus.as_ref(),
+                auth_user.user_id,
+                Entity::Group,
+                Action::Updated,
+                vec![group.group_id],
+            );
+            mutations::updated(repo.get_group(auth_user.workspace_id, group.group_id).await)
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to remove contact from group")
+        }
+    }
+}
+
+/// Queue the group's digest for emailing to the user, answering 202 with the email
+#[post("/groups/{id}/digest")]
+pub async fn send_group_digest(
+    repo: web::Data<dyn Repository>,
+    auth_user: AuthUser,
+    Owned(group): OwnedGroup,
+) -> impl Responder {
+    let repo = repo.get_ref();
+    let queued = async {
+        let Some(profile) = repo.get_profile(auth_user.user_id).await? else {
+            return Ok(None);
+        };
+        let dates = LocalDates::for_user(repo, auth_user.user_id).await?;
+        let members = members(repo, auth_user.workspace_id, &group).await?;
+        let summaries: HashMap<i32, ContactSummary> = repo
+            .contact_summaries(auth_user.workspace_id, &group.contact_ids, dates.today())
+            .await?
+            .into_iter()
+            .collect();
+        let email = render_digest(&profile.email, &group, &members, &summaries, &dates);
+        repo.queue_email(auth_user.workspace_id, &email).await?;
+        RepoResult::Ok(Some(email))
+    };
+
+    match queued.await {
+        Ok(Some(email)) => HttpResponse::Accepted().json(GroupDigest {
+            to: email.to,
+            subject: email.subject,
+            text: email.text,
+        }),
+        Ok(None) => HttpResponse::NotFound().body("User not found"),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to queue group digest")
+        }
+    }
+}
+
+/// Log one interaction with the whole group: its first member is the interaction's contact and
+/// the others its participants, all saved together. A group without members gets 400.
+#[post("/groups/{id}/interactions")]
+pub async fn log_group_interaction(
+    req: HttpRequest,
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    auth_user: AuthUser,
+    Owned(group): OwnedGroup,
+    request: web::Json<GroupInteractionRequest>,
+) -> impl Responder {
+    let Some((&contact_id, others)) = group.contact_ids.split_first() else {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "The group has no members"
+        }));
+    };
+    if let Some(response) = check_interaction_type(
+        repo.get_ref(),
+        auth_user.workspace_id,
+        request.interaction_type.as_deref(),
+    )
+    .await
+    {
+        return response;
+    }
+    let request = request.into_inner();
+    let new_interaction = NewInteractionRequest {
+        contact_id,
+        interaction_date: request.interaction_date,
+        notes: request.notes,
+        follow_up_priority: request.follow_up_priority,
+        interaction_type: request.interaction_type,
+        contact_ids: others.to_vec(),
+        follow_up_in_days: None,
+    };
+
+    match repo
+        .create_interaction(auth_user.workspace_id, &new_interaction, None)
+        .await
+    {
+        Ok((interaction_id, _)) => {
+            events::publish(
+                bus.as_ref(),
+                auth_user.user_id,
+                Entity::Interaction,
+                Action::Created,
+                vec![interaction_id],
+            );
+            // The interaction lives at /interactions/{id}, under the same scope as this route
+            let scope = req
+                .path()
+                .rsplit_once("/groups/")
+                .map_or("", |(scope, _)| scope);
+            mutations::created_in(
+                &format!("{}/interactions", scope),
+                interaction_id,
+                Interaction::load(repo.get_ref(), auth_user.workspace_id, interaction_id).await,
+            )
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to create interaction")
+        }
+    }
+}
@@ -0,0 +1,184 @@
+//! Relationship health: a score from 0 to 100 per contact, and whether the relationship is
+//! drifting apart.
+//!
+//! Where `predicted_contact_priority` only looks at how far the latest gap is past the average
+//! one, the score weighs three parts:
+//!
+//! - recency: days since the last interaction against the contact's desired frequency, else
+//!   the average gap, else the default cadence. Full marks while not yet due, none at three
+//!   times that.
+//! - trend: the average of the later half of the gaps between interaction days over the
+//!   average of the earlier half. Full marks while gaps are not growing, none once they are
+//!   three times as long. Needs interactions on three different days.
+//! - quality: what kind of interactions the latest few were, a meeting counting for more than
+//!   a call, and a call for more than a message or an email.
+//!
+//! A part that can't be measured is left out and the others make up the score. Interactions on
+//! the same day count as one for the gaps, so a dinner logged per guest doesn't look like a
+//! burst of contact. `GET /contacts/declining` lists the contacts whose gaps are growing.
+
+use crate::AuthUser;
+use crate::models::{
+    DEFAULT_REMINDER_CADENCE_DAYS, DecliningContact, HealthTrend, Interaction, RelationshipHealth,
+};
+use crate::repository::{RepoResult, Repository};
+use crate::timezone::LocalDates;
+use actix_web::{HttpResponse, Responder, get, web};
+use std::collections::HashMap;
+use time::Date;
+
+const RECENCY_WEIGHT: f64 = 0.5;
+const TREND_WEIGHT: f64 = 0.3;
+const QUALITY_WEIGHT: f64 = 0.2;
+
+/// Latest interactions whose types make up the quality part
+pub const QUALITY_WINDOW: usize = 5;
+
+/// Gap growth above which the trend is declining, and below whose inverse it is improving
+pub const TREND_THRESHOLD: f64 = 1.25;
+
+/// How much an interaction of this type says about the relationship, from 0 to 1
+fn type_quality(interaction_type: Option<&str>) -> f64 {
+    match interaction_type {
+        Some("meeting") => 1.0,
+        Some("call") => 0.8,
+        Some("message") => 0.5,
+        Some("email") => 0.4,
+        // Custom types and untyped interactions
+        _ => 0.6,
+    }
+}
+
+/// 1 up to `full`, falling linearly to 0 at three times `full`
+fn falloff(value: f64, full: f64) -> f64 {
+    ((3.0 - value / full) / 2.0).clamp(0.0, 1.0)
+}
+
+fn mean(values: &[i64]) -> f64 {
+    values.iter().sum::<i64>() as f64 / values.len() as f64
+}
+
+/// How the relationship with a contact is doing, from the interactions they took part in, in
+/// any order. None without any interaction.
+pub fn assess(
+    desired_frequency_days: Option<i32>,
+    interactions: &[Interaction],
+    dates: &LocalDates,
+) -> Option<RelationshipHealth> {
+    let mut by_date: Vec<(Date, &Interaction)> = interactions
+        .iter()
+        .map(|i| (dates.date_of(i.interaction_date), i))
+        .collect();
+    by_date.sort_by_key(|(date, _)| *date);
+    let mut days: Vec<Date> = by_date.iter().map(|(date, _)| *date).collect();
+    days.dedup();
+    let last = *days.last()?;
+    let gaps: Vec<i64> = days
+        .windows(2)
+        .map(|w| (w[1] - w[0]).whole_days())
+        .collect();
+
+    let cadence = match desired_frequency_days {
+        Some(days) => f64::from(days),
+        None if !gaps.is_empty() => mean(&gaps),
+        None => f64::from(DEFAULT_REMINDER_CADENCE_DAYS),
+    };
+    let days_since = (dates.today() - last).whole_days().max(0);
+    let recency = falloff(days_since as f64, cadence.max(1.0));
+
+    // The middle gap of an odd number counts as recent
+    let gap_growth = (gaps.len() >= 2).then(|| {
+        let (earlier, recent) = gaps.split_at(gaps.len() / 2);
+        mean(recent) / mean(earlier).max(1.0)
+    });
+    let trend = gap_growth.map(|growth| {
+        if growth > TREND_THRESHOLD {
+            HealthTrend::Declining
+        } else if growth < 1.0 / TREND_THRESHOLD {
+            HealthTrend::Improving
+        } else {
+            HealthTrend::Steady
+        }
+    });
+
+    let latest = &by_date[by_date.len().saturating_sub(QUALITY_WINDOW)..];
+    let quality = latest
+        .iter()
+        .map(|(_, i)| type_quality(i.interaction_type.as_deref()))
+        .sum::<f64>()
+        / latest.len() as f64;
+
+    let mut total = RECENCY_WEIGHT * recency + QUALITY_WEIGHT * quality;
+    let mut weights = RECENCY_WEIGHT + QUALITY_WEIGHT;
+    if let Some(growth) = gap_growth {
+        total += TREND_WEIGHT * falloff(growth, 1.0);
+        weights += TREND_WEIGHT;
+    }
+
+    Some(RelationshipHealth {
+        score: (100.0 * total / weights).round() as i32,
+        trend,
+        gap_growth,
+    })
+}
+
+/// The unarchived contacts with a health trend of `Declining`, lowest score first
+pub async fn declining(
+    repo: &dyn Repository,
+    workspace_id: i32,
+    dates: &LocalDates,
+) -> RepoResult<Vec<DecliningContact>> {
+    let contacts: Vec<_> = repo
+        .list_contacts(workspace_id)
+        .await?
+        .into_iter()
+        .filter(|c| !c.archived)
+        .collect();
+    let contact_ids: Vec<i32> = contacts.iter().map(|c| c.contact_id).collect();
+    let mut interactions: HashMap<i32, Vec<Interaction>> = HashMap::new();
+    for interaction in repo.interactions_for_contacts(&contact_ids).await? {
+        for contact_id in interaction.participants() {
+            interactions
+                .entry(contact_id)
+                .or_default()
+                .push(interaction.clone());
+        }
+    }
+
+    let mut declining: Vec<DecliningContact> = contacts
+        .into_iter()
+        .filter_map(|contact| {
+            let health = assess(
+                contact.desired_frequency_days,
+                interactions.get(&contact.contact_id)?,
+                dates,
+            )?;
+            (health.trend == Some(HealthTrend::Declining))
+                .then_some(DecliningContact { contact, health })
+        })
+        .collect();
+    declining.sort_by_key(|d| (d.health.score, d.contact.contact_id));
+    Ok(declining)
+}
+
+/// Contacts the user is drifting apart from: the gaps between interactions are growing
+#[get("/contacts/declining")]
+pub async fn declining_contacts(
+    repo: web::Data<dyn Repository>,
+    auth_user: AuthUser,
+) -> impl Responder {
+    let dates = match LocalDates::for_user(repo.get_ref(), auth_user.user_id).await {
+        Ok(dates) => dates,
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            return HttpResponse::InternalServerError().body("Failed to fetch preferences");
+        }
+    };
+    match declining(repo.get_ref(), auth_user.workspace_id, &dates).await {
+        Ok(declining) => HttpResponse::Ok().json(declining),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to fetch declining contacts")
+        }
+    }
+}
@@ -0,0 +1,163 @@
+//! Contact change history.
+//!
+//! Updating or archiving a contact records each field whose value changed, with the value
+//! before and after, so the user can see when someone's phone number or email was edited.
+//! Fields are compared as text, `met_at` as its JSON; the derived `phone_e164` and coordinates
+//! are left out since they follow `phone` and `location`.
+
+use crate::AuthUser;
+use crate::cursor::{Cursor, MAX_PAGE_SIZE, next_page_link};
+use crate::models::{Contact, MetAt, NewContactRequest, SharePermission};
+use crate::repository::Repository;
+use crate::routes::ensure_contact_access;
+use actix_web::http::header::LINK;
+use actix_web::{HttpRequest, HttpResponse, Responder, get, web};
+use serde::Deserialize;
+
+/// A field's value before and after an edit
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldChange {
+    pub field: &'static str,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+fn change(
+    field: &'static str,
+    before: Option<String>,
+    after: Option<String>,
+) -> Option<FieldChange> {
+    (before != after).then_some(FieldChange {
+        field,
+        before,
+        after,
+    })
+}
+
+/// `met_at` as its JSON text
+fn met_at_text(met_at: &MetAt) -> String {
+    serde_json::to_string(met_at).unwrap_or_default()
+}
+
+/// The fields an update of `before` to `after` changes
+pub fn changes(before: &Contact, after: &NewContactRequest) -> Vec<FieldChange> {
+    [
+        change(
+            "first_name",
+            before.first_name.clone(),
+            after.first_name.clone(),
+        ),
+        change(
+            "last_name",
+            before.last_name.clone(),
+            after.last_name.clone(),
+        ),
+        change("email", before.email.clone(), after.email.clone()),
+        change("phone", before.phone.clone(), after.phone.clone()),
+        change(
+            "short_note",
+            before.short_note.clone(),
+            after.short_note.clone(),
+        ),
+        change("notes", before.notes.clone(), after.notes.clone()),
+        change(
+            "avatar_url",
+            before.avatar_url.clone(),
+            after.avatar_url.clone(),
+        ),
+        change(
+            "desired_frequency_days",
+            before.desired_frequency_days.map(|d| d.to_string()),
+            after.desired_frequency_days.map(|d| d.to_string()),
+        ),
+        change(
+            "met_at",
+            before.met_at.as_ref().map(met_at_text),
+            after.met_at.as_ref().map(met_at_text),
+        ),
+        change(
+            "introduced_by_contact_id",
+            before.introduced_by_contact_id.map(|id| id.to_string()),
+            after.introduced_by_contact_id.map(|id| id.to_string()),
+        ),
+        change("location", before.location.clone(), after.location.clone()),
+    ]
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
+/// The change of archiving or unarchiving a contact, None if it already was
+pub fn archived_change(before: bool, after: bool) -> Option<FieldChange> {
+    change(
+        "archived",
+        Some(before.to_string()),
+        Some(after.to_string()),
+    )
+}
+
+#[derive(Deserialize)]
+struct HistoryQuery {
+    /// Page size; the whole history when left out
+    limit: Option<u32>,
+    /// Cursor from the previous page's `Link` header
+    after: Option<String>,
+}
+
+/// A contact's field changes, newest first.
+/// With `?limit=N` a full page links to the next one in a `Link: <...>; rel="next"` header.
+#[get("/contacts/{id}/history")]
+pub async fn contact_history(
+    req: HttpRequest,
+    repo: web::Data<dyn Repository>,
+    auth_user: AuthUser,
+    contact_id: web::Path<i32>,
+    query: web::Query<HistoryQuery>,
+) -> impl Responder {
+    let id = contact_id.into_inner();
+
+    if query
+        .limit
+        .is_some_and(|limit| !(1..=MAX_PAGE_SIZE).contains(&limit))
+    {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("limit must be 1 to {}", MAX_PAGE_SIZE)
+        }));
+    }
+    let after = match query.after.as_deref().map(Cursor::decode) {
+        None => None,
+        Some(Some(cursor)) => Some(cursor),
+        Some(None) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Invalid cursor"
+            }));
+        }
+    };
+
+    // The user's own contact or one shared with them
+    if let Err(response) =
+        ensure_contact_access(repo.get_ref(), &auth_user, id, SharePermission::Read).await
+    {
+        return response;
+    }
+
+    let history = match repo
+        .contact_history(id, after, query.limit.map(i64::from))
+        .await
+    {
+        Ok(history) => history,
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            return HttpResponse::InternalServerError().body("Failed to fetch contact history");
+        }
+    };
+
+    let mut response = HttpResponse::Ok();
+    if let (Some(limit), Some(last)) = (query.limit, history.last())
+        && history.len() == limit as usize
+    {
+        let cursor = Cursor::new(last.changed_at, last.change_id);
+        response.insert_header((LINK, next_page_link(&req, &cursor)));
+    }
+    response.json(history)
+}
@@ -0,0 +1,156 @@
+//! Built-in public holiday calendars for the countries users can pick in their preferences.
+//!
+//! Holidays are listed on their nominal dates; substitute days off when a holiday falls on a
+//! weekend are not modelled. "Major" holidays are the ones worth a greeting to friends and
+//! family, and are what the greeting reminders are suggested for.
+
+use Month::*;
+use Rule::*;
+use Weekday::*;
+use time::{Date, Duration, Month, Weekday};
+
+/// Country codes (ISO 3166-1 alpha-2) with a built-in calendar
+pub const SUPPORTED_COUNTRIES: &[&str] = &["CA", "DE", "GB", "US"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Holiday {
+    pub date: Date,
+    pub name: &'static str,
+    pub major: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Rule {
+    /// The same day every year
+    Fixed(Month, u8),
+    /// The nth weekday of the month
+    Nth(Month, Weekday, u8),
+    /// The last weekday of the month
+    Last(Month, Weekday),
+    /// The last weekday strictly before a day of the month
+    Before(Month, u8, Weekday),
+    /// Days after (or, if negative, before) Easter Sunday
+    Easter(i64),
+}
+
+const US: &[(Rule, &str, bool)] = &[
+    (Fixed(January, 1), "New Year's Day", true),
+    (Nth(January, Monday, 3), "Martin Luther King Jr. Day", false),
+    (Nth(February, Monday, 3), "Presidents' Day", false),
+    (Last(May, Monday), "Memorial Day", false),
+    (Fixed(June, 19), "Juneteenth", false),
+    (Fixed(July, 4), "Independence Day", true),
+    (Nth(September, Monday, 1), "Labor Day", false),
+    (Nth(October, Monday, 2), "Columbus Day", false),
+    (Fixed(November, 11), "Veterans Day", false),
+    (Nth(November, Thursday, 4), "Thanksgiving", true),
+    (Fixed(December, 25), "Christmas Day", true),
+];
+
+const GB: &[(Rule, &str, bool)] = &[
+    (Fixed(January, 1), "New Year's Day", true),
+    (Easter(-2), "Good Friday", false),
+    (Easter(1), "Easter Monday", false),
+    (Nth(May, Monday, 1), "Early May Bank Holiday", false),
+    (Last(May, Monday), "Spring Bank Holiday", false),
+    (Last(August, Monday), "Summer Bank Holiday", false),
+    (Fixed(December, 25), "Christmas Day", true),
+    (Fixed(December, 26), "Boxing Day", false),
+];
+
+const CA: &[(Rule, &str, bool)] = &[
+    (Fixed(January, 1), "New Year's Day", true),
+    (Easter(-2), "Good Friday", false),
+    (Before(May, 25, Monday), "Victoria Day", false),
+    (Fixed(July, 1), "Canada Day", true),
+    (Nth(September, Monday, 1), "Labour Day", false),
+    (Nth(October, Monday, 2), "Thanksgiving", true),
+    (Fixed(November, 11), "Remembrance Day", false),
+    (Fixed(December, 25), "Christmas Day", true),
+    (Fixed(December, 26), "Boxing Day", false),
+];
+
+const DE: &[(Rule, &str, bool)] = &[
+    (Fixed(January, 1), "New Year's Day", true),
+    (Easter(-2), "Good Friday", false),
+    (Easter(1), "Easter Monday", false),
+    (Fixed(May, 1), "Labour Day", false),
+    (Easter(39), "Ascension Day", false),
+    (Easter(50), "Whit Monday", false),
+    (Fixed(October, 3), "German Unity Day", false),
+    (Fixed(December, 25), "Christmas Day", true),
+    (Fixed(December, 26), "Second Day of Christmas", false),
+];
+
+fn rules(country: &str) -> &'static [(Rule, &'static str, bool)] {
+    match country {
+        "US" => US,
+        "GB" => GB,
+        "CA" => CA,
+        "DE" => DE,
+        _ => &[],
+    }
+}
+
+/// Easter Sunday in the Gregorian calendar (anonymous Gregorian algorithm)
+pub fn easter(year: i32) -> Date {
+    let a = year % 19;
+    let b = year / 100;
+    let c = year % 100;
+    let d = b / 4;
+    let e = b % 4;
+    let f = (b + 8) / 25;
+    let g = (b - f + 1) / 3;
+    let h = (19 * a + b - d - g + 15) % 30;
+    let i = c / 4;
+    let k = c % 4;
+    let l = (32 + 2 * e + 2 * i - h - k) % 7;
+    let m = (a + 11 * h + 22 * l) / 451;
+    let month = (h + l - 7 * m + 114) / 31;
+    let day = (h + l - 7 * m + 114) % 31 + 1;
+    Date::from_calendar_date(year, Month::try_from(month as u8).unwrap(), day as u8).unwrap()
+}
+
+/// The latest `weekday` on or before `date`
+fn on_or_before(date: Date, weekday: Weekday) -> Date {
+    let offset =
+        (date.weekday().number_days_from_monday() + 7 - weekday.number_days_from_monday()) % 7;
+    date - Duration::days(offset as i64)
+}
+
+fn resolve(rule: Rule, year: i32) -> Date {
+    let day = |month, day| Date::from_calendar_date(year, month, day).unwrap();
+    match rule {
+        Fixed(month, d) => day(month, d),
+        Nth(month, weekday, n) => {
+            on_or_before(day(month, 7), weekday) + Duration::weeks(n as i64 - 1)
+        }
+        Last(month, weekday) => on_or_before(day(month, month.length(year)), weekday),
+        Before(month, d, weekday) => on_or_before(day(month, d) - Duration::days(1), weekday),
+        Easter(days) => easter(year) + Duration::days(days),
+    }
+}
+
+/// Holidays in `country` from `from` to `to` inclusive, in date order.
+/// Unsupported countries have no holidays.
+pub fn between(country: &str, from: Date, to: Date) -> Vec<Holiday> {
+    let mut holidays: Vec<Holiday> = (from.year()..=to.year())
+        .flat_map(|year| {
+            rules(country)
+                .iter()
+                .map(move |&(rule, name, major)| Holiday {
+                    date: resolve(rule, year),
+                    name,
+                    major,
+                })
+        })
+        .filter(|h| h.date >= from && h.date <= to)
+        .collect();
+    holidays.sort_by_key(|h| h.date);
+    holidays
+}
+
+/// Whether `country` has a built-in calendar
+pub fn is_supported(country: &str) -> bool {
+    SUPPORTED_COUNTRIES.contains(&country)
+}
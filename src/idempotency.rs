@@ -0,0 +1,206 @@
+//! Idempotency keys for the create endpoints.
+//!
+//! A POST to /contacts, /interactions or /occasions carrying an `Idempotency-Key` header claims
+//! the key for the calling user. Its response is kept for 24 hours, and a retry with the same
+//! key gets that response back, Location header included, marked `Idempotent-Replayed: true`,
+//! instead of creating the record again. A retry while the first request is still running gets
+//! 409; reusing a key for a different request (another path, workspace or body) gets 422.
+//! Requests that fail with a 5xx, or whose response can't be read to be kept, free the key so
+//! they can be retried.
+
+use crate::AuthUser;
+use crate::limits::{self, Limits};
+use crate::models::StoredResponse;
+use crate::repository::Repository;
+use actix_web::body::{BoxBody, MessageBody, to_bytes};
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::error::ErrorInternalServerError;
+use actix_web::http::header::{CONTENT_TYPE, HeaderMap, HeaderName, HeaderValue, LOCATION};
+use actix_web::http::{Method, StatusCode};
+use actix_web::middleware::Next;
+use actix_web::web::{Bytes, BytesMut};
+use actix_web::{Error, HttpMessage, HttpResponse, web};
+use futures::StreamExt;
+use sha2::{Digest, Sha256};
+
+pub const IDEMPOTENCY_KEY: HeaderName = HeaderName::from_static("idempotency-key");
+
+/// Set on responses replayed from an earlier request
+pub const IDEMPOTENT_REPLAYED: HeaderName = HeaderName::from_static("idempotent-replayed");
+
+/// How long a key and its response are kept
+pub const IDEMPOTENCY_KEY_TTL_HOURS: i32 = 24;
+
+/// Longest key accepted, matching the column
+const MAX_KEY_LENGTH: usize = 255;
+
+/// Create endpoints that honour the header, after `api_version` has added the version prefix
+const IDEMPOTENT_PATHS: &[&str] = &["/v1/contacts", "/v1/interactions", "/v1/occasions"];
+
+fn error(status: StatusCode, message: &str) -> HttpResponse {
+    HttpResponse::build(status).json(serde_json::json!({ "error": message }))
+}
+
+/// Hash of everything that makes a retry the same request
+fn fingerprint(req: &ServiceRequest, workspace_id: i32, body: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(format!(
+        "{} {}?{}\n{}\n",
+        req.method(),
+        req.path(),
+        req.query_string(),
+        workspace_id
+    ));
+    hasher.update(body);
+    format!("{:x}", hasher.finalize())
+}
+
+/// Read the whole body, giving up past `limit` bytes
+async fn read_body(req: &mut ServiceRequest, limit: usize) -> Result<Option<Bytes>, Error> {
+    let mut payload = req.take_payload();
+    let mut body = BytesMut::new();
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk?;
+        if body.len() + chunk.len() > limit {
+            return Ok(None);
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(Some(body.freeze()))
+}
+
+fn header(headers: &HeaderMap, name: HeaderName) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+}
+
+fn replay(stored: StoredResponse) -> HttpResponse {
+    let status = StatusCode::from_u16(stored.status).unwrap_or(StatusCode::OK);
+    let mut response = HttpResponse::build(status);
+    if let Some(content_type) = stored.content_type {
+        response.insert_header((CONTENT_TYPE, content_type));
+    }
+    if let Some(location) = stored.location {
+        response.insert_header((LOCATION, location));
+    }
+    response.insert_header((IDEMPOTENT_REPLAYED, HeaderValue::from_static("true")));
+    response.body(stored.body)
+}
+
+/// Middleware replaying the stored response to a create request retried with the same
+/// Idempotency-Key. Requires a `web::Data<dyn Repository>` in app data; requests without the
+/// header, or that fail authentication, pass through untouched.
+pub async fn idempotency(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let repo = req.app_data::<web::Data<dyn Repository>>().cloned();
+    let key = req.headers().get(IDEMPOTENCY_KEY).cloned();
+    let (repo, key) = match (repo, key) {
+        (Some(repo), Some(key))
+            if req.method() == Method::POST && IDEMPOTENT_PATHS.contains(&req.path()) =>
+        {
+            (repo, key)
+        }
+        _ => return next.call(req).await.map(|res| res.map_into_boxed_body()),
+    };
+
+    let key = match key.to_str() {
+        Ok(key) if !key.is_empty() && key.len() <= MAX_KEY_LENGTH => key.to_string(),
+        _ => {
+            let response = error(
+                StatusCode::BAD_REQUEST,
+                "Idempotency-Key must be 1 to 255 visible ASCII characters",
+            );
+            return Ok(req.into_response(response));
+        }
+    };
+    // The handler reports authentication failures itself
+    let auth_user = match req.extract::<AuthUser>().await {
+        Ok(auth_user) => auth_user,
+        Err(_) => return next.call(req).await.map(|res| res.map_into_boxed_body()),
+    };
+
+    let limit = limits::configured(req.app_data::<web::Data<Limits>>()).max_json_payload_bytes;
+    let body = match read_body(&mut req, limit).await? {
+        Some(body) => body,
+        None => return Ok(req.into_response(limits::payload_too_large(limit))),
+    };
+    let fingerprint = fingerprint(&req, auth_user.workspace_id, &body);
+    req.set_payload(Payload::from(body));
+
+    let held = match repo
+        .claim_idempotency_key(
+            auth_user.user_id,
+            &key,
+            &fingerprint,
+            IDEMPOTENCY_KEY_TTL_HOURS,
+        )
+        .await
+    {
+        Ok(held) => held,
+        Err(e) => {
+            eprintln!("Database error claiming idempotency key: {:?}", e);
+            let response = error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to check the Idempotency-Key",
+            );
+            return Ok(req.into_response(response));
+        }
+    };
+    if let Some(held) = held {
+        let response = if held.fingerprint != fingerprint {
+            error(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "Idempotency-Key was already used for a different request",
+            )
+        } else if let Some(stored) = held.response {
+            replay(stored)
+        } else {
+            error(
+                StatusCode::CONFLICT,
+                "A request with this Idempotency-Key is still in progress",
+            )
+        };
+        return Ok(req.into_response(response));
+    }
+
+    let release = async || {
+        if let Err(e) = repo.release_idempotency_key(auth_user.user_id, &key).await {
+            eprintln!("Database error releasing idempotency key: {:?}", e);
+        }
+    };
+    let res = next.call(req).await;
+    let res = match res {
+        Ok(res) if !res.status().is_server_error() => res,
+        res => {
+            release().await;
+            return res.map(|res| res.map_into_boxed_body());
+        }
+    };
+
+    let (req, res) = res.into_parts();
+    let (res, body) = res.into_parts();
+    let body = match to_bytes(body).await {
+        Ok(body) => body,
+        Err(e) => {
+            release().await;
+            return Err(ErrorInternalServerError(e.into()));
+        }
+    };
+    let stored = StoredResponse {
+        status: res.status().as_u16(),
+        content_type: header(res.headers(), CONTENT_TYPE),
+        location: header(res.headers(), LOCATION),
+        body: body.to_vec(),
+    };
+    if let Err(e) = repo
+        .complete_idempotency_key(auth_user.user_id, &key, &stored)
+        .await
+    {
+        eprintln!("Database error storing idempotent response: {:?}", e);
+    }
+    Ok(ServiceResponse::new(req, res.set_body(BoxBody::new(body))))
+}
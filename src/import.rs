@@ -0,0 +1,515 @@
+//! Import contacts from arbitrary JSON using a small jq-like mapping.
+//!
+//! A mapping names the array of records and, for each contact field, an expression that is
+//! evaluated against every record:
+//!
+//! - `.`, `.name`, `.name.first`, `.emails[0]`, `.["Full Name"]` select values
+//! - `"text"` is a literal string
+//! - `a + b` joins strings (nulls count as empty)
+//! - `a // b` uses `b` when `a` is null, false or missing
+//! - `expr | trim`, `| ascii_downcase`, `| ascii_upcase` post-process the result
+//!
+//! Numbers and booleans are converted to strings; arrays and objects are rejected.
+
+use crate::AuthUser;
+use crate::contacts;
+use crate::dedupe::{self, DedupePolicy, Plan, RowReport};
+use crate::events::EventBus;
+use crate::limits::{self, Limits};
+use crate::models::NewContactRequest;
+use crate::quotas::{self, Quotas};
+use crate::repository::Repository;
+use actix_web::{HttpResponse, Responder, post, web};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// Contact fields a mapping can fill
+pub const FIELDS: &[&str] = &[
+    "first_name",
+    "last_name",
+    "email",
+    "phone",
+    "short_note",
+    "notes",
+    "avatar_url",
+];
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct ImportMapping {
+    /// Path to the array of records; `.` when the document itself is the array
+    #[serde(default = "root_path")]
+    pub records: String,
+    /// Contact field name to the expression that produces it
+    pub fields: BTreeMap<String, String>,
+}
+
+fn root_path() -> String {
+    ".".to_string()
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct JsonImportRequest {
+    pub mapping: ImportMapping,
+    /// The document to import from, in any shape
+    pub data: Value,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    Key(String),
+    Index(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Term {
+    Path(Vec<Step>),
+    Literal(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Filter {
+    Trim,
+    Lowercase,
+    Uppercase,
+}
+
+/// A parsed expression: alternatives of concatenations, then filters
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    alternatives: Vec<Vec<Term>>,
+    filters: Vec<Filter>,
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.input[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let trimmed = self.rest().trim_start();
+        self.pos = self.input.len() - trimmed.len();
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        if self.rest().starts_with(token) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn error(&self, expected: &str) -> String {
+        format!("expected {} at position {}", expected, self.pos)
+    }
+
+    /// A JSON string literal starting at the current position
+    fn string(&mut self) -> Result<String, String> {
+        let mut stream = serde_json::Deserializer::from_str(self.rest()).into_iter::<String>();
+        match stream.next() {
+            Some(Ok(s)) => {
+                self.pos += stream.byte_offset();
+                Ok(s)
+            }
+            _ => Err(self.error("a string")),
+        }
+    }
+
+    /// An identifier-like key, if one starts here
+    fn key(&mut self) -> Option<String> {
+        let key: String = self
+            .rest()
+            .chars()
+            .take_while(|c| c.is_alphanumeric() || *c == '_' || *c == '-')
+            .collect();
+        self.pos += key.len();
+        (!key.is_empty()).then_some(key)
+    }
+
+    /// A path starting at the current `.`
+    fn path(&mut self) -> Result<Vec<Step>, String> {
+        self.pos += 1;
+        let mut steps: Vec<Step> = self.key().map(Step::Key).into_iter().collect();
+        loop {
+            if self.rest().starts_with('[') || self.rest().starts_with(".[") {
+                self.pos += if self.rest().starts_with('.') { 2 } else { 1 };
+                if self.rest().starts_with('"') {
+                    steps.push(Step::Key(self.string()?));
+                } else {
+                    let digits: String = self
+                        .rest()
+                        .chars()
+                        .take_while(|c| c.is_ascii_digit())
+                        .collect();
+                    let index = digits.parse().map_err(|_| self.error("an index"))?;
+                    self.pos += digits.len();
+                    steps.push(Step::Index(index));
+                }
+                if !self.rest().starts_with(']') {
+                    return Err(self.error("`]`"));
+                }
+                self.pos += 1;
+            } else if self.rest().starts_with('.') {
+                self.pos += 1;
+                let key = self.key().ok_or_else(|| self.error("a key"))?;
+                steps.push(Step::Key(key));
+            } else {
+                return Ok(steps);
+            }
+        }
+    }
+
+    /// A whole input that is nothing but a path
+    fn only_path(&mut self) -> Result<Vec<Step>, String> {
+        self.skip_whitespace();
+        if !self.rest().starts_with('.') {
+            return Err(self.error("a path"));
+        }
+        let steps = self.path()?;
+        self.skip_whitespace();
+        if !self.rest().is_empty() {
+            return Err(self.error("end of path"));
+        }
+        Ok(steps)
+    }
+
+    fn term(&mut self) -> Result<Term, String> {
+        self.skip_whitespace();
+        if self.rest().starts_with('"') {
+            Ok(Term::Literal(self.string()?))
+        } else if self.rest().starts_with('.') {
+            Ok(Term::Path(self.path()?))
+        } else {
+            Err(self.error("a path or a string"))
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, String> {
+        let mut alternatives = Vec::new();
+        loop {
+            let mut sum = vec![self.term()?];
+            while self.eat("+") {
+                sum.push(self.term()?);
+            }
+            alternatives.push(sum);
+            if !self.eat("//") {
+                break;
+            }
+        }
+
+        let mut filters = Vec::new();
+        while self.eat("|") {
+            self.skip_whitespace();
+            let name: String = self
+                .rest()
+                .chars()
+                .take_while(|c| c.is_ascii_alphanumeric() || *c == '_')
+                .collect();
+            self.pos += name.len();
+            filters.push(match name.as_str() {
+                "trim" => Filter::Trim,
+                "ascii_downcase" => Filter::Lowercase,
+                "ascii_upcase" => Filter::Uppercase,
+                _ => return Err(format!("unknown filter `{}`", name)),
+            });
+        }
+
+        self.skip_whitespace();
+        if !self.rest().is_empty() {
+            return Err(self.error("end of expression"));
+        }
+        Ok(Expr {
+            alternatives,
+            filters,
+        })
+    }
+}
+
+impl Expr {
+    pub fn parse(input: &str) -> Result<Expr, String> {
+        Parser { input, pos: 0 }.expr()
+    }
+
+    /// Evaluate against one record. Ok(None) means the field has no value.
+    pub fn eval(&self, record: &Value) -> Result<Option<String>, String> {
+        let mut result = None;
+        for sum in &self.alternatives {
+            let mut joined: Option<String> = None;
+            for term in sum {
+                let part = match term {
+                    Term::Literal(s) => Some(s.clone()),
+                    Term::Path(steps) => scalar(select(record, steps))?,
+                };
+                if let Some(part) = part {
+                    joined.get_or_insert_with(String::new).push_str(&part);
+                }
+            }
+            if joined.is_some() {
+                result = joined;
+                break;
+            }
+        }
+
+        Ok(result.map(|mut value| {
+            for filter in &self.filters {
+                value = match filter {
+                    Filter::Trim => value.trim().to_string(),
+                    Filter::Lowercase => value.to_ascii_lowercase(),
+                    Filter::Uppercase => value.to_ascii_uppercase(),
+                };
+            }
+            value
+        }))
+    }
+}
+
+fn select<'v>(value: &'v Value, steps: &[Step]) -> Option<&'v Value> {
+    steps.iter().try_fold(value, |value, step| match step {
+        Step::Key(key) => value.get(key),
+        Step::Index(index) => value.get(index),
+    })
+}
+
+/// Strings as they are, other scalars as their JSON text; null and false mean "no value"
+fn scalar(value: Option<&Value>) -> Result<Option<String>, String> {
+    match value {
+        None | Some(Value::Null) | Some(Value::Bool(false)) => Ok(None),
+        Some(Value::String(s)) => Ok(Some(s.clone())),
+        Some(value @ (Value::Number(_) | Value::Bool(true))) => Ok(Some(value.to_string())),
+        Some(_) => Err("expected a string, number or boolean".to_string()),
+    }
+}
+
+/// A mapping with its expressions parsed
+pub struct CompiledMapping {
+    records: Vec<Step>,
+    fields: Vec<(String, Expr)>,
+}
+
+impl CompiledMapping {
+    /// Errors name the offending field
+    pub fn compile(mapping: &ImportMapping) -> Result<CompiledMapping, String> {
+        let records = Parser {
+            input: &mapping.records,
+            pos: 0,
+        }
+        .only_path()
+        .map_err(|e| format!("records: {}", e))?;
+
+        if mapping.fields.is_empty() {
+            return Err("fields: map at least one contact field".to_string());
+        }
+        let fields = mapping
+            .fields
+            .iter()
+            .map(|(field, expression)| {
+                if !FIELDS.contains(&field.as_str()) {
+                    return Err(format!(
+                        "{}: unknown field, expected one of {}",
+                        field,
+                        FIELDS.join(", ")
+                    ));
+                }
+                Expr::parse(expression)
+                    .map(|expr| (field.clone(), expr))
+                    .map_err(|e| format!("{}: {}", field, e))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(CompiledMapping { records, fields })
+    }
+
+    /// Map every record to a contact, or to the reason it could not be mapped
+    pub fn apply(&self, data: &Value) -> Result<Vec<Result<NewContactRequest, String>>, String> {
+        let records = select(data, &self.records)
+            .and_then(Value::as_array)
+            .ok_or("records: does not point at an array")?;
+        Ok(records.iter().map(|record| self.contact(record)).collect())
+    }
+
+    fn contact(&self, record: &Value) -> Result<NewContactRequest, String> {
+        let mut contact = NewContactRequest::default();
+        for (field, expr) in &self.fields {
+            let value = expr
+                .eval(record)
+                .map_err(|e| format!("{}: {}", field, e))?
+                .filter(|v| !v.is_empty());
+            let slot = match field.as_str() {
+                "first_name" => &mut contact.first_name,
+                "last_name" => &mut contact.last_name,
+                "email" => &mut contact.email,
+                "phone" => &mut contact.phone,
+                "short_note" => &mut contact.short_note,
+                "notes" => &mut contact.notes,
+                _ => &mut contact.avatar_url,
+            };
+            *slot = value;
+        }
+        if contact.first_name.is_none() && contact.last_name.is_none() && contact.email.is_none() {
+            return Err("record has no name or email".to_string());
+        }
+        Ok(contact)
+    }
+}
+
+#[derive(Deserialize)]
+struct ImportQuery {
+    /// Roll back the whole import if any contact fails to save
+    #[serde(default)]
+    atomic: bool,
+    /// Return the mapped contacts and what saving them would do, without saving them
+    #[serde(default)]
+    dry_run: bool,
+    /// What to do with records that match existing contacts
+    #[serde(default)]
+    dedupe: DedupePolicy,
+}
+
+/// Import contacts from any JSON document. Records that fail to map are reported by index
+/// and skipped; `?dedupe=` decides what happens to records that match existing contacts, as
+/// for `/contacts/bulk`. `?dry_run=true` shows the result of the mapping, how many contacts
+/// would be created and which emails are already taken, without saving anything. Like
+/// `/contacts/bulk`, it takes at most MAX_BULK_ITEMS records.
+#[allow(clippy::too_many_arguments)]
+#[post("/contacts/import/json")]
+pub async fn import_json(
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    auth_user: AuthUser,
+    limits: Option<web::Data<Limits>>,
+    quotas: Option<web::Data<Quotas>>,
+    query: web::Query<ImportQuery>,
+    request: web::Json<JsonImportRequest>,
+) -> impl Responder {
+    let mapped = match CompiledMapping::compile(&request.mapping)
+        .and_then(|mapping| mapping.apply(&request.data))
+    {
+        Ok(mapped) => mapped,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Invalid mapping",
+                "details": e
+            }));
+        }
+    };
+
+    let mut records = Vec::new();
+    let mut errors = Vec::new();
+    for (index, result) in mapped.into_iter().enumerate() {
+        match result {
+            Ok(contact) => records.push((index, contact)),
+            Err(e) => errors.push(serde_json::json!({ "index": index, "error": e })),
+        }
+    }
+    if let Some(response) = limits::configured(limits.as_ref()).reject_bulk(records.len()) {
+        return response;
+    }
+
+    let existing =
+        match dedupe::existing_contacts(repo.get_ref(), auth_user.workspace_id, query.dedupe).await
+        {
+            Ok(existing) => existing,
+            Err(e) => {
+                eprintln!("Database error: {:?}", e);
+                return HttpResponse::InternalServerError().body("Failed to import contacts");
+            }
+        };
+    let plan = Plan::new(query.dedupe, &existing, records);
+    let unmapped: Vec<usize> = errors
+        .iter()
+        .filter_map(|e| e["index"].as_u64().map(|index| index as usize))
+        .collect();
+
+    if let Some(response) = quotas::configured(quotas.as_ref())
+        .reject_contacts(repo.get_ref(), &auth_user, plan.contacts.len())
+        .await
+    {
+        return response;
+    }
+
+    if query.dry_run {
+        return match contacts::preview_create(
+            repo.get_ref(),
+            auth_user.workspace_id,
+            &plan,
+            query.atomic,
+            errors,
+        )
+        .await
+        {
+            Ok(mut preview) => {
+                preview["contacts"] = serde_json::json!(plan.contacts);
+                preview["rows"] = serde_json::json!(with_failed(plan.report(None, &[]), &unmapped));
+                HttpResponse::Ok().json(preview)
+            }
+            Err(e) => {
+                eprintln!("Database error: {:?}", e);
+                HttpResponse::InternalServerError().body("Failed to check contacts")
+            }
+        };
+    }
+
+    let results = match repo
+        .create_contacts(auth_user.workspace_id, &plan.contacts, query.atomic)
+        .await
+    {
+        Ok(results) => results,
+        Err(e) => {
+            eprintln!("Database error importing contacts: {:?}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to import contacts",
+                "details": format!("{:?}", e)
+            }));
+        }
+    };
+
+    let mut created_ids = Vec::new();
+    let mut saved = Vec::new();
+    for (index, result) in plan.indexes.iter().zip(results) {
+        match result {
+            Ok(contact_id) => {
+                created_ids.push(contact_id);
+                saved.push(Some(contact_id));
+            }
+            Err(e) => {
+                eprintln!("Database error importing record {}: {:?}", index, e);
+                saved.push(None);
+                errors.push(serde_json::json!({
+                    "index": index,
+                    "error": format!("{:?}", e)
+                }));
+            }
+        }
+    }
+    let (updated_ids, failed_ids) = dedupe::save_updates(
+        repo.get_ref(),
+        auth_user.workspace_id,
+        &plan.updates,
+        &mut errors,
+    )
+    .await;
+    errors.sort_by_key(|e| e["index"].as_u64());
+    contacts::publish_saved(&bus, &auth_user, &created_ids, &updated_ids);
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "created_contact_ids": created_ids,
+        "updated_contact_ids": updated_ids,
+        "rows": with_failed(plan.report(Some(&saved), &failed_ids), &unmapped),
+        "errors": errors,
+        "message": format!("Imported {} contacts", created_ids.len())
+    }))
+}
+
+/// `rows` with the records that could not be mapped added as failed, by index
+fn with_failed(mut rows: Vec<RowReport>, unmapped: &[usize]) -> Vec<RowReport> {
+    rows.extend(unmapped.iter().map(|index| RowReport::failed(*index)));
+    rows.sort_by_key(|row| row.index);
+    rows
+}
@@ -0,0 +1,430 @@
+//! Logging emails as interactions by BCC.
+//!
+//! Each workspace can have a secret address, `<token>@INBOUND_EMAIL_DOMAIN`. The domain's mail is
+//! handed to `POST /webhooks/email?key=INBOUND_EMAIL_SECRET` by the mail provider (SendGrid
+//! Inbound Parse or a Mailgun route, posting form fields). An email sent to, copied to or BCC'd
+//! to the address is logged as an `email` interaction with every contact of the workspace among
+//! its sender and recipients, its subject and the start of its text as the notes.
+//!
+//! Once authenticated, the webhook answers 200 even for mail it cannot place, so providers
+//! don't retry it.
+
+use crate::AuthUser;
+use crate::events::{self, Action, Entity, EventBus};
+use crate::limits::{self, Limits};
+use crate::models::{InboundEmailAddress, NewInteractionRequest};
+use crate::repository::Repository;
+use actix_multipart::Multipart;
+use actix_web::web::BytesMut;
+use actix_web::{HttpMessage, HttpRequest, HttpResponse, Responder, delete, get, post, put, web};
+use futures::StreamExt;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use time::OffsetDateTime;
+
+/// Hex digits in an inbound email token
+pub const TOKEN_LENGTH: usize = 32;
+
+/// Characters of the email's text kept in the interaction's notes
+pub const MAX_SNIPPET_CHARS: usize = 500;
+
+pub struct InboundEmail {
+    /// Key the mail provider must send; the webhook is disabled without one
+    secret: Option<String>,
+    domain: Option<String>,
+}
+
+impl InboundEmail {
+    pub fn new(secret: Option<String>, domain: Option<String>) -> Self {
+        InboundEmail { secret, domain }
+    }
+
+    /// Read INBOUND_EMAIL_SECRET and INBOUND_EMAIL_DOMAIN
+    pub fn from_env() -> Self {
+        InboundEmail::new(
+            std::env::var("INBOUND_EMAIL_SECRET").ok(),
+            std::env::var("INBOUND_EMAIL_DOMAIN").ok(),
+        )
+    }
+
+    pub fn address(&self, token: &str) -> InboundEmailAddress {
+        InboundEmailAddress {
+            token: token.to_string(),
+            address: self
+                .domain
+                .as_ref()
+                .map(|domain| format!("{}@{}", token, domain)),
+        }
+    }
+
+    /// Compare digests so the check takes the same time however much of the key matches
+    fn accepts(&self, key: Option<&str>) -> bool {
+        match (&self.secret, key) {
+            (Some(expected), Some(key)) => {
+                Sha256::digest(expected.as_bytes()) == Sha256::digest(key.as_bytes())
+            }
+            _ => false,
+        }
+    }
+}
+
+/// A new random token, hex encoded
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; TOKEN_LENGTH / 2];
+    getrandom::fill(&mut bytes).expect("Failed to read random bytes");
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Lowercased addresses in a header such as `"Doe, Jane" <jane@example.com>, bob@example.com`
+pub fn parse_addresses(list: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut bracketed = false;
+    for c in list.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            '<' if !quoted => bracketed = true,
+            '>' if !quoted => bracketed = false,
+            ',' if !quoted && !bracketed => {
+                parts.push(std::mem::take(&mut current));
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+    }
+    parts.push(current);
+
+    parts
+        .iter()
+        .filter_map(|part| {
+            let address = match (part.rfind('<'), part.rfind('>')) {
+                (Some(start), Some(end)) if start < end => &part[start + 1..end],
+                _ => part.as_str(),
+            };
+            let address = address.trim().to_lowercase();
+            address.contains('@').then_some(address)
+        })
+        .collect()
+}
+
+/// The start of an email's text on one line, at most `MAX_SNIPPET_CHARS` long
+pub fn snippet(text: &str) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    match text.char_indices().nth(MAX_SNIPPET_CHARS) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text,
+    }
+}
+
+/// SendGrid's `envelope` field
+#[derive(Deserialize)]
+struct Envelope {
+    from: Option<String>,
+    #[serde(default)]
+    to: Vec<String>,
+}
+
+/// What the CRM logs of an inbound email
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InboundMessage {
+    pub sender: String,
+    /// Envelope recipients first, then To and Cc, without duplicates
+    pub recipients: Vec<String>,
+    pub subject: Option<String>,
+    pub text: Option<String>,
+}
+
+impl InboundMessage {
+    /// Read the form fields posted by SendGrid Inbound Parse or a Mailgun route, names
+    /// lowercased. None without a sender.
+    pub fn from_fields(fields: &HashMap<String, String>) -> Option<InboundMessage> {
+        let field = |name: &str| fields.get(name).map(|v| v.trim()).filter(|v| !v.is_empty());
+        let envelope = field("envelope").and_then(|e| serde_json::from_str::<Envelope>(e).ok());
+
+        let sender = envelope
+            .as_ref()
+            .and_then(|e| e.from.as_deref())
+            .into_iter()
+            .chain(field("sender"))
+            .chain(field("from"))
+            .flat_map(parse_addresses)
+            .next()?;
+
+        let mut recipients: Vec<String> = Vec::new();
+        let lists = envelope
+            .iter()
+            .flat_map(|e| e.to.iter().map(String::as_str))
+            .chain(field("recipient"))
+            .chain(field("to"))
+            .chain(field("cc"));
+        for address in lists.flat_map(parse_addresses) {
+            if !recipients.contains(&address) {
+                recipients.push(address);
+            }
+        }
+
+        Some(InboundMessage {
+            sender,
+            recipients,
+            subject: field("subject").map(str::to_string),
+            text: field("stripped-text")
+                .or(field("body-plain"))
+                .or(field("text"))
+                .map(str::to_string),
+        })
+    }
+
+    /// Subject, then the start of the text
+    pub fn notes(&self) -> Option<String> {
+        let parts: Vec<String> = self
+            .subject
+            .iter()
+            .cloned()
+            .chain(self.text.as_deref().map(snippet))
+            .collect();
+        (!parts.is_empty()).then(|| parts.join("\n\n"))
+    }
+}
+
+/// Text fields of a form post, names lowercased; uploaded files are skipped
+async fn read_fields(
+    req: &HttpRequest,
+    mut payload: web::Payload,
+    limit: usize,
+) -> Result<HashMap<String, String>, HttpResponse> {
+    let mut fields = HashMap::new();
+    match req.content_type() {
+        "multipart/form-data" => {
+            let mut multipart = Multipart::new(req.headers(), payload);
+            let mut remaining = limit;
+            while let Some(field) = multipart.next().await {
+                let mut field =
+                    field.map_err(|e| HttpResponse::BadRequest().body(e.to_string()))?;
+                let is_file = field
+                    .content_disposition()
+                    .is_some_and(|d| d.get_filename().is_some());
+                let name = field.name().map(str::to_lowercase);
+                let (Some(name), false) = (name, is_file) else {
+                    while let Some(chunk) = field.next().await {
+                        chunk.map_err(|e| HttpResponse::BadRequest().body(e.to_string()))?;
+                    }
+                    continue;
+                };
+                let value = field
+                    .bytes(remaining)
+                    .await
+                    .map_err(|_| limits::payload_too_large(limit))?
+                    .map_err(|e| HttpResponse::BadRequest().body(e.to_string()))?;
+                remaining -= value.len();
+                fields.insert(name, String::from_utf8_lossy(&value).into_owned());
+            }
+        }
+        "application/x-www-form-urlencoded" => {
+            let mut body = BytesMut::new();
+            while let Some(chunk) = payload.next().await {
+                let chunk = chunk.map_err(|e| HttpResponse::BadRequest().body(e.to_string()))?;
+                if body.len() + chunk.len() > limit {
+                    return Err(limits::payload_too_large(limit));
+                }
+                body.extend_from_slice(&chunk);
+            }
+            let form =
+                web::Query::<HashMap<String, String>>::from_query(&String::from_utf8_lossy(&body))
+                    .map_err(|e| HttpResponse::BadRequest().body(e.to_string()))?;
+            for (name, value) in form.into_inner() {
+                fields.insert(name.to_lowercase(), value);
+            }
+        }
+        _ => {
+            return Err(HttpResponse::UnsupportedMediaType()
+                .body("Expected multipart/form-data or application/x-www-form-urlencoded"));
+        }
+    }
+    Ok(fields)
+}
+
+/// Answer for mail that is accepted but not logged
+fn ignored(reason: &str) -> HttpResponse {
+    HttpResponse::Ok().json(serde_json::json!({
+        "interaction_id": null,
+        "message": reason
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WebhookQuery {
+    pub key: Option<String>,
+}
+
+/// Inbound mail from the provider, authenticated by `?key=`. Disabled (404) unless
+/// INBOUND_EMAIL_SECRET is set.
+#[allow(clippy::too_many_arguments)]
+#[post("/webhooks/email")]
+pub async fn receive_email(
+    req: HttpRequest,
+    payload: web::Payload,
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    inbound: Option<web::Data<InboundEmail>>,
+    limits: Option<web::Data<Limits>>,
+    query: web::Query<WebhookQuery>,
+) -> impl Responder {
+    match inbound.as_deref() {
+        Some(inbound) if inbound.secret.is_some() => {
+            if !inbound.accepts(query.key.as_deref()) {
+                return HttpResponse::Unauthorized().body("Invalid key");
+            }
+        }
+        _ => return HttpResponse::NotFound().body("Inbound email is not configured"),
+    }
+
+    let limit = limits::configured(limits.as_ref()).max_json_payload_bytes;
+    let fields = match read_fields(&req, payload, limit).await {
+        Ok(fields) => fields,
+        Err(response) => return response,
+    };
+    let Some(message) = InboundMessage::from_fields(&fields) else {
+        return HttpResponse::BadRequest().body("The email has no sender");
+    };
+
+    // The first recipient whose local part is a known token says whose email this is
+    let mut found = None;
+    for recipient in &message.recipients {
+        let token = recipient.split('@').next().unwrap_or_default();
+        if token.len() != TOKEN_LENGTH {
+            continue;
+        }
+        match repo.inbound_email_workspace(token).await {
+            Ok(Some(owner)) => {
+                found = Some((recipient, owner));
+                break;
+            }
+            Ok(None) => {}
+            Err(e) => {
+                eprintln!("Database error: {:?}", e);
+                return HttpResponse::InternalServerError().body("Failed to look up address");
+            }
+        }
+    }
+    let Some((inbound_address, (user_id, workspace_id))) = found else {
+        return ignored("No recipient is an inbound address");
+    };
+
+    let emails: Vec<String> = std::iter::once(&message.sender)
+        .chain(&message.recipients)
+        .filter(|address| *address != inbound_address)
+        .cloned()
+        .collect();
+    let mut contact_ids = match repo.contacts_by_email(workspace_id, &emails).await {
+        Ok(contact_ids) => contact_ids,
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            return HttpResponse::InternalServerError().body("Failed to match contacts");
+        }
+    };
+    if contact_ids.is_empty() {
+        return ignored("No contact matches the sender or recipients");
+    }
+    let contact_id = contact_ids.remove(0);
+
+    let interaction = NewInteractionRequest {
+        contact_id,
+        interaction_date: OffsetDateTime::now_utc(),
+        notes: message.notes(),
+        follow_up_priority: None,
+        interaction_type: Some("email".to_string()),
+        contact_ids,
+        follow_up_in_days: None,
+    };
+    match repo
+        .create_interaction(workspace_id, &interaction, None)
+        .await
+    {
+        Ok((interaction_id, _)) => {
+            events::publish(
+                bus.as_ref(),
+                user_id,
+                Entity::Interaction,
+                Action::Created,
+                vec![interaction_id],
+            );
+            HttpResponse::Ok().json(serde_json::json!({
+                "interaction_id": interaction_id,
+                "message": "Email logged"
+            }))
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to log email")
+        }
+    }
+}
+
+/// The workspace's inbound address; 404 until one is created with PUT
+#[get("/inbound-email")]
+pub async fn get_inbound_email(
+    repo: web::Data<dyn Repository>,
+    inbound: Option<web::Data<InboundEmail>>,
+    auth_user: AuthUser,
+) -> impl Responder {
+    match repo.inbound_email_token(auth_user.workspace_id).await {
+        Ok(Some(token)) => HttpResponse::Ok().json(address(inbound.as_ref(), &token)),
+        Ok(None) => HttpResponse::NotFound().body("No inbound email address"),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to fetch inbound email address")
+        }
+    }
+}
+
+/// Create the workspace's inbound address, or replace it with a new one so mail to the old
+/// one is no longer logged
+#[put("/inbound-email")]
+pub async fn update_inbound_email(
+    repo: web::Data<dyn Repository>,
+    inbound: Option<web::Data<InboundEmail>>,
+    auth_user: AuthUser,
+) -> impl Responder {
+    let token = generate_token();
+    match repo
+        .save_inbound_email_token(auth_user.workspace_id, &token)
+        .await
+    {
+        Ok(()) => HttpResponse::Ok().json(address(inbound.as_ref(), &token)),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to create inbound email address")
+        }
+    }
+}
+
+#[delete("/inbound-email")]
+pub async fn delete_inbound_email(
+    repo: web::Data<dyn Repository>,
+    auth_user: AuthUser,
+) -> impl Responder {
+    match repo
+        .delete_inbound_email_token(auth_user.workspace_id)
+        .await
+    {
+        Ok(true) => HttpResponse::Ok().body("Inbound email address deleted"),
+        Ok(false) => HttpResponse::NotFound().body("No inbound email address"),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to delete inbound email address")
+        }
+    }
+}
+
+fn address(inbound: Option<&web::Data<InboundEmail>>, token: &str) -> InboundEmailAddress {
+    match inbound {
+        Some(inbound) => inbound.address(token),
+        None => InboundEmailAddress {
+            token: token.to_string(),
+            address: None,
+        },
+    }
+}
@@ -0,0 +1,174 @@
+//! Quick-capture inbox.
+//!
+//! Jot down "lunch w/ Priya yesterday, she's moving to Austin" when it happens and sort it out
+//! later: a capture is free text kept until it is resolved into an interaction with a chosen
+//! contact, or discarded.
+
+use crate::AuthUser;
+use crate::events::{self, Action, Entity, EventBus};
+use crate::interactions::check_interaction_type;
+use crate::models::{NewCaptureRequest, NewInteractionRequest, ResolveCaptureRequest};
+use crate::mutations;
+use crate::repository::Repository;
+use crate::routes::{Resource, ensure_owned};
+use actix_web::{HttpRequest, HttpResponse, Responder, delete, get, post, web};
+
+/// Longest capture, in characters
+pub const MAX_CAPTURE_CHARS: usize = 5000;
+
+#[get("/inbox")]
+pub async fn list_captures(repo: web::Data<dyn Repository>, auth_user: AuthUser) -> impl Responder {
+    match repo.list_captures(auth_user.workspace_id).await {
+        Ok(captures) => HttpResponse::Ok().json(captures),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to fetch inbox")
+        }
+    }
+}
+
+#[post("/inbox")]
+pub async fn create_capture(
+    req: HttpRequest,
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    auth_user: AuthUser,
+    new_capture: web::Json<NewCaptureRequest>,
+) -> impl Responder {
+    let text = new_capture.text.trim();
+    if text.is_empty() || text.chars().count() > MAX_CAPTURE_CHARS {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Text must be 1 to {} characters", MAX_CAPTURE_CHARS)
+        }));
+    }
+
+    match repo.create_capture(auth_user.workspace_id, text).await {
+        Ok(capture_id) => {
+            events::publish(
+                bus.as_ref(),
+                auth_user.user_id,
+                Entity::Capture,
+                Action::Created,
+                vec![capture_id],
+            );
+            mutations::created(
+                &req,
+                capture_id,
+                repo.get_capture(auth_user.workspace_id, capture_id).await,
+            )
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to save capture")
+        }
+    }
+}
+
+/// Turn a capture into an interaction with one of the workspace's contacts, removing it from
+/// the inbox
+#[post("/inbox/{id}/resolve")]
+pub async fn resolve_capture(
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    auth_user: AuthUser,
+    capture_id: web::Path<i32>,
+    resolution: web::Json<ResolveCaptureRequest>,
+) -> impl Responder {
+    let id = capture_id.into_inner();
+    let resolution = resolution.into_inner();
+
+    let capture = match repo.get_capture(auth_user.workspace_id, id).await {
+        Ok(Some(capture)) => capture,
+        Ok(None) => return HttpResponse::NotFound().body("Capture not found"),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            return HttpResponse::InternalServerError().body("Failed to fetch capture");
+        }
+    };
+    // Verify the contacts belong to the user
+    for &contact_id in std::iter::once(&resolution.contact_id).chain(&resolution.contact_ids) {
+        if let Err(response) =
+            ensure_owned(repo.get_ref(), &auth_user, Resource::Contact, contact_id).await
+        {
+            return response;
+        }
+    }
+    if let Some(response) = check_interaction_type(
+        repo.get_ref(),
+        auth_user.workspace_id,
+        resolution.interaction_type.as_deref(),
+    )
+    .await
+    {
+        return response;
+    }
+
+    let interaction = NewInteractionRequest {
+        contact_id: resolution.contact_id,
+        interaction_date: resolution.interaction_date.unwrap_or(capture.captured_at),
+        notes: resolution.notes.or(Some(capture.text)),
+        follow_up_priority: None,
+        interaction_type: resolution.interaction_type,
+        contact_ids: resolution.contact_ids,
+        follow_up_in_days: None,
+    };
+    match repo
+        .resolve_capture(auth_user.workspace_id, id, &interaction)
+        .await
+    {
+        // Resolved or discarded by another request since it was read
+        Ok(None) => HttpResponse::NotFound().body("Capture not found"),
+        Ok(Some(interaction_id)) => {
+            events::publish(
+                bus.as_ref(),
+                auth_user.user_id,
+                Entity::Capture,
+                Action::Deleted,
+                vec![id],
+            );
+            events::publish(
+                bus.as_ref(),
+                auth_user.user_id,
+                Entity::Interaction,
+                Action::Created,
+                vec![interaction_id],
+            );
+            HttpResponse::Ok().json(serde_json::json!({
+                "interaction_id": interaction_id,
+                "message": "Capture resolved successfully"
+            }))
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to resolve capture")
+        }
+    }
+}
+
+#[delete("/inbox/{id}")]
+pub async fn delete_capture(
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    auth_user: AuthUser,
+    capture_id: web::Path<i32>,
+) -> impl Responder {
+    let id = capture_id.into_inner();
+
+    match repo.delete_capture(auth_user.workspace_id, id).await {
+        Ok(false) => HttpResponse::NotFound().body("Capture not found"),
+        Ok(true) => {
+            events::publish(
+                bus.as_ref(),
+                auth_user.user_id,
+                Entity::Capture,
+                Action::Deleted,
+                vec![id],
+            );
+            HttpResponse::Ok().body("Capture discarded successfully")
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to discard capture")
+        }
+    }
+}
@@ -0,0 +1,505 @@
+//! Interaction handlers
+
+use crate::AuthUser;
+use crate::cursor::{Cursor, MAX_PAGE_SIZE, next_page_link};
+use crate::events::{self, Action, Entity, EventBus};
+use crate::models::{
+    CreatedInteraction, Interaction, InteractionTypesResponse, NewInteractionRequest,
+    NewInteractionTypeRequest, NewOccasionRequest, OccasionType, SharePermission,
+};
+use crate::mutations;
+use crate::repository::Repository;
+use crate::routes::{
+    Owned, OwnedInteraction, OwnedRow, Resource, ensure_contact_access, ensure_owned,
+};
+use crate::timezone::LocalDates;
+use actix_web::http::header::LINK;
+use actix_web::{HttpRequest, HttpResponse, Responder, delete, get, patch, post, web};
+use serde::Deserialize;
+use time::Duration;
+
+/// Interaction types every user has, matching the interaction_kind enum
+pub const BUILTIN_INTERACTION_TYPES: &[&str] = &["call", "email", "meeting", "message"];
+
+/// Longest custom type name the interaction_types table accepts
+const MAX_TYPE_NAME_LEN: usize = 50;
+
+/// Furthest out a follow-up can be scheduled, in days
+const MAX_FOLLOW_UP_DAYS: i32 = 365;
+
+/// The follow-up occasion asked for with `follow_up_in_days`, dated that many days after the
+/// interaction's day in the user's timezone. Errors are the JSON body of the 400 response.
+fn follow_up(
+    interaction: &NewInteractionRequest,
+    dates: &LocalDates,
+) -> Result<Option<NewOccasionRequest>, serde_json::Value> {
+    let Some(days) = interaction.follow_up_in_days else {
+        return Ok(None);
+    };
+    if interaction.follow_up_priority.is_none() {
+        return Err(serde_json::json!({
+            "error": "follow_up_in_days requires a follow_up_priority"
+        }));
+    }
+    if !(1..=MAX_FOLLOW_UP_DAYS).contains(&days) {
+        return Err(serde_json::json!({
+            "error": format!("follow_up_in_days must be 1 to {}", MAX_FOLLOW_UP_DAYS)
+        }));
+    }
+    Ok(Some(NewOccasionRequest {
+        contact_id: interaction.contact_id,
+        name: "Follow up".to_string(),
+        date: dates.date_of(interaction.interaction_date) + Duration::days(days.into()),
+        recurring: false,
+        recurring_interval: None,
+        details: interaction.notes.clone(),
+        occasion_type: OccasionType::Custom,
+        birth_year: None,
+        remind_days_before: None,
+    }))
+}
+
+/// Reject an interaction type that is neither built in nor one of the workspace's own
+pub(crate) async fn check_interaction_type(
+    repo: &dyn Repository,
+    workspace_id: i32,
+    interaction_type: Option<&str>,
+) -> Option<HttpResponse> {
+    let interaction_type = interaction_type?;
+    if BUILTIN_INTERACTION_TYPES.contains(&interaction_type) {
+        return None;
+    }
+    let custom = match repo.list_interaction_types(workspace_id).await {
+        Ok(custom) => custom,
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            return Some(
+                HttpResponse::InternalServerError().body("Failed to check interaction type"),
+            );
+        }
+    };
+    if custom.iter().any(|t| t.name == interaction_type) {
+        return None;
+    }
+    let known: Vec<&str> = BUILTIN_INTERACTION_TYPES
+        .iter()
+        .copied()
+        .chain(custom.iter().map(|t| t.name.as_str()))
+        .collect();
+    Some(HttpResponse::BadRequest().json(serde_json::json!({
+        "error": "Unknown interaction type",
+        "interaction_types": known
+    })))
+}
+
+#[post("/interactions")]
+pub async fn create_interaction(
+    req: HttpRequest,
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    auth_user: AuthUser,
+    new_interaction: web::Json<NewInteractionRequest>,
+) -> impl Responder {
+    // The contact is the user's own or shared with them for writing, and the interaction is
+    // stored in its workspace, so every other participant must be a writable contact of the
+    // same workspace
+    let access = match ensure_contact_access(
+        repo.get_ref(),
+        &auth_user,
+        new_interaction.contact_id,
+        SharePermission::Write,
+    )
+    .await
+    {
+        Ok(access) => access,
+        Err(response) => return response,
+    };
+    for &contact_id in &new_interaction.contact_ids {
+        match ensure_contact_access(
+            repo.get_ref(),
+            &auth_user,
+            contact_id,
+            SharePermission::Write,
+        )
+        .await
+        {
+            Ok(participant) if participant.workspace_id == access.workspace_id => {}
+            Ok(_) => return HttpResponse::NotFound().body("Contact not found"),
+            Err(response) => return response,
+        }
+    }
+    if let Some(response) = check_interaction_type(
+        repo.get_ref(),
+        access.workspace_id,
+        new_interaction.interaction_type.as_deref(),
+    )
+    .await
+    {
+        return response;
+    }
+    let dates = match LocalDates::for_user(repo.get_ref(), auth_user.user_id).await {
+        Ok(dates) => dates,
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            return HttpResponse::InternalServerError().body("Failed to fetch preferences");
+        }
+    };
+    let follow_up = match follow_up(&new_interaction, &dates) {
+        Ok(follow_up) => follow_up,
+        Err(error) => return HttpResponse::BadRequest().json(error),
+    };
+
+    match repo
+        .create_interaction(access.workspace_id, &new_interaction, follow_up.as_ref())
+        .await
+    {
+        Ok((interaction_id, follow_up_occasion_id)) => {
+            events::publish(
+                bus.as_ref(),
+                access.owner_id,
+                Entity::Interaction,
+                Action::Created,
+                vec![interaction_id],
+            );
+            if let Some(occasion_id) = follow_up_occasion_id {
+                events::publish(
+                    bus.as_ref(),
+                    access.owner_id,
+                    Entity::Occasion,
+                    Action::Created,
+                    vec![occasion_id],
+                );
+            }
+            let saved = Interaction::load(repo.get_ref(), access.workspace_id, interaction_id)
+                .await
+                .map(|interaction| {
+                    interaction.map(|interaction| CreatedInteraction {
+                        interaction,
+                        follow_up_occasion_id,
+                    })
+                });
+            mutations::created(&req, interaction_id, saved)
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to create interaction")
+        }
+    }
+}
+
+#[delete("/interactions/{id}")]
+pub async fn delete_interaction(
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    auth_user: AuthUser,
+    Owned(interaction): OwnedInteraction,
+) -> impl Responder {
+    let id = interaction.interaction_id;
+
+    match repo.delete_interaction(auth_user.workspace_id, id).await {
+        Ok(_) => {
+            events::publish(
+                bus.as_ref(),
+                auth_user.user_id,
+                Entity::Interaction,
+                Action::Deleted,
+                vec![id],
+            );
+            HttpResponse::Ok().body("Interaction deleted successfully")
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to delete interaction")
+        }
+    }
+}
+
+#[patch("/interactions/{id}")]
+pub async fn update_interaction(
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    auth_user: AuthUser,
+    Owned(interaction): OwnedInteraction,
+    updated_interaction: web::Json<NewInteractionRequest>,
+) -> impl Responder {
+    let id = interaction.interaction_id;
+
+    // Verify the contacts belong to the user; an unassigned interaction is given `contact_id`
+    for &contact_id in
+        std::iter::once(&updated_interaction.contact_id).chain(&updated_interaction.contact_ids)
+    {
+        if let Err(response) =
+            ensure_owned(repo.get_ref(), &auth_user, Resource::Contact, contact_id).await
+        {
+            return response;
+        }
+    }
+    if let Some(response) = check_interaction_type(
+        repo.get_ref(),
+        auth_user.workspace_id,
+        updated_interaction.interaction_type.as_deref(),
+    )
+    .await
+    {
+        return response;
+    }
+
+    match repo
+        .update_interaction(auth_user.workspace_id, id, &updated_interaction)
+        .await
+    {
+        Ok(_) => {
+            events::publish(
+                bus.as_ref(),
+                auth_user.user_id,
+                Entity::Interaction,
+                Action::Updated,
+                vec![id],
+            );
+            mutations::updated(Interaction::load(repo.get_ref(), auth_user.workspace_id, id).await)
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to update interaction")
+        }
+    }
+}
+
+/// Most results one notes search returns
+const MAX_SEARCH_LIMIT: u32 = 100;
+
+#[derive(Deserialize)]
+struct SearchQuery {
+    #[serde(default)]
+    q: String,
+    #[serde(default = "default_search_limit")]
+    limit: u32,
+}
+
+fn default_search_limit() -> u32 {
+    20
+}
+
+/// Full-text search of interaction notes and contacts' notes, best match first, for finding
+/// where something was written down. `?q=` takes web search syntax: words, "quoted phrases",
+/// `or` and `-excluded`. Each result has a highlighted snippet and the contact the notes belong
+/// to.
+#[get("/interactions/search")]
+pub async fn search_notes(
+    repo: web::Data<dyn Repository>,
+    auth_user: AuthUser,
+    query: web::Query<SearchQuery>,
+) -> impl Responder {
+    if !(1..=MAX_SEARCH_LIMIT).contains(&query.limit) {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("limit must be 1 to {}", MAX_SEARCH_LIMIT)
+        }));
+    }
+    if query.q.trim().is_empty() {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "q must not be empty"
+        }));
+    }
+
+    match repo
+        .search_notes(auth_user.workspace_id, query.q.trim(), query.limit.into())
+        .await
+    {
+        Ok(matches) => HttpResponse::Ok().json(matches),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to search notes")
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct TimelineQuery {
+    /// Only interactions of this type
+    #[serde(rename = "type")]
+    interaction_type: Option<String>,
+    /// Only interactions carrying this tag
+    tag_id: Option<i32>,
+    /// Page size; the whole timeline when left out
+    limit: Option<u32>,
+    /// Cursor from the previous page's `Link` header
+    after: Option<String>,
+}
+
+/// A contact's interactions, newest first.
+/// With `?limit=N` a full page links to the next one in a `Link: <...>; rel="next"` header.
+#[get("/contacts/{id}/interactions")]
+pub async fn contact_interactions(
+    req: HttpRequest,
+    repo: web::Data<dyn Repository>,
+    auth_user: AuthUser,
+    contact_id: web::Path<i32>,
+    query: web::Query<TimelineQuery>,
+) -> impl Responder {
+    let id = contact_id.into_inner();
+
+    if query
+        .limit
+        .is_some_and(|limit| !(1..=MAX_PAGE_SIZE).contains(&limit))
+    {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("limit must be 1 to {}", MAX_PAGE_SIZE)
+        }));
+    }
+    let after = match query.after.as_deref().map(Cursor::decode) {
+        None => None,
+        Some(Some(cursor)) => Some(cursor),
+        Some(None) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Invalid cursor"
+            }));
+        }
+    };
+
+    // The user's own contact or one shared with them
+    if let Err(response) =
+        ensure_contact_access(repo.get_ref(), &auth_user, id, SharePermission::Read).await
+    {
+        return response;
+    }
+
+    let interactions = match repo
+        .contact_timeline(
+            id,
+            query.interaction_type.as_deref(),
+            query.tag_id,
+            after,
+            query.limit.map(i64::from),
+        )
+        .await
+    {
+        Ok(interactions) => interactions,
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            return HttpResponse::InternalServerError().body("Failed to fetch interactions");
+        }
+    };
+
+    let mut response = HttpResponse::Ok();
+    if let (Some(limit), Some(last)) = (query.limit, interactions.last())
+        && interactions.len() == limit as usize
+    {
+        let cursor = Cursor::new(last.interaction_date, last.interaction_id);
+        response.insert_header((LINK, next_page_link(&req, &cursor)));
+    }
+    response.json(interactions)
+}
+
+#[get("/interaction-types")]
+pub async fn list_interaction_types(
+    repo: web::Data<dyn Repository>,
+    auth_user: AuthUser,
+) -> impl Responder {
+    match repo.list_interaction_types(auth_user.workspace_id).await {
+        Ok(custom) => HttpResponse::Ok().json(InteractionTypesResponse {
+            builtin: BUILTIN_INTERACTION_TYPES
+                .iter()
+                .map(|t| t.to_string())
+                .collect(),
+            custom,
+        }),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to fetch interaction types")
+        }
+    }
+}
+
+/// Add a custom interaction type. Names are trimmed and may not shadow a built-in type.
+#[post("/interaction-types")]
+pub async fn create_interaction_type(
+    req: HttpRequest,
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    auth_user: AuthUser,
+    new_type: web::Json<NewInteractionTypeRequest>,
+) -> impl Responder {
+    let name = new_type.name.trim();
+    if name.is_empty() || name.chars().count() > MAX_TYPE_NAME_LEN {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Name must be 1 to {} characters", MAX_TYPE_NAME_LEN)
+        }));
+    }
+    if BUILTIN_INTERACTION_TYPES
+        .iter()
+        .any(|t| t.eq_ignore_ascii_case(name))
+    {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Name is taken by a built-in interaction type"
+        }));
+    }
+
+    match repo.list_interaction_types(auth_user.workspace_id).await {
+        Ok(custom) if custom.iter().any(|t| t.name == name) => {
+            return HttpResponse::Conflict().json(serde_json::json!({
+                "error": "Interaction type already exists"
+            }));
+        }
+        Ok(_) => {}
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            return HttpResponse::InternalServerError().body("Failed to create interaction type");
+        }
+    }
+
+    match repo
+        .create_interaction_type(auth_user.workspace_id, name)
+        .await
+    {
+        Ok(type_id) => {
+            events::publish(
+                bus.as_ref(),
+                auth_user.user_id,
+                Entity::InteractionType,
+                Action::Created,
+                vec![type_id],
+            );
+            let saved = repo
+                .list_interaction_types(auth_user.workspace_id)
+                .await
+                .map(|types| types.into_iter().find(|t| t.type_id == type_id));
+            mutations::created(&req, type_id, saved)
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to create interaction type")
+        }
+    }
+}
+
+/// Delete a custom interaction type; its interactions are kept, untyped
+#[delete("/interaction-types/{id}")]
+pub async fn delete_interaction_type(
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    auth_user: AuthUser,
+    type_id: web::Path<i32>,
+) -> impl Responder {
+    let id = type_id.into_inner();
+
+    match repo
+        .delete_interaction_type(auth_user.workspace_id, id)
+        .await
+    {
+        Ok(false) => HttpResponse::NotFound().body("Interaction type not found"),
+        Ok(true) => {
+            events::publish(
+                bus.as_ref(),
+                auth_user.user_id,
+                Entity::InteractionType,
+                Action::Deleted,
+                vec![id],
+            );
+            HttpResponse::Ok().body("Interaction type deleted successfully")
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to delete interaction type")
+        }
+    }
+}
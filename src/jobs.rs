@@ -0,0 +1,86 @@
+//! Periodic background jobs, run by one replica at a time.
+//!
+//! Every replica schedules the same jobs. Before each run a replica claims the job's lease in
+//! the `job_leases` table for one interval; the others find it held and skip that run, so
+//! scheduled emails and pushes go out once however many replicas there are. A replica that
+//! stops holds the lease only until it runs out, then another takes the job over.
+//!
+//! A new periodic task implements `Job` and is registered with `Jobs` in `main`.
+
+use crate::repository::{RepoResult, Repository};
+use actix_web::web;
+use async_trait::async_trait;
+use std::sync::Arc;
+use std::time::Duration;
+use time::OffsetDateTime;
+
+#[async_trait]
+pub trait Job: Send + Sync {
+    /// Names the job's lease, so it must be unique among jobs and stable across releases
+    fn name(&self) -> &'static str;
+    /// How often the job runs, and how long a replica's claim on it lasts
+    fn interval(&self) -> Duration;
+    async fn run(&self, repo: &dyn Repository) -> RepoResult<()>;
+}
+
+/// Run `job` at `now` unless another replica than `holder` holds its lease, returning
+/// whether it ran
+pub async fn run_claimed(
+    repo: &dyn Repository,
+    job: &dyn Job,
+    holder: &str,
+    now: OffsetDateTime,
+) -> RepoResult<bool> {
+    if !repo
+        .claim_job(job.name(), holder, now, now + job.interval())
+        .await?
+    {
+        return Ok(false);
+    }
+    job.run(repo).await?;
+    Ok(true)
+}
+
+/// The jobs this process schedules
+pub struct Jobs {
+    /// Identifies this process in the leases it holds
+    holder: String,
+    jobs: Vec<Arc<dyn Job>>,
+}
+
+impl Default for Jobs {
+    fn default() -> Self {
+        let mut bytes = [0u8; 8];
+        getrandom::fill(&mut bytes).expect("Failed to read random bytes");
+        let id: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        Jobs {
+            holder: format!("{}-{}", std::process::id(), id),
+            jobs: Vec::new(),
+        }
+    }
+}
+
+impl Jobs {
+    pub fn register(&mut self, job: impl Job + 'static) {
+        self.jobs.push(Arc::new(job));
+    }
+
+    /// Run each job every interval, when its lease is free, for as long as the server runs
+    pub fn spawn(self, repo: web::Data<dyn Repository>) {
+        let holder: Arc<str> = self.holder.into();
+        for job in self.jobs {
+            let repo = repo.clone();
+            let holder = holder.clone();
+            actix_web::rt::spawn(async move {
+                let mut interval = actix_web::rt::time::interval(job.interval());
+                loop {
+                    interval.tick().await;
+                    let now = OffsetDateTime::now_utc();
+                    if let Err(e) = run_claimed(repo.get_ref(), job.as_ref(), &holder, now).await {
+                        eprintln!("Job {} failed: {:?}", job.name(), e);
+                    }
+                }
+            });
+        }
+    }
+}
@@ -1,34 +1,222 @@
-use actix_web::{Error, FromRequest, HttpRequest, error::ErrorUnauthorized};
+use actix_web::http::header::{HeaderValue, RETRY_AFTER};
+use actix_web::{
+    Error, FromRequest, HttpMessage, HttpRequest, HttpResponse,
+    error::{
+        ErrorBadRequest, ErrorForbidden, ErrorInternalServerError, ErrorNotFound,
+        ErrorUnauthorized, InternalError,
+    },
+};
+use backoff::Backoff;
+use cache::SharedCache;
 use dotenvy::dotenv;
 use jsonwebtoken::{Algorithm, DecodingKey, Validation, decode};
-use moka::future::Cache;
+use maintenance::is_read;
+use repository::Repository;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use signups::{SIGNUPS_CLOSED, Signups};
 use sqlx::PgPool;
-use std::sync::LazyLock;
-use std::time::Duration;
-
-// Cache for validated tokens (token -> claims) - 5 minute TTL
-static TOKEN_CACHE: LazyLock<Cache<String, Auth0Claims>> = LazyLock::new(|| {
-    Cache::builder()
-        .time_to_live(Duration::from_secs(300))
-        .max_capacity(1000)
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use time::OffsetDateTime;
+use user_cache::UserCache;
+
+pub mod account;
+pub mod admin;
+pub mod attachments;
+pub mod avatar;
+pub mod backoff;
+pub mod cache;
+#[cfg(feature = "capture-parsing")]
+pub mod capture_parsing;
+pub mod cli;
+pub mod conditional;
+pub mod contact_links;
+pub mod contacts;
+pub mod cursor;
+pub mod database;
+pub mod dedupe;
+pub mod deletion;
+pub mod deprecation;
+pub mod encryption;
+pub mod enrichment;
+pub mod events;
+pub mod examples;
+pub mod export;
+pub mod fieldsets;
+pub mod filters;
+pub mod geocoding;
+pub mod gift_ideas;
+pub mod goals;
+pub mod groups;
+pub mod health;
+pub mod history;
+pub mod holidays;
+pub mod idempotency;
+pub mod import;
+pub mod inbound_email;
+pub mod inbox;
+pub mod interactions;
+pub mod jobs;
+pub mod limits;
+pub mod link_preview;
+#[cfg(feature = "loadtest")]
+pub mod load;
+pub mod mail;
+pub mod maintenance;
+pub mod models;
+pub mod mutations;
+pub mod occasions;
+pub mod outbox;
+pub mod phone;
+pub mod preferences;
+pub mod probes;
+pub mod push;
+pub mod quick_add;
+pub mod quotas;
+pub mod rate_limit;
+pub mod reauth;
+pub mod reconnect;
+pub mod reminders;
+pub mod repository;
+pub mod review;
+pub mod routes;
+pub mod scores;
+pub mod security;
+#[cfg(feature = "seed")]
+pub mod seed;
+pub mod shares;
+pub mod signups;
+pub mod social_profiles;
+pub mod stats;
+pub mod streaming;
+pub mod tags;
+pub mod tenancy;
+#[cfg(feature = "test-support")]
+pub mod test_support;
+pub mod timezone;
+pub mod transcription;
+pub mod user_cache;
+pub mod versioning;
+pub mod workspaces;
+pub mod zapier;
+
+/// Longest a validated token is trusted without asking Auth0 again
+const MAX_TOKEN_TTL: Duration = Duration::from_secs(300);
+
+/// Token caches are keyed by the SHA-256 of the token, so raw tokens are not kept in memory
+/// or in Redis
+fn token_key(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// How long validated claims are kept: until the token expires, and never longer than
+/// `MAX_TOKEN_TTL`
+fn claims_ttl(claims: &Auth0Claims) -> Duration {
+    let Some(exp) = claims.exp else {
+        return MAX_TOKEN_TTL;
+    };
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let left = Duration::from_secs(exp as u64).saturating_sub(now);
+    left.min(MAX_TOKEN_TTL)
+}
+
+// Cache for validated tokens (token hash -> claims)
+static TOKEN_CACHE: SharedCache<Auth0Claims> = SharedCache::new("token", MAX_TOKEN_TTL);
+
+// Tokens Auth0 has refused - 1 minute TTL, so clients retrying a bad token can't make every
+// request call Auth0
+static REJECTED_TOKENS: SharedCache<()> =
+    SharedCache::new("rejected-token", Duration::from_secs(60));
+
+// While Auth0 is failing, tokens that aren't cached yet are turned away without calling it
+static AUTH0_BACKOFF: Mutex<Backoff> = Mutex::new(Backoff::new(
+    Duration::from_secs(1),
+    Duration::from_secs(60),
+));
+
+/// Auth0 request timeout, unless AUTH0_TIMEOUT_SECS says otherwise
+const DEFAULT_AUTH0_TIMEOUT: Duration = Duration::from_secs(5);
+
+// One client for every Auth0 call, so connections are pooled and reused. Proxies come from
+// HTTPS_PROXY/HTTP_PROXY/NO_PROXY; AUTH0_CONNECT_TIMEOUT_SECS and AUTH0_TIMEOUT_SECS bound how
+// long a call can hold up the request waiting on it.
+static AUTH0_CLIENT: LazyLock<reqwest::Client> = LazyLock::new(|| {
+    let secs = |key: &str, default: Duration| {
+        std::env::var(key)
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .map_or(default, Duration::from_secs)
+    };
+    let timeout = secs("AUTH0_TIMEOUT_SECS", DEFAULT_AUTH0_TIMEOUT);
+    reqwest::Client::builder()
+        .connect_timeout(secs("AUTH0_CONNECT_TIMEOUT_SECS", timeout))
+        .timeout(timeout)
         .build()
+        .expect("Failed to build HTTP client")
 });
 
 // Cache for JWKS - 1 hour TTL
-static JWKS_CACHE: LazyLock<Cache<String, String>> = LazyLock::new(|| {
-    Cache::builder()
-        .time_to_live(Duration::from_secs(3600))
-        .max_capacity(10)
-        .build()
-});
+static JWKS_CACHE: SharedCache<String> = SharedCache::new("jwks", Duration::from_secs(3600));
+
+// The readiness probe's last failed JWKS check, so probes don't call Auth0 every few seconds
+static JWKS_FAILURE: SharedCache<String> =
+    SharedCache::new("jwks-failure", Duration::from_secs(30));
+
+/// Scope a token needs for GET, HEAD and OPTIONS requests; `write` implies it
+pub const READ_SCOPE: &str = "read";
+/// Scope a token needs for every other request
+pub const WRITE_SCOPE: &str = "write";
+
+/// Request header naming the workspace a request works in; without it, the default workspace
+pub const WORKSPACE_HEADER: &str = "X-Workspace";
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct AuthUser {
     pub user_id: i32,
+    /// The workspace the request works in: the one named by `WORKSPACE_HEADER`, else the
+    /// user's default workspace
+    pub workspace_id: i32,
     pub auth0_id: String,
     pub email: Option<String>,
     pub name: Option<String>,
+    /// Set from `users.is_admin`; `admin::AdminUser` also accepts the subjects in
+    /// ADMIN_AUTH0_IDS
+    pub is_admin: bool,
+    /// Deactivated users are refused with 403 before any handler runs
+    pub deactivated: bool,
+    /// When the account is to be deleted, if the user asked for that; such users are
+    /// deactivated until then, but may restore the account
+    #[serde(default, with = "crate::models::datetime_format::option")]
+    pub delete_after: Option<OffsetDateTime>,
+    /// The API scopes granted to the request's token; None when it grants none of them,
+    /// which leaves it unrestricted
+    #[serde(skip)]
+    pub scopes: Option<Vec<String>>,
+    /// Unix time the user last signed in, else when the token was issued; see `reauth`
+    #[serde(skip)]
+    pub authenticated_at: Option<usize>,
+}
+
+impl AuthUser {
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scopes.as_ref().is_none_or(|granted| {
+            granted
+                .iter()
+                .any(|s| s == scope || (scope == READ_SCOPE && s == WRITE_SCOPE))
+        })
+    }
+
+    /// 403 unless the token grants `scope`, for handlers needing more than the method implies
+    pub fn require_scope(&self, scope: &str) -> Result<(), Error> {
+        if self.has_scope(scope) {
+            Ok(())
+        } else {
+            Err(ErrorForbidden(format!("Token lacks the {} scope", scope)))
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -39,6 +227,58 @@ pub struct Auth0Claims {
     pub iss: Option<String>,
     pub aud: Option<serde_json::Value>,
     pub exp: Option<usize>,
+    /// Unix time the token was issued
+    #[serde(default)]
+    pub iat: Option<usize>,
+    /// Unix time the user last signed in, which refreshed tokens carry over
+    #[serde(default)]
+    pub auth_time: Option<usize>,
+    /// Space separated OAuth scopes
+    #[serde(default)]
+    pub scope: Option<String>,
+    /// Auth0 RBAC permissions
+    #[serde(default)]
+    pub permissions: Option<Vec<String>>,
+}
+
+impl Auth0Claims {
+    /// This API's scopes among the token's scopes and permissions. None when there are none:
+    /// tokens minted before scopes existed, or for login only, keep full access.
+    pub fn api_scopes(&self) -> Option<Vec<String>> {
+        let granted: Vec<String> = self
+            .scope
+            .iter()
+            .flat_map(|s| s.split_whitespace())
+            .chain(self.permissions.iter().flatten().map(String::as_str))
+            .filter(|s| [READ_SCOPE, WRITE_SCOPE].contains(s))
+            .map(str::to_string)
+            .collect();
+        Some(granted).filter(|g| !g.is_empty())
+    }
+}
+
+/// Why a token could not be validated
+enum AuthFailure {
+    /// Auth0 answered and does not accept the token
+    Rejected,
+    /// Auth0 could not be reached or failed to answer
+    Unavailable,
+}
+
+fn auth0_backoff() -> std::sync::MutexGuard<'static, Backoff> {
+    AUTH0_BACKOFF.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// 503 telling the client when Auth0 will be tried again
+fn auth_unavailable(retry_after: Duration) -> Error {
+    let secs = retry_after.as_secs().max(1);
+    InternalError::from_response(
+        "Authentication service unavailable",
+        HttpResponse::ServiceUnavailable()
+            .insert_header((RETRY_AFTER, secs))
+            .body("Authentication service unavailable"),
+    )
+    .into()
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -52,102 +292,266 @@ impl FromRequest for AuthUser {
     type Error = Error;
     type Future = std::pin::Pin<Box<dyn std::future::Future<Output = Result<Self, Self::Error>>>>;
 
+    /// The user is kept in the request's extensions, so guards such as `routes::Owned` that
+    /// authenticate too don't look them up again
     fn from_request(req: &HttpRequest, _payload: &mut actix_web::dev::Payload) -> Self::Future {
-        let auth_header = req.headers().get("Authorization").cloned();
-        let pool = req.app_data::<actix_web::web::Data<PgPool>>().cloned();
-
+        if let Some(user) = req.extensions().get::<AuthUser>().cloned() {
+            return Box::pin(async move { Ok(user) });
+        }
+        let user = authenticate(req, false);
+        let req = req.clone();
         Box::pin(async move {
-            let auth_header = match auth_header {
-                Some(h) => h,
-                None => return Err(ErrorUnauthorized("No Authorization header")),
-            };
-
-            let auth_str = match auth_header.to_str() {
-                Ok(s) => s,
-                Err(_) => return Err(ErrorUnauthorized("Invalid Authorization header")),
-            };
-
-            if !auth_str.starts_with("Bearer ") {
-                return Err(ErrorUnauthorized("Invalid Authorization format"));
-            }
+            let user = user.await?;
+            req.extensions_mut().insert(user.clone());
+            Ok(user)
+        })
+    }
+}
 
-            let token = &auth_str[7..];
-            let pool = pool.ok_or_else(|| ErrorUnauthorized("Database not available"))?;
+/// The user a request's token stands for. Users whose account is scheduled for deletion are
+/// refused like deactivated ones unless `allow_pending_deletion`, which only restoring the
+/// account sets.
+pub(crate) fn authenticate(
+    req: &HttpRequest,
+    allow_pending_deletion: bool,
+) -> <AuthUser as FromRequest>::Future {
+    let auth_header = req.headers().get("Authorization").cloned();
+    let workspace_header = req.headers().get(WORKSPACE_HEADER).cloned();
+    let method = req.method().clone();
+    let repo = req
+        .app_data::<actix_web::web::Data<dyn Repository>>()
+        .cloned();
+    let users = req.app_data::<actix_web::web::Data<UserCache>>().cloned();
+    let signups = req.app_data::<actix_web::web::Data<Signups>>().cloned();
+
+    Box::pin(async move {
+        let auth_header = match auth_header {
+            Some(h) => h,
+            None => return Err(ErrorUnauthorized("No Authorization header")),
+        };
+
+        let auth_str = match auth_header.to_str() {
+            Ok(s) => s,
+            Err(_) => return Err(ErrorUnauthorized("Invalid Authorization header")),
+        };
+
+        if !auth_str.starts_with("Bearer ") {
+            return Err(ErrorUnauthorized("Invalid Authorization format"));
+        }
 
-            // Check token cache first
-            if let Some(cached_claims) = TOKEN_CACHE.get(token).await {
-                return get_or_create_user(&pool, cached_claims).await;
+        let token = &auth_str[7..];
+        let repo = repo.ok_or_else(|| ErrorUnauthorized("Database not available"))?;
+
+        let claims = validated_claims(token).await?;
+        let scopes = claims.api_scopes();
+        let authenticated_at = claims.auth_time.or(claims.iat);
+        let mut user = get_or_create_user(
+            repo.get_ref(),
+            users.as_ref().map(|u| u.get_ref()),
+            signups.as_ref().map(|s| s.get_ref()),
+            claims,
+        )
+        .await?;
+        user.scopes = scopes;
+        user.authenticated_at = authenticated_at;
+        if user.delete_after.is_some() {
+            if !allow_pending_deletion {
+                return Err(ErrorForbidden(
+                    "Account is scheduled for deletion; POST /account/restore keeps it",
+                ));
             }
+        } else if user.deactivated {
+            return Err(ErrorForbidden("Account is deactivated"));
+        }
 
-            let auth0_domain = std::env::var("AUTH0_DOMAIN")
-                .unwrap_or_else(|_| "dev-example.auth0.com".to_string());
+        user.require_scope(if is_read(&method) {
+            READ_SCOPE
+        } else {
+            WRITE_SCOPE
+        })?;
+        if let Some(header) = workspace_header {
+            user.workspace_id = selected_workspace(repo.get_ref(), &user, &header).await?;
+        }
+        Ok(user)
+    })
+}
 
-            // Try to validate as JWT first, fall back to userinfo endpoint for opaque tokens
-            let claims = match validate_jwt(token, &auth0_domain).await {
-                Ok(claims) => claims,
-                Err(_) => {
-                    // Token might be opaque, try userinfo endpoint
-                    validate_via_userinfo(token, &auth0_domain).await?
-                }
-            };
+/// The workspace named by a `WORKSPACE_HEADER` value, which must be one of the user's. Other
+/// users' workspaces are reported like missing ones.
+async fn selected_workspace(
+    repo: &dyn Repository,
+    user: &AuthUser,
+    header: &HeaderValue,
+) -> Result<i32, Error> {
+    let workspace_id: i32 = header
+        .to_str()
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .ok_or_else(|| ErrorBadRequest("Invalid X-Workspace header"))?;
+    if workspace_id == user.workspace_id {
+        return Ok(workspace_id);
+    }
+    match repo.owns_workspace(user.user_id, workspace_id).await {
+        Ok(true) => Ok(workspace_id),
+        Ok(false) => Err(ErrorNotFound("Workspace not found")),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            Err(ErrorInternalServerError("Database error"))
+        }
+    }
+}
 
-            // Cache the validated token
-            TOKEN_CACHE.insert(token.to_string(), claims.clone()).await;
+/// The claims of a token, from the cache or else validated with Auth0
+async fn validated_claims(token: &str) -> Result<Auth0Claims, Error> {
+    // Check token cache first
+    let key = token_key(token);
+    if let Some(cached_claims) = TOKEN_CACHE.get(&key).await {
+        return Ok(cached_claims);
+    }
 
-            get_or_create_user(&pool, claims).await
-        })
+    if REJECTED_TOKENS.get(&key).await.is_some() {
+        return Err(ErrorUnauthorized("Invalid token"));
     }
+    if let Some(wait) = auth0_backoff().remaining(Instant::now()) {
+        return Err(auth_unavailable(wait));
+    }
+
+    let auth0_domain = auth0_domain();
+
+    // Try to validate as JWT first, fall back to userinfo endpoint for opaque tokens
+    let claims = match validate_jwt(token, &auth0_domain).await {
+        Ok(claims) => claims,
+        // Token might be opaque, try userinfo endpoint
+        Err(_) => match validate_via_userinfo(token, &auth0_domain).await {
+            Ok(claims) => claims,
+            Err(AuthFailure::Rejected) => {
+                REJECTED_TOKENS.insert(&key, &()).await;
+                return Err(ErrorUnauthorized("Invalid token"));
+            }
+            Err(AuthFailure::Unavailable) => {
+                let now = Instant::now();
+                let mut backoff = auth0_backoff();
+                backoff.failed(now);
+                return Err(auth_unavailable(backoff.remaining(now).unwrap_or_default()));
+            }
+        },
+    };
+    auth0_backoff().succeeded();
+
+    // Cache the validated token
+    TOKEN_CACHE
+        .insert_for(&key, &claims, claims_ttl(&claims))
+        .await;
+
+    Ok(claims)
+}
+
+/// Auth0 subject for a token that has already been validated and cached, if any
+pub(crate) async fn cached_subject(token: &str) -> Option<String> {
+    TOKEN_CACHE
+        .get(&token_key(token))
+        .await
+        .map(|claims| claims.sub)
+}
+
+/// Trust `token` as if Auth0 had validated it, for fixtures and load tests
+#[cfg(any(feature = "test-support", feature = "loadtest"))]
+pub(crate) async fn cache_token(token: &str, claims: Auth0Claims) {
+    TOKEN_CACHE
+        .insert_for(&token_key(token), &claims, claims_ttl(&claims))
+        .await;
 }
 
+/// Refuse `token` as if Auth0 had rejected it
+#[cfg(feature = "test-support")]
+pub(crate) async fn cache_rejected_token(token: &str) {
+    REJECTED_TOKENS.insert(&token_key(token), &()).await;
+}
+
+/// The user for the claims' subject, created on their first request unless `signups` turns
+/// the subject away
 async fn get_or_create_user(
-    pool: &actix_web::web::Data<PgPool>,
+    repo: &dyn Repository,
+    users: Option<&UserCache>,
+    signups: Option<&Signups>,
     claims: Auth0Claims,
 ) -> Result<AuthUser, Error> {
-    let user_result = sqlx::query!(
-        "SELECT user_id, auth0_id, email, name FROM users WHERE auth0_id = $1",
-        claims.sub
-    )
-    .fetch_optional(pool.get_ref())
-    .await
-    .map_err(|_| ErrorUnauthorized("Database error"))?;
-
-    match user_result {
-        Some(user) => Ok(AuthUser {
-            user_id: user.user_id,
-            auth0_id: user.auth0_id,
-            email: Some(user.email),
-            name: Some(user.name),
-        }),
-        None => {
-            // Provide defaults for required fields if not present in claims
-            let email = claims
-                .email
-                .unwrap_or_else(|| format!("{}@unknown.local", claims.sub));
-            let name = claims.name.unwrap_or_else(|| "Unknown User".to_string());
-
-            let new_user = sqlx::query!(
-                "INSERT INTO users (auth0_id, email, name) VALUES ($1, $2, $3) RETURNING user_id, auth0_id, email, name",
-                claims.sub,
-                email,
-                name
-            )
-            .fetch_one(pool.get_ref())
-            .await
-            .map_err(|e| {
-                eprintln!("Failed to create user: {:?}", e);
-                ErrorUnauthorized("Failed to create user")
-            })?;
-
-            Ok(AuthUser {
-                user_id: new_user.user_id,
-                auth0_id: new_user.auth0_id,
-                email: Some(new_user.email),
-                name: Some(new_user.name),
-            })
+    if let Some(users) = users
+        && let Some(user) = users.get(&claims.sub).await
+    {
+        return Ok(user);
+    }
+
+    if signups.is_some_and(|s| !s.allows(&claims.sub)) {
+        let user = repo.find_user(&claims.sub).await.map_err(|e| {
+            eprintln!("Failed to look up user: {:?}", e);
+            ErrorUnauthorized("Failed to look up user")
+        })?;
+        let user = user.ok_or_else(|| ErrorForbidden(SIGNUPS_CLOSED))?;
+        if let Some(users) = users {
+            users.insert(&user).await;
+        }
+        return Ok(user);
+    }
+
+    // Provide defaults for required fields if not present in claims
+    let email = claims
+        .email
+        .unwrap_or_else(|| format!("{}@unknown.local", claims.sub));
+    let name = claims.name.unwrap_or_else(|| "Unknown User".to_string());
+
+    let user = repo
+        .get_or_create_user(&claims.sub, &email, &name)
+        .await
+        .map_err(|e| {
+            eprintln!("Failed to create user: {:?}", e);
+            ErrorUnauthorized("Failed to create user")
+        })?;
+    if let Some(users) = users {
+        users.insert(&user).await;
+    }
+    Ok(user)
+}
+
+fn auth0_domain() -> String {
+    std::env::var("AUTH0_DOMAIN").unwrap_or_else(|_| "dev-example.auth0.com".to_string())
+}
+
+/// Check that the Auth0 JWKS endpoint answers, for the readiness probe. A cached key set counts
+/// as reachable, and a fetch is what fills the cache; a failure is remembered for 30 seconds.
+pub async fn check_jwks_reachable() -> Result<(), String> {
+    let jwks_uri = format!("https://{}/.well-known/jwks.json", auth0_domain());
+    if JWKS_CACHE.get(&jwks_uri).await.is_some() {
+        return Ok(());
+    }
+    if let Some(error) = JWKS_FAILURE.get(&jwks_uri).await {
+        return Err(error);
+    }
+    match fetch_jwks(&jwks_uri).await {
+        Ok(jwks) => {
+            JWKS_CACHE.insert(&jwks_uri, &jwks).await;
+            Ok(())
+        }
+        Err(error) => {
+            JWKS_FAILURE.insert(&jwks_uri, &error).await;
+            Err(error)
         }
     }
 }
 
+async fn fetch_jwks(jwks_uri: &str) -> Result<String, String> {
+    let response = AUTH0_CLIENT
+        .get(jwks_uri)
+        .timeout(Duration::from_secs(3))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("JWKS returned status {}", response.status()));
+    }
+    response.text().await.map_err(|e| e.to_string())
+}
+
 async fn validate_jwt(token: &str, auth0_domain: &str) -> Result<Auth0Claims, Error> {
     let jwks_uri = format!("https://{}/.well-known/jwks.json", auth0_domain);
 
@@ -155,14 +559,16 @@ async fn validate_jwt(token: &str, auth0_domain: &str) -> Result<Auth0Claims, Er
     let jwks_response = match JWKS_CACHE.get(&jwks_uri).await {
         Some(cached) => cached,
         None => {
-            let response = reqwest::get(&jwks_uri)
+            let response = AUTH0_CLIENT
+                .get(&jwks_uri)
+                .send()
                 .await
                 .map_err(|_| ErrorUnauthorized("Failed to fetch JWKS"))?
                 .text()
                 .await
                 .map_err(|_| ErrorUnauthorized("Failed to read JWKS"))?;
 
-            JWKS_CACHE.insert(jwks_uri.clone(), response.clone()).await;
+            JWKS_CACHE.insert(&jwks_uri, &response).await;
             response
         }
     };
@@ -202,28 +608,37 @@ async fn validate_jwt(token: &str, auth0_domain: &str) -> Result<Auth0Claims, Er
     Ok(token_data.claims)
 }
 
-async fn validate_via_userinfo(token: &str, auth0_domain: &str) -> Result<Auth0Claims, Error> {
+/// Network errors, server errors and rate limiting mean Auth0 is unavailable; any other
+/// unsuccessful status is a rejection of the token
+async fn validate_via_userinfo(
+    token: &str,
+    auth0_domain: &str,
+) -> Result<Auth0Claims, AuthFailure> {
     let userinfo_url = format!("https://{}/userinfo", auth0_domain);
 
-    let client = reqwest::Client::new();
-    let response = client
+    let response = AUTH0_CLIENT
         .get(&userinfo_url)
         .header("Authorization", format!("Bearer {}", token))
         .send()
         .await
         .map_err(|e| {
             eprintln!("Userinfo request error: {:?}", e);
-            ErrorUnauthorized("Failed to validate token")
+            AuthFailure::Unavailable
         })?;
 
-    if !response.status().is_success() {
-        eprintln!("Userinfo returned status: {}", response.status());
-        return Err(ErrorUnauthorized("Invalid token"));
+    let status = response.status();
+    if status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+        eprintln!("Userinfo returned status: {}", status);
+        return Err(AuthFailure::Unavailable);
+    }
+    if !status.is_success() {
+        eprintln!("Userinfo returned status: {}", status);
+        return Err(AuthFailure::Rejected);
     }
 
     let user_info: UserInfoResponse = response.json().await.map_err(|e| {
         eprintln!("Userinfo parse error: {:?}", e);
-        ErrorUnauthorized("Failed to parse userinfo")
+        AuthFailure::Unavailable
     })?;
 
     Ok(Auth0Claims {
@@ -233,26 +648,51 @@ async fn validate_via_userinfo(token: &str, auth0_domain: &str) -> Result<Auth0C
         iss: None,
         aud: None,
         exp: None,
+        iat: None,
+        auth_time: None,
+        scope: None,
+        permissions: None,
     })
 }
 
+/// Connect to DATABASE_URL with the pool settings from `database::DbConfig::from_env`,
+/// retrying while the database starts up
 pub async fn db() -> PgPool {
     dotenv().ok();
     let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    connect_url("DATABASE_URL", &database_url).await
+}
+
+/// Pool for the read-only replica at DATABASE_READ_URL, or None when it is unset so every
+/// query stays on the primary
+pub async fn db_read() -> Option<PgPool> {
+    dotenv().ok();
+    let read_url = std::env::var("DATABASE_READ_URL")
+        .ok()
+        .filter(|url| !url.is_empty())?;
+    Some(connect_url("DATABASE_READ_URL", &read_url).await)
+}
 
+/// Connect to the Postgres database at `database_url`, read from the `var` environment variable
+async fn connect_url(var: &str, database_url: &str) -> PgPool {
     // Validate URL format before attempting connection
     if !database_url.starts_with("postgres://") && !database_url.starts_with("postgresql://") {
         panic!(
-            "DATABASE_URL must be a valid PostgreSQL URL starting with postgres:// or postgresql://. Got: {}",
+            "{} must be a valid PostgreSQL URL starting with postgres:// or postgresql://. Got: {}",
+            var,
             if database_url.len() > 20 {
                 &database_url[..20]
             } else {
-                &database_url
+                database_url
             }
         );
     }
 
-    sqlx::postgres::PgPool::connect(&database_url)
+    let options = database_url
+        .parse()
+        .unwrap_or_else(|_| panic!("{} is not a valid connection string", var));
+    database::DbConfig::from_env()
+        .connect(options)
         .await
-        .expect("Failed to connect to database")
+        .unwrap_or_else(|e| panic!("Failed to connect to database at {}: {}", var, e))
 }
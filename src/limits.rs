@@ -0,0 +1,101 @@
+//! Request size limits.
+//!
+//! JSON bodies larger than MAX_JSON_PAYLOAD_BYTES are refused with 413 before they are parsed,
+//! and the bulk endpoints refuse more than MAX_BULK_ITEMS items with 422, so one request can't
+//! tie up a worker and a database connection for minutes. Uploaded attachments have their own
+//! limit, MAX_ATTACHMENT_BYTES.
+
+use actix_web::error::{InternalError, JsonPayloadError};
+use actix_web::{HttpResponse, web};
+
+/// Largest JSON body accepted, unless MAX_JSON_PAYLOAD_BYTES says otherwise
+pub const DEFAULT_MAX_JSON_PAYLOAD_BYTES: usize = 2 * 1024 * 1024;
+
+/// Most items one bulk request may carry, unless MAX_BULK_ITEMS says otherwise
+pub const DEFAULT_MAX_BULK_ITEMS: usize = 500;
+
+/// Largest interaction attachment accepted, unless MAX_ATTACHMENT_BYTES says otherwise
+pub const DEFAULT_MAX_ATTACHMENT_BYTES: usize = 10 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    pub max_json_payload_bytes: usize,
+    pub max_bulk_items: usize,
+    pub max_attachment_bytes: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_json_payload_bytes: DEFAULT_MAX_JSON_PAYLOAD_BYTES,
+            max_bulk_items: DEFAULT_MAX_BULK_ITEMS,
+            max_attachment_bytes: DEFAULT_MAX_ATTACHMENT_BYTES,
+        }
+    }
+}
+
+impl Limits {
+    /// Read MAX_JSON_PAYLOAD_BYTES, MAX_BULK_ITEMS and MAX_ATTACHMENT_BYTES, falling back to
+    /// the defaults for anything unset or unparsable
+    pub fn from_env() -> Self {
+        let defaults = Limits::default();
+        let env_or = |key: &str, default: usize| {
+            std::env::var(key)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default)
+        };
+        Limits {
+            max_json_payload_bytes: env_or(
+                "MAX_JSON_PAYLOAD_BYTES",
+                defaults.max_json_payload_bytes,
+            ),
+            max_bulk_items: env_or("MAX_BULK_ITEMS", defaults.max_bulk_items),
+            max_attachment_bytes: env_or("MAX_ATTACHMENT_BYTES", defaults.max_attachment_bytes),
+        }
+    }
+
+    /// JSON extractor config enforcing the payload limit. Oversized bodies get 413 and other
+    /// parse failures 400, both with a JSON error body.
+    pub fn json_config(&self) -> web::JsonConfig {
+        let limit = self.max_json_payload_bytes;
+        web::JsonConfig::default()
+            .limit(limit)
+            .error_handler(move |err, _req| {
+                let response = match &err {
+                    JsonPayloadError::Overflow { .. }
+                    | JsonPayloadError::OverflowKnownLength { .. } => payload_too_large(limit),
+                    _ => HttpResponse::BadRequest().json(serde_json::json!({
+                        "error": err.to_string()
+                    })),
+                };
+                InternalError::from_response(err, response).into()
+            })
+    }
+
+    /// The 422 for a bulk request carrying more than `max_bulk_items` items
+    pub fn reject_bulk(&self, items: usize) -> Option<HttpResponse> {
+        if items > self.max_bulk_items {
+            return Some(HttpResponse::UnprocessableEntity().json(serde_json::json!({
+                "error": format!(
+                    "Too many items: {} sent, at most {} per request",
+                    items, self.max_bulk_items
+                ),
+                "max_items": self.max_bulk_items
+            })));
+        }
+        None
+    }
+}
+
+/// The 413 for a body over `limit` bytes
+pub fn payload_too_large(limit: usize) -> HttpResponse {
+    HttpResponse::PayloadTooLarge().json(serde_json::json!({
+        "error": format!("Request body is larger than {} bytes", limit)
+    }))
+}
+
+/// The app's limits, or the defaults when none are registered
+pub fn configured(limits: Option<&web::Data<Limits>>) -> Limits {
+    limits.map_or_else(Limits::default, |limits| *limits.get_ref())
+}
@@ -0,0 +1,287 @@
+//! Open Graph previews of social profile links.
+//! The server fetches a profile page's `og:title` and `og:image` so clients can show the link as
+//! a chip without loading third-party pages themselves. Results are cached per URL for a day.
+
+use crate::models::{LinkPreview, SocialProfile};
+use futures::stream::{self, StreamExt};
+use moka::future::Cache;
+use reqwest::Url;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::redirect::{Action, Attempt, Policy};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+/// Page fetches running at once while filling in a list of profiles
+const CONCURRENT_FETCHES: usize = 8;
+
+/// How much of a page is read; Open Graph tags are in its head
+const MAX_PAGE_BYTES: usize = 256 * 1024;
+
+const MAX_REDIRECTS: usize = 5;
+
+/// Host suffixes that only resolve on a local network
+const LOCAL_SUFFIXES: &[&str] = &[".localhost", ".local", ".internal", ".lan", ".home.arpa"];
+
+/// Whether the server may fetch `url`: https on the default port of a public-looking host name.
+/// IP addresses and local names are refused so profile links can't reach services next to the
+/// server.
+pub fn is_fetchable(url: &Url) -> bool {
+    let Some(domain) = url.domain() else {
+        return false;
+    };
+    let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+    url.scheme() == "https"
+        && url.port().is_none()
+        && domain.contains('.')
+        && !LOCAL_SUFFIXES.iter().any(|suffix| domain.ends_with(suffix))
+}
+
+/// Whether `ip` is on the public internet rather than the server itself, its network or the
+/// cloud metadata service
+pub fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_ipv4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_ipv4(ip),
+            None => is_public_ipv6(ip),
+        },
+    }
+}
+
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        || ip.is_documentation()
+        || a == 0
+        // Carrier-grade NAT, 100.64.0.0/10
+        || (a == 100 && (64..128).contains(&b)))
+}
+
+fn is_public_ipv6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // Unique local, fc00::/7, and link-local, fe80::/10
+        || (first & 0xfe00) == 0xfc00
+        || (first & 0xffc0) == 0xfe80)
+}
+
+/// Resolves host names to their public addresses only, for clients that request URLs users
+/// give. Checking at connect time rather than when the URL is saved means a name can't be
+/// pointed at the server's network afterwards.
+pub struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0))
+                .await?
+                .filter(|addr| is_public_ip(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(format!("{} has no public address", host).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Follow a few redirects, and only to hosts that could have been fetched directly
+fn redirect_policy(attempt: Attempt) -> Action {
+    if attempt.previous().len() >= MAX_REDIRECTS || !is_fetchable(attempt.url()) {
+        attempt.stop()
+    } else {
+        attempt.follow()
+    }
+}
+
+/// Undo the entity escapes that show up in attribute values
+fn unescape(value: &str) -> String {
+    value
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+/// Attribute name/value pairs of one tag's source, names lowercased
+fn attributes(tag: &str) -> Vec<(String, String)> {
+    let mut attributes = Vec::new();
+    let mut rest = tag;
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == '/');
+        let name_end = rest
+            .find(|c: char| c.is_whitespace() || c == '=' || c == '/')
+            .unwrap_or(rest.len());
+        if name_end == 0 {
+            return attributes;
+        }
+        let name = rest[..name_end].to_ascii_lowercase();
+        rest = rest[name_end..].trim_start();
+        let Some(after_equals) = rest.strip_prefix('=') else {
+            attributes.push((name, String::new()));
+            continue;
+        };
+        rest = after_equals.trim_start();
+        let value = match rest.chars().next() {
+            Some(quote @ ('"' | '\'')) => {
+                let end = rest[1..].find(quote).map_or(rest.len(), |n| n + 1);
+                let value = &rest[1..end];
+                rest = rest.get(end + 1..).unwrap_or_default();
+                value
+            }
+            _ => {
+                let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                let value = &rest[..end];
+                rest = &rest[end..];
+                value
+            }
+        };
+        attributes.push((name, unescape(value)));
+    }
+}
+
+/// The Open Graph title and image of an HTML page at `base`; None if it has neither. Relative
+/// image URLs are resolved against `base`, and images that are not http(s) are left out.
+pub fn parse_preview(html: &str, base: &Url) -> Option<LinkPreview> {
+    // ASCII lowercasing keeps byte offsets, so positions found here index `html` too
+    let lower = html.to_ascii_lowercase();
+    let mut preview = LinkPreview::default();
+    let mut from = 0;
+    while let Some(start) = lower[from..]
+        .find("<meta")
+        .map(|n| from + n + "<meta".len())
+    {
+        let end = lower[start..].find('>').map_or(lower.len(), |n| start + n);
+        from = end;
+        let attributes = attributes(&html[start..end]);
+        let get = |name: &str| {
+            attributes
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, v)| v.trim())
+        };
+        let (Some(property), Some(content)) = (get("property").or(get("name")), get("content"))
+        else {
+            continue;
+        };
+        if content.is_empty() {
+            continue;
+        }
+        match property.to_ascii_lowercase().as_str() {
+            "og:title" if preview.title.is_none() => preview.title = Some(content.to_string()),
+            "og:image" | "og:image:url" | "og:image:secure_url" if preview.image_url.is_none() => {
+                preview.image_url = base
+                    .join(content)
+                    .ok()
+                    .filter(|url| matches!(url.scheme(), "http" | "https"))
+                    .map(String::from);
+            }
+            _ => {}
+        }
+    }
+    (preview != LinkPreview::default()).then_some(preview)
+}
+
+pub struct LinkPreviewer {
+    client: reqwest::Client,
+    /// URL -> preview, or None when the page has no Open Graph tags or is gone
+    cache: Cache<String, Option<LinkPreview>>,
+}
+
+impl LinkPreviewer {
+    pub fn new() -> Self {
+        LinkPreviewer {
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(3))
+                .redirect(Policy::custom(redirect_policy))
+                .dns_resolver(PublicResolver)
+                .build()
+                .expect("Failed to build HTTP client"),
+            cache: Cache::builder()
+                .max_capacity(100_000)
+                .time_to_live(Duration::from_secs(24 * 3600))
+                .build(),
+        }
+    }
+
+    /// The previewer if `LINK_PREVIEWS` is set to `true`. Off by default because it makes the
+    /// server request the pages users link to.
+    pub fn from_env() -> Option<Self> {
+        let enabled = std::env::var("LINK_PREVIEWS")
+            .map(|v| v.eq_ignore_ascii_case("true"))
+            .unwrap_or(false);
+        enabled.then(LinkPreviewer::new)
+    }
+
+    /// Preview of the page at `url`, if it can be fetched and has one. Failed fetches are not
+    /// cached.
+    pub async fn lookup(&self, url: &str) -> Option<LinkPreview> {
+        let parsed = Url::parse(url).ok().filter(is_fetchable)?;
+        if let Some(cached) = self.cache.get(url).await {
+            return cached;
+        }
+
+        let mut response = match self.client.get(parsed).send().await {
+            Ok(response) => response,
+            Err(e) => {
+                eprintln!("Link preview fetch failed: {:?}", e);
+                return None;
+            }
+        };
+        let status = response.status();
+        if status.is_server_error() || status.is_redirection() {
+            eprintln!("Link preview fetch returned status {}", status);
+            return None;
+        }
+        let is_html = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.to_ascii_lowercase().starts_with("text/html"));
+
+        let mut found = None;
+        if status.is_success() && is_html {
+            let base = response.url().clone();
+            let mut page = Vec::new();
+            while page.len() < MAX_PAGE_BYTES {
+                match response.chunk().await {
+                    Ok(Some(chunk)) => page.extend_from_slice(&chunk),
+                    Ok(None) => break,
+                    Err(e) => {
+                        eprintln!("Link preview fetch failed: {:?}", e);
+                        return None;
+                    }
+                }
+            }
+            page.truncate(MAX_PAGE_BYTES);
+            found = parse_preview(&String::from_utf8_lossy(&page), &base);
+        }
+        self.cache.insert(url.to_string(), found.clone()).await;
+        found
+    }
+
+    /// Fill in the preview of each profile
+    pub async fn fill(&self, profiles: &mut [SocialProfile]) {
+        stream::iter(profiles.iter_mut())
+            .for_each_concurrent(CONCURRENT_FETCHES, |profile| async move {
+                profile.preview = self.lookup(&profile.url).await;
+            })
+            .await;
+    }
+}
+
+impl Default for LinkPreviewer {
+    fn default() -> Self {
+        LinkPreviewer::new()
+    }
+}
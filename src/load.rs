@@ -0,0 +1,341 @@
+//! Load test harness (enabled with the `loadtest` feature).
+//!
+//! Seeds synthetic users and contacts, registers a bearer token per user in the token cache so
+//! requests skip Auth0, then drives the HTTP API with a configurable number of concurrent
+//! workers and reports latency percentiles per scenario.
+
+use crate::repository::DEFAULT_WORKSPACE_NAME;
+use crate::{Auth0Claims, cache_token};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone)]
+pub struct LoadConfig {
+    pub users: usize,
+    pub contacts_per_user: usize,
+    pub interactions_per_contact: usize,
+    pub concurrency: usize,
+    pub requests: usize,
+    /// Keep the seeded rows after the run instead of deleting them
+    pub keep_data: bool,
+}
+
+impl Default for LoadConfig {
+    fn default() -> Self {
+        LoadConfig {
+            users: 5,
+            contacts_per_user: 200,
+            interactions_per_contact: 5,
+            concurrency: 8,
+            requests: 1000,
+            keep_data: false,
+        }
+    }
+}
+
+impl LoadConfig {
+    /// Parse `--users N --contacts M --interactions K --concurrency C --requests R --keep-data`
+    pub fn from_args<I: Iterator<Item = String>>(mut args: I) -> Result<LoadConfig, String> {
+        let mut config = LoadConfig::default();
+        while let Some(arg) = args.next() {
+            if arg == "--keep-data" {
+                config.keep_data = true;
+                continue;
+            }
+            let target = match arg.as_str() {
+                "--users" => &mut config.users,
+                "--contacts" => &mut config.contacts_per_user,
+                "--interactions" => &mut config.interactions_per_contact,
+                "--concurrency" => &mut config.concurrency,
+                "--requests" => &mut config.requests,
+                other => return Err(format!("Unknown argument: {}", other)),
+            };
+            *target = args
+                .next()
+                .and_then(|v| v.parse().ok())
+                .ok_or_else(|| format!("{} expects a number", arg))?;
+        }
+        if config.users == 0 || config.concurrency == 0 {
+            return Err("--users and --concurrency must be at least 1".to_string());
+        }
+        Ok(config)
+    }
+}
+
+/// A synthetic user created for a load run
+#[derive(Debug, Clone)]
+pub struct SeededUser {
+    pub user_id: i32,
+    pub token: String,
+    pub contact_ids: Vec<i32>,
+}
+
+fn run_prefix(run_id: &str) -> String {
+    format!("load|{}|", run_id)
+}
+
+/// Insert users, contacts and interactions for a run and make their tokens valid
+pub async fn seed(
+    pool: &PgPool,
+    run_id: &str,
+    config: &LoadConfig,
+) -> Result<Vec<SeededUser>, sqlx::Error> {
+    let mut users = Vec::with_capacity(config.users);
+
+    for u in 0..config.users {
+        let auth0_id = format!("{}{}", run_prefix(run_id), u);
+        let user_id: i32 = sqlx::query_scalar(
+            "INSERT INTO users (auth0_id, name, email) VALUES ($1, $2, $3) RETURNING user_id",
+        )
+        .bind(&auth0_id)
+        .bind(format!("Load User {}", u))
+        .bind(format!("load-{}-{}@example.com", run_id, u))
+        .fetch_one(pool)
+        .await?;
+        let workspace_id: i32 = sqlx::query_scalar(
+            "INSERT INTO workspaces (user_id, name, is_default) VALUES ($1, $2, TRUE)
+             RETURNING workspace_id",
+        )
+        .bind(user_id)
+        .bind(DEFAULT_WORKSPACE_NAME)
+        .fetch_one(pool)
+        .await?;
+
+        let first_names: Vec<String> = (0..config.contacts_per_user)
+            .map(|c| format!("First{}", c))
+            .collect();
+        let last_names: Vec<String> = (0..config.contacts_per_user)
+            .map(|c| format!("Last{}", c))
+            .collect();
+        let contact_ids: Vec<i32> = sqlx::query_scalar(
+            "INSERT INTO contacts (workspace_id, first_name, last_name)
+             SELECT $1, f, l FROM UNNEST($2::text[], $3::text[]) AS t(f, l)
+             RETURNING contact_id",
+        )
+        .bind(workspace_id)
+        .bind(&first_names)
+        .bind(&last_names)
+        .fetch_all(pool)
+        .await?;
+
+        if config.interactions_per_contact > 0 {
+            sqlx::query(
+                "INSERT INTO interactions (workspace_id, contact_id, interaction_date, notes)
+                 SELECT $1, c, NOW() - (n * INTERVAL '9 days'), 'Load test interaction'
+                 FROM UNNEST($2::int[]) AS c, generate_series(1, $3) AS n",
+            )
+            .bind(workspace_id)
+            .bind(&contact_ids)
+            .bind(config.interactions_per_contact as i32)
+            .execute(pool)
+            .await?;
+        }
+
+        let token = format!("loadtest-{}-{}", run_id, u);
+        cache_token(
+            &token,
+            Auth0Claims {
+                sub: auth0_id,
+                email: None,
+                name: None,
+                iss: None,
+                aud: None,
+                exp: None,
+                iat: None,
+                auth_time: None,
+                scope: None,
+                permissions: None,
+            },
+        )
+        .await;
+
+        users.push(SeededUser {
+            user_id,
+            token,
+            contact_ids,
+        });
+    }
+
+    Ok(users)
+}
+
+/// Remove every row created for a run (contacts and history cascade from users)
+pub async fn cleanup(pool: &PgPool, run_id: &str) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM users WHERE auth0_id LIKE $1")
+        .bind(format!("{}%", run_prefix(run_id)))
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Scenario {
+    ListContacts,
+    GetContact,
+    ListTags,
+}
+
+impl Scenario {
+    const ALL: [Scenario; 3] = [
+        Scenario::ListContacts,
+        Scenario::GetContact,
+        Scenario::ListTags,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Scenario::ListContacts => "GET /v1/contacts",
+            Scenario::GetContact => "GET /v1/contacts/{id}",
+            Scenario::ListTags => "GET /v1/tags",
+        }
+    }
+}
+
+/// Latency summary for one scenario
+#[derive(Debug, Clone)]
+pub struct ScenarioReport {
+    pub name: &'static str,
+    pub requests: usize,
+    pub errors: usize,
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub struct LoadReport {
+    pub total_requests: usize,
+    pub elapsed: Duration,
+    pub scenarios: Vec<ScenarioReport>,
+}
+
+impl LoadReport {
+    pub fn requests_per_sec(&self) -> f64 {
+        self.total_requests as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+/// Nearest-rank percentile of an ascending list of samples
+pub fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Drive the API at `base_url` with `config.concurrency` workers until `config.requests`
+/// requests have been issued, cycling through scenarios and users
+pub async fn run(base_url: &str, users: Arc<Vec<SeededUser>>, config: &LoadConfig) -> LoadReport {
+    let client = reqwest::Client::new();
+    let counter = Arc::new(AtomicUsize::new(0));
+    let started = Instant::now();
+
+    let workers: Vec<_> = (0..config.concurrency)
+        .map(|_| {
+            let client = client.clone();
+            let counter = counter.clone();
+            let users = users.clone();
+            let base_url = base_url.to_string();
+            let total = config.requests;
+            tokio::spawn(async move {
+                let mut samples: Vec<(Scenario, Duration, bool)> = Vec::new();
+                loop {
+                    let n = counter.fetch_add(1, Ordering::Relaxed);
+                    if n >= total {
+                        break;
+                    }
+                    let user = &users[n % users.len()];
+                    let scenario = Scenario::ALL[n % Scenario::ALL.len()];
+                    let url = match scenario {
+                        Scenario::ListContacts => format!("{}/v1/contacts", base_url),
+                        Scenario::GetContact => {
+                            match user.contact_ids.get(n % user.contact_ids.len().max(1)) {
+                                Some(id) => format!("{}/v1/contacts/{}", base_url, id),
+                                None => format!("{}/v1/contacts", base_url),
+                            }
+                        }
+                        Scenario::ListTags => format!("{}/v1/tags", base_url),
+                    };
+
+                    let request_started = Instant::now();
+                    let ok = match client.get(&url).bearer_auth(&user.token).send().await {
+                        Ok(response) => {
+                            let ok = response.status().is_success();
+                            // Read the body so the timing includes serialization and transfer
+                            ok && response.bytes().await.is_ok()
+                        }
+                        Err(_) => false,
+                    };
+                    samples.push((scenario, request_started.elapsed(), ok));
+                }
+                samples
+            })
+        })
+        .collect();
+
+    let mut by_scenario: HashMap<Scenario, (Vec<Duration>, usize)> = HashMap::new();
+    for worker in workers {
+        for (scenario, latency, ok) in worker.await.unwrap_or_default() {
+            let entry = by_scenario.entry(scenario).or_default();
+            entry.0.push(latency);
+            if !ok {
+                entry.1 += 1;
+            }
+        }
+    }
+    let elapsed = started.elapsed();
+
+    let scenarios = Scenario::ALL
+        .iter()
+        .filter_map(|scenario| {
+            let (mut latencies, errors) = by_scenario.remove(scenario)?;
+            latencies.sort();
+            Some(ScenarioReport {
+                name: scenario.name(),
+                requests: latencies.len(),
+                errors,
+                p50: percentile(&latencies, 50.0),
+                p90: percentile(&latencies, 90.0),
+                p99: percentile(&latencies, 99.0),
+                max: latencies.last().copied().unwrap_or_default(),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    LoadReport {
+        total_requests: scenarios.iter().map(|s| s.requests).sum(),
+        elapsed,
+        scenarios,
+    }
+}
+
+/// Print a report as a plain text table
+pub fn print_report(report: &LoadReport) {
+    println!(
+        "{:<24} {:>8} {:>7} {:>10} {:>10} {:>10} {:>10}",
+        "scenario", "requests", "errors", "p50 ms", "p90 ms", "p99 ms", "max ms"
+    );
+    for s in &report.scenarios {
+        println!(
+            "{:<24} {:>8} {:>7} {:>10.2} {:>10.2} {:>10.2} {:>10.2}",
+            s.name,
+            s.requests,
+            s.errors,
+            s.p50.as_secs_f64() * 1000.0,
+            s.p90.as_secs_f64() * 1000.0,
+            s.p99.as_secs_f64() * 1000.0,
+            s.max.as_secs_f64() * 1000.0,
+        );
+    }
+    println!(
+        "{} requests in {:.2}s ({:.1} req/s)",
+        report.total_requests,
+        report.elapsed.as_secs_f64(),
+        report.requests_per_sec()
+    );
+}
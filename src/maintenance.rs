@@ -0,0 +1,166 @@
+//! Operational read-only mode for migrations, restores and storage failovers.
+//!
+//! While read-only, every request that could change data is answered with 503 and a
+//! Retry-After header; reads keep working. The mode starts from READ_ONLY_MODE and can be
+//! flipped at runtime through /admin/maintenance, which requires the ADMIN_TOKEN bearer token
+//! and is disabled when no token is configured.
+
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::http::header::{HeaderValue, RETRY_AFTER};
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpRequest, HttpResponse, Responder, get, put, web};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+/// Retry-After sent while read-only, unless READ_ONLY_RETRY_AFTER says otherwise
+pub const DEFAULT_RETRY_AFTER_SECS: u64 = 300;
+
+/// Paths that stay writable in read-only mode, so it can be switched off again
+const EXEMPT_PREFIXES: &[&str] = &["/admin/"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MaintenanceStatus {
+    pub read_only: bool,
+    #[serde(default = "default_retry_after")]
+    pub retry_after_secs: u64,
+}
+
+fn default_retry_after() -> u64 {
+    DEFAULT_RETRY_AFTER_SECS
+}
+
+pub struct Maintenance {
+    read_only: AtomicBool,
+    retry_after_secs: AtomicU64,
+    admin_token: Option<String>,
+}
+
+impl Maintenance {
+    pub fn new(status: MaintenanceStatus, admin_token: Option<String>) -> Self {
+        Maintenance {
+            read_only: AtomicBool::new(status.read_only),
+            retry_after_secs: AtomicU64::new(status.retry_after_secs),
+            admin_token: admin_token.filter(|t| !t.is_empty()),
+        }
+    }
+
+    /// Read READ_ONLY_MODE (true/false), READ_ONLY_RETRY_AFTER (seconds) and ADMIN_TOKEN
+    pub fn from_env() -> Self {
+        let status = MaintenanceStatus {
+            read_only: std::env::var("READ_ONLY_MODE").is_ok_and(|v| v == "true"),
+            retry_after_secs: std::env::var("READ_ONLY_RETRY_AFTER")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_RETRY_AFTER_SECS),
+        };
+        Maintenance::new(status, std::env::var("ADMIN_TOKEN").ok())
+    }
+
+    pub fn status(&self) -> MaintenanceStatus {
+        MaintenanceStatus {
+            read_only: self.read_only.load(Ordering::Relaxed),
+            retry_after_secs: self.retry_after_secs.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn set(&self, status: MaintenanceStatus) {
+        self.retry_after_secs
+            .store(status.retry_after_secs, Ordering::Relaxed);
+        self.read_only.store(status.read_only, Ordering::Relaxed);
+    }
+
+    /// Compare digests so the check takes the same time however much of the token matches
+    fn is_admin(&self, req: &HttpRequest) -> bool {
+        let presented = req
+            .headers()
+            .get("Authorization")
+            .and_then(|h| h.to_str().ok())
+            .and_then(|s| s.strip_prefix("Bearer "));
+        match (&self.admin_token, presented) {
+            (Some(expected), Some(presented)) => {
+                Sha256::digest(expected.as_bytes()) == Sha256::digest(presented.as_bytes())
+            }
+            _ => false,
+        }
+    }
+}
+
+/// GET, HEAD and OPTIONS, the methods that never change data
+pub(crate) fn is_read(method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+/// Middleware rejecting mutating requests with 503 while in read-only mode.
+/// Requires a `web::Data<Maintenance>` in app data.
+pub async fn read_only(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let status = req
+        .app_data::<web::Data<Maintenance>>()
+        .map(|m| m.status())
+        .filter(|s| s.read_only);
+    let status = match status {
+        Some(status)
+            if !is_read(req.method())
+                && !EXEMPT_PREFIXES.iter().any(|p| req.path().starts_with(p)) =>
+        {
+            status
+        }
+        _ => return next.call(req).await.map(|res| res.map_into_boxed_body()),
+    };
+
+    let mut response = HttpResponse::ServiceUnavailable().json(serde_json::json!({
+        "error": "The service is in read-only maintenance mode",
+        "retry_after": status.retry_after_secs
+    }));
+    response
+        .headers_mut()
+        .insert(RETRY_AFTER, HeaderValue::from(status.retry_after_secs));
+    Ok(req.into_response(response))
+}
+
+fn forbidden_unless_admin(req: &HttpRequest, maintenance: &Maintenance) -> Option<HttpResponse> {
+    if maintenance.admin_token.is_none() {
+        return Some(HttpResponse::NotFound().body("Admin endpoints are disabled"));
+    }
+    if !maintenance.is_admin(req) {
+        return Some(HttpResponse::Unauthorized().body("Invalid admin token"));
+    }
+    None
+}
+
+#[get("/admin/maintenance")]
+pub async fn get_maintenance(
+    req: HttpRequest,
+    maintenance: web::Data<Maintenance>,
+) -> impl Responder {
+    if let Some(response) = forbidden_unless_admin(&req, &maintenance) {
+        return response;
+    }
+    HttpResponse::Ok().json(maintenance.status())
+}
+
+#[put("/admin/maintenance")]
+pub async fn set_maintenance(
+    req: HttpRequest,
+    maintenance: web::Data<Maintenance>,
+    status: web::Json<MaintenanceStatus>,
+) -> impl Responder {
+    if let Some(response) = forbidden_unless_admin(&req, &maintenance) {
+        return response;
+    }
+    maintenance.set(status.into_inner());
+    println!(
+        "Read-only mode {}",
+        if maintenance.status().read_only {
+            "enabled"
+        } else {
+            "disabled"
+        }
+    );
+    HttpResponse::Ok().json(maintenance.status())
+}
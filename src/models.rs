@@ -0,0 +1,1831 @@
+//! Request and response types shared by the handlers and the repository layer
+
+use crate::health;
+use crate::reminders::{next_occurrence, upcoming_age};
+use crate::timezone::LocalDates;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::collections::HashMap;
+use time::{Date, OffsetDateTime, PrimitiveDateTime};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, FromRow, JsonSchema)]
+pub struct Contact {
+    pub contact_id: i32,
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    pub email: Option<String>,
+    pub phone: Option<String>,
+    /// `phone` in E.164 form (`+14155550123`), when it is a valid number
+    #[serde(default)]
+    pub phone_e164: Option<String>,
+    pub short_note: Option<String>,
+    pub notes: Option<String>,
+    /// Photo uploaded for the contact, or found by avatar enrichment
+    pub avatar_url: Option<String>,
+    /// Hidden from the contact list and suggestions unless asked for
+    #[serde(default)]
+    pub archived: bool,
+    /// Days the user wants between interactions with the contact
+    #[serde(default)]
+    pub desired_frequency_days: Option<i32>,
+    /// When, where and how the user met the contact
+    #[serde(default)]
+    #[sqlx(json(nullable))]
+    pub met_at: Option<MetAt>,
+    /// The contact who introduced the user to this one
+    #[serde(default)]
+    pub introduced_by_contact_id: Option<i32>,
+    /// Where the contact lives, such as a city
+    #[serde(default)]
+    pub location: Option<String>,
+    /// `location` geocoded, when a geocoder is configured and found it
+    #[serde(default)]
+    pub latitude: Option<f64>,
+    #[serde(default)]
+    pub longitude: Option<f64>,
+    /// Last change to the contact or anything attached to it; sent as Last-Modified
+    #[serde(skip)]
+    pub updated_at: Option<PrimitiveDateTime>,
+}
+
+/// A contact with everything attached to it, as loaded for a `ContactResponse`
+#[derive(Debug, Clone, FromRow)]
+pub struct ContactDetails {
+    #[sqlx(flatten)]
+    pub contact: Contact,
+    #[sqlx(json)]
+    pub tags: Vec<Tag>,
+    /// Every interaction the contact took part in
+    #[sqlx(json)]
+    pub interactions: Vec<Interaction>,
+    #[sqlx(json)]
+    pub occasions: Vec<Occasion>,
+}
+
+impl ContactDetails {
+    /// Attach tags, interactions and occasions loaded separately to their contacts, keeping
+    /// the contacts' order. Interactions go under every contact that took part.
+    pub fn group(
+        contacts: Vec<Contact>,
+        tags: impl IntoIterator<Item = (i32, Tag)>,
+        interactions: impl IntoIterator<Item = Interaction>,
+        occasions: impl IntoIterator<Item = Occasion>,
+    ) -> Vec<ContactDetails> {
+        let mut tags_map: HashMap<i32, Vec<Tag>> = HashMap::new();
+        for (contact_id, tag) in tags {
+            tags_map.entry(contact_id).or_default().push(tag);
+        }
+
+        let mut interactions_map: HashMap<i32, Vec<Interaction>> = HashMap::new();
+        for interaction in interactions {
+            for contact_id in interaction.participants() {
+                interactions_map
+                    .entry(contact_id)
+                    .or_default()
+                    .push(interaction.clone());
+            }
+        }
+
+        let mut occasions_map: HashMap<i32, Vec<Occasion>> = HashMap::new();
+        for occasion in occasions {
+            if let Some(contact_id) = occasion.contact_id {
+                occasions_map.entry(contact_id).or_default().push(occasion);
+            }
+        }
+
+        contacts
+            .into_iter()
+            .map(|contact| {
+                let contact_id = contact.contact_id;
+                ContactDetails {
+                    contact,
+                    tags: tags_map.remove(&contact_id).unwrap_or_default(),
+                    interactions: interactions_map.remove(&contact_id).unwrap_or_default(),
+                    occasions: occasions_map.remove(&contact_id).unwrap_or_default(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Where a contact's displayed avatar came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum AvatarSource {
+    Uploaded,
+    Gravatar,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct ContactResponse {
+    pub contact: Contact,
+    pub tags: Vec<Tag>,
+    pub interactions: Vec<Interaction>,
+    pub occasions: Vec<Occasion>,
+    pub predicted_contact_priority: Option<f32>,
+    /// Days since the last interaction beyond `desired_frequency_days`; negative while the
+    /// contact is not due yet. None without a desired frequency or any interaction.
+    #[serde(default)]
+    pub overdue_days: Option<i64>,
+    /// Avatar to display: the uploaded photo, else a Gravatar when that fallback is enabled
+    pub avatar_url: Option<String>,
+    pub avatar_source: Option<AvatarSource>,
+    /// How the relationship is doing, see `health`; None without any interaction
+    #[serde(default)]
+    pub health: Option<RelationshipHealth>,
+    /// Sent with `?include=summary`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<ContactSummary>,
+}
+
+/// Whether the gaps between a contact's interactions are shrinking, holding or growing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthTrend {
+    Improving,
+    Steady,
+    Declining,
+}
+
+/// How a relationship is doing, combining recency, the trend of the gaps between interactions
+/// and what kind of interactions they were
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, JsonSchema)]
+pub struct RelationshipHealth {
+    /// 0 for a neglected relationship to 100 for a thriving one
+    pub score: i32,
+    /// None until interactions on three different days give two gaps to compare
+    pub trend: Option<HealthTrend>,
+    /// Average gap between the recent interactions over the average between earlier ones;
+    /// above 1 the gaps are growing
+    pub gap_growth: Option<f64>,
+}
+
+/// A contact from `GET /contacts/declining`
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct DecliningContact {
+    #[serde(flatten)]
+    pub contact: Contact,
+    pub health: RelationshipHealth,
+}
+
+/// When the user last saw a contact and next has an occasion of theirs, for summary chips in
+/// the contact list without its interactions and occasions
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, JsonSchema)]
+pub struct ContactSummary {
+    /// When the latest interaction the contact took part in happened
+    #[serde(default, with = "datetime_format::option")]
+    #[schemars(with = "Option<String>")]
+    pub last_interaction_at: Option<OffsetDateTime>,
+    /// The next day one of the contact's occasions falls on, today or later in the user's
+    /// timezone
+    #[serde(default, with = "date_format::option")]
+    #[schemars(with = "Option<String>")]
+    pub next_occasion_at: Option<Date>,
+}
+
+impl ContactResponse {
+    /// Calculate predicted contact priority based on interactions and occasions
+    /// This is a placeholder for future implementation
+    /// Currently, we calculate the average number of days between interactions
+    /// and use that to estimate how soon the next interaction should be,
+    /// unless the contact has a desired frequency, which is used instead
+    /// We also increase the score if an occasion is coming up
+    /// Days are counted in the user's timezone, and interactions are taken in date order
+    /// whatever order they come in, matching the contact_priority SQL function
+    pub fn new(
+        contact: Contact,
+        tags: Vec<Tag>,
+        interactions: Vec<Interaction>,
+        occasions: Vec<Occasion>,
+        dates: &LocalDates,
+    ) -> ContactResponse {
+        let today = dates.today();
+        let mut occasions = occasions;
+        for occasion in &mut occasions {
+            occasion.upcoming_age = upcoming_age(occasion, today);
+        }
+        let days_to_closest_occasion = occasions
+            .iter()
+            .filter_map(|occasion| next_occurrence(occasion, today))
+            .map(|date| (date - today).whole_days())
+            .min();
+
+        let interaction_days: Vec<Date> = interactions
+            .iter()
+            .map(|i| dates.date_of(i.interaction_date))
+            .collect();
+        let first_interaction = interaction_days.iter().min();
+        let last_interaction = interaction_days.iter().max();
+        let days_since_last_interaction = last_interaction.map(|date| (today - *date).whole_days());
+        let overdue_days = contact
+            .desired_frequency_days
+            .zip(days_since_last_interaction)
+            .map(|(frequency, days)| days - i64::from(frequency));
+
+        let offset_from_last_interaction = if let Some(overdue) = overdue_days {
+            Some(overdue as f32)
+        } else if let (Some(first), Some(last), true) =
+            (first_interaction, last_interaction, interactions.len() >= 2)
+        {
+            // The gaps between consecutive interactions add up to the first-to-last span
+            let total_days = (*last - *first).whole_days();
+            let avg_days = total_days as f32 / (interactions.len() - 1) as f32;
+            let delta = today - *last;
+            Some(delta.whole_days() as f32 - avg_days)
+        } else {
+            None
+        };
+
+        let predicted_contact_priority =
+            match (days_to_closest_occasion, offset_from_last_interaction) {
+                (Some(occ_days), Some(int_days)) => {
+                    let occasion_score = if occ_days < 7 {
+                        10.0
+                    } else if occ_days < 30 {
+                        5.0
+                    } else if occ_days < 90 {
+                        1.0
+                    } else {
+                        0.0
+                    };
+                    Some(int_days + occasion_score)
+                }
+                (Some(occ_days), None) => {
+                    // Only occasion data available
+                    let occasion_score = if occ_days < 7 {
+                        10.0
+                    } else if occ_days < 30 {
+                        5.0
+                    } else if occ_days < 90 {
+                        1.0
+                    } else {
+                        0.0
+                    };
+                    Some(occasion_score)
+                }
+                (None, Some(int_days)) => {
+                    // Only interaction data available
+                    Some(int_days)
+                }
+                (None, None) => None, // No data available
+            };
+
+        let avatar_url = contact.avatar_url.clone();
+        let avatar_source = avatar_url.as_ref().map(|_| AvatarSource::Uploaded);
+        let health = health::assess(contact.desired_frequency_days, &interactions, dates);
+
+        ContactResponse {
+            contact,
+            tags,
+            interactions,
+            occasions,
+            predicted_contact_priority,
+            overdue_days,
+            avatar_url,
+            avatar_source,
+            health,
+            summary: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+pub struct NewContactRequest {
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    pub email: Option<String>,
+    pub phone: Option<String>,
+    pub short_note: Option<String>,
+    pub notes: Option<String>,
+    pub avatar_url: Option<String>,
+    /// Days the user wants between interactions, from 1 to 365
+    #[serde(default)]
+    pub desired_frequency_days: Option<i32>,
+    /// An object with no parts set is the same as null
+    #[serde(default, deserialize_with = "MetAt::deserialize_non_empty")]
+    pub met_at: Option<MetAt>,
+    /// Another contact in the same workspace
+    #[serde(default)]
+    pub introduced_by_contact_id: Option<i32>,
+    /// Geocoded to the contact's coordinates when a geocoder is configured
+    #[serde(default)]
+    pub location: Option<String>,
+}
+
+/// The fields of a saved contact, as a request that would save it unchanged
+impl From<&Contact> for NewContactRequest {
+    fn from(contact: &Contact) -> NewContactRequest {
+        NewContactRequest {
+            first_name: contact.first_name.clone(),
+            last_name: contact.last_name.clone(),
+            email: contact.email.clone(),
+            phone: contact.phone.clone(),
+            short_note: contact.short_note.clone(),
+            notes: contact.notes.clone(),
+            avatar_url: contact.avatar_url.clone(),
+            desired_frequency_days: contact.desired_frequency_days,
+            met_at: contact.met_at.clone(),
+            introduced_by_contact_id: contact.introduced_by_contact_id,
+            location: contact.location.clone(),
+        }
+    }
+}
+
+/// One line such as `Alice Zhang alice@x.com #college @sf birthday:1990-04-02`
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct QuickAddRequest {
+    pub text: String,
+}
+
+/// A birthday or anniversary named in a quick-add line, repeating every year
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct QuickAddOccasion {
+    pub occasion_type: OccasionType,
+    /// In the current year when the line gave no year
+    #[serde(with = "date_format")]
+    #[schemars(with = "String")]
+    pub date: time::Date,
+    /// The year of a birthday given with one
+    pub birth_year: Option<i32>,
+}
+
+/// How a quick-add line was read
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, JsonSchema)]
+pub struct QuickAddParse {
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    pub email: Option<String>,
+    pub phone: Option<String>,
+    pub location: Option<String>,
+    pub short_note: Option<String>,
+    pub desired_frequency_days: Option<i32>,
+    /// Tag names, each once whatever its case
+    pub tags: Vec<String>,
+    pub occasions: Vec<QuickAddOccasion>,
+}
+
+/// What `POST /quick-add` read from the line and what it saved
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct QuickAddResponse {
+    pub parsed: QuickAddParse,
+    /// The contact created; None for a dry run
+    pub contact: Option<Contact>,
+    /// Tags put on the contact. A dry run lists those the workspace already has.
+    pub tag_ids: Vec<i32>,
+    /// Tags the workspace didn't have, created unless this is a dry run
+    pub new_tags: Vec<String>,
+    pub occasion_ids: Vec<i32>,
+    pub dry_run: bool,
+}
+
+/// A point on the globe, in degrees
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, JsonSchema)]
+pub struct Coordinates {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+/// A contact found by /contacts/nearby
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct NearbyContact {
+    #[serde(flatten)]
+    pub contact: Contact,
+    /// Great-circle distance from the searched point
+    pub distance_km: f64,
+}
+
+/// How the user met a contact; every part is optional
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq, JsonSchema)]
+pub struct MetAt {
+    #[serde(default, with = "date_format::option")]
+    #[schemars(with = "Option<String>")]
+    pub date: Option<time::Date>,
+    pub place: Option<String>,
+    /// Such as "Dana's birthday party" or "Rust meetup"
+    pub context: Option<String>,
+}
+
+impl MetAt {
+    pub fn is_empty(&self) -> bool {
+        self.date.is_none() && self.place.is_none() && self.context.is_none()
+    }
+
+    fn deserialize_non_empty<'de, D>(deserializer: D) -> Result<Option<MetAt>, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let met_at = Option::<MetAt>::deserialize(deserializer)?;
+        Ok(met_at.filter(|m| !m.is_empty()))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct Tag {
+    pub tag_id: i32,
+    pub name: String,
+    pub color: Option<String>,
+    pub details: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct NewTagRequest {
+    pub name: String,
+    pub color: Option<String>,
+    pub details: Option<String>,
+}
+
+/// The colors tags saved without one are given
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct TagPalette {
+    pub colors: Vec<String>,
+}
+
+/// A tag with the number of contacts it's attached to
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct TagUsage {
+    #[serde(flatten)]
+    pub tag: Tag,
+    pub contact_count: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct TagResponse {
+    pub tags: Vec<TagUsage>,
+}
+
+/// A tag and the emails of the contacts it's attached to, which is how they're found again
+/// on import. Contacts without an email are left out.
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct TagExport {
+    pub name: String,
+    pub color: Option<String>,
+    pub details: Option<String>,
+    #[serde(default)]
+    pub contact_emails: Vec<String>,
+}
+
+/// The workspace's tags and tag assignments, from `GET /tags/export` and for `POST /tags/import`
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct TagArchive {
+    pub tags: Vec<TagExport>,
+}
+
+/// What importing a [`TagArchive`] did, or would do for a dry run
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct TagImportReport {
+    /// Tags created; empty for a dry run, which lists them under `new_tags`
+    pub created_tag_ids: Vec<i32>,
+    /// Tags already in the workspace under the same name, left as they were
+    pub existing_tag_ids: Vec<i32>,
+    /// Tags the workspace didn't have, created unless this is a dry run
+    pub new_tags: Vec<String>,
+    /// Tag assignments now in place, including those that already were
+    pub tagged_count: usize,
+    /// Emails matching no contact in the workspace
+    pub unmatched_emails: Vec<String>,
+    pub dry_run: bool,
+}
+
+pub mod date_format {
+    use serde::{self, Deserialize, Deserializer, Serializer};
+    use time::Date;
+    use time::macros::format_description;
+
+    const FORMAT: &[time::format_description::BorrowedFormatItem<'static>] =
+        format_description!("[year]-[month]-[day]");
+
+    pub fn serialize<S>(date: &Date, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let s = date.format(&FORMAT).map_err(serde::ser::Error::custom)?;
+        serializer.serialize_str(&s)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Date, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        Date::parse(&s, &FORMAT).map_err(serde::de::Error::custom)
+    }
+
+    pub mod option {
+        use serde::{Deserialize, Deserializer, Serializer};
+        use time::Date;
+
+        pub fn serialize<S>(date: &Option<Date>, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+        {
+            match date {
+                Some(date) => super::serialize(date, serializer),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<Date>, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            #[derive(Deserialize)]
+            struct Wrapper(#[serde(with = "super")] Date);
+            Ok(Option::<Wrapper>::deserialize(deserializer)?.map(|Wrapper(date)| date))
+        }
+    }
+}
+
+/// Timestamps are written as RFC 3339 in UTC. RFC 3339 input with any offset is accepted and
+/// converted to UTC; input without an offset (the format used before timezones were
+/// supported) is taken to be UTC.
+pub mod datetime_format {
+    use serde::{self, Deserialize, Deserializer, Serializer};
+    use time::format_description::well_known::Rfc3339;
+    use time::macros::format_description;
+    use time::{OffsetDateTime, PrimitiveDateTime, UtcOffset};
+
+    const LEGACY_FORMAT: &[time::format_description::BorrowedFormatItem<'static>] =
+        format_description!("[year]-[month]-[day]T[hour]:[minute]:[second]");
+
+    pub fn serialize<S>(dt: &OffsetDateTime, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let s = dt
+            .to_offset(UtcOffset::UTC)
+            .format(&Rfc3339)
+            .map_err(serde::ser::Error::custom)?;
+        serializer.serialize_str(&s)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<OffsetDateTime, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        let dt = match OffsetDateTime::parse(&s, &Rfc3339) {
+            Ok(dt) => dt,
+            Err(e) => PrimitiveDateTime::parse(&s, &LEGACY_FORMAT)
+                .map_err(|_| serde::de::Error::custom(e))?
+                .assume_utc(),
+        };
+        Ok(dt.to_offset(UtcOffset::UTC))
+    }
+
+    pub mod option {
+        use serde::{Deserialize, Deserializer, Serializer};
+        use time::OffsetDateTime;
+
+        pub fn serialize<S>(dt: &Option<OffsetDateTime>, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+        {
+            match dt {
+                Some(dt) => super::serialize(dt, serializer),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<OffsetDateTime>, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            #[derive(Deserialize)]
+            struct Wrapper(#[serde(with = "super")] OffsetDateTime);
+            Ok(Option::<Wrapper>::deserialize(deserializer)?.map(|Wrapper(dt)| dt))
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct Interaction {
+    pub interaction_id: i32,
+    /// None once the contact was deleted and the interaction kept, unassigned
+    pub contact_id: Option<i32>,
+    #[serde(with = "datetime_format")]
+    #[schemars(with = "String")]
+    pub interaction_date: OffsetDateTime,
+    pub notes: Option<String>,
+    pub follow_up_priority: Option<i32>,
+    /// A built-in type ("call", "email", "meeting", "message") or one of the user's own
+    #[serde(default)]
+    pub interaction_type: Option<String>,
+    /// Other contacts who took part, besides `contact_id`
+    #[serde(default)]
+    pub contact_ids: Vec<i32>,
+    /// Tags on the interaction, by id
+    #[serde(default)]
+    pub tag_ids: Vec<i32>,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct NewInteractionRequest {
+    pub contact_id: i32,
+    #[serde(with = "datetime_format")]
+    #[schemars(with = "String")]
+    pub interaction_date: OffsetDateTime,
+    pub notes: Option<String>,
+    pub follow_up_priority: Option<i32>,
+    /// A built-in type ("call", "email", "meeting", "message") or one of the user's own
+    #[serde(default)]
+    pub interaction_type: Option<String>,
+    /// Other contacts who took part, besides `contact_id`
+    #[serde(default)]
+    pub contact_ids: Vec<i32>,
+    /// On creation with a `follow_up_priority`, also add a "Follow up" occasion this many days
+    /// after the interaction. Ignored on updates.
+    #[serde(default)]
+    pub follow_up_in_days: Option<i32>,
+}
+
+/// A new interaction, with the follow-up occasion added for it
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct CreatedInteraction {
+    #[serde(flatten)]
+    pub interaction: Interaction,
+    pub follow_up_occasion_id: Option<i32>,
+}
+
+impl Interaction {
+    /// Every contact the interaction belongs to: `contact_id` first, then the others
+    pub fn participants(&self) -> impl Iterator<Item = i32> + '_ {
+        self.contact_id
+            .into_iter()
+            .chain(self.contact_ids.iter().copied())
+    }
+}
+
+/// An interaction type added by the user
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema)]
+pub struct CustomInteractionType {
+    pub type_id: i32,
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct NewInteractionTypeRequest {
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct InteractionTypesResponse {
+    pub builtin: Vec<String>,
+    pub custom: Vec<CustomInteractionType>,
+}
+
+/// Where an attachment's transcription into its interaction's notes stands
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "transcription_status", rename_all = "lowercase")]
+pub enum TranscriptionStatus {
+    Pending,
+    Done,
+    Failed,
+}
+
+/// A file attached to an interaction; the file itself is downloaded separately
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Attachment {
+    pub attachment_id: i32,
+    pub interaction_id: i32,
+    pub file_name: String,
+    pub content_type: String,
+    pub size_bytes: i32,
+    /// None unless the file was sent for transcription
+    pub transcription: Option<TranscriptionStatus>,
+    #[serde(with = "datetime_format")]
+    #[schemars(with = "String")]
+    pub created_at: OffsetDateTime,
+}
+
+/// An uploaded file to attach to an interaction
+#[derive(Debug, Clone)]
+pub struct NewAttachment {
+    pub file_name: String,
+    pub content_type: String,
+    pub data: Vec<u8>,
+    /// Queue the file for transcription into the interaction's notes
+    pub transcribe: bool,
+}
+
+/// An attachment's file, for download
+#[derive(Debug, Clone)]
+pub struct AttachmentFile {
+    pub file_name: String,
+    pub content_type: String,
+    pub data: Vec<u8>,
+}
+
+/// An attachment waiting for the transcription job
+#[derive(Debug, Clone)]
+pub struct PendingTranscription {
+    pub attachment_id: i32,
+    pub workspace_id: i32,
+    pub file_name: String,
+    pub content_type: String,
+    pub data: Vec<u8>,
+    /// Failed attempts so far
+    pub attempts: i32,
+}
+
+/// A contact with an email but no avatar, due for avatar enrichment
+#[derive(Debug, Clone)]
+pub struct PendingAvatarLookup {
+    pub contact_id: i32,
+    pub workspace_id: i32,
+    pub email: String,
+}
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema, sqlx::Type,
+)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "occasion_kind", rename_all = "lowercase")]
+pub enum OccasionType {
+    /// Repeats every year; can carry a birth year for the contact's age
+    Birthday,
+    Anniversary,
+    #[default]
+    Custom,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct Occasion {
+    pub occasion_id: i32,
+    /// None once the contact was deleted and the occasion kept, unassigned
+    pub contact_id: Option<i32>,
+    pub name: String,
+    #[serde(with = "date_format")]
+    #[schemars(with = "String")]
+    pub date: time::Date,
+    pub recurring: Option<bool>,
+    pub recurring_interval: Option<i32>,
+    pub details: Option<String>,
+    #[serde(default)]
+    pub occasion_type: OccasionType,
+    pub birth_year: Option<i32>,
+    /// Days ahead of the occasion to remind, e.g. `[14, 3, 0]`; None uses the user's
+    /// `remind_days_before` preference
+    #[serde(default)]
+    pub remind_days_before: Option<Vec<i32>>,
+    /// Age the contact turns on their next birthday, when the birth year is known.
+    /// Computed for responses, never stored.
+    #[serde(default)]
+    pub upcoming_age: Option<i32>,
+    /// The interaction this is the follow-up of, for occasions created by `follow_up_in_days`
+    #[serde(default)]
+    pub interaction_id: Option<i32>,
+}
+
+/// History kept from deleted contacts under CONTACT_DELETE_BEHAVIOR=orphan, as listed by
+/// /unassigned. Updating one with a `contact_id` assigns it to that contact.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, JsonSchema)]
+pub struct Unassigned {
+    pub interactions: Vec<Interaction>,
+    pub occasions: Vec<Occasion>,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct NewOccasionRequest {
+    pub contact_id: i32,
+    pub name: String,
+    #[serde(with = "date_format")]
+    #[schemars(with = "String")]
+    pub date: time::Date,
+    pub recurring: bool,
+    pub recurring_interval: Option<i32>,
+    pub details: Option<String>,
+    #[serde(default)]
+    pub occasion_type: OccasionType,
+    /// Only allowed on birthdays
+    #[serde(default)]
+    pub birth_year: Option<i32>,
+    /// Days ahead of the occasion to remind; the user's default when left out
+    #[serde(default)]
+    pub remind_days_before: Option<Vec<i32>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "social_platform", rename_all = "lowercase")]
+pub enum SocialPlatform {
+    Linkedin,
+    Twitter,
+    Github,
+    Instagram,
+    Facebook,
+    Mastodon,
+    /// A personal site or blog
+    Website,
+    Other,
+}
+
+/// A contact's profile on another site
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct SocialProfile {
+    pub social_profile_id: i32,
+    pub contact_id: i32,
+    pub platform: SocialPlatform,
+    pub url: String,
+    /// The profile page's Open Graph title and image, when link previews are enabled.
+    /// Computed for responses, never stored.
+    #[serde(default)]
+    pub preview: Option<LinkPreview>,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct NewSocialProfileRequest {
+    pub contact_id: i32,
+    pub platform: SocialPlatform,
+    /// An http or https URL
+    pub url: String,
+}
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema, sqlx::Type,
+)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "gift_status", rename_all = "lowercase")]
+pub enum GiftStatus {
+    #[default]
+    Idea,
+    Purchased,
+    Given,
+}
+
+/// A gift thought of for a contact
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct GiftIdea {
+    pub gift_idea_id: i32,
+    pub contact_id: i32,
+    /// The occasion it is meant for, if any
+    pub occasion_id: Option<i32>,
+    pub idea: String,
+    pub status: GiftStatus,
+    pub price_cents: Option<i32>,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct NewGiftIdeaRequest {
+    pub contact_id: i32,
+    /// One of the contact's occasions
+    #[serde(default)]
+    pub occasion_id: Option<i32>,
+    pub idea: String,
+    #[serde(default)]
+    pub status: GiftStatus,
+    #[serde(default)]
+    pub price_cents: Option<i32>,
+}
+
+/// A named roster of contacts, such as a book club. Unlike a tag, membership is an explicit
+/// list the user keeps in order.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct Group {
+    pub group_id: i32,
+    pub name: String,
+    pub description: Option<String>,
+    /// Members, in the group's order
+    pub contact_ids: Vec<i32>,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct NewGroupRequest {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+/// The whole roster of a group, in order
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct GroupMembersRequest {
+    pub contact_ids: Vec<i32>,
+}
+
+/// An interaction with the whole group, such as a dinner party
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct GroupInteractionRequest {
+    #[serde(with = "datetime_format")]
+    #[schemars(with = "String")]
+    pub interaction_date: OffsetDateTime,
+    pub notes: Option<String>,
+    pub follow_up_priority: Option<i32>,
+    /// A built-in type ("call", "email", "meeting", "message") or one of the user's own
+    #[serde(default)]
+    pub interaction_type: Option<String>,
+}
+
+/// A group's digest as queued for emailing to the user
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct GroupDigest {
+    pub to: String,
+    pub subject: String,
+    pub text: String,
+}
+
+/// `og:title` and `og:image` of a page, for showing a link as a chip
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq, JsonSchema)]
+pub struct LinkPreview {
+    pub title: Option<String>,
+    pub image_url: Option<String>,
+}
+
+/// A contact suggested by the weekly reconnect rotation
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct ReconnectPick {
+    pub contact: Contact,
+    pub tags: Vec<Tag>,
+    /// None if there has never been an interaction
+    pub days_since_last_interaction: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct ReconnectResponse {
+    /// Monday the picks are for
+    #[serde(with = "date_format")]
+    #[schemars(with = "String")]
+    pub week_of: time::Date,
+    pub contacts: Vec<ReconnectPick>,
+}
+
+/// A contact left out of the user's suggestions
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SuggestionSnooze {
+    pub contact_id: i32,
+    /// First day the contact can be suggested again; None when dismissed until the next
+    /// interaction with them
+    #[serde(default, with = "date_format::option")]
+    #[schemars(with = "Option<String>")]
+    pub snoozed_until: Option<time::Date>,
+}
+
+/// Per-user settings that shape reminders
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Preferences {
+    /// ISO 3166-1 alpha-2 code choosing the holiday calendar
+    #[serde(default)]
+    pub country: Option<String>,
+    /// ISO weekday numbers (1 = Monday, 7 = Sunday) on which no reminders fire
+    #[serde(default)]
+    pub quiet_weekdays: Vec<i32>,
+    /// Tags whose contacts get a "send holiday greetings" reminder before major holidays
+    #[serde(default)]
+    pub greeting_tag_ids: Vec<i32>,
+    /// IANA timezone name, e.g. "Europe/Berlin", deciding when the user's days start.
+    /// UTC when unset.
+    #[serde(default)]
+    pub timezone: Option<String>,
+    /// Days ahead of an occasion to remind, for occasions that don't say; on the day itself
+    /// unless set
+    #[serde(default = "default_remind_days_before")]
+    pub remind_days_before: Vec<i32>,
+}
+
+fn default_remind_days_before() -> Vec<i32> {
+    vec![0]
+}
+
+impl Default for Preferences {
+    fn default() -> Self {
+        Preferences {
+            country: None,
+            quiet_weekdays: Vec::new(),
+            greeting_tag_ids: Vec::new(),
+            timezone: None,
+            remind_days_before: default_remind_days_before(),
+        }
+    }
+}
+
+/// Days between check-ins for contacts without a cadence of their own
+pub const DEFAULT_REMINDER_CADENCE_DAYS: i32 = 30;
+
+/// Channels the user wants notifications on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct NotificationSettings {
+    pub email: bool,
+    pub push: bool,
+    /// Email the weekly review every Monday; needs `email` too
+    #[serde(default)]
+    pub weekly_review: bool,
+}
+
+impl Default for NotificationSettings {
+    fn default() -> Self {
+        NotificationSettings {
+            email: true,
+            push: false,
+            weekly_review: false,
+        }
+    }
+}
+
+/// The authenticated user as returned by /me
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct UserProfile {
+    pub user_id: i32,
+    pub name: String,
+    pub email: String,
+    /// The same setting as `timezone` in /preferences
+    pub timezone: Option<String>,
+    pub notifications: NotificationSettings,
+    pub default_reminder_cadence_days: i32,
+}
+
+impl UserProfile {
+    /// A user who has not changed any settings yet
+    pub fn defaults(user_id: i32, name: String, email: String) -> Self {
+        UserProfile {
+            user_id,
+            name,
+            email,
+            timezone: None,
+            notifications: NotificationSettings::default(),
+            default_reminder_cadence_days: DEFAULT_REMINDER_CADENCE_DAYS,
+        }
+    }
+}
+
+/// Changes to the profile; fields left out stay as they are, and `"timezone": null` clears
+/// the timezone
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct UpdateProfileRequest {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub email: Option<String>,
+    #[serde(default, deserialize_with = "present")]
+    pub timezone: Option<Option<String>>,
+    #[serde(default)]
+    pub notifications: Option<NotificationSettings>,
+    #[serde(default)]
+    pub default_reminder_cadence_days: Option<i32>,
+}
+
+/// Deserialize a field that was sent, even as null, to `Some`, so a missing field (`None`
+/// through `#[serde(default)]`) can be told apart from an explicit null
+fn present<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReminderKind {
+    Occasion,
+    HolidayGreeting,
+}
+
+/// Something to do on `remind_on` ahead of the occasion or holiday on `date`
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Reminder {
+    pub kind: ReminderKind,
+    #[serde(with = "date_format")]
+    #[schemars(with = "String")]
+    pub remind_on: time::Date,
+    #[serde(with = "date_format")]
+    #[schemars(with = "String")]
+    pub date: time::Date,
+    /// Occasion or holiday name
+    pub name: String,
+    /// Days ahead of `date` the reminder is for; `remind_on` is earlier still when that day
+    /// is quiet
+    #[serde(default)]
+    pub days_before: i32,
+    pub occasion_id: Option<i32>,
+    pub contact_ids: Vec<i32>,
+    /// Gift ideas not yet given for an occasion's contact, meant for this occasion or none
+    #[serde(default)]
+    pub gift_ideas: Vec<GiftIdea>,
+}
+
+/// Where and how often a workspace's export archive is pushed
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ExportSchedule {
+    #[serde(skip)]
+    pub user_id: i32,
+    #[serde(skip)]
+    pub workspace_id: i32,
+    pub url: String,
+    pub interval_hours: i32,
+    /// HMAC key for the push signature; only shown when the schedule is saved
+    #[serde(skip)]
+    pub secret: String,
+    #[serde(default, with = "datetime_format::option")]
+    #[schemars(with = "Option<String>")]
+    pub last_pushed_at: Option<OffsetDateTime>,
+    /// HTTP status, or the error, of the last push
+    pub last_status: Option<String>,
+}
+
+/// A schedule as saved, with its new signing secret; the one response that shows it
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SavedExportSchedule {
+    #[serde(flatten)]
+    pub schedule: ExportSchedule,
+    pub secret: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct ExportScheduleRequest {
+    pub url: String,
+    pub interval_hours: i32,
+}
+
+/// What a REST hook is sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "hook_event", rename_all = "snake_case")]
+pub enum HookEvent {
+    NewContact,
+    NewInteraction,
+    UpcomingOccasion,
+}
+
+/// A URL that is POSTed a workspace's new items as they come, as subscribed by Zapier
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct RestHook {
+    pub hook_id: i32,
+    #[serde(skip)]
+    pub user_id: i32,
+    #[serde(skip)]
+    pub workspace_id: i32,
+    pub event: HookEvent,
+    pub target_url: String,
+    /// The user's day upcoming occasions were last sent for
+    #[serde(skip)]
+    pub delivered_on: Option<Date>,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct NewRestHookRequest {
+    pub event: HookEvent,
+    /// An http or https URL
+    pub target_url: String,
+}
+
+/// Where an outbox delivery has got to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "delivery_status", rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    /// Not sent yet, or to be tried again at `next_attempt_at`
+    Pending,
+    Delivered,
+    /// Given up on after the last attempt failed
+    Failed,
+}
+
+/// One delivery to a REST hook and its attempts so far, from `GET /webhooks/{id}/deliveries`
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct HookDelivery {
+    pub delivery_id: i32,
+    pub status: DeliveryStatus,
+    pub attempts: i32,
+    /// HTTP status the target last answered with, if it answered
+    pub last_response_status: Option<i32>,
+    /// Why the last attempt failed
+    pub last_error: Option<String>,
+    #[serde(with = "datetime_format")]
+    #[schemars(with = "String")]
+    pub created_at: OffsetDateTime,
+    /// When a pending delivery is tried next
+    #[serde(with = "datetime_format")]
+    #[schemars(with = "String")]
+    pub next_attempt_at: OffsetDateTime,
+    /// When it was delivered or given up on
+    #[serde(default, with = "datetime_format::option")]
+    #[schemars(with = "Option<String>")]
+    pub finished_at: Option<OffsetDateTime>,
+}
+
+/// What an outbox row is to send
+#[derive(Debug, Clone)]
+pub enum OutboxPayload {
+    /// New contacts or interactions by id, rendered when sent so they are current
+    Entities(Vec<i32>),
+    /// Items rendered when queued, as upcoming occasions are
+    Items(serde_json::Value),
+    Email(crate::mail::Email),
+    Push(crate::push::PushMessage),
+}
+
+/// An outbox row claimed for sending
+#[derive(Debug, Clone)]
+pub struct OutboxMessage {
+    pub delivery_id: i32,
+    pub workspace_id: i32,
+    /// The hook it is for, with its event and target; None for emails and pushes
+    pub hook: Option<(i32, HookEvent, String)>,
+    /// The subscription a push is for; None once it is gone
+    pub push: Option<PushSubscription>,
+    pub payload: OutboxPayload,
+    /// Attempts before this one
+    pub attempts: i32,
+}
+
+/// How an attempt at an outbox delivery went
+#[derive(Debug, Clone)]
+pub struct DeliveryAttempt {
+    /// Delivered, failed for good, or pending to be tried again at `retry_at`
+    pub status: DeliveryStatus,
+    pub response_status: Option<i32>,
+    pub error: Option<String>,
+    pub at: OffsetDateTime,
+    pub retry_at: Option<OffsetDateTime>,
+}
+
+/// An item of a trigger list or REST hook delivery; Zapier tells items apart by `id`
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct TriggerItem<T> {
+    pub id: String,
+    #[serde(flatten)]
+    pub item: T,
+}
+
+/// The next occurrence of an occasion
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct UpcomingOccasion {
+    #[serde(with = "date_format")]
+    #[schemars(with = "String")]
+    pub occurs_on: Date,
+    #[serde(flatten)]
+    pub occasion: Occasion,
+}
+
+/// The past week and the one ahead, from `GET /review/weekly`
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct WeeklyReview {
+    /// First and last day reviewed: the seven days before today, in the user's timezone
+    #[serde(with = "date_format")]
+    #[schemars(with = "String")]
+    pub from: Date,
+    #[serde(with = "date_format")]
+    #[schemars(with = "String")]
+    pub to: Date,
+    pub timezone: String,
+    /// Interactions dated within the week
+    pub interactions_logged: usize,
+    /// Contacts added within the week, oldest first
+    pub new_contacts: Vec<Contact>,
+    /// Occasions from today through the next six days, soonest first
+    pub upcoming_occasions: Vec<UpcomingOccasion>,
+    /// This week's reconnect suggestions, as `/reconnect/this-week` lists them
+    pub reconnect: Vec<ReconnectPick>,
+}
+
+/// A user who gets the weekly review by email
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReviewRecipient {
+    pub user_id: i32,
+    /// The user's default workspace, which the review covers
+    pub workspace_id: i32,
+    pub name: String,
+    pub email: String,
+    /// Monday of the week the last review was sent in
+    pub sent_week: Option<Date>,
+}
+
+/// A browser or device subscribed to the user's push notifications
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct PushSubscription {
+    pub subscription_id: i32,
+    #[serde(skip)]
+    pub user_id: i32,
+    pub endpoint: String,
+    /// The keys notifications are encrypted to, never shown again
+    #[serde(skip)]
+    pub p256dh: String,
+    #[serde(skip)]
+    pub auth: String,
+    pub device_name: Option<String>,
+    #[serde(with = "datetime_format")]
+    #[schemars(with = "String")]
+    pub created_at: OffsetDateTime,
+}
+
+/// The `keys` of a browser's `PushSubscription`, base64url encoded
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct PushKeys {
+    /// The subscription's P-256 public key
+    pub p256dh: String,
+    /// Its 16-byte authentication secret
+    pub auth: String,
+}
+
+/// A browser's `PushSubscription.toJSON()`, with a name for the device
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct NewPushSubscriptionRequest {
+    /// The push service URL, which must be https
+    pub endpoint: String,
+    pub keys: PushKeys,
+    pub device_name: Option<String>,
+}
+
+/// A user whose due reminders are pushed to their devices
+#[derive(Debug, Clone, PartialEq)]
+pub struct PushRecipient {
+    pub user_id: i32,
+    /// The user's default workspace, whose reminders are pushed
+    pub workspace_id: i32,
+    /// The user's day reminders were last pushed for
+    pub sent_on: Option<Date>,
+}
+
+/// What `GET /stats/timeseries` counts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum StatsMetric {
+    /// Interactions, by the day they are dated
+    Interactions,
+    /// Contacts, by the day they were added
+    Contacts,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Granularity {
+    Day,
+    /// Weeks start on Monday
+    #[default]
+    Week,
+    Month,
+}
+
+impl Granularity {
+    /// The unit `date_trunc` takes
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Granularity::Day => "day",
+            Granularity::Week => "week",
+            Granularity::Month => "month",
+        }
+    }
+}
+
+/// One bucket of a time series
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct TimeSeriesPoint {
+    /// First day of the bucket
+    #[serde(with = "date_format")]
+    #[schemars(with = "String")]
+    pub bucket: Date,
+    pub count: i64,
+}
+
+/// Counts per day, week or month, from `GET /stats/timeseries`
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct TimeSeries {
+    pub metric: StatsMetric,
+    pub granularity: Granularity,
+    /// First and last day counted, in the user's timezone. `from` is moved back to the start
+    /// of its bucket so every bucket is whole.
+    #[serde(with = "date_format")]
+    #[schemars(with = "String")]
+    pub from: Date,
+    #[serde(with = "date_format")]
+    #[schemars(with = "String")]
+    pub to: Date,
+    pub timezone: String,
+    /// Every bucket from `from` through `to`, oldest first, empty ones included
+    pub points: Vec<TimeSeriesPoint>,
+}
+
+/// A link anyone can open to see a contact's card, without signing in
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ContactLink {
+    pub link_id: i32,
+    pub contact_id: i32,
+    /// Secret part of the URL. Only its hash is stored, so it is returned when the link is made
+    /// and never again.
+    #[serde(default)]
+    pub token: Option<String>,
+    #[serde(with = "datetime_format")]
+    #[schemars(with = "String")]
+    pub expires_at: OffsetDateTime,
+    /// The full URL, as reached through this request's host. Like `token`, only returned when
+    /// the link is made.
+    #[serde(default)]
+    pub url: Option<String>,
+}
+
+/// What a contact link shows: how to reach the contact, and nothing the user wrote about them
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct ContactCard {
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    pub email: Option<String>,
+    pub phone: Option<String>,
+    pub avatar_url: Option<String>,
+    pub location: Option<String>,
+    pub social_profiles: Vec<CardProfile>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct CardProfile {
+    pub platform: SocialPlatform,
+    pub url: String,
+}
+
+/// A workspace's address for logging emails as interactions
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct InboundEmailAddress {
+    /// Local part of the address; anyone who knows it can log emails into the workspace
+    pub token: String,
+    /// The full address, when the server has an INBOUND_EMAIL_DOMAIN
+    pub address: Option<String>,
+}
+
+/// Free text captured on the go, waiting to be turned into an interaction
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct Capture {
+    pub capture_id: i32,
+    pub text: String,
+    #[serde(with = "datetime_format")]
+    #[schemars(with = "String")]
+    pub captured_at: OffsetDateTime,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct NewCaptureRequest {
+    pub text: String,
+}
+
+/// The interaction a capture becomes. Left out, the date is when the text was captured and
+/// the notes are the text itself.
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct ResolveCaptureRequest {
+    pub contact_id: i32,
+    #[serde(default, with = "datetime_format::option")]
+    #[schemars(with = "Option<String>")]
+    pub interaction_date: Option<OffsetDateTime>,
+    #[serde(default)]
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub interaction_type: Option<String>,
+    /// Other contacts who took part
+    #[serde(default)]
+    pub contact_ids: Vec<i32>,
+}
+
+/// A guess at how to resolve a capture, for the user to confirm or correct. Fields the text
+/// gave no answer for are null.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct CaptureSuggestion {
+    pub contact_id: Option<i32>,
+    #[serde(default, with = "datetime_format::option")]
+    #[schemars(with = "Option<String>")]
+    pub interaction_date: Option<OffsetDateTime>,
+    pub notes: Option<String>,
+    pub interaction_type: Option<String>,
+    /// What made the suggestion: `rules`, or the configured model as `llm`
+    pub source: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct ContactTag {
+    pub contact_id: i32,
+    pub tag_id: i32,
+}
+
+/// Everything in a workspace, with the user's preferences, as downloaded from /export and
+/// pushed on schedule
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct ExportArchive {
+    #[serde(with = "datetime_format")]
+    #[schemars(with = "String")]
+    pub exported_at: OffsetDateTime,
+    pub contacts: Vec<Contact>,
+    pub tags: Vec<Tag>,
+    pub contact_tags: Vec<ContactTag>,
+    pub interactions: Vec<Interaction>,
+    pub occasions: Vec<Occasion>,
+    #[serde(default)]
+    pub social_profiles: Vec<SocialProfile>,
+    #[serde(default)]
+    pub gift_ideas: Vec<GiftIdea>,
+    #[serde(default)]
+    pub groups: Vec<Group>,
+    pub preferences: Preferences,
+}
+
+/// A contact offered by /contacts/autocomplete
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema)]
+pub struct ContactMatch {
+    pub contact_id: i32,
+    /// First and last name
+    pub display_name: String,
+    /// Photo uploaded for the contact
+    pub avatar_url: Option<String>,
+}
+
+/// Where a /interactions/search result was found
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum NoteSource {
+    Interaction,
+    Contact,
+}
+
+/// Notes matching /interactions/search
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct NoteMatch {
+    pub source: NoteSource,
+    /// The interaction whose notes matched, for `source: "interaction"`
+    pub interaction_id: Option<i32>,
+    #[serde(default, with = "datetime_format::option")]
+    #[schemars(with = "Option<String>")]
+    pub interaction_date: Option<OffsetDateTime>,
+    /// The contact the notes belong to; None for an unassigned interaction
+    pub contact: Option<ContactMatch>,
+    /// The matching part of the notes, HTML-escaped, with the matched words in `<mark>` tags
+    pub snippet: String,
+}
+
+/// Content hash of a contact and everything attached to it
+#[derive(Debug, Serialize)]
+pub struct ContactChecksum {
+    pub contact_id: i32,
+    pub hash: String,
+}
+
+/// One field of a contact changing, as listed by /contacts/{id}/history. Values are shown as
+/// text, and are null when the field was empty.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct ContactChange {
+    pub change_id: i32,
+    pub field: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+    #[serde(with = "datetime_format")]
+    #[schemars(with = "String")]
+    pub changed_at: OffsetDateTime,
+}
+
+/// Conditions of a saved filter; a contact must meet all of those that are set
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq, JsonSchema)]
+pub struct FilterQuery {
+    /// Contacts with every one of these tags
+    #[serde(default)]
+    pub all_tag_ids: Vec<i32>,
+    /// Contacts with at least one of these tags
+    #[serde(default)]
+    pub any_tag_ids: Vec<i32>,
+    /// Contacts without an interaction in this many days, including those never contacted
+    pub last_interaction_older_than_days: Option<i32>,
+    /// Contacts with an occasion (or its next recurrence) within this many days
+    pub upcoming_occasion_within_days: Option<i32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct SavedFilter {
+    pub filter_id: i32,
+    pub name: String,
+    pub query: FilterQuery,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct NewSavedFilterRequest {
+    pub name: String,
+    #[serde(default)]
+    pub query: FilterQuery,
+}
+
+/// How often a goal's count starts over: Monday to Sunday, or a calendar month
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "goal_period", rename_all = "lowercase")]
+pub enum GoalPeriod {
+    Week,
+    Month,
+}
+
+/// A number of interactions to have every period, such as two a week overall or a call with
+/// one contact every week
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema)]
+pub struct Goal {
+    pub goal_id: i32,
+    pub name: String,
+    /// Only interactions with this contact count; all of them when None
+    pub contact_id: Option<i32>,
+    /// Only interactions of this type count
+    pub interaction_type: Option<String>,
+    pub target_count: i32,
+    pub period: GoalPeriod,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct NewGoalRequest {
+    pub name: String,
+    #[serde(default)]
+    pub contact_id: Option<i32>,
+    #[serde(default)]
+    pub interaction_type: Option<String>,
+    /// Interactions per period, from 1 to 100
+    pub target_count: i32,
+    pub period: GoalPeriod,
+}
+
+/// Where a goal stands in the current period
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema)]
+pub struct GoalProgress {
+    #[serde(flatten)]
+    pub goal: Goal,
+    /// First day of the current period
+    #[serde(with = "date_format")]
+    #[schemars(with = "String")]
+    pub period_start: Date,
+    /// Matching interactions so far this period
+    pub count: i32,
+    pub completed: bool,
+    /// Consecutive periods that met the target, up to this one if it already has or else the
+    /// one before
+    pub streak: i32,
+    /// Longest run of periods that met the target in the history kept for streaks
+    pub best_streak: i32,
+}
+
+/// What a share lets the grantee do; write includes read
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+    JsonSchema,
+    sqlx::Type,
+)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "share_permission", rename_all = "lowercase")]
+pub enum SharePermission {
+    /// See the contacts with their tags, interactions and occasions
+    Read,
+    /// Also edit the contacts and log interactions and occasions on them
+    Write,
+}
+
+/// Contacts one user has shared with another: those carrying a tag, or a single contact
+#[derive(Debug, Serialize, Deserialize, Clone, FromRow, JsonSchema)]
+pub struct Share {
+    pub share_id: i32,
+    pub owner_id: i32,
+    pub owner_email: String,
+    pub grantee_id: i32,
+    pub grantee_email: String,
+    pub tag_id: Option<i32>,
+    pub contact_id: Option<i32>,
+    pub permission: SharePermission,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct NewShareRequest {
+    /// Email of the user to share with
+    pub email: String,
+    /// Share every contact carrying this tag, now or later
+    pub tag_id: Option<i32>,
+    /// Or share these contacts; one share is created per contact
+    #[serde(default)]
+    pub contact_ids: Vec<i32>,
+    pub permission: SharePermission,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SharesResponse {
+    /// Shares the user has made
+    pub given: Vec<Share>,
+    /// Shares made with the user
+    pub received: Vec<Share>,
+}
+
+/// How a user may reach a contact
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContactAccess {
+    pub owner_id: i32,
+    /// The owner's workspace holding the contact
+    pub workspace_id: i32,
+    /// The strongest share granting access; None when the user owns the contact
+    pub shared: Option<SharePermission>,
+}
+
+impl ContactAccess {
+    pub fn allows(&self, needed: SharePermission) -> bool {
+        self.shared.is_none_or(|permission| permission >= needed)
+    }
+}
+
+/// A contact shared with the user, with the strongest permission any share grants on it
+#[derive(Debug, Clone, FromRow)]
+pub struct ContactShare {
+    pub contact_id: i32,
+    pub owner_id: i32,
+    pub workspace_id: i32,
+    pub owner_name: String,
+    pub owner_email: String,
+    pub permission: SharePermission,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SharedContact {
+    pub owner_id: i32,
+    pub owner_name: String,
+    pub owner_email: String,
+    pub permission: SharePermission,
+    #[serde(flatten)]
+    pub contact: ContactResponse,
+}
+
+/// One of a user's separate address books
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, FromRow, JsonSchema)]
+pub struct Workspace {
+    pub workspace_id: i32,
+    pub name: String,
+    /// Used by requests without an `X-Workspace` header; created with the user and never deleted
+    pub is_default: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct NewWorkspaceRequest {
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct WorkspacesResponse {
+    pub workspaces: Vec<Workspace>,
+}
+
+/// A user as listed by /admin/users
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct AccountSummary {
+    pub user_id: i32,
+    pub auth0_id: String,
+    pub name: String,
+    pub email: String,
+    /// The `users.is_admin` flag; admins listed in ADMIN_AUTH0_IDS are not marked
+    pub is_admin: bool,
+    pub deactivated: bool,
+    pub workspace_count: i64,
+    pub contact_count: i64,
+}
+
+/// How much a user's workspaces hold together
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, JsonSchema)]
+pub struct Usage {
+    pub workspaces: i64,
+    pub contacts: i64,
+    pub interactions: i64,
+    pub tags: i64,
+    /// Total size of the files attached to interactions
+    pub attachment_bytes: i64,
+}
+
+/// The instance's quotas, null where there is no limit
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, JsonSchema)]
+pub struct UsageLimits {
+    pub contacts: Option<i64>,
+    pub attachment_bytes: Option<i64>,
+}
+
+/// The user's usage against the instance's quotas, for GET /account/usage
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct AccountUsage {
+    #[serde(flatten)]
+    pub usage: Usage,
+    pub limits: UsageLimits,
+}
+
+/// Row counts across the whole instance, for /admin/stats
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct InstanceStats {
+    pub users: i64,
+    pub deactivated_users: i64,
+    pub workspaces: i64,
+    pub contacts: i64,
+    pub interactions: i64,
+    pub shares: i64,
+}
+
+/// A request made with an Idempotency-Key, with its response once it has finished
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdempotentRequest {
+    /// Hash of the request the key was first used with
+    pub fingerprint: String,
+    pub response: Option<StoredResponse>,
+}
+
+/// A response kept for replaying to retries
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredResponse {
+    pub status: u16,
+    pub content_type: Option<String>,
+    /// The Location header of a 201
+    pub location: Option<String>,
+    pub body: Vec<u8>,
+}
@@ -0,0 +1,70 @@
+//! Responses to creates and updates, sending the entity as saved so clients don't have to fetch
+//! it again.
+//!
+//! A create answers 201 Created with the new entity's URL in Location, an update 200 OK. The
+//! entity is read back through the repository after the write, so it has the shape it's listed
+//! in, with encrypted notes opened. Should reading it back fail, the write still stands and the
+//! response goes without a body.
+
+use crate::repository::RepoResult;
+use actix_web::http::header::LOCATION;
+use actix_web::{HttpRequest, HttpResponse, HttpResponseBuilder};
+use serde::Serialize;
+
+/// 201 Created for entity `id`, made by a POST to the collection at `req`'s path
+pub fn created<T: Serialize>(
+    req: &HttpRequest,
+    id: i32,
+    saved: RepoResult<Option<T>>,
+) -> HttpResponse {
+    created_in(req.path(), id, saved)
+}
+
+/// 201 Created for entity `id`, listed in the collection at `collection`, for creates made
+/// elsewhere, such as an interaction logged against a group
+pub fn created_in<T: Serialize>(
+    collection: &str,
+    id: i32,
+    saved: RepoResult<Option<T>>,
+) -> HttpResponse {
+    let mut response = HttpResponse::Created();
+    response.insert_header((
+        LOCATION,
+        format!("{}/{}", collection.trim_end_matches('/'), id),
+    ));
+    with_entity(response, saved)
+}
+
+/// 201 Created for several entities made by one POST to the collection at `req`'s path, such as
+/// a share per contact. The body is always the list; Location names the entity when there is
+/// only one.
+pub fn created_all<T: Serialize>(
+    req: &HttpRequest,
+    ids: &[i32],
+    saved: RepoResult<Vec<T>>,
+) -> HttpResponse {
+    if let [id] = ids {
+        return created(req, *id, saved.map(Some));
+    }
+    with_entity(HttpResponse::Created(), saved.map(Some))
+}
+
+/// 200 OK with the updated entity
+pub fn updated<T: Serialize>(saved: RepoResult<Option<T>>) -> HttpResponse {
+    with_entity(HttpResponse::Ok(), saved)
+}
+
+fn with_entity<T: Serialize>(
+    mut response: HttpResponseBuilder,
+    saved: RepoResult<Option<T>>,
+) -> HttpResponse {
+    match saved {
+        Ok(Some(entity)) => response.json(entity),
+        // Deleted again before it could be read back
+        Ok(None) => response.finish(),
+        Err(e) => {
+            eprintln!("Database error reading back a saved entity: {:?}", e);
+            response.finish()
+        }
+    }
+}
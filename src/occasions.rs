@@ -0,0 +1,167 @@
+//! Occasion handlers
+
+use crate::AuthUser;
+use crate::events::{self, Action, Entity, EventBus};
+use crate::models::{NewOccasionRequest, OccasionType, SharePermission};
+use crate::mutations;
+use crate::reminders;
+use crate::repository::Repository;
+use crate::routes::{Resource, ensure_contact_access, ensure_owned};
+use actix_web::{HttpRequest, HttpResponse, Responder, delete, patch, post, web};
+use time::OffsetDateTime;
+
+/// Check the birth year and lead times, sorting the latter. Errors are the JSON body of the
+/// 400 response.
+pub(crate) fn normalize(occasion: &mut NewOccasionRequest) -> Result<(), serde_json::Value> {
+    if let Some(days_before) = &mut occasion.remind_days_before {
+        reminders::normalize_days_before(days_before)?;
+    }
+    let Some(birth_year) = occasion.birth_year else {
+        return Ok(());
+    };
+    if occasion.occasion_type != OccasionType::Birthday {
+        return Err(serde_json::json!({
+            "error": "Only birthdays can have a birth year"
+        }));
+    }
+    if birth_year > OffsetDateTime::now_utc().year() {
+        return Err(serde_json::json!({
+            "error": "Birth year must not be in the future"
+        }));
+    }
+    Ok(())
+}
+
+#[post("/occasions")]
+pub async fn create_occasion(
+    req: HttpRequest,
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    auth_user: AuthUser,
+    mut new_occasion: web::Json<NewOccasionRequest>,
+) -> impl Responder {
+    if let Err(error) = normalize(&mut new_occasion) {
+        return HttpResponse::BadRequest().json(error);
+    }
+
+    // The contact is the user's own or shared with them for writing; the occasion goes in the
+    // contact's workspace
+    let access = match ensure_contact_access(
+        repo.get_ref(),
+        &auth_user,
+        new_occasion.contact_id,
+        SharePermission::Write,
+    )
+    .await
+    {
+        Ok(access) => access,
+        Err(response) => return response,
+    };
+
+    match repo
+        .create_occasion(access.workspace_id, &new_occasion)
+        .await
+    {
+        Ok(occasion_id) => {
+            events::publish(
+                bus.as_ref(),
+                access.owner_id,
+                Entity::Occasion,
+                Action::Created,
+                vec![occasion_id],
+            );
+            mutations::created(
+                &req,
+                occasion_id,
+                repo.get_occasion(access.workspace_id, occasion_id).await,
+            )
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to create occasion")
+        }
+    }
+}
+
+#[delete("/occasions/{id}")]
+pub async fn delete_occasion(
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    auth_user: AuthUser,
+    occasion_id: web::Path<i32>,
+) -> impl Responder {
+    let id = occasion_id.into_inner();
+
+    // Verify the occasion belongs to the user
+    if let Err(response) = ensure_owned(repo.get_ref(), &auth_user, Resource::Occasion, id).await {
+        return response;
+    }
+
+    match repo.delete_occasion(auth_user.workspace_id, id).await {
+        Ok(false) => HttpResponse::NotFound().body("Occasion not found"),
+        Ok(true) => {
+            events::publish(
+                bus.as_ref(),
+                auth_user.user_id,
+                Entity::Occasion,
+                Action::Deleted,
+                vec![id],
+            );
+            HttpResponse::Ok().body("Occasion deleted successfully")
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to delete occasion")
+        }
+    }
+}
+
+#[patch("/occasions/{id}")]
+pub async fn update_occasion(
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    auth_user: AuthUser,
+    occasion_id: web::Path<i32>,
+    mut updated_occasion: web::Json<NewOccasionRequest>,
+) -> impl Responder {
+    let id = occasion_id.into_inner();
+    if let Err(error) = normalize(&mut updated_occasion) {
+        return HttpResponse::BadRequest().json(error);
+    }
+
+    // Verify the occasion belongs to the user
+    if let Err(response) = ensure_owned(repo.get_ref(), &auth_user, Resource::Occasion, id).await {
+        return response;
+    }
+    // Verify the contact does too; an unassigned occasion is given it
+    if let Err(response) = ensure_owned(
+        repo.get_ref(),
+        &auth_user,
+        Resource::Contact,
+        updated_occasion.contact_id,
+    )
+    .await
+    {
+        return response;
+    }
+
+    match repo
+        .update_occasion(auth_user.workspace_id, id, &updated_occasion)
+        .await
+    {
+        Ok(_) => {
+            events::publish(
+                bus.as_ref(),
+                auth_user.user_id,
+                Entity::Occasion,
+                Action::Updated,
+                vec![id],
+            );
+            mutations::updated(repo.get_occasion(auth_user.workspace_id, id).await)
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to update occasion")
+        }
+    }
+}
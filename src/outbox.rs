@@ -0,0 +1,347 @@
+//! The outbox: webhooks and emails owed, sent in the background with retries.
+//!
+//! A change that owes a delivery writes it to the `outbox` table in the same transaction as the
+//! change, so no delivery is lost to a crash between the two and none is sent for a change that
+//! rolled back. New contacts and interactions are queued for the workspace's REST hooks as they
+//! are created, upcoming occasions by `zapier::UpcomingHooks`, weekly review emails by
+//! `review::ReviewEmails` and push notifications by `push::PushReminders`.
+//!
+//! The dispatcher claims due rows, sends them and records each attempt. A failed attempt is
+//! tried again after `FIRST_RETRY`, twice as long after each failure up to `MAX_RETRY`, until
+//! `MAX_ATTEMPTS` have failed and the delivery is given up on. A hook whose target answers 410
+//! Gone is unsubscribed, as is a push subscription its push service no longer knows.
+//! `GET /webhooks/{id}/deliveries` shows a hook's recent deliveries with the last status and
+//! error of each; finished deliveries are kept for `RETENTION`.
+
+use crate::AuthUser;
+use crate::events::{Action, Entity, EventBus};
+use crate::jobs::Job;
+use crate::link_preview::PublicResolver;
+use crate::mail::Mailer;
+use crate::models::{DeliveryAttempt, DeliveryStatus, HookEvent, OutboxMessage, OutboxPayload};
+use crate::push::Pusher;
+use crate::repository::{RepoResult, Repository};
+use crate::routes::{Resource, ensure_owned};
+use crate::zapier::{contact_items, interaction_items};
+use actix_web::{HttpResponse, Responder, get, web};
+use async_trait::async_trait;
+use reqwest::redirect::Policy;
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::Duration;
+use time::OffsetDateTime;
+use tokio::sync::broadcast::error::RecvError;
+
+/// Wait before the first retry
+pub const FIRST_RETRY: Duration = Duration::from_secs(30);
+
+/// Longest wait between retries
+pub const MAX_RETRY: Duration = Duration::from_secs(6 * 60 * 60);
+
+/// Failed attempts before a delivery is given up on
+pub const MAX_ATTEMPTS: i32 = 10;
+
+/// How often the dispatcher looks for due deliveries
+pub const DRAIN_INTERVAL: Duration = Duration::from_secs(15);
+
+/// How long finished deliveries are kept to be inspected
+pub const RETENTION: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Deliveries listed for a hook
+pub const LIST_LIMIT: i64 = 100;
+
+/// Deliveries claimed at a time
+const BATCH: i64 = 20;
+
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long claimed deliveries wait for their attempts before another sender may take them;
+/// longer than a batch can take
+const CLAIM_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// Wait before trying again after `attempts` failed attempts
+pub fn retry_delay(attempts: i32) -> Duration {
+    let doublings = attempts.saturating_sub(1).clamp(0, 20) as u32;
+    FIRST_RETRY.saturating_mul(1 << doublings).min(MAX_RETRY)
+}
+
+/// Why an attempt failed
+struct Failure {
+    response_status: Option<i32>,
+    error: String,
+}
+
+/// Sends what is in the outbox
+#[derive(Clone)]
+pub struct Outbox {
+    client: reqwest::Client,
+    /// Sends emails; without one they fail until SMTP is configured
+    mailer: Option<Arc<dyn Mailer>>,
+    /// Sends pushes; without one they fail until VAPID is configured
+    pusher: Option<Arc<dyn Pusher>>,
+}
+
+impl Default for Outbox {
+    fn default() -> Self {
+        Outbox {
+            client: reqwest::Client::builder()
+                .timeout(DELIVERY_TIMEOUT)
+                // Redirects could lead to an address the resolver never sees
+                .redirect(Policy::none())
+                .dns_resolver(PublicResolver)
+                .build()
+                .expect("Failed to build HTTP client"),
+            mailer: None,
+            pusher: None,
+        }
+    }
+}
+
+impl Outbox {
+    pub fn with_mailer(mut self, mailer: impl Mailer + 'static) -> Self {
+        self.mailer = Some(Arc::new(mailer));
+        self
+    }
+
+    pub fn with_pusher(mut self, pusher: impl Pusher + 'static) -> Self {
+        self.pusher = Some(Arc::new(pusher));
+        self
+    }
+
+    /// POST the items to a hook's target, unsubscribing the hook if the target is gone
+    async fn post<T: Serialize>(
+        &self,
+        repo: &dyn Repository,
+        message: &OutboxMessage,
+        (hook_id, target_url): (i32, &str),
+        items: &T,
+    ) -> RepoResult<Result<(), Failure>> {
+        let response = match self.client.post(target_url).json(items).send().await {
+            Ok(response) => response,
+            Err(e) => {
+                return Ok(Err(Failure {
+                    response_status: None,
+                    error: e.to_string(),
+                }));
+            }
+        };
+        let status = response.status();
+        if status == reqwest::StatusCode::GONE {
+            // Unsubscribing drops the hook's deliveries with it
+            repo.delete_rest_hook(message.workspace_id, hook_id).await?;
+        }
+        if status.is_success() {
+            return Ok(Ok(()));
+        }
+        Ok(Err(Failure {
+            response_status: Some(i32::from(status.as_u16())),
+            error: format!("Target answered {}", status),
+        }))
+    }
+
+    /// Make one attempt at a delivery. New contacts and interactions deleted before they were
+    /// sent are left out, and a delivery left with nothing to send counts as delivered.
+    async fn send(
+        &self,
+        repo: &dyn Repository,
+        message: &OutboxMessage,
+    ) -> RepoResult<Result<(), Failure>> {
+        match (&message.hook, &message.payload) {
+            (_, OutboxPayload::Email(email)) => {
+                let Some(mailer) = &self.mailer else {
+                    return Ok(Err(Failure {
+                        response_status: None,
+                        error: "SMTP is not configured".to_string(),
+                    }));
+                };
+                Ok(mailer.send(email).await.map_err(|error| Failure {
+                    response_status: None,
+                    error,
+                }))
+            }
+            (_, OutboxPayload::Push(push)) => {
+                // The subscription was deleted after the delivery was claimed
+                let Some(subscription) = &message.push else {
+                    return Ok(Ok(()));
+                };
+                let Some(pusher) = &self.pusher else {
+                    return Ok(Err(Failure {
+                        response_status: None,
+                        error: "Web Push is not configured".to_string(),
+                    }));
+                };
+                let Err(e) = pusher.push(subscription, push).await else {
+                    return Ok(Ok(()));
+                };
+                if e.is_gone() {
+                    // Deleting the subscription drops its deliveries with it
+                    repo.delete_push_subscription(
+                        subscription.user_id,
+                        subscription.subscription_id,
+                    )
+                    .await?;
+                }
+                Ok(Err(Failure {
+                    response_status: e.status.map(i32::from),
+                    error: e.error,
+                }))
+            }
+            (Some((hook_id, _, target_url)), OutboxPayload::Items(items)) => {
+                self.post(repo, message, (*hook_id, target_url), items)
+                    .await
+            }
+            (Some((hook_id, event, target_url)), OutboxPayload::Entities(ids)) => {
+                let target = (*hook_id, target_url.as_str());
+                match event {
+                    HookEvent::NewContact => {
+                        let contacts = repo
+                            .contact_details(message.workspace_id, ids)
+                            .await?
+                            .into_iter()
+                            .map(|d| d.contact)
+                            .collect();
+                        let items = contact_items(contacts);
+                        if items.is_empty() {
+                            return Ok(Ok(()));
+                        }
+                        self.post(repo, message, target, &items).await
+                    }
+                    HookEvent::NewInteraction => {
+                        let interactions =
+                            repo.interactions_by_id(message.workspace_id, ids).await?;
+                        let items = interaction_items(interactions);
+                        if items.is_empty() {
+                            return Ok(Ok(()));
+                        }
+                        self.post(repo, message, target, &items).await
+                    }
+                    HookEvent::UpcomingOccasion => Ok(Ok(())),
+                }
+            }
+            // The hook was unsubscribed after the delivery was claimed
+            (None, _) => Ok(Ok(())),
+        }
+    }
+
+    /// Attempt every delivery due at `now` and drop those finished before `RETENTION`,
+    /// returning how many were attempted
+    pub async fn drain(&self, repo: &dyn Repository, now: OffsetDateTime) -> RepoResult<usize> {
+        let mut attempted = 0;
+        loop {
+            let messages = repo
+                .claim_deliveries(now, now + CLAIM_TIMEOUT, BATCH)
+                .await?;
+            if messages.is_empty() {
+                break;
+            }
+            for message in messages {
+                let outcome = self.send(repo, &message).await?;
+                // Never before `now`, so a retry is not due again in this drain
+                let at = OffsetDateTime::now_utc().max(now);
+                let attempts = message.attempts + 1;
+                let attempt = match outcome {
+                    Ok(()) => DeliveryAttempt {
+                        status: DeliveryStatus::Delivered,
+                        response_status: None,
+                        error: None,
+                        at,
+                        retry_at: None,
+                    },
+                    Err(failure) => {
+                        eprintln!(
+                            "Delivery {} failed, attempt {}: {}",
+                            message.delivery_id, attempts, failure.error
+                        );
+                        let retry = attempts < MAX_ATTEMPTS;
+                        DeliveryAttempt {
+                            status: if retry {
+                                DeliveryStatus::Pending
+                            } else {
+                                DeliveryStatus::Failed
+                            },
+                            response_status: failure.response_status,
+                            error: Some(failure.error),
+                            at,
+                            retry_at: retry.then(|| at + retry_delay(attempts)),
+                        }
+                    }
+                };
+                repo.record_delivery_attempt(message.delivery_id, &attempt)
+                    .await?;
+                attempted += 1;
+            }
+        }
+        repo.purge_deliveries(now - RETENTION).await?;
+        Ok(attempted)
+    }
+
+    /// Drain the outbox as soon as new contacts and interactions are published on this
+    /// process's bus, for as long as the server runs, rather than waiting for the `Job`
+    pub fn spawn(self, repo: web::Data<dyn Repository>, bus: &EventBus) {
+        let mut events = bus.subscribe();
+        actix_web::rt::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok((_, event)) => {
+                        let created = matches!(
+                            (event.entity, event.action),
+                            (Entity::Contact | Entity::Interaction, Action::Created)
+                        );
+                        if !created {
+                            continue;
+                        }
+                    }
+                    // Whatever was missed is in the outbox all the same
+                    Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => return,
+                }
+                if let Err(e) = self.drain(repo.get_ref(), OffsetDateTime::now_utc()).await {
+                    eprintln!("Database error: {:?}", e);
+                }
+            }
+        });
+    }
+}
+
+/// Sends due deliveries every `DRAIN_INTERVAL`, including retries
+#[async_trait]
+impl Job for Outbox {
+    fn name(&self) -> &'static str {
+        "outbox"
+    }
+
+    fn interval(&self) -> Duration {
+        DRAIN_INTERVAL
+    }
+
+    async fn run(&self, repo: &dyn Repository) -> RepoResult<()> {
+        self.drain(repo, OffsetDateTime::now_utc())
+            .await
+            .map(|_| ())
+    }
+}
+
+/// The hook's latest deliveries, newest first, to see why a target is not getting them
+#[get("/webhooks/{id}/deliveries")]
+pub async fn list_deliveries(
+    repo: web::Data<dyn Repository>,
+    auth_user: AuthUser,
+    hook_id: web::Path<i32>,
+) -> impl Responder {
+    let id = hook_id.into_inner();
+
+    if let Err(response) = ensure_owned(repo.get_ref(), &auth_user, Resource::RestHook, id).await {
+        return response;
+    }
+
+    match repo
+        .hook_deliveries(auth_user.workspace_id, id, LIST_LIMIT)
+        .await
+    {
+        Ok(deliveries) => HttpResponse::Ok().json(deliveries),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to fetch deliveries")
+        }
+    }
+}
@@ -0,0 +1,46 @@
+//! Phone numbers in E.164 form, so a number matches however it was typed.
+//! Contacts keep the number as entered in `phone` and its E.164 form in `phone_e164`.
+
+use phonenumber::Mode;
+use phonenumber::country::Id;
+use std::sync::LazyLock;
+
+/// Region for numbers written without a country code, unless PHONE_DEFAULT_REGION says otherwise
+pub const DEFAULT_REGION: Id = Id::US;
+
+static REGION: LazyLock<Id> = LazyLock::new(|| {
+    let Ok(code) = std::env::var("PHONE_DEFAULT_REGION") else {
+        return DEFAULT_REGION;
+    };
+    parse_region(&code).unwrap_or_else(|| {
+        eprintln!(
+            "PHONE_DEFAULT_REGION {:?} is not a region code, using {:?}",
+            code, DEFAULT_REGION
+        );
+        DEFAULT_REGION
+    })
+});
+
+/// The region for a two-letter ISO 3166 code such as `GB`
+pub fn parse_region(code: &str) -> Option<Id> {
+    code.trim().to_ascii_uppercase().parse().ok()
+}
+
+/// `raw` in E.164 (`+14155550123`), reading a number without a country code as one of
+/// `region`'s. None unless it is a valid number.
+pub fn normalize_in(raw: &str, region: Id) -> Option<String> {
+    let number = phonenumber::parse(Some(region), raw).ok()?;
+    number
+        .is_valid()
+        .then(|| number.format().mode(Mode::E164).to_string())
+}
+
+/// `normalize_in` the configured default region
+pub fn normalize(raw: &str) -> Option<String> {
+    normalize_in(raw, *REGION)
+}
+
+/// E.164 form of a contact's phone field
+pub fn e164(phone: Option<&str>) -> Option<String> {
+    phone.and_then(normalize)
+}
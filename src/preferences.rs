@@ -0,0 +1,109 @@
+//! Preference handlers
+
+use crate::AuthUser;
+use crate::events::{self, Action, Entity, EventBus};
+use crate::holidays::{SUPPORTED_COUNTRIES, is_supported};
+use crate::models::Preferences;
+use crate::reminders;
+use crate::repository::Repository;
+use crate::routes::{Resource, ensure_owned};
+use crate::timezone;
+use actix_web::{HttpResponse, Responder, get, put, web};
+
+#[get("/preferences")]
+pub async fn get_preferences(
+    repo: web::Data<dyn Repository>,
+    auth_user: AuthUser,
+) -> impl Responder {
+    match repo.get_preferences(auth_user.user_id).await {
+        Ok(preferences) => HttpResponse::Ok().json(preferences),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to fetch preferences")
+        }
+    }
+}
+
+/// Check and normalize submitted preferences: country codes are uppercased, lists are sorted
+/// and deduplicated. At least one weekday must be left for reminders, the timezone must be one
+/// the server knows, and lead times are limited as on occasions. Errors are the JSON body of
+/// the 400 response.
+fn normalize(mut preferences: Preferences) -> Result<Preferences, serde_json::Value> {
+    if let Some(country) = &mut preferences.country {
+        country.make_ascii_uppercase();
+        if !is_supported(country) {
+            return Err(serde_json::json!({
+                "error": "Unsupported country",
+                "supported_countries": SUPPORTED_COUNTRIES
+            }));
+        }
+    }
+
+    preferences.quiet_weekdays.sort_unstable();
+    preferences.quiet_weekdays.dedup();
+    if preferences
+        .quiet_weekdays
+        .iter()
+        .any(|day| !(1..=7).contains(day))
+    {
+        return Err(serde_json::json!({
+            "error": "Quiet weekdays must be between 1 (Monday) and 7 (Sunday)"
+        }));
+    }
+    if preferences.quiet_weekdays.len() == 7 {
+        return Err(serde_json::json!({
+            "error": "At least one weekday must allow reminders"
+        }));
+    }
+
+    preferences.greeting_tag_ids.sort_unstable();
+    preferences.greeting_tag_ids.dedup();
+    reminders::normalize_days_before(&mut preferences.remind_days_before)?;
+
+    if let Some(timezone) = &preferences.timezone
+        && !timezone::is_known(timezone)
+    {
+        return Err(serde_json::json!({
+            "error": "Unknown timezone; use an IANA name such as \"Europe/Berlin\""
+        }));
+    }
+    Ok(preferences)
+}
+
+#[put("/preferences")]
+pub async fn update_preferences(
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    auth_user: AuthUser,
+    preferences: web::Json<Preferences>,
+) -> impl Responder {
+    let preferences = match normalize(preferences.into_inner()) {
+        Ok(preferences) => preferences,
+        Err(error) => return HttpResponse::BadRequest().json(error),
+    };
+
+    // Verify the greeting tags belong to the user
+    for &tag_id in &preferences.greeting_tag_ids {
+        if let Err(response) = ensure_owned(repo.get_ref(), &auth_user, Resource::Tag, tag_id).await
+        {
+            return response;
+        }
+    }
+
+    match repo.save_preferences(auth_user.user_id, &preferences).await {
+        Ok(()) => {
+            events::publish(
+                bus.as_ref(),
+                auth_user.user_id,
+                Entity::Preferences,
+                Action::Updated,
+                Vec::new(),
+            );
+            HttpResponse::Ok().json(preferences)
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to update preferences")
+        }
+    }
+}
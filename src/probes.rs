@@ -0,0 +1,98 @@
+//! Liveness and readiness probes for the load balancer and the orchestrator.
+//!
+//! `/health/live` only says the process is serving requests. `/health/ready` also checks what
+//! requests depend on: the database, its read replica when configured, and Auth0's JWKS.
+
+use crate::check_jwks_reachable;
+use actix_web::{HttpResponse, Responder, get, web};
+use async_trait::async_trait;
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// The databases the readiness probe pings
+#[async_trait]
+pub trait Databases: Send + Sync {
+    async fn ping(&self) -> Result<(), String>;
+
+    /// Ping the read replica, or None when there is none
+    async fn ping_replica(&self) -> Option<Result<(), String>>;
+}
+
+/// Wrap a set of databases as the `web::Data<dyn Databases>` the readiness probe takes
+pub fn app_data<D: Databases + 'static>(databases: D) -> web::Data<dyn Databases> {
+    web::Data::from(Arc::new(databases) as Arc<dyn Databases>)
+}
+
+#[derive(Serialize)]
+struct DependencyStatus {
+    status: &'static str,
+    latency_ms: u128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl DependencyStatus {
+    fn from_result(result: Result<(), String>, started: Instant) -> Self {
+        let latency_ms = started.elapsed().as_millis();
+        match result {
+            Ok(()) => DependencyStatus {
+                status: "up",
+                latency_ms,
+                error: None,
+            },
+            Err(e) => DependencyStatus {
+                status: "down",
+                latency_ms,
+                error: Some(e),
+            },
+        }
+    }
+}
+
+/// Liveness probe: the process is up and serving requests
+#[get("/health/live")]
+pub async fn health_live() -> impl Responder {
+    HttpResponse::Ok().json(serde_json::json!({
+        "status": "alive",
+        "service": "personal-crm"
+    }))
+}
+
+/// Readiness probe: checks the database, its read replica when configured, and Auth0 JWKS.
+/// The databases are required, so their failure returns 503. Auth0 being unreachable only
+/// reports "degraded", since cached tokens keep working and restarting us won't fix Auth0.
+#[get("/health/ready")]
+pub async fn health_ready(databases: web::Data<dyn Databases>) -> impl Responder {
+    let started = Instant::now();
+    let replica = databases
+        .ping_replica()
+        .await
+        .map(|result| DependencyStatus::from_result(result, started));
+
+    let started = Instant::now();
+    let database = DependencyStatus::from_result(databases.ping().await, started);
+
+    let started = Instant::now();
+    let auth0_jwks = DependencyStatus::from_result(check_jwks_reachable().await, started);
+
+    let replica_status = replica.as_ref().map_or("up", |r| r.status);
+    let (status, mut response) = match (database.status, replica_status, auth0_jwks.status) {
+        ("up", "up", "up") => ("ready", HttpResponse::Ok()),
+        ("up", "up", _) => ("degraded", HttpResponse::Ok()),
+        _ => ("unavailable", HttpResponse::ServiceUnavailable()),
+    };
+
+    let mut checks = serde_json::json!({
+        "database": database,
+        "auth0_jwks": auth0_jwks
+    });
+    if let Some(replica) = replica {
+        checks["database_replica"] = serde_json::json!(replica);
+    }
+    response.json(serde_json::json!({
+        "status": status,
+        "service": "personal-crm",
+        "checks": checks
+    }))
+}
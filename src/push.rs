@@ -0,0 +1,507 @@
+//! Web Push notifications of due reminders.
+//!
+//! Browsers and devices subscribe through the Push API with the key from
+//! `GET /push/vapid-public-key`, then register the subscription with `POST /push/subscriptions`.
+//! Once a day, the first time the background task runs after the user's midnight, users with
+//! `notifications.push` on in `/me` are pushed the reminders of `/reminders/upcoming` that are
+//! due that day, occasions falling on it included, through the `outbox`, one delivery per
+//! reminder and subscription. Messages are encrypted to the subscription's keys (RFC 8291) and
+//! signed for the push service with the VAPID key in VAPID_PRIVATE_KEY (RFC 8292). A
+//! subscription the push service no longer knows (404 or 410) is deleted. Without
+//! VAPID_PRIVATE_KEY nothing is pushed.
+
+use crate::AuthUser;
+use crate::jobs::Job;
+use crate::link_preview::{PublicResolver, is_fetchable};
+use crate::models::{
+    Contact, NewPushSubscriptionRequest, PushSubscription, Reminder, ReminderKind,
+};
+use crate::reminders;
+use crate::repository::{RepoResult, Repository};
+use crate::review::contact_name;
+use crate::routes::{Resource, ensure_owned};
+use crate::timezone::LocalDates;
+use actix_web::{HttpResponse, Responder, delete, get, post, web};
+use aes_gcm::Aes128Gcm;
+use aes_gcm::aead::{Aead, KeyInit};
+use async_trait::async_trait;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use hkdf::Hkdf;
+use p256::ecdsa::signature::Signer;
+use p256::ecdsa::{Signature, SigningKey};
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use p256::{PublicKey, SecretKey};
+use reqwest::redirect::Policy;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::time::Duration;
+use time::OffsetDateTime;
+
+/// How often the background task looks for reminders to push
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+/// How long push services keep a notification for a device that is offline
+pub const TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How long a VAPID signature is valid; push services accept at most 24 hours
+const VAPID_VALIDITY: Duration = Duration::from_secs(12 * 60 * 60);
+
+/// Record size of the encrypted body, which holds the message in a single record
+const RECORD_SIZE: u32 = 4096;
+
+/// Longest message that fits a 4096-byte push once encrypted: the header takes 86 bytes, the
+/// padding delimiter one and the tag 16
+pub const MAX_MESSAGE_BYTES: usize = 4096 - 86 - 1 - 16;
+
+/// Longest device name kept
+pub const MAX_DEVICE_NAME: usize = 100;
+
+const PUSH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// What a service worker is pushed, as JSON
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PushMessage {
+    pub title: String,
+    pub body: String,
+    /// Notifications with the same tag replace each other on the device
+    pub tag: String,
+}
+
+/// Why a push failed
+#[derive(Debug)]
+pub struct PushError {
+    /// The push service's answer, when it gave one
+    pub status: Option<u16>,
+    pub error: String,
+}
+
+impl PushError {
+    /// The push service no longer knows the subscription
+    pub fn is_gone(&self) -> bool {
+        matches!(self.status, Some(404 | 410))
+    }
+}
+
+#[async_trait]
+pub trait Pusher: Send + Sync {
+    async fn push(
+        &self,
+        subscription: &PushSubscription,
+        message: &PushMessage,
+    ) -> Result<(), PushError>;
+}
+
+/// Sends pushes signed with the instance's VAPID key
+#[derive(Clone)]
+pub struct WebPush {
+    client: reqwest::Client,
+    signing_key: SigningKey,
+    /// The uncompressed public key, base64url encoded, as browsers take it
+    public_key: String,
+    /// The contact push services may reach the sender at: a mailto: or https: URL
+    subject: String,
+}
+
+// Never print the key
+impl std::fmt::Debug for WebPush {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WebPush")
+            .field("public_key", &self.public_key)
+            .field("subject", &self.subject)
+            .finish()
+    }
+}
+
+impl WebPush {
+    /// A sender for a base64url encoded P-256 private key
+    pub fn new(private_key: &str, subject: &str) -> Result<Self, String> {
+        let bytes = URL_SAFE_NO_PAD
+            .decode(private_key.trim().trim_end_matches('='))
+            .map_err(|e| format!("VAPID_PRIVATE_KEY is not base64url: {}", e))?;
+        let signing_key = SigningKey::from_slice(&bytes)
+            .map_err(|_| "VAPID_PRIVATE_KEY must be a 32-byte P-256 private key".to_string())?;
+        if !(subject.starts_with("mailto:") || subject.starts_with("https://")) {
+            return Err("VAPID_SUBJECT must be a mailto: or https: URL".to_string());
+        }
+        let public_key = URL_SAFE_NO_PAD.encode(
+            signing_key
+                .verifying_key()
+                .to_encoded_point(false)
+                .as_bytes(),
+        );
+        Ok(WebPush {
+            client: reqwest::Client::builder()
+                .timeout(PUSH_TIMEOUT)
+                // Redirects could lead to an address the resolver never sees
+                .redirect(Policy::none())
+                .dns_resolver(PublicResolver)
+                .build()
+                .expect("Failed to build HTTP client"),
+            signing_key,
+            public_key,
+            subject: subject.to_string(),
+        })
+    }
+
+    /// The sender for VAPID_PRIVATE_KEY and VAPID_SUBJECT; None when VAPID_PRIVATE_KEY is unset
+    pub fn from_env() -> Result<Option<Self>, String> {
+        let Some(key) = std::env::var("VAPID_PRIVATE_KEY")
+            .ok()
+            .filter(|k| !k.trim().is_empty())
+        else {
+            return Ok(None);
+        };
+        let subject = std::env::var("VAPID_SUBJECT").map_err(|_| {
+            "Set VAPID_SUBJECT to send push notifications with VAPID_PRIVATE_KEY".to_string()
+        })?;
+        WebPush::new(&key, subject.trim()).map(Some)
+    }
+
+    /// The key browsers subscribe with, as `applicationServerKey`
+    pub fn public_key(&self) -> &str {
+        &self.public_key
+    }
+
+    /// The `Authorization` header for a push to `endpoint`: a JWT for the endpoint's origin,
+    /// signed with ES256, and the public key to check it with
+    pub fn authorization(&self, endpoint: &str, now: OffsetDateTime) -> Result<String, String> {
+        let url = reqwest::Url::parse(endpoint).map_err(|e| format!("Bad endpoint: {}", e))?;
+        let header = URL_SAFE_NO_PAD.encode(br#"{"typ":"JWT","alg":"ES256"}"#);
+        let claims = serde_json::json!({
+            "aud": url.origin().ascii_serialization(),
+            "exp": (now + VAPID_VALIDITY).unix_timestamp(),
+            "sub": self.subject
+        });
+        let claims = URL_SAFE_NO_PAD.encode(claims.to_string());
+        let signing_input = format!("{}.{}", header, claims);
+        let signature: Signature = self.signing_key.sign(signing_input.as_bytes());
+        Ok(format!(
+            "vapid t={}.{}, k={}",
+            signing_input,
+            URL_SAFE_NO_PAD.encode(signature.to_bytes()),
+            self.public_key
+        ))
+    }
+}
+
+#[async_trait]
+impl Pusher for WebPush {
+    async fn push(
+        &self,
+        subscription: &PushSubscription,
+        message: &PushMessage,
+    ) -> Result<(), PushError> {
+        let failed = |error: String| PushError {
+            status: None,
+            error,
+        };
+        let plaintext = serde_json::to_vec(message).map_err(|e| failed(e.to_string()))?;
+        let body = encrypt(&subscription.p256dh, &subscription.auth, &plaintext).map_err(failed)?;
+        let authorization = self
+            .authorization(&subscription.endpoint, OffsetDateTime::now_utc())
+            .map_err(failed)?;
+        let response = self
+            .client
+            .post(&subscription.endpoint)
+            .header("Authorization", authorization)
+            .header("TTL", TTL.as_secs().to_string())
+            .header("Content-Encoding", "aes128gcm")
+            .header("Content-Type", "application/octet-stream")
+            .header("Urgency", "normal")
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| failed(e.to_string()))?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+        Err(PushError {
+            status: Some(status.as_u16()),
+            error: format!("Push service answered {}", status),
+        })
+    }
+}
+
+fn random<const N: usize>() -> [u8; N] {
+    let mut bytes = [0u8; N];
+    getrandom::fill(&mut bytes).expect("Failed to read random bytes");
+    bytes
+}
+
+/// Decode a subscription's base64url keys, checking they are a P-256 public key and a
+/// 16-byte secret
+pub fn decode_keys(p256dh: &str, auth: &str) -> Result<(PublicKey, Vec<u8>), String> {
+    let decode = |key: &str| URL_SAFE_NO_PAD.decode(key.trim().trim_end_matches('='));
+    let public_key = decode(p256dh)
+        .ok()
+        .and_then(|bytes| PublicKey::from_sec1_bytes(&bytes).ok())
+        .ok_or_else(|| "keys.p256dh must be a base64url P-256 public key".to_string())?;
+    let auth = decode(auth)
+        .ok()
+        .filter(|bytes| bytes.len() == 16)
+        .ok_or_else(|| "keys.auth must be 16 bytes, base64url encoded".to_string())?;
+    Ok((public_key, auth))
+}
+
+/// Encrypt `plaintext` to a subscription's keys as an `aes128gcm` body (RFC 8291), in one
+/// record, with a fresh key pair and salt every time
+pub fn encrypt(p256dh: &str, auth: &str, plaintext: &[u8]) -> Result<Vec<u8>, String> {
+    if plaintext.len() > MAX_MESSAGE_BYTES {
+        return Err(format!(
+            "Message is {} bytes, more than a push can take",
+            plaintext.len()
+        ));
+    }
+    let (ua_public, auth) = decode_keys(p256dh, auth)?;
+    let ua_public = ua_public.to_encoded_point(false);
+
+    let as_secret = loop {
+        if let Ok(secret) = SecretKey::from_slice(&random::<32>()) {
+            break secret;
+        }
+    };
+    let as_public = as_secret.public_key().to_encoded_point(false);
+    let shared = p256::ecdh::diffie_hellman(
+        as_secret.to_nonzero_scalar(),
+        PublicKey::from_sec1_bytes(ua_public.as_bytes())
+            .map_err(|_| "Bad public key".to_string())?
+            .as_affine(),
+    );
+
+    let mut key_info = b"WebPush: info\0".to_vec();
+    key_info.extend_from_slice(ua_public.as_bytes());
+    key_info.extend_from_slice(as_public.as_bytes());
+    let mut ikm = [0u8; 32];
+    Hkdf::<Sha256>::new(Some(&auth), shared.raw_secret_bytes())
+        .expand(&key_info, &mut ikm)
+        .map_err(|e| e.to_string())?;
+
+    let salt = random::<16>();
+    let hkdf = Hkdf::<Sha256>::new(Some(&salt), &ikm);
+    let (mut cek, mut nonce) = ([0u8; 16], [0u8; 12]);
+    hkdf.expand(b"Content-Encoding: aes128gcm\0", &mut cek)
+        .and_then(|_| hkdf.expand(b"Content-Encoding: nonce\0", &mut nonce))
+        .map_err(|e| e.to_string())?;
+
+    // The last (and only) record ends with the delimiter 2 and no padding
+    let mut record = plaintext.to_vec();
+    record.push(2);
+    let ciphertext = Aes128Gcm::new_from_slice(&cek)
+        .map_err(|e| e.to_string())?
+        .encrypt(aes_gcm::Nonce::from_slice(&nonce), record.as_slice())
+        .map_err(|_| "AES-GCM encryption failed".to_string())?;
+
+    let mut body = salt.to_vec();
+    body.extend_from_slice(&RECORD_SIZE.to_be_bytes());
+    body.push(as_public.as_bytes().len() as u8);
+    body.extend_from_slice(as_public.as_bytes());
+    body.extend(ciphertext);
+    Ok(body)
+}
+
+/// The notifications for the reminders due `today`. `contacts` are the workspace's, for naming
+/// the reminders' contacts.
+pub fn reminder_messages(
+    reminders: &[Reminder],
+    today: time::Date,
+    contacts: &[Contact],
+) -> Vec<PushMessage> {
+    let names = |ids: &[i32]| -> Vec<String> {
+        contacts
+            .iter()
+            .filter(|c| ids.contains(&c.contact_id))
+            .map(contact_name)
+            .collect()
+    };
+    reminders
+        .iter()
+        .filter(|r| r.remind_on == today)
+        .map(|reminder| match reminder.kind {
+            ReminderKind::Occasion => {
+                let title = if reminder.date == today {
+                    format!("Today: {}", reminder.name)
+                } else {
+                    format!("{} on {}", reminder.name, reminder.date)
+                };
+                let mut body = names(&reminder.contact_ids).join(", ");
+                if !reminder.gift_ideas.is_empty() {
+                    let ideas: Vec<&str> = reminder
+                        .gift_ideas
+                        .iter()
+                        .map(|g| g.idea.as_str())
+                        .collect();
+                    body = format!("{}. Gift ideas: {}", body, ideas.join(", "));
+                }
+                PushMessage {
+                    title,
+                    body,
+                    tag: format!(
+                        "occasion-{}-{}",
+                        reminder.occasion_id.unwrap_or_default(),
+                        reminder.date
+                    ),
+                }
+            }
+            ReminderKind::HolidayGreeting => {
+                let count = reminder.contact_ids.len();
+                PushMessage {
+                    title: reminder.name.clone(),
+                    body: format!(
+                        "{} on {}, for {} {}",
+                        reminder.name.trim_start_matches("Send holiday greetings: "),
+                        reminder.date,
+                        count,
+                        if count == 1 { "contact" } else { "contacts" }
+                    ),
+                    tag: format!("greeting-{}", reminder.date),
+                }
+            }
+        })
+        .map(|mut message| {
+            // Keep to what a push can carry; names and ideas are what gets cut
+            let too_long =
+                |m: &PushMessage| serde_json::to_vec(m).map_or(0, |m| m.len()) > MAX_MESSAGE_BYTES;
+            while too_long(&message) && message.body.chars().count() > 1 {
+                let keep = message.body.chars().count().saturating_sub(64);
+                message.body = message.body.chars().take(keep).collect::<String>() + "…";
+            }
+            message
+        })
+        .collect()
+}
+
+/// Queues pushes of due reminders, which the `outbox` sends
+pub struct PushReminders;
+
+impl PushReminders {
+    /// Queue the reminders due today for every recipient not yet pushed them today in their
+    /// timezone, returning how many notifications were queued
+    pub async fn queue_due(&self, repo: &dyn Repository) -> RepoResult<usize> {
+        let mut queued = 0;
+        for recipient in repo.push_reminder_recipients().await? {
+            let today = LocalDates::for_user(repo, recipient.user_id).await?.today();
+            if recipient.sent_on.is_some_and(|sent| sent >= today) {
+                continue;
+            }
+            let (today, due) = reminders::upcoming(
+                repo,
+                recipient.user_id,
+                recipient.workspace_id,
+                reminders::DEFAULT_DAYS,
+            )
+            .await?;
+            let contacts = repo.list_contacts(recipient.workspace_id).await?;
+            let messages = reminder_messages(&due, today, &contacts);
+            repo.queue_push_reminders(recipient.user_id, recipient.workspace_id, today, &messages)
+                .await?;
+            queued += messages.len();
+        }
+        Ok(queued)
+    }
+}
+
+/// Checks for reminders to push every `CHECK_INTERVAL`
+#[async_trait]
+impl Job for PushReminders {
+    fn name(&self) -> &'static str {
+        "push-reminders"
+    }
+
+    fn interval(&self) -> Duration {
+        CHECK_INTERVAL
+    }
+
+    async fn run(&self, repo: &dyn Repository) -> RepoResult<()> {
+        self.queue_due(repo).await.map(|_| ())
+    }
+}
+
+/// The key to subscribe with; null when this instance does not send pushes
+#[get("/push/vapid-public-key")]
+pub async fn vapid_public_key(
+    _auth_user: AuthUser,
+    web_push: Option<web::Data<WebPush>>,
+) -> impl Responder {
+    HttpResponse::Ok().json(serde_json::json!({
+        "public_key": web_push.as_ref().map(|w| w.public_key())
+    }))
+}
+
+#[get("/push/subscriptions")]
+pub async fn list_subscriptions(
+    repo: web::Data<dyn Repository>,
+    auth_user: AuthUser,
+) -> impl Responder {
+    match repo.list_push_subscriptions(auth_user.user_id).await {
+        Ok(subscriptions) => HttpResponse::Ok().json(subscriptions),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to fetch push subscriptions")
+        }
+    }
+}
+
+/// Register a device's subscription, or update the keys and name of one registered before
+#[post("/push/subscriptions")]
+pub async fn subscribe(
+    repo: web::Data<dyn Repository>,
+    auth_user: AuthUser,
+    subscription: web::Json<NewPushSubscriptionRequest>,
+) -> impl Responder {
+    let valid_endpoint =
+        reqwest::Url::parse(&subscription.endpoint).is_ok_and(|url| is_fetchable(&url));
+    if !valid_endpoint {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "endpoint must be an https URL on a public host name"
+        }));
+    }
+    if let Err(error) = decode_keys(&subscription.keys.p256dh, &subscription.keys.auth) {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": error }));
+    }
+    if subscription
+        .device_name
+        .as_ref()
+        .is_some_and(|name| name.chars().count() > MAX_DEVICE_NAME)
+    {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("device_name must be at most {} characters", MAX_DEVICE_NAME)
+        }));
+    }
+
+    match repo
+        .save_push_subscription(auth_user.user_id, &subscription)
+        .await
+    {
+        Ok(saved) => HttpResponse::Ok().json(saved),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to save push subscription")
+        }
+    }
+}
+
+#[delete("/push/subscriptions/{id}")]
+pub async fn unsubscribe(
+    repo: web::Data<dyn Repository>,
+    auth_user: AuthUser,
+    subscription_id: web::Path<i32>,
+) -> impl Responder {
+    let id = subscription_id.into_inner();
+
+    if let Err(response) =
+        ensure_owned(repo.get_ref(), &auth_user, Resource::PushSubscription, id).await
+    {
+        return response;
+    }
+
+    match repo.delete_push_subscription(auth_user.user_id, id).await {
+        Ok(false) => HttpResponse::NotFound().body("Push subscription not found"),
+        Ok(true) => HttpResponse::Ok().body("Push subscription deleted successfully"),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to delete push subscription")
+        }
+    }
+}
@@ -0,0 +1,430 @@
+//! Quick-add: a contact with its tags and occasions from one line of text.
+//!
+//! `Alice Zhang alice@x.com #college @sf birthday:1990-04-02` reads as the contact Alice Zhang
+//! with that email, tagged `college`, living in `sf` and born on April 2, 1990. Words are split
+//! on whitespace; double quotes keep spaces in one, as in `#"book club"` or `@"New York"`.
+//!
+//! - `#tag` tags the contact, reusing the workspace's tag of that name whatever its case
+//! - `@place` is where the contact lives
+//! - a word with `@` inside is the email, one starting with `+` the phone number
+//! - `birthday:` (or `bday:`) and `anniversary:` take `YYYY-MM-DD`, or `MM-DD` when the year
+//!   isn't known; a birthday's year is the contact's birth year
+//! - `phone:`, `note:` for the short note, and `every:` for the days wanted between
+//!   interactions, as `30` or `30d`
+//! - the other words are the name, the first of them the first name
+
+use crate::AuthUser;
+use crate::contacts;
+use crate::events::{self, Action, Entity, EventBus};
+use crate::geocoding::Geocoding;
+use crate::models::{
+    NewContactRequest, NewOccasionRequest, NewTagRequest, OccasionType, QuickAddOccasion,
+    QuickAddParse, QuickAddRequest, QuickAddResponse,
+};
+use crate::mutations;
+use crate::occasions;
+use crate::quotas::{self, Quotas};
+use crate::repository::{self, Repository};
+use crate::tags;
+use crate::timezone::LocalDates;
+use actix_web::{HttpRequest, HttpResponse, Responder, post, web};
+use serde::Deserialize;
+use std::collections::HashMap;
+use time::macros::format_description;
+use time::{Date, Month};
+
+/// Longest line, in characters
+pub const MAX_QUICK_ADD_CHARS: usize = 1000;
+
+/// Split on whitespace outside double quotes, dropping the quotes
+fn words(text: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    // A word was started, even if only by an empty pair of quotes
+    let mut started = false;
+    let mut quoted = false;
+    for c in text.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                started = true;
+            }
+            c if c.is_whitespace() && !quoted => {
+                if started {
+                    words.push(std::mem::take(&mut word));
+                    started = false;
+                }
+            }
+            c => {
+                word.push(c);
+                started = true;
+            }
+        }
+    }
+    if quoted {
+        return Err("A quote is not closed".to_string());
+    }
+    if started {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+/// `YYYY-MM-DD`, or `MM-DD` in `today`'s year (the last leap year for February 29), with
+/// whether the year was given
+fn parse_date(value: &str, today: Date) -> Option<(Date, bool)> {
+    if let Ok(date) = Date::parse(value, format_description!("[year]-[month]-[day]")) {
+        return Some((date, true));
+    }
+    let (month, day) = value.split_once('-')?;
+    let month = Month::try_from(month.parse::<u8>().ok()?).ok()?;
+    let day = day.parse::<u8>().ok()?;
+    (0..4)
+        .find_map(|back| Date::from_calendar_date(today.year() - back, month, day).ok())
+        .map(|date| (date, false))
+}
+
+fn set_once(field: &mut Option<String>, value: &str, what: &str) -> Result<(), String> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Err(format!("The {} is empty", what));
+    }
+    if field.is_some() {
+        return Err(format!("Only one {} can be given", what));
+    }
+    *field = Some(value.to_string());
+    Ok(())
+}
+
+fn add_occasion(
+    parsed: &mut QuickAddParse,
+    occasion_type: OccasionType,
+    value: &str,
+    today: Date,
+) -> Result<(), String> {
+    let what = match occasion_type {
+        OccasionType::Birthday => "birthday",
+        OccasionType::Anniversary => "anniversary",
+        OccasionType::Custom => "occasion",
+    };
+    if parsed
+        .occasions
+        .iter()
+        .any(|o| o.occasion_type == occasion_type)
+    {
+        return Err(format!("Only one {} can be given", what));
+    }
+    let (date, year_given) = parse_date(value, today)
+        .ok_or_else(|| format!("The {} must be YYYY-MM-DD or MM-DD", what))?;
+    parsed.occasions.push(QuickAddOccasion {
+        occasion_type,
+        date,
+        birth_year: (year_given && occasion_type == OccasionType::Birthday).then(|| date.year()),
+    });
+    Ok(())
+}
+
+/// Read a quick-add line; `today` is the year of dates given without one
+pub fn parse(text: &str, today: Date) -> Result<QuickAddParse, String> {
+    let mut parsed = QuickAddParse::default();
+    let mut name = Vec::new();
+    for word in words(text)? {
+        if let Some(tag) = word.strip_prefix('#') {
+            let tag = tag.trim();
+            if tag.is_empty() {
+                return Err("# needs a tag name after it".to_string());
+            }
+            if !parsed
+                .tags
+                .iter()
+                .any(|t| t.to_lowercase() == tag.to_lowercase())
+            {
+                parsed.tags.push(tag.to_string());
+            }
+        } else if let Some(place) = word.strip_prefix('@') {
+            set_once(&mut parsed.location, place, "location")?;
+        } else if word.contains('@') {
+            set_once(&mut parsed.email, &word, "email")?;
+        } else if word.starts_with('+') {
+            set_once(&mut parsed.phone, &word, "phone number")?;
+        } else if let Some((key, value)) = word.split_once(':') {
+            match key.to_lowercase().as_str() {
+                "birthday" | "bday" => {
+                    add_occasion(&mut parsed, OccasionType::Birthday, value, today)?
+                }
+                "anniversary" => {
+                    add_occasion(&mut parsed, OccasionType::Anniversary, value, today)?
+                }
+                "phone" => set_once(&mut parsed.phone, value, "phone number")?,
+                "note" => set_once(&mut parsed.short_note, value, "note")?,
+                "every" => {
+                    if parsed.desired_frequency_days.is_some() {
+                        return Err("Only one frequency can be given".to_string());
+                    }
+                    let days = value.strip_suffix('d').unwrap_or(value);
+                    parsed.desired_frequency_days = Some(
+                        days.parse()
+                            .map_err(|_| "every: takes a number of days".to_string())?,
+                    );
+                }
+                _ => return Err(format!("Unknown field {}:", key)),
+            }
+        } else if !word.trim().is_empty() {
+            name.push(word.trim().to_string());
+        }
+    }
+
+    let mut name = name.into_iter();
+    parsed.first_name = Some(
+        name.next()
+            .ok_or_else(|| "The line needs a name".to_string())?,
+    );
+    let last_name = name.collect::<Vec<String>>().join(" ");
+    parsed.last_name = (!last_name.is_empty()).then_some(last_name);
+    Ok(parsed)
+}
+
+/// The contact a parsed line creates
+pub fn new_contact(parsed: &QuickAddParse) -> NewContactRequest {
+    NewContactRequest {
+        first_name: parsed.first_name.clone(),
+        last_name: parsed.last_name.clone(),
+        email: parsed.email.clone(),
+        phone: parsed.phone.clone(),
+        short_note: parsed.short_note.clone(),
+        desired_frequency_days: parsed.desired_frequency_days,
+        location: parsed.location.clone(),
+        ..Default::default()
+    }
+}
+
+/// The occasions a parsed line adds to `contact_id`, repeating every year
+pub fn new_occasions(parsed: &QuickAddParse, contact_id: i32) -> Vec<NewOccasionRequest> {
+    parsed
+        .occasions
+        .iter()
+        .map(|occasion| NewOccasionRequest {
+            contact_id,
+            name: match occasion.occasion_type {
+                OccasionType::Birthday => "Birthday",
+                OccasionType::Anniversary => "Anniversary",
+                OccasionType::Custom => "Occasion",
+            }
+            .to_string(),
+            date: occasion.date,
+            recurring: true,
+            recurring_interval: None,
+            details: None,
+            occasion_type: occasion.occasion_type,
+            birth_year: occasion.birth_year,
+            remind_days_before: None,
+        })
+        .collect()
+}
+
+#[derive(Deserialize)]
+struct QuickAddQuery {
+    #[serde(default)]
+    dry_run: bool,
+}
+
+/// Create a contact with its tags and occasions from one line, answering with how the line
+/// was read. `?dry_run=true` only reads and checks it, for the user to confirm.
+#[post("/quick-add")]
+#[allow(clippy::too_many_arguments)]
+pub async fn quick_add(
+    req: HttpRequest,
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    geocoding: Option<web::Data<Geocoding>>,
+    quotas: Option<web::Data<Quotas>>,
+    auth_user: AuthUser,
+    query: web::Query<QuickAddQuery>,
+    body: web::Json<QuickAddRequest>,
+) -> impl Responder {
+    if body.text.chars().count() > MAX_QUICK_ADD_CHARS {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Text must be up to {} characters", MAX_QUICK_ADD_CHARS)
+        }));
+    }
+    let dates = LocalDates::for_user(repo.get_ref(), auth_user.user_id)
+        .await
+        .unwrap_or_default();
+    let parsed = match parse(&body.text, dates.today()) {
+        Ok(parsed) => parsed,
+        Err(error) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({ "error": error }));
+        }
+    };
+
+    let contact = new_contact(&parsed);
+    if let Err(error) = contacts::validate(&contact) {
+        return HttpResponse::BadRequest().json(error);
+    }
+    for mut occasion in new_occasions(&parsed, 0) {
+        if let Err(error) = occasions::normalize(&mut occasion) {
+            return HttpResponse::BadRequest().json(error);
+        }
+    }
+    if let Some(response) = quotas::configured(quotas.as_ref())
+        .reject_contacts(repo.get_ref(), &auth_user, 1)
+        .await
+    {
+        return response;
+    }
+    let email_taken = HttpResponse::Conflict().json(serde_json::json!({
+        "error": "Another contact already has this email"
+    }));
+    match repo
+        .check_contacts(auth_user.workspace_id, std::slice::from_ref(&contact))
+        .await
+        .map(|mut results| results.pop())
+    {
+        Ok(Some(Err(e))) if repository::is_unique_violation(&e) => return email_taken,
+        Ok(_) => {}
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            return HttpResponse::InternalServerError().body("Failed to check contact");
+        }
+    }
+
+    let existing: HashMap<String, i32> = match repo.list_tags(auth_user.workspace_id).await {
+        Ok(existing) => existing
+            .into_iter()
+            .map(|tag| (tags::match_key(&tag.name), tag.tag_id))
+            .collect(),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            return HttpResponse::InternalServerError().body("Failed to fetch tags");
+        }
+    };
+    let mut tag_ids = Vec::new();
+    let mut new_tags = Vec::new();
+    for name in &parsed.tags {
+        match existing.get(&tags::match_key(name)) {
+            Some(&tag_id) => tag_ids.push(tag_id),
+            None => new_tags.push(name.clone()),
+        }
+    }
+
+    if query.dry_run {
+        return HttpResponse::Ok().json(QuickAddResponse {
+            parsed,
+            contact: None,
+            tag_ids,
+            new_tags,
+            occasion_ids: Vec::new(),
+            dry_run: true,
+        });
+    }
+
+    // Tags first, so they're in place when the contact is tagged
+    let mut created_tag_ids = Vec::new();
+    for name in &new_tags {
+        let tag = NewTagRequest {
+            name: name.clone(),
+            color: None,
+            details: None,
+        };
+        let tag = match tags::validate(tag) {
+            Ok(tag) => tag,
+            Err(error) => return HttpResponse::BadRequest().json(error),
+        };
+        match repo.create_tag(auth_user.workspace_id, &tag).await {
+            Ok(tag_id) => created_tag_ids.push(tag_id),
+            Err(e) => {
+                eprintln!("Database error: {:?}", e);
+                return HttpResponse::InternalServerError().body("Failed to create tag");
+            }
+        }
+    }
+    if !created_tag_ids.is_empty() {
+        events::publish(
+            bus.as_ref(),
+            auth_user.user_id,
+            Entity::Tag,
+            Action::Created,
+            created_tag_ids.clone(),
+        );
+    }
+    tag_ids.extend(created_tag_ids);
+
+    let contact_id = match repo.create_contact(auth_user.workspace_id, &contact).await {
+        Ok(contact_id) => contact_id,
+        // Taken since it was checked
+        Err(e) if repository::is_unique_violation(&e) => return email_taken,
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            return HttpResponse::InternalServerError().body("Failed to create contact");
+        }
+    };
+    for &tag_id in &tag_ids {
+        if let Err(e) = repo
+            .add_tag_to_contacts(auth_user.workspace_id, tag_id, &[contact_id])
+            .await
+        {
+            eprintln!("Database error: {:?}", e);
+            return HttpResponse::InternalServerError().body("Failed to tag contact");
+        }
+    }
+    let mut occasion_ids = Vec::new();
+    for occasion in new_occasions(&parsed, contact_id) {
+        match repo
+            .create_occasion(auth_user.workspace_id, &occasion)
+            .await
+        {
+            Ok(occasion_id) => occasion_ids.push(occasion_id),
+            Err(e) => {
+                eprintln!("Database error: {:?}", e);
+                return HttpResponse::InternalServerError().body("Failed to create occasion");
+            }
+        }
+    }
+    contacts::locate(
+        geocoding.as_ref(),
+        repo.get_ref(),
+        auth_user.workspace_id,
+        [(contact_id, &contact)],
+    )
+    .await;
+    events::publish(
+        bus.as_ref(),
+        auth_user.user_id,
+        Entity::Contact,
+        Action::Created,
+        vec![contact_id],
+    );
+    if !occasion_ids.is_empty() {
+        events::publish(
+            bus.as_ref(),
+            auth_user.user_id,
+            Entity::Occasion,
+            Action::Created,
+            occasion_ids.clone(),
+        );
+    }
+
+    let contact = match repo.get_contact(auth_user.workspace_id, contact_id).await {
+        Ok(contact) => contact,
+        Err(e) => {
+            eprintln!("Database error reading back a saved entity: {:?}", e);
+            None
+        }
+    };
+    let scope = req
+        .path()
+        .rsplit_once("/quick-add")
+        .map_or("", |(scope, _)| scope);
+    mutations::created_in(
+        &format!("{}/contacts", scope),
+        contact_id,
+        Ok(Some(QuickAddResponse {
+            parsed,
+            contact,
+            tag_ids,
+            new_tags,
+            occasion_ids,
+            dry_run: false,
+        })),
+    )
+}
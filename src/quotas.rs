@@ -0,0 +1,141 @@
+//! Per-user quotas, for running an instance as a small hosted service.
+//!
+//! QUOTA_MAX_CONTACTS caps the contacts a user keeps across their workspaces. Requests that
+//! would go over it get 402 with `"code": "quota_exceeded"` before anything is saved; the count
+//! is taken before saving, so requests racing each other can go over by a few contacts.
+//! QUOTA_MAX_ATTACHMENT_BYTES caps the total size of the files they attach to interactions in the
+//! same way. `GET /account/usage` shows the user's usage against the quotas.
+
+use crate::AuthUser;
+use crate::models::{AccountUsage, Usage, UsageLimits};
+use crate::repository::Repository;
+use actix_web::{HttpResponse, Responder, get, web};
+
+/// `code` of the 402 sent for requests over a quota
+pub const QUOTA_EXCEEDED: &str = "quota_exceeded";
+
+/// No quotas unless configured
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Quotas {
+    /// None for no limit
+    pub max_contacts: Option<i64>,
+    /// None for no limit
+    pub max_attachment_bytes: Option<i64>,
+}
+
+/// A positive limit from the environment, where 0, unset or unparsable means no limit
+fn env_limit(name: &str) -> Option<i64> {
+    std::env::var(name)
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .filter(|max| *max > 0)
+}
+
+impl Quotas {
+    /// Read QUOTA_MAX_CONTACTS and QUOTA_MAX_ATTACHMENT_BYTES
+    pub fn from_env() -> Self {
+        Quotas {
+            max_contacts: env_limit("QUOTA_MAX_CONTACTS"),
+            max_attachment_bytes: env_limit("QUOTA_MAX_ATTACHMENT_BYTES"),
+        }
+    }
+
+    pub fn limits(&self) -> UsageLimits {
+        UsageLimits {
+            contacts: self.max_contacts,
+            attachment_bytes: self.max_attachment_bytes,
+        }
+    }
+
+    /// The 402 for a request that would take the user over `max_contacts` by creating
+    /// `adding` contacts, or the 500 if their contacts can't be counted
+    pub async fn reject_contacts(
+        &self,
+        repo: &dyn Repository,
+        auth_user: &AuthUser,
+        adding: usize,
+    ) -> Option<HttpResponse> {
+        let max = self.max_contacts?;
+        reject_over(
+            repo,
+            auth_user,
+            max,
+            adding,
+            |usage| usage.contacts,
+            |max| format!("Contact limit reached: at most {} contacts", max),
+        )
+        .await
+    }
+
+    /// The 402 for an upload that would take the user over `max_attachment_bytes` by storing
+    /// `adding` more bytes, or the 500 if their attachments can't be measured
+    pub async fn reject_attachment(
+        &self,
+        repo: &dyn Repository,
+        auth_user: &AuthUser,
+        adding: usize,
+    ) -> Option<HttpResponse> {
+        let max = self.max_attachment_bytes?;
+        reject_over(
+            repo,
+            auth_user,
+            max,
+            adding,
+            |usage| usage.attachment_bytes,
+            |max| format!("Attachment storage limit reached: at most {} bytes", max),
+        )
+        .await
+    }
+}
+
+/// The 402 if the user's `used` amount plus `adding` is over `max`, with `error` describing
+/// the limit
+async fn reject_over(
+    repo: &dyn Repository,
+    auth_user: &AuthUser,
+    max: i64,
+    adding: usize,
+    used: impl FnOnce(Usage) -> i64,
+    error: impl FnOnce(i64) -> String,
+) -> Option<HttpResponse> {
+    let used = match repo.usage(auth_user.user_id).await {
+        Ok(usage) => used(usage),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            return Some(HttpResponse::InternalServerError().body("Failed to check quota"));
+        }
+    };
+    if used.saturating_add(adding as i64) <= max {
+        return None;
+    }
+    Some(HttpResponse::PaymentRequired().json(serde_json::json!({
+        "error": error(max),
+        "code": QUOTA_EXCEEDED,
+        "limit": max,
+        "used": used
+    })))
+}
+
+/// The app's quotas, or none when none are registered
+pub fn configured(quotas: Option<&web::Data<Quotas>>) -> Quotas {
+    quotas.map_or_else(Quotas::default, |quotas| *quotas.get_ref())
+}
+
+/// What the user's workspaces hold, against the instance's quotas
+#[get("/account/usage")]
+pub async fn get_usage(
+    repo: web::Data<dyn Repository>,
+    auth_user: AuthUser,
+    quotas: Option<web::Data<Quotas>>,
+) -> impl Responder {
+    match repo.usage(auth_user.user_id).await {
+        Ok(usage) => HttpResponse::Ok().json(AccountUsage {
+            usage,
+            limits: configured(quotas.as_ref()).limits(),
+        }),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to fetch usage")
+        }
+    }
+}
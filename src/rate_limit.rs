@@ -0,0 +1,242 @@
+use crate::cached_subject;
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue, RETRY_AFTER, X_FORWARDED_FOR};
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpResponse, web};
+use moka::future::Cache;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("ratelimit-limit");
+const RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("ratelimit-remaining");
+const RATELIMIT_RESET: HeaderName = HeaderName::from_static("ratelimit-reset");
+
+#[derive(Debug, Clone)]
+pub struct RateLimitConfig {
+    /// Maximum number of tokens a bucket can hold (the burst size)
+    pub capacity: u32,
+    /// Tokens added back to a bucket every second
+    pub refill_per_sec: f64,
+    /// Tokens consumed by a single request to a bulk endpoint
+    pub bulk_cost: u32,
+    /// Reverse proxies in front of the server. Anonymous callers are keyed by the connection's
+    /// address, or by the address a trusted proxy forwarded in X-Forwarded-For.
+    pub trusted_proxies: Vec<IpAddr>,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        RateLimitConfig {
+            capacity: 60,
+            refill_per_sec: 1.0,
+            bulk_cost: 10,
+            trusted_proxies: Vec::new(),
+        }
+    }
+}
+
+impl RateLimitConfig {
+    /// Read RATE_LIMIT_CAPACITY, RATE_LIMIT_REFILL_PER_SEC, RATE_LIMIT_BULK_COST and
+    /// RATE_LIMIT_TRUSTED_PROXIES (comma separated addresses), falling back to the defaults for
+    /// anything unset or unparsable
+    pub fn from_env() -> Self {
+        let defaults = RateLimitConfig::default();
+        RateLimitConfig {
+            capacity: env_or("RATE_LIMIT_CAPACITY", defaults.capacity),
+            refill_per_sec: env_or("RATE_LIMIT_REFILL_PER_SEC", defaults.refill_per_sec),
+            bulk_cost: env_or("RATE_LIMIT_BULK_COST", defaults.bulk_cost),
+            trusted_proxies: std::env::var("RATE_LIMIT_TRUSTED_PROXIES")
+                .unwrap_or_default()
+                .split(',')
+                .filter_map(|ip| ip.trim().parse().ok())
+                .collect(),
+        }
+    }
+}
+
+fn env_or<T: std::str::FromStr>(key: &str, default: T) -> T {
+    std::env::var(key)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+/// Outcome of a rate limit check, used to populate the RateLimit-* headers
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimitDecision {
+    pub allowed: bool,
+    pub limit: u32,
+    pub remaining: u32,
+    /// Seconds until the bucket is full again (or, when rejected, until the request would fit)
+    pub reset_secs: u64,
+}
+
+/// Token bucket rate limiter keyed by an arbitrary client identifier
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    buckets: Cache<String, Arc<Mutex<Bucket>>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        // An idle bucket is full again after capacity / refill seconds, so it can be dropped
+        let refill_secs = (config.capacity as f64 / config.refill_per_sec.max(f64::EPSILON)).ceil();
+        let buckets = Cache::builder()
+            .time_to_idle(Duration::from_secs_f64(refill_secs.clamp(1.0, 86_400.0)))
+            .max_capacity(100_000)
+            .build();
+        RateLimiter { config, buckets }
+    }
+
+    pub fn from_env() -> Self {
+        RateLimiter::new(RateLimitConfig::from_env())
+    }
+
+    pub fn config(&self) -> &RateLimitConfig {
+        &self.config
+    }
+
+    /// Take `cost` tokens from the bucket for `key`, refilling it for the time elapsed first
+    pub async fn check(&self, key: &str, cost: u32) -> RateLimitDecision {
+        let capacity = self.config.capacity as f64;
+        let refill = self.config.refill_per_sec.max(f64::EPSILON);
+        let bucket = self
+            .buckets
+            .get_with(key.to_string(), async move {
+                Arc::new(Mutex::new(Bucket {
+                    tokens: capacity,
+                    last_refill: Instant::now(),
+                }))
+            })
+            .await;
+
+        let mut bucket = bucket.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * refill).min(capacity);
+        bucket.last_refill = now;
+
+        let cost = cost as f64;
+        let allowed = bucket.tokens >= cost;
+        if allowed {
+            bucket.tokens -= cost;
+        }
+
+        let reset_secs = if allowed {
+            (capacity - bucket.tokens) / refill
+        } else {
+            (cost - bucket.tokens) / refill
+        };
+
+        RateLimitDecision {
+            allowed,
+            limit: self.config.capacity,
+            remaining: bucket.tokens.floor() as u32,
+            reset_secs: reset_secs.ceil() as u64,
+        }
+    }
+
+    /// Bulk endpoints do many rows of work per request, so they drain the bucket faster
+    pub fn request_cost(&self, path: &str) -> u32 {
+        if path.ends_with("/bulk")
+            || path.ends_with("/bulk-delete")
+            || path.ends_with("/import/json")
+        {
+            self.config.bulk_cost
+        } else {
+            1
+        }
+    }
+}
+
+/// The caller's address: the connection's, unless it comes from a trusted proxy. Then it is the
+/// nearest address in X-Forwarded-For that isn't a trusted proxy; proxies append to the header,
+/// so anything further left came from the client and can't be trusted.
+fn client_ip(req: &ServiceRequest, trusted_proxies: &[IpAddr]) -> Option<IpAddr> {
+    let mut ip = req.peer_addr()?.ip();
+    let forwarded: Vec<&str> = req
+        .headers()
+        .get_all(X_FORWARDED_FOR)
+        .filter_map(|h| h.to_str().ok())
+        .flat_map(|h| h.split(','))
+        .collect();
+    for hop in forwarded.iter().rev() {
+        if !trusted_proxies.contains(&ip) {
+            break;
+        }
+        let hop = hop.trim();
+        match hop
+            .parse()
+            .or_else(|_| hop.parse::<SocketAddr>().map(|addr| addr.ip()))
+        {
+            Ok(hop) => ip = hop,
+            Err(_) => break,
+        }
+    }
+    Some(ip)
+}
+
+/// Identify the caller: the Auth0 subject for an already validated token, otherwise the client IP
+async fn client_key(req: &ServiceRequest, trusted_proxies: &[IpAddr]) -> String {
+    let token = req
+        .headers()
+        .get("Authorization")
+        .and_then(|h| h.to_str().ok())
+        .and_then(|s| s.strip_prefix("Bearer "));
+
+    if let Some(token) = token
+        && let Some(sub) = cached_subject(token).await
+    {
+        return format!("user:{}", sub);
+    }
+
+    match client_ip(req, trusted_proxies) {
+        Some(ip) => format!("ip:{}", ip),
+        None => "ip:unknown".to_string(),
+    }
+}
+
+fn set_headers(headers: &mut actix_web::http::header::HeaderMap, decision: &RateLimitDecision) {
+    headers.insert(RATELIMIT_LIMIT, HeaderValue::from(decision.limit));
+    headers.insert(RATELIMIT_REMAINING, HeaderValue::from(decision.remaining));
+    headers.insert(RATELIMIT_RESET, HeaderValue::from(decision.reset_secs));
+}
+
+/// Middleware rejecting requests with 429 once the caller's bucket is empty.
+/// Requires a `web::Data<RateLimiter>` in app data; the health check is never limited.
+pub async fn rate_limit(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let limiter = req.app_data::<web::Data<RateLimiter>>().cloned();
+    let limiter = match limiter {
+        Some(l) if !req.path().starts_with("/health") => l,
+        _ => return next.call(req).await.map(|res| res.map_into_boxed_body()),
+    };
+
+    let key = client_key(&req, &limiter.config.trusted_proxies).await;
+    let decision = limiter.check(&key, limiter.request_cost(req.path())).await;
+
+    if !decision.allowed {
+        let mut response = HttpResponse::TooManyRequests().json(serde_json::json!({
+            "error": "Rate limit exceeded",
+            "retry_after": decision.reset_secs
+        }));
+        set_headers(response.headers_mut(), &decision);
+        response
+            .headers_mut()
+            .insert(RETRY_AFTER, HeaderValue::from(decision.reset_secs));
+        return Ok(req.into_response(response));
+    }
+
+    let mut res = next.call(req).await?;
+    set_headers(res.headers_mut(), &decision);
+    Ok(res.map_into_boxed_body())
+}
@@ -0,0 +1,78 @@
+//! Recent sign-in for requests that could wipe or copy out everything.
+//!
+//! Deleting the account, the bulk deletes and the exports need a token whose user signed in
+//! (its `auth_time` claim) or, failing that, which was issued (`iat`) within
+//! REAUTH_MAX_AGE_SECS. Older tokens, and tokens carrying neither claim, get 403 with
+//! `"code": "reauth_required"`; the client should have the user sign in again, e.g. with Auth0's
+//! `max_age` or `prompt=login`, and retry. A stolen long-lived token can still read, but can't
+//! delete the account or take a copy of it.
+
+use crate::AuthUser;
+use actix_web::{HttpResponse, web};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How recent a sign-in must be, unless REAUTH_MAX_AGE_SECS says otherwise
+pub const DEFAULT_REAUTH_MAX_AGE_SECS: u64 = 15 * 60;
+
+/// `code` of the 403 sent for tokens that are too old
+pub const REAUTH_REQUIRED: &str = "reauth_required";
+
+#[derive(Debug, Clone, Copy)]
+pub struct Reauth {
+    /// None turns the check off
+    pub max_age_secs: Option<u64>,
+}
+
+impl Default for Reauth {
+    fn default() -> Self {
+        Reauth {
+            max_age_secs: Some(DEFAULT_REAUTH_MAX_AGE_SECS),
+        }
+    }
+}
+
+impl Reauth {
+    /// Read REAUTH_MAX_AGE_SECS, where 0 turns the check off, falling back to the default when
+    /// it is unset or unparsable
+    pub fn from_env() -> Self {
+        match std::env::var("REAUTH_MAX_AGE_SECS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+        {
+            Some(0) => Reauth { max_age_secs: None },
+            Some(secs) => Reauth {
+                max_age_secs: Some(secs),
+            },
+            None => Reauth::default(),
+        }
+    }
+
+    /// The 403 for a request whose sign-in is older than `max_age_secs` at unix time `now`, or
+    /// whose token doesn't say when it was
+    pub fn reject_stale_at(&self, auth_user: &AuthUser, now: u64) -> Option<HttpResponse> {
+        let max_age = self.max_age_secs?;
+        let fresh = auth_user
+            .authenticated_at
+            .is_some_and(|at| now.saturating_sub(at as u64) <= max_age);
+        if fresh {
+            return None;
+        }
+        Some(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Sign in again to do this",
+            "code": REAUTH_REQUIRED,
+            "max_age_secs": max_age
+        })))
+    }
+
+    pub fn reject_stale(&self, auth_user: &AuthUser) -> Option<HttpResponse> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        self.reject_stale_at(auth_user, now)
+    }
+}
+
+/// The app's setting, or the default when none is registered
+pub fn configured(reauth: Option<&web::Data<Reauth>>) -> Reauth {
+    reauth.map_or_else(Reauth::default, |reauth| *reauth.get_ref())
+}
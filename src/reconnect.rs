@@ -0,0 +1,357 @@
+//! Weekly "who to reconnect with" rotation.
+//!
+//! Each week a handful of the most neglected contacts are picked, spread across tags so the
+//! list isn't all coworkers one week and all family the next. Neglect is measured against each
+//! contact's desired frequency, or the user's default cadence for contacts without one. Picks
+//! are stored per week, so the list stays stable until Monday, and contacts picked in the last
+//! few weeks sit out while there are enough others to choose from. Archived contacts are never
+//! picked, and neither are contacts the user snoozed or dismissed; those also drop out of a
+//! week already picked.
+
+use crate::AuthUser;
+use crate::models::{
+    Contact, DEFAULT_REMINDER_CADENCE_DAYS, ReconnectPick, ReconnectResponse, SuggestionSnooze, Tag,
+};
+use crate::repository::{RepoResult, Repository};
+use crate::routes::{Owned, OwnedContact, Resource, ensure_owned};
+use crate::timezone::LocalDates;
+use actix_web::{HttpResponse, Responder, delete, get, post, web};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use time::{Date, Duration};
+
+/// Contacts suggested per week
+pub const PICKS_PER_WEEK: usize = 5;
+
+/// Weeks a picked contact sits out before it can be picked again
+pub const COOLDOWN_WEEKS: i64 = 4;
+
+/// Snooze length when `days` is left out
+pub const DEFAULT_SNOOZE_DAYS: i64 = 14;
+
+/// Longest snooze; dismiss a contact to leave it out for longer
+pub const MAX_SNOOZE_DAYS: i64 = 365;
+
+/// A contact considered for the rotation
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub contact_id: i32,
+    pub tag_ids: Vec<i32>,
+    /// None if there has never been an interaction
+    pub days_since_last_interaction: Option<i64>,
+    /// Days the user wants between interactions with the contact
+    pub cadence_days: i32,
+}
+
+impl Candidate {
+    /// Days past the cadence since the last interaction, negative while not due
+    fn overdue_days(&self) -> Option<i64> {
+        self.days_since_last_interaction
+            .map(|days| days - i64::from(self.cadence_days))
+    }
+}
+
+/// Monday of the week containing `date`
+pub fn week_start(date: Date) -> Date {
+    date - Duration::days(date.weekday().number_days_from_monday() as i64)
+}
+
+/// Pick up to `count` contacts, most overdue first (never contacted before anyone else).
+/// Contacts sharing a tag with an earlier pick wait for a second pass, and contacts in
+/// `recent` are only used when there are not enough others.
+pub fn pick(candidates: &[Candidate], recent: &HashSet<i32>, count: usize) -> Vec<i32> {
+    let mut ordered: Vec<&Candidate> = candidates.iter().collect();
+    ordered.sort_by_key(|c| {
+        (
+            recent.contains(&c.contact_id),
+            c.days_since_last_interaction.is_some(),
+            std::cmp::Reverse(c.overdue_days()),
+            c.contact_id,
+        )
+    });
+
+    let mut picked: Vec<i32> = Vec::new();
+    let mut covered_tags: HashSet<i32> = HashSet::new();
+    let mut picked_untagged = false;
+
+    // First pass: one contact per tag group, untagged contacts counting as their own group
+    for candidate in &ordered {
+        if picked.len() == count || recent.contains(&candidate.contact_id) {
+            break;
+        }
+        let overlaps = if candidate.tag_ids.is_empty() {
+            picked_untagged
+        } else {
+            candidate.tag_ids.iter().any(|t| covered_tags.contains(t))
+        };
+        if overlaps {
+            continue;
+        }
+        picked.push(candidate.contact_id);
+        covered_tags.extend(&candidate.tag_ids);
+        picked_untagged |= candidate.tag_ids.is_empty();
+    }
+
+    // Second pass: fill up in neglect order, recent picks last
+    for candidate in &ordered {
+        if picked.len() == count {
+            break;
+        }
+        if !picked.contains(&candidate.contact_id) {
+            picked.push(candidate.contact_id);
+        }
+    }
+
+    picked
+}
+
+/// The workspace's picks for the current week in the user's timezone, choosing and storing
+/// them on first request
+async fn picks_for_week(
+    repo: &dyn Repository,
+    user_id: i32,
+    workspace_id: i32,
+    dates: &LocalDates,
+) -> RepoResult<Vec<i32>> {
+    let today = dates.today();
+    let week = week_start(today);
+    let history = repo
+        .reconnect_picks(workspace_id, week - Duration::weeks(COOLDOWN_WEEKS))
+        .await?;
+
+    let mut this_week: Vec<i32> = history
+        .iter()
+        .filter(|(w, _)| *w == week)
+        .map(|(_, contact_id)| *contact_id)
+        .collect();
+    if !this_week.is_empty() {
+        this_week.sort_unstable();
+        return Ok(this_week);
+    }
+
+    let snoozed = repo.snoozed_contacts(user_id, today).await?;
+    let contacts: Vec<Contact> = repo
+        .list_contacts(workspace_id)
+        .await?
+        .into_iter()
+        .filter(|c| !c.archived && !snoozed.contains(&c.contact_id))
+        .collect();
+    let contact_ids: Vec<i32> = contacts.iter().map(|c| c.contact_id).collect();
+    let default_cadence = repo
+        .get_profile(user_id)
+        .await?
+        .map_or(DEFAULT_REMINDER_CADENCE_DAYS, |p| {
+            p.default_reminder_cadence_days
+        });
+
+    let mut last_interaction: HashMap<i32, Date> = HashMap::new();
+    for interaction in repo.interactions_for_contacts(&contact_ids).await? {
+        let date = dates.date_of(interaction.interaction_date);
+        for contact_id in interaction.participants() {
+            let latest = last_interaction.entry(contact_id).or_insert(date);
+            *latest = (*latest).max(date);
+        }
+    }
+
+    let mut tags: HashMap<i32, Vec<i32>> = HashMap::new();
+    for (contact_id, tag) in repo.tags_for_contacts(&contact_ids).await? {
+        tags.entry(contact_id).or_default().push(tag.tag_id);
+    }
+
+    let candidates: Vec<Candidate> = contacts
+        .iter()
+        .map(|c| Candidate {
+            contact_id: c.contact_id,
+            tag_ids: tags.remove(&c.contact_id).unwrap_or_default(),
+            days_since_last_interaction: last_interaction
+                .get(&c.contact_id)
+                .map(|date| (today - *date).whole_days()),
+            cadence_days: c.desired_frequency_days.unwrap_or(default_cadence),
+        })
+        .collect();
+    let recent: HashSet<i32> = history.iter().map(|(_, contact_id)| *contact_id).collect();
+
+    let picked = pick(&candidates, &recent, PICKS_PER_WEEK);
+    repo.save_reconnect_picks(workspace_id, week, &picked)
+        .await?;
+
+    // Read back in case a concurrent request saved this week's picks first
+    let mut saved: Vec<i32> = repo
+        .reconnect_picks(workspace_id, week)
+        .await?
+        .into_iter()
+        .filter(|(w, _)| *w == week)
+        .map(|(_, contact_id)| contact_id)
+        .collect();
+    saved.sort_unstable();
+    Ok(saved)
+}
+
+/// This week's suggestions for the user's workspace, in their timezone
+pub async fn this_week(
+    repo: &dyn Repository,
+    user_id: i32,
+    workspace_id: i32,
+    dates: &LocalDates,
+) -> RepoResult<ReconnectResponse> {
+    let today = dates.today();
+    let picked = picks_for_week(repo, user_id, workspace_id, dates).await?;
+    // Picks snoozed since the week's list was chosen drop out of it
+    let snoozed = repo.snoozed_contacts(user_id, today).await?;
+    let picked: Vec<i32> = picked
+        .into_iter()
+        .filter(|id| !snoozed.contains(id))
+        .collect();
+
+    let contacts = repo.list_contacts(workspace_id).await?;
+    let interactions = repo
+        .interactions_for_contacts(&picked)
+        .await
+        .unwrap_or_default();
+    let mut tags: HashMap<i32, Vec<Tag>> = HashMap::new();
+    for (contact_id, tag) in repo.tags_for_contacts(&picked).await.unwrap_or_default() {
+        tags.entry(contact_id).or_default().push(tag);
+    }
+
+    let picks: Vec<ReconnectPick> = contacts
+        .into_iter()
+        .filter(|c| picked.contains(&c.contact_id))
+        .map(|contact| {
+            let contact_id = contact.contact_id;
+            ReconnectPick {
+                days_since_last_interaction: interactions
+                    .iter()
+                    .filter(|i| i.participants().any(|id| id == contact_id))
+                    .map(|i| (today - dates.date_of(i.interaction_date)).whole_days())
+                    .min(),
+                tags: tags.remove(&contact_id).unwrap_or_default(),
+                contact,
+            }
+        })
+        .collect();
+
+    Ok(ReconnectResponse {
+        week_of: week_start(today),
+        contacts: picks,
+    })
+}
+
+/// This week's reconnect suggestions
+#[get("/reconnect/this-week")]
+pub async fn reconnect_this_week(
+    repo: web::Data<dyn Repository>,
+    auth_user: AuthUser,
+) -> impl Responder {
+    let response = async {
+        let dates = LocalDates::for_user(repo.get_ref(), auth_user.user_id).await?;
+        this_week(
+            repo.get_ref(),
+            auth_user.user_id,
+            auth_user.workspace_id,
+            &dates,
+        )
+        .await
+    };
+    match response.await {
+        Ok(response) => HttpResponse::Ok().json(response),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to pick contacts")
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct SnoozeQuery {
+    /// Days to leave the contact out, from 1 to `MAX_SNOOZE_DAYS`
+    days: Option<i64>,
+}
+
+/// Leave a contact out of the user's suggestions until `until`, or until the next interaction
+/// with it when None
+async fn snooze(
+    repo: &dyn Repository,
+    auth_user: &AuthUser,
+    contact_id: i32,
+    until: Option<Date>,
+) -> HttpResponse {
+    // Verify the contact belongs to the user
+    if let Err(response) = ensure_owned(repo, auth_user, Resource::Contact, contact_id).await {
+        return response;
+    }
+
+    match repo
+        .snooze_suggestion(auth_user.user_id, contact_id, until)
+        .await
+    {
+        Ok(()) => HttpResponse::Ok().json(SuggestionSnooze {
+            contact_id,
+            snoozed_until: until,
+        }),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to snooze contact")
+        }
+    }
+}
+
+/// Leave a contact out of the reconnect suggestions, and put it last when contacts are sorted
+/// by priority, for `days` (14 by default) in the user's timezone
+#[post("/contacts/{id}/snooze")]
+pub async fn snooze_contact(
+    repo: web::Data<dyn Repository>,
+    auth_user: AuthUser,
+    contact_id: web::Path<i32>,
+    query: web::Query<SnoozeQuery>,
+) -> impl Responder {
+    let days = query.days.unwrap_or(DEFAULT_SNOOZE_DAYS);
+    if !(1..=MAX_SNOOZE_DAYS).contains(&days) {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("days must be 1 to {}", MAX_SNOOZE_DAYS)
+        }));
+    }
+    let today = match LocalDates::for_user(repo.get_ref(), auth_user.user_id).await {
+        Ok(dates) => dates.today(),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            return HttpResponse::InternalServerError().body("Failed to snooze contact");
+        }
+    };
+
+    snooze(
+        repo.get_ref(),
+        &auth_user,
+        contact_id.into_inner(),
+        Some(today + Duration::days(days)),
+    )
+    .await
+}
+
+/// Like a snooze that lasts until the next interaction with the contact is logged
+#[post("/contacts/{id}/dismiss-suggestion")]
+pub async fn dismiss_suggestion(
+    repo: web::Data<dyn Repository>,
+    auth_user: AuthUser,
+    contact_id: web::Path<i32>,
+) -> impl Responder {
+    snooze(repo.get_ref(), &auth_user, contact_id.into_inner(), None).await
+}
+
+/// End a snooze or dismissal early; succeeds when the contact wasn't snoozed too
+#[delete("/contacts/{id}/snooze")]
+pub async fn unsnooze_contact(
+    repo: web::Data<dyn Repository>,
+    auth_user: AuthUser,
+    Owned(contact): OwnedContact,
+) -> impl Responder {
+    match repo
+        .unsnooze_suggestion(auth_user.user_id, contact.contact_id)
+        .await
+    {
+        Ok(_) => HttpResponse::Ok().body("Contact unsnoozed successfully"),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to unsnooze contact")
+        }
+    }
+}
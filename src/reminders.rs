@@ -0,0 +1,283 @@
+//! Upcoming reminders for occasions and holiday greetings.
+//!
+//! Reminders never fire on the user's quiet weekdays or on public holidays in their country;
+//! one that would is moved to the closest earlier day that is free, so it still arrives
+//! before the occasion. Major holidays also get a "send holiday greetings" reminder for the
+//! contacts in the user's greeting tags. Occasion reminders list the contact's gift ideas
+//! not yet given.
+//!
+//! An occasion is reminded of once for each of its `remind_days_before`, so `[14, 3, 0]` means
+//! two weeks ahead, three days ahead and on the day; occasions without their own use the
+//! user's preference. Lead times already past when an occasion is added are skipped.
+
+use crate::AuthUser;
+use crate::holidays::{self, Holiday};
+use crate::models::{
+    GiftIdea, GiftStatus, Occasion, OccasionType, Preferences, Reminder, ReminderKind,
+};
+use crate::repository::{RepoResult, Repository};
+use crate::timezone::LocalDates;
+use actix_web::{HttpResponse, Responder, get, web};
+use serde::Deserialize;
+use std::collections::BTreeSet;
+use time::{Date, Duration, Month};
+
+/// Days ahead of a major holiday that the greeting reminder is due
+pub const GREETING_LEAD_DAYS: i64 = 3;
+
+/// Default and maximum look-ahead for /reminders/upcoming
+pub const DEFAULT_DAYS: i64 = 30;
+pub const MAX_DAYS: i64 = 366;
+
+/// Most lead times an occasion or the user's default may have
+pub const MAX_LEAD_TIMES: usize = 10;
+/// Longest lead time, in days
+pub const MAX_DAYS_BEFORE: i32 = 365;
+
+/// Sort and deduplicate lead times, checking each is between 0 and `MAX_DAYS_BEFORE` days.
+/// Errors are the JSON body of the 400 response.
+pub fn normalize_days_before(days_before: &mut Vec<i32>) -> Result<(), serde_json::Value> {
+    days_before.sort_unstable();
+    days_before.dedup();
+    if days_before.len() > MAX_LEAD_TIMES
+        || days_before
+            .iter()
+            .any(|days| !(0..=MAX_DAYS_BEFORE).contains(days))
+    {
+        return Err(serde_json::json!({
+            "error": format!(
+                "remind_days_before takes up to {} values between 0 and {}",
+                MAX_LEAD_TIMES, MAX_DAYS_BEFORE
+            )
+        }));
+    }
+    Ok(())
+}
+
+/// `date`'s month and day in `year`; February 29 falls on the 28th in other years
+fn in_year(date: Date, year: i32) -> Date {
+    Date::from_calendar_date(year, date.month(), date.day())
+        .unwrap_or_else(|_| Date::from_calendar_date(year, Month::February, 28).unwrap())
+}
+
+/// The next time an occasion falls on or after `today`. Recurring occasions repeat every
+/// `recurring_interval` years (default 1), counted from the year of their date; birthdays
+/// repeat every year regardless.
+pub fn next_occurrence(occasion: &Occasion, today: Date) -> Option<Date> {
+    let birthday = occasion.occasion_type == OccasionType::Birthday;
+    if !birthday && occasion.recurring != Some(true) {
+        return (occasion.date >= today).then_some(occasion.date);
+    }
+    let interval = if birthday {
+        1
+    } else {
+        occasion.recurring_interval.filter(|n| *n > 0).unwrap_or(1)
+    };
+    let start = occasion.date.year();
+    let elapsed = (today.year() - start).max(0);
+    let mut year = start + (elapsed + interval - 1) / interval * interval;
+    if in_year(occasion.date, year) < today {
+        year += interval;
+    }
+    Some(in_year(occasion.date, year))
+}
+
+/// The age a contact turns on their next birthday on or after `today`; None unless the
+/// occasion is a birthday with a known birth year
+pub fn upcoming_age(occasion: &Occasion, today: Date) -> Option<i32> {
+    if occasion.occasion_type != OccasionType::Birthday {
+        return None;
+    }
+    let birth_year = occasion.birth_year?;
+    Some(next_occurrence(occasion, today)?.year() - birth_year)
+}
+
+/// Public holidays in the user's country from `from` to `to`; none without a country
+pub fn holidays_for(preferences: &Preferences, from: Date, to: Date) -> Vec<Holiday> {
+    preferences
+        .country
+        .as_deref()
+        .map(|country| holidays::between(country, from, to))
+        .unwrap_or_default()
+}
+
+/// Whether reminders and digests keep off `day`: one of the user's quiet weekdays, or one of
+/// `holidays`
+pub fn is_quiet_day(preferences: &Preferences, holidays: &[Holiday], day: Date) -> bool {
+    preferences
+        .quiet_weekdays
+        .contains(&(day.weekday().number_from_monday() as i32))
+        || holidays.iter().any(|h| h.date == day)
+}
+
+/// The closest day on or before `target`, but not before `today`, that is not quiet.
+/// If every such day is quiet the reminder stays on `target`.
+pub fn remind_on(target: Date, today: Date, is_quiet: impl Fn(Date) -> bool) -> Date {
+    let mut day = target;
+    while day >= today {
+        if !is_quiet(day) {
+            return day;
+        }
+        day -= Duration::days(1);
+    }
+    target
+}
+
+/// Reminders due from `today` for the next `days` days, in the order they fire.
+/// `greeting_contacts` are the contacts in the user's greeting tags.
+pub fn schedule(
+    today: Date,
+    days: i64,
+    preferences: &Preferences,
+    occasions: &[Occasion],
+    greeting_contacts: &[i32],
+) -> Vec<Reminder> {
+    let until = today + Duration::days(days);
+    let holidays = holidays_for(preferences, today, until);
+    let is_quiet = |day: Date| is_quiet_day(preferences, &holidays, day);
+
+    let mut reminders: Vec<Reminder> = occasions
+        .iter()
+        .flat_map(|occasion| {
+            let days_before: BTreeSet<i32> = occasion
+                .remind_days_before
+                .as_ref()
+                .unwrap_or(&preferences.remind_days_before)
+                .iter()
+                .copied()
+                .collect();
+            days_before.into_iter().filter_map(move |days_before| {
+                // The occurrence this lead time is still ahead for
+                let lead = Duration::days(days_before.into());
+                let date = next_occurrence(occasion, today + lead)?;
+                let target = date - lead;
+                (target <= until).then(|| Reminder {
+                    kind: ReminderKind::Occasion,
+                    remind_on: remind_on(target, today, is_quiet),
+                    date,
+                    name: occasion.name.clone(),
+                    days_before,
+                    occasion_id: Some(occasion.occasion_id),
+                    contact_ids: occasion.contact_id.into_iter().collect(),
+                    gift_ideas: Vec::new(),
+                })
+            })
+        })
+        .collect();
+
+    if !greeting_contacts.is_empty() {
+        let contact_ids: Vec<i32> = greeting_contacts
+            .iter()
+            .copied()
+            .collect::<BTreeSet<i32>>()
+            .into_iter()
+            .collect();
+        for holiday in holidays.iter().filter(|h| h.major) {
+            let target = (holiday.date - Duration::days(GREETING_LEAD_DAYS)).max(today);
+            reminders.push(Reminder {
+                kind: ReminderKind::HolidayGreeting,
+                remind_on: remind_on(target, today, is_quiet),
+                date: holiday.date,
+                name: format!("Send holiday greetings: {}", holiday.name),
+                days_before: (holiday.date - target).whole_days() as i32,
+                occasion_id: None,
+                contact_ids: contact_ids.clone(),
+                gift_ideas: Vec::new(),
+            });
+        }
+    }
+
+    reminders.sort_by(|a, b| (a.remind_on, a.date, &a.name).cmp(&(b.remind_on, b.date, &b.name)));
+    reminders
+}
+
+/// Give each occasion reminder the gift ideas not yet given for its contact that are meant for
+/// that occasion or for none
+pub fn attach_gift_ideas(reminders: &mut [Reminder], gift_ideas: &[GiftIdea]) {
+    for reminder in reminders
+        .iter_mut()
+        .filter(|r| r.kind == ReminderKind::Occasion)
+    {
+        reminder.gift_ideas = gift_ideas
+            .iter()
+            .filter(|g| {
+                g.status != GiftStatus::Given
+                    && reminder.contact_ids.contains(&g.contact_id)
+                    && g.occasion_id
+                        .is_none_or(|id| reminder.occasion_id == Some(id))
+            })
+            .cloned()
+            .collect();
+    }
+}
+
+#[derive(Deserialize)]
+struct UpcomingQuery {
+    /// Days to look ahead
+    days: Option<i64>,
+}
+
+/// The user's today, in their timezone, and the workspace's reminders from it
+pub async fn upcoming(
+    repo: &dyn Repository,
+    user_id: i32,
+    workspace_id: i32,
+    days: i64,
+) -> RepoResult<(Date, Vec<Reminder>)> {
+    let preferences = repo.get_preferences(user_id).await?;
+    let today = LocalDates::for_preferences(&preferences).today();
+    let contact_ids: Vec<i32> = repo
+        .list_contacts(workspace_id)
+        .await?
+        .iter()
+        .map(|c| c.contact_id)
+        .collect();
+    let occasions = repo.occasions_for_contacts(&contact_ids).await?;
+
+    let greeting_contacts: Vec<i32> = if preferences.greeting_tag_ids.is_empty() {
+        Vec::new()
+    } else {
+        repo.tags_for_contacts(&contact_ids)
+            .await?
+            .into_iter()
+            .filter(|(_, tag)| preferences.greeting_tag_ids.contains(&tag.tag_id))
+            .map(|(contact_id, _)| contact_id)
+            .collect()
+    };
+
+    let mut reminders = schedule(today, days, &preferences, &occasions, &greeting_contacts);
+    attach_gift_ideas(
+        &mut reminders,
+        &repo.gift_ideas_for_contacts(&contact_ids).await?,
+    );
+    Ok((today, reminders))
+}
+
+/// Reminders due in the next `?days=` days (default 30), adjusted for quiet days and holidays
+#[get("/reminders/upcoming")]
+pub async fn upcoming_reminders(
+    repo: web::Data<dyn Repository>,
+    auth_user: AuthUser,
+    query: web::Query<UpcomingQuery>,
+) -> impl Responder {
+    let days = query.days.unwrap_or(DEFAULT_DAYS);
+    if !(1..=MAX_DAYS).contains(&days) {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("days must be between 1 and {}", MAX_DAYS)
+        }));
+    }
+    match upcoming(
+        repo.get_ref(),
+        auth_user.user_id,
+        auth_user.workspace_id,
+        days,
+    )
+    .await
+    {
+        Ok((_, reminders)) => HttpResponse::Ok().json(reminders),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to fetch reminders")
+        }
+    }
+}
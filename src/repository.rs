@@ -0,0 +1,831 @@
+//! Storage interface used by the handlers.
+//!
+//! Every method is scoped to the owning workspace where the underlying table has a
+//! `workspace_id`, and to the owning user where it has a `user_id`, mirroring the
+//! `WHERE workspace_id = $n` and `WHERE user_id = $n` guards in the SQL. Callers check that the
+//! workspace is the user's first, as the `AuthUser` extractor does. Link-table and child-row
+//! methods (tags on a contact, interactions, occasions and social profiles for a set of
+//! contacts) expect the caller to have verified ownership of the parent first, as the handlers
+//! already do.
+
+use crate::AuthUser;
+use crate::cursor::Cursor;
+use crate::geocoding::BoundingBox;
+use crate::mail::Email;
+use crate::models::{
+    AccountSummary, Attachment, AttachmentFile, Capture, Contact, ContactAccess, ContactChange,
+    ContactChecksum, ContactDetails, ContactLink, ContactMatch, ContactShare, ContactSummary,
+    Coordinates, CustomInteractionType, DeliveryAttempt, ExportSchedule, GiftIdea, Goal,
+    Granularity, Group, HookDelivery, HookEvent, IdempotentRequest, InstanceStats, Interaction,
+    NewAttachment, NewContactRequest, NewGiftIdeaRequest, NewGoalRequest, NewGroupRequest,
+    NewInteractionRequest, NewOccasionRequest, NewPushSubscriptionRequest, NewRestHookRequest,
+    NewSavedFilterRequest, NewSocialProfileRequest, NewTagRequest, NoteMatch, Occasion,
+    OutboxMessage, PendingAvatarLookup, PendingTranscription, Preferences, PushRecipient,
+    PushSubscription, RestHook, ReviewRecipient, SavedFilter, Share, SharePermission,
+    SocialProfile, StatsMetric, StoredResponse, Tag, TagUsage, Unassigned, Usage, UserProfile,
+    Workspace,
+};
+use crate::push::PushMessage;
+use crate::timezone::LocalDates;
+use actix_web::web;
+use async_trait::async_trait;
+use std::sync::Arc;
+use time::{Date, OffsetDateTime};
+
+pub mod memory;
+pub mod postgres;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+pub use memory::InMemoryRepository;
+pub use postgres::PgRepository;
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteRepository;
+
+pub type RepoResult<T> = Result<T, sqlx::Error>;
+
+/// Name of the workspace created with every user
+pub const DEFAULT_WORKSPACE_NAME: &str = "Personal";
+
+/// Whether an error is a unique constraint violation, from Postgres, SQLite or the in-memory
+/// store
+pub fn is_unique_violation(e: &sqlx::Error) -> bool {
+    match e {
+        sqlx::Error::Database(e) => e.is_unique_violation(),
+        sqlx::Error::Protocol(message) => {
+            message.starts_with("duplicate key value violates unique constraint")
+        }
+        _ => false,
+    }
+}
+
+/// Wrap a repository for `App::app_data`; handlers extract it as `web::Data<dyn Repository>`
+pub fn app_data<R: Repository + 'static>(repo: R) -> web::Data<dyn Repository> {
+    web::Data::from(Arc::new(repo) as Arc<dyn Repository>)
+}
+
+#[async_trait]
+pub trait Repository: Send + Sync {
+    /// The user for an Auth0 subject, with their default workspace as `workspace_id`; None
+    /// before their first login
+    async fn find_user(&self, auth0_id: &str) -> RepoResult<Option<AuthUser>>;
+    /// Look up the user for an Auth0 subject, creating it with its default workspace on first
+    /// login. The user's `workspace_id` is the default workspace.
+    async fn get_or_create_user(
+        &self,
+        auth0_id: &str,
+        email: &str,
+        name: &str,
+    ) -> RepoResult<AuthUser>;
+    /// Delete a user and, by cascade, everything they own
+    async fn delete_user(&self, user_id: i32) -> RepoResult<()>;
+    /// Deactivate the user until `delete_after`, when `purge_deleted_users` deletes them
+    async fn schedule_user_deletion(
+        &self,
+        user_id: i32,
+        delete_after: OffsetDateTime,
+    ) -> RepoResult<()>;
+    /// Cancel the user's scheduled deletion and reactivate them; false if none was scheduled
+    async fn restore_user(&self, user_id: i32) -> RepoResult<bool>;
+    /// Delete every user whose scheduled deletion is due at `now`, returning their Auth0
+    /// subjects
+    async fn purge_deleted_users(&self, now: OffsetDateTime) -> RepoResult<Vec<String>>;
+    /// The user with their settings, defaults filled in; None if the user does not exist
+    async fn get_profile(&self, user_id: i32) -> RepoResult<Option<UserProfile>>;
+    /// Replace the user's name, email, timezone and settings together. Another user's email
+    /// is a unique violation.
+    async fn save_profile(&self, profile: &UserProfile) -> RepoResult<()>;
+    /// How much the user's workspaces hold together
+    async fn usage(&self, user_id: i32) -> RepoResult<Usage>;
+    /// The user with this email, compared case-insensitively
+    async fn find_user_by_email(&self, email: &str) -> RepoResult<Option<i32>>;
+    /// Every user with how much they keep, by user_id, for instance admins
+    async fn list_accounts(&self) -> RepoResult<Vec<AccountSummary>>;
+    async fn instance_stats(&self) -> RepoResult<InstanceStats>;
+    /// Deactivate or reactivate a user, returning their Auth0 subject; None if the user does
+    /// not exist. Reactivating cancels a scheduled deletion.
+    async fn set_deactivated(&self, user_id: i32, deactivated: bool) -> RepoResult<Option<String>>;
+    /// Every active user with the weekly review and email notifications on, by user_id
+    async fn weekly_review_recipients(&self) -> RepoResult<Vec<ReviewRecipient>>;
+    /// Note that the user's review for the week starting on Monday `week` was sent
+    async fn record_weekly_review(&self, user_id: i32, week: Date) -> RepoResult<()>;
+    /// Queue the user's review email in the outbox and note it was sent for the week starting
+    /// on Monday `week`, together
+    async fn queue_weekly_review(
+        &self,
+        user_id: i32,
+        workspace_id: i32,
+        week: Date,
+        email: &Email,
+    ) -> RepoResult<()>;
+    /// Queue an email in the workspace's outbox
+    async fn queue_email(&self, workspace_id: i32, email: &Email) -> RepoResult<()>;
+    /// Every active user with push notifications on and a device subscribed, by user_id
+    async fn push_reminder_recipients(&self) -> RepoResult<Vec<PushRecipient>>;
+    /// Queue each message in the outbox for every one of the user's subscriptions and note
+    /// that the reminders of their day `day` were pushed, together
+    async fn queue_push_reminders(
+        &self,
+        user_id: i32,
+        workspace_id: i32,
+        day: Date,
+        messages: &[PushMessage],
+    ) -> RepoResult<()>;
+    /// The user's subscriptions, oldest first
+    async fn list_push_subscriptions(&self, user_id: i32) -> RepoResult<Vec<PushSubscription>>;
+    /// Save a subscription, replacing the keys and device name of the user's subscription for
+    /// the same endpoint if they have one
+    async fn save_push_subscription(
+        &self,
+        user_id: i32,
+        subscription: &NewPushSubscriptionRequest,
+    ) -> RepoResult<PushSubscription>;
+    /// Delete a subscription with the pushes queued for it; false if the user has no such
+    /// subscription
+    async fn delete_push_subscription(
+        &self,
+        user_id: i32,
+        subscription_id: i32,
+    ) -> RepoResult<bool>;
+    async fn owns_push_subscription(&self, user_id: i32, subscription_id: i32) -> RepoResult<bool>;
+    /// Take the named job's lease for `holder` until `until`, unless another holder's lease
+    /// is still running at `now`; false when it is
+    async fn claim_job(
+        &self,
+        name: &str,
+        holder: &str,
+        now: OffsetDateTime,
+        until: OffsetDateTime,
+    ) -> RepoResult<bool>;
+
+    /// The user's workspaces, the default one first, then by name
+    async fn list_workspaces(&self, user_id: i32) -> RepoResult<Vec<Workspace>>;
+    /// A duplicate name is a unique violation
+    async fn create_workspace(&self, user_id: i32, name: &str) -> RepoResult<i32>;
+    async fn rename_workspace(
+        &self,
+        user_id: i32,
+        workspace_id: i32,
+        name: &str,
+    ) -> RepoResult<bool>;
+    /// Delete a workspace and, by cascade, everything in it. The default workspace is never
+    /// deleted; returns false for it as for workspaces that are not the user's.
+    async fn delete_workspace(&self, user_id: i32, workspace_id: i32) -> RepoResult<bool>;
+    async fn owns_workspace(&self, user_id: i32, workspace_id: i32) -> RepoResult<bool>;
+
+    /// Contacts ordered by last name, then first name (missing names last), archived included
+    async fn list_contacts(&self, workspace_id: i32) -> RepoResult<Vec<Contact>>;
+    /// A page of contacts, highest `predicted_contact_priority` as of the user's today first and
+    /// contacts without one last, then the `snoozed` ones, ties by id. The priority is computed
+    /// by the store, so only the requested page is loaded.
+    async fn contacts_by_priority(
+        &self,
+        workspace_id: i32,
+        dates: &LocalDates,
+        include_archived: bool,
+        snoozed: &[i32],
+        limit: Option<i64>,
+        offset: i64,
+    ) -> RepoResult<Vec<Contact>>;
+    async fn get_contact(&self, workspace_id: i32, contact_id: i32) -> RepoResult<Option<Contact>>;
+    /// The workspace's contacts whose `phone_e164` is `number`, archived ones included, by id
+    async fn contacts_by_phone(&self, workspace_id: i32, number: &str) -> RepoResult<Vec<Contact>>;
+    /// The workspace's contacts introduced by `contact_id`, archived ones included, by name
+    async fn contacts_introduced_by(
+        &self,
+        workspace_id: i32,
+        contact_id: i32,
+    ) -> RepoResult<Vec<Contact>>;
+    /// The workspace's `limit` most recently created contacts, archived ones included, newest
+    /// first
+    async fn latest_contacts(&self, workspace_id: i32, limit: i64) -> RepoResult<Vec<Contact>>;
+    /// The workspace's contacts created at or after `from` and before `until`, archived ones
+    /// included, oldest first
+    async fn contacts_created_between(
+        &self,
+        workspace_id: i32,
+        from: OffsetDateTime,
+        until: OffsetDateTime,
+    ) -> RepoResult<Vec<Contact>>;
+    /// The workspace's unarchived contacts with coordinates inside `area`, by contact_id
+    async fn contacts_in_box(
+        &self,
+        workspace_id: i32,
+        area: &BoundingBox,
+    ) -> RepoResult<Vec<Contact>>;
+    /// The workspace's unarchived contacts with a first or last name starting with each of the
+    /// lowercase `words`, by name
+    async fn autocomplete_contacts(
+        &self,
+        workspace_id: i32,
+        words: &[String],
+        limit: i64,
+    ) -> RepoResult<Vec<ContactMatch>>;
+    /// The given contacts with their tags, interactions and occasions, in the order of
+    /// `contact_ids`, loaded in a single query. Ids that do not exist or belong to someone else
+    /// are left out.
+    async fn contact_details(
+        &self,
+        workspace_id: i32,
+        contact_ids: &[i32],
+    ) -> RepoResult<Vec<ContactDetails>>;
+    /// Summaries of the given contacts by contact_id, with occasions from `today` on. Ids that
+    /// do not exist or belong to someone else are left out.
+    async fn contact_summaries(
+        &self,
+        workspace_id: i32,
+        contact_ids: &[i32],
+        today: Date,
+    ) -> RepoResult<Vec<(i32, ContactSummary)>>;
+    async fn create_contact(
+        &self,
+        workspace_id: i32,
+        contact: &NewContactRequest,
+    ) -> RepoResult<i32>;
+    /// Create many contacts in one transaction, returning one result per input in order.
+    /// With `atomic` the first failure rolls back the whole batch and is returned as the error;
+    /// otherwise failing rows are skipped and the rest are kept.
+    async fn create_contacts(
+        &self,
+        workspace_id: i32,
+        contacts: &[NewContactRequest],
+        atomic: bool,
+    ) -> RepoResult<Vec<RepoResult<i32>>>;
+    /// Whether each contact would be created by a non-atomic `create_contacts`, which fails
+    /// rows on the same constraints, without keeping any of them
+    async fn check_contacts(
+        &self,
+        workspace_id: i32,
+        contacts: &[NewContactRequest],
+    ) -> RepoResult<Vec<RepoResult<()>>>;
+    /// Returns false if the contact does not exist or belongs to someone else. The fields it
+    /// changes are recorded in the contact's history.
+    async fn update_contact(
+        &self,
+        workspace_id: i32,
+        contact_id: i32,
+        contact: &NewContactRequest,
+    ) -> RepoResult<bool>;
+    /// Returns false if the contact does not exist or belongs to someone else. With
+    /// `keep_history` the contact's interactions and occasions are kept, unassigned, instead of
+    /// going with it.
+    async fn delete_contact(
+        &self,
+        workspace_id: i32,
+        contact_id: i32,
+        keep_history: bool,
+    ) -> RepoResult<bool>;
+    /// Delete the given contacts in one statement, returning the ids that were deleted.
+    /// Ids that do not exist or belong to someone else are left alone. `keep_history` is as for
+    /// `delete_contact`.
+    async fn delete_contacts(
+        &self,
+        workspace_id: i32,
+        contact_ids: &[i32],
+        keep_history: bool,
+    ) -> RepoResult<Vec<i32>>;
+    /// Interactions and occasions kept from deleted contacts, oldest first
+    async fn list_unassigned(&self, workspace_id: i32) -> RepoResult<Unassigned>;
+    /// Returns false if the contact does not exist or belongs to someone else. A change is
+    /// recorded in the contact's history.
+    async fn set_contact_archived(
+        &self,
+        workspace_id: i32,
+        contact_id: i32,
+        archived: bool,
+    ) -> RepoResult<bool>;
+    /// Store the coordinates geocoded from `location`. Returns false if the contact does not
+    /// exist, belongs to someone else or has moved on from `location`.
+    async fn set_contact_coordinates(
+        &self,
+        workspace_id: i32,
+        contact_id: i32,
+        location: &str,
+        coordinates: Coordinates,
+    ) -> RepoResult<bool>;
+    async fn owns_contact(&self, workspace_id: i32, contact_id: i32) -> RepoResult<bool>;
+    /// Content hash per contact, ordered by contact_id
+    async fn contact_checksums(&self, workspace_id: i32) -> RepoResult<Vec<ContactChecksum>>;
+    /// Store the priority of up to `limit` contacts, across all workspaces, whose stored score is
+    /// missing or outdated, returning how many were stored. Stores that compute priorities on
+    /// every read store nothing.
+    async fn refresh_contact_scores(&self, limit: i64) -> RepoResult<u64>;
+
+    async fn list_tags(&self, workspace_id: i32) -> RepoResult<Vec<Tag>>;
+    async fn get_tag(&self, workspace_id: i32, tag_id: i32) -> RepoResult<Option<Tag>>;
+    /// The workspace's tags, each with the number of contacts it's attached to
+    async fn tag_usage(&self, workspace_id: i32) -> RepoResult<Vec<TagUsage>>;
+    async fn create_tag(&self, workspace_id: i32, tag: &NewTagRequest) -> RepoResult<i32>;
+    async fn update_tag(
+        &self,
+        workspace_id: i32,
+        tag_id: i32,
+        tag: &NewTagRequest,
+    ) -> RepoResult<bool>;
+    async fn delete_tag(&self, workspace_id: i32, tag_id: i32) -> RepoResult<bool>;
+    /// Delete the workspace's tags on no contact or interaction and not shared, returning their
+    /// ids
+    async fn delete_unused_tags(&self, workspace_id: i32) -> RepoResult<Vec<i32>>;
+    async fn owns_tag(&self, workspace_id: i32, tag_id: i32) -> RepoResult<bool>;
+    /// (contact_id, tag) pairs for every tag attached to the given contacts
+    async fn tags_for_contacts(&self, contact_ids: &[i32]) -> RepoResult<Vec<(i32, Tag)>>;
+    /// Attaching a tag twice is not an error
+    async fn add_tag_to_contact(&self, contact_id: i32, tag_id: i32) -> RepoResult<()>;
+    async fn remove_tag_from_contact(&self, contact_id: i32, tag_id: i32) -> RepoResult<()>;
+    async fn add_tag_to_interaction(&self, interaction_id: i32, tag_id: i32) -> RepoResult<()>;
+    async fn remove_tag_from_interaction(&self, interaction_id: i32, tag_id: i32)
+    -> RepoResult<()>;
+    /// Attach a tag to every given contact in the workspace in one statement, returning those
+    /// ids
+    async fn add_tag_to_contacts(
+        &self,
+        workspace_id: i32,
+        tag_id: i32,
+        contact_ids: &[i32],
+    ) -> RepoResult<Vec<i32>>;
+
+    async fn interactions_for_contacts(&self, contact_ids: &[i32]) -> RepoResult<Vec<Interaction>>;
+    /// The workspace's interactions among `interaction_ids`, by interaction_id
+    async fn interactions_by_id(
+        &self,
+        workspace_id: i32,
+        interaction_ids: &[i32],
+    ) -> RepoResult<Vec<Interaction>>;
+    /// The workspace's interaction notes and contact notes matching the web-search style
+    /// `query`, best match first. Encrypted contact notes are not searched.
+    async fn search_notes(
+        &self,
+        workspace_id: i32,
+        query: &str,
+        limit: i64,
+    ) -> RepoResult<Vec<NoteMatch>>;
+    /// The workspace's `limit` most recently logged interactions, newest first
+    async fn latest_interactions(
+        &self,
+        workspace_id: i32,
+        limit: i64,
+    ) -> RepoResult<Vec<Interaction>>;
+    /// The workspace's interactions dated `since` or later
+    async fn interactions_since(
+        &self,
+        workspace_id: i32,
+        since: OffsetDateTime,
+    ) -> RepoResult<Vec<Interaction>>;
+    /// How many of the workspace's interactions (by `interaction_date`) or contacts (by when
+    /// they were created) fall in each `granularity` bucket of the user's days `from` through
+    /// `to`, keyed by the bucket's first day, oldest first. Empty buckets are left out.
+    async fn count_by_period(
+        &self,
+        workspace_id: i32,
+        metric: StatsMetric,
+        granularity: Granularity,
+        from: Date,
+        to: Date,
+        dates: &LocalDates,
+    ) -> RepoResult<Vec<(Date, i64)>>;
+    /// Field changes recorded by `update_contact` and `set_contact_archived`, newest first by
+    /// (changed_at, change_id). Starts after the `after` position and returns at most `limit`
+    /// of them, all without a limit.
+    async fn contact_history(
+        &self,
+        contact_id: i32,
+        after: Option<Cursor>,
+        limit: Option<i64>,
+    ) -> RepoResult<Vec<ContactChange>>;
+    /// Interactions the contact took part in, newest first by (interaction_date,
+    /// interaction_id), optionally only those of one type. Starts after the `after` position and
+    /// returns at most `limit` of them, all without a limit.
+    async fn contact_timeline(
+        &self,
+        contact_id: i32,
+        interaction_type: Option<&str>,
+        tag_id: Option<i32>,
+        after: Option<Cursor>,
+        limit: Option<i64>,
+    ) -> RepoResult<Vec<Interaction>>;
+    /// The interaction type must be a built-in one or one of the workspace's custom types;
+    /// callers check this first. A `follow_up` occasion is created in the same transaction,
+    /// linked to the interaction; its id is returned alongside the interaction's.
+    async fn create_interaction(
+        &self,
+        workspace_id: i32,
+        interaction: &NewInteractionRequest,
+        follow_up: Option<&NewOccasionRequest>,
+    ) -> RepoResult<(i32, Option<i32>)>;
+    async fn update_interaction(
+        &self,
+        workspace_id: i32,
+        interaction_id: i32,
+        interaction: &NewInteractionRequest,
+    ) -> RepoResult<bool>;
+    async fn delete_interaction(&self, workspace_id: i32, interaction_id: i32) -> RepoResult<bool>;
+    async fn owns_interaction(&self, workspace_id: i32, interaction_id: i32) -> RepoResult<bool>;
+    /// The interaction's attachments, oldest first, without their files
+    async fn list_attachments(
+        &self,
+        workspace_id: i32,
+        interaction_id: i32,
+    ) -> RepoResult<Vec<Attachment>>;
+    /// Attach a file to the interaction, pending transcription when `transcribe` is set
+    async fn create_attachment(
+        &self,
+        workspace_id: i32,
+        interaction_id: i32,
+        attachment: &NewAttachment,
+    ) -> RepoResult<i32>;
+    async fn attachment_file(
+        &self,
+        workspace_id: i32,
+        attachment_id: i32,
+    ) -> RepoResult<Option<AttachmentFile>>;
+    /// The interaction the attachment was removed from; None if the attachment does not exist
+    /// or belongs to someone else. A transcript already in the interaction's notes stays there.
+    async fn delete_attachment(
+        &self,
+        workspace_id: i32,
+        attachment_id: i32,
+    ) -> RepoResult<Option<i32>>;
+    async fn owns_attachment(&self, workspace_id: i32, attachment_id: i32) -> RepoResult<bool>;
+    /// Up to `limit` attachments pending transcription, across all workspaces, oldest first
+    async fn pending_transcriptions(&self, limit: i64) -> RepoResult<Vec<PendingTranscription>>;
+    /// Append `transcript` to the notes of the attachment's interaction and mark the
+    /// transcription done, together. Returns false, changing nothing, if the attachment is gone
+    /// or no longer pending.
+    async fn save_transcript(
+        &self,
+        workspace_id: i32,
+        attachment_id: i32,
+        transcript: &str,
+    ) -> RepoResult<bool>;
+    /// Count a failed transcription attempt, and with `give_up` mark the transcription failed
+    async fn transcription_failed(
+        &self,
+        workspace_id: i32,
+        attachment_id: i32,
+        give_up: bool,
+    ) -> RepoResult<()>;
+
+    /// Up to `limit` unarchived contacts, across all workspaces, with an email and no avatar
+    /// whose next avatar lookup is due at `now`; those never looked up first
+    async fn pending_avatar_lookups(
+        &self,
+        now: OffsetDateTime,
+        limit: i64,
+    ) -> RepoResult<Vec<PendingAvatarLookup>>;
+    /// Record a lookup of the contact's avatar, not to be repeated before `next_lookup_at`, and
+    /// store `avatar_url` if one was found. Returns whether it was stored: not when the contact
+    /// is gone, has an avatar by now or no longer has `email`.
+    async fn record_avatar_lookup(
+        &self,
+        workspace_id: i32,
+        contact_id: i32,
+        email: &str,
+        avatar_url: Option<&str>,
+        next_lookup_at: OffsetDateTime,
+    ) -> RepoResult<bool>;
+
+    /// The workspace's inbox, oldest capture first
+    async fn list_captures(&self, workspace_id: i32) -> RepoResult<Vec<Capture>>;
+    async fn get_capture(&self, workspace_id: i32, capture_id: i32) -> RepoResult<Option<Capture>>;
+    async fn create_capture(&self, workspace_id: i32, text: &str) -> RepoResult<i32>;
+    /// Create the interaction and remove the capture in one transaction, returning the new
+    /// interaction's id; None, creating nothing, if the capture does not exist (or was
+    /// resolved meanwhile) or belongs to someone else. Callers check the interaction as for
+    /// `create_interaction`.
+    async fn resolve_capture(
+        &self,
+        workspace_id: i32,
+        capture_id: i32,
+        interaction: &NewInteractionRequest,
+    ) -> RepoResult<Option<i32>>;
+    /// Returns false if the capture does not exist or belongs to someone else
+    async fn delete_capture(&self, workspace_id: i32, capture_id: i32) -> RepoResult<bool>;
+    async fn owns_capture(&self, workspace_id: i32, capture_id: i32) -> RepoResult<bool>;
+
+    async fn list_interaction_types(
+        &self,
+        workspace_id: i32,
+    ) -> RepoResult<Vec<CustomInteractionType>>;
+    async fn create_interaction_type(&self, workspace_id: i32, name: &str) -> RepoResult<i32>;
+    /// Interactions of the deleted type are left untyped
+    async fn delete_interaction_type(&self, workspace_id: i32, type_id: i32) -> RepoResult<bool>;
+    async fn owns_interaction_type(&self, workspace_id: i32, type_id: i32) -> RepoResult<bool>;
+
+    async fn occasions_for_contacts(&self, contact_ids: &[i32]) -> RepoResult<Vec<Occasion>>;
+    async fn get_occasion(
+        &self,
+        workspace_id: i32,
+        occasion_id: i32,
+    ) -> RepoResult<Option<Occasion>>;
+    async fn create_occasion(
+        &self,
+        workspace_id: i32,
+        occasion: &NewOccasionRequest,
+    ) -> RepoResult<i32>;
+    async fn update_occasion(
+        &self,
+        workspace_id: i32,
+        occasion_id: i32,
+        occasion: &NewOccasionRequest,
+    ) -> RepoResult<bool>;
+    async fn delete_occasion(&self, workspace_id: i32, occasion_id: i32) -> RepoResult<bool>;
+    async fn owns_occasion(&self, workspace_id: i32, occasion_id: i32) -> RepoResult<bool>;
+
+    async fn social_profiles_for_contacts(
+        &self,
+        contact_ids: &[i32],
+    ) -> RepoResult<Vec<SocialProfile>>;
+    /// Without a preview
+    async fn get_social_profile(
+        &self,
+        workspace_id: i32,
+        social_profile_id: i32,
+    ) -> RepoResult<Option<SocialProfile>>;
+    /// The same URL twice on one contact is a unique violation
+    async fn create_social_profile(
+        &self,
+        workspace_id: i32,
+        profile: &NewSocialProfileRequest,
+    ) -> RepoResult<i32>;
+    /// Changes the platform and URL; the profile stays on its contact
+    async fn update_social_profile(
+        &self,
+        workspace_id: i32,
+        social_profile_id: i32,
+        profile: &NewSocialProfileRequest,
+    ) -> RepoResult<bool>;
+    async fn delete_social_profile(
+        &self,
+        workspace_id: i32,
+        social_profile_id: i32,
+    ) -> RepoResult<bool>;
+    async fn owns_social_profile(
+        &self,
+        workspace_id: i32,
+        social_profile_id: i32,
+    ) -> RepoResult<bool>;
+
+    async fn gift_ideas_for_contacts(&self, contact_ids: &[i32]) -> RepoResult<Vec<GiftIdea>>;
+    async fn get_gift_idea(
+        &self,
+        workspace_id: i32,
+        gift_idea_id: i32,
+    ) -> RepoResult<Option<GiftIdea>>;
+    async fn create_gift_idea(
+        &self,
+        workspace_id: i32,
+        gift_idea: &NewGiftIdeaRequest,
+    ) -> RepoResult<i32>;
+    /// Changes everything but the contact; the idea stays on its contact
+    async fn update_gift_idea(
+        &self,
+        workspace_id: i32,
+        gift_idea_id: i32,
+        gift_idea: &NewGiftIdeaRequest,
+    ) -> RepoResult<bool>;
+    async fn delete_gift_idea(&self, workspace_id: i32, gift_idea_id: i32) -> RepoResult<bool>;
+    async fn owns_gift_idea(&self, workspace_id: i32, gift_idea_id: i32) -> RepoResult<bool>;
+
+    /// The workspace's groups by name, each with its members in order
+    async fn list_groups(&self, workspace_id: i32) -> RepoResult<Vec<Group>>;
+    async fn get_group(&self, workspace_id: i32, group_id: i32) -> RepoResult<Option<Group>>;
+    async fn create_group(&self, workspace_id: i32, group: &NewGroupRequest) -> RepoResult<i32>;
+    /// Changes the name and description; the members stay as they are
+    async fn update_group(
+        &self,
+        workspace_id: i32,
+        group_id: i32,
+        group: &NewGroupRequest,
+    ) -> RepoResult<bool>;
+    async fn delete_group(&self, workspace_id: i32, group_id: i32) -> RepoResult<bool>;
+    async fn owns_group(&self, workspace_id: i32, group_id: i32) -> RepoResult<bool>;
+    /// Make `contact_ids`, which must not repeat, the group's members in that order, together.
+    /// False, changing nothing, if the group or any of the contacts isn't the workspace's.
+    async fn set_group_members(
+        &self,
+        workspace_id: i32,
+        group_id: i32,
+        contact_ids: &[i32],
+    ) -> RepoResult<bool>;
+    /// Add a contact after the group's last member; false if it already is one
+    async fn add_group_member(&self, group_id: i32, contact_id: i32) -> RepoResult<bool>;
+    /// False if the contact isn't a member
+    async fn remove_group_member(&self, group_id: i32, contact_id: i32) -> RepoResult<bool>;
+
+    /// Shares the user has made or received, oldest first
+    async fn list_shares(&self, user_id: i32) -> RepoResult<Vec<Share>>;
+    /// Share a tag, or each of the given contacts, with another user, returning the share ids.
+    /// Sharing the same thing again replaces the permission. Callers check that the owner owns
+    /// the tag and contacts first.
+    async fn create_shares(
+        &self,
+        owner_id: i32,
+        grantee_id: i32,
+        tag_id: Option<i32>,
+        contact_ids: &[i32],
+        permission: SharePermission,
+    ) -> RepoResult<Vec<i32>>;
+    /// Either side of a share can delete it. Returns false if the user is neither.
+    async fn delete_share(&self, user_id: i32, share_id: i32) -> RepoResult<bool>;
+    /// Whether the user made or received the share
+    async fn owns_share(&self, user_id: i32, share_id: i32) -> RepoResult<bool>;
+    /// How the user, working in `workspace_id`, may reach a contact: as its owner when it is in
+    /// that workspace, or through the strongest share of the contact or of a tag on it. None if
+    /// the user cannot see it, which includes their own contacts in other workspaces.
+    async fn contact_access(
+        &self,
+        user_id: i32,
+        workspace_id: i32,
+        contact_id: i32,
+    ) -> RepoResult<Option<ContactAccess>>;
+    /// Contacts shared with the user, archived included, by owner then contact id
+    async fn shared_contacts(&self, user_id: i32) -> RepoResult<Vec<ContactShare>>;
+
+    async fn list_filters(&self, workspace_id: i32) -> RepoResult<Vec<SavedFilter>>;
+    async fn get_filter(
+        &self,
+        workspace_id: i32,
+        filter_id: i32,
+    ) -> RepoResult<Option<SavedFilter>>;
+    async fn create_filter(
+        &self,
+        workspace_id: i32,
+        filter: &NewSavedFilterRequest,
+    ) -> RepoResult<i32>;
+    async fn update_filter(
+        &self,
+        workspace_id: i32,
+        filter_id: i32,
+        filter: &NewSavedFilterRequest,
+    ) -> RepoResult<bool>;
+    async fn delete_filter(&self, workspace_id: i32, filter_id: i32) -> RepoResult<bool>;
+    async fn owns_filter(&self, workspace_id: i32, filter_id: i32) -> RepoResult<bool>;
+
+    /// By name
+    async fn list_goals(&self, workspace_id: i32) -> RepoResult<Vec<Goal>>;
+    async fn get_goal(&self, workspace_id: i32, goal_id: i32) -> RepoResult<Option<Goal>>;
+    async fn create_goal(&self, workspace_id: i32, goal: &NewGoalRequest) -> RepoResult<i32>;
+    async fn update_goal(
+        &self,
+        workspace_id: i32,
+        goal_id: i32,
+        goal: &NewGoalRequest,
+    ) -> RepoResult<bool>;
+    async fn delete_goal(&self, workspace_id: i32, goal_id: i32) -> RepoResult<bool>;
+    async fn owns_goal(&self, workspace_id: i32, goal_id: i32) -> RepoResult<bool>;
+
+    /// (week, contact_id) for every reconnect pick in weeks starting on or after `since`
+    async fn reconnect_picks(&self, workspace_id: i32, since: Date)
+    -> RepoResult<Vec<(Date, i32)>>;
+    /// Record the reconnect picks for `week`; does nothing if that week already has picks
+    async fn save_reconnect_picks(
+        &self,
+        workspace_id: i32,
+        week: Date,
+        contact_ids: &[i32],
+    ) -> RepoResult<()>;
+
+    /// Leave a contact out of the user's suggestions until `until`, or until the next interaction
+    /// with it when None, replacing any earlier snooze of it
+    async fn snooze_suggestion(
+        &self,
+        user_id: i32,
+        contact_id: i32,
+        until: Option<Date>,
+    ) -> RepoResult<()>;
+    /// Returns false if the contact was not snoozed
+    async fn unsnooze_suggestion(&self, user_id: i32, contact_id: i32) -> RepoResult<bool>;
+    /// Contacts the user dismissed or snoozed until after `today`, by id. Logging an interaction
+    /// with a contact ends its dismissals.
+    async fn snoozed_contacts(&self, user_id: i32, today: Date) -> RepoResult<Vec<i32>>;
+
+    /// The user's preferences, or the defaults if none have been saved.
+    /// Greeting tags that have since been deleted are left out.
+    async fn get_preferences(&self, user_id: i32) -> RepoResult<Preferences>;
+    /// Replace the user's preferences
+    async fn save_preferences(&self, user_id: i32, preferences: &Preferences) -> RepoResult<()>;
+
+    async fn export_schedule(&self, workspace_id: i32) -> RepoResult<Option<ExportSchedule>>;
+    /// Create or replace the workspace's schedule; the last push is kept
+    async fn save_export_schedule(&self, schedule: &ExportSchedule) -> RepoResult<()>;
+    /// Returns false if the workspace had no schedule
+    async fn delete_export_schedule(&self, workspace_id: i32) -> RepoResult<bool>;
+    /// Every schedule of an active user's workspace that has never pushed or whose interval has
+    /// passed since the last push, as of `now`
+    async fn due_export_schedules(&self, now: OffsetDateTime) -> RepoResult<Vec<ExportSchedule>>;
+    async fn record_export_push(
+        &self,
+        workspace_id: i32,
+        pushed_at: OffsetDateTime,
+        status: &str,
+    ) -> RepoResult<()>;
+
+    async fn inbound_email_token(&self, workspace_id: i32) -> RepoResult<Option<String>>;
+    /// Give the workspace a new inbound email token, replacing any it had
+    async fn save_inbound_email_token(&self, workspace_id: i32, token: &str) -> RepoResult<()>;
+    /// Returns false if the workspace had no token
+    async fn delete_inbound_email_token(&self, workspace_id: i32) -> RepoResult<bool>;
+    /// The (user_id, workspace_id) an inbound email token belongs to; None for unknown tokens
+    /// and deactivated users
+    async fn inbound_email_workspace(&self, token: &str) -> RepoResult<Option<(i32, i32)>>;
+    /// Ids of the workspace's contacts whose email is one of `emails` (given lowercase),
+    /// compared case-insensitively, in the order of `emails`
+    async fn contacts_by_email(&self, workspace_id: i32, emails: &[String])
+    -> RepoResult<Vec<i32>>;
+
+    /// The workspace's REST hooks, by hook_id
+    async fn list_rest_hooks(&self, workspace_id: i32) -> RepoResult<Vec<RestHook>>;
+    async fn create_rest_hook(
+        &self,
+        workspace_id: i32,
+        hook: &NewRestHookRequest,
+    ) -> RepoResult<i32>;
+    /// Returns false if the hook does not exist or belongs to someone else
+    async fn delete_rest_hook(&self, workspace_id: i32, hook_id: i32) -> RepoResult<bool>;
+    async fn owns_rest_hook(&self, workspace_id: i32, hook_id: i32) -> RepoResult<bool>;
+    /// Hooks for `event` in any of the user's workspaces, or of every active user when `user_id`
+    /// is None, by hook_id
+    async fn rest_hooks_for_event(
+        &self,
+        event: HookEvent,
+        user_id: Option<i32>,
+    ) -> RepoResult<Vec<RestHook>>;
+    async fn record_rest_hook_delivery(&self, hook_id: i32, delivered_on: Date) -> RepoResult<()>;
+    /// Queue upcoming occasion items for the hook in the outbox and record its delivery for
+    /// `delivered_on`, together
+    async fn queue_upcoming_occasions(
+        &self,
+        workspace_id: i32,
+        hook_id: i32,
+        items: &serde_json::Value,
+        delivered_on: Date,
+    ) -> RepoResult<()>;
+    /// The hook's outbox deliveries, newest first
+    async fn hook_deliveries(
+        &self,
+        workspace_id: i32,
+        hook_id: i32,
+        limit: i64,
+    ) -> RepoResult<Vec<HookDelivery>>;
+
+    /// Take up to `limit` pending outbox rows due by `now`, oldest first, putting them off until
+    /// `until` in case the sender stops before recording its attempt. Rows another sender has
+    /// claimed are skipped.
+    async fn claim_deliveries(
+        &self,
+        now: OffsetDateTime,
+        until: OffsetDateTime,
+        limit: i64,
+    ) -> RepoResult<Vec<OutboxMessage>>;
+    async fn record_delivery_attempt(
+        &self,
+        delivery_id: i32,
+        attempt: &DeliveryAttempt,
+    ) -> RepoResult<()>;
+    /// Delete outbox rows delivered or failed before `before`, returning how many
+    async fn purge_deliveries(&self, before: OffsetDateTime) -> RepoResult<u64>;
+
+    /// The contact's links that have not expired by `now`, by link_id, without their tokens
+    async fn contact_links(
+        &self,
+        workspace_id: i32,
+        contact_id: i32,
+        now: OffsetDateTime,
+    ) -> RepoResult<Vec<ContactLink>>;
+    async fn create_contact_link(
+        &self,
+        workspace_id: i32,
+        contact_id: i32,
+        token_hash: &str,
+        expires_at: OffsetDateTime,
+    ) -> RepoResult<ContactLink>;
+    async fn delete_contact_link(&self, workspace_id: i32, link_id: i32) -> RepoResult<bool>;
+    async fn owns_contact_link(&self, workspace_id: i32, link_id: i32) -> RepoResult<bool>;
+    /// The (workspace_id, contact_id) a link token opens, by the token's hash; None for unknown
+    /// or expired tokens and deactivated users
+    async fn contact_link_target(
+        &self,
+        token_hash: &str,
+        now: OffsetDateTime,
+    ) -> RepoResult<Option<(i32, i32)>>;
+
+    /// Claim an Idempotency-Key for a request, returning None when it was free. Otherwise the
+    /// request holding it is returned, without a response while it is still running. Keys
+    /// claimed more than `ttl_hours` ago are free again.
+    async fn claim_idempotency_key(
+        &self,
+        user_id: i32,
+        key: &str,
+        fingerprint: &str,
+        ttl_hours: i32,
+    ) -> RepoResult<Option<IdempotentRequest>>;
+    /// Keep the response to the request holding the key
+    async fn complete_idempotency_key(
+        &self,
+        user_id: i32,
+        key: &str,
+        response: &StoredResponse,
+    ) -> RepoResult<()>;
+    /// Free a key whose request failed, so a retry runs it again
+    async fn release_idempotency_key(&self, user_id: i32, key: &str) -> RepoResult<()>;
+}
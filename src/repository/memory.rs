@@ -0,0 +1,3901 @@
+//! In-memory `Repository` for tests.
+//!
+//! Mirrors the Postgres schema closely enough for handler tests: ids are assigned per table
+//! like SERIAL columns, the unique constraints on users, workspace names, contact emails, tag
+//! names and social profile URLs are enforced, and deletes cascade the same way the foreign keys
+//! do. Nothing is persisted.
+
+use super::{DEFAULT_WORKSPACE_NAME, RepoResult, Repository};
+use crate::AuthUser;
+use crate::cursor::Cursor;
+use crate::geocoding::BoundingBox;
+use crate::history::{self, FieldChange};
+use crate::mail::Email;
+use crate::models::{
+    AccountSummary, Attachment, AttachmentFile, Capture, Contact, ContactAccess, ContactChange,
+    ContactChecksum, ContactDetails, ContactLink, ContactMatch, ContactResponse, ContactShare,
+    ContactSummary, Coordinates, CustomInteractionType, DeliveryAttempt, DeliveryStatus,
+    ExportSchedule, GiftIdea, Goal, Granularity, Group, HookDelivery, HookEvent, IdempotentRequest,
+    InstanceStats, Interaction, NewAttachment, NewContactRequest, NewGiftIdeaRequest,
+    NewGoalRequest, NewGroupRequest, NewInteractionRequest, NewOccasionRequest,
+    NewPushSubscriptionRequest, NewRestHookRequest, NewSavedFilterRequest, NewSocialProfileRequest,
+    NewTagRequest, NoteMatch, NoteSource, NotificationSettings, Occasion, OutboxMessage,
+    OutboxPayload, PendingAvatarLookup, PendingTranscription, Preferences, PushRecipient,
+    PushSubscription, RestHook, ReviewRecipient, SavedFilter, Share, SharePermission,
+    SocialProfile, StatsMetric, StoredResponse, Tag, TagUsage, TranscriptionStatus, Unassigned,
+    Usage, UserProfile, Workspace,
+};
+use crate::phone;
+use crate::push::PushMessage;
+use crate::reminders;
+use crate::stats::bucket_start;
+use crate::timezone::LocalDates;
+use async_trait::async_trait;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Mutex, MutexGuard};
+use time::{Date, OffsetDateTime, PrimitiveDateTime};
+
+/// Rows keyed by id, each paired with the owning workspace_id, or user_id for per-user tables
+struct Table<T> {
+    last_id: i32,
+    rows: BTreeMap<i32, (i32, T)>,
+}
+
+impl<T> Default for Table<T> {
+    fn default() -> Self {
+        Table {
+            last_id: 0,
+            rows: BTreeMap::new(),
+        }
+    }
+}
+
+impl<T> Table<T> {
+    fn next_id(&mut self) -> i32 {
+        self.last_id += 1;
+        self.last_id
+    }
+
+    fn owned(&self, owner_id: i32, id: i32) -> Option<&T> {
+        self.rows
+            .get(&id)
+            .filter(|(owner, _)| *owner == owner_id)
+            .map(|(_, row)| row)
+    }
+
+    fn owned_mut(&mut self, owner_id: i32, id: i32) -> Option<&mut T> {
+        self.rows
+            .get_mut(&id)
+            .filter(|(owner, _)| *owner == owner_id)
+            .map(|(_, row)| row)
+    }
+
+    fn remove_owned(&mut self, owner_id: i32, id: i32) -> bool {
+        if self.owned(owner_id, id).is_none() {
+            return false;
+        }
+        self.rows.remove(&id);
+        true
+    }
+}
+
+/// A row of the shares table; the owner is kept by `Table`
+struct ShareRow {
+    grantee_id: i32,
+    tag_id: Option<i32>,
+    contact_id: Option<i32>,
+    permission: SharePermission,
+}
+
+/// A row of interaction_attachments; the workspace is kept by `Table`
+struct AttachmentRow {
+    attachment: Attachment,
+    data: Vec<u8>,
+    transcription_attempts: i32,
+}
+
+/// A row of contact_links; the workspace is kept by `Table`
+struct ContactLinkRow {
+    link: ContactLink,
+    token_hash: String,
+}
+
+/// A row of the outbox; the workspace is kept by `Table`
+struct OutboxRow {
+    hook_id: Option<i32>,
+    push_subscription_id: Option<i32>,
+    payload: OutboxPayload,
+    status: DeliveryStatus,
+    attempts: i32,
+    next_attempt_at: OffsetDateTime,
+    last_response_status: Option<i32>,
+    last_error: Option<String>,
+    created_at: OffsetDateTime,
+    finished_at: Option<OffsetDateTime>,
+}
+
+#[derive(Default)]
+struct Store {
+    users: Table<AuthUser>,
+    workspaces: Table<Workspace>,
+    contacts: Table<Contact>,
+    /// Creation time by contact_id
+    contacts_created_at: BTreeMap<i32, OffsetDateTime>,
+    /// Owned by their contact
+    contact_changes: Table<ContactChange>,
+    tags: Table<Tag>,
+    /// (contact_id, tag_id)
+    contact_tags: BTreeSet<(i32, i32)>,
+    interactions: Table<Interaction>,
+    attachments: Table<AttachmentRow>,
+    captures: Table<Capture>,
+    interaction_types: Table<CustomInteractionType>,
+    occasions: Table<Occasion>,
+    social_profiles: Table<SocialProfile>,
+    gift_ideas: Table<GiftIdea>,
+    groups: Table<Group>,
+    rest_hooks: Table<RestHook>,
+    outbox: Table<OutboxRow>,
+    contact_links: Table<ContactLinkRow>,
+    filters: Table<SavedFilter>,
+    goals: Table<Goal>,
+    shares: Table<ShareRow>,
+    /// (workspace_id, week, contact_id)
+    reconnect_picks: BTreeSet<(i32, Date, i32)>,
+    /// Keyed by contact_id: when avatar enrichment may next look the contact up
+    avatar_lookups: BTreeMap<i32, OffsetDateTime>,
+    /// Keyed by (user_id, contact_id): the snooze end, None when dismissed
+    suggestion_snoozes: BTreeMap<(i32, i32), Option<Date>>,
+    /// Keyed by user_id
+    preferences: BTreeMap<i32, Preferences>,
+    /// Keyed by workspace_id
+    export_schedules: BTreeMap<i32, ExportSchedule>,
+    /// Inbound email token by workspace_id
+    inbound_email_tokens: BTreeMap<i32, String>,
+    /// Keyed by user_id: notification settings and default reminder cadence
+    settings: BTreeMap<i32, (NotificationSettings, i32)>,
+    /// Keyed by user_id: Monday of the week the last weekly review was sent in
+    weekly_reviews_sent: BTreeMap<i32, Date>,
+    /// Owned by their user
+    push_subscriptions: Table<PushSubscription>,
+    /// Keyed by user_id: the user's day reminders were last pushed for
+    push_reminders_sent: BTreeMap<i32, Date>,
+    /// Keyed by job name: the holder and when its lease runs out
+    job_leases: BTreeMap<String, (String, OffsetDateTime)>,
+    /// Keyed by (user_id, key), with the time the key was claimed
+    idempotency_keys: BTreeMap<(i32, String), (OffsetDateTime, IdempotentRequest)>,
+}
+
+/// Current UTC time, as stored in TIMESTAMP columns
+fn now() -> PrimitiveDateTime {
+    let now = OffsetDateTime::now_utc();
+    PrimitiveDateTime::new(now.date(), now.time())
+}
+
+impl Store {
+    /// Add edits of a contact to its history
+    fn record_changes(&mut self, contact_id: i32, changes: Vec<FieldChange>) {
+        let changed_at = OffsetDateTime::now_utc();
+        for change in changes {
+            let change_id = self.contact_changes.next_id();
+            self.contact_changes.rows.insert(
+                change_id,
+                (
+                    contact_id,
+                    ContactChange {
+                        change_id,
+                        field: change.field.to_string(),
+                        before: change.before,
+                        after: change.after,
+                        changed_at,
+                    },
+                ),
+            );
+        }
+    }
+
+    /// Bump a contact's updated_at, like the touch_contact trigger does for child rows
+    fn touch_contact(&mut self, contact_id: i32) {
+        if let Some((_, contact)) = self.contacts.rows.get_mut(&contact_id) {
+            contact.updated_at = Some(now());
+        }
+    }
+
+    /// The workspace's contacts among `contact_ids`, sorted and without `contact_id` itself, as
+    /// interaction_participants would hold them
+    fn participants(&self, workspace_id: i32, contact_id: i32, contact_ids: &[i32]) -> Vec<i32> {
+        let participants: BTreeSet<i32> = contact_ids
+            .iter()
+            .copied()
+            .filter(|id| *id != contact_id && self.contacts.owned(workspace_id, *id).is_some())
+            .collect();
+        participants.into_iter().collect()
+    }
+
+    /// Add a pending row to the outbox
+    fn queue(&mut self, workspace_id: i32, hook_id: Option<i32>, payload: OutboxPayload) {
+        self.queue_for(workspace_id, hook_id, None, payload);
+    }
+
+    /// Add a pending row to the outbox for a hook or a push subscription
+    fn queue_for(
+        &mut self,
+        workspace_id: i32,
+        hook_id: Option<i32>,
+        push_subscription_id: Option<i32>,
+        payload: OutboxPayload,
+    ) {
+        let delivery_id = self.outbox.next_id();
+        let now = OffsetDateTime::now_utc();
+        self.outbox.rows.insert(
+            delivery_id,
+            (
+                workspace_id,
+                OutboxRow {
+                    hook_id,
+                    push_subscription_id,
+                    payload,
+                    status: DeliveryStatus::Pending,
+                    attempts: 0,
+                    next_attempt_at: now,
+                    last_response_status: None,
+                    last_error: None,
+                    created_at: now,
+                    finished_at: None,
+                },
+            ),
+        );
+    }
+
+    /// Queue new entities for each of the workspace's hooks for `event`
+    fn queue_hook_deliveries(&mut self, workspace_id: i32, event: HookEvent, ids: &[i32]) {
+        if ids.is_empty() {
+            return;
+        }
+        let hook_ids: Vec<i32> = self
+            .rest_hooks
+            .rows
+            .iter()
+            .filter(|(_, (owner, h))| *owner == workspace_id && h.event == event)
+            .map(|(id, _)| *id)
+            .collect();
+        for hook_id in hook_ids {
+            self.queue(
+                workspace_id,
+                Some(hook_id),
+                OutboxPayload::Entities(ids.to_vec()),
+            );
+        }
+    }
+
+    /// Insert an interaction with its participants, ending dismissals of the contacts involved,
+    /// and queue it for the workspace's new interaction hooks
+    fn insert_interaction(
+        &mut self,
+        workspace_id: i32,
+        interaction: &NewInteractionRequest,
+    ) -> i32 {
+        let contact_ids = self.participants(
+            workspace_id,
+            interaction.contact_id,
+            &interaction.contact_ids,
+        );
+        let interaction_id = self.interactions.next_id();
+        self.interactions.rows.insert(
+            interaction_id,
+            (
+                workspace_id,
+                Interaction {
+                    interaction_id,
+                    contact_id: Some(interaction.contact_id),
+                    interaction_date: interaction.interaction_date,
+                    notes: interaction.notes.clone(),
+                    follow_up_priority: interaction.follow_up_priority,
+                    interaction_type: interaction.interaction_type.clone(),
+                    contact_ids: contact_ids.clone(),
+                    tag_ids: Vec::new(),
+                },
+            ),
+        );
+        self.touch_contact(interaction.contact_id);
+        // Getting in touch ends dismissals of the contacts involved
+        self.suggestion_snoozes.retain(|(_, c), until| {
+            until.is_some() || (*c != interaction.contact_id && !contact_ids.contains(c))
+        });
+        for contact_id in contact_ids {
+            self.touch_contact(contact_id);
+        }
+        self.queue_hook_deliveries(workspace_id, HookEvent::NewInteraction, &[interaction_id]);
+        interaction_id
+    }
+
+    fn insert_occasion(
+        &mut self,
+        workspace_id: i32,
+        occasion: &NewOccasionRequest,
+        interaction_id: Option<i32>,
+    ) -> i32 {
+        let occasion_id = self.occasions.next_id();
+        self.occasions.rows.insert(
+            occasion_id,
+            (
+                workspace_id,
+                Occasion {
+                    occasion_id,
+                    contact_id: Some(occasion.contact_id),
+                    name: occasion.name.clone(),
+                    date: occasion.date,
+                    recurring: Some(occasion.recurring),
+                    recurring_interval: occasion.recurring_interval,
+                    details: occasion.details.clone(),
+                    occasion_type: occasion.occasion_type,
+                    birth_year: occasion.birth_year,
+                    remind_days_before: occasion.remind_days_before.clone(),
+                    upcoming_age: None,
+                    interaction_id,
+                },
+            ),
+        );
+        self.touch_contact(occasion.contact_id);
+        occasion_id
+    }
+
+    /// Keep the contact's interactions and occasions once it is gone, as `delete_contact` does
+    /// with `keep_history`
+    fn unassign_history(&mut self, contact_id: i32) {
+        for (_, interaction) in self.interactions.rows.values_mut() {
+            if interaction.contact_id == Some(contact_id) {
+                interaction.contact_id = None;
+            }
+        }
+        for (_, occasion) in self.occasions.rows.values_mut() {
+            if occasion.contact_id == Some(contact_id) {
+                occasion.contact_id = None;
+            }
+        }
+    }
+
+    fn remove_contact_children(&mut self, contact_id: i32) {
+        // Contacts they introduced are kept, like `ON DELETE SET NULL`
+        for (_, contact) in self.contacts.rows.values_mut() {
+            if contact.introduced_by_contact_id == Some(contact_id) {
+                contact.introduced_by_contact_id = None;
+                contact.updated_at = Some(now());
+            }
+        }
+        self.contact_changes
+            .rows
+            .retain(|_, (c, _)| *c != contact_id);
+        self.contact_tags.retain(|(c, _)| *c != contact_id);
+        self.interactions
+            .rows
+            .retain(|_, (_, i)| i.contact_id != Some(contact_id));
+        for (_, interaction) in self.interactions.rows.values_mut() {
+            interaction.contact_ids.retain(|c| *c != contact_id);
+        }
+        self.remove_orphan_attachments();
+        self.occasions
+            .rows
+            .retain(|_, (_, o)| o.contact_id != Some(contact_id));
+        self.social_profiles
+            .rows
+            .retain(|_, (_, p)| p.contact_id != contact_id);
+        self.gift_ideas
+            .rows
+            .retain(|_, (_, g)| g.contact_id != contact_id);
+        for (_, group) in self.groups.rows.values_mut() {
+            group.contact_ids.retain(|c| *c != contact_id);
+        }
+        self.contact_links
+            .rows
+            .retain(|_, (_, l)| l.link.contact_id != contact_id);
+        self.goals
+            .rows
+            .retain(|_, (_, g)| g.contact_id != Some(contact_id));
+        self.reconnect_picks.retain(|(_, _, c)| *c != contact_id);
+        self.suggestion_snoozes.retain(|(_, c), _| *c != contact_id);
+        self.avatar_lookups.remove(&contact_id);
+        self.shares
+            .rows
+            .retain(|_, (_, s)| s.contact_id != Some(contact_id));
+    }
+
+    /// Delete attachments whose interaction is gone, like the cascade from interactions
+    fn remove_orphan_attachments(&mut self) {
+        let interactions = &self.interactions.rows;
+        self.attachments
+            .rows
+            .retain(|_, (_, a)| interactions.contains_key(&a.attachment.interaction_id));
+    }
+
+    /// Clear the occasion of gift ideas whose occasion is gone, like `ON DELETE SET NULL`
+    fn unlink_gift_ideas(&mut self) {
+        for (_, gift_idea) in self.gift_ideas.rows.values_mut() {
+            if gift_idea
+                .occasion_id
+                .is_some_and(|id| !self.occasions.rows.contains_key(&id))
+            {
+                gift_idea.occasion_id = None;
+            }
+        }
+    }
+
+    /// The user owning a workspace
+    fn workspace_owner(&self, workspace_id: i32) -> Option<i32> {
+        self.workspaces
+            .rows
+            .get(&workspace_id)
+            .map(|(user_id, _)| *user_id)
+    }
+
+    /// Delete a user and everything they own, like the cascade from users
+    fn remove_user(&mut self, user_id: i32) {
+        self.users.rows.remove(&user_id);
+        let workspace_ids: Vec<i32> = self
+            .workspaces
+            .rows
+            .iter()
+            .filter(|(_, (owner, _))| *owner == user_id)
+            .map(|(id, _)| *id)
+            .collect();
+        for workspace_id in workspace_ids {
+            self.workspaces.rows.remove(&workspace_id);
+            self.remove_workspace_rows(workspace_id);
+        }
+        self.shares
+            .rows
+            .retain(|_, (owner, s)| *owner != user_id && s.grantee_id != user_id);
+        self.preferences.remove(&user_id);
+        self.settings.remove(&user_id);
+        self.weekly_reviews_sent.remove(&user_id);
+        self.push_subscriptions
+            .rows
+            .retain(|_, (owner, _)| *owner != user_id);
+        self.push_reminders_sent.remove(&user_id);
+        self.suggestion_snoozes.retain(|(u, _), _| *u != user_id);
+    }
+
+    /// Delete everything in a workspace, like the cascade from workspaces
+    fn remove_workspace_rows(&mut self, workspace_id: i32) {
+        let contact_ids: Vec<i32> = self
+            .contacts
+            .rows
+            .iter()
+            .filter(|(_, (owner, _))| *owner == workspace_id)
+            .map(|(id, _)| *id)
+            .collect();
+        for contact_id in contact_ids {
+            self.contacts.rows.remove(&contact_id);
+            self.remove_contact_children(contact_id);
+        }
+        let tag_ids: Vec<i32> = self
+            .tags
+            .rows
+            .iter()
+            .filter(|(_, (owner, _))| *owner == workspace_id)
+            .map(|(id, _)| *id)
+            .collect();
+        for tag_id in tag_ids {
+            self.tags.rows.remove(&tag_id);
+            self.contact_tags.retain(|(_, t)| *t != tag_id);
+            self.shares
+                .rows
+                .retain(|_, (_, s)| s.tag_id != Some(tag_id));
+        }
+        self.interactions
+            .rows
+            .retain(|_, (owner, _)| *owner != workspace_id);
+        self.attachments
+            .rows
+            .retain(|_, (owner, _)| *owner != workspace_id);
+        self.captures
+            .rows
+            .retain(|_, (owner, _)| *owner != workspace_id);
+        self.interaction_types
+            .rows
+            .retain(|_, (owner, _)| *owner != workspace_id);
+        self.occasions
+            .rows
+            .retain(|_, (owner, _)| *owner != workspace_id);
+        self.social_profiles
+            .rows
+            .retain(|_, (owner, _)| *owner != workspace_id);
+        self.gift_ideas
+            .rows
+            .retain(|_, (owner, _)| *owner != workspace_id);
+        self.groups
+            .rows
+            .retain(|_, (owner, _)| *owner != workspace_id);
+        self.filters
+            .rows
+            .retain(|_, (owner, _)| *owner != workspace_id);
+        self.goals
+            .rows
+            .retain(|_, (owner, _)| *owner != workspace_id);
+        self.reconnect_picks
+            .retain(|(owner, _, _)| *owner != workspace_id);
+        self.rest_hooks
+            .rows
+            .retain(|_, (owner, _)| *owner != workspace_id);
+        self.outbox
+            .rows
+            .retain(|_, (owner, _)| *owner != workspace_id);
+        self.contact_links
+            .rows
+            .retain(|_, (owner, _)| *owner != workspace_id);
+        self.export_schedules.remove(&workspace_id);
+        self.inbound_email_tokens.remove(&workspace_id);
+    }
+
+    fn email(&self, user_id: i32) -> String {
+        self.users
+            .rows
+            .get(&user_id)
+            .and_then(|(_, u)| u.email.clone())
+            .unwrap_or_default()
+    }
+
+    /// The strongest permission the grantee's shares give on a contact of `owner_id`
+    fn share_permission(
+        &self,
+        grantee_id: i32,
+        owner_id: i32,
+        contact_id: i32,
+    ) -> Option<SharePermission> {
+        self.shares
+            .rows
+            .values()
+            .filter(|(owner, s)| *owner == owner_id && s.grantee_id == grantee_id)
+            .filter(|(_, s)| match (s.contact_id, s.tag_id) {
+                (Some(id), _) => id == contact_id,
+                (None, Some(tag_id)) => self.contact_tags.contains(&(contact_id, tag_id)),
+                (None, None) => false,
+            })
+            .map(|(_, s)| s.permission)
+            .max()
+    }
+}
+
+/// Sort key matching `ORDER BY last_name, first_name` (Postgres puts NULLs last)
+fn name_order(c: &Contact) -> (bool, &Option<String>, bool, &Option<String>) {
+    (
+        c.last_name.is_none(),
+        &c.last_name,
+        c.first_name.is_none(),
+        &c.first_name,
+    )
+}
+
+/// The lowercase words of a search query
+pub(super) fn search_words(query: &str) -> Vec<String> {
+    query
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Like `ts_headline` without stemming or fragments: when every search word starts a word of
+/// `notes`, how many of its words matched and the whole notes, HTML-escaped, with those words
+/// in `<mark>` tags
+pub(super) fn highlight(notes: &str, words: &[String]) -> Option<(usize, String)> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (i, c) in notes
+        .char_indices()
+        .chain(std::iter::once((notes.len(), ' ')))
+    {
+        match (c.is_alphanumeric(), start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                tokens.push(s..i);
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    let matches = |range: &std::ops::Range<usize>, word: &String| {
+        notes[range.clone()]
+            .to_lowercase()
+            .starts_with(word.as_str())
+    };
+    if !words.iter().all(|w| tokens.iter().any(|r| matches(r, w))) {
+        return None;
+    }
+    let mut snippet = String::new();
+    let mut at = 0;
+    let mut count = 0;
+    for range in tokens
+        .iter()
+        .filter(|r| words.iter().any(|w| matches(r, w)))
+    {
+        snippet.push_str(&escape_html(&notes[at..range.start]));
+        snippet.push_str("<mark>");
+        snippet.push_str(&escape_html(&notes[range.clone()]));
+        snippet.push_str("</mark>");
+        at = range.end;
+        count += 1;
+    }
+    snippet.push_str(&escape_html(&notes[at..]));
+    Some((count, snippet))
+}
+
+/// Violations are reported as protocol errors carrying the Postgres message text
+fn unique_violation(constraint: &str) -> sqlx::Error {
+    sqlx::Error::Protocol(format!(
+        "duplicate key value violates unique constraint \"{}\"",
+        constraint
+    ))
+}
+
+#[derive(Default)]
+pub struct InMemoryRepository {
+    store: Mutex<Store>,
+}
+
+impl InMemoryRepository {
+    pub fn new() -> Self {
+        InMemoryRepository::default()
+    }
+
+    fn store(&self) -> MutexGuard<'_, Store> {
+        self.store.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Emails are unique within a workspace
+    fn check_contact_email(
+        store: &Store,
+        workspace_id: i32,
+        email: Option<&str>,
+        except_id: Option<i32>,
+    ) -> RepoResult<()> {
+        let taken = email.is_some_and(|email| {
+            store.contacts.rows.iter().any(|(id, (w, c))| {
+                *w == workspace_id && Some(*id) != except_id && c.email.as_deref() == Some(email)
+            })
+        });
+        if taken {
+            return Err(unique_violation("contacts_workspace_id_email_key"));
+        }
+        Ok(())
+    }
+
+    /// Check and insert one contact into an already locked store
+    fn insert_contact(
+        store: &mut Store,
+        workspace_id: i32,
+        contact: &NewContactRequest,
+    ) -> RepoResult<i32> {
+        Self::check_contact_email(store, workspace_id, contact.email.as_deref(), None)?;
+        let contact_id = store.contacts.next_id();
+        store.contacts.rows.insert(
+            contact_id,
+            (
+                workspace_id,
+                Contact {
+                    contact_id,
+                    first_name: contact.first_name.clone(),
+                    last_name: contact.last_name.clone(),
+                    email: contact.email.clone(),
+                    phone: contact.phone.clone(),
+                    phone_e164: phone::e164(contact.phone.as_deref()),
+                    short_note: contact.short_note.clone(),
+                    notes: contact.notes.clone(),
+                    avatar_url: contact.avatar_url.clone(),
+                    archived: false,
+                    desired_frequency_days: contact.desired_frequency_days,
+                    met_at: contact.met_at.clone(),
+                    introduced_by_contact_id: contact.introduced_by_contact_id,
+                    location: contact.location.clone(),
+                    latitude: None,
+                    longitude: None,
+                    updated_at: Some(now()),
+                },
+            ),
+        );
+        store
+            .contacts_created_at
+            .insert(contact_id, OffsetDateTime::now_utc());
+        Ok(contact_id)
+    }
+
+    /// Tag names are unique within a workspace
+    fn check_tag_name(
+        store: &Store,
+        workspace_id: i32,
+        name: &str,
+        except_id: Option<i32>,
+    ) -> RepoResult<()> {
+        let taken = store
+            .tags
+            .rows
+            .iter()
+            .any(|(id, (w, t))| *w == workspace_id && Some(*id) != except_id && t.name == name);
+        if taken {
+            return Err(unique_violation("tags_workspace_id_name_key"));
+        }
+        Ok(())
+    }
+
+    fn check_social_profile_url(
+        store: &Store,
+        contact_id: i32,
+        url: &str,
+        except_id: Option<i32>,
+    ) -> RepoResult<()> {
+        let taken = store.social_profiles.rows.iter().any(|(id, (_, p))| {
+            Some(*id) != except_id && p.contact_id == contact_id && p.url == url
+        });
+        if taken {
+            return Err(unique_violation("social_profiles_contact_id_url_key"));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Repository for InMemoryRepository {
+    async fn find_user(&self, auth0_id: &str) -> RepoResult<Option<AuthUser>> {
+        Ok(self
+            .store()
+            .users
+            .rows
+            .values()
+            .find(|(_, u)| u.auth0_id == auth0_id)
+            .map(|(_, u)| u.clone()))
+    }
+
+    async fn get_or_create_user(
+        &self,
+        auth0_id: &str,
+        email: &str,
+        name: &str,
+    ) -> RepoResult<AuthUser> {
+        let mut store = self.store();
+        if let Some((_, user)) = store
+            .users
+            .rows
+            .values()
+            .find(|(_, u)| u.auth0_id == auth0_id)
+        {
+            return Ok(user.clone());
+        }
+        if store
+            .users
+            .rows
+            .values()
+            .any(|(_, u)| u.email.as_deref() == Some(email))
+        {
+            return Err(unique_violation("users_email_key"));
+        }
+
+        let user_id = store.users.next_id();
+        let workspace_id = store.workspaces.next_id();
+        store.workspaces.rows.insert(
+            workspace_id,
+            (
+                user_id,
+                Workspace {
+                    workspace_id,
+                    name: DEFAULT_WORKSPACE_NAME.to_string(),
+                    is_default: true,
+                },
+            ),
+        );
+        let user = AuthUser {
+            user_id,
+            workspace_id,
+            auth0_id: auth0_id.to_string(),
+            email: Some(email.to_string()),
+            name: Some(name.to_string()),
+            is_admin: false,
+            deactivated: false,
+            delete_after: None,
+            scopes: None,
+            authenticated_at: None,
+        };
+        store.users.rows.insert(user_id, (user_id, user.clone()));
+        Ok(user)
+    }
+
+    async fn delete_user(&self, user_id: i32) -> RepoResult<()> {
+        self.store().remove_user(user_id);
+        Ok(())
+    }
+
+    async fn schedule_user_deletion(
+        &self,
+        user_id: i32,
+        delete_after: OffsetDateTime,
+    ) -> RepoResult<()> {
+        if let Some((_, user)) = self.store().users.rows.get_mut(&user_id) {
+            user.deactivated = true;
+            user.delete_after = Some(delete_after);
+        }
+        Ok(())
+    }
+
+    async fn restore_user(&self, user_id: i32) -> RepoResult<bool> {
+        Ok(match self.store().users.rows.get_mut(&user_id) {
+            Some((_, user)) if user.delete_after.is_some() => {
+                user.deactivated = false;
+                user.delete_after = None;
+                true
+            }
+            _ => false,
+        })
+    }
+
+    async fn purge_deleted_users(&self, now: OffsetDateTime) -> RepoResult<Vec<String>> {
+        let mut store = self.store();
+        let due: Vec<(i32, String)> = store
+            .users
+            .rows
+            .iter()
+            .filter(|(_, (_, u))| u.delete_after.is_some_and(|at| at <= now))
+            .map(|(id, (_, u))| (*id, u.auth0_id.clone()))
+            .collect();
+        Ok(due
+            .into_iter()
+            .map(|(user_id, auth0_id)| {
+                store.remove_user(user_id);
+                auth0_id
+            })
+            .collect())
+    }
+
+    async fn get_profile(&self, user_id: i32) -> RepoResult<Option<UserProfile>> {
+        let store = self.store();
+        let Some((_, user)) = store.users.rows.get(&user_id) else {
+            return Ok(None);
+        };
+        let mut profile = UserProfile::defaults(
+            user_id,
+            user.name.clone().unwrap_or_default(),
+            user.email.clone().unwrap_or_default(),
+        );
+        profile.timezone = store
+            .preferences
+            .get(&user_id)
+            .and_then(|p| p.timezone.clone());
+        if let Some((notifications, cadence)) = store.settings.get(&user_id) {
+            profile.notifications = *notifications;
+            profile.default_reminder_cadence_days = *cadence;
+        }
+        Ok(Some(profile))
+    }
+
+    async fn save_profile(&self, profile: &UserProfile) -> RepoResult<()> {
+        let mut store = self.store();
+        let user_id = profile.user_id;
+        if store
+            .users
+            .rows
+            .iter()
+            .any(|(id, (_, u))| *id != user_id && u.email.as_deref() == Some(&profile.email))
+        {
+            return Err(unique_violation("users_email_key"));
+        }
+        let Some((_, user)) = store.users.rows.get_mut(&user_id) else {
+            return Ok(());
+        };
+        user.name = Some(profile.name.clone());
+        user.email = Some(profile.email.clone());
+        store.preferences.entry(user_id).or_default().timezone = profile.timezone.clone();
+        store.settings.insert(
+            user_id,
+            (profile.notifications, profile.default_reminder_cadence_days),
+        );
+        Ok(())
+    }
+
+    async fn list_workspaces(&self, user_id: i32) -> RepoResult<Vec<Workspace>> {
+        let mut workspaces: Vec<Workspace> = self
+            .store()
+            .workspaces
+            .rows
+            .values()
+            .filter(|(owner, _)| *owner == user_id)
+            .map(|(_, w)| w.clone())
+            .collect();
+        workspaces.sort_by(|a, b| (!a.is_default, &a.name).cmp(&(!b.is_default, &b.name)));
+        Ok(workspaces)
+    }
+
+    async fn create_workspace(&self, user_id: i32, name: &str) -> RepoResult<i32> {
+        let mut store = self.store();
+        if store
+            .workspaces
+            .rows
+            .values()
+            .any(|(owner, w)| *owner == user_id && w.name == name)
+        {
+            return Err(unique_violation("workspaces_user_id_name_key"));
+        }
+        let workspace_id = store.workspaces.next_id();
+        store.workspaces.rows.insert(
+            workspace_id,
+            (
+                user_id,
+                Workspace {
+                    workspace_id,
+                    name: name.to_string(),
+                    is_default: false,
+                },
+            ),
+        );
+        Ok(workspace_id)
+    }
+
+    async fn rename_workspace(
+        &self,
+        user_id: i32,
+        workspace_id: i32,
+        name: &str,
+    ) -> RepoResult<bool> {
+        let mut store = self.store();
+        if store.workspaces.owned(user_id, workspace_id).is_none() {
+            return Ok(false);
+        }
+        if store
+            .workspaces
+            .rows
+            .iter()
+            .any(|(id, (owner, w))| *id != workspace_id && *owner == user_id && w.name == name)
+        {
+            return Err(unique_violation("workspaces_user_id_name_key"));
+        }
+        store
+            .workspaces
+            .owned_mut(user_id, workspace_id)
+            .unwrap()
+            .name = name.to_string();
+        Ok(true)
+    }
+
+    async fn delete_workspace(&self, user_id: i32, workspace_id: i32) -> RepoResult<bool> {
+        let mut store = self.store();
+        if store
+            .workspaces
+            .owned(user_id, workspace_id)
+            .is_none_or(|w| w.is_default)
+        {
+            return Ok(false);
+        }
+        store.workspaces.rows.remove(&workspace_id);
+        store.remove_workspace_rows(workspace_id);
+        Ok(true)
+    }
+
+    async fn owns_workspace(&self, user_id: i32, workspace_id: i32) -> RepoResult<bool> {
+        Ok(self
+            .store()
+            .workspaces
+            .owned(user_id, workspace_id)
+            .is_some())
+    }
+
+    async fn list_contacts(&self, workspace_id: i32) -> RepoResult<Vec<Contact>> {
+        let store = self.store();
+        let mut contacts: Vec<Contact> = store
+            .contacts
+            .rows
+            .values()
+            .filter(|(owner, _)| *owner == workspace_id)
+            .map(|(_, c)| c.clone())
+            .collect();
+        contacts.sort_by(|a, b| name_order(a).cmp(&name_order(b)));
+        Ok(contacts)
+    }
+
+    async fn contacts_by_priority(
+        &self,
+        workspace_id: i32,
+        dates: &LocalDates,
+        include_archived: bool,
+        snoozed: &[i32],
+        limit: Option<i64>,
+        offset: i64,
+    ) -> RepoResult<Vec<Contact>> {
+        let store = self.store();
+        let mut scored: Vec<(Option<f32>, Contact)> = store
+            .contacts
+            .rows
+            .values()
+            .filter(|(owner, c)| *owner == workspace_id && (include_archived || !c.archived))
+            .map(|(_, c)| {
+                let interactions = store
+                    .interactions
+                    .rows
+                    .values()
+                    .map(|(_, i)| i)
+                    .filter(|i| i.participants().any(|id| id == c.contact_id))
+                    .cloned()
+                    .collect();
+                let occasions = store
+                    .occasions
+                    .rows
+                    .values()
+                    .map(|(_, o)| o)
+                    .filter(|o| o.contact_id == Some(c.contact_id))
+                    .cloned()
+                    .collect();
+                let response =
+                    ContactResponse::new(c.clone(), Vec::new(), interactions, occasions, dates);
+                (response.predicted_contact_priority, response.contact)
+            })
+            .collect();
+        // Rows come out by id, and the sort is stable, so ties stay in id order
+        scored.sort_by(|(a, x), (b, y)| {
+            let snoozed_first = snoozed
+                .contains(&x.contact_id)
+                .cmp(&snoozed.contains(&y.contact_id));
+            snoozed_first.then_with(|| match (a, b) {
+                (Some(a), Some(b)) => b.total_cmp(a),
+                _ => a.is_none().cmp(&b.is_none()),
+            })
+        });
+        Ok(scored
+            .into_iter()
+            .map(|(_, contact)| contact)
+            .skip(offset.max(0) as usize)
+            .take(limit.map_or(usize::MAX, |n| n.max(0) as usize))
+            .collect())
+    }
+
+    async fn get_contact(&self, workspace_id: i32, contact_id: i32) -> RepoResult<Option<Contact>> {
+        Ok(self
+            .store()
+            .contacts
+            .owned(workspace_id, contact_id)
+            .cloned())
+    }
+
+    async fn contacts_by_phone(&self, workspace_id: i32, number: &str) -> RepoResult<Vec<Contact>> {
+        Ok(self
+            .store()
+            .contacts
+            .rows
+            .values()
+            .filter(|(owner, c)| *owner == workspace_id && c.phone_e164.as_deref() == Some(number))
+            .map(|(_, c)| c.clone())
+            .collect())
+    }
+
+    async fn contacts_introduced_by(
+        &self,
+        workspace_id: i32,
+        contact_id: i32,
+    ) -> RepoResult<Vec<Contact>> {
+        let mut contacts: Vec<Contact> = self
+            .store()
+            .contacts
+            .rows
+            .values()
+            .filter(|(owner, c)| {
+                *owner == workspace_id && c.introduced_by_contact_id == Some(contact_id)
+            })
+            .map(|(_, c)| c.clone())
+            .collect();
+        contacts.sort_by(|a, b| name_order(a).cmp(&name_order(b)));
+        Ok(contacts)
+    }
+
+    async fn latest_contacts(&self, workspace_id: i32, limit: i64) -> RepoResult<Vec<Contact>> {
+        Ok(self
+            .store()
+            .contacts
+            .rows
+            .values()
+            .rev()
+            .filter(|(owner, _)| *owner == workspace_id)
+            .take(limit as usize)
+            .map(|(_, c)| c.clone())
+            .collect())
+    }
+
+    async fn contacts_created_between(
+        &self,
+        workspace_id: i32,
+        from: OffsetDateTime,
+        until: OffsetDateTime,
+    ) -> RepoResult<Vec<Contact>> {
+        let store = self.store();
+        let mut contacts: Vec<(OffsetDateTime, Contact)> = store
+            .contacts
+            .rows
+            .values()
+            .filter(|(owner, _)| *owner == workspace_id)
+            .filter_map(|(_, c)| {
+                let created_at = *store.contacts_created_at.get(&c.contact_id)?;
+                (from <= created_at && created_at < until).then(|| (created_at, c.clone()))
+            })
+            .collect();
+        contacts.sort_by_key(|(created_at, c)| (*created_at, c.contact_id));
+        Ok(contacts.into_iter().map(|(_, c)| c).collect())
+    }
+
+    async fn contacts_in_box(
+        &self,
+        workspace_id: i32,
+        area: &BoundingBox,
+    ) -> RepoResult<Vec<Contact>> {
+        Ok(self
+            .store()
+            .contacts
+            .rows
+            .values()
+            .filter(|(owner, c)| {
+                let inside = match (c.latitude, c.longitude) {
+                    (Some(latitude), Some(longitude)) => area.contains(Coordinates {
+                        latitude,
+                        longitude,
+                    }),
+                    _ => false,
+                };
+                *owner == workspace_id && !c.archived && inside
+            })
+            .map(|(_, c)| c.clone())
+            .collect())
+    }
+
+    async fn autocomplete_contacts(
+        &self,
+        workspace_id: i32,
+        words: &[String],
+        limit: i64,
+    ) -> RepoResult<Vec<ContactMatch>> {
+        let store = self.store();
+        let starts_with = |name: &Option<String>, word: &str| {
+            name.as_deref()
+                .is_some_and(|name| name.to_lowercase().starts_with(word))
+        };
+        let mut contacts: Vec<&Contact> = store
+            .contacts
+            .rows
+            .values()
+            .filter(|(owner, c)| {
+                *owner == workspace_id
+                    && !c.archived
+                    && words.iter().all(|word| {
+                        starts_with(&c.first_name, word) || starts_with(&c.last_name, word)
+                    })
+            })
+            .map(|(_, c)| c)
+            .collect();
+        contacts.sort_by(|a, b| name_order(a).cmp(&name_order(b)));
+        Ok(contacts
+            .into_iter()
+            .take(limit as usize)
+            .map(|c| ContactMatch {
+                contact_id: c.contact_id,
+                display_name: [&c.first_name, &c.last_name]
+                    .into_iter()
+                    .flatten()
+                    .map(String::as_str)
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                avatar_url: c.avatar_url.clone(),
+            })
+            .collect())
+    }
+
+    async fn contact_details(
+        &self,
+        workspace_id: i32,
+        contact_ids: &[i32],
+    ) -> RepoResult<Vec<ContactDetails>> {
+        let store = self.store();
+        let contacts: Vec<Contact> = contact_ids
+            .iter()
+            .filter_map(|&contact_id| store.contacts.owned(workspace_id, contact_id).cloned())
+            .collect();
+        let ids: BTreeSet<i32> = contacts.iter().map(|c| c.contact_id).collect();
+        let tags = store
+            .contact_tags
+            .iter()
+            .filter(|(contact_id, _)| ids.contains(contact_id))
+            .filter_map(|(contact_id, tag_id)| {
+                let (_, tag) = store.tags.rows.get(tag_id)?;
+                Some((*contact_id, tag.clone()))
+            });
+        let interactions = store
+            .interactions
+            .rows
+            .values()
+            .map(|(_, i)| i)
+            .filter(|i| i.participants().any(|id| ids.contains(&id)))
+            .cloned();
+        let occasions = store
+            .occasions
+            .rows
+            .values()
+            .map(|(_, o)| o)
+            .filter(|o| o.contact_id.is_some_and(|c| ids.contains(&c)))
+            .cloned();
+        Ok(ContactDetails::group(
+            contacts,
+            tags,
+            interactions,
+            occasions,
+        ))
+    }
+
+    async fn contact_summaries(
+        &self,
+        workspace_id: i32,
+        contact_ids: &[i32],
+        today: Date,
+    ) -> RepoResult<Vec<(i32, ContactSummary)>> {
+        let store = self.store();
+        Ok(contact_ids
+            .iter()
+            .filter(|id| store.contacts.owned(workspace_id, **id).is_some())
+            .map(|&contact_id| {
+                let last_interaction_at = store
+                    .interactions
+                    .rows
+                    .values()
+                    .map(|(_, i)| i)
+                    .filter(|i| i.participants().any(|id| id == contact_id))
+                    .map(|i| i.interaction_date)
+                    .max();
+                let next_occasion_at = store
+                    .occasions
+                    .rows
+                    .values()
+                    .map(|(_, o)| o)
+                    .filter(|o| o.contact_id == Some(contact_id))
+                    .filter_map(|o| reminders::next_occurrence(o, today))
+                    .min();
+                let summary = ContactSummary {
+                    last_interaction_at,
+                    next_occasion_at,
+                };
+                (contact_id, summary)
+            })
+            .collect())
+    }
+
+    async fn create_contact(
+        &self,
+        workspace_id: i32,
+        contact: &NewContactRequest,
+    ) -> RepoResult<i32> {
+        let mut store = self.store();
+        let contact_id = Self::insert_contact(&mut store, workspace_id, contact)?;
+        store.queue_hook_deliveries(workspace_id, HookEvent::NewContact, &[contact_id]);
+        Ok(contact_id)
+    }
+
+    async fn create_contacts(
+        &self,
+        workspace_id: i32,
+        contacts: &[NewContactRequest],
+        atomic: bool,
+    ) -> RepoResult<Vec<RepoResult<i32>>> {
+        let mut store = self.store();
+        let results: Vec<RepoResult<i32>> = contacts
+            .iter()
+            .map(|contact| Self::insert_contact(&mut store, workspace_id, contact))
+            .collect();
+
+        if atomic && results.iter().any(Result::is_err) {
+            for contact_id in results.iter().flatten() {
+                store.contacts.rows.remove(contact_id);
+            }
+            return Err(results.into_iter().find_map(Result::err).unwrap());
+        }
+        let created: Vec<i32> = results.iter().flatten().copied().collect();
+        store.queue_hook_deliveries(workspace_id, HookEvent::NewContact, &created);
+        Ok(results)
+    }
+
+    async fn check_contacts(
+        &self,
+        workspace_id: i32,
+        contacts: &[NewContactRequest],
+    ) -> RepoResult<Vec<RepoResult<()>>> {
+        let store = self.store();
+        let mut emails = BTreeSet::new();
+        Ok(contacts
+            .iter()
+            .map(|contact| {
+                Self::check_contact_email(&store, workspace_id, contact.email.as_deref(), None)?;
+                // Earlier rows of the batch would hold their emails by then
+                if let Some(email) = contact.email.as_deref()
+                    && !emails.insert(email)
+                {
+                    return Err(unique_violation("contacts_workspace_id_email_key"));
+                }
+                Ok(())
+            })
+            .collect())
+    }
+
+    async fn update_contact(
+        &self,
+        workspace_id: i32,
+        contact_id: i32,
+        contact: &NewContactRequest,
+    ) -> RepoResult<bool> {
+        let mut store = self.store();
+        if store.contacts.owned(workspace_id, contact_id).is_none() {
+            return Ok(false);
+        }
+        Self::check_contact_email(
+            &store,
+            workspace_id,
+            contact.email.as_deref(),
+            Some(contact_id),
+        )?;
+        let changes = history::changes(
+            store.contacts.owned(workspace_id, contact_id).unwrap(),
+            contact,
+        );
+        store.record_changes(contact_id, changes);
+        let existing = store.contacts.owned_mut(workspace_id, contact_id).unwrap();
+        existing.first_name = contact.first_name.clone();
+        existing.last_name = contact.last_name.clone();
+        existing.email = contact.email.clone();
+        existing.phone = contact.phone.clone();
+        existing.phone_e164 = phone::e164(contact.phone.as_deref());
+        existing.short_note = contact.short_note.clone();
+        existing.notes = contact.notes.clone();
+        existing.avatar_url = contact.avatar_url.clone();
+        existing.desired_frequency_days = contact.desired_frequency_days;
+        existing.met_at = contact.met_at.clone();
+        existing.introduced_by_contact_id = contact.introduced_by_contact_id;
+        if existing.location != contact.location {
+            existing.location = contact.location.clone();
+            existing.latitude = None;
+            existing.longitude = None;
+        }
+        existing.updated_at = Some(now());
+        Ok(true)
+    }
+
+    async fn delete_contact(
+        &self,
+        workspace_id: i32,
+        contact_id: i32,
+        keep_history: bool,
+    ) -> RepoResult<bool> {
+        let mut store = self.store();
+        if !store.contacts.remove_owned(workspace_id, contact_id) {
+            return Ok(false);
+        }
+        if keep_history {
+            store.unassign_history(contact_id);
+        }
+        store.remove_contact_children(contact_id);
+        Ok(true)
+    }
+
+    async fn delete_contacts(
+        &self,
+        workspace_id: i32,
+        contact_ids: &[i32],
+        keep_history: bool,
+    ) -> RepoResult<Vec<i32>> {
+        let mut store = self.store();
+        let mut deleted = Vec::new();
+        for &contact_id in contact_ids {
+            if store.contacts.remove_owned(workspace_id, contact_id) {
+                if keep_history {
+                    store.unassign_history(contact_id);
+                }
+                store.remove_contact_children(contact_id);
+                deleted.push(contact_id);
+            }
+        }
+        Ok(deleted)
+    }
+
+    async fn list_unassigned(&self, workspace_id: i32) -> RepoResult<Unassigned> {
+        let store = self.store();
+        let mut interactions: Vec<Interaction> = store
+            .interactions
+            .rows
+            .values()
+            .filter(|(owner, i)| *owner == workspace_id && i.contact_id.is_none())
+            .map(|(_, i)| i.clone())
+            .collect();
+        interactions.sort_by_key(|i| (i.interaction_date, i.interaction_id));
+        let mut occasions: Vec<Occasion> = store
+            .occasions
+            .rows
+            .values()
+            .filter(|(owner, o)| *owner == workspace_id && o.contact_id.is_none())
+            .map(|(_, o)| o.clone())
+            .collect();
+        occasions.sort_by_key(|o| (o.date, o.occasion_id));
+        Ok(Unassigned {
+            interactions,
+            occasions,
+        })
+    }
+
+    async fn set_contact_archived(
+        &self,
+        workspace_id: i32,
+        contact_id: i32,
+        archived: bool,
+    ) -> RepoResult<bool> {
+        let mut store = self.store();
+        let Some(contact) = store.contacts.owned_mut(workspace_id, contact_id) else {
+            return Ok(false);
+        };
+        let change = history::archived_change(contact.archived, archived);
+        contact.archived = archived;
+        contact.updated_at = Some(now());
+        store.record_changes(contact_id, change.into_iter().collect());
+        Ok(true)
+    }
+
+    async fn set_contact_coordinates(
+        &self,
+        workspace_id: i32,
+        contact_id: i32,
+        location: &str,
+        coordinates: Coordinates,
+    ) -> RepoResult<bool> {
+        let mut store = self.store();
+        let Some(contact) = store.contacts.owned_mut(workspace_id, contact_id) else {
+            return Ok(false);
+        };
+        if contact.location.as_deref() != Some(location) {
+            return Ok(false);
+        }
+        contact.latitude = Some(coordinates.latitude);
+        contact.longitude = Some(coordinates.longitude);
+        contact.updated_at = Some(now());
+        Ok(true)
+    }
+
+    async fn owns_contact(&self, workspace_id: i32, contact_id: i32) -> RepoResult<bool> {
+        Ok(self
+            .store()
+            .contacts
+            .owned(workspace_id, contact_id)
+            .is_some())
+    }
+
+    async fn contact_checksums(&self, workspace_id: i32) -> RepoResult<Vec<ContactChecksum>> {
+        let store = self.store();
+        let checksums = store
+            .contacts
+            .rows
+            .values()
+            .filter(|(owner, _)| *owner == workspace_id)
+            .map(|(_, c)| {
+                let tag_ids: Vec<i32> = store
+                    .contact_tags
+                    .iter()
+                    .filter(|(contact_id, _)| *contact_id == c.contact_id)
+                    .map(|(_, tag_id)| *tag_id)
+                    .collect();
+                let interactions: Vec<&Interaction> = store
+                    .interactions
+                    .rows
+                    .values()
+                    .map(|(_, i)| i)
+                    .filter(|i| i.participants().any(|id| id == c.contact_id))
+                    .collect();
+                let occasions: Vec<&Occasion> = store
+                    .occasions
+                    .rows
+                    .values()
+                    .map(|(_, o)| o)
+                    .filter(|o| o.contact_id == Some(c.contact_id))
+                    .collect();
+                let content = serde_json::json!([c, tag_ids, interactions, occasions]);
+                ContactChecksum {
+                    contact_id: c.contact_id,
+                    hash: format!("{:x}", Sha256::digest(content.to_string())),
+                }
+            })
+            .collect();
+        Ok(checksums)
+    }
+
+    async fn refresh_contact_scores(&self, _limit: i64) -> RepoResult<u64> {
+        Ok(0)
+    }
+
+    async fn list_tags(&self, workspace_id: i32) -> RepoResult<Vec<Tag>> {
+        Ok(self
+            .store()
+            .tags
+            .rows
+            .values()
+            .filter(|(owner, _)| *owner == workspace_id)
+            .map(|(_, t)| t.clone())
+            .collect())
+    }
+
+    async fn get_tag(&self, workspace_id: i32, tag_id: i32) -> RepoResult<Option<Tag>> {
+        Ok(self.store().tags.owned(workspace_id, tag_id).cloned())
+    }
+
+    async fn tag_usage(&self, workspace_id: i32) -> RepoResult<Vec<TagUsage>> {
+        let store = self.store();
+        Ok(store
+            .tags
+            .rows
+            .values()
+            .filter(|(owner, _)| *owner == workspace_id)
+            .map(|(_, tag)| TagUsage {
+                tag: tag.clone(),
+                contact_count: store
+                    .contact_tags
+                    .iter()
+                    .filter(|(_, t)| *t == tag.tag_id)
+                    .count() as i64,
+            })
+            .collect())
+    }
+
+    async fn create_tag(&self, workspace_id: i32, tag: &NewTagRequest) -> RepoResult<i32> {
+        let mut store = self.store();
+        Self::check_tag_name(&store, workspace_id, &tag.name, None)?;
+        let tag_id = store.tags.next_id();
+        store.tags.rows.insert(
+            tag_id,
+            (
+                workspace_id,
+                Tag {
+                    tag_id,
+                    name: tag.name.clone(),
+                    color: tag.color.clone(),
+                    details: tag.details.clone(),
+                },
+            ),
+        );
+        Ok(tag_id)
+    }
+
+    async fn update_tag(
+        &self,
+        workspace_id: i32,
+        tag_id: i32,
+        tag: &NewTagRequest,
+    ) -> RepoResult<bool> {
+        let mut store = self.store();
+        if store.tags.owned(workspace_id, tag_id).is_none() {
+            return Ok(false);
+        }
+        Self::check_tag_name(&store, workspace_id, &tag.name, Some(tag_id))?;
+        let existing = store.tags.owned_mut(workspace_id, tag_id).unwrap();
+        existing.name = tag.name.clone();
+        existing.color = tag.color.clone();
+        existing.details = tag.details.clone();
+        let tagged: Vec<i32> = store
+            .contact_tags
+            .iter()
+            .filter(|(_, t)| *t == tag_id)
+            .map(|(c, _)| *c)
+            .collect();
+        for contact_id in tagged {
+            store.touch_contact(contact_id);
+        }
+        Ok(true)
+    }
+
+    async fn delete_tag(&self, workspace_id: i32, tag_id: i32) -> RepoResult<bool> {
+        let mut store = self.store();
+        if !store.tags.remove_owned(workspace_id, tag_id) {
+            return Ok(false);
+        }
+        let mut tagged: Vec<i32> = store
+            .contact_tags
+            .iter()
+            .filter(|(_, t)| *t == tag_id)
+            .map(|(c, _)| *c)
+            .collect();
+        store.contact_tags.retain(|(_, t)| *t != tag_id);
+        for (_, interaction) in store.interactions.rows.values_mut() {
+            if let Ok(at) = interaction.tag_ids.binary_search(&tag_id) {
+                interaction.tag_ids.remove(at);
+                tagged.extend(interaction.participants());
+            }
+        }
+        store
+            .shares
+            .rows
+            .retain(|_, (_, s)| s.tag_id != Some(tag_id));
+        for contact_id in tagged {
+            store.touch_contact(contact_id);
+        }
+        Ok(true)
+    }
+
+    async fn delete_unused_tags(&self, workspace_id: i32) -> RepoResult<Vec<i32>> {
+        let mut store = self.store();
+        let unused: Vec<i32> = store
+            .tags
+            .rows
+            .iter()
+            .filter(|(tag_id, (owner, _))| {
+                *owner == workspace_id
+                    && !store.contact_tags.iter().any(|(_, t)| t == *tag_id)
+                    && !store
+                        .interactions
+                        .rows
+                        .values()
+                        .any(|(_, i)| i.tag_ids.contains(tag_id))
+                    && !store
+                        .shares
+                        .rows
+                        .values()
+                        .any(|(_, s)| s.tag_id == Some(**tag_id))
+            })
+            .map(|(tag_id, _)| *tag_id)
+            .collect();
+        for tag_id in &unused {
+            store.tags.rows.remove(tag_id);
+        }
+        Ok(unused)
+    }
+
+    async fn owns_tag(&self, workspace_id: i32, tag_id: i32) -> RepoResult<bool> {
+        Ok(self.store().tags.owned(workspace_id, tag_id).is_some())
+    }
+
+    async fn tags_for_contacts(&self, contact_ids: &[i32]) -> RepoResult<Vec<(i32, Tag)>> {
+        let store = self.store();
+        Ok(store
+            .contact_tags
+            .iter()
+            .filter(|(contact_id, _)| contact_ids.contains(contact_id))
+            .filter_map(|(contact_id, tag_id)| {
+                let (_, tag) = store.tags.rows.get(tag_id)?;
+                Some((*contact_id, tag.clone()))
+            })
+            .collect())
+    }
+
+    async fn add_tag_to_contact(&self, contact_id: i32, tag_id: i32) -> RepoResult<()> {
+        let mut store = self.store();
+        if store.contact_tags.insert((contact_id, tag_id)) {
+            store.touch_contact(contact_id);
+        }
+        Ok(())
+    }
+
+    async fn remove_tag_from_contact(&self, contact_id: i32, tag_id: i32) -> RepoResult<()> {
+        let mut store = self.store();
+        if store.contact_tags.remove(&(contact_id, tag_id)) {
+            store.touch_contact(contact_id);
+        }
+        Ok(())
+    }
+
+    async fn add_tag_to_interaction(&self, interaction_id: i32, tag_id: i32) -> RepoResult<()> {
+        let mut store = self.store();
+        let Some((_, interaction)) = store.interactions.rows.get_mut(&interaction_id) else {
+            return Ok(());
+        };
+        if let Err(at) = interaction.tag_ids.binary_search(&tag_id) {
+            interaction.tag_ids.insert(at, tag_id);
+            let participants: Vec<i32> = interaction.participants().collect();
+            for contact_id in participants {
+                store.touch_contact(contact_id);
+            }
+        }
+        Ok(())
+    }
+
+    async fn remove_tag_from_interaction(
+        &self,
+        interaction_id: i32,
+        tag_id: i32,
+    ) -> RepoResult<()> {
+        let mut store = self.store();
+        let Some((_, interaction)) = store.interactions.rows.get_mut(&interaction_id) else {
+            return Ok(());
+        };
+        if let Ok(at) = interaction.tag_ids.binary_search(&tag_id) {
+            interaction.tag_ids.remove(at);
+            let participants: Vec<i32> = interaction.participants().collect();
+            for contact_id in participants {
+                store.touch_contact(contact_id);
+            }
+        }
+        Ok(())
+    }
+
+    async fn add_tag_to_contacts(
+        &self,
+        workspace_id: i32,
+        tag_id: i32,
+        contact_ids: &[i32],
+    ) -> RepoResult<Vec<i32>> {
+        let mut store = self.store();
+        let mut tagged = Vec::new();
+        for &contact_id in contact_ids {
+            if store.contacts.owned(workspace_id, contact_id).is_some()
+                && !tagged.contains(&contact_id)
+            {
+                if store.contact_tags.insert((contact_id, tag_id)) {
+                    store.touch_contact(contact_id);
+                }
+                tagged.push(contact_id);
+            }
+        }
+        Ok(tagged)
+    }
+
+    async fn interactions_for_contacts(&self, contact_ids: &[i32]) -> RepoResult<Vec<Interaction>> {
+        Ok(self
+            .store()
+            .interactions
+            .rows
+            .values()
+            .filter(|(_, i)| i.participants().any(|id| contact_ids.contains(&id)))
+            .map(|(_, i)| i.clone())
+            .collect())
+    }
+
+    async fn interactions_by_id(
+        &self,
+        workspace_id: i32,
+        interaction_ids: &[i32],
+    ) -> RepoResult<Vec<Interaction>> {
+        Ok(self
+            .store()
+            .interactions
+            .rows
+            .values()
+            .filter(|(owner, i)| {
+                *owner == workspace_id && interaction_ids.contains(&i.interaction_id)
+            })
+            .map(|(_, i)| i.clone())
+            .collect())
+    }
+
+    async fn search_notes(
+        &self,
+        workspace_id: i32,
+        query: &str,
+        limit: i64,
+    ) -> RepoResult<Vec<NoteMatch>> {
+        let words = search_words(query);
+        if words.is_empty() {
+            return Ok(Vec::new());
+        }
+        let store = self.store();
+        let contact = |contact_id: Option<i32>| {
+            let contact = store.contacts.owned(workspace_id, contact_id?)?;
+            Some(ContactMatch {
+                contact_id: contact.contact_id,
+                display_name: [&contact.first_name, &contact.last_name]
+                    .into_iter()
+                    .flatten()
+                    .map(String::as_str)
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                avatar_url: contact.avatar_url.clone(),
+            })
+        };
+        let mut hits = Vec::new();
+        for (owner, interaction) in store.interactions.rows.values() {
+            let Some(notes) = interaction
+                .notes
+                .as_deref()
+                .filter(|_| *owner == workspace_id)
+            else {
+                continue;
+            };
+            if let Some((rank, snippet)) = highlight(notes, &words) {
+                let found = NoteMatch {
+                    source: NoteSource::Interaction,
+                    interaction_id: Some(interaction.interaction_id),
+                    interaction_date: Some(interaction.interaction_date),
+                    contact: contact(interaction.contact_id),
+                    snippet,
+                };
+                hits.push((rank, found));
+            }
+        }
+        for (owner, c) in store.contacts.rows.values() {
+            let Some(notes) = c.notes.as_deref().filter(|_| *owner == workspace_id) else {
+                continue;
+            };
+            if let Some((rank, snippet)) = highlight(notes, &words) {
+                let found = NoteMatch {
+                    source: NoteSource::Contact,
+                    interaction_id: None,
+                    interaction_date: None,
+                    contact: contact(Some(c.contact_id)),
+                    snippet,
+                };
+                hits.push((rank, found));
+            }
+        }
+        // Best first, then newest first with contacts' notes last
+        hits.sort_by(|(a_rank, a), (b_rank, b)| {
+            b_rank
+                .cmp(a_rank)
+                .then(b.interaction_date.cmp(&a.interaction_date))
+        });
+        Ok(hits
+            .into_iter()
+            .take(limit.max(0) as usize)
+            .map(|(_, found)| found)
+            .collect())
+    }
+
+    async fn latest_interactions(
+        &self,
+        workspace_id: i32,
+        limit: i64,
+    ) -> RepoResult<Vec<Interaction>> {
+        Ok(self
+            .store()
+            .interactions
+            .rows
+            .values()
+            .rev()
+            .filter(|(owner, _)| *owner == workspace_id)
+            .take(limit as usize)
+            .map(|(_, i)| i.clone())
+            .collect())
+    }
+
+    async fn interactions_since(
+        &self,
+        workspace_id: i32,
+        since: OffsetDateTime,
+    ) -> RepoResult<Vec<Interaction>> {
+        Ok(self
+            .store()
+            .interactions
+            .rows
+            .values()
+            .filter(|(owner, i)| *owner == workspace_id && i.interaction_date >= since)
+            .map(|(_, i)| i.clone())
+            .collect())
+    }
+
+    async fn count_by_period(
+        &self,
+        workspace_id: i32,
+        metric: StatsMetric,
+        granularity: Granularity,
+        from: Date,
+        to: Date,
+        dates: &LocalDates,
+    ) -> RepoResult<Vec<(Date, i64)>> {
+        let store = self.store();
+        let instants: Vec<OffsetDateTime> = match metric {
+            StatsMetric::Interactions => store
+                .interactions
+                .rows
+                .values()
+                .filter(|(owner, _)| *owner == workspace_id)
+                .map(|(_, i)| i.interaction_date)
+                .collect(),
+            StatsMetric::Contacts => store
+                .contacts
+                .rows
+                .values()
+                .filter(|(owner, _)| *owner == workspace_id)
+                .filter_map(|(_, c)| store.contacts_created_at.get(&c.contact_id).copied())
+                .collect(),
+        };
+        let mut counts: BTreeMap<Date, i64> = BTreeMap::new();
+        for at in instants {
+            let day = dates.date_of(at);
+            if from <= day && day <= to {
+                *counts.entry(bucket_start(granularity, day)).or_default() += 1;
+            }
+        }
+        Ok(counts.into_iter().collect())
+    }
+
+    async fn contact_history(
+        &self,
+        contact_id: i32,
+        after: Option<Cursor>,
+        limit: Option<i64>,
+    ) -> RepoResult<Vec<ContactChange>> {
+        let mut history: Vec<ContactChange> = self
+            .store()
+            .contact_changes
+            .rows
+            .values()
+            .filter(|(owner, c)| {
+                *owner == contact_id
+                    && after.is_none_or(|after| Cursor::new(c.changed_at, c.change_id) < after)
+            })
+            .map(|(_, c)| c.clone())
+            .collect();
+        history.sort_by_key(|c| std::cmp::Reverse((c.changed_at, c.change_id)));
+        history.truncate(limit.map_or(usize::MAX, |n| n as usize));
+        Ok(history)
+    }
+
+    async fn contact_timeline(
+        &self,
+        contact_id: i32,
+        interaction_type: Option<&str>,
+        tag_id: Option<i32>,
+        after: Option<Cursor>,
+        limit: Option<i64>,
+    ) -> RepoResult<Vec<Interaction>> {
+        let mut interactions: Vec<Interaction> = self
+            .store()
+            .interactions
+            .rows
+            .values()
+            .map(|(_, i)| i)
+            .filter(|i| i.participants().any(|id| id == contact_id))
+            .filter(|i| {
+                interaction_type.is_none() || i.interaction_type.as_deref() == interaction_type
+            })
+            .filter(|i| tag_id.is_none_or(|tag_id| i.tag_ids.contains(&tag_id)))
+            .filter(|i| {
+                after.is_none_or(|after| Cursor::new(i.interaction_date, i.interaction_id) < after)
+            })
+            .cloned()
+            .collect();
+        interactions.sort_by_key(|i| std::cmp::Reverse((i.interaction_date, i.interaction_id)));
+        interactions.truncate(limit.map_or(usize::MAX, |n| n as usize));
+        Ok(interactions)
+    }
+
+    async fn create_interaction(
+        &self,
+        workspace_id: i32,
+        interaction: &NewInteractionRequest,
+        follow_up: Option<&NewOccasionRequest>,
+    ) -> RepoResult<(i32, Option<i32>)> {
+        let mut store = self.store();
+        let interaction_id = store.insert_interaction(workspace_id, interaction);
+        let occasion_id = follow_up
+            .map(|occasion| store.insert_occasion(workspace_id, occasion, Some(interaction_id)));
+        Ok((interaction_id, occasion_id))
+    }
+
+    async fn update_interaction(
+        &self,
+        workspace_id: i32,
+        interaction_id: i32,
+        interaction: &NewInteractionRequest,
+    ) -> RepoResult<bool> {
+        let mut store = self.store();
+        let Some(contact_id) = store
+            .interactions
+            .owned(workspace_id, interaction_id)
+            .map(|i| i.contact_id)
+        else {
+            return Ok(false);
+        };
+        // An unassigned interaction takes the request's contact
+        let contact_id = contact_id.unwrap_or(interaction.contact_id);
+        if store.contacts.owned(workspace_id, contact_id).is_none() {
+            return Ok(false);
+        }
+        let contact_ids = store.participants(workspace_id, contact_id, &interaction.contact_ids);
+        let existing = store
+            .interactions
+            .owned_mut(workspace_id, interaction_id)
+            .unwrap();
+        existing.contact_id = Some(contact_id);
+        existing.interaction_date = interaction.interaction_date;
+        existing.notes = interaction.notes.clone();
+        existing.follow_up_priority = interaction.follow_up_priority;
+        existing.interaction_type = interaction.interaction_type.clone();
+        let removed = std::mem::replace(&mut existing.contact_ids, contact_ids.clone());
+        store.touch_contact(contact_id);
+        for contact_id in removed.into_iter().chain(contact_ids) {
+            store.touch_contact(contact_id);
+        }
+        Ok(true)
+    }
+
+    async fn delete_interaction(&self, workspace_id: i32, interaction_id: i32) -> RepoResult<bool> {
+        let mut store = self.store();
+        let Some(participants) = store
+            .interactions
+            .owned(workspace_id, interaction_id)
+            .map(|i| i.participants().collect::<Vec<i32>>())
+        else {
+            return Ok(false);
+        };
+        store.interactions.rows.remove(&interaction_id);
+        store.remove_orphan_attachments();
+        store
+            .occasions
+            .rows
+            .retain(|_, (_, o)| o.interaction_id != Some(interaction_id));
+        store.unlink_gift_ideas();
+        for contact_id in participants {
+            store.touch_contact(contact_id);
+        }
+        Ok(true)
+    }
+
+    async fn owns_interaction(&self, workspace_id: i32, interaction_id: i32) -> RepoResult<bool> {
+        Ok(self
+            .store()
+            .interactions
+            .owned(workspace_id, interaction_id)
+            .is_some())
+    }
+
+    async fn list_attachments(
+        &self,
+        workspace_id: i32,
+        interaction_id: i32,
+    ) -> RepoResult<Vec<Attachment>> {
+        Ok(self
+            .store()
+            .attachments
+            .rows
+            .values()
+            .filter(|(owner, a)| {
+                *owner == workspace_id && a.attachment.interaction_id == interaction_id
+            })
+            .map(|(_, a)| a.attachment.clone())
+            .collect())
+    }
+
+    async fn create_attachment(
+        &self,
+        workspace_id: i32,
+        interaction_id: i32,
+        attachment: &NewAttachment,
+    ) -> RepoResult<i32> {
+        let mut store = self.store();
+        let attachment_id = store.attachments.next_id();
+        let row = AttachmentRow {
+            attachment: Attachment {
+                attachment_id,
+                interaction_id,
+                file_name: attachment.file_name.clone(),
+                content_type: attachment.content_type.clone(),
+                size_bytes: attachment.data.len() as i32,
+                transcription: attachment
+                    .transcribe
+                    .then_some(TranscriptionStatus::Pending),
+                created_at: OffsetDateTime::now_utc(),
+            },
+            data: attachment.data.clone(),
+            transcription_attempts: 0,
+        };
+        store
+            .attachments
+            .rows
+            .insert(attachment_id, (workspace_id, row));
+        Ok(attachment_id)
+    }
+
+    async fn attachment_file(
+        &self,
+        workspace_id: i32,
+        attachment_id: i32,
+    ) -> RepoResult<Option<AttachmentFile>> {
+        Ok(self
+            .store()
+            .attachments
+            .owned(workspace_id, attachment_id)
+            .map(|a| AttachmentFile {
+                file_name: a.attachment.file_name.clone(),
+                content_type: a.attachment.content_type.clone(),
+                data: a.data.clone(),
+            }))
+    }
+
+    async fn delete_attachment(
+        &self,
+        workspace_id: i32,
+        attachment_id: i32,
+    ) -> RepoResult<Option<i32>> {
+        let mut store = self.store();
+        let interaction_id = store
+            .attachments
+            .owned(workspace_id, attachment_id)
+            .map(|a| a.attachment.interaction_id);
+        store.attachments.remove_owned(workspace_id, attachment_id);
+        Ok(interaction_id)
+    }
+
+    async fn owns_attachment(&self, workspace_id: i32, attachment_id: i32) -> RepoResult<bool> {
+        Ok(self
+            .store()
+            .attachments
+            .owned(workspace_id, attachment_id)
+            .is_some())
+    }
+
+    async fn pending_transcriptions(&self, limit: i64) -> RepoResult<Vec<PendingTranscription>> {
+        Ok(self
+            .store()
+            .attachments
+            .rows
+            .values()
+            .filter(|(_, a)| a.attachment.transcription == Some(TranscriptionStatus::Pending))
+            .take(limit.max(0) as usize)
+            .map(|(workspace_id, a)| PendingTranscription {
+                attachment_id: a.attachment.attachment_id,
+                workspace_id: *workspace_id,
+                file_name: a.attachment.file_name.clone(),
+                content_type: a.attachment.content_type.clone(),
+                data: a.data.clone(),
+                attempts: a.transcription_attempts,
+            })
+            .collect())
+    }
+
+    async fn save_transcript(
+        &self,
+        workspace_id: i32,
+        attachment_id: i32,
+        transcript: &str,
+    ) -> RepoResult<bool> {
+        let mut store = self.store();
+        let Some(attachment) = store
+            .attachments
+            .owned_mut(workspace_id, attachment_id)
+            .filter(|a| a.attachment.transcription == Some(TranscriptionStatus::Pending))
+        else {
+            return Ok(false);
+        };
+        attachment.attachment.transcription = Some(TranscriptionStatus::Done);
+        let interaction_id = attachment.attachment.interaction_id;
+        let Some(interaction) = store.interactions.owned_mut(workspace_id, interaction_id) else {
+            return Ok(false);
+        };
+        interaction.notes = Some(match interaction.notes.take().filter(|n| !n.is_empty()) {
+            Some(notes) => format!("{}\n\n{}", notes, transcript),
+            None => transcript.to_string(),
+        });
+        let participants: Vec<i32> = interaction.participants().collect();
+        for contact_id in participants {
+            store.touch_contact(contact_id);
+        }
+        Ok(true)
+    }
+
+    async fn transcription_failed(
+        &self,
+        workspace_id: i32,
+        attachment_id: i32,
+        give_up: bool,
+    ) -> RepoResult<()> {
+        if let Some(attachment) = self
+            .store()
+            .attachments
+            .owned_mut(workspace_id, attachment_id)
+        {
+            attachment.transcription_attempts += 1;
+            if give_up {
+                attachment.attachment.transcription = Some(TranscriptionStatus::Failed);
+            }
+        }
+        Ok(())
+    }
+
+    async fn pending_avatar_lookups(
+        &self,
+        now: OffsetDateTime,
+        limit: i64,
+    ) -> RepoResult<Vec<PendingAvatarLookup>> {
+        let store = self.store();
+        let mut pending: Vec<(Option<OffsetDateTime>, PendingAvatarLookup)> = store
+            .contacts
+            .rows
+            .values()
+            .filter(|(_, c)| !c.archived && c.avatar_url.is_none())
+            .filter_map(|(workspace_id, c)| {
+                let email = c.email.clone().filter(|e| !e.is_empty())?;
+                let next_lookup_at = store.avatar_lookups.get(&c.contact_id).copied();
+                if next_lookup_at.is_some_and(|at| at > now) {
+                    return None;
+                }
+                Some((
+                    next_lookup_at,
+                    PendingAvatarLookup {
+                        contact_id: c.contact_id,
+                        workspace_id: *workspace_id,
+                        email,
+                    },
+                ))
+            })
+            .collect();
+        // Never looked up first, like NULLS FIRST
+        pending.sort_by_key(|(at, p)| (at.is_some(), *at, p.contact_id));
+        Ok(pending
+            .into_iter()
+            .take(limit.max(0) as usize)
+            .map(|(_, p)| p)
+            .collect())
+    }
+
+    async fn record_avatar_lookup(
+        &self,
+        workspace_id: i32,
+        contact_id: i32,
+        email: &str,
+        avatar_url: Option<&str>,
+        next_lookup_at: OffsetDateTime,
+    ) -> RepoResult<bool> {
+        let mut store = self.store();
+        if store.contacts.owned(workspace_id, contact_id).is_none() {
+            return Ok(false);
+        }
+        store.avatar_lookups.insert(contact_id, next_lookup_at);
+        let (Some(avatar_url), Some(contact)) = (
+            avatar_url,
+            store.contacts.owned_mut(workspace_id, contact_id),
+        ) else {
+            return Ok(false);
+        };
+        if contact.avatar_url.is_some() || contact.email.as_deref() != Some(email) {
+            return Ok(false);
+        }
+        contact.avatar_url = Some(avatar_url.to_string());
+        contact.updated_at = Some(now());
+        Ok(true)
+    }
+
+    async fn list_captures(&self, workspace_id: i32) -> RepoResult<Vec<Capture>> {
+        let mut captures: Vec<Capture> = self
+            .store()
+            .captures
+            .rows
+            .values()
+            .filter(|(owner, _)| *owner == workspace_id)
+            .map(|(_, c)| c.clone())
+            .collect();
+        captures.sort_by_key(|c| (c.captured_at, c.capture_id));
+        Ok(captures)
+    }
+
+    async fn get_capture(&self, workspace_id: i32, capture_id: i32) -> RepoResult<Option<Capture>> {
+        Ok(self
+            .store()
+            .captures
+            .owned(workspace_id, capture_id)
+            .cloned())
+    }
+
+    async fn create_capture(&self, workspace_id: i32, text: &str) -> RepoResult<i32> {
+        let mut store = self.store();
+        let capture_id = store.captures.next_id();
+        store.captures.rows.insert(
+            capture_id,
+            (
+                workspace_id,
+                Capture {
+                    capture_id,
+                    text: text.to_string(),
+                    captured_at: OffsetDateTime::now_utc(),
+                },
+            ),
+        );
+        Ok(capture_id)
+    }
+
+    async fn resolve_capture(
+        &self,
+        workspace_id: i32,
+        capture_id: i32,
+        interaction: &NewInteractionRequest,
+    ) -> RepoResult<Option<i32>> {
+        let mut store = self.store();
+        if !store.captures.remove_owned(workspace_id, capture_id) {
+            return Ok(None);
+        }
+        Ok(Some(store.insert_interaction(workspace_id, interaction)))
+    }
+
+    async fn delete_capture(&self, workspace_id: i32, capture_id: i32) -> RepoResult<bool> {
+        Ok(self.store().captures.remove_owned(workspace_id, capture_id))
+    }
+
+    async fn owns_capture(&self, workspace_id: i32, capture_id: i32) -> RepoResult<bool> {
+        Ok(self
+            .store()
+            .captures
+            .owned(workspace_id, capture_id)
+            .is_some())
+    }
+
+    async fn list_interaction_types(
+        &self,
+        workspace_id: i32,
+    ) -> RepoResult<Vec<CustomInteractionType>> {
+        let mut types: Vec<CustomInteractionType> = self
+            .store()
+            .interaction_types
+            .rows
+            .values()
+            .filter(|(owner, _)| *owner == workspace_id)
+            .map(|(_, t)| t.clone())
+            .collect();
+        types.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(types)
+    }
+
+    async fn create_interaction_type(&self, workspace_id: i32, name: &str) -> RepoResult<i32> {
+        let mut store = self.store();
+        if store
+            .interaction_types
+            .rows
+            .values()
+            .any(|(owner, t)| *owner == workspace_id && t.name == name)
+        {
+            return Err(unique_violation("interaction_types_workspace_id_name_key"));
+        }
+        let type_id = store.interaction_types.next_id();
+        store.interaction_types.rows.insert(
+            type_id,
+            (
+                workspace_id,
+                CustomInteractionType {
+                    type_id,
+                    name: name.to_string(),
+                },
+            ),
+        );
+        Ok(type_id)
+    }
+
+    async fn delete_interaction_type(&self, workspace_id: i32, type_id: i32) -> RepoResult<bool> {
+        let mut store = self.store();
+        let Some(name) = store
+            .interaction_types
+            .owned(workspace_id, type_id)
+            .map(|t| t.name.clone())
+        else {
+            return Ok(false);
+        };
+        store.interaction_types.rows.remove(&type_id);
+        let mut retyped = Vec::new();
+        for (owner, interaction) in store.interactions.rows.values_mut() {
+            if *owner == workspace_id && interaction.interaction_type.as_ref() == Some(&name) {
+                interaction.interaction_type = None;
+                retyped.extend(interaction.contact_id);
+            }
+        }
+        for contact_id in retyped {
+            store.touch_contact(contact_id);
+        }
+        Ok(true)
+    }
+
+    async fn owns_interaction_type(&self, workspace_id: i32, type_id: i32) -> RepoResult<bool> {
+        Ok(self
+            .store()
+            .interaction_types
+            .owned(workspace_id, type_id)
+            .is_some())
+    }
+
+    async fn occasions_for_contacts(&self, contact_ids: &[i32]) -> RepoResult<Vec<Occasion>> {
+        Ok(self
+            .store()
+            .occasions
+            .rows
+            .values()
+            .filter(|(_, o)| o.contact_id.is_some_and(|c| contact_ids.contains(&c)))
+            .map(|(_, o)| o.clone())
+            .collect())
+    }
+
+    async fn create_occasion(
+        &self,
+        workspace_id: i32,
+        occasion: &NewOccasionRequest,
+    ) -> RepoResult<i32> {
+        Ok(self.store().insert_occasion(workspace_id, occasion, None))
+    }
+
+    async fn update_occasion(
+        &self,
+        workspace_id: i32,
+        occasion_id: i32,
+        occasion: &NewOccasionRequest,
+    ) -> RepoResult<bool> {
+        let mut store = self.store();
+        let Some(contact_id) = store
+            .occasions
+            .owned(workspace_id, occasion_id)
+            .map(|o| o.contact_id)
+        else {
+            return Ok(false);
+        };
+        // An unassigned occasion takes the request's contact
+        let contact_id = contact_id.unwrap_or(occasion.contact_id);
+        if store.contacts.owned(workspace_id, contact_id).is_none() {
+            return Ok(false);
+        }
+        let existing = store
+            .occasions
+            .owned_mut(workspace_id, occasion_id)
+            .unwrap();
+        existing.contact_id = Some(contact_id);
+        existing.name = occasion.name.clone();
+        existing.date = occasion.date;
+        existing.recurring = Some(occasion.recurring);
+        existing.recurring_interval = occasion.recurring_interval;
+        existing.details = occasion.details.clone();
+        existing.occasion_type = occasion.occasion_type;
+        existing.birth_year = occasion.birth_year;
+        existing.remind_days_before = occasion.remind_days_before.clone();
+        store.touch_contact(contact_id);
+        Ok(true)
+    }
+
+    async fn delete_occasion(&self, workspace_id: i32, occasion_id: i32) -> RepoResult<bool> {
+        let mut store = self.store();
+        let Some(contact_id) = store
+            .occasions
+            .owned(workspace_id, occasion_id)
+            .map(|o| o.contact_id)
+        else {
+            return Ok(false);
+        };
+        store.occasions.rows.remove(&occasion_id);
+        store.unlink_gift_ideas();
+        if let Some(contact_id) = contact_id {
+            store.touch_contact(contact_id);
+        }
+        Ok(true)
+    }
+
+    async fn get_occasion(
+        &self,
+        workspace_id: i32,
+        occasion_id: i32,
+    ) -> RepoResult<Option<Occasion>> {
+        Ok(self
+            .store()
+            .occasions
+            .owned(workspace_id, occasion_id)
+            .cloned())
+    }
+
+    async fn owns_occasion(&self, workspace_id: i32, occasion_id: i32) -> RepoResult<bool> {
+        Ok(self
+            .store()
+            .occasions
+            .owned(workspace_id, occasion_id)
+            .is_some())
+    }
+
+    async fn social_profiles_for_contacts(
+        &self,
+        contact_ids: &[i32],
+    ) -> RepoResult<Vec<SocialProfile>> {
+        Ok(self
+            .store()
+            .social_profiles
+            .rows
+            .values()
+            .filter(|(_, p)| contact_ids.contains(&p.contact_id))
+            .map(|(_, p)| p.clone())
+            .collect())
+    }
+
+    async fn create_social_profile(
+        &self,
+        workspace_id: i32,
+        profile: &NewSocialProfileRequest,
+    ) -> RepoResult<i32> {
+        let mut store = self.store();
+        Self::check_social_profile_url(&store, profile.contact_id, &profile.url, None)?;
+        let social_profile_id = store.social_profiles.next_id();
+        store.social_profiles.rows.insert(
+            social_profile_id,
+            (
+                workspace_id,
+                SocialProfile {
+                    social_profile_id,
+                    contact_id: profile.contact_id,
+                    platform: profile.platform,
+                    url: profile.url.clone(),
+                    preview: None,
+                },
+            ),
+        );
+        Ok(social_profile_id)
+    }
+
+    async fn update_social_profile(
+        &self,
+        workspace_id: i32,
+        social_profile_id: i32,
+        profile: &NewSocialProfileRequest,
+    ) -> RepoResult<bool> {
+        let mut store = self.store();
+        let Some(contact_id) = store
+            .social_profiles
+            .owned(workspace_id, social_profile_id)
+            .map(|p| p.contact_id)
+        else {
+            return Ok(false);
+        };
+        Self::check_social_profile_url(&store, contact_id, &profile.url, Some(social_profile_id))?;
+        let existing = store
+            .social_profiles
+            .owned_mut(workspace_id, social_profile_id)
+            .unwrap();
+        existing.platform = profile.platform;
+        existing.url = profile.url.clone();
+        Ok(true)
+    }
+
+    async fn delete_social_profile(
+        &self,
+        workspace_id: i32,
+        social_profile_id: i32,
+    ) -> RepoResult<bool> {
+        Ok(self
+            .store()
+            .social_profiles
+            .remove_owned(workspace_id, social_profile_id))
+    }
+
+    async fn get_social_profile(
+        &self,
+        workspace_id: i32,
+        social_profile_id: i32,
+    ) -> RepoResult<Option<SocialProfile>> {
+        Ok(self
+            .store()
+            .social_profiles
+            .owned(workspace_id, social_profile_id)
+            .cloned())
+    }
+
+    async fn owns_social_profile(
+        &self,
+        workspace_id: i32,
+        social_profile_id: i32,
+    ) -> RepoResult<bool> {
+        Ok(self
+            .store()
+            .social_profiles
+            .owned(workspace_id, social_profile_id)
+            .is_some())
+    }
+
+    async fn gift_ideas_for_contacts(&self, contact_ids: &[i32]) -> RepoResult<Vec<GiftIdea>> {
+        Ok(self
+            .store()
+            .gift_ideas
+            .rows
+            .values()
+            .filter(|(_, g)| contact_ids.contains(&g.contact_id))
+            .map(|(_, g)| g.clone())
+            .collect())
+    }
+
+    async fn create_gift_idea(
+        &self,
+        workspace_id: i32,
+        gift_idea: &NewGiftIdeaRequest,
+    ) -> RepoResult<i32> {
+        let mut store = self.store();
+        let gift_idea_id = store.gift_ideas.next_id();
+        store.gift_ideas.rows.insert(
+            gift_idea_id,
+            (
+                workspace_id,
+                GiftIdea {
+                    gift_idea_id,
+                    contact_id: gift_idea.contact_id,
+                    occasion_id: gift_idea.occasion_id,
+                    idea: gift_idea.idea.clone(),
+                    status: gift_idea.status,
+                    price_cents: gift_idea.price_cents,
+                },
+            ),
+        );
+        Ok(gift_idea_id)
+    }
+
+    async fn update_gift_idea(
+        &self,
+        workspace_id: i32,
+        gift_idea_id: i32,
+        gift_idea: &NewGiftIdeaRequest,
+    ) -> RepoResult<bool> {
+        let mut store = self.store();
+        let Some(existing) = store.gift_ideas.owned_mut(workspace_id, gift_idea_id) else {
+            return Ok(false);
+        };
+        existing.occasion_id = gift_idea.occasion_id;
+        existing.idea = gift_idea.idea.clone();
+        existing.status = gift_idea.status;
+        existing.price_cents = gift_idea.price_cents;
+        Ok(true)
+    }
+
+    async fn delete_gift_idea(&self, workspace_id: i32, gift_idea_id: i32) -> RepoResult<bool> {
+        Ok(self
+            .store()
+            .gift_ideas
+            .remove_owned(workspace_id, gift_idea_id))
+    }
+
+    async fn get_gift_idea(
+        &self,
+        workspace_id: i32,
+        gift_idea_id: i32,
+    ) -> RepoResult<Option<GiftIdea>> {
+        Ok(self
+            .store()
+            .gift_ideas
+            .owned(workspace_id, gift_idea_id)
+            .cloned())
+    }
+
+    async fn owns_gift_idea(&self, workspace_id: i32, gift_idea_id: i32) -> RepoResult<bool> {
+        Ok(self
+            .store()
+            .gift_ideas
+            .owned(workspace_id, gift_idea_id)
+            .is_some())
+    }
+
+    async fn list_groups(&self, workspace_id: i32) -> RepoResult<Vec<Group>> {
+        let mut groups: Vec<Group> = self
+            .store()
+            .groups
+            .rows
+            .values()
+            .filter(|(owner, _)| *owner == workspace_id)
+            .map(|(_, g)| g.clone())
+            .collect();
+        groups.sort_by_key(|g| (g.name.to_lowercase(), g.group_id));
+        Ok(groups)
+    }
+
+    async fn get_group(&self, workspace_id: i32, group_id: i32) -> RepoResult<Option<Group>> {
+        Ok(self.store().groups.owned(workspace_id, group_id).cloned())
+    }
+
+    async fn create_group(&self, workspace_id: i32, group: &NewGroupRequest) -> RepoResult<i32> {
+        let mut store = self.store();
+        let group_id = store.groups.next_id();
+        store.groups.rows.insert(
+            group_id,
+            (
+                workspace_id,
+                Group {
+                    group_id,
+                    name: group.name.clone(),
+                    description: group.description.clone(),
+                    contact_ids: Vec::new(),
+                },
+            ),
+        );
+        Ok(group_id)
+    }
+
+    async fn update_group(
+        &self,
+        workspace_id: i32,
+        group_id: i32,
+        group: &NewGroupRequest,
+    ) -> RepoResult<bool> {
+        let mut store = self.store();
+        let Some(existing) = store.groups.owned_mut(workspace_id, group_id) else {
+            return Ok(false);
+        };
+        existing.name = group.name.clone();
+        existing.description = group.description.clone();
+        Ok(true)
+    }
+
+    async fn delete_group(&self, workspace_id: i32, group_id: i32) -> RepoResult<bool> {
+        Ok(self.store().groups.remove_owned(workspace_id, group_id))
+    }
+
+    async fn owns_group(&self, workspace_id: i32, group_id: i32) -> RepoResult<bool> {
+        Ok(self.store().groups.owned(workspace_id, group_id).is_some())
+    }
+
+    async fn set_group_members(
+        &self,
+        workspace_id: i32,
+        group_id: i32,
+        contact_ids: &[i32],
+    ) -> RepoResult<bool> {
+        let mut store = self.store();
+        if !contact_ids
+            .iter()
+            .all(|id| store.contacts.owned(workspace_id, *id).is_some())
+        {
+            return Ok(false);
+        }
+        let Some(group) = store.groups.owned_mut(workspace_id, group_id) else {
+            return Ok(false);
+        };
+        group.contact_ids = contact_ids.to_vec();
+        Ok(true)
+    }
+
+    async fn add_group_member(&self, group_id: i32, contact_id: i32) -> RepoResult<bool> {
+        let mut store = self.store();
+        let Some((_, group)) = store.groups.rows.get_mut(&group_id) else {
+            return Ok(false);
+        };
+        if group.contact_ids.contains(&contact_id) {
+            return Ok(false);
+        }
+        group.contact_ids.push(contact_id);
+        Ok(true)
+    }
+
+    async fn remove_group_member(&self, group_id: i32, contact_id: i32) -> RepoResult<bool> {
+        let mut store = self.store();
+        let Some((_, group)) = store.groups.rows.get_mut(&group_id) else {
+            return Ok(false);
+        };
+        let before = group.contact_ids.len();
+        group.contact_ids.retain(|c| *c != contact_id);
+        Ok(group.contact_ids.len() < before)
+    }
+
+    async fn list_shares(&self, user_id: i32) -> RepoResult<Vec<Share>> {
+        let store = self.store();
+        Ok(store
+            .shares
+            .rows
+            .iter()
+            .filter(|(_, (owner, s))| *owner == user_id || s.grantee_id == user_id)
+            .map(|(share_id, (owner_id, s))| Share {
+                share_id: *share_id,
+                owner_id: *owner_id,
+                owner_email: store.email(*owner_id),
+                grantee_id: s.grantee_id,
+                grantee_email: store.email(s.grantee_id),
+                tag_id: s.tag_id,
+                contact_id: s.contact_id,
+                permission: s.permission,
+            })
+            .collect())
+    }
+
+    async fn create_shares(
+        &self,
+        owner_id: i32,
+        grantee_id: i32,
+        tag_id: Option<i32>,
+        contact_ids: &[i32],
+        permission: SharePermission,
+    ) -> RepoResult<Vec<i32>> {
+        let mut store = self.store();
+        let targets: BTreeSet<(Option<i32>, Option<i32>)> = match tag_id {
+            Some(tag_id) => BTreeSet::from([(Some(tag_id), None)]),
+            None => contact_ids.iter().map(|id| (None, Some(*id))).collect(),
+        };
+        let mut share_ids = Vec::new();
+        for (tag_id, contact_id) in targets {
+            let existing = store.shares.rows.iter_mut().find(|(_, (owner, s))| {
+                *owner == owner_id
+                    && s.grantee_id == grantee_id
+                    && s.tag_id == tag_id
+                    && s.contact_id == contact_id
+            });
+            if let Some((share_id, (_, share))) = existing {
+                share.permission = permission;
+                share_ids.push(*share_id);
+                continue;
+            }
+            let share_id = store.shares.next_id();
+            store.shares.rows.insert(
+                share_id,
+                (
+                    owner_id,
+                    ShareRow {
+                        grantee_id,
+                        tag_id,
+                        contact_id,
+                        permission,
+                    },
+                ),
+            );
+            share_ids.push(share_id);
+        }
+        Ok(share_ids)
+    }
+
+    async fn delete_share(&self, user_id: i32, share_id: i32) -> RepoResult<bool> {
+        let mut store = self.store();
+        let party = store
+            .shares
+            .rows
+            .get(&share_id)
+            .is_some_and(|(owner, s)| *owner == user_id || s.grantee_id == user_id);
+        if party {
+            store.shares.rows.remove(&share_id);
+        }
+        Ok(party)
+    }
+
+    async fn owns_share(&self, user_id: i32, share_id: i32) -> RepoResult<bool> {
+        Ok(self
+            .store()
+            .shares
+            .rows
+            .get(&share_id)
+            .is_some_and(|(owner, s)| *owner == user_id || s.grantee_id == user_id))
+    }
+
+    async fn contact_access(
+        &self,
+        user_id: i32,
+        workspace_id: i32,
+        contact_id: i32,
+    ) -> RepoResult<Option<ContactAccess>> {
+        let store = self.store();
+        let Some((contact_workspace, _)) = store.contacts.rows.get(&contact_id) else {
+            return Ok(None);
+        };
+        let Some(owner_id) = store.workspace_owner(*contact_workspace) else {
+            return Ok(None);
+        };
+        if *contact_workspace == workspace_id {
+            return Ok(Some(ContactAccess {
+                owner_id,
+                workspace_id,
+                shared: None,
+            }));
+        }
+        if owner_id == user_id {
+            return Ok(None);
+        }
+        Ok(store
+            .share_permission(user_id, owner_id, contact_id)
+            .map(|permission| ContactAccess {
+                owner_id,
+                workspace_id: *contact_workspace,
+                shared: Some(permission),
+            }))
+    }
+
+    async fn shared_contacts(&self, user_id: i32) -> RepoResult<Vec<ContactShare>> {
+        let store = self.store();
+        let mut shared: Vec<ContactShare> = store
+            .contacts
+            .rows
+            .iter()
+            .filter_map(|(contact_id, (workspace_id, _))| {
+                let owner_id = store.workspace_owner(*workspace_id)?;
+                if owner_id == user_id {
+                    return None;
+                }
+                let permission = store.share_permission(user_id, owner_id, *contact_id)?;
+                let (_, owner) = store.users.rows.get(&owner_id)?;
+                Some(ContactShare {
+                    contact_id: *contact_id,
+                    owner_id,
+                    workspace_id: *workspace_id,
+                    owner_name: owner.name.clone().unwrap_or_default(),
+                    owner_email: owner.email.clone().unwrap_or_default(),
+                    permission,
+                })
+            })
+            .collect();
+        shared.sort_by_key(|s| (s.owner_id, s.workspace_id, s.contact_id));
+        Ok(shared)
+    }
+
+    async fn find_user_by_email(&self, email: &str) -> RepoResult<Option<i32>> {
+        let email = email.to_lowercase();
+        Ok(self
+            .store()
+            .users
+            .rows
+            .iter()
+            .find(|(_, (_, u))| u.email.as_ref().is_some_and(|e| e.to_lowercase() == email))
+            .map(|(user_id, _)| *user_id))
+    }
+
+    async fn list_accounts(&self) -> RepoResult<Vec<AccountSummary>> {
+        let store = self.store();
+        Ok(store
+            .users
+            .rows
+            .iter()
+            .map(|(user_id, (_, user))| {
+                let workspace_ids: BTreeSet<i32> = store
+                    .workspaces
+                    .rows
+                    .iter()
+                    .filter(|(_, (owner, _))| owner == user_id)
+                    .map(|(id, _)| *id)
+                    .collect();
+                AccountSummary {
+                    user_id: *user_id,
+                    auth0_id: user.auth0_id.clone(),
+                    name: user.name.clone().unwrap_or_default(),
+                    email: user.email.clone().unwrap_or_default(),
+                    is_admin: user.is_admin,
+                    deactivated: user.deactivated,
+                    workspace_count: workspace_ids.len() as i64,
+                    contact_count: store
+                        .contacts
+                        .rows
+                        .values()
+                        .filter(|(owner, _)| workspace_ids.contains(owner))
+                        .count() as i64,
+                }
+            })
+            .collect())
+    }
+
+    async fn usage(&self, user_id: i32) -> RepoResult<Usage> {
+        let store = self.store();
+        let workspace_ids: BTreeSet<i32> = store
+            .workspaces
+            .rows
+            .iter()
+            .filter(|(_, (owner, _))| *owner == user_id)
+            .map(|(id, _)| *id)
+            .collect();
+        let owned = |owner: &i32| workspace_ids.contains(owner);
+        Ok(Usage {
+            workspaces: workspace_ids.len() as i64,
+            contacts: store
+                .contacts
+                .rows
+                .values()
+                .filter(|(o, _)| owned(o))
+                .count() as i64,
+            interactions: store
+                .interactions
+                .rows
+                .values()
+                .filter(|(o, _)| owned(o))
+                .count() as i64,
+            tags: store.tags.rows.values().filter(|(o, _)| owned(o)).count() as i64,
+            attachment_bytes: store
+                .attachments
+                .rows
+                .values()
+                .filter(|(o, _)| owned(o))
+                .map(|(_, row)| i64::from(row.attachment.size_bytes))
+                .sum(),
+        })
+    }
+
+    async fn instance_stats(&self) -> RepoResult<InstanceStats> {
+        let store = self.store();
+        Ok(InstanceStats {
+            users: store.users.rows.len() as i64,
+            deactivated_users: store
+                .users
+                .rows
+                .values()
+                .filter(|(_, u)| u.deactivated)
+                .count() as i64,
+            workspaces: store.workspaces.rows.len() as i64,
+            contacts: store.contacts.rows.len() as i64,
+            interactions: store.interactions.rows.len() as i64,
+            shares: store.shares.rows.len() as i64,
+        })
+    }
+
+    async fn set_deactivated(&self, user_id: i32, deactivated: bool) -> RepoResult<Option<String>> {
+        Ok(self.store().users.rows.get_mut(&user_id).map(|(_, user)| {
+            user.deactivated = deactivated;
+            if !deactivated {
+                user.delete_after = None;
+            }
+            user.auth0_id.clone()
+        }))
+    }
+
+    async fn weekly_review_recipients(&self) -> RepoResult<Vec<ReviewRecipient>> {
+        let store = self.store();
+        Ok(store
+            .users
+            .rows
+            .iter()
+            .filter(|(user_id, (_, user))| {
+                !user.deactivated
+                    && store
+                        .settings
+                        .get(user_id)
+                        .is_some_and(|(n, _)| n.weekly_review && n.email)
+            })
+            .filter_map(|(user_id, (_, user))| {
+                let (workspace_id, _) = store
+                    .workspaces
+                    .rows
+                    .iter()
+                    .find(|(_, (owner, w))| owner == user_id && w.is_default)?;
+                Some(ReviewRecipient {
+                    user_id: *user_id,
+                    workspace_id: *workspace_id,
+                    name: user.name.clone().unwrap_or_default(),
+                    email: user.email.clone().unwrap_or_default(),
+                    sent_week: store.weekly_reviews_sent.get(user_id).copied(),
+                })
+            })
+            .collect())
+    }
+
+    async fn record_weekly_review(&self, user_id: i32, week: Date) -> RepoResult<()> {
+        self.store().weekly_reviews_sent.insert(user_id, week);
+        Ok(())
+    }
+
+    async fn queue_weekly_review(
+        &self,
+        user_id: i32,
+        workspace_id: i32,
+        week: Date,
+        email: &Email,
+    ) -> RepoResult<()> {
+        let mut store = self.store();
+        store.queue(workspace_id, None, OutboxPayload::Email(email.clone()));
+        store.weekly_reviews_sent.insert(user_id, week);
+        Ok(())
+    }
+
+    async fn queue_email(&self, workspace_id: i32, email: &Email) -> RepoResult<()> {
+        self.store()
+            .queue(workspace_id, None, OutboxPayload::Email(email.clone()));
+        Ok(())
+    }
+
+    async fn push_reminder_recipients(&self) -> RepoResult<Vec<PushRecipient>> {
+        let store = self.store();
+        Ok(store
+            .users
+            .rows
+            .iter()
+            .filter(|(user_id, (_, user))| {
+                !user.deactivated
+                    && store.settings.get(user_id).is_some_and(|(n, _)| n.push)
+                    && store
+                        .push_subscriptions
+                        .rows
+                        .values()
+                        .any(|(owner, _)| owner == *user_id)
+            })
+            .filter_map(|(user_id, _)| {
+                let (workspace_id, _) = store
+                    .workspaces
+                    .rows
+                    .iter()
+                    .find(|(_, (owner, w))| owner == user_id && w.is_default)?;
+                Some(PushRecipient {
+                    user_id: *user_id,
+                    workspace_id: *workspace_id,
+                    sent_on: store.push_reminders_sent.get(user_id).copied(),
+                })
+            })
+            .collect())
+    }
+
+    async fn queue_push_reminders(
+        &self,
+        user_id: i32,
+        workspace_id: i32,
+        day: Date,
+        messages: &[PushMessage],
+    ) -> RepoResult<()> {
+        let mut store = self.store();
+        let subscription_ids: Vec<i32> = store
+            .push_subscriptions
+            .rows
+            .iter()
+            .filter(|(_, (owner, _))| *owner == user_id)
+            .map(|(id, _)| *id)
+            .collect();
+        for message in messages {
+            for subscription_id in &subscription_ids {
+                store.queue_for(
+                    workspace_id,
+                    None,
+                    Some(*subscription_id),
+                    OutboxPayload::Push(message.clone()),
+                );
+            }
+        }
+        store.push_reminders_sent.insert(user_id, day);
+        Ok(())
+    }
+
+    async fn list_push_subscriptions(&self, user_id: i32) -> RepoResult<Vec<PushSubscription>> {
+        Ok(self
+            .store()
+            .push_subscriptions
+            .rows
+            .values()
+            .filter(|(owner, _)| *owner == user_id)
+            .map(|(_, s)| s.clone())
+            .collect())
+    }
+
+    async fn save_push_subscription(
+        &self,
+        user_id: i32,
+        subscription: &NewPushSubscriptionRequest,
+    ) -> RepoResult<PushSubscription> {
+        let mut store = self.store();
+        if let Some((_, saved)) = store
+            .push_subscriptions
+            .rows
+            .values_mut()
+            .find(|(owner, s)| *owner == user_id && s.endpoint == subscription.endpoint)
+        {
+            saved.p256dh = subscription.keys.p256dh.clone();
+            saved.auth = subscription.keys.auth.clone();
+            saved.device_name = subscription.device_name.clone();
+            return Ok(saved.clone());
+        }
+        let subscription_id = store.push_subscriptions.next_id();
+        let saved = PushSubscription {
+            subscription_id,
+            user_id,
+            endpoint: subscription.endpoint.clone(),
+            p256dh: subscription.keys.p256dh.clone(),
+            auth: subscription.keys.auth.clone(),
+            device_name: subscription.device_name.clone(),
+            created_at: OffsetDateTime::now_utc(),
+        };
+        store
+            .push_subscriptions
+            .rows
+            .insert(subscription_id, (user_id, saved.clone()));
+        Ok(saved)
+    }
+
+    async fn delete_push_subscription(
+        &self,
+        user_id: i32,
+        subscription_id: i32,
+    ) -> RepoResult<bool> {
+        let mut store = self.store();
+        if !store
+            .push_subscriptions
+            .remove_owned(user_id, subscription_id)
+        {
+            return Ok(false);
+        }
+        store
+            .outbox
+            .rows
+            .retain(|_, (_, row)| row.push_subscription_id != Some(subscription_id));
+        Ok(true)
+    }
+
+    async fn owns_push_subscription(&self, user_id: i32, subscription_id: i32) -> RepoResult<bool> {
+        Ok(self
+            .store()
+            .push_subscriptions
+            .owned(user_id, subscription_id)
+            .is_some())
+    }
+
+    async fn claim_job(
+        &self,
+        name: &str,
+        holder: &str,
+        now: OffsetDateTime,
+        until: OffsetDateTime,
+    ) -> RepoResult<bool> {
+        let mut store = self.store();
+        if let Some((current, locked_until)) = store.job_leases.get(name)
+            && current != holder
+            && *locked_until > now
+        {
+            return Ok(false);
+        }
+        store
+            .job_leases
+            .insert(name.to_string(), (holder.to_string(), until));
+        Ok(true)
+    }
+
+    async fn list_filters(&self, workspace_id: i32) -> RepoResult<Vec<SavedFilter>> {
+        let mut filters: Vec<SavedFilter> = self
+            .store()
+            .filters
+            .rows
+            .values()
+            .filter(|(owner, _)| *owner == workspace_id)
+            .map(|(_, f)| f.clone())
+            .collect();
+        filters.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(filters)
+    }
+
+    async fn get_filter(
+        &self,
+        workspace_id: i32,
+        filter_id: i32,
+    ) -> RepoResult<Option<SavedFilter>> {
+        Ok(self.store().filters.owned(workspace_id, filter_id).cloned())
+    }
+
+    async fn create_filter(
+        &self,
+        workspace_id: i32,
+        filter: &NewSavedFilterRequest,
+    ) -> RepoResult<i32> {
+        let mut store = self.store();
+        let filter_id = store.filters.next_id();
+        store.filters.rows.insert(
+            filter_id,
+            (
+                workspace_id,
+                SavedFilter {
+                    filter_id,
+                    name: filter.name.clone(),
+                    query: filter.query.clone(),
+                },
+            ),
+        );
+        Ok(filter_id)
+    }
+
+    async fn update_filter(
+        &self,
+        workspace_id: i32,
+        filter_id: i32,
+        filter: &NewSavedFilterRequest,
+    ) -> RepoResult<bool> {
+        let mut store = self.store();
+        let Some(existing) = store.filters.owned_mut(workspace_id, filter_id) else {
+            return Ok(false);
+        };
+        existing.name = filter.name.clone();
+        existing.query = filter.query.clone();
+        Ok(true)
+    }
+
+    async fn delete_filter(&self, workspace_id: i32, filter_id: i32) -> RepoResult<bool> {
+        Ok(self.store().filters.remove_owned(workspace_id, filter_id))
+    }
+
+    async fn owns_filter(&self, workspace_id: i32, filter_id: i32) -> RepoResult<bool> {
+        Ok(self
+            .store()
+            .filters
+            .owned(workspace_id, filter_id)
+            .is_some())
+    }
+
+    async fn list_goals(&self, workspace_id: i32) -> RepoResult<Vec<Goal>> {
+        let mut goals: Vec<Goal> = self
+            .store()
+            .goals
+            .rows
+            .values()
+            .filter(|(owner, _)| *owner == workspace_id)
+            .map(|(_, g)| g.clone())
+            .collect();
+        goals.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(goals)
+    }
+
+    async fn create_goal(&self, workspace_id: i32, goal: &NewGoalRequest) -> RepoResult<i32> {
+        let mut store = self.store();
+        let goal_id = store.goals.next_id();
+        store.goals.rows.insert(
+            goal_id,
+            (
+                workspace_id,
+                Goal {
+                    goal_id,
+                    name: goal.name.clone(),
+                    contact_id: goal.contact_id,
+                    interaction_type: goal.interaction_type.clone(),
+                    target_count: goal.target_count,
+                    period: goal.period,
+                },
+            ),
+        );
+        Ok(goal_id)
+    }
+
+    async fn update_goal(
+        &self,
+        workspace_id: i32,
+        goal_id: i32,
+        goal: &NewGoalRequest,
+    ) -> RepoResult<bool> {
+        let mut store = self.store();
+        let Some(existing) = store.goals.owned_mut(workspace_id, goal_id) else {
+            return Ok(false);
+        };
+        existing.name = goal.name.clone();
+        existing.contact_id = goal.contact_id;
+        existing.interaction_type = goal.interaction_type.clone();
+        existing.target_count = goal.target_count;
+        existing.period = goal.period;
+        Ok(true)
+    }
+
+    async fn delete_goal(&self, workspace_id: i32, goal_id: i32) -> RepoResult<bool> {
+        Ok(self.store().goals.remove_owned(workspace_id, goal_id))
+    }
+
+    async fn get_goal(&self, workspace_id: i32, goal_id: i32) -> RepoResult<Option<Goal>> {
+        Ok(self.store().goals.owned(workspace_id, goal_id).cloned())
+    }
+
+    async fn owns_goal(&self, workspace_id: i32, goal_id: i32) -> RepoResult<bool> {
+        Ok(self.store().goals.owned(workspace_id, goal_id).is_some())
+    }
+
+    async fn reconnect_picks(
+        &self,
+        workspace_id: i32,
+        since: Date,
+    ) -> RepoResult<Vec<(Date, i32)>> {
+        Ok(self
+            .store()
+            .reconnect_picks
+            .iter()
+            .filter(|(owner, week, _)| *owner == workspace_id && *week >= since)
+            .map(|(_, week, contact_id)| (*week, *contact_id))
+            .collect())
+    }
+
+    async fn save_reconnect_picks(
+        &self,
+        workspace_id: i32,
+        week: Date,
+        contact_ids: &[i32],
+    ) -> RepoResult<()> {
+        let mut store = self.store();
+        if store
+            .reconnect_picks
+            .iter()
+            .any(|(owner, w, _)| *owner == workspace_id && *w == week)
+        {
+            return Ok(());
+        }
+        for &contact_id in contact_ids {
+            store
+                .reconnect_picks
+                .insert((workspace_id, week, contact_id));
+        }
+        Ok(())
+    }
+
+    async fn snooze_suggestion(
+        &self,
+        user_id: i32,
+        contact_id: i32,
+        until: Option<Date>,
+    ) -> RepoResult<()> {
+        self.store()
+            .suggestion_snoozes
+            .insert((user_id, contact_id), until);
+        Ok(())
+    }
+
+    async fn unsnooze_suggestion(&self, user_id: i32, contact_id: i32) -> RepoResult<bool> {
+        Ok(self
+            .store()
+            .suggestion_snoozes
+            .remove(&(user_id, contact_id))
+            .is_some())
+    }
+
+    async fn snoozed_contacts(&self, user_id: i32, today: Date) -> RepoResult<Vec<i32>> {
+        Ok(self
+            .store()
+            .suggestion_snoozes
+            .iter()
+            .filter(|((u, _), until)| *u == user_id && until.is_none_or(|d| d > today))
+            .map(|((_, contact_id), _)| *contact_id)
+            .collect())
+    }
+
+    async fn get_preferences(&self, user_id: i32) -> RepoResult<Preferences> {
+        let store = self.store();
+        let mut preferences = store.preferences.get(&user_id).cloned().unwrap_or_default();
+        preferences.greeting_tag_ids.retain(|tag_id| {
+            store
+                .tags
+                .rows
+                .get(tag_id)
+                .is_some_and(|(workspace_id, _)| {
+                    store.workspace_owner(*workspace_id) == Some(user_id)
+                })
+        });
+        Ok(preferences)
+    }
+
+    async fn save_preferences(&self, user_id: i32, preferences: &Preferences) -> RepoResult<()> {
+        self.store()
+            .preferences
+            .insert(user_id, preferences.clone());
+        Ok(())
+    }
+
+    async fn export_schedule(&self, workspace_id: i32) -> RepoResult<Option<ExportSchedule>> {
+        Ok(self.store().export_schedules.get(&workspace_id).cloned())
+    }
+
+    async fn save_export_schedule(&self, schedule: &ExportSchedule) -> RepoResult<()> {
+        let mut store = self.store();
+        let previous = store.export_schedules.remove(&schedule.workspace_id);
+        let mut schedule = schedule.clone();
+        if let Some(previous) = previous {
+            schedule.last_pushed_at = previous.last_pushed_at;
+            schedule.last_status = previous.last_status;
+        }
+        store
+            .export_schedules
+            .insert(schedule.workspace_id, schedule);
+        Ok(())
+    }
+
+    async fn delete_export_schedule(&self, workspace_id: i32) -> RepoResult<bool> {
+        Ok(self
+            .store()
+            .export_schedules
+            .remove(&workspace_id)
+            .is_some())
+    }
+
+    async fn due_export_schedules(&self, now: OffsetDateTime) -> RepoResult<Vec<ExportSchedule>> {
+        let store = self.store();
+        Ok(store
+            .export_schedules
+            .values()
+            .filter(|s| {
+                store
+                    .users
+                    .rows
+                    .get(&s.user_id)
+                    .is_some_and(|(_, u)| !u.deactivated)
+            })
+            .filter(|s| match s.last_pushed_at {
+                None => true,
+                Some(at) => at + time::Duration::hours(s.interval_hours as i64) <= now,
+            })
+            .cloned()
+            .collect())
+    }
+
+    async fn record_export_push(
+        &self,
+        workspace_id: i32,
+        pushed_at: OffsetDateTime,
+        status: &str,
+    ) -> RepoResult<()> {
+        if let Some(schedule) = self.store().export_schedules.get_mut(&workspace_id) {
+            schedule.last_pushed_at = Some(pushed_at);
+            schedule.last_status = Some(status.to_string());
+        }
+        Ok(())
+    }
+
+    async fn inbound_email_token(&self, workspace_id: i32) -> RepoResult<Option<String>> {
+        Ok(self
+            .store()
+            .inbound_email_tokens
+            .get(&workspace_id)
+            .cloned())
+    }
+
+    async fn save_inbound_email_token(&self, workspace_id: i32, token: &str) -> RepoResult<()> {
+        let mut store = self.store();
+        let taken = store
+            .inbound_email_tokens
+            .iter()
+            .any(|(id, t)| *id != workspace_id && t == token);
+        if taken {
+            return Err(unique_violation("inbound_email_addresses_token_key"));
+        }
+        store
+            .inbound_email_tokens
+            .insert(workspace_id, token.to_string());
+        Ok(())
+    }
+
+    async fn delete_inbound_email_token(&self, workspace_id: i32) -> RepoResult<bool> {
+        Ok(self
+            .store()
+            .inbound_email_tokens
+            .remove(&workspace_id)
+            .is_some())
+    }
+
+    async fn inbound_email_workspace(&self, token: &str) -> RepoResult<Option<(i32, i32)>> {
+        let store = self.store();
+        Ok(store
+            .inbound_email_tokens
+            .iter()
+            .find(|(_, t)| *t == token)
+            .and_then(|(workspace_id, _)| {
+                let user_id = store.workspace_owner(*workspace_id)?;
+                let (_, user) = store.users.rows.get(&user_id)?;
+                (!user.deactivated).then_some((user_id, *workspace_id))
+            }))
+    }
+
+    async fn contacts_by_email(
+        &self,
+        workspace_id: i32,
+        emails: &[String],
+    ) -> RepoResult<Vec<i32>> {
+        let store = self.store();
+        Ok(emails
+            .iter()
+            .flat_map(|email| {
+                store
+                    .contacts
+                    .rows
+                    .iter()
+                    .filter(move |(_, (owner, c))| {
+                        *owner == workspace_id
+                            && c.email.as_ref().is_some_and(|e| e.to_lowercase() == *email)
+                    })
+                    .map(|(id, _)| *id)
+            })
+            .collect())
+    }
+
+    async fn list_rest_hooks(&self, workspace_id: i32) -> RepoResult<Vec<RestHook>> {
+        Ok(self
+            .store()
+            .rest_hooks
+            .rows
+            .values()
+            .filter(|(owner, _)| *owner == workspace_id)
+            .map(|(_, h)| h.clone())
+            .collect())
+    }
+
+    async fn create_rest_hook(
+        &self,
+        workspace_id: i32,
+        hook: &NewRestHookRequest,
+    ) -> RepoResult<i32> {
+        let mut store = self.store();
+        let user_id = store.workspace_owner(workspace_id).unwrap_or_default();
+        let hook_id = store.rest_hooks.next_id();
+        store.rest_hooks.rows.insert(
+            hook_id,
+            (
+                workspace_id,
+                RestHook {
+                    hook_id,
+                    user_id,
+                    workspace_id,
+                    event: hook.event,
+                    target_url: hook.target_url.clone(),
+                    delivered_on: None,
+                },
+            ),
+        );
+        Ok(hook_id)
+    }
+
+    async fn delete_rest_hook(&self, workspace_id: i32, hook_id: i32) -> RepoResult<bool> {
+        let mut store = self.store();
+        if !store.rest_hooks.remove_owned(workspace_id, hook_id) {
+            return Ok(false);
+        }
+        store
+            .outbox
+            .rows
+            .retain(|_, (_, row)| row.hook_id != Some(hook_id));
+        Ok(true)
+    }
+
+    async fn owns_rest_hook(&self, workspace_id: i32, hook_id: i32) -> RepoResult<bool> {
+        Ok(self
+            .store()
+            .rest_hooks
+            .owned(workspace_id, hook_id)
+            .is_some())
+    }
+
+    async fn rest_hooks_for_event(
+        &self,
+        event: HookEvent,
+        user_id: Option<i32>,
+    ) -> RepoResult<Vec<RestHook>> {
+        let store = self.store();
+        Ok(store
+            .rest_hooks
+            .rows
+            .values()
+            .map(|(_, h)| h)
+            .filter(|h| h.event == event && user_id.is_none_or(|id| id == h.user_id))
+            .filter(|h| {
+                store
+                    .users
+                    .rows
+                    .get(&h.user_id)
+                    .is_some_and(|(_, u)| !u.deactivated)
+            })
+            .cloned()
+            .collect())
+    }
+
+    async fn record_rest_hook_delivery(&self, hook_id: i32, delivered_on: Date) -> RepoResult<()> {
+        if let Some((_, hook)) = self.store().rest_hooks.rows.get_mut(&hook_id) {
+            hook.delivered_on = Some(delivered_on);
+        }
+        Ok(())
+    }
+
+    async fn queue_upcoming_occasions(
+        &self,
+        workspace_id: i32,
+        hook_id: i32,
+        items: &serde_json::Value,
+        delivered_on: Date,
+    ) -> RepoResult<()> {
+        let mut store = self.store();
+        let Some(hook) = store.rest_hooks.owned_mut(workspace_id, hook_id) else {
+            return Ok(());
+        };
+        hook.delivered_on = Some(delivered_on);
+        store.queue(
+            workspace_id,
+            Some(hook_id),
+            OutboxPayload::Items(items.clone()),
+        );
+        Ok(())
+    }
+
+    async fn hook_deliveries(
+        &self,
+        workspace_id: i32,
+        hook_id: i32,
+        limit: i64,
+    ) -> RepoResult<Vec<HookDelivery>> {
+        Ok(self
+            .store()
+            .outbox
+            .rows
+            .iter()
+            .rev()
+            .filter(|(_, (owner, row))| *owner == workspace_id && row.hook_id == Some(hook_id))
+            .take(limit as usize)
+            .map(|(delivery_id, (_, row))| HookDelivery {
+                delivery_id: *delivery_id,
+                status: row.status,
+                attempts: row.attempts,
+                last_response_status: row.last_response_status,
+                last_error: row.last_error.clone(),
+                created_at: row.created_at,
+                next_attempt_at: row.next_attempt_at,
+                finished_at: row.finished_at,
+            })
+            .collect())
+    }
+
+    async fn claim_deliveries(
+        &self,
+        now: OffsetDateTime,
+        until: OffsetDateTime,
+        limit: i64,
+    ) -> RepoResult<Vec<OutboxMessage>> {
+        let mut store = self.store();
+        let mut due: Vec<(OffsetDateTime, i32)> = store
+            .outbox
+            .rows
+            .iter()
+            .filter(|(_, (_, row))| {
+                row.status == DeliveryStatus::Pending && row.next_attempt_at <= now
+            })
+            .map(|(id, (_, row))| (row.next_attempt_at, *id))
+            .collect();
+        due.sort();
+        due.truncate(limit as usize);
+        let mut claimed: Vec<i32> = due.into_iter().map(|(_, id)| id).collect();
+        claimed.sort_unstable();
+
+        let mut messages = Vec::with_capacity(claimed.len());
+        for delivery_id in claimed {
+            let (workspace_id, row) = store.outbox.rows.get_mut(&delivery_id).unwrap();
+            row.next_attempt_at = until;
+            let (workspace_id, hook_id) = (*workspace_id, row.hook_id);
+            let push_subscription_id = row.push_subscription_id;
+            let (payload, attempts) = (row.payload.clone(), row.attempts);
+            let hook = hook_id
+                .and_then(|id| store.rest_hooks.rows.get(&id))
+                .map(|(_, h)| (h.hook_id, h.event, h.target_url.clone()));
+            let push = push_subscription_id
+                .and_then(|id| store.push_subscriptions.rows.get(&id))
+                .map(|(_, s)| s.clone());
+            messages.push(OutboxMessage {
+                delivery_id,
+                workspace_id,
+                hook,
+                push,
+                payload,
+                attempts,
+            });
+        }
+        Ok(messages)
+    }
+
+    async fn record_delivery_attempt(
+        &self,
+        delivery_id: i32,
+        attempt: &DeliveryAttempt,
+    ) -> RepoResult<()> {
+        if let Some((_, row)) = self.store().outbox.rows.get_mut(&delivery_id) {
+            row.status = attempt.status;
+            row.attempts += 1;
+            row.last_response_status = attempt.response_status;
+            row.last_error = attempt.error.clone();
+            if let Some(retry_at) = attempt.retry_at {
+                row.next_attempt_at = retry_at;
+            }
+            row.finished_at = (attempt.status != DeliveryStatus::Pending).then_some(attempt.at);
+        }
+        Ok(())
+    }
+
+    async fn purge_deliveries(&self, before: OffsetDateTime) -> RepoResult<u64> {
+        let mut store = self.store();
+        let count = store.outbox.rows.len();
+        store.outbox.rows.retain(|_, (_, row)| {
+            row.status == DeliveryStatus::Pending || row.finished_at.is_none_or(|at| at >= before)
+        });
+        Ok((count - store.outbox.rows.len()) as u64)
+    }
+
+    async fn contact_links(
+        &self,
+        workspace_id: i32,
+        contact_id: i32,
+        now: OffsetDateTime,
+    ) -> RepoResult<Vec<ContactLink>> {
+        Ok(self
+            .store()
+            .contact_links
+            .rows
+            .values()
+            .map(|(owner, l)| (owner, &l.link))
+            .filter(|(owner, l)| {
+                **owner == workspace_id && l.contact_id == contact_id && l.expires_at > now
+            })
+            .map(|(_, l)| l.clone())
+            .collect())
+    }
+
+    async fn create_contact_link(
+        &self,
+        workspace_id: i32,
+        contact_id: i32,
+        token_hash: &str,
+        expires_at: OffsetDateTime,
+    ) -> RepoResult<ContactLink> {
+        let mut store = self.store();
+        if store
+            .contact_links
+            .rows
+            .values()
+            .any(|(_, l)| l.token_hash == token_hash)
+        {
+            return Err(unique_violation("contact_links_token_hash_key"));
+        }
+        let link = ContactLink {
+            link_id: store.contact_links.next_id(),
+            contact_id,
+            token: None,
+            expires_at,
+            url: None,
+        };
+        let row = ContactLinkRow {
+            link: link.clone(),
+            token_hash: token_hash.to_string(),
+        };
+        store
+            .contact_links
+            .rows
+            .insert(link.link_id, (workspace_id, row));
+        Ok(link)
+    }
+
+    async fn delete_contact_link(&self, workspace_id: i32, link_id: i32) -> RepoResult<bool> {
+        Ok(self
+            .store()
+            .contact_links
+            .remove_owned(workspace_id, link_id))
+    }
+
+    async fn owns_contact_link(&self, workspace_id: i32, link_id: i32) -> RepoResult<bool> {
+        Ok(self
+            .store()
+            .contact_links
+            .owned(workspace_id, link_id)
+            .is_some())
+    }
+
+    async fn contact_link_target(
+        &self,
+        token_hash: &str,
+        now: OffsetDateTime,
+    ) -> RepoResult<Option<(i32, i32)>> {
+        let store = self.store();
+        Ok(store
+            .contact_links
+            .rows
+            .values()
+            .find(|(_, l)| l.token_hash == token_hash && l.link.expires_at > now)
+            .and_then(|(workspace_id, l)| {
+                let user_id = store.workspace_owner(*workspace_id)?;
+                let (_, user) = store.users.rows.get(&user_id)?;
+                (!user.deactivated).then_some((*workspace_id, l.link.contact_id))
+            }))
+    }
+
+    async fn claim_idempotency_key(
+        &self,
+        user_id: i32,
+        key: &str,
+        fingerprint: &str,
+        ttl_hours: i32,
+    ) -> RepoResult<Option<IdempotentRequest>> {
+        let now = OffsetDateTime::now_utc();
+        let expired_before = now - time::Duration::hours(i64::from(ttl_hours));
+        let mut store = self.store();
+        store
+            .idempotency_keys
+            .retain(|(owner, _), (claimed_at, _)| {
+                *owner != user_id || *claimed_at >= expired_before
+            });
+        match store.idempotency_keys.get(&(user_id, key.to_string())) {
+            Some((_, held)) => Ok(Some(held.clone())),
+            None => {
+                store.idempotency_keys.insert(
+                    (user_id, key.to_string()),
+                    (
+                        now,
+                        IdempotentRequest {
+                            fingerprint: fingerprint.to_string(),
+                            response: None,
+                        },
+                    ),
+                );
+                Ok(None)
+            }
+        }
+    }
+
+    async fn complete_idempotency_key(
+        &self,
+        user_id: i32,
+        key: &str,
+        response: &StoredResponse,
+    ) -> RepoResult<()> {
+        if let Some((_, held)) = self
+            .store()
+            .idempotency_keys
+            .get_mut(&(user_id, key.to_string()))
+        {
+            held.response = Some(response.clone());
+        }
+        Ok(())
+    }
+
+    async fn release_idempotency_key(&self, user_id: i32, key: &str) -> RepoResult<()> {
+        self.store()
+            .idempotency_keys
+            .remove(&(user_id, key.to_string()));
+        Ok(())
+    }
+}
@@ -0,0 +1,4042 @@
+//! `Repository` backed by the production Postgres database
+
+use super::{DEFAULT_WORKSPACE_NAME, RepoResult, Repository};
+use crate::AuthUser;
+use crate::cursor::Cursor;
+use crate::database;
+use crate::encryption::{self, ENCRYPTED_FIELDS, NotesCipher};
+use crate::geocoding::BoundingBox;
+use crate::history::{self, FieldChange};
+use crate::mail::Email;
+use crate::models::{
+    AccountSummary, Attachment, AttachmentFile, Capture, Contact, ContactAccess, ContactChange,
+    ContactChecksum, ContactDetails, ContactLink, ContactMatch, ContactShare, ContactSummary,
+    Coordinates, CustomInteractionType, DeliveryAttempt, DeliveryStatus, ExportSchedule,
+    FilterQuery, GiftIdea, GiftStatus, Goal, GoalPeriod, Granularity, Group, HookDelivery,
+    HookEvent, IdempotentRequest, InstanceStats, Interaction, MetAt, NewAttachment,
+    NewContactRequest, NewGiftIdeaRequest, NewGoalRequest, NewGroupRequest, NewInteractionRequest,
+    NewOccasionRequest, NewPushSubscriptionRequest, NewRestHookRequest, NewSavedFilterRequest,
+    NewSocialProfileRequest, NewTagRequest, NoteMatch, NoteSource, NotificationSettings, Occasion,
+    OccasionType, OutboxMessage, OutboxPayload, PendingAvatarLookup, PendingTranscription,
+    Preferences, PushRecipient, PushSubscription, RestHook, ReviewRecipient, SavedFilter, Share,
+    SharePermission, SocialPlatform, SocialProfile, StatsMetric, StoredResponse, Tag, TagUsage,
+    TranscriptionStatus, Unassigned, Usage, UserProfile, Workspace,
+};
+use crate::phone;
+use crate::push::PushMessage;
+use crate::timezone::LocalDates;
+use async_trait::async_trait;
+use sqlx::types::Json;
+use sqlx::{Acquire, PgConnection, PgExecutor, PgPool};
+use time::{Date, OffsetDateTime};
+
+#[derive(Clone)]
+pub struct PgRepository {
+    pool: PgPool,
+    /// Read-only replica for the list, get and search queries of read requests; see
+    /// `database::replica_reads`
+    read_pool: Option<PgPool>,
+    /// Encrypts contacts' notes at rest when set; see `encryption`
+    notes: Option<NotesCipher>,
+}
+
+/// Contacts whose notes `encrypt_notes` encrypts per statement
+const ENCRYPT_BATCH_SIZE: i64 = 500;
+
+impl PgRepository {
+    pub fn new(pool: PgPool) -> Self {
+        PgRepository {
+            pool,
+            read_pool: None,
+            notes: None,
+        }
+    }
+
+    /// Send the list, get and search queries of read requests to `pool`; None keeps every
+    /// query on the primary
+    pub fn with_read_pool(mut self, pool: Option<PgPool>) -> Self {
+        self.read_pool = pool;
+        self
+    }
+
+    /// Encrypt contacts' notes with `cipher` from now on
+    pub fn with_notes_cipher(mut self, cipher: Option<NotesCipher>) -> Self {
+        self.notes = cipher;
+        self
+    }
+
+    pub fn pool(&self) -> &PgPool {
+        &self.pool
+    }
+
+    /// The replica while serving a read request, otherwise the primary, so a request or job
+    /// that writes always reads its own writes
+    fn reader(&self) -> &PgPool {
+        match &self.read_pool {
+            Some(replica) if database::in_read_request() => replica,
+            _ => &self.pool,
+        }
+    }
+
+    /// The contact with its notes decrypted
+    fn open_contact(&self, mut contact: Contact) -> RepoResult<Contact> {
+        contact.short_note = open_note(self.notes.as_ref(), contact.short_note)?;
+        contact.notes = open_note(self.notes.as_ref(), contact.notes)?;
+        Ok(contact)
+    }
+
+    fn open_contacts(&self, contacts: Vec<Contact>) -> RepoResult<Vec<Contact>> {
+        contacts.into_iter().map(|c| self.open_contact(c)).collect()
+    }
+
+    /// Encrypt the notes, and their history, that were stored before the key was set,
+    /// returning how many contacts were changed. Does nothing without a cipher.
+    pub async fn encrypt_notes(&self) -> RepoResult<u64> {
+        let Some(cipher) = &self.notes else {
+            return Ok(0);
+        };
+        let mut total = 0;
+        loop {
+            let rows = sqlx::query!(
+                "SELECT contact_id, short_note, notes FROM contacts
+                 WHERE NOT starts_with(short_note, $1) OR NOT starts_with(notes, $1)
+                 ORDER BY contact_id
+                 LIMIT $2",
+                encryption::ENCRYPTED_PREFIX,
+                ENCRYPT_BATCH_SIZE
+            )
+            .fetch_all(&self.pool)
+            .await?;
+            let mut tx = self.pool.begin().await?;
+            for row in &rows {
+                // Encrypted values are left alone, so a row is never encrypted twice
+                let seal = |value: &Option<String>| match value {
+                    Some(v) if !encryption::is_encrypted(v) => Some(cipher.encrypt(v)),
+                    other => other.clone(),
+                };
+                sqlx::query!(
+                    "UPDATE contacts SET short_note = $2, notes = $3 WHERE contact_id = $1",
+                    row.contact_id,
+                    seal(&row.short_note),
+                    seal(&row.notes),
+                )
+                .execute(&mut *tx)
+                .await?;
+            }
+            tx.commit().await?;
+            total += rows.len() as u64;
+            if (rows.len() as i64) < ENCRYPT_BATCH_SIZE {
+                break;
+            }
+        }
+
+        loop {
+            let rows = sqlx::query!(
+                "SELECT change_id, old_value, new_value FROM contact_changes
+                 WHERE field = ANY($1)
+                   AND (NOT starts_with(old_value, $2) OR NOT starts_with(new_value, $2))
+                 ORDER BY change_id
+                 LIMIT $3",
+                ENCRYPTED_FIELDS as &[&str],
+                encryption::ENCRYPTED_PREFIX,
+                ENCRYPT_BATCH_SIZE
+            )
+            .fetch_all(&self.pool)
+            .await?;
+            let mut tx = self.pool.begin().await?;
+            for row in &rows {
+                let seal = |value: &Option<String>| match value {
+                    Some(v) if !encryption::is_encrypted(v) => Some(cipher.encrypt(v)),
+                    other => other.clone(),
+                };
+                sqlx::query!(
+                    "UPDATE contact_changes SET old_value = $2, new_value = $3 WHERE change_id = $1",
+                    row.change_id,
+                    seal(&row.old_value),
+                    seal(&row.new_value),
+                )
+                .execute(&mut *tx)
+                .await?;
+            }
+            tx.commit().await?;
+            if (rows.len() as i64) < ENCRYPT_BATCH_SIZE {
+                return Ok(total);
+            }
+        }
+    }
+}
+
+/// A stored note's plaintext, failing the read like a bad column would when it can't be
+/// decrypted
+fn open_note(cipher: Option<&NotesCipher>, stored: Option<String>) -> RepoResult<Option<String>> {
+    encryption::open(cipher, stored).map_err(|e| sqlx::Error::Decode(e.into()))
+}
+
+#[async_trait]
+impl Repository for PgRepository {
+    async fn find_user(&self, auth0_id: &str) -> RepoResult<Option<AuthUser>> {
+        let user = sqlx::query!(
+            r#"SELECT u.user_id, u.auth0_id, u.email, u.name, u.is_admin, w.workspace_id,
+                      u.deactivated_at IS NOT NULL AS "deactivated!", u.delete_after
+             FROM users u
+             JOIN workspaces w ON w.user_id = u.user_id AND w.is_default
+             WHERE u.auth0_id = $1"#,
+            auth0_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(user.map(|user| AuthUser {
+            user_id: user.user_id,
+            workspace_id: user.workspace_id,
+            auth0_id: user.auth0_id,
+            email: Some(user.email),
+            name: Some(user.name),
+            is_admin: user.is_admin,
+            deactivated: user.deactivated,
+            delete_after: user.delete_after,
+            scopes: None,
+            authenticated_at: None,
+        }))
+    }
+
+    async fn get_or_create_user(
+        &self,
+        auth0_id: &str,
+        email: &str,
+        name: &str,
+    ) -> RepoResult<AuthUser> {
+        if let Some(user) = self.find_user(auth0_id).await? {
+            return Ok(user);
+        }
+
+        let mut tx = self.pool.begin().await?;
+        let user = sqlx::query!(
+            "INSERT INTO users (auth0_id, email, name) VALUES ($1, $2, $3) RETURNING user_id, auth0_id, email, name",
+            auth0_id,
+            email,
+            name
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        let workspace_id = sqlx::query_scalar!(
+            "INSERT INTO workspaces (user_id, name, is_default) VALUES ($1, $2, TRUE)
+             RETURNING workspace_id",
+            user.user_id,
+            DEFAULT_WORKSPACE_NAME
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(AuthUser {
+            user_id: user.user_id,
+            workspace_id,
+            auth0_id: user.auth0_id,
+            email: Some(user.email),
+            name: Some(user.name),
+            is_admin: false,
+            deactivated: false,
+            delete_after: None,
+            scopes: None,
+            authenticated_at: None,
+        })
+    }
+
+    async fn delete_user(&self, user_id: i32) -> RepoResult<()> {
+        sqlx::query!("DELETE FROM users WHERE user_id = $1", user_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn schedule_user_deletion(
+        &self,
+        user_id: i32,
+        delete_after: OffsetDateTime,
+    ) -> RepoResult<()> {
+        sqlx::query!(
+            "UPDATE users
+             SET deactivated_at = COALESCE(deactivated_at, CURRENT_TIMESTAMP), delete_after = $2
+             WHERE user_id = $1",
+            user_id,
+            delete_after
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn restore_user(&self, user_id: i32) -> RepoResult<bool> {
+        let result = sqlx::query!(
+            "UPDATE users SET deactivated_at = NULL, delete_after = NULL
+             WHERE user_id = $1 AND delete_after IS NOT NULL",
+            user_id
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn purge_deleted_users(&self, now: OffsetDateTime) -> RepoResult<Vec<String>> {
+        sqlx::query_scalar!(
+            "DELETE FROM users WHERE delete_after <= $1 RETURNING auth0_id",
+            now
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn get_profile(&self, user_id: i32) -> RepoResult<Option<UserProfile>> {
+        let record = sqlx::query!(
+            r#"SELECT u.user_id, u.name, u.email, p.timezone AS "timezone?",
+                      s.email_notifications AS "email_notifications?",
+                      s.push_notifications AS "push_notifications?",
+                      s.weekly_review_email AS "weekly_review_email?",
+                      s.default_reminder_cadence_days AS "default_reminder_cadence_days?"
+               FROM users u
+               LEFT JOIN user_preferences p ON p.user_id = u.user_id
+               LEFT JOIN user_settings s ON s.user_id = u.user_id
+               WHERE u.user_id = $1"#,
+            user_id,
+        )
+        .fetch_optional(self.reader())
+        .await?;
+        Ok(record.map(|r| {
+            let defaults = UserProfile::defaults(r.user_id, r.name, r.email);
+            UserProfile {
+                timezone: r.timezone,
+                notifications: NotificationSettings {
+                    email: r
+                        .email_notifications
+                        .unwrap_or(defaults.notifications.email),
+                    push: r.push_notifications.unwrap_or(defaults.notifications.push),
+                    weekly_review: r
+                        .weekly_review_email
+                        .unwrap_or(defaults.notifications.weekly_review),
+                },
+                default_reminder_cadence_days: r
+                    .default_reminder_cadence_days
+                    .unwrap_or(defaults.default_reminder_cadence_days),
+                ..defaults
+            }
+        }))
+    }
+
+    async fn save_profile(&self, profile: &UserProfile) -> RepoResult<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query!(
+            "UPDATE users SET name = $2, email = $3 WHERE user_id = $1",
+            profile.user_id,
+            profile.name,
+            profile.email,
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "INSERT INTO user_preferences (user_id, timezone) VALUES ($1, $2)
+             ON CONFLICT (user_id) DO UPDATE SET timezone = EXCLUDED.timezone",
+            profile.user_id,
+            profile.timezone.as_deref(),
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "INSERT INTO user_settings
+                 (user_id, email_notifications, push_notifications, weekly_review_email,
+                  default_reminder_cadence_days)
+             VALUES ($1, $2, $3, $4, $5)
+             ON CONFLICT (user_id) DO UPDATE SET
+                 email_notifications = EXCLUDED.email_notifications,
+                 push_notifications = EXCLUDED.push_notifications,
+                 weekly_review_email = EXCLUDED.weekly_review_email,
+                 default_reminder_cadence_days = EXCLUDED.default_reminder_cadence_days",
+            profile.user_id,
+            profile.notifications.email,
+            profile.notifications.push,
+            profile.notifications.weekly_review,
+            profile.default_reminder_cadence_days,
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await
+    }
+
+    async fn find_user_by_email(&self, email: &str) -> RepoResult<Option<i32>> {
+        sqlx::query_scalar!(
+            "SELECT user_id FROM users WHERE lower(email) = lower($1) ORDER BY user_id LIMIT 1",
+            email
+        )
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    async fn list_accounts(&self) -> RepoResult<Vec<AccountSummary>> {
+        sqlx::query_as!(
+            AccountSummary,
+            r#"SELECT u.user_id, u.auth0_id, u.name, u.email, u.is_admin,
+                      u.deactivated_at IS NOT NULL AS "deactivated!",
+                      COUNT(DISTINCT w.workspace_id) AS "workspace_count!",
+                      COUNT(c.contact_id) AS "contact_count!"
+               FROM users u
+               LEFT JOIN workspaces w ON w.user_id = u.user_id
+               LEFT JOIN contacts c ON c.workspace_id = w.workspace_id
+               GROUP BY u.user_id
+               ORDER BY u.user_id"#
+        )
+        .fetch_all(self.reader())
+        .await
+    }
+
+    async fn usage(&self, user_id: i32) -> RepoResult<Usage> {
+        sqlx::query_as!(
+            Usage,
+            r#"SELECT COUNT(*) AS "workspaces!",
+                      (SELECT COUNT(*) FROM contacts c JOIN workspaces w USING (workspace_id)
+                       WHERE w.user_id = $1) AS "contacts!",
+                      (SELECT COUNT(*) FROM interactions i JOIN workspaces w USING (workspace_id)
+                       WHERE w.user_id = $1) AS "interactions!",
+                      (SELECT COUNT(*) FROM tags t JOIN workspaces w USING (workspace_id)
+                       WHERE w.user_id = $1) AS "tags!",
+                      (SELECT COALESCE(SUM(a.size_bytes), 0)
+                       FROM interaction_attachments a JOIN workspaces w USING (workspace_id)
+                       WHERE w.user_id = $1) AS "attachment_bytes!"
+               FROM workspaces
+               WHERE user_id = $1"#,
+            user_id,
+        )
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    async fn instance_stats(&self) -> RepoResult<InstanceStats> {
+        sqlx::query_as!(
+            InstanceStats,
+            r#"SELECT (SELECT COUNT(*) FROM users) AS "users!",
+                      (SELECT COUNT(*) FROM users WHERE deactivated_at IS NOT NULL)
+                          AS "deactivated_users!",
+                      (SELECT COUNT(*) FROM workspaces) AS "workspaces!",
+                      (SELECT COUNT(*) FROM contacts) AS "contacts!",
+                      (SELECT COUNT(*) FROM interactions) AS "interactions!",
+                      (SELECT COUNT(*) FROM shares) AS "shares!""#
+        )
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    async fn set_deactivated(&self, user_id: i32, deactivated: bool) -> RepoResult<Option<String>> {
+        // An already deactivated user keeps their original deactivation time
+        sqlx::query_scalar!(
+            "UPDATE users
+             SET deactivated_at = CASE WHEN $2 THEN COALESCE(deactivated_at, CURRENT_TIMESTAMP) END,
+                 delete_after = CASE WHEN $2 THEN delete_after END
+             WHERE user_id = $1
+             RETURNING auth0_id",
+            user_id,
+            deactivated
+        )
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    async fn weekly_review_recipients(&self) -> RepoResult<Vec<ReviewRecipient>> {
+        sqlx::query_as!(
+            ReviewRecipient,
+            "SELECT u.user_id, w.workspace_id, u.name, u.email,
+                    s.weekly_review_sent_on AS sent_week
+             FROM users u
+             JOIN user_settings s ON s.user_id = u.user_id
+             JOIN workspaces w ON w.user_id = u.user_id AND w.is_default
+             WHERE u.deactivated_at IS NULL AND s.weekly_review_email AND s.email_notifications
+             ORDER BY u.user_id"
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn record_weekly_review(&self, user_id: i32, week: Date) -> RepoResult<()> {
+        sqlx::query!(
+            "UPDATE user_settings SET weekly_review_sent_on = $2 WHERE user_id = $1",
+            user_id,
+            week
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn queue_weekly_review(
+        &self,
+        user_id: i32,
+        workspace_id: i32,
+        week: Date,
+        email: &Email,
+    ) -> RepoResult<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query!(
+            "INSERT INTO outbox (workspace_id, payload) VALUES ($1, $2)",
+            workspace_id,
+            Json(email) as _,
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "UPDATE user_settings SET weekly_review_sent_on = $2 WHERE user_id = $1",
+            user_id,
+            week
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await
+    }
+
+    async fn queue_email(&self, workspace_id: i32, email: &Email) -> RepoResult<()> {
+        sqlx::query!(
+            "INSERT INTO outbox (workspace_id, payload) VALUES ($1, $2)",
+            workspace_id,
+            Json(email) as _,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn push_reminder_recipients(&self) -> RepoResult<Vec<PushRecipient>> {
+        sqlx::query_as!(
+            PushRecipient,
+            "SELECT u.user_id, w.workspace_id, s.push_reminders_sent_on AS sent_on
+             FROM users u
+             JOIN user_settings s ON s.user_id = u.user_id
+             JOIN workspaces w ON w.user_id = u.user_id AND w.is_default
+             WHERE u.deactivated_at IS NULL AND s.push_notifications
+               AND EXISTS (SELECT 1 FROM push_subscriptions p WHERE p.user_id = u.user_id)
+             ORDER BY u.user_id"
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn queue_push_reminders(
+        &self,
+        user_id: i32,
+        workspace_id: i32,
+        day: Date,
+        messages: &[PushMessage],
+    ) -> RepoResult<()> {
+        let mut tx = self.pool.begin().await?;
+        for message in messages {
+            sqlx::query!(
+                "INSERT INTO outbox (workspace_id, push_subscription_id, payload)
+                 SELECT $2, subscription_id, $3 FROM push_subscriptions WHERE user_id = $1
+                 ORDER BY subscription_id",
+                user_id,
+                workspace_id,
+                Json(message) as _,
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        sqlx::query!(
+            "UPDATE user_settings SET push_reminders_sent_on = $2 WHERE user_id = $1",
+            user_id,
+            day
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await
+    }
+
+    async fn list_push_subscriptions(&self, user_id: i32) -> RepoResult<Vec<PushSubscription>> {
+        sqlx::query_as!(
+            PushSubscription,
+            "SELECT subscription_id, user_id, endpoint, p256dh, auth, device_name, created_at
+             FROM push_subscriptions WHERE user_id = $1
+             ORDER BY subscription_id",
+            user_id
+        )
+        .fetch_all(self.reader())
+        .await
+    }
+
+    async fn save_push_subscription(
+        &self,
+        user_id: i32,
+        subscription: &NewPushSubscriptionRequest,
+    ) -> RepoResult<PushSubscription> {
+        sqlx::query_as!(
+            PushSubscription,
+            "INSERT INTO push_subscriptions (user_id, endpoint, p256dh, auth, device_name)
+             VALUES ($1, $2, $3, $4, $5)
+             ON CONFLICT (user_id, endpoint) DO UPDATE
+             SET p256dh = EXCLUDED.p256dh, auth = EXCLUDED.auth, device_name = EXCLUDED.device_name
+             RETURNING subscription_id, user_id, endpoint, p256dh, auth, device_name, created_at",
+            user_id,
+            subscription.endpoint,
+            subscription.keys.p256dh,
+            subscription.keys.auth,
+            subscription.device_name,
+        )
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    async fn delete_push_subscription(
+        &self,
+        user_id: i32,
+        subscription_id: i32,
+    ) -> RepoResult<bool> {
+        let result = sqlx::query!(
+            "DELETE FROM push_subscriptions WHERE subscription_id = $1 AND user_id = $2",
+            subscription_id,
+            user_id,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn owns_push_subscription(&self, user_id: i32, subscription_id: i32) -> RepoResult<bool> {
+        let found = sqlx::query_scalar!(
+            "SELECT subscription_id FROM push_subscriptions
+             WHERE subscription_id = $1 AND user_id = $2",
+            subscription_id,
+            user_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(found.is_some())
+    }
+
+    async fn claim_job(
+        &self,
+        name: &str,
+        holder: &str,
+        now: OffsetDateTime,
+        until: OffsetDateTime,
+    ) -> RepoResult<bool> {
+        let claimed = sqlx::query_scalar!(
+            "INSERT INTO job_leases (name, holder, locked_until) VALUES ($1, $2, $4)
+             ON CONFLICT (name) DO UPDATE SET holder = $2, locked_until = $4
+             WHERE job_leases.holder = $2 OR job_leases.locked_until <= $3
+             RETURNING name",
+            name,
+            holder,
+            now,
+            until
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(claimed.is_some())
+    }
+
+    async fn list_workspaces(&self, user_id: i32) -> RepoResult<Vec<Workspace>> {
+        sqlx::query_as!(
+            Workspace,
+            "SELECT workspace_id, name, is_default FROM workspaces
+             WHERE user_id = $1 ORDER BY is_default DESC, name",
+            user_id
+        )
+        .fetch_all(self.reader())
+        .await
+    }
+
+    async fn create_workspace(&self, user_id: i32, name: &str) -> RepoResult<i32> {
+        sqlx::query_scalar!(
+            "INSERT INTO workspaces (user_id, name) VALUES ($1, $2) RETURNING workspace_id",
+            user_id,
+            name
+        )
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    async fn rename_workspace(
+        &self,
+        user_id: i32,
+        workspace_id: i32,
+        name: &str,
+    ) -> RepoResult<bool> {
+        let result = sqlx::query!(
+            "UPDATE workspaces SET name = $1 WHERE workspace_id = $2 AND user_id = $3",
+            name,
+            workspace_id,
+            user_id,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn delete_workspace(&self, user_id: i32, workspace_id: i32) -> RepoResult<bool> {
+        let result = sqlx::query!(
+            "DELETE FROM workspaces WHERE workspace_id = $1 AND user_id = $2 AND NOT is_default",
+            workspace_id,
+            user_id,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn owns_workspace(&self, user_id: i32, workspace_id: i32) -> RepoResult<bool> {
+        let found = sqlx::query_scalar!(
+            "SELECT workspace_id FROM workspaces WHERE workspace_id = $1 AND user_id = $2",
+            workspace_id,
+            user_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(found.is_some())
+    }
+
+    async fn list_contacts(&self, workspace_id: i32) -> RepoResult<Vec<Contact>> {
+        let contacts = sqlx::query_as(
+            "SELECT contact_id, first_name, last_name, email, phone, phone_e164, short_note, notes,
+                    avatar_url, archived, desired_frequency_days, met_at, introduced_by_contact_id,
+                    location, latitude, longitude, updated_at
+             FROM contacts
+             WHERE workspace_id = $1
+             ORDER BY last_name, first_name",
+        )
+        .bind(workspace_id)
+        .fetch_all(self.reader())
+        .await?;
+        self.open_contacts(contacts)
+    }
+
+    async fn contacts_by_priority(
+        &self,
+        workspace_id: i32,
+        dates: &LocalDates,
+        include_archived: bool,
+        snoozed: &[i32],
+        limit: Option<i64>,
+        offset: i64,
+    ) -> RepoResult<Vec<Contact>> {
+        let contacts = sqlx::query_as(
+            "SELECT c.contact_id, c.first_name, c.last_name, c.email, c.phone, c.phone_e164, c.short_note,
+                    c.notes, c.avatar_url, c.archived, c.desired_frequency_days, c.met_at,
+                    c.introduced_by_contact_id, c.location, c.latitude, c.longitude, c.updated_at
+             FROM contacts c
+             LEFT JOIN contact_scores s
+                    ON s.contact_id = c.contact_id AND s.computed_on = $3 AND s.timezone = $4
+                       AND s.contact_updated_at IS NOT DISTINCT FROM c.updated_at
+             WHERE c.workspace_id = $1 AND ($2 OR NOT c.archived)
+             ORDER BY c.contact_id = ANY($7),
+                      CASE WHEN s.contact_id IS NOT NULL THEN s.priority
+                           ELSE contact_priority(c.contact_id, c.desired_frequency_days, $3, $4)
+                      END DESC NULLS LAST,
+                      c.contact_id
+             LIMIT $5 OFFSET $6",
+        )
+        .bind(workspace_id)
+        .bind(include_archived)
+        .bind(dates.today())
+        .bind(dates.timezone_name())
+        .bind(limit)
+        .bind(offset)
+        .bind(snoozed)
+        .fetch_all(self.reader())
+        .await?;
+        self.open_contacts(contacts)
+    }
+
+    async fn get_contact(&self, workspace_id: i32, contact_id: i32) -> RepoResult<Option<Contact>> {
+        let contact = sqlx::query_as(
+            "SELECT contact_id, first_name, last_name, email, phone, phone_e164, short_note, notes,
+                    avatar_url, archived, desired_frequency_days, met_at, introduced_by_contact_id,
+                    location, latitude, longitude, updated_at
+             FROM contacts
+             WHERE contact_id = $1 AND workspace_id = $2",
+        )
+        .bind(contact_id)
+        .bind(workspace_id)
+        .fetch_optional(self.reader())
+        .await?;
+        contact.map(|c| self.open_contact(c)).transpose()
+    }
+
+    async fn contacts_by_phone(&self, workspace_id: i32, number: &str) -> RepoResult<Vec<Contact>> {
+        let contacts = sqlx::query_as(
+            "SELECT contact_id, first_name, last_name, email, phone, phone_e164, short_note, notes,
+                    avatar_url, archived, desired_frequency_days, met_at, introduced_by_contact_id,
+                    location, latitude, longitude, updated_at
+             FROM contacts
+             WHERE workspace_id = $1 AND phone_e164 = $2
+             ORDER BY contact_id",
+        )
+        .bind(workspace_id)
+        .bind(number)
+        .fetch_all(&self.pool)
+        .await?;
+        self.open_contacts(contacts)
+    }
+
+    async fn contacts_introduced_by(
+        &self,
+        workspace_id: i32,
+        contact_id: i32,
+    ) -> RepoResult<Vec<Contact>> {
+        let contacts = sqlx::query_as(
+            "SELECT contact_id, first_name, last_name, email, phone, phone_e164, short_note, notes,
+                    avatar_url, archived, desired_frequency_days, met_at, introduced_by_contact_id,
+                    location, latitude, longitude, updated_at
+             FROM contacts
+             WHERE workspace_id = $1 AND introduced_by_contact_id = $2
+             ORDER BY last_name, first_name, contact_id",
+        )
+        .bind(workspace_id)
+        .bind(contact_id)
+        .fetch_all(&self.pool)
+        .await?;
+        self.open_contacts(contacts)
+    }
+
+    async fn latest_contacts(&self, workspace_id: i32, limit: i64) -> RepoResult<Vec<Contact>> {
+        let contacts = sqlx::query_as(
+            "SELECT contact_id, first_name, last_name, email, phone, phone_e164, short_note, notes,
+                    avatar_url, archived, desired_frequency_days, met_at, introduced_by_contact_id,
+                    location, latitude, longitude, updated_at
+             FROM contacts
+             WHERE workspace_id = $1
+             ORDER BY contact_id DESC
+             LIMIT $2",
+        )
+        .bind(workspace_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        self.open_contacts(contacts)
+    }
+
+    async fn contacts_created_between(
+        &self,
+        workspace_id: i32,
+        from: OffsetDateTime,
+        until: OffsetDateTime,
+    ) -> RepoResult<Vec<Contact>> {
+        // created_at holds UTC without a zone
+        let contacts = sqlx::query_as(
+            "SELECT contact_id, first_name, last_name, email, phone, phone_e164, short_note, notes,
+                    avatar_url, archived, desired_frequency_days, met_at, introduced_by_contact_id,
+                    location, latitude, longitude, updated_at
+             FROM contacts
+             WHERE workspace_id = $1
+               AND created_at >= ($2::timestamptz AT TIME ZONE 'UTC')
+               AND created_at < ($3::timestamptz AT TIME ZONE 'UTC')
+             ORDER BY created_at, contact_id",
+        )
+        .bind(workspace_id)
+        .bind(from)
+        .bind(until)
+        .fetch_all(&self.pool)
+        .await?;
+        self.open_contacts(contacts)
+    }
+
+    async fn contacts_in_box(
+        &self,
+        workspace_id: i32,
+        area: &BoundingBox,
+    ) -> RepoResult<Vec<Contact>> {
+        let contacts = sqlx::query_as(
+            "SELECT contact_id, first_name, last_name, email, phone, phone_e164, short_note, notes,
+                    avatar_url, archived, desired_frequency_days, met_at, introduced_by_contact_id,
+                    location, latitude, longitude, updated_at
+             FROM contacts
+             WHERE workspace_id = $1 AND NOT archived AND latitude BETWEEN $2 AND $3
+               AND CASE WHEN $4 <= $5 THEN longitude BETWEEN $4 AND $5
+                        -- Across the antimeridian
+                        ELSE longitude >= $4 OR longitude <= $5
+                   END
+             ORDER BY contact_id",
+        )
+        .bind(workspace_id)
+        .bind(area.min_latitude)
+        .bind(area.max_latitude)
+        .bind(area.min_longitude)
+        .bind(area.max_longitude)
+        .fetch_all(&self.pool)
+        .await?;
+        self.open_contacts(contacts)
+    }
+
+    async fn autocomplete_contacts(
+        &self,
+        workspace_id: i32,
+        words: &[String],
+        limit: i64,
+    ) -> RepoResult<Vec<ContactMatch>> {
+        let patterns: Vec<String> = words
+            .iter()
+            .map(|word| {
+                let escaped = word
+                    .replace('\\', "\\\\")
+                    .replace('%', "\\%")
+                    .replace('_', "\\_");
+                format!("{}%", escaped)
+            })
+            .collect();
+        let Some(first) = patterns.first() else {
+            return Ok(Vec::new());
+        };
+        // The first word narrows the search through the name prefix indexes
+        sqlx::query_as!(
+            ContactMatch,
+            r#"SELECT contact_id, concat_ws(' ', first_name, last_name) AS "display_name!", avatar_url
+               FROM contacts
+               WHERE workspace_id = $1 AND NOT archived
+                 AND (lower(first_name) LIKE $2 OR lower(last_name) LIKE $2)
+                 AND NOT EXISTS (
+                     SELECT 1 FROM unnest($3::text[]) AS word(pattern)
+                     WHERE (lower(first_name) LIKE word.pattern
+                            OR lower(last_name) LIKE word.pattern) IS NOT TRUE
+                 )
+               ORDER BY last_name, first_name, contact_id
+               LIMIT $4"#,
+            workspace_id,
+            first,
+            &patterns,
+            limit,
+        )
+        .fetch_all(self.reader())
+        .await
+    }
+
+    async fn contact_details(
+        &self,
+        workspace_id: i32,
+        contact_ids: &[i32],
+    ) -> RepoResult<Vec<ContactDetails>> {
+        let details: Vec<ContactDetails> = sqlx::query_as(
+            "SELECT c.contact_id, c.first_name, c.last_name, c.email, c.phone, c.phone_e164, c.short_note,
+                    c.notes, c.avatar_url, c.archived, c.desired_frequency_days, c.met_at,
+                    c.introduced_by_contact_id, c.location, c.latitude, c.longitude, c.updated_at,
+                    COALESCE(t.tags, '[]') AS tags,
+                    COALESCE(i.interactions, '[]') AS interactions,
+                    COALESCE(o.occasions, '[]') AS occasions
+             FROM unnest($2::int[]) WITH ORDINALITY AS wanted(contact_id, position)
+             JOIN contacts c ON c.contact_id = wanted.contact_id AND c.workspace_id = $1
+             LEFT JOIN LATERAL (
+                 SELECT json_agg(json_build_object('tag_id', t.tag_id, 'name', t.name,
+                                                   'color', t.color, 'details', t.details)
+                                 ORDER BY t.tag_id) AS tags
+                 FROM contact_tags ct
+                 JOIN tags t ON t.tag_id = ct.tag_id
+                 WHERE ct.contact_id = c.contact_id
+             ) t ON true
+             LEFT JOIN LATERAL (
+                 SELECT json_agg(json_build_object(
+                            'interaction_id', i.interaction_id,
+                            'contact_id', i.contact_id,
+                            'interaction_date', i.interaction_date,
+                            'notes', i.notes,
+                            'follow_up_priority', i.followup_priority,
+                            'interaction_type', COALESCE(i.interaction_type::text, it.name),
+                            'contact_ids', ARRAY(SELECT p.contact_id FROM interaction_participants p
+                                                 WHERE p.interaction_id = i.interaction_id
+                                                 ORDER BY p.contact_id),
+                            'tag_ids', ARRAY(SELECT itg.tag_id FROM interaction_tags itg
+                                             WHERE itg.interaction_id = i.interaction_id
+                                             ORDER BY itg.tag_id))
+                        ORDER BY i.interaction_id) AS interactions
+                 FROM interactions i
+                 LEFT JOIN interaction_types it ON it.type_id = i.custom_type_id
+                 WHERE i.contact_id = c.contact_id
+                    OR i.interaction_id IN (SELECT p.interaction_id FROM interaction_participants p
+                                            WHERE p.contact_id = c.contact_id)
+             ) i ON true
+             LEFT JOIN LATERAL (
+                 SELECT json_agg(json_build_object(
+                            'occasion_id', o.occasion_id,
+                            'contact_id', o.contact_id,
+                            'name', o.name,
+                            'date', o.date,
+                            'recurring', o.recurring,
+                            'recurring_interval', o.recurring_interval,
+                            'details', o.details,
+                            'occasion_type', o.occasion_type,
+                            'birth_year', o.birth_year,
+                            'remind_days_before', o.remind_days_before,
+                            'interaction_id', o.interaction_id)
+                        ORDER BY o.occasion_id) AS occasions
+                 FROM occasions o
+                 WHERE o.contact_id = c.contact_id
+             ) o ON true
+             ORDER BY wanted.position",
+        )
+        .bind(workspace_id)
+        .bind(contact_ids)
+        .fetch_all(self.reader())
+        .await?;
+        details
+            .into_iter()
+            .map(|d| {
+                Ok(ContactDetails {
+                    contact: self.open_contact(d.contact)?,
+                    ..d
+                })
+            })
+            .collect()
+    }
+
+    async fn contact_summaries(
+        &self,
+        workspace_id: i32,
+        contact_ids: &[i32],
+        today: Date,
+    ) -> RepoResult<Vec<(i32, ContactSummary)>> {
+        let rows = sqlx::query!(
+            "SELECT c.contact_id, c.last_interaction_at,
+                    (SELECT MIN(occasion_next_occurrence(o.date, o.recurring, o.recurring_interval,
+                                                         o.occasion_type, $3))
+                     FROM occasions o
+                     WHERE o.contact_id = c.contact_id) AS next_occasion_at
+             FROM contacts c
+             WHERE c.contact_id = ANY($1) AND c.workspace_id = $2",
+            contact_ids,
+            workspace_id,
+            today,
+        )
+        .fetch_all(self.reader())
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let summary = ContactSummary {
+                    last_interaction_at: row.last_interaction_at,
+                    next_occasion_at: row.next_occasion_at,
+                };
+                (row.contact_id, summary)
+            })
+            .collect())
+    }
+
+    async fn create_contact(
+        &self,
+        workspace_id: i32,
+        contact: &NewContactRequest,
+    ) -> RepoResult<i32> {
+        let mut tx = self.pool.begin().await?;
+        let contact_id =
+            insert_contact(&mut tx, workspace_id, contact, self.notes.as_ref()).await?;
+        tx.commit().await?;
+        Ok(contact_id)
+    }
+
+    async fn create_contacts(
+        &self,
+        workspace_id: i32,
+        contacts: &[NewContactRequest],
+        atomic: bool,
+    ) -> RepoResult<Vec<RepoResult<i32>>> {
+        let mut tx = self.pool.begin().await?;
+
+        if atomic {
+            let ids = insert_contacts(&mut tx, workspace_id, contacts, self.notes.as_ref()).await?;
+            tx.commit().await?;
+            return Ok(ids.into_iter().map(Ok).collect());
+        }
+
+        let results =
+            insert_contacts_skipping_failures(&mut tx, workspace_id, contacts, self.notes.as_ref())
+                .await?;
+        tx.commit().await?;
+        Ok(results)
+    }
+
+    async fn check_contacts(
+        &self,
+        workspace_id: i32,
+        contacts: &[NewContactRequest],
+    ) -> RepoResult<Vec<RepoResult<()>>> {
+        let mut tx = self.pool.begin().await?;
+        let results =
+            insert_contacts_skipping_failures(&mut tx, workspace_id, contacts, self.notes.as_ref())
+                .await?;
+        tx.rollback().await?;
+        Ok(results.into_iter().map(|r| r.map(|_| ())).collect())
+    }
+
+    async fn update_contact(
+        &self,
+        workspace_id: i32,
+        contact_id: i32,
+        contact: &NewContactRequest,
+    ) -> RepoResult<bool> {
+        let mut tx = self.pool.begin().await?;
+        let Some(before): Option<Contact> = sqlx::query_as(
+            "SELECT contact_id, first_name, last_name, email, phone, phone_e164, short_note, notes,
+                    avatar_url, archived, desired_frequency_days, met_at, introduced_by_contact_id,
+                    location, latitude, longitude, updated_at
+             FROM contacts
+             WHERE contact_id = $1 AND workspace_id = $2
+             FOR UPDATE",
+        )
+        .bind(contact_id)
+        .bind(workspace_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        else {
+            return Ok(false);
+        };
+        let before = self.open_contact(before)?;
+
+        sqlx::query!(
+            "UPDATE contacts
+             SET first_name = $1, last_name = $2, email = $3, phone = $4, short_note = $5, notes = $6,
+                 avatar_url = $7, desired_frequency_days = $10, phone_e164 = $11, met_at = $12,
+                 introduced_by_contact_id = $13, location = $14::varchar,
+                 -- Coordinates belong to the old location until it is geocoded again
+                 latitude = CASE WHEN location IS DISTINCT FROM $14::varchar THEN NULL ELSE latitude END,
+                 longitude = CASE WHEN location IS DISTINCT FROM $14::varchar THEN NULL ELSE longitude END
+             WHERE contact_id = $8 AND workspace_id = $9",
+            contact.first_name.as_deref(),
+            contact.last_name.as_deref(),
+            contact.email.as_deref(),
+            contact.phone.as_deref(),
+            encryption::seal(self.notes.as_ref(), contact.short_note.as_deref()),
+            encryption::seal(self.notes.as_ref(), contact.notes.as_deref()),
+            contact.avatar_url.as_deref(),
+            contact_id,
+            workspace_id,
+            contact.desired_frequency_days,
+            phone::e164(contact.phone.as_deref()),
+            contact.met_at.as_ref().map(Json) as _,
+            contact.introduced_by_contact_id,
+            contact.location.as_deref(),
+        )
+        .execute(&mut *tx)
+        .await?;
+        insert_contact_changes(
+            &mut *tx,
+            contact_id,
+            &history::changes(&before, contact),
+            self.notes.as_ref(),
+        )
+        .await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn delete_contact(
+        &self,
+        workspace_id: i32,
+        contact_id: i32,
+        keep_history: bool,
+    ) -> RepoResult<bool> {
+        let deleted = self
+            .delete_contacts(workspace_id, &[contact_id], keep_history)
+            .await?;
+        Ok(!deleted.is_empty())
+    }
+
+    async fn delete_contacts(
+        &self,
+        workspace_id: i32,
+        contact_ids: &[i32],
+        keep_history: bool,
+    ) -> RepoResult<Vec<i32>> {
+        let mut tx = self.pool.begin().await?;
+        if keep_history {
+            // Detached first, or the foreign keys would cascade the delete to them
+            sqlx::query!(
+                "UPDATE interactions SET contact_id = NULL
+                 WHERE contact_id = ANY($1) AND workspace_id = $2",
+                contact_ids,
+                workspace_id,
+            )
+            .execute(&mut *tx)
+            .await?;
+            sqlx::query!(
+                "UPDATE occasions SET contact_id = NULL
+                 WHERE contact_id = ANY($1) AND workspace_id = $2",
+                contact_ids,
+                workspace_id,
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        let deleted = sqlx::query_scalar!(
+            "DELETE FROM contacts WHERE contact_id = ANY($1) AND workspace_id = $2 RETURNING contact_id",
+            contact_ids,
+            workspace_id,
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(deleted)
+    }
+
+    async fn list_unassigned(&self, workspace_id: i32) -> RepoResult<Unassigned> {
+        let interactions = sqlx::query_as!(
+            Interaction,
+            r#"SELECT i.interaction_id, i.contact_id, i.interaction_date, i.notes,
+                    i.followup_priority AS follow_up_priority,
+                    COALESCE(i.interaction_type::text, t.name) AS "interaction_type?",
+                    ARRAY(SELECT p.contact_id FROM interaction_participants p
+                          WHERE p.interaction_id = i.interaction_id
+                          ORDER BY p.contact_id) AS "contact_ids!",
+                    ARRAY(SELECT it.tag_id FROM interaction_tags it
+                          WHERE it.interaction_id = i.interaction_id
+                          ORDER BY it.tag_id) AS "tag_ids!"
+             FROM interactions i
+             LEFT JOIN interaction_types t ON t.type_id = i.custom_type_id
+             WHERE i.workspace_id = $1 AND i.contact_id IS NULL
+             ORDER BY i.interaction_date, i.interaction_id"#,
+            workspace_id
+        )
+        .fetch_all(self.reader())
+        .await?;
+        let occasions = sqlx::query_as!(
+            Occasion,
+            r#"SELECT occasion_id, contact_id, name, date, recurring, recurring_interval, details,
+                    occasion_type AS "occasion_type: OccasionType", birth_year,
+                    remind_days_before, NULL::INT AS "upcoming_age", interaction_id
+             FROM occasions
+             WHERE workspace_id = $1 AND contact_id IS NULL
+             ORDER BY date, occasion_id"#,
+            workspace_id
+        )
+        .fetch_all(self.reader())
+        .await?;
+        Ok(Unassigned {
+            interactions,
+            occasions,
+        })
+    }
+
+    async fn set_contact_archived(
+        &self,
+        workspace_id: i32,
+        contact_id: i32,
+        archived: bool,
+    ) -> RepoResult<bool> {
+        let mut tx = self.pool.begin().await?;
+        let Some(before) = sqlx::query_scalar!(
+            "SELECT archived FROM contacts WHERE contact_id = $1 AND workspace_id = $2 FOR UPDATE",
+            contact_id,
+            workspace_id,
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        else {
+            return Ok(false);
+        };
+
+        sqlx::query!(
+            "UPDATE contacts SET archived = $1 WHERE contact_id = $2 AND workspace_id = $3",
+            archived,
+            contact_id,
+            workspace_id,
+        )
+        .execute(&mut *tx)
+        .await?;
+        let change = history::archived_change(before, archived);
+        insert_contact_changes(&mut *tx, contact_id, change.as_slice(), self.notes.as_ref())
+            .await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn set_contact_coordinates(
+        &self,
+        workspace_id: i32,
+        contact_id: i32,
+        location: &str,
+        coordinates: Coordinates,
+    ) -> RepoResult<bool> {
+        let result = sqlx::query!(
+            "UPDATE contacts SET latitude = $1, longitude = $2
+             WHERE contact_id = $3 AND workspace_id = $4 AND location = $5",
+            coordinates.latitude,
+            coordinates.longitude,
+            contact_id,
+            workspace_id,
+            location,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn owns_contact(&self, workspace_id: i32, contact_id: i32) -> RepoResult<bool> {
+        let found = sqlx::query_scalar!(
+            "SELECT contact_id FROM contacts WHERE contact_id = $1 AND workspace_id = $2",
+            contact_id,
+            workspace_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(found.is_some())
+    }
+
+    async fn contact_checksums(&self, workspace_id: i32) -> RepoResult<Vec<ContactChecksum>> {
+        sqlx::query_as!(
+            ContactChecksum,
+            r#"SELECT c.contact_id, md5(json_build_array(
+                    c.first_name, c.last_name, c.email, c.phone, c.short_note, c.notes, c.avatar_url,
+                    c.archived, c.desired_frequency_days, c.met_at, c.introduced_by_contact_id,
+                    c.location, c.latitude, c.longitude,
+                    (SELECT json_agg(ct.tag_id ORDER BY ct.tag_id)
+                     FROM contact_tags ct WHERE ct.contact_id = c.contact_id),
+                    (SELECT json_agg(json_build_array(i.interaction_id, i.interaction_date, i.notes, i.followup_priority,
+                                                      i.interaction_type, i.custom_type_id,
+                                                      (SELECT json_agg(p.contact_id ORDER BY p.contact_id)
+                                                       FROM interaction_participants p
+                                                       WHERE p.interaction_id = i.interaction_id),
+                                                      (SELECT json_agg(it.tag_id ORDER BY it.tag_id)
+                                                       FROM interaction_tags it
+                                                       WHERE it.interaction_id = i.interaction_id))
+                            ORDER BY i.interaction_id)
+                     FROM interactions i
+                     WHERE i.contact_id = c.contact_id
+                        OR i.interaction_id IN (SELECT p.interaction_id FROM interaction_participants p
+                                                WHERE p.contact_id = c.contact_id)),
+                    (SELECT json_agg(json_build_array(o.occasion_id, o.name, o.date, o.recurring, o.recurring_interval, o.details,
+                                             o.occasion_type, o.birth_year, o.interaction_id,
+                                             o.remind_days_before)
+                            ORDER BY o.occasion_id)
+                     FROM occasions o WHERE o.contact_id = c.contact_id)
+                )::text) AS "hash!"
+             FROM contacts c
+             WHERE c.workspace_id = $1
+             ORDER BY c.contact_id"#,
+            workspace_id
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn refresh_contact_scores(&self, limit: i64) -> RepoResult<u64> {
+        let result = sqlx::query!(
+            "WITH stale AS (
+                 SELECT c.contact_id, c.desired_frequency_days, c.updated_at, local.timezone,
+                        (now() AT TIME ZONE local.timezone)::date AS today
+                 FROM contacts c
+                 JOIN workspaces w ON w.workspace_id = c.workspace_id
+                 LEFT JOIN user_preferences p ON p.user_id = w.user_id
+                 CROSS JOIN LATERAL (SELECT COALESCE(p.timezone, 'UTC') AS timezone) local
+                 LEFT JOIN contact_scores s ON s.contact_id = c.contact_id
+                 WHERE s.contact_id IS NULL
+                    OR s.computed_on < (now() AT TIME ZONE local.timezone)::date
+                    OR s.timezone <> local.timezone
+                    OR s.contact_updated_at IS DISTINCT FROM c.updated_at
+                 ORDER BY c.contact_id
+                 LIMIT $1
+             )
+             INSERT INTO contact_scores (contact_id, priority, computed_on, timezone, contact_updated_at)
+             SELECT contact_id, contact_priority(contact_id, desired_frequency_days, today, timezone),
+                    today, timezone, updated_at
+             FROM stale
+             ON CONFLICT (contact_id) DO UPDATE
+             SET priority = EXCLUDED.priority, computed_on = EXCLUDED.computed_on,
+                 timezone = EXCLUDED.timezone, contact_updated_at = EXCLUDED.contact_updated_at",
+            limit
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn list_tags(&self, workspace_id: i32) -> RepoResult<Vec<Tag>> {
+        sqlx::query_as!(
+            Tag,
+            "SELECT tag_id, name, color, details FROM tags WHERE workspace_id = $1 ORDER BY tag_id",
+            workspace_id,
+        )
+        .fetch_all(self.reader())
+        .await
+    }
+
+    async fn get_tag(&self, workspace_id: i32, tag_id: i32) -> RepoResult<Option<Tag>> {
+        sqlx::query_as!(
+            Tag,
+            "SELECT tag_id, name, color, details FROM tags WHERE tag_id = $1 AND workspace_id = $2",
+            tag_id,
+            workspace_id,
+        )
+        .fetch_optional(self.reader())
+        .await
+    }
+
+    async fn tag_usage(&self, workspace_id: i32) -> RepoResult<Vec<TagUsage>> {
+        let rows = sqlx::query!(
+            r#"SELECT t.tag_id, t.name, t.color, t.details, COUNT(ct.contact_id) AS "contact_count!"
+               FROM tags t
+               LEFT JOIN contact_tags ct ON ct.tag_id = t.tag_id
+               WHERE t.workspace_id = $1
+               GROUP BY t.tag_id
+               ORDER BY t.tag_id"#,
+            workspace_id,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| TagUsage {
+                tag: Tag {
+                    tag_id: row.tag_id,
+                    name: row.name,
+                    color: row.color,
+                    details: row.details,
+                },
+                contact_count: row.contact_count,
+            })
+            .collect())
+    }
+
+    async fn create_tag(&self, workspace_id: i32, tag: &NewTagRequest) -> RepoResult<i32> {
+        let record = sqlx::query!(
+            "INSERT INTO tags (workspace_id, name, color, details)
+             VALUES ($1, $2, $3, $4)
+             RETURNING tag_id",
+            workspace_id,
+            tag.name,
+            tag.color.as_deref(),
+            tag.details.as_deref(),
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(record.tag_id)
+    }
+
+    async fn update_tag(
+        &self,
+        workspace_id: i32,
+        tag_id: i32,
+        tag: &NewTagRequest,
+    ) -> RepoResult<bool> {
+        let result = sqlx::query!(
+            "UPDATE tags SET name = $1, color = $2, details = $3 WHERE tag_id = $4 AND workspace_id = $5",
+            tag.name,
+            tag.color.as_deref(),
+            tag.details.as_deref(),
+            tag_id,
+            workspace_id,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn delete_tag(&self, workspace_id: i32, tag_id: i32) -> RepoResult<bool> {
+        let result = sqlx::query!(
+            "DELETE FROM tags WHERE tag_id = $1 AND workspace_id = $2",
+            tag_id,
+            workspace_id,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn delete_unused_tags(&self, workspace_id: i32) -> RepoResult<Vec<i32>> {
+        sqlx::query_scalar!(
+            "DELETE FROM tags t
+             WHERE t.workspace_id = $1
+               AND NOT EXISTS (SELECT 1 FROM contact_tags ct WHERE ct.tag_id = t.tag_id)
+               AND NOT EXISTS (SELECT 1 FROM interaction_tags it WHERE it.tag_id = t.tag_id)
+               AND NOT EXISTS (SELECT 1 FROM shares s WHERE s.tag_id = t.tag_id)
+             RETURNING t.tag_id",
+            workspace_id,
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn owns_tag(&self, workspace_id: i32, tag_id: i32) -> RepoResult<bool> {
+        let found = sqlx::query_scalar!(
+            "SELECT tag_id FROM tags WHERE tag_id = $1 AND workspace_id = $2",
+            tag_id,
+            workspace_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(found.is_some())
+    }
+
+    async fn tags_for_contacts(&self, contact_ids: &[i32]) -> RepoResult<Vec<(i32, Tag)>> {
+        let rows = sqlx::query!(
+            "SELECT ct.contact_id, t.tag_id, t.name, t.color, t.details
+             FROM contact_tags ct
+             JOIN tags t ON ct.tag_id = t.tag_id
+             WHERE ct.contact_id = ANY($1)",
+            contact_ids
+        )
+        .fetch_all(self.reader())
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                (
+                    row.contact_id,
+                    Tag {
+                        tag_id: row.tag_id,
+                        name: row.name,
+                        color: row.color,
+                        details: row.details,
+                    },
+                )
+            })
+            .collect())
+    }
+
+    async fn add_tag_to_contact(&self, contact_id: i32, tag_id: i32) -> RepoResult<()> {
+        sqlx::query!(
+            "INSERT INTO contact_tags (contact_id, tag_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+            contact_id,
+            tag_id,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn remove_tag_from_contact(&self, contact_id: i32, tag_id: i32) -> RepoResult<()> {
+        sqlx::query!(
+            "DELETE FROM contact_tags WHERE contact_id = $1 AND tag_id = $2",
+            contact_id,
+            tag_id,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn add_tag_to_interaction(&self, interaction_id: i32, tag_id: i32) -> RepoResult<()> {
+        sqlx::query!(
+            "INSERT INTO interaction_tags (interaction_id, tag_id) VALUES ($1, $2)
+             ON CONFLICT DO NOTHING",
+            interaction_id,
+            tag_id,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn remove_tag_from_interaction(
+        &self,
+        interaction_id: i32,
+        tag_id: i32,
+    ) -> RepoResult<()> {
+        sqlx::query!(
+            "DELETE FROM interaction_tags WHERE interaction_id = $1 AND tag_id = $2",
+            interaction_id,
+            tag_id,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn add_tag_to_contacts(
+        &self,
+        workspace_id: i32,
+        tag_id: i32,
+        contact_ids: &[i32],
+    ) -> RepoResult<Vec<i32>> {
+        let mut tx = self.pool.begin().await?;
+        let tagged = sqlx::query_scalar!(
+            r#"WITH owned AS (
+                 SELECT DISTINCT c.contact_id
+                 FROM unnest($1::int[]) AS ids(contact_id)
+                 JOIN contacts c ON c.contact_id = ids.contact_id
+                 WHERE c.workspace_id = $2
+             ), inserted AS (
+                 INSERT INTO contact_tags (contact_id, tag_id)
+                 SELECT contact_id, $3 FROM owned
+                 ON CONFLICT DO NOTHING
+             )
+             SELECT contact_id AS "contact_id!" FROM owned"#,
+            contact_ids,
+            workspace_id,
+            tag_id,
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(tagged)
+    }
+
+    async fn interactions_for_contacts(&self, contact_ids: &[i32]) -> RepoResult<Vec<Interaction>> {
+        sqlx::query_as!(
+            Interaction,
+            r#"SELECT i.interaction_id, i.contact_id, i.interaction_date, i.notes,
+                    i.followup_priority AS follow_up_priority,
+                    COALESCE(i.interaction_type::text, t.name) AS "interaction_type?",
+                    ARRAY(SELECT p.contact_id FROM interaction_participants p
+                          WHERE p.interaction_id = i.interaction_id
+                          ORDER BY p.contact_id) AS "contact_ids!",
+                    ARRAY(SELECT it.tag_id FROM interaction_tags it
+                          WHERE it.interaction_id = i.interaction_id
+                          ORDER BY it.tag_id) AS "tag_ids!"
+             FROM interactions i
+             LEFT JOIN interaction_types t ON t.type_id = i.custom_type_id
+             WHERE i.contact_id = ANY($1)
+                OR i.interaction_id IN (SELECT interaction_id FROM interaction_participants
+                                        WHERE contact_id = ANY($1))"#,
+            contact_ids
+        )
+        .fetch_all(self.reader())
+        .await
+    }
+
+    async fn interactions_by_id(
+        &self,
+        workspace_id: i32,
+        interaction_ids: &[i32],
+    ) -> RepoResult<Vec<Interaction>> {
+        sqlx::query_as!(
+            Interaction,
+            r#"SELECT i.interaction_id, i.contact_id, i.interaction_date, i.notes,
+                    i.followup_priority AS follow_up_priority,
+                    COALESCE(i.interaction_type::text, t.name) AS "interaction_type?",
+                    ARRAY(SELECT p.contact_id FROM interaction_participants p
+                          WHERE p.interaction_id = i.interaction_id
+                          ORDER BY p.contact_id) AS "contact_ids!",
+                    ARRAY(SELECT it.tag_id FROM interaction_tags it
+                          WHERE it.interaction_id = i.interaction_id
+                          ORDER BY it.tag_id) AS "tag_ids!"
+             FROM interactions i
+             LEFT JOIN interaction_types t ON t.type_id = i.custom_type_id
+             WHERE i.workspace_id = $1 AND i.interaction_id = ANY($2)
+             ORDER BY i.interaction_id"#,
+            workspace_id,
+            interaction_ids
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn search_notes(
+        &self,
+        workspace_id: i32,
+        query: &str,
+        limit: i64,
+    ) -> RepoResult<Vec<NoteMatch>> {
+        // Notes are escaped before highlighting, so the <mark> tags are the only markup
+        let rows = sqlx::query!(
+            r#"WITH q AS (SELECT websearch_to_tsquery('english', $2) AS query),
+               hits AS (
+                   SELECT i.interaction_id, i.interaction_date, i.contact_id, i.notes,
+                          ts_rank(to_tsvector('english', COALESCE(i.notes, '')), q.query) AS rank
+                   FROM interactions i, q
+                   WHERE i.workspace_id = $1
+                     AND to_tsvector('english', COALESCE(i.notes, '')) @@ q.query
+                   UNION ALL
+                   SELECT NULL, NULL, c.contact_id, c.notes,
+                          ts_rank(to_tsvector('english', COALESCE(c.notes, '')), q.query)
+                   FROM contacts c, q
+                   WHERE c.workspace_id = $1 AND NOT starts_with(c.notes, $4)
+                     AND to_tsvector('english', COALESCE(c.notes, '')) @@ q.query
+               )
+               SELECT h.interaction_id, h.interaction_date, c.contact_id AS "contact_id?",
+                      concat_ws(' ', c.first_name, c.last_name) AS "display_name!", c.avatar_url,
+                      ts_headline('english',
+                                  replace(replace(replace(h.notes, '&', '&amp;'), '<', '&lt;'), '>', '&gt;'),
+                                  q.query,
+                                  'StartSel=<mark>, StopSel=</mark>, MinWords=10, MaxWords=30, MaxFragments=2')
+                          AS "snippet!"
+               FROM hits h
+               CROSS JOIN q
+               LEFT JOIN contacts c ON c.contact_id = h.contact_id
+               ORDER BY h.rank DESC, h.interaction_date DESC NULLS LAST, h.contact_id
+               LIMIT $3"#,
+            workspace_id,
+            query,
+            limit,
+            encryption::ENCRYPTED_PREFIX,
+        )
+        .fetch_all(self.reader())
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| NoteMatch {
+                source: match row.interaction_id {
+                    Some(_) => NoteSource::Interaction,
+                    None => NoteSource::Contact,
+                },
+                interaction_id: row.interaction_id,
+                interaction_date: row.interaction_date,
+                contact: row.contact_id.map(|contact_id| ContactMatch {
+                    contact_id,
+                    display_name: row.display_name,
+                    avatar_url: row.avatar_url,
+                }),
+                snippet: row.snippet,
+            })
+            .collect())
+    }
+
+    async fn latest_interactions(
+        &self,
+        workspace_id: i32,
+        limit: i64,
+    ) -> RepoResult<Vec<Interaction>> {
+        sqlx::query_as!(
+            Interaction,
+            r#"SELECT i.interaction_id, i.contact_id, i.interaction_date, i.notes,
+                    i.followup_priority AS follow_up_priority,
+                    COALESCE(i.interaction_type::text, t.name) AS "interaction_type?",
+                    ARRAY(SELECT p.contact_id FROM interaction_participants p
+                          WHERE p.interaction_id = i.interaction_id
+                          ORDER BY p.contact_id) AS "contact_ids!",
+                    ARRAY(SELECT it.tag_id FROM interaction_tags it
+                          WHERE it.interaction_id = i.interaction_id
+                          ORDER BY it.tag_id) AS "tag_ids!"
+             FROM interactions i
+             LEFT JOIN interaction_types t ON t.type_id = i.custom_type_id
+             WHERE i.workspace_id = $1
+             ORDER BY i.interaction_id DESC
+             LIMIT $2"#,
+            workspace_id,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn interactions_since(
+        &self,
+        workspace_id: i32,
+        since: OffsetDateTime,
+    ) -> RepoResult<Vec<Interaction>> {
+        sqlx::query_as!(
+            Interaction,
+            r#"SELECT i.interaction_id, i.contact_id, i.interaction_date, i.notes,
+                    i.followup_priority AS follow_up_priority,
+                    COALESCE(i.interaction_type::text, t.name) AS "interaction_type?",
+                    ARRAY(SELECT p.contact_id FROM interaction_participants p
+                          WHERE p.interaction_id = i.interaction_id
+                          ORDER BY p.contact_id) AS "contact_ids!",
+                    ARRAY(SELECT it.tag_id FROM interaction_tags it
+                          WHERE it.interaction_id = i.interaction_id
+                          ORDER BY it.tag_id) AS "tag_ids!"
+             FROM interactions i
+             LEFT JOIN interaction_types t ON t.type_id = i.custom_type_id
+             WHERE i.workspace_id = $1 AND i.interaction_date >= $2"#,
+            workspace_id,
+            since
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn count_by_period(
+        &self,
+        workspace_id: i32,
+        metric: StatsMetric,
+        granularity: Granularity,
+        from: Date,
+        to: Date,
+        dates: &LocalDates,
+    ) -> RepoResult<Vec<(Date, i64)>> {
+        let (start, end) = (
+            dates.start_of(from),
+            dates.start_of(to.next_day().unwrap_or(to)),
+        );
+        let unit = granularity.as_str();
+        let timezone = dates.timezone_name();
+        let counts = match metric {
+            StatsMetric::Interactions => sqlx::query!(
+                r#"SELECT date_trunc($2, interaction_date AT TIME ZONE $3)::date AS "bucket!",
+                          COUNT(*) AS "count!"
+                   FROM interactions
+                   WHERE workspace_id = $1 AND interaction_date >= $4 AND interaction_date < $5
+                   GROUP BY 1
+                   ORDER BY 1"#,
+                workspace_id,
+                unit,
+                timezone,
+                start,
+                end
+            )
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .map(|r| (r.bucket, r.count))
+            .collect(),
+            // created_at holds UTC without a zone
+            StatsMetric::Contacts => sqlx::query!(
+                r#"SELECT date_trunc($2, (created_at AT TIME ZONE 'UTC') AT TIME ZONE $3)::date
+                              AS "bucket!",
+                          COUNT(*) AS "count!"
+                   FROM contacts
+                   WHERE workspace_id = $1
+                     AND created_at >= ($4::timestamptz AT TIME ZONE 'UTC')
+                     AND created_at < ($5::timestamptz AT TIME ZONE 'UTC')
+                   GROUP BY 1
+                   ORDER BY 1"#,
+                workspace_id,
+                unit,
+                timezone,
+                start,
+                end
+            )
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .map(|r| (r.bucket, r.count))
+            .collect(),
+        };
+        Ok(counts)
+    }
+
+    async fn contact_history(
+        &self,
+        contact_id: i32,
+        after: Option<Cursor>,
+        limit: Option<i64>,
+    ) -> RepoResult<Vec<ContactChange>> {
+        let changes = sqlx::query_as!(
+            ContactChange,
+            r#"SELECT change_id, field, old_value AS before, new_value AS after, changed_at
+             FROM contact_changes
+             WHERE contact_id = $1
+               AND ($2::timestamptz IS NULL OR (changed_at, change_id) < ($2, $3))
+             ORDER BY changed_at DESC, change_id DESC
+             LIMIT $4"#,
+            contact_id,
+            after.map(|c| c.at),
+            after.map_or(0, |c| c.id),
+            limit,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        changes
+            .into_iter()
+            .map(|mut change| {
+                if ENCRYPTED_FIELDS.contains(&change.field.as_str()) {
+                    change.before = open_note(self.notes.as_ref(), change.before)?;
+                    change.after = open_note(self.notes.as_ref(), change.after)?;
+                }
+                Ok(change)
+            })
+            .collect()
+    }
+
+    async fn contact_timeline(
+        &self,
+        contact_id: i32,
+        interaction_type: Option<&str>,
+        tag_id: Option<i32>,
+        after: Option<Cursor>,
+        limit: Option<i64>,
+    ) -> RepoResult<Vec<Interaction>> {
+        sqlx::query_as!(
+            Interaction,
+            r#"SELECT i.interaction_id, i.contact_id, i.interaction_date, i.notes,
+                    i.followup_priority AS follow_up_priority,
+                    COALESCE(i.interaction_type::text, t.name) AS "interaction_type?",
+                    ARRAY(SELECT p.contact_id FROM interaction_participants p
+                          WHERE p.interaction_id = i.interaction_id
+                          ORDER BY p.contact_id) AS "contact_ids!",
+                    ARRAY(SELECT it.tag_id FROM interaction_tags it
+                          WHERE it.interaction_id = i.interaction_id
+                          ORDER BY it.tag_id) AS "tag_ids!"
+             FROM interactions i
+             LEFT JOIN interaction_types t ON t.type_id = i.custom_type_id
+             WHERE (i.contact_id = $1
+                    OR i.interaction_id IN (SELECT interaction_id FROM interaction_participants
+                                            WHERE contact_id = $1))
+               AND ($2::text IS NULL OR COALESCE(i.interaction_type::text, t.name) = $2)
+               AND ($3::int IS NULL OR EXISTS (SELECT 1 FROM interaction_tags it
+                                               WHERE it.interaction_id = i.interaction_id
+                                                 AND it.tag_id = $3))
+               AND ($4::timestamptz IS NULL OR (i.interaction_date, i.interaction_id) < ($4, $5))
+             ORDER BY i.interaction_date DESC, i.interaction_id DESC
+             LIMIT $6"#,
+            contact_id,
+            interaction_type,
+            tag_id,
+            after.map(|c| c.at),
+            after.map_or(0, |c| c.id),
+            limit,
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn create_interaction(
+        &self,
+        workspace_id: i32,
+        interaction: &NewInteractionRequest,
+        follow_up: Option<&NewOccasionRequest>,
+    ) -> RepoResult<(i32, Option<i32>)> {
+        let mut tx = self.pool.begin().await?;
+        let interaction_id = insert_interaction(&mut tx, workspace_id, interaction).await?;
+        let occasion_id = match follow_up {
+            Some(occasion) => {
+                Some(insert_occasion(&mut *tx, workspace_id, occasion, Some(interaction_id)).await?)
+            }
+            None => None,
+        };
+        tx.commit().await?;
+        Ok((interaction_id, occasion_id))
+    }
+
+    async fn update_interaction(
+        &self,
+        workspace_id: i32,
+        interaction_id: i32,
+        interaction: &NewInteractionRequest,
+    ) -> RepoResult<bool> {
+        let mut tx = self.pool.begin().await?;
+        // An unassigned interaction takes the request's contact
+        let result = sqlx::query!(
+            "UPDATE interactions SET interaction_date = $1, notes = $2, followup_priority = $3,
+                 interaction_type = (SELECT k FROM unnest(enum_range(NULL::interaction_kind)) k WHERE k::text = $6),
+                 custom_type_id = (SELECT type_id FROM interaction_types WHERE workspace_id = $5 AND name = $6),
+                 contact_id = COALESCE(contact_id, (SELECT contact_id FROM contacts WHERE contact_id = $7 AND workspace_id = $5))
+             WHERE interaction_id = $4 AND workspace_id = $5",
+            interaction.interaction_date,
+            interaction.notes,
+            interaction.follow_up_priority,
+            interaction_id,
+            workspace_id,
+            interaction.interaction_type,
+            interaction.contact_id,
+        )
+        .execute(&mut *tx)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Ok(false);
+        }
+        sqlx::query!(
+            "DELETE FROM interaction_participants WHERE interaction_id = $1",
+            interaction_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        save_participants(
+            &mut *tx,
+            workspace_id,
+            interaction_id,
+            &interaction.contact_ids,
+        )
+        .await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn delete_interaction(&self, workspace_id: i32, interaction_id: i32) -> RepoResult<bool> {
+        let result = sqlx::query!(
+            "DELETE FROM interactions WHERE interaction_id = $1 AND workspace_id = $2",
+            interaction_id,
+            workspace_id,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn owns_interaction(&self, workspace_id: i32, interaction_id: i32) -> RepoResult<bool> {
+        let found = sqlx::query_scalar!(
+            "SELECT interaction_id FROM interactions WHERE interaction_id = $1 AND workspace_id = $2",
+            interaction_id,
+            workspace_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(found.is_some())
+    }
+
+    async fn list_attachments(
+        &self,
+        workspace_id: i32,
+        interaction_id: i32,
+    ) -> RepoResult<Vec<Attachment>> {
+        sqlx::query_as!(
+            Attachment,
+            r#"SELECT attachment_id, interaction_id, file_name, content_type, size_bytes,
+                      transcription AS "transcription: TranscriptionStatus", created_at
+               FROM interaction_attachments
+               WHERE interaction_id = $1 AND workspace_id = $2
+               ORDER BY attachment_id"#,
+            interaction_id,
+            workspace_id
+        )
+        .fetch_all(self.reader())
+        .await
+    }
+
+    async fn create_attachment(
+        &self,
+        workspace_id: i32,
+        interaction_id: i32,
+        attachment: &NewAttachment,
+    ) -> RepoResult<i32> {
+        sqlx::query_scalar!(
+            "INSERT INTO interaction_attachments
+                 (workspace_id, interaction_id, file_name, content_type, size_bytes, data,
+                  transcription)
+             VALUES ($1, $2, $3, $4, $5, $6, $7)
+             RETURNING attachment_id",
+            workspace_id,
+            interaction_id,
+            attachment.file_name,
+            attachment.content_type,
+            attachment.data.len() as i32,
+            attachment.data,
+            attachment
+                .transcribe
+                .then_some(TranscriptionStatus::Pending) as Option<TranscriptionStatus>,
+        )
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    async fn attachment_file(
+        &self,
+        workspace_id: i32,
+        attachment_id: i32,
+    ) -> RepoResult<Option<AttachmentFile>> {
+        sqlx::query_as!(
+            AttachmentFile,
+            "SELECT file_name, content_type, data FROM interaction_attachments
+             WHERE attachment_id = $1 AND workspace_id = $2",
+            attachment_id,
+            workspace_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    async fn delete_attachment(
+        &self,
+        workspace_id: i32,
+        attachment_id: i32,
+    ) -> RepoResult<Option<i32>> {
+        let interaction_id = sqlx::query_scalar!(
+            "DELETE FROM interaction_attachments WHERE attachment_id = $1 AND workspace_id = $2
+             RETURNING interaction_id",
+            attachment_id,
+            workspace_id,
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(interaction_id)
+    }
+
+    async fn owns_attachment(&self, workspace_id: i32, attachment_id: i32) -> RepoResult<bool> {
+        let found = sqlx::query_scalar!(
+            "SELECT attachment_id FROM interaction_attachments
+             WHERE attachment_id = $1 AND workspace_id = $2",
+            attachment_id,
+            workspace_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(found.is_some())
+    }
+
+    async fn pending_transcriptions(&self, limit: i64) -> RepoResult<Vec<PendingTranscription>> {
+        sqlx::query_as!(
+            PendingTranscription,
+            r#"SELECT attachment_id, workspace_id, file_name, content_type, data,
+                      transcription_attempts AS attempts
+               FROM interaction_attachments
+               WHERE transcription = 'pending'
+               ORDER BY attachment_id
+               LIMIT $1"#,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn save_transcript(
+        &self,
+        workspace_id: i32,
+        attachment_id: i32,
+        transcript: &str,
+    ) -> RepoResult<bool> {
+        let mut tx = self.pool.begin().await?;
+        let interaction_id = sqlx::query_scalar!(
+            "UPDATE interaction_attachments SET transcription = 'done'
+             WHERE attachment_id = $1 AND workspace_id = $2 AND transcription = 'pending'
+             RETURNING interaction_id",
+            attachment_id,
+            workspace_id
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(interaction_id) = interaction_id else {
+            return Ok(false);
+        };
+        sqlx::query!(
+            "UPDATE interactions
+             SET notes = CASE WHEN COALESCE(notes, '') = '' THEN $3
+                              ELSE notes || E'\\n\\n' || $3 END,
+                 updated_at = CURRENT_TIMESTAMP
+             WHERE interaction_id = $1 AND workspace_id = $2",
+            interaction_id,
+            workspace_id,
+            transcript
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn transcription_failed(
+        &self,
+        workspace_id: i32,
+        attachment_id: i32,
+        give_up: bool,
+    ) -> RepoResult<()> {
+        sqlx::query!(
+            "UPDATE interaction_attachments
+             SET transcription_attempts = transcription_attempts + 1,
+                 transcription = CASE WHEN $3 THEN 'failed' ELSE transcription END
+             WHERE attachment_id = $1 AND workspace_id = $2",
+            attachment_id,
+            workspace_id,
+            give_up
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn pending_avatar_lookups(
+        &self,
+        now: OffsetDateTime,
+        limit: i64,
+    ) -> RepoResult<Vec<PendingAvatarLookup>> {
+        sqlx::query_as!(
+            PendingAvatarLookup,
+            r#"SELECT c.contact_id, c.workspace_id, c.email AS "email!"
+               FROM contacts c
+               LEFT JOIN avatar_lookups l ON l.contact_id = c.contact_id
+               WHERE c.email <> '' AND c.avatar_url IS NULL AND NOT c.archived
+                 AND (l.next_lookup_at IS NULL OR l.next_lookup_at <= $1)
+               ORDER BY l.next_lookup_at NULLS FIRST, c.contact_id
+               LIMIT $2"#,
+            now,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn record_avatar_lookup(
+        &self,
+        workspace_id: i32,
+        contact_id: i32,
+        email: &str,
+        avatar_url: Option<&str>,
+        next_lookup_at: OffsetDateTime,
+    ) -> RepoResult<bool> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query!(
+            "INSERT INTO avatar_lookups (contact_id, workspace_id, next_lookup_at)
+             SELECT contact_id, workspace_id, $3 FROM contacts
+             WHERE contact_id = $1 AND workspace_id = $2
+             ON CONFLICT (contact_id) DO UPDATE SET next_lookup_at = EXCLUDED.next_lookup_at",
+            contact_id,
+            workspace_id,
+            next_lookup_at
+        )
+        .execute(&mut *tx)
+        .await?;
+        let stored = match avatar_url {
+            Some(avatar_url) => {
+                sqlx::query!(
+                    "UPDATE contacts SET avatar_url = $1
+                     WHERE contact_id = $2 AND workspace_id = $3 AND email = $4
+                       AND avatar_url IS NULL",
+                    avatar_url,
+                    contact_id,
+                    workspace_id,
+                    email
+                )
+                .execute(&mut *tx)
+                .await?
+                .rows_affected()
+                    > 0
+            }
+            None => false,
+        };
+        tx.commit().await?;
+        Ok(stored)
+    }
+
+    async fn list_captures(&self, workspace_id: i32) -> RepoResult<Vec<Capture>> {
+        sqlx::query_as!(
+            Capture,
+            "SELECT capture_id, text, captured_at FROM inbox_captures
+             WHERE workspace_id = $1 ORDER BY captured_at, capture_id",
+            workspace_id
+        )
+        .fetch_all(self.reader())
+        .await
+    }
+
+    async fn get_capture(&self, workspace_id: i32, capture_id: i32) -> RepoResult<Option<Capture>> {
+        sqlx::query_as!(
+            Capture,
+            "SELECT capture_id, text, captured_at FROM inbox_captures
+             WHERE capture_id = $1 AND workspace_id = $2",
+            capture_id,
+            workspace_id
+        )
+        .fetch_optional(self.reader())
+        .await
+    }
+
+    async fn create_capture(&self, workspace_id: i32, text: &str) -> RepoResult<i32> {
+        sqlx::query_scalar!(
+            "INSERT INTO inbox_captures (workspace_id, text) VALUES ($1, $2) RETURNING capture_id",
+            workspace_id,
+            text,
+        )
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    async fn resolve_capture(
+        &self,
+        workspace_id: i32,
+        capture_id: i32,
+        interaction: &NewInteractionRequest,
+    ) -> RepoResult<Option<i32>> {
+        let mut tx = self.pool.begin().await?;
+        let deleted = sqlx::query!(
+            "DELETE FROM inbox_captures WHERE capture_id = $1 AND workspace_id = $2",
+            capture_id,
+            workspace_id,
+        )
+        .execute(&mut *tx)
+        .await?;
+        if deleted.rows_affected() == 0 {
+            return Ok(None);
+        }
+        let interaction_id = insert_interaction(&mut tx, workspace_id, interaction).await?;
+        tx.commit().await?;
+        Ok(Some(interaction_id))
+    }
+
+    async fn delete_capture(&self, workspace_id: i32, capture_id: i32) -> RepoResult<bool> {
+        let result = sqlx::query!(
+            "DELETE FROM inbox_captures WHERE capture_id = $1 AND workspace_id = $2",
+            capture_id,
+            workspace_id,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn owns_capture(&self, workspace_id: i32, capture_id: i32) -> RepoResult<bool> {
+        let found = sqlx::query_scalar!(
+            "SELECT capture_id FROM inbox_captures WHERE capture_id = $1 AND workspace_id = $2",
+            capture_id,
+            workspace_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(found.is_some())
+    }
+
+    async fn list_interaction_types(
+        &self,
+        workspace_id: i32,
+    ) -> RepoResult<Vec<CustomInteractionType>> {
+        sqlx::query_as!(
+            CustomInteractionType,
+            "SELECT type_id, name FROM interaction_types WHERE workspace_id = $1 ORDER BY name",
+            workspace_id
+        )
+        .fetch_all(self.reader())
+        .await
+    }
+
+    async fn create_interaction_type(&self, workspace_id: i32, name: &str) -> RepoResult<i32> {
+        let record = sqlx::query!(
+            "INSERT INTO interaction_types (workspace_id, name) VALUES ($1, $2) RETURNING type_id",
+            workspace_id,
+            name,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(record.type_id)
+    }
+
+    async fn delete_interaction_type(&self, workspace_id: i32, type_id: i32) -> RepoResult<bool> {
+        let result = sqlx::query!(
+            "DELETE FROM interaction_types WHERE type_id = $1 AND workspace_id = $2",
+            type_id,
+            workspace_id,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn owns_interaction_type(&self, workspace_id: i32, type_id: i32) -> RepoResult<bool> {
+        let found = sqlx::query_scalar!(
+            "SELECT type_id FROM interaction_types WHERE type_id = $1 AND workspace_id = $2",
+            type_id,
+            workspace_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(found.is_some())
+    }
+
+    async fn occasions_for_contacts(&self, contact_ids: &[i32]) -> RepoResult<Vec<Occasion>> {
+        sqlx::query_as!(
+            Occasion,
+            r#"SELECT occasion_id, contact_id, name, date, recurring, recurring_interval, details,
+                    occasion_type AS "occasion_type: OccasionType", birth_year,
+                    remind_days_before, NULL::INT AS "upcoming_age", interaction_id
+             FROM occasions
+             WHERE contact_id = ANY($1)"#,
+            contact_ids
+        )
+        .fetch_all(self.reader())
+        .await
+    }
+
+    async fn create_occasion(
+        &self,
+        workspace_id: i32,
+        occasion: &NewOccasionRequest,
+    ) -> RepoResult<i32> {
+        insert_occasion(&self.pool, workspace_id, occasion, None).await
+    }
+
+    async fn update_occasion(
+        &self,
+        workspace_id: i32,
+        occasion_id: i32,
+        occasion: &NewOccasionRequest,
+    ) -> RepoResult<bool> {
+        // An unassigned occasion takes the request's contact
+        let result = sqlx::query!(
+            "UPDATE occasions SET name = $1, date = $2, recurring = $3, recurring_interval = $4, details = $5,
+                                  occasion_type = $6, birth_year = $7, remind_days_before = $11,
+                                  contact_id = COALESCE(contact_id, (SELECT contact_id FROM contacts WHERE contact_id = $10 AND workspace_id = $9))
+             WHERE occasion_id = $8 AND workspace_id = $9",
+            occasion.name,
+            occasion.date,
+            occasion.recurring,
+            occasion.recurring_interval,
+            occasion.details.as_deref(),
+            occasion.occasion_type as OccasionType,
+            occasion.birth_year,
+            occasion_id,
+            workspace_id,
+            occasion.contact_id,
+            occasion.remind_days_before.as_deref(),
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn delete_occasion(&self, workspace_id: i32, occasion_id: i32) -> RepoResult<bool> {
+        let result = sqlx::query!(
+            "DELETE FROM occasions WHERE occasion_id = $1 AND workspace_id = $2",
+            occasion_id,
+            workspace_id,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn get_occasion(
+        &self,
+        workspace_id: i32,
+        occasion_id: i32,
+    ) -> RepoResult<Option<Occasion>> {
+        sqlx::query_as!(
+            Occasion,
+            r#"SELECT occasion_id, contact_id, name, date, recurring, recurring_interval, details,
+                    occasion_type AS "occasion_type: OccasionType", birth_year,
+                    remind_days_before, NULL::INT AS "upcoming_age", interaction_id
+             FROM occasions
+             WHERE occasion_id = $1 AND workspace_id = $2"#,
+            occasion_id,
+            workspace_id
+        )
+        .fetch_optional(self.reader())
+        .await
+    }
+
+    async fn owns_occasion(&self, workspace_id: i32, occasion_id: i32) -> RepoResult<bool> {
+        let found = sqlx::query_scalar!(
+            "SELECT occasion_id FROM occasions WHERE occasion_id = $1 AND workspace_id = $2",
+            occasion_id,
+            workspace_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(found.is_some())
+    }
+
+    async fn social_profiles_for_contacts(
+        &self,
+        contact_ids: &[i32],
+    ) -> RepoResult<Vec<SocialProfile>> {
+        let rows = sqlx::query!(
+            r#"SELECT social_profile_id, contact_id, platform AS "platform: SocialPlatform", url
+             FROM social_profiles
+             WHERE contact_id = ANY($1)
+             ORDER BY social_profile_id"#,
+            contact_ids
+        )
+        .fetch_all(self.reader())
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| SocialProfile {
+                social_profile_id: row.social_profile_id,
+                contact_id: row.contact_id,
+                platform: row.platform,
+                url: row.url,
+                preview: None,
+            })
+            .collect())
+    }
+
+    async fn create_social_profile(
+        &self,
+        workspace_id: i32,
+        profile: &NewSocialProfileRequest,
+    ) -> RepoResult<i32> {
+        sqlx::query_scalar!(
+            "INSERT INTO social_profiles (workspace_id, contact_id, platform, url)
+             VALUES ($1, $2, $3, $4)
+             RETURNING social_profile_id",
+            workspace_id,
+            profile.contact_id,
+            profile.platform as SocialPlatform,
+            profile.url,
+        )
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    async fn update_social_profile(
+        &self,
+        workspace_id: i32,
+        social_profile_id: i32,
+        profile: &NewSocialProfileRequest,
+    ) -> RepoResult<bool> {
+        let result = sqlx::query!(
+            "UPDATE social_profiles SET platform = $1, url = $2
+             WHERE social_profile_id = $3 AND workspace_id = $4",
+            profile.platform as SocialPlatform,
+            profile.url,
+            social_profile_id,
+            workspace_id,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn delete_social_profile(
+        &self,
+        workspace_id: i32,
+        social_profile_id: i32,
+    ) -> RepoResult<bool> {
+        let result = sqlx::query!(
+            "DELETE FROM social_profiles WHERE social_profile_id = $1 AND workspace_id = $2",
+            social_profile_id,
+            workspace_id,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn get_social_profile(
+        &self,
+        workspace_id: i32,
+        social_profile_id: i32,
+    ) -> RepoResult<Option<SocialProfile>> {
+        let row = sqlx::query!(
+            r#"SELECT social_profile_id, contact_id, platform AS "platform: SocialPlatform", url
+             FROM social_profiles
+             WHERE social_profile_id = $1 AND workspace_id = $2"#,
+            social_profile_id,
+            workspace_id
+        )
+        .fetch_optional(self.reader())
+        .await?;
+        Ok(row.map(|row| SocialProfile {
+            social_profile_id: row.social_profile_id,
+            contact_id: row.contact_id,
+            platform: row.platform,
+            url: row.url,
+            preview: None,
+        }))
+    }
+
+    async fn owns_social_profile(
+        &self,
+        workspace_id: i32,
+        social_profile_id: i32,
+    ) -> RepoResult<bool> {
+        let found = sqlx::query_scalar!(
+            "SELECT social_profile_id FROM social_profiles
+             WHERE social_profile_id = $1 AND workspace_id = $2",
+            social_profile_id,
+            workspace_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(found.is_some())
+    }
+
+    async fn gift_ideas_for_contacts(&self, contact_ids: &[i32]) -> RepoResult<Vec<GiftIdea>> {
+        sqlx::query_as!(
+            GiftIdea,
+            r#"SELECT gift_idea_id, contact_id, occasion_id, idea,
+                      status AS "status: GiftStatus", price_cents
+             FROM gift_ideas
+             WHERE contact_id = ANY($1)
+             ORDER BY gift_idea_id"#,
+            contact_ids
+        )
+        .fetch_all(self.reader())
+        .await
+    }
+
+    async fn create_gift_idea(
+        &self,
+        workspace_id: i32,
+        gift_idea: &NewGiftIdeaRequest,
+    ) -> RepoResult<i32> {
+        sqlx::query_scalar!(
+            "INSERT INTO gift_ideas (workspace_id, contact_id, occasion_id, idea, status, price_cents)
+             VALUES ($1, $2, $3, $4, $5, $6)
+             RETURNING gift_idea_id",
+            workspace_id,
+            gift_idea.contact_id,
+            gift_idea.occasion_id,
+            gift_idea.idea,
+            gift_idea.status as GiftStatus,
+            gift_idea.price_cents,
+        )
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    async fn update_gift_idea(
+        &self,
+        workspace_id: i32,
+        gift_idea_id: i32,
+        gift_idea: &NewGiftIdeaRequest,
+    ) -> RepoResult<bool> {
+        let result = sqlx::query!(
+            "UPDATE gift_ideas SET occasion_id = $1, idea = $2, status = $3, price_cents = $4
+             WHERE gift_idea_id = $5 AND workspace_id = $6",
+            gift_idea.occasion_id,
+            gift_idea.idea,
+            gift_idea.status as GiftStatus,
+            gift_idea.price_cents,
+            gift_idea_id,
+            workspace_id,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn delete_gift_idea(&self, workspace_id: i32, gift_idea_id: i32) -> RepoResult<bool> {
+        let result = sqlx::query!(
+            "DELETE FROM gift_ideas WHERE gift_idea_id = $1 AND workspace_id = $2",
+            gift_idea_id,
+            workspace_id,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn get_gift_idea(
+        &self,
+        workspace_id: i32,
+        gift_idea_id: i32,
+    ) -> RepoResult<Option<GiftIdea>> {
+        sqlx::query_as!(
+            GiftIdea,
+            r#"SELECT gift_idea_id, contact_id, occasion_id, idea,
+                      status AS "status: GiftStatus", price_cents
+             FROM gift_ideas
+             WHERE gift_idea_id = $1 AND workspace_id = $2"#,
+            gift_idea_id,
+            workspace_id
+        )
+        .fetch_optional(self.reader())
+        .await
+    }
+
+    async fn owns_gift_idea(&self, workspace_id: i32, gift_idea_id: i32) -> RepoResult<bool> {
+        let found = sqlx::query_scalar!(
+            "SELECT gift_idea_id FROM gift_ideas WHERE gift_idea_id = $1 AND workspace_id = $2",
+            gift_idea_id,
+            workspace_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(found.is_some())
+    }
+
+    async fn list_groups(&self, workspace_id: i32) -> RepoResult<Vec<Group>> {
+        sqlx::query_as!(
+            Group,
+            r#"SELECT g.group_id, g.name, g.description,
+                      ARRAY(SELECT m.contact_id FROM contact_group_members m
+                            WHERE m.group_id = g.group_id
+                            ORDER BY m.position, m.contact_id) AS "contact_ids!"
+             FROM contact_groups g
+             WHERE g.workspace_id = $1
+             ORDER BY lower(g.name), g.group_id"#,
+            workspace_id
+        )
+        .fetch_all(self.reader())
+        .await
+    }
+
+    async fn get_group(&self, workspace_id: i32, group_id: i32) -> RepoResult<Option<Group>> {
+        sqlx::query_as!(
+            Group,
+            r#"SELECT g.group_id, g.name, g.description,
+                      ARRAY(SELECT m.contact_id FROM contact_group_members m
+                            WHERE m.group_id = g.group_id
+                            ORDER BY m.position, m.contact_id) AS "contact_ids!"
+             FROM contact_groups g
+             WHERE g.group_id = $1 AND g.workspace_id = $2"#,
+            group_id,
+            workspace_id
+        )
+        .fetch_optional(self.reader())
+        .await
+    }
+
+    async fn create_group(&self, workspace_id: i32, group: &NewGroupRequest) -> RepoResult<i32> {
+        sqlx::query_scalar!(
+            "INSERT INTO contact_groups (workspace_id, name, description)
+             VALUES ($1, $2, $3)
+             RETURNING group_id",
+            workspace_id,
+            group.name,
+            group.description,
+        )
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    async fn update_group(
+        &self,
+        workspace_id: i32,
+        group_id: i32,
+        group: &NewGroupRequest,
+    ) -> RepoResult<bool> {
+        let result = sqlx::query!(
+            "UPDATE contact_groups SET name = $1, description = $2
+             WHERE group_id = $3 AND workspace_id = $4",
+            group.name,
+            group.description,
+            group_id,
+            workspace_id,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn delete_group(&self, workspace_id: i32, group_id: i32) -> RepoResult<bool> {
+        let result = sqlx::query!(
+            "DELETE FROM contact_groups WHERE group_id = $1 AND workspace_id = $2",
+            group_id,
+            workspace_id,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn owns_group(&self, workspace_id: i32, group_id: i32) -> RepoResult<bool> {
+        let found = sqlx::query_scalar!(
+            "SELECT group_id FROM contact_groups WHERE group_id = $1 AND workspace_id = $2",
+            group_id,
+            workspace_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(found.is_some())
+    }
+
+    async fn set_group_members(
+        &self,
+        workspace_id: i32,
+        group_id: i32,
+        contact_ids: &[i32],
+    ) -> RepoResult<bool> {
+        let mut tx = self.pool.begin().await?;
+        // Locks the group, so concurrent changes to its members go one after the other
+        let group = sqlx::query_scalar!(
+            "UPDATE contact_groups SET updated_at = CURRENT_TIMESTAMP
+             WHERE group_id = $1 AND workspace_id = $2
+             RETURNING group_id",
+            group_id,
+            workspace_id
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+        let owned = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) AS "count!" FROM contacts
+             WHERE contact_id = ANY($1) AND workspace_id = $2"#,
+            contact_ids,
+            workspace_id
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        if group.is_none() || owned != contact_ids.len() as i64 {
+            return Ok(false);
+        }
+        sqlx::query!(
+            "DELETE FROM contact_group_members WHERE group_id = $1",
+            group_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "INSERT INTO contact_group_members (group_id, contact_id, position)
+             SELECT $1, m.contact_id, m.position - 1
+             FROM unnest($2::int[]) WITH ORDINALITY AS m(contact_id, position)",
+            group_id,
+            contact_ids
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn add_group_member(&self, group_id: i32, contact_id: i32) -> RepoResult<bool> {
+        let result = sqlx::query!(
+            "INSERT INTO contact_group_members (group_id, contact_id, position)
+             SELECT $1, $2, COALESCE(MAX(position) + 1, 0)
+             FROM contact_group_members WHERE group_id = $1
+             ON CONFLICT DO NOTHING",
+            group_id,
+            contact_id
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn remove_group_member(&self, group_id: i32, contact_id: i32) -> RepoResult<bool> {
+        let result = sqlx::query!(
+            "DELETE FROM contact_group_members WHERE group_id = $1 AND contact_id = $2",
+            group_id,
+            contact_id
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn list_shares(&self, user_id: i32) -> RepoResult<Vec<Share>> {
+        sqlx::query_as!(
+            Share,
+            r#"SELECT s.share_id, s.owner_id, o.email AS owner_email, s.grantee_id,
+                      g.email AS grantee_email, s.tag_id, s.contact_id,
+                      s.permission AS "permission: SharePermission"
+               FROM shares s
+               JOIN users o ON o.user_id = s.owner_id
+               JOIN users g ON g.user_id = s.grantee_id
+               WHERE $1 IN (s.owner_id, s.grantee_id)
+               ORDER BY s.share_id"#,
+            user_id
+        )
+        .fetch_all(self.reader())
+        .await
+    }
+
+    async fn create_shares(
+        &self,
+        owner_id: i32,
+        grantee_id: i32,
+        tag_id: Option<i32>,
+        contact_ids: &[i32],
+        permission: SharePermission,
+    ) -> RepoResult<Vec<i32>> {
+        // One (tag_id, contact_id) pair per share; a repeated contact would make the upsert
+        // touch the same row twice
+        let (tag_ids, contact_ids): (Vec<Option<i32>>, Vec<Option<i32>>) = match tag_id {
+            Some(tag_id) => (vec![Some(tag_id)], vec![None]),
+            None => {
+                let mut contact_ids = contact_ids.to_vec();
+                contact_ids.sort_unstable();
+                contact_ids.dedup();
+                contact_ids.into_iter().map(|id| (None, Some(id))).unzip()
+            }
+        };
+        sqlx::query_scalar!(
+            "INSERT INTO shares (owner_id, grantee_id, tag_id, contact_id, permission)
+             SELECT $1, $2, t.tag_id, t.contact_id, $5
+             FROM unnest($3::int[], $4::int[]) AS t(tag_id, contact_id)
+             ON CONFLICT (owner_id, grantee_id, tag_id, contact_id)
+             DO UPDATE SET permission = EXCLUDED.permission
+             RETURNING share_id",
+            owner_id,
+            grantee_id,
+            &tag_ids as &[Option<i32>],
+            &contact_ids as &[Option<i32>],
+            permission as SharePermission,
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn delete_share(&self, user_id: i32, share_id: i32) -> RepoResult<bool> {
+        let result = sqlx::query!(
+            "DELETE FROM shares WHERE share_id = $1 AND $2 IN (owner_id, grantee_id)",
+            share_id,
+            user_id,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn owns_share(&self, user_id: i32, share_id: i32) -> RepoResult<bool> {
+        let found = sqlx::query_scalar!(
+            "SELECT share_id FROM shares WHERE share_id = $1 AND $2 IN (owner_id, grantee_id)",
+            share_id,
+            user_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(found.is_some())
+    }
+
+    async fn contact_access(
+        &self,
+        user_id: i32,
+        workspace_id: i32,
+        contact_id: i32,
+    ) -> RepoResult<Option<ContactAccess>> {
+        let row = sqlx::query!(
+            r#"SELECT w.user_id AS owner_id, c.workspace_id,
+                      (SELECT max(s.permission)
+                       FROM shares s
+                       WHERE s.grantee_id = $1 AND s.owner_id = w.user_id
+                         AND (s.contact_id = c.contact_id
+                              OR s.tag_id IN (SELECT tag_id FROM contact_tags
+                                              WHERE contact_id = c.contact_id))
+                      ) AS "shared: SharePermission"
+               FROM contacts c
+               JOIN workspaces w ON w.workspace_id = c.workspace_id
+               WHERE c.contact_id = $2"#,
+            user_id,
+            contact_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row.and_then(|r| {
+            if r.workspace_id == workspace_id {
+                Some(ContactAccess {
+                    owner_id: r.owner_id,
+                    workspace_id: r.workspace_id,
+                    shared: None,
+                })
+            } else if r.owner_id != user_id {
+                r.shared.map(|permission| ContactAccess {
+                    owner_id: r.owner_id,
+                    workspace_id: r.workspace_id,
+                    shared: Some(permission),
+                })
+            } else {
+                None
+            }
+        }))
+    }
+
+    async fn shared_contacts(&self, user_id: i32) -> RepoResult<Vec<ContactShare>> {
+        sqlx::query_as!(
+            ContactShare,
+            r#"SELECT c.contact_id, w.user_id AS owner_id, c.workspace_id, u.name AS owner_name,
+                      u.email AS owner_email, max(s.permission) AS "permission!: SharePermission"
+               FROM shares s
+               JOIN workspaces w ON w.user_id = s.owner_id
+               JOIN contacts c ON c.workspace_id = w.workspace_id
+                   AND (c.contact_id = s.contact_id
+                        OR EXISTS (SELECT 1 FROM contact_tags ct
+                                   WHERE ct.contact_id = c.contact_id AND ct.tag_id = s.tag_id))
+               JOIN users u ON u.user_id = w.user_id
+               WHERE s.grantee_id = $1
+               GROUP BY c.contact_id, w.user_id, u.user_id
+               ORDER BY w.user_id, c.workspace_id, c.contact_id"#,
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn list_filters(&self, workspace_id: i32) -> RepoResult<Vec<SavedFilter>> {
+        let rows = sqlx::query!(
+            r#"SELECT filter_id, name, query AS "query: Json<FilterQuery>"
+             FROM saved_filters WHERE workspace_id = $1 ORDER BY name"#,
+            workspace_id
+        )
+        .fetch_all(self.reader())
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|r| SavedFilter {
+                filter_id: r.filter_id,
+                name: r.name,
+                query: r.query.0,
+            })
+            .collect())
+    }
+
+    async fn get_filter(
+        &self,
+        workspace_id: i32,
+        filter_id: i32,
+    ) -> RepoResult<Option<SavedFilter>> {
+        let row = sqlx::query!(
+            r#"SELECT filter_id, name, query AS "query: Json<FilterQuery>"
+             FROM saved_filters WHERE filter_id = $1 AND workspace_id = $2"#,
+            filter_id,
+            workspace_id
+        )
+        .fetch_optional(self.reader())
+        .await?;
+        Ok(row.map(|r| SavedFilter {
+            filter_id: r.filter_id,
+            name: r.name,
+            query: r.query.0,
+        }))
+    }
+
+    async fn create_filter(
+        &self,
+        workspace_id: i32,
+        filter: &NewSavedFilterRequest,
+    ) -> RepoResult<i32> {
+        let record = sqlx::query!(
+            "INSERT INTO saved_filters (workspace_id, name, query) VALUES ($1, $2, $3) RETURNING filter_id",
+            workspace_id,
+            filter.name,
+            Json(&filter.query) as _,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(record.filter_id)
+    }
+
+    async fn update_filter(
+        &self,
+        workspace_id: i32,
+        filter_id: i32,
+        filter: &NewSavedFilterRequest,
+    ) -> RepoResult<bool> {
+        let result = sqlx::query!(
+            "UPDATE saved_filters SET name = $1, query = $2 WHERE filter_id = $3 AND workspace_id = $4",
+            filter.name,
+            Json(&filter.query) as _,
+            filter_id,
+            workspace_id,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn delete_filter(&self, workspace_id: i32, filter_id: i32) -> RepoResult<bool> {
+        let result = sqlx::query!(
+            "DELETE FROM saved_filters WHERE filter_id = $1 AND workspace_id = $2",
+            filter_id,
+            workspace_id,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn owns_filter(&self, workspace_id: i32, filter_id: i32) -> RepoResult<bool> {
+        let found = sqlx::query_scalar!(
+            "SELECT filter_id FROM saved_filters WHERE filter_id = $1 AND workspace_id = $2",
+            filter_id,
+            workspace_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(found.is_some())
+    }
+
+    async fn list_goals(&self, workspace_id: i32) -> RepoResult<Vec<Goal>> {
+        sqlx::query_as!(
+            Goal,
+            r#"SELECT goal_id, name, contact_id, interaction_type, target_count,
+                    period AS "period: GoalPeriod"
+             FROM goals WHERE workspace_id = $1 ORDER BY name, goal_id"#,
+            workspace_id
+        )
+        .fetch_all(self.reader())
+        .await
+    }
+
+    async fn create_goal(&self, workspace_id: i32, goal: &NewGoalRequest) -> RepoResult<i32> {
+        let record = sqlx::query!(
+            "INSERT INTO goals (workspace_id, name, contact_id, interaction_type, target_count, period)
+             VALUES ($1, $2, $3, $4, $5, $6)
+             RETURNING goal_id",
+            workspace_id,
+            goal.name,
+            goal.contact_id,
+            goal.interaction_type.as_deref(),
+            goal.target_count,
+            goal.period as GoalPeriod,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(record.goal_id)
+    }
+
+    async fn update_goal(
+        &self,
+        workspace_id: i32,
+        goal_id: i32,
+        goal: &NewGoalRequest,
+    ) -> RepoResult<bool> {
+        let result = sqlx::query!(
+            "UPDATE goals
+             SET name = $1, contact_id = $2, interaction_type = $3, target_count = $4, period = $5
+             WHERE goal_id = $6 AND workspace_id = $7",
+            goal.name,
+            goal.contact_id,
+            goal.interaction_type.as_deref(),
+            goal.target_count,
+            goal.period as GoalPeriod,
+            goal_id,
+            workspace_id,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn delete_goal(&self, workspace_id: i32, goal_id: i32) -> RepoResult<bool> {
+        let result = sqlx::query!(
+            "DELETE FROM goals WHERE goal_id = $1 AND workspace_id = $2",
+            goal_id,
+            workspace_id,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn get_goal(&self, workspace_id: i32, goal_id: i32) -> RepoResult<Option<Goal>> {
+        sqlx::query_as!(
+            Goal,
+            r#"SELECT goal_id, name, contact_id, interaction_type, target_count,
+                    period AS "period: GoalPeriod"
+             FROM goals WHERE goal_id = $1 AND workspace_id = $2"#,
+            goal_id,
+            workspace_id
+        )
+        .fetch_optional(self.reader())
+        .await
+    }
+
+    async fn owns_goal(&self, workspace_id: i32, goal_id: i32) -> RepoResult<bool> {
+        let found = sqlx::query_scalar!(
+            "SELECT goal_id FROM goals WHERE goal_id = $1 AND workspace_id = $2",
+            goal_id,
+            workspace_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(found.is_some())
+    }
+
+    async fn reconnect_picks(
+        &self,
+        workspace_id: i32,
+        since: Date,
+    ) -> RepoResult<Vec<(Date, i32)>> {
+        let rows = sqlx::query!(
+            "SELECT week, contact_id FROM reconnect_picks WHERE workspace_id = $1 AND week >= $2",
+            workspace_id,
+            since,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.into_iter().map(|r| (r.week, r.contact_id)).collect())
+    }
+
+    async fn save_reconnect_picks(
+        &self,
+        workspace_id: i32,
+        week: Date,
+        contact_ids: &[i32],
+    ) -> RepoResult<()> {
+        sqlx::query!(
+            "INSERT INTO reconnect_picks (workspace_id, week, contact_id)
+             SELECT $1, $2, contact_id FROM unnest($3::int[]) AS ids(contact_id)
+             WHERE NOT EXISTS (SELECT 1 FROM reconnect_picks WHERE workspace_id = $1 AND week = $2)
+             ON CONFLICT DO NOTHING",
+            workspace_id,
+            week,
+            contact_ids,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn snooze_suggestion(
+        &self,
+        user_id: i32,
+        contact_id: i32,
+        until: Option<Date>,
+    ) -> RepoResult<()> {
+        sqlx::query!(
+            "INSERT INTO suggestion_snoozes (user_id, contact_id, snoozed_until)
+             VALUES ($1, $2, $3)
+             ON CONFLICT (user_id, contact_id)
+             DO UPDATE SET snoozed_until = EXCLUDED.snoozed_until, created_at = CURRENT_TIMESTAMP",
+            user_id,
+            contact_id,
+            until,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn unsnooze_suggestion(&self, user_id: i32, contact_id: i32) -> RepoResult<bool> {
+        let result = sqlx::query!(
+            "DELETE FROM suggestion_snoozes WHERE user_id = $1 AND contact_id = $2",
+            user_id,
+            contact_id,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn snoozed_contacts(&self, user_id: i32, today: Date) -> RepoResult<Vec<i32>> {
+        sqlx::query_scalar!(
+            "SELECT contact_id FROM suggestion_snoozes
+             WHERE user_id = $1 AND (snoozed_until IS NULL OR snoozed_until > $2)
+             ORDER BY contact_id",
+            user_id,
+            today,
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn get_preferences(&self, user_id: i32) -> RepoResult<Preferences> {
+        let record = sqlx::query!(
+            r#"SELECT country, quiet_weekdays, timezone, remind_days_before,
+                      ARRAY(SELECT t.tag_id FROM tags t
+                            JOIN workspaces w ON w.workspace_id = t.workspace_id
+                            WHERE w.user_id = p.user_id AND t.tag_id = ANY(p.greeting_tag_ids)
+                            ORDER BY t.tag_id) AS "greeting_tag_ids!"
+               FROM user_preferences p WHERE user_id = $1"#,
+            user_id,
+        )
+        .fetch_optional(self.reader())
+        .await?;
+        Ok(record
+            .map(|r| Preferences {
+                country: r.country,
+                quiet_weekdays: r.quiet_weekdays,
+                greeting_tag_ids: r.greeting_tag_ids,
+                timezone: r.timezone,
+                remind_days_before: r.remind_days_before,
+            })
+            .unwrap_or_default())
+    }
+
+    async fn save_preferences(&self, user_id: i32, preferences: &Preferences) -> RepoResult<()> {
+        sqlx::query!(
+            "INSERT INTO user_preferences (user_id, country, quiet_weekdays, greeting_tag_ids, timezone,
+                                           remind_days_before)
+             VALUES ($1, $2, $3, $4, $5, $6)
+             ON CONFLICT (user_id) DO UPDATE SET country = EXCLUDED.country,
+                 quiet_weekdays = EXCLUDED.quiet_weekdays,
+                 greeting_tag_ids = EXCLUDED.greeting_tag_ids,
+                 timezone = EXCLUDED.timezone,
+                 remind_days_before = EXCLUDED.remind_days_before",
+            user_id,
+            preferences.country.as_deref(),
+            &preferences.quiet_weekdays,
+            &preferences.greeting_tag_ids,
+            preferences.timezone.as_deref(),
+            &preferences.remind_days_before,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn export_schedule(&self, workspace_id: i32) -> RepoResult<Option<ExportSchedule>> {
+        sqlx::query_as!(
+            ExportSchedule,
+            "SELECT w.user_id, e.workspace_id, e.url, e.interval_hours, e.secret, e.last_pushed_at,
+                    e.last_status
+             FROM export_schedules e
+             JOIN workspaces w ON w.workspace_id = e.workspace_id
+             WHERE e.workspace_id = $1",
+            workspace_id,
+        )
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    async fn save_export_schedule(&self, schedule: &ExportSchedule) -> RepoResult<()> {
+        sqlx::query!(
+            "INSERT INTO export_schedules (workspace_id, url, interval_hours, secret)
+             VALUES ($1, $2, $3, $4)
+             ON CONFLICT (workspace_id) DO UPDATE SET url = EXCLUDED.url,
+                 interval_hours = EXCLUDED.interval_hours, secret = EXCLUDED.secret",
+            schedule.workspace_id,
+            schedule.url,
+            schedule.interval_hours,
+            schedule.secret,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_export_schedule(&self, workspace_id: i32) -> RepoResult<bool> {
+        let result = sqlx::query!(
+            "DELETE FROM export_schedules WHERE workspace_id = $1",
+            workspace_id
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn due_export_schedules(&self, now: OffsetDateTime) -> RepoResult<Vec<ExportSchedule>> {
+        sqlx::query_as!(
+            ExportSchedule,
+            "SELECT w.user_id, e.workspace_id, e.url, e.interval_hours, e.secret, e.last_pushed_at,
+                    e.last_status
+             FROM export_schedules e
+             JOIN workspaces w ON w.workspace_id = e.workspace_id
+             JOIN users u ON u.user_id = w.user_id AND u.deactivated_at IS NULL
+             WHERE e.last_pushed_at IS NULL
+                OR e.last_pushed_at + make_interval(hours => e.interval_hours) <= $1",
+            now,
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn record_export_push(
+        &self,
+        workspace_id: i32,
+        pushed_at: OffsetDateTime,
+        status: &str,
+    ) -> RepoResult<()> {
+        sqlx::query!(
+            "UPDATE export_schedules SET last_pushed_at = $2, last_status = $3 WHERE workspace_id = $1",
+            workspace_id,
+            pushed_at,
+            status,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn inbound_email_token(&self, workspace_id: i32) -> RepoResult<Option<String>> {
+        sqlx::query_scalar!(
+            "SELECT token FROM inbound_email_addresses WHERE workspace_id = $1",
+            workspace_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    async fn save_inbound_email_token(&self, workspace_id: i32, token: &str) -> RepoResult<()> {
+        sqlx::query!(
+            "INSERT INTO inbound_email_addresses (workspace_id, token) VALUES ($1, $2)
+             ON CONFLICT (workspace_id)
+             DO UPDATE SET token = EXCLUDED.token, created_at = CURRENT_TIMESTAMP",
+            workspace_id,
+            token,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_inbound_email_token(&self, workspace_id: i32) -> RepoResult<bool> {
+        let result = sqlx::query!(
+            "DELETE FROM inbound_email_addresses WHERE workspace_id = $1",
+            workspace_id
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn inbound_email_workspace(&self, token: &str) -> RepoResult<Option<(i32, i32)>> {
+        let found = sqlx::query!(
+            "SELECT w.user_id, a.workspace_id
+             FROM inbound_email_addresses a
+             JOIN workspaces w ON w.workspace_id = a.workspace_id
+             JOIN users u ON u.user_id = w.user_id AND u.deactivated_at IS NULL
+             WHERE a.token = $1",
+            token
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(found.map(|row| (row.user_id, row.workspace_id)))
+    }
+
+    async fn contacts_by_email(
+        &self,
+        workspace_id: i32,
+        emails: &[String],
+    ) -> RepoResult<Vec<i32>> {
+        sqlx::query_scalar!(
+            r#"SELECT c.contact_id AS "contact_id!"
+             FROM unnest($2::TEXT[]) WITH ORDINALITY AS e(email, n)
+             JOIN contacts c ON LOWER(c.email) = e.email AND c.workspace_id = $1
+             ORDER BY e.n, c.contact_id"#,
+            workspace_id,
+            emails
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn list_rest_hooks(&self, workspace_id: i32) -> RepoResult<Vec<RestHook>> {
+        sqlx::query_as!(
+            RestHook,
+            r#"SELECT h.hook_id, w.user_id, h.workspace_id, h.event AS "event: HookEvent",
+                      h.target_url, h.delivered_on
+               FROM rest_hooks h
+               JOIN workspaces w ON w.workspace_id = h.workspace_id
+               WHERE h.workspace_id = $1
+               ORDER BY h.hook_id"#,
+            workspace_id,
+        )
+        .fetch_all(self.reader())
+        .await
+    }
+
+    async fn create_rest_hook(
+        &self,
+        workspace_id: i32,
+        hook: &NewRestHookRequest,
+    ) -> RepoResult<i32> {
+        sqlx::query_scalar!(
+            "INSERT INTO rest_hooks (workspace_id, event, target_url)
+             VALUES ($1, $2, $3)
+             RETURNING hook_id",
+            workspace_id,
+            hook.event as HookEvent,
+            hook.target_url,
+        )
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    async fn delete_rest_hook(&self, workspace_id: i32, hook_id: i32) -> RepoResult<bool> {
+        let result = sqlx::query!(
+            "DELETE FROM rest_hooks WHERE hook_id = $1 AND workspace_id = $2",
+            hook_id,
+            workspace_id,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn owns_rest_hook(&self, workspace_id: i32, hook_id: i32) -> RepoResult<bool> {
+        let found = sqlx::query_scalar!(
+            "SELECT hook_id FROM rest_hooks WHERE hook_id = $1 AND workspace_id = $2",
+            hook_id,
+            workspace_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(found.is_some())
+    }
+
+    async fn rest_hooks_for_event(
+        &self,
+        event: HookEvent,
+        user_id: Option<i32>,
+    ) -> RepoResult<Vec<RestHook>> {
+        sqlx::query_as!(
+            RestHook,
+            r#"SELECT h.hook_id, w.user_id, h.workspace_id, h.event AS "event: HookEvent",
+                      h.target_url, h.delivered_on
+               FROM rest_hooks h
+               JOIN workspaces w ON w.workspace_id = h.workspace_id
+               JOIN users u ON u.user_id = w.user_id AND u.deactivated_at IS NULL
+               WHERE h.event = $1 AND ($2::int IS NULL OR w.user_id = $2)
+               ORDER BY h.hook_id"#,
+            event as HookEvent,
+            user_id,
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn record_rest_hook_delivery(&self, hook_id: i32, delivered_on: Date) -> RepoResult<()> {
+        sqlx::query!(
+            "UPDATE rest_hooks SET delivered_on = $2 WHERE hook_id = $1",
+            hook_id,
+            delivered_on,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn queue_upcoming_occasions(
+        &self,
+        workspace_id: i32,
+        hook_id: i32,
+        items: &serde_json::Value,
+        delivered_on: Date,
+    ) -> RepoResult<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query!(
+            "INSERT INTO outbox (workspace_id, hook_id, payload)
+             SELECT workspace_id, hook_id, $3 FROM rest_hooks WHERE hook_id = $2 AND workspace_id = $1",
+            workspace_id,
+            hook_id,
+            items,
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "UPDATE rest_hooks SET delivered_on = $3 WHERE hook_id = $2 AND workspace_id = $1",
+            workspace_id,
+            hook_id,
+            delivered_on,
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await
+    }
+
+    async fn hook_deliveries(
+        &self,
+        workspace_id: i32,
+        hook_id: i32,
+        limit: i64,
+    ) -> RepoResult<Vec<HookDelivery>> {
+        sqlx::query_as!(
+            HookDelivery,
+            r#"SELECT delivery_id, status AS "status: DeliveryStatus", attempts, last_response_status,
+                      last_error, created_at, next_attempt_at, finished_at
+               FROM outbox
+               WHERE workspace_id = $1 AND hook_id = $2
+               ORDER BY delivery_id DESC
+               LIMIT $3"#,
+            workspace_id,
+            hook_id,
+            limit,
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn claim_deliveries(
+        &self,
+        now: OffsetDateTime,
+        until: OffsetDateTime,
+        limit: i64,
+    ) -> RepoResult<Vec<OutboxMessage>> {
+        let rows = sqlx::query!(
+            r#"WITH claimed AS (
+                   UPDATE outbox SET next_attempt_at = $2
+                   WHERE delivery_id IN (SELECT delivery_id FROM outbox
+                                         WHERE status = 'pending' AND next_attempt_at <= $1
+                                         ORDER BY next_attempt_at, delivery_id
+                                         LIMIT $3
+                                         FOR UPDATE SKIP LOCKED)
+                   RETURNING delivery_id, workspace_id, hook_id, push_subscription_id, entity_ids,
+                             payload, attempts
+               )
+               SELECT c.delivery_id, c.workspace_id, c.hook_id, c.push_subscription_id,
+                      c.entity_ids, c.payload, c.attempts,
+                      h.event AS "event?: HookEvent", h.target_url AS "target_url?",
+                      p.user_id AS "push_user_id?", p.endpoint AS "endpoint?",
+                      p.p256dh AS "p256dh?", p.auth AS "auth?", p.device_name AS "device_name?",
+                      p.created_at AS "push_created_at?"
+               FROM claimed c
+               LEFT JOIN rest_hooks h ON h.hook_id = c.hook_id
+               LEFT JOIN push_subscriptions p ON p.subscription_id = c.push_subscription_id
+               ORDER BY c.delivery_id"#,
+            now,
+            until,
+            limit,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        fn decode<T: serde::de::DeserializeOwned>(
+            payload: Option<serde_json::Value>,
+        ) -> RepoResult<T> {
+            serde_json::from_value(payload.unwrap_or_default())
+                .map_err(|e| sqlx::Error::Decode(Box::new(e)))
+        }
+        rows.into_iter()
+            .map(|row| {
+                let payload = match (row.hook_id, row.push_subscription_id, row.payload) {
+                    (Some(_), _, Some(items)) => OutboxPayload::Items(items),
+                    (Some(_), _, None) => OutboxPayload::Entities(row.entity_ids),
+                    (None, Some(_), payload) => OutboxPayload::Push(decode(payload)?),
+                    (None, None, payload) => OutboxPayload::Email(decode(payload)?),
+                };
+                let push = row.push_subscription_id.zip(row.push_user_id).map(
+                    |(subscription_id, user_id)| PushSubscription {
+                        subscription_id,
+                        user_id,
+                        endpoint: row.endpoint.unwrap_or_default(),
+                        p256dh: row.p256dh.unwrap_or_default(),
+                        auth: row.auth.unwrap_or_default(),
+                        device_name: row.device_name,
+                        created_at: row.push_created_at.unwrap_or(OffsetDateTime::UNIX_EPOCH),
+                    },
+                );
+                Ok(OutboxMessage {
+                    delivery_id: row.delivery_id,
+                    workspace_id: row.workspace_id,
+                    hook: row
+                        .hook_id
+                        .zip(row.event)
+                        .zip(row.target_url)
+                        .map(|((hook_id, event), target_url)| (hook_id, event, target_url)),
+                    push,
+                    payload,
+                    attempts: row.attempts,
+                })
+            })
+            .collect()
+    }
+
+    async fn record_delivery_attempt(
+        &self,
+        delivery_id: i32,
+        attempt: &DeliveryAttempt,
+    ) -> RepoResult<()> {
+        sqlx::query!(
+            "UPDATE outbox
+             SET status = $2, attempts = attempts + 1, last_response_status = $3, last_error = $4,
+                 next_attempt_at = COALESCE($6, next_attempt_at),
+                 finished_at = CASE WHEN $2 = 'pending'::delivery_status THEN NULL ELSE $5::timestamptz END
+             WHERE delivery_id = $1",
+            delivery_id,
+            attempt.status as DeliveryStatus,
+            attempt.response_status,
+            attempt.error.as_deref(),
+            attempt.at,
+            attempt.retry_at,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn purge_deliveries(&self, before: OffsetDateTime) -> RepoResult<u64> {
+        let result = sqlx::query!(
+            "DELETE FROM outbox WHERE status <> 'pending' AND finished_at < $1",
+            before
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn contact_links(
+        &self,
+        workspace_id: i32,
+        contact_id: i32,
+        now: OffsetDateTime,
+    ) -> RepoResult<Vec<ContactLink>> {
+        sqlx::query_as!(
+            ContactLink,
+            r#"SELECT link_id, contact_id, NULL::text AS token, expires_at, NULL::text AS url
+               FROM contact_links
+               WHERE workspace_id = $1 AND contact_id = $2 AND expires_at > $3
+               ORDER BY link_id"#,
+            workspace_id,
+            contact_id,
+            now,
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn create_contact_link(
+        &self,
+        workspace_id: i32,
+        contact_id: i32,
+        token_hash: &str,
+        expires_at: OffsetDateTime,
+    ) -> RepoResult<ContactLink> {
+        sqlx::query_as!(
+            ContactLink,
+            r#"INSERT INTO contact_links (workspace_id, contact_id, token_hash, expires_at)
+               VALUES ($1, $2, $3, $4)
+               RETURNING link_id, contact_id, NULL::text AS token, expires_at, NULL::text AS url"#,
+            workspace_id,
+            contact_id,
+            token_hash,
+            expires_at,
+        )
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    async fn delete_contact_link(&self, workspace_id: i32, link_id: i32) -> RepoResult<bool> {
+        let result = sqlx::query!(
+            "DELETE FROM contact_links WHERE link_id = $1 AND workspace_id = $2",
+            link_id,
+            workspace_id,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn owns_contact_link(&self, workspace_id: i32, link_id: i32) -> RepoResult<bool> {
+        let found = sqlx::query_scalar!(
+            "SELECT link_id FROM contact_links WHERE link_id = $1 AND workspace_id = $2",
+            link_id,
+            workspace_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(found.is_some())
+    }
+
+    async fn contact_link_target(
+        &self,
+        token_hash: &str,
+        now: OffsetDateTime,
+    ) -> RepoResult<Option<(i32, i32)>> {
+        let found = sqlx::query!(
+            "SELECT l.workspace_id, l.contact_id
+             FROM contact_links l
+             JOIN workspaces w ON w.workspace_id = l.workspace_id
+             JOIN users u ON u.user_id = w.user_id AND u.deactivated_at IS NULL
+             WHERE l.token_hash = $1 AND l.expires_at > $2",
+            token_hash,
+            now,
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(found.map(|row| (row.workspace_id, row.contact_id)))
+    }
+
+    async fn claim_idempotency_key(
+        &self,
+        user_id: i32,
+        key: &str,
+        fingerprint: &str,
+        ttl_hours: i32,
+    ) -> RepoResult<Option<IdempotentRequest>> {
+        sqlx::query!(
+            "DELETE FROM idempotency_keys
+             WHERE user_id = $1 AND created_at < NOW() - make_interval(hours => $2)",
+            user_id,
+            ttl_hours,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // A key released between the insert and the select is claimed on the next pass
+        loop {
+            let claimed = sqlx::query!(
+                "INSERT INTO idempotency_keys (user_id, idempotency_key, fingerprint)
+                 VALUES ($1, $2, $3)
+                 ON CONFLICT DO NOTHING
+                 RETURNING user_id",
+                user_id,
+                key,
+                fingerprint,
+            )
+            .fetch_optional(&self.pool)
+            .await?;
+            if claimed.is_some() {
+                return Ok(None);
+            }
+
+            let held = sqlx::query!(
+                "SELECT fingerprint, status, content_type, location, body FROM idempotency_keys
+                 WHERE user_id = $1 AND idempotency_key = $2",
+                user_id,
+                key,
+            )
+            .fetch_optional(&self.pool)
+            .await?;
+            if let Some(r) = held {
+                return Ok(Some(IdempotentRequest {
+                    fingerprint: r.fingerprint,
+                    response: r.status.map(|status| StoredResponse {
+                        status: status as u16,
+                        content_type: r.content_type,
+                        location: r.location,
+                        body: r.body.unwrap_or_default(),
+                    }),
+                }));
+            }
+        }
+    }
+
+    async fn complete_idempotency_key(
+        &self,
+        user_id: i32,
+        key: &str,
+        response: &StoredResponse,
+    ) -> RepoResult<()> {
+        sqlx::query!(
+            "UPDATE idempotency_keys SET status = $3, content_type = $4, location = $5, body = $6
+             WHERE user_id = $1 AND idempotency_key = $2",
+            user_id,
+            key,
+            response.status as i16,
+            response.content_type,
+            response.location,
+            response.body,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn release_idempotency_key(&self, user_id: i32, key: &str) -> RepoResult<()> {
+        sqlx::query!(
+            "DELETE FROM idempotency_keys WHERE user_id = $1 AND idempotency_key = $2",
+            user_id,
+            key,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+}
+
+/// Insert a contact, queueing it for the workspace's new contact hooks
+async fn insert_contact(
+    conn: &mut PgConnection,
+    workspace_id: i32,
+    contact: &NewContactRequest,
+    notes: Option<&NotesCipher>,
+) -> RepoResult<i32> {
+    let record = sqlx::query!(
+        "INSERT INTO contacts (workspace_id, first_name, last_name, email, phone, phone_e164, short_note, notes,
+                               avatar_url, desired_frequency_days, met_at, introduced_by_contact_id,
+                               location)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
+         RETURNING contact_id",
+        workspace_id,
+        contact.first_name.as_deref(),
+        contact.last_name.as_deref(),
+        contact.email.as_deref(),
+        contact.phone.as_deref(),
+        phone::e164(contact.phone.as_deref()),
+        encryption::seal(notes, contact.short_note.as_deref()),
+        encryption::seal(notes, contact.notes.as_deref()),
+        contact.avatar_url.as_deref(),
+        contact.desired_frequency_days,
+        contact.met_at.as_ref().map(Json) as _,
+        contact.introduced_by_contact_id,
+        contact.location.as_deref(),
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+    queue_hook_deliveries(
+        &mut *conn,
+        workspace_id,
+        HookEvent::NewContact,
+        &[record.contact_id],
+    )
+    .await?;
+    Ok(record.contact_id)
+}
+
+/// Insert every contact with a single statement, returning ids in input order, and queue them
+/// for the workspace's new contact hooks
+async fn insert_contacts(
+    conn: &mut PgConnection,
+    workspace_id: i32,
+    contacts: &[NewContactRequest],
+    notes: Option<&NotesCipher>,
+) -> RepoResult<Vec<i32>> {
+    let column = |field: fn(&NewContactRequest) -> &Option<String>| -> Vec<Option<String>> {
+        contacts.iter().map(|c| field(c).clone()).collect()
+    };
+    let sealed = |field: fn(&NewContactRequest) -> &Option<String>| -> Vec<Option<String>> {
+        contacts
+            .iter()
+            .map(|c| encryption::seal(notes, field(c).as_deref()))
+            .collect()
+    };
+
+    let mut ids = sqlx::query_scalar!(
+        "INSERT INTO contacts (workspace_id, first_name, last_name, email, phone, short_note, notes, avatar_url,
+                               desired_frequency_days, phone_e164, met_at, introduced_by_contact_id,
+                               location)
+         SELECT $1, c.first_name, c.last_name, c.email, c.phone, c.short_note, c.notes, c.avatar_url,
+                c.desired_frequency_days, c.phone_e164, c.met_at, c.introduced_by_contact_id, c.location
+         FROM UNNEST($2::text[], $3::text[], $4::text[], $5::text[], $6::text[], $7::text[], $8::text[],
+                     $9::int[], $10::text[], $11::jsonb[], $12::int[], $13::text[])
+             WITH ORDINALITY AS c(first_name, last_name, email, phone, short_note, notes, avatar_url,
+                                  desired_frequency_days, phone_e164, met_at, introduced_by_contact_id,
+                                  location, ord)
+         ORDER BY c.ord
+         RETURNING contact_id",
+        workspace_id,
+        &column(|c| &c.first_name) as &[Option<String>],
+        &column(|c| &c.last_name) as &[Option<String>],
+        &column(|c| &c.email) as &[Option<String>],
+        &column(|c| &c.phone) as &[Option<String>],
+        &sealed(|c| &c.short_note) as &[Option<String>],
+        &sealed(|c| &c.notes) as &[Option<String>],
+        &column(|c| &c.avatar_url) as &[Option<String>],
+        &contacts
+            .iter()
+            .map(|c| c.desired_frequency_days)
+            .collect::<Vec<_>>() as &[Option<i32>],
+        &contacts
+            .iter()
+            .map(|c| phone::e164(c.phone.as_deref()))
+            .collect::<Vec<_>>() as &[Option<String>],
+        &contacts
+            .iter()
+            .map(|c| c.met_at.as_ref().map(Json))
+            .collect::<Vec<_>>() as &[Option<Json<&MetAt>>],
+        &contacts
+            .iter()
+            .map(|c| c.introduced_by_contact_id)
+            .collect::<Vec<_>>() as &[Option<i32>],
+        &column(|c| &c.location) as &[Option<String>],
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    // Ids come from a sequence, so rows inserted in input order get ascending ids
+    ids.sort_unstable();
+    queue_hook_deliveries(&mut *conn, workspace_id, HookEvent::NewContact, &ids).await?;
+    Ok(ids)
+}
+
+/// Insert the contacts as one batch; if any row fails, redo it row by row so only the failing
+/// rows are dropped
+async fn insert_contacts_skipping_failures(
+    conn: &mut PgConnection,
+    workspace_id: i32,
+    contacts: &[NewContactRequest],
+    notes: Option<&NotesCipher>,
+) -> RepoResult<Vec<RepoResult<i32>>> {
+    let mut batch = conn.begin().await?;
+    match insert_contacts(&mut batch, workspace_id, contacts, notes).await {
+        Ok(ids) => {
+            batch.commit().await?;
+            return Ok(ids.into_iter().map(Ok).collect());
+        }
+        Err(_) => batch.rollback().await?,
+    }
+    let mut results = Vec::with_capacity(contacts.len());
+    for contact in contacts {
+        let mut row = conn.begin().await?;
+        match insert_contact(&mut row, workspace_id, contact, notes).await {
+            Ok(contact_id) => {
+                row.commit().await?;
+                results.push(Ok(contact_id));
+            }
+            Err(e) => {
+                row.rollback().await?;
+                results.push(Err(e));
+            }
+        }
+    }
+    Ok(results)
+}
+
+/// Queue new entities for each of the workspace's hooks for `event`, in the caller's
+/// transaction
+async fn queue_hook_deliveries(
+    executor: impl PgExecutor<'_>,
+    workspace_id: i32,
+    event: HookEvent,
+    ids: &[i32],
+) -> RepoResult<()> {
+    if ids.is_empty() {
+        return Ok(());
+    }
+    sqlx::query!(
+        "INSERT INTO outbox (workspace_id, hook_id, entity_ids)
+         SELECT workspace_id, hook_id, $3 FROM rest_hooks WHERE workspace_id = $1 AND event = $2",
+        workspace_id,
+        event as HookEvent,
+        ids,
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Record edits of a contact in its history
+async fn insert_contact_changes(
+    executor: impl PgExecutor<'_>,
+    contact_id: i32,
+    changes: &[FieldChange],
+    notes: Option<&NotesCipher>,
+) -> RepoResult<()> {
+    if changes.is_empty() {
+        return Ok(());
+    }
+    // Notes stay encrypted in the history too
+    let seal = |change: &FieldChange, value: &Option<String>| match notes {
+        Some(_) if ENCRYPTED_FIELDS.contains(&change.field) => {
+            encryption::seal(notes, value.as_deref())
+        }
+        _ => value.clone(),
+    };
+    sqlx::query!(
+        "INSERT INTO contact_changes (contact_id, field, old_value, new_value)
+         SELECT $1, c.field, c.old_value, c.new_value
+         FROM UNNEST($2::text[], $3::text[], $4::text[]) AS c(field, old_value, new_value)",
+        contact_id,
+        &changes.iter().map(|c| c.field).collect::<Vec<_>>() as &[&str],
+        &changes
+            .iter()
+            .map(|c| seal(c, &c.before))
+            .collect::<Vec<_>>() as &[Option<String>],
+        &changes
+            .iter()
+            .map(|c| seal(c, &c.after))
+            .collect::<Vec<_>>() as &[Option<String>],
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Insert an interaction with its participants, ending dismissals of the contacts involved,
+/// and queue it for the workspace's new interaction hooks
+async fn insert_interaction(
+    conn: &mut PgConnection,
+    workspace_id: i32,
+    interaction: &NewInteractionRequest,
+) -> RepoResult<i32> {
+    let record = sqlx::query!(
+        "INSERT INTO interactions (workspace_id, contact_id, interaction_date, notes, followup_priority,
+                                   interaction_type, custom_type_id)
+         VALUES ($1, $2, $3, $4, $5,
+                 (SELECT k FROM unnest(enum_range(NULL::interaction_kind)) k WHERE k::text = $6),
+                 (SELECT type_id FROM interaction_types WHERE workspace_id = $1 AND name = $6))
+         RETURNING interaction_id",
+        workspace_id,
+        interaction.contact_id,
+        interaction.interaction_date,
+        interaction.notes,
+        interaction.follow_up_priority,
+        interaction.interaction_type,
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+    save_participants(
+        &mut *conn,
+        workspace_id,
+        record.interaction_id,
+        &interaction.contact_ids,
+    )
+    .await?;
+    // Getting in touch ends dismissals of the contacts involved
+    sqlx::query!(
+        "DELETE FROM suggestion_snoozes
+         WHERE snoozed_until IS NULL
+           AND (contact_id = $1
+                OR contact_id IN (SELECT contact_id FROM interaction_participants
+                                  WHERE interaction_id = $2))",
+        interaction.contact_id,
+        record.interaction_id,
+    )
+    .execute(&mut *conn)
+    .await?;
+    queue_hook_deliveries(
+        &mut *conn,
+        workspace_id,
+        HookEvent::NewInteraction,
+        &[record.interaction_id],
+    )
+    .await?;
+    Ok(record.interaction_id)
+}
+
+async fn insert_occasion(
+    executor: impl PgExecutor<'_>,
+    workspace_id: i32,
+    occasion: &NewOccasionRequest,
+    interaction_id: Option<i32>,
+) -> RepoResult<i32> {
+    let record = sqlx::query!(
+        "INSERT INTO occasions (workspace_id, contact_id, name, date, recurring, recurring_interval, details,
+                                occasion_type, birth_year, interaction_id, remind_days_before)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+         RETURNING occasion_id",
+        workspace_id,
+        occasion.contact_id,
+        occasion.name,
+        occasion.date,
+        occasion.recurring,
+        occasion.recurring_interval,
+        occasion.details.as_deref(),
+        occasion.occasion_type as OccasionType,
+        occasion.birth_year,
+        interaction_id,
+        occasion.remind_days_before.as_deref(),
+    )
+    .fetch_one(executor)
+    .await?;
+    Ok(record.occasion_id)
+}
+
+/// Add the workspace's contacts among `contact_ids` to an interaction, skipping its own contact
+async fn save_participants(
+    executor: impl PgExecutor<'_>,
+    workspace_id: i32,
+    interaction_id: i32,
+    contact_ids: &[i32],
+) -> RepoResult<()> {
+    sqlx::query!(
+        "INSERT INTO interaction_participants (interaction_id, contact_id)
+         SELECT i.interaction_id, c.contact_id
+         FROM interactions i
+         JOIN contacts c ON c.contact_id = ANY($3) AND c.workspace_id = $2 AND c.contact_id IS DISTINCT FROM i.contact_id
+         WHERE i.interaction_id = $1
+         ON CONFLICT DO NOTHING",
+        interaction_id,
+        workspace_id,
+        contact_ids,
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
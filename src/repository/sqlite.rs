@@ -0,0 +1,4029 @@
+//! `Repository` backed by a SQLite file, for single-user local installs without a Postgres
+//! server. Enabled by the `sqlite` feature and chosen by a `sqlite:` DATABASE_URL.
+//!
+//! The schema is in `migrations/sqlite`, applied on connect. What Postgres computes in SQL
+//! and SQLite has no equivalent for (full-text search, contact priorities, time zone buckets)
+//! is computed here the way `InMemoryRepository` does it.
+
+use super::memory::{highlight, search_words};
+use super::{DEFAULT_WORKSPACE_NAME, RepoResult, Repository};
+use crate::AuthUser;
+use crate::cursor::Cursor;
+use crate::encryption::{self, ENCRYPTED_FIELDS, NotesCipher};
+use crate::geocoding::BoundingBox;
+use crate::history::{self, FieldChange};
+use crate::interactions::BUILTIN_INTERACTION_TYPES;
+use crate::mail::Email;
+use crate::models::{
+    AccountSummary, Attachment, AttachmentFile, Capture, Contact, ContactAccess, ContactChange,
+    ContactChecksum, ContactDetails, ContactLink, ContactMatch, ContactResponse, ContactShare,
+    ContactSummary, Coordinates, CustomInteractionType, DeliveryAttempt, ExportSchedule,
+    FilterQuery, GiftIdea, Goal, Granularity, Group, HookDelivery, HookEvent, IdempotentRequest,
+    InstanceStats, Interaction, NewAttachment, NewContactRequest, NewGiftIdeaRequest,
+    NewGoalRequest, NewGroupRequest, NewInteractionRequest, NewOccasionRequest,
+    NewPushSubscriptionRequest, NewRestHookRequest, NewSavedFilterRequest, NewSocialProfileRequest,
+    NewTagRequest, NoteMatch, NoteSource, NotificationSettings, Occasion, OutboxMessage,
+    OutboxPayload, PendingAvatarLookup, PendingTranscription, Preferences, PushRecipient,
+    PushSubscription, RestHook, ReviewRecipient, SavedFilter, Share, SharePermission,
+    SocialProfile, StatsMetric, StoredResponse, Tag, TagUsage, TranscriptionStatus, Unassigned,
+    Usage, UserProfile, Workspace,
+};
+use crate::phone;
+use crate::push::PushMessage;
+use crate::reminders;
+use crate::stats::bucket_start;
+use crate::timezone::LocalDates;
+use async_trait::async_trait;
+use sha2::{Digest, Sha256};
+use sqlx::encode::IsNull;
+use sqlx::error::BoxDynError;
+use sqlx::migrate::Migrator;
+use sqlx::sqlite::{
+    SqliteArgumentValue, SqliteConnectOptions, SqliteConnection, SqliteJournalMode,
+    SqlitePoolOptions, SqliteRow, SqliteTypeInfo,
+};
+use sqlx::types::Json;
+use sqlx::{Acquire, Encode, Row, Sqlite, SqlitePool, Type};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::time::Duration;
+use time::format_description::BorrowedFormatItem;
+use time::macros::format_description;
+use time::{Date, OffsetDateTime, UtcOffset};
+
+#[derive(Clone)]
+pub struct SqliteRepository {
+    pool: SqlitePool,
+    /// Encrypts contacts' notes at rest when set; see `encryption`
+    notes: Option<NotesCipher>,
+}
+
+/// The SQLite schema, applied in order on connect; see `database::MIGRATOR`
+pub static MIGRATOR: Migrator = sqlx::migrate!("./migrations/sqlite");
+
+/// How long a statement waits for another process writing to the same file
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The columns of `Contact`, in its field order
+const CONTACT_COLUMNS: &str = "contact_id, first_name, last_name, email, phone, phone_e164,
+    short_note, notes, avatar_url, archived, desired_frequency_days, met_at,
+    introduced_by_contact_id, location, latitude, longitude, updated_at";
+
+/// The columns of `Interaction` for `interaction`, from `interactions i` joined to its custom
+/// type as `t`
+const INTERACTION_COLUMNS: &str = "i.interaction_id, i.contact_id, i.interaction_date, i.notes,
+    i.followup_priority AS follow_up_priority,
+    COALESCE(i.interaction_type, t.name) AS interaction_type,
+    (SELECT json_group_array(p.contact_id ORDER BY p.contact_id)
+     FROM interaction_participants p
+     WHERE p.interaction_id = i.interaction_id) AS contact_ids,
+    (SELECT json_group_array(it.tag_id ORDER BY it.tag_id)
+     FROM interaction_tags it
+     WHERE it.interaction_id = i.interaction_id) AS tag_ids";
+
+/// The columns of `Occasion` for `occasion`
+const OCCASION_COLUMNS: &str = "occasion_id, contact_id, name, date, recurring,
+    recurring_interval, details, occasion_type, birth_year, remind_days_before, interaction_id";
+
+/// The columns of `Group` for `group`, from `contact_groups g`
+const GROUP_COLUMNS: &str = "g.group_id, g.name, g.description,
+    (SELECT json_group_array(m.contact_id ORDER BY m.position, m.contact_id)
+     FROM contact_group_members m
+     WHERE m.group_id = g.group_id) AS contact_ids";
+
+/// Stored timestamps: UTC to the microsecond, fixed width so they compare as text in time
+/// order
+const TIMESTAMP_FORMAT: &[BorrowedFormatItem<'_>] =
+    format_description!("[year]-[month]-[day] [hour]:[minute]:[second].[subsecond digits:6]");
+
+/// An instant bound in the stored timestamp form. `OffsetDateTime` itself binds as RFC 3339,
+/// which keeps the offset and drops trailing zeros, so it would not compare with stored times.
+struct Timestamp(OffsetDateTime);
+
+impl Type<Sqlite> for Timestamp {
+    fn type_info() -> SqliteTypeInfo {
+        <String as Type<Sqlite>>::type_info()
+    }
+}
+
+impl Encode<'_, Sqlite> for Timestamp {
+    fn encode_by_ref(&self, buf: &mut Vec<SqliteArgumentValue<'_>>) -> Result<IsNull, BoxDynError> {
+        let text = self.0.to_offset(UtcOffset::UTC).format(TIMESTAMP_FORMAT)?;
+        Encode::<Sqlite>::encode(text, buf)
+    }
+}
+
+impl SqliteRepository {
+    /// Open the database at `url` (`sqlite://crm.db`, or `sqlite::memory:` for a throwaway
+    /// one), creating the file and the schema if they don't exist yet
+    pub async fn connect(url: &str) -> RepoResult<Self> {
+        let options = SqliteConnectOptions::from_str(url)?
+            .create_if_missing(true)
+            .foreign_keys(true)
+            .journal_mode(SqliteJournalMode::Wal)
+            .busy_timeout(BUSY_TIMEOUT);
+        // SQLite takes one writer at a time anyway, and a single connection that is never
+        // closed keeps an in-memory database alive
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect_with(options)
+            .await?;
+        MIGRATOR.run(&pool).await?;
+        Ok(SqliteRepository { pool, notes: None })
+    }
+
+    /// Encrypt contacts' notes with `cipher` from now on
+    pub fn with_notes_cipher(mut self, cipher: Option<NotesCipher>) -> Self {
+        self.notes = cipher;
+        self
+    }
+
+    pub fn pool(&self) -> &SqlitePool {
+        &self.pool
+    }
+
+    /// The contact with its notes decrypted
+    fn open_contact(&self, mut contact: Contact) -> RepoResult<Contact> {
+        contact.short_note = open_note(self.notes.as_ref(), contact.short_note)?;
+        contact.notes = open_note(self.notes.as_ref(), contact.notes)?;
+        Ok(contact)
+    }
+
+    fn open_contacts(&self, contacts: Vec<Contact>) -> RepoResult<Vec<Contact>> {
+        contacts.into_iter().map(|c| self.open_contact(c)).collect()
+    }
+
+    /// Whether `query`, given a row's id and its owner's, finds the row
+    async fn exists(&self, query: &str, id: i32, owner_id: i32) -> RepoResult<bool> {
+        let found: Option<i32> = sqlx::query_scalar(query)
+            .bind(id)
+            .bind(owner_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(found.is_some())
+    }
+}
+
+/// A stored note's plaintext, failing the read like a bad column would when it can't be
+/// decrypted
+fn open_note(cipher: Option<&NotesCipher>, stored: Option<String>) -> RepoResult<Option<String>> {
+    encryption::open(cipher, stored).map_err(|e| sqlx::Error::Decode(e.into()))
+}
+
+fn interaction(row: &SqliteRow) -> RepoResult<Interaction> {
+    Ok(Interaction {
+        interaction_id: row.try_get("interaction_id")?,
+        contact_id: row.try_get("contact_id")?,
+        interaction_date: row.try_get("interaction_date")?,
+        notes: row.try_get("notes")?,
+        follow_up_priority: row.try_get("follow_up_priority")?,
+        interaction_type: row.try_get("interaction_type")?,
+        contact_ids: row.try_get::<Json<Vec<i32>>, _>("contact_ids")?.0,
+        tag_ids: row.try_get::<Json<Vec<i32>>, _>("tag_ids")?.0,
+    })
+}
+
+fn interactions(rows: Vec<SqliteRow>) -> RepoResult<Vec<Interaction>> {
+    rows.iter().map(interaction).collect()
+}
+
+fn occasion(row: &SqliteRow) -> RepoResult<Occasion> {
+    Ok(Occasion {
+        occasion_id: row.try_get("occasion_id")?,
+        contact_id: row.try_get("contact_id")?,
+        name: row.try_get("name")?,
+        date: row.try_get("date")?,
+        recurring: row.try_get("recurring")?,
+        recurring_interval: row.try_get("recurring_interval")?,
+        details: row.try_get("details")?,
+        occasion_type: row.try_get("occasion_type")?,
+        birth_year: row.try_get("birth_year")?,
+        remind_days_before: row
+            .try_get::<Option<Json<Vec<i32>>>, _>("remind_days_before")?
+            .map(|days| days.0),
+        upcoming_age: None,
+        interaction_id: row.try_get("interaction_id")?,
+    })
+}
+
+fn occasions(rows: Vec<SqliteRow>) -> RepoResult<Vec<Occasion>> {
+    rows.iter().map(occasion).collect()
+}
+
+fn push_subscription(row: &SqliteRow) -> RepoResult<PushSubscription> {
+    Ok(PushSubscription {
+        subscription_id: row.try_get("subscription_id")?,
+        user_id: row.try_get("user_id")?,
+        endpoint: row.try_get("endpoint")?,
+        p256dh: row.try_get("p256dh")?,
+        auth: row.try_get("auth")?,
+        device_name: row.try_get("device_name")?,
+        created_at: row.try_get("created_at")?,
+    })
+}
+
+fn tag(row: &SqliteRow) -> RepoResult<Tag> {
+    Ok(Tag {
+        tag_id: row.try_get("tag_id")?,
+        name: row.try_get("name")?,
+        color: row.try_get("color")?,
+        details: row.try_get("details")?,
+    })
+}
+
+/// First and last name, as Postgres' `concat_ws(' ', first_name, last_name)`
+fn display_name(first_name: Option<String>, last_name: Option<String>) -> String {
+    [first_name, last_name]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[async_trait]
+impl Repository for SqliteRepository {
+    async fn find_user(&self, auth0_id: &str) -> RepoResult<Option<AuthUser>> {
+        let user = sqlx::query(
+            "SELECT u.user_id, u.auth0_id, u.email, u.name, u.is_admin, w.workspace_id,
+                    u.deactivated_at IS NOT NULL AS deactivated, u.delete_after
+             FROM users u
+             JOIN workspaces w ON w.user_id = u.user_id AND w.is_default
+             WHERE u.auth0_id = $1",
+        )
+        .bind(auth0_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        user.map(|user| {
+            Ok(AuthUser {
+                user_id: user.try_get("user_id")?,
+                workspace_id: user.try_get("workspace_id")?,
+                auth0_id: user.try_get("auth0_id")?,
+                email: Some(user.try_get("email")?),
+                name: Some(user.try_get("name")?),
+                is_admin: user.try_get("is_admin")?,
+                deactivated: user.try_get("deactivated")?,
+                delete_after: user.try_get("delete_after")?,
+                scopes: None,
+                authenticated_at: None,
+            })
+        })
+        .transpose()
+    }
+
+    async fn get_or_create_user(
+        &self,
+        auth0_id: &str,
+        email: &str,
+        name: &str,
+    ) -> RepoResult<AuthUser> {
+        if let Some(user) = self.find_user(auth0_id).await? {
+            return Ok(user);
+        }
+
+        let mut tx = self.pool.begin().await?;
+        let user_id: i32 = sqlx::query_scalar(
+            "INSERT INTO users (auth0_id, email, name) VALUES ($1, $2, $3) RETURNING user_id",
+        )
+        .bind(auth0_id)
+        .bind(email)
+        .bind(name)
+        .fetch_one(&mut *tx)
+        .await?;
+        let workspace_id = sqlx::query_scalar(
+            "INSERT INTO workspaces (user_id, name, is_default) VALUES ($1, $2, TRUE)
+             RETURNING workspace_id",
+        )
+        .bind(user_id)
+        .bind(DEFAULT_WORKSPACE_NAME)
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(AuthUser {
+            user_id,
+            workspace_id,
+            auth0_id: auth0_id.to_string(),
+            email: Some(email.to_string()),
+            name: Some(name.to_string()),
+            is_admin: false,
+            deactivated: false,
+            delete_after: None,
+            scopes: None,
+            authenticated_at: None,
+        })
+    }
+
+    async fn delete_user(&self, user_id: i32) -> RepoResult<()> {
+        sqlx::query("DELETE FROM users WHERE user_id = $1")
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn schedule_user_deletion(
+        &self,
+        user_id: i32,
+        delete_after: OffsetDateTime,
+    ) -> RepoResult<()> {
+        sqlx::query(
+            "UPDATE users SET deactivated_at = COALESCE(deactivated_at, $3), delete_after = $2
+             WHERE user_id = $1",
+        )
+        .bind(user_id)
+        .bind(Timestamp(delete_after))
+        .bind(Timestamp(OffsetDateTime::now_utc()))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn restore_user(&self, user_id: i32) -> RepoResult<bool> {
+        let result = sqlx::query(
+            "UPDATE users SET deactivated_at = NULL, delete_after = NULL
+             WHERE user_id = $1 AND delete_after IS NOT NULL",
+        )
+        .bind(user_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn purge_deleted_users(&self, now: OffsetDateTime) -> RepoResult<Vec<String>> {
+        sqlx::query_scalar("DELETE FROM users WHERE delete_after <= $1 RETURNING auth0_id")
+            .bind(Timestamp(now))
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn get_profile(&self, user_id: i32) -> RepoResult<Option<UserProfile>> {
+        let row = sqlx::query(
+            "SELECT u.user_id, u.name, u.email, p.timezone, s.email_notifications,
+                    s.push_notifications, s.weekly_review_email, s.default_reminder_cadence_days
+             FROM users u
+             LEFT JOIN user_preferences p ON p.user_id = u.user_id
+             LEFT JOIN user_settings s ON s.user_id = u.user_id
+             WHERE u.user_id = $1",
+        )
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        row.map(|r| {
+            let defaults = UserProfile::defaults(
+                r.try_get("user_id")?,
+                r.try_get("name")?,
+                r.try_get("email")?,
+            );
+            Ok(UserProfile {
+                timezone: r.try_get("timezone")?,
+                notifications: NotificationSettings {
+                    email: r
+                        .try_get::<Option<bool>, _>("email_notifications")?
+                        .unwrap_or(defaults.notifications.email),
+                    push: r
+                        .try_get::<Option<bool>, _>("push_notifications")?
+                        .unwrap_or(defaults.notifications.push),
+                    weekly_review: r
+                        .try_get::<Option<bool>, _>("weekly_review_email")?
+                        .unwrap_or(defaults.notifications.weekly_review),
+                },
+                default_reminder_cadence_days: r
+                    .try_get::<Option<i32>, _>("default_reminder_cadence_days")?
+                    .unwrap_or(defaults.default_reminder_cadence_days),
+                ..defaults
+            })
+        })
+        .transpose()
+    }
+
+    async fn save_profile(&self, profile: &UserProfile) -> RepoResult<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("UPDATE users SET name = $2, email = $3 WHERE user_id = $1")
+            .bind(profile.user_id)
+            .bind(&profile.name)
+            .bind(&profile.email)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(
+            "INSERT INTO user_preferences (user_id, timezone) VALUES ($1, $2)
+             ON CONFLICT (user_id) DO UPDATE SET timezone = excluded.timezone",
+        )
+        .bind(profile.user_id)
+        .bind(profile.timezone.as_deref())
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query(
+            "INSERT INTO user_settings
+                 (user_id, email_notifications, push_notifications, weekly_review_email,
+                  default_reminder_cadence_days)
+             VALUES ($1, $2, $3, $4, $5)
+             ON CONFLICT (user_id) DO UPDATE SET
+                 email_notifications = excluded.email_notifications,
+                 push_notifications = excluded.push_notifications,
+                 weekly_review_email = excluded.weekly_review_email,
+                 default_reminder_cadence_days = excluded.default_reminder_cadence_days",
+        )
+        .bind(profile.user_id)
+        .bind(profile.notifications.email)
+        .bind(profile.notifications.push)
+        .bind(profile.notifications.weekly_review)
+        .bind(profile.default_reminder_cadence_days)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await
+    }
+
+    async fn find_user_by_email(&self, email: &str) -> RepoResult<Option<i32>> {
+        sqlx::query_scalar(
+            "SELECT user_id FROM users WHERE lower(email) = lower($1) ORDER BY user_id LIMIT 1",
+        )
+        .bind(email)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    async fn list_accounts(&self) -> RepoResult<Vec<AccountSummary>> {
+        let rows = sqlx::query(
+            "SELECT u.user_id, u.auth0_id, u.name, u.email, u.is_admin,
+                    u.deactivated_at IS NOT NULL AS deactivated,
+                    COUNT(DISTINCT w.workspace_id) AS workspace_count,
+                    COUNT(c.contact_id) AS contact_count
+             FROM users u
+             LEFT JOIN workspaces w ON w.user_id = u.user_id
+             LEFT JOIN contacts c ON c.workspace_id = w.workspace_id
+             GROUP BY u.user_id
+             ORDER BY u.user_id",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter()
+            .map(|row| {
+                Ok(AccountSummary {
+                    user_id: row.try_get("user_id")?,
+                    auth0_id: row.try_get("auth0_id")?,
+                    name: row.try_get("name")?,
+                    email: row.try_get("email")?,
+                    is_admin: row.try_get("is_admin")?,
+                    deactivated: row.try_get("deactivated")?,
+                    workspace_count: row.try_get("workspace_count")?,
+                    contact_count: row.try_get("contact_count")?,
+                })
+            })
+            .collect()
+    }
+
+    async fn usage(&self, user_id: i32) -> RepoResult<Usage> {
+        let row = sqlx::query(
+            "SELECT COUNT(*) AS workspaces,
+                    (SELECT COUNT(*) FROM contacts c JOIN workspaces w USING (workspace_id)
+                     WHERE w.user_id = $1) AS contacts,
+                    (SELECT COUNT(*) FROM interactions i JOIN workspaces w USING (workspace_id)
+                     WHERE w.user_id = $1) AS interactions,
+                    (SELECT COUNT(*) FROM tags t JOIN workspaces w USING (workspace_id)
+                     WHERE w.user_id = $1) AS tags,
+                    (SELECT COALESCE(SUM(a.size_bytes), 0)
+                     FROM interaction_attachments a JOIN workspaces w USING (workspace_id)
+                     WHERE w.user_id = $1) AS attachment_bytes
+             FROM workspaces
+             WHERE user_id = $1",
+        )
+        .bind(user_id)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(Usage {
+            workspaces: row.try_get("workspaces")?,
+            contacts: row.try_get("contacts")?,
+            interactions: row.try_get("interactions")?,
+            tags: row.try_get("tags")?,
+            attachment_bytes: row.try_get("attachment_bytes")?,
+        })
+    }
+
+    async fn instance_stats(&self) -> RepoResult<InstanceStats> {
+        let row = sqlx::query(
+            "SELECT (SELECT COUNT(*) FROM users) AS users,
+                    (SELECT COUNT(*) FROM users WHERE deactivated_at IS NOT NULL)
+                        AS deactivated_users,
+                    (SELECT COUNT(*) FROM workspaces) AS workspaces,
+                    (SELECT COUNT(*) FROM contacts) AS contacts,
+                    (SELECT COUNT(*) FROM interactions) AS interactions,
+                    (SELECT COUNT(*) FROM shares) AS shares",
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(InstanceStats {
+            users: row.try_get("users")?,
+            deactivated_users: row.try_get("deactivated_users")?,
+            workspaces: row.try_get("workspaces")?,
+            contacts: row.try_get("contacts")?,
+            interactions: row.try_get("interactions")?,
+            shares: row.try_get("shares")?,
+        })
+    }
+
+    async fn set_deactivated(&self, user_id: i32, deactivated: bool) -> RepoResult<Option<String>> {
+        // An already deactivated user keeps their original deactivation time
+        sqlx::query_scalar(
+            "UPDATE users
+             SET deactivated_at = CASE WHEN $2 THEN COALESCE(deactivated_at, $3) END,
+                 delete_after = CASE WHEN $2 THEN delete_after END
+             WHERE user_id = $1
+             RETURNING auth0_id",
+        )
+        .bind(user_id)
+        .bind(deactivated)
+        .bind(Timestamp(OffsetDateTime::now_utc()))
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    async fn weekly_review_recipients(&self) -> RepoResult<Vec<ReviewRecipient>> {
+        let rows = sqlx::query(
+            "SELECT u.user_id, w.workspace_id, u.name, u.email, s.weekly_review_sent_on
+             FROM users u
+             JOIN user_settings s ON s.user_id = u.user_id
+             JOIN workspaces w ON w.user_id = u.user_id AND w.is_default
+             WHERE u.deactivated_at IS NULL AND s.weekly_review_email AND s.email_notifications
+             ORDER BY u.user_id",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter()
+            .map(|row| {
+                Ok(ReviewRecipient {
+                    user_id: row.try_get("user_id")?,
+                    workspace_id: row.try_get("workspace_id")?,
+                    name: row.try_get("name")?,
+                    email: row.try_get("email")?,
+                    sent_week: row.try_get("weekly_review_sent_on")?,
+                })
+            })
+            .collect()
+    }
+
+    async fn record_weekly_review(&self, user_id: i32, week: Date) -> RepoResult<()> {
+        sqlx::query("UPDATE user_settings SET weekly_review_sent_on = $2 WHERE user_id = $1")
+            .bind(user_id)
+            .bind(week)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn queue_weekly_review(
+        &self,
+        user_id: i32,
+        workspace_id: i32,
+        week: Date,
+        email: &Email,
+    ) -> RepoResult<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("INSERT INTO outbox (workspace_id, payload) VALUES ($1, $2)")
+            .bind(workspace_id)
+            .bind(Json(email))
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("UPDATE user_settings SET weekly_review_sent_on = $2 WHERE user_id = $1")
+            .bind(user_id)
+            .bind(week)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await
+    }
+
+    async fn queue_email(&self, workspace_id: i32, email: &Email) -> RepoResult<()> {
+        sqlx::query("INSERT INTO outbox (workspace_id, payload) VALUES ($1, $2)")
+            .bind(workspace_id)
+            .bind(Json(email))
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn push_reminder_recipients(&self) -> RepoResult<Vec<PushRecipient>> {
+        let rows = sqlx::query(
+            "SELECT u.user_id, w.workspace_id, s.push_reminders_sent_on
+             FROM users u
+             JOIN user_settings s ON s.user_id = u.user_id
+             JOIN workspaces w ON w.user_id = u.user_id AND w.is_default
+             WHERE u.deactivated_at IS NULL AND s.push_notifications
+               AND EXISTS (SELECT 1 FROM push_subscriptions p WHERE p.user_id = u.user_id)
+             ORDER BY u.user_id",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter()
+            .map(|row| {
+                Ok(PushRecipient {
+                    user_id: row.try_get("user_id")?,
+                    workspace_id: row.try_get("workspace_id")?,
+                    sent_on: row.try_get("push_reminders_sent_on")?,
+                })
+            })
+            .collect()
+    }
+
+    async fn queue_push_reminders(
+        &self,
+        user_id: i32,
+        workspace_id: i32,
+        day: Date,
+        messages: &[PushMessage],
+    ) -> RepoResult<()> {
+        let mut tx = self.pool.begin().await?;
+        for message in messages {
+            sqlx::query(
+                "INSERT INTO outbox (workspace_id, push_subscription_id, payload)
+                 SELECT $2, subscription_id, $3 FROM push_subscriptions WHERE user_id = $1
+                 ORDER BY subscription_id",
+            )
+            .bind(user_id)
+            .bind(workspace_id)
+            .bind(Json(message))
+            .execute(&mut *tx)
+            .await?;
+        }
+        sqlx::query("UPDATE user_settings SET push_reminders_sent_on = $2 WHERE user_id = $1")
+            .bind(user_id)
+            .bind(day)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await
+    }
+
+    async fn list_push_subscriptions(&self, user_id: i32) -> RepoResult<Vec<PushSubscription>> {
+        let rows = sqlx::query(
+            "SELECT subscription_id, user_id, endpoint, p256dh, auth, device_name, created_at
+             FROM push_subscriptions WHERE user_id = $1
+             ORDER BY subscription_id",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(push_subscription).collect()
+    }
+
+    async fn save_push_subscription(
+        &self,
+        user_id: i32,
+        subscription: &NewPushSubscriptionRequest,
+    ) -> RepoResult<PushSubscription> {
+        let row = sqlx::query(
+            "INSERT INTO push_subscriptions (user_id, endpoint, p256dh, auth, device_name)
+             VALUES ($1, $2, $3, $4, $5)
+             ON CONFLICT (user_id, endpoint) DO UPDATE
+             SET p256dh = excluded.p256dh, auth = excluded.auth, device_name = excluded.device_name
+             RETURNING subscription_id, user_id, endpoint, p256dh, auth, device_name, created_at",
+        )
+        .bind(user_id)
+        .bind(&subscription.endpoint)
+        .bind(&subscription.keys.p256dh)
+        .bind(&subscription.keys.auth)
+        .bind(subscription.device_name.as_deref())
+        .fetch_one(&self.pool)
+        .await?;
+        push_subscription(&row)
+    }
+
+    async fn delete_push_subscription(
+        &self,
+        user_id: i32,
+        subscription_id: i32,
+    ) -> RepoResult<bool> {
+        let result = sqlx::query(
+            "DELETE FROM push_subscriptions WHERE subscription_id = $1 AND user_id = $2",
+        )
+        .bind(subscription_id)
+        .bind(user_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn owns_push_subscription(&self, user_id: i32, subscription_id: i32) -> RepoResult<bool> {
+        self.exists(
+            "SELECT 1 FROM push_subscriptions WHERE subscription_id = $1 AND user_id = $2",
+            subscription_id,
+            user_id,
+        )
+        .await
+    }
+
+    async fn claim_job(
+        &self,
+        name: &str,
+        holder: &str,
+        now: OffsetDateTime,
+        until: OffsetDateTime,
+    ) -> RepoResult<bool> {
+        let claimed: Option<String> = sqlx::query_scalar(
+            "INSERT INTO job_leases (name, holder, locked_until) VALUES ($1, $2, $4)
+             ON CONFLICT (name) DO UPDATE SET holder = $2, locked_until = $4
+             WHERE job_leases.holder = $2 OR job_leases.locked_until <= $3
+             RETURNING name",
+        )
+        .bind(name)
+        .bind(holder)
+        .bind(Timestamp(now))
+        .bind(Timestamp(until))
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(claimed.is_some())
+    }
+
+    async fn list_workspaces(&self, user_id: i32) -> RepoResult<Vec<Workspace>> {
+        sqlx::query_as(
+            "SELECT workspace_id, name, is_default FROM workspaces
+             WHERE user_id = $1 ORDER BY is_default DESC, name",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn create_workspace(&self, user_id: i32, name: &str) -> RepoResult<i32> {
+        sqlx::query_scalar(
+            "INSERT INTO workspaces (user_id, name) VALUES ($1, $2) RETURNING workspace_id",
+        )
+        .bind(user_id)
+        .bind(name)
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    async fn rename_workspace(
+        &self,
+        user_id: i32,
+        workspace_id: i32,
+        name: &str,
+    ) -> RepoResult<bool> {
+        let result =
+            sqlx::query("UPDATE workspaces SET name = $1 WHERE workspace_id = $2 AND user_id = $3")
+                .bind(name)
+                .bind(workspace_id)
+                .bind(user_id)
+                .execute(&self.pool)
+                .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn delete_workspace(&self, user_id: i32, workspace_id: i32) -> RepoResult<bool> {
+        let result = sqlx::query(
+            "DELETE FROM workspaces WHERE workspace_id = $1 AND user_id = $2 AND NOT is_default",
+        )
+        .bind(workspace_id)
+        .bind(user_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn owns_workspace(&self, user_id: i32, workspace_id: i32) -> RepoResult<bool> {
+        self.exists(
+            "SELECT 1 FROM workspaces WHERE workspace_id = $1 AND user_id = $2",
+            workspace_id,
+            user_id,
+        )
+        .await
+    }
+
+    async fn list_contacts(&self, workspace_id: i32) -> RepoResult<Vec<Contact>> {
+        let contacts = sqlx::query_as(&format!(
+            "SELECT {CONTACT_COLUMNS} FROM contacts
+             WHERE workspace_id = $1
+             ORDER BY last_name NULLS LAST, first_name NULLS LAST"
+        ))
+        .bind(workspace_id)
+        .fetch_all(&self.pool)
+        .await?;
+        self.open_contacts(contacts)
+    }
+
+    async fn contacts_by_priority(
+        &self,
+        workspace_id: i32,
+        dates: &LocalDates,
+        include_archived: bool,
+        snoozed: &[i32],
+        limit: Option<i64>,
+        offset: i64,
+    ) -> RepoResult<Vec<Contact>> {
+        let contact_ids: Vec<i32> = sqlx::query_scalar(
+            "SELECT contact_id FROM contacts
+             WHERE workspace_id = $1 AND ($2 OR NOT archived)
+             ORDER BY contact_id",
+        )
+        .bind(workspace_id)
+        .bind(include_archived)
+        .fetch_all(&self.pool)
+        .await?;
+        let mut scored: Vec<(Option<f32>, Contact)> = self
+            .contact_details(workspace_id, &contact_ids)
+            .await?
+            .into_iter()
+            .map(|d| {
+                let response =
+                    ContactResponse::new(d.contact, Vec::new(), d.interactions, d.occasions, dates);
+                (response.predicted_contact_priority, response.contact)
+            })
+            .collect();
+        // Contacts come by id, and the sort is stable, so ties stay in id order
+        scored.sort_by(|(a, x), (b, y)| {
+            let snoozed_first = snoozed
+                .contains(&x.contact_id)
+                .cmp(&snoozed.contains(&y.contact_id));
+            snoozed_first.then_with(|| match (a, b) {
+                (Some(a), Some(b)) => b.total_cmp(a),
+                _ => a.is_none().cmp(&b.is_none()),
+            })
+        });
+        Ok(scored
+            .into_iter()
+            .map(|(_, contact)| contact)
+            .skip(offset.max(0) as usize)
+            .take(limit.map_or(usize::MAX, |n| n.max(0) as usize))
+            .collect())
+    }
+
+    async fn get_contact(&self, workspace_id: i32, contact_id: i32) -> RepoResult<Option<Contact>> {
+        let contact = sqlx::query_as(&format!(
+            "SELECT {CONTACT_COLUMNS} FROM contacts WHERE contact_id = $1 AND workspace_id = $2"
+        ))
+        .bind(contact_id)
+        .bind(workspace_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        contact.map(|c| self.open_contact(c)).transpose()
+    }
+
+    async fn contacts_by_phone(&self, workspace_id: i32, number: &str) -> RepoResult<Vec<Contact>> {
+        let contacts = sqlx::query_as(&format!(
+            "SELECT {CONTACT_COLUMNS} FROM contacts
+             WHERE workspace_id = $1 AND phone_e164 = $2
+             ORDER BY contact_id"
+        ))
+        .bind(workspace_id)
+        .bind(number)
+        .fetch_all(&self.pool)
+        .await?;
+        self.open_contacts(contacts)
+    }
+
+    async fn contacts_introduced_by(
+        &self,
+        workspace_id: i32,
+        contact_id: i32,
+    ) -> RepoResult<Vec<Contact>> {
+        let contacts = sqlx::query_as(&format!(
+            "SELECT {CONTACT_COLUMNS} FROM contacts
+             WHERE workspace_id = $1 AND introduced_by_contact_id = $2
+             ORDER BY last_name NULLS LAST, first_name NULLS LAST, contact_id"
+        ))
+        .bind(workspace_id)
+        .bind(contact_id)
+        .fetch_all(&self.pool)
+        .await?;
+        self.open_contacts(contacts)
+    }
+
+    async fn latest_contacts(&self, workspace_id: i32, limit: i64) -> RepoResult<Vec<Contact>> {
+        let contacts = sqlx::query_as(&format!(
+            "SELECT {CONTACT_COLUMNS} FROM contacts
+             WHERE workspace_id = $1
+             ORDER BY contact_id DESC
+             LIMIT $2"
+        ))
+        .bind(workspace_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        self.open_contacts(contacts)
+    }
+
+    async fn contacts_created_between(
+        &self,
+        workspace_id: i32,
+        from: OffsetDateTime,
+        until: OffsetDateTime,
+    ) -> RepoResult<Vec<Contact>> {
+        let contacts = sqlx::query_as(&format!(
+            "SELECT {CONTACT_COLUMNS} FROM contacts
+             WHERE workspace_id = $1 AND created_at >= $2 AND created_at < $3
+             ORDER BY created_at, contact_id"
+        ))
+        .bind(workspace_id)
+        .bind(Timestamp(from))
+        .bind(Timestamp(until))
+        .fetch_all(&self.pool)
+        .await?;
+        self.open_contacts(contacts)
+    }
+
+    async fn contacts_in_box(
+        &self,
+        workspace_id: i32,
+        area: &BoundingBox,
+    ) -> RepoResult<Vec<Contact>> {
+        let contacts = sqlx::query_as(&format!(
+            "SELECT {CONTACT_COLUMNS} FROM contacts
+             WHERE workspace_id = $1 AND NOT archived AND latitude BETWEEN $2 AND $3
+               AND CASE WHEN $4 <= $5 THEN longitude BETWEEN $4 AND $5
+                        -- Across the antimeridian
+                        ELSE longitude >= $4 OR longitude <= $5
+                   END
+             ORDER BY contact_id"
+        ))
+        .bind(workspace_id)
+        .bind(area.min_latitude)
+        .bind(area.max_latitude)
+        .bind(area.min_longitude)
+        .bind(area.max_longitude)
+        .fetch_all(&self.pool)
+        .await?;
+        self.open_contacts(contacts)
+    }
+
+    async fn autocomplete_contacts(
+        &self,
+        workspace_id: i32,
+        words: &[String],
+        limit: i64,
+    ) -> RepoResult<Vec<ContactMatch>> {
+        if words.is_empty() {
+            return Ok(Vec::new());
+        }
+        // SQLite's lower() and LIKE only fold ASCII, so names are matched here
+        let rows = sqlx::query(
+            "SELECT contact_id, first_name, last_name, avatar_url
+             FROM contacts
+             WHERE workspace_id = $1 AND NOT archived
+             ORDER BY last_name NULLS LAST, first_name NULLS LAST, contact_id",
+        )
+        .bind(workspace_id)
+        .fetch_all(&self.pool)
+        .await?;
+        let mut matches = Vec::new();
+        for row in rows {
+            let first_name: Option<String> = row.try_get("first_name")?;
+            let last_name: Option<String> = row.try_get("last_name")?;
+            let starts = |name: &Option<String>, word: &String| {
+                name.as_ref()
+                    .is_some_and(|name| name.to_lowercase().starts_with(word.as_str()))
+            };
+            if words
+                .iter()
+                .all(|w| starts(&first_name, w) || starts(&last_name, w))
+            {
+                matches.push(ContactMatch {
+                    contact_id: row.try_get("contact_id")?,
+                    display_name: display_name(first_name, last_name),
+                    avatar_url: row.try_get("avatar_url")?,
+                });
+            }
+        }
+        matches.truncate(limit.max(0) as usize);
+        Ok(matches)
+    }
+
+    async fn contact_details(
+        &self,
+        workspace_id: i32,
+        contact_ids: &[i32],
+    ) -> RepoResult<Vec<ContactDetails>> {
+        let contacts: Vec<Contact> = sqlx::query_as(&format!(
+            "SELECT {CONTACT_COLUMNS}
+             FROM json_each($2) AS wanted
+             JOIN contacts c ON c.contact_id = wanted.value AND c.workspace_id = $1
+             ORDER BY wanted.key"
+        ))
+        .bind(workspace_id)
+        .bind(Json(contact_ids))
+        .fetch_all(&self.pool)
+        .await?;
+        let contacts = self.open_contacts(contacts)?;
+        let found: Vec<i32> = contacts.iter().map(|c| c.contact_id).collect();
+        let tags = self.tags_for_contacts(&found).await?;
+        let interactions = self.interactions_for_contacts(&found).await?;
+        let occasions = self.occasions_for_contacts(&found).await?;
+        Ok(ContactDetails::group(
+            contacts,
+            tags,
+            interactions,
+            occasions,
+        ))
+    }
+
+    async fn contact_summaries(
+        &self,
+        workspace_id: i32,
+        contact_ids: &[i32],
+        today: Date,
+    ) -> RepoResult<Vec<(i32, ContactSummary)>> {
+        let rows = sqlx::query(
+            "SELECT contact_id, last_interaction_at FROM contacts
+             WHERE contact_id IN (SELECT value FROM json_each($1)) AND workspace_id = $2
+             ORDER BY contact_id",
+        )
+        .bind(Json(contact_ids))
+        .bind(workspace_id)
+        .fetch_all(&self.pool)
+        .await?;
+        let mut summaries = Vec::with_capacity(rows.len());
+        for row in &rows {
+            let contact_id: i32 = row.try_get("contact_id")?;
+            let summary = ContactSummary {
+                last_interaction_at: row.try_get("last_interaction_at")?,
+                next_occasion_at: None,
+            };
+            summaries.push((contact_id, summary));
+        }
+        let found: Vec<i32> = summaries.iter().map(|(id, _)| *id).collect();
+        for occasion in self.occasions_for_contacts(&found).await? {
+            let next = reminders::next_occurrence(&occasion, today);
+            if let Some((_, summary)) = summaries
+                .iter_mut()
+                .find(|(id, _)| Some(*id) == occasion.contact_id)
+            {
+                summary.next_occasion_at = match (summary.next_occasion_at, next) {
+                    (Some(a), Some(b)) => Some(a.min(b)),
+                    (a, b) => a.or(b),
+                };
+            }
+        }
+        Ok(summaries)
+    }
+
+    async fn create_contact(
+        &self,
+        workspace_id: i32,
+        contact: &NewContactRequest,
+    ) -> RepoResult<i32> {
+        let mut tx = self.pool.begin().await?;
+        let contact_id =
+            insert_contact(&mut tx, workspace_id, contact, self.notes.as_ref()).await?;
+        tx.commit().await?;
+        Ok(contact_id)
+    }
+
+    async fn create_contacts(
+        &self,
+        workspace_id: i32,
+        contacts: &[NewContactRequest],
+        atomic: bool,
+    ) -> RepoResult<Vec<RepoResult<i32>>> {
+        let mut tx = self.pool.begin().await?;
+
+        if atomic {
+            let ids = insert_contacts(&mut tx, workspace_id, contacts, self.notes.as_ref()).await?;
+            tx.commit().await?;
+            return Ok(ids.into_iter().map(Ok).collect());
+        }
+
+        let results =
+            insert_contacts_skipping_failures(&mut tx, workspace_id, contacts, self.notes.as_ref())
+                .await?;
+        tx.commit().await?;
+        Ok(results)
+    }
+
+    async fn check_contacts(
+        &self,
+        workspace_id: i32,
+        contacts: &[NewContactRequest],
+    ) -> RepoResult<Vec<RepoResult<()>>> {
+        let mut tx = self.pool.begin().await?;
+        let results =
+            insert_contacts_skipping_failures(&mut tx, workspace_id, contacts, self.notes.as_ref())
+                .await?;
+        tx.rollback().await?;
+        Ok(results.into_iter().map(|r| r.map(|_| ())).collect())
+    }
+
+    async fn update_contact(
+        &self,
+        workspace_id: i32,
+        contact_id: i32,
+        contact: &NewContactRequest,
+    ) -> RepoResult<bool> {
+        let mut tx = self.pool.begin().await?;
+        let Some(before): Option<Contact> = sqlx::query_as(&format!(
+            "SELECT {CONTACT_COLUMNS} FROM contacts WHERE contact_id = $1 AND workspace_id = $2"
+        ))
+        .bind(contact_id)
+        .bind(workspace_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        else {
+            return Ok(false);
+        };
+        let before = self.open_contact(before)?;
+
+        sqlx::query(
+            "UPDATE contacts
+             SET first_name = $1, last_name = $2, email = $3, phone = $4, short_note = $5, notes = $6,
+                 avatar_url = $7, desired_frequency_days = $10, phone_e164 = $11, met_at = $12,
+                 introduced_by_contact_id = $13, location = $14,
+                 -- Coordinates belong to the old location until it is geocoded again
+                 latitude = CASE WHEN location IS NOT $14 THEN NULL ELSE latitude END,
+                 longitude = CASE WHEN location IS NOT $14 THEN NULL ELSE longitude END
+             WHERE contact_id = $8 AND workspace_id = $9",
+        )
+        .bind(contact.first_name.as_deref())
+        .bind(contact.last_name.as_deref())
+        .bind(contact.email.as_deref())
+        .bind(contact.phone.as_deref())
+        .bind(encryption::seal(
+            self.notes.as_ref(),
+            contact.short_note.as_deref(),
+        ))
+        .bind(encryption::seal(self.notes.as_ref(), contact.notes.as_deref()))
+        .bind(contact.avatar_url.as_deref())
+        .bind(contact_id)
+        .bind(workspace_id)
+        .bind(contact.desired_frequency_days)
+        .bind(phone::e164(contact.phone.as_deref()))
+        .bind(contact.met_at.as_ref().map(Json))
+        .bind(contact.introduced_by_contact_id)
+        .bind(contact.location.as_deref())
+        .execute(&mut *tx)
+        .await?;
+        insert_contact_changes(
+            &mut tx,
+            contact_id,
+            &history::changes(&before, contact),
+            self.notes.as_ref(),
+        )
+        .await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn delete_contact(
+        &self,
+        workspace_id: i32,
+        contact_id: i32,
+        keep_history: bool,
+    ) -> RepoResult<bool> {
+        let deleted = self
+            .delete_contacts(workspace_id, &[contact_id], keep_history)
+            .await?;
+        Ok(!deleted.is_empty())
+    }
+
+    async fn delete_contacts(
+        &self,
+        workspace_id: i32,
+        contact_ids: &[i32],
+        keep_history: bool,
+    ) -> RepoResult<Vec<i32>> {
+        let mut tx = self.pool.begin().await?;
+        if keep_history {
+            // Detached first, or the foreign keys would cascade the delete to them
+            sqlx::query(
+                "UPDATE interactions SET contact_id = NULL
+                 WHERE contact_id IN (SELECT value FROM json_each($1)) AND workspace_id = $2",
+            )
+            .bind(Json(contact_ids))
+            .bind(workspace_id)
+            .execute(&mut *tx)
+            .await?;
+            sqlx::query(
+                "UPDATE occasions SET contact_id = NULL
+                 WHERE contact_id IN (SELECT value FROM json_each($1)) AND workspace_id = $2",
+            )
+            .bind(Json(contact_ids))
+            .bind(workspace_id)
+            .execute(&mut *tx)
+            .await?;
+        }
+        let deleted = sqlx::query_scalar(
+            "DELETE FROM contacts
+             WHERE contact_id IN (SELECT value FROM json_each($1)) AND workspace_id = $2
+             RETURNING contact_id",
+        )
+        .bind(Json(contact_ids))
+        .bind(workspace_id)
+        .fetch_all(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(deleted)
+    }
+
+    async fn list_unassigned(&self, workspace_id: i32) -> RepoResult<Unassigned> {
+        let rows = sqlx::query(&format!(
+            "SELECT {INTERACTION_COLUMNS}
+             FROM interactions i
+             LEFT JOIN interaction_types t ON t.type_id = i.custom_type_id
+             WHERE i.workspace_id = $1 AND i.contact_id IS NULL
+             ORDER BY i.interaction_date, i.interaction_id"
+        ))
+        .bind(workspace_id)
+        .fetch_all(&self.pool)
+        .await?;
+        let interactions = interactions(rows)?;
+        let rows = sqlx::query(&format!(
+            "SELECT {OCCASION_COLUMNS} FROM occasions
+             WHERE workspace_id = $1 AND contact_id IS NULL
+             ORDER BY date, occasion_id"
+        ))
+        .bind(workspace_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(Unassigned {
+            interactions,
+            occasions: occasions(rows)?,
+        })
+    }
+
+    async fn set_contact_archived(
+        &self,
+        workspace_id: i32,
+        contact_id: i32,
+        archived: bool,
+    ) -> RepoResult<bool> {
+        let mut tx = self.pool.begin().await?;
+        let Some(before): Option<bool> = sqlx::query_scalar(
+            "SELECT archived FROM contacts WHERE contact_id = $1 AND workspace_id = $2",
+        )
+        .bind(contact_id)
+        .bind(workspace_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        else {
+            return Ok(false);
+        };
+
+        sqlx::query(
+            "UPDATE contacts SET archived = $1 WHERE contact_id = $2 AND workspace_id = $3",
+        )
+        .bind(archived)
+        .bind(contact_id)
+        .bind(workspace_id)
+        .execute(&mut *tx)
+        .await?;
+        let change = history::archived_change(before, archived);
+        insert_contact_changes(&mut tx, contact_id, change.as_slice(), self.notes.as_ref()).await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn set_contact_coordinates(
+        &self,
+        workspace_id: i32,
+        contact_id: i32,
+        location: &str,
+        coordinates: Coordinates,
+    ) -> RepoResult<bool> {
+        let result = sqlx::query(
+            "UPDATE contacts SET latitude = $1, longitude = $2
+             WHERE contact_id = $3 AND workspace_id = $4 AND location = $5",
+        )
+        .bind(coordinates.latitude)
+        .bind(coordinates.longitude)
+        .bind(contact_id)
+        .bind(workspace_id)
+        .bind(location)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn owns_contact(&self, workspace_id: i32, contact_id: i32) -> RepoResult<bool> {
+        self.exists(
+            "SELECT 1 FROM contacts WHERE contact_id = $1 AND workspace_id = $2",
+            contact_id,
+            workspace_id,
+        )
+        .await
+    }
+
+    async fn contact_checksums(&self, workspace_id: i32) -> RepoResult<Vec<ContactChecksum>> {
+        let contact_ids: Vec<i32> = sqlx::query_scalar(
+            "SELECT contact_id FROM contacts WHERE workspace_id = $1 ORDER BY contact_id",
+        )
+        .bind(workspace_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(self
+            .contact_details(workspace_id, &contact_ids)
+            .await?
+            .into_iter()
+            .map(|d| {
+                let tag_ids: Vec<i32> = d.tags.iter().map(|t| t.tag_id).collect();
+                let content = serde_json::json!([d.contact, tag_ids, d.interactions, d.occasions]);
+                ContactChecksum {
+                    contact_id: d.contact.contact_id,
+                    hash: format!("{:x}", Sha256::digest(content.to_string())),
+                }
+            })
+            .collect())
+    }
+
+    async fn refresh_contact_scores(&self, _limit: i64) -> RepoResult<u64> {
+        // Priorities are computed on every read, so there is no score to store
+        Ok(0)
+    }
+
+    async fn list_tags(&self, workspace_id: i32) -> RepoResult<Vec<Tag>> {
+        let rows = sqlx::query(
+            "SELECT tag_id, name, color, details FROM tags WHERE workspace_id = $1 ORDER BY tag_id",
+        )
+        .bind(workspace_id)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(tag).collect()
+    }
+
+    async fn get_tag(&self, workspace_id: i32, tag_id: i32) -> RepoResult<Option<Tag>> {
+        let row = sqlx::query(
+            "SELECT tag_id, name, color, details FROM tags WHERE tag_id = $1 AND workspace_id = $2",
+        )
+        .bind(tag_id)
+        .bind(workspace_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        row.as_ref().map(tag).transpose()
+    }
+
+    async fn tag_usage(&self, workspace_id: i32) -> RepoResult<Vec<TagUsage>> {
+        let rows = sqlx::query(
+            "SELECT t.tag_id, t.name, t.color, t.details, COUNT(ct.contact_id) AS contact_count
+             FROM tags t
+             LEFT JOIN contact_tags ct ON ct.tag_id = t.tag_id
+             WHERE t.workspace_id = $1
+             GROUP BY t.tag_id
+             ORDER BY t.tag_id",
+        )
+        .bind(workspace_id)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter()
+            .map(|row| {
+                Ok(TagUsage {
+                    tag: tag(row)?,
+                    contact_count: row.try_get("contact_count")?,
+                })
+            })
+            .collect()
+    }
+
+    async fn create_tag(&self, workspace_id: i32, tag: &NewTagRequest) -> RepoResult<i32> {
+        sqlx::query_scalar(
+            "INSERT INTO tags (workspace_id, name, color, details)
+             VALUES ($1, $2, $3, $4)
+             RETURNING tag_id",
+        )
+        .bind(workspace_id)
+        .bind(&tag.name)
+        .bind(tag.color.as_deref())
+        .bind(tag.details.as_deref())
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    async fn update_tag(
+        &self,
+        workspace_id: i32,
+        tag_id: i32,
+        tag: &NewTagRequest,
+    ) -> RepoResult<bool> {
+        let result = sqlx::query(
+            "UPDATE tags SET name = $1, color = $2, details = $3 WHERE tag_id = $4 AND workspace_id = $5",
+        )
+        .bind(&tag.name)
+        .bind(tag.color.as_deref())
+        .bind(tag.details.as_deref())
+        .bind(tag_id)
+        .bind(workspace_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn delete_tag(&self, workspace_id: i32, tag_id: i32) -> RepoResult<bool> {
+        let result = sqlx::query("DELETE FROM tags WHERE tag_id = $1 AND workspace_id = $2")
+            .bind(tag_id)
+            .bind(workspace_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn delete_unused_tags(&self, workspace_id: i32) -> RepoResult<Vec<i32>> {
+        sqlx::query_scalar(
+            "DELETE FROM tags
+             WHERE workspace_id = $1
+               AND NOT EXISTS (SELECT 1 FROM contact_tags ct WHERE ct.tag_id = tags.tag_id)
+               AND NOT EXISTS (SELECT 1 FROM interaction_tags it WHERE it.tag_id = tags.tag_id)
+               AND NOT EXISTS (SELECT 1 FROM shares s WHERE s.tag_id = tags.tag_id)
+             RETURNING tag_id",
+        )
+        .bind(workspace_id)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn owns_tag(&self, workspace_id: i32, tag_id: i32) -> RepoResult<bool> {
+        self.exists(
+            "SELECT 1 FROM tags WHERE tag_id = $1 AND workspace_id = $2",
+            tag_id,
+            workspace_id,
+        )
+        .await
+    }
+
+    async fn tags_for_contacts(&self, contact_ids: &[i32]) -> RepoResult<Vec<(i32, Tag)>> {
+        let rows = sqlx::query(
+            "SELECT ct.contact_id, t.tag_id, t.name, t.color, t.details
+             FROM contact_tags ct
+             JOIN tags t ON ct.tag_id = t.tag_id
+             WHERE ct.contact_id IN (SELECT value FROM json_each($1))
+             ORDER BY t.tag_id",
+        )
+        .bind(Json(contact_ids))
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter()
+            .map(|row| Ok((row.try_get("contact_id")?, tag(row)?)))
+            .collect()
+    }
+
+    async fn add_tag_to_contact(&self, contact_id: i32, tag_id: i32) -> RepoResult<()> {
+        sqlx::query(
+            "INSERT INTO contact_tags (contact_id, tag_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+        )
+        .bind(contact_id)
+        .bind(tag_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn remove_tag_from_contact(&self, contact_id: i32, tag_id: i32) -> RepoResult<()> {
+        sqlx::query("DELETE FROM contact_tags WHERE contact_id = $1 AND tag_id = $2")
+            .bind(contact_id)
+            .bind(tag_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn add_tag_to_interaction(&self, interaction_id: i32, tag_id: i32) -> RepoResult<()> {
+        sqlx::query(
+            "INSERT INTO interaction_tags (interaction_id, tag_id) VALUES ($1, $2)
+             ON CONFLICT DO NOTHING",
+        )
+        .bind(interaction_id)
+        .bind(tag_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn remove_tag_from_interaction(
+        &self,
+        interaction_id: i32,
+        tag_id: i32,
+    ) -> RepoResult<()> {
+        sqlx::query("DELETE FROM interaction_tags WHERE interaction_id = $1 AND tag_id = $2")
+            .bind(interaction_id)
+            .bind(tag_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn add_tag_to_contacts(
+        &self,
+        workspace_id: i32,
+        tag_id: i32,
+        contact_ids: &[i32],
+    ) -> RepoResult<Vec<i32>> {
+        let mut tx = self.pool.begin().await?;
+        let tagged: Vec<i32> = sqlx::query_scalar(
+            "SELECT DISTINCT c.contact_id
+             FROM json_each($1) AS ids
+             JOIN contacts c ON c.contact_id = ids.value
+             WHERE c.workspace_id = $2
+             ORDER BY c.contact_id",
+        )
+        .bind(Json(contact_ids))
+        .bind(workspace_id)
+        .fetch_all(&mut *tx)
+        .await?;
+        sqlx::query(
+            "INSERT INTO contact_tags (contact_id, tag_id)
+             SELECT c.contact_id, $3
+             FROM json_each($1) AS ids
+             JOIN contacts c ON c.contact_id = ids.value
+             WHERE c.workspace_id = $2
+             ON CONFLICT DO NOTHING",
+        )
+        .bind(Json(&tagged))
+        .bind(workspace_id)
+        .bind(tag_id)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(tagged)
+    }
+
+    async fn interactions_for_contacts(&self, contact_ids: &[i32]) -> RepoResult<Vec<Interaction>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {INTERACTION_COLUMNS}
+             FROM interactions i
+             LEFT JOIN interaction_types t ON t.type_id = i.custom_type_id
+             WHERE i.contact_id IN (SELECT value FROM json_each($1))
+                OR i.interaction_id IN (SELECT interaction_id FROM interaction_participants
+                                        WHERE contact_id IN (SELECT value FROM json_each($1)))
+             ORDER BY i.interaction_id"
+        ))
+        .bind(Json(contact_ids))
+        .fetch_all(&self.pool)
+        .await?;
+        interactions(rows)
+    }
+
+    async fn interactions_by_id(
+        &self,
+        workspace_id: i32,
+        interaction_ids: &[i32],
+    ) -> RepoResult<Vec<Interaction>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {INTERACTION_COLUMNS}
+             FROM interactions i
+             LEFT JOIN interaction_types t ON t.type_id = i.custom_type_id
+             WHERE i.workspace_id = $1 AND i.interaction_id IN (SELECT value FROM json_each($2))
+             ORDER BY i.interaction_id"
+        ))
+        .bind(workspace_id)
+        .bind(Json(interaction_ids))
+        .fetch_all(&self.pool)
+        .await?;
+        interactions(rows)
+    }
+
+    async fn search_notes(
+        &self,
+        workspace_id: i32,
+        query: &str,
+        limit: i64,
+    ) -> RepoResult<Vec<NoteMatch>> {
+        let words = search_words(query);
+        if words.is_empty() {
+            return Ok(Vec::new());
+        }
+        // Without Postgres' text search, notes are matched by word prefixes as in memory
+        let rows = sqlx::query(
+            "SELECT i.interaction_id, i.interaction_date, i.notes,
+                    c.contact_id, c.first_name, c.last_name, c.avatar_url
+             FROM interactions i
+             LEFT JOIN contacts c ON c.contact_id = i.contact_id
+             WHERE i.workspace_id = $1 AND i.notes IS NOT NULL
+             UNION ALL
+             SELECT NULL, NULL, notes, contact_id, first_name, last_name, avatar_url
+             FROM contacts
+             WHERE workspace_id = $1 AND notes IS NOT NULL",
+        )
+        .bind(workspace_id)
+        .fetch_all(&self.pool)
+        .await?;
+        let mut hits = Vec::new();
+        for row in rows {
+            let notes: String = row.try_get("notes")?;
+            // Encrypted contact notes are not searched
+            if encryption::is_encrypted(&notes) {
+                continue;
+            }
+            let Some((rank, snippet)) = highlight(&notes, &words) else {
+                continue;
+            };
+            let interaction_id: Option<i32> = row.try_get("interaction_id")?;
+            let contact_id: Option<i32> = row.try_get("contact_id")?;
+            let contact = match contact_id {
+                Some(contact_id) => Some(ContactMatch {
+                    contact_id,
+                    display_name: display_name(
+                        row.try_get("first_name")?,
+                        row.try_get("last_name")?,
+                    ),
+                    avatar_url: row.try_get("avatar_url")?,
+                }),
+                None => None,
+            };
+            let found = NoteMatch {
+                source: match interaction_id {
+                    Some(_) => NoteSource::Interaction,
+                    None => NoteSource::Contact,
+                },
+                interaction_id,
+                interaction_date: row.try_get("interaction_date")?,
+                contact,
+                snippet,
+            };
+            hits.push((rank, found));
+        }
+        // Best first, then newest first with contacts' notes last
+        hits.sort_by(|(a_rank, a), (b_rank, b)| {
+            b_rank
+                .cmp(a_rank)
+                .then(b.interaction_date.cmp(&a.interaction_date))
+        });
+        Ok(hits
+            .into_iter()
+            .take(limit.max(0) as usize)
+            .map(|(_, found)| found)
+            .collect())
+    }
+
+    async fn latest_interactions(
+        &self,
+        workspace_id: i32,
+        limit: i64,
+    ) -> RepoResult<Vec<Interaction>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {INTERACTION_COLUMNS}
+             FROM interactions i
+             LEFT JOIN interaction_types t ON t.type_id = i.custom_type_id
+             WHERE i.workspace_id = $1
+             ORDER BY i.interaction_id DESC
+             LIMIT $2"
+        ))
+        .bind(workspace_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        interactions(rows)
+    }
+
+    async fn interactions_since(
+        &self,
+        workspace_id: i32,
+        since: OffsetDateTime,
+    ) -> RepoResult<Vec<Interaction>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {INTERACTION_COLUMNS}
+             FROM interactions i
+             LEFT JOIN interaction_types t ON t.type_id = i.custom_type_id
+             WHERE i.workspace_id = $1 AND i.interaction_date >= $2"
+        ))
+        .bind(workspace_id)
+        .bind(Timestamp(since))
+        .fetch_all(&self.pool)
+        .await?;
+        interactions(rows)
+    }
+
+    async fn count_by_period(
+        &self,
+        workspace_id: i32,
+        metric: StatsMetric,
+        granularity: Granularity,
+        from: Date,
+        to: Date,
+        dates: &LocalDates,
+    ) -> RepoResult<Vec<(Date, i64)>> {
+        let (start, end) = (
+            dates.start_of(from),
+            dates.start_of(to.next_day().unwrap_or(to)),
+        );
+        // SQLite has no time zones, so the instants are bucketed into the user's days here
+        let query = match metric {
+            StatsMetric::Interactions => {
+                "SELECT interaction_date FROM interactions
+                 WHERE workspace_id = $1 AND interaction_date >= $2 AND interaction_date < $3"
+            }
+            StatsMetric::Contacts => {
+                "SELECT created_at FROM contacts
+                 WHERE workspace_id = $1 AND created_at >= $2 AND created_at < $3"
+            }
+        };
+        let instants: Vec<OffsetDateTime> = sqlx::query_scalar(query)
+            .bind(workspace_id)
+            .bind(Timestamp(start))
+            .bind(Timestamp(end))
+            .fetch_all(&self.pool)
+            .await?;
+        let mut counts: BTreeMap<Date, i64> = BTreeMap::new();
+        for at in instants {
+            *counts
+                .entry(bucket_start(granularity, dates.date_of(at)))
+                .or_default() += 1;
+        }
+        Ok(counts.into_iter().collect())
+    }
+
+    async fn contact_history(
+        &self,
+        contact_id: i32,
+        after: Option<Cursor>,
+        limit: Option<i64>,
+    ) -> RepoResult<Vec<ContactChange>> {
+        let rows = sqlx::query(
+            "SELECT change_id, field, old_value, new_value, changed_at
+             FROM contact_changes
+             WHERE contact_id = $1
+               AND ($2 IS NULL OR (changed_at, change_id) < ($2, $3))
+             ORDER BY changed_at DESC, change_id DESC
+             LIMIT COALESCE($4, -1)",
+        )
+        .bind(contact_id)
+        .bind(after.map(|c| Timestamp(c.at)))
+        .bind(after.map_or(0, |c| c.id))
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter()
+            .map(|row| {
+                let field: String = row.try_get("field")?;
+                let mut before = row.try_get("old_value")?;
+                let mut after = row.try_get("new_value")?;
+                if ENCRYPTED_FIELDS.contains(&field.as_str()) {
+                    before = open_note(self.notes.as_ref(), before)?;
+                    after = open_note(self.notes.as_ref(), after)?;
+                }
+                Ok(ContactChange {
+                    change_id: row.try_get("change_id")?,
+                    field,
+                    before,
+                    after,
+                    changed_at: row.try_get("changed_at")?,
+                })
+            })
+            .collect()
+    }
+
+    async fn contact_timeline(
+        &self,
+        contact_id: i32,
+        interaction_type: Option<&str>,
+        tag_id: Option<i32>,
+        after: Option<Cursor>,
+        limit: Option<i64>,
+    ) -> RepoResult<Vec<Interaction>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {INTERACTION_COLUMNS}
+             FROM interactions i
+             LEFT JOIN interaction_types t ON t.type_id = i.custom_type_id
+             WHERE (i.contact_id = $1
+                    OR i.interaction_id IN (SELECT interaction_id FROM interaction_participants
+                                            WHERE contact_id = $1))
+               AND ($2 IS NULL OR COALESCE(i.interaction_type, t.name) = $2)
+               AND ($3 IS NULL OR EXISTS (SELECT 1 FROM interaction_tags it
+                                          WHERE it.interaction_id = i.interaction_id
+                                            AND it.tag_id = $3))
+               AND ($4 IS NULL OR (i.interaction_date, i.interaction_id) < ($4, $5))
+             ORDER BY i.interaction_date DESC, i.interaction_id DESC
+             LIMIT COALESCE($6, -1)"
+        ))
+        .bind(contact_id)
+        .bind(interaction_type)
+        .bind(tag_id)
+        .bind(after.map(|c| Timestamp(c.at)))
+        .bind(after.map_or(0, |c| c.id))
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        interactions(rows)
+    }
+
+    async fn create_interaction(
+        &self,
+        workspace_id: i32,
+        interaction: &NewInteractionRequest,
+        follow_up: Option<&NewOccasionRequest>,
+    ) -> RepoResult<(i32, Option<i32>)> {
+        let mut tx = self.pool.begin().await?;
+        let interaction_id = insert_interaction(&mut tx, workspace_id, interaction).await?;
+        let occasion_id = match follow_up {
+            Some(occasion) => {
+                Some(insert_occasion(&mut tx, workspace_id, occasion, Some(interaction_id)).await?)
+            }
+            None => None,
+        };
+        tx.commit().await?;
+        Ok((interaction_id, occasion_id))
+    }
+
+    async fn update_interaction(
+        &self,
+        workspace_id: i32,
+        interaction_id: i32,
+        interaction: &NewInteractionRequest,
+    ) -> RepoResult<bool> {
+        let mut tx = self.pool.begin().await?;
+        // An unassigned interaction takes the request's contact
+        let result = sqlx::query(
+            "UPDATE interactions SET interaction_date = $1, notes = $2, followup_priority = $3,
+                 interaction_type = $6,
+                 custom_type_id = (SELECT type_id FROM interaction_types
+                                   WHERE workspace_id = $5 AND name = $7),
+                 contact_id = COALESCE(contact_id, (SELECT contact_id FROM contacts
+                                                    WHERE contact_id = $8 AND workspace_id = $5))
+             WHERE interaction_id = $4 AND workspace_id = $5",
+        )
+        .bind(Timestamp(interaction.interaction_date))
+        .bind(interaction.notes.as_deref())
+        .bind(interaction.follow_up_priority)
+        .bind(interaction_id)
+        .bind(workspace_id)
+        .bind(builtin_type(interaction))
+        .bind(interaction.interaction_type.as_deref())
+        .bind(interaction.contact_id)
+        .execute(&mut *tx)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Ok(false);
+        }
+        sqlx::query("DELETE FROM interaction_participants WHERE interaction_id = $1")
+            .bind(interaction_id)
+            .execute(&mut *tx)
+            .await?;
+        save_participants(
+            &mut tx,
+            workspace_id,
+            interaction_id,
+            &interaction.contact_ids,
+        )
+        .await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn delete_interaction(&self, workspace_id: i32, interaction_id: i32) -> RepoResult<bool> {
+        let result =
+            sqlx::query("DELETE FROM interactions WHERE interaction_id = $1 AND workspace_id = $2")
+                .bind(interaction_id)
+                .bind(workspace_id)
+                .execute(&self.pool)
+                .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn owns_interaction(&self, workspace_id: i32, interaction_id: i32) -> RepoResult<bool> {
+        self.exists(
+            "SELECT 1 FROM interactions WHERE interaction_id = $1 AND workspace_id = $2",
+            interaction_id,
+            workspace_id,
+        )
+        .await
+    }
+
+    async fn list_attachments(
+        &self,
+        workspace_id: i32,
+        interaction_id: i32,
+    ) -> RepoResult<Vec<Attachment>> {
+        let rows = sqlx::query(
+            "SELECT attachment_id, interaction_id, file_name, content_type, size_bytes,
+                    transcription, created_at
+             FROM interaction_attachments
+             WHERE interaction_id = $1 AND workspace_id = $2
+             ORDER BY attachment_id",
+        )
+        .bind(interaction_id)
+        .bind(workspace_id)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter()
+            .map(|row| {
+                Ok(Attachment {
+                    attachment_id: row.try_get("attachment_id")?,
+                    interaction_id: row.try_get("interaction_id")?,
+                    file_name: row.try_get("file_name")?,
+                    content_type: row.try_get("content_type")?,
+                    size_bytes: row.try_get("size_bytes")?,
+                    transcription: row.try_get("transcription")?,
+                    created_at: row.try_get("created_at")?,
+                })
+            })
+            .collect()
+    }
+
+    async fn create_attachment(
+        &self,
+        workspace_id: i32,
+        interaction_id: i32,
+        attachment: &NewAttachment,
+    ) -> RepoResult<i32> {
+        sqlx::query_scalar(
+            "INSERT INTO interaction_attachments
+                 (workspace_id, interaction_id, file_name, content_type, size_bytes, data,
+                  transcription)
+             VALUES ($1, $2, $3, $4, $5, $6, $7)
+             RETURNING attachment_id",
+        )
+        .bind(workspace_id)
+        .bind(interaction_id)
+        .bind(&attachment.file_name)
+        .bind(&attachment.content_type)
+        .bind(attachment.data.len() as i32)
+        .bind(&attachment.data)
+        .bind(
+            attachment
+                .transcribe
+                .then_some(TranscriptionStatus::Pending),
+        )
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    async fn attachment_file(
+        &self,
+        workspace_id: i32,
+        attachment_id: i32,
+    ) -> RepoResult<Option<AttachmentFile>> {
+        let row = sqlx::query(
+            "SELECT file_name, content_type, data FROM interaction_attachments
+             WHERE attachment_id = $1 AND workspace_id = $2",
+        )
+        .bind(attachment_id)
+        .bind(workspace_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        row.map(|row| {
+            Ok(AttachmentFile {
+                file_name: row.try_get("file_name")?,
+                content_type: row.try_get("content_type")?,
+                data: row.try_get("data")?,
+            })
+        })
+        .transpose()
+    }
+
+    async fn delete_attachment(
+        &self,
+        workspace_id: i32,
+        attachment_id: i32,
+    ) -> RepoResult<Option<i32>> {
+        sqlx::query_scalar(
+            "DELETE FROM interaction_attachments WHERE attachment_id = $1 AND workspace_id = $2
+             RETURNING interaction_id",
+        )
+        .bind(attachment_id)
+        .bind(workspace_id)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    async fn owns_attachment(&self, workspace_id: i32, attachment_id: i32) -> RepoResult<bool> {
+        self.exists(
+            "SELECT 1 FROM interaction_attachments WHERE attachment_id = $1 AND workspace_id = $2",
+            attachment_id,
+            workspace_id,
+        )
+        .await
+    }
+
+    async fn pending_transcriptions(&self, limit: i64) -> RepoResult<Vec<PendingTranscription>> {
+        let rows = sqlx::query(
+            "SELECT attachment_id, workspace_id, file_name, content_type, data,
+                    transcription_attempts
+             FROM interaction_attachments
+             WHERE transcription = 'pending'
+             ORDER BY attachment_id
+             LIMIT $1",
+        )
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter()
+            .map(|row| {
+                Ok(PendingTranscription {
+                    attachment_id: row.try_get("attachment_id")?,
+                    workspace_id: row.try_get("workspace_id")?,
+                    file_name: row.try_get("file_name")?,
+                    content_type: row.try_get("content_type")?,
+                    data: row.try_get("data")?,
+                    attempts: row.try_get("transcription_attempts")?,
+                })
+            })
+            .collect()
+    }
+
+    async fn save_transcript(
+        &self,
+        workspace_id: i32,
+        attachment_id: i32,
+        transcript: &str,
+    ) -> RepoResult<bool> {
+        let mut tx = self.pool.begin().await?;
+        let interaction_id: Option<i32> = sqlx::query_scalar(
+            "UPDATE interaction_attachments SET transcription = 'done'
+             WHERE attachment_id = $1 AND workspace_id = $2 AND transcription = 'pending'
+             RETURNING interaction_id",
+        )
+        .bind(attachment_id)
+        .bind(workspace_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(interaction_id) = interaction_id else {
+            return Ok(false);
+        };
+        sqlx::query(
+            "UPDATE interactions
+             SET notes = CASE WHEN COALESCE(notes, '') = '' THEN $3
+                              ELSE notes || char(10, 10) || $3 END
+             WHERE interaction_id = $1 AND workspace_id = $2",
+        )
+        .bind(interaction_id)
+        .bind(workspace_id)
+        .bind(transcript)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn transcription_failed(
+        &self,
+        workspace_id: i32,
+        attachment_id: i32,
+        give_up: bool,
+    ) -> RepoResult<()> {
+        sqlx::query(
+            "UPDATE interaction_attachments
+             SET transcription_attempts = transcription_attempts + 1,
+                 transcription = CASE WHEN $3 THEN 'failed' ELSE transcription END
+             WHERE attachment_id = $1 AND workspace_id = $2",
+        )
+        .bind(attachment_id)
+        .bind(workspace_id)
+        .bind(give_up)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn pending_avatar_lookups(
+        &self,
+        now: OffsetDateTime,
+        limit: i64,
+    ) -> RepoResult<Vec<PendingAvatarLookup>> {
+        let rows = sqlx::query(
+            "SELECT c.contact_id, c.workspace_id, c.email
+             FROM contacts c
+             LEFT JOIN avatar_lookups l ON l.contact_id = c.contact_id
+             WHERE c.email <> '' AND c.avatar_url IS NULL AND NOT c.archived
+               AND (l.next_lookup_at IS NULL OR l.next_lookup_at <= $1)
+             ORDER BY l.next_lookup_at NULLS FIRST, c.contact_id
+             LIMIT $2",
+        )
+        .bind(Timestamp(now))
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter()
+            .map(|row| {
+                Ok(PendingAvatarLookup {
+                    contact_id: row.try_get("contact_id")?,
+                    workspace_id: row.try_get("workspace_id")?,
+                    email: row.try_get("email")?,
+                })
+            })
+            .collect()
+    }
+
+    async fn record_avatar_lookup(
+        &self,
+        workspace_id: i32,
+        contact_id: i32,
+        email: &str,
+        avatar_url: Option<&str>,
+        next_lookup_at: OffsetDateTime,
+    ) -> RepoResult<bool> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            "INSERT INTO avatar_lookups (contact_id, workspace_id, next_lookup_at)
+             SELECT contact_id, workspace_id, $3 FROM contacts
+             WHERE contact_id = $1 AND workspace_id = $2
+             ON CONFLICT (contact_id) DO UPDATE SET next_lookup_at = excluded.next_lookup_at",
+        )
+        .bind(contact_id)
+        .bind(workspace_id)
+        .bind(Timestamp(next_lookup_at))
+        .execute(&mut *tx)
+        .await?;
+        let stored = match avatar_url {
+            Some(avatar_url) => {
+                sqlx::query(
+                    "UPDATE contacts SET avatar_url = $1
+                     WHERE contact_id = $2 AND workspace_id = $3 AND email = $4
+                       AND avatar_url IS NULL",
+                )
+                .bind(avatar_url)
+                .bind(contact_id)
+                .bind(workspace_id)
+                .bind(email)
+                .execute(&mut *tx)
+                .await?
+                .rows_affected()
+                    > 0
+            }
+            None => false,
+        };
+        tx.commit().await?;
+        Ok(stored)
+    }
+
+    async fn list_captures(&self, workspace_id: i32) -> RepoResult<Vec<Capture>> {
+        let rows = sqlx::query(
+            "SELECT capture_id, text, captured_at FROM inbox_captures
+             WHERE workspace_id = $1 ORDER BY captured_at, capture_id",
+        )
+        .bind(workspace_id)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(capture).collect()
+    }
+
+    async fn get_capture(&self, workspace_id: i32, capture_id: i32) -> RepoResult<Option<Capture>> {
+        let row = sqlx::query(
+            "SELECT capture_id, text, captured_at FROM inbox_captures
+             WHERE capture_id = $1 AND workspace_id = $2",
+        )
+        .bind(capture_id)
+        .bind(workspace_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        row.as_ref().map(capture).transpose()
+    }
+
+    async fn create_capture(&self, workspace_id: i32, text: &str) -> RepoResult<i32> {
+        sqlx::query_scalar(
+            "INSERT INTO inbox_captures (workspace_id, text) VALUES ($1, $2) RETURNING capture_id",
+        )
+        .bind(workspace_id)
+        .bind(text)
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    async fn resolve_capture(
+        &self,
+        workspace_id: i32,
+        capture_id: i32,
+        interaction: &NewInteractionRequest,
+    ) -> RepoResult<Option<i32>> {
+        let mut tx = self.pool.begin().await?;
+        let deleted =
+            sqlx::query("DELETE FROM inbox_captures WHERE capture_id = $1 AND workspace_id = $2")
+                .bind(capture_id)
+                .bind(workspace_id)
+                .execute(&mut *tx)
+                .await?;
+        if deleted.rows_affected() == 0 {
+            return Ok(None);
+        }
+        let interaction_id = insert_interaction(&mut tx, workspace_id, interaction).await?;
+        tx.commit().await?;
+        Ok(Some(interaction_id))
+    }
+
+    async fn delete_capture(&self, workspace_id: i32, capture_id: i32) -> RepoResult<bool> {
+        let result =
+            sqlx::query("DELETE FROM inbox_captures WHERE capture_id = $1 AND workspace_id = $2")
+                .bind(capture_id)
+                .bind(workspace_id)
+                .execute(&self.pool)
+                .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn owns_capture(&self, workspace_id: i32, capture_id: i32) -> RepoResult<bool> {
+        self.exists(
+            "SELECT 1 FROM inbox_captures WHERE capture_id = $1 AND workspace_id = $2",
+            capture_id,
+            workspace_id,
+        )
+        .await
+    }
+
+    async fn list_interaction_types(
+        &self,
+        workspace_id: i32,
+    ) -> RepoResult<Vec<CustomInteractionType>> {
+        let rows = sqlx::query(
+            "SELECT type_id, name FROM interaction_types WHERE workspace_id = $1 ORDER BY name",
+        )
+        .bind(workspace_id)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter()
+            .map(|row| {
+                Ok(CustomInteractionType {
+                    type_id: row.try_get("type_id")?,
+                    name: row.try_get("name")?,
+                })
+            })
+            .collect()
+    }
+
+    async fn create_interaction_type(&self, workspace_id: i32, name: &str) -> RepoResult<i32> {
+        sqlx::query_scalar(
+            "INSERT INTO interaction_types (workspace_id, name) VALUES ($1, $2) RETURNING type_id",
+        )
+        .bind(workspace_id)
+        .bind(name)
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    async fn delete_interaction_type(&self, workspace_id: i32, type_id: i32) -> RepoResult<bool> {
+        let result =
+            sqlx::query("DELETE FROM interaction_types WHERE type_id = $1 AND workspace_id = $2")
+                .bind(type_id)
+                .bind(workspace_id)
+                .execute(&self.pool)
+                .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn owns_interaction_type(&self, workspace_id: i32, type_id: i32) -> RepoResult<bool> {
+        self.exists(
+            "SELECT 1 FROM interaction_types WHERE type_id = $1 AND workspace_id = $2",
+            type_id,
+            workspace_id,
+        )
+        .await
+    }
+
+    async fn occasions_for_contacts(&self, contact_ids: &[i32]) -> RepoResult<Vec<Occasion>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {OCCASION_COLUMNS} FROM occasions
+             WHERE contact_id IN (SELECT value FROM json_each($1))
+             ORDER BY occasion_id"
+        ))
+        .bind(Json(contact_ids))
+        .fetch_all(&self.pool)
+        .await?;
+        occasions(rows)
+    }
+
+    async fn get_occasion(
+        &self,
+        workspace_id: i32,
+        occasion_id: i32,
+    ) -> RepoResult<Option<Occasion>> {
+        let row = sqlx::query(&format!(
+            "SELECT {OCCASION_COLUMNS} FROM occasions WHERE occasion_id = $1 AND workspace_id = $2"
+        ))
+        .bind(occasion_id)
+        .bind(workspace_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        row.as_ref().map(occasion).transpose()
+    }
+
+    async fn create_occasion(
+        &self,
+        workspace_id: i32,
+        occasion: &NewOccasionRequest,
+    ) -> RepoResult<i32> {
+        let mut conn = self.pool.acquire().await?;
+        insert_occasion(&mut conn, workspace_id, occasion, None).await
+    }
+
+    async fn update_occasion(
+        &self,
+        workspace_id: i32,
+        occasion_id: i32,
+        occasion: &NewOccasionRequest,
+    ) -> RepoResult<bool> {
+        // An unassigned occasion takes the request's contact
+        let result = sqlx::query(
+            "UPDATE occasions SET name = $1, date = $2, recurring = $3, recurring_interval = $4,
+                                  details = $5, occasion_type = $6, birth_year = $7,
+                                  remind_days_before = $11,
+                                  contact_id = COALESCE(contact_id, (SELECT contact_id FROM contacts
+                                                                     WHERE contact_id = $10
+                                                                       AND workspace_id = $9))
+             WHERE occasion_id = $8 AND workspace_id = $9",
+        )
+        .bind(&occasion.name)
+        .bind(occasion.date)
+        .bind(occasion.recurring)
+        .bind(occasion.recurring_interval)
+        .bind(occasion.details.as_deref())
+        .bind(occasion.occasion_type)
+        .bind(occasion.birth_year)
+        .bind(occasion_id)
+        .bind(workspace_id)
+        .bind(occasion.contact_id)
+        .bind(occasion.remind_days_before.as_ref().map(Json))
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn delete_occasion(&self, workspace_id: i32, occasion_id: i32) -> RepoResult<bool> {
+        let result =
+            sqlx::query("DELETE FROM occasions WHERE occasion_id = $1 AND workspace_id = $2")
+                .bind(occasion_id)
+                .bind(workspace_id)
+                .execute(&self.pool)
+                .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn owns_occasion(&self, workspace_id: i32, occasion_id: i32) -> RepoResult<bool> {
+        self.exists(
+            "SELECT 1 FROM occasions WHERE occasion_id = $1 AND workspace_id = $2",
+            occasion_id,
+            workspace_id,
+        )
+        .await
+    }
+
+    async fn social_profiles_for_contacts(
+        &self,
+        contact_ids: &[i32],
+    ) -> RepoResult<Vec<SocialProfile>> {
+        let rows = sqlx::query(
+            "SELECT social_profile_id, contact_id, platform, url
+             FROM social_profiles
+             WHERE contact_id IN (SELECT value FROM json_each($1))
+             ORDER BY social_profile_id",
+        )
+        .bind(Json(contact_ids))
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(social_profile).collect()
+    }
+
+    async fn create_social_profile(
+        &self,
+        workspace_id: i32,
+        profile: &NewSocialProfileRequest,
+    ) -> RepoResult<i32> {
+        sqlx::query_scalar(
+            "INSERT INTO social_profiles (workspace_id, contact_id, platform, url)
+             VALUES ($1, $2, $3, $4)
+             RETURNING social_profile_id",
+        )
+        .bind(workspace_id)
+        .bind(profile.contact_id)
+        .bind(profile.platform)
+        .bind(&profile.url)
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    async fn update_social_profile(
+        &self,
+        workspace_id: i32,
+        social_profile_id: i32,
+        profile: &NewSocialProfileRequest,
+    ) -> RepoResult<bool> {
+        let result = sqlx::query(
+            "UPDATE social_profiles SET platform = $1, url = $2
+             WHERE social_profile_id = $3 AND workspace_id = $4",
+        )
+        .bind(profile.platform)
+        .bind(&profile.url)
+        .bind(social_profile_id)
+        .bind(workspace_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn delete_social_profile(
+        &self,
+        workspace_id: i32,
+        social_profile_id: i32,
+    ) -> RepoResult<bool> {
+        let result = sqlx::query(
+            "DELETE FROM social_profiles WHERE social_profile_id = $1 AND workspace_id = $2",
+        )
+        .bind(social_profile_id)
+        .bind(workspace_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn get_social_profile(
+        &self,
+        workspace_id: i32,
+        social_profile_id: i32,
+    ) -> RepoResult<Option<SocialProfile>> {
+        let row = sqlx::query(
+            "SELECT social_profile_id, contact_id, platform, url
+             FROM social_profiles
+             WHERE social_profile_id = $1 AND workspace_id = $2",
+        )
+        .bind(social_profile_id)
+        .bind(workspace_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        row.as_ref().map(social_profile).transpose()
+    }
+
+    async fn owns_social_profile(
+        &self,
+        workspace_id: i32,
+        social_profile_id: i32,
+    ) -> RepoResult<bool> {
+        self.exists(
+            "SELECT 1 FROM social_profiles WHERE social_profile_id = $1 AND workspace_id = $2",
+            social_profile_id,
+            workspace_id,
+        )
+        .await
+    }
+
+    async fn gift_ideas_for_contacts(&self, contact_ids: &[i32]) -> RepoResult<Vec<GiftIdea>> {
+        let rows = sqlx::query(
+            "SELECT gift_idea_id, contact_id, occasion_id, idea, status, price_cents
+             FROM gift_ideas
+             WHERE contact_id IN (SELECT value FROM json_each($1))
+             ORDER BY gift_idea_id",
+        )
+        .bind(Json(contact_ids))
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(gift_idea).collect()
+    }
+
+    async fn create_gift_idea(
+        &self,
+        workspace_id: i32,
+        gift_idea: &NewGiftIdeaRequest,
+    ) -> RepoResult<i32> {
+        sqlx::query_scalar(
+            "INSERT INTO gift_ideas (workspace_id, contact_id, occasion_id, idea, status, price_cents)
+             VALUES ($1, $2, $3, $4, $5, $6)
+             RETURNING gift_idea_id",
+        )
+        .bind(workspace_id)
+        .bind(gift_idea.contact_id)
+        .bind(gift_idea.occasion_id)
+        .bind(&gift_idea.idea)
+        .bind(gift_idea.status)
+        .bind(gift_idea.price_cents)
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    async fn update_gift_idea(
+        &self,
+        workspace_id: i32,
+        gift_idea_id: i32,
+        gift_idea: &NewGiftIdeaRequest,
+    ) -> RepoResult<bool> {
+        let result = sqlx::query(
+            "UPDATE gift_ideas SET occasion_id = $1, idea = $2, status = $3, price_cents = $4
+             WHERE gift_idea_id = $5 AND workspace_id = $6",
+        )
+        .bind(gift_idea.occasion_id)
+        .bind(&gift_idea.idea)
+        .bind(gift_idea.status)
+        .bind(gift_idea.price_cents)
+        .bind(gift_idea_id)
+        .bind(workspace_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn delete_gift_idea(&self, workspace_id: i32, gift_idea_id: i32) -> RepoResult<bool> {
+        let result =
+            sqlx::query("DELETE FROM gift_ideas WHERE gift_idea_id = $1 AND workspace_id = $2")
+                .bind(gift_idea_id)
+                .bind(workspace_id)
+                .execute(&self.pool)
+                .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn get_gift_idea(
+        &self,
+        workspace_id: i32,
+        gift_idea_id: i32,
+    ) -> RepoResult<Option<GiftIdea>> {
+        let row = sqlx::query(
+            "SELECT gift_idea_id, contact_id, occasion_id, idea, status, price_cents
+             FROM gift_ideas
+             WHERE gift_idea_id = $1 AND workspace_id = $2",
+        )
+        .bind(gift_idea_id)
+        .bind(workspace_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        row.as_ref().map(gift_idea).transpose()
+    }
+
+    async fn owns_gift_idea(&self, workspace_id: i32, gift_idea_id: i32) -> RepoResult<bool> {
+        self.exists(
+            "SELECT 1 FROM gift_ideas WHERE gift_idea_id = $1 AND workspace_id = $2",
+            gift_idea_id,
+            workspace_id,
+        )
+        .await
+    }
+
+    async fn list_groups(&self, workspace_id: i32) -> RepoResult<Vec<Group>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {GROUP_COLUMNS} FROM contact_groups g
+             WHERE g.workspace_id = $1
+             ORDER BY lower(g.name), g.group_id"
+        ))
+        .bind(workspace_id)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(group).collect()
+    }
+
+    async fn get_group(&self, workspace_id: i32, group_id: i32) -> RepoResult<Option<Group>> {
+        let row = sqlx::query(&format!(
+            "SELECT {GROUP_COLUMNS} FROM contact_groups g
+             WHERE g.group_id = $1 AND g.workspace_id = $2"
+        ))
+        .bind(group_id)
+        .bind(workspace_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        row.as_ref().map(group).transpose()
+    }
+
+    async fn create_group(&self, workspace_id: i32, group: &NewGroupRequest) -> RepoResult<i32> {
+        sqlx::query_scalar(
+            "INSERT INTO contact_groups (workspace_id, name, description)
+             VALUES ($1, $2, $3)
+             RETURNING group_id",
+        )
+        .bind(workspace_id)
+        .bind(&group.name)
+        .bind(group.description.as_deref())
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    async fn update_group(
+        &self,
+        workspace_id: i32,
+        group_id: i32,
+        group: &NewGroupRequest,
+    ) -> RepoResult<bool> {
+        let result = sqlx::query(
+            "UPDATE contact_groups SET name = $1, description = $2
+             WHERE group_id = $3 AND workspace_id = $4",
+        )
+        .bind(&group.name)
+        .bind(group.description.as_deref())
+        .bind(group_id)
+        .bind(workspace_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn delete_group(&self, workspace_id: i32, group_id: i32) -> RepoResult<bool> {
+        let result =
+            sqlx::query("DELETE FROM contact_groups WHERE group_id = $1 AND workspace_id = $2")
+                .bind(group_id)
+                .bind(workspace_id)
+                .execute(&self.pool)
+                .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn owns_group(&self, workspace_id: i32, group_id: i32) -> RepoResult<bool> {
+        self.exists(
+            "SELECT 1 FROM contact_groups WHERE group_id = $1 AND workspace_id = $2",
+            group_id,
+            workspace_id,
+        )
+        .await
+    }
+
+    async fn set_group_members(
+        &self,
+        workspace_id: i32,
+        group_id: i32,
+        contact_ids: &[i32],
+    ) -> RepoResult<bool> {
+        let mut tx = self.pool.begin().await?;
+        let group: Option<i32> = sqlx::query_scalar(
+            "SELECT group_id FROM contact_groups WHERE group_id = $1 AND workspace_id = $2",
+        )
+        .bind(group_id)
+        .bind(workspace_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let owned: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM contacts
+             WHERE contact_id IN (SELECT value FROM json_each($1)) AND workspace_id = $2",
+        )
+        .bind(Json(contact_ids))
+        .bind(workspace_id)
+        .fetch_one(&mut *tx)
+        .await?;
+        if group.is_none() || owned != contact_ids.len() as i64 {
+            return Ok(false);
+        }
+        sqlx::query("DELETE FROM contact_group_members WHERE group_id = $1")
+            .bind(group_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(
+            "INSERT INTO contact_group_members (group_id, contact_id, position)
+             SELECT $1, value, key FROM json_each($2)",
+        )
+        .bind(group_id)
+        .bind(Json(contact_ids))
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("UPDATE contact_groups SET updated_at = $2 WHERE group_id = $1")
+            .bind(group_id)
+            .bind(Timestamp(OffsetDateTime::now_utc()))
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn add_group_member(&self, group_id: i32, contact_id: i32) -> RepoResult<bool> {
+        let result = sqlx::query(
+            "INSERT INTO contact_group_members (group_id, contact_id, position)
+             SELECT $1, $2, COALESCE(MAX(position) + 1, 0)
+             FROM contact_group_members WHERE group_id = $1
+             ON CONFLICT DO NOTHING",
+        )
+        .bind(group_id)
+        .bind(contact_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn remove_group_member(&self, group_id: i32, contact_id: i32) -> RepoResult<bool> {
+        let result = sqlx::query(
+            "DELETE FROM contact_group_members WHERE group_id = $1 AND contact_id = $2",
+        )
+        .bind(group_id)
+        .bind(contact_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn list_shares(&self, user_id: i32) -> RepoResult<Vec<Share>> {
+        sqlx::query_as(
+            "SELECT s.share_id, s.owner_id, o.email AS owner_email, s.grantee_id,
+                    g.email AS grantee_email, s.tag_id, s.contact_id, s.permission
+             FROM shares s
+             JOIN users o ON o.user_id = s.owner_id
+             JOIN users g ON g.user_id = s.grantee_id
+             WHERE $1 IN (s.owner_id, s.grantee_id)
+             ORDER BY s.share_id",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn create_shares(
+        &self,
+        owner_id: i32,
+        grantee_id: i32,
+        tag_id: Option<i32>,
+        contact_ids: &[i32],
+        permission: SharePermission,
+    ) -> RepoResult<Vec<i32>> {
+        let pairs: Vec<(Option<i32>, Option<i32>)> = match tag_id {
+            Some(tag_id) => vec![(Some(tag_id), None)],
+            None => {
+                let mut contact_ids = contact_ids.to_vec();
+                contact_ids.sort_unstable();
+                contact_ids.dedup();
+                contact_ids.into_iter().map(|id| (None, Some(id))).collect()
+            }
+        };
+        let mut tx = self.pool.begin().await?;
+        let mut share_ids = Vec::with_capacity(pairs.len());
+        for (tag_id, contact_id) in pairs {
+            let share_id = sqlx::query_scalar(
+                "INSERT INTO shares (owner_id, grantee_id, tag_id, contact_id, permission)
+                 VALUES ($1, $2, $3, $4, $5)
+                 ON CONFLICT (owner_id, grantee_id, IFNULL(tag_id, 0), IFNULL(contact_id, 0))
+                 DO UPDATE SET permission = excluded.permission
+                 RETURNING share_id",
+            )
+            .bind(owner_id)
+            .bind(grantee_id)
+            .bind(tag_id)
+            .bind(contact_id)
+            .bind(permission)
+            .fetch_one(&mut *tx)
+            .await?;
+            share_ids.push(share_id);
+        }
+        tx.commit().await?;
+        Ok(share_ids)
+    }
+
+    async fn delete_share(&self, user_id: i32, share_id: i32) -> RepoResult<bool> {
+        let result =
+            sqlx::query("DELETE FROM shares WHERE share_id = $1 AND $2 IN (owner_id, grantee_id)")
+                .bind(share_id)
+                .bind(user_id)
+                .execute(&self.pool)
+                .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn owns_share(&self, user_id: i32, share_id: i32) -> RepoResult<bool> {
+        self.exists(
+            "SELECT 1 FROM shares WHERE share_id = $1 AND $2 IN (owner_id, grantee_id)",
+            share_id,
+            user_id,
+        )
+        .await
+    }
+
+    async fn contact_access(
+        &self,
+        user_id: i32,
+        workspace_id: i32,
+        contact_id: i32,
+    ) -> RepoResult<Option<ContactAccess>> {
+        // 'write' sorts after 'read', so max() is the strongest permission as in Postgres
+        let row = sqlx::query(
+            "SELECT w.user_id AS owner_id, c.workspace_id,
+                    (SELECT max(s.permission)
+                     FROM shares s
+                     WHERE s.grantee_id = $1 AND s.owner_id = w.user_id
+                       AND (s.contact_id = c.contact_id
+                            OR s.tag_id IN (SELECT tag_id FROM contact_tags
+                                            WHERE contact_id = c.contact_id))
+                    ) AS shared
+             FROM contacts c
+             JOIN workspaces w ON w.workspace_id = c.workspace_id
+             WHERE c.contact_id = $2",
+        )
+        .bind(user_id)
+        .bind(contact_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        let owner_id: i32 = row.try_get("owner_id")?;
+        let contact_workspace_id: i32 = row.try_get("workspace_id")?;
+        let shared: Option<SharePermission> = row.try_get("shared")?;
+        Ok(if contact_workspace_id == workspace_id {
+            Some(ContactAccess {
+                owner_id,
+                workspace_id: contact_workspace_id,
+                shared: None,
+            })
+        } else if owner_id != user_id {
+            shared.map(|permission| ContactAccess {
+                owner_id,
+                workspace_id: contact_workspace_id,
+                shared: Some(permission),
+            })
+        } else {
+            None
+        })
+    }
+
+    async fn shared_contacts(&self, user_id: i32) -> RepoResult<Vec<ContactShare>> {
+        sqlx::query_as(
+            "SELECT c.contact_id, w.user_id AS owner_id, c.workspace_id, u.name AS owner_name,
+                    u.email AS owner_email, max(s.permission) AS permission
+             FROM shares s
+             JOIN workspaces w ON w.user_id = s.owner_id
+             JOIN contacts c ON c.workspace_id = w.workspace_id
+                 AND (c.contact_id = s.contact_id
+                      OR EXISTS (SELECT 1 FROM contact_tags ct
+                                 WHERE ct.contact_id = c.contact_id AND ct.tag_id = s.tag_id))
+             JOIN users u ON u.user_id = w.user_id
+             WHERE s.grantee_id = $1
+             GROUP BY c.contact_id, w.user_id, u.user_id
+             ORDER BY w.user_id, c.workspace_id, c.contact_id",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn list_filters(&self, workspace_id: i32) -> RepoResult<Vec<SavedFilter>> {
+        let rows = sqlx::query(
+            "SELECT filter_id, name, query FROM saved_filters WHERE workspace_id = $1 ORDER BY name",
+        )
+        .bind(workspace_id)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(saved_filter).collect()
+    }
+
+    async fn get_filter(
+        &self,
+        workspace_id: i32,
+        filter_id: i32,
+    ) -> RepoResult<Option<SavedFilter>> {
+        let row = sqlx::query(
+            "SELECT filter_id, name, query FROM saved_filters
+             WHERE filter_id = $1 AND workspace_id = $2",
+        )
+        .bind(filter_id)
+        .bind(workspace_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        row.as_ref().map(saved_filter).transpose()
+    }
+
+    async fn create_filter(
+        &self,
+        workspace_id: i32,
+        filter: &NewSavedFilterRequest,
+    ) -> RepoResult<i32> {
+        sqlx::query_scalar(
+            "INSERT INTO saved_filters (workspace_id, name, query) VALUES ($1, $2, $3)
+             RETURNING filter_id",
+        )
+        .bind(workspace_id)
+        .bind(&filter.name)
+        .bind(Json(&filter.query))
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    async fn update_filter(
+        &self,
+        workspace_id: i32,
+        filter_id: i32,
+        filter: &NewSavedFilterRequest,
+    ) -> RepoResult<bool> {
+        let result = sqlx::query(
+            "UPDATE saved_filters SET name = $1, query = $2
+             WHERE filter_id = $3 AND workspace_id = $4",
+        )
+        .bind(&filter.name)
+        .bind(Json(&filter.query))
+        .bind(filter_id)
+        .bind(workspace_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn delete_filter(&self, workspace_id: i32, filter_id: i32) -> RepoResult<bool> {
+        let result =
+            sqlx::query("DELETE FROM saved_filters WHERE filter_id = $1 AND workspace_id = $2")
+                .bind(filter_id)
+                .bind(workspace_id)
+                .execute(&self.pool)
+                .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn owns_filter(&self, workspace_id: i32, filter_id: i32) -> RepoResult<bool> {
+        self.exists(
+            "SELECT 1 FROM saved_filters WHERE filter_id = $1 AND workspace_id = $2",
+            filter_id,
+            workspace_id,
+        )
+        .await
+    }
+
+    async fn list_goals(&self, workspace_id: i32) -> RepoResult<Vec<Goal>> {
+        let rows = sqlx::query(
+            "SELECT goal_id, name, contact_id, interaction_type, target_count, period
+             FROM goals WHERE workspace_id = $1 ORDER BY name, goal_id",
+        )
+        .bind(workspace_id)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(goal).collect()
+    }
+
+    async fn create_goal(&self, workspace_id: i32, goal: &NewGoalRequest) -> RepoResult<i32> {
+        sqlx::query_scalar(
+            "INSERT INTO goals (workspace_id, name, contact_id, interaction_type, target_count, period)
+             VALUES ($1, $2, $3, $4, $5, $6)
+             RETURNING goal_id",
+        )
+        .bind(workspace_id)
+        .bind(&goal.name)
+        .bind(goal.contact_id)
+        .bind(goal.interaction_type.as_deref())
+        .bind(goal.target_count)
+        .bind(goal.period)
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    async fn update_goal(
+        &self,
+        workspace_id: i32,
+        goal_id: i32,
+        goal: &NewGoalRequest,
+    ) -> RepoResult<bool> {
+        let result = sqlx::query(
+            "UPDATE goals
+             SET name = $1, contact_id = $2, interaction_type = $3, target_count = $4, period = $5
+             WHERE goal_id = $6 AND workspace_id = $7",
+        )
+        .bind(&goal.name)
+        .bind(goal.contact_id)
+        .bind(goal.interaction_type.as_deref())
+        .bind(goal.target_count)
+        .bind(goal.period)
+        .bind(goal_id)
+        .bind(workspace_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn delete_goal(&self, workspace_id: i32, goal_id: i32) -> RepoResult<bool> {
+        let result = sqlx::query("DELETE FROM goals WHERE goal_id = $1 AND workspace_id = $2")
+            .bind(goal_id)
+            .bind(workspace_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn get_goal(&self, workspace_id: i32, goal_id: i32) -> RepoResult<Option<Goal>> {
+        let row = sqlx::query(
+            "SELECT goal_id, name, contact_id, interaction_type, target_count, period
+             FROM goals WHERE goal_id = $1 AND workspace_id = $2",
+        )
+        .bind(goal_id)
+        .bind(workspace_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        row.as_ref().map(goal).transpose()
+    }
+
+    async fn owns_goal(&self, workspace_id: i32, goal_id: i32) -> RepoResult<bool> {
+        self.exists(
+            "SELECT 1 FROM goals WHERE goal_id = $1 AND workspace_id = $2",
+            goal_id,
+            workspace_id,
+        )
+        .await
+    }
+
+    async fn reconnect_picks(
+        &self,
+        workspace_id: i32,
+        since: Date,
+    ) -> RepoResult<Vec<(Date, i32)>> {
+        let rows = sqlx::query(
+            "SELECT week, contact_id FROM reconnect_picks WHERE workspace_id = $1 AND week >= $2",
+        )
+        .bind(workspace_id)
+        .bind(since)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter()
+            .map(|row| Ok((row.try_get("week")?, row.try_get("contact_id")?)))
+            .collect()
+    }
+
+    async fn save_reconnect_picks(
+        &self,
+        workspace_id: i32,
+        week: Date,
+        contact_ids: &[i32],
+    ) -> RepoResult<()> {
+        sqlx::query(
+            "INSERT INTO reconnect_picks (workspace_id, week, contact_id)
+             SELECT $1, $2, value FROM json_each($3)
+             WHERE NOT EXISTS (SELECT 1 FROM reconnect_picks WHERE workspace_id = $1 AND week = $2)
+             ON CONFLICT DO NOTHING",
+        )
+        .bind(workspace_id)
+        .bind(week)
+        .bind(Json(contact_ids))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn snooze_suggestion(
+        &self,
+        user_id: i32,
+        contact_id: i32,
+        until: Option<Date>,
+    ) -> RepoResult<()> {
+        sqlx::query(
+            "INSERT INTO suggestion_snoozes (user_id, contact_id, snoozed_until, created_at)
+             VALUES ($1, $2, $3, $4)
+             ON CONFLICT (user_id, contact_id)
+             DO UPDATE SET snoozed_until = excluded.snoozed_until, created_at = excluded.created_at",
+        )
+        .bind(user_id)
+        .bind(contact_id)
+        .bind(until)
+        .bind(Timestamp(OffsetDateTime::now_utc()))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn unsnooze_suggestion(&self, user_id: i32, contact_id: i32) -> RepoResult<bool> {
+        let result =
+            sqlx::query("DELETE FROM suggestion_snoozes WHERE user_id = $1 AND contact_id = $2")
+                .bind(user_id)
+                .bind(contact_id)
+                .execute(&self.pool)
+                .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn snoozed_contacts(&self, user_id: i32, today: Date) -> RepoResult<Vec<i32>> {
+        sqlx::query_scalar(
+            "SELECT contact_id FROM suggestion_snoozes
+             WHERE user_id = $1 AND (snoozed_until IS NULL OR snoozed_until > $2)
+             ORDER BY contact_id",
+        )
+        .bind(user_id)
+        .bind(today)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn get_preferences(&self, user_id: i32) -> RepoResult<Preferences> {
+        let row = sqlx::query(
+            "SELECT country, quiet_weekdays, timezone, remind_days_before,
+                    (SELECT json_group_array(t.tag_id ORDER BY t.tag_id) FROM tags t
+                     JOIN workspaces w ON w.workspace_id = t.workspace_id
+                     WHERE w.user_id = p.user_id
+                       AND t.tag_id IN (SELECT value FROM json_each(p.greeting_tag_ids))
+                    ) AS greeting_tag_ids
+             FROM user_preferences p WHERE user_id = $1",
+        )
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        let Some(row) = row else {
+            return Ok(Preferences::default());
+        };
+        Ok(Preferences {
+            country: row.try_get("country")?,
+            quiet_weekdays: row.try_get::<Json<Vec<i32>>, _>("quiet_weekdays")?.0,
+            greeting_tag_ids: row.try_get::<Json<Vec<i32>>, _>("greeting_tag_ids")?.0,
+            timezone: row.try_get("timezone")?,
+            remind_days_before: row.try_get::<Json<Vec<i32>>, _>("remind_days_before")?.0,
+        })
+    }
+
+    async fn save_preferences(&self, user_id: i32, preferences: &Preferences) -> RepoResult<()> {
+        sqlx::query(
+            "INSERT INTO user_preferences (user_id, country, quiet_weekdays, greeting_tag_ids,
+                                           timezone, remind_days_before)
+             VALUES ($1, $2, $3, $4, $5, $6)
+             ON CONFLICT (user_id) DO UPDATE SET country = excluded.country,
+                 quiet_weekdays = excluded.quiet_weekdays,
+                 greeting_tag_ids = excluded.greeting_tag_ids,
+                 timezone = excluded.timezone,
+                 remind_days_before = excluded.remind_days_before",
+        )
+        .bind(user_id)
+        .bind(preferences.country.as_deref())
+        .bind(Json(&preferences.quiet_weekdays))
+        .bind(Json(&preferences.greeting_tag_ids))
+        .bind(preferences.timezone.as_deref())
+        .bind(Json(&preferences.remind_days_before))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn export_schedule(&self, workspace_id: i32) -> RepoResult<Option<ExportSchedule>> {
+        let row = sqlx::query(
+            "SELECT w.user_id, e.workspace_id, e.url, e.interval_hours, e.secret, e.last_pushed_at,
+                    e.last_status
+             FROM export_schedules e
+             JOIN workspaces w ON w.workspace_id = e.workspace_id
+             WHERE e.workspace_id = $1",
+        )
+        .bind(workspace_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        row.as_ref().map(export_schedule).transpose()
+    }
+
+    async fn save_export_schedule(&self, schedule: &ExportSchedule) -> RepoResult<()> {
+        sqlx::query(
+            "INSERT INTO export_schedules (workspace_id, url, interval_hours, secret)
+             VALUES ($1, $2, $3, $4)
+             ON CONFLICT (workspace_id) DO UPDATE SET url = excluded.url,
+                 interval_hours = excluded.interval_hours, secret = excluded.secret",
+        )
+        .bind(schedule.workspace_id)
+        .bind(&schedule.url)
+        .bind(schedule.interval_hours)
+        .bind(&schedule.secret)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_export_schedule(&self, workspace_id: i32) -> RepoResult<bool> {
+        let result = sqlx::query("DELETE FROM export_schedules WHERE workspace_id = $1")
+            .bind(workspace_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn due_export_schedules(&self, now: OffsetDateTime) -> RepoResult<Vec<ExportSchedule>> {
+        let rows = sqlx::query(
+            "SELECT w.user_id, e.workspace_id, e.url, e.interval_hours, e.secret, e.last_pushed_at,
+                    e.last_status
+             FROM export_schedules e
+             JOIN workspaces w ON w.workspace_id = e.workspace_id
+             JOIN users u ON u.user_id = w.user_id AND u.deactivated_at IS NULL
+             ORDER BY e.workspace_id",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        let schedules = rows
+            .iter()
+            .map(export_schedule)
+            .collect::<RepoResult<Vec<_>>>()?;
+        Ok(schedules
+            .into_iter()
+            .filter(|s| {
+                s.last_pushed_at
+                    .is_none_or(|at| at + time::Duration::hours(s.interval_hours.into()) <= now)
+            })
+            .collect())
+    }
+
+    async fn record_export_push(
+        &self,
+        workspace_id: i32,
+        pushed_at: OffsetDateTime,
+        status: &str,
+    ) -> RepoResult<()> {
+        sqlx::query(
+            "UPDATE export_schedules SET last_pushed_at = $2, last_status = $3
+             WHERE workspace_id = $1",
+        )
+        .bind(workspace_id)
+        .bind(Timestamp(pushed_at))
+        .bind(status)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn inbound_email_token(&self, workspace_id: i32) -> RepoResult<Option<String>> {
+        sqlx::query_scalar("SELECT token FROM inbound_email_addresses WHERE workspace_id = $1")
+            .bind(workspace_id)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn save_inbound_email_token(&self, workspace_id: i32, token: &str) -> RepoResult<()> {
+        sqlx::query(
+            "INSERT INTO inbound_email_addresses (workspace_id, token, created_at)
+             VALUES ($1, $2, $3)
+             ON CONFLICT (workspace_id)
+             DO UPDATE SET token = excluded.token, created_at = excluded.created_at",
+        )
+        .bind(workspace_id)
+        .bind(token)
+        .bind(Timestamp(OffsetDateTime::now_utc()))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_inbound_email_token(&self, workspace_id: i32) -> RepoResult<bool> {
+        let result = sqlx::query("DELETE FROM inbound_email_addresses WHERE workspace_id = $1")
+            .bind(workspace_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn inbound_email_workspace(&self, token: &str) -> RepoResult<Option<(i32, i32)>> {
+        let row = sqlx::query(
+            "SELECT w.user_id, a.workspace_id
+             FROM inbound_email_addresses a
+             JOIN workspaces w ON w.workspace_id = a.workspace_id
+             JOIN users u ON u.user_id = w.user_id AND u.deactivated_at IS NULL
+             WHERE a.token = $1",
+        )
+        .bind(token)
+        .fetch_optional(&self.pool)
+        .await?;
+        row.map(|row| Ok((row.try_get("user_id")?, row.try_get("workspace_id")?)))
+            .transpose()
+    }
+
+    async fn contacts_by_email(
+        &self,
+        workspace_id: i32,
+        emails: &[String],
+    ) -> RepoResult<Vec<i32>> {
+        sqlx::query_scalar(
+            "SELECT c.contact_id
+             FROM json_each($2) e
+             JOIN contacts c ON lower(c.email) = e.value AND c.workspace_id = $1
+             ORDER BY e.key, c.contact_id",
+        )
+        .bind(workspace_id)
+        .bind(Json(emails))
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn list_rest_hooks(&self, workspace_id: i32) -> RepoResult<Vec<RestHook>> {
+        let rows = sqlx::query(
+            "SELECT h.hook_id, w.user_id, h.workspace_id, h.event, h.target_url, h.delivered_on
+             FROM rest_hooks h
+             JOIN workspaces w ON w.workspace_id = h.workspace_id
+             WHERE h.workspace_id = $1
+             ORDER BY h.hook_id",
+        )
+        .bind(workspace_id)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(rest_hook).collect()
+    }
+
+    async fn create_rest_hook(
+        &self,
+        workspace_id: i32,
+        hook: &NewRestHookRequest,
+    ) -> RepoResult<i32> {
+        sqlx::query_scalar(
+            "INSERT INTO rest_hooks (workspace_id, event, target_url)
+             VALUES ($1, $2, $3)
+             RETURNING hook_id",
+        )
+        .bind(workspace_id)
+        .bind(hook.event)
+        .bind(&hook.target_url)
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    async fn delete_rest_hook(&self, workspace_id: i32, hook_id: i32) -> RepoResult<bool> {
+        let result = sqlx::query("DELETE FROM rest_hooks WHERE hook_id = $1 AND workspace_id = $2")
+            .bind(hook_id)
+            .bind(workspace_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn owns_rest_hook(&self, workspace_id: i32, hook_id: i32) -> RepoResult<bool> {
+        self.exists(
+            "SELECT 1 FROM rest_hooks WHERE hook_id = $1 AND workspace_id = $2",
+            hook_id,
+            workspace_id,
+        )
+        .await
+    }
+
+    async fn rest_hooks_for_event(
+        &self,
+        event: HookEvent,
+        user_id: Option<i32>,
+    ) -> RepoResult<Vec<RestHook>> {
+        let rows = sqlx::query(
+            "SELECT h.hook_id, w.user_id, h.workspace_id, h.event, h.target_url, h.delivered_on
+             FROM rest_hooks h
+             JOIN workspaces w ON w.workspace_id = h.workspace_id
+             JOIN users u ON u.user_id = w.user_id AND u.deactivated_at IS NULL
+             WHERE h.event = $1 AND ($2 IS NULL OR w.user_id = $2)
+             ORDER BY h.hook_id",
+        )
+        .bind(event)
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(rest_hook).collect()
+    }
+
+    async fn record_rest_hook_delivery(&self, hook_id: i32, delivered_on: Date) -> RepoResult<()> {
+        sqlx::query("UPDATE rest_hooks SET delivered_on = $2 WHERE hook_id = $1")
+            .bind(hook_id)
+            .bind(delivered_on)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn queue_upcoming_occasions(
+        &self,
+        workspace_id: i32,
+        hook_id: i32,
+        items: &serde_json::Value,
+        delivered_on: Date,
+    ) -> RepoResult<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            "INSERT INTO outbox (workspace_id, hook_id, payload)
+             SELECT workspace_id, hook_id, $3 FROM rest_hooks
+             WHERE hook_id = $2 AND workspace_id = $1",
+        )
+        .bind(workspace_id)
+        .bind(hook_id)
+        .bind(Json(items))
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query(
+            "UPDATE rest_hooks SET delivered_on = $3 WHERE hook_id = $2 AND workspace_id = $1",
+        )
+        .bind(workspace_id)
+        .bind(hook_id)
+        .bind(delivered_on)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await
+    }
+
+    async fn hook_deliveries(
+        &self,
+        workspace_id: i32,
+        hook_id: i32,
+        limit: i64,
+    ) -> RepoResult<Vec<HookDelivery>> {
+        let rows = sqlx::query(
+            "SELECT delivery_id, status, attempts, last_response_status, last_error, created_at,
+                    next_attempt_at, finished_at
+             FROM outbox
+             WHERE workspace_id = $1 AND hook_id = $2
+             ORDER BY delivery_id DESC
+             LIMIT $3",
+        )
+        .bind(workspace_id)
+        .bind(hook_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter()
+            .map(|row| {
+                Ok(HookDelivery {
+                    delivery_id: row.try_get("delivery_id")?,
+                    status: row.try_get("status")?,
+                    attempts: row.try_get("attempts")?,
+                    last_response_status: row.try_get("last_response_status")?,
+                    last_error: row.try_get("last_error")?,
+                    created_at: row.try_get("created_at")?,
+                    next_attempt_at: row.try_get("next_attempt_at")?,
+                    finished_at: row.try_get("finished_at")?,
+                })
+            })
+            .collect()
+    }
+
+    async fn claim_deliveries(
+        &self,
+        now: OffsetDateTime,
+        until: OffsetDateTime,
+        limit: i64,
+    ) -> RepoResult<Vec<OutboxMessage>> {
+        // SQLite has one writer at a time, so the claim needs no row locks
+        let mut tx = self.pool.begin().await?;
+        let claimed: Vec<i32> = sqlx::query_scalar(
+            "UPDATE outbox SET next_attempt_at = $2
+             WHERE delivery_id IN (SELECT delivery_id FROM outbox
+                                   WHERE status = 'pending' AND next_attempt_at <= $1
+                                   ORDER BY next_attempt_at, delivery_id
+                                   LIMIT $3)
+             RETURNING delivery_id",
+        )
+        .bind(Timestamp(now))
+        .bind(Timestamp(until))
+        .bind(limit)
+        .fetch_all(&mut *tx)
+        .await?;
+        let rows = sqlx::query(
+            "SELECT o.delivery_id, o.workspace_id, o.hook_id, o.push_subscription_id,
+                    o.entity_ids, o.payload, o.attempts, h.event, h.target_url,
+                    p.user_id AS push_user_id, p.endpoint, p.p256dh, p.auth, p.device_name,
+                    p.created_at AS push_created_at
+             FROM outbox o
+             LEFT JOIN rest_hooks h ON h.hook_id = o.hook_id
+             LEFT JOIN push_subscriptions p ON p.subscription_id = o.push_subscription_id
+             WHERE o.delivery_id IN (SELECT value FROM json_each($1))
+             ORDER BY o.delivery_id",
+        )
+        .bind(Json(&claimed))
+        .fetch_all(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        fn decode<T: serde::de::DeserializeOwned>(
+            payload: Option<serde_json::Value>,
+        ) -> RepoResult<T> {
+            serde_json::from_value(payload.unwrap_or_default())
+                .map_err(|e| sqlx::Error::Decode(Box::new(e)))
+        }
+        rows.iter()
+            .map(|row| {
+                let hook_id: Option<i32> = row.try_get("hook_id")?;
+                let push_subscription_id: Option<i32> = row.try_get("push_subscription_id")?;
+                let payload = row
+                    .try_get::<Option<Json<serde_json::Value>>, _>("payload")?
+                    .map(|payload| payload.0);
+                let payload = match (hook_id, push_subscription_id, payload) {
+                    (Some(_), _, Some(items)) => OutboxPayload::Items(items),
+                    (Some(_), _, None) => {
+                        OutboxPayload::Entities(row.try_get::<Json<Vec<i32>>, _>("entity_ids")?.0)
+                    }
+                    (None, Some(_), payload) => OutboxPayload::Push(decode(payload)?),
+                    (None, None, payload) => OutboxPayload::Email(decode(payload)?),
+                };
+                let push_user_id: Option<i32> = row.try_get("push_user_id")?;
+                let push = match push_subscription_id.zip(push_user_id) {
+                    Some((subscription_id, user_id)) => Some(PushSubscription {
+                        subscription_id,
+                        user_id,
+                        endpoint: row.try_get("endpoint")?,
+                        p256dh: row.try_get("p256dh")?,
+                        auth: row.try_get("auth")?,
+                        device_name: row.try_get("device_name")?,
+                        created_at: row.try_get("push_created_at")?,
+                    }),
+                    None => None,
+                };
+                let event: Option<HookEvent> = row.try_get("event")?;
+                let target_url: Option<String> = row.try_get("target_url")?;
+                Ok(OutboxMessage {
+                    delivery_id: row.try_get("delivery_id")?,
+                    workspace_id: row.try_get("workspace_id")?,
+                    hook: hook_id
+                        .zip(event)
+                        .zip(target_url)
+                        .map(|((hook_id, event), target_url)| (hook_id, event, target_url)),
+                    push,
+                    payload,
+                    attempts: row.try_get("attempts")?,
+                })
+            })
+            .collect()
+    }
+
+    async fn record_delivery_attempt(
+        &self,
+        delivery_id: i32,
+        attempt: &DeliveryAttempt,
+    ) -> RepoResult<()> {
+        sqlx::query(
+            "UPDATE outbox
+             SET status = $2, attempts = attempts + 1, last_response_status = $3, last_error = $4,
+                 next_attempt_at = COALESCE($6, next_attempt_at),
+                 finished_at = CASE WHEN $2 = 'pending' THEN NULL ELSE $5 END
+             WHERE delivery_id = $1",
+        )
+        .bind(delivery_id)
+        .bind(attempt.status)
+        .bind(attempt.response_status)
+        .bind(attempt.error.as_deref())
+        .bind(Timestamp(attempt.at))
+        .bind(attempt.retry_at.map(Timestamp))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn purge_deliveries(&self, before: OffsetDateTime) -> RepoResult<u64> {
+        let result =
+            sqlx::query("DELETE FROM outbox WHERE status <> 'pending' AND finished_at < $1")
+                .bind(Timestamp(before))
+                .execute(&self.pool)
+                .await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn contact_links(
+        &self,
+        workspace_id: i32,
+        contact_id: i32,
+        now: OffsetDateTime,
+    ) -> RepoResult<Vec<ContactLink>> {
+        let rows = sqlx::query(
+            "SELECT link_id, contact_id, expires_at
+             FROM contact_links
+             WHERE workspace_id = $1 AND contact_id = $2 AND expires_at > $3
+             ORDER BY link_id",
+        )
+        .bind(workspace_id)
+        .bind(contact_id)
+        .bind(Timestamp(now))
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(contact_link).collect()
+    }
+
+    async fn create_contact_link(
+        &self,
+        workspace_id: i32,
+        contact_id: i32,
+        token_hash: &str,
+        expires_at: OffsetDateTime,
+    ) -> RepoResult<ContactLink> {
+        let row = sqlx::query(
+            "INSERT INTO contact_links (workspace_id, contact_id, token_hash, expires_at)
+             VALUES ($1, $2, $3, $4)
+             RETURNING link_id, contact_id, expires_at",
+        )
+        .bind(workspace_id)
+        .bind(contact_id)
+        .bind(token_hash)
+        .bind(Timestamp(expires_at))
+        .fetch_one(&self.pool)
+        .await?;
+        contact_link(&row)
+    }
+
+    async fn delete_contact_link(&self, workspace_id: i32, link_id: i32) -> RepoResult<bool> {
+        let result =
+            sqlx::query("DELETE FROM contact_links WHERE link_id = $1 AND workspace_id = $2")
+                .bind(link_id)
+                .bind(workspace_id)
+                .execute(&self.pool)
+                .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn owns_contact_link(&self, workspace_id: i32, link_id: i32) -> RepoResult<bool> {
+        self.exists(
+            "SELECT 1 FROM contact_links WHERE link_id = $1 AND workspace_id = $2",
+            link_id,
+            workspace_id,
+        )
+        .await
+    }
+
+    async fn contact_link_target(
+        &self,
+        token_hash: &str,
+        now: OffsetDateTime,
+    ) -> RepoResult<Option<(i32, i32)>> {
+        let row = sqlx::query(
+            "SELECT l.workspace_id, l.contact_id
+             FROM contact_links l
+             JOIN workspaces w ON w.workspace_id = l.workspace_id
+             JOIN users u ON u.user_id = w.user_id AND u.deactivated_at IS NULL
+             WHERE l.token_hash = $1 AND l.expires_at > $2",
+        )
+        .bind(token_hash)
+        .bind(Timestamp(now))
+        .fetch_optional(&self.pool)
+        .await?;
+        row.map(|row| Ok((row.try_get("workspace_id")?, row.try_get("contact_id")?)))
+            .transpose()
+    }
+
+    async fn claim_idempotency_key(
+        &self,
+        user_id: i32,
+        key: &str,
+        fingerprint: &str,
+        ttl_hours: i32,
+    ) -> RepoResult<Option<IdempotentRequest>> {
+        let expired = OffsetDateTime::now_utc() - time::Duration::hours(ttl_hours.into());
+        sqlx::query("DELETE FROM idempotency_keys WHERE user_id = $1 AND created_at < $2")
+            .bind(user_id)
+            .bind(Timestamp(expired))
+            .execute(&self.pool)
+            .await?;
+
+        // A key released between the insert and the select is claimed on the next pass
+        loop {
+            let claimed: Option<i32> = sqlx::query_scalar(
+                "INSERT INTO idempotency_keys (user_id, idempotency_key, fingerprint)
+                 VALUES ($1, $2, $3)
+                 ON CONFLICT DO NOTHING
+                 RETURNING user_id",
+            )
+            .bind(user_id)
+            .bind(key)
+            .bind(fingerprint)
+            .fetch_optional(&self.pool)
+            .await?;
+            if claimed.is_some() {
+                return Ok(None);
+            }
+
+            let held = sqlx::query(
+                "SELECT fingerprint, status, content_type, location, body FROM idempotency_keys
+                 WHERE user_id = $1 AND idempotency_key = $2",
+            )
+            .bind(user_id)
+            .bind(key)
+            .fetch_optional(&self.pool)
+            .await?;
+            if let Some(row) = held {
+                let status: Option<i32> = row.try_get("status")?;
+                let response = match status {
+                    Some(status) => Some(StoredResponse {
+                        status: status as u16,
+                        content_type: row.try_get("content_type")?,
+                        location: row.try_get("location")?,
+                        body: row
+                            .try_get::<Option<Vec<u8>>, _>("body")?
+                            .unwrap_or_default(),
+                    }),
+                    None => None,
+                };
+                return Ok(Some(IdempotentRequest {
+                    fingerprint: row.try_get("fingerprint")?,
+                    response,
+                }));
+            }
+        }
+    }
+
+    async fn complete_idempotency_key(
+        &self,
+        user_id: i32,
+        key: &str,
+        response: &StoredResponse,
+    ) -> RepoResult<()> {
+        sqlx::query(
+            "UPDATE idempotency_keys SET status = $3, content_type = $4, location = $5, body = $6
+             WHERE user_id = $1 AND idempotency_key = $2",
+        )
+        .bind(user_id)
+        .bind(key)
+        .bind(i32::from(response.status))
+        .bind(response.content_type.as_deref())
+        .bind(response.location.as_deref())
+        .bind(&response.body)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn release_idempotency_key(&self, user_id: i32, key: &str) -> RepoResult<()> {
+        sqlx::query("DELETE FROM idempotency_keys WHERE user_id = $1 AND idempotency_key = $2")
+            .bind(user_id)
+            .bind(key)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+}
+
+/// Insert a contact, queueing it for the workspace's new contact hooks
+async fn insert_contact(
+    conn: &mut SqliteConnection,
+    workspace_id: i32,
+    contact: &NewContactRequest,
+    notes: Option<&NotesCipher>,
+) -> RepoResult<i32> {
+    let contact_id = insert_contact_row(&mut *conn, workspace_id, contact, notes).await?;
+    queue_hook_deliveries(
+        &mut *conn,
+        workspace_id,
+        HookEvent::NewContact,
+        &[contact_id],
+    )
+    .await?;
+    Ok(contact_id)
+}
+
+/// Insert every contact, returning ids in input order, and queue them for the workspace's new
+/// contact hooks
+async fn insert_contacts(
+    conn: &mut SqliteConnection,
+    workspace_id: i32,
+    contacts: &[NewContactRequest],
+    notes: Option<&NotesCipher>,
+) -> RepoResult<Vec<i32>> {
+    let mut ids = Vec::with_capacity(contacts.len());
+    for contact in contacts {
+        ids.push(insert_contact_row(&mut *conn, workspace_id, contact, notes).await?);
+    }
+    queue_hook_deliveries(&mut *conn, workspace_id, HookEvent::NewContact, &ids).await?;
+    Ok(ids)
+}
+
+async fn insert_contact_row(
+    conn: &mut SqliteConnection,
+    workspace_id: i32,
+    contact: &NewContactRequest,
+    notes: Option<&NotesCipher>,
+) -> RepoResult<i32> {
+    // SQLite's clock only has milliseconds, so the column default would sort a contact before
+    // instants taken just ahead of creating it
+    sqlx::query_scalar(
+        "INSERT INTO contacts (workspace_id, first_name, last_name, email, phone, phone_e164,
+                               short_note, notes, avatar_url, desired_frequency_days, met_at,
+                               introduced_by_contact_id, location, created_at)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
+         RETURNING contact_id",
+    )
+    .bind(workspace_id)
+    .bind(contact.first_name.as_deref())
+    .bind(contact.last_name.as_deref())
+    .bind(contact.email.as_deref())
+    .bind(contact.phone.as_deref())
+    .bind(phone::e164(contact.phone.as_deref()))
+    .bind(encryption::seal(notes, contact.short_note.as_deref()))
+    .bind(encryption::seal(notes, contact.notes.as_deref()))
+    .bind(contact.avatar_url.as_deref())
+    .bind(contact.desired_frequency_days)
+    .bind(contact.met_at.as_ref().map(Json))
+    .bind(contact.introduced_by_contact_id)
+    .bind(contact.location.as_deref())
+    .bind(Timestamp(OffsetDateTime::now_utc()))
+    .fetch_one(conn)
+    .await
+}
+
+/// Insert the contacts as one batch; if any row fails, redo it row by row so only the failing
+/// rows are dropped
+async fn insert_contacts_skipping_failures(
+    conn: &mut SqliteConnection,
+    workspace_id: i32,
+    contacts: &[NewContactRequest],
+    notes: Option<&NotesCipher>,
+) -> RepoResult<Vec<RepoResult<i32>>> {
+    let mut batch = conn.begin().await?;
+    match insert_contacts(&mut batch, workspace_id, contacts, notes).await {
+        Ok(ids) => {
+            batch.commit().await?;
+            return Ok(ids.into_iter().map(Ok).collect());
+        }
+        Err(_) => batch.rollback().await?,
+    }
+    let mut results = Vec::with_capacity(contacts.len());
+    for contact in contacts {
+        let mut row = conn.begin().await?;
+        match insert_contact(&mut row, workspace_id, contact, notes).await {
+            Ok(contact_id) => {
+                row.commit().await?;
+                results.push(Ok(contact_id));
+            }
+            Err(e) => {
+                row.rollback().await?;
+                results.push(Err(e));
+            }
+        }
+    }
+    Ok(results)
+}
+
+/// Queue new entities for each of the workspace's hooks for `event`, in the caller's
+/// transaction
+async fn queue_hook_deliveries(
+    conn: &mut SqliteConnection,
+    workspace_id: i32,
+    event: HookEvent,
+    ids: &[i32],
+) -> RepoResult<()> {
+    if ids.is_empty() {
+        return Ok(());
+    }
+    sqlx::query(
+        "INSERT INTO outbox (workspace_id, hook_id, entity_ids)
+         SELECT workspace_id, hook_id, $3 FROM rest_hooks WHERE workspace_id = $1 AND event = $2",
+    )
+    .bind(workspace_id)
+    .bind(event)
+    .bind(Json(ids))
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Record edits of a contact in its history
+async fn insert_contact_changes(
+    conn: &mut SqliteConnection,
+    contact_id: i32,
+    changes: &[FieldChange],
+    notes: Option<&NotesCipher>,
+) -> RepoResult<()> {
+    // Notes stay encrypted in the history too
+    let seal = |change: &FieldChange, value: &Option<String>| match notes {
+        Some(_) if ENCRYPTED_FIELDS.contains(&change.field) => {
+            encryption::seal(notes, value.as_deref())
+        }
+        _ => value.clone(),
+    };
+    for change in changes {
+        sqlx::query(
+            "INSERT INTO contact_changes (contact_id, field, old_value, new_value)
+             VALUES ($1, $2, $3, $4)",
+        )
+        .bind(contact_id)
+        .bind(change.field)
+        .bind(seal(change, &change.before))
+        .bind(seal(change, &change.after))
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(())
+}
+
+/// Insert an interaction with its participants, ending dismissals of the contacts involved,
+/// and queue it for the workspace's new interaction hooks
+async fn insert_interaction(
+    conn: &mut SqliteConnection,
+    workspace_id: i32,
+    interaction: &NewInteractionRequest,
+) -> RepoResult<i32> {
+    let interaction_id: i32 = sqlx::query_scalar(
+        "INSERT INTO interactions (workspace_id, contact_id, interaction_date, notes,
+                                   followup_priority, interaction_type, custom_type_id)
+         VALUES ($1, $2, $3, $4, $5, $6,
+                 (SELECT type_id FROM interaction_types WHERE workspace_id = $1 AND name = $7))
+         RETURNING interaction_id",
+    )
+    .bind(workspace_id)
+    .bind(interaction.contact_id)
+    .bind(Timestamp(interaction.interaction_date))
+    .bind(interaction.notes.as_deref())
+    .bind(interaction.follow_up_priority)
+    .bind(builtin_type(interaction))
+    .bind(interaction.interaction_type.as_deref())
+    .fetch_one(&mut *conn)
+    .await?;
+    save_participants(
+        &mut *conn,
+        workspace_id,
+        interaction_id,
+        &interaction.contact_ids,
+    )
+    .await?;
+    // Getting in touch ends dismissals of the contacts involved
+    sqlx::query(
+        "DELETE FROM suggestion_snoozes
+         WHERE snoozed_until IS NULL
+           AND (contact_id = $1
+                OR contact_id IN (SELECT contact_id FROM interaction_participants
+                                  WHERE interaction_id = $2))",
+    )
+    .bind(interaction.contact_id)
+    .bind(interaction_id)
+    .execute(&mut *conn)
+    .await?;
+    queue_hook_deliveries(
+        &mut *conn,
+        workspace_id,
+        HookEvent::NewInteraction,
+        &[interaction_id],
+    )
+    .await?;
+    Ok(interaction_id)
+}
+
+async fn insert_occasion(
+    conn: &mut SqliteConnection,
+    workspace_id: i32,
+    occasion: &NewOccasionRequest,
+    interaction_id: Option<i32>,
+) -> RepoResult<i32> {
+    sqlx::query_scalar(
+        "INSERT INTO occasions (workspace_id, contact_id, name, date, recurring,
+                                recurring_interval, details, occasion_type, birth_year,
+                                interaction_id, remind_days_before)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+         RETURNING occasion_id",
+    )
+    .bind(workspace_id)
+    .bind(occasion.contact_id)
+    .bind(&occasion.name)
+    .bind(occasion.date)
+    .bind(occasion.recurring)
+    .bind(occasion.recurring_interval)
+    .bind(occasion.details.as_deref())
+    .bind(occasion.occasion_type)
+    .bind(occasion.birth_year)
+    .bind(interaction_id)
+    .bind(occasion.remind_days_before.as_ref().map(Json))
+    .fetch_one(conn)
+    .await
+}
+
+/// Add the workspace's contacts among `contact_ids` to an interaction, skipping its own contact
+async fn save_participants(
+    conn: &mut SqliteConnection,
+    workspace_id: i32,
+    interaction_id: i32,
+    contact_ids: &[i32],
+) -> RepoResult<()> {
+    sqlx::query(
+        "INSERT INTO interaction_participants (interaction_id, contact_id)
+         SELECT i.interaction_id, c.contact_id
+         FROM interactions i
+         JOIN contacts c ON c.contact_id IN (SELECT value FROM json_each($3))
+                        AND c.workspace_id = $2 AND c.contact_id IS NOT i.contact_id
+         WHERE i.interaction_id = $1
+         ON CONFLICT DO NOTHING",
+    )
+    .bind(interaction_id)
+    .bind(workspace_id)
+    .bind(Json(contact_ids))
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// The stored `interaction_type` of a built-in type; custom types go in `custom_type_id`
+fn builtin_type(interaction: &NewInteractionRequest) -> Option<&str> {
+    interaction
+        .interaction_type
+        .as_deref()
+        .filter(|name| BUILTIN_INTERACTION_TYPES.contains(name))
+}
+
+fn capture(row: &SqliteRow) -> RepoResult<Capture> {
+    Ok(Capture {
+        capture_id: row.try_get("capture_id")?,
+        text: row.try_get("text")?,
+        captured_at: row.try_get("captured_at")?,
+    })
+}
+
+fn social_profile(row: &SqliteRow) -> RepoResult<SocialProfile> {
+    Ok(SocialProfile {
+        social_profile_id: row.try_get("social_profile_id")?,
+        contact_id: row.try_get("contact_id")?,
+        platform: row.try_get("platform")?,
+        url: row.try_get("url")?,
+        preview: None,
+    })
+}
+
+fn gift_idea(row: &SqliteRow) -> RepoResult<GiftIdea> {
+    Ok(GiftIdea {
+        gift_idea_id: row.try_get("gift_idea_id")?,
+        contact_id: row.try_get("contact_id")?,
+        occasion_id: row.try_get("occasion_id")?,
+        idea: row.try_get("idea")?,
+        status: row.try_get("status")?,
+        price_cents: row.try_get("price_cents")?,
+    })
+}
+
+fn group(row: &SqliteRow) -> RepoResult<Group> {
+    Ok(Group {
+        group_id: row.try_get("group_id")?,
+        name: row.try_get("name")?,
+        description: row.try_get("description")?,
+        contact_ids: row.try_get::<Json<Vec<i32>>, _>("contact_ids")?.0,
+    })
+}
+
+fn saved_filter(row: &SqliteRow) -> RepoResult<SavedFilter> {
+    Ok(SavedFilter {
+        filter_id: row.try_get("filter_id")?,
+        name: row.try_get("name")?,
+        query: row.try_get::<Json<FilterQuery>, _>("query")?.0,
+    })
+}
+
+fn goal(row: &SqliteRow) -> RepoResult<Goal> {
+    Ok(Goal {
+        goal_id: row.try_get("goal_id")?,
+        name: row.try_get("name")?,
+        contact_id: row.try_get("contact_id")?,
+        interaction_type: row.try_get("interaction_type")?,
+        target_count: row.try_get("target_count")?,
+        period: row.try_get("period")?,
+    })
+}
+
+fn export_schedule(row: &SqliteRow) -> RepoResult<ExportSchedule> {
+    Ok(ExportSchedule {
+        user_id: row.try_get("user_id")?,
+        workspace_id: row.try_get("workspace_id")?,
+        url: row.try_get("url")?,
+        interval_hours: row.try_get("interval_hours")?,
+        secret: row.try_get("secret")?,
+        last_pushed_at: row.try_get("last_pushed_at")?,
+        last_status: row.try_get("last_status")?,
+    })
+}
+
+fn rest_hook(row: &SqliteRow) -> RepoResult<RestHook> {
+    Ok(RestHook {
+        hook_id: row.try_get("hook_id")?,
+        user_id: row.try_get("user_id")?,
+        workspace_id: row.try_get("workspace_id")?,
+        event: row.try_get("event")?,
+        target_url: row.try_get("target_url")?,
+        delivered_on: row.try_get("delivered_on")?,
+    })
+}
+
+fn contact_link(row: &SqliteRow) -> RepoResult<ContactLink> {
+    Ok(ContactLink {
+        link_id: row.try_get("link_id")?,
+        contact_id: row.try_get("contact_id")?,
+        token: None,
+        expires_at: row.try_get("expires_at")?,
+        url: None,
+    })
+}
@@ -0,0 +1,247 @@
+//! The weekly review: the week just gone and the one ahead.
+//!
+//! `GET /review/weekly` covers the seven days before today in the user's timezone: how many
+//! interactions are dated in them and which contacts were added, then the occasions of the next
+//! seven days and this week's reconnect suggestions. Users who turn on
+//! `notifications.weekly_review` in `/me`, with `email` still on, are emailed the review once a
+//! week, the first time the background task runs after their Monday begins (right away for a
+//! user who turns it on midweek), through the `outbox`. Like reminders, the email keeps off the
+//! user's quiet weekdays and public holidays and goes out on the next day that is neither.
+//! Emails need SMTP_URL; see `mail`.
+
+use crate::AuthUser;
+use crate::jobs::Job;
+use crate::mail::Email;
+use crate::models::{Contact, Preferences, ReviewRecipient, UpcomingOccasion, WeeklyReview};
+use crate::reconnect::{self, week_start};
+use crate::reminders::{holidays_for, is_quiet_day, next_occurrence, upcoming_age};
+use crate::repository::{RepoResult, Repository};
+use crate::timezone::LocalDates;
+use actix_web::{HttpResponse, Responder, get, web};
+use async_trait::async_trait;
+use std::fmt::Write;
+use time::{Date, Duration};
+
+/// Days reviewed, and days of upcoming occasions
+pub const REVIEW_DAYS: i64 = 7;
+
+/// How often the background task looks for reviews to send
+pub const CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(15 * 60);
+
+/// The review for the user's workspace as of their today
+pub async fn weekly_review(
+    repo: &dyn Repository,
+    user_id: i32,
+    workspace_id: i32,
+    dates: &LocalDates,
+) -> RepoResult<WeeklyReview> {
+    let today = dates.today();
+    let from = today - Duration::days(REVIEW_DAYS);
+    let (start, end) = (dates.start_of(from), dates.start_of(today));
+
+    let interactions_logged = repo
+        .interactions_since(workspace_id, start)
+        .await?
+        .iter()
+        .filter(|i| i.interaction_date < end)
+        .count();
+    let new_contacts = repo
+        .contacts_created_between(workspace_id, start, end)
+        .await?;
+
+    let contact_ids: Vec<i32> = repo
+        .list_contacts(workspace_id)
+        .await?
+        .iter()
+        .map(|c| c.contact_id)
+        .collect();
+    let until = today + Duration::days(REVIEW_DAYS);
+    let mut upcoming_occasions: Vec<UpcomingOccasion> = repo
+        .occasions_for_contacts(&contact_ids)
+        .await?
+        .into_iter()
+        .filter_map(|mut occasion| {
+            let occurs_on = next_occurrence(&occasion, today).filter(|on| *on < until)?;
+            occasion.upcoming_age = upcoming_age(&occasion, today);
+            Some(UpcomingOccasion {
+                occurs_on,
+                occasion,
+            })
+        })
+        .collect();
+    upcoming_occasions.sort_by_key(|u| (u.occurs_on, u.occasion.occasion_id));
+
+    let reconnect = reconnect::this_week(repo, user_id, workspace_id, dates)
+        .await?
+        .contacts;
+
+    Ok(WeeklyReview {
+        from,
+        to: today - Duration::days(1),
+        timezone: dates.timezone_name().to_string(),
+        interactions_logged,
+        new_contacts,
+        upcoming_occasions,
+        reconnect,
+    })
+}
+
+pub(crate) fn contact_name(contact: &Contact) -> String {
+    let name = [contact.first_name.as_deref(), contact.last_name.as_deref()]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(" ");
+    if !name.is_empty() {
+        name
+    } else {
+        contact
+            .email
+            .clone()
+            .unwrap_or_else(|| format!("Contact {}", contact.contact_id))
+    }
+}
+
+pub(crate) fn plural(count: usize, one: &str, many: &str) -> String {
+    format!("{} {}", count, if count == 1 { one } else { many })
+}
+
+/// The review as a plain text email. `contacts` are the workspace's, for naming the
+/// occasions' contacts.
+pub fn render_email(
+    recipient: &ReviewRecipient,
+    review: &WeeklyReview,
+    contacts: &[Contact],
+) -> Email {
+    let mut text = format!("Hi {},\n\n", recipient.name);
+    let _ = writeln!(text, "Your week, {} to {}:", review.from, review.to);
+    let _ = writeln!(
+        text,
+        "- {} logged",
+        plural(review.interactions_logged, "interaction", "interactions")
+    );
+    let new_contacts: Vec<String> = review.new_contacts.iter().map(contact_name).collect();
+    let _ = write!(
+        text,
+        "- {} added",
+        plural(new_contacts.len(), "new contact", "new contacts")
+    );
+    if !new_contacts.is_empty() {
+        let _ = write!(text, ": {}", new_contacts.join(", "));
+    }
+
+    text.push_str("\n\nComing up:\n");
+    if review.upcoming_occasions.is_empty() {
+        text.push_str("- Nothing in the next seven days\n");
+    }
+    for upcoming in &review.upcoming_occasions {
+        let occasion = &upcoming.occasion;
+        let name = contacts
+            .iter()
+            .find(|c| Some(c.contact_id) == occasion.contact_id)
+            .map_or_else(String::new, contact_name);
+        let _ = writeln!(
+            text,
+            "- {}: {}, {}",
+            upcoming.occurs_on, name, occasion.name
+        );
+    }
+
+    text.push_str("\nReach out this week:\n");
+    if review.reconnect.is_empty() {
+        text.push_str("- Nobody is due\n");
+    }
+    for pick in &review.reconnect {
+        let last = match pick.days_since_last_interaction {
+            Some(days) => format!("last in touch {} ago", plural(days as usize, "day", "days")),
+            None => "not in touch yet".to_string(),
+        };
+        let _ = writeln!(text, "- {}, {}", contact_name(&pick.contact), last);
+    }
+
+    Email {
+        to: recipient.email.clone(),
+        subject: format!("Your weekly review, {} to {}", review.from, review.to),
+        text,
+    }
+}
+
+/// Whether the review due in `today`'s week is sent today: not on a quiet day, unless every day
+/// of the week is quiet
+pub fn sends_on(today: Date, preferences: &Preferences) -> bool {
+    let week = week_start(today);
+    let holidays = holidays_for(preferences, week, week + Duration::days(6));
+    let is_quiet = |day: Date| is_quiet_day(preferences, &holidays, day);
+    !is_quiet(today) || (0..7).all(|days| is_quiet(week + Duration::days(days)))
+}
+
+/// Queues the weekly review emails, which the `outbox` sends
+pub struct ReviewEmails;
+
+impl ReviewEmails {
+    /// Queue a review for every recipient not yet sent one in their current week and not on
+    /// a quiet day, returning how many were queued
+    pub async fn queue_due(&self, repo: &dyn Repository) -> RepoResult<usize> {
+        let mut queued = 0;
+        for recipient in repo.weekly_review_recipients().await? {
+            let preferences = repo.get_preferences(recipient.user_id).await?;
+            let dates = LocalDates::for_preferences(&preferences);
+            let today = dates.today();
+            let week = week_start(today);
+            if recipient.sent_week.is_some_and(|sent| sent >= week)
+                || !sends_on(today, &preferences)
+            {
+                continue;
+            }
+            let review =
+                weekly_review(repo, recipient.user_id, recipient.workspace_id, &dates).await?;
+            let contacts = repo.list_contacts(recipient.workspace_id).await?;
+            let email = render_email(&recipient, &review, &contacts);
+            repo.queue_weekly_review(recipient.user_id, recipient.workspace_id, week, &email)
+                .await?;
+            queued += 1;
+        }
+        Ok(queued)
+    }
+}
+
+/// Checks for reviews to queue every `CHECK_INTERVAL`
+#[async_trait]
+impl Job for ReviewEmails {
+    fn name(&self) -> &'static str {
+        "weekly-review-emails"
+    }
+
+    fn interval(&self) -> std::time::Duration {
+        CHECK_INTERVAL
+    }
+
+    async fn run(&self, repo: &dyn Repository) -> RepoResult<()> {
+        self.queue_due(repo).await.map(|_| ())
+    }
+}
+
+/// The past seven days and the week ahead, in the user's timezone
+#[get("/review/weekly")]
+pub async fn get_weekly_review(
+    repo: web::Data<dyn Repository>,
+    auth_user: AuthUser,
+) -> impl Responder {
+    let review = async {
+        let dates = LocalDates::for_user(repo.get_ref(), auth_user.user_id).await?;
+        weekly_review(
+            repo.get_ref(),
+            auth_user.user_id,
+            auth_user.workspace_id,
+            &dates,
+        )
+        .await
+    };
+    match review.await {
+        Ok(review) => HttpResponse::Ok().json(review),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to build review")
+        }
+    }
+}
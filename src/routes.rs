@@ -0,0 +1,810 @@
+//! Route table for the versioned API and the tenant guard shared by its handlers
+
+use crate::AuthUser;
+#[cfg(feature = "capture-parsing")]
+use crate::capture_parsing;
+use crate::models::{Contact, ContactAccess, Group, Interaction, SharePermission, Tag};
+use crate::repository::{RepoResult, Repository};
+use crate::{
+    account, attachments, contact_links, contacts, deletion, enrichment, events, export, filters,
+    gift_ideas, goals, groups, health, history, import, inbound_email, inbox, interactions,
+    occasions, outbox, preferences, push, quick_add, quotas, reconnect, reminders, review, shares,
+    social_profiles, stats, tags, workspaces, zapier,
+};
+use actix_web::dev::Payload;
+use actix_web::error::{ErrorInternalServerError, InternalError};
+use actix_web::http::Method;
+use actix_web::{Error, FromRequest, HttpRequest, HttpResponse, web};
+use std::collections::HashSet;
+use std::future::Future;
+use std::pin::Pin;
+
+/// Kinds of user-owned rows that routes reference by id. All but `Share` and `Workspace` live
+/// in a workspace and are only found in the request's one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resource {
+    Contact,
+    Tag,
+    Interaction,
+    Capture,
+    InteractionType,
+    Occasion,
+    SocialProfile,
+    GiftIdea,
+    Group,
+    Filter,
+    Goal,
+    /// Visible to both the user who made the share and the one who received it
+    Share,
+    Workspace,
+    RestHook,
+    ContactLink,
+    /// Belongs to the user rather than a workspace
+    PushSubscription,
+    Attachment,
+}
+
+impl Resource {
+    fn not_found(self) -> &'static str {
+        match self {
+            Resource::Contact => "Contact not found",
+            Resource::Tag => "Tag not found",
+            Resource::Interaction => "Interaction not found",
+            Resource::Capture => "Capture not found",
+            Resource::InteractionType => "Interaction type not found",
+            Resource::Occasion => "Occasion not found",
+            Resource::SocialProfile => "Social profile not found",
+            Resource::GiftIdea => "Gift idea not found",
+            Resource::Group => "Group not found",
+            Resource::Filter => "Filter not found",
+            Resource::Goal => "Goal not found",
+            Resource::Share => "Share not found",
+            Resource::Workspace => "Workspace not found",
+            Resource::RestHook => "Hook not found",
+            Resource::ContactLink => "Link not found",
+            Resource::PushSubscription => "Push subscription not found",
+            Resource::Attachment => "Attachment not found",
+        }
+    }
+}
+
+/// Check whether a row belongs to the user and, for workspace rows, to the request's workspace
+pub(crate) async fn owns(
+    repo: &dyn Repository,
+    auth_user: &AuthUser,
+    resource: Resource,
+    id: i32,
+) -> RepoResult<bool> {
+    let workspace_id = auth_user.workspace_id;
+    match resource {
+        Resource::Contact => repo.owns_contact(workspace_id, id).await,
+        Resource::Tag => repo.owns_tag(workspace_id, id).await,
+        Resource::Interaction => repo.owns_interaction(workspace_id, id).await,
+        Resource::Capture => repo.owns_capture(workspace_id, id).await,
+        Resource::InteractionType => repo.owns_interaction_type(workspace_id, id).await,
+        Resource::Occasion => repo.owns_occasion(workspace_id, id).await,
+        Resource::SocialProfile => repo.owns_social_profile(workspace_id, id).await,
+        Resource::GiftIdea => repo.owns_gift_idea(workspace_id, id).await,
+        Resource::Group => repo.owns_group(workspace_id, id).await,
+        Resource::Filter => repo.owns_filter(workspace_id, id).await,
+        Resource::Goal => repo.owns_goal(workspace_id, id).await,
+        Resource::Share => repo.owns_share(auth_user.user_id, id).await,
+        Resource::Workspace => repo.owns_workspace(auth_user.user_id, id).await,
+        Resource::RestHook => repo.owns_rest_hook(workspace_id, id).await,
+        Resource::ContactLink => repo.owns_contact_link(workspace_id, id).await,
+        Resource::PushSubscription => repo.owns_push_subscription(auth_user.user_id, id).await,
+        Resource::Attachment => repo.owns_attachment(workspace_id, id).await,
+    }
+}
+
+/// Tenant guard for handlers touching a row by id.
+/// Rows owned by someone else, or in another of the user's workspaces, are reported exactly
+/// like missing ones.
+pub(crate) async fn ensure_owned(
+    repo: &dyn Repository,
+    auth_user: &AuthUser,
+    resource: Resource,
+    id: i32,
+) -> Result<(), HttpResponse> {
+    match owns(repo, auth_user, resource, id).await {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(HttpResponse::NotFound().body(resource.not_found())),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            Err(HttpResponse::InternalServerError().body("Database error"))
+        }
+    }
+}
+
+/// A row of the request's workspace named in the route's path, loaded before the handler runs
+/// so it gets the row already checked. As with `ensure_owned`, rows that aren't the user's
+/// are reported like missing ones, with a 404 naming the resource.
+pub struct Owned<T>(pub T);
+
+pub type OwnedContact = Owned<Contact>;
+pub type OwnedTag = Owned<Tag>;
+pub type OwnedInteraction = Owned<Interaction>;
+pub type OwnedGroup = Owned<Group>;
+
+/// Rows `Owned` can load
+pub trait OwnedRow: Sized + 'static {
+    const RESOURCE: Resource;
+    /// The path parameter holding the row's id, such as `{tag_id}` in
+    /// `/tags/{tag_id}/contacts/bulk`. Routes with a single id for the row itself use `{id}`.
+    const PARAM: &'static str;
+
+    fn load(
+        repo: &dyn Repository,
+        workspace_id: i32,
+        id: i32,
+    ) -> impl Future<Output = RepoResult<Option<Self>>>;
+}
+
+impl OwnedRow for Contact {
+    const RESOURCE: Resource = Resource::Contact;
+    const PARAM: &'static str = "contact_id";
+
+    async fn load(repo: &dyn Repository, workspace_id: i32, id: i32) -> RepoResult<Option<Self>> {
+        repo.get_contact(workspace_id, id).await
+    }
+}
+
+impl OwnedRow for Tag {
+    const RESOURCE: Resource = Resource::Tag;
+    const PARAM: &'static str = "tag_id";
+
+    async fn load(repo: &dyn Repository, workspace_id: i32, id: i32) -> RepoResult<Option<Self>> {
+        repo.get_tag(workspace_id, id).await
+    }
+}
+
+impl OwnedRow for Interaction {
+    const RESOURCE: Resource = Resource::Interaction;
+    const PARAM: &'static str = "interaction_id";
+
+    async fn load(repo: &dyn Repository, workspace_id: i32, id: i32) -> RepoResult<Option<Self>> {
+        Ok(repo
+            .interactions_by_id(workspace_id, &[id])
+            .await?
+            .into_iter()
+            .next())
+    }
+}
+
+impl OwnedRow for Group {
+    const RESOURCE: Resource = Resource::Group;
+    const PARAM: &'static str = "group_id";
+
+    async fn load(repo: &dyn Repository, workspace_id: i32, id: i32) -> RepoResult<Option<Self>> {
+        repo.get_group(workspace_id, id).await
+    }
+}
+
+impl<T: OwnedRow> FromRequest for Owned<T> {
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let user = AuthUser::from_request(req, payload);
+        let repo = req.app_data::<web::Data<dyn Repository>>().cloned();
+        let id = req
+            .match_info()
+            .get(T::PARAM)
+            .or_else(|| req.match_info().get("id"))
+            .and_then(|id| id.parse::<i32>().ok());
+
+        Box::pin(async move {
+            let user = user.await?;
+            let repo = repo.ok_or_else(|| ErrorInternalServerError("Database not available"))?;
+            let not_found = || {
+                let message = T::RESOURCE.not_found();
+                InternalError::from_response(message, HttpResponse::NotFound().body(message))
+            };
+            let id = id.ok_or_else(not_found)?;
+            match T::load(repo.get_ref(), user.workspace_id, id).await {
+                Ok(Some(row)) => Ok(Owned(row)),
+                Ok(None) => Err(not_found().into()),
+                Err(e) => {
+                    eprintln!("Database error: {:?}", e);
+                    Err(ErrorInternalServerError("Database error"))
+                }
+            }
+        })
+    }
+}
+
+/// Guard for handlers that also serve contacts shared with the user, returning the contact's
+/// owner and workspace, whose rows the handler then works on. Contacts the user cannot see are
+/// reported like missing ones; a share without the needed permission is a 403.
+pub(crate) async fn ensure_contact_access(
+    repo: &dyn Repository,
+    auth_user: &AuthUser,
+    contact_id: i32,
+    needed: SharePermission,
+) -> Result<ContactAccess, HttpResponse> {
+    match repo
+        .contact_access(auth_user.user_id, auth_user.workspace_id, contact_id)
+        .await
+    {
+        Ok(Some(access)) if access.allows(needed) => Ok(access),
+        Ok(Some(_)) => Err(HttpResponse::Forbidden().body("Contact is shared read-only")),
+        Ok(None) => Err(HttpResponse::NotFound().body(Resource::Contact.not_found())),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            Err(HttpResponse::InternalServerError().body("Database error"))
+        }
+    }
+}
+
+/// Requested ids a bulk operation left alone (missing or owned by someone else), in request
+/// order and without duplicates
+pub(crate) fn skipped_ids(requested: &[i32], done: &[i32]) -> Vec<i32> {
+    let mut seen: HashSet<i32> = done.iter().copied().collect();
+    requested
+        .iter()
+        .copied()
+        .filter(|id| seen.insert(*id))
+        .collect()
+}
+
+/// Description of a route for tooling and tests: which user-owned rows it references
+#[derive(Debug, Clone)]
+pub struct RouteSpec {
+    pub method: Method,
+    /// Route pattern as registered, including the version scope
+    pub pattern: &'static str,
+    /// Rows referenced by the path parameters, in order
+    pub path: &'static [Resource],
+    /// Rows referenced by ids in the JSON body
+    pub body: &'static [Resource],
+}
+
+const fn route(
+    method: Method,
+    pattern: &'static str,
+    path: &'static [Resource],
+    body: &'static [Resource],
+) -> RouteSpec {
+    RouteSpec {
+        method,
+        pattern,
+        path,
+        body,
+    }
+}
+
+/// Every route registered by `v1_routes`. Keep the two in sync: the multi-user isolation
+/// test walks this list and checks that one user can never read or change another's rows.
+pub const ROUTES: &[RouteSpec] = &[
+    route(Method::GET, "/v1/contacts", &[], &[]),
+    route(Method::GET, "/v1/contacts/checksum", &[], &[]),
+    route(Method::GET, "/v1/contacts/lookup", &[], &[]),
+    route(Method::GET, "/v1/contacts/autocomplete", &[], &[]),
+    route(Method::GET, "/v1/contacts/nearby", &[], &[]),
+    route(Method::GET, "/v1/contacts/declining", &[], &[]),
+    route(Method::GET, "/v1/contacts/{id}", &[Resource::Contact], &[]),
+    route(
+        Method::GET,
+        "/v1/contacts/{id}/interactions",
+        &[Resource::Contact],
+        &[],
+    ),
+    route(
+        Method::GET,
+        "/v1/contacts/{id}/history",
+        &[Resource::Contact],
+        &[],
+    ),
+    route(
+        Method::GET,
+        "/v1/contacts/{id}/introductions",
+        &[Resource::Contact],
+        &[],
+    ),
+    route(
+        Method::GET,
+        "/v1/contacts/{id}/share-links",
+        &[Resource::Contact],
+        &[],
+    ),
+    route(
+        Method::POST,
+        "/v1/contacts/{id}/share",
+        &[Resource::Contact],
+        &[],
+    ),
+    route(
+        Method::DELETE,
+        "/v1/share-links/{id}",
+        &[Resource::ContactLink],
+        &[],
+    ),
+    route(Method::POST, "/v1/contacts", &[], &[]),
+    route(Method::POST, "/v1/contacts/bulk", &[], &[]),
+    route(Method::POST, "/v1/contacts/import/json", &[], &[]),
+    route(Method::POST, "/v1/quick-add", &[], &[]),
+    route(
+        Method::PATCH,
+        "/v1/contacts/{id}",
+        &[Resource::Contact],
+        &[],
+    ),
+    route(
+        Method::DELETE,
+        "/v1/contacts/{id}",
+        &[Resource::Contact],
+        &[],
+    ),
+    route(
+        Method::POST,
+        "/v1/contacts/{id}/archive",
+        &[Resource::Contact],
+        &[],
+    ),
+    route(
+        Method::POST,
+        "/v1/contacts/{id}/unarchive",
+        &[Resource::Contact],
+        &[],
+    ),
+    route(
+        Method::POST,
+        "/v1/contacts/{id}/enrich",
+        &[Resource::Contact],
+        &[],
+    ),
+    route(Method::POST, "/v1/tags", &[], &[]),
+    route(Method::DELETE, "/v1/tags/unused", &[], &[]),
+    route(Method::DELETE, "/v1/tags/{id}", &[Resource::Tag], &[]),
+    route(Method::PATCH, "/v1/tags/{id}", &[Resource::Tag], &[]),
+    route(Method::GET, "/v1/tags", &[], &[]),
+    route(Method::GET, "/v1/tags/palette", &[], &[]),
+    route(Method::GET, "/v1/tags/export", &[], &[]),
+    route(Method::POST, "/v1/tags/import", &[], &[]),
+    route(
+        Method::POST,
+        "/v1/contacts/{contact_id}/tags/{tag_id}",
+        &[Resource::Contact, Resource::Tag],
+        &[],
+    ),
+    route(
+        Method::DELETE,
+        "/v1/contacts/{contact_id}/tags/{tag_id}",
+        &[Resource::Contact, Resource::Tag],
+        &[],
+    ),
+    route(
+        Method::POST,
+        "/v1/tags/{tag_id}/contacts/bulk",
+        &[Resource::Tag],
+        &[Resource::Contact],
+    ),
+    route(
+        Method::POST,
+        "/v1/contacts/bulk-delete",
+        &[],
+        &[Resource::Contact],
+    ),
+    // The interaction's contact, then another participant
+    route(
+        Method::POST,
+        "/v1/interactions",
+        &[],
+        &[Resource::Contact, Resource::Contact],
+    ),
+    route(Method::GET, "/v1/interactions/search", &[], &[]),
+    route(
+        Method::DELETE,
+        "/v1/interactions/{id}",
+        &[Resource::Interaction],
+        &[],
+    ),
+    route(
+        Method::PATCH,
+        "/v1/interactions/{id}",
+        &[Resource::Interaction],
+        &[Resource::Contact, Resource::Contact],
+    ),
+    route(
+        Method::POST,
+        "/v1/interactions/{interaction_id}/tags/{tag_id}",
+        &[Resource::Interaction, Resource::Tag],
+        &[],
+    ),
+    route(
+        Method::DELETE,
+        "/v1/interactions/{interaction_id}/tags/{tag_id}",
+        &[Resource::Interaction, Resource::Tag],
+        &[],
+    ),
+    route(
+        Method::GET,
+        "/v1/interactions/{id}/attachments",
+        &[Resource::Interaction],
+        &[],
+    ),
+    route(
+        Method::POST,
+        "/v1/interactions/{id}/attachments",
+        &[Resource::Interaction],
+        &[],
+    ),
+    route(
+        Method::GET,
+        "/v1/attachments/{id}",
+        &[Resource::Attachment],
+        &[],
+    ),
+    route(
+        Method::DELETE,
+        "/v1/attachments/{id}",
+        &[Resource::Attachment],
+        &[],
+    ),
+    route(Method::GET, "/v1/inbox", &[], &[]),
+    route(Method::POST, "/v1/inbox", &[], &[]),
+    // The interaction's contact, then another participant
+    route(
+        Method::POST,
+        "/v1/inbox/{id}/resolve",
+        &[Resource::Capture],
+        &[Resource::Contact, Resource::Contact],
+    ),
+    route(Method::DELETE, "/v1/inbox/{id}", &[Resource::Capture], &[]),
+    #[cfg(feature = "capture-parsing")]
+    route(
+        Method::GET,
+        "/v1/inbox/{id}/suggestion",
+        &[Resource::Capture],
+        &[],
+    ),
+    route(Method::GET, "/v1/interaction-types", &[], &[]),
+    route(Method::POST, "/v1/interaction-types", &[], &[]),
+    route(
+        Method::DELETE,
+        "/v1/interaction-types/{id}",
+        &[Resource::InteractionType],
+        &[],
+    ),
+    route(Method::POST, "/v1/occasions", &[], &[Resource::Contact]),
+    route(
+        Method::DELETE,
+        "/v1/occasions/{id}",
+        &[Resource::Occasion],
+        &[],
+    ),
+    route(
+        Method::PATCH,
+        "/v1/occasions/{id}",
+        &[Resource::Occasion],
+        &[Resource::Contact],
+    ),
+    route(Method::GET, "/v1/unassigned", &[], &[]),
+    route(
+        Method::GET,
+        "/v1/contacts/{id}/social-profiles",
+        &[Resource::Contact],
+        &[],
+    ),
+    route(
+        Method::POST,
+        "/v1/social-profiles",
+        &[],
+        &[Resource::Contact],
+    ),
+    route(
+        Method::PATCH,
+        "/v1/social-profiles/{id}",
+        &[Resource::SocialProfile],
+        &[Resource::Contact],
+    ),
+    route(
+        Method::DELETE,
+        "/v1/social-profiles/{id}",
+        &[Resource::SocialProfile],
+        &[],
+    ),
+    route(
+        Method::GET,
+        "/v1/contacts/{id}/gift-ideas",
+        &[Resource::Contact],
+        &[],
+    ),
+    route(
+        Method::POST,
+        "/v1/gift-ideas",
+        &[],
+        &[Resource::Contact, Resource::Occasion],
+    ),
+    route(
+        Method::PATCH,
+        "/v1/gift-ideas/{id}",
+        &[Resource::GiftIdea],
+        &[Resource::Contact, Resource::Occasion],
+    ),
+    route(
+        Method::DELETE,
+        "/v1/gift-ideas/{id}",
+        &[Resource::GiftIdea],
+        &[],
+    ),
+    route(Method::GET, "/v1/groups", &[], &[]),
+    route(Method::POST, "/v1/groups", &[], &[]),
+    route(Method::GET, "/v1/groups/{id}", &[Resource::Group], &[]),
+    route(Method::PATCH, "/v1/groups/{id}", &[Resource::Group], &[]),
+    route(Method::DELETE, "/v1/groups/{id}", &[Resource::Group], &[]),
+    route(
+        Method::GET,
+        "/v1/groups/{id}/contacts",
+        &[Resource::Group],
+        &[],
+    ),
+    route(
+        Method::PUT,
+        "/v1/groups/{id}/contacts",
+        &[Resource::Group],
+        &[Resource::Contact],
+    ),
+    route(
+        Method::POST,
+        "/v1/groups/{group_id}/contacts/{contact_id}",
+        &[Resource::Group, Resource::Contact],
+        &[],
+    ),
+    route(
+        Method::DELETE,
+        "/v1/groups/{group_id}/contacts/{contact_id}",
+        &[Resource::Group, Resource::Contact],
+        &[],
+    ),
+    route(
+        Method::POST,
+        "/v1/groups/{id}/interactions",
+        &[Resource::Group],
+        &[],
+    ),
+    route(
+        Method::POST,
+        "/v1/groups/{id}/digest",
+        &[Resource::Group],
+        &[],
+    ),
+    route(Method::GET, "/v1/filters", &[], &[]),
+    route(Method::POST, "/v1/filters", &[], &[Resource::Tag]),
+    route(
+        Method::PATCH,
+        "/v1/filters/{id}",
+        &[Resource::Filter],
+        &[Resource::Tag],
+    ),
+    route(
+        Method::GET,
+        "/v1/filters/{id}/contacts",
+        &[Resource::Filter],
+        &[],
+    ),
+    route(Method::DELETE, "/v1/filters/{id}", &[Resource::Filter], &[]),
+    route(Method::GET, "/v1/goals", &[], &[]),
+    route(Method::POST, "/v1/goals", &[], &[Resource::Contact]),
+    route(Method::GET, "/v1/goals/progress", &[], &[]),
+    route(
+        Method::PATCH,
+        "/v1/goals/{id}",
+        &[Resource::Goal],
+        &[Resource::Contact],
+    ),
+    route(Method::DELETE, "/v1/goals/{id}", &[Resource::Goal], &[]),
+    route(Method::GET, "/v1/reconnect/this-week", &[], &[]),
+    route(
+        Method::POST,
+        "/v1/contacts/{id}/snooze",
+        &[Resource::Contact],
+        &[],
+    ),
+    route(
+        Method::POST,
+        "/v1/contacts/{id}/dismiss-suggestion",
+        &[Resource::Contact],
+        &[],
+    ),
+    route(
+        Method::DELETE,
+        "/v1/contacts/{id}/snooze",
+        &[Resource::Contact],
+        &[],
+    ),
+    route(Method::GET, "/v1/preferences", &[], &[]),
+    route(Method::PUT, "/v1/preferences", &[], &[Resource::Tag]),
+    route(Method::GET, "/v1/reminders/upcoming", &[], &[]),
+    route(Method::GET, "/v1/review/weekly", &[], &[]),
+    route(Method::GET, "/v1/push/vapid-public-key", &[], &[]),
+    route(Method::GET, "/v1/push/subscriptions", &[], &[]),
+    route(Method::POST, "/v1/push/subscriptions", &[], &[]),
+    route(
+        Method::DELETE,
+        "/v1/push/subscriptions/{id}",
+        &[Resource::PushSubscription],
+        &[],
+    ),
+    route(Method::GET, "/v1/stats/timeseries", &[], &[]),
+    route(Method::GET, "/v1/export", &[], &[]),
+    // PUT first so the GET and DELETE that follow find a schedule
+    route(Method::PUT, "/v1/export/schedule", &[], &[]),
+    route(Method::GET, "/v1/export/schedule", &[], &[]),
+    route(Method::DELETE, "/v1/export/schedule", &[], &[]),
+    // PUT first, like the export schedule
+    route(Method::PUT, "/v1/inbound-email", &[], &[]),
+    route(Method::GET, "/v1/inbound-email", &[], &[]),
+    route(Method::DELETE, "/v1/inbound-email", &[], &[]),
+    route(Method::GET, "/v1/hooks", &[], &[]),
+    route(Method::POST, "/v1/hooks", &[], &[]),
+    route(
+        Method::GET,
+        "/v1/webhooks/{id}/deliveries",
+        &[Resource::RestHook],
+        &[],
+    ),
+    route(Method::DELETE, "/v1/hooks/{id}", &[Resource::RestHook], &[]),
+    route(Method::GET, "/v1/triggers/new-contact", &[], &[]),
+    route(Method::GET, "/v1/triggers/new-interaction", &[], &[]),
+    route(Method::GET, "/v1/triggers/upcoming-occasion", &[], &[]),
+    route(Method::GET, "/v1/me", &[], &[]),
+    route(Method::PATCH, "/v1/me", &[], &[]),
+    route(Method::GET, "/v1/shares", &[], &[]),
+    route(Method::POST, "/v1/shares", &[], &[Resource::Tag]),
+    route(Method::GET, "/v1/shares/contacts", &[], &[]),
+    route(Method::DELETE, "/v1/shares/{id}", &[Resource::Share], &[]),
+    route(Method::GET, "/v1/workspaces", &[], &[]),
+    route(Method::POST, "/v1/workspaces", &[], &[]),
+    route(
+        Method::PATCH,
+        "/v1/workspaces/{id}",
+        &[Resource::Workspace],
+        &[],
+    ),
+    route(
+        Method::DELETE,
+        "/v1/workspaces/{id}",
+        &[Resource::Workspace],
+        &[],
+    ),
+    route(Method::GET, "/v1/events", &[], &[]),
+    route(Method::DELETE, "/v1/account", &[], &[]),
+    route(Method::POST, "/v1/account/restore", &[], &[]),
+    route(Method::GET, "/v1/account/usage", &[], &[]),
+];
+
+/// Routes served under /v1 (and, via the compatibility layer, unversioned paths)
+pub fn v1_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(contacts::list_contacts)
+        // Registered before /contacts/{id} so "checksum", "lookup", "autocomplete" and "nearby"
+        // are not taken for ids
+        .service(contacts::contacts_checksum)
+        .service(contacts::lookup_contacts)
+        .service(contacts::autocomplete_contacts)
+        .service(contacts::nearby_contacts)
+        .service(health::declining_contacts)
+        .service(contacts::get_contact)
+        .service(interactions::contact_interactions)
+        .service(history::contact_history)
+        .service(contacts::contact_introductions)
+        .service(contact_links::list_contact_links)
+        .service(contact_links::create_contact_link)
+        .service(contact_links::delete_contact_link)
+        .service(contacts::create_contact)
+        .service(contacts::create_contacts_bulk)
+        .service(import::import_json)
+        .service(quick_add::quick_add)
+        // Before update_contact, which takes any other body
+        .service(contacts::patch_contact)
+        .service(contacts::update_contact)
+        .service(contacts::delete_contact)
+        .service(contacts::archive_contact)
+        .service(contacts::unarchive_contact)
+        .service(enrichment::enrich_contact)
+        .service(tags::create_tag)
+        .service(tags::tag_palette)
+        .service(tags::export_tags)
+        .service(tags::import_tags)
+        .service(tags::delete_unused_tags)
+        .service(tags::delete_tag)
+        .service(tags::update_tag)
+        .service(tags::list_tags)
+        .service(tags::add_tag_to_contact)
+        .service(tags::remove_tag_from_contact)
+        .service(tags::bulk_add_tag_to_contacts)
+        .service(contacts::bulk_delete_contacts)
+        .service(interactions::create_interaction)
+        .service(interactions::delete_interaction)
+        .service(interactions::update_interaction)
+        .service(interactions::search_notes)
+        .service(tags::add_tag_to_interaction)
+        .service(tags::remove_tag_from_interaction)
+        .service(attachments::list_attachments)
+        .service(attachments::upload_attachment)
+        .service(attachments::download_attachment)
+        .service(attachments::delete_attachment)
+        .service(inbox::list_captures)
+        .service(inbox::create_capture)
+        .service(inbox::resolve_capture)
+        .service(inbox::delete_capture)
+        .service(interactions::list_interaction_types)
+        .service(interactions::create_interaction_type)
+        .service(interactions::delete_interaction_type)
+        .service(occasions::create_occasion)
+        .service(occasions::delete_occasion)
+        .service(occasions::update_occasion)
+        .service(deletion::list_unassigned)
+        .service(social_profiles::list_social_profiles)
+        .service(social_profiles::create_social_profile)
+        .service(social_profiles::update_social_profile)
+        .service(social_profiles::delete_social_profile)
+        .service(gift_ideas::list_gift_ideas)
+        .service(gift_ideas::create_gift_idea)
+        .service(gift_ideas::update_gift_idea)
+        .service(gift_ideas::delete_gift_idea)
+        .service(groups::list_groups)
+        .service(groups::create_group)
+        .service(groups::get_group)
+        .service(groups::update_group)
+        .service(groups::delete_group)
+        .service(groups::group_contacts)
+        .service(groups::set_group_contacts)
+        .service(groups::add_group_contact)
+        .service(groups::remove_group_contact)
+        .service(groups::log_group_interaction)
+        .service(groups::send_group_digest)
+        .service(filters::list_filters)
+        .service(filters::create_filter)
+        .service(filters::update_filter)
+        .service(filters::filter_contacts)
+        .service(filters::delete_filter)
+        .service(goals::list_goals)
+        .service(goals::create_goal)
+        .service(goals::goals_progress)
+        .service(goals::update_goal)
+        .service(goals::delete_goal)
+        .service(reconnect::reconnect_this_week)
+        .service(reconnect::snooze_contact)
+        .service(reconnect::dismiss_suggestion)
+        .service(reconnect::unsnooze_contact)
+        .service(preferences::get_preferences)
+        .service(preferences::update_preferences)
+        .service(reminders::upcoming_reminders)
+        .service(review::get_weekly_review)
+        .service(push::vapid_public_key)
+        .service(push::list_subscriptions)
+        .service(push::subscribe)
+        .service(push::unsubscribe)
+        .service(stats::get_timeseries)
+        .service(export::export_archive)
+        .service(export::get_export_schedule)
+        .service(export::update_export_schedule)
+        .service(export::delete_export_schedule)
+        .service(inbound_email::get_inbound_email)
+        .service(inbound_email::update_inbound_email)
+        .service(inbound_email::delete_inbound_email)
+        .service(zapier::list_hooks)
+        .service(zapier::subscribe_hook)
+        .service(zapier::unsubscribe_hook)
+        .service(outbox::list_deliveries)
+        .service(zapier::new_contact_trigger)
+        .service(zapier::new_interaction_trigger)
+        .service(zapier::upcoming_occasion_trigger)
+        .service(account::get_me)
+        .service(account::update_me)
+        .service(shares::list_shares)
+        .service(shares::create_share)
+        .service(shares::shared_contacts)
+        .service(shares::delete_share)
+        .service(workspaces::list_workspaces)
+        .service(workspaces::create_workspace)
+        .service(workspaces::rename_workspace)
+        .service(workspaces::delete_workspace)
+        .service(events::event_stream)
+        .service(account::delete_account)
+        .service(account::restore_account)
+        .service(quotas::get_usage);
+    #[cfg(feature = "capture-parsing")]
+    cfg.service(capture_parsing::suggest_resolution);
+}
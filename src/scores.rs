@@ -0,0 +1,46 @@
+//! Background refresh of stored contact priorities.
+//!
+//! Listing contacts by priority reads each contact's score from `contact_scores` when it is
+//! still current and computes it on the spot otherwise. This task keeps the table current so
+//! those reads rarely have to recompute over a contact's interactions and occasions.
+
+use crate::jobs::Job;
+use crate::repository::{RepoResult, Repository};
+use async_trait::async_trait;
+use std::time::Duration;
+
+/// How often the background task refreshes outdated scores
+pub const REFRESH_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Scores stored per statement, so one refresh never holds a long transaction
+const BATCH_SIZE: i64 = 500;
+
+/// Store every missing or outdated score, returning how many were stored
+pub async fn refresh_all(repo: &dyn Repository) -> RepoResult<u64> {
+    let mut total = 0;
+    loop {
+        let stored = repo.refresh_contact_scores(BATCH_SIZE).await?;
+        total += stored;
+        if stored < BATCH_SIZE as u64 {
+            return Ok(total);
+        }
+    }
+}
+
+/// Refreshes scores every `REFRESH_INTERVAL`
+pub struct RefreshScores;
+
+#[async_trait]
+impl Job for RefreshScores {
+    fn name(&self) -> &'static str {
+        "refresh-scores"
+    }
+
+    fn interval(&self) -> Duration {
+        REFRESH_INTERVAL
+    }
+
+    async fn run(&self, repo: &dyn Repository) -> RepoResult<()> {
+        refresh_all(repo).await.map(|_| ())
+    }
+}
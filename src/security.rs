@@ -0,0 +1,80 @@
+use crate::idempotency::{IDEMPOTENCY_KEY, IDEMPOTENT_REPLAYED};
+use actix_cors::Cors;
+use actix_web::http::{Method, header};
+use actix_web::middleware::DefaultHeaders;
+
+/// Build the CORS layer from the environment.
+/// CORS_ALLOWED_ORIGINS is a comma separated list of origins (or `*` for any origin);
+/// when unset, cross-origin requests are refused. CORS_MAX_AGE sets the preflight cache
+/// lifetime in seconds (default 3600).
+pub fn cors_from_env() -> Cors {
+    let origins = std::env::var("CORS_ALLOWED_ORIGINS").unwrap_or_default();
+    let max_age = std::env::var("CORS_MAX_AGE")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(3600);
+
+    cors(&origins, max_age)
+}
+
+/// Build the CORS layer for the given comma separated origin list
+pub fn cors(origins: &str, max_age: usize) -> Cors {
+    let mut cors = Cors::default()
+        .allowed_methods([
+            Method::GET,
+            Method::POST,
+            Method::PUT,
+            Method::PATCH,
+            Method::DELETE,
+        ])
+        .allowed_headers([
+            header::AUTHORIZATION,
+            header::CONTENT_TYPE,
+            header::ACCEPT,
+            header::HeaderName::from_static("api-version"),
+            header::HeaderName::from_static("x-workspace"),
+            header::IF_NONE_MATCH,
+            header::IF_MODIFIED_SINCE,
+            IDEMPOTENCY_KEY,
+        ])
+        .expose_headers([
+            header::RETRY_AFTER,
+            header::HeaderName::from_static("api-version"),
+            header::HeaderName::from_static("ratelimit-limit"),
+            header::HeaderName::from_static("ratelimit-remaining"),
+            header::HeaderName::from_static("ratelimit-reset"),
+            header::HeaderName::from_static("deprecation"),
+            header::HeaderName::from_static("sunset"),
+            header::ETAG,
+            header::LAST_MODIFIED,
+            IDEMPOTENT_REPLAYED,
+            header::LINK,
+        ])
+        .max_age(max_age);
+
+    for origin in origins.split(',').map(str::trim).filter(|o| !o.is_empty()) {
+        cors = if origin == "*" {
+            cors.allow_any_origin()
+        } else {
+            cors.allowed_origin(origin)
+        };
+    }
+
+    cors
+}
+
+/// Standard security headers for a JSON API that is never meant to be framed or sniffed
+pub fn security_headers() -> DefaultHeaders {
+    DefaultHeaders::new()
+        .add((header::X_CONTENT_TYPE_OPTIONS, "nosniff"))
+        .add((header::X_FRAME_OPTIONS, "DENY"))
+        .add((header::REFERRER_POLICY, "no-referrer"))
+        .add((
+            header::STRICT_TRANSPORT_SECURITY,
+            "max-age=63072000; includeSubDomains",
+        ))
+        .add((
+            header::CONTENT_SECURITY_POLICY,
+            "default-src 'none'; frame-ancestors 'none'",
+        ))
+}
@@ -0,0 +1,307 @@
+//! Fake data for frontend development and trying out the list endpoints (enabled with the
+//! `seed` feature).
+//!
+//! Fills a user's default workspace with contacts carrying tags, a history of interactions and
+//! birthdays or anniversaries, all written through the `Repository` like the API would. The
+//! data comes from a small seeded generator, so the same seed gives the same contacts, with
+//! dates placed relative to the day of the run.
+
+use crate::interactions::BUILTIN_INTERACTION_TYPES;
+use crate::models::{
+    NewContactRequest, NewInteractionRequest, NewOccasionRequest, NewTagRequest, OccasionType,
+};
+use crate::repository::{RepoResult, Repository};
+use time::{Date, Duration, Month, OffsetDateTime};
+
+const FIRST_NAMES: &[&str] = &[
+    "Ada",
+    "Grace",
+    "Linus",
+    "Margaret",
+    "Alan",
+    "Katherine",
+    "Dennis",
+    "Barbara",
+    "Edsger",
+    "Frances",
+    "Ken",
+    "Radia",
+    "Tim",
+    "Hedy",
+    "Donald",
+    "Annie",
+    "John",
+    "Sophie",
+    "Niklaus",
+    "Joan",
+];
+
+const LAST_NAMES: &[&str] = &[
+    "Lovelace",
+    "Hopper",
+    "Torvalds",
+    "Hamilton",
+    "Turing",
+    "Johnson",
+    "Ritchie",
+    "Liskov",
+    "Dijkstra",
+    "Allen",
+    "Thompson",
+    "Perlman",
+    "Berners-Lee",
+    "Lamarr",
+    "Knuth",
+    "Easley",
+    "Backus",
+    "Wilson",
+    "Wirth",
+    "Clarke",
+];
+
+/// Tag names with their colors
+const TAGS: &[(&str, &str)] = &[
+    ("Family", "#e11d48"),
+    ("Friends", "#2563eb"),
+    ("Work", "#16a34a"),
+    ("College", "#9333ea"),
+    ("Neighbors", "#ea580c"),
+    ("Book club", "#0891b2"),
+];
+
+const NOTES: &[&str] = &[
+    "Caught up over coffee",
+    "Talked about their new job",
+    "Planned a trip together",
+    "Wished them luck with the move",
+    "Quick check-in",
+    "Long call about the kids",
+    "Traded book recommendations",
+];
+
+const SHORT_NOTES: &[&str] = &[
+    "Met at a conference",
+    "Old school friend",
+    "Plays in the Sunday league",
+    "Introduced by a colleague",
+];
+
+#[derive(Debug, Clone)]
+pub struct SeedConfig {
+    /// Email of the user to seed, who must have signed in once
+    pub email: String,
+    pub contacts: usize,
+    pub seed: u64,
+}
+
+impl SeedConfig {
+    /// Parse `--email E [--contacts N] [--seed S]`
+    pub fn from_args<I: Iterator<Item = String>>(mut args: I) -> Result<SeedConfig, String> {
+        let mut email = None;
+        let mut config = SeedConfig {
+            email: String::new(),
+            contacts: 50,
+            seed: 1,
+        };
+        while let Some(arg) = args.next() {
+            let value = args
+                .next()
+                .ok_or_else(|| format!("{} expects a value", arg))?;
+            match arg.as_str() {
+                "--email" => email = Some(value),
+                "--contacts" => {
+                    config.contacts = value
+                        .parse()
+                        .map_err(|_| "--contacts expects a number".to_string())?
+                }
+                "--seed" => {
+                    config.seed = value
+                        .parse()
+                        .map_err(|_| "--seed expects a number".to_string())?
+                }
+                other => return Err(format!("Unknown argument: {}", other)),
+            }
+        }
+        config.email = email.ok_or_else(|| "--email is required".to_string())?;
+        Ok(config)
+    }
+}
+
+/// SplitMix64: tiny, and stable across releases unlike the generators of the rand crates
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..n`; `n` must not be 0
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    pub fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len())]
+    }
+
+    /// True `percent` times in a hundred
+    pub fn chance(&mut self, percent: usize) -> bool {
+        self.below(100) < percent
+    }
+}
+
+/// What a seed run created
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SeedReport {
+    pub contacts: usize,
+    pub interactions: usize,
+    pub occasions: usize,
+}
+
+fn fake_contact(rng: &mut Rng, seed: u64, n: usize) -> NewContactRequest {
+    let first = *rng.pick(FIRST_NAMES);
+    let last = *rng.pick(LAST_NAMES);
+    NewContactRequest {
+        first_name: Some(first.to_string()),
+        last_name: Some(last.to_string()),
+        // The seed and index keep emails unique across contacts and runs with other seeds
+        email: Some(format!(
+            "{}.{}.{}-{}@example.com",
+            first.to_lowercase(),
+            last.to_lowercase(),
+            seed,
+            n
+        )),
+        phone: rng
+            .chance(60)
+            .then(|| format!("+1 555 {:03} {:04}", rng.below(1000), rng.below(10_000))),
+        short_note: rng.chance(30).then(|| rng.pick(SHORT_NOTES).to_string()),
+        desired_frequency_days: rng.chance(25).then(|| *rng.pick(&[7, 14, 30, 90])),
+        ..Default::default()
+    }
+}
+
+/// A day of the month that exists in every month
+fn fake_date(rng: &mut Rng, year: i32) -> Date {
+    let month = Month::try_from(rng.below(12) as u8 + 1).unwrap_or(Month::January);
+    Date::from_calendar_date(year, month, rng.below(28) as u8 + 1)
+        .expect("days up to 28 exist in every month")
+}
+
+/// Add `config.contacts` fake contacts with tags, interactions and occasions to the workspace.
+/// The tags are created unless the workspace already has tags by those names.
+pub async fn seed(
+    repo: &dyn Repository,
+    workspace_id: i32,
+    config: &SeedConfig,
+) -> RepoResult<SeedReport> {
+    let mut rng = Rng::new(config.seed);
+    let mut report = SeedReport::default();
+
+    let existing = repo.list_tags(workspace_id).await?;
+    let mut tag_ids = Vec::with_capacity(TAGS.len());
+    for (name, color) in TAGS {
+        let tag_id = match existing.iter().find(|t| t.name == *name) {
+            Some(tag) => tag.tag_id,
+            None => {
+                repo.create_tag(
+                    workspace_id,
+                    &NewTagRequest {
+                        name: name.to_string(),
+                        color: Some(color.to_string()),
+                        details: None,
+                    },
+                )
+                .await?
+            }
+        };
+        tag_ids.push(tag_id);
+    }
+
+    let contacts: Vec<NewContactRequest> = (0..config.contacts)
+        .map(|n| fake_contact(&mut rng, config.seed, n))
+        .collect();
+    let contact_ids = repo
+        .create_contacts(workspace_id, &contacts, true)
+        .await?
+        .into_iter()
+        .collect::<RepoResult<Vec<i32>>>()?;
+    report.contacts = contact_ids.len();
+
+    let today = OffsetDateTime::now_utc().date();
+    for contact_id in contact_ids {
+        for _ in 0..rng.below(3) {
+            repo.add_tag_to_contact(contact_id, *rng.pick(&tag_ids))
+                .await?;
+        }
+
+        for _ in 0..rng.below(9) {
+            let days_ago = rng.below(730) as i64;
+            let interaction_date =
+                (today - Duration::days(days_ago)).midnight().assume_utc() + Duration::hours(18);
+            repo.create_interaction(
+                workspace_id,
+                &NewInteractionRequest {
+                    contact_id,
+                    interaction_date,
+                    notes: rng.chance(70).then(|| rng.pick(NOTES).to_string()),
+                    follow_up_priority: None,
+                    interaction_type: Some(rng.pick(BUILTIN_INTERACTION_TYPES).to_string()),
+                    contact_ids: Vec::new(),
+                    follow_up_in_days: None,
+                },
+                None,
+            )
+            .await?;
+            report.interactions += 1;
+        }
+
+        let mut occasions = Vec::new();
+        if rng.chance(70) {
+            let year = today.year() - 20 - rng.below(50) as i32;
+            occasions.push((
+                "Birthday",
+                OccasionType::Birthday,
+                fake_date(&mut rng, year),
+                rng.chance(60).then_some(year),
+            ));
+        }
+        if rng.chance(15) {
+            let year = today.year() - 1 - rng.below(30) as i32;
+            occasions.push((
+                "Anniversary",
+                OccasionType::Anniversary,
+                fake_date(&mut rng, year),
+                None,
+            ));
+        }
+        for (name, occasion_type, date, birth_year) in occasions {
+            repo.create_occasion(
+                workspace_id,
+                &NewOccasionRequest {
+                    contact_id,
+                    name: name.to_string(),
+                    date,
+                    recurring: true,
+                    recurring_interval: None,
+                    details: None,
+                    occasion_type,
+                    birth_year,
+                    remind_days_before: None,
+                },
+            )
+            .await?;
+            report.occasions += 1;
+        }
+    }
+
+    Ok(report)
+}
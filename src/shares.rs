@@ -0,0 +1,209 @@
+//! Sharing contacts with other users, by tag or one contact at a time
+
+use crate::AuthUser;
+use crate::avatar::GravatarResolver;
+use crate::events::{self, Action, Entity, EventBus};
+use crate::models::{
+    ContactResponse, ContactShare, NewShareRequest, SharedContact, SharesResponse,
+};
+use crate::mutations;
+use crate::repository::{RepoResult, Repository};
+use crate::routes::{Resource, ensure_owned};
+use crate::timezone::LocalDates;
+use actix_web::{HttpRequest, HttpResponse, Responder, delete, get, post, web};
+
+/// A share names a tag or some contacts, never both. Errors are the JSON body of the 400
+/// response.
+fn validate(share: &NewShareRequest) -> Result<(), serde_json::Value> {
+    if share.tag_id.is_some() != share.contact_ids.is_empty() {
+        return Err(serde_json::json!({
+            "error": "Give either tag_id or contact_ids"
+        }));
+    }
+    Ok(())
+}
+
+#[get("/shares")]
+pub async fn list_shares(repo: web::Data<dyn Repository>, auth_user: AuthUser) -> impl Responder {
+    match repo.list_shares(auth_user.user_id).await {
+        Ok(shares) => {
+            let (given, received) = shares
+                .into_iter()
+                .partition(|share| share.owner_id == auth_user.user_id);
+            HttpResponse::Ok().json(SharesResponse { given, received })
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to fetch shares")
+        }
+    }
+}
+
+/// Share a tag's contacts, or the listed contacts, with the user registered under `email`.
+/// Sharing the same tag or contact again changes the permission.
+#[post("/shares")]
+pub async fn create_share(
+    req: HttpRequest,
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    auth_user: AuthUser,
+    new_share: web::Json<NewShareRequest>,
+) -> impl Responder {
+    if let Err(error) = validate(&new_share) {
+        return HttpResponse::BadRequest().json(error);
+    }
+    if let Some(tag_id) = new_share.tag_id
+        && let Err(response) = ensure_owned(repo.get_ref(), &auth_user, Resource::Tag, tag_id).await
+    {
+        return response;
+    }
+    for contact_id in &new_share.contact_ids {
+        if let Err(response) =
+            ensure_owned(repo.get_ref(), &auth_user, Resource::Contact, *contact_id).await
+        {
+            return response;
+        }
+    }
+
+    let grantee_id = match repo.find_user_by_email(&new_share.email).await {
+        Ok(Some(grantee_id)) => grantee_id,
+        Ok(None) => return HttpResponse::NotFound().body("User not found"),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            return HttpResponse::InternalServerError().body("Failed to create share");
+        }
+    };
+    if grantee_id == auth_user.user_id {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Contacts cannot be shared with yourself"
+        }));
+    }
+
+    match repo
+        .create_shares(
+            auth_user.user_id,
+            grantee_id,
+            new_share.tag_id,
+            &new_share.contact_ids,
+            new_share.permission,
+        )
+        .await
+    {
+        Ok(share_ids) => {
+            for user_id in [auth_user.user_id, grantee_id] {
+                events::publish(
+                    bus.as_ref(),
+                    user_id,
+                    Entity::Share,
+                    Action::Created,
+                    share_ids.clone(),
+                );
+            }
+            let saved = repo.list_shares(auth_user.user_id).await.map(|shares| {
+                shares
+                    .into_iter()
+                    .filter(|s| share_ids.contains(&s.share_id))
+                    .collect::<Vec<_>>()
+            });
+            mutations::created_all(&req, &share_ids, saved)
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to create share")
+        }
+    }
+}
+
+/// Either side can end a share: the owner revokes it, the grantee leaves it
+#[delete("/shares/{id}")]
+pub async fn delete_share(
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    auth_user: AuthUser,
+    share_id: web::Path<i32>,
+) -> impl Responder {
+    let id = share_id.into_inner();
+
+    match repo.delete_share(auth_user.user_id, id).await {
+        Ok(false) => HttpResponse::NotFound().body("Share not found"),
+        Ok(true) => {
+            events::publish(
+                bus.as_ref(),
+                auth_user.user_id,
+                Entity::Share,
+                Action::Deleted,
+                vec![id],
+            );
+            HttpResponse::Ok().body("Share deleted successfully")
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to delete share")
+        }
+    }
+}
+
+/// The shared contacts with their details, loaded workspace by workspace since details are
+/// looked up per workspace. Contacts deleted in the meantime are left out.
+async fn load_shared(
+    repo: &dyn Repository,
+    shares: &[ContactShare],
+    dates: &LocalDates,
+) -> RepoResult<Vec<(ContactShare, ContactResponse)>> {
+    let mut loaded = Vec::with_capacity(shares.len());
+    for workspace in shares.chunk_by(|a, b| a.workspace_id == b.workspace_id) {
+        let contact_ids: Vec<i32> = workspace.iter().map(|s| s.contact_id).collect();
+        let details = repo
+            .contact_details(workspace[0].workspace_id, &contact_ids)
+            .await?;
+        loaded.extend(details.into_iter().filter_map(|d| {
+            let share = workspace
+                .iter()
+                .find(|s| s.contact_id == d.contact.contact_id)?
+                .clone();
+            let contact =
+                ContactResponse::new(d.contact, d.tags, d.interactions, d.occasions, dates);
+            Some((share, contact))
+        }));
+    }
+    Ok(loaded)
+}
+
+/// Contacts other users have shared with the user, with their tags, interactions and occasions
+#[get("/shares/contacts")]
+pub async fn shared_contacts(
+    repo: web::Data<dyn Repository>,
+    gravatar: Option<web::Data<GravatarResolver>>,
+    auth_user: AuthUser,
+) -> impl Responder {
+    let dates = LocalDates::for_user(repo.get_ref(), auth_user.user_id)
+        .await
+        .unwrap_or_default();
+    let loaded = match repo.shared_contacts(auth_user.user_id).await {
+        Ok(shares) => load_shared(repo.get_ref(), &shares, &dates).await,
+        Err(e) => Err(e),
+    };
+    let (shares, mut contacts): (Vec<ContactShare>, Vec<ContactResponse>) = match loaded {
+        Ok(loaded) => loaded.into_iter().unzip(),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            return HttpResponse::InternalServerError().body("Failed to fetch shared contacts");
+        }
+    };
+
+    if let Some(gravatar) = gravatar {
+        gravatar.fill(&mut contacts).await;
+    }
+    let response: Vec<SharedContact> = shares
+        .into_iter()
+        .zip(contacts)
+        .map(|(share, contact)| SharedContact {
+            owner_id: share.owner_id,
+            owner_name: share.owner_name,
+            owner_email: share.owner_email,
+            permission: share.permission,
+            contact,
+        })
+        .collect();
+    HttpResponse::Ok().json(response)
+}
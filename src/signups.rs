@@ -0,0 +1,46 @@
+//! Invite-only registration.
+//!
+//! Any valid Auth0 token normally gets an account on its first request. With SIGNUPS_ENABLED
+//! set to false, only the Auth0 subjects listed in SIGNUP_ALLOWLIST (comma separated) get one;
+//! everyone else is refused with 403. Existing accounts are never affected.
+
+use std::collections::HashSet;
+
+/// Shown to users turned away by invite-only registration
+pub const SIGNUPS_CLOSED: &str =
+    "Registration is invite-only; ask the administrator to approve your account";
+
+#[derive(Debug)]
+pub struct Signups {
+    enabled: bool,
+    allowlist: HashSet<String>,
+}
+
+impl Default for Signups {
+    fn default() -> Self {
+        Signups::new(true, [])
+    }
+}
+
+impl Signups {
+    pub fn new(enabled: bool, allowlist: impl IntoIterator<Item = String>) -> Self {
+        Signups {
+            enabled,
+            allowlist: allowlist.into_iter().filter(|id| !id.is_empty()).collect(),
+        }
+    }
+
+    /// Read SIGNUPS_ENABLED (true/false, default true) and SIGNUP_ALLOWLIST
+    pub fn from_env() -> Self {
+        let allowlist = std::env::var("SIGNUP_ALLOWLIST").unwrap_or_default();
+        Signups::new(
+            !std::env::var("SIGNUPS_ENABLED").is_ok_and(|v| v == "false"),
+            allowlist.split(',').map(|id| id.trim().to_string()),
+        )
+    }
+
+    /// Whether a first request from this Auth0 subject may create an account
+    pub fn allows(&self, auth0_id: &str) -> bool {
+        self.enabled || self.allowlist.contains(auth0_id)
+    }
+}
@@ -0,0 +1,193 @@
+//! Social profile handlers
+
+use crate::AuthUser;
+use crate::events::{self, Action, Entity, EventBus};
+use crate::link_preview::LinkPreviewer;
+use crate::models::{NewSocialProfileRequest, SharePermission};
+use crate::mutations;
+use crate::repository::{self, Repository};
+use crate::routes::{Resource, ensure_contact_access, ensure_owned};
+use actix_web::{HttpRequest, HttpResponse, Responder, delete, get, patch, post, web};
+use reqwest::Url;
+
+/// Longest profile URL accepted
+pub const MAX_URL_LENGTH: usize = 2048;
+
+/// Check the URL. Errors are the JSON body of the 400 response.
+fn validate(profile: &NewSocialProfileRequest) -> Result<(), serde_json::Value> {
+    let valid = profile.url.len() <= MAX_URL_LENGTH
+        && Url::parse(&profile.url)
+            .is_ok_and(|url| matches!(url.scheme(), "http" | "https") && url.host().is_some());
+    if !valid {
+        return Err(serde_json::json!({
+            "error": format!("url must be an http or https URL of up to {} characters", MAX_URL_LENGTH)
+        }));
+    }
+    Ok(())
+}
+
+fn url_taken() -> HttpResponse {
+    HttpResponse::Conflict().json(serde_json::json!({
+        "error": "The contact already has a profile with this URL"
+    }))
+}
+
+/// A contact's social profiles, with link previews when they are enabled
+#[get("/contacts/{id}/social-profiles")]
+pub async fn list_social_profiles(
+    repo: web::Data<dyn Repository>,
+    previewer: Option<web::Data<LinkPreviewer>>,
+    auth_user: AuthUser,
+    contact_id: web::Path<i32>,
+) -> impl Responder {
+    let id = contact_id.into_inner();
+
+    // The user's own contact or one shared with them
+    if let Err(response) =
+        ensure_contact_access(repo.get_ref(), &auth_user, id, SharePermission::Read).await
+    {
+        return response;
+    }
+
+    let mut profiles = match repo.social_profiles_for_contacts(&[id]).await {
+        Ok(profiles) => profiles,
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            return HttpResponse::InternalServerError().body("Failed to fetch social profiles");
+        }
+    };
+    if let Some(previewer) = previewer {
+        previewer.fill(&mut profiles).await;
+    }
+    HttpResponse::Ok().json(profiles)
+}
+
+#[post("/social-profiles")]
+pub async fn create_social_profile(
+    req: HttpRequest,
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    auth_user: AuthUser,
+    new_profile: web::Json<NewSocialProfileRequest>,
+) -> impl Responder {
+    if let Err(error) = validate(&new_profile) {
+        return HttpResponse::BadRequest().json(error);
+    }
+
+    // The contact is the user's own or shared with them for writing; the profile goes in the
+    // contact's workspace
+    let access = match ensure_contact_access(
+        repo.get_ref(),
+        &auth_user,
+        new_profile.contact_id,
+        SharePermission::Write,
+    )
+    .await
+    {
+        Ok(access) => access,
+        Err(response) => return response,
+    };
+
+    match repo
+        .create_social_profile(access.workspace_id, &new_profile)
+        .await
+    {
+        Ok(social_profile_id) => {
+            events::publish(
+                bus.as_ref(),
+                access.owner_id,
+                Entity::SocialProfile,
+                Action::Created,
+                vec![social_profile_id],
+            );
+            mutations::created(
+                &req,
+                social_profile_id,
+                repo.get_social_profile(access.workspace_id, social_profile_id)
+                    .await,
+            )
+        }
+        Err(e) if repository::is_unique_violation(&e) => url_taken(),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to create social profile")
+        }
+    }
+}
+
+#[patch("/social-profiles/{id}")]
+pub async fn update_social_profile(
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    auth_user: AuthUser,
+    social_profile_id: web::Path<i32>,
+    updated_profile: web::Json<NewSocialProfileRequest>,
+) -> impl Responder {
+    let id = social_profile_id.into_inner();
+    if let Err(error) = validate(&updated_profile) {
+        return HttpResponse::BadRequest().json(error);
+    }
+
+    // Verify the profile belongs to the user
+    if let Err(response) =
+        ensure_owned(repo.get_ref(), &auth_user, Resource::SocialProfile, id).await
+    {
+        return response;
+    }
+
+    match repo
+        .update_social_profile(auth_user.workspace_id, id, &updated_profile)
+        .await
+    {
+        Ok(_) => {
+            events::publish(
+                bus.as_ref(),
+                auth_user.user_id,
+                Entity::SocialProfile,
+                Action::Updated,
+                vec![id],
+            );
+            mutations::updated(repo.get_social_profile(auth_user.workspace_id, id).await)
+        }
+        Err(e) if repository::is_unique_violation(&e) => url_taken(),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to update social profile")
+        }
+    }
+}
+
+#[delete("/social-profiles/{id}")]
+pub async fn delete_social_profile(
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    auth_user: AuthUser,
+    social_profile_id: web::Path<i32>,
+) -> impl Responder {
+    let id = social_profile_id.into_inner();
+
+    // Verify the profile belongs to the user
+    if let Err(response) =
+        ensure_owned(repo.get_ref(), &auth_user, Resource::SocialProfile, id).await
+    {
+        return response;
+    }
+
+    match repo.delete_social_profile(auth_user.workspace_id, id).await {
+        Ok(false) => HttpResponse::NotFound().body("Social profile not found"),
+        Ok(true) => {
+            events::publish(
+                bus.as_ref(),
+                auth_user.user_id,
+                Entity::SocialProfile,
+                Action::Deleted,
+                vec![id],
+            );
+            HttpResponse::Ok().body("Social profile deleted successfully")
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to delete social profile")
+        }
+    }
+}
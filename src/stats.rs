@@ -0,0 +1,157 @@
+//! Relationship activity over time, for charts.
+//!
+//! `GET /stats/timeseries?metric=interactions&granularity=week&from=&to=` counts the workspace's
+//! interactions by the day they are dated, or its contacts by the day they were added, per day,
+//! week (Monday to Sunday) or calendar month of the user's timezone. The store does the
+//! counting with `date_trunc`, so a chart doesn't need every interaction. Every bucket from
+//! `from` through `to` is listed, empty ones with a count of 0.
+
+use crate::AuthUser;
+use crate::models::{Granularity, StatsMetric, TimeSeries, TimeSeriesPoint, date_format};
+use crate::reconnect::week_start;
+use crate::repository::Repository;
+use crate::timezone::LocalDates;
+use actix_web::{HttpResponse, Responder, get, web};
+use serde::Deserialize;
+use time::{Date, Duration, Month};
+
+/// Most buckets one request may cover
+pub const MAX_BUCKETS: usize = 400;
+
+/// Earliest and latest years `from` and `to` may be in
+const YEARS: std::ops::RangeInclusive<i32> = 1900..=2999;
+
+/// The first day of the bucket `date` falls in
+pub fn bucket_start(granularity: Granularity, date: Date) -> Date {
+    match granularity {
+        Granularity::Day => date,
+        Granularity::Week => week_start(date),
+        Granularity::Month => date.replace_day(1).expect("every month has a first day"),
+    }
+}
+
+/// The first day of the bucket after the one starting on `start`
+pub fn next_bucket(granularity: Granularity, start: Date) -> Date {
+    match granularity {
+        Granularity::Day => start + Duration::days(1),
+        Granularity::Week => start + Duration::weeks(1),
+        Granularity::Month => {
+            let (year, month) = match start.month() {
+                Month::December => (start.year() + 1, Month::January),
+                month => (start.year(), month.next()),
+            };
+            Date::from_calendar_date(year, month, 1).expect("every month has a first day")
+        }
+    }
+}
+
+/// Buckets covered when `from` is not given, ending with the one `to` is in
+fn default_buckets(granularity: Granularity) -> i64 {
+    match granularity {
+        Granularity::Day => 30,
+        Granularity::Week | Granularity::Month => 12,
+    }
+}
+
+/// Every bucket from the one starting on `from` through the one `to` falls in, with the
+/// `counts` the store found and 0 for the rest
+pub fn fill(
+    granularity: Granularity,
+    from: Date,
+    to: Date,
+    counts: &[(Date, i64)],
+) -> Vec<TimeSeriesPoint> {
+    let mut points = Vec::new();
+    let mut bucket = from;
+    while bucket <= to {
+        let count = counts
+            .iter()
+            .find(|(start, _)| *start == bucket)
+            .map_or(0, |(_, count)| *count);
+        points.push(TimeSeriesPoint { bucket, count });
+        bucket = next_bucket(granularity, bucket);
+    }
+    points
+}
+
+#[derive(Deserialize)]
+struct TimeSeriesQuery {
+    metric: StatsMetric,
+    #[serde(default)]
+    granularity: Granularity,
+    /// First day to count, moved back to the start of its bucket. Defaults to 30 days, 12
+    /// weeks or 12 months back, counting the bucket `to` is in.
+    #[serde(default, with = "date_format::option")]
+    from: Option<Date>,
+    /// Last day to count, default today
+    #[serde(default, with = "date_format::option")]
+    to: Option<Date>,
+}
+
+/// Interactions or new contacts per day, week (the default) or month, in the user's timezone
+#[get("/stats/timeseries")]
+pub async fn get_timeseries(
+    repo: web::Data<dyn Repository>,
+    auth_user: AuthUser,
+    query: web::Query<TimeSeriesQuery>,
+) -> impl Responder {
+    let dates = match LocalDates::for_user(repo.get_ref(), auth_user.user_id).await {
+        Ok(dates) => dates,
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            return HttpResponse::InternalServerError().body("Failed to fetch stats");
+        }
+    };
+    let granularity = query.granularity;
+    let to = query.to.unwrap_or_else(|| dates.today());
+    let from = query.from.unwrap_or_else(|| {
+        (1..default_buckets(granularity)).fold(bucket_start(granularity, to), |start, _| {
+            bucket_start(granularity, start - Duration::days(1))
+        })
+    });
+    if !YEARS.contains(&from.year()) || !YEARS.contains(&to.year()) {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("from and to must be in the years {} to {}", YEARS.start(), YEARS.end())
+        }));
+    }
+    if from > to {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "from must not be after to"
+        }));
+    }
+    let from = bucket_start(granularity, from);
+    let buckets = std::iter::successors(Some(from), |start| Some(next_bucket(granularity, *start)))
+        .take_while(|start| *start <= to)
+        .take(MAX_BUCKETS + 1)
+        .count();
+    if buckets > MAX_BUCKETS {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("from and to may be at most {} buckets apart", MAX_BUCKETS)
+        }));
+    }
+
+    match repo
+        .count_by_period(
+            auth_user.workspace_id,
+            query.metric,
+            granularity,
+            from,
+            to,
+            &dates,
+        )
+        .await
+    {
+        Ok(counts) => HttpResponse::Ok().json(TimeSeries {
+            metric: query.metric,
+            granularity,
+            from,
+            to,
+            timezone: dates.timezone_name().to_string(),
+            points: fill(granularity, from, to, &counts),
+        }),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to fetch stats")
+        }
+    }
+}
@@ -0,0 +1,98 @@
+//! Streamed JSON response bodies.
+//!
+//! A large account's contact list or export serializes to several megabytes. Rather than
+//! building that string per request, the value is serialized on the blocking pool into
+//! `CHUNK_SIZE` pieces that are handed to the client as it reads them, with at most
+//! `CHUNKS_IN_FLIGHT` waiting at a time.
+
+use actix_web::web::Bytes;
+use futures::Stream;
+use serde::Serialize;
+use sha2::Digest;
+use std::io::{self, Write};
+use tokio::sync::mpsc;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+const CHUNKS_IN_FLIGHT: usize = 4;
+
+/// Collects serialized bytes and passes them on a chunk at a time
+struct ChunkWriter {
+    buf: Vec<u8>,
+    tx: mpsc::Sender<io::Result<Bytes>>,
+}
+
+impl ChunkWriter {
+    /// Fails once the response is dropped, which stops serializing for a client that left
+    fn send(&mut self) -> io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let chunk = std::mem::replace(&mut self.buf, Vec::with_capacity(CHUNK_SIZE));
+        self.tx
+            .blocking_send(Ok(Bytes::from(chunk)))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "response was dropped"))
+    }
+}
+
+impl Write for ChunkWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(data);
+        if self.buf.len() >= CHUNK_SIZE {
+            self.send()?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.send()
+    }
+}
+
+/// `value` as a stream of JSON chunks, for `HttpResponseBuilder::streaming`
+pub fn json_stream<T: Serialize + Send + 'static>(
+    value: T,
+) -> impl Stream<Item = io::Result<Bytes>> {
+    let (tx, rx) = mpsc::channel(CHUNKS_IN_FLIGHT);
+    tokio::task::spawn_blocking(move || {
+        let mut writer = ChunkWriter {
+            buf: Vec::with_capacity(CHUNK_SIZE),
+            tx,
+        };
+        let result = serde_json::to_writer(&mut writer, &value)
+            .map_err(io::Error::from)
+            .and_then(|()| writer.flush());
+        match result {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => {}
+            Err(e) => {
+                eprintln!("Failed to serialize response: {:?}", e);
+                // Ends the response early so the client sees a broken body, not a short one
+                let _ = writer.tx.blocking_send(Err(e));
+            }
+        }
+    });
+    futures::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|chunk| (chunk, rx))
+    })
+}
+
+/// Hash of `value`'s JSON, computed without holding the JSON in memory
+pub fn json_digest<T: Serialize, D: Digest>(value: &T) -> serde_json::Result<D> {
+    struct DigestWriter<D>(D);
+
+    impl<D: Digest> Write for DigestWriter<D> {
+        fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+            self.0.update(data);
+            Ok(data.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut writer = DigestWriter(D::new());
+    serde_json::to_writer(&mut writer, value)?;
+    Ok(writer.0)
+}
@@ -0,0 +1,642 @@
+//! Tag handlers, including tagging contacts and interactions
+//!
+//! Tag colors are stored as lowercase `#rrggbb`. A tag saved without a color gets one from
+//! [`PALETTE`], picked from its name so the same name always gets the same color.
+//!
+//! Tags and their assignments can be moved between instances with `GET /tags/export` and
+//! `POST /tags/import`, which find contacts again by email.
+
+use crate::AuthUser;
+use crate::events::{self, Action, Entity, EventBus};
+use crate::limits::{self, Limits};
+use crate::models::{
+    NewTagRequest, TagArchive, TagExport, TagImportReport, TagPalette, TagResponse,
+};
+use crate::mutations;
+use crate::reauth::{self, Reauth};
+use crate::repository::{RepoResult, Repository};
+use crate::routes::{Owned, OwnedContact, OwnedInteraction, OwnedTag, skipped_ids};
+use actix_web::{HttpRequest, HttpResponse, Responder, delete, get, patch, post, web};
+use serde::Deserialize;
+use std::collections::{BTreeSet, HashMap};
+
+/// Colors for tags saved without one
+pub const PALETTE: &[&str] = &[
+    "#e11d48", "#ea580c", "#d97706", "#65a30d", "#16a34a", "#0891b2", "#2563eb", "#7c3aed",
+    "#c026d3", "#4b5563",
+];
+
+/// The palette color for a tag name, ignoring case and surrounding whitespace
+pub fn palette_color(name: &str) -> &'static str {
+    // FNV-1a, which unlike the std hasher gives the same answer across builds
+    let hash = name
+        .trim()
+        .to_lowercase()
+        .bytes()
+        .fold(0x811c_9dc5_u32, |hash, byte| {
+            (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+        });
+    PALETTE[hash as usize % PALETTE.len()]
+}
+
+/// A CSS hex color, `#rgb` or `#rrggbb` in either case, as lowercase `#rrggbb`.
+/// None if it's anything else, such as a color name.
+pub fn normalize_color(color: &str) -> Option<String> {
+    let hex = color.trim().strip_prefix('#')?;
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let hex = hex.to_ascii_lowercase();
+    match hex.len() {
+        3 => Some(hex.chars().fold(String::from("#"), |mut color, c| {
+            color.push(c);
+            color.push(c);
+            color
+        })),
+        6 => Some(format!("#{}", hex)),
+        _ => None,
+    }
+}
+
+/// Normalize the color, or pick one from the palette when there's none.
+/// Errors are the JSON body of the 400 response.
+pub(crate) fn validate(mut tag: NewTagRequest) -> Result<NewTagRequest, serde_json::Value> {
+    tag.color = match tag.color.as_deref().map(str::trim) {
+        None | Some("") => Some(palette_color(&tag.name).to_string()),
+        Some(color) => Some(normalize_color(color).ok_or_else(|| {
+            serde_json::json!({
+                "error": "color must be a CSS hex color such as #2563eb"
+            })
+        })?),
+    };
+    Ok(tag)
+}
+
+#[get("/tags/palette")]
+pub async fn tag_palette(_auth_user: AuthUser) -> impl Responder {
+    HttpResponse::Ok().json(TagPalette {
+        colors: PALETTE.iter().map(|color| color.to_string()).collect(),
+    })
+}
+
+#[post("/tags")]
+pub async fn create_tag(
+    req: HttpRequest,
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    auth_user: AuthUser,
+    new_tag: web::Json<NewTagRequest>,
+) -> impl Responder {
+    let new_tag = match validate(new_tag.into_inner()) {
+        Ok(tag) => tag,
+        Err(error) => return HttpResponse::BadRequest().json(error),
+    };
+
+    match repo.create_tag(auth_user.workspace_id, &new_tag).await {
+        Ok(tag_id) => {
+            events::publish(
+                bus.as_ref(),
+                auth_user.user_id,
+                Entity::Tag,
+                Action::Created,
+                vec![tag_id],
+            );
+            mutations::created(
+                &req,
+                tag_id,
+                repo.get_tag(auth_user.workspace_id, tag_id).await,
+            )
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to create tag")
+        }
+    }
+}
+
+/// Delete every tag on no contact or interaction. Tags shared with someone are kept, since
+/// deleting them would end the share. Needs a recent sign-in.
+#[delete("/tags/unused")]
+pub async fn delete_unused_tags(
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    reauth: Option<web::Data<Reauth>>,
+    auth_user: AuthUser,
+) -> impl Responder {
+    if let Some(response) = reauth::configured(reauth.as_ref()).reject_stale(&auth_user) {
+        return response;
+    }
+    let deleted = match repo.delete_unused_tags(auth_user.workspace_id).await {
+        Ok(deleted) => deleted,
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            return HttpResponse::InternalServerError().body("Failed to delete unused tags");
+        }
+    };
+
+    if !deleted.is_empty() {
+        events::publish(
+            bus.as_ref(),
+            auth_user.user_id,
+            Entity::Tag,
+            Action::Deleted,
+            deleted.clone(),
+        );
+    }
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "deleted_count": deleted.len(),
+        "deleted_ids": deleted,
+        "message": format!("Deleted {} unused tags", deleted.len())
+    }))
+}
+
+#[delete("/tags/{id}")]
+pub async fn delete_tag(
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    auth_user: AuthUser,
+    tag_id: web::Path<i32>,
+) -> impl Responder {
+    let id = tag_id.into_inner();
+
+    match repo.delete_tag(auth_user.workspace_id, id).await {
+        Ok(false) => HttpResponse::NotFound().body("Tag not found"),
+        Ok(true) => {
+            events::publish(
+                bus.as_ref(),
+                auth_user.user_id,
+                Entity::Tag,
+                Action::Deleted,
+                vec![id],
+            );
+            HttpResponse::Ok().body("Tag deleted successfully")
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to delete tag")
+        }
+    }
+}
+
+#[patch("/tags/{id}")]
+pub async fn update_tag(
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    auth_user: AuthUser,
+    tag_id: web::Path<i32>,
+    updated_tag: web::Json<NewTagRequest>,
+) -> impl Responder {
+    let id = tag_id.into_inner();
+    let updated_tag = match validate(updated_tag.into_inner()) {
+        Ok(tag) => tag,
+        Err(error) => return HttpResponse::BadRequest().json(error),
+    };
+
+    match repo
+        .update_tag(auth_user.workspace_id, id, &updated_tag)
+        .await
+    {
+        Ok(false) => HttpResponse::NotFound().body("Tag not found"),
+        Ok(true) => {
+            events::publish(
+                bus.as_ref(),
+                auth_user.user_id,
+                Entity::Tag,
+                Action::Updated,
+                vec![id],
+            );
+            mutations::updated(repo.get_tag(auth_user.workspace_id, id).await)
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to update tag")
+        }
+    }
+}
+
+/// The workspace's tags, each with its `contact_count`
+#[get("/tags")]
+pub async fn list_tags(repo: web::Data<dyn Repository>, auth_user: AuthUser) -> impl Responder {
+    match repo.tag_usage(auth_user.workspace_id).await {
+        Ok(tags) => HttpResponse::Ok().json(TagResponse { tags }),
+        Err(e) => {
+            eprintln!(
+                "Database error fetching tags for user {}: {:?}",
+                auth_user.user_id, e
+            );
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to fetch tags",
+                "details": format!("{:?}", e)
+            }))
+        }
+    }
+}
+
+/// Tag names and contact emails as they're matched on import and quick-add: trimmed, ignoring
+/// case
+pub(crate) fn match_key(value: &str) -> String {
+    value.trim().to_lowercase()
+}
+
+/// The workspace's tags by name, each with the sorted emails of its contacts
+pub async fn tag_archive(repo: &dyn Repository, workspace_id: i32) -> RepoResult<TagArchive> {
+    let contacts = repo.list_contacts(workspace_id).await?;
+    let emails: HashMap<i32, &str> = contacts
+        .iter()
+        .filter_map(|c| Some((c.contact_id, c.email.as_deref()?)))
+        .collect();
+    let contact_ids: Vec<i32> = contacts.iter().map(|c| c.contact_id).collect();
+    let mut tagged: HashMap<i32, Vec<String>> = HashMap::new();
+    for (contact_id, tag) in repo.tags_for_contacts(&contact_ids).await? {
+        if let Some(email) = emails.get(&contact_id) {
+            tagged
+                .entry(tag.tag_id)
+                .or_default()
+                .push(email.to_string());
+        }
+    }
+
+    let mut tags: Vec<TagExport> = repo
+        .list_tags(workspace_id)
+        .await?
+        .into_iter()
+        .map(|tag| {
+            let mut contact_emails = tagged.remove(&tag.tag_id).unwrap_or_default();
+            contact_emails.sort();
+            contact_emails.dedup();
+            TagExport {
+                name: tag.name,
+                color: tag.color,
+                details: tag.details,
+                contact_emails,
+            }
+        })
+        .collect();
+    tags.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(TagArchive { tags })
+}
+
+/// Save validated tags from an archive, reusing those already in the workspace under the same
+/// name, and attach them to the contacts with their emails. Also returns the contacts tagged.
+/// A dry run saves nothing and reports what it would have done.
+pub async fn import_archive(
+    repo: &dyn Repository,
+    workspace_id: i32,
+    tags: &[(NewTagRequest, Vec<String>)],
+    dry_run: bool,
+) -> RepoResult<(TagImportReport, Vec<i32>)> {
+    let mut by_name: HashMap<String, i32> = repo
+        .list_tags(workspace_id)
+        .await?
+        .into_iter()
+        .map(|tag| (match_key(&tag.name), tag.tag_id))
+        .collect();
+    let mut report = TagImportReport {
+        created_tag_ids: Vec::new(),
+        existing_tag_ids: Vec::new(),
+        new_tags: Vec::new(),
+        tagged_count: 0,
+        unmatched_emails: Vec::new(),
+        dry_run,
+    };
+    // Tags a dry run would create have no id yet
+    let mut tag_ids: Vec<Option<i32>> = Vec::with_capacity(tags.len());
+    let mut new_keys = BTreeSet::new();
+    for (tag, _) in tags {
+        let key = match_key(&tag.name);
+        let tag_id = match by_name.get(&key) {
+            Some(&tag_id) => {
+                // Named twice in the archive: only count it the first time
+                if !report.created_tag_ids.contains(&tag_id)
+                    && !report.existing_tag_ids.contains(&tag_id)
+                {
+                    report.existing_tag_ids.push(tag_id);
+                }
+                Some(tag_id)
+            }
+            None if dry_run => {
+                if new_keys.insert(key) {
+                    report.new_tags.push(tag.name.clone());
+                }
+                None
+            }
+            None => {
+                let tag_id = repo.create_tag(workspace_id, tag).await?;
+                by_name.insert(key, tag_id);
+                report.created_tag_ids.push(tag_id);
+                report.new_tags.push(tag.name.clone());
+                Some(tag_id)
+            }
+        };
+        tag_ids.push(tag_id);
+    }
+
+    let mut by_email: HashMap<String, Vec<i32>> = HashMap::new();
+    for contact in repo.list_contacts(workspace_id).await? {
+        if let Some(email) = contact.email.as_deref() {
+            by_email
+                .entry(match_key(email))
+                .or_default()
+                .push(contact.contact_id);
+        }
+    }
+    let mut unmatched = BTreeSet::new();
+    let mut touched = BTreeSet::new();
+    for (tag_id, (_, emails)) in tag_ids.into_iter().zip(tags) {
+        let mut contact_ids = Vec::new();
+        for email in emails {
+            match by_email.get(&match_key(email)) {
+                Some(ids) => contact_ids.extend(ids),
+                None => {
+                    unmatched.insert(email.trim().to_string());
+                }
+            }
+        }
+        if contact_ids.is_empty() {
+            continue;
+        }
+        match tag_id {
+            Some(tag_id) if !dry_run => {
+                let tagged = repo
+                    .add_tag_to_contacts(workspace_id, tag_id, &contact_ids)
+                    .await?;
+                report.tagged_count += tagged.len();
+                touched.extend(tagged);
+            }
+            _ => {
+                contact_ids.sort();
+                contact_ids.dedup();
+                report.tagged_count += contact_ids.len();
+            }
+        }
+    }
+    report.unmatched_emails = unmatched.into_iter().collect();
+    Ok((report, touched.into_iter().collect()))
+}
+
+/// Every tag in the workspace with the emails of the contacts it's attached to, for
+/// `POST /tags/import` here or on another instance
+#[get("/tags/export")]
+pub async fn export_tags(repo: web::Data<dyn Repository>, auth_user: AuthUser) -> impl Responder {
+    match tag_archive(repo.get_ref(), auth_user.workspace_id).await {
+        Ok(archive) => HttpResponse::Ok().json(archive),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to export tags")
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ImportTagsQuery {
+    /// Report what would be created and tagged without saving anything
+    #[serde(default)]
+    dry_run: bool,
+}
+
+/// Import tags from `GET /tags/export`, attaching them to the contacts whose emails are
+/// listed. Tags are matched by name and contacts by email, both ignoring case, so importing the
+/// same archive twice changes nothing. Every tag is checked before any is saved, and
+/// `?dry_run=true` saves nothing at all.
+#[post("/tags/import")]
+pub async fn import_tags(
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    auth_user: AuthUser,
+    limits: Option<web::Data<Limits>>,
+    query: web::Query<ImportTagsQuery>,
+    archive: web::Json<TagArchive>,
+) -> impl Responder {
+    let archive = archive.into_inner();
+    if let Some(response) = limits::configured(limits.as_ref()).reject_bulk(archive.tags.len()) {
+        return response;
+    }
+
+    let mut tags = Vec::with_capacity(archive.tags.len());
+    for tag in archive.tags {
+        if tag.name.trim().is_empty() {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Every tag needs a name"
+            }));
+        }
+        let new_tag = NewTagRequest {
+            name: tag.name,
+            color: tag.color,
+            details: tag.details,
+        };
+        match validate(new_tag) {
+            Ok(new_tag) => tags.push((new_tag, tag.contact_emails)),
+            Err(error) => return HttpResponse::BadRequest().json(error),
+        }
+    }
+
+    let imported =
+        import_archive(repo.get_ref(), auth_user.workspace_id, &tags, query.dry_run).await;
+    let (report, tagged) = match imported {
+        Ok(imported) => imported,
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            return HttpResponse::InternalServerError().body("Failed to import tags");
+        }
+    };
+
+    if !report.created_tag_ids.is_empty() {
+        events::publish(
+            bus.as_ref(),
+            auth_user.user_id,
+            Entity::Tag,
+            Action::Created,
+            report.created_tag_ids.clone(),
+        );
+    }
+    if !tagged.is_empty() {
+        events::publish(
+            bus.as_ref(),
+            auth_user.user_id,
+            Entity::Contact,
+            Action::Updated,
+            tagged,
+        );
+    }
+
+    HttpResponse::Ok().json(report)
+}
+
+#[post("/contacts/{contact_id}/tags/{tag_id}")]
+pub async fn add_tag_to_contact(
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    auth_user: AuthUser,
+    Owned(contact): OwnedContact,
+    Owned(tag): OwnedTag,
+) -> impl Responder {
+    let contact_id = contact.contact_id;
+
+    match repo.add_tag_to_contact(contact_id, tag.tag_id).await {
+        Ok(_) => {
+            events::publish(
+                bus.as_ref(),
+                auth_user.user_id,
+                Entity::Contact,
+                Action::Updated,
+                vec![contact_id],
+            );
+            HttpResponse::Ok().json(serde_json::json!({
+                "message": "Tag added to contact successfully"
+            }))
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to add tag to contact")
+        }
+    }
+}
+
+#[delete("/contacts/{contact_id}/tags/{tag_id}")]
+pub async fn remove_tag_from_contact(
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    auth_user: AuthUser,
+    Owned(contact): OwnedContact,
+    Owned(tag): OwnedTag,
+) -> impl Responder {
+    let contact_id = contact.contact_id;
+
+    match repo.remove_tag_from_contact(contact_id, tag.tag_id).await {
+        Ok(_) => {
+            events::publish(
+                bus.as_ref(),
+                auth_user.user_id,
+                Entity::Contact,
+                Action::Updated,
+                vec![contact_id],
+            );
+            HttpResponse::Ok().body("Tag removed from contact successfully")
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to remove tag from contact")
+        }
+    }
+}
+
+#[post("/interactions/{interaction_id}/tags/{tag_id}")]
+pub async fn add_tag_to_interaction(
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    auth_user: AuthUser,
+    Owned(interaction): OwnedInteraction,
+    Owned(tag): OwnedTag,
+) -> impl Responder {
+    let interaction_id = interaction.interaction_id;
+
+    match repo
+        .add_tag_to_interaction(interaction_id, tag.tag_id)
+        .await
+    {
+        Ok(_) => {
+            events::publish(
+                bus.as_ref(),
+                auth_user.user_id,
+                Entity::Interaction,
+                Action::Updated,
+                vec![interaction_id],
+            );
+            HttpResponse::Ok().json(serde_json::json!({
+                "message": "Tag added to interaction successfully"
+            }))
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to add tag to interaction")
+        }
+    }
+}
+
+#[delete("/interactions/{interaction_id}/tags/{tag_id}")]
+pub async fn remove_tag_from_interaction(
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    auth_user: AuthUser,
+    Owned(interaction): OwnedInteraction,
+    Owned(tag): OwnedTag,
+) -> impl Responder {
+    let interaction_id = interaction.interaction_id;
+
+    match repo
+        .remove_tag_from_interaction(interaction_id, tag.tag_id)
+        .await
+    {
+        Ok(_) => {
+            events::publish(
+                bus.as_ref(),
+                auth_user.user_id,
+                Entity::Interaction,
+                Action::Updated,
+                vec![interaction_id],
+            );
+            HttpResponse::Ok().body("Tag removed from interaction successfully")
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to remove tag from interaction")
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct BulkTagAssignRequest {
+    contact_ids: Vec<i32>,
+}
+
+#[post("/tags/{tag_id}/contacts/bulk")]
+pub async fn bulk_add_tag_to_contacts(
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    auth_user: AuthUser,
+    limits: Option<web::Data<Limits>>,
+    Owned(tag): OwnedTag,
+    request: web::Json<BulkTagAssignRequest>,
+) -> impl Responder {
+    let tag_id = tag.tag_id;
+    if let Some(response) =
+        limits::configured(limits.as_ref()).reject_bulk(request.contact_ids.len())
+    {
+        return response;
+    }
+
+    let tagged = match repo
+        .add_tag_to_contacts(auth_user.workspace_id, tag_id, &request.contact_ids)
+        .await
+    {
+        Ok(tagged) => tagged,
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            return HttpResponse::InternalServerError().body("Failed to add tag to contacts");
+        }
+    };
+
+    if !tagged.is_empty() {
+        events::publish(
+            bus.as_ref(),
+            auth_user.user_id,
+            Entity::Contact,
+            Action::Updated,
+            tagged.clone(),
+        );
+    }
+
+    let skipped = skipped_ids(&request.contact_ids, &tagged);
+    let errors: Vec<_> = skipped
+        .iter()
+        .map(|id| serde_json::json!({"contact_id": id, "error": "Contact not found"}))
+        .collect();
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "success_count": tagged.len(),
+        "skipped_ids": skipped,
+        "errors": errors,
+        "message": format!("Added tag to {} contacts", tagged.len())
+    }))
+}
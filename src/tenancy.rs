@@ -0,0 +1,426 @@
+//! Tenant isolation audit of the SQL repositories.
+//!
+//! Every statement in `repository/postgres.rs` and `repository/sqlite.rs` should compare a
+//! tenant column (`workspace_id`, `user_id`, `owner_id` or `grantee_id`) with a bind parameter,
+//! or insert into one, so a forgotten predicate can't hand one user another's rows. `audit`
+//! reads a repository's source, compiled into the binary, finds every SQL literal and the
+//! method it is in, and reports the statements without such a predicate, other than those of
+//! the methods in `UNSCOPED`. Run it with `personal-crm audit-tenancy`; the tests run it on
+//! every build. It is a textual check that catches a missing predicate, not a proof that the
+//! predicate is right; the route isolation tests cover that.
+
+/// A repository's source, as audited
+pub struct RepositorySource {
+    /// Path under src/, for reports
+    pub path: &'static str,
+    pub source: &'static str,
+    /// Methods in `UNSCOPED` that run no SQL in this repository, so aren't stale here
+    pub without_sql: &'static [&'static str],
+}
+
+/// The SQL repositories
+pub const REPOSITORY_SOURCES: &[RepositorySource] = &[
+    RepositorySource {
+        path: "repository/postgres.rs",
+        source: include_str!("repository/postgres.rs"),
+        without_sql: &[],
+    },
+    RepositorySource {
+        path: "repository/sqlite.rs",
+        source: include_str!("repository/sqlite.rs"),
+        // Priorities are computed on read, and notes aren't encrypted
+        without_sql: &["refresh_contact_scores", "encrypt_notes"],
+    },
+];
+
+impl RepositorySource {
+    /// Audit the source against the `UNSCOPED` methods that run SQL here
+    pub fn audit(&self) -> Report {
+        let mut report = audit(self.source);
+        report
+            .stale
+            .retain(|method| !self.without_sql.contains(method));
+        report
+    }
+}
+
+/// Columns naming the workspace or user a row belongs to
+pub const TENANT_COLUMNS: &[&str] = &["workspace_id", "user_id", "owner_id", "grantee_id"];
+
+/// Methods whose statements may go without a tenant predicate, and why
+pub const UNSCOPED: &[(&str, &str)] = &[
+    (
+        "find_user",
+        "looks the user up by Auth0 subject to sign them in",
+    ),
+    ("get_or_create_user", "creates the user on first sign-in"),
+    ("purge_deleted_users", "background purge across all users"),
+    (
+        "find_user_by_email",
+        "finds the user a share is for; returns only their id",
+    ),
+    ("list_accounts", "instance-wide, for admins only"),
+    ("weekly_review_recipients", "background emails across users"),
+    (
+        "push_reminder_recipients",
+        "background push notifications across users",
+    ),
+    (
+        "pending_transcriptions",
+        "background transcription across workspaces",
+    ),
+    (
+        "pending_avatar_lookups",
+        "background avatar enrichment across workspaces",
+    ),
+    (
+        "claim_job",
+        "background job leases, shared by every replica",
+    ),
+    ("instance_stats", "instance-wide, for admins only"),
+    (
+        "refresh_contact_scores",
+        "background refresh across workspaces",
+    ),
+    ("encrypt_notes", "instance-wide encryption run from the CLI"),
+    (
+        "tags_for_contacts",
+        "loads tags for contacts already fetched within the workspace",
+    ),
+    (
+        "interactions_for_contacts",
+        "loads interactions for contacts already fetched within the workspace",
+    ),
+    (
+        "occasions_for_contacts",
+        "loads occasions for contacts already fetched within the workspace",
+    ),
+    (
+        "social_profiles_for_contacts",
+        "loads profiles for contacts already fetched within the workspace",
+    ),
+    (
+        "gift_ideas_for_contacts",
+        "loads ideas for contacts already fetched within the workspace",
+    ),
+    (
+        "contact_history",
+        "called for a contact the handler checked the caller can read",
+    ),
+    (
+        "contact_timeline",
+        "called for a contact the handler checked the caller can read",
+    ),
+    (
+        "add_tag_to_contact",
+        "the handler checks the caller owns both ids",
+    ),
+    (
+        "remove_tag_from_contact",
+        "the handler checks the caller owns both ids",
+    ),
+    (
+        "add_tag_to_interaction",
+        "the handler checks the caller owns both ids",
+    ),
+    (
+        "remove_tag_from_interaction",
+        "the handler checks the caller owns both ids",
+    ),
+    (
+        "set_group_members",
+        "replaces the members of a group it first checks is in the workspace",
+    ),
+    (
+        "add_group_member",
+        "the handler checks the caller owns both ids",
+    ),
+    (
+        "remove_group_member",
+        "the handler checks the caller owns both ids",
+    ),
+    (
+        "update_interaction",
+        "replaces the participants of the interaction just updated within the workspace",
+    ),
+    (
+        "due_export_schedules",
+        "background pushes across workspaces",
+    ),
+    (
+        "inbound_email_workspace",
+        "finds the workspace by its secret inbound address token",
+    ),
+    (
+        "record_rest_hook_delivery",
+        "background dispatch, for hooks it just listed",
+    ),
+    (
+        "claim_deliveries",
+        "the outbox dispatcher sends every workspace's deliveries",
+    ),
+    (
+        "record_delivery_attempt",
+        "the outbox dispatcher, for deliveries it just claimed",
+    ),
+    (
+        "purge_deliveries",
+        "drops every workspace's finished deliveries once kept long enough",
+    ),
+    (
+        "contact_link_target",
+        "finds the contact by its unguessable public link token",
+    ),
+    (
+        "insert_contact_changes",
+        "records history of a contact just written within the workspace",
+    ),
+    (
+        "insert_interaction",
+        "ends snoozes of the contacts in the interaction just inserted",
+    ),
+];
+
+/// A SQL literal in the repository source
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Statement {
+    /// The function it is in
+    pub method: String,
+    /// Line of the source it starts on
+    pub line: usize,
+    pub sql: String,
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    /// How many statements were found
+    pub statements: usize,
+    /// Statements without a tenant predicate, outside the methods in `UNSCOPED`
+    pub unscoped: Vec<Statement>,
+    /// `UNSCOPED` methods that no longer have an unscoped statement, and should be dropped
+    /// from it
+    pub stale: Vec<&'static str>,
+}
+
+impl Report {
+    pub fn passed(&self) -> bool {
+        self.unscoped.is_empty() && self.stale.is_empty()
+    }
+}
+
+/// The name of the function declared on a line, if any
+fn declared_fn(line: &str) -> Option<&str> {
+    let mut rest = line.trim_start();
+    for prefix in ["pub(crate) ", "pub ", "async "] {
+        rest = rest.strip_prefix(prefix).unwrap_or(rest);
+    }
+    let rest = rest.strip_prefix("fn ")?;
+    let end = rest
+        .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .unwrap_or(rest.len());
+    Some(&rest[..end])
+}
+
+fn is_sql(literal: &str) -> bool {
+    let first = literal
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .to_ascii_uppercase();
+    matches!(
+        first.as_str(),
+        "SELECT" | "INSERT" | "UPDATE" | "DELETE" | "WITH"
+    )
+}
+
+/// Every SQL string literal in Rust `source`, with the function it is in
+pub fn statements(source: &str) -> Vec<Statement> {
+    let bytes = source.as_bytes();
+    let mut found = Vec::new();
+    let mut method = String::new();
+    let mut line = 1;
+    let mut line_start = true;
+    let mut i = 0;
+    while i < bytes.len() {
+        if line_start {
+            let end = source[i..].find('\n').map_or(source.len(), |n| i + n);
+            if let Some(name) = declared_fn(&source[i..end]) {
+                method = name.to_string();
+            }
+            line_start = false;
+        }
+        let rest = &source[i..];
+        let (literal, len) = if rest.starts_with("//") {
+            (None, rest.find('\n').unwrap_or(rest.len()))
+        } else if rest.starts_with("'\"'") || rest.starts_with("'\\''") {
+            (None, rest[1..].find('\'').map_or(1, |n| n + 2))
+        } else if rest.starts_with("r#\"")
+            && (i == 0 || !(bytes[i - 1].is_ascii_alphanumeric() || bytes[i - 1] == b'_'))
+        {
+            let end = rest[3..].find("\"#").map_or(rest.len(), |n| n + 3);
+            (Some(rest[3..end].to_string()), (end + 2).min(rest.len()))
+        } else if rest.starts_with('"') {
+            let mut end = 1;
+            let body = rest.as_bytes();
+            while end < body.len() && body[end] != b'"' {
+                end += if body[end] == b'\\' { 2 } else { 1 };
+            }
+            (
+                Some(rest[1..end.min(rest.len())].replace("\\\"", "\"")),
+                end + 1,
+            )
+        } else {
+            (None, rest.chars().next().map_or(1, char::len_utf8))
+        };
+        let len = len.min(rest.len()).max(1);
+        if let Some(sql) = literal.filter(|sql| is_sql(sql)) {
+            found.push(Statement {
+                method: method.clone(),
+                line,
+                sql,
+            });
+        }
+        let newlines = rest[..len].matches('\n').count();
+        if newlines > 0 {
+            line += newlines;
+            line_start = rest[..len].ends_with('\n');
+        }
+        if rest[len..].starts_with('\n') {
+            line += 1;
+            line_start = true;
+            i += len + 1;
+        } else {
+            i += len;
+        }
+    }
+    found
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Param,
+    Symbol(char),
+}
+
+/// Words (with any `alias.` prefix), bind parameters and symbols of lowercased SQL, with
+/// quoted text and `::type` casts left out
+fn tokens(sql: &str) -> Vec<Token> {
+    let sql = sql.to_ascii_lowercase();
+    let mut chars = sql.chars().peekable();
+    let mut tokens = Vec::new();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '\'' | '"' => {
+                for next in chars.by_ref() {
+                    if next == c {
+                        break;
+                    }
+                }
+            }
+            '$' => {
+                while chars.next_if(char::is_ascii_digit).is_some() {}
+                tokens.push(Token::Param);
+            }
+            ':' if chars.peek() == Some(&':') => {
+                chars.next();
+                while chars
+                    .next_if(|c| c.is_alphanumeric() || *c == '_' || *c == '[' || *c == ']')
+                    .is_some()
+                {}
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut word = c.to_string();
+                while let Some(next) =
+                    chars.next_if(|c| c.is_alphanumeric() || *c == '_' || *c == '.')
+                {
+                    word.push(next);
+                }
+                tokens.push(Token::Word(word));
+            }
+            c => tokens.push(Token::Symbol(c)),
+        }
+    }
+    tokens
+}
+
+fn is_tenant_column(token: &Token) -> bool {
+    match token {
+        Token::Word(word) => {
+            let column = word.rsplit('.').next().unwrap_or(word);
+            TENANT_COLUMNS.contains(&column)
+        }
+        _ => false,
+    }
+}
+
+fn is_word(token: Option<&Token>, word: &str) -> bool {
+    matches!(token, Some(Token::Word(w)) if w == word)
+}
+
+/// Whether the statement compares a tenant column with a bind parameter (`workspace_id = $1`,
+/// `user_id = ANY($2)`, `$2 IN (owner_id, grantee_id)`), or inserts into one
+pub fn is_scoped(sql: &str) -> bool {
+    let tokens = tokens(sql);
+    let at = |i: usize| tokens.get(i);
+    for (i, token) in tokens.iter().enumerate() {
+        if is_tenant_column(token) && at(i + 1) == Some(&Token::Symbol('=')) {
+            let compared = match at(i + 2) {
+                Some(Token::Param) => true,
+                Some(Token::Word(w)) if w == "any" => at(i + 4) == Some(&Token::Param),
+                _ => false,
+            };
+            if compared {
+                return true;
+            }
+        }
+        if *token == Token::Param {
+            if at(i + 1) == Some(&Token::Symbol('=')) && at(i + 2).is_some_and(is_tenant_column) {
+                return true;
+            }
+            if is_word(at(i + 1), "in") && at(i + 2) == Some(&Token::Symbol('(')) {
+                let listed = tokens[i + 3..]
+                    .iter()
+                    .take_while(|t| **t != Token::Symbol(')'))
+                    .any(is_tenant_column);
+                if listed {
+                    return true;
+                }
+            }
+        }
+    }
+    // INSERT INTO table (columns ...) naming a tenant column
+    if is_word(at(0), "insert") && is_word(at(1), "into") && at(3) == Some(&Token::Symbol('(')) {
+        return tokens[4..]
+            .iter()
+            .take_while(|t| **t != Token::Symbol(')'))
+            .any(is_tenant_column);
+    }
+    false
+}
+
+/// Audit the statements of Rust `source` against `UNSCOPED`
+pub fn audit(source: &str) -> Report {
+    let statements = statements(source);
+    let mut report = Report {
+        statements: statements.len(),
+        ..Report::default()
+    };
+    let mut excused = Vec::new();
+    for statement in statements {
+        if is_scoped(&statement.sql) {
+            continue;
+        }
+        match UNSCOPED.iter().find(|(m, _)| *m == statement.method) {
+            Some((method, _)) => excused.push(*method),
+            None => report.unscoped.push(statement),
+        }
+    }
+    report.stale = UNSCOPED
+        .iter()
+        .map(|(method, _)| *method)
+        .filter(|method| !excused.contains(method))
+        .collect();
+    report
+}
@@ -0,0 +1,643 @@
+//! Fixtures for integration tests (enabled with the `test-support` feature).
+//!
+//! `provision` creates a user that owns one of every kind of row, through any `Repository`, and
+//! registers a bearer token for it, so tests can call the real handlers without Auth0, on
+//! Postgres or in memory. Pair two of them with the route registry in `routes::ROUTES` to check
+//! tenant isolation across the whole API, or walk the registry as one to check that every route
+//! serves its owner.
+
+use crate::models::{
+    ExportSchedule, FilterQuery, GiftStatus, GoalPeriod, HookEvent, NewAttachment,
+    NewContactRequest, NewGiftIdeaRequest, NewGoalRequest, NewGroupRequest, NewInteractionRequest,
+    NewOccasionRequest, NewPushSubscriptionRequest, NewRestHookRequest, NewSavedFilterRequest,
+    NewSocialProfileRequest, NewTagRequest, OccasionType, PushKeys, SharePermission,
+    SocialPlatform,
+};
+use crate::repository::{PgRepository, Repository};
+use crate::routes::{Resource, RouteSpec};
+use crate::{Auth0Claims, cache_rejected_token, cache_token};
+use sqlx::PgPool;
+use std::time::{SystemTime, UNIX_EPOCH};
+use time::OffsetDateTime;
+
+/// Phone number of every provisioned contact
+pub const PHONE: &str = "(415) 555-0132";
+
+/// A provisioned user and the ids of the rows they own
+#[derive(Debug, Clone)]
+pub struct Tenant {
+    pub user_id: i32,
+    /// The user's default workspace, holding all the rows below
+    pub workspace_id: i32,
+    /// An empty second workspace, which unlike the default one can be deleted
+    pub spare_workspace_id: i32,
+    pub auth0_id: String,
+    /// Bearer token accepted by the `AuthUser` extractor
+    pub token: String,
+    /// Unique string embedded in the user's rows, for spotting leaks in responses
+    pub marker: String,
+    pub contact_id: i32,
+    pub tag_id: i32,
+    pub interaction_id: i32,
+    pub capture_id: i32,
+    pub interaction_type_id: i32,
+    pub occasion_id: i32,
+    pub social_profile_id: i32,
+    /// Gift idea for the contact's occasion
+    pub gift_idea_id: i32,
+    /// Group with the contact as its only member
+    pub group_id: i32,
+    pub filter_id: i32,
+    pub goal_id: i32,
+    /// REST hook for new contacts
+    pub rest_hook_id: i32,
+    /// Public link to the contact's card
+    pub contact_link_id: i32,
+    /// Share of `tag_id` with a second user created alongside this one
+    pub share_id: i32,
+    /// A device subscribed to the user's push notifications
+    pub push_subscription_id: i32,
+    /// A file attached to the interaction
+    pub attachment_id: i32,
+    /// That second user's email
+    pub friend_email: String,
+}
+
+impl Tenant {
+    pub fn id(&self, resource: Resource) -> i32 {
+        match resource {
+            Resource::Contact => self.contact_id,
+            Resource::Tag => self.tag_id,
+            Resource::Interaction => self.interaction_id,
+            Resource::Capture => self.capture_id,
+            Resource::InteractionType => self.interaction_type_id,
+            Resource::Occasion => self.occasion_id,
+            Resource::SocialProfile => self.social_profile_id,
+            Resource::GiftIdea => self.gift_idea_id,
+            Resource::Group => self.group_id,
+            Resource::Filter => self.filter_id,
+            Resource::Goal => self.goal_id,
+            Resource::Share => self.share_id,
+            Resource::Workspace => self.spare_workspace_id,
+            Resource::RestHook => self.rest_hook_id,
+            Resource::ContactLink => self.contact_link_id,
+            Resource::PushSubscription => self.push_subscription_id,
+            Resource::Attachment => self.attachment_id,
+        }
+    }
+}
+
+fn unique(label: &str) -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    format!("{}-{}", label, nanos)
+}
+
+/// A push subscription to `endpoint` with valid keys, whose private key is
+/// `PUSH_SUBSCRIBER_KEY`
+pub fn push_subscription(endpoint: &str) -> NewPushSubscriptionRequest {
+    use base64::Engine;
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    use p256::elliptic_curve::sec1::ToEncodedPoint;
+
+    let secret = p256::SecretKey::from_slice(&PUSH_SUBSCRIBER_KEY).unwrap();
+    NewPushSubscriptionRequest {
+        endpoint: endpoint.to_string(),
+        keys: PushKeys {
+            p256dh: URL_SAFE_NO_PAD.encode(secret.public_key().to_encoded_point(false).as_bytes()),
+            auth: URL_SAFE_NO_PAD.encode(PUSH_SUBSCRIBER_AUTH),
+        },
+        device_name: None,
+    }
+}
+
+/// Private key of the subscriptions made by `push_subscription`, for decrypting their pushes
+pub const PUSH_SUBSCRIBER_KEY: [u8; 32] = [7; 32];
+
+/// Authentication secret of those subscriptions
+pub const PUSH_SUBSCRIBER_AUTH: [u8; 16] = [9; 16];
+
+/// Make `token` authenticate as `auth0_id` without contacting Auth0. The token counts as issued
+/// now, so it passes `reauth` checks for a while.
+pub async fn register_token(token: &str, auth0_id: &str) {
+    register_token_expiring(token, auth0_id, None).await;
+}
+
+/// Like `register_token`, for a token whose `exp` claim is the given unix time
+pub async fn register_token_expiring(token: &str, auth0_id: &str, exp: Option<usize>) {
+    register_claims(
+        token,
+        Auth0Claims {
+            sub: auth0_id.to_string(),
+            email: None,
+            name: None,
+            iss: None,
+            aud: None,
+            exp,
+            iat: Some(
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs() as usize,
+            ),
+            auth_time: None,
+            scope: None,
+            permissions: None,
+        },
+    )
+    .await;
+}
+
+/// Make `token` authenticate with the given claims without contacting Auth0
+pub async fn register_claims(token: &str, claims: Auth0Claims) {
+    cache_token(token, claims).await;
+}
+
+/// Make `token` be refused as if Auth0 had rejected it
+pub async fn reject_token(token: &str) {
+    cache_rejected_token(token).await;
+}
+
+/// Create a user (auth0_id "test|{label}-{nanos}") whose default workspace holds a tagged
+/// contact with one tagged interaction of a custom type and an attachment, one occasion with a
+/// gift idea, one social profile and a public link, a group, an inbox capture, a saved filter
+/// for the tag, a weekly goal for the contact, an export schedule, an inbound email address and
+/// a REST hook, plus an empty second workspace and a push subscription
+pub async fn provision(repo: &dyn Repository, label: &str) -> Result<Tenant, sqlx::Error> {
+    let marker = unique(label);
+    let auth0_id = format!("test|{}", marker);
+
+    let user = repo
+        .get_or_create_user(&auth0_id, &format!("{}@example.com", marker), &marker)
+        .await?;
+    let workspace_id = user.workspace_id;
+    let spare_workspace_id = repo
+        .create_workspace(user.user_id, &format!("{} spare", marker))
+        .await?;
+
+    let contact_id = repo
+        .create_contact(
+            workspace_id,
+            &NewContactRequest {
+                first_name: Some(marker.clone()),
+                email: Some(format!("contact-{}@example.com", marker)),
+                phone: Some(PHONE.to_string()),
+                ..Default::default()
+            },
+        )
+        .await?;
+
+    let tag_id = repo
+        .create_tag(
+            workspace_id,
+            &NewTagRequest {
+                name: marker.clone(),
+                color: None,
+                details: None,
+            },
+        )
+        .await?;
+    repo.add_tag_to_contact(contact_id, tag_id).await?;
+
+    let interaction_type_id = repo.create_interaction_type(workspace_id, &marker).await?;
+    let (interaction_id, _) = repo
+        .create_interaction(
+            workspace_id,
+            &NewInteractionRequest {
+                contact_id,
+                interaction_date: OffsetDateTime::now_utc(),
+                notes: Some(marker.clone()),
+                follow_up_priority: None,
+                interaction_type: Some(marker.clone()),
+                contact_ids: Vec::new(),
+                follow_up_in_days: None,
+            },
+            None,
+        )
+        .await?;
+    repo.add_tag_to_interaction(interaction_id, tag_id).await?;
+    let attachment_id = repo
+        .create_attachment(
+            workspace_id,
+            interaction_id,
+            &NewAttachment {
+                file_name: format!("{}.txt", marker),
+                content_type: "text/plain".to_string(),
+                data: marker.clone().into_bytes(),
+                transcribe: false,
+            },
+        )
+        .await?;
+
+    let capture_id = repo.create_capture(workspace_id, &marker).await?;
+
+    let occasion_id = repo
+        .create_occasion(
+            workspace_id,
+            &NewOccasionRequest {
+                contact_id,
+                name: marker.clone(),
+                date: OffsetDateTime::now_utc().date(),
+                recurring: false,
+                recurring_interval: None,
+                details: None,
+                occasion_type: OccasionType::default(),
+                birth_year: None,
+                remind_days_before: None,
+            },
+        )
+        .await?;
+
+    let contact_link = repo
+        .create_contact_link(
+            workspace_id,
+            contact_id,
+            &crate::contact_links::token_hash(&crate::inbound_email::generate_token()),
+            OffsetDateTime::now_utc() + time::Duration::days(7),
+        )
+        .await?;
+
+    let social_profile_id = repo
+        .create_social_profile(
+            workspace_id,
+            &NewSocialProfileRequest {
+                contact_id,
+                platform: SocialPlatform::Website,
+                url: format!("https://example.com/{}", marker),
+            },
+        )
+        .await?;
+
+    let gift_idea_id = repo
+        .create_gift_idea(
+            workspace_id,
+            &NewGiftIdeaRequest {
+                contact_id,
+                occasion_id: Some(occasion_id),
+                idea: marker.clone(),
+                status: GiftStatus::Idea,
+                price_cents: Some(2500),
+            },
+        )
+        .await?;
+
+    let group_id = repo
+        .create_group(
+            workspace_id,
+            &NewGroupRequest {
+                name: marker.clone(),
+                description: None,
+            },
+        )
+        .await?;
+    repo.set_group_members(workspace_id, group_id, &[contact_id])
+        .await?;
+
+    let filter_id = repo
+        .create_filter(
+            workspace_id,
+            &NewSavedFilterRequest {
+                name: marker.clone(),
+                query: FilterQuery {
+                    all_tag_ids: vec![tag_id],
+                    ..FilterQuery::default()
+                },
+            },
+        )
+        .await?;
+
+    let goal_id = repo
+        .create_goal(
+            workspace_id,
+            &NewGoalRequest {
+                name: marker.clone(),
+                contact_id: Some(contact_id),
+                interaction_type: None,
+                target_count: 1,
+                period: GoalPeriod::Week,
+            },
+        )
+        .await?;
+
+    repo.save_export_schedule(&ExportSchedule {
+        user_id: user.user_id,
+        workspace_id,
+        url: "https://backup.example.com/crm".to_string(),
+        interval_hours: 24,
+        secret: marker.clone(),
+        last_pushed_at: None,
+        last_status: None,
+    })
+    .await?;
+    repo.save_inbound_email_token(workspace_id, &crate::inbound_email::generate_token())
+        .await?;
+    let rest_hook_id = repo
+        .create_rest_hook(
+            workspace_id,
+            &NewRestHookRequest {
+                event: HookEvent::NewContact,
+                target_url: format!("https://hooks.example.com/{}", marker),
+            },
+        )
+        .await?;
+
+    let friend_email = format!("friend-{}@example.com", marker);
+    let friend = repo
+        .get_or_create_user(
+            &format!("test|friend-{}", marker),
+            &friend_email,
+            &format!("friend-{}", marker),
+        )
+        .await?;
+    let share_ids = repo
+        .create_shares(
+            user.user_id,
+            friend.user_id,
+            Some(tag_id),
+            &[],
+            SharePermission::Read,
+        )
+        .await?;
+
+    let push_subscription = repo
+        .save_push_subscription(
+            user.user_id,
+            &push_subscription(&format!("https://push.example.com/{}", marker)),
+        )
+        .await?;
+
+    let token = format!("token-{}", marker);
+    register_token(&token, &auth0_id).await;
+
+    Ok(Tenant {
+        user_id: user.user_id,
+        workspace_id,
+        spare_workspace_id,
+        auth0_id,
+        token,
+        marker,
+        friend_email,
+        contact_id,
+        tag_id,
+        interaction_id,
+        capture_id,
+        interaction_type_id,
+        occasion_id,
+        social_profile_id,
+        gift_idea_id,
+        group_id,
+        filter_id,
+        goal_id,
+        rest_hook_id,
+        contact_link_id: contact_link.link_id,
+        share_id: share_ids[0],
+        push_subscription_id: push_subscription.subscription_id,
+        attachment_id,
+    })
+}
+
+/// `provision` in the Postgres database behind `pool`
+pub async fn provision_user(pool: &PgPool, label: &str) -> Result<Tenant, sqlx::Error> {
+    provision(&PgRepository::new(pool.clone()), label).await
+}
+
+/// Everything a user owns as JSON (timestamps excluded), for before/after comparisons
+pub async fn snapshot(pool: &PgPool, user_id: i32) -> Result<serde_json::Value, sqlx::Error> {
+    let text: String = sqlx::query_scalar(
+        "WITH ws AS (SELECT workspace_id FROM workspaces WHERE user_id = $1)
+         SELECT json_build_object(
+            'user', (SELECT json_agg(u) FROM (SELECT user_id, auth0_id, name, email
+                     FROM users WHERE user_id = $1) u),
+            'workspaces', (SELECT json_agg(w ORDER BY w.workspace_id) FROM (SELECT workspace_id,
+                           name, is_default FROM workspaces WHERE user_id = $1) w),
+            'contacts', (SELECT json_agg(c ORDER BY c.contact_id) FROM (SELECT contact_id,
+                         first_name, last_name, email, phone, phone_e164, short_note, notes, avatar_url
+                         FROM contacts WHERE workspace_id IN (SELECT * FROM ws)) c),
+            'contact_changes', (SELECT json_agg(h ORDER BY h.change_id) FROM (SELECT h.change_id,
+                                h.contact_id, h.field, h.old_value, h.new_value
+                                FROM contact_changes h JOIN contacts c ON c.contact_id = h.contact_id
+                                WHERE c.workspace_id IN (SELECT * FROM ws)) h),
+            'tags', (SELECT json_agg(t ORDER BY t.tag_id) FROM (SELECT tag_id, name, color, details
+                     FROM tags WHERE workspace_id IN (SELECT * FROM ws)) t),
+            'contact_tags', (SELECT json_agg(ct ORDER BY ct.contact_id, ct.tag_id)
+                             FROM (SELECT ct.contact_id, ct.tag_id FROM contact_tags ct
+                                   JOIN contacts c ON c.contact_id = ct.contact_id
+                                   WHERE c.workspace_id IN (SELECT * FROM ws)) ct),
+            'interactions', (SELECT json_agg(i ORDER BY i.interaction_id) FROM (SELECT
+                             interaction_id, contact_id, interaction_date, notes, followup_priority,
+                             interaction_type, custom_type_id
+                             FROM interactions WHERE workspace_id IN (SELECT * FROM ws)) i),
+            'interaction_participants', (SELECT json_agg(p ORDER BY p.interaction_id, p.contact_id)
+                                         FROM (SELECT p.interaction_id, p.contact_id
+                                               FROM interaction_participants p
+                                               JOIN interactions i ON i.interaction_id = p.interaction_id
+                                               WHERE i.workspace_id IN (SELECT * FROM ws)) p),
+            'interaction_tags', (SELECT json_agg(it ORDER BY it.interaction_id, it.tag_id)
+                                 FROM (SELECT it.interaction_id, it.tag_id
+                                       FROM interaction_tags it
+                                       JOIN interactions i ON i.interaction_id = it.interaction_id
+                                       WHERE i.workspace_id IN (SELECT * FROM ws)) it),
+            'interaction_attachments', (SELECT json_agg(a ORDER BY a.attachment_id) FROM (SELECT
+                                        attachment_id, interaction_id, file_name, content_type,
+                                        size_bytes, transcription FROM interaction_attachments
+                                        WHERE workspace_id IN (SELECT * FROM ws)) a),
+            'inbox_captures', (SELECT json_agg(c ORDER BY c.capture_id) FROM (SELECT capture_id,
+                               text FROM inbox_captures
+                               WHERE workspace_id IN (SELECT * FROM ws)) c),
+            'interaction_types', (SELECT json_agg(t ORDER BY t.type_id) FROM (SELECT type_id,
+                                  name FROM interaction_types
+                                  WHERE workspace_id IN (SELECT * FROM ws)) t),
+            'occasions', (SELECT json_agg(o ORDER BY o.occasion_id) FROM (SELECT occasion_id,
+                          contact_id, name, date, recurring, recurring_interval, details, occasion_type,
+                          birth_year FROM occasions WHERE workspace_id IN (SELECT * FROM ws)) o),
+            'social_profiles', (SELECT json_agg(p ORDER BY p.social_profile_id) FROM (SELECT
+                                social_profile_id, contact_id, platform, url FROM social_profiles
+                                WHERE workspace_id IN (SELECT * FROM ws)) p),
+            'gift_ideas', (SELECT json_agg(g ORDER BY g.gift_idea_id) FROM (SELECT gift_idea_id,
+                           contact_id, occasion_id, idea, status, price_cents FROM gift_ideas
+                           WHERE workspace_id IN (SELECT * FROM ws)) g),
+            'groups', (SELECT json_agg(g ORDER BY g.group_id) FROM (SELECT group_id, name,
+                       description FROM contact_groups
+                       WHERE workspace_id IN (SELECT * FROM ws)) g),
+            'group_members', (SELECT json_agg(m ORDER BY m.group_id, m.contact_id) FROM (SELECT
+                              m.group_id, m.contact_id, m.position FROM contact_group_members m
+                              JOIN contact_groups g ON g.group_id = m.group_id
+                              WHERE g.workspace_id IN (SELECT * FROM ws)) m),
+            'saved_filters', (SELECT json_agg(f ORDER BY f.filter_id) FROM (SELECT filter_id,
+                              name, query FROM saved_filters
+                              WHERE workspace_id IN (SELECT * FROM ws)) f),
+            'goals', (SELECT json_agg(g ORDER BY g.goal_id) FROM (SELECT goal_id, name,
+                      contact_id, interaction_type, target_count, period FROM goals
+                      WHERE workspace_id IN (SELECT * FROM ws)) g),
+            'reconnect_picks', (SELECT json_agg(p ORDER BY p.week, p.contact_id) FROM (SELECT
+                                week, contact_id FROM reconnect_picks
+                                WHERE workspace_id IN (SELECT * FROM ws)) p),
+            'avatar_lookups', (SELECT json_agg(l ORDER BY l.contact_id) FROM (SELECT
+                               contact_id, next_lookup_at FROM avatar_lookups
+                               WHERE workspace_id IN (SELECT * FROM ws)) l),
+            'suggestion_snoozes', (SELECT json_agg(s ORDER BY s.contact_id) FROM (SELECT
+                                   contact_id, snoozed_until FROM suggestion_snoozes
+                                   WHERE user_id = $1) s),
+            'preferences', (SELECT json_agg(p) FROM (SELECT country, quiet_weekdays,
+                            greeting_tag_ids FROM user_preferences WHERE user_id = $1) p),
+            'export_schedule', (SELECT json_agg(s) FROM (SELECT url, interval_hours, secret
+                                FROM export_schedules WHERE workspace_id IN (SELECT * FROM ws)) s),
+            'inbound_email', (SELECT json_agg(a) FROM (SELECT token FROM inbound_email_addresses
+                              WHERE workspace_id IN (SELECT * FROM ws)) a),
+            'contact_links', (SELECT json_agg(l ORDER BY l.link_id) FROM (SELECT link_id,
+                              contact_id, token_hash FROM contact_links
+                              WHERE workspace_id IN (SELECT * FROM ws)) l),
+            'rest_hooks', (SELECT json_agg(h ORDER BY h.hook_id) FROM (SELECT hook_id, event,
+                           target_url FROM rest_hooks WHERE workspace_id IN (SELECT * FROM ws)) h),
+            'shares', (SELECT json_agg(s ORDER BY s.share_id) FROM (SELECT share_id, grantee_id,
+                       tag_id, contact_id, permission FROM shares WHERE owner_id = $1) s),
+            'push_subscriptions', (SELECT json_agg(p ORDER BY p.subscription_id) FROM (SELECT
+                                   subscription_id, endpoint, p256dh, auth, device_name
+                                   FROM push_subscriptions WHERE user_id = $1) p)
+        )::text",
+    )
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+    Ok(serde_json::from_str(&text).unwrap_or_default())
+}
+
+/// Fill in a route's path parameters, in order, with the given ids, and add the query string
+/// the route requires
+pub fn path_for(spec: &RouteSpec, ids: &[i32]) -> String {
+    let mut path = String::new();
+    let mut ids = ids.iter();
+    for segment in spec.pattern.split('/').skip(1) {
+        path.push('/');
+        if segment.starts_with('{') {
+            path.push_str(&ids.next().expect("missing path id").to_string());
+        } else {
+            path.push_str(segment);
+        }
+    }
+    match spec.pattern {
+        // `PHONE` in E.164
+        "/v1/contacts/lookup" => path.push_str("?phone=%2B14155550132"),
+        "/v1/contacts/autocomplete" => path.push_str("?q=test"),
+        "/v1/contacts/nearby" => path.push_str("?lat=38.72&lng=-9.14"),
+        "/v1/stats/timeseries" => path.push_str("?metric=interactions"),
+        "/v1/interactions/search" => path.push_str("?q=test"),
+        _ => {}
+    }
+    path
+}
+
+/// A valid request body for a route sent by `caller`, referencing `ids` for its body resources
+/// in order
+pub fn body_for(spec: &RouteSpec, caller: &Tenant, ids: &[i32]) -> Option<serde_json::Value> {
+    let id = |n: usize| ids.get(n).copied().unwrap_or_default();
+    let body = match (spec.method.as_str(), spec.pattern) {
+        ("POST", "/v1/contacts") | ("PATCH", "/v1/contacts/{id}") => {
+            serde_json::json!({ "first_name": "Isolation" })
+        }
+        ("POST", "/v1/contacts/bulk") => serde_json::json!([{ "first_name": "Isolation" }]),
+        ("POST", "/v1/contacts/import/json") => serde_json::json!({
+            "mapping": { "fields": { "first_name": ".name" } },
+            "data": [{ "name": "Isolation" }]
+        }),
+        ("POST", "/v1/quick-add") => serde_json::json!({
+            "text": "Isolation Quick @Lisbon birthday:1990-04-02"
+        }),
+        ("POST", "/v1/tags") | ("PATCH", "/v1/tags/{id}") => {
+            serde_json::json!({ "name": unique("isolation") })
+        }
+        ("POST", "/v1/tags/import") => serde_json::json!({
+            "tags": [{ "name": unique("isolation"), "contact_emails": ["isolation@example.com"] }]
+        }),
+        ("POST", "/v1/tags/{tag_id}/contacts/bulk") | ("POST", "/v1/contacts/bulk-delete") => {
+            serde_json::json!({ "contact_ids": [id(0)] })
+        }
+        ("POST", "/v1/interactions") | ("PATCH", "/v1/interactions/{id}") => serde_json::json!({
+            "contact_id": id(0),
+            "interaction_date": "2024-01-01T12:00:00",
+            "notes": "isolation",
+            "interaction_type": "call",
+            "contact_ids": [id(1)]
+        }),
+        ("POST", "/v1/inbox") => serde_json::json!({ "text": "lunch w/ isolation yesterday" }),
+        ("POST", "/v1/inbox/{id}/resolve") => serde_json::json!({
+            "contact_id": id(0),
+            "interaction_type": "meeting",
+            "contact_ids": [id(1)]
+        }),
+        ("POST", "/v1/interaction-types") => serde_json::json!({ "name": unique("isolation") }),
+        ("POST", "/v1/occasions") | ("PATCH", "/v1/occasions/{id}") => serde_json::json!({
+            "contact_id": id(0),
+            "name": "isolation",
+            "date": "2024-01-01",
+            "recurring": false
+        }),
+        ("POST", "/v1/social-profiles") | ("PATCH", "/v1/social-profiles/{id}") => {
+            serde_json::json!({
+                "contact_id": id(0),
+                "platform": "github",
+                "url": format!("https://github.com/{}", unique("isolation"))
+            })
+        }
+        ("POST", "/v1/groups") | ("PATCH", "/v1/groups/{id}") => serde_json::json!({
+            "name": "isolation",
+            "description": "Isolation check"
+        }),
+        ("PUT", "/v1/groups/{id}/contacts") => serde_json::json!({ "contact_ids": [id(0)] }),
+        ("POST", "/v1/groups/{id}/interactions") => serde_json::json!({
+            "interaction_date": "2024-01-01T12:00:00",
+            "notes": "isolation",
+            "interaction_type": "meeting"
+        }),
+        ("POST", "/v1/gift-ideas") | ("PATCH", "/v1/gift-ideas/{id}") => serde_json::json!({
+            "contact_id": id(0),
+            "occasion_id": id(1),
+            "idea": "isolation",
+            "status": "purchased",
+            "price_cents": 1999
+        }),
+        ("POST", "/v1/filters") | ("PATCH", "/v1/filters/{id}") => serde_json::json!({
+            "name": "isolation",
+            "query": { "all_tag_ids": [id(0)], "last_interaction_older_than_days": 90 }
+        }),
+        ("POST", "/v1/goals") | ("PATCH", "/v1/goals/{id}") => serde_json::json!({
+            "name": "isolation",
+            "contact_id": id(0),
+            "interaction_type": "call",
+            "target_count": 2,
+            "period": "month"
+        }),
+        ("PUT", "/v1/preferences") => serde_json::json!({
+            "country": "US",
+            "quiet_weekdays": [7],
+            "greeting_tag_ids": [id(0)]
+        }),
+        ("POST", "/v1/shares") => serde_json::json!({
+            "email": caller.friend_email,
+            "tag_id": id(0),
+            "permission": "read"
+        }),
+        ("PATCH", "/v1/me") => serde_json::json!({ "name": "Isolation" }),
+        ("POST", "/v1/workspaces") | ("PATCH", "/v1/workspaces/{id}") => {
+            serde_json::json!({ "name": unique("isolation") })
+        }
+        ("POST", "/v1/hooks") => serde_json::json!({
+            "event": "new_interaction",
+            "target_url": "https://hooks.example.com/isolation"
+        }),
+        ("PUT", "/v1/export/schedule") => serde_json::json!({
+            "url": "https://backup.example.com/crm",
+            "interval_hours": 24
+        }),
+        ("POST", "/v1/interactions/{id}/attachments") => {
+            serde_json::json!({ "attachment": "isolation" })
+        }
+        ("POST", "/v1/push/subscriptions") => {
+            serde_json::to_value(push_subscription("https://push.example.com/isolation")).unwrap()
+        }
+        _ => return None,
+    };
+    Some(body)
+}
@@ -0,0 +1,76 @@
+//! Users' local calendar days.
+//!
+//! Timestamps are stored in UTC. Whenever a calculation needs a calendar day, such as "today"
+//! for reminders and occasions or the day an interaction happened on, it is read in the user's
+//! timezone preference, falling back to UTC when none is set.
+
+use crate::models::Preferences;
+use crate::repository::{RepoResult, Repository};
+use time::{Date, Duration, OffsetDateTime, UtcOffset};
+use time_tz::{OffsetDateTimeExt, PrimitiveDateTimeExt, TimeZone, Tz, timezones};
+
+/// Whether `name` is an IANA timezone the server knows
+pub fn is_known(name: &str) -> bool {
+    timezones::get_by_name(name).is_some()
+}
+
+/// Turns instants into calendar days in one user's timezone
+#[derive(Clone, Copy, Default)]
+pub struct LocalDates {
+    /// None for UTC
+    tz: Option<&'static Tz>,
+}
+
+impl LocalDates {
+    pub fn utc() -> Self {
+        LocalDates::default()
+    }
+
+    /// Unknown timezone names fall back to UTC
+    pub fn new(timezone: Option<&str>) -> Self {
+        LocalDates {
+            tz: timezone.and_then(timezones::get_by_name),
+        }
+    }
+
+    pub fn for_preferences(preferences: &Preferences) -> Self {
+        LocalDates::new(preferences.timezone.as_deref())
+    }
+
+    pub async fn for_user(repo: &dyn Repository, user_id: i32) -> RepoResult<Self> {
+        Ok(LocalDates::for_preferences(
+            &repo.get_preferences(user_id).await?,
+        ))
+    }
+
+    /// IANA name of the timezone, "UTC" when none is set
+    pub fn timezone_name(&self) -> &'static str {
+        self.tz.map_or("UTC", |tz| tz.name())
+    }
+
+    /// The user's calendar day at `at`
+    pub fn date_of(&self, at: OffsetDateTime) -> Date {
+        match self.tz {
+            Some(tz) => at.to_timezone(tz).date(),
+            None => at.to_offset(UtcOffset::UTC).date(),
+        }
+    }
+
+    /// The instant the user's `date` begins
+    pub fn start_of(&self, date: Date) -> OffsetDateTime {
+        let midnight = date.midnight();
+        let Some(tz) = self.tz else {
+            return midnight.assume_utc();
+        };
+        midnight
+            .assume_timezone(tz)
+            .take_first()
+            // Where the clocks go forward at midnight the day starts at one
+            .or_else(|| (midnight + Duration::HOUR).assume_timezone(tz).take_first())
+            .unwrap_or_else(|| midnight.assume_utc())
+    }
+
+    pub fn today(&self) -> Date {
+        self.date_of(OffsetDateTime::now_utc())
+    }
+}
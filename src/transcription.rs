@@ -0,0 +1,226 @@
+//! Transcripts of voice memos attached to interactions.
+//!
+//! Audio uploaded with `?transcribe=true` waits as pending until the `transcriptions` job sends
+//! it to the transcriber and appends the transcript to the interaction's notes. The transcriber
+//! is an OpenAI-compatible `/audio/transcriptions` endpoint set by `TRANSCRIPTION_URL`, hosted
+//! or local (whisper.cpp's server, faster-whisper-server); other services plug in by
+//! implementing `Transcriber`. Without one, uploads can't ask for a transcript.
+
+use crate::jobs::Job;
+use crate::repository::{RepoResult, Repository};
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How often pending transcriptions are picked up
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Attachments transcribed per run
+const BATCH_SIZE: i64 = 5;
+
+/// Attempts at an attachment before its transcription is marked failed
+pub const MAX_ATTEMPTS: i32 = 3;
+
+#[async_trait]
+pub trait Transcriber: Send + Sync {
+    /// The text spoken in the audio, or why it couldn't be transcribed
+    async fn transcribe(
+        &self,
+        file_name: &str,
+        content_type: &str,
+        audio: &[u8],
+    ) -> Result<String, String>;
+}
+
+/// Whether a file of this type can be sent for transcription
+pub fn is_audio(content_type: &str) -> bool {
+    content_type
+        .trim_start()
+        .to_ascii_lowercase()
+        .starts_with("audio/")
+}
+
+/// A Whisper model behind an OpenAI-compatible `/audio/transcriptions` endpoint
+pub struct WhisperTranscriber {
+    client: reqwest::Client,
+    url: String,
+    model: String,
+    api_key: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TranscriptionResponse {
+    text: String,
+}
+
+impl WhisperTranscriber {
+    pub fn new(url: String, model: String, api_key: Option<String>) -> Self {
+        WhisperTranscriber {
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(120))
+                .build()
+                .expect("Failed to build HTTP client"),
+            url,
+            model,
+            api_key,
+        }
+    }
+
+    /// The transcriber if TRANSCRIPTION_URL and TRANSCRIPTION_MODEL are set, with
+    /// TRANSCRIPTION_API_KEY sent as a bearer token when there is one. Off by default because
+    /// the recordings go to the provider.
+    pub fn from_env() -> Option<Self> {
+        let url = std::env::var("TRANSCRIPTION_URL").ok()?;
+        let Ok(model) = std::env::var("TRANSCRIPTION_MODEL") else {
+            eprintln!("TRANSCRIPTION_URL is set without TRANSCRIPTION_MODEL; not transcribing");
+            return None;
+        };
+        Some(WhisperTranscriber::new(
+            url,
+            model,
+            std::env::var("TRANSCRIPTION_API_KEY").ok(),
+        ))
+    }
+}
+
+/// A multipart/form-data body with the `model` and `file` fields the endpoint expects
+pub fn form_body(
+    boundary: &str,
+    model: &str,
+    file_name: &str,
+    content_type: &str,
+    audio: &[u8],
+) -> Vec<u8> {
+    // Quotes and line breaks would end the header early
+    let file_name: String = file_name
+        .chars()
+        .map(|c| if c == '"' || c.is_control() { '_' } else { c })
+        .collect();
+    let mut body = format!(
+        "--{boundary}\r\nContent-Disposition: form-data; name=\"model\"\r\n\r\n{model}\r\n\
+         --{boundary}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{file_name}\"\r\n\
+         Content-Type: {content_type}\r\n\r\n"
+    )
+    .into_bytes();
+    body.extend_from_slice(audio);
+    body.extend_from_slice(format!("\r\n--{boundary}--\r\n").as_bytes());
+    body
+}
+
+#[async_trait]
+impl Transcriber for WhisperTranscriber {
+    async fn transcribe(
+        &self,
+        file_name: &str,
+        content_type: &str,
+        audio: &[u8],
+    ) -> Result<String, String> {
+        let mut bytes = [0u8; 16];
+        getrandom::fill(&mut bytes).expect("Failed to read random bytes");
+        let boundary: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+
+        let mut request = self
+            .client
+            .post(&self.url)
+            .header(
+                reqwest::header::CONTENT_TYPE,
+                format!("multipart/form-data; boundary={}", boundary),
+            )
+            .body(form_body(
+                &boundary,
+                &self.model,
+                file_name,
+                content_type,
+                audio,
+            ));
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|e| format!("Request failed: {:?}", e))?;
+        if !response.status().is_success() {
+            return Err(format!("Returned status {}", response.status()));
+        }
+        let transcription: TranscriptionResponse = response
+            .json()
+            .await
+            .map_err(|e| format!("Unreadable response: {:?}", e))?;
+        Ok(transcription.text)
+    }
+}
+
+/// The configured transcriber. Registered as app data, which is how uploads know they may ask
+/// for transcripts, and as the job that writes them.
+#[derive(Clone)]
+pub struct Transcription {
+    transcriber: Arc<dyn Transcriber>,
+}
+
+impl Transcription {
+    pub fn new(transcriber: impl Transcriber + 'static) -> Self {
+        Transcription {
+            transcriber: Arc::new(transcriber),
+        }
+    }
+
+    /// Transcription when `WhisperTranscriber::from_env` configures a transcriber
+    pub fn from_env() -> Option<Self> {
+        WhisperTranscriber::from_env().map(Transcription::new)
+    }
+
+    /// Transcribe a batch of pending attachments into their interactions' notes, returning how
+    /// many were saved. Failures are tried again on later runs, up to `MAX_ATTEMPTS` in all;
+    /// audio with no speech in it fails straight away.
+    pub async fn transcribe_pending(&self, repo: &dyn Repository) -> RepoResult<usize> {
+        let mut saved = 0;
+        for pending in repo.pending_transcriptions(BATCH_SIZE).await? {
+            let result = self
+                .transcriber
+                .transcribe(&pending.file_name, &pending.content_type, &pending.data)
+                .await;
+            match result.map(|text| text.trim().to_string()) {
+                Ok(transcript) if !transcript.is_empty() => {
+                    if repo
+                        .save_transcript(pending.workspace_id, pending.attachment_id, &transcript)
+                        .await?
+                    {
+                        saved += 1;
+                    }
+                }
+                Ok(_) => {
+                    eprintln!("No speech in attachment {}", pending.attachment_id);
+                    repo.transcription_failed(pending.workspace_id, pending.attachment_id, true)
+                        .await?;
+                }
+                Err(e) => {
+                    eprintln!(
+                        "Transcribing attachment {} failed: {}",
+                        pending.attachment_id, e
+                    );
+                    let give_up = pending.attempts + 1 >= MAX_ATTEMPTS;
+                    repo.transcription_failed(pending.workspace_id, pending.attachment_id, give_up)
+                        .await?;
+                }
+            }
+        }
+        Ok(saved)
+    }
+}
+
+#[async_trait]
+impl Job for Transcription {
+    fn name(&self) -> &'static str {
+        "transcriptions"
+    }
+
+    fn interval(&self) -> Duration {
+        CHECK_INTERVAL
+    }
+
+    async fn run(&self, repo: &dyn Repository) -> RepoResult<()> {
+        self.transcribe_pending(repo).await.map(|_| ())
+    }
+}
@@ -0,0 +1,38 @@
+//! Authenticated users by Auth0 subject, so requests with a cached token don't look up the
+//! users table before doing any real work. Handlers that change or delete the user forget
+//! their entry, on every replica when the caches are in Redis; anything else is picked up once
+//! the short TTL runs out.
+
+use crate::AuthUser;
+use crate::cache::SharedCache;
+use std::time::Duration;
+
+/// How long a user is served from the cache before being read from the database again
+const USER_TTL: Duration = Duration::from_secs(60);
+
+pub struct UserCache {
+    /// auth0_id -> user
+    users: SharedCache<AuthUser>,
+}
+
+impl Default for UserCache {
+    fn default() -> Self {
+        UserCache {
+            users: SharedCache::new("user", USER_TTL),
+        }
+    }
+}
+
+impl UserCache {
+    pub async fn get(&self, auth0_id: &str) -> Option<AuthUser> {
+        self.users.get(auth0_id).await
+    }
+
+    pub async fn insert(&self, user: &AuthUser) {
+        self.users.insert(&user.auth0_id, user).await;
+    }
+
+    pub async fn forget(&self, auth0_id: &str) {
+        self.users.remove(auth0_id).await;
+    }
+}
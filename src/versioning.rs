@@ -0,0 +1,113 @@
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::http::Uri;
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::http::uri::PathAndQuery;
+use actix_web::middleware::Next;
+use actix_web::{Error, FromRequest, HttpMessage, HttpRequest, HttpResponse};
+use std::future::{Ready, ready};
+
+pub const API_VERSION_HEADER: HeaderName = HeaderName::from_static("api-version");
+
+/// Versions currently served under /v{n}
+pub const SUPPORTED_VERSIONS: &[u32] = &[1];
+
+/// Version assumed for unversioned paths sent without an Api-Version header
+pub const DEFAULT_VERSION: u32 = 1;
+
+/// Paths that live outside the versioned API and are never rewritten
+const UNVERSIONED_PREFIXES: &[&str] =
+    &["/health", "/api/", "/admin/", "/webhooks/email", "/public/"];
+
+/// The API version a request was routed to.
+/// Handlers shared between versions can extract this to keep older clients on the old shape.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ApiVersion(pub u32);
+
+impl FromRequest for ApiVersion {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let version = req
+            .extensions()
+            .get::<ApiVersion>()
+            .copied()
+            .unwrap_or(ApiVersion(DEFAULT_VERSION));
+        ready(Ok(version))
+    }
+}
+
+/// Parse "/v1/contacts" into (1, "/contacts")
+fn split_version_prefix(path: &str) -> Option<(u32, &str)> {
+    let rest = path.strip_prefix("/v")?;
+    let end = rest.find('/').unwrap_or(rest.len());
+    let version = rest[..end].parse().ok()?;
+    Some((version, &rest[end..]))
+}
+
+/// Parse an Api-Version header value, accepting both "1" and "v1"
+fn parse_version_header(value: &str) -> Option<u32> {
+    let value = value.trim();
+    value.strip_prefix('v').unwrap_or(value).parse().ok()
+}
+
+fn rewrite_path(req: &mut ServiceRequest, path: &str) {
+    let mut parts = req.head().uri.clone().into_parts();
+    let path_and_query = match req.query_string() {
+        "" => path.to_string(),
+        query => format!("{}?{}", path, query),
+    };
+    parts.path_and_query = Some(PathAndQuery::try_from(path_and_query).unwrap());
+
+    let uri = Uri::from_parts(parts).unwrap();
+    req.match_info_mut().get_mut().update(&uri);
+    req.head_mut().uri = uri;
+}
+
+/// Compatibility layer for the versioned API.
+/// Requests to /v{n}/... pass through untouched. Unversioned requests are routed to the
+/// version named by the Api-Version header (or DEFAULT_VERSION) so existing clients keep working.
+/// The resolved version is echoed back in the Api-Version response header.
+pub async fn api_version(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let path = req.path().to_string();
+
+    if UNVERSIONED_PREFIXES.iter().any(|p| path.starts_with(p)) {
+        return next.call(req).await.map(|res| res.map_into_boxed_body());
+    }
+
+    let version = match split_version_prefix(&path) {
+        Some((version, _)) => version,
+        None => {
+            let requested = req
+                .headers()
+                .get(API_VERSION_HEADER)
+                .map(|h| h.to_str().ok().and_then(parse_version_header));
+
+            let version = match requested {
+                None => DEFAULT_VERSION,
+                Some(Some(v)) if SUPPORTED_VERSIONS.contains(&v) => v,
+                Some(_) => {
+                    let response = HttpResponse::BadRequest().json(serde_json::json!({
+                        "error": "Unsupported Api-Version",
+                        "supported_versions": SUPPORTED_VERSIONS
+                    }));
+                    return Ok(req.into_response(response));
+                }
+            };
+
+            rewrite_path(&mut req, &format!("/v{}{}", version, path));
+            version
+        }
+    };
+
+    req.extensions_mut().insert(ApiVersion(version));
+
+    let mut res = next.call(req).await?;
+    res.headers_mut()
+        .insert(API_VERSION_HEADER, HeaderValue::from(version));
+    Ok(res.map_into_boxed_body())
+}
@@ -0,0 +1,170 @@
+//! Workspace handlers: separate address books under one account.
+//!
+//! Every contact, tag, interaction, occasion and saved filter lives in one workspace, and
+//! requests only see the workspace named by the `X-Workspace` header (the default one without
+//! it). Preferences, the profile and shares stay with the user.
+
+use crate::AuthUser;
+use crate::events::{self, Action, Entity, EventBus};
+use crate::models::{NewWorkspaceRequest, Workspace, WorkspacesResponse};
+use crate::mutations;
+use crate::reauth::{self, Reauth};
+use crate::repository::{self, RepoResult, Repository};
+use crate::routes::{Resource, ensure_owned};
+use actix_web::{HttpRequest, HttpResponse, Responder, delete, get, patch, post, web};
+
+/// Longest workspace name, as stored in workspaces.name
+pub const MAX_NAME_LEN: usize = 100;
+
+/// The trimmed name, or the 400 error body
+fn validated_name(request: &NewWorkspaceRequest) -> Result<&str, serde_json::Value> {
+    let name = request.name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LEN {
+        return Err(serde_json::json!({
+            "error": format!("Name must be 1 to {} characters", MAX_NAME_LEN)
+        }));
+    }
+    Ok(name)
+}
+
+/// One of the user's workspaces as listed
+async fn saved_workspace(
+    repo: &dyn Repository,
+    user_id: i32,
+    workspace_id: i32,
+) -> RepoResult<Option<Workspace>> {
+    Ok(repo
+        .list_workspaces(user_id)
+        .await?
+        .into_iter()
+        .find(|w| w.workspace_id == workspace_id))
+}
+
+fn name_taken() -> HttpResponse {
+    HttpResponse::Conflict().json(serde_json::json!({
+        "error": "A workspace with this name already exists"
+    }))
+}
+
+#[get("/workspaces")]
+pub async fn list_workspaces(
+    repo: web::Data<dyn Repository>,
+    auth_user: AuthUser,
+) -> impl Responder {
+    match repo.list_workspaces(auth_user.user_id).await {
+        Ok(workspaces) => HttpResponse::Ok().json(WorkspacesResponse { workspaces }),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to fetch workspaces")
+        }
+    }
+}
+
+#[post("/workspaces")]
+pub async fn create_workspace(
+    req: HttpRequest,
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    auth_user: AuthUser,
+    new_workspace: web::Json<NewWorkspaceRequest>,
+) -> impl Responder {
+    let name = match validated_name(&new_workspace) {
+        Ok(name) => name,
+        Err(error) => return HttpResponse::BadRequest().json(error),
+    };
+
+    match repo.create_workspace(auth_user.user_id, name).await {
+        Ok(workspace_id) => {
+            events::publish(
+                bus.as_ref(),
+                auth_user.user_id,
+                Entity::Workspace,
+                Action::Created,
+                vec![workspace_id],
+            );
+            mutations::created(
+                &req,
+                workspace_id,
+                saved_workspace(repo.get_ref(), auth_user.user_id, workspace_id).await,
+            )
+        }
+        Err(e) if repository::is_unique_violation(&e) => name_taken(),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to create workspace")
+        }
+    }
+}
+
+#[patch("/workspaces/{id}")]
+pub async fn rename_workspace(
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    auth_user: AuthUser,
+    workspace_id: web::Path<i32>,
+    updated_workspace: web::Json<NewWorkspaceRequest>,
+) -> impl Responder {
+    let id = workspace_id.into_inner();
+    let name = match validated_name(&updated_workspace) {
+        Ok(name) => name,
+        Err(error) => return HttpResponse::BadRequest().json(error),
+    };
+
+    match repo.rename_workspace(auth_user.user_id, id, name).await {
+        Ok(false) => HttpResponse::NotFound().body("Workspace not found"),
+        Ok(true) => {
+            events::publish(
+                bus.as_ref(),
+                auth_user.user_id,
+                Entity::Workspace,
+                Action::Updated,
+                vec![id],
+            );
+            mutations::updated(saved_workspace(repo.get_ref(), auth_user.user_id, id).await)
+        }
+        Err(e) if repository::is_unique_violation(&e) => name_taken(),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to rename workspace")
+        }
+    }
+}
+
+/// Delete a workspace with everything in it. The default workspace cannot be deleted. Needs a
+/// recent sign-in.
+#[delete("/workspaces/{id}")]
+pub async fn delete_workspace(
+    repo: web::Data<dyn Repository>,
+    bus: Option<web::Data<EventBus>>,
+    reauth: Option<web::Data<Reauth>>,
+    auth_user: AuthUser,
+    workspace_id: web::Path<i32>,
+) -> impl Responder {
+    if let Some(response) = reauth::configured(reauth.as_ref()).reject_stale(&auth_user) {
+        return response;
+    }
+    let id = workspace_id.into_inner();
+    if let Err(response) = ensure_owned(repo.get_ref(), &auth_user, Resource::Workspace, id).await {
+        return response;
+    }
+
+    match repo.delete_workspace(auth_user.user_id, id).await {
+        Ok(false) => HttpResponse::Conflict().json(serde_json::json!({
+            "error": "The default workspace cannot be deleted"
+        })),
+        Ok(true) => {
+            events::publish(
+                bus.as_ref(),
+                auth_user.user_id,
+                Entity::Workspace,
+                Action::Deleted,
+                vec![id],
+            );
+            HttpResponse::Ok().body("Workspace deleted successfully")
+        }
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to delete workspace")
+        }
+    }
+}
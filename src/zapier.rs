@@ -0,0 +1,290 @@
+//! Triggers for Zapier and similar automation services.
+//!
+//! Each trigger has a polling list, newest first with an `id` on every item, and can be
+//! subscribed to as a REST hook: `POST /hooks` with an `event` and a `target_url`, which is then
+//! POSTed a JSON array of new items in the same shape as the list, and `DELETE /hooks/{id}` to
+//! unsubscribe. New contacts and interactions are sent as they are created; upcoming occasions
+//! once a day, when they come within `UPCOMING_DAYS`. Deliveries go through the `outbox`, which
+//! retries failures and unsubscribes a target answering 410 Gone.
+
+use crate::AuthUser;
+use crate::jobs::Job;
+use crate::link_preview::is_fetchable;
+use crate::models::{
+    Contact, HookEvent, Interaction, NewRestHookRequest, Occasion, RestHook, TriggerItem,
+    UpcomingOccasion,
+};
+use crate::mutations;
+use crate::reminders::next_occurrence;
+use crate::repository::{RepoResult, Repository};
+use crate::routes::{Resource, ensure_owned};
+use crate::timezone::LocalDates;
+use actix_web::{HttpRequest, HttpResponse, Responder, delete, get, post, web};
+use async_trait::async_trait;
+use std::time::Duration;
+use time::Date;
+
+/// Items in a trigger list; Zapier only looks at the newest
+pub const POLL_LIMIT: i64 = 100;
+
+/// How far ahead an occasion is upcoming
+pub const UPCOMING_DAYS: i64 = 7;
+
+/// How often the background task looks for hooks owed upcoming occasions
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+pub fn contact_items(contacts: Vec<Contact>) -> Vec<TriggerItem<Contact>> {
+    contacts
+        .into_iter()
+        .map(|contact| TriggerItem {
+            id: contact.contact_id.to_string(),
+            item: contact,
+        })
+        .collect()
+}
+
+pub fn interaction_items(interactions: Vec<Interaction>) -> Vec<TriggerItem<Interaction>> {
+    interactions
+        .into_iter()
+        .map(|interaction| TriggerItem {
+            id: interaction.interaction_id.to_string(),
+            item: interaction,
+        })
+        .collect()
+}
+
+/// Occasions next occurring after `after` and on or before `until`, latest first. Each
+/// occurrence has its own id, so a yearly occasion triggers again next year.
+pub fn upcoming_items(
+    occasions: Vec<Occasion>,
+    today: Date,
+    after: Date,
+    until: Date,
+) -> Vec<TriggerItem<UpcomingOccasion>> {
+    let mut items: Vec<TriggerItem<UpcomingOccasion>> = occasions
+        .into_iter()
+        .filter_map(|occasion| {
+            let occurs_on = next_occurrence(&occasion, today)?;
+            (after < occurs_on && occurs_on <= until).then(|| TriggerItem {
+                id: format!("{}-{}", occasion.occasion_id, occurs_on),
+                item: UpcomingOccasion {
+                    occurs_on,
+                    occasion,
+                },
+            })
+        })
+        .collect();
+    items.sort_by(|a, b| {
+        (b.item.occurs_on, b.item.occasion.occasion_id)
+            .cmp(&(a.item.occurs_on, a.item.occasion.occasion_id))
+    });
+    items
+}
+
+/// The workspace's occasions
+async fn workspace_occasions(
+    repo: &dyn Repository,
+    workspace_id: i32,
+) -> RepoResult<Vec<Occasion>> {
+    let contact_ids: Vec<i32> = repo
+        .list_contacts(workspace_id)
+        .await?
+        .iter()
+        .map(|c| c.contact_id)
+        .collect();
+    repo.occasions_for_contacts(&contact_ids).await
+}
+
+/// Queues upcoming occasions for REST hooks
+pub struct UpcomingHooks;
+
+impl UpcomingHooks {
+    /// Queue for each upcoming occasion hook the occasions that came within `UPCOMING_DAYS`
+    /// since it was last sent any, once per day of its user, returning how many hooks were
+    /// queued items. A new hook gets only those that came within reach today.
+    pub async fn queue_due(&self, repo: &dyn Repository) -> RepoResult<usize> {
+        let mut queued = 0;
+        for hook in repo
+            .rest_hooks_for_event(HookEvent::UpcomingOccasion, None)
+            .await?
+        {
+            let today = LocalDates::for_user(repo, hook.user_id).await?.today();
+            let last = hook.delivered_on.unwrap_or(today - time::Duration::days(1));
+            if last >= today {
+                continue;
+            }
+            let lead = time::Duration::days(UPCOMING_DAYS);
+            let items = upcoming_items(
+                workspace_occasions(repo, hook.workspace_id).await?,
+                today,
+                last + lead,
+                today + lead,
+            );
+            if items.is_empty() {
+                repo.record_rest_hook_delivery(hook.hook_id, today).await?;
+                continue;
+            }
+            let items =
+                serde_json::to_value(&items).map_err(|e| sqlx::Error::Decode(Box::new(e)))?;
+            repo.queue_upcoming_occasions(hook.workspace_id, hook.hook_id, &items, today)
+                .await?;
+            queued += 1;
+        }
+        Ok(queued)
+    }
+}
+
+/// Queues upcoming occasions every `CHECK_INTERVAL`
+#[async_trait]
+impl Job for UpcomingHooks {
+    fn name(&self) -> &'static str {
+        "upcoming-occasion-hooks"
+    }
+
+    fn interval(&self) -> Duration {
+        CHECK_INTERVAL
+    }
+
+    async fn run(&self, repo: &dyn Repository) -> RepoResult<()> {
+        self.queue_due(repo).await.map(|_| ())
+    }
+}
+
+/// The workspace's newest contacts, for the new contact trigger
+#[get("/triggers/new-contact")]
+pub async fn new_contact_trigger(
+    repo: web::Data<dyn Repository>,
+    auth_user: AuthUser,
+) -> impl Responder {
+    match repo
+        .latest_contacts(auth_user.workspace_id, POLL_LIMIT)
+        .await
+    {
+        Ok(contacts) => HttpResponse::Ok().json(contact_items(contacts)),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to fetch contacts")
+        }
+    }
+}
+
+/// The workspace's newest interactions, for the new interaction trigger
+#[get("/triggers/new-interaction")]
+pub async fn new_interaction_trigger(
+    repo: web::Data<dyn Repository>,
+    auth_user: AuthUser,
+) -> impl Responder {
+    match repo
+        .latest_interactions(auth_user.workspace_id, POLL_LIMIT)
+        .await
+    {
+        Ok(interactions) => HttpResponse::Ok().json(interaction_items(interactions)),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to fetch interactions")
+        }
+    }
+}
+
+/// Occasions in the next `UPCOMING_DAYS` days, the latest to come within reach first
+#[get("/triggers/upcoming-occasion")]
+pub async fn upcoming_occasion_trigger(
+    repo: web::Data<dyn Repository>,
+    auth_user: AuthUser,
+) -> impl Responder {
+    let occasions = async {
+        let today = LocalDates::for_user(repo.get_ref(), auth_user.user_id)
+            .await?
+            .today();
+        let occasions = workspace_occasions(repo.get_ref(), auth_user.workspace_id).await?;
+        Ok::<_, sqlx::Error>(upcoming_items(
+            occasions,
+            today,
+            today - time::Duration::days(1),
+            today + time::Duration::days(UPCOMING_DAYS),
+        ))
+    };
+    match occasions.await {
+        Ok(items) => HttpResponse::Ok().json(items),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to fetch occasions")
+        }
+    }
+}
+
+#[get("/hooks")]
+pub async fn list_hooks(repo: web::Data<dyn Repository>, auth_user: AuthUser) -> impl Responder {
+    match repo.list_rest_hooks(auth_user.workspace_id).await {
+        Ok(hooks) => HttpResponse::Ok().json(hooks),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to fetch hooks")
+        }
+    }
+}
+
+async fn saved_hook(
+    repo: &dyn Repository,
+    workspace_id: i32,
+    hook_id: i32,
+) -> RepoResult<Option<RestHook>> {
+    Ok(repo
+        .list_rest_hooks(workspace_id)
+        .await?
+        .into_iter()
+        .find(|h| h.hook_id == hook_id))
+}
+
+/// Subscribe a URL to one of the workspace's triggers
+#[post("/hooks")]
+pub async fn subscribe_hook(
+    req: HttpRequest,
+    repo: web::Data<dyn Repository>,
+    auth_user: AuthUser,
+    new_hook: web::Json<NewRestHookRequest>,
+) -> impl Responder {
+    if !reqwest::Url::parse(&new_hook.target_url).is_ok_and(|url| is_fetchable(&url)) {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "target_url must be an https URL on a public host name"
+        }));
+    }
+
+    match repo
+        .create_rest_hook(auth_user.workspace_id, &new_hook)
+        .await
+    {
+        Ok(hook_id) => mutations::created(
+            &req,
+            hook_id,
+            saved_hook(repo.get_ref(), auth_user.workspace_id, hook_id).await,
+        ),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to subscribe hook")
+        }
+    }
+}
+
+#[delete("/hooks/{id}")]
+pub async fn unsubscribe_hook(
+    repo: web::Data<dyn Repository>,
+    auth_user: AuthUser,
+    hook_id: web::Path<i32>,
+) -> impl Responder {
+    let id = hook_id.into_inner();
+
+    // Verify the hook belongs to the user
+    if let Err(response) = ensure_owned(repo.get_ref(), &auth_user, Resource::RestHook, id).await {
+        return response;
+    }
+
+    match repo.delete_rest_hook(auth_user.workspace_id, id).await {
+        Ok(false) => HttpResponse::NotFound().body("Hook not found"),
+        Ok(true) => HttpResponse::Ok().body("Hook unsubscribed successfully"),
+        Err(e) => {
+            eprintln!("Database error: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to unsubscribe hook")
+        }
+    }
+}
@@ -0,0 +1,305 @@
+mod common;
+
+use actix_web::{App, test, web};
+use common::*;
+use personal_crm::account::{self, DeletionGrace};
+use personal_crm::repository::{
+    self, InMemoryRepository, PgRepository, Repository, SqliteRepository,
+};
+use personal_crm::routes::v1_routes;
+use personal_crm::test_support::{provision, register_token};
+use personal_crm::user_cache::UserCache;
+use serde_json::{Value, json};
+use time::{Duration, OffsetDateTime};
+
+/// Test reading and changing the profile through /me, including the shared timezone
+#[actix_rt::test]
+async fn test_me() {
+    register_token("token-me-owner", "test|me-owner").await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(repository::app_data(InMemoryRepository::new()))
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let auth = ("Authorization", "Bearer token-me-owner");
+
+    let req = test::TestRequest::get()
+        .uri("/v1/me")
+        .insert_header(auth)
+        .to_request();
+    let me: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(me["email"], "test|me-owner@unknown.local");
+    assert_eq!(me["timezone"], Value::Null);
+    assert_eq!(
+        me["notifications"],
+        json!({"email": true, "push": false, "weekly_review": false})
+    );
+    assert_eq!(me["default_reminder_cadence_days"], 30);
+
+    let req = test::TestRequest::patch()
+        .uri("/v1/me")
+        .insert_header(auth)
+        .set_json(json!({
+            "name": "  Grace Hopper ",
+            "timezone": "Europe/London",
+            "notifications": {"email": false, "push": true},
+            "default_reminder_cadence_days": 14
+        }))
+        .to_request();
+    let me: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(me["name"], "Grace Hopper");
+    assert_eq!(me["email"], "test|me-owner@unknown.local");
+
+    let req = test::TestRequest::get()
+        .uri("/v1/preferences")
+        .insert_header(auth)
+        .to_request();
+    let preferences: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(preferences["timezone"], "Europe/London");
+
+    // Only the fields sent change; an explicit null clears the timezone
+    let req = test::TestRequest::patch()
+        .uri("/v1/me")
+        .insert_header(auth)
+        .set_json(json!({"timezone": null}))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+
+    let req = test::TestRequest::get()
+        .uri("/v1/me")
+        .insert_header(auth)
+        .to_request();
+    let me: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(me["name"], "Grace Hopper");
+    assert_eq!(me["timezone"], Value::Null);
+    assert_eq!(
+        me["notifications"],
+        json!({"email": false, "push": true, "weekly_review": false})
+    );
+    assert_eq!(me["default_reminder_cadence_days"], 14);
+}
+
+/// Test that invalid changes and another user's email are rejected
+#[actix_rt::test]
+async fn test_me_validation() {
+    register_token("token-me-checker", "test|me-checker").await;
+    register_token("token-me-other", "test|me-other").await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(repository::app_data(InMemoryRepository::new()))
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let update = |token: &str, body: Value| {
+        test::TestRequest::patch()
+            .uri("/v1/me")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .set_json(body)
+            .to_request()
+    };
+
+    for body in [
+        json!({"name": " "}),
+        json!({"email": "not an address"}),
+        json!({"timezone": "Mars/Olympus"}),
+        json!({"default_reminder_cadence_days": 0}),
+    ] {
+        let res = test::call_service(&app, update("token-me-checker", body.clone())).await;
+        assert_eq!(res.status(), 400, "{}", body);
+    }
+
+    let res = test::call_service(
+        &app,
+        update("token-me-other", json!({"email": "shared@example.com"})),
+    )
+    .await;
+    assert_eq!(res.status(), 200);
+    let res = test::call_service(
+        &app,
+        update("token-me-checker", json!({"email": "shared@example.com"})),
+    )
+    .await;
+    assert_eq!(res.status(), 409);
+}
+
+/// Test that deleting the account without a grace period drops the cached user, so the next
+/// request starts afresh
+#[actix_rt::test]
+async fn test_user_cache_forgets_deleted_account() {
+    register_token("token-me-cached", "test|me-cached").await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(repository::app_data(InMemoryRepository::new()))
+            .app_data(web::Data::new(UserCache::default()))
+            .app_data(web::Data::new(DeletionGrace { days: 0 }))
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let auth = ("Authorization", "Bearer token-me-cached");
+    let me = async || {
+        let req = test::TestRequest::get()
+            .uri("/v1/me")
+            .insert_header(auth)
+            .to_request();
+        test::call_and_read_body_json::<_, _, Value>(&app, req).await
+    };
+
+    let before = me().await;
+    assert_eq!(me().await["user_id"], before["user_id"]);
+
+    let req = test::TestRequest::delete()
+        .uri("/v1/account")
+        .insert_header(auth)
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 204);
+
+    let after = me().await;
+    assert_ne!(after["user_id"], before["user_id"]);
+}
+
+/// Check that a scheduled deletion deactivates the user, is undone by restoring or
+/// reactivating them, and deletes them once due
+async fn check_scheduled_deletion(repo: web::Data<dyn Repository>) {
+    let tenant = provision(repo.get_ref(), "deletion").await.unwrap();
+    let other = provision(repo.get_ref(), "deletion-other").await.unwrap();
+    let auth0_id = tenant.auth0_id.clone();
+    let now = OffsetDateTime::now_utc();
+    let delete_after = now + Duration::days(30);
+
+    assert!(!repo.restore_user(tenant.user_id).await.unwrap());
+    repo.schedule_user_deletion(tenant.user_id, delete_after)
+        .await
+        .unwrap();
+    let user = repo.find_user(&auth0_id).await.unwrap().unwrap();
+    assert!(user.deactivated);
+    assert_eq!(
+        user.delete_after.map(OffsetDateTime::unix_timestamp),
+        Some(delete_after.unix_timestamp())
+    );
+    assert!(repo.restore_user(tenant.user_id).await.unwrap());
+    let user = repo.find_user(&auth0_id).await.unwrap().unwrap();
+    assert!(!user.deactivated);
+    assert_eq!(user.delete_after, None);
+
+    // Reactivating cancels the deletion too
+    repo.schedule_user_deletion(tenant.user_id, delete_after)
+        .await
+        .unwrap();
+    repo.set_deactivated(tenant.user_id, false).await.unwrap();
+    let user = repo.find_user(&auth0_id).await.unwrap().unwrap();
+    assert_eq!(user.delete_after, None);
+
+    repo.schedule_user_deletion(tenant.user_id, delete_after)
+        .await
+        .unwrap();
+    assert!(repo.purge_deleted_users(now).await.unwrap().is_empty());
+    assert!(repo.find_user(&auth0_id).await.unwrap().is_some());
+    let purged = repo
+        .purge_deleted_users(delete_after + Duration::seconds(1))
+        .await
+        .unwrap();
+    assert!(purged.contains(&auth0_id));
+    assert!(repo.find_user(&auth0_id).await.unwrap().is_none());
+    assert!(
+        repo.get_contact(tenant.workspace_id, tenant.contact_id)
+            .await
+            .unwrap()
+            .is_none()
+    );
+    assert!(
+        repo.get_contact(other.workspace_id, other.contact_id)
+            .await
+            .unwrap()
+            .is_some()
+    );
+}
+
+#[actix_rt::test]
+async fn test_scheduled_deletion_in_memory() {
+    check_scheduled_deletion(repository::app_data(InMemoryRepository::new())).await;
+}
+
+#[actix_rt::test]
+async fn test_scheduled_deletion_in_postgres() {
+    let ctx = setup_test_db().await;
+    check_scheduled_deletion(repository::app_data(PgRepository::new(ctx.pool.clone()))).await;
+}
+
+#[actix_rt::test]
+async fn test_scheduled_deletion_in_sqlite() {
+    let repo = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+    check_scheduled_deletion(repository::app_data(repo)).await;
+}
+
+/// Test that a deleted account is refused until restored, and is gone once the purge runs
+/// after the grace period
+#[actix_rt::test]
+async fn test_account_deletion_grace_period() {
+    register_token("token-me-deleting", "test|me-deleting").await;
+    let repo = repository::app_data(InMemoryRepository::new());
+
+    let app = test::init_service(
+        App::new()
+            .app_data(repo.clone())
+            .app_data(web::Data::new(UserCache::default()))
+            .app_data(web::Data::new(DeletionGrace { days: 14 }))
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let auth = ("Authorization", "Bearer token-me-deleting");
+    let call = async |req: test::TestRequest| {
+        test::call_service(&app, req.insert_header(auth).to_request()).await
+    };
+    let delete = async || {
+        let before = OffsetDateTime::now_utc();
+        let res = call(test::TestRequest::delete().uri("/v1/account")).await;
+        assert_eq!(res.status(), 202);
+        let body: Value = test::read_body_json(res).await;
+        let delete_after = OffsetDateTime::parse(
+            body["delete_after"].as_str().unwrap(),
+            &time::format_description::well_known::Rfc3339,
+        )
+        .unwrap();
+        assert!(delete_after >= before + Duration::days(14) - Duration::seconds(1));
+        assert!(delete_after <= before + Duration::days(14) + Duration::minutes(1));
+    };
+
+    let res = call(test::TestRequest::get().uri("/v1/me")).await;
+    let user_id = test::read_body_json::<Value, _>(res).await["user_id"].clone();
+
+    delete().await;
+    let res = call(test::TestRequest::get().uri("/v1/me")).await;
+    assert_eq!(res.status(), 403);
+    let body = String::from_utf8(test::read_body(res).await.to_vec()).unwrap();
+    assert!(body.contains("/account/restore"), "{}", body);
+
+    let res = call(test::TestRequest::post().uri("/v1/account/restore")).await;
+    assert_eq!(res.status(), 204);
+    let res = call(test::TestRequest::get().uri("/v1/me")).await;
+    assert_eq!(res.status(), 200);
+    assert_eq!(
+        test::read_body_json::<Value, _>(res).await["user_id"],
+        user_id
+    );
+
+    delete().await;
+    let now = OffsetDateTime::now_utc();
+    assert_eq!(account::purge_due(repo.get_ref(), now).await.unwrap(), 0);
+    assert_eq!(
+        account::purge_due(repo.get_ref(), now + Duration::days(15))
+            .await
+            .unwrap(),
+        1
+    );
+    // The next sign-in starts a new account
+    let res = call(test::TestRequest::get().uri("/v1/me")).await;
+    assert_eq!(res.status(), 200);
+    assert_ne!(
+        test::read_body_json::<Value, _>(res).await["user_id"],
+        user_id
+    );
+}
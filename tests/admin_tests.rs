@@ -0,0 +1,94 @@
+use actix_web::{App, test, web};
+use personal_crm::admin::{self, Admins};
+use personal_crm::repository::{self, InMemoryRepository};
+use personal_crm::routes::v1_routes;
+use personal_crm::test_support::register_token;
+use personal_crm::user_cache::UserCache;
+use serde_json::{Value, json};
+
+/// Test that only admins reach the admin endpoints, and that a deactivated user is refused
+/// until reactivated, even while cached
+#[actix_rt::test]
+async fn test_admin_deactivates_users() {
+    register_token("token-admin-admin", "test|admin-admin").await;
+    register_token("token-admin-member", "test|admin-member").await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(repository::app_data(InMemoryRepository::new()))
+            .app_data(web::Data::new(UserCache::default()))
+            .app_data(web::Data::new(Admins::new(
+                ["test|admin-admin".to_string()],
+            )))
+            .service(admin::list_users)
+            .service(admin::instance_stats)
+            .service(admin::deactivate_user)
+            .service(admin::reactivate_user)
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let admin = ("Authorization", "Bearer token-admin-admin");
+    let member = ("Authorization", "Bearer token-admin-member");
+
+    let req = test::TestRequest::post()
+        .uri("/v1/contacts")
+        .insert_header(member)
+        .set_json(json!({"first_name": "Ada"}))
+        .to_request();
+    assert!(test::call_service(&app, req).await.status().is_success());
+
+    for uri in ["/admin/users", "/admin/stats"] {
+        let req = test::TestRequest::get()
+            .uri(uri)
+            .insert_header(member)
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 403);
+    }
+
+    let req = test::TestRequest::get()
+        .uri("/admin/users")
+        .insert_header(admin)
+        .to_request();
+    let users: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(users.as_array().unwrap().len(), 2);
+    assert_eq!(users[0]["auth0_id"], "test|admin-member");
+    assert_eq!(users[0]["contact_count"], 1);
+    assert_eq!(users[0]["workspace_count"], 1);
+    assert_eq!(users[0]["deactivated"], false);
+    let member_id = users[0]["user_id"].as_i64().unwrap();
+    let admin_id = users[1]["user_id"].as_i64().unwrap();
+
+    for (id, status) in [(admin_id, 400), (9999, 404), (member_id, 204)] {
+        let req = test::TestRequest::post()
+            .uri(&format!("/admin/users/{}/deactivate", id))
+            .insert_header(admin)
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), status);
+    }
+
+    let req = test::TestRequest::get()
+        .uri("/v1/contacts")
+        .insert_header(member)
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 403);
+
+    let req = test::TestRequest::get()
+        .uri("/admin/stats")
+        .insert_header(admin)
+        .to_request();
+    let stats: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(stats["users"], 2);
+    assert_eq!(stats["deactivated_users"], 1);
+    assert_eq!(stats["contacts"], 1);
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/admin/users/{}/reactivate", member_id))
+        .insert_header(admin)
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 204);
+    let req = test::TestRequest::get()
+        .uri("/v1/contacts")
+        .insert_header(member)
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+}
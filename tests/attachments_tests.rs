@@ -0,0 +1,328 @@
+mod common;
+
+use actix_web::{App, test as actix_test, web};
+use async_trait::async_trait;
+use common::*;
+use personal_crm::limits::Limits;
+use personal_crm::models::{NewAttachment, TranscriptionStatus};
+use personal_crm::repository::{
+    self, InMemoryRepository, PgRepository, Repository, SqliteRepository,
+};
+use personal_crm::routes::v1_routes;
+use personal_crm::test_support::provision;
+use personal_crm::transcription::{MAX_ATTEMPTS, Transcriber, Transcription, form_body};
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+
+/// Test the multipart body sent to the transcription endpoint
+#[test]
+fn test_form_body() {
+    let body = form_body(
+        "b0undary",
+        "whisper-1",
+        "memo \"1\".m4a",
+        "audio/mp4",
+        b"RIFF",
+    );
+    assert_eq!(
+        String::from_utf8(body).unwrap(),
+        "--b0undary\r\nContent-Disposition: form-data; name=\"model\"\r\n\r\nwhisper-1\r\n\
+         --b0undary\r\nContent-Disposition: form-data; name=\"file\"; filename=\"memo _1_.m4a\"\r\n\
+         Content-Type: audio/mp4\r\n\r\nRIFF\r\n--b0undary--\r\n"
+    );
+}
+
+/// Check uploading, listing, downloading and deleting attachments, and the upload limits
+async fn check_attachments(repo: web::Data<dyn Repository>) {
+    let owner = provision(repo.get_ref(), "attachments").await.unwrap();
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(repo.clone())
+            .app_data(web::Data::new(Limits {
+                max_attachment_bytes: 16,
+                ..Limits::default()
+            }))
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let auth = ("Authorization", format!("Bearer {}", owner.token));
+    let upload = |query: &str, content_type: &str, body: &'static [u8]| {
+        actix_test::TestRequest::post()
+            .uri(&format!(
+                "/v1/interactions/{}/attachments{}",
+                owner.interaction_id, query
+            ))
+            .insert_header(auth.clone())
+            .insert_header(("Content-Type", content_type.to_string()))
+            .set_payload(body)
+            .to_request()
+    };
+
+    let res = actix_test::call_service(&app, upload("", "text/plain", b"0123456789abcdefg")).await;
+    assert_eq!(res.status(), 413);
+    let res = actix_test::call_service(&app, upload("", "text/plain", b"")).await;
+    assert_eq!(res.status(), 400);
+    let res = actix_test::call_service(&app, upload("", "not a type", b"memo")).await;
+    assert_eq!(res.status(), 400);
+    // No transcriber is configured
+    let res =
+        actix_test::call_service(&app, upload("?transcribe=true", "audio/ogg", b"memo")).await;
+    assert_eq!(res.status(), 400);
+
+    let created: Value = actix_test::call_and_read_body_json(
+        &app,
+        upload(
+            "?file_name=..%2Fnotes%2Fcaf%C3%A9.ogg",
+            "audio/ogg",
+            b"memo",
+        ),
+    )
+    .await;
+    let attachment_id = created["attachment_id"].as_i64().unwrap();
+
+    let req = actix_test::TestRequest::get()
+        .uri(&format!(
+            "/v1/interactions/{}/attachments",
+            owner.interaction_id
+        ))
+        .insert_header(auth.clone())
+        .to_request();
+    let listed: Vec<Value> = actix_test::call_and_read_body_json(&app, req).await;
+    assert_eq!(listed.len(), 2);
+    assert_eq!(listed[0]["attachment_id"], owner.attachment_id);
+    assert_eq!(listed[1]["attachment_id"], attachment_id);
+    assert_eq!(listed[1]["file_name"], "café.ogg");
+    assert_eq!(listed[1]["content_type"], "audio/ogg");
+    assert_eq!(listed[1]["size_bytes"], 4);
+    assert_eq!(listed[1]["transcription"], Value::Null);
+
+    let download = || {
+        actix_test::TestRequest::get()
+            .uri(&format!("/v1/attachments/{}", attachment_id))
+            .insert_header(auth.clone())
+            .to_request()
+    };
+    let res = actix_test::call_service(&app, download()).await;
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.headers().get("Content-Type").unwrap(), "audio/ogg");
+    let disposition = res
+        .headers()
+        .get("Content-Disposition")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string();
+    assert!(disposition.starts_with("attachment"));
+    assert!(disposition.contains("filename=\"caf_.ogg\""));
+    assert!(disposition.contains("filename*=UTF-8''caf%C3%A9.ogg"));
+    assert_eq!(actix_test::read_body(res).await, "memo");
+
+    let delete = || {
+        actix_test::TestRequest::delete()
+            .uri(&format!("/v1/attachments/{}", attachment_id))
+            .insert_header(auth.clone())
+            .to_request()
+    };
+    assert_eq!(actix_test::call_service(&app, delete()).await.status(), 200);
+    assert_eq!(actix_test::call_service(&app, delete()).await.status(), 404);
+    assert_eq!(
+        actix_test::call_service(&app, download()).await.status(),
+        404
+    );
+
+    // Attachments go with their interaction
+    repo.delete_interaction(owner.workspace_id, owner.interaction_id)
+        .await
+        .unwrap();
+    assert!(
+        !repo
+            .owns_attachment(owner.workspace_id, owner.attachment_id)
+            .await
+            .unwrap()
+    );
+}
+
+#[actix_rt::test]
+async fn test_attachments_in_memory() {
+    check_attachments(repository::app_data(InMemoryRepository::new())).await;
+}
+
+#[actix_rt::test]
+async fn test_attachments_in_postgres() {
+    let ctx = setup_test_db().await;
+    check_attachments(repository::app_data(PgRepository::new(ctx.pool.clone()))).await;
+}
+
+#[actix_rt::test]
+async fn test_attachments_in_sqlite() {
+    let repo = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+    check_attachments(repository::app_data(repo)).await;
+}
+
+/// Transcribes every file as `text`, or fails when there is none
+#[derive(Clone, Default)]
+struct FakeTranscriber {
+    text: Option<String>,
+    calls: Arc<Mutex<Vec<Vec<u8>>>>,
+}
+
+#[async_trait]
+impl Transcriber for FakeTranscriber {
+    async fn transcribe(
+        &self,
+        _file_name: &str,
+        _content_type: &str,
+        audio: &[u8],
+    ) -> Result<String, String> {
+        self.calls.lock().unwrap().push(audio.to_vec());
+        self.text.clone().ok_or_else(|| "Unavailable".to_string())
+    }
+}
+
+fn voice_memo(data: &[u8]) -> NewAttachment {
+    NewAttachment {
+        file_name: "memo.ogg".to_string(),
+        content_type: "audio/ogg".to_string(),
+        data: data.to_vec(),
+        transcribe: true,
+    }
+}
+
+/// Check that the job appends transcripts to the interaction's notes once, and gives up on
+/// audio the transcriber keeps failing on
+async fn check_transcriptions(repo: web::Data<dyn Repository>) {
+    let owner = provision(repo.get_ref(), "transcripts").await.unwrap();
+    let transcriber = FakeTranscriber {
+        text: Some(" Call Ada about the trip. ".to_string()),
+        ..FakeTranscriber::default()
+    };
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(repo.clone())
+            .app_data(web::Data::new(Transcription::new(transcriber.clone())))
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let auth = ("Authorization", format!("Bearer {}", owner.token));
+
+    // Only audio can be transcribed
+    let req = actix_test::TestRequest::post()
+        .uri(&format!(
+            "/v1/interactions/{}/attachments?transcribe=true",
+            owner.interaction_id
+        ))
+        .insert_header(auth.clone())
+        .insert_header(("Content-Type", "text/plain"))
+        .set_payload("memo")
+        .to_request();
+    assert_eq!(actix_test::call_service(&app, req).await.status(), 400);
+    let req = actix_test::TestRequest::post()
+        .uri(&format!(
+            "/v1/interactions/{}/attachments?transcribe=true&file_name=memo.ogg",
+            owner.interaction_id
+        ))
+        .insert_header(auth.clone())
+        .insert_header(("Content-Type", "audio/ogg; codecs=opus"))
+        .set_payload("first memo")
+        .to_request();
+    let created: Value = actix_test::call_and_read_body_json(&app, req).await;
+    let attachment_id = created["attachment_id"].as_i64().unwrap() as i32;
+    let listed = repo
+        .list_attachments(owner.workspace_id, owner.interaction_id)
+        .await
+        .unwrap();
+    assert_eq!(listed[1].transcription, Some(TranscriptionStatus::Pending));
+
+    let transcription = Transcription::new(transcriber.clone());
+    assert_eq!(
+        transcription
+            .transcribe_pending(repo.get_ref())
+            .await
+            .unwrap(),
+        1
+    );
+    assert_eq!(
+        transcription
+            .transcribe_pending(repo.get_ref())
+            .await
+            .unwrap(),
+        0
+    );
+    assert_eq!(
+        *transcriber.calls.lock().unwrap(),
+        vec![b"first memo".to_vec()]
+    );
+    let interaction = repo
+        .interactions_by_id(owner.workspace_id, &[owner.interaction_id])
+        .await
+        .unwrap()
+        .remove(0);
+    assert_eq!(
+        interaction.notes.unwrap(),
+        format!("{}\n\nCall Ada about the trip.", owner.marker)
+    );
+    let listed = repo
+        .list_attachments(owner.workspace_id, owner.interaction_id)
+        .await
+        .unwrap();
+    assert_eq!(listed[1].attachment_id, attachment_id);
+    assert_eq!(listed[1].transcription, Some(TranscriptionStatus::Done));
+
+    let failing = FakeTranscriber::default();
+    let attachment_id = repo
+        .create_attachment(
+            owner.workspace_id,
+            owner.interaction_id,
+            &voice_memo(b"second memo"),
+        )
+        .await
+        .unwrap();
+    let transcription = Transcription::new(failing.clone());
+    for _ in 0..MAX_ATTEMPTS + 1 {
+        assert_eq!(
+            transcription
+                .transcribe_pending(repo.get_ref())
+                .await
+                .unwrap(),
+            0
+        );
+    }
+    assert_eq!(failing.calls.lock().unwrap().len(), MAX_ATTEMPTS as usize);
+    let listed = repo
+        .list_attachments(owner.workspace_id, owner.interaction_id)
+        .await
+        .unwrap();
+    assert_eq!(listed[2].attachment_id, attachment_id);
+    assert_eq!(listed[2].transcription, Some(TranscriptionStatus::Failed));
+
+    // A memo deleted before its turn is never sent
+    let attachment_id = repo
+        .create_attachment(
+            owner.workspace_id,
+            owner.interaction_id,
+            &voice_memo(b"third memo"),
+        )
+        .await
+        .unwrap();
+    repo.delete_attachment(owner.workspace_id, attachment_id)
+        .await
+        .unwrap();
+    assert!(repo.pending_transcriptions(10).await.unwrap().is_empty());
+}
+
+#[actix_rt::test]
+async fn test_transcriptions_in_memory() {
+    check_transcriptions(repository::app_data(InMemoryRepository::new())).await;
+}
+
+#[actix_rt::test]
+async fn test_transcriptions_in_postgres() {
+    let ctx = setup_test_db().await;
+    check_transcriptions(repository::app_data(PgRepository::new(ctx.pool.clone()))).await;
+}
+
+#[actix_rt::test]
+async fn test_transcriptions_in_sqlite() {
+    let repo = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+    check_transcriptions(repository::app_data(repo)).await;
+}
@@ -0,0 +1,265 @@
+use actix_web::{App, test as actix_test, web};
+use personal_crm::backoff::Backoff;
+use personal_crm::reauth::{REAUTH_REQUIRED, Reauth};
+use personal_crm::repository::{self, InMemoryRepository};
+use personal_crm::routes::v1_routes;
+use personal_crm::test_support::{register_claims, register_token_expiring, reject_token};
+use personal_crm::{Auth0Claims, AuthUser};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Test that a token Auth0 refused recently is turned away without asking Auth0 again
+#[actix_rt::test]
+async fn test_rejected_token_is_refused() {
+    reject_token("token-auth-rejected").await;
+
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(repository::app_data(InMemoryRepository::new()))
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let req = actix_test::TestRequest::get()
+        .uri("/v1/contacts")
+        .insert_header(("Authorization", "Bearer token-auth-rejected"))
+        .to_request();
+    let res = actix_test::call_service(&app, req).await;
+    assert_eq!(res.status(), 401);
+    assert_eq!(actix_test::read_body(res).await, "Invalid token");
+}
+
+/// Test that the wait doubles after each failure up to the maximum and clears on success
+#[test]
+fn test_backoff() {
+    let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(5));
+    let now = Instant::now();
+    assert_eq!(backoff.remaining(now), None);
+
+    let waits: Vec<Duration> = (0..4)
+        .map(|_| {
+            backoff.failed(now);
+            backoff.remaining(now).unwrap()
+        })
+        .collect();
+    assert_eq!(waits, [1, 2, 4, 5].map(Duration::from_secs).to_vec());
+    assert_eq!(backoff.remaining(now + Duration::from_secs(5)), None);
+
+    backoff.succeeded();
+    assert_eq!(backoff.remaining(now), None);
+    backoff.failed(now);
+    assert_eq!(backoff.remaining(now), Some(Duration::from_secs(1)));
+}
+
+/// Test that a validated token is trusted until its exp claim and not after
+#[actix_rt::test]
+async fn test_token_cache_follows_exp() {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as usize;
+    register_token_expiring("token-auth-fresh", "test|auth-fresh", Some(now + 3600)).await;
+    register_token_expiring("token-auth-expired", "test|auth-expired", Some(now - 10)).await;
+    // Rejected tokens are refused before Auth0 is asked, so the expired one gets a quick 401
+    reject_token("token-auth-expired").await;
+
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(repository::app_data(InMemoryRepository::new()))
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let status = async |token: &str| {
+        let req = actix_test::TestRequest::get()
+            .uri("/v1/contacts")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request();
+        actix_test::call_service(&app, req).await.status()
+    };
+    assert_eq!(status("token-auth-fresh").await, 200);
+    assert_eq!(status("token-auth-expired").await, 401);
+}
+
+/// Test that tokens granting only the read scope can list but not change anything, while
+/// tokens without any of the API's scopes keep full access
+#[actix_rt::test]
+async fn test_scopes() {
+    let claims = |sub: &str, scope: Option<&str>, permissions: Option<Vec<&str>>| Auth0Claims {
+        sub: sub.to_string(),
+        email: None,
+        name: None,
+        iss: None,
+        aud: None,
+        exp: None,
+        iat: None,
+        auth_time: None,
+        scope: scope.map(str::to_string),
+        permissions: permissions.map(|p| p.into_iter().map(str::to_string).collect()),
+    };
+    register_claims(
+        "token-scope-read",
+        claims("test|scope-read", Some("openid read"), None),
+    )
+    .await;
+    register_claims(
+        "token-scope-login",
+        claims("test|scope-login", Some("openid profile email"), None),
+    )
+    .await;
+    register_claims(
+        "token-scope-write",
+        claims("test|scope-write", None, Some(vec!["write"])),
+    )
+    .await;
+
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(repository::app_data(InMemoryRepository::new()))
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let list = async |token: &str| {
+        let req = actix_test::TestRequest::get()
+            .uri("/v1/contacts")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request();
+        actix_test::call_service(&app, req).await.status()
+    };
+    let create = async |token: &str| {
+        let req = actix_test::TestRequest::post()
+            .uri("/v1/contacts")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .set_json(serde_json::json!({"first_name": "Ada"}))
+            .to_request();
+        actix_test::call_service(&app, req).await.status()
+    };
+
+    assert_eq!(list("token-scope-read").await, 200);
+    assert_eq!(create("token-scope-read").await, 403);
+    for token in ["token-scope-login", "token-scope-write"] {
+        assert_eq!(list(token).await, 200);
+        assert_eq!(create(token).await, 201);
+    }
+}
+
+/// Test that only a sign-in within the max age passes, by `auth_time` before `iat`
+#[test]
+fn test_reauth_max_age() {
+    let user = |authenticated_at: Option<usize>| AuthUser {
+        user_id: 1,
+        workspace_id: 1,
+        auth0_id: "test|reauth".to_string(),
+        email: None,
+        name: None,
+        is_admin: false,
+        deactivated: false,
+        delete_after: None,
+        scopes: None,
+        authenticated_at,
+    };
+    let reauth = Reauth {
+        max_age_secs: Some(600),
+    };
+    assert!(
+        reauth
+            .reject_stale_at(&user(Some(10_000)), 10_600)
+            .is_none()
+    );
+    assert!(
+        reauth
+            .reject_stale_at(&user(Some(10_000)), 10_601)
+            .is_some()
+    );
+    assert!(reauth.reject_stale_at(&user(None), 10_000).is_some());
+    let off = Reauth { max_age_secs: None };
+    assert!(off.reject_stale_at(&user(None), 10_000).is_none());
+}
+
+/// Test that deleting the account and exporting need a recent sign-in
+#[actix_rt::test]
+async fn test_reauth_required() {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as usize;
+    let claims = |sub: &str, iat: Option<usize>, auth_time: Option<usize>| Auth0Claims {
+        sub: sub.to_string(),
+        email: None,
+        name: None,
+        iss: None,
+        aud: None,
+        exp: None,
+        iat,
+        auth_time,
+        scope: None,
+        permissions: None,
+    };
+    register_claims(
+        "token-reauth-stale",
+        claims("test|reauth", Some(now - 3600), None),
+    )
+    .await;
+    register_claims("token-reauth-none", claims("test|reauth", None, None)).await;
+    // Issued just now by refreshing a sign-in from an hour ago
+    register_claims(
+        "token-reauth-refreshed",
+        claims("test|reauth", Some(now), Some(now - 3600)),
+    )
+    .await;
+    register_claims(
+        "token-reauth-fresh",
+        claims("test|reauth", Some(now - 3600), Some(now - 60)),
+    )
+    .await;
+
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(repository::app_data(InMemoryRepository::new()))
+            .app_data(web::Data::new(Reauth {
+                max_age_secs: Some(900),
+            }))
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let call = async |req: actix_test::TestRequest, token: &str| {
+        let req = req
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request();
+        actix_test::call_service(&app, req).await
+    };
+
+    for token in [
+        "token-reauth-stale",
+        "token-reauth-none",
+        "token-reauth-refreshed",
+    ] {
+        for req in [
+            actix_test::TestRequest::get().uri("/v1/export"),
+            actix_test::TestRequest::delete().uri("/v1/account"),
+        ] {
+            let res = call(req, token).await;
+            assert_eq!(res.status(), 403, "{} was let through", token);
+            let body: serde_json::Value = actix_test::read_body_json(res).await;
+            assert_eq!(body["code"], REAUTH_REQUIRED);
+            assert_eq!(body["max_age_secs"], 900);
+        }
+    }
+    // Reading is unaffected
+    let res = call(
+        actix_test::TestRequest::get().uri("/v1/contacts"),
+        "token-reauth-stale",
+    )
+    .await;
+    assert_eq!(res.status(), 200);
+
+    let res = call(
+        actix_test::TestRequest::get().uri("/v1/export"),
+        "token-reauth-fresh",
+    )
+    .await;
+    assert_eq!(res.status(), 200);
+    let res = call(
+        actix_test::TestRequest::delete().uri("/v1/account"),
+        "token-reauth-fresh",
+    )
+    .await;
+    assert!(res.status().is_success());
+}
@@ -0,0 +1,101 @@
+mod common;
+
+use actix_web::{App, test as actix_test, web};
+use common::*;
+use personal_crm::models::{ContactMatch, NewContactRequest};
+use personal_crm::repository::{
+    self, InMemoryRepository, PgRepository, Repository, SqliteRepository,
+};
+use personal_crm::routes::v1_routes;
+use personal_crm::test_support::provision;
+
+/// Names match by word prefix, case-insensitively, skipping archived and other people's contacts
+async fn check_autocomplete(repo: web::Data<dyn Repository>) {
+    let owner = provision(repo.get_ref(), "autocomplete").await.unwrap();
+    let other = provision(repo.get_ref(), "autocomplete-other")
+        .await
+        .unwrap();
+    let mut ids = Vec::new();
+    for (workspace_id, first, last) in [
+        (owner.workspace_id, "Alan", Some("Turing")),
+        (owner.workspace_id, "Alice", Some("Wu_ley")),
+        (owner.workspace_id, "Walter", Some("Alvarez")),
+        (owner.workspace_id, "Alma", None),
+        (owner.workspace_id, "Albert", Some("Archived")),
+        (other.workspace_id, "Alan", Some("Kay")),
+    ] {
+        let request = NewContactRequest {
+            last_name: last.map(str::to_string),
+            ..contact(first)
+        };
+        ids.push(repo.create_contact(workspace_id, &request).await.unwrap());
+    }
+    repo.set_contact_archived(owner.workspace_id, ids[4], true)
+        .await
+        .unwrap();
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(repo.clone())
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let search = |query: &str| {
+        actix_test::TestRequest::get()
+            .uri(&format!("/v1/contacts/autocomplete?{}", query))
+            .insert_header(("Authorization", format!("Bearer {}", owner.token)))
+            .to_request()
+    };
+    let names = |matches: Vec<ContactMatch>| -> Vec<String> {
+        matches.into_iter().map(|m| m.display_name).collect()
+    };
+
+    let matches: Vec<ContactMatch> =
+        actix_test::call_and_read_body_json(&app, search("q=AL")).await;
+    assert_eq!(
+        names(matches),
+        ["Walter Alvarez", "Alan Turing", "Alice Wu_ley", "Alma"]
+    );
+
+    let matches: Vec<ContactMatch> =
+        actix_test::call_and_read_body_json(&app, search("q=al%20tu")).await;
+    assert_eq!(matches.len(), 1);
+    assert_eq!(matches[0].contact_id, ids[0]);
+
+    let matches: Vec<ContactMatch> =
+        actix_test::call_and_read_body_json(&app, search("q=al&limit=2")).await;
+    assert_eq!(names(matches), ["Walter Alvarez", "Alan Turing"]);
+
+    // `_` is matched as itself, not as any character
+    let matches: Vec<ContactMatch> =
+        actix_test::call_and_read_body_json(&app, search("q=wu_")).await;
+    assert_eq!(names(matches), ["Alice Wu_ley"]);
+    let matches: Vec<ContactMatch> =
+        actix_test::call_and_read_body_json(&app, search("q=tu_")).await;
+    assert!(matches.is_empty());
+
+    let matches: Vec<ContactMatch> =
+        actix_test::call_and_read_body_json(&app, search("q=%20")).await;
+    assert!(matches.is_empty());
+
+    let res = actix_test::call_service(&app, search("q=al&limit=100")).await;
+    assert_eq!(res.status(), 400);
+}
+
+/// Test autocomplete on the in-memory repository
+#[actix_rt::test]
+async fn test_autocomplete_in_memory() {
+    check_autocomplete(repository::app_data(InMemoryRepository::new())).await;
+}
+
+/// Test autocomplete on Postgres
+#[actix_rt::test]
+async fn test_autocomplete_in_postgres() {
+    let ctx = setup_test_db().await;
+    check_autocomplete(repository::app_data(PgRepository::new(ctx.pool.clone()))).await;
+}
+
+#[actix_rt::test]
+async fn test_autocomplete_in_sqlite() {
+    let repo = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+    check_autocomplete(repository::app_data(repo)).await;
+}
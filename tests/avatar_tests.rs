@@ -0,0 +1,68 @@
+use actix_web::{App, test as actix_test, web};
+use personal_crm::avatar::gravatar_hash;
+use personal_crm::repository::{self, InMemoryRepository};
+use personal_crm::routes::v1_routes;
+use personal_crm::test_support::register_token;
+use serde_json::{Value, json};
+
+/// Test that Gravatar hashes ignore case and surrounding whitespace
+#[test]
+fn test_gravatar_hash_normalizes_email() {
+    let expected = "973dfe463ec85785f5f95af5ba3906eedb2d931c24e69824a89ea65dba4e813b";
+    assert_eq!(gravatar_hash("test@example.com"), expected);
+    assert_eq!(gravatar_hash("  Test@Example.COM "), expected);
+}
+
+/// Test that uploaded avatars are reported with their source and missing ones stay empty
+/// when the Gravatar fallback is not configured
+#[actix_rt::test]
+async fn test_avatar_source_in_contact_responses() {
+    register_token("token-avatar-owner", "test|avatar-owner").await;
+
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(repository::app_data(InMemoryRepository::new()))
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+
+    for body in [
+        json!({"first_name": "Ada", "avatar_url": "https://example.com/ada.jpg"}),
+        json!({"first_name": "Bea", "email": "bea@example.com"}),
+    ] {
+        let req = actix_test::TestRequest::post()
+            .uri("/v1/contacts")
+            .insert_header(("Authorization", "Bearer token-avatar-owner"))
+            .set_json(body)
+            .to_request();
+        assert!(
+            actix_test::call_service(&app, req)
+                .await
+                .status()
+                .is_success()
+        );
+    }
+
+    let req = actix_test::TestRequest::get()
+        .uri("/v1/contacts")
+        .insert_header(("Authorization", "Bearer token-avatar-owner"))
+        .to_request();
+    let contacts: Value = actix_test::call_and_read_body_json(&app, req).await;
+    let by_name = |name: &str| {
+        contacts
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|c| c["contact"]["first_name"] == name)
+            .unwrap()
+            .clone()
+    };
+
+    let ada = by_name("Ada");
+    assert_eq!(ada["avatar_url"], "https://example.com/ada.jpg");
+    assert_eq!(ada["avatar_source"], "uploaded");
+
+    let bea = by_name("Bea");
+    assert!(bea["avatar_url"].is_null());
+    assert!(bea["avatar_source"].is_null());
+}
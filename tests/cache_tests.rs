@@ -0,0 +1,115 @@
+use personal_crm::cache::{CacheStore, MemoryStore, RedisStore, SharedCache};
+use personal_crm::user_cache::UserCache;
+use std::time::Duration;
+
+/// Unique across runs sharing a Redis server
+fn unique(label: &str) -> String {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    format!("test:{}:{}:{}", label, std::process::id(), nanos)
+}
+
+/// Check that entries are kept for their time to live and forgotten when removed
+async fn check_store(store: &dyn CacheStore) {
+    let kept = unique("kept");
+    let brief = unique("brief");
+    assert_eq!(store.get(&kept).await, None);
+
+    store
+        .insert(&kept, "first".to_string(), Duration::from_secs(60))
+        .await;
+    store
+        .insert(&brief, "gone soon".to_string(), Duration::from_millis(200))
+        .await;
+    assert_eq!(store.get(&kept).await.as_deref(), Some("first"));
+    assert_eq!(store.get(&brief).await.as_deref(), Some("gone soon"));
+
+    store
+        .insert(&kept, "second".to_string(), Duration::from_secs(60))
+        .await;
+    assert_eq!(store.get(&kept).await.as_deref(), Some("second"));
+
+    tokio::time::sleep(Duration::from_millis(400)).await;
+    assert_eq!(store.get(&brief).await, None);
+    assert_eq!(store.get(&kept).await.as_deref(), Some("second"));
+
+    store.remove(&kept).await;
+    assert_eq!(store.get(&kept).await, None);
+}
+
+#[actix_rt::test]
+async fn test_memory_store() {
+    check_store(&MemoryStore::default()).await;
+}
+
+/// Needs a Redis server at TEST_REDIS_URL, and is skipped without one
+#[actix_rt::test]
+async fn test_redis_store() {
+    let Ok(url) = std::env::var("TEST_REDIS_URL") else {
+        eprintln!("Skipping test_redis_store: TEST_REDIS_URL is not set");
+        return;
+    };
+    let store = RedisStore::connect(&url).await.unwrap();
+    check_store(&store).await;
+    // A second connection sees the same entries, as another replica would
+    let key = unique("shared");
+    store
+        .insert(&key, "from one".to_string(), Duration::from_secs(60))
+        .await;
+    let other = RedisStore::connect(&url).await.unwrap();
+    assert_eq!(other.get(&key).await.as_deref(), Some("from one"));
+    other.remove(&key).await;
+    assert_eq!(store.get(&key).await, None);
+}
+
+#[actix_rt::test]
+async fn test_redis_url_must_be_valid() {
+    assert!(RedisStore::connect("not a url").await.is_err());
+}
+
+#[actix_rt::test]
+async fn test_shared_cache() {
+    static NUMBERS: SharedCache<Vec<u32>> = SharedCache::new("numbers", Duration::from_secs(60));
+    let key = unique("numbers");
+    NUMBERS.insert(&key, &vec![1, 2, 3]).await;
+    assert_eq!(NUMBERS.get(&key).await, Some(vec![1, 2, 3]));
+    // Kept for nothing, so not at all
+    let brief = unique("numbers");
+    NUMBERS.insert_for(&brief, &vec![4], Duration::ZERO).await;
+    assert_eq!(NUMBERS.get(&brief).await, None);
+
+    // Entries of another type under the same key don't parse, and are misses
+    static WORDS: SharedCache<String> = SharedCache::new("numbers", Duration::from_secs(60));
+    assert_eq!(WORDS.get(&key).await, None);
+
+    NUMBERS.remove(&key).await;
+    assert_eq!(NUMBERS.get(&key).await, None);
+}
+
+/// Test that a user forgotten through one `UserCache` is gone from the others, as on other
+/// replicas sharing the store
+#[actix_rt::test]
+async fn test_user_cache_forgets_everywhere() {
+    let user: personal_crm::AuthUser = serde_json::from_value(serde_json::json!({
+        "user_id": 1,
+        "workspace_id": 2,
+        "auth0_id": unique("user"),
+        "email": "ada@example.com",
+        "name": "Ada",
+        "is_admin": false,
+        "deactivated": false,
+    }))
+    .unwrap();
+    let one = UserCache::default();
+    let other = UserCache::default();
+    one.insert(&user).await;
+    let cached = other.get(&user.auth0_id).await.unwrap();
+    assert_eq!(cached.user_id, 1);
+    assert_eq!(cached.workspace_id, 2);
+    assert_eq!(cached.email.as_deref(), Some("ada@example.com"));
+
+    other.forget(&user.auth0_id).await;
+    assert!(one.get(&user.auth0_id).await.is_none());
+}
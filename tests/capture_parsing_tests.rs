@@ -0,0 +1,187 @@
+mod common;
+
+use actix_web::{App, test as actix_test, web};
+use async_trait::async_trait;
+use common::*;
+use personal_crm::capture_parsing::{
+    CaptureParser, CaptureParsing, ParseContext, ParsedCapture, RuleParser, prompt,
+};
+use personal_crm::models::{Contact, NewContactRequest};
+use personal_crm::repository::{self, InMemoryRepository};
+use personal_crm::routes::v1_routes;
+use personal_crm::test_support::provision;
+use serde_json::Value;
+use time::format_description::well_known::Rfc3339;
+use time::macros::date;
+use time::{Duration, OffsetDateTime};
+
+fn context() -> ParseContext {
+    ParseContext {
+        // A Wednesday
+        captured_on: date!(2024 - 03 - 13),
+        contacts: vec![
+            Contact {
+                last_name: Some("Patel".to_string()),
+                ..saved_contact(1, "Priya")
+            },
+            Contact {
+                last_name: Some("Shah".to_string()),
+                ..saved_contact(2, "Priya")
+            },
+            Contact {
+                last_name: Some("Lee".to_string()),
+                ..saved_contact(3, "Sam")
+            },
+        ],
+        interaction_types: ["call", "email", "meeting", "message", "Dinner"]
+            .iter()
+            .map(|t| t.to_string())
+            .collect(),
+    }
+}
+
+/// Stands in for a model, answering the same whatever the text
+struct Fixed(Option<ParsedCapture>);
+
+#[async_trait]
+impl CaptureParser for Fixed {
+    fn name(&self) -> &'static str {
+        "fixed"
+    }
+
+    async fn parse(&self, _text: &str, _context: &ParseContext) -> Option<ParsedCapture> {
+        self.0.clone()
+    }
+}
+
+/// Test matching contacts, dates and types with the rules
+#[test]
+fn test_rule_parser() {
+    let context = context();
+    let parse = |text: &str| RuleParser::parse_text(text, &context);
+
+    let parsed = parse("lunch w/ Priya Patel yesterday, she's moving to Austin");
+    assert_eq!(parsed.contact_id, Some(1));
+    assert_eq!(parsed.date, Some(date!(2024 - 03 - 12)));
+    assert_eq!(parsed.interaction_type.as_deref(), Some("meeting"));
+    assert_eq!(
+        parsed.notes.as_deref(),
+        Some("lunch w/ Priya Patel yesterday, she's moving to Austin")
+    );
+
+    let parsed = parse("Called Sam on Friday about the move");
+    assert_eq!(parsed.contact_id, Some(3));
+    assert_eq!(parsed.date, Some(date!(2024 - 03 - 08)));
+    assert_eq!(parsed.interaction_type.as_deref(), Some("call"));
+
+    // Two Priyas: the user picks
+    let parsed = parse("coffee with Priya last wednesday");
+    assert_eq!(parsed.contact_id, None);
+    assert_eq!(parsed.date, Some(date!(2024 - 03 - 06)));
+
+    // A custom type named in the text wins over the keyword
+    let parsed = parse("dinner at Sam's (2024-03-01)");
+    assert_eq!(parsed.contact_id, Some(3));
+    assert_eq!(parsed.date, Some(date!(2024 - 03 - 01)));
+    assert_eq!(parsed.interaction_type.as_deref(), Some("Dinner"));
+
+    let parsed = parse("texted someone 3 days ago");
+    assert_eq!(parsed.contact_id, None);
+    assert_eq!(parsed.date, Some(date!(2024 - 03 - 10)));
+    assert_eq!(parsed.interaction_type.as_deref(), Some("message"));
+}
+
+/// Test that a model's answer is checked against the workspace and the rules step in when it
+/// fails
+#[actix_rt::test]
+async fn test_parsing_falls_back_to_rules() {
+    let context = context();
+    let text = "called Sam yesterday";
+
+    let invented = CaptureParsing::new(Box::new(Fixed(Some(ParsedCapture {
+        contact_id: Some(99),
+        date: Some(date!(2024 - 03 - 14)),
+        notes: Some("  Called Sam ".to_string()),
+        interaction_type: Some("carrier pigeon".to_string()),
+    }))));
+    let (source, parsed) = invented.parse(text, &context).await;
+    assert_eq!(source, "fixed");
+    assert_eq!(
+        parsed,
+        ParsedCapture {
+            notes: Some("Called Sam".to_string()),
+            ..ParsedCapture::default()
+        }
+    );
+
+    let failing = CaptureParsing::new(Box::new(Fixed(None)));
+    let (source, parsed) = failing.parse(text, &context).await;
+    assert_eq!(source, "rules");
+    assert_eq!(parsed.contact_id, Some(3));
+    assert_eq!(parsed.date, Some(date!(2024 - 03 - 12)));
+}
+
+/// Test that prompts only name contacts the text could be about
+#[test]
+fn test_prompt_names_candidates() {
+    let prompt = prompt("drinks w/ Pri", &context());
+    assert!(prompt.contains("Today is 2024-03-13 (Wednesday)"));
+    assert!(prompt.contains("1: Priya Patel\n2: Priya Shah"));
+    assert!(!prompt.contains("Sam"));
+    assert!(prompt.contains("Note: drinks w/ Pri"));
+}
+
+/// Test suggesting a resolution through the API
+#[actix_rt::test]
+async fn test_suggest_resolution() {
+    let repo = repository::app_data(InMemoryRepository::new());
+    let owner = provision(repo.get_ref(), "suggest").await.unwrap();
+    let other = provision(repo.get_ref(), "suggest-other").await.unwrap();
+    let contact_id = repo
+        .create_contact(
+            owner.workspace_id,
+            &NewContactRequest {
+                last_name: Some("Patel".to_string()),
+                ..contact("Priya")
+            },
+        )
+        .await
+        .unwrap();
+    let capture_id = repo
+        .create_capture(owner.workspace_id, "lunch w/ Priya yesterday")
+        .await
+        .unwrap();
+    let capture = repo
+        .get_capture(owner.workspace_id, capture_id)
+        .await
+        .unwrap()
+        .unwrap();
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(repo.clone())
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let suggest = |id: i32| {
+        actix_test::TestRequest::get()
+            .uri(&format!("/v1/inbox/{}/suggestion", id))
+            .insert_header(("Authorization", format!("Bearer {}", owner.token)))
+            .to_request()
+    };
+
+    let res = actix_test::call_service(&app, suggest(capture_id)).await;
+    assert_eq!(res.status(), 200);
+    let suggestion: Value = actix_test::read_body_json(res).await;
+    assert_eq!(suggestion["source"], "rules");
+    assert_eq!(suggestion["contact_id"], contact_id);
+    assert_eq!(suggestion["interaction_type"], "meeting");
+    // The capture's time, a day earlier
+    let date = suggestion["interaction_date"].as_str().unwrap();
+    assert_eq!(
+        OffsetDateTime::parse(date, &Rfc3339).unwrap(),
+        capture.captured_at - Duration::days(1)
+    );
+
+    let res = actix_test::call_service(&app, suggest(other.capture_id)).await;
+    assert_eq!(res.status(), 404);
+}
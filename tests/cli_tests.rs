@@ -0,0 +1,224 @@
+mod common;
+
+use actix_web::{App, HttpServer, web};
+use common::*;
+use personal_crm::cli::{Client, Command, DEFAULT_DUE_DAYS};
+use personal_crm::models::{NewInteractionRequest, NewOccasionRequest, OccasionType};
+use personal_crm::repository::{
+    self, InMemoryRepository, PgRepository, Repository, SqliteRepository,
+};
+use personal_crm::routes::v1_routes;
+use personal_crm::test_support::provision;
+use personal_crm::timezone::LocalDates;
+use std::net::TcpListener;
+use time::Duration;
+
+fn args(args: &[&str]) -> impl Iterator<Item = String> {
+    args.iter()
+        .map(|arg| arg.to_string())
+        .collect::<Vec<String>>()
+        .into_iter()
+}
+
+#[test]
+fn test_from_args() {
+    let Ok(Command::AddContact(contact)) = Command::from_args(args(&[
+        "add-contact",
+        "Alice van Zhang",
+        "--email",
+        "alice@example.com",
+        "--every",
+        "14",
+    ])) else {
+        panic!("add-contact should parse");
+    };
+    assert_eq!(contact.first_name.as_deref(), Some("Alice"));
+    assert_eq!(contact.last_name.as_deref(), Some("van Zhang"));
+    assert_eq!(contact.email.as_deref(), Some("alice@example.com"));
+    assert_eq!(contact.desired_frequency_days, Some(14));
+
+    let Ok(Command::Log {
+        text,
+        contact_id,
+        interaction_type,
+    }) = Command::from_args(args(&["log", "--type", "call", "coffee with Alice"]))
+    else {
+        panic!("log should parse");
+    };
+    assert_eq!(text, "coffee with Alice");
+    assert_eq!(contact_id, None);
+    assert_eq!(interaction_type.as_deref(), Some("call"));
+
+    assert!(matches!(
+        Command::from_args(args(&["due"])),
+        Ok(Command::Due {
+            days: DEFAULT_DUE_DAYS
+        })
+    ));
+    assert!(matches!(
+        Command::from_args(args(&["export", "--output", "crm.json"])),
+        Ok(Command::Export { output: Some(path) }) if path == "crm.json"
+    ));
+
+    for bad in [
+        &[][..],
+        &["add-contact"][..],
+        &["log", "coffee", "with", "Alice"][..],
+        &["log", "coffee", "--contact", "Alice"][..],
+        &["due", "--days"][..],
+        &["export", "--format", "csv"][..],
+        &["delete-everything"][..],
+    ] {
+        assert!(Command::from_args(args(bad)).is_err(), "{:?}", bad);
+    }
+}
+
+/// Check each command against a running server
+async fn check_cli(repo: web::Data<dyn Repository>) {
+    let owner = provision(repo.get_ref(), "cli").await.unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    let app_repo = repo.clone();
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(app_repo.clone())
+            .service(web::scope("/v1").configure(v1_routes))
+    })
+    .workers(1)
+    .listen(listener)
+    .unwrap()
+    .run();
+    let handle = server.handle();
+    actix_rt::spawn(server);
+    let client = Client::new(&base_url, owner.token.clone());
+    let run = |command: &[&str]| {
+        let command = Command::from_args(args(command)).unwrap();
+        let client = &client;
+        async move { client.run(command).await }
+    };
+
+    let email = format!("ada-{}@example.com", owner.marker);
+    let added = run(&[
+        "add-contact",
+        "Ada Lovelace",
+        "--email",
+        &email,
+        "--every",
+        "7",
+    ])
+    .await
+    .unwrap();
+    assert!(added.starts_with("Added Ada Lovelace"), "{}", added);
+    let contacts = repo.list_contacts(owner.workspace_id).await.unwrap();
+    let ada = contacts
+        .iter()
+        .find(|c| c.email.as_deref() == Some(email.as_str()))
+        .unwrap();
+    assert_eq!(ada.desired_frequency_days, Some(7));
+
+    // The server suggests Ada from her name
+    let logged = run(&["log", "coffee with Ada"]).await.unwrap();
+    assert_eq!(logged, "Logged with Ada Lovelace");
+    let logged = run(&[
+        "log",
+        "sent the slides",
+        "--contact",
+        &owner.contact_id.to_string(),
+    ])
+    .await
+    .unwrap();
+    assert!(logged.starts_with("Logged with"), "{}", logged);
+    let interactions = repo
+        .interactions_for_contacts(&[ada.contact_id])
+        .await
+        .unwrap();
+    assert_eq!(interactions.len(), 1);
+    assert_eq!(interactions[0].notes.as_deref(), Some("coffee with Ada"));
+    let kept = run(&["log", "remember to water the plants"]).await.unwrap();
+    assert!(kept.starts_with("Saved to the inbox"), "{}", kept);
+    let err = run(&["log", "lunch", "--contact", "999999"])
+        .await
+        .unwrap_err();
+    assert!(err.contains("404"), "{}", err);
+
+    // Nobody is overdue yet; then Ada's coffee was last seen ten days ago
+    let due = run(&["due"]).await.unwrap();
+    assert!(due.starts_with("Nobody is due for contact"), "{}", due);
+    let dates = LocalDates::utc();
+    let babbage = repo
+        .create_contact(
+            owner.workspace_id,
+            &personal_crm::models::NewContactRequest {
+                desired_frequency_days: Some(30),
+                ..contact("Charles")
+            },
+        )
+        .await
+        .unwrap();
+    repo.create_interaction(
+        owner.workspace_id,
+        &NewInteractionRequest {
+            contact_id: babbage,
+            interaction_date: dates.start_of(dates.today() - Duration::days(40)),
+            notes: None,
+            follow_up_priority: None,
+            interaction_type: None,
+            contact_ids: Vec::new(),
+            follow_up_in_days: None,
+        },
+        None,
+    )
+    .await
+    .unwrap();
+    let birthday = dates.today() + Duration::days(3);
+    repo.create_occasion(
+        owner.workspace_id,
+        &NewOccasionRequest {
+            contact_id: ada.contact_id,
+            name: "Birthday".to_string(),
+            date: birthday,
+            recurring: false,
+            recurring_interval: None,
+            details: None,
+            occasion_type: OccasionType::Birthday,
+            birth_year: None,
+            remind_days_before: Some(vec![0]),
+        },
+    )
+    .await
+    .unwrap();
+    let due = run(&["due"]).await.unwrap();
+    assert!(
+        due.contains("Due for contact:\n  Charles  (10 days overdue)"),
+        "{}",
+        due
+    );
+    assert!(
+        due.contains(&format!("  {}  Birthday (Ada Lovelace)", birthday)),
+        "{}",
+        due
+    );
+
+    let exported = run(&["export"]).await.unwrap();
+    let archive: serde_json::Value = serde_json::from_str(&exported).unwrap();
+    assert!(archive.to_string().contains(&email));
+
+    handle.stop(true).await;
+}
+
+#[actix_rt::test]
+async fn test_cli_in_memory() {
+    check_cli(repository::app_data(InMemoryRepository::new())).await;
+}
+
+#[actix_rt::test]
+async fn test_cli_in_postgres() {
+    let ctx = setup_test_db().await;
+    check_cli(repository::app_data(PgRepository::new(ctx.pool.clone()))).await;
+}
+
+#[actix_rt::test]
+async fn test_cli_in_sqlite() {
+    let repo = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+    check_cli(repository::app_data(repo)).await;
+}
@@ -0,0 +1,335 @@
+mod common;
+
+use actix_web::http::header::LOCATION;
+use actix_web::{App, test as actix_test, web};
+use common::*;
+use personal_crm::contact_links::{self, render_card_html};
+use personal_crm::models::{CardProfile, ContactCard, SocialPlatform};
+use personal_crm::repository::{
+    self, InMemoryRepository, PgRepository, Repository, SqliteRepository,
+};
+use personal_crm::routes::v1_routes;
+use personal_crm::security::security_headers;
+use personal_crm::test_support::provision;
+use serde_json::Value;
+use time::{Duration, OffsetDateTime};
+
+#[test]
+fn test_render_card_html() {
+    let html = render_card_html(&ContactCard {
+        first_name: Some("Bob <script>".to_string()),
+        last_name: Some("O'Neil".to_string()),
+        email: Some("bob@example.com".to_string()),
+        phone: None,
+        avatar_url: Some("javascript:alert(1)".to_string()),
+        location: Some("Lisbon & Porto".to_string()),
+        social_profiles: vec![
+            CardProfile {
+                platform: SocialPlatform::Github,
+                url: "https://github.com/bob?a=1&b=\"2\"".to_string(),
+            },
+            CardProfile {
+                platform: SocialPlatform::Website,
+                url: "javascript:alert(2)".to_string(),
+            },
+        ],
+    });
+    assert!(html.contains("<h1>Bob &lt;script&gt; O&#39;Neil</h1>"));
+    assert!(html.contains("<title>Bob &lt;script&gt; O&#39;Neil</title>"));
+    assert!(html.contains("mailto:bob@example.com"));
+    assert!(html.contains("Lisbon &amp; Porto"));
+    assert!(html.contains("https://github.com/bob?a=1&amp;b=&quot;2&quot;"));
+    assert!(!html.contains("<script>"));
+    assert!(!html.contains("javascript:"));
+    assert!(!html.contains("Phone"));
+
+    // Only HTTPS avatars, the only images the card's policy lets load
+    let card = |avatar_url: &str| ContactCard {
+        first_name: Some("Bob".to_string()),
+        last_name: None,
+        email: None,
+        phone: None,
+        avatar_url: Some(avatar_url.to_string()),
+        location: None,
+        social_profiles: vec![],
+    };
+    let html = render_card_html(&card("https://example.com/bob.png"));
+    assert!(html.contains("<img src=\"https://example.com/bob.png\""));
+    let html = render_card_html(&card("http://example.com/bob.png"));
+    assert!(!html.contains("<img"));
+}
+
+/// Check that links are listed until they expire, open their contact until then, and are gone
+/// with the contact, the workspace's owner being deactivated or the link being revoked
+async fn check_contact_links(repo: web::Data<dyn Repository>) {
+    let owner = provision(repo.get_ref(), "links").await.unwrap();
+    let other = provision(repo.get_ref(), "links-other").await.unwrap();
+    let workspace_id = owner.workspace_id;
+    let now = OffsetDateTime::now_utc();
+
+    let link = repo
+        .create_contact_link(
+            workspace_id,
+            owner.contact_id,
+            "links-live-token",
+            now + Duration::hours(1),
+        )
+        .await
+        .unwrap();
+    assert_eq!(link.contact_id, owner.contact_id);
+    assert_eq!(link.token, None);
+    assert_eq!(link.url, None);
+    let expired = repo
+        .create_contact_link(
+            workspace_id,
+            owner.contact_id,
+            "links-expired-token",
+            now - Duration::hours(1),
+        )
+        .await
+        .unwrap();
+    assert!(
+        repo.create_contact_link(
+            workspace_id,
+            owner.contact_id,
+            "links-live-token",
+            now + Duration::hours(1),
+        )
+        .await
+        .is_err()
+    );
+
+    let listed = repo
+        .contact_links(workspace_id, owner.contact_id, now)
+        .await
+        .unwrap();
+    assert_eq!(
+        listed.iter().map(|l| l.link_id).collect::<Vec<_>>(),
+        vec![owner.contact_link_id, link.link_id]
+    );
+    assert!(
+        repo.contact_links(other.workspace_id, owner.contact_id, now)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    assert!(
+        repo.owns_contact_link(workspace_id, expired.link_id)
+            .await
+            .unwrap()
+    );
+    assert!(
+        !repo
+            .owns_contact_link(other.workspace_id, link.link_id)
+            .await
+            .unwrap()
+    );
+
+    assert_eq!(
+        repo.contact_link_target("links-live-token", now)
+            .await
+            .unwrap(),
+        Some((workspace_id, owner.contact_id))
+    );
+    for token in ["links-expired-token", "links-unknown-token"] {
+        assert_eq!(repo.contact_link_target(token, now).await.unwrap(), None);
+    }
+    assert_eq!(
+        repo.contact_link_target("links-live-token", now + Duration::hours(2))
+            .await
+            .unwrap(),
+        None
+    );
+
+    repo.set_deactivated(owner.user_id, true).await.unwrap();
+    assert_eq!(
+        repo.contact_link_target("links-live-token", now)
+            .await
+            .unwrap(),
+        None
+    );
+    repo.set_deactivated(owner.user_id, false).await.unwrap();
+
+    assert!(
+        !repo
+            .delete_contact_link(other.workspace_id, link.link_id)
+            .await
+            .unwrap()
+    );
+    assert!(
+        repo.delete_contact_link(workspace_id, link.link_id)
+            .await
+            .unwrap()
+    );
+    assert_eq!(
+        repo.contact_link_target("links-live-token", now)
+            .await
+            .unwrap(),
+        None
+    );
+
+    assert!(
+        repo.delete_contact(workspace_id, owner.contact_id, false)
+            .await
+            .unwrap()
+    );
+    assert!(
+        !repo
+            .owns_contact_link(workspace_id, owner.contact_link_id)
+            .await
+            .unwrap()
+    );
+}
+
+#[actix_rt::test]
+async fn test_contact_links_in_memory() {
+    check_contact_links(repository::app_data(InMemoryRepository::new())).await;
+}
+
+#[actix_rt::test]
+async fn test_contact_links_in_postgres() {
+    let ctx = setup_test_db().await;
+    check_contact_links(repository::app_data(PgRepository::new(ctx.pool.clone()))).await;
+}
+
+#[actix_rt::test]
+async fn test_contact_links_in_sqlite() {
+    let repo = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+    check_contact_links(repository::app_data(repo)).await;
+}
+
+/// Test making a link, opening it without signing in as JSON and HTML, and revoking it
+#[actix_rt::test]
+async fn test_contact_links_api() {
+    let repo = repository::app_data(InMemoryRepository::new());
+    let owner = provision(repo.get_ref(), "links-api").await.unwrap();
+    let other = provision(repo.get_ref(), "links-api-other").await.unwrap();
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(repo.clone())
+            .wrap(security_headers())
+            .service(contact_links::view_contact_card)
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let auth = ("Authorization", format!("Bearer {}", owner.token));
+    let share = |query: &str, token: &str| {
+        actix_test::TestRequest::post()
+            .uri(&format!("/v1/contacts/{}/share{}", owner.contact_id, query))
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request()
+    };
+
+    let before = OffsetDateTime::now_utc();
+    let res = actix_test::call_service(&app, share("?expires_in_hours=2", &owner.token)).await;
+    assert_eq!(res.status(), 201);
+    let location = res
+        .headers()
+        .get(LOCATION)
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string();
+    let link: Value = actix_test::read_body_json(res).await;
+    assert_eq!(location, format!("/v1/share-links/{}", link["link_id"]));
+    let token = link["token"].as_str().unwrap().to_string();
+    assert_eq!(token.len(), 32);
+    assert_eq!(
+        link["url"],
+        format!("http://localhost:8080/public/contacts/{}", token)
+    );
+    let expires_at = OffsetDateTime::parse(
+        link["expires_at"].as_str().unwrap(),
+        &time::format_description::well_known::Rfc3339,
+    )
+    .unwrap();
+    assert!(expires_at > before + Duration::minutes(119));
+    assert!(expires_at < before + Duration::minutes(121));
+
+    for query in ["?expires_in_hours=0", "?expires_in_hours=721"] {
+        let res = actix_test::call_service(&app, share(query, &owner.token)).await;
+        assert_eq!(res.status(), 400, "{} was accepted", query);
+    }
+    // Another user's contact
+    let res = actix_test::call_service(&app, share("", &other.token)).await;
+    assert_eq!(res.status(), 404);
+
+    let req = actix_test::TestRequest::get()
+        .uri(&format!("/v1/contacts/{}/share-links", owner.contact_id))
+        .insert_header(auth.clone())
+        .to_request();
+    let links: Vec<Value> =
+        actix_test::read_body_json(actix_test::call_service(&app, req).await).await;
+    assert_eq!(links.len(), 2);
+    assert_eq!(links[1]["link_id"], link["link_id"]);
+    assert_eq!(links[1]["token"], Value::Null);
+    assert_eq!(links[1]["url"], Value::Null);
+
+    // Only the token's hash is stored
+    let now = OffsetDateTime::now_utc();
+    assert_eq!(repo.contact_link_target(&token, now).await.unwrap(), None);
+    assert_eq!(
+        repo.contact_link_target(&contact_links::token_hash(&token), now)
+            .await
+            .unwrap(),
+        Some((owner.workspace_id, owner.contact_id))
+    );
+
+    let view = |query: &str, accept: &str| {
+        actix_test::TestRequest::get()
+            .uri(&format!("/public/contacts/{}{}", token, query))
+            .insert_header(("Accept", accept.to_string()))
+            .to_request()
+    };
+    let res = actix_test::call_service(&app, view("", "application/json")).await;
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.headers().get("Cache-Control").unwrap(), "no-store");
+    assert_eq!(
+        res.headers().get("Content-Security-Policy").unwrap(),
+        "default-src 'none'; frame-ancestors 'none'"
+    );
+    let card: Value = actix_test::read_body_json(res).await;
+    assert_eq!(card["first_name"], owner.marker.as_str());
+    assert_eq!(card["phone"], "(415) 555-0132");
+    assert_eq!(card["social_profiles"][0]["platform"], "website");
+    assert!(card.get("notes").is_none());
+    assert!(card.get("contact_id").is_none());
+
+    for (query, accept) in [
+        ("", "text/html,application/xhtml+xml"),
+        ("?format=html", "*/*"),
+    ] {
+        let res = actix_test::call_service(&app, view(query, accept)).await;
+        assert_eq!(res.status(), 200);
+        assert_eq!(
+            res.headers().get("Content-Type").unwrap(),
+            "text/html; charset=utf-8"
+        );
+        // The avatar may load, over HTTPS, but nothing else
+        assert_eq!(
+            res.headers().get("Content-Security-Policy").unwrap(),
+            "default-src 'none'; img-src https:; frame-ancestors 'none'"
+        );
+        let body = String::from_utf8(actix_test::read_body(res).await.to_vec()).unwrap();
+        assert!(body.contains(&format!("<h1>{}</h1>", owner.marker)));
+    }
+    let res = actix_test::call_service(&app, view("?format=json", "text/html")).await;
+    assert_eq!(
+        res.headers().get("Content-Type").unwrap(),
+        "application/json"
+    );
+
+    let revoke = |link_id: i64, token: &str| {
+        actix_test::TestRequest::delete()
+            .uri(&format!("/v1/share-links/{}", link_id))
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request()
+    };
+    let link_id = link["link_id"].as_i64().unwrap();
+    let res = actix_test::call_service(&app, revoke(link_id, &other.token)).await;
+    assert_eq!(res.status(), 404);
+    let res = actix_test::call_service(&app, revoke(link_id, &owner.token)).await;
+    assert_eq!(res.status(), 200);
+    let res = actix_test::call_service(&app, view("", "application/json")).await;
+    assert_eq!(res.status(), 404);
+}
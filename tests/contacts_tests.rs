@@ -1,19 +1,31 @@
 mod common;
 
+use actix_web::{App, test, web};
 use common::*;
+use personal_crm::models::{
+    NewContactRequest, NewInteractionRequest, NewOccasionRequest, NewTagRequest, OccasionType,
+};
+use personal_crm::repository::{
+    self, InMemoryRepository, PgRepository, Repository, SqliteRepository,
+};
+use personal_crm::routes::v1_routes;
+use personal_crm::scores;
+use personal_crm::test_support::{provision, provision_user};
+use serde_json::{Value, json};
+use time::{Date, Duration, Month, OffsetDateTime};
 
 /// Test creating a contact and verifying it exists in the database
 #[tokio::test]
 async fn test_create_contact() {
     let test_ctx = setup_test_db().await;
-    let user_id = setup_test_user(&test_ctx.pool).await;
+    let workspace_id = setup_test_workspace(&test_ctx.pool).await;
 
     // Create a contact
     let result = sqlx::query!(
-        "INSERT INTO contacts (user_id, first_name, last_name, email, phone, short_note, notes) 
+        "INSERT INTO contacts (workspace_id, first_name, last_name, email, phone, short_note, notes) 
          VALUES ($1, $2, $3, $4, $5, $6, $7) 
          RETURNING contact_id",
-        user_id,
+        workspace_id,
         "John",
         "Doe",
         "john.doe@example.com",
@@ -36,22 +48,22 @@ async fn test_create_contact() {
     .await
     .expect("Failed to fetch contact");
 
-    assert_eq!(contact.first_name, "John");
-    assert_eq!(contact.last_name, "Doe");
-    assert_eq!(contact.email, "john.doe@example.com");
+    assert_eq!(contact.first_name, Some("John".to_string()));
+    assert_eq!(contact.last_name, Some("Doe".to_string()));
+    assert_eq!(contact.email, Some("john.doe@example.com".to_string()));
 }
 
 /// Test updating a contact
 #[tokio::test]
 async fn test_update_contact() {
     let test_ctx = setup_test_db().await;
-    let user_id = setup_test_user(&test_ctx.pool).await;
+    let workspace_id = setup_test_workspace(&test_ctx.pool).await;
 
     // Create a contact first
     let contact_id = sqlx::query!(
-        "INSERT INTO contacts (user_id, first_name, last_name, email) 
+        "INSERT INTO contacts (workspace_id, first_name, last_name, email) 
          VALUES ($1, $2, $3, $4) RETURNING contact_id",
-        user_id,
+        workspace_id,
         "Jane",
         "Smith",
         "jane@example.com"
@@ -65,13 +77,13 @@ async fn test_update_contact() {
     sqlx::query!(
         "UPDATE contacts 
          SET first_name = $1, last_name = $2, email = $3, phone = $4 
-         WHERE contact_id = $5 AND user_id = $6",
+         WHERE contact_id = $5 AND workspace_id = $6",
         "Jane",
         "Doe-Smith",
         "jane.doe@example.com",
         "555-5678",
         contact_id,
-        user_id,
+        workspace_id,
     )
     .execute(&test_ctx.pool)
     .await
@@ -86,7 +98,7 @@ async fn test_update_contact() {
     .await
     .expect("Failed to fetch updated contact");
 
-    assert_eq!(result.last_name, "Doe-Smith");
+    assert_eq!(result.last_name, Some("Doe-Smith".to_string()));
     assert_eq!(result.phone, Some("555-5678".to_string()));
 }
 
@@ -94,13 +106,13 @@ async fn test_update_contact() {
 #[tokio::test]
 async fn test_delete_contact() {
     let test_ctx = setup_test_db().await;
-    let user_id = setup_test_user(&test_ctx.pool).await;
+    let workspace_id = setup_test_workspace(&test_ctx.pool).await;
 
     // Create a contact first
     let contact_id = sqlx::query!(
-        "INSERT INTO contacts (user_id, first_name, last_name, email) 
+        "INSERT INTO contacts (workspace_id, first_name, last_name, email) 
          VALUES ($1, $2, $3, $4) RETURNING contact_id",
-        user_id,
+        workspace_id,
         "Bob",
         "Johnson",
         "bob@example.com"
@@ -112,9 +124,9 @@ async fn test_delete_contact() {
 
     // Delete the contact
     sqlx::query!(
-        "DELETE FROM contacts WHERE contact_id = $1 AND user_id = $2",
+        "DELETE FROM contacts WHERE contact_id = $1 AND workspace_id = $2",
         contact_id,
-        user_id,
+        workspace_id,
     )
     .execute(&test_ctx.pool)
     .await
@@ -136,14 +148,14 @@ async fn test_delete_contact() {
 #[tokio::test]
 async fn test_list_contacts() {
     let test_ctx = setup_test_db().await;
-    let user_id = setup_test_user(&test_ctx.pool).await;
+    let workspace_id = setup_test_workspace(&test_ctx.pool).await;
 
     // Create multiple contacts
     for i in 1..=3 {
         sqlx::query!(
-            "INSERT INTO contacts (user_id, first_name, last_name, email) 
+            "INSERT INTO contacts (workspace_id, first_name, last_name, email) 
              VALUES ($1, $2, $3, $4)",
-            user_id,
+            workspace_id,
             format!("User{}", i),
             format!("Test{}", i),
             format!("user{}@example.com", i)
@@ -157,16 +169,708 @@ async fn test_list_contacts() {
     let contacts = sqlx::query!(
         "SELECT contact_id, first_name, last_name 
          FROM contacts 
-         WHERE user_id = $1 
+         WHERE workspace_id = $1 
          ORDER BY last_name",
-        user_id
+        workspace_id
     )
     .fetch_all(&test_ctx.pool)
     .await
     .expect("Failed to list contacts");
 
     assert_eq!(contacts.len(), 3);
-    assert_eq!(contacts[0].first_name, "User1");
-    assert_eq!(contacts[1].first_name, "User2");
-    assert_eq!(contacts[2].first_name, "User3");
+    assert_eq!(contacts[0].first_name, Some("User1".to_string()));
+    assert_eq!(contacts[1].first_name, Some("User2".to_string()));
+    assert_eq!(contacts[2].first_name, Some("User3".to_string()));
+}
+
+/// Test bulk creation in atomic and best-effort modes
+#[tokio::test]
+async fn test_create_contacts_bulk() {
+    let test_ctx = setup_test_db().await;
+    let workspace_id = setup_test_workspace(&test_ctx.pool).await;
+    let repo = PgRepository::new(test_ctx.pool.clone());
+
+    let with_email = |name: &str, email: &str| NewContactRequest {
+        email: Some(format!("{}-{}@example.com", email, workspace_id)),
+        ..contact(name)
+    };
+    let batch = [
+        with_email("First", "bulk-a"),
+        with_email("Second", "bulk-b"),
+        with_email("Duplicate", "bulk-a"),
+        with_email("Fourth", "bulk-c"),
+    ];
+
+    // Checking the batch finds the duplicate and keeps nothing
+    let checked = repo.check_contacts(workspace_id, &batch).await.unwrap();
+    assert_eq!(
+        checked.iter().map(Result::is_ok).collect::<Vec<_>>(),
+        vec![true, true, false, true]
+    );
+    assert!(repository::is_unique_violation(
+        checked[2].as_ref().unwrap_err()
+    ));
+    assert!(repo.list_contacts(workspace_id).await.unwrap().is_empty());
+
+    // A duplicate email rolls back the whole atomic batch
+    assert!(
+        repo.create_contacts(workspace_id, &batch, true)
+            .await
+            .is_err()
+    );
+    assert!(repo.list_contacts(workspace_id).await.unwrap().is_empty());
+
+    // Best effort keeps every other row, in input order
+    let results = repo
+        .create_contacts(workspace_id, &batch, false)
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 4);
+    assert!(results[2].is_err());
+    let created: Vec<i32> = results.into_iter().flatten().collect();
+    assert!(created.windows(2).all(|w| w[0] < w[1]));
+
+    let mut names: Vec<_> = repo
+        .list_contacts(workspace_id)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|c| (c.contact_id, c.first_name.unwrap()))
+        .collect();
+    names.sort();
+    assert_eq!(
+        names,
+        vec![
+            (created[0], "First".to_string()),
+            (created[1], "Second".to_string()),
+            (created[2], "Fourth".to_string()),
+        ]
+    );
+
+    // Without failures both modes take the single-statement path
+    let results = repo
+        .create_contacts(workspace_id, &[with_email("Fifth", "bulk-d")], true)
+        .await
+        .unwrap();
+    assert!(results[0].is_ok());
+}
+
+/// Test that bulk delete and bulk tag touch only the caller's contacts and report the rest
+#[tokio::test]
+async fn test_bulk_delete_and_tag_skip_foreign_contacts() {
+    let test_ctx = setup_test_db().await;
+    let workspace_id = setup_test_workspace(&test_ctx.pool).await;
+    let other_id = setup_test_workspace(&test_ctx.pool).await;
+    let repo = PgRepository::new(test_ctx.pool.clone());
+
+    let mine = [
+        repo.create_contact(workspace_id, &contact("A"))
+            .await
+            .unwrap(),
+        repo.create_contact(workspace_id, &contact("B"))
+            .await
+            .unwrap(),
+    ];
+    let theirs = repo.create_contact(other_id, &contact("C")).await.unwrap();
+    let tag_id = repo
+        .create_tag(
+            workspace_id,
+            &NewTagRequest {
+                name: format!("bulk-{}", workspace_id),
+                color: None,
+                details: None,
+            },
+        )
+        .await
+        .unwrap();
+
+    // Already tagged contacts count as tagged; foreign and duplicate ids are skipped
+    repo.add_tag_to_contact(mine[0], tag_id).await.unwrap();
+    let mut tagged = repo
+        .add_tag_to_contacts(workspace_id, tag_id, &[mine[0], mine[1], mine[1], theirs])
+        .await
+        .unwrap();
+    tagged.sort();
+    assert_eq!(tagged, mine.to_vec());
+    assert!(repo.tags_for_contacts(&[theirs]).await.unwrap().is_empty());
+
+    let deleted = repo
+        .delete_contacts(workspace_id, &[mine[0], theirs, 0], false)
+        .await
+        .unwrap();
+    assert_eq!(deleted, vec![mine[0]]);
+    assert!(repo.owns_contact(other_id, theirs).await.unwrap());
+    assert!(repo.owns_contact(workspace_id, mine[1]).await.unwrap());
+}
+
+/// Test that changes to a contact's tags, interactions and occasions move its updated_at
+#[tokio::test]
+async fn test_child_changes_touch_contact() {
+    let test_ctx = setup_test_db().await;
+    let workspace_id = setup_test_workspace(&test_ctx.pool).await;
+
+    let contact_id = sqlx::query_scalar!(
+        "INSERT INTO contacts (workspace_id, first_name) VALUES ($1, 'Touched') RETURNING contact_id",
+        workspace_id
+    )
+    .fetch_one(&test_ctx.pool)
+    .await
+    .unwrap();
+
+    let updated_at = || async {
+        sqlx::query_scalar!(
+            "SELECT updated_at FROM contacts WHERE contact_id = $1",
+            contact_id
+        )
+        .fetch_one(&test_ctx.pool)
+        .await
+        .unwrap()
+        .unwrap()
+    };
+
+    let before = updated_at().await;
+    sqlx::query!(
+        "INSERT INTO interactions (workspace_id, contact_id, interaction_date) VALUES ($1, $2, NOW())",
+        workspace_id,
+        contact_id
+    )
+    .execute(&test_ctx.pool)
+    .await
+    .unwrap();
+    let after_interaction = updated_at().await;
+    assert!(after_interaction > before);
+
+    let tag_id = sqlx::query_scalar!(
+        "INSERT INTO tags (workspace_id, name) VALUES ($1, $2) RETURNING tag_id",
+        workspace_id,
+        format!("touch-{}", workspace_id)
+    )
+    .fetch_one(&test_ctx.pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        "INSERT INTO contact_tags (contact_id, tag_id) VALUES ($1, $2)",
+        contact_id,
+        tag_id
+    )
+    .execute(&test_ctx.pool)
+    .await
+    .unwrap();
+    let after_tagging = updated_at().await;
+    assert!(after_tagging > after_interaction);
+
+    sqlx::query!("UPDATE tags SET color = 'red' WHERE tag_id = $1", tag_id)
+        .execute(&test_ctx.pool)
+        .await
+        .unwrap();
+    assert!(updated_at().await > after_tagging);
+}
+
+/// Test that the desired frequency is stored by both the single and the bulk insert
+#[tokio::test]
+async fn test_desired_frequency_round_trip() {
+    let test_ctx = setup_test_db().await;
+    let workspace_id = setup_test_workspace(&test_ctx.pool).await;
+    let repo = PgRepository::new(test_ctx.pool.clone());
+
+    let every = |name: &str, days: Option<i32>| NewContactRequest {
+        desired_frequency_days: days,
+        ..contact(name)
+    };
+    let single = repo
+        .create_contact(workspace_id, &every("Monthly", Some(30)))
+        .await
+        .unwrap();
+    let bulk = repo
+        .create_contacts(
+            workspace_id,
+            &[every("Weekly", Some(7)), every("Whenever", None)],
+            true,
+        )
+        .await
+        .unwrap();
+
+    let frequency = |contact_id: i32| {
+        let repo = repo.clone();
+        async move {
+            repo.get_contact(workspace_id, contact_id)
+                .await
+                .unwrap()
+                .unwrap()
+                .desired_frequency_days
+        }
+    };
+    assert_eq!(frequency(single).await, Some(30));
+    assert_eq!(frequency(*bulk[0].as_ref().unwrap()).await, Some(7));
+    assert_eq!(frequency(*bulk[1].as_ref().unwrap()).await, None);
+}
+
+/// Test that sorting by priority in the database agrees with the priorities in the response
+#[actix_rt::test]
+async fn test_list_contacts_by_priority() {
+    let test_ctx = setup_test_db().await;
+    let tenant = provision_user(&test_ctx.pool, "priority").await.unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(repository::app_data(PgRepository::new(
+                test_ctx.pool.clone(),
+            )))
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let auth = ("Authorization", format!("Bearer {}", tenant.token));
+    let today = OffsetDateTime::now_utc().date();
+
+    let create = async |body: Value| -> i64 {
+        let req = test::TestRequest::post()
+            .uri("/v1/contacts")
+            .insert_header(auth.clone())
+            .set_json(body)
+            .to_request();
+        let created: Value = test::call_and_read_body_json(&app, req).await;
+        created["contact_id"].as_i64().unwrap()
+    };
+    let steady = create(json!({"first_name": "Steady"})).await;
+    let weekly = create(json!({"first_name": "Weekly", "desired_frequency_days": 7})).await;
+    let birthday = create(json!({"first_name": "Birthday"})).await;
+    let unknown = create(json!({"first_name": "Unknown"})).await;
+
+    let interactions = [
+        (steady, 90),
+        (steady, 60),
+        (steady, 10),
+        (weekly, 20),
+        (birthday, 50),
+        (birthday, 45),
+    ];
+    for (contact_id, days_ago) in interactions {
+        let req = test::TestRequest::post()
+            .uri("/v1/interactions")
+            .insert_header(auth.clone())
+            .set_json(json!({
+                "contact_id": contact_id,
+                "interaction_date": format!("{}T12:00:00Z", today - Duration::days(days_ago))
+            }))
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+    }
+    let req = test::TestRequest::post()
+        .uri("/v1/occasions")
+        .insert_header(auth.clone())
+        .set_json(json!({
+            "contact_id": birthday,
+            "name": "Birthday",
+            "date": (today + Duration::days(3)).replace_year(1992).unwrap().to_string(),
+            "recurring": false,
+            "occasion_type": "birthday"
+        }))
+        .to_request();
+    assert!(test::call_service(&app, req).await.status().is_success());
+
+    let list = async |query: &str| -> Vec<Value> {
+        let req = test::TestRequest::get()
+            .uri(&format!("/v1/contacts{}", query))
+            .insert_header(auth.clone())
+            .to_request();
+        test::call_and_read_body_json(&app, req).await
+    };
+    let id = |contact: &Value| contact["contact"]["contact_id"].as_i64().unwrap();
+    let ids = |contacts: &[Value]| -> Vec<i64> { contacts.iter().map(id).collect() };
+
+    let mut expected = list("").await;
+    expected.sort_by(|a, b| {
+        let priority = |c: &Value| c["predicted_contact_priority"].as_f64();
+        match (priority(a), priority(b)) {
+            (Some(a), Some(b)) => b.total_cmp(&a),
+            (a, b) => a.is_none().cmp(&b.is_none()),
+        }
+        .then(id(a).cmp(&id(b)))
+    });
+    let by_priority = list("?sort=priority").await;
+    assert_eq!(ids(&by_priority), ids(&expected));
+    assert_eq!(ids(&by_priority).first(), Some(&birthday));
+    assert_eq!(ids(&by_priority).last(), Some(&unknown));
+
+    let page = list("?sort=priority&limit=2&offset=1").await;
+    assert_eq!(ids(&page), ids(&expected)[1..3]);
+
+    // Stored scores give the same order until an interaction makes one outdated
+    let repo = PgRepository::new(test_ctx.pool.clone());
+    scores::refresh_all(&repo).await.unwrap();
+    let current = |contact_id: i64| {
+        let pool = test_ctx.pool.clone();
+        async move {
+            sqlx::query_scalar!(
+                "SELECT COUNT(*) FROM contact_scores s JOIN contacts c USING (contact_id)
+                 WHERE s.contact_id = $1 AND s.contact_updated_at = c.updated_at",
+                contact_id as i32
+            )
+            .fetch_one(&pool)
+            .await
+            .unwrap()
+                == Some(1)
+        }
+    };
+    let stored = |contact_id: i64| {
+        let pool = test_ctx.pool.clone();
+        async move {
+            sqlx::query_scalar::<_, Option<f64>>(
+                "SELECT priority FROM contact_scores WHERE contact_id = $1",
+            )
+            .bind(contact_id as i32)
+            .fetch_one(&pool)
+            .await
+            .unwrap()
+        }
+    };
+    // The stored score is the one the response computes from the contact's history
+    let matches_computed = async |contacts: &[Value]| {
+        for contact in contacts {
+            let computed = contact["predicted_contact_priority"].as_f64();
+            match (stored(id(contact)).await, computed) {
+                (Some(stored), Some(computed)) => assert!(
+                    (stored - computed).abs() < 1e-9,
+                    "stored {} but computed {} for {}",
+                    stored,
+                    computed,
+                    id(contact)
+                ),
+                (stored, computed) => assert_eq!(stored, computed, "for {}", id(contact)),
+            }
+        }
+    };
+    for contact_id in [steady, weekly, birthday, unknown] {
+        assert!(current(contact_id).await);
+    }
+    matches_computed(&expected).await;
+    assert_eq!(stored(unknown).await, None);
+    assert_eq!(ids(&list("?sort=priority").await), ids(&expected));
+
+    for days_ago in [400, 300] {
+        let req = test::TestRequest::post()
+            .uri("/v1/interactions")
+            .insert_header(auth.clone())
+            .set_json(json!({
+                "contact_id": unknown,
+                "interaction_date": format!("{}T12:00:00Z", today - Duration::days(days_ago))
+            }))
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+    }
+    assert!(!current(unknown).await);
+    assert_ne!(ids(&list("?sort=priority").await).last(), Some(&unknown));
+
+    // The next refresh stores the new score
+    scores::refresh_all(&repo).await.unwrap();
+    assert!(current(unknown).await);
+    assert!(stored(unknown).await.is_some());
+    matches_computed(&list("").await).await;
+}
+
+/// Test that contact details loaded in one query match what the per-table queries return
+#[tokio::test]
+async fn test_contact_details() {
+    let test_ctx = setup_test_db().await;
+    let workspace_id = setup_test_workspace(&test_ctx.pool).await;
+    let other_id = setup_test_workspace(&test_ctx.pool).await;
+    let repo = PgRepository::new(test_ctx.pool.clone());
+
+    let ada = repo
+        .create_contact(workspace_id, &contact("Ada"))
+        .await
+        .unwrap();
+    let grace = repo
+        .create_contact(workspace_id, &contact("Grace"))
+        .await
+        .unwrap();
+    let empty = repo
+        .create_contact(workspace_id, &contact("Empty"))
+        .await
+        .unwrap();
+    let theirs = repo
+        .create_contact(other_id, &contact("Theirs"))
+        .await
+        .unwrap();
+
+    let tag_id = repo
+        .create_tag(
+            workspace_id,
+            &NewTagRequest {
+                name: format!("details-{}", workspace_id),
+                color: Some("#00ff00".to_string()),
+                details: None,
+            },
+        )
+        .await
+        .unwrap();
+    repo.add_tag_to_contact(ada, tag_id).await.unwrap();
+    repo.create_interaction_type(workspace_id, "Dinner")
+        .await
+        .unwrap();
+    // A shared interaction belongs to both participants
+    repo.create_interaction(
+        workspace_id,
+        &NewInteractionRequest {
+            contact_id: ada,
+            interaction_date: OffsetDateTime::now_utc(),
+            notes: Some("Caught up".to_string()),
+            follow_up_priority: Some(2),
+            interaction_type: Some("Dinner".to_string()),
+            contact_ids: vec![grace],
+            follow_up_in_days: None,
+        },
+        None,
+    )
+    .await
+    .unwrap();
+    repo.create_occasion(
+        workspace_id,
+        &NewOccasionRequest {
+            contact_id: grace,
+            name: "Birthday".to_string(),
+            date: Date::from_calendar_date(1990, Month::March, 4).unwrap(),
+            recurring: true,
+            recurring_interval: None,
+            details: None,
+            occasion_type: OccasionType::Birthday,
+            birth_year: Some(1990),
+            remind_days_before: None,
+        },
+    )
+    .await
+    .unwrap();
+
+    // Requested order is kept; other users' and unknown ids are left out
+    let details = repo
+        .contact_details(workspace_id, &[grace, theirs, empty, 0, ada])
+        .await
+        .unwrap();
+    let ids: Vec<i32> = details.iter().map(|d| d.contact.contact_id).collect();
+    assert_eq!(ids, vec![grace, empty, ada]);
+
+    for d in &details {
+        let id = d.contact.contact_id;
+        let expected = repo.get_contact(workspace_id, id).await.unwrap().unwrap();
+        assert_eq!(d.contact.updated_at, expected.updated_at);
+        let tags: Vec<Value> = repo
+            .tags_for_contacts(&[id])
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(_, t)| json!(t))
+            .collect();
+        assert_eq!(json!(d.tags), json!(tags));
+        let interactions = repo.interactions_for_contacts(&[id]).await.unwrap();
+        assert_eq!(json!(d.interactions), json!(interactions));
+        let occasions = repo.occasions_for_contacts(&[id]).await.unwrap();
+        assert_eq!(json!(d.occasions), json!(occasions));
+    }
+    assert_eq!(details[0].interactions[0].contact_ids, vec![grace]);
+    assert_eq!(details[0].occasions[0].birth_year, Some(1990));
+    assert!(details[1].tags.is_empty() && details[1].interactions.is_empty());
+}
+
+/// Check that a JSON Patch changes only the fields it names, all or nothing
+async fn check_json_patch(repo: web::Data<dyn Repository>) {
+    let owner = provision(repo.get_ref(), "json-patch").await.unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(repo.clone())
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let id = owner.contact_id;
+    repo.update_contact(
+        owner.workspace_id,
+        id,
+        &NewContactRequest {
+            last_name: Some("Lovelace".to_string()),
+            email: Some("ada@example.com".to_string()),
+            phone: Some("555-1234".to_string()),
+            notes: Some("Likes engines".to_string()),
+            desired_frequency_days: Some(14),
+            ..contact("Ada")
+        },
+    )
+    .await
+    .unwrap();
+    let patch = |operations: Value| {
+        test::TestRequest::patch()
+            .uri(&format!("/v1/contacts/{}", id))
+            .insert_header(("Authorization", format!("Bearer {}", owner.token)))
+            .insert_header(("Content-Type", "application/json-patch+json"))
+            .set_payload(operations.to_string())
+            .to_request()
+    };
+
+    let res = test::call_service(
+        &app,
+        patch(json!([
+            { "op": "test", "path": "/notes", "value": "Likes engines" },
+            { "op": "replace", "path": "/notes", "value": "Likes engines and poetry" },
+            { "op": "remove", "path": "/phone" },
+            { "op": "replace", "path": "/email", "value": "ada@analytical.org" }
+        ])),
+    )
+    .await;
+    assert_eq!(res.status(), 200);
+    let contact: Value = test::read_body_json(res).await;
+    assert_eq!(contact["notes"], "Likes engines and poetry");
+    assert_eq!(contact["phone"], Value::Null);
+    assert_eq!(contact["email"], "ada@analytical.org");
+    assert_eq!(contact["last_name"], "Lovelace");
+    assert_eq!(contact["desired_frequency_days"], 14);
+
+    // A stale `test` changes nothing and sends the fields as saved
+    let res = test::call_service(
+        &app,
+        patch(json!([
+            { "op": "replace", "path": "/first_name", "value": "Augusta" },
+            { "op": "test", "path": "/notes", "value": "Likes engines" }
+        ])),
+    )
+    .await;
+    assert_eq!(res.status(), 409);
+    let conflict: Value = test::read_body_json(res).await;
+    assert_eq!(conflict["contact"]["notes"], "Likes engines and poetry");
+
+    for operations in [
+        json!([{ "op": "add", "path": "/nickname", "value": "Ada" }]),
+        json!([{ "op": "replace", "path": "/desired_frequency_days", "value": "often" }]),
+        json!([{ "op": "remove", "path": "/met_at/place" }]),
+    ] {
+        let res = test::call_service(&app, patch(operations.clone())).await;
+        assert_eq!(res.status(), 422, "{} was applied", operations);
+    }
+    let res = test::call_service(
+        &app,
+        patch(json!([{ "op": "replace", "path": "/desired_frequency_days", "value": 0 }])),
+    )
+    .await;
+    assert_eq!(res.status(), 400);
+    let res = test::call_service(&app, patch(json!({ "op": "remove" }))).await;
+    assert_eq!(res.status(), 400);
+
+    let contact = repo
+        .get_contact(owner.workspace_id, id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(contact.first_name.as_deref(), Some("Ada"));
+    assert_eq!(contact.desired_frequency_days, Some(14));
+}
+
+#[actix_rt::test]
+async fn test_json_patch_in_memory() {
+    check_json_patch(repository::app_data(InMemoryRepository::new())).await;
+}
+
+#[actix_rt::test]
+async fn test_json_patch_in_postgres() {
+    let test_ctx = setup_test_db().await;
+    check_json_patch(repository::app_data(PgRepository::new(
+        test_ctx.pool.clone(),
+    )))
+    .await;
+}
+
+#[actix_rt::test]
+async fn test_json_patch_in_sqlite() {
+    let repo = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+    check_json_patch(repository::app_data(repo)).await;
+}
+
+/// Check that checksums are stable while nothing changes, and that an edit to a contact or
+/// its tags changes only that contact's hash and the global one
+async fn check_checksum(repo: web::Data<dyn Repository>) {
+    let owner = provision(repo.get_ref(), "checksum").await.unwrap();
+    let bystander = repo
+        .create_contact(owner.workspace_id, &contact("Grace"))
+        .await
+        .unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(repo.clone())
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let checksum = || async {
+        let req = test::TestRequest::get()
+            .uri("/v1/contacts/checksum")
+            .insert_header(("Authorization", format!("Bearer {}", owner.token)))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        let hash = |contact_id: i32| {
+            body["contacts"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|c| c["contact_id"] == contact_id)
+                .map(|c| c["hash"].as_str().unwrap().to_string())
+                .unwrap()
+        };
+        (
+            body["global_hash"].as_str().unwrap().to_string(),
+            hash(owner.contact_id),
+            hash(bystander),
+        )
+    };
+
+    let before = checksum().await;
+    assert_eq!(checksum().await, before);
+
+    repo.update_contact(
+        owner.workspace_id,
+        owner.contact_id,
+        &NewContactRequest {
+            notes: Some("Met at the conference".to_string()),
+            ..contact("checksum")
+        },
+    )
+    .await
+    .unwrap();
+    let edited = checksum().await;
+    assert_ne!(edited.0, before.0);
+    assert_ne!(edited.1, before.1);
+    assert_eq!(edited.2, before.2);
+    assert_eq!(checksum().await, edited);
+
+    let tag_id = repo
+        .create_tag(
+            owner.workspace_id,
+            &NewTagRequest {
+                name: "conference".to_string(),
+                color: None,
+                details: None,
+            },
+        )
+        .await
+        .unwrap();
+    repo.add_tag_to_contact(owner.contact_id, tag_id)
+        .await
+        .unwrap();
+    let tagged = checksum().await;
+    assert_ne!(tagged.0, edited.0);
+    assert_ne!(tagged.1, edited.1);
+    assert_eq!(tagged.2, before.2);
+}
+
+#[actix_rt::test]
+async fn test_checksum_in_memory() {
+    check_checksum(repository::app_data(InMemoryRepository::new())).await;
+}
+
+#[actix_rt::test]
+async fn test_checksum_in_postgres() {
+    let test_ctx = setup_test_db().await;
+    check_checksum(repository::app_data(PgRepository::new(
+        test_ctx.pool.clone(),
+    )))
+    .await;
+}
+
+#[actix_rt::test]
+async fn test_checksum_in_sqlite() {
+    let repo = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+    check_checksum(repository::app_data(repo)).await;
 }
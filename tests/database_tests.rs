@@ -0,0 +1,109 @@
+mod common;
+
+use actix_web::middleware::from_fn;
+use actix_web::{App, HttpResponse, test, web};
+use common::*;
+use personal_crm::database::{DbConfig, as_read_request, in_read_request, replica_reads};
+use personal_crm::repository::{PgRepository, Repository};
+use sqlx::postgres::PgConnectOptions;
+use std::time::{Duration, Instant};
+
+/// Test that pooled connections get the configured statement timeout
+#[tokio::test]
+async fn test_statement_timeout() {
+    let test_ctx = setup_test_db().await;
+    let config = DbConfig {
+        max_connections: 2,
+        statement_timeout: Some(Duration::from_millis(200)),
+        ..DbConfig::default()
+    };
+    let options = (*test_ctx.pool.connect_options()).clone();
+    let pool = config.connect(options).await.unwrap();
+
+    let timeout: String = sqlx::query_scalar("SHOW statement_timeout")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(timeout, "200ms");
+    assert!(
+        sqlx::query("SELECT pg_sleep(2)")
+            .execute(&pool)
+            .await
+            .is_err()
+    );
+}
+
+/// Test that connecting retries with backoff and gives up after the configured attempts
+#[tokio::test]
+async fn test_connect_retries() {
+    let config = DbConfig {
+        acquire_timeout: Duration::from_millis(200),
+        connect_attempts: 3,
+        initial_backoff: Duration::from_millis(50),
+        ..DbConfig::default()
+    };
+    let unreachable = PgConnectOptions::new().host("127.0.0.1").port(1);
+
+    let started = Instant::now();
+    assert!(config.connect(unreachable).await.is_err());
+    // Waited 50ms, then 100ms, between the three attempts
+    assert!(started.elapsed() >= Duration::from_millis(150));
+}
+
+/// Test that only list, get and search queries made during a read request go to the replica,
+/// and that they stay on the primary without one
+#[tokio::test]
+async fn test_read_replica_routing() {
+    let primary = setup_test_db().await;
+    // A schema of its own stands in for a replica that hasn't caught up
+    let replica = setup_test_db().await;
+    let workspace_id = setup_test_workspace(&primary.pool).await;
+    let repo = PgRepository::new(primary.pool.clone()).with_read_pool(Some(replica.pool.clone()));
+    let contact = contact("Ada");
+    let contact_id = repo.create_contact(workspace_id, &contact).await.unwrap();
+
+    // Outside a read request, e.g. reading back a write, queries go to the primary
+    assert!(
+        repo.get_contact(workspace_id, contact_id)
+            .await
+            .unwrap()
+            .is_some()
+    );
+    let (found, listed) = as_read_request(async {
+        (
+            repo.get_contact(workspace_id, contact_id).await.unwrap(),
+            repo.list_contacts(workspace_id).await.unwrap(),
+        )
+    })
+    .await;
+    assert!(found.is_none());
+    assert!(listed.is_empty());
+    // Ownership checks always go to the primary
+    assert!(
+        as_read_request(repo.owns_contact(workspace_id, contact_id))
+            .await
+            .unwrap()
+    );
+
+    let without_replica = PgRepository::new(primary.pool.clone());
+    let listed = as_read_request(without_replica.list_contacts(workspace_id))
+        .await
+        .unwrap();
+    assert_eq!(listed.len(), 1);
+}
+
+/// Test that the middleware marks GET requests as reads and leaves mutations on the primary
+#[actix_rt::test]
+async fn test_replica_reads_middleware() {
+    let app = test::init_service(App::new().wrap(from_fn(replica_reads)).route(
+        "/",
+        web::route().to(|| async { HttpResponse::Ok().body(in_read_request().to_string()) }),
+    ))
+    .await;
+
+    let get = test::TestRequest::get().uri("/").to_request();
+    assert_eq!(test::call_and_read_body(&app, get).await, "true");
+    let post = test::TestRequest::post().uri("/").to_request();
+    assert_eq!(test::call_and_read_body(&app, post).await, "false");
+    assert!(!in_read_request());
+}
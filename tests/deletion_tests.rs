@@ -0,0 +1,257 @@
+mod common;
+
+use actix_web::{App, test as actix_test, web};
+use common::*;
+use personal_crm::deletion::{DeleteBehavior, DeletePolicies};
+use personal_crm::repository::{
+    self, InMemoryRepository, PgRepository, Repository, SqliteRepository,
+};
+use personal_crm::routes::v1_routes;
+use personal_crm::test_support::provision;
+use serde_json::{Value, json};
+
+fn policies(contacts: DeleteBehavior) -> web::Data<DeletePolicies> {
+    web::Data::new(DeletePolicies { contacts })
+}
+
+#[test]
+fn test_parse_behavior() {
+    assert_eq!(" Orphan ".parse(), Ok(DeleteBehavior::Orphan));
+    assert_eq!("block".parse(), Ok(DeleteBehavior::Block));
+    assert_eq!("cascade".parse(), Ok(DeleteBehavior::Cascade));
+    assert!("archive".parse::<DeleteBehavior>().is_err());
+}
+
+/// Check that under `orphan` a deleted contact's interactions and occasions are kept,
+/// listed as unassigned until an update gives them a contact again
+async fn check_orphan(repo: web::Data<dyn Repository>) {
+    let owner = provision(repo.get_ref(), "orphan").await.unwrap();
+    let other = provision(repo.get_ref(), "orphan-other").await.unwrap();
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(repo.clone())
+            .app_data(policies(DeleteBehavior::Orphan))
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let auth = ("Authorization", format!("Bearer {}", owner.token));
+
+    let req = actix_test::TestRequest::post()
+        .uri("/v1/contacts/bulk-delete?dry_run=true")
+        .insert_header(auth.clone())
+        .set_json(json!({ "contact_ids": [owner.contact_id] }))
+        .to_request();
+    let preview: Value = actix_test::call_and_read_body_json(&app, req).await;
+    assert_eq!(preview["deleted_count"], 1);
+    assert_eq!(preview["interaction_count"], 0);
+    assert_eq!(preview["occasion_count"], 0);
+    assert_eq!(preview["unassigned_interaction_count"], 1);
+    assert_eq!(preview["unassigned_occasion_count"], 1);
+
+    let req = actix_test::TestRequest::delete()
+        .uri(&format!("/v1/contacts/{}", owner.contact_id))
+        .insert_header(auth.clone())
+        .to_request();
+    assert_eq!(actix_test::call_service(&app, req).await.status(), 200);
+
+    let unassigned = || {
+        actix_test::TestRequest::get()
+            .uri("/v1/unassigned")
+            .insert_header(auth.clone())
+            .to_request()
+    };
+    let listed: Value = actix_test::call_and_read_body_json(&app, unassigned()).await;
+    assert_eq!(listed["interactions"].as_array().unwrap().len(), 1);
+    assert_eq!(
+        listed["interactions"][0]["interaction_id"],
+        owner.interaction_id
+    );
+    assert_eq!(listed["interactions"][0]["contact_id"], Value::Null);
+    assert_eq!(listed["interactions"][0]["notes"], owner.marker);
+    assert_eq!(listed["occasions"].as_array().unwrap().len(), 1);
+    assert_eq!(listed["occasions"][0]["occasion_id"], owner.occasion_id);
+    // The attachment stays with its interaction
+    assert!(
+        repo.owns_attachment(owner.workspace_id, owner.attachment_id)
+            .await
+            .unwrap()
+    );
+
+    // Another user's contact can't take them
+    let req = actix_test::TestRequest::patch()
+        .uri(&format!("/v1/occasions/{}", owner.occasion_id))
+        .insert_header(auth.clone())
+        .set_json(json!({
+            "contact_id": other.contact_id,
+            "name": "Anniversary",
+            "date": "2024-06-01",
+            "recurring": true
+        }))
+        .to_request();
+    assert_eq!(actix_test::call_service(&app, req).await.status(), 404);
+
+    let req = actix_test::TestRequest::post()
+        .uri("/v1/contacts")
+        .insert_header(auth.clone())
+        .set_json(json!({ "first_name": "Grace" }))
+        .to_request();
+    let created: Value = actix_test::call_and_read_body_json(&app, req).await;
+    let contact_id = created["contact_id"].as_i64().unwrap();
+
+    let req = actix_test::TestRequest::patch()
+        .uri(&format!("/v1/interactions/{}", owner.interaction_id))
+        .insert_header(auth.clone())
+        .set_json(json!({
+            "contact_id": contact_id,
+            "interaction_date": "2024-03-01T12:00:00",
+            "notes": "Reassigned"
+        }))
+        .to_request();
+    assert_eq!(actix_test::call_service(&app, req).await.status(), 200);
+    let listed: Value = actix_test::call_and_read_body_json(&app, unassigned()).await;
+    assert!(listed["interactions"].as_array().unwrap().is_empty());
+    assert_eq!(listed["occasions"].as_array().unwrap().len(), 1);
+    let interaction = repo
+        .interactions_by_id(owner.workspace_id, &[owner.interaction_id])
+        .await
+        .unwrap()
+        .remove(0);
+    assert_eq!(interaction.contact_id, Some(contact_id as i32));
+
+    // Once assigned, an update's contact_id doesn't move it
+    let other_contact = repo
+        .create_contact(owner.workspace_id, &contact("Grace"))
+        .await
+        .unwrap();
+    let req = actix_test::TestRequest::patch()
+        .uri(&format!("/v1/interactions/{}", owner.interaction_id))
+        .insert_header(auth.clone())
+        .set_json(json!({
+            "contact_id": other_contact,
+            "interaction_date": "2024-03-01T12:00:00",
+            "notes": "Reassigned"
+        }))
+        .to_request();
+    assert_eq!(actix_test::call_service(&app, req).await.status(), 200);
+    let interaction = repo
+        .interactions_by_id(owner.workspace_id, &[owner.interaction_id])
+        .await
+        .unwrap()
+        .remove(0);
+    assert_eq!(interaction.contact_id, Some(contact_id as i32));
+}
+
+#[actix_rt::test]
+async fn test_orphan_in_memory() {
+    check_orphan(repository::app_data(InMemoryRepository::new())).await;
+}
+
+#[actix_rt::test]
+async fn test_orphan_in_postgres() {
+    let ctx = setup_test_db().await;
+    check_orphan(repository::app_data(PgRepository::new(ctx.pool.clone()))).await;
+}
+
+#[actix_rt::test]
+async fn test_orphan_in_sqlite() {
+    let repo = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+    check_orphan(repository::app_data(repo)).await;
+}
+
+/// Check that under `block` contacts with history are only deleted with `?force=true`, which
+/// takes their history with them, and that one such contact refuses a whole bulk delete
+async fn check_block(repo: web::Data<dyn Repository>) {
+    let owner = provision(repo.get_ref(), "block").await.unwrap();
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(repo.clone())
+            .app_data(policies(DeleteBehavior::Block))
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let auth = ("Authorization", format!("Bearer {}", owner.token));
+    let fresh = repo
+        .create_contact(owner.workspace_id, &contact("Grace"))
+        .await
+        .unwrap();
+
+    let bulk_delete = |query: &str| {
+        actix_test::TestRequest::post()
+            .uri(&format!("/v1/contacts/bulk-delete{}", query))
+            .insert_header(auth.clone())
+            .set_json(json!({ "contact_ids": [owner.contact_id, fresh] }))
+            .to_request()
+    };
+    for query in ["", "?dry_run=true"] {
+        let res = actix_test::call_service(&app, bulk_delete(query)).await;
+        assert_eq!(res.status(), 409);
+        let body: Value = actix_test::read_body_json(res).await;
+        assert_eq!(body["code"], "has_history");
+        assert_eq!(
+            body["contacts"],
+            json!([{
+                "contact_id": owner.contact_id,
+                "interaction_count": 1,
+                "occasion_count": 1
+            }])
+        );
+    }
+    assert!(repo.owns_contact(owner.workspace_id, fresh).await.unwrap());
+
+    // A contact without history goes as usual
+    let delete = |id: i32, query: &str| {
+        actix_test::TestRequest::delete()
+            .uri(&format!("/v1/contacts/{}{}", id, query))
+            .insert_header(auth.clone())
+            .to_request()
+    };
+    assert_eq!(
+        actix_test::call_service(&app, delete(fresh, ""))
+            .await
+            .status(),
+        200
+    );
+    assert_eq!(
+        actix_test::call_service(&app, delete(owner.contact_id, ""))
+            .await
+            .status(),
+        409
+    );
+    assert_eq!(
+        actix_test::call_service(&app, delete(owner.contact_id, "?force=true"))
+            .await
+            .status(),
+        200
+    );
+    assert!(
+        !repo
+            .owns_interaction(owner.workspace_id, owner.interaction_id)
+            .await
+            .unwrap()
+    );
+    assert!(
+        !repo
+            .owns_occasion(owner.workspace_id, owner.occasion_id)
+            .await
+            .unwrap()
+    );
+    let unassigned = repo.list_unassigned(owner.workspace_id).await.unwrap();
+    assert!(unassigned.interactions.is_empty() && unassigned.occasions.is_empty());
+}
+
+#[actix_rt::test]
+async fn test_block_in_memory() {
+    check_block(repository::app_data(InMemoryRepository::new())).await;
+}
+
+#[actix_rt::test]
+async fn test_block_in_postgres() {
+    let ctx = setup_test_db().await;
+    check_block(repository::app_data(PgRepository::new(ctx.pool.clone()))).await;
+}
+
+#[actix_rt::test]
+async fn test_block_in_sqlite() {
+    let repo = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+    check_block(repository::app_data(repo)).await;
+}
@@ -0,0 +1,96 @@
+use actix_web::http::Method;
+use actix_web::middleware::from_fn;
+use actix_web::{App, HttpResponse, Responder, get, post, test, web};
+use personal_crm::deprecation::{DeprecatedRoute, DeprecationRegistry, deprecation};
+use time::macros::date;
+
+#[post("/old")]
+async fn old() -> impl Responder {
+    HttpResponse::Ok().json(serde_json::json!({ "message": "done" }))
+}
+
+#[get("/old")]
+async fn old_list() -> impl Responder {
+    HttpResponse::Ok().json(vec![1, 2, 3])
+}
+
+#[get("/new")]
+async fn new() -> impl Responder {
+    HttpResponse::Ok().json(serde_json::json!({ "message": "done" }))
+}
+
+fn registry() -> DeprecationRegistry {
+    DeprecationRegistry::new(vec![DeprecatedRoute {
+        method: Method::POST,
+        pattern: "/v1/old",
+        deprecated_at: date!(2026 - 01 - 01),
+        sunset_at: Some(date!(2026 - 07 - 01)),
+        successor: Some("/v1/new"),
+        message: "",
+    }])
+}
+
+/// Test that registered endpoints get headers and a warning field
+#[actix_rt::test]
+async fn test_deprecated_route_is_annotated() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(registry()))
+            .wrap(from_fn(deprecation))
+            .service(
+                web::scope("/v1")
+                    .service(old)
+                    .service(old_list)
+                    .service(new),
+            ),
+    )
+    .await;
+
+    let req = test::TestRequest::post().uri("/v1/old").to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.headers().get("Deprecation").unwrap(), "@1767225600");
+    assert_eq!(
+        res.headers().get("Sunset").unwrap(),
+        "Wed, 01 Jul 2026 00:00:00 GMT"
+    );
+    assert_eq!(
+        res.headers().get("Link").unwrap(),
+        "</v1/new>; rel=\"successor-version\""
+    );
+
+    let body: serde_json::Value = test::read_body_json(res).await;
+    assert_eq!(body["message"], "done");
+    assert!(
+        body["deprecation_warning"]
+            .as_str()
+            .unwrap()
+            .contains("use /v1/new instead")
+    );
+}
+
+/// Test that other methods and routes are left untouched
+#[actix_rt::test]
+async fn test_other_routes_are_untouched() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(registry()))
+            .wrap(from_fn(deprecation))
+            .service(
+                web::scope("/v1")
+                    .service(old)
+                    .service(old_list)
+                    .service(new),
+            ),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/v1/old").to_request();
+    let res = test::call_service(&app, req).await;
+    assert!(res.headers().get("Deprecation").is_none());
+    let body: serde_json::Value = test::read_body_json(res).await;
+    assert_eq!(body, serde_json::json!([1, 2, 3]));
+
+    let req = test::TestRequest::get().uri("/v1/new").to_request();
+    let res = test::call_service(&app, req).await;
+    assert!(res.headers().get("Deprecation").is_none());
+}
@@ -0,0 +1,184 @@
+mod common;
+
+use common::*;
+use personal_crm::encryption::{self, ENCRYPTED_PREFIX, NotesCipher};
+use personal_crm::models::NewContactRequest;
+use personal_crm::repository::{PgRepository, Repository};
+use personal_crm::test_support::provision;
+
+fn cipher(byte: u8) -> NotesCipher {
+    NotesCipher::new(&[byte; 32]).unwrap()
+}
+
+fn noted(short_note: &str, notes: &str) -> NewContactRequest {
+    NewContactRequest {
+        short_note: Some(short_note.to_string()),
+        notes: Some(notes.to_string()),
+        ..contact("Ada")
+    }
+}
+
+#[test]
+fn test_notes_cipher() {
+    let key = cipher(7);
+    let sealed = key.encrypt("Sister is getting married in June");
+    assert!(sealed.starts_with(ENCRYPTED_PREFIX));
+    assert!(!sealed.contains("married"));
+    assert_eq!(
+        key.decrypt(&sealed).unwrap(),
+        "Sister is getting married in June"
+    );
+    // Fresh nonces: equal notes don't give equal ciphertexts
+    assert_ne!(key.encrypt("same"), key.encrypt("same"));
+    assert_eq!(
+        key.decrypt("written before the key").unwrap(),
+        "written before the key"
+    );
+
+    assert!(cipher(8).decrypt(&sealed).is_err());
+    assert!(key.decrypt(&format!("{}AAAA", ENCRYPTED_PREFIX)).is_err());
+    assert!(NotesCipher::new(&[0; 16]).is_err());
+
+    assert_eq!(
+        encryption::open(None, Some("plain".to_string()))
+            .unwrap()
+            .as_deref(),
+        Some("plain")
+    );
+    assert!(encryption::open(None, Some(sealed)).is_err());
+    assert_eq!(
+        encryption::seal(None, Some("plain")).as_deref(),
+        Some("plain")
+    );
+}
+
+/// Test that notes are stored encrypted, with their history, and read back in plaintext
+#[actix_rt::test]
+async fn test_notes_encrypted_at_rest() {
+    let ctx = setup_test_db().await;
+    let repo = PgRepository::new(ctx.pool.clone()).with_notes_cipher(Some(cipher(1)));
+    let tenant = provision(&repo, "encrypted-notes").await.unwrap();
+    let workspace_id = tenant.workspace_id;
+
+    let contact_id = repo
+        .create_contact(workspace_id, &noted("Met at PyCon", "Allergic to cats"))
+        .await
+        .unwrap();
+    let stored: (String, String) =
+        sqlx::query_as("SELECT short_note, notes FROM contacts WHERE contact_id = $1")
+            .bind(contact_id)
+            .fetch_one(&ctx.pool)
+            .await
+            .unwrap();
+    assert!(stored.0.starts_with(ENCRYPTED_PREFIX));
+    assert!(stored.1.starts_with(ENCRYPTED_PREFIX));
+    let read = repo
+        .get_contact(workspace_id, contact_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(read.short_note.as_deref(), Some("Met at PyCon"));
+    assert_eq!(read.notes.as_deref(), Some("Allergic to cats"));
+
+    let ids = repo
+        .create_contacts(workspace_id, &[noted("Bulk", "Bulk notes")], true)
+        .await
+        .unwrap();
+    let bulk_id = *ids[0].as_ref().unwrap();
+    let listed = repo.list_contacts(workspace_id).await.unwrap();
+    let bulk = listed.iter().find(|c| c.contact_id == bulk_id).unwrap();
+    assert_eq!(bulk.notes.as_deref(), Some("Bulk notes"));
+
+    assert!(
+        repo.update_contact(
+            workspace_id,
+            contact_id,
+            &noted("Met at PyCon", "Allergic to dogs")
+        )
+        .await
+        .unwrap()
+    );
+    let history = repo.contact_history(contact_id, None, None).await.unwrap();
+    // Only the changed note is recorded, though it was encrypted with a new nonce
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].field, "notes");
+    assert_eq!(history[0].before.as_deref(), Some("Allergic to cats"));
+    assert_eq!(history[0].after.as_deref(), Some("Allergic to dogs"));
+    let stored: String =
+        sqlx::query_scalar("SELECT new_value FROM contact_changes WHERE contact_id = $1")
+            .bind(contact_id)
+            .fetch_one(&ctx.pool)
+            .await
+            .unwrap();
+    assert!(stored.starts_with(ENCRYPTED_PREFIX));
+
+    let details = repo
+        .contact_details(workspace_id, &[contact_id])
+        .await
+        .unwrap();
+    assert_eq!(
+        details[0].contact.notes.as_deref(),
+        Some("Allergic to dogs")
+    );
+
+    // Without the key the notes can't be read
+    let keyless = PgRepository::new(ctx.pool.clone());
+    assert!(keyless.get_contact(workspace_id, contact_id).await.is_err());
+}
+
+/// Test that `encrypt_notes` encrypts notes written before the key was set, once
+#[actix_rt::test]
+async fn test_encrypt_existing_notes() {
+    let ctx = setup_test_db().await;
+    let plain = PgRepository::new(ctx.pool.clone());
+    let tenant = provision(&plain, "encrypt-existing").await.unwrap();
+    let workspace_id = tenant.workspace_id;
+    let contact_id = plain
+        .create_contact(workspace_id, &noted("Old short note", "Old notes"))
+        .await
+        .unwrap();
+    plain
+        .update_contact(
+            workspace_id,
+            contact_id,
+            &noted("Old short note", "Newer notes"),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(plain.encrypt_notes().await.unwrap(), 0);
+    let repo = PgRepository::new(ctx.pool.clone()).with_notes_cipher(Some(cipher(2)));
+    // Plaintext rows still read while they wait to be encrypted
+    let read = repo
+        .get_contact(workspace_id, contact_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(read.notes.as_deref(), Some("Newer notes"));
+
+    // The provisioned contact has no notes
+    assert_eq!(repo.encrypt_notes().await.unwrap(), 1);
+    assert_eq!(repo.encrypt_notes().await.unwrap(), 0);
+    let plaintext: i64 = sqlx::query_scalar(
+        "SELECT (SELECT COUNT(*) FROM contacts
+                 WHERE NOT starts_with(short_note, $1) OR NOT starts_with(notes, $1))
+              + (SELECT COUNT(*) FROM contact_changes
+                 WHERE field IN ('short_note', 'notes')
+                   AND (NOT starts_with(old_value, $1) OR NOT starts_with(new_value, $1)))",
+    )
+    .bind(ENCRYPTED_PREFIX)
+    .fetch_one(&ctx.pool)
+    .await
+    .unwrap();
+    assert_eq!(plaintext, 0);
+
+    let read = repo
+        .get_contact(workspace_id, contact_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(read.short_note.as_deref(), Some("Old short note"));
+    assert_eq!(read.notes.as_deref(), Some("Newer notes"));
+    let history = repo.contact_history(contact_id, None, None).await.unwrap();
+    assert_eq!(history[0].before.as_deref(), Some("Old notes"));
+}
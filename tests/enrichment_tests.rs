@@ -0,0 +1,209 @@
+mod common;
+
+use actix_web::{App, test as actix_test, web};
+use async_trait::async_trait;
+use common::*;
+use personal_crm::enrichment::{AvatarFinder, Enrichment, LookupOutcome, avatar_at};
+use personal_crm::models::{Contact, NewContactRequest, PendingAvatarLookup};
+use personal_crm::repository::{
+    self, InMemoryRepository, PgRepository, Repository, SqliteRepository,
+};
+use personal_crm::routes::v1_routes;
+use personal_crm::test_support::provision;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::Arc;
+use time::{Duration, OffsetDateTime};
+
+/// Avatars by email; None for the rest
+struct StaticFinder(HashMap<String, String>);
+
+#[async_trait]
+impl AvatarFinder for StaticFinder {
+    async fn find(&self, email: &str) -> Result<Option<String>, String> {
+        Ok(self.0.get(email).cloned())
+    }
+}
+
+struct FailingFinder;
+
+#[async_trait]
+impl AvatarFinder for FailingFinder {
+    async fn find(&self, _email: &str) -> Result<Option<String>, String> {
+        Err("Returned status 503".to_string())
+    }
+}
+
+fn finder(avatars: &[(&str, &str)]) -> Arc<dyn AvatarFinder> {
+    Arc::new(StaticFinder(
+        avatars
+            .iter()
+            .map(|(email, url)| (email.to_string(), url.to_string()))
+            .collect(),
+    ))
+}
+
+#[test]
+fn test_avatar_at() {
+    let body = json!({"avatar": "https://img.example/ada.png", "person": {"avatar": " "}});
+    assert_eq!(
+        avatar_at(&body, "/avatar").as_deref(),
+        Some("https://img.example/ada.png")
+    );
+    assert_eq!(avatar_at(&body, "/person/avatar"), None);
+    assert_eq!(avatar_at(&body, "/missing"), None);
+    assert_eq!(avatar_at(&json!({"avatar": 7}), "/avatar"), None);
+    for avatar in [
+        "javascript:alert(1)",
+        "data:image/png;base64,AAAA",
+        "/ada.png",
+    ] {
+        assert_eq!(avatar_at(&json!({ "avatar": avatar }), "/avatar"), None);
+    }
+}
+
+/// Check that the job works through contacts with an email but no avatar a batch at a time,
+/// waits before looking them up again, and that one-off lookups store the avatar
+async fn check_enrichment(repo: web::Data<dyn Repository>) {
+    let owner = provision(repo.get_ref(), "enrichment").await.unwrap();
+    let ws = owner.workspace_id;
+    let create = |new: NewContactRequest| {
+        let repo = repo.clone();
+        async move { repo.create_contact(ws, &new).await.unwrap() }
+    };
+    let ada = create(NewContactRequest {
+        email: Some("ada@example.com".to_string()),
+        ..contact("Ada")
+    })
+    .await;
+    let bob = create(NewContactRequest {
+        email: Some("bob@example.com".to_string()),
+        ..contact("Bob")
+    })
+    .await;
+    create(NewContactRequest {
+        email: Some("cy@example.com".to_string()),
+        avatar_url: Some("https://img.example/cy.png".to_string()),
+        ..contact("Cy")
+    })
+    .await;
+    let dee = create(contact("Dee")).await;
+    let eve = create(NewContactRequest {
+        email: Some("eve@example.com".to_string()),
+        ..contact("Eve")
+    })
+    .await;
+    repo.set_contact_archived(ws, eve, true).await.unwrap();
+
+    let now = OffsetDateTime::now_utc();
+    let due = |at: OffsetDateTime| {
+        let repo = repo.clone();
+        async move {
+            let pending = repo.pending_avatar_lookups(at, 10).await.unwrap();
+            pending.iter().map(|p| p.contact_id).collect::<Vec<i32>>()
+        }
+    };
+    assert_eq!(due(now).await, vec![owner.contact_id, ada, bob]);
+
+    let enrichment = Enrichment::new(vec![finder(&[(
+        "ada@example.com",
+        "https://img.example/ada.png",
+    )])])
+    .with_batch_size(2);
+    assert_eq!(
+        enrichment
+            .enrich_pending(repo.get_ref(), now)
+            .await
+            .unwrap(),
+        1
+    );
+    let saved = repo.get_contact(ws, ada).await.unwrap().unwrap();
+    assert_eq!(
+        saved.avatar_url.as_deref(),
+        Some("https://img.example/ada.png")
+    );
+    assert_eq!(due(now).await, vec![bob]);
+    assert_eq!(
+        enrichment
+            .enrich_pending(repo.get_ref(), now)
+            .await
+            .unwrap(),
+        0
+    );
+    assert!(due(now).await.is_empty());
+    assert!(due(now + Duration::days(29)).await.is_empty());
+    assert_eq!(
+        due(now + Duration::days(31)).await,
+        vec![owner.contact_id, bob]
+    );
+
+    // A failed lookup is tried again the next day, and doesn't hide what another finder found
+    let failing = Enrichment::new(vec![Arc::new(FailingFinder), finder(&[])]);
+    let pending = PendingAvatarLookup {
+        contact_id: bob,
+        workspace_id: ws,
+        email: "bob@example.com".to_string(),
+    };
+    let outcome = failing.enrich(repo.get_ref(), &pending, now).await.unwrap();
+    assert!(matches!(outcome, LookupOutcome::Failed(_)));
+    assert_eq!(due(now + Duration::days(2)).await, vec![bob]);
+    let bob_finder = finder(&[("bob@example.com", "https://img.example/bob.png")]);
+    let found = Enrichment::new(vec![Arc::new(FailingFinder), bob_finder.clone()]);
+    assert_eq!(
+        found.find("bob@example.com").await.unwrap().as_deref(),
+        Some("https://img.example/bob.png")
+    );
+
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(repo.clone())
+            .app_data(web::Data::new(Enrichment::new(vec![bob_finder])))
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let enrich = |contact_id: i32| {
+        actix_test::TestRequest::post()
+            .uri(&format!("/v1/contacts/{}/enrich", contact_id))
+            .insert_header(("Authorization", format!("Bearer {}", owner.token)))
+            .to_request()
+    };
+    let res = actix_test::call_service(&app, enrich(bob)).await;
+    assert_eq!(res.status(), 200);
+    let enriched: Contact = actix_test::read_body_json(res).await;
+    assert_eq!(
+        enriched.avatar_url.as_deref(),
+        Some("https://img.example/bob.png")
+    );
+    let res = actix_test::call_service(&app, enrich(bob)).await;
+    assert_eq!(res.status(), 409);
+    let res = actix_test::call_service(&app, enrich(dee)).await;
+    assert_eq!(res.status(), 400);
+    let res = actix_test::call_service(&app, enrich(owner.contact_id)).await;
+    assert_eq!(res.status(), 404);
+
+    let unconfigured = actix_test::init_service(
+        App::new()
+            .app_data(repo.clone())
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let res = actix_test::call_service(&unconfigured, enrich(eve)).await;
+    assert_eq!(res.status(), 400);
+}
+
+#[actix_rt::test]
+async fn test_enrichment_in_memory() {
+    check_enrichment(repository::app_data(InMemoryRepository::new())).await;
+}
+
+#[actix_rt::test]
+async fn test_enrichment_in_postgres() {
+    let ctx = setup_test_db().await;
+    check_enrichment(repository::app_data(PgRepository::new(ctx.pool.clone()))).await;
+}
+
+#[actix_rt::test]
+async fn test_enrichment_in_sqlite() {
+    let repo = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+    check_enrichment(repository::app_data(repo)).await;
+}
@@ -0,0 +1,130 @@
+use actix_web::body::MessageBody;
+use actix_web::{App, test, web};
+use futures::future::poll_fn;
+use personal_crm::events::{Action, ChangeEvent, Entity, EventBus};
+use personal_crm::repository::{self, InMemoryRepository};
+use personal_crm::routes::v1_routes;
+use personal_crm::test_support::register_token;
+use serde_json::{Value, json};
+use std::time::Duration;
+
+/// Test that a stream carries the caller's own changes and nothing of other users
+#[actix_rt::test]
+async fn test_event_stream() {
+    register_token("token-events-owner", "test|events-owner").await;
+    register_token("token-events-other", "test|events-other").await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(repository::app_data(InMemoryRepository::new()))
+            .app_data(web::Data::new(EventBus::default()))
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let create = |token: &str| {
+        test::TestRequest::post()
+            .uri("/v1/contacts")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .set_json(json!({"first_name": "Ada"}))
+            .to_request()
+    };
+
+    let req = test::TestRequest::get()
+        .uri("/v1/events")
+        .insert_header(("Authorization", "Bearer token-events-owner"))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), 200);
+    assert_eq!(
+        res.headers().get("Content-Type").unwrap(),
+        "text/event-stream"
+    );
+    let mut body = Box::pin(res.into_body());
+    let mut next_chunk = async || {
+        let chunk = tokio::time::timeout(
+            Duration::from_secs(5),
+            poll_fn(|cx| body.as_mut().poll_next(cx)),
+        )
+        .await
+        .expect("no event within 5 seconds");
+        String::from_utf8(chunk.unwrap().unwrap().to_vec()).unwrap()
+    };
+    assert_eq!(next_chunk().await, ": keepalive\n\n");
+
+    test::call_service(&app, create("token-events-other")).await;
+    let created: Value = test::call_and_read_body_json(&app, create("token-events-owner")).await;
+
+    let chunk = next_chunk().await;
+    let data = chunk
+        .strip_prefix("event: change\ndata: ")
+        .and_then(|c| c.strip_suffix("\n\n"))
+        .unwrap();
+    assert_eq!(
+        serde_json::from_str::<ChangeEvent>(data).unwrap(),
+        ChangeEvent {
+            entity: Entity::Contact,
+            action: Action::Created,
+            ids: vec![created["contact_id"].as_i64().unwrap() as i32],
+        }
+    );
+}
+
+/// Test the events published for tagging and bulk changes
+#[actix_rt::test]
+async fn test_mutations_publish() {
+    register_token("token-events-tagger", "test|events-tagger").await;
+
+    let bus = web::Data::new(EventBus::default());
+    let mut events = bus.subscribe();
+    let app = test::init_service(
+        App::new()
+            .app_data(repository::app_data(InMemoryRepository::new()))
+            .app_data(bus.clone())
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let auth = ("Authorization", "Bearer token-events-tagger");
+
+    let req = test::TestRequest::post()
+        .uri("/v1/contacts/bulk")
+        .insert_header(auth)
+        .set_json(json!([{"first_name": "Ada"}, {"first_name": "Grace"}]))
+        .to_request();
+    let created: Value = test::call_and_read_body_json(&app, req).await;
+    let contact_ids: Vec<i32> =
+        serde_json::from_value(created["created_contact_ids"].clone()).unwrap();
+
+    let req = test::TestRequest::post()
+        .uri("/v1/tags")
+        .insert_header(auth)
+        .set_json(json!({"name": "Friends"}))
+        .to_request();
+    let tag: Value = test::call_and_read_body_json(&app, req).await;
+    let tag_id = tag["tag_id"].as_i64().unwrap() as i32;
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/v1/contacts/{}/tags/{}", contact_ids[0], tag_id))
+        .insert_header(auth)
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+
+    // Failed requests publish nothing
+    let req = test::TestRequest::delete()
+        .uri("/v1/tags/999999")
+        .insert_header(auth)
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 404);
+
+    let mut received = Vec::new();
+    while let Ok((_, event)) = events.try_recv() {
+        received.push((event.entity, event.action, event.ids));
+    }
+    assert_eq!(
+        received,
+        vec![
+            (Entity::Contact, Action::Created, contact_ids.clone()),
+            (Entity::Tag, Action::Created, vec![tag_id]),
+            (Entity::Contact, Action::Updated, vec![contact_ids[0]]),
+        ]
+    );
+}
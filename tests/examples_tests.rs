@@ -0,0 +1,136 @@
+use actix_web::App;
+use actix_web::test::{
+    TestRequest, call_and_read_body_json, call_service, init_service, read_body_json,
+};
+use personal_crm::examples::{examples, get_example};
+use personal_crm::routes::ROUTES;
+use serde_json::Value;
+
+/// Check `value` against the subset of JSON Schema that schemars emits for our DTOs
+fn conforms(value: &Value, schema: &Value, root: &Value) -> Result<(), String> {
+    if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+        let name = reference.trim_start_matches("#/$defs/");
+        return conforms(value, &root["$defs"][name], root);
+    }
+
+    if let Some(options) = schema.get("anyOf").and_then(Value::as_array) {
+        return match options.iter().any(|o| conforms(value, o, root).is_ok()) {
+            true => Ok(()),
+            false => Err(format!("{} matches none of {}", value, schema)),
+        };
+    }
+
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array)
+        && !allowed.contains(value)
+    {
+        return Err(format!("{} is not one of {:?}", value, allowed));
+    }
+
+    let types: Vec<&str> = match &schema["type"] {
+        Value::String(t) => vec![t.as_str()],
+        Value::Array(ts) => ts.iter().filter_map(Value::as_str).collect(),
+        // Free-form JSON (serde_json::Value)
+        Value::Null => return Ok(()),
+        _ => return Err(format!("unsupported schema {}", schema)),
+    };
+
+    let matches = |t: &str| match t {
+        "null" => value.is_null(),
+        "string" => value.is_string(),
+        "boolean" => value.is_boolean(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        _ => false,
+    };
+    if !types.iter().any(|t| matches(t)) {
+        return Err(format!("{} is not one of {:?}", value, types));
+    }
+
+    if let Some(items) = value.as_array() {
+        for item in items {
+            conforms(item, &schema["items"], root)?;
+        }
+    }
+
+    if let Some(object) = value.as_object() {
+        let properties = schema["properties"]
+            .as_object()
+            .cloned()
+            .unwrap_or_default();
+        for required in schema["required"].as_array().into_iter().flatten() {
+            let key = required.as_str().unwrap();
+            if !object.contains_key(key) {
+                return Err(format!("missing required field {}", key));
+            }
+        }
+        for (key, field) in object {
+            // Maps list no properties, only the schema shared by every value
+            let field_schema = properties
+                .get(key)
+                .or_else(|| schema.get("additionalProperties").filter(|s| s.is_object()))
+                .ok_or_else(|| format!("field {} is not in the schema", key))?;
+            conforms(field, field_schema, root).map_err(|e| format!("{}: {}", key, e))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Test that every example documents a registered route and matches its DTO schema
+#[test]
+fn test_examples_match_routes_and_schemas() {
+    for example in examples() {
+        assert!(
+            ROUTES
+                .iter()
+                .any(|r| r.method == example.method && r.pattern == example.pattern),
+            "{} documents {} {}, which is not in ROUTES",
+            example.endpoint,
+            example.method,
+            example.pattern
+        );
+
+        for payload in example.request.iter().chain(example.response.iter()) {
+            let schema = serde_json::to_value(&payload.schema).unwrap();
+            if let Err(e) = conforms(&payload.example, &schema, &schema) {
+                panic!(
+                    "{} example does not match its schema: {}",
+                    example.endpoint, e
+                );
+            }
+        }
+    }
+}
+
+/// Test that examples are served by handler name and unknown names list the valid ones
+#[actix_rt::test]
+async fn test_get_example() {
+    let app = init_service(App::new().service(get_example)).await;
+
+    let req = TestRequest::get()
+        .uri("/api/examples/create_interaction")
+        .to_request();
+    let body: Value = call_and_read_body_json(&app, req).await;
+    assert_eq!(body["method"], "POST");
+    assert_eq!(body["pattern"], "/v1/interactions");
+    assert_eq!(
+        body["request"]["example"]["interaction_date"],
+        "2024-03-14T18:30:00Z"
+    );
+    assert!(body["request"]["schema"]["properties"]["interaction_date"].is_object());
+
+    let req = TestRequest::get()
+        .uri("/api/examples/no_such_endpoint")
+        .to_request();
+    let res = call_service(&app, req).await;
+    assert_eq!(res.status(), 404);
+    let body: Value = read_body_json(res).await;
+    assert!(
+        body["endpoints"]
+            .as_array()
+            .unwrap()
+            .contains(&Value::from("list_contacts"))
+    );
+}
@@ -0,0 +1,261 @@
+mod common;
+
+use actix_web::{App, HttpRequest, HttpResponse, HttpServer, test as actix_test, web};
+use common::*;
+use personal_crm::export::{ExportPusher, signature};
+use personal_crm::models::ExportSchedule;
+use personal_crm::repository::{self, InMemoryRepository, Repository};
+use personal_crm::routes::v1_routes;
+use personal_crm::test_support::register_token;
+use serde_json::{Value, json};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use time::Duration;
+use time::macros::datetime;
+
+/// Test the signature against an independently computed HMAC-SHA256
+#[test]
+fn test_signature() {
+    assert_eq!(
+        signature("secret", 1700000000, br#"{"a":1}"#),
+        "sha256=49f24e537407743fa4a0242bb63b94b9a47ee99cbbe071ccd8a22550ae411686"
+    );
+}
+
+/// (timestamp header, signature header, body) of every request the receiver got
+type Received = Arc<Mutex<Vec<(String, String, Vec<u8>)>>>;
+
+async fn receive(
+    req: HttpRequest,
+    body: web::Bytes,
+    received: web::Data<Received>,
+) -> HttpResponse {
+    let header = |name: &str| {
+        req.headers()
+            .get(name)
+            .and_then(|h| h.to_str().ok())
+            .unwrap_or_default()
+            .to_string()
+    };
+    received.lock().unwrap().push((
+        header("X-CRM-Timestamp"),
+        header("X-CRM-Signature"),
+        body.to_vec(),
+    ));
+    HttpResponse::Ok().finish()
+}
+
+/// Test that due schedules push a signed archive and are not pushed again until the
+/// interval has passed
+#[actix_rt::test]
+async fn test_scheduled_push() {
+    let received: Received = Arc::default();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/backup", listener.local_addr().unwrap());
+    let data = web::Data::new(received.clone());
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(data.clone())
+            .default_service(web::to(receive))
+    })
+    .workers(1)
+    .listen(listener)
+    .unwrap()
+    .run();
+    actix_rt::spawn(server);
+
+    let repo = InMemoryRepository::new();
+    let user = repo
+        .get_or_create_user("test|export", "export@example.com", "Export")
+        .await
+        .unwrap();
+    repo.create_contact(user.workspace_id, &contact("Ada"))
+        .await
+        .unwrap();
+    repo.save_export_schedule(&ExportSchedule {
+        user_id: user.user_id,
+        workspace_id: user.workspace_id,
+        url,
+        interval_hours: 24,
+        secret: "s3cret".to_string(),
+        last_pushed_at: None,
+        last_status: None,
+    })
+    .await
+    .unwrap();
+
+    let pusher = ExportPusher::default();
+    let now = datetime!(2024-03-15 02:00:00 UTC);
+    assert_eq!(pusher.run_due(&repo, now).await.unwrap(), 1);
+
+    let (timestamp, sig, body) = received.lock().unwrap().pop().unwrap();
+    assert_eq!(sig, signature("s3cret", timestamp.parse().unwrap(), &body));
+    let archive: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(archive["contacts"][0]["first_name"], "Ada");
+
+    let schedule = repo
+        .export_schedule(user.workspace_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(schedule.last_pushed_at, Some(now));
+    assert_eq!(schedule.last_status.as_deref(), Some("200 OK"));
+
+    assert_eq!(
+        pusher
+            .run_due(&repo, now + Duration::hours(23))
+            .await
+            .unwrap(),
+        0
+    );
+    assert_eq!(
+        pusher
+            .run_due(&repo, now + Duration::hours(24))
+            .await
+            .unwrap(),
+        1
+    );
+}
+
+/// Test that a push to a name resolving to the server's own network is refused before it
+/// connects
+#[actix_rt::test]
+async fn test_push_to_local_name() {
+    let received: Received = Arc::default();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!(
+        "http://localhost:{}/backup",
+        listener.local_addr().unwrap().port()
+    );
+    let data = web::Data::new(received.clone());
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(data.clone())
+            .default_service(web::to(receive))
+    })
+    .workers(1)
+    .listen(listener)
+    .unwrap()
+    .run();
+    actix_rt::spawn(server);
+
+    let repo = InMemoryRepository::new();
+    let user = repo
+        .get_or_create_user("test|export-local", "export-local@example.com", "Export")
+        .await
+        .unwrap();
+    repo.save_export_schedule(&ExportSchedule {
+        user_id: user.user_id,
+        workspace_id: user.workspace_id,
+        url,
+        interval_hours: 24,
+        secret: "s3cret".to_string(),
+        last_pushed_at: None,
+        last_status: None,
+    })
+    .await
+    .unwrap();
+
+    let now = datetime!(2024-03-15 02:00:00 UTC);
+    assert_eq!(
+        ExportPusher::default().run_due(&repo, now).await.unwrap(),
+        1
+    );
+    assert!(received.lock().unwrap().is_empty());
+    let schedule = repo
+        .export_schedule(user.workspace_id)
+        .await
+        .unwrap()
+        .unwrap();
+    let status = schedule.last_status.unwrap();
+    assert!(status.starts_with("Push failed"), "{}", status);
+}
+
+/// Test configuring a schedule and downloading the archive through the API
+#[actix_rt::test]
+async fn test_export_endpoints() {
+    register_token("token-export-owner", "test|export-owner").await;
+
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(repository::app_data(InMemoryRepository::new()))
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let auth = ("Authorization", "Bearer token-export-owner");
+
+    let req = actix_test::TestRequest::post()
+        .uri("/v1/contacts")
+        .insert_header(auth)
+        .set_json(json!({"first_name": "Ada"}))
+        .to_request();
+    assert!(
+        actix_test::call_service(&app, req)
+            .await
+            .status()
+            .is_success()
+    );
+
+    let req = actix_test::TestRequest::get()
+        .uri("/v1/export")
+        .insert_header(auth)
+        .to_request();
+    let archive: Value = actix_test::call_and_read_body_json(&app, req).await;
+    assert_eq!(archive["contacts"][0]["first_name"], "Ada");
+
+    for body in [
+        json!({"url": "ftp://backup.example.com", "interval_hours": 24}),
+        json!({"url": "not a url", "interval_hours": 24}),
+        json!({"url": "https://backup.example.com", "interval_hours": 0}),
+        // Nothing next to the server
+        json!({"url": "http://backup.example.com", "interval_hours": 24}),
+        json!({"url": "http://localhost:8080/backup", "interval_hours": 24}),
+        json!({"url": "https://localhost/backup", "interval_hours": 24}),
+        json!({"url": "http://169.254.169.254/latest/meta-data", "interval_hours": 24}),
+        json!({"url": "https://10.0.0.1/backup", "interval_hours": 24}),
+        json!({"url": "https://[::1]/backup", "interval_hours": 24}),
+        json!({"url": "https://nas.local/backup", "interval_hours": 24}),
+    ] {
+        let req = actix_test::TestRequest::put()
+            .uri("/v1/export/schedule")
+            .insert_header(auth)
+            .set_json(body)
+            .to_request();
+        assert_eq!(actix_test::call_service(&app, req).await.status(), 400);
+    }
+
+    let req = actix_test::TestRequest::put()
+        .uri("/v1/export/schedule")
+        .insert_header(auth)
+        .set_json(json!({"url": "https://backup.example.com/crm", "interval_hours": 24}))
+        .to_request();
+    let saved: Value = actix_test::call_and_read_body_json(&app, req).await;
+    assert_eq!(saved["secret"].as_str().unwrap().len(), 64);
+
+    // The secret is never shown again
+    let req = actix_test::TestRequest::get()
+        .uri("/v1/export/schedule")
+        .insert_header(auth)
+        .to_request();
+    let schedule: Value = actix_test::call_and_read_body_json(&app, req).await;
+    assert_eq!(
+        schedule,
+        json!({
+            "url": "https://backup.example.com/crm",
+            "interval_hours": 24,
+            "last_pushed_at": null,
+            "last_status": null
+        })
+    );
+
+    let req = actix_test::TestRequest::delete()
+        .uri("/v1/export/schedule")
+        .insert_header(auth)
+        .to_request();
+    assert_eq!(actix_test::call_service(&app, req).await.status(), 200);
+    let req = actix_test::TestRequest::get()
+        .uri("/v1/export/schedule")
+        .insert_header(auth)
+        .to_request();
+    assert_eq!(actix_test::call_service(&app, req).await.status(), 404);
+}
@@ -0,0 +1,306 @@
+mod common;
+
+use actix_web::{App, test as actix_test, web};
+use common::*;
+use personal_crm::fieldsets::{CONTACT_FIELDS, RESPONSE_FIELDS};
+use personal_crm::models::NewInteractionRequest;
+use personal_crm::repository::{
+    self, InMemoryRepository, PgRepository, Repository, SqliteRepository,
+};
+use personal_crm::routes::v1_routes;
+use personal_crm::test_support::provision;
+use serde_json::{Map, Value};
+use time::OffsetDateTime;
+use time::macros::datetime;
+
+fn keys(value: &Value) -> Vec<&str> {
+    value
+        .as_object()
+        .map(Map::keys)
+        .into_iter()
+        .flatten()
+        .map(String::as_str)
+        .collect()
+}
+
+/// Test that `?fields=` trims each contact to the named fields, and that naming every field
+/// gives the full response
+#[actix_rt::test]
+async fn test_contact_fieldsets() {
+    let repo = repository::app_data(InMemoryRepository::new());
+    let owner = provision(repo.get_ref(), "fieldsets").await.unwrap();
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(repo.clone())
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let list = |query: &str| {
+        actix_test::TestRequest::get()
+            .uri(&format!("/v1/contacts{}", query))
+            .insert_header(("Authorization", format!("Bearer {}", owner.token)))
+            .to_request()
+    };
+
+    let full: Value = actix_test::call_and_read_body_json(&app, list("")).await;
+    assert!(!full[0]["interactions"].as_array().unwrap().is_empty());
+
+    let every = CONTACT_FIELDS
+        .iter()
+        .chain(RESPONSE_FIELDS)
+        .copied()
+        .collect::<Vec<_>>()
+        .join(",");
+    let all: Value =
+        actix_test::call_and_read_body_json(&app, list(&format!("?fields={}", every))).await;
+    assert_eq!(all, full);
+
+    let sparse: Value = actix_test::call_and_read_body_json(
+        &app,
+        list("?fields=contact_id,first_name,last_name,tags"),
+    )
+    .await;
+    assert_eq!(keys(&sparse[0]), ["contact", "tags"]);
+    assert_eq!(
+        keys(&sparse[0]["contact"]),
+        ["contact_id", "first_name", "last_name"]
+    );
+    assert_eq!(
+        sparse[0]["contact"]["first_name"],
+        full[0]["contact"]["first_name"]
+    );
+    assert_eq!(sparse[0]["tags"], full[0]["tags"]);
+
+    for query in ["?fields=contact_id,history", "?fields="] {
+        let res = actix_test::call_service(&app, list(query)).await;
+        assert_eq!(res.status(), 400, "{}", query);
+    }
+}
+
+/// Test that `?include=` on a contact leaves out the lists it doesn't name, and that
+/// interactions are ordered and limited by date
+#[actix_rt::test]
+async fn test_contact_detail_include() {
+    let repo = repository::app_data(InMemoryRepository::new());
+    let owner = provision(repo.get_ref(), "detail-include").await.unwrap();
+    for date in [
+        datetime!(2020-01-02 10:00 UTC),
+        datetime!(2021-01-02 10:00 UTC),
+    ] {
+        repo.create_interaction(
+            owner.workspace_id,
+            &NewInteractionRequest {
+                contact_id: owner.contact_id,
+                interaction_date: date,
+                notes: None,
+                follow_up_priority: None,
+                interaction_type: None,
+                contact_ids: Vec::new(),
+                follow_up_in_days: None,
+            },
+            None,
+        )
+        .await
+        .unwrap();
+    }
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(repo.clone())
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let get = |query: &str| {
+        actix_test::TestRequest::get()
+            .uri(&format!("/v1/contacts/{}{}", owner.contact_id, query))
+            .insert_header(("Authorization", format!("Bearer {}", owner.token)))
+            .to_request()
+    };
+
+    let full: Value = actix_test::call_and_read_body_json(&app, get("")).await;
+    let dates: Vec<&Value> = full["interactions"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|i| &i["interaction_date"])
+        .collect();
+    assert_eq!(dates.len(), 3);
+    assert_eq!(dates[0], "2020-01-02T10:00:00Z");
+    let both: Value =
+        actix_test::call_and_read_body_json(&app, get("?include=occasions,interactions")).await;
+    assert_eq!(both, full);
+
+    let header: Value = actix_test::call_and_read_body_json(&app, get("?include=")).await;
+    assert_eq!(
+        keys(&header),
+        [
+            "avatar_source",
+            "avatar_url",
+            "contact",
+            "health",
+            "overdue_days",
+            "predicted_contact_priority",
+            "tags"
+        ]
+    );
+    assert_eq!(
+        header["predicted_contact_priority"],
+        full["predicted_contact_priority"]
+    );
+    assert_eq!(header["health"], full["health"]);
+    let occasions: Value =
+        actix_test::call_and_read_body_json(&app, get("?include=occasions")).await;
+    assert!(occasions.get("interactions").is_none());
+    assert_eq!(occasions["occasions"], full["occasions"]);
+
+    let latest: Value = actix_test::call_and_read_body_json(
+        &app,
+        get("?include=interactions&interactions_limit=2&interactions_order=desc"),
+    )
+    .await;
+    assert!(latest.get("occasions").is_none());
+    let latest: Vec<&Value> = latest["interactions"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|i| &i["interaction_date"])
+        .collect();
+    assert_eq!(latest, [dates[2], dates[1]]);
+
+    for query in ["?include=history", "?interactions_order=newest"] {
+        let res = actix_test::call_service(&app, get(query)).await;
+        assert_eq!(res.status(), 400, "{}", query);
+    }
+}
+
+/// Check that summaries follow the interactions a contact takes part in, and that
+/// `?include=summary` sends them in place of interactions and occasions
+async fn check_summaries(repo: web::Data<dyn Repository>) {
+    let owner = provision(repo.get_ref(), "summaries").await.unwrap();
+    let workspace_id = owner.workspace_id;
+    let today = OffsetDateTime::now_utc().date();
+    let guest = repo
+        .create_contact(workspace_id, &contact("Guest"))
+        .await
+        .unwrap();
+    let summary = |contact_id: i32| {
+        let repo = repo.clone();
+        async move {
+            repo.contact_summaries(workspace_id, &[contact_id], today)
+                .await
+                .unwrap()
+                .pop()
+                .map(|(_, summary)| summary)
+                .unwrap()
+        }
+    };
+
+    assert!(summary(guest).await.last_interaction_at.is_none());
+    assert_eq!(
+        summary(owner.contact_id).await.next_occasion_at,
+        Some(today)
+    );
+
+    let mut later = NewInteractionRequest {
+        contact_id: owner.contact_id,
+        interaction_date: datetime!(2099-01-02 10:00 UTC),
+        notes: None,
+        follow_up_priority: None,
+        interaction_type: None,
+        contact_ids: vec![guest],
+        follow_up_in_days: None,
+    };
+    let (interaction_id, _) = repo
+        .create_interaction(workspace_id, &later, None)
+        .await
+        .unwrap();
+    assert_eq!(
+        summary(guest).await.last_interaction_at,
+        Some(later.interaction_date)
+    );
+    assert_eq!(
+        summary(owner.contact_id).await.last_interaction_at,
+        Some(later.interaction_date)
+    );
+
+    later.interaction_date = datetime!(2098-01-02 10:00 UTC);
+    repo.update_interaction(workspace_id, interaction_id, &later)
+        .await
+        .unwrap();
+    assert_eq!(
+        summary(guest).await.last_interaction_at,
+        Some(later.interaction_date)
+    );
+
+    repo.delete_interaction(workspace_id, interaction_id)
+        .await
+        .unwrap();
+    assert!(summary(guest).await.last_interaction_at.is_none());
+    assert!(
+        summary(owner.contact_id)
+            .await
+            .last_interaction_at
+            .is_some()
+    );
+
+    // Someone else's contact has no summary to give
+    let other = provision(repo.get_ref(), "summaries-other").await.unwrap();
+    assert!(
+        repo.contact_summaries(workspace_id, &[other.contact_id], today)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(repo.clone())
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let list = |query: &str| {
+        actix_test::TestRequest::get()
+            .uri(&format!("/v1/contacts{}", query))
+            .insert_header(("Authorization", format!("Bearer {}", owner.token)))
+            .to_request()
+    };
+
+    let listed: Value = actix_test::call_and_read_body_json(&app, list("?include=summary")).await;
+    let owned = listed
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|c| c["contact"]["contact_id"] == owner.contact_id)
+        .unwrap();
+    assert_eq!(
+        keys(owned),
+        ["avatar_source", "avatar_url", "contact", "summary", "tags"]
+    );
+    assert_eq!(owned["summary"]["next_occasion_at"], today.to_string());
+
+    let sparse: Value = actix_test::call_and_read_body_json(
+        &app,
+        list("?include=summary&fields=contact_id,occasions"),
+    )
+    .await;
+    assert_eq!(keys(&sparse[0]), ["contact", "occasions", "summary"]);
+
+    let res = actix_test::call_service(&app, list("?include=history")).await;
+    assert_eq!(res.status(), 400);
+}
+
+#[actix_rt::test]
+async fn test_summaries_in_memory() {
+    check_summaries(repository::app_data(InMemoryRepository::new())).await;
+}
+
+#[actix_rt::test]
+async fn test_summaries_in_postgres() {
+    let ctx = setup_test_db().await;
+    check_summaries(repository::app_data(PgRepository::new(ctx.pool.clone()))).await;
+}
+
+#[actix_rt::test]
+async fn test_summaries_in_sqlite() {
+    let repo = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+    check_summaries(repository::app_data(repo)).await;
+}
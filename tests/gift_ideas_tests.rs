@@ -0,0 +1,273 @@
+mod common;
+
+use actix_web::{App, test as actix_test, web};
+use common::*;
+use personal_crm::models::{GiftIdea, GiftStatus, NewGiftIdeaRequest, Reminder, ReminderKind};
+use personal_crm::reminders::attach_gift_ideas;
+use personal_crm::repository::{
+    self, InMemoryRepository, PgRepository, Repository, SqliteRepository,
+};
+use personal_crm::routes::v1_routes;
+use personal_crm::test_support::provision;
+use serde_json::{Value, json};
+use time::macros::date;
+
+/// Test adding, listing, changing and removing a contact's gift ideas
+#[actix_rt::test]
+async fn test_gift_idea_crud() {
+    let repo = repository::app_data(InMemoryRepository::new());
+    let owner = provision(repo.get_ref(), "gifts").await.unwrap();
+    let other = provision(repo.get_ref(), "gifts-other").await.unwrap();
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(repo.clone())
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let auth = ("Authorization", format!("Bearer {}", owner.token));
+    let create = |body: Value| {
+        actix_test::TestRequest::post()
+            .uri("/v1/gift-ideas")
+            .insert_header(auth.clone())
+            .set_json(body)
+            .to_request()
+    };
+    let list = || {
+        actix_test::TestRequest::get()
+            .uri(&format!("/v1/contacts/{}/gift-ideas", owner.contact_id))
+            .insert_header(auth.clone())
+            .to_request()
+    };
+
+    let res = actix_test::call_service(
+        &app,
+        create(json!({ "contact_id": owner.contact_id, "idea": "Fountain pen" })),
+    )
+    .await;
+    assert_eq!(res.status(), 201);
+    let created: Value = actix_test::read_body_json(res).await;
+    let id = created["gift_idea_id"].as_i64().unwrap();
+
+    for body in [
+        json!({ "contact_id": owner.contact_id, "idea": "  " }),
+        json!({ "contact_id": owner.contact_id, "idea": "Scarf", "price_cents": -1 }),
+        // Another user's occasion
+        json!({ "contact_id": owner.contact_id, "idea": "Scarf", "occasion_id": other.occasion_id }),
+    ] {
+        let res = actix_test::call_service(&app, create(body.clone())).await;
+        assert_eq!(res.status(), 400, "{} was accepted", body);
+    }
+
+    // The provisioned idea first, then the new one with the defaults
+    let ideas: Vec<Value> =
+        actix_test::read_body_json(actix_test::call_service(&app, list()).await).await;
+    assert_eq!(ideas.len(), 2);
+    assert_eq!(ideas[1]["status"], "idea");
+    assert_eq!(ideas[1]["occasion_id"], Value::Null);
+    assert_eq!(ideas[1]["price_cents"], Value::Null);
+
+    let update = |body: Value| {
+        actix_test::TestRequest::patch()
+            .uri(&format!("/v1/gift-ideas/{}", id))
+            .insert_header(auth.clone())
+            .set_json(body)
+            .to_request()
+    };
+    let res = actix_test::call_service(
+        &app,
+        update(json!({
+            "contact_id": owner.contact_id,
+            "occasion_id": owner.occasion_id,
+            "idea": "Fountain pen and ink",
+            "status": "purchased",
+            "price_cents": 4500
+        })),
+    )
+    .await;
+    assert_eq!(res.status(), 200);
+    let ideas: Vec<Value> =
+        actix_test::read_body_json(actix_test::call_service(&app, list()).await).await;
+    assert_eq!(ideas[1]["status"], "purchased");
+    assert_eq!(ideas[1]["occasion_id"], owner.occasion_id);
+    assert_eq!(ideas[1]["price_cents"], 4500);
+
+    // Ideas stay on their contact
+    let res = actix_test::call_service(
+        &app,
+        update(json!({ "contact_id": other.contact_id, "idea": "Moved" })),
+    )
+    .await;
+    assert_eq!(res.status(), 400);
+
+    let req = actix_test::TestRequest::get()
+        .uri("/v1/export")
+        .insert_header(auth.clone())
+        .to_request();
+    let archive: Value =
+        actix_test::read_body_json(actix_test::call_service(&app, req).await).await;
+    assert_eq!(archive["gift_ideas"].as_array().unwrap().len(), 2);
+
+    let delete = || {
+        actix_test::TestRequest::delete()
+            .uri(&format!("/v1/gift-ideas/{}", id))
+            .insert_header(auth.clone())
+            .to_request()
+    };
+    assert_eq!(actix_test::call_service(&app, delete()).await.status(), 200);
+    assert_eq!(actix_test::call_service(&app, delete()).await.status(), 404);
+    let ideas: Vec<Value> =
+        actix_test::read_body_json(actix_test::call_service(&app, list()).await).await;
+    assert_eq!(ideas.len(), 1);
+}
+
+fn reminder(occasion_id: Option<i32>, contact_id: i32) -> Reminder {
+    Reminder {
+        kind: if occasion_id.is_some() {
+            ReminderKind::Occasion
+        } else {
+            ReminderKind::HolidayGreeting
+        },
+        remind_on: date!(2024 - 12 - 20),
+        date: date!(2024 - 12 - 22),
+        name: "Birthday".to_string(),
+        days_before: 2,
+        occasion_id,
+        contact_ids: vec![contact_id],
+        gift_ideas: Vec::new(),
+    }
+}
+
+fn idea(
+    gift_idea_id: i32,
+    contact_id: i32,
+    occasion_id: Option<i32>,
+    status: GiftStatus,
+) -> GiftIdea {
+    GiftIdea {
+        gift_idea_id,
+        contact_id,
+        occasion_id,
+        idea: format!("Idea {}", gift_idea_id),
+        status,
+        price_cents: None,
+    }
+}
+
+/// Test which gift ideas are listed on which reminders
+#[test]
+fn test_attach_gift_ideas() {
+    let mut reminders = vec![
+        reminder(Some(5), 1),
+        reminder(Some(6), 2),
+        reminder(None, 1),
+    ];
+    let ideas = [
+        idea(1, 1, Some(5), GiftStatus::Idea),
+        // For no occasion in particular
+        idea(2, 1, None, GiftStatus::Purchased),
+        idea(3, 1, Some(5), GiftStatus::Given),
+        // Another of the contact's occasions
+        idea(4, 1, Some(9), GiftStatus::Idea),
+        idea(5, 2, Some(6), GiftStatus::Idea),
+    ];
+    attach_gift_ideas(&mut reminders, &ideas);
+
+    let ids = |r: &Reminder| {
+        r.gift_ideas
+            .iter()
+            .map(|g| g.gift_idea_id)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(ids(&reminders[0]), vec![1, 2]);
+    assert_eq!(ids(&reminders[1]), vec![5]);
+    // Greetings carry no gift ideas
+    assert!(reminders[2].gift_ideas.is_empty());
+}
+
+/// Check that deleting an occasion keeps its gift ideas, for no occasion, and deleting the
+/// contact removes them
+async fn check_gift_ideas_follow_occasions(repo: web::Data<dyn Repository>) {
+    let owner = provision(repo.get_ref(), "gift-cascade").await.unwrap();
+    let ideas = repo
+        .gift_ideas_for_contacts(&[owner.contact_id])
+        .await
+        .unwrap();
+    assert_eq!(ideas.len(), 1);
+    assert_eq!(ideas[0].occasion_id, Some(owner.occasion_id));
+    assert_eq!(ideas[0].price_cents, Some(2500));
+
+    let second = repo
+        .create_gift_idea(
+            owner.workspace_id,
+            &NewGiftIdeaRequest {
+                contact_id: owner.contact_id,
+                occasion_id: None,
+                idea: "Book".to_string(),
+                status: GiftStatus::Given,
+                price_cents: Some(0),
+            },
+        )
+        .await
+        .unwrap();
+    assert!(
+        repo.owns_gift_idea(owner.workspace_id, second)
+            .await
+            .unwrap()
+    );
+    assert!(
+        !repo
+            .owns_gift_idea(owner.spare_workspace_id, second)
+            .await
+            .unwrap()
+    );
+
+    assert!(
+        repo.delete_occasion(owner.workspace_id, owner.occasion_id)
+            .await
+            .unwrap()
+    );
+    let ideas = repo
+        .gift_ideas_for_contacts(&[owner.contact_id])
+        .await
+        .unwrap();
+    assert_eq!(
+        ideas
+            .iter()
+            .map(|g| (g.gift_idea_id, g.occasion_id, g.status))
+            .collect::<Vec<_>>(),
+        vec![
+            (owner.gift_idea_id, None, GiftStatus::Idea),
+            (second, None, GiftStatus::Given),
+        ]
+    );
+
+    assert!(
+        repo.delete_contact(owner.workspace_id, owner.contact_id, false)
+            .await
+            .unwrap()
+    );
+    assert!(
+        repo.gift_ideas_for_contacts(&[owner.contact_id])
+            .await
+            .unwrap()
+            .is_empty()
+    );
+}
+
+#[actix_rt::test]
+async fn test_gift_ideas_follow_occasions_in_memory() {
+    check_gift_ideas_follow_occasions(repository::app_data(InMemoryRepository::new())).await;
+}
+
+#[actix_rt::test]
+async fn test_gift_ideas_follow_occasions_in_postgres() {
+    let ctx = setup_test_db().await;
+    check_gift_ideas_follow_occasions(repository::app_data(PgRepository::new(ctx.pool.clone())))
+        .await;
+}
+
+#[actix_rt::test]
+async fn test_gift_ideas_follow_occasions_in_sqlite() {
+    let repo = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+    check_gift_ideas_follow_occasions(repository::app_data(repo)).await;
+}
@@ -0,0 +1,243 @@
+use actix_web::{App, test as actix_test, web};
+use personal_crm::goals::{period_start, progress};
+use personal_crm::models::{Goal, GoalPeriod, Interaction};
+use personal_crm::repository::{self, InMemoryRepository};
+use personal_crm::routes::v1_routes;
+use personal_crm::test_support::provision;
+use personal_crm::timezone::LocalDates;
+use serde_json::{Value, json};
+use time::macros::{date, datetime};
+use time::{Date, OffsetDateTime};
+
+fn interaction(
+    contact_id: i32,
+    at: OffsetDateTime,
+    interaction_type: &str,
+    contact_ids: Vec<i32>,
+) -> Interaction {
+    Interaction {
+        interaction_id: 0,
+        contact_id: Some(contact_id),
+        interaction_date: at,
+        notes: None,
+        follow_up_priority: None,
+        interaction_type: Some(interaction_type.to_string()),
+        contact_ids,
+        tag_ids: Vec::new(),
+    }
+}
+
+fn call_on(contact_id: i32, day: Date) -> Interaction {
+    interaction(contact_id, day.midnight().assume_utc(), "call", Vec::new())
+}
+
+fn weekly_call(contact_id: Option<i32>) -> Goal {
+    Goal {
+        goal_id: 1,
+        name: "Call Mom".to_string(),
+        contact_id,
+        interaction_type: Some("call".to_string()),
+        target_count: 1,
+        period: GoalPeriod::Week,
+    }
+}
+
+/// Test that weeks start on Monday and months on the first
+#[test]
+fn test_period_start() {
+    assert_eq!(
+        period_start(GoalPeriod::Week, date!(2024 - 03 - 13)),
+        date!(2024 - 03 - 11)
+    );
+    assert_eq!(
+        period_start(GoalPeriod::Week, date!(2024 - 03 - 10)),
+        date!(2024 - 03 - 04)
+    );
+    assert_eq!(
+        period_start(GoalPeriod::Month, date!(2024 - 03 - 31)),
+        date!(2024 - 03 - 01)
+    );
+}
+
+/// Test counting the current week and the current and best streaks
+#[test]
+fn test_progress_streaks() {
+    let today = date!(2024 - 03 - 13);
+    let mut interactions: Vec<Interaction> = [
+        date!(2024 - 01 - 22),
+        date!(2024 - 01 - 29),
+        date!(2024 - 02 - 06),
+        date!(2024 - 02 - 15),
+        // Nothing the week of February 19
+        date!(2024 - 02 - 26),
+        date!(2024 - 03 - 08),
+    ]
+    .into_iter()
+    .map(|day| call_on(1, day))
+    .collect();
+    // Other contacts and types don't count
+    interactions.push(call_on(2, date!(2024 - 02 - 20)));
+    interactions.push(interaction(
+        1,
+        datetime!(2024 - 02 - 21 12:00 UTC),
+        "email",
+        Vec::new(),
+    ));
+
+    // The week under way doesn't break the streak yet
+    let goal = weekly_call(Some(1));
+    let status = progress(&goal, &interactions, &LocalDates::utc(), today);
+    assert_eq!(status.period_start, date!(2024 - 03 - 11));
+    assert_eq!(status.count, 0);
+    assert!(!status.completed);
+    assert_eq!(status.streak, 2);
+    assert_eq!(status.best_streak, 4);
+
+    // Taking part in someone else's interaction counts
+    interactions.push(interaction(
+        2,
+        datetime!(2024 - 03 - 12 12:00 UTC),
+        "call",
+        vec![1],
+    ));
+    let status = progress(&goal, &interactions, &LocalDates::utc(), today);
+    assert_eq!(status.count, 1);
+    assert!(status.completed);
+    assert_eq!(status.streak, 3);
+
+    // A goal across every contact also counts the week of February 19
+    let status = progress(&weekly_call(None), &interactions, &LocalDates::utc(), today);
+    assert_eq!(status.count, 1);
+    assert_eq!(status.streak, 8);
+    assert_eq!(status.best_streak, 8);
+}
+
+/// Test that periods follow the user's calendar days
+#[test]
+fn test_progress_in_timezone() {
+    // Sunday evening in Los Angeles, Monday in UTC
+    let interactions = vec![interaction(
+        1,
+        datetime!(2024 - 03 - 11 03:00 UTC),
+        "call",
+        Vec::new(),
+    )];
+    let goal = weekly_call(Some(1));
+    let today = date!(2024 - 03 - 13);
+
+    let utc = progress(&goal, &interactions, &LocalDates::utc(), today);
+    assert_eq!((utc.count, utc.streak), (1, 1));
+    let local = LocalDates::new(Some("America/Los_Angeles"));
+    let local = progress(&goal, &interactions, &local, today);
+    assert_eq!((local.count, local.streak), (0, 1));
+
+    let monthly = Goal {
+        period: GoalPeriod::Month,
+        target_count: 2,
+        ..goal
+    };
+    let status = progress(&monthly, &interactions, &LocalDates::utc(), today);
+    assert_eq!(status.period_start, date!(2024 - 03 - 01));
+    assert!(!status.completed);
+    assert_eq!(status.best_streak, 0);
+}
+
+/// Test managing goals and reading their progress through the API
+#[actix_rt::test]
+async fn test_goals_endpoints() {
+    let repo = repository::app_data(InMemoryRepository::new());
+    let owner = provision(repo.get_ref(), "goals").await.unwrap();
+    let other = provision(repo.get_ref(), "goals-other").await.unwrap();
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(repo.clone())
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let auth = ("Authorization", format!("Bearer {}", owner.token));
+    let create = |body: Value| {
+        actix_test::TestRequest::post()
+            .uri("/v1/goals")
+            .insert_header(auth.clone())
+            .set_json(body)
+            .to_request()
+    };
+
+    let res = actix_test::call_service(
+        &app,
+        create(json!({ "name": "  Keep in touch ", "target_count": 2, "period": "week" })),
+    )
+    .await;
+    assert_eq!(res.status(), 201);
+    let created: Value = actix_test::read_body_json(res).await;
+    let goal_id = created["goal_id"].as_i64().unwrap();
+
+    for (body, status) in [
+        (
+            json!({ "name": " ", "target_count": 2, "period": "week" }),
+            400,
+        ),
+        (
+            json!({ "name": "Lots", "target_count": 0, "period": "week" }),
+            400,
+        ),
+        (
+            json!({ "name": "Lots", "target_count": 1, "period": "week", "interaction_type": "carrier pigeon" }),
+            400,
+        ),
+        (
+            json!({ "name": "Theirs", "target_count": 1, "period": "month", "contact_id": other.contact_id }),
+            404,
+        ),
+    ] {
+        let res = actix_test::call_service(&app, create(body.clone())).await;
+        assert_eq!(res.status(), status, "{}", body);
+    }
+
+    // The provisioned contact's weekly goal is met by the interaction logged with it today
+    let req = actix_test::TestRequest::get()
+        .uri("/v1/goals/progress")
+        .insert_header(auth.clone())
+        .to_request();
+    let goals: Vec<Value> =
+        actix_test::read_body_json(actix_test::call_service(&app, req).await).await;
+    assert_eq!(goals.len(), 2);
+    let overall = goals.iter().find(|g| g["goal_id"] == goal_id).unwrap();
+    assert_eq!(overall["name"], "Keep in touch");
+    assert_eq!(overall["count"], 1);
+    assert_eq!(overall["completed"], false);
+    assert_eq!(overall["streak"], 0);
+    let contact = goals
+        .iter()
+        .find(|g| g["goal_id"] == owner.goal_id)
+        .unwrap();
+    assert_eq!(contact["contact_id"], owner.contact_id);
+    assert_eq!(contact["completed"], true);
+    assert_eq!(contact["streak"], 1);
+
+    let req = actix_test::TestRequest::patch()
+        .uri(&format!("/v1/goals/{}", other.goal_id))
+        .insert_header(auth.clone())
+        .set_json(json!({ "name": "Mine now", "target_count": 1, "period": "week" }))
+        .to_request();
+    assert_eq!(actix_test::call_service(&app, req).await.status(), 404);
+
+    // Deleting the contact deletes its goal
+    let req = actix_test::TestRequest::delete()
+        .uri(&format!("/v1/contacts/{}", owner.contact_id))
+        .insert_header(auth.clone())
+        .to_request();
+    assert_eq!(actix_test::call_service(&app, req).await.status(), 200);
+    let goals = repo.list_goals(owner.workspace_id).await.unwrap();
+    assert_eq!(goals.len(), 1);
+    assert_eq!(i64::from(goals[0].goal_id), goal_id);
+
+    let delete = || {
+        actix_test::TestRequest::delete()
+            .uri(&format!("/v1/goals/{}", goal_id))
+            .insert_header(auth.clone())
+            .to_request()
+    };
+    assert_eq!(actix_test::call_service(&app, delete()).await.status(), 200);
+    assert_eq!(actix_test::call_service(&app, delete()).await.status(), 404);
+}
@@ -0,0 +1,359 @@
+mod common;
+
+use actix_web::http::header::LOCATION;
+use actix_web::{App, test as actix_test, web};
+use common::*;
+use personal_crm::groups::render_digest;
+use personal_crm::models::{Contact, ContactSummary, Group, Interaction, OutboxPayload};
+use personal_crm::repository::{
+    self, InMemoryRepository, PgRepository, Repository, SqliteRepository,
+};
+use personal_crm::routes::v1_routes;
+use personal_crm::test_support::provision;
+use personal_crm::timezone::LocalDates;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use time::macros::date;
+use time::{Duration, OffsetDateTime};
+
+/// Check creating, listing, renaming and deleting groups, and keeping their members in order
+async fn check_groups(repo: web::Data<dyn Repository>) {
+    let owner = provision(repo.get_ref(), "groups").await.unwrap();
+    let other = provision(repo.get_ref(), "groups-other").await.unwrap();
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(repo.clone())
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let auth = ("Authorization", format!("Bearer {}", owner.token));
+    let ada = repo
+        .create_contact(owner.workspace_id, &contact("Ada"))
+        .await
+        .unwrap();
+    let grace = repo
+        .create_contact(owner.workspace_id, &contact("Grace"))
+        .await
+        .unwrap();
+
+    let create = |body: Value| {
+        actix_test::TestRequest::post()
+            .uri("/v1/groups")
+            .insert_header(auth.clone())
+            .set_json(body)
+            .to_request()
+    };
+    let res = actix_test::call_service(
+        &app,
+        create(json!({ "name": "  Book club ", "description": " " })),
+    )
+    .await;
+    assert_eq!(res.status(), 201);
+    let created: Group = actix_test::read_body_json(res).await;
+    assert_eq!(created.name, "Book club");
+    assert_eq!(created.description, None);
+    assert!(created.contact_ids.is_empty());
+    let id = created.group_id;
+    for body in [json!({ "name": "" }), json!({ "name": "x".repeat(101) })] {
+        let res = actix_test::call_service(&app, create(body.clone())).await;
+        assert_eq!(res.status(), 400, "{} was accepted", body);
+    }
+
+    // The roster keeps the order given, repeats counted once
+    let set = |contact_ids: Vec<i32>| {
+        actix_test::TestRequest::put()
+            .uri(&format!("/v1/groups/{}/contacts", id))
+            .insert_header(auth.clone())
+            .set_json(json!({ "contact_ids": contact_ids }))
+            .to_request()
+    };
+    let res = actix_test::call_service(&app, set(vec![grace, owner.contact_id, grace])).await;
+    assert_eq!(res.status(), 200);
+    let group: Group = actix_test::read_body_json(res).await;
+    assert_eq!(group.contact_ids, vec![grace, owner.contact_id]);
+
+    // Another user's contact changes nothing
+    let res = actix_test::call_service(&app, set(vec![ada, other.contact_id])).await;
+    assert_eq!(res.status(), 404);
+    let group = repo
+        .get_group(owner.workspace_id, id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(group.contact_ids, vec![grace, owner.contact_id]);
+
+    // Added contacts go last; adding a member again keeps its place
+    let member = |contact_id: i32| format!("/v1/groups/{}/contacts/{}", id, contact_id);
+    for contact_id in [ada, grace] {
+        let req = actix_test::TestRequest::post()
+            .uri(&member(contact_id))
+            .insert_header(auth.clone())
+            .to_request();
+        assert_eq!(actix_test::call_service(&app, req).await.status(), 200);
+    }
+    let req = actix_test::TestRequest::get()
+        .uri(&format!("/v1/groups/{}/contacts", id))
+        .insert_header(auth.clone())
+        .to_request();
+    let contacts: Vec<Contact> = actix_test::call_and_read_body_json(&app, req).await;
+    let ids: Vec<i32> = contacts.iter().map(|c| c.contact_id).collect();
+    assert_eq!(ids, vec![grace, owner.contact_id, ada]);
+
+    let remove = || {
+        actix_test::TestRequest::delete()
+            .uri(&member(grace))
+            .insert_header(auth.clone())
+            .to_request()
+    };
+    let res = actix_test::call_service(&app, remove()).await;
+    assert_eq!(res.status(), 200);
+    let group: Group = actix_test::read_body_json(res).await;
+    assert_eq!(group.contact_ids, vec![owner.contact_id, ada]);
+    assert_eq!(actix_test::call_service(&app, remove()).await.status(), 404);
+
+    // Deleting a contact takes it out of its groups
+    repo.delete_contact(owner.workspace_id, ada, false)
+        .await
+        .unwrap();
+
+    let req = actix_test::TestRequest::patch()
+        .uri(&format!("/v1/groups/{}", id))
+        .insert_header(auth.clone())
+        .set_json(json!({ "name": "Another book club", "description": "Thursdays" }))
+        .to_request();
+    let group: Group = actix_test::call_and_read_body_json(&app, req).await;
+    assert_eq!(group.name, "Another book club");
+    assert_eq!(group.description.as_deref(), Some("Thursdays"));
+    assert_eq!(group.contact_ids, vec![owner.contact_id]);
+
+    // The provisioned group sorts after "Another book club"
+    let req = actix_test::TestRequest::get()
+        .uri("/v1/groups")
+        .insert_header(auth.clone())
+        .to_request();
+    let groups: Vec<Group> = actix_test::call_and_read_body_json(&app, req).await;
+    let ids: Vec<i32> = groups.iter().map(|g| g.group_id).collect();
+    assert_eq!(ids, vec![id, owner.group_id]);
+
+    let req = actix_test::TestRequest::get()
+        .uri("/v1/export")
+        .insert_header(auth.clone())
+        .to_request();
+    let archive: Value = actix_test::call_and_read_body_json(&app, req).await;
+    assert_eq!(archive["groups"].as_array().unwrap().len(), 2);
+
+    let delete = || {
+        actix_test::TestRequest::delete()
+            .uri(&format!("/v1/groups/{}", id))
+            .insert_header(auth.clone())
+            .to_request()
+    };
+    assert_eq!(actix_test::call_service(&app, delete()).await.status(), 200);
+    assert_eq!(actix_test::call_service(&app, delete()).await.status(), 404);
+    assert!(
+        repo.owns_contact(owner.workspace_id, owner.contact_id)
+            .await
+            .unwrap()
+    );
+}
+
+#[actix_rt::test]
+async fn test_groups_in_memory() {
+    check_groups(repository::app_data(InMemoryRepository::new())).await;
+}
+
+#[actix_rt::test]
+async fn test_groups_in_postgres() {
+    let ctx = setup_test_db().await;
+    check_groups(repository::app_data(PgRepository::new(ctx.pool.clone()))).await;
+}
+
+#[actix_rt::test]
+async fn test_groups_in_sqlite() {
+    let repo = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+    check_groups(repository::app_data(repo)).await;
+}
+
+/// Check that one interaction is logged with every member taking part
+async fn check_group_interaction(repo: web::Data<dyn Repository>) {
+    let owner = provision(repo.get_ref(), "group-interaction")
+        .await
+        .unwrap();
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(repo.clone())
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let auth = ("Authorization", format!("Bearer {}", owner.token));
+    let ada = repo
+        .create_contact(owner.workspace_id, &contact("Ada"))
+        .await
+        .unwrap();
+    assert!(repo.add_group_member(owner.group_id, ada).await.unwrap());
+
+    let log = |group_id: i32, interaction_type: &str| {
+        actix_test::TestRequest::post()
+            .uri(&format!("/v1/groups/{}/interactions", group_id))
+            .insert_header(auth.clone())
+            .set_json(json!({
+                "interaction_date": "2024-03-14T19:00:00",
+                "notes": "Dinner party",
+                "interaction_type": interaction_type
+            }))
+            .to_request()
+    };
+    let res = actix_test::call_service(&app, log(owner.group_id, "meeting")).await;
+    assert_eq!(res.status(), 201);
+    let location = res
+        .headers()
+        .get(LOCATION)
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string();
+    let interaction: Interaction = actix_test::read_body_json(res).await;
+    assert_eq!(
+        location,
+        format!("/v1/interactions/{}", interaction.interaction_id)
+    );
+    assert_eq!(interaction.contact_id, Some(owner.contact_id));
+    assert_eq!(interaction.contact_ids, vec![ada]);
+    assert_eq!(interaction.notes.as_deref(), Some("Dinner party"));
+    let logged = repo.interactions_for_contacts(&[ada]).await.unwrap();
+    assert_eq!(logged.len(), 1);
+
+    let res = actix_test::call_service(&app, log(owner.group_id, "séance")).await;
+    assert_eq!(res.status(), 400);
+
+    let req = actix_test::TestRequest::post()
+        .uri("/v1/groups")
+        .insert_header(auth.clone())
+        .set_json(json!({ "name": "Empty" }))
+        .to_request();
+    let empty: Group = actix_test::call_and_read_body_json(&app, req).await;
+    let res = actix_test::call_service(&app, log(empty.group_id, "meeting")).await;
+    assert_eq!(res.status(), 400);
+
+    // Nothing logged but the provisioned interaction and the dinner party
+    assert_eq!(
+        repo.interactions_for_contacts(&[owner.contact_id, ada])
+            .await
+            .unwrap()
+            .len(),
+        2
+    );
+}
+
+#[actix_rt::test]
+async fn test_group_interaction_in_memory() {
+    check_group_interaction(repository::app_data(InMemoryRepository::new())).await;
+}
+
+#[actix_rt::test]
+async fn test_group_interaction_in_postgres() {
+    let ctx = setup_test_db().await;
+    check_group_interaction(repository::app_data(PgRepository::new(ctx.pool.clone()))).await;
+}
+
+#[actix_rt::test]
+async fn test_group_interaction_in_sqlite() {
+    let repo = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+    check_group_interaction(repository::app_data(repo)).await;
+}
+
+/// Check that the digest is queued in the outbox for the user
+async fn check_digest(repo: web::Data<dyn Repository>) {
+    let owner = provision(repo.get_ref(), "digest").await.unwrap();
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(repo.clone())
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+
+    let req = actix_test::TestRequest::post()
+        .uri(&format!("/v1/groups/{}/digest", owner.group_id))
+        .insert_header(("Authorization", format!("Bearer {}", owner.token)))
+        .to_request();
+    let res = actix_test::call_service(&app, req).await;
+    assert_eq!(res.status(), 202);
+    let digest: Value = actix_test::read_body_json(res).await;
+    let email = format!("{}@example.com", owner.marker);
+    assert_eq!(digest["to"], email);
+    assert_eq!(digest["subject"], format!("Group digest: {}", owner.marker));
+    let text = digest["text"].as_str().unwrap();
+    assert!(text.starts_with(&format!("{}, 1 member:\n", owner.marker)));
+    assert!(text.contains(&format!("- {}: last in touch", owner.marker)));
+
+    let now = OffsetDateTime::now_utc();
+    let claimed = repo
+        .claim_deliveries(now, now + Duration::minutes(5), 10)
+        .await
+        .unwrap();
+    assert_eq!(claimed.len(), 1);
+    assert_eq!(claimed[0].workspace_id, owner.workspace_id);
+    assert!(matches!(&claimed[0].payload, OutboxPayload::Email(sent) if sent.to == email));
+}
+
+#[actix_rt::test]
+async fn test_digest_in_memory() {
+    check_digest(repository::app_data(InMemoryRepository::new())).await;
+}
+
+#[actix_rt::test]
+async fn test_digest_in_postgres() {
+    let ctx = setup_test_db().await;
+    check_digest(repository::app_data(PgRepository::new(ctx.pool.clone()))).await;
+}
+
+#[actix_rt::test]
+async fn test_digest_in_sqlite() {
+    let repo = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+    check_digest(repository::app_data(repo)).await;
+}
+
+#[test]
+fn test_render_digest() {
+    let dates = LocalDates::utc();
+    let today = dates.today();
+    let group = Group {
+        group_id: 1,
+        name: "Book club".to_string(),
+        description: Some("First Thursday of the month".to_string()),
+        contact_ids: vec![2, 1],
+    };
+    let summaries = HashMap::from([(
+        2,
+        ContactSummary {
+            last_interaction_at: Some(dates.start_of(today - Duration::days(12))),
+            next_occasion_at: Some(date!(2099 - 12 - 25)),
+        },
+    )]);
+
+    let email = render_digest(
+        "grace@example.com",
+        &group,
+        &[saved_contact(2, "Ada"), saved_contact(1, "Charles")],
+        &summaries,
+        &dates,
+    );
+    assert_eq!(email.to, "grace@example.com");
+    assert_eq!(email.subject, "Group digest: Book club");
+    assert_eq!(
+        email.text,
+        "Book club, 2 members:\n\
+         First Thursday of the month\n\
+         \n\
+         - Ada: last in touch 12 days ago, next occasion 2099-12-25\n\
+         - Charles: not in touch yet\n"
+    );
+
+    let empty = Group {
+        description: None,
+        contact_ids: Vec::new(),
+        ..group
+    };
+    let email = render_digest("grace@example.com", &empty, &[], &HashMap::new(), &dates);
+    assert_eq!(email.text, "Book club, 0 members:\n\n- Nobody yet\n");
+}
@@ -0,0 +1,162 @@
+mod common;
+
+use actix_web::http::header::LOCATION;
+use actix_web::{App, test, web};
+use common::*;
+use personal_crm::repository::{
+    self, InMemoryRepository, PgRepository, Repository, SqliteRepository,
+};
+use personal_crm::routes::v1_routes;
+use personal_crm::test_support::{provision, register_token};
+use serde_json::{Value, json};
+
+/// Test the contact handlers end to end against the in-memory repository, no database required
+#[actix_rt::test]
+async fn test_contact_handlers_with_in_memory_repository() {
+    register_token("token-handler-owner", "test|handler-owner").await;
+    register_token("token-handler-other", "test|handler-other").await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(repository::app_data(InMemoryRepository::new()))
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/v1/contacts")
+        .insert_header(("Authorization", "Bearer token-handler-owner"))
+        .set_json(json!({"first_name": "Ada", "last_name": "Lovelace"}))
+        .to_request();
+    let created: Value = test::call_and_read_body_json(&app, req).await;
+    let contact_id = created["contact_id"].as_i64().unwrap();
+
+    let req = test::TestRequest::post()
+        .uri("/v1/tags")
+        .insert_header(("Authorization", "Bearer token-handler-owner"))
+        .set_json(json!({"name": "friends"}))
+        .to_request();
+    let tag: Value = test::call_and_read_body_json(&app, req).await;
+    let tag_id = tag["tag_id"].as_i64().unwrap();
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/v1/contacts/{}/tags/{}", contact_id, tag_id))
+        .insert_header(("Authorization", "Bearer token-handler-owner"))
+        .to_request();
+    assert!(test::call_service(&app, req).await.status().is_success());
+
+    let req = test::TestRequest::get()
+        .uri("/v1/contacts")
+        .insert_header(("Authorization", "Bearer token-handler-owner"))
+        .to_request();
+    let contacts: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(contacts.as_array().unwrap().len(), 1);
+    assert_eq!(contacts[0]["contact"]["first_name"], "Ada");
+    assert_eq!(contacts[0]["tags"][0]["name"], "friends");
+
+    // Another user sees neither the contact nor the tag
+    let req = test::TestRequest::get()
+        .uri(&format!("/v1/contacts/{}", contact_id))
+        .insert_header(("Authorization", "Bearer token-handler-other"))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 404);
+
+    let req = test::TestRequest::get()
+        .uri("/v1/tags")
+        .insert_header(("Authorization", "Bearer token-handler-other"))
+        .to_request();
+    let tags: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(tags["tags"].as_array().unwrap().len(), 0);
+}
+
+/// Check that creates answer 201 with the new entity and its Location, and updates 200 with
+/// the entity as saved
+async fn check_mutation_responses(repo: web::Data<dyn Repository>) {
+    let owner = provision(repo.get_ref(), "mutations").await.unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(repo.clone())
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let auth = ("Authorization", format!("Bearer {}", owner.token));
+
+    let req = test::TestRequest::post()
+        .uri("/v1/contacts")
+        .insert_header(auth.clone())
+        .set_json(json!({"first_name": "Ada", "notes": "Likes engines"}))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), 201);
+    let location = res.headers().get(LOCATION).unwrap().to_str().unwrap();
+    let location = location.to_string();
+    let created: Value = test::read_body_json(res).await;
+    let contact_id = created["contact_id"].as_i64().unwrap();
+    assert_eq!(location, format!("/v1/contacts/{}", contact_id));
+    assert_eq!(created["first_name"], "Ada");
+    assert_eq!(created["notes"], "Likes engines");
+    assert_eq!(created["archived"], false);
+
+    let req = test::TestRequest::patch()
+        .uri(&location)
+        .insert_header(auth.clone())
+        .set_json(json!({"first_name": "Ada", "last_name": "Lovelace"}))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), 200);
+    let updated: Value = test::read_body_json(res).await;
+    assert_eq!(updated["contact_id"], contact_id);
+    assert_eq!(updated["last_name"], "Lovelace");
+
+    let req = test::TestRequest::post()
+        .uri("/v1/interactions")
+        .insert_header(auth.clone())
+        .set_json(json!({
+            "contact_id": contact_id,
+            "interaction_date": "2024-03-01T12:00:00",
+            "notes": "Coffee",
+            "follow_up_priority": 2,
+            "follow_up_in_days": 7
+        }))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), 201);
+    let created: Value = test::read_body_json(res).await;
+    assert_eq!(created["contact_id"], contact_id);
+    assert_eq!(created["notes"], "Coffee");
+    assert!(created["follow_up_occasion_id"].is_i64());
+
+    let req = test::TestRequest::post()
+        .uri("/v1/tags")
+        .insert_header(auth.clone())
+        .set_json(json!({"name": "friends"}))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), 201);
+    let tag: Value = test::read_body_json(res).await;
+    let req = test::TestRequest::patch()
+        .uri(&format!("/v1/tags/{}", tag["tag_id"]))
+        .insert_header(auth.clone())
+        .set_json(json!({"name": "close friends"}))
+        .to_request();
+    let updated: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(updated["tag_id"], tag["tag_id"]);
+    assert_eq!(updated["name"], "close friends");
+}
+
+#[actix_rt::test]
+async fn test_mutation_responses_in_memory() {
+    check_mutation_responses(repository::app_data(InMemoryRepository::new())).await;
+}
+
+#[actix_rt::test]
+async fn test_mutation_responses_in_postgres() {
+    let ctx = setup_test_db().await;
+    check_mutation_responses(repository::app_data(PgRepository::new(ctx.pool.clone()))).await;
+}
+
+#[actix_rt::test]
+async fn test_mutation_responses_in_sqlite() {
+    let repo = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+    check_mutation_responses(repository::app_data(repo)).await;
+}
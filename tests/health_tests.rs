@@ -0,0 +1,163 @@
+mod common;
+
+use actix_web::{App, test as actix_test, web};
+use common::*;
+use personal_crm::health::assess;
+use personal_crm::models::{DecliningContact, HealthTrend, Interaction, NewInteractionRequest};
+use personal_crm::repository::{
+    self, InMemoryRepository, PgRepository, Repository, SqliteRepository,
+};
+use personal_crm::routes::v1_routes;
+use personal_crm::test_support::provision;
+use personal_crm::timezone::LocalDates;
+use time::Duration;
+
+/// An interaction `days_ago` days before today
+fn interaction(dates: &LocalDates, days_ago: i64, interaction_type: &str) -> Interaction {
+    Interaction {
+        interaction_id: days_ago as i32,
+        contact_id: Some(1),
+        interaction_date: dates.start_of(dates.today() - Duration::days(days_ago)),
+        notes: None,
+        follow_up_priority: None,
+        interaction_type: Some(interaction_type.to_string()),
+        contact_ids: Vec::new(),
+        tag_ids: Vec::new(),
+    }
+}
+
+fn history(dates: &LocalDates, days_ago: &[i64], interaction_type: &str) -> Vec<Interaction> {
+    days_ago
+        .iter()
+        .map(|&days| interaction(dates, days, interaction_type))
+        .collect()
+}
+
+#[test]
+fn test_assess() {
+    let dates = LocalDates::utc();
+    assert_eq!(assess(Some(7), &[], &dates), None);
+
+    // Weekly meetings, the last one today: as healthy as it gets
+    let weekly = history(&dates, &[21, 14, 7, 0], "meeting");
+    let health = assess(None, &weekly, &dates).unwrap();
+    assert_eq!(health.score, 100);
+    assert_eq!(health.trend, Some(HealthTrend::Steady));
+    assert_eq!(health.gap_growth, Some(1.0));
+
+    // Same gaps, but only emails
+    let emails = history(&dates, &[21, 14, 7, 0], "email");
+    let health = assess(None, &emails, &dates).unwrap();
+    assert_eq!(health.score, 88);
+
+    // Gaps of 5 and 5, then 20 and 40: drifting apart, and 40 days since the last
+    let drifting = history(&dates, &[110, 105, 100, 80, 40], "meeting");
+    let health = assess(None, &drifting, &dates).unwrap();
+    assert_eq!(health.trend, Some(HealthTrend::Declining));
+    assert!(health.gap_growth.unwrap() > 3.0);
+    assert!(health.score < 50, "{}", health.score);
+
+    // Gaps of 30 then 10 and 5
+    let closer = history(&dates, &[45, 15, 5, 0], "call");
+    let health = assess(None, &closer, &dates).unwrap();
+    assert_eq!(health.trend, Some(HealthTrend::Improving));
+
+    // A dinner logged twice on the same day is one day of contact
+    let mut dinner = history(&dates, &[14, 7], "meeting");
+    dinner.push(interaction(&dates, 7, "meeting"));
+    let health = assess(None, &dinner, &dates).unwrap();
+    assert_eq!(health.trend, None);
+    assert_eq!(health.gap_growth, None);
+
+    // Recency follows the desired frequency when there is one
+    let once = history(&dates, &[20], "meeting");
+    let relaxed = assess(Some(30), &once, &dates).unwrap();
+    let strict = assess(Some(5), &once, &dates).unwrap();
+    assert_eq!(relaxed.score, 100);
+    assert!(strict.score < relaxed.score);
+    assert_eq!(strict.trend, None);
+}
+
+/// Check that `/contacts/declining` lists the contacts whose gaps are growing, and that a
+/// contact's detail reports its health
+async fn check_declining(repo: web::Data<dyn Repository>) {
+    let owner = provision(repo.get_ref(), "declining").await.unwrap();
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(repo.clone())
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let dates = LocalDates::utc();
+    let log = |contact_id: i32, days_ago: i64| NewInteractionRequest {
+        contact_id,
+        interaction_date: dates.start_of(dates.today() - Duration::days(days_ago)),
+        notes: None,
+        follow_up_priority: None,
+        interaction_type: Some("call".to_string()),
+        contact_ids: Vec::new(),
+        follow_up_in_days: None,
+    };
+
+    let drifting = repo
+        .create_contact(owner.workspace_id, &contact("Drifting"))
+        .await
+        .unwrap();
+    let regular = repo
+        .create_contact(owner.workspace_id, &contact("Regular"))
+        .await
+        .unwrap();
+    let archived = repo
+        .create_contact(owner.workspace_id, &contact("Archived"))
+        .await
+        .unwrap();
+    for contact_id in [drifting, archived] {
+        for days_ago in [200, 195, 190, 150, 60] {
+            repo.create_interaction(owner.workspace_id, &log(contact_id, days_ago), None)
+                .await
+                .unwrap();
+        }
+    }
+    for days_ago in [30, 20, 10, 0] {
+        repo.create_interaction(owner.workspace_id, &log(regular, days_ago), None)
+            .await
+            .unwrap();
+    }
+    repo.set_contact_archived(owner.workspace_id, archived, true)
+        .await
+        .unwrap();
+
+    let req = actix_test::TestRequest::get()
+        .uri("/v1/contacts/declining")
+        .insert_header(("Authorization", format!("Bearer {}", owner.token)))
+        .to_request();
+    let declining: Vec<DecliningContact> = actix_test::call_and_read_body_json(&app, req).await;
+    let ids: Vec<i32> = declining.iter().map(|d| d.contact.contact_id).collect();
+    assert_eq!(ids, vec![drifting]);
+    assert_eq!(declining[0].health.trend, Some(HealthTrend::Declining));
+
+    let req = actix_test::TestRequest::get()
+        .uri(&format!("/v1/contacts/{}", regular))
+        .insert_header(("Authorization", format!("Bearer {}", owner.token)))
+        .to_request();
+    let contact: serde_json::Value = actix_test::call_and_read_body_json(&app, req).await;
+    assert_eq!(contact["health"]["trend"], "steady");
+    assert_eq!(contact["health"]["score"], 96);
+}
+
+#[actix_rt::test]
+async fn test_declining_in_memory() {
+    check_declining(repository::app_data(InMemoryRepository::new())).await;
+}
+
+#[actix_rt::test]
+async fn test_declining_in_postgres() {
+    let ctx = setup_test_db().await;
+    check_declining(repository::app_data(PgRepository::new(ctx.pool.clone()))).await;
+}
+
+#[actix_rt::test]
+async fn test_declining_in_sqlite() {
+    let repo = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+    check_declining(repository::app_data(repo)).await;
+}
@@ -0,0 +1,158 @@
+mod common;
+
+use actix_web::{App, test as actix_test, web};
+use common::*;
+use personal_crm::history::{FieldChange, changes};
+use personal_crm::models::{Contact, NewContactRequest};
+use personal_crm::repository::{
+    self, InMemoryRepository, PgRepository, Repository, SqliteRepository,
+};
+use personal_crm::routes::v1_routes;
+use personal_crm::test_support::{PHONE, provision};
+use serde_json::{Value, json};
+
+/// Test that only fields whose value differs are reported
+#[test]
+fn test_changes() {
+    let before = Contact {
+        contact_id: 1,
+        first_name: Some("Priya".to_string()),
+        last_name: Some("Patel".to_string()),
+        phone: Some("+1 415 555 0123".to_string()),
+        phone_e164: Some("+14155550123".to_string()),
+        desired_frequency_days: Some(30),
+        ..Default::default()
+    };
+    let after = NewContactRequest {
+        last_name: Some("Patel".to_string()),
+        email: Some("priya@example.com".to_string()),
+        phone: Some("+1 415 555 0199".to_string()),
+        ..contact("Priya")
+    };
+
+    assert_eq!(
+        changes(&before, &after),
+        vec![
+            FieldChange {
+                field: "email",
+                before: None,
+                after: Some("priya@example.com".to_string()),
+            },
+            FieldChange {
+                field: "phone",
+                before: Some("+1 415 555 0123".to_string()),
+                after: Some("+1 415 555 0199".to_string()),
+            },
+            FieldChange {
+                field: "desired_frequency_days",
+                before: Some("30".to_string()),
+                after: None,
+            },
+        ]
+    );
+}
+
+/// Edits and archiving show up in the history, newest first and paged
+async fn check_contact_history(repo: web::Data<dyn Repository>) {
+    let owner = provision(repo.get_ref(), "history").await.unwrap();
+    let other = provision(repo.get_ref(), "history-other").await.unwrap();
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(repo.clone())
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let auth = ("Authorization", format!("Bearer {}", owner.token));
+    let get = |uri: &str| {
+        actix_test::TestRequest::get()
+            .uri(uri)
+            .insert_header(auth.clone())
+            .to_request()
+    };
+    let update = |phone: &str| {
+        actix_test::TestRequest::patch()
+            .uri(&format!("/v1/contacts/{}", owner.contact_id))
+            .insert_header(auth.clone())
+            .set_json(json!({ "first_name": "Priya", "phone": phone }))
+            .to_request()
+    };
+    let history_uri = format!("/v1/contacts/{}/history", owner.contact_id);
+
+    let contact = repo
+        .get_contact(owner.workspace_id, owner.contact_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        actix_test::call_service(&app, update(PHONE)).await.status(),
+        200
+    );
+    assert_eq!(
+        actix_test::call_service(&app, update("+1 415 555 0199"))
+            .await
+            .status(),
+        200
+    );
+    let req = actix_test::TestRequest::post()
+        .uri(&format!("/v1/contacts/{}/archive", owner.contact_id))
+        .insert_header(auth.clone())
+        .to_request();
+    assert_eq!(actix_test::call_service(&app, req).await.status(), 200);
+
+    let history: Vec<Value> =
+        actix_test::read_body_json(actix_test::call_service(&app, get(&history_uri)).await).await;
+    let fields: Vec<&str> = history
+        .iter()
+        .map(|c| c["field"].as_str().unwrap())
+        .collect();
+    // The first update renamed the contact and dropped its email; the provisioned phone was
+    // already in place, so only the second update changed it
+    assert_eq!(fields, ["archived", "phone", "email", "first_name"]);
+    assert_eq!(history[0]["before"], "false");
+    assert_eq!(history[0]["after"], "true");
+    assert_eq!(history[1]["before"], PHONE);
+    assert_eq!(history[1]["after"], "+1 415 555 0199");
+    assert_eq!(history[2]["before"], json!(contact.email));
+    assert_eq!(history[2]["after"], Value::Null);
+    assert_eq!(history[3]["after"], "Priya");
+
+    let mut paged = Vec::new();
+    let mut next = Some(format!("{}?limit=3", history_uri));
+    while let Some(uri) = next {
+        let res = actix_test::call_service(&app, get(&uri)).await;
+        assert_eq!(res.status(), 200);
+        next = res.headers().get("Link").map(|link| {
+            let link = link.to_str().unwrap();
+            link[1..link.find('>').unwrap()].to_string()
+        });
+        let page: Vec<Value> = actix_test::read_body_json(res).await;
+        paged.extend(page);
+    }
+    assert_eq!(paged, history);
+
+    let res = actix_test::call_service(
+        &app,
+        get(&format!("/v1/contacts/{}/history", other.contact_id)),
+    )
+    .await;
+    assert_eq!(res.status(), 404);
+}
+
+/// Test the history on the in-memory repository
+#[actix_rt::test]
+async fn test_contact_history_in_memory() {
+    check_contact_history(repository::app_data(InMemoryRepository::new())).await;
+}
+
+/// Test the history on Postgres
+#[actix_rt::test]
+async fn test_contact_history_in_postgres() {
+    let ctx = setup_test_db().await;
+    check_contact_history(repository::app_data(PgRepository::new(ctx.pool.clone()))).await;
+}
+
+#[actix_rt::test]
+async fn test_contact_history_in_sqlite() {
+    let repo = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+    check_contact_history(repository::app_data(repo)).await;
+}
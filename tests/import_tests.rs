@@ -0,0 +1,412 @@
+mod common;
+
+use actix_web::{App, test as actix_test, web};
+use common::*;
+use personal_crm::import::Expr;
+use personal_crm::repository::{
+    self, InMemoryRepository, PgRepository, Repository, SqliteRepository,
+};
+use personal_crm::routes::v1_routes;
+use personal_crm::test_support::{PHONE, Tenant, provision, register_token};
+use serde_json::{Value, json};
+
+fn eval(expression: &str, record: &Value) -> Option<String> {
+    Expr::parse(expression).unwrap().eval(record).unwrap()
+}
+
+/// Test paths, literals, concatenation, alternatives and filters
+#[test]
+fn test_expressions() {
+    let record = json!({
+        "name": { "first": "Ada", "last": "Lovelace" },
+        "emails": ["  ADA@Example.com ", "ada@work.example"],
+        "Full Name": "Ada Lovelace",
+        "age": 36,
+        "nickname": null
+    });
+
+    assert_eq!(eval(".name.first", &record).as_deref(), Some("Ada"));
+    assert_eq!(
+        eval(".emails[1]", &record).as_deref(),
+        Some("ada@work.example")
+    );
+    assert_eq!(
+        eval(r#".["Full Name"]"#, &record).as_deref(),
+        Some("Ada Lovelace")
+    );
+    assert_eq!(eval(".age", &record).as_deref(), Some("36"));
+    assert_eq!(
+        eval(r#".name.first + " " + .name.last"#, &record).as_deref(),
+        Some("Ada Lovelace")
+    );
+    assert_eq!(
+        eval(".nickname // .missing // .name.first", &record).as_deref(),
+        Some("Ada")
+    );
+    assert_eq!(
+        eval(".emails[0] | trim | ascii_downcase", &record).as_deref(),
+        Some("ada@example.com")
+    );
+    assert_eq!(eval(".nickname", &record), None);
+    assert_eq!(eval(".emails[5]", &record), None);
+
+    assert!(Expr::parse(".name").unwrap().eval(&record).is_err());
+    for invalid in [
+        "name",
+        ".a.",
+        ".a[x]",
+        ".a | shout",
+        r#""unterminated"#,
+        ".a .b",
+    ] {
+        assert!(
+            Expr::parse(invalid).is_err(),
+            "{} should not parse",
+            invalid
+        );
+    }
+}
+
+/// Test importing through the API, including dry runs and records that fail to map
+#[actix_rt::test]
+async fn test_import_json() {
+    register_token("token-import-owner", "test|import-owner").await;
+
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(repository::app_data(InMemoryRepository::new()))
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let auth = ("Authorization", "Bearer token-import-owner");
+
+    let request = json!({
+        "mapping": {
+            "records": ".export.people",
+            "fields": {
+                "first_name": ".given",
+                "last_name": ".family",
+                "email": ".mail | ascii_downcase",
+                "phone": ".phones[0]"
+            }
+        },
+        "data": {
+            "export": {
+                "people": [
+                    { "given": "Ada", "family": "Lovelace", "mail": "ADA@example.com" },
+                    { "phones": ["555-0100"] },
+                    { "given": "Grace", "phones": [5550101] }
+                ]
+            }
+        }
+    });
+
+    let req = actix_test::TestRequest::post()
+        .uri("/v1/contacts/import/json?dry_run=true")
+        .insert_header(auth)
+        .set_json(&request)
+        .to_request();
+    let preview: Value = actix_test::call_and_read_body_json(&app, req).await;
+    assert_eq!(preview["contacts"][0]["email"], "ada@example.com");
+    assert_eq!(preview["contacts"][1]["phone"], "5550101");
+    assert_eq!(preview["errors"][0]["index"], 1);
+    assert_eq!(preview["dry_run"], true);
+    assert_eq!(preview["created_count"], 2);
+
+    let req = actix_test::TestRequest::get()
+        .uri("/v1/contacts")
+        .insert_header(auth)
+        .to_request();
+    let contacts: Value = actix_test::call_and_read_body_json(&app, req).await;
+    assert!(contacts.as_array().unwrap().is_empty());
+
+    let req = actix_test::TestRequest::post()
+        .uri("/v1/contacts/import/json")
+        .insert_header(auth)
+        .set_json(&request)
+        .to_request();
+    let imported: Value = actix_test::call_and_read_body_json(&app, req).await;
+    assert_eq!(imported["created_contact_ids"].as_array().unwrap().len(), 2);
+    assert_eq!(imported["errors"].as_array().unwrap().len(), 1);
+
+    // Importing again would only duplicate Ada's email
+    let req = actix_test::TestRequest::post()
+        .uri("/v1/contacts/import/json?dry_run=true&atomic=true")
+        .insert_header(auth)
+        .set_json(&request)
+        .to_request();
+    let preview: Value = actix_test::call_and_read_body_json(&app, req).await;
+    assert_eq!(
+        preview["duplicates"],
+        json!([{ "index": 0, "email": "ada@example.com" }])
+    );
+    assert_eq!(preview["created_count"], 0);
+
+    for mapping in [
+        json!({ "records": ".export.people", "fields": { "nickname": ".given" } }),
+        json!({ "records": ".export.people", "fields": { "first_name": "given" } }),
+        json!({ "records": ".export", "fields": { "first_name": ".given" } }),
+    ] {
+        let req = actix_test::TestRequest::post()
+            .uri("/v1/contacts/import/json")
+            .insert_header(auth)
+            .set_json(json!({ "mapping": mapping, "data": request["data"] }))
+            .to_request();
+        assert_eq!(actix_test::call_service(&app, req).await.status(), 400);
+    }
+}
+
+/// Test that dry runs of bulk create and bulk delete report what would happen and change nothing
+#[actix_rt::test]
+async fn test_bulk_dry_runs() {
+    register_token("token-dry-run-owner", "test|dry-run-owner").await;
+
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(repository::app_data(InMemoryRepository::new()))
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let auth = ("Authorization", "Bearer token-dry-run-owner");
+
+    let req = actix_test::TestRequest::post()
+        .uri("/v1/contacts/bulk")
+        .insert_header(auth)
+        .set_json(json!([{ "first_name": "Kept", "email": "kept@example.com" }]))
+        .to_request();
+    let created: Value = actix_test::call_and_read_body_json(&app, req).await;
+    let kept = created["created_contact_ids"][0].as_i64().unwrap();
+
+    let req = actix_test::TestRequest::post()
+        .uri("/v1/contacts/bulk?dry_run=true")
+        .insert_header(auth)
+        .set_json(json!([
+            { "first_name": "New", "email": "new@example.com" },
+            { "first_name": "Taken", "email": "kept@example.com" },
+            { "first_name": "Repeated", "email": "new@example.com" }
+        ]))
+        .to_request();
+    let preview: Value = actix_test::call_and_read_body_json(&app, req).await;
+    assert_eq!(preview["created_count"], 1);
+    assert_eq!(
+        preview["duplicates"],
+        json!([
+            { "index": 1, "email": "kept@example.com" },
+            { "index": 2, "email": "new@example.com" }
+        ])
+    );
+
+    let req = actix_test::TestRequest::post()
+        .uri("/v1/contacts/bulk-delete?dry_run=true")
+        .insert_header(auth)
+        .set_json(json!({ "contact_ids": [kept, kept, 0] }))
+        .to_request();
+    let preview: Value = actix_test::call_and_read_body_json(&app, req).await;
+    assert_eq!(preview["deleted_ids"], json!([kept]));
+    assert_eq!(preview["skipped_ids"], json!([0]));
+
+    let req = actix_test::TestRequest::get()
+        .uri("/v1/contacts")
+        .insert_header(auth)
+        .to_request();
+    let contacts: Value = actix_test::call_and_read_body_json(&app, req).await;
+    assert_eq!(contacts.as_array().unwrap().len(), 1);
+}
+
+/// Check that re-imported contacts are skipped, overwritten or merged by email, phone or name,
+/// with a row per record saying what was done
+async fn check_dedupe(repo: web::Data<dyn Repository>) {
+    let owner = provision(repo.get_ref(), "dedupe-owner").await.unwrap();
+    let existing = repo
+        .get_contact(owner.workspace_id, owner.contact_id)
+        .await
+        .unwrap()
+        .unwrap();
+    let email = existing.email.clone().unwrap();
+
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(repo.clone())
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let auth = ("Authorization", format!("Bearer {}", owner.token));
+    let post = |uri: &str, body: Value| {
+        actix_test::TestRequest::post()
+            .uri(uri)
+            .insert_header(auth.clone())
+            .set_json(body)
+            .to_request()
+    };
+
+    let records = json!([
+        { "first_name": "Renamed", "email": email.to_uppercase(), "short_note": "Imported" },
+        { "first_name": "Phone twin", "phone": PHONE, "email": "twin@example.com" },
+        { "first_name": "Bo", "last_name": "Diddley", "email": "bo@example.com" },
+        { "first_name": "bo", "last_name": "DIDDLEY", "short_note": "Guitarist" }
+    ]);
+    let preview: Value = actix_test::call_and_read_body_json(
+        &app,
+        post(
+            "/v1/contacts/bulk?dedupe=merge&dry_run=true",
+            records.clone(),
+        ),
+    )
+    .await;
+    assert_eq!(preview["created_count"], 2);
+    assert_eq!(preview["updated_count"], 1);
+    assert_eq!(
+        preview["rows"],
+        json!([
+            { "index": 0, "action": "merged", "contact_id": owner.contact_id, "matched_by": "email" },
+            { "index": 1, "action": "created", "contact_id": null, "matched_by": null },
+            { "index": 2, "action": "created", "contact_id": null, "matched_by": null },
+            { "index": 3, "action": "merged", "contact_id": null, "matched_by": "name" }
+        ])
+    );
+
+    let merged: Value =
+        actix_test::call_and_read_body_json(&app, post("/v1/contacts/bulk?dedupe=merge", records))
+            .await;
+    assert_eq!(merged["updated_contact_ids"], json!([owner.contact_id]));
+    let created = merged["created_contact_ids"].as_array().unwrap().clone();
+    assert_eq!(created.len(), 2);
+    assert_eq!(merged["rows"][3]["contact_id"], created[1]);
+    let contact = repo
+        .get_contact(owner.workspace_id, owner.contact_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(contact.first_name, existing.first_name);
+    assert_eq!(contact.short_note.as_deref(), Some("Imported"));
+    let bo = repo
+        .get_contact(owner.workspace_id, created[1].as_i64().unwrap() as i32)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(bo.short_note.as_deref(), Some("Guitarist"));
+
+    let overwritten: Value = actix_test::call_and_read_body_json(
+        &app,
+        post(
+            "/v1/contacts/bulk?dedupe=overwrite",
+            json!([{ "first_name": "Renamed", "email": email }]),
+        ),
+    )
+    .await;
+    assert_eq!(overwritten["rows"][0]["action"], "overwritten");
+    let contact = repo
+        .get_contact(owner.workspace_id, owner.contact_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(contact.first_name.as_deref(), Some("Renamed"));
+    assert_eq!(contact.short_note.as_deref(), Some("Imported"));
+
+    let request = json!({
+        "mapping": { "fields": { "first_name": ".name", "email": ".mail" } },
+        "data": [{ "name": "Again", "mail": "bo@example.com" }, { "nickname": "Nobody" }]
+    });
+    let skipped: Value = actix_test::call_and_read_body_json(
+        &app,
+        post("/v1/contacts/import/json?dedupe=skip", request),
+    )
+    .await;
+    assert_eq!(skipped["created_contact_ids"], json!([]));
+    assert_eq!(
+        skipped["rows"],
+        json!([
+            { "index": 0, "action": "skipped", "contact_id": created[1], "matched_by": "email" },
+            { "index": 1, "action": "failed", "contact_id": null, "matched_by": null }
+        ])
+    );
+    assert_eq!(
+        repo.list_contacts(owner.workspace_id).await.unwrap().len(),
+        3
+    );
+}
+
+#[actix_rt::test]
+async fn test_dedupe_in_memory() {
+    check_dedupe(repository::app_data(InMemoryRepository::new())).await;
+}
+
+#[actix_rt::test]
+async fn test_dedupe_in_postgres() {
+    let ctx = setup_test_db().await;
+    check_dedupe(repository::app_data(PgRepository::new(ctx.pool.clone()))).await;
+}
+
+#[actix_rt::test]
+async fn test_dedupe_in_sqlite() {
+    let repo = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+    check_dedupe(repository::app_data(repo)).await;
+}
+
+/// Check that `create_duplicate` creates a contact with the email of one in another workspace,
+/// the user's own or someone else's, and in the same workspace unless the email is exactly the
+/// same
+async fn check_create_duplicate(repo: web::Data<dyn Repository>) {
+    let owner = provision(repo.get_ref(), "duplicate-owner").await.unwrap();
+    let other = provision(repo.get_ref(), "duplicate-other").await.unwrap();
+    let email = repo
+        .get_contact(owner.workspace_id, owner.contact_id)
+        .await
+        .unwrap()
+        .unwrap()
+        .email
+        .unwrap();
+
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(repo.clone())
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let post = |tenant: &Tenant, workspace_id: i32, email: &str| {
+        actix_test::TestRequest::post()
+            .uri("/v1/contacts/bulk?dedupe=create_duplicate")
+            .insert_header(("Authorization", format!("Bearer {}", tenant.token)))
+            .insert_header(("X-Workspace", workspace_id.to_string()))
+            .set_json(json!([{ "first_name": "Twin", "email": email }]))
+            .to_request()
+    };
+
+    for (tenant, workspace_id, email) in [
+        (&owner, owner.spare_workspace_id, email.clone()),
+        (&other, other.workspace_id, email.clone()),
+        (&owner, owner.workspace_id, email.to_uppercase()),
+    ] {
+        let created: Value =
+            actix_test::call_and_read_body_json(&app, post(tenant, workspace_id, &email)).await;
+        assert_eq!(created["rows"][0]["action"], "created", "{}", created);
+        let contact_id = created["created_contact_ids"][0].as_i64().unwrap() as i32;
+        let twin = repo.get_contact(workspace_id, contact_id).await.unwrap();
+        assert_eq!(twin.unwrap().email, Some(email));
+    }
+
+    let res = actix_test::call_service(&app, post(&owner, owner.workspace_id, &email)).await;
+    let failed: Value = actix_test::read_body_json(res).await;
+    assert_eq!(failed["rows"][0]["action"], "failed");
+    assert_eq!(
+        repo.list_contacts(owner.workspace_id).await.unwrap().len(),
+        2
+    );
+}
+
+#[actix_rt::test]
+async fn test_create_duplicate_in_memory() {
+    check_create_duplicate(repository::app_data(InMemoryRepository::new())).await;
+}
+
+#[actix_rt::test]
+async fn test_create_duplicate_in_postgres() {
+    let ctx = setup_test_db().await;
+    check_create_duplicate(repository::app_data(PgRepository::new(ctx.pool.clone()))).await;
+}
+
+#[actix_rt::test]
+async fn test_create_duplicate_in_sqlite() {
+    let repo = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+    check_create_duplicate(repository::app_data(repo)).await;
+}
@@ -0,0 +1,233 @@
+mod common;
+
+use actix_web::{App, test as actix_test, web};
+use common::*;
+use personal_crm::inbound_email::{self, InboundEmail, InboundMessage, parse_addresses};
+use personal_crm::models::NewContactRequest;
+use personal_crm::repository::{self, InMemoryRepository};
+use personal_crm::routes::v1_routes;
+use personal_crm::test_support::provision;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+
+/// Test logging a Mailgun-style and a SendGrid-style post as interactions
+#[actix_rt::test]
+async fn test_receive_email() {
+    let repo = repository::app_data(InMemoryRepository::new());
+    let owner = provision(repo.get_ref(), "inbound").await.unwrap();
+    let colleague_id = repo
+        .create_contact(
+            owner.workspace_id,
+            &NewContactRequest {
+                email: Some("Grace@Example.com".to_string()),
+                ..contact("Grace")
+            },
+        )
+        .await
+        .unwrap();
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(repo.clone())
+            .app_data(web::Data::new(InboundEmail::new(
+                Some("hook-secret".to_string()),
+                Some("in.example.com".to_string()),
+            )))
+            .service(inbound_email::receive_email)
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let auth = ("Authorization", format!("Bearer {}", owner.token));
+
+    // Rotating replaces the provisioned address
+    let req = actix_test::TestRequest::put()
+        .uri("/v1/inbound-email")
+        .insert_header(auth.clone())
+        .to_request();
+    let address: Value =
+        actix_test::read_body_json(actix_test::call_service(&app, req).await).await;
+    let token = address["token"].as_str().unwrap().to_string();
+    assert_eq!(token.len(), inbound_email::TOKEN_LENGTH);
+    assert_eq!(address["address"], format!("{}@in.example.com", token));
+
+    let contact_email = format!("contact-{}@example.com", owner.marker);
+    let mailgun = |key: &str, recipient: &str| {
+        actix_test::TestRequest::post()
+            .uri(&format!("/webhooks/email?key={}", key))
+            .set_form(HashMap::from([
+                ("sender", format!("Ada <{}>", contact_email)),
+                ("recipient", recipient.to_string()),
+                ("To", "grace@example.com".to_string()),
+                ("subject", "Lunch next week".to_string()),
+                ("stripped-text", "Are you free\n  on Tuesday?".to_string()),
+            ]))
+            .to_request()
+    };
+
+    let inbound_address = format!("{}@in.example.com", token);
+    let res = actix_test::call_service(&app, mailgun("wrong", &inbound_address)).await;
+    assert_eq!(res.status(), 401);
+
+    let res = actix_test::call_service(&app, mailgun("hook-secret", &inbound_address)).await;
+    assert_eq!(res.status(), 200);
+    let logged: Value = actix_test::read_body_json(res).await;
+    assert!(logged["interaction_id"].is_i64());
+
+    // Sender first, the other recipients as participants
+    let req = actix_test::TestRequest::get()
+        .uri(&format!("/v1/contacts/{}/interactions", owner.contact_id))
+        .insert_header(auth.clone())
+        .to_request();
+    let timeline: Vec<Value> =
+        actix_test::read_body_json(actix_test::call_service(&app, req).await).await;
+    let interaction = timeline
+        .iter()
+        .find(|i| i["interaction_id"] == logged["interaction_id"])
+        .unwrap();
+    assert_eq!(interaction["interaction_type"], "email");
+    assert_eq!(
+        interaction["notes"],
+        "Lunch next week\n\nAre you free on Tuesday?"
+    );
+    assert_eq!(interaction["contact_ids"], json!([colleague_id]));
+
+    // Mail to an address that isn't (or is no longer) an inbound one is accepted, not logged
+    let old_address = format!("{}@in.example.com", "0".repeat(inbound_email::TOKEN_LENGTH));
+    let res = actix_test::call_service(&app, mailgun("hook-secret", &old_address)).await;
+    assert_eq!(res.status(), 200);
+    let ignored: Value = actix_test::read_body_json(res).await;
+    assert_eq!(ignored["interaction_id"], Value::Null);
+
+    // SendGrid posts multipart with an envelope; attachments are skipped
+    let boundary = "XyZ";
+    let part = |name: &str, value: &str| {
+        format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
+            boundary, name, value
+        )
+    };
+    let envelope = json!({ "to": [inbound_address], "from": "stranger@example.org" });
+    let body = [
+        part("envelope", &envelope.to_string()),
+        part("from", "Stranger <stranger@example.org>"),
+        part("to", &format!("\"Ada, Contact\" <{}>", contact_email)),
+        part("subject", "Intro"),
+        part("text", "Meet Ada."),
+        format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"attachment1\"; filename=\"a.txt\"\r\nContent-Type: text/plain\r\n\r\nfile\r\n--{}--\r\n",
+            boundary, boundary
+        ),
+    ]
+    .concat();
+    let req = actix_test::TestRequest::post()
+        .uri("/webhooks/email?key=hook-secret")
+        .insert_header((
+            "Content-Type",
+            format!("multipart/form-data; boundary={}", boundary),
+        ))
+        .set_payload(body)
+        .to_request();
+    let res = actix_test::call_service(&app, req).await;
+    assert_eq!(res.status(), 200);
+    let logged: Value = actix_test::read_body_json(res).await;
+    assert!(logged["interaction_id"].is_i64());
+
+    // No contact among the sender and recipients
+    let req = actix_test::TestRequest::post()
+        .uri("/webhooks/email?key=hook-secret")
+        .set_form(HashMap::from([
+            ("from", "stranger@example.org"),
+            ("to", inbound_address.as_str()),
+        ]))
+        .to_request();
+    let ignored: Value =
+        actix_test::read_body_json(actix_test::call_service(&app, req).await).await;
+    assert_eq!(ignored["interaction_id"], Value::Null);
+
+    let delete = || {
+        actix_test::TestRequest::delete()
+            .uri("/v1/inbound-email")
+            .insert_header(auth.clone())
+            .to_request()
+    };
+    assert_eq!(actix_test::call_service(&app, delete()).await.status(), 200);
+    assert_eq!(actix_test::call_service(&app, delete()).await.status(), 404);
+    let req = actix_test::TestRequest::get()
+        .uri("/v1/inbound-email")
+        .insert_header(auth.clone())
+        .to_request();
+    assert_eq!(actix_test::call_service(&app, req).await.status(), 404);
+}
+
+/// Test that the webhook is off without a secret
+#[actix_rt::test]
+async fn test_receive_email_unconfigured() {
+    let repo = repository::app_data(InMemoryRepository::new());
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(repo.clone())
+            .app_data(web::Data::new(InboundEmail::new(None, None)))
+            .service(inbound_email::receive_email),
+    )
+    .await;
+    let req = actix_test::TestRequest::post()
+        .uri("/webhooks/email?key=")
+        .set_form(HashMap::from([("from", "a@example.com")]))
+        .to_request();
+    assert_eq!(actix_test::call_service(&app, req).await.status(), 404);
+}
+
+/// Test reading addresses out of header values
+#[test]
+fn test_parse_addresses() {
+    assert_eq!(
+        parse_addresses(
+            r#""Doe, Jane" <Jane@Example.com>, bob@example.com, undisclosed-recipients:;"#
+        ),
+        ["jane@example.com", "bob@example.com"]
+    );
+    assert_eq!(parse_addresses("<a@b.c>"), ["a@b.c"]);
+    assert!(parse_addresses("").is_empty());
+}
+
+/// Test choosing the sender, recipients and text among the provider's fields
+#[test]
+fn test_inbound_message_from_fields() {
+    let fields = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    };
+
+    let message = InboundMessage::from_fields(&fields(&[
+        ("from", "Ada <ada@example.com>"),
+        ("to", "grace@example.com, ada@example.com"),
+        ("cc", "Grace <GRACE@example.com>"),
+        ("body-plain", "Hello\nthere"),
+        ("text", "Ignored"),
+    ]))
+    .unwrap();
+    assert_eq!(message.sender, "ada@example.com");
+    assert_eq!(message.recipients, ["grace@example.com", "ada@example.com"]);
+    assert_eq!(message.notes().as_deref(), Some("Hello there"));
+
+    let long = "word ".repeat(200);
+    let message = InboundMessage::from_fields(&fields(&[
+        ("from", "a@example.com"),
+        ("subject", "Hi"),
+        ("text", &long),
+    ]))
+    .unwrap();
+    let notes = message.notes().unwrap();
+    assert!(notes.starts_with("Hi\n\nword word"));
+    assert!(notes.ends_with('…'));
+    assert_eq!(
+        notes.chars().count(),
+        "Hi\n\n".len() + inbound_email::MAX_SNIPPET_CHARS + 1
+    );
+
+    assert_eq!(
+        InboundMessage::from_fields(&fields(&[("to", "a@example.com")])),
+        None
+    );
+}
@@ -0,0 +1,130 @@
+use actix_web::{App, test as actix_test, web};
+use personal_crm::repository::{self, InMemoryRepository};
+use personal_crm::routes::v1_routes;
+use personal_crm::test_support::provision;
+use serde_json::{Value, json};
+
+/// Test capturing text and resolving it into an interaction
+#[actix_rt::test]
+async fn test_capture_and_resolve() {
+    let repo = repository::app_data(InMemoryRepository::new());
+    let owner = provision(repo.get_ref(), "inbox").await.unwrap();
+    let other = provision(repo.get_ref(), "inbox-other").await.unwrap();
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(repo.clone())
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let auth = ("Authorization", format!("Bearer {}", owner.token));
+    let post = |uri: String, body: Value| {
+        actix_test::TestRequest::post()
+            .uri(&uri)
+            .insert_header(auth.clone())
+            .set_json(body)
+            .to_request()
+    };
+
+    let res = actix_test::call_service(
+        &app,
+        post(
+            "/v1/inbox".to_string(),
+            json!({ "text": "  lunch w/ Priya yesterday, she's moving to Austin\n" }),
+        ),
+    )
+    .await;
+    assert_eq!(res.status(), 201);
+    let created: Value = actix_test::read_body_json(res).await;
+    let capture_id = created["capture_id"].as_i64().unwrap();
+
+    let res =
+        actix_test::call_service(&app, post("/v1/inbox".to_string(), json!({ "text": " " }))).await;
+    assert_eq!(res.status(), 400);
+
+    // Oldest first, after the provisioned capture
+    let req = actix_test::TestRequest::get()
+        .uri("/v1/inbox")
+        .insert_header(auth.clone())
+        .to_request();
+    let inbox: Vec<Value> =
+        actix_test::read_body_json(actix_test::call_service(&app, req).await).await;
+    assert_eq!(inbox.len(), 2);
+    assert_eq!(inbox[0]["capture_id"], owner.capture_id);
+    assert_eq!(inbox[1]["capture_id"], capture_id);
+    assert_eq!(
+        inbox[1]["text"],
+        "lunch w/ Priya yesterday, she's moving to Austin"
+    );
+
+    let resolve = |body: Value| post(format!("/v1/inbox/{}/resolve", capture_id), body);
+    for (body, status) in [
+        (json!({ "contact_id": other.contact_id }), 404),
+        (
+            json!({ "contact_id": owner.contact_id, "contact_ids": [other.contact_id] }),
+            404,
+        ),
+        (
+            json!({ "contact_id": owner.contact_id, "interaction_type": "telegram" }),
+            400,
+        ),
+    ] {
+        let res = actix_test::call_service(&app, resolve(body.clone())).await;
+        assert_eq!(res.status(), status, "{}", body);
+    }
+
+    // The text and capture time become the interaction's notes and date
+    let res = actix_test::call_service(
+        &app,
+        resolve(json!({ "contact_id": owner.contact_id, "interaction_type": "meeting" })),
+    )
+    .await;
+    assert_eq!(res.status(), 200);
+    let resolved: Value = actix_test::read_body_json(res).await;
+    let req = actix_test::TestRequest::get()
+        .uri(&format!("/v1/contacts/{}/interactions", owner.contact_id))
+        .insert_header(auth.clone())
+        .to_request();
+    let timeline: Vec<Value> =
+        actix_test::read_body_json(actix_test::call_service(&app, req).await).await;
+    let interaction = timeline
+        .iter()
+        .find(|i| i["interaction_id"] == resolved["interaction_id"])
+        .unwrap();
+    assert_eq!(
+        interaction["notes"],
+        "lunch w/ Priya yesterday, she's moving to Austin"
+    );
+    assert_eq!(interaction["interaction_date"], inbox[1]["captured_at"]);
+    assert_eq!(interaction["interaction_type"], "meeting");
+
+    // Resolving removes the capture
+    let res =
+        actix_test::call_service(&app, resolve(json!({ "contact_id": owner.contact_id }))).await;
+    assert_eq!(res.status(), 404);
+
+    // Discarding works on the caller's own captures only
+    let discard = |id: i32| {
+        actix_test::TestRequest::delete()
+            .uri(&format!("/v1/inbox/{}", id))
+            .insert_header(auth.clone())
+            .to_request()
+    };
+    assert_eq!(
+        actix_test::call_service(&app, discard(other.capture_id))
+            .await
+            .status(),
+        404
+    );
+    assert_eq!(
+        actix_test::call_service(&app, discard(owner.capture_id))
+            .await
+            .status(),
+        200
+    );
+    assert!(
+        repo.list_captures(owner.workspace_id)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+}
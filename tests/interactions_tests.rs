@@ -1,19 +1,27 @@
 mod common;
 
+use actix_web::{App, test, web};
 use common::*;
+use personal_crm::models::{NewContactRequest, NewInteractionRequest, NewTagRequest};
+use personal_crm::repository::{
+    self, InMemoryRepository, PgRepository, Repository, SqliteRepository,
+};
+use personal_crm::routes::v1_routes;
+use personal_crm::test_support::{provision, provision_user};
+use serde_json::{Value, json};
 use time::macros::datetime;
 
 /// Test creating an interaction and verifying it exists in the database
 #[tokio::test]
 async fn test_create_interaction() {
     let test_ctx = setup_test_db().await;
-    let user_id = setup_test_user(&test_ctx.pool).await;
+    let workspace_id = setup_test_workspace(&test_ctx.pool).await;
 
     // Create a contact first
     let contact_id = sqlx::query!(
-        "INSERT INTO contacts (user_id, first_name, last_name, email) 
+        "INSERT INTO contacts (workspace_id, first_name, last_name, email) 
          VALUES ($1, $2, $3, $4) RETURNING contact_id",
-        user_id,
+        workspace_id,
         "Alice",
         "Wonder",
         "alice@example.com"
@@ -23,13 +31,14 @@ async fn test_create_interaction() {
     .expect("Failed to create contact")
     .contact_id;
 
-    let interaction_date = datetime!(2026-01-04 14:30:00);
+    let interaction_date = datetime!(2026-01-04 14:30:00 UTC);
 
     // Create an interaction
     let result = sqlx::query!(
-        "INSERT INTO interactions (contact_id, interaction_date, notes, followup_priority) 
-         VALUES ($1, $2, $3, $4) 
+        "INSERT INTO interactions (workspace_id, contact_id, interaction_date, notes, followup_priority) 
+         VALUES ($1, $2, $3, $4, $5) 
          RETURNING interaction_id",
+        workspace_id,
         contact_id,
         interaction_date,
         "Had coffee meeting",
@@ -58,13 +67,13 @@ async fn test_create_interaction() {
 #[tokio::test]
 async fn test_update_interaction() {
     let test_ctx = setup_test_db().await;
-    let user_id = setup_test_user(&test_ctx.pool).await;
+    let workspace_id = setup_test_workspace(&test_ctx.pool).await;
 
     // Create a contact
     let contact_id = sqlx::query!(
-        "INSERT INTO contacts (user_id, first_name, last_name, email) 
+        "INSERT INTO contacts (workspace_id, first_name, last_name, email) 
          VALUES ($1, $2, $3, $4) RETURNING contact_id",
-        user_id,
+        workspace_id,
         "Bob",
         "Builder",
         "bob@example.com"
@@ -74,12 +83,13 @@ async fn test_update_interaction() {
     .expect("Failed to create contact")
     .contact_id;
 
-    let interaction_date = datetime!(2026-01-01 10:00:00);
+    let interaction_date = datetime!(2026-01-01 10:00:00 UTC);
 
     // Create an interaction
     let interaction_id = sqlx::query!(
-        "INSERT INTO interactions (contact_id, interaction_date, notes, followup_priority) 
-         VALUES ($1, $2, $3, $4) RETURNING interaction_id",
+        "INSERT INTO interactions (workspace_id, contact_id, interaction_date, notes, followup_priority) 
+         VALUES ($1, $2, $3, $4, $5) RETURNING interaction_id",
+        workspace_id,
         contact_id,
         interaction_date,
         "Initial meeting",
@@ -91,7 +101,7 @@ async fn test_update_interaction() {
     .interaction_id;
 
     // Update the interaction
-    let new_date = datetime!(2026-01-02 10:00:00);
+    let new_date = datetime!(2026-01-02 10:00:00 UTC);
     sqlx::query!(
         "UPDATE interactions SET interaction_date = $1, notes = $2, followup_priority = $3 WHERE interaction_id = $4",
         new_date,
@@ -123,13 +133,13 @@ async fn test_update_interaction() {
 #[tokio::test]
 async fn test_delete_interaction() {
     let test_ctx = setup_test_db().await;
-    let user_id = setup_test_user(&test_ctx.pool).await;
+    let workspace_id = setup_test_workspace(&test_ctx.pool).await;
 
     // Create a contact
     let contact_id = sqlx::query!(
-        "INSERT INTO contacts (user_id, first_name, last_name, email) 
+        "INSERT INTO contacts (workspace_id, first_name, last_name, email) 
          VALUES ($1, $2, $3, $4) RETURNING contact_id",
-        user_id,
+        workspace_id,
         "Charlie",
         "Brown",
         "charlie@example.com"
@@ -139,12 +149,13 @@ async fn test_delete_interaction() {
     .expect("Failed to create contact")
     .contact_id;
 
-    let interaction_date = datetime!(2026-01-03 15:00:00);
+    let interaction_date = datetime!(2026-01-03 15:00:00 UTC);
 
     // Create an interaction
     let interaction_id = sqlx::query!(
-        "INSERT INTO interactions (contact_id, interaction_date, notes) 
-         VALUES ($1, $2, $3) RETURNING interaction_id",
+        "INSERT INTO interactions (workspace_id, contact_id, interaction_date, notes) 
+         VALUES ($1, $2, $3, $4) RETURNING interaction_id",
+        workspace_id,
         contact_id,
         interaction_date,
         "Phone call"
@@ -174,3 +185,569 @@ async fn test_delete_interaction() {
 
     assert!(result.is_none());
 }
+
+/// Test built-in and custom interaction types, the typed timeline, and deleting a type
+#[actix_rt::test]
+async fn test_interaction_types() {
+    let test_ctx = setup_test_db().await;
+    let tenant = provision_user(&test_ctx.pool, "interaction-types")
+        .await
+        .unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(repository::app_data(PgRepository::new(
+                test_ctx.pool.clone(),
+            )))
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let auth = ("Authorization", format!("Bearer {}", tenant.token));
+
+    let req = test::TestRequest::post()
+        .uri("/v1/interaction-types")
+        .insert_header(auth.clone())
+        .set_json(json!({"name": " Dinner "}))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), 201);
+    let created: Value = test::read_body_json(res).await;
+    assert_eq!(created["name"], "Dinner");
+    let dinner_id = created["type_id"].as_i64().unwrap();
+
+    for (name, status) in [("Dinner", 409), ("Call", 400), ("", 400)] {
+        let req = test::TestRequest::post()
+            .uri("/v1/interaction-types")
+            .insert_header(auth.clone())
+            .set_json(json!({ "name": name }))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), status);
+    }
+
+    for (date, interaction_type, status) in [
+        // Stored and returned in UTC
+        ("2026-01-01T10:00:00+01:00", "call", 201),
+        ("2026-01-02T19:00:00", "Dinner", 201),
+        ("2026-01-03T12:00:00", "carrier pigeon", 400),
+    ] {
+        let req = test::TestRequest::post()
+            .uri("/v1/interactions")
+            .insert_header(auth.clone())
+            .set_json(json!({
+                "contact_id": tenant.contact_id,
+                "interaction_date": date,
+                "interaction_type": interaction_type
+            }))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), status);
+    }
+
+    let timeline = |query: &str| {
+        test::TestRequest::get()
+            .uri(&format!(
+                "/v1/contacts/{}/interactions{}",
+                tenant.contact_id, query
+            ))
+            .insert_header(auth.clone())
+            .to_request()
+    };
+    let all: Vec<Value> = test::call_and_read_body_json(&app, timeline("")).await;
+    let types: Vec<&Value> = all.iter().map(|i| &i["interaction_type"]).collect();
+    // The provisioned interaction (dated now) is newest
+    assert_eq!(types[1..], [&json!("Dinner"), &json!("call")]);
+    let calls: Vec<Value> = test::call_and_read_body_json(&app, timeline("?type=call")).await;
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0]["interaction_date"], "2026-01-01T09:00:00Z");
+
+    let req = test::TestRequest::delete()
+        .uri(&format!("/v1/interaction-types/{}", dinner_id))
+        .insert_header(auth.clone())
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+
+    let dinners: Vec<Value> = test::call_and_read_body_json(&app, timeline("?type=Dinner")).await;
+    assert!(dinners.is_empty());
+    let all: Vec<Value> = test::call_and_read_body_json(&app, timeline("")).await;
+    assert_eq!(all.len(), 3);
+    assert_eq!(all[1]["interaction_type"], Value::Null);
+}
+
+/// Test an interaction with several contacts showing up under each of them
+#[actix_rt::test]
+async fn test_interaction_participants() {
+    let test_ctx = setup_test_db().await;
+    let tenant = provision_user(&test_ctx.pool, "participants")
+        .await
+        .unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(repository::app_data(PgRepository::new(
+                test_ctx.pool.clone(),
+            )))
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let auth = ("Authorization", format!("Bearer {}", tenant.token));
+
+    let req = test::TestRequest::post()
+        .uri("/v1/contacts")
+        .insert_header(auth.clone())
+        .set_json(json!({"first_name": "Grace"}))
+        .to_request();
+    let created: Value = test::call_and_read_body_json(&app, req).await;
+    let guest_id = created["contact_id"].as_i64().unwrap() as i32;
+
+    let body = |contact_ids: Vec<i32>| {
+        json!({
+            "contact_id": tenant.contact_id,
+            "interaction_date": "2026-02-01T19:00:00",
+            "notes": "Group dinner",
+            "contact_ids": contact_ids
+        })
+    };
+    let req = test::TestRequest::post()
+        .uri("/v1/interactions")
+        .insert_header(auth.clone())
+        .set_json(body(vec![guest_id, tenant.contact_id, guest_id]))
+        .to_request();
+    let created: Value = test::call_and_read_body_json(&app, req).await;
+    let interaction_id = created["interaction_id"].as_i64().unwrap();
+
+    let timeline = |contact_id: i32| {
+        test::TestRequest::get()
+            .uri(&format!("/v1/contacts/{}/interactions", contact_id))
+            .insert_header(auth.clone())
+            .to_request()
+    };
+    let guest: Vec<Value> = test::call_and_read_body_json(&app, timeline(guest_id)).await;
+    assert_eq!(guest.len(), 1);
+    assert_eq!(guest[0]["interaction_id"], interaction_id);
+    assert_eq!(guest[0]["contact_id"], tenant.contact_id);
+    assert_eq!(guest[0]["contact_ids"], json!([guest_id]));
+
+    let req = test::TestRequest::get()
+        .uri("/v1/contacts")
+        .insert_header(auth.clone())
+        .to_request();
+    let contacts: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    for contact in &contacts {
+        assert!(
+            contact["interactions"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .any(|i| i["interaction_id"] == interaction_id)
+        );
+    }
+
+    // Participants must belong to the caller
+    let other = provision_user(&test_ctx.pool, "participants-other")
+        .await
+        .unwrap();
+    let req = test::TestRequest::patch()
+        .uri(&format!("/v1/interactions/{}", interaction_id))
+        .insert_header(auth.clone())
+        .set_json(body(vec![other.contact_id]))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 404);
+
+    let req = test::TestRequest::patch()
+        .uri(&format!("/v1/interactions/{}", interaction_id))
+        .insert_header(auth.clone())
+        .set_json(body(vec![]))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+    let guest: Vec<Value> = test::call_and_read_body_json(&app, timeline(guest_id)).await;
+    assert!(guest.is_empty());
+}
+
+/// Test that follow_up_in_days adds a linked follow-up occasion, removed with the interaction
+#[actix_rt::test]
+async fn test_interaction_follow_up() {
+    let test_ctx = setup_test_db().await;
+    let tenant = provision_user(&test_ctx.pool, "follow-up").await.unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(repository::app_data(PgRepository::new(
+                test_ctx.pool.clone(),
+            )))
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let auth = ("Authorization", format!("Bearer {}", tenant.token));
+
+    let body = |priority: Option<i32>, days: i32| {
+        json!({
+            "contact_id": tenant.contact_id,
+            "interaction_date": "2026-02-01T19:00:00Z",
+            "notes": "Talked about the move",
+            "follow_up_priority": priority,
+            "follow_up_in_days": days
+        })
+    };
+    for (priority, days) in [(None, 7), (Some(1), 0)] {
+        let req = test::TestRequest::post()
+            .uri("/v1/interactions")
+            .insert_header(auth.clone())
+            .set_json(body(priority, days))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 400);
+    }
+
+    let req = test::TestRequest::post()
+        .uri("/v1/interactions")
+        .insert_header(auth.clone())
+        .set_json(body(Some(1), 7))
+        .to_request();
+    let created: Value = test::call_and_read_body_json(&app, req).await;
+    let interaction_id = created["interaction_id"].as_i64().unwrap();
+    let occasion_id = created["follow_up_occasion_id"].as_i64().unwrap();
+
+    let contact = || {
+        test::TestRequest::get()
+            .uri(&format!("/v1/contacts/{}", tenant.contact_id))
+            .insert_header(auth.clone())
+            .to_request()
+    };
+    let response: Value = test::call_and_read_body_json(&app, contact()).await;
+    let follow_up = response["occasions"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|o| o["occasion_id"] == occasion_id)
+        .unwrap();
+    assert_eq!(follow_up["date"], "2026-02-08");
+    assert_eq!(follow_up["interaction_id"], interaction_id);
+    assert_eq!(follow_up["details"], "Talked about the move");
+
+    let req = test::TestRequest::delete()
+        .uri(&format!("/v1/interactions/{}", interaction_id))
+        .insert_header(auth.clone())
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+    let response: Value = test::call_and_read_body_json(&app, contact()).await;
+    assert!(
+        response["occasions"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .all(|o| o["occasion_id"] != occasion_id)
+    );
+}
+
+/// Test paging a timeline with cursors, including ties on the date and inserts between pages
+#[actix_rt::test]
+async fn test_timeline_cursor_pagination() {
+    let test_ctx = setup_test_db().await;
+    let tenant = provision_user(&test_ctx.pool, "timeline-pages")
+        .await
+        .unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(repository::app_data(PgRepository::new(
+                test_ctx.pool.clone(),
+            )))
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let auth = ("Authorization", format!("Bearer {}", tenant.token));
+    let log = |date: &str| {
+        test::TestRequest::post()
+            .uri("/v1/interactions")
+            .insert_header(auth.clone())
+            .set_json(json!({ "contact_id": tenant.contact_id, "interaction_date": date }))
+            .to_request()
+    };
+    // Two share a date, so the id breaks the tie
+    for date in [
+        "2025-01-01T10:00:00",
+        "2025-02-01T10:00:00",
+        "2025-02-01T10:00:00",
+        "2025-03-01T10:00:00",
+        "2025-04-01T10:00:00",
+    ] {
+        assert_eq!(test::call_service(&app, log(date)).await.status(), 201);
+    }
+    let get = |uri: &str| {
+        test::TestRequest::get()
+            .uri(uri)
+            .insert_header(auth.clone())
+            .to_request()
+    };
+    let ids = |page: &[Value]| -> Vec<i64> {
+        page.iter()
+            .map(|i| i["interaction_id"].as_i64().unwrap())
+            .collect()
+    };
+
+    let whole: Vec<Value> = test::call_and_read_body_json(
+        &app,
+        get(&format!("/v1/contacts/{}/interactions", tenant.contact_id)),
+    )
+    .await;
+    assert_eq!(whole.len(), 6);
+
+    let mut paged = Vec::new();
+    let mut next = Some(format!(
+        "/v1/contacts/{}/interactions?limit=2",
+        tenant.contact_id
+    ));
+    while let Some(uri) = next {
+        let res = test::call_service(&app, get(&uri)).await;
+        assert_eq!(res.status(), 200);
+        next = res.headers().get("Link").map(|link| {
+            let link = link.to_str().unwrap();
+            link[1..link.find('>').unwrap()].to_string()
+        });
+        let page: Vec<Value> = test::read_body_json(res).await;
+        assert!(page.len() <= 2);
+        paged.extend(ids(&page));
+        // A newer interaction logged mid-way doesn't shift the later pages
+        if paged.len() == 2 {
+            let res = test::call_service(&app, log("2030-01-01T10:00:00")).await;
+            assert_eq!(res.status(), 201);
+        }
+    }
+    assert_eq!(paged, ids(&whole));
+
+    for query in ["limit=0", "limit=501", "after=nonsense"] {
+        let res = test::call_service(
+            &app,
+            get(&format!(
+                "/v1/contacts/{}/interactions?{}",
+                tenant.contact_id, query
+            )),
+        )
+        .await;
+        assert_eq!(res.status(), 400, "{}", query);
+    }
+}
+
+/// Interactions take the workspace's tags, and the timeline filters by them
+async fn check_interaction_tags(repo: web::Data<dyn Repository>) {
+    let owner = provision(repo.get_ref(), "interaction-tags").await.unwrap();
+    let other = provision(repo.get_ref(), "interaction-tags-other")
+        .await
+        .unwrap();
+    let tag_id = repo
+        .create_tag(
+            owner.workspace_id,
+            &NewTagRequest {
+                name: format!("deep conversation {}", owner.user_id),
+                color: None,
+                details: None,
+            },
+        )
+        .await
+        .unwrap();
+    let (interaction_id, _) = repo
+        .create_interaction(
+            owner.workspace_id,
+            &NewInteractionRequest {
+                contact_id: owner.contact_id,
+                interaction_date: datetime!(2026-02-01 19:00 UTC),
+                notes: Some("Long walk".to_string()),
+                follow_up_priority: None,
+                interaction_type: None,
+                contact_ids: Vec::new(),
+                follow_up_in_days: None,
+            },
+            None,
+        )
+        .await
+        .unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(repo.clone())
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let auth = ("Authorization", format!("Bearer {}", owner.token));
+    let tag_uri = |interaction_id: i32, tag_id: i32| {
+        format!("/v1/interactions/{}/tags/{}", interaction_id, tag_id)
+    };
+    let tagged = |tag_id: i32| {
+        test::TestRequest::get()
+            .uri(&format!(
+                "/v1/contacts/{}/interactions?tag_id={}",
+                owner.contact_id, tag_id
+            ))
+            .insert_header(auth.clone())
+            .to_request()
+    };
+
+    let req = test::TestRequest::post()
+        .uri(&tag_uri(interaction_id, tag_id))
+        .insert_header(auth.clone())
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+
+    let timeline: Vec<Value> = test::call_and_read_body_json(&app, tagged(tag_id)).await;
+    assert_eq!(timeline.len(), 1);
+    assert_eq!(timeline[0]["interaction_id"], interaction_id);
+    assert_eq!(timeline[0]["tag_ids"], json!([tag_id]));
+    let timeline: Vec<Value> = test::call_and_read_body_json(&app, tagged(owner.tag_id)).await;
+    assert_eq!(timeline.len(), 1);
+    assert_eq!(timeline[0]["interaction_id"], owner.interaction_id);
+
+    // A tag on an interaction only is still in use
+    let req = test::TestRequest::delete()
+        .uri("/v1/tags/unused")
+        .insert_header(auth.clone())
+        .to_request();
+    let deleted: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(deleted["deleted_ids"], json!([]));
+
+    // Both the interaction and the tag must belong to the caller
+    for uri in [
+        tag_uri(interaction_id, other.tag_id),
+        tag_uri(other.interaction_id, tag_id),
+    ] {
+        let req = test::TestRequest::post()
+            .uri(&uri)
+            .insert_header(auth.clone())
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 404);
+    }
+
+    let req = test::TestRequest::delete()
+        .uri(&tag_uri(interaction_id, tag_id))
+        .insert_header(auth.clone())
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+    let timeline: Vec<Value> = test::call_and_read_body_json(&app, tagged(tag_id)).await;
+    assert!(timeline.is_empty());
+}
+
+/// Test interaction tags on the in-memory repository
+#[actix_rt::test]
+async fn test_interaction_tags_in_memory() {
+    check_interaction_tags(repository::app_data(InMemoryRepository::new())).await;
+}
+
+/// Test interaction tags on Postgres
+#[actix_rt::test]
+async fn test_interaction_tags_in_postgres() {
+    let test_ctx = setup_test_db().await;
+    check_interaction_tags(repository::app_data(PgRepository::new(
+        test_ctx.pool.clone(),
+    )))
+    .await;
+}
+
+#[actix_rt::test]
+async fn test_interaction_tags_in_sqlite() {
+    let repo = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+    check_interaction_tags(repository::app_data(repo)).await;
+}
+
+/// Check that searching notes finds the user's interactions and contacts, with escaped,
+/// highlighted snippets, and nobody else's
+async fn check_search_notes(repo: web::Data<dyn Repository>) {
+    let owner = provision(repo.get_ref(), "search").await.unwrap();
+    let other = provision(repo.get_ref(), "search-other").await.unwrap();
+    let log = |workspace_id: i32, contact_id: i32, notes: &str| {
+        let repo = repo.clone();
+        let notes = notes.to_string();
+        async move {
+            repo.create_interaction(
+                workspace_id,
+                &NewInteractionRequest {
+                    contact_id,
+                    interaction_date: datetime!(2026-02-01 19:00 UTC),
+                    notes: Some(notes),
+                    follow_up_priority: None,
+                    interaction_type: None,
+                    contact_ids: Vec::new(),
+                    follow_up_in_days: None,
+                },
+                None,
+            )
+            .await
+            .unwrap()
+            .0
+        }
+    };
+    let interaction_id = log(
+        owner.workspace_id,
+        owner.contact_id,
+        "Her dog is called Biscuit & loves <treats>",
+    )
+    .await;
+    log(other.workspace_id, other.contact_id, "Biscuit again").await;
+    let contact_id = repo
+        .create_contact(
+            owner.workspace_id,
+            &NewContactRequest {
+                last_name: Some("Lovelace".to_string()),
+                notes: Some("Takes Biscuit to the vet on Main Street".to_string()),
+                ..contact("Ada")
+            },
+        )
+        .await
+        .unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(repo.clone())
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let search = |query: &str| {
+        test::TestRequest::get()
+            .uri(&format!("/v1/interactions/search?{}", query))
+            .insert_header(("Authorization", format!("Bearer {}", owner.token)))
+            .to_request()
+    };
+
+    let found: Vec<Value> = test::call_and_read_body_json(&app, search("q=biscuit")).await;
+    assert_eq!(found.len(), 2);
+    let interaction = found.iter().find(|m| m["source"] == "interaction").unwrap();
+    assert_eq!(interaction["interaction_id"], interaction_id);
+    assert_eq!(interaction["interaction_date"], "2026-02-01T19:00:00Z");
+    assert_eq!(interaction["contact"]["contact_id"], owner.contact_id);
+    assert_eq!(
+        interaction["contact"]["display_name"],
+        owner.marker.as_str()
+    );
+    let snippet = interaction["snippet"].as_str().unwrap();
+    assert!(snippet.contains("<mark>Biscuit</mark>"), "{}", snippet);
+    assert!(snippet.contains("&amp; loves &lt;treats"), "{}", snippet);
+    assert!(!snippet.contains("<treats"), "{}", snippet);
+    let contact = found.iter().find(|m| m["source"] == "contact").unwrap();
+    assert_eq!(contact["interaction_id"], Value::Null);
+    assert_eq!(contact["contact"]["contact_id"], contact_id);
+    assert_eq!(contact["contact"]["display_name"], "Ada Lovelace");
+
+    // Every word has to match
+    let found: Vec<Value> = test::call_and_read_body_json(&app, search("q=biscuit+vet")).await;
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0]["source"], "contact");
+    let found: Vec<Value> = test::call_and_read_body_json(&app, search("q=biscuit&limit=1")).await;
+    assert_eq!(found.len(), 1);
+    let found: Vec<Value> = test::call_and_read_body_json(&app, search("q=poodle")).await;
+    assert!(found.is_empty());
+
+    for query in ["q=+", "q=biscuit&limit=0", "q=biscuit&limit=101"] {
+        let res = test::call_service(&app, search(query)).await;
+        assert_eq!(res.status(), 400, "{}", query);
+    }
+}
+
+#[actix_rt::test]
+async fn test_search_notes_in_memory() {
+    check_search_notes(repository::app_data(InMemoryRepository::new())).await;
+}
+
+#[actix_rt::test]
+async fn test_search_notes_in_postgres() {
+    let test_ctx = setup_test_db().await;
+    check_search_notes(repository::app_data(PgRepository::new(
+        test_ctx.pool.clone(),
+    )))
+    .await;
+}
+
+#[actix_rt::test]
+async fn test_search_notes_in_sqlite() {
+    let repo = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+    check_search_notes(repository::app_data(repo)).await;
+}
@@ -0,0 +1,237 @@
+mod common;
+
+use actix_web::{App, test as actix_test, web};
+use common::*;
+use personal_crm::models::{Contact, MetAt, NewContactRequest};
+use personal_crm::repository::{
+    self, InMemoryRepository, PgRepository, Repository, SqliteRepository,
+};
+use personal_crm::routes::v1_routes;
+use personal_crm::test_support::provision;
+use serde_json::{Value, json};
+use time::macros::date;
+
+fn introduced(name: &str, by: i32) -> NewContactRequest {
+    NewContactRequest {
+        introduced_by_contact_id: Some(by),
+        ..contact(name)
+    }
+}
+
+/// Check that how and through whom the user met a contact is stored by the single and bulk
+/// inserts and by updates, and that deleting the introducer keeps the contacts they introduced
+async fn check_introductions(repo: web::Data<dyn Repository>) {
+    let owner = provision(repo.get_ref(), "introductions").await.unwrap();
+    let workspace_id = owner.workspace_id;
+    let dana = repo
+        .create_contact(workspace_id, &contact("Dana"))
+        .await
+        .unwrap();
+    let met_at = MetAt {
+        date: Some(date!(2023 - 06 - 10)),
+        place: Some("Lisbon".to_string()),
+        context: Some("Dana's birthday party".to_string()),
+    };
+    let zoe = repo
+        .create_contact(
+            workspace_id,
+            &NewContactRequest {
+                met_at: Some(met_at.clone()),
+                ..introduced("Zoe", dana)
+            },
+        )
+        .await
+        .unwrap();
+    let bulk = repo
+        .create_contacts(
+            workspace_id,
+            &[
+                introduced("Ben", dana),
+                NewContactRequest {
+                    met_at: Some(MetAt {
+                        place: Some("Porto".to_string()),
+                        ..MetAt::default()
+                    }),
+                    ..contact("Cy")
+                },
+            ],
+            true,
+        )
+        .await
+        .unwrap();
+    let ben = *bulk[0].as_ref().unwrap();
+    let cy = *bulk[1].as_ref().unwrap();
+
+    let ids =
+        |contacts: Vec<Contact>| -> Vec<i32> { contacts.iter().map(|c| c.contact_id).collect() };
+    assert_eq!(
+        ids(repo
+            .contacts_introduced_by(workspace_id, dana)
+            .await
+            .unwrap()),
+        vec![ben, zoe]
+    );
+    assert!(
+        repo.contacts_introduced_by(owner.spare_workspace_id, dana)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    let stored = repo.get_contact(workspace_id, zoe).await.unwrap().unwrap();
+    assert_eq!(stored.met_at, Some(met_at));
+    assert_eq!(stored.introduced_by_contact_id, Some(dana));
+    let stored = repo.get_contact(workspace_id, cy).await.unwrap().unwrap();
+    assert_eq!(stored.met_at.unwrap().place.as_deref(), Some("Porto"));
+
+    // Cy turns out to be another of Dana's introductions
+    assert!(
+        repo.update_contact(workspace_id, cy, &introduced("Cy", dana))
+            .await
+            .unwrap()
+    );
+    assert_eq!(
+        ids(repo
+            .contacts_introduced_by(workspace_id, dana)
+            .await
+            .unwrap()),
+        vec![ben, cy, zoe]
+    );
+    let fields: Vec<String> = repo
+        .contact_history(cy, None, None)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|c| c.field)
+        .collect();
+    assert!(fields.contains(&"met_at".to_string()));
+    assert!(fields.contains(&"introduced_by_contact_id".to_string()));
+
+    assert!(
+        repo.delete_contact(workspace_id, dana, false)
+            .await
+            .unwrap()
+    );
+    let stored = repo.get_contact(workspace_id, zoe).await.unwrap().unwrap();
+    assert_eq!(stored.introduced_by_contact_id, None);
+    assert!(stored.met_at.is_some());
+}
+
+#[actix_rt::test]
+async fn test_introductions_in_memory() {
+    check_introductions(repository::app_data(InMemoryRepository::new())).await;
+}
+
+#[actix_rt::test]
+async fn test_introductions_in_postgres() {
+    let ctx = setup_test_db().await;
+    check_introductions(repository::app_data(PgRepository::new(ctx.pool.clone()))).await;
+}
+
+#[actix_rt::test]
+async fn test_introductions_in_sqlite() {
+    let repo = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+    check_introductions(repository::app_data(repo)).await;
+}
+
+/// Test setting how the user met a contact and listing who a contact introduced
+#[actix_rt::test]
+async fn test_introductions_api() {
+    let repo = repository::app_data(InMemoryRepository::new());
+    let owner = provision(repo.get_ref(), "introductions-api")
+        .await
+        .unwrap();
+    let other = provision(repo.get_ref(), "introductions-other")
+        .await
+        .unwrap();
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(repo.clone())
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let auth = ("Authorization", format!("Bearer {}", owner.token));
+    let create = |body: Value| {
+        actix_test::TestRequest::post()
+            .uri("/v1/contacts")
+            .insert_header(auth.clone())
+            .set_json(body)
+            .to_request()
+    };
+
+    let res = actix_test::call_service(
+        &app,
+        create(json!({
+            "first_name": "Zoe",
+            "met_at": {"date": "2023-06-10", "place": "Lisbon"},
+            "introduced_by_contact_id": owner.contact_id
+        })),
+    )
+    .await;
+    assert_eq!(res.status(), 201);
+    let created: Value = actix_test::read_body_json(res).await;
+    let zoe = created["contact_id"].as_i64().unwrap();
+
+    for body in [
+        // Another user's contact
+        json!({"first_name": "Ben", "introduced_by_contact_id": other.contact_id}),
+        json!({"first_name": "Ben", "met_at": {"context": "x".repeat(256)}}),
+        json!({"first_name": "Ben", "short_note": "x".repeat(256)}),
+    ] {
+        let res = actix_test::call_service(&app, create(body.clone())).await;
+        assert_eq!(res.status(), 400, "{} was accepted", body);
+    }
+    let req = actix_test::TestRequest::post()
+        .uri("/v1/contacts/bulk")
+        .insert_header(auth.clone())
+        .set_json(json!([
+            {"first_name": "Ben"},
+            {"first_name": "Cy", "introduced_by_contact_id": other.contact_id}
+        ]))
+        .to_request();
+    let res = actix_test::call_service(&app, req).await;
+    assert_eq!(res.status(), 400);
+    let error: Value = actix_test::read_body_json(res).await;
+    assert_eq!(error["index"], 1);
+
+    // Nobody introduces themselves, and an empty met_at is none at all
+    let update = |body: Value| {
+        actix_test::TestRequest::patch()
+            .uri(&format!("/v1/contacts/{}", zoe))
+            .insert_header(auth.clone())
+            .set_json(body)
+            .to_request()
+    };
+    let res = actix_test::call_service(
+        &app,
+        update(json!({"first_name": "Zoe", "introduced_by_contact_id": zoe})),
+    )
+    .await;
+    assert_eq!(res.status(), 400);
+    let res = actix_test::call_service(
+        &app,
+        update(json!({
+            "first_name": "Zoe",
+            "met_at": {},
+            "introduced_by_contact_id": owner.contact_id
+        })),
+    )
+    .await;
+    assert_eq!(res.status(), 200);
+
+    let req = actix_test::TestRequest::get()
+        .uri(&format!("/v1/contacts/{}/introductions", owner.contact_id))
+        .insert_header(auth.clone())
+        .to_request();
+    let introduced: Vec<Value> =
+        actix_test::read_body_json(actix_test::call_service(&app, req).await).await;
+    assert_eq!(introduced.len(), 1);
+    assert_eq!(introduced[0]["contact_id"], zoe);
+    assert_eq!(introduced[0]["met_at"], Value::Null);
+    assert_eq!(introduced[0]["introduced_by_contact_id"], owner.contact_id);
+
+    let req = actix_test::TestRequest::get()
+        .uri(&format!("/v1/contacts/{}/introductions", owner.contact_id))
+        .insert_header(("Authorization", format!("Bearer {}", other.token)))
+        .to_request();
+    assert_eq!(actix_test::call_service(&app, req).await.status(), 404);
+}
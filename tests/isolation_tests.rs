@@ -0,0 +1,117 @@
+mod common;
+
+use actix_web::http::header::CONTENT_TYPE;
+use actix_web::{App, test, web};
+use common::*;
+use personal_crm::events::EventBus;
+use personal_crm::repository::{self, PgRepository};
+use personal_crm::routes::{ROUTES, RouteSpec, v1_routes};
+use personal_crm::test_support::{Tenant, body_for, path_for, provision_user, snapshot};
+
+/// Every way to aim a route at the victim: one variant per id the route takes, with that
+/// id pointing at the victim's row and the rest at the intruder's own rows
+fn variants(spec: &RouteSpec, victim: &Tenant, intruder: &Tenant) -> Vec<(Vec<i32>, Vec<i32>)> {
+    let refs: Vec<_> = spec.path.iter().chain(spec.body.iter()).collect();
+    let own = |skip: Option<usize>| -> Vec<i32> {
+        refs.iter()
+            .enumerate()
+            .map(|(n, r)| {
+                if Some(n) == skip {
+                    victim.id(**r)
+                } else {
+                    intruder.id(**r)
+                }
+            })
+            .collect()
+    };
+
+    let ids: Vec<Vec<i32>> = if refs.is_empty() {
+        vec![own(None)]
+    } else {
+        (0..refs.len()).map(|n| own(Some(n))).collect()
+    };
+    ids.into_iter()
+        .map(|ids| {
+            let (path, body) = ids.split_at(spec.path.len());
+            (path.to_vec(), body.to_vec())
+        })
+        .collect()
+}
+
+/// Test that no route lets one user read or change another user's rows
+#[actix_rt::test]
+async fn test_routes_enforce_tenant_isolation() {
+    let test_ctx = setup_test_db().await;
+    let victim = provision_user(&test_ctx.pool, "victim").await.unwrap();
+    let intruder = provision_user(&test_ctx.pool, "intruder").await.unwrap();
+
+    let app = test::init_service(
+        App::new()
+            .app_data(repository::app_data(PgRepository::new(
+                test_ctx.pool.clone(),
+            )))
+            .app_data(web::Data::new(EventBus::default()))
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+
+    let before = snapshot(&test_ctx.pool, victim.user_id).await.unwrap();
+
+    for spec in ROUTES {
+        for (path_ids, body_ids) in variants(spec, &victim, &intruder) {
+            let uri = path_for(spec, &path_ids);
+            let mut req = test::TestRequest::default()
+                .method(spec.method.clone())
+                .uri(&uri)
+                .insert_header(("Authorization", format!("Bearer {}", intruder.token)));
+            if let Some(body) = body_for(spec, &intruder, &body_ids) {
+                req = req.set_json(body);
+            }
+            let res = test::call_service(&app, req.to_request()).await;
+            let status = res.status();
+            // Event streams never end; what they carry is covered by the events tests
+            let streaming = res
+                .headers()
+                .get(CONTENT_TYPE)
+                .is_some_and(|t| t == "text/event-stream");
+            let body = if streaming {
+                Default::default()
+            } else {
+                test::read_body(res).await
+            };
+            let label = format!("{} {} ({})", spec.method, uri, status);
+
+            // An empty 404 means nothing matched: the registry is out of date
+            assert!(
+                !(status == 404 && body.is_empty()),
+                "{} is in ROUTES but not routed",
+                label
+            );
+            assert!(
+                !String::from_utf8_lossy(&body).contains(&victim.marker),
+                "{} leaked the victim's data",
+                label
+            );
+            let victim_path = path_ids
+                .iter()
+                .zip(spec.path)
+                .any(|(id, r)| *id == victim.id(*r));
+            if victim_path {
+                assert_eq!(status, 404, "{} should not find the victim's row", label);
+            }
+            if spec.path.is_empty() && spec.body.is_empty() {
+                assert!(
+                    status.is_success(),
+                    "{} failed on the caller's own data",
+                    label
+                );
+            }
+            assert_eq!(
+                snapshot(&test_ctx.pool, victim.user_id).await.unwrap(),
+                before,
+                "{} changed the victim's data",
+                label
+            );
+        }
+    }
+}
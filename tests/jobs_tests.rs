@@ -0,0 +1,132 @@
+mod common;
+
+use actix_web::web;
+use async_trait::async_trait;
+use common::*;
+use personal_crm::jobs::{Job, run_claimed};
+use personal_crm::repository::{
+    self, InMemoryRepository, PgRepository, RepoResult, Repository, SqliteRepository,
+};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use time::OffsetDateTime;
+use time::macros::datetime;
+
+/// Counts its runs
+#[derive(Default)]
+struct CountingJob {
+    runs: AtomicUsize,
+}
+
+#[async_trait]
+impl Job for CountingJob {
+    fn name(&self) -> &'static str {
+        "counting"
+    }
+
+    fn interval(&self) -> Duration {
+        Duration::from_secs(600)
+    }
+
+    async fn run(&self, _repo: &dyn Repository) -> RepoResult<()> {
+        self.runs.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+/// Check that a lease keeps other holders out until it runs out, but not its own holder
+async fn check_claim_job(repo: web::Data<dyn Repository>) {
+    let now = datetime!(2024-03-04 12:00 UTC);
+    let until = now + time::Duration::minutes(10);
+    assert!(repo.claim_job("sweep", "a", now, until).await.unwrap());
+    assert!(
+        !repo
+            .claim_job("sweep", "b", now + time::Duration::minutes(1), until)
+            .await
+            .unwrap()
+    );
+    // Other jobs have leases of their own
+    assert!(repo.claim_job("other", "b", now, until).await.unwrap());
+    // The holder takes its lease again without waiting for it to run out
+    let later = now + time::Duration::minutes(9);
+    assert!(
+        repo.claim_job("sweep", "a", later, later + time::Duration::minutes(10))
+            .await
+            .unwrap()
+    );
+    assert!(
+        !repo
+            .claim_job("sweep", "b", until, until + time::Duration::minutes(10))
+            .await
+            .unwrap()
+    );
+    // Once it has run out, as when its replica stopped, another holder takes over
+    let expired = later + time::Duration::minutes(10);
+    assert!(
+        repo.claim_job("sweep", "b", expired, expired + time::Duration::minutes(10))
+            .await
+            .unwrap()
+    );
+    assert!(
+        !repo
+            .claim_job("sweep", "a", expired, expired + time::Duration::minutes(10))
+            .await
+            .unwrap()
+    );
+}
+
+#[actix_rt::test]
+async fn test_claim_job_in_memory() {
+    check_claim_job(repository::app_data(InMemoryRepository::new())).await;
+}
+
+#[actix_rt::test]
+async fn test_claim_job_in_postgres() {
+    let ctx = setup_test_db().await;
+    check_claim_job(repository::app_data(PgRepository::new(ctx.pool.clone()))).await;
+}
+
+#[actix_rt::test]
+async fn test_claim_job_in_sqlite() {
+    let repo = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+    check_claim_job(repository::app_data(repo)).await;
+}
+
+/// Test that of two replicas ticking at the same time only one runs the job, and that the
+/// other takes it over when the first stops
+#[actix_rt::test]
+async fn test_one_replica_runs_each_interval() {
+    let ctx = setup_test_db().await;
+    let repo = PgRepository::new(ctx.pool.clone());
+    let job = CountingJob::default();
+    let now = OffsetDateTime::now_utc();
+
+    let (first, second) = tokio::join!(
+        run_claimed(&repo, &job, "replica-1", now),
+        run_claimed(&repo, &job, "replica-2", now)
+    );
+    let (first, second) = (first.unwrap(), second.unwrap());
+    assert!(first ^ second);
+    assert_eq!(job.runs.load(Ordering::SeqCst), 1);
+    let (holder, other) = if first {
+        ("replica-1", "replica-2")
+    } else {
+        ("replica-2", "replica-1")
+    };
+
+    // Before the interval is up the lease is still held
+    let soon = now + Duration::from_secs(300);
+    assert!(!run_claimed(&repo, &job, other, soon).await.unwrap());
+    assert_eq!(job.runs.load(Ordering::SeqCst), 1);
+
+    // The holder's next tick, then nothing from it after it stops
+    let next = now + Duration::from_secs(600);
+    assert!(run_claimed(&repo, &job, holder, next).await.unwrap());
+    assert!(!run_claimed(&repo, &job, other, next).await.unwrap());
+    assert_eq!(job.runs.load(Ordering::SeqCst), 2);
+
+    // The other replica takes over once that lease runs out
+    let after = next + Duration::from_secs(600);
+    assert!(run_claimed(&repo, &job, other, after).await.unwrap());
+    assert_eq!(job.runs.load(Ordering::SeqCst), 3);
+}
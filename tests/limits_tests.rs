@@ -0,0 +1,83 @@
+use actix_web::{App, test, web};
+use personal_crm::limits::Limits;
+use personal_crm::models::NewTagRequest;
+use personal_crm::repository::{self, InMemoryRepository, Repository};
+use personal_crm::routes::v1_routes;
+use personal_crm::test_support::register_token;
+use serde_json::{Value, json};
+
+/// Test that bulk requests and JSON imports over the item cap get 422 and oversized bodies 413
+#[actix_rt::test]
+async fn test_bulk_item_cap_and_payload_limit() {
+    register_token("token-limits", "test|limits").await;
+    let repo = InMemoryRepository::new();
+    let user = repo
+        .get_or_create_user("test|limits", "limits@example.com", "Limits")
+        .await
+        .unwrap();
+    let tag_id = repo
+        .create_tag(
+            user.workspace_id,
+            &NewTagRequest {
+                name: "Capped".to_string(),
+                color: None,
+                details: None,
+            },
+        )
+        .await
+        .unwrap();
+    let limits = Limits {
+        max_json_payload_bytes: 1024,
+        max_bulk_items: 3,
+        ..Limits::default()
+    };
+    let app = test::init_service(
+        App::new()
+            .app_data(repository::app_data(repo))
+            .app_data(web::Data::new(limits))
+            .app_data(limits.json_config())
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let post = |uri: String, body: Value| {
+        test::TestRequest::post()
+            .uri(&uri)
+            .insert_header(("Authorization", "Bearer token-limits"))
+            .set_json(body)
+            .to_request()
+    };
+
+    let contacts = |n: usize| json!(vec![json!({ "first_name": "Bulk" }); n]);
+    let res = test::call_service(&app, post("/v1/contacts/bulk".into(), contacts(3))).await;
+    assert_eq!(res.status(), 200);
+
+    let ids = json!({ "contact_ids": [1, 2, 3, 4] });
+    let import = json!({
+        "mapping": { "records": ".", "fields": { "first_name": ".name" } },
+        "data": vec![json!({ "name": "Imported" }); 4],
+    });
+    for (uri, body) in [
+        ("/v1/contacts/bulk".to_string(), contacts(4)),
+        ("/v1/contacts/import/json".to_string(), import),
+        ("/v1/contacts/bulk-delete".to_string(), ids.clone()),
+        (format!("/v1/tags/{}/contacts/bulk", tag_id), ids.clone()),
+    ] {
+        let res = test::call_service(&app, post(uri.clone(), body)).await;
+        assert_eq!(res.status(), 422, "{}", uri);
+        let body: Value = test::read_body_json(res).await;
+        assert_eq!(body["max_items"], 3);
+    }
+
+    let long_note = "x".repeat(2048);
+    let res = test::call_service(
+        &app,
+        post(
+            "/v1/contacts".into(),
+            json!({ "first_name": "Big", "notes": long_note }),
+        ),
+    )
+    .await;
+    assert_eq!(res.status(), 413);
+    let body: Value = test::read_body_json(res).await;
+    assert_eq!(body["error"], "Request body is larger than 1024 bytes");
+}
@@ -0,0 +1,322 @@
+mod common;
+
+use common::*;
+use personal_crm::models::{
+    ContactResponse, NewContactRequest, NewInteractionRequest, NewOccasionRequest, NewTagRequest,
+    OccasionType, SharePermission,
+};
+use personal_crm::repository::{InMemoryRepository, Repository};
+use personal_crm::timezone::LocalDates;
+use time::{Duration, OffsetDateTime};
+
+fn days_ago(days: i64) -> OffsetDateTime {
+    let date = OffsetDateTime::now_utc().date() - Duration::days(days);
+    date.midnight().assume_utc()
+}
+
+/// Test that contacts are scoped to their owner and sorted like the SQL query
+#[tokio::test]
+async fn test_contacts_are_scoped_and_sorted() {
+    let repo = InMemoryRepository::new();
+    let alice = repo
+        .get_or_create_user("test|alice", "alice@example.com", "Alice")
+        .await
+        .unwrap();
+    let bob = repo
+        .get_or_create_user("test|bob", "bob@example.com", "Bob")
+        .await
+        .unwrap();
+
+    repo.create_contact(alice.workspace_id, &contact("Zed"))
+        .await
+        .unwrap();
+    repo.create_contact(
+        alice.workspace_id,
+        &NewContactRequest {
+            last_name: Some("Young".to_string()),
+            ..contact("Ann")
+        },
+    )
+    .await
+    .unwrap();
+    let bobs = repo
+        .create_contact(
+            bob.workspace_id,
+            &NewContactRequest {
+                last_name: Some("Adams".to_string()),
+                ..contact("Bea")
+            },
+        )
+        .await
+        .unwrap();
+
+    let names: Vec<_> = repo
+        .list_contacts(alice.workspace_id)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|c| c.first_name.unwrap())
+        .collect();
+    assert_eq!(names, vec!["Ann", "Zed"]);
+
+    assert!(
+        repo.get_contact(alice.workspace_id, bobs)
+            .await
+            .unwrap()
+            .is_none()
+    );
+    assert!(
+        !repo
+            .delete_contact(alice.workspace_id, bobs, false)
+            .await
+            .unwrap()
+    );
+    assert!(repo.owns_contact(bob.workspace_id, bobs).await.unwrap());
+}
+
+/// Test that deleting a contact cascades to its tags, interactions and occasions
+#[tokio::test]
+async fn test_delete_contact_cascades() {
+    let repo = InMemoryRepository::new();
+    let user = repo
+        .get_or_create_user("test|cascade", "cascade@example.com", "Cascade")
+        .await
+        .unwrap();
+    let contact_id = repo
+        .create_contact(
+            user.workspace_id,
+            &NewContactRequest {
+                last_name: Some("Doe".to_string()),
+                ..contact("Jane")
+            },
+        )
+        .await
+        .unwrap();
+    let tag_id = repo
+        .create_tag(
+            user.workspace_id,
+            &NewTagRequest {
+                name: "friends".to_string(),
+                color: None,
+                details: None,
+            },
+        )
+        .await
+        .unwrap();
+    repo.add_tag_to_contact(contact_id, tag_id).await.unwrap();
+    repo.create_interaction(
+        user.workspace_id,
+        &NewInteractionRequest {
+            contact_id,
+            interaction_date: days_ago(1),
+            notes: None,
+            follow_up_priority: None,
+            interaction_type: None,
+            contact_ids: Vec::new(),
+            follow_up_in_days: None,
+        },
+        None,
+    )
+    .await
+    .unwrap();
+    repo.create_occasion(
+        user.workspace_id,
+        &NewOccasionRequest {
+            contact_id,
+            name: "Birthday".to_string(),
+            date: days_ago(300).date(),
+            recurring: true,
+            recurring_interval: None,
+            details: None,
+            occasion_type: OccasionType::Birthday,
+            birth_year: None,
+            remind_days_before: None,
+        },
+    )
+    .await
+    .unwrap();
+
+    assert!(
+        repo.delete_contact(user.workspace_id, contact_id, false)
+            .await
+            .unwrap()
+    );
+    assert!(
+        repo.tags_for_contacts(&[contact_id])
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    assert!(
+        repo.interactions_for_contacts(&[contact_id])
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    assert!(
+        repo.occasions_for_contacts(&[contact_id])
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    // The tag itself survives
+    assert_eq!(repo.list_tags(user.workspace_id).await.unwrap().len(), 1);
+}
+
+/// Test that the unique constraints from the schema are enforced
+#[tokio::test]
+async fn test_unique_constraints() {
+    let repo = InMemoryRepository::new();
+    let user = repo
+        .get_or_create_user("test|unique", "unique@example.com", "Unique")
+        .await
+        .unwrap();
+
+    repo.create_contact(
+        user.workspace_id,
+        &NewContactRequest {
+            email: Some("a@example.com".to_string()),
+            ..contact("A")
+        },
+    )
+    .await
+    .unwrap();
+    assert!(
+        repo.create_contact(
+            user.workspace_id,
+            &NewContactRequest {
+                email: Some("a@example.com".to_string()),
+                ..contact("B")
+            }
+        )
+        .await
+        .is_err()
+    );
+
+    // Logging in again returns the same user
+    let again = repo
+        .get_or_create_user("test|unique", "unique@example.com", "Unique")
+        .await
+        .unwrap();
+    assert_eq!(again.user_id, user.user_id);
+}
+
+/// Test priority calculation on data loaded from the repository
+#[tokio::test]
+async fn test_priority_from_repository_data() {
+    let repo = InMemoryRepository::new();
+    let user = repo
+        .get_or_create_user("test|priority", "priority@example.com", "Priority")
+        .await
+        .unwrap();
+    let contact_id = repo
+        .create_contact(user.workspace_id, &contact("Sam"))
+        .await
+        .unwrap();
+    for days in [20, 10] {
+        repo.create_interaction(
+            user.workspace_id,
+            &NewInteractionRequest {
+                contact_id,
+                interaction_date: days_ago(days),
+                notes: None,
+                follow_up_priority: None,
+                interaction_type: None,
+                contact_ids: Vec::new(),
+                follow_up_in_days: None,
+            },
+            None,
+        )
+        .await
+        .unwrap();
+    }
+
+    let contact = repo
+        .get_contact(user.workspace_id, contact_id)
+        .await
+        .unwrap()
+        .unwrap();
+    let interactions = repo.interactions_for_contacts(&[contact_id]).await.unwrap();
+    let response = ContactResponse::new(contact, vec![], interactions, vec![], &LocalDates::utc());
+
+    // Seen every 10 days and last seen 10 days ago: due now
+    assert_eq!(response.predicted_contact_priority, Some(0.0));
+}
+
+/// Test that a tag share follows the tag, resolves to the strongest permission and cascades
+#[tokio::test]
+async fn test_shares_follow_tags() {
+    let repo = InMemoryRepository::new();
+    let alice = repo
+        .get_or_create_user("test|alice", "alice@example.com", "Alice")
+        .await
+        .unwrap();
+    let bob = repo
+        .get_or_create_user("test|bob", "bob@example.com", "Bob")
+        .await
+        .unwrap();
+    assert_eq!(
+        repo.find_user_by_email("Bob@Example.com").await.unwrap(),
+        Some(bob.user_id)
+    );
+
+    let ann = repo
+        .create_contact(alice.workspace_id, &contact("Ann"))
+        .await
+        .unwrap();
+    let tag = NewTagRequest {
+        name: "Family".to_string(),
+        color: None,
+        details: None,
+    };
+    let tag_id = repo.create_tag(alice.workspace_id, &tag).await.unwrap();
+    repo.create_shares(
+        alice.user_id,
+        bob.user_id,
+        Some(tag_id),
+        &[],
+        SharePermission::Read,
+    )
+    .await
+    .unwrap();
+    repo.create_shares(
+        alice.user_id,
+        bob.user_id,
+        None,
+        &[ann],
+        SharePermission::Write,
+    )
+    .await
+    .unwrap();
+    let access = repo
+        .contact_access(bob.user_id, bob.workspace_id, ann)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(access.owner_id, alice.user_id);
+    assert_eq!(access.shared, Some(SharePermission::Write));
+
+    // Only the tag share is left, and it covers Ann once she carries the tag
+    repo.delete_contact(alice.workspace_id, ann, false)
+        .await
+        .unwrap();
+    assert_eq!(repo.list_shares(bob.user_id).await.unwrap().len(), 1);
+    let ann = repo
+        .create_contact(alice.workspace_id, &contact("Ann"))
+        .await
+        .unwrap();
+    assert!(repo.shared_contacts(bob.user_id).await.unwrap().is_empty());
+    repo.add_tag_to_contact(ann, tag_id).await.unwrap();
+    let shared = repo.shared_contacts(bob.user_id).await.unwrap();
+    assert_eq!(shared[0].contact_id, ann);
+    assert_eq!(shared[0].permission, SharePermission::Read);
+
+    repo.delete_tag(alice.workspace_id, tag_id).await.unwrap();
+    assert!(repo.list_shares(alice.user_id).await.unwrap().is_empty());
+    assert_eq!(
+        repo.contact_access(bob.user_id, bob.workspace_id, ann)
+            .await
+            .unwrap(),
+        None
+    );
+}
@@ -0,0 +1,302 @@
+mod common;
+
+use common::*;
+use personal_crm::contact_links::token_hash;
+use personal_crm::repository::{PgRepository, Repository};
+use sqlx::{PgPool, Row};
+use time::OffsetDateTime;
+use time::macros::datetime;
+
+/// The tables as they were before workspaces, which existing installs still have
+const BEFORE_WORKSPACES: &str = r#"
+CREATE TABLE users (
+    user_id SERIAL PRIMARY KEY,
+    auth0_id VARCHAR(100) UNIQUE NOT NULL,
+    name VARCHAR(100) NOT NULL,
+    email VARCHAR(100) UNIQUE NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE contacts (
+    contact_id SERIAL PRIMARY KEY,
+    user_id INT NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users(user_id) ON DELETE CASCADE,
+    first_name VARCHAR(50),
+    last_name VARCHAR(50),
+    email VARCHAR(100) UNIQUE,
+    phone VARCHAR(20),
+    short_note VARCHAR(255),
+    notes TEXT,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE tags (
+    tag_id SERIAL PRIMARY KEY,
+    user_id INT NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users(user_id) ON DELETE CASCADE,
+    name VARCHAR(50) UNIQUE NOT NULL,
+    details TEXT,
+    color VARCHAR(20),
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE contact_tags (
+    contact_id INT NOT NULL,
+    tag_id INT NOT NULL,
+    PRIMARY KEY (contact_id, tag_id),
+    FOREIGN KEY (contact_id) REFERENCES contacts(contact_id) ON DELETE CASCADE,
+    FOREIGN KEY (tag_id) REFERENCES tags(tag_id) ON DELETE CASCADE
+);
+
+CREATE TABLE interactions (
+    interaction_id SERIAL PRIMARY KEY,
+    user_id INT NOT NULL,
+    contact_id INT NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users(user_id) ON DELETE CASCADE,
+    FOREIGN KEY (contact_id) REFERENCES contacts(contact_id) ON DELETE CASCADE,
+    interaction_date TIMESTAMP NOT NULL,
+    notes TEXT,
+    followup_priority INT,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE occasions (
+    occasion_id SERIAL PRIMARY KEY,
+    user_id INT NOT NULL,
+    contact_id INT NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users(user_id) ON DELETE CASCADE,
+    FOREIGN KEY (contact_id) REFERENCES contacts(contact_id) ON DELETE CASCADE,
+    name VARCHAR(100) NOT NULL,
+    date DATE NOT NULL,
+    recurring BOOLEAN DEFAULT FALSE,
+    recurring_interval INT,
+    details TEXT,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+INSERT INTO users (auth0_id, name, email) VALUES
+    ('auth0|ada', 'Ada', 'ada@example.com'),
+    ('auth0|grace', 'Grace', 'grace@example.com');
+INSERT INTO contacts (user_id, first_name, email) VALUES
+    (1, 'Charles', 'charles@example.com'),
+    (2, 'Alan', 'alan@example.com');
+INSERT INTO tags (user_id, name) VALUES (1, 'friends'), (2, 'work');
+INSERT INTO contact_tags (contact_id, tag_id) VALUES (1, 1), (2, 2);
+INSERT INTO interactions (user_id, contact_id, interaction_date) VALUES
+    (1, 1, '2024-03-15 09:30:00'),
+    (2, 2, '2024-03-16 18:00:00');
+INSERT INTO occasions (user_id, contact_id, name, date) VALUES (2, 2, 'Birthday', '1912-06-23');
+"#;
+
+async fn run(pool: &PgPool, sql: &str) {
+    sqlx::raw_sql(sql).execute(pool).await.unwrap();
+}
+
+/// (user_id, rows) of each user's default workspace, for `table`
+async fn rows_by_user(pool: &PgPool, table: &str) -> Vec<(i32, i64)> {
+    sqlx::query(&format!(
+        "SELECT w.user_id, COUNT(t.*) FROM workspaces w JOIN {} t USING (workspace_id)
+         WHERE w.is_default GROUP BY w.user_id ORDER BY w.user_id",
+        table
+    ))
+    .fetch_all(pool)
+    .await
+    .unwrap()
+    .iter()
+    .map(|row| (row.get(0), row.get(1)))
+    .collect()
+}
+
+/// Test that a database from before workspaces gets a default workspace per user holding their
+/// rows, and emails and tag names unique per workspace, and that running it again changes nothing
+#[actix_rt::test]
+async fn test_workspaces_migration() {
+    let ctx = setup_empty_test_db().await;
+    let pool = &ctx.pool;
+    run(pool, BEFORE_WORKSPACES).await;
+    for _ in 0..2 {
+        run(
+            pool,
+            include_str!("../migrations/postgres/0001_workspaces.sql"),
+        )
+        .await;
+    }
+
+    let workspaces: Vec<(i32, String)> =
+        sqlx::query_as("SELECT user_id, name FROM workspaces WHERE is_default ORDER BY user_id")
+            .fetch_all(pool)
+            .await
+            .unwrap();
+    assert_eq!(
+        workspaces,
+        vec![(1, "Personal".to_string()), (2, "Personal".to_string())]
+    );
+    assert_eq!(rows_by_user(pool, "contacts").await, vec![(1, 1), (2, 1)]);
+    assert_eq!(rows_by_user(pool, "tags").await, vec![(1, 1), (2, 1)]);
+    assert_eq!(
+        rows_by_user(pool, "interactions").await,
+        vec![(1, 1), (2, 1)]
+    );
+    assert_eq!(rows_by_user(pool, "occasions").await, vec![(2, 1)]);
+
+    // Grace can now have Ada's contact's email and tag name, but not twice
+    let grace: i32 = sqlx::query_scalar("SELECT workspace_id FROM workspaces WHERE user_id = 2")
+        .fetch_one(pool)
+        .await
+        .unwrap();
+    run(
+        pool,
+        &format!(
+            "INSERT INTO contacts (workspace_id, email) VALUES ({0}, 'charles@example.com');
+             INSERT INTO tags (workspace_id, name) VALUES ({0}, 'friends');",
+            grace
+        ),
+    )
+    .await;
+    for duplicate in [
+        "INSERT INTO contacts (workspace_id, email) VALUES ($1, 'charles@example.com')",
+        "INSERT INTO tags (workspace_id, name) VALUES ($1, 'friends')",
+    ] {
+        let e = sqlx::query(duplicate)
+            .bind(grace)
+            .execute(pool)
+            .await
+            .unwrap_err();
+        assert!(personal_crm::repository::is_unique_violation(&e));
+    }
+}
+
+/// Test that interaction dates stored without a time zone are read as UTC
+#[actix_rt::test]
+async fn test_utc_timestamps_migration() {
+    let ctx = setup_empty_test_db().await;
+    let pool = &ctx.pool;
+    run(pool, BEFORE_WORKSPACES).await;
+    // The session's zone mustn't matter
+    let migration = format!(
+        "SET TIME ZONE 'America/New_York'; {}",
+        include_str!("../migrations/postgres/0002_utc_timestamps.sql")
+    );
+    for _ in 0..2 {
+        run(pool, &migration).await;
+    }
+
+    let dates: Vec<OffsetDateTime> =
+        sqlx::query_scalar("SELECT interaction_date FROM interactions ORDER BY interaction_id")
+            .fetch_all(pool)
+            .await
+            .unwrap();
+    assert_eq!(
+        dates,
+        vec![
+            datetime!(2024-03-15 09:30:00 UTC),
+            datetime!(2024-03-16 18:00:00 UTC)
+        ]
+    );
+}
+
+/// Test that a database from before workspaces migrates to one the repository works with
+#[actix_rt::test]
+async fn test_migrate_before_workspaces() {
+    let ctx = setup_empty_test_db().await;
+    run(&ctx.pool, BEFORE_WORKSPACES).await;
+    migrate(&ctx.pool).await;
+
+    let repo = PgRepository::new(ctx.pool.clone());
+    let grace = repo
+        .get_or_create_user("auth0|grace", "grace@example.com", "Grace")
+        .await
+        .unwrap();
+    let contacts = repo.list_contacts(grace.workspace_id).await.unwrap();
+    assert_eq!(contacts.len(), 1);
+    assert_eq!(contacts[0].first_name.as_deref(), Some("Alan"));
+    let tags = repo.list_tags(grace.workspace_id).await.unwrap();
+    assert_eq!(tags[0].name, "work");
+
+    let last_interaction_at: Option<OffsetDateTime> =
+        sqlx::query_scalar("SELECT last_interaction_at FROM contacts WHERE contact_id = $1")
+            .bind(contacts[0].contact_id)
+            .fetch_one(&ctx.pool)
+            .await
+            .unwrap();
+    assert_eq!(
+        last_interaction_at,
+        Some(datetime!(2024-03-16 18:00:00 UTC))
+    );
+}
+
+/// Test that a database set up from the schema before there were migrations, which has no
+/// record of them, migrates without losing anything
+#[actix_rt::test]
+async fn test_migrate_unrecorded_schema() {
+    let ctx = setup_empty_test_db().await;
+    run(
+        &ctx.pool,
+        include_str!("../migrations/postgres/0003_schema.sql"),
+    )
+    .await;
+    let workspace_id = setup_test_workspace(&ctx.pool).await;
+    migrate(&ctx.pool).await;
+    // And once recorded, again
+    migrate(&ctx.pool).await;
+
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM workspaces WHERE workspace_id = $1")
+        .bind(workspace_id)
+        .fetch_one(&ctx.pool)
+        .await
+        .unwrap();
+    assert_eq!(count, 1);
+}
+
+/// Test that links made before only their token's hash was stored keep opening their contact
+#[actix_rt::test]
+async fn test_contact_link_token_hash_migration() {
+    let ctx = setup_empty_test_db().await;
+    run(
+        &ctx.pool,
+        include_str!("../migrations/postgres/0003_schema.sql"),
+    )
+    .await;
+    let workspace_id = setup_test_workspace(&ctx.pool).await;
+    let contact_id: i32 = sqlx::query_scalar(
+        "INSERT INTO contacts (workspace_id, first_name) VALUES ($1, 'Ada') RETURNING contact_id",
+    )
+    .bind(workspace_id)
+    .fetch_one(&ctx.pool)
+    .await
+    .unwrap();
+    sqlx::query(
+        "INSERT INTO contact_links (workspace_id, contact_id, token, expires_at)
+         VALUES ($1, $2, 'handed-out-token', NOW() + INTERVAL '1 day')",
+    )
+    .bind(workspace_id)
+    .bind(contact_id)
+    .execute(&ctx.pool)
+    .await
+    .unwrap();
+    run(
+        &ctx.pool,
+        include_str!("../migrations/postgres/0004_contact_link_token_hash.sql"),
+    )
+    .await;
+
+    let repo = PgRepository::new(ctx.pool.clone());
+    let now = OffsetDateTime::now_utc();
+    assert_eq!(
+        repo.contact_link_target(&token_hash("handed-out-token"), now)
+            .await
+            .unwrap(),
+        Some((workspace_id, contact_id))
+    );
+    assert_eq!(
+        repo.contact_link_target("handed-out-token", now)
+            .await
+            .unwrap(),
+        None
+    );
+}
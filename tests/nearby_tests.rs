@@ -0,0 +1,327 @@
+mod common;
+
+use actix_web::{App, test as actix_test, web};
+use async_trait::async_trait;
+use common::*;
+use personal_crm::geocoding::{BoundingBox, Geocoder, Geocoding, distance_km, parse_nominatim};
+use personal_crm::models::{Coordinates, NewContactRequest};
+use personal_crm::repository::{
+    self, InMemoryRepository, PgRepository, Repository, SqliteRepository,
+};
+use personal_crm::routes::v1_routes;
+use personal_crm::test_support::provision;
+use serde_json::{Value, json};
+
+const LISBON: Coordinates = Coordinates {
+    latitude: 38.7223,
+    longitude: -9.1393,
+};
+const SINTRA: Coordinates = Coordinates {
+    latitude: 38.8029,
+    longitude: -9.3817,
+};
+const PORTO: Coordinates = Coordinates {
+    latitude: 41.1579,
+    longitude: -8.6291,
+};
+
+/// Knows a few places and nothing else
+struct FakeGeocoder;
+
+#[async_trait]
+impl Geocoder for FakeGeocoder {
+    async fn geocode(&self, location: &str) -> Option<Coordinates> {
+        match location {
+            "Lisbon" => Some(LISBON),
+            "Sintra" => Some(SINTRA),
+            "Porto" => Some(PORTO),
+            _ => None,
+        }
+    }
+}
+
+fn located(name: &str, location: &str) -> NewContactRequest {
+    NewContactRequest {
+        location: Some(location.to_string()),
+        ..contact(name)
+    }
+}
+
+#[test]
+fn test_distance_km() {
+    assert_eq!(distance_km(LISBON, LISBON), 0.0);
+    let lisbon_porto = distance_km(LISBON, PORTO);
+    assert!((270.0..280.0).contains(&lisbon_porto), "{}", lisbon_porto);
+    assert_eq!(lisbon_porto, distance_km(PORTO, LISBON));
+}
+
+#[test]
+fn test_bounding_box() {
+    let around = BoundingBox::around(LISBON, 50.0);
+    assert!(around.contains(SINTRA));
+    assert!(!around.contains(PORTO));
+
+    // Fiji straddles the antimeridian
+    let fiji = Coordinates {
+        latitude: -17.7,
+        longitude: 179.9,
+    };
+    let around = BoundingBox::around(fiji, 100.0);
+    assert!(around.min_longitude > around.max_longitude);
+    assert!(around.contains(Coordinates {
+        latitude: -17.7,
+        longitude: -179.8,
+    }));
+    assert!(!around.contains(Coordinates {
+        latitude: -17.7,
+        longitude: 0.0,
+    }));
+
+    // Near a pole every longitude is close
+    let around = BoundingBox::around(
+        Coordinates {
+            latitude: 89.9,
+            longitude: 0.0,
+        },
+        50.0,
+    );
+    assert_eq!(
+        (around.min_longitude, around.max_longitude),
+        (-180.0, 180.0)
+    );
+    assert_eq!(around.max_latitude, 90.0);
+}
+
+#[test]
+fn test_parse_nominatim() {
+    let body = json!([{ "lat": "38.7077507", "lon": "-9.1365919", "display_name": "Lisboa" }]);
+    assert_eq!(
+        parse_nominatim(&body),
+        Some(Coordinates {
+            latitude: 38.7077507,
+            longitude: -9.1365919,
+        })
+    );
+    assert_eq!(parse_nominatim(&json!([])), None);
+    assert_eq!(parse_nominatim(&json!({ "error": "down" })), None);
+}
+
+/// Check that coordinates are stored only for a contact's current location, cleared when the
+/// location changes, and found by box for unarchived contacts in the workspace
+async fn check_contact_coordinates(repo: web::Data<dyn Repository>) {
+    let owner = provision(repo.get_ref(), "nearby").await.unwrap();
+    let workspace_id = owner.workspace_id;
+    let ana = repo
+        .create_contact(workspace_id, &located("Ana", "Lisbon"))
+        .await
+        .unwrap();
+    let bulk = repo
+        .create_contacts(
+            workspace_id,
+            &[located("Rui", "Sintra"), located("Eva", "Porto")],
+            true,
+        )
+        .await
+        .unwrap();
+    let rui = *bulk[0].as_ref().unwrap();
+    let eva = *bulk[1].as_ref().unwrap();
+    assert_eq!(
+        repo.get_contact(workspace_id, rui)
+            .await
+            .unwrap()
+            .unwrap()
+            .location
+            .as_deref(),
+        Some("Sintra")
+    );
+
+    for (contact_id, location, coordinates) in [
+        (ana, "Lisbon", LISBON),
+        (rui, "Sintra", SINTRA),
+        (eva, "Porto", PORTO),
+    ] {
+        assert!(
+            repo.set_contact_coordinates(workspace_id, contact_id, location, coordinates)
+                .await
+                .unwrap()
+        );
+    }
+    // Another workspace, or a location the contact has moved on from
+    assert!(
+        !repo
+            .set_contact_coordinates(owner.spare_workspace_id, ana, "Lisbon", LISBON)
+            .await
+            .unwrap()
+    );
+    assert!(
+        !repo
+            .set_contact_coordinates(workspace_id, ana, "Porto", PORTO)
+            .await
+            .unwrap()
+    );
+    let stored = repo.get_contact(workspace_id, ana).await.unwrap().unwrap();
+    assert_eq!(
+        (stored.latitude, stored.longitude),
+        (Some(LISBON.latitude), Some(LISBON.longitude))
+    );
+
+    let ids = |contacts: Vec<personal_crm::models::Contact>| -> Vec<i32> {
+        contacts.iter().map(|c| c.contact_id).collect()
+    };
+    let around_lisbon = BoundingBox::around(LISBON, 50.0);
+    assert_eq!(
+        ids(repo
+            .contacts_in_box(workspace_id, &around_lisbon)
+            .await
+            .unwrap()),
+        vec![ana, rui]
+    );
+    assert!(
+        repo.contacts_in_box(owner.spare_workspace_id, &around_lisbon)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+
+    // Updating other fields keeps the coordinates; a new location drops them
+    assert!(
+        repo.update_contact(workspace_id, ana, &located("Ana Sofia", "Lisbon"))
+            .await
+            .unwrap()
+    );
+    assert!(
+        repo.update_contact(workspace_id, rui, &located("Rui", "Porto"))
+            .await
+            .unwrap()
+    );
+    let stored = repo.get_contact(workspace_id, rui).await.unwrap().unwrap();
+    assert_eq!((stored.latitude, stored.longitude), (None, None));
+    assert!(
+        repo.set_contact_archived(workspace_id, eva, true)
+            .await
+            .unwrap()
+    );
+    assert_eq!(
+        ids(repo
+            .contacts_in_box(workspace_id, &around_lisbon)
+            .await
+            .unwrap()),
+        vec![ana]
+    );
+    assert!(
+        repo.contacts_in_box(workspace_id, &BoundingBox::around(PORTO, 50.0))
+            .await
+            .unwrap()
+            .is_empty()
+    );
+}
+
+#[actix_rt::test]
+async fn test_contact_coordinates_in_memory() {
+    check_contact_coordinates(repository::app_data(InMemoryRepository::new())).await;
+}
+
+#[actix_rt::test]
+async fn test_contact_coordinates_in_postgres() {
+    let ctx = setup_test_db().await;
+    check_contact_coordinates(repository::app_data(PgRepository::new(ctx.pool.clone()))).await;
+}
+
+#[actix_rt::test]
+async fn test_contact_coordinates_in_sqlite() {
+    let repo = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+    check_contact_coordinates(repository::app_data(repo)).await;
+}
+
+/// Test geocoding locations as contacts are saved and finding them near a point
+#[actix_rt::test]
+async fn test_nearby_api() {
+    let repo = repository::app_data(InMemoryRepository::new());
+    let owner = provision(repo.get_ref(), "nearby-api").await.unwrap();
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(repo.clone())
+            .app_data(web::Data::new(Geocoding::new(Box::new(FakeGeocoder))))
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let auth = ("Authorization", format!("Bearer {}", owner.token));
+    let nearby = |query: &str| {
+        actix_test::TestRequest::get()
+            .uri(&format!("/v1/contacts/nearby?{}", query))
+            .insert_header(auth.clone())
+            .to_request()
+    };
+
+    let req = actix_test::TestRequest::post()
+        .uri("/v1/contacts")
+        .insert_header(auth.clone())
+        .set_json(json!({ "first_name": "Ana", "location": "Lisbon" }))
+        .to_request();
+    let created: Value =
+        actix_test::read_body_json(actix_test::call_service(&app, req).await).await;
+    let ana = created["contact_id"].as_i64().unwrap();
+    let req = actix_test::TestRequest::post()
+        .uri("/v1/contacts/bulk")
+        .insert_header(auth.clone())
+        .set_json(json!([
+            { "first_name": "Rui", "location": "Sintra" },
+            { "first_name": "Eva", "location": "Porto" },
+            // Not found, so kept without coordinates
+            { "first_name": "Kit", "location": "Atlantis" }
+        ]))
+        .to_request();
+    let created: Value =
+        actix_test::read_body_json(actix_test::call_service(&app, req).await).await;
+    let rui = created["created_contact_ids"][0].as_i64().unwrap();
+
+    let found: Vec<Value> = actix_test::read_body_json(
+        actix_test::call_service(&app, nearby("lat=38.7223&lng=-9.1393&radius_km=50")).await,
+    )
+    .await;
+    assert_eq!(
+        found
+            .iter()
+            .map(|c| c["contact_id"].as_i64().unwrap())
+            .collect::<Vec<_>>(),
+        vec![ana, rui]
+    );
+    assert_eq!(found[0]["distance_km"], 0.0);
+    assert_eq!(found[1]["location"], "Sintra");
+    assert!(found[1]["distance_km"].as_f64().unwrap() > 20.0);
+
+    // Sintra is some 23 km out: inside the default 25 km, not within 10
+    for (query, count) in [
+        ("lat=38.7223&lng=-9.1393", 2),
+        ("lat=38.7223&lng=-9.1393&radius_km=10", 1),
+    ] {
+        let found: Vec<Value> =
+            actix_test::read_body_json(actix_test::call_service(&app, nearby(query)).await).await;
+        assert_eq!(found.len(), count, "{}", query);
+    }
+
+    // Moving Ana to Porto geocodes her there
+    let req = actix_test::TestRequest::patch()
+        .uri(&format!("/v1/contacts/{}", ana))
+        .insert_header(auth.clone())
+        .set_json(json!({ "first_name": "Ana", "location": "Porto" }))
+        .to_request();
+    assert_eq!(actix_test::call_service(&app, req).await.status(), 200);
+    let found: Vec<Value> = actix_test::read_body_json(
+        actix_test::call_service(&app, nearby("lat=41.1579&lng=-8.6291&radius_km=10")).await,
+    )
+    .await;
+    assert_eq!(found.len(), 2);
+    assert_eq!(found[0]["contact_id"], ana);
+
+    for query in [
+        "lat=91&lng=0",
+        "lat=0&lng=-181",
+        "lat=0&lng=0&radius_km=0",
+        "lat=0&lng=0&radius_km=501",
+        "lng=0",
+    ] {
+        let res = actix_test::call_service(&app, nearby(query)).await;
+        assert_eq!(res.status(), 400, "{} was accepted", query);
+    }
+}
@@ -0,0 +1,462 @@
+mod common;
+
+use actix_web::middleware::from_fn;
+use actix_web::{App, HttpRequest, HttpResponse, HttpServer, test as actix_test, web};
+use common::*;
+use personal_crm::models::{
+    DeliveryAttempt, DeliveryStatus, HookEvent, NewContactRequest, NewRestHookRequest,
+    OutboxPayload,
+};
+use personal_crm::outbox::{FIRST_RETRY, MAX_ATTEMPTS, MAX_RETRY, Outbox, retry_delay};
+use personal_crm::repository::{
+    self, InMemoryRepository, PgRepository, Repository, SqliteRepository,
+};
+use personal_crm::routes::v1_routes;
+use personal_crm::test_support::provision;
+use personal_crm::versioning::api_version;
+use serde_json::Value;
+use std::net::TcpListener;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use time::{Duration, OffsetDateTime};
+
+fn failed(at: OffsetDateTime, retry_at: Option<OffsetDateTime>) -> DeliveryAttempt {
+    DeliveryAttempt {
+        status: if retry_at.is_some() {
+            DeliveryStatus::Pending
+        } else {
+            DeliveryStatus::Failed
+        },
+        response_status: Some(503),
+        error: Some("Target answered 503".to_string()),
+        at,
+        retry_at,
+    }
+}
+
+#[test]
+fn test_retry_delay() {
+    assert_eq!(retry_delay(1), FIRST_RETRY);
+    assert_eq!(retry_delay(2), FIRST_RETRY * 2);
+    assert_eq!(retry_delay(4), FIRST_RETRY * 8);
+    assert_eq!(retry_delay(MAX_ATTEMPTS * 10), MAX_RETRY);
+}
+
+/// Check that new contacts are queued for their workspace's hooks with the change, that claimed
+/// deliveries wait out their claim or retry, and that attempts are recorded
+async fn check_outbox(repo: web::Data<dyn Repository>) {
+    let owner = provision(repo.get_ref(), "outbox-owner").await.unwrap();
+    let other = provision(repo.get_ref(), "outbox-other").await.unwrap();
+    let (workspace_id, hook_id) = (owner.workspace_id, owner.rest_hook_id);
+    let soon = OffsetDateTime::now_utc() + Duration::minutes(1);
+
+    // Provisioning created its contact before its hook, so nothing is owed yet
+    assert!(
+        repo.claim_deliveries(soon, soon, 10)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+
+    let ada = repo
+        .create_contact(workspace_id, &contact("Ada"))
+        .await
+        .unwrap();
+    // Contacts in a workspace without hooks owe nothing
+    repo.create_contact(owner.spare_workspace_id, &contact("Bo"))
+        .await
+        .unwrap();
+    // A batch that rolls back queues nothing
+    let taken = format!("taken-{}@example.com", owner.marker);
+    repo.create_contact(
+        workspace_id,
+        &NewContactRequest {
+            email: Some(taken.clone()),
+            ..contact("Cy")
+        },
+    )
+    .await
+    .unwrap();
+    assert!(
+        repo.create_contacts(
+            workspace_id,
+            &[
+                contact("Di"),
+                NewContactRequest {
+                    email: Some(taken.clone()),
+                    ..contact("Ed")
+                }
+            ],
+            true
+        )
+        .await
+        .is_err()
+    );
+
+    let now = OffsetDateTime::now_utc();
+    let later = now + Duration::hours(1);
+    let claim_until = now + Duration::minutes(30);
+    let claimed = repo.claim_deliveries(now, claim_until, 1).await.unwrap();
+    assert_eq!(claimed.len(), 1);
+    let message = &claimed[0];
+    assert_eq!(message.workspace_id, workspace_id);
+    assert_eq!(message.attempts, 0);
+    let (claimed_hook, event, target_url) = message.hook.clone().unwrap();
+    assert_eq!(claimed_hook, hook_id);
+    assert_eq!(event, HookEvent::NewContact);
+    assert!(target_url.contains(&owner.marker));
+    assert!(matches!(&message.payload, OutboxPayload::Entities(ids) if ids == &vec![ada]));
+    let first = message.delivery_id;
+
+    // The rest, up to the limit, then nothing while the claims last
+    let claimed = repo.claim_deliveries(now, claim_until, 10).await.unwrap();
+    assert_eq!(claimed.len(), 1);
+    let second = claimed[0].delivery_id;
+    assert!(
+        repo.claim_deliveries(now + Duration::minutes(29), claim_until, 10)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+
+    // A failure is tried again when it is due
+    let retry_at = now + Duration::minutes(1);
+    repo.record_delivery_attempt(first, &failed(now, Some(retry_at)))
+        .await
+        .unwrap();
+    repo.record_delivery_attempt(
+        second,
+        &DeliveryAttempt {
+            status: DeliveryStatus::Delivered,
+            response_status: None,
+            error: None,
+            at: now,
+            retry_at: None,
+        },
+    )
+    .await
+    .unwrap();
+    let deliveries = repo
+        .hook_deliveries(workspace_id, hook_id, 10)
+        .await
+        .unwrap();
+    assert_eq!(
+        deliveries.iter().map(|d| d.delivery_id).collect::<Vec<_>>(),
+        vec![second, first]
+    );
+    assert_eq!(deliveries[0].status, DeliveryStatus::Delivered);
+    assert!(deliveries[0].finished_at.is_some());
+    assert_eq!(deliveries[1].status, DeliveryStatus::Pending);
+    assert_eq!(deliveries[1].attempts, 1);
+    assert_eq!(deliveries[1].last_response_status, Some(503));
+    assert_eq!(
+        deliveries[1].last_error.as_deref(),
+        Some("Target answered 503")
+    );
+    assert_eq!(deliveries[1].finished_at, None);
+    assert!(
+        repo.hook_deliveries(other.workspace_id, hook_id, 10)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+
+    let claimed = repo.claim_deliveries(retry_at, later, 10).await.unwrap();
+    assert_eq!(claimed.len(), 1);
+    assert_eq!(claimed[0].delivery_id, first);
+    assert_eq!(claimed[0].attempts, 1);
+    repo.record_delivery_attempt(first, &failed(retry_at, None))
+        .await
+        .unwrap();
+    let deliveries = repo
+        .hook_deliveries(workspace_id, hook_id, 1)
+        .await
+        .unwrap();
+    assert_eq!(deliveries.len(), 1);
+    assert_eq!(deliveries[0].delivery_id, second);
+
+    // Finished deliveries are dropped once old enough
+    assert_eq!(repo.purge_deliveries(now).await.unwrap(), 0);
+    assert_eq!(
+        repo.purge_deliveries(later + Duration::days(1))
+            .await
+            .unwrap(),
+        2
+    );
+
+    // Unsubscribing drops what the hook is still owed
+    repo.create_contact(workspace_id, &contact("Fay"))
+        .await
+        .unwrap();
+    assert!(repo.delete_rest_hook(workspace_id, hook_id).await.unwrap());
+    assert!(
+        repo.claim_deliveries(later, later, 10)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+}
+
+#[actix_rt::test]
+async fn test_outbox_in_memory() {
+    check_outbox(repository::app_data(InMemoryRepository::new())).await;
+}
+
+#[actix_rt::test]
+async fn test_outbox_in_postgres() {
+    let ctx = setup_test_db().await;
+    check_outbox(repository::app_data(PgRepository::new(ctx.pool.clone()))).await;
+}
+
+#[actix_rt::test]
+async fn test_outbox_in_sqlite() {
+    let repo = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+    check_outbox(repository::app_data(repo)).await;
+}
+
+/// Test that senders claiming at the same time never get the same delivery
+#[actix_rt::test]
+async fn test_claims_do_not_overlap() {
+    let ctx = setup_test_db().await;
+    let repo = PgRepository::new(ctx.pool.clone());
+    let owner = provision(&repo, "outbox-claims").await.unwrap();
+    let contacts: Vec<NewContactRequest> = (0..20)
+        .map(|n| contact(&format!("Contact {}", n)))
+        .collect();
+    repo.create_contacts(owner.workspace_id, &contacts, true)
+        .await
+        .unwrap();
+    for contact in &contacts[..5] {
+        repo.create_contact(owner.workspace_id, contact)
+            .await
+            .unwrap();
+    }
+
+    let now = OffsetDateTime::now_utc();
+    let until = now + Duration::minutes(30);
+    let (first, second) = tokio::join!(
+        repo.claim_deliveries(now, until, 4),
+        repo.claim_deliveries(now, until, 4)
+    );
+    let mut ids: Vec<i32> = first
+        .unwrap()
+        .into_iter()
+        .chain(second.unwrap())
+        .map(|m| m.delivery_id)
+        .collect();
+    ids.sort_unstable();
+    ids.dedup();
+    assert_eq!(ids.len(), 6);
+}
+
+/// A hook target answering 503 to its first `failures` requests, then 200
+struct Target {
+    failures: usize,
+    requests: AtomicUsize,
+    /// (path, body) of every request it got
+    received: Mutex<Vec<(String, Value)>>,
+}
+
+async fn flaky(req: HttpRequest, body: web::Bytes, target: web::Data<Target>) -> HttpResponse {
+    target.received.lock().unwrap().push((
+        req.path().to_string(),
+        serde_json::from_slice(&body).unwrap_or_default(),
+    ));
+    if target.requests.fetch_add(1, Ordering::SeqCst) < target.failures {
+        HttpResponse::ServiceUnavailable().finish()
+    } else {
+        HttpResponse::Ok().finish()
+    }
+}
+
+/// Start a target, returning its base URL
+fn start_target(failures: usize) -> (String, web::Data<Target>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    let target = web::Data::new(Target {
+        failures,
+        requests: AtomicUsize::new(0),
+        received: Mutex::new(Vec::new()),
+    });
+    let data = target.clone();
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(data.clone())
+            .default_service(web::to(flaky))
+    })
+    .workers(1)
+    .listen(listener)
+    .unwrap()
+    .run();
+    actix_rt::spawn(server);
+    (base, target)
+}
+
+/// Test that a target whose name resolves to the server's own network is not connected to
+#[actix_rt::test]
+async fn test_local_target_refused() {
+    let repo = repository::app_data(InMemoryRepository::new());
+    let owner = provision(repo.get_ref(), "outbox-local").await.unwrap();
+    let workspace_id = owner.workspace_id;
+    repo.delete_rest_hook(workspace_id, owner.rest_hook_id)
+        .await
+        .unwrap();
+    let (base, target) = start_target(0);
+    let local = base.replace("127.0.0.1", "localhost");
+    let hook_id = repo
+        .create_rest_hook(
+            workspace_id,
+            &NewRestHookRequest {
+                event: HookEvent::NewContact,
+                target_url: format!("{}/hook", local),
+            },
+        )
+        .await
+        .unwrap();
+    repo.create_contact(workspace_id, &contact("Ada"))
+        .await
+        .unwrap();
+
+    let outbox = Outbox::default();
+    assert_eq!(
+        outbox
+            .drain(repo.get_ref(), OffsetDateTime::now_utc())
+            .await
+            .unwrap(),
+        1
+    );
+    assert_eq!(target.requests.load(Ordering::SeqCst), 0);
+    let delivery = repo
+        .hook_deliveries(workspace_id, hook_id, 10)
+        .await
+        .unwrap()
+        .remove(0);
+    assert_eq!(delivery.status, DeliveryStatus::Pending);
+    assert_eq!(delivery.last_response_status, None);
+    assert!(delivery.last_error.is_some());
+}
+
+/// Test that a failing target is retried with growing waits until it answers, or given up on
+/// after `MAX_ATTEMPTS`, and that the deliveries can be inspected through the API
+#[actix_rt::test]
+async fn test_retries_until_delivered_or_failed() {
+    let repo = repository::app_data(InMemoryRepository::new());
+    let owner = provision(repo.get_ref(), "outbox-retries").await.unwrap();
+    let other = provision(repo.get_ref(), "outbox-retries-other")
+        .await
+        .unwrap();
+    let workspace_id = owner.workspace_id;
+    repo.delete_rest_hook(workspace_id, owner.rest_hook_id)
+        .await
+        .unwrap();
+    let (recovering, recovering_target) = start_target(2);
+    let (broken, _) = start_target(usize::MAX);
+    let subscribe = |url: String| NewRestHookRequest {
+        event: HookEvent::NewContact,
+        target_url: url,
+    };
+    let recovering_hook = repo
+        .create_rest_hook(workspace_id, &subscribe(format!("{}/hook", recovering)))
+        .await
+        .unwrap();
+    let broken_hook = repo
+        .create_rest_hook(workspace_id, &subscribe(format!("{}/hook", broken)))
+        .await
+        .unwrap();
+
+    let ada = repo
+        .create_contact(workspace_id, &contact("Ada"))
+        .await
+        .unwrap();
+    let outbox = Outbox::default();
+    let start = OffsetDateTime::now_utc();
+    assert_eq!(outbox.drain(repo.get_ref(), start).await.unwrap(), 2);
+    // Nothing is due again before the first retry
+    assert_eq!(outbox.drain(repo.get_ref(), start).await.unwrap(), 0);
+
+    let status = |hook_id: i32| {
+        let repo = repo.clone();
+        async move {
+            repo.hook_deliveries(workspace_id, hook_id, 10)
+                .await
+                .unwrap()
+                .remove(0)
+        }
+    };
+    let pending = status(recovering_hook).await;
+    assert_eq!(pending.status, DeliveryStatus::Pending);
+    assert_eq!(pending.last_response_status, Some(503));
+    let wait = pending.next_attempt_at - pending.created_at;
+    assert!(wait >= FIRST_RETRY && wait < FIRST_RETRY * 2);
+
+    // The waits double
+    let retry = pending.next_attempt_at + Duration::seconds(5);
+    assert_eq!(outbox.drain(repo.get_ref(), retry).await.unwrap(), 2);
+    let pending = status(recovering_hook).await;
+    assert_eq!(pending.attempts, 2);
+    assert!(pending.next_attempt_at - pending.created_at >= FIRST_RETRY * 2);
+
+    assert_eq!(
+        outbox
+            .drain(
+                repo.get_ref(),
+                pending.next_attempt_at + Duration::seconds(5)
+            )
+            .await
+            .unwrap(),
+        2
+    );
+    let delivered = status(recovering_hook).await;
+    assert_eq!(delivered.status, DeliveryStatus::Delivered);
+    assert_eq!(delivered.attempts, 3);
+    assert!(delivered.finished_at.is_some());
+    let got = recovering_target.received.lock().unwrap().clone();
+    assert_eq!(got.len(), 3);
+    assert_eq!(got[2].1[0]["id"], ada.to_string());
+
+    // The broken target is given up on
+    let mut at = start;
+    for _ in 3..MAX_ATTEMPTS {
+        at += MAX_RETRY;
+        assert_eq!(outbox.drain(repo.get_ref(), at).await.unwrap(), 1);
+    }
+    let given_up = status(broken_hook).await;
+    assert_eq!(given_up.status, DeliveryStatus::Failed);
+    assert_eq!(given_up.attempts, MAX_ATTEMPTS);
+    assert_eq!(given_up.last_response_status, Some(503));
+    assert_eq!(
+        outbox.drain(repo.get_ref(), at + MAX_RETRY).await.unwrap(),
+        0
+    );
+
+    let app = actix_test::init_service(
+        App::new()
+            .wrap(from_fn(api_version))
+            .app_data(repo.clone())
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    // The documented, unversioned path
+    let get = |token: &str, hook_id: i32| {
+        actix_test::TestRequest::get()
+            .uri(&format!("/webhooks/{}/deliveries", hook_id))
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request()
+    };
+    let body: Value =
+        actix_test::call_and_read_body_json(&app, get(&owner.token, broken_hook)).await;
+    assert_eq!(body.as_array().unwrap().len(), 1);
+    assert_eq!(body[0]["status"], "failed");
+    assert_eq!(body[0]["attempts"], MAX_ATTEMPTS);
+    assert_eq!(body[0]["last_response_status"], 503);
+    assert!(body[0]["last_error"].as_str().unwrap().contains("503"));
+    let req = actix_test::TestRequest::get()
+        .uri(&format!("/v1/webhooks/{}/deliveries", broken_hook))
+        .insert_header(("Authorization", format!("Bearer {}", owner.token)))
+        .to_request();
+    let versioned: Value = actix_test::call_and_read_body_json(&app, req).await;
+    assert_eq!(versioned, body);
+
+    let response = actix_test::call_service(&app, get(&other.token, broken_hook)).await;
+    assert_eq!(response.status(), 404);
+}
@@ -0,0 +1,120 @@
+mod common;
+
+use actix_web::{App, test as actix_test, web};
+use common::*;
+use personal_crm::models::NewContactRequest;
+use personal_crm::phone::{normalize_in, parse_region};
+use personal_crm::repository::{self, InMemoryRepository};
+use personal_crm::routes::v1_routes;
+use personal_crm::test_support::provision;
+use phonenumber::country::Id;
+use serde_json::Value;
+
+/// Test that differently written numbers normalize to the same E.164 form
+#[test]
+fn test_normalize() {
+    for raw in [
+        "(415) 555-0132",
+        "415.555.0132",
+        "+1 415 555 0132",
+        "1-415-555-0132",
+        "tel:+1-415-555-0132",
+    ] {
+        assert_eq!(
+            normalize_in(raw, Id::US).as_deref(),
+            Some("+14155550132"),
+            "{}",
+            raw
+        );
+    }
+
+    // National numbers are read in the given region; international ones keep their own
+    assert_eq!(
+        normalize_in("020 7946 0958", Id::GB).as_deref(),
+        Some("+442079460958")
+    );
+    assert_eq!(
+        normalize_in("+44 20 7946 0958", Id::US).as_deref(),
+        Some("+442079460958")
+    );
+
+    assert_eq!(normalize_in("555-0100", Id::US), None);
+    assert_eq!(normalize_in("call me", Id::US), None);
+    assert_eq!(normalize_in("", Id::US), None);
+
+    assert_eq!(parse_region(" gb"), Some(Id::GB));
+    assert_eq!(parse_region("Atlantis"), None);
+}
+
+/// Test finding contacts by a number written differently from the stored one
+#[actix_rt::test]
+async fn test_lookup_contacts() {
+    let repo = repository::app_data(InMemoryRepository::new());
+    let owner = provision(repo.get_ref(), "phone").await.unwrap();
+    let other = provision(repo.get_ref(), "phone-other").await.unwrap();
+    let unparsed_id = repo
+        .create_contact(
+            owner.workspace_id,
+            &NewContactRequest {
+                phone: Some("ext. 12".to_string()),
+                ..contact("Extension")
+            },
+        )
+        .await
+        .unwrap();
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(repo.clone())
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let lookup = |query: &str| {
+        actix_test::TestRequest::get()
+            .uri(&format!("/v1/contacts/lookup?{}", query))
+            .insert_header(("Authorization", format!("Bearer {}", owner.token)))
+            .to_request()
+    };
+
+    // Only the caller's own contact, though the other user's has the same number
+    let res = actix_test::call_service(&app, lookup("phone=415-555-0132")).await;
+    assert_eq!(res.status(), 200);
+    let found: Vec<Value> = actix_test::read_body_json(res).await;
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0]["contact_id"], owner.contact_id);
+    assert_eq!(found[0]["phone"], "(415) 555-0132");
+    assert_eq!(found[0]["phone_e164"], "+14155550132");
+    assert_ne!(found[0]["contact_id"], other.contact_id);
+
+    let found: Vec<Value> = actix_test::read_body_json(
+        actix_test::call_service(&app, lookup("phone=%2B1%20212%20555%200100")).await,
+    )
+    .await;
+    assert!(found.is_empty());
+
+    // Numbers that don't parse are kept as typed, without an E.164 form
+    let contact = repo
+        .get_contact(owner.workspace_id, unparsed_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(contact.phone.as_deref(), Some("ext. 12"));
+    assert_eq!(contact.phone_e164, None);
+
+    let res = actix_test::call_service(&app, lookup("phone=ext.%2012")).await;
+    assert_eq!(res.status(), 400);
+    let res = actix_test::call_service(&app, lookup("")).await;
+    assert_eq!(res.status(), 400);
+
+    // Updates renormalize
+    let req = actix_test::TestRequest::patch()
+        .uri(&format!("/v1/contacts/{}", unparsed_id))
+        .insert_header(("Authorization", format!("Bearer {}", owner.token)))
+        .set_json(serde_json::json!({ "first_name": "Extension", "phone": "+1 (415) 555 0132" }))
+        .to_request();
+    assert_eq!(actix_test::call_service(&app, req).await.status(), 200);
+    let found: Vec<Value> = actix_test::read_body_json(
+        actix_test::call_service(&app, lookup("phone=4155550132")).await,
+    )
+    .await;
+    assert_eq!(found.len(), 2);
+}
@@ -0,0 +1,91 @@
+use actix_web::http::StatusCode;
+use actix_web::{App, test};
+use async_trait::async_trait;
+use personal_crm::probes::{self, Databases, health_live, health_ready};
+use serde_json::Value;
+
+/// Databases whose pings give fixed answers
+struct Stub {
+    primary: Result<(), String>,
+    replica: Option<Result<(), String>>,
+}
+
+#[async_trait]
+impl Databases for Stub {
+    async fn ping(&self) -> Result<(), String> {
+        self.primary.clone()
+    }
+
+    async fn ping_replica(&self) -> Option<Result<(), String>> {
+        self.replica.clone()
+    }
+}
+
+async fn ready(databases: Stub) -> (StatusCode, Value) {
+    let app = test::init_service(
+        App::new()
+            .app_data(probes::app_data(databases))
+            .service(health_ready),
+    )
+    .await;
+    let req = test::TestRequest::get().uri("/health/ready").to_request();
+    let res = test::call_service(&app, req).await;
+    let status = res.status();
+    (status, test::read_body_json(res).await)
+}
+
+/// Test that the liveness probe answers without checking anything
+#[actix_rt::test]
+async fn test_live() {
+    let app = test::init_service(App::new().service(health_live)).await;
+    let req = test::TestRequest::get().uri("/health/live").to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let body: Value = test::read_body_json(res).await;
+    assert_eq!(body["status"], "alive");
+}
+
+/// Test that a reachable database reports up and leaves the replica out when there is none
+#[actix_rt::test]
+async fn test_ready_database_up() {
+    let (status, body) = ready(Stub {
+        primary: Ok(()),
+        replica: None,
+    })
+    .await;
+    // Auth0 may be out of reach from the test machine, which only degrades readiness
+    assert_eq!(status, StatusCode::OK);
+    assert_ne!(body["status"], "unavailable");
+    assert_eq!(body["checks"]["database"]["status"], "up");
+    assert!(body["checks"]["database"].get("error").is_none());
+    assert!(body["checks"].get("database_replica").is_none());
+}
+
+/// Test that a failed database makes the service unavailable and reports why
+#[actix_rt::test]
+async fn test_ready_database_down() {
+    let (status, body) = ready(Stub {
+        primary: Err("connection refused".to_string()),
+        replica: None,
+    })
+    .await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body["status"], "unavailable");
+    assert_eq!(body["checks"]["database"]["status"], "down");
+    assert_eq!(body["checks"]["database"]["error"], "connection refused");
+}
+
+/// Test that a failed read replica makes the service unavailable too
+#[actix_rt::test]
+async fn test_ready_replica_down() {
+    let (status, body) = ready(Stub {
+        primary: Ok(()),
+        replica: Some(Err("replica gone".to_string())),
+    })
+    .await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body["status"], "unavailable");
+    assert_eq!(body["checks"]["database"]["status"], "up");
+    assert_eq!(body["checks"]["database_replica"]["status"], "down");
+    assert_eq!(body["checks"]["database_replica"]["error"], "replica gone");
+}
@@ -0,0 +1,352 @@
+mod common;
+
+use actix_web::{App, test as actix_test, web};
+use aes_gcm::Aes128Gcm;
+use aes_gcm::aead::{Aead, KeyInit};
+use async_trait::async_trait;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use common::*;
+use hkdf::Hkdf;
+use p256::ecdsa::signature::Verifier;
+use p256::ecdsa::{Signature, VerifyingKey};
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use p256::{PublicKey, SecretKey};
+use personal_crm::models::PushSubscription;
+use personal_crm::outbox::Outbox;
+use personal_crm::push::{PushError, PushMessage, PushReminders, Pusher, WebPush, encrypt};
+use personal_crm::repository::{
+    self, InMemoryRepository, PgRepository, Repository, SqliteRepository,
+};
+use personal_crm::routes::v1_routes;
+use personal_crm::test_support::{
+    PUSH_SUBSCRIBER_AUTH, PUSH_SUBSCRIBER_KEY, provision, push_subscription,
+};
+use serde_json::{Value, json};
+use sha2::Sha256;
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use time::OffsetDateTime;
+
+/// A VAPID key for tests, base64url
+const VAPID_KEY: &str = "AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE";
+
+/// Decrypt an `aes128gcm` push body with the subscriber's private key, as a browser would
+fn decrypt(body: &[u8]) -> Vec<u8> {
+    let (salt, rest) = body.split_at(16);
+    let (record_size, rest) = rest.split_at(4);
+    assert_eq!(u32::from_be_bytes(record_size.try_into().unwrap()), 4096);
+    let key_len = rest[0] as usize;
+    let (as_public, ciphertext) = rest[1..].split_at(key_len);
+
+    let ua_secret = SecretKey::from_slice(&PUSH_SUBSCRIBER_KEY).unwrap();
+    let ua_public = ua_secret.public_key().to_encoded_point(false);
+    let shared = p256::ecdh::diffie_hellman(
+        ua_secret.to_nonzero_scalar(),
+        PublicKey::from_sec1_bytes(as_public).unwrap().as_affine(),
+    );
+    let mut key_info = b"WebPush: info\0".to_vec();
+    key_info.extend_from_slice(ua_public.as_bytes());
+    key_info.extend_from_slice(as_public);
+    let mut ikm = [0u8; 32];
+    Hkdf::<Sha256>::new(Some(&PUSH_SUBSCRIBER_AUTH), shared.raw_secret_bytes())
+        .expand(&key_info, &mut ikm)
+        .unwrap();
+    let hkdf = Hkdf::<Sha256>::new(Some(salt), &ikm);
+    let (mut cek, mut nonce) = ([0u8; 16], [0u8; 12]);
+    hkdf.expand(b"Content-Encoding: aes128gcm\0", &mut cek)
+        .unwrap();
+    hkdf.expand(b"Content-Encoding: nonce\0", &mut nonce)
+        .unwrap();
+    let mut record = Aes128Gcm::new_from_slice(&cek)
+        .unwrap()
+        .decrypt(aes_gcm::Nonce::from_slice(&nonce), ciphertext)
+        .unwrap();
+    assert_eq!(
+        record.pop(),
+        Some(2),
+        "the only record ends with delimiter 2"
+    );
+    record
+}
+
+/// Test that pushes decrypt with the subscriber's keys, and each is encrypted afresh
+#[test]
+fn test_encrypt_round_trip() {
+    let keys = push_subscription("https://push.example.com/round-trip").keys;
+    let plaintext = br#"{"title":"Today: Birthday","body":"Ada","tag":"occasion-1"}"#;
+    let body = encrypt(&keys.p256dh, &keys.auth, plaintext).unwrap();
+    assert_eq!(decrypt(&body), plaintext);
+    assert_ne!(encrypt(&keys.p256dh, &keys.auth, plaintext).unwrap(), body);
+
+    assert!(encrypt("not-a-key", &keys.auth, plaintext).is_err());
+    assert!(encrypt(&keys.p256dh, "c2hvcnQ", plaintext).is_err());
+    assert!(encrypt(&keys.p256dh, &keys.auth, &[b'x'; 4000]).is_err());
+}
+
+/// Test that the VAPID header signs the endpoint's origin with the advertised key
+#[test]
+fn test_vapid_authorization() {
+    assert!(WebPush::new(VAPID_KEY, "crm@example.com").is_err());
+    assert!(WebPush::new("AQID", "mailto:crm@example.com").is_err());
+
+    let web_push = WebPush::new(VAPID_KEY, "mailto:crm@example.com").unwrap();
+    let now = OffsetDateTime::now_utc();
+    let header = web_push
+        .authorization("https://fcm.googleapis.com/fcm/send/abc:def", now)
+        .unwrap();
+    let (token, key) = header
+        .strip_prefix("vapid t=")
+        .unwrap()
+        .split_once(", k=")
+        .unwrap();
+    assert_eq!(key, web_push.public_key());
+
+    let (signing_input, signature) = token.rsplit_once('.').unwrap();
+    let verifying_key =
+        VerifyingKey::from_sec1_bytes(&URL_SAFE_NO_PAD.decode(key).unwrap()).unwrap();
+    let signature = Signature::from_slice(&URL_SAFE_NO_PAD.decode(signature).unwrap()).unwrap();
+    verifying_key
+        .verify(signing_input.as_bytes(), &signature)
+        .unwrap();
+
+    let claims = signing_input.split('.').nth(1).unwrap();
+    let claims: Value = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(claims).unwrap()).unwrap();
+    assert_eq!(claims["aud"], "https://fcm.googleapis.com");
+    assert_eq!(claims["sub"], "mailto:crm@example.com");
+    assert!(claims["exp"].as_i64().unwrap() > now.unix_timestamp());
+}
+
+/// Check that users register, update, list and delete their own subscriptions
+async fn check_subscriptions(repo: web::Data<dyn Repository>) {
+    let owner = provision(repo.get_ref(), "push-owner").await.unwrap();
+    let web_push = WebPush::new(VAPID_KEY, "mailto:crm@example.com").unwrap();
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(repo.clone())
+            .app_data(web::Data::new(web_push.clone()))
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let auth = ("Authorization", format!("Bearer {}", owner.token));
+
+    let req = actix_test::TestRequest::get()
+        .uri("/v1/push/vapid-public-key")
+        .insert_header(auth.clone())
+        .to_request();
+    let key: Value = actix_test::call_and_read_body_json(&app, req).await;
+    assert_eq!(key["public_key"], web_push.public_key());
+
+    let post = |body: Value| {
+        actix_test::TestRequest::post()
+            .uri("/v1/push/subscriptions")
+            .insert_header(auth.clone())
+            .set_json(body)
+            .to_request()
+    };
+    let mut subscription = serde_json::to_value(push_subscription(
+        "https://updates.push.services.mozilla.com/wpush/v2/abc",
+    ))
+    .unwrap();
+    subscription["device_name"] = json!("Laptop");
+
+    for endpoint in [
+        "http://push.example.com/insecure",
+        "https://127.0.0.1/wpush",
+        "https://localhost/wpush",
+        "https://169.254.169.254/latest/meta-data",
+        "https://push.example.com:8443/wpush",
+    ] {
+        let mut rejected = subscription.clone();
+        rejected["endpoint"] = json!(endpoint);
+        let res = actix_test::call_service(&app, post(rejected)).await;
+        assert_eq!(res.status(), 400, "{} was accepted", endpoint);
+    }
+    let mut bad_keys = subscription.clone();
+    bad_keys["keys"]["p256dh"] = json!("AAAA");
+    let res = actix_test::call_service(&app, post(bad_keys)).await;
+    assert_eq!(res.status(), 400);
+
+    let saved: Value = actix_test::call_and_read_body_json(&app, post(subscription.clone())).await;
+    assert_eq!(saved["device_name"], "Laptop");
+    assert!(saved.get("p256dh").is_none());
+    let subscription_id = saved["subscription_id"].as_i64().unwrap();
+
+    // The same endpoint again updates the subscription
+    subscription["device_name"] = json!("Work laptop");
+    let saved: Value = actix_test::call_and_read_body_json(&app, post(subscription)).await;
+    assert_eq!(saved["subscription_id"], subscription_id);
+    assert_eq!(saved["device_name"], "Work laptop");
+
+    let req = actix_test::TestRequest::get()
+        .uri("/v1/push/subscriptions")
+        .insert_header(auth.clone())
+        .to_request();
+    let listed: Vec<Value> = actix_test::call_and_read_body_json(&app, req).await;
+    let ids: Vec<i64> = listed
+        .iter()
+        .map(|s| s["subscription_id"].as_i64().unwrap())
+        .collect();
+    assert_eq!(
+        ids,
+        vec![i64::from(owner.push_subscription_id), subscription_id]
+    );
+
+    let delete = || {
+        actix_test::TestRequest::delete()
+            .uri(&format!("/v1/push/subscriptions/{}", subscription_id))
+            .insert_header(auth.clone())
+            .to_request()
+    };
+    assert_eq!(actix_test::call_service(&app, delete()).await.status(), 200);
+    assert_eq!(actix_test::call_service(&app, delete()).await.status(), 404);
+}
+
+#[actix_rt::test]
+async fn test_subscriptions_in_memory() {
+    check_subscriptions(repository::app_data(InMemoryRepository::new())).await;
+}
+
+#[actix_rt::test]
+async fn test_subscriptions_in_postgres() {
+    let ctx = setup_test_db().await;
+    check_subscriptions(repository::app_data(PgRepository::new(ctx.pool.clone()))).await;
+}
+
+#[actix_rt::test]
+async fn test_subscriptions_in_sqlite() {
+    let repo = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+    check_subscriptions(repository::app_data(repo)).await;
+}
+
+/// Records pushes, or answers every one with `status`
+#[derive(Clone, Default)]
+struct RecordingPusher {
+    sent: Arc<Mutex<Vec<(String, PushMessage)>>>,
+    status: Option<u16>,
+}
+
+#[async_trait]
+impl Pusher for RecordingPusher {
+    async fn push(
+        &self,
+        subscription: &PushSubscription,
+        message: &PushMessage,
+    ) -> Result<(), PushError> {
+        if let Some(status) = self.status {
+            return Err(PushError {
+                status: Some(status),
+                error: format!("Push service answered {}", status),
+            });
+        }
+        self.sent
+            .lock()
+            .unwrap()
+            .push((subscription.endpoint.clone(), message.clone()));
+        Ok(())
+    }
+}
+
+/// Check that users with push on are pushed the day's reminders once, and that a subscription
+/// the push service has forgotten is deleted
+async fn check_push_reminders(repo: web::Data<dyn Repository>) {
+    // Provisioned users have a subscription and an occasion today
+    let reader = provision(repo.get_ref(), "push-reader").await.unwrap();
+    provision(repo.get_ref(), "push-off").await.unwrap();
+    let mut profile = repo.get_profile(reader.user_id).await.unwrap().unwrap();
+    profile.notifications.push = true;
+    repo.save_profile(&profile).await.unwrap();
+
+    let recipients = repo.push_reminder_recipients().await.unwrap();
+    assert_eq!(recipients.len(), 1);
+    assert_eq!(recipients[0].user_id, reader.user_id);
+    assert_eq!(recipients[0].sent_on, None);
+
+    let reminders = PushReminders;
+    assert_eq!(reminders.queue_due(repo.get_ref()).await.unwrap(), 1);
+    assert_eq!(reminders.queue_due(repo.get_ref()).await.unwrap(), 0);
+
+    let now = OffsetDateTime::now_utc();
+    let unconfigured = Outbox::default();
+    assert_eq!(unconfigured.drain(repo.get_ref(), now).await.unwrap(), 1);
+    let pusher = RecordingPusher::default();
+    let outbox = Outbox::default().with_pusher(pusher.clone());
+    let retry = now + time::Duration::minutes(1);
+    assert_eq!(outbox.drain(repo.get_ref(), retry).await.unwrap(), 1);
+    {
+        let sent = pusher.sent.lock().unwrap();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(
+            sent[0].0,
+            format!("https://push.example.com/{}", reader.marker)
+        );
+        assert_eq!(sent[0].1.title, format!("Today: {}", reader.marker));
+        assert!(sent[0].1.body.contains(&reader.marker));
+    }
+
+    let message = PushMessage {
+        title: "Test".to_string(),
+        body: String::new(),
+        tag: "test".to_string(),
+    };
+    repo.queue_push_reminders(reader.user_id, reader.workspace_id, now.date(), &[message])
+        .await
+        .unwrap();
+    let gone = Outbox::default().with_pusher(RecordingPusher {
+        status: Some(410),
+        ..RecordingPusher::default()
+    });
+    assert_eq!(gone.drain(repo.get_ref(), retry).await.unwrap(), 1);
+    assert!(
+        repo.list_push_subscriptions(reader.user_id)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    assert!(repo.push_reminder_recipients().await.unwrap().is_empty());
+}
+
+#[actix_rt::test]
+async fn test_push_reminders_in_memory() {
+    check_push_reminders(repository::app_data(InMemoryRepository::new())).await;
+}
+
+#[actix_rt::test]
+async fn test_push_reminders_in_postgres() {
+    let ctx = setup_test_db().await;
+    check_push_reminders(repository::app_data(PgRepository::new(ctx.pool.clone()))).await;
+}
+
+#[actix_rt::test]
+async fn test_push_reminders_in_sqlite() {
+    let repo = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+    check_push_reminders(repository::app_data(repo)).await;
+}
+
+/// Test that a push to a name resolving to the server's own network is not connected to
+#[actix_rt::test]
+async fn test_local_endpoint_refused() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.set_nonblocking(true).unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let keys = push_subscription("https://push.example.com").keys;
+    let subscription = PushSubscription {
+        subscription_id: 1,
+        user_id: 1,
+        endpoint: format!("https://localhost:{}/wpush", port),
+        p256dh: keys.p256dh,
+        auth: keys.auth,
+        device_name: None,
+        created_at: OffsetDateTime::now_utc(),
+    };
+    let message = PushMessage {
+        title: "Test".to_string(),
+        body: String::new(),
+        tag: "test".to_string(),
+    };
+
+    let pusher = WebPush::new(VAPID_KEY, "mailto:admin@example.com").unwrap();
+    let error = pusher.push(&subscription, &message).await.unwrap_err();
+    assert_eq!(error.status, None);
+    assert!(listener.accept().is_err(), "the endpoint was connected to");
+}
@@ -0,0 +1,228 @@
+mod common;
+
+use actix_web::{App, test as actix_test, web};
+use common::*;
+use personal_crm::models::{
+    NewTagRequest, OccasionType, QuickAddOccasion, QuickAddParse, QuickAddResponse,
+};
+use personal_crm::quick_add::parse;
+use personal_crm::repository::{
+    self, InMemoryRepository, PgRepository, Repository, SqliteRepository,
+};
+use personal_crm::routes::v1_routes;
+use personal_crm::test_support::provision;
+use serde_json::json;
+use time::macros::date;
+
+const TODAY: time::Date = date!(2025 - 06 - 15);
+
+#[test]
+fn test_parse() {
+    let parsed = parse(
+        "Alice Zhang alice@x.com #college @sf birthday:1990-04-02",
+        TODAY,
+    )
+    .unwrap();
+    assert_eq!(
+        parsed,
+        QuickAddParse {
+            first_name: Some("Alice".to_string()),
+            last_name: Some("Zhang".to_string()),
+            email: Some("alice@x.com".to_string()),
+            phone: None,
+            location: Some("sf".to_string()),
+            short_note: None,
+            desired_frequency_days: None,
+            tags: vec!["college".to_string()],
+            occasions: vec![QuickAddOccasion {
+                occasion_type: OccasionType::Birthday,
+                date: date!(1990 - 04 - 02),
+                birth_year: Some(1990),
+            }],
+        }
+    );
+
+    // Quotes keep spaces, and a date without a year falls in this year, or the last one it can
+    let parsed = parse(
+        r#""Mary Ann" #"Book club" van Dyke @"New York" #book-club #BOOK-CLUB +1-555-0100
+           every:30d note:"met at RustConf" bday:02-29 anniversary:2015-06-20"#,
+        TODAY,
+    )
+    .unwrap();
+    assert_eq!(parsed.first_name.as_deref(), Some("Mary Ann"));
+    assert_eq!(parsed.last_name.as_deref(), Some("van Dyke"));
+    assert_eq!(parsed.location.as_deref(), Some("New York"));
+    assert_eq!(parsed.tags, vec!["Book club", "book-club"]);
+    assert_eq!(parsed.phone.as_deref(), Some("+1-555-0100"));
+    assert_eq!(parsed.desired_frequency_days, Some(30));
+    assert_eq!(parsed.short_note.as_deref(), Some("met at RustConf"));
+    assert_eq!(
+        parsed.occasions,
+        vec![
+            QuickAddOccasion {
+                occasion_type: OccasionType::Birthday,
+                date: date!(2024 - 02 - 29),
+                birth_year: None,
+            },
+            QuickAddOccasion {
+                occasion_type: OccasionType::Anniversary,
+                date: date!(2015 - 06 - 20),
+                birth_year: None,
+            },
+        ]
+    );
+    assert_eq!(
+        parse("Bo phone:555-0100 bday:04-02", TODAY)
+            .unwrap()
+            .occasions[0]
+            .date,
+        date!(2025 - 04 - 02)
+    );
+
+    for (text, error) in [
+        ("", "The line needs a name"),
+        ("alice@x.com #college", "The line needs a name"),
+        (r#"Alice "Zhang"#, "A quote is not closed"),
+        ("Alice colour:red", "Unknown field colour:"),
+        ("Alice a@x.com b@x.com", "Only one email can be given"),
+        ("Alice @sf @la", "Only one location can be given"),
+        ("Alice # ", "# needs a tag name after it"),
+        ("Alice every:often", "every: takes a number of days"),
+        (
+            "Alice birthday:1990-13-01",
+            "The birthday must be YYYY-MM-DD or MM-DD",
+        ),
+        (
+            "Alice bday:04-02 birthday:05-03",
+            "Only one birthday can be given",
+        ),
+    ] {
+        assert_eq!(parse(text, TODAY).unwrap_err(), error, "{}", text);
+    }
+}
+
+/// Check that a quick-add creates the contact with its tags and occasion, reusing the
+/// workspace's tags, and that a dry run only reports the parse
+async fn check_quick_add(repo: web::Data<dyn Repository>) {
+    let owner = provision(repo.get_ref(), "quick-add").await.unwrap();
+    let ws = owner.workspace_id;
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(repo.clone())
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let quick_add = |uri: &str, text: &str| {
+        actix_test::TestRequest::post()
+            .uri(uri)
+            .insert_header(("Authorization", format!("Bearer {}", owner.token)))
+            .set_json(json!({ "text": text }))
+            .to_request()
+    };
+
+    let college = format!("College {}", owner.marker);
+    let existing = repo
+        .create_tag(
+            ws,
+            &NewTagRequest {
+                name: college.clone(),
+                color: None,
+                details: None,
+            },
+        )
+        .await
+        .unwrap();
+    let climbing = format!("climbing-{}", owner.marker);
+    let email = format!("alice-{}@example.com", owner.marker);
+    let text = format!(
+        "Alice Zhang {} #\"{}\" #{} @sf birthday:1990-04-02",
+        email,
+        college.to_uppercase(),
+        climbing
+    );
+
+    let res = actix_test::call_service(&app, quick_add("/v1/quick-add?dry_run=true", &text)).await;
+    assert_eq!(res.status(), 200);
+    let preview: QuickAddResponse = actix_test::read_body_json(res).await;
+    assert!(preview.dry_run);
+    assert!(preview.contact.is_none());
+    assert_eq!(preview.tag_ids, vec![existing]);
+    assert_eq!(preview.new_tags, vec![climbing.clone()]);
+    assert_eq!(preview.parsed.email.as_deref(), Some(email.as_str()));
+    let contacts = repo.list_contacts(ws).await.unwrap();
+    assert!(contacts.iter().all(|c| c.email.as_deref() != Some(&email)));
+
+    let res = actix_test::call_service(&app, quick_add("/v1/quick-add", &text)).await;
+    assert_eq!(res.status(), 201);
+    let location = res
+        .headers()
+        .get("Location")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string();
+    let added: QuickAddResponse = actix_test::read_body_json(res).await;
+    assert!(!added.dry_run);
+    assert_eq!(added.parsed, preview.parsed);
+    let contact = added.contact.unwrap();
+    assert_eq!(location, format!("/v1/contacts/{}", contact.contact_id));
+    assert_eq!(contact.first_name.as_deref(), Some("Alice"));
+    assert_eq!(contact.last_name.as_deref(), Some("Zhang"));
+    assert_eq!(contact.location.as_deref(), Some("sf"));
+    assert_eq!(added.tag_ids.len(), 2);
+    assert_eq!(added.tag_ids[0], existing);
+    assert_eq!(added.occasion_ids.len(), 1);
+
+    let mut tagged: Vec<i32> = repo
+        .tags_for_contacts(&[contact.contact_id])
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|(_, tag)| tag.tag_id)
+        .collect();
+    tagged.sort();
+    let mut expected = added.tag_ids.clone();
+    expected.sort();
+    assert_eq!(tagged, expected);
+    let occasion = repo
+        .get_occasion(ws, added.occasion_ids[0])
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(occasion.occasion_type, OccasionType::Birthday);
+    assert_eq!(occasion.date, date!(1990 - 04 - 02));
+    assert_eq!(occasion.birth_year, Some(1990));
+    assert_eq!(occasion.recurring, Some(true));
+
+    // The email is now taken, and bad lines are rejected before anything is saved
+    let res = actix_test::call_service(&app, quick_add("/v1/quick-add", &text)).await;
+    assert_eq!(res.status(), 409);
+    let before = repo.list_tags(ws).await.unwrap().len();
+    for text in [
+        format!("#orphan-{}", owner.marker),
+        format!("Bob #orphan-{} birthday:2999-01-01", owner.marker),
+        format!("Bob #orphan-{} every:0", owner.marker),
+        "Bob colour:red".to_string(),
+    ] {
+        let res = actix_test::call_service(&app, quick_add("/v1/quick-add", &text)).await;
+        assert_eq!(res.status(), 400, "{}", text);
+    }
+    assert_eq!(repo.list_tags(ws).await.unwrap().len(), before);
+}
+
+#[actix_rt::test]
+async fn test_quick_add_in_memory() {
+    check_quick_add(repository::app_data(InMemoryRepository::new())).await;
+}
+
+#[actix_rt::test]
+async fn test_quick_add_in_postgres() {
+    let ctx = setup_test_db().await;
+    check_quick_add(repository::app_data(PgRepository::new(ctx.pool.clone()))).await;
+}
+
+#[actix_rt::test]
+async fn test_quick_add_in_sqlite() {
+    let repo = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+    check_quick_add(repository::app_data(repo)).await;
+}
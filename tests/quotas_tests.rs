@@ -0,0 +1,137 @@
+mod common;
+
+use actix_web::{App, test as actix_test, web};
+use common::*;
+use personal_crm::models::{Usage, UsageLimits};
+use personal_crm::quotas::{QUOTA_EXCEEDED, Quotas};
+use personal_crm::repository::{
+    self, InMemoryRepository, PgRepository, Repository, SqliteRepository,
+};
+use personal_crm::routes::v1_routes;
+use personal_crm::test_support::provision;
+use serde_json::{Value, json};
+
+/// Check that usage counts only the user's own workspaces, and that creates over the contact
+/// quota and uploads over the attachment quota get 402 before anything is saved
+async fn check_quotas(repo: web::Data<dyn Repository>) {
+    let owner = provision(repo.get_ref(), "quota-owner").await.unwrap();
+    provision(repo.get_ref(), "quota-other").await.unwrap();
+
+    assert_eq!(
+        repo.usage(owner.user_id).await.unwrap(),
+        Usage {
+            workspaces: 2,
+            contacts: 1,
+            interactions: 1,
+            tags: 1,
+            attachment_bytes: 31,
+        }
+    );
+
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(repo.clone())
+            .app_data(web::Data::new(Quotas {
+                max_contacts: Some(3),
+                max_attachment_bytes: Some(64),
+            }))
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let auth = ("Authorization", format!("Bearer {}", owner.token));
+    let post = |uri: &str, body: Value| {
+        actix_test::TestRequest::post()
+            .uri(uri)
+            .insert_header(auth.clone())
+            .set_json(body)
+            .to_request()
+    };
+
+    let res = actix_test::call_service(
+        &app,
+        post("/v1/contacts", json!({ "first_name": "Second" })),
+    )
+    .await;
+    assert_eq!(res.status(), 201);
+
+    // Two more would make four
+    let two = json!([{ "first_name": "Third" }, { "first_name": "Fourth" }]);
+    let res = actix_test::call_service(&app, post("/v1/contacts/bulk", two)).await;
+    assert_eq!(res.status(), 402);
+    let body: Value = actix_test::read_body_json(res).await;
+    assert_eq!(body["code"], QUOTA_EXCEEDED);
+    assert_eq!(body["used"], 2);
+
+    let res =
+        actix_test::call_service(&app, post("/v1/contacts", json!({ "first_name": "Third" })))
+            .await;
+    assert_eq!(res.status(), 201);
+    let res = actix_test::call_service(
+        &app,
+        post("/v1/contacts", json!({ "first_name": "Fourth" })),
+    )
+    .await;
+    assert_eq!(res.status(), 402);
+
+    let req = actix_test::TestRequest::get()
+        .uri("/v1/account/usage")
+        .insert_header(auth.clone())
+        .to_request();
+    let usage: Value = actix_test::call_and_read_body_json(&app, req).await;
+    assert_eq!(usage["contacts"], 3);
+    assert_eq!(usage["workspaces"], 2);
+    assert_eq!(usage["attachment_bytes"], 31);
+    assert_eq!(
+        usage["limits"],
+        json!({ "contacts": 3, "attachment_bytes": 64 })
+    );
+
+    // 31 bytes are stored already, so 33 more fill the quota and one more byte goes over
+    let upload = |body: &'static [u8]| {
+        actix_test::TestRequest::post()
+            .uri(&format!(
+                "/v1/interactions/{}/attachments",
+                owner.interaction_id
+            ))
+            .insert_header(auth.clone())
+            .insert_header(("Content-Type", "text/plain"))
+            .set_payload(body)
+            .to_request()
+    };
+    let res = actix_test::call_service(&app, upload(&[b'a'; 33])).await;
+    assert_eq!(res.status(), 201);
+    let res = actix_test::call_service(&app, upload(b"b")).await;
+    assert_eq!(res.status(), 402);
+    let body: Value = actix_test::read_body_json(res).await;
+    assert_eq!(body["code"], QUOTA_EXCEEDED);
+    assert_eq!(body["limit"], 64);
+    assert_eq!(body["used"], 64);
+    assert_eq!(
+        repo.usage(owner.user_id).await.unwrap().attachment_bytes,
+        64
+    );
+}
+
+#[actix_rt::test]
+async fn test_quotas_in_memory() {
+    check_quotas(repository::app_data(InMemoryRepository::new())).await;
+}
+
+#[actix_rt::test]
+async fn test_quotas_in_postgres() {
+    let ctx = setup_test_db().await;
+    check_quotas(repository::app_data(PgRepository::new(ctx.pool.clone()))).await;
+}
+
+#[actix_rt::test]
+async fn test_quotas_in_sqlite() {
+    let repo = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+    check_quotas(repository::app_data(repo)).await;
+}
+
+/// Test that there are no quotas unless configured
+#[test]
+fn test_unlimited_by_default() {
+    assert_eq!(Quotas::default().max_contacts, None);
+    assert_eq!(Quotas::default().limits(), UsageLimits::default());
+}
@@ -0,0 +1,112 @@
+use actix_web::middleware::from_fn;
+use actix_web::{App, HttpResponse, test as actix_test, web};
+use personal_crm::rate_limit::{RateLimitConfig, RateLimiter, rate_limit};
+
+fn limiter(capacity: u32, refill_per_sec: f64) -> RateLimiter {
+    RateLimiter::new(RateLimitConfig {
+        capacity,
+        refill_per_sec,
+        bulk_cost: 5,
+        trusted_proxies: Vec::new(),
+    })
+}
+
+/// Test that a bucket allows a burst up to capacity and then rejects
+#[tokio::test]
+async fn test_bucket_exhausts_after_capacity() {
+    let limiter = limiter(3, 0.001);
+
+    for expected_remaining in [2, 1, 0] {
+        let decision = limiter.check("ip:127.0.0.1", 1).await;
+        assert!(decision.allowed);
+        assert_eq!(decision.remaining, expected_remaining);
+        assert_eq!(decision.limit, 3);
+    }
+
+    let decision = limiter.check("ip:127.0.0.1", 1).await;
+    assert!(!decision.allowed);
+    assert!(decision.reset_secs > 0);
+}
+
+/// Test that each client key gets its own bucket
+#[tokio::test]
+async fn test_buckets_are_per_key() {
+    let limiter = limiter(1, 0.001);
+
+    assert!(limiter.check("user:auth0|a", 1).await.allowed);
+    assert!(!limiter.check("user:auth0|a", 1).await.allowed);
+    assert!(limiter.check("user:auth0|b", 1).await.allowed);
+}
+
+/// Test that bulk endpoints are charged the configured bulk cost
+#[tokio::test]
+async fn test_bulk_requests_cost_more() {
+    let limiter = limiter(6, 0.001);
+
+    assert_eq!(limiter.request_cost("/contacts"), 1);
+    assert_eq!(limiter.request_cost("/contacts/bulk"), 5);
+    assert_eq!(limiter.request_cost("/contacts/bulk-delete"), 5);
+    assert_eq!(limiter.request_cost("/v1/contacts/import/json"), 5);
+
+    assert!(limiter.check("ip:10.0.0.1", 5).await.allowed);
+    assert!(!limiter.check("ip:10.0.0.1", 5).await.allowed);
+    assert!(limiter.check("ip:10.0.0.1", 1).await.allowed);
+}
+
+/// Send anonymous requests as (connection address, X-Forwarded-For, expected status)
+async fn check_keys(limiter: RateLimiter, requests: &[(&str, &str, u16)]) {
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(web::Data::new(limiter))
+            .wrap(from_fn(rate_limit))
+            .route("/ping", web::get().to(HttpResponse::Ok)),
+    )
+    .await;
+    for &(peer, forwarded, expected) in requests {
+        let req = actix_test::TestRequest::get()
+            .uri("/ping")
+            .peer_addr(peer.parse().unwrap())
+            .insert_header(("X-Forwarded-For", forwarded))
+            .to_request();
+        let status = actix_test::call_service(&app, req).await.status();
+        assert_eq!(status, expected, "{} forwarding {}", peer, forwarded);
+    }
+}
+
+/// Test that an anonymous caller is limited by its connection's address, whatever
+/// X-Forwarded-For it sends
+#[actix_rt::test]
+async fn test_spoofed_forwarded_for() {
+    check_keys(
+        limiter(2, 0.001),
+        &[
+            ("192.0.2.1:5000", "203.0.113.1", 200),
+            ("192.0.2.1:5001", "203.0.113.2", 200),
+            ("192.0.2.1:5002", "203.0.113.3", 429),
+            ("192.0.2.2:5000", "203.0.113.3", 200),
+        ],
+    )
+    .await;
+}
+
+/// Test that behind a trusted proxy callers are limited by the address it forwarded, and not by
+/// addresses they put in front of it
+#[actix_rt::test]
+async fn test_trusted_proxy() {
+    let limiter = RateLimiter::new(RateLimitConfig {
+        capacity: 1,
+        refill_per_sec: 0.001,
+        bulk_cost: 5,
+        trusted_proxies: vec!["10.0.0.1".parse().unwrap()],
+    });
+    check_keys(
+        limiter,
+        &[
+            ("10.0.0.1:443", "198.51.100.7", 200),
+            ("10.0.0.1:443", "198.51.100.7", 429),
+            ("10.0.0.1:443", "198.51.100.8", 200),
+            ("10.0.0.1:443", "203.0.113.9, 198.51.100.8", 429),
+        ],
+    )
+    .await;
+}
@@ -0,0 +1,380 @@
+mod common;
+
+use actix_web::{App, test as actix_test, web};
+use async_trait::async_trait;
+use common::*;
+use personal_crm::mail::{Email, Mailer};
+use personal_crm::models::{
+    Contact, NewInteractionRequest, Preferences, ReconnectPick, ReviewRecipient, UpcomingOccasion,
+    WeeklyReview,
+};
+use personal_crm::outbox::Outbox;
+use personal_crm::reconnect::week_start;
+use personal_crm::repository::{
+    self, InMemoryRepository, PgRepository, Repository, SqliteRepository,
+};
+use personal_crm::review::{ReviewEmails, render_email, sends_on};
+use personal_crm::routes::v1_routes;
+use personal_crm::test_support::provision;
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+use time::macros::date;
+use time::{Duration, OffsetDateTime};
+
+#[test]
+fn test_render_email() {
+    let recipient = ReviewRecipient {
+        user_id: 1,
+        workspace_id: 1,
+        name: "Grace".to_string(),
+        email: "grace@example.com".to_string(),
+        sent_week: None,
+    };
+    let occasion: personal_crm::models::Occasion = serde_json::from_value(serde_json::json!({
+        "occasion_id": 5, "contact_id": 2, "name": "Birthday", "date": "1990-03-14",
+        "recurring": true, "recurring_interval": null, "details": null, "birth_year": null
+    }))
+    .unwrap();
+    let review = WeeklyReview {
+        from: date!(2024 - 03 - 04),
+        to: date!(2024 - 03 - 10),
+        timezone: "UTC".to_string(),
+        interactions_logged: 1,
+        new_contacts: vec![
+            saved_contact(1, "Ada"),
+            Contact {
+                contact_id: 3,
+                email: Some("x@example.com".to_string()),
+                ..Default::default()
+            },
+        ],
+        upcoming_occasions: vec![UpcomingOccasion {
+            occurs_on: date!(2024 - 03 - 14),
+            occasion,
+        }],
+        reconnect: vec![
+            ReconnectPick {
+                contact: saved_contact(2, "Bob"),
+                tags: Vec::new(),
+                days_since_last_interaction: Some(45),
+            },
+            ReconnectPick {
+                contact: Contact {
+                    contact_id: 4,
+                    ..Default::default()
+                },
+                tags: Vec::new(),
+                days_since_last_interaction: None,
+            },
+        ],
+    };
+    let email = render_email(&recipient, &review, &[saved_contact(2, "Bob")]);
+    assert_eq!(email.to, "grace@example.com");
+    assert_eq!(
+        email.subject,
+        "Your weekly review, 2024-03-04 to 2024-03-10"
+    );
+    assert!(email.text.starts_with("Hi Grace,\n"));
+    assert!(email.text.contains("- 1 interaction logged\n"));
+    assert!(
+        email
+            .text
+            .contains("- 2 new contacts added: Ada, x@example.com\n")
+    );
+    assert!(email.text.contains("- 2024-03-14: Bob, Birthday\n"));
+    assert!(email.text.contains("- Bob, last in touch 45 days ago\n"));
+    assert!(email.text.contains("- Contact 4, not in touch yet\n"));
+
+    let quiet = WeeklyReview {
+        interactions_logged: 0,
+        new_contacts: Vec::new(),
+        upcoming_occasions: Vec::new(),
+        reconnect: Vec::new(),
+        ..review
+    };
+    let email = render_email(&recipient, &quiet, &[]);
+    assert!(email.text.contains("- 0 interactions logged\n"));
+    assert!(email.text.contains("- 0 new contacts added\n"));
+    assert!(email.text.contains("- Nothing in the next seven days\n"));
+    assert!(email.text.contains("- Nobody is due\n"));
+}
+
+/// Check that contacts are found by when they were created, within the workspace
+async fn check_contacts_created_between(repo: web::Data<dyn Repository>) {
+    let owner = provision(repo.get_ref(), "created-between").await.unwrap();
+    let other = provision(repo.get_ref(), "created-between-other")
+        .await
+        .unwrap();
+    let before = OffsetDateTime::now_utc();
+    let contact_id = repo
+        .create_contact(owner.workspace_id, &contact("Newcomer"))
+        .await
+        .unwrap();
+    let after = OffsetDateTime::now_utc() + Duration::seconds(1);
+
+    let created = repo
+        .contacts_created_between(owner.workspace_id, before, after)
+        .await
+        .unwrap();
+    assert_eq!(
+        created.iter().map(|c| c.contact_id).collect::<Vec<_>>(),
+        vec![contact_id]
+    );
+    assert!(
+        repo.contacts_created_between(owner.workspace_id, after, after + Duration::days(1))
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    // Only the provisioned contact was created before
+    let earlier = repo
+        .contacts_created_between(owner.workspace_id, before - Duration::days(1), before)
+        .await
+        .unwrap();
+    assert_eq!(
+        earlier.iter().map(|c| c.contact_id).collect::<Vec<_>>(),
+        vec![owner.contact_id]
+    );
+    assert!(
+        repo.contacts_created_between(other.workspace_id, before, after)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+}
+
+#[actix_rt::test]
+async fn test_contacts_created_between_in_memory() {
+    check_contacts_created_between(repository::app_data(InMemoryRepository::new())).await;
+}
+
+#[actix_rt::test]
+async fn test_contacts_created_between_in_postgres() {
+    let ctx = setup_test_db().await;
+    check_contacts_created_between(repository::app_data(PgRepository::new(ctx.pool.clone()))).await;
+}
+
+#[actix_rt::test]
+async fn test_contacts_created_between_in_sqlite() {
+    let repo = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+    check_contacts_created_between(repository::app_data(repo)).await;
+}
+
+/// Test that the review email waits out quiet weekdays and holidays for the next free day
+#[test]
+fn test_sends_on() {
+    // Mondays are quiet, so the review comes on Tuesday
+    let quiet_mondays = Preferences {
+        quiet_weekdays: vec![1],
+        ..Preferences::default()
+    };
+    assert!(!sends_on(date!(2024 - 12 - 02), &quiet_mondays));
+    assert!(sends_on(date!(2024 - 12 - 03), &quiet_mondays));
+
+    // Easter Monday is a holiday in GB
+    let gb = Preferences {
+        country: Some("GB".to_string()),
+        ..Preferences::default()
+    };
+    assert!(!sends_on(date!(2025 - 04 - 21), &gb));
+    assert!(sends_on(date!(2025 - 04 - 22), &gb));
+    assert!(sends_on(date!(2025 - 04 - 28), &gb));
+
+    // With no free day in the week it goes out anyway
+    let all_quiet = Preferences {
+        quiet_weekdays: (1..=7).collect(),
+        ..Preferences::default()
+    };
+    assert!(sends_on(date!(2024 - 12 - 02), &all_quiet));
+}
+
+/// Test that the review counts the seven days before today and looks seven days ahead
+#[actix_rt::test]
+async fn test_weekly_review_api() {
+    let ctx = setup_test_db().await;
+    let repo = repository::app_data(PgRepository::new(ctx.pool.clone()));
+    let owner = provision(repo.get_ref(), "review-api").await.unwrap();
+    let now = OffsetDateTime::now_utc();
+    for days_ago in [1, 3, 8] {
+        repo.create_interaction(
+            owner.workspace_id,
+            &NewInteractionRequest {
+                contact_id: owner.contact_id,
+                interaction_date: now - Duration::days(days_ago),
+                notes: None,
+                follow_up_priority: None,
+                interaction_type: None,
+                contact_ids: Vec::new(),
+                follow_up_in_days: None,
+            },
+            None,
+        )
+        .await
+        .unwrap();
+    }
+    let old_contact_id = repo
+        .create_contact(owner.workspace_id, &contact("Added last week"))
+        .await
+        .unwrap();
+    sqlx::query(
+        "UPDATE contacts SET created_at = created_at - INTERVAL '2 days' WHERE contact_id = $1",
+    )
+    .bind(old_contact_id)
+    .execute(&ctx.pool)
+    .await
+    .unwrap();
+
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(repo.clone())
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let req = actix_test::TestRequest::get()
+        .uri("/v1/review/weekly")
+        .insert_header(("Authorization", format!("Bearer {}", owner.token)))
+        .to_request();
+    let res = actix_test::call_service(&app, req).await;
+    assert_eq!(res.status(), 200);
+    let review: Value = actix_test::read_body_json(res).await;
+
+    let today = now.date();
+    assert_eq!(review["from"], (today - Duration::days(7)).to_string());
+    assert_eq!(review["to"], (today - Duration::days(1)).to_string());
+    assert_eq!(review["timezone"], "UTC");
+    // Not the one from today, nor the one eight days ago
+    assert_eq!(review["interactions_logged"], 2);
+    let new_contacts = review["new_contacts"].as_array().unwrap();
+    assert_eq!(new_contacts.len(), 1);
+    assert_eq!(new_contacts[0]["contact_id"], old_contact_id);
+    // The provisioned occasion is today
+    assert_eq!(
+        review["upcoming_occasions"][0]["occasion_id"],
+        owner.occasion_id
+    );
+    assert_eq!(
+        review["upcoming_occasions"][0]["occurs_on"],
+        today.to_string()
+    );
+    assert!(!review["reconnect"].as_array().unwrap().is_empty());
+}
+
+/// Records what it sends, or fails every send
+#[derive(Clone, Default)]
+struct RecordingMailer {
+    sent: Arc<Mutex<Vec<Email>>>,
+    failing: bool,
+}
+
+#[async_trait]
+impl Mailer for RecordingMailer {
+    async fn send(&self, email: &Email) -> Result<(), String> {
+        if self.failing {
+            return Err("SMTP is down".to_string());
+        }
+        self.sent.lock().unwrap().push(email.clone());
+        Ok(())
+    }
+}
+
+async fn opt_in(repo: &dyn Repository, user_id: i32, weekly_review: bool, email: bool) {
+    let mut profile = repo.get_profile(user_id).await.unwrap().unwrap();
+    profile.notifications.weekly_review = weekly_review;
+    profile.notifications.email = email;
+    repo.save_profile(&profile).await.unwrap();
+}
+
+/// Check that opted-in users are emailed once a week, and only they are
+async fn check_weekly_review_emails(repo: web::Data<dyn Repository>) {
+    let reader = provision(repo.get_ref(), "review-reader").await.unwrap();
+    let no_email = provision(repo.get_ref(), "review-no-email").await.unwrap();
+    let deactivated = provision(repo.get_ref(), "review-deactivated")
+        .await
+        .unwrap();
+    provision(repo.get_ref(), "review-not-opted-in")
+        .await
+        .unwrap();
+    opt_in(repo.get_ref(), reader.user_id, true, true).await;
+    opt_in(repo.get_ref(), no_email.user_id, true, false).await;
+    opt_in(repo.get_ref(), deactivated.user_id, true, true).await;
+    repo.set_deactivated(deactivated.user_id, true)
+        .await
+        .unwrap();
+
+    let recipients = repo.weekly_review_recipients().await.unwrap();
+    assert_eq!(recipients.len(), 1);
+    assert_eq!(recipients[0].user_id, reader.user_id);
+    assert_eq!(recipients[0].workspace_id, reader.workspace_id);
+    assert_eq!(
+        recipients[0].email,
+        format!("{}@example.com", reader.marker)
+    );
+    assert_eq!(recipients[0].sent_week, None);
+
+    // Nothing goes out on a quiet weekday
+    let today = OffsetDateTime::now_utc().date();
+    let quiet_today = Preferences {
+        quiet_weekdays: vec![today.weekday().number_from_monday() as i32],
+        ..Preferences::default()
+    };
+    repo.save_preferences(reader.user_id, &quiet_today)
+        .await
+        .unwrap();
+    let reviews = ReviewEmails;
+    assert_eq!(reviews.queue_due(repo.get_ref()).await.unwrap(), 0);
+    repo.save_preferences(reader.user_id, &Preferences::default())
+        .await
+        .unwrap();
+
+    assert_eq!(reviews.queue_due(repo.get_ref()).await.unwrap(), 1);
+    assert_eq!(reviews.queue_due(repo.get_ref()).await.unwrap(), 0);
+
+    // A failed send is tried again later rather than queued again
+    let now = OffsetDateTime::now_utc();
+    let failing = Outbox::default().with_mailer(RecordingMailer {
+        failing: true,
+        ..RecordingMailer::default()
+    });
+    assert_eq!(failing.drain(repo.get_ref(), now).await.unwrap(), 1);
+    let mailer = RecordingMailer::default();
+    let outbox = Outbox::default().with_mailer(mailer.clone());
+    assert_eq!(outbox.drain(repo.get_ref(), now).await.unwrap(), 0);
+    let retry = now + Duration::minutes(1);
+    assert_eq!(outbox.drain(repo.get_ref(), retry).await.unwrap(), 1);
+    {
+        let sent = mailer.sent.lock().unwrap();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].to, recipients[0].email);
+        assert!(sent[0].text.contains("Reach out this week:"));
+    }
+    let week = week_start(OffsetDateTime::now_utc().date());
+    assert_eq!(
+        repo.weekly_review_recipients().await.unwrap()[0].sent_week,
+        Some(week)
+    );
+
+    // Come next week it goes out again
+    repo.record_weekly_review(reader.user_id, week - Duration::weeks(1))
+        .await
+        .unwrap();
+    assert_eq!(reviews.queue_due(repo.get_ref()).await.unwrap(), 1);
+    outbox.drain(repo.get_ref(), retry).await.unwrap();
+    assert_eq!(mailer.sent.lock().unwrap().len(), 2);
+}
+
+#[actix_rt::test]
+async fn test_weekly_review_emails_in_memory() {
+    check_weekly_review_emails(repository::app_data(InMemoryRepository::new())).await;
+}
+
+#[actix_rt::test]
+async fn test_weekly_review_emails_in_postgres() {
+    let ctx = setup_test_db().await;
+    check_weekly_review_emails(repository::app_data(PgRepository::new(ctx.pool.clone()))).await;
+}
+
+#[actix_rt::test]
+async fn test_weekly_review_emails_in_sqlite() {
+    let repo = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+    check_weekly_review_emails(repository::app_data(repo)).await;
+}
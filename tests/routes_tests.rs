@@ -0,0 +1,154 @@
+mod common;
+
+use actix_web::http::header::CONTENT_TYPE;
+use actix_web::{App, test, web};
+use async_trait::async_trait;
+use common::*;
+use personal_crm::enrichment::{AvatarFinder, Enrichment};
+use personal_crm::events::EventBus;
+use personal_crm::repository::{
+    self, InMemoryRepository, PgRepository, Repository, SqliteRepository,
+};
+use personal_crm::routes::{ROUTES, v1_routes};
+use personal_crm::test_support::{body_for, path_for, provision};
+use std::sync::Arc;
+
+/// Has an avatar for every email, so `POST /contacts/{id}/enrich` finds one
+struct EveryAvatar;
+
+#[async_trait]
+impl AvatarFinder for EveryAvatar {
+    async fn find(&self, email: &str) -> Result<Option<String>, String> {
+        Ok(Some(format!("https://img.example/{}", email)))
+    }
+}
+
+/// Call every route as the owner of the rows it references, each on a freshly provisioned
+/// user so that deletes don't take rows away from the routes after them
+async fn check_routes_serve_owner(repo: web::Data<dyn Repository>) {
+    let app = test::init_service(
+        App::new()
+            .app_data(repo.clone())
+            .app_data(web::Data::new(EventBus::default()))
+            .app_data(web::Data::new(Enrichment::new(vec![Arc::new(EveryAvatar)])))
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+
+    for spec in ROUTES {
+        let owner = provision(repo.get_ref(), "owner").await.unwrap();
+        let path_ids: Vec<i32> = spec.path.iter().map(|r| owner.id(*r)).collect();
+        let body_ids: Vec<i32> = spec.body.iter().map(|r| owner.id(*r)).collect();
+        let uri = path_for(spec, &path_ids);
+        let mut req = test::TestRequest::default()
+            .method(spec.method.clone())
+            .uri(&uri)
+            .insert_header(("Authorization", format!("Bearer {}", owner.token)));
+        if let Some(body) = body_for(spec, &owner, &body_ids) {
+            req = req.set_json(body);
+        }
+        let res = test::call_service(&app, req.to_request()).await;
+        let status = res.status();
+        // Event streams never end, so only their headers are checked
+        let streaming = res
+            .headers()
+            .get(CONTENT_TYPE)
+            .is_some_and(|t| t == "text/event-stream");
+        let body = if streaming {
+            Default::default()
+        } else {
+            test::read_body(res).await
+        };
+        assert!(
+            status.is_success(),
+            "{} {} failed on the caller's own rows ({}): {}",
+            spec.method,
+            uri,
+            status,
+            String::from_utf8_lossy(&body)
+        );
+    }
+}
+
+/// Test that every route serves its owner with the in-memory repository
+#[actix_rt::test]
+async fn test_routes_serve_owner_in_memory() {
+    check_routes_serve_owner(repository::app_data(InMemoryRepository::new())).await;
+}
+
+/// Test that every route serves its owner with Postgres
+#[actix_rt::test]
+async fn test_routes_serve_owner_in_postgres() {
+    let test_ctx = setup_test_db().await;
+    check_routes_serve_owner(repository::app_data(PgRepository::new(
+        test_ctx.pool.clone(),
+    )))
+    .await;
+}
+
+#[actix_rt::test]
+async fn test_routes_serve_owner_in_sqlite() {
+    let repo = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+    check_routes_serve_owner(repository::app_data(repo)).await;
+}
+
+/// Check that the `Owned` guards answer for rows the caller can't have before the handler runs:
+/// 401 without a token, and a 404 naming the resource for another user's row or a malformed id
+async fn check_owned_guards(repo: web::Data<dyn Repository>) {
+    let owner = provision(repo.get_ref(), "guards").await.unwrap();
+    let other = provision(repo.get_ref(), "guards-other").await.unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(repo.clone())
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let tag = |contact_id: String, tag_id: String, token: Option<&str>| {
+        let mut req =
+            test::TestRequest::post().uri(&format!("/v1/contacts/{}/tags/{}", contact_id, tag_id));
+        if let Some(token) = token {
+            req = req.insert_header(("Authorization", format!("Bearer {}", token)));
+        }
+        req.to_request()
+    };
+    let (contact_id, tag_id) = (owner.contact_id.to_string(), owner.tag_id.to_string());
+
+    let res = test::call_service(&app, tag(contact_id.clone(), tag_id.clone(), None)).await;
+    assert_eq!(res.status(), 401);
+    for (contact_id, tag_id, error) in [
+        (
+            other.contact_id.to_string(),
+            tag_id.clone(),
+            "Contact not found",
+        ),
+        (
+            contact_id.clone(),
+            other.tag_id.to_string(),
+            "Tag not found",
+        ),
+        (contact_id.clone(), "first".to_string(), "Tag not found"),
+    ] {
+        let res = test::call_service(&app, tag(contact_id, tag_id, Some(&owner.token))).await;
+        assert_eq!(res.status(), 404);
+        assert_eq!(test::read_body(res).await, error);
+    }
+    let res = test::call_service(&app, tag(contact_id, tag_id, Some(&owner.token))).await;
+    assert_eq!(res.status(), 200);
+}
+
+#[actix_rt::test]
+async fn test_owned_guards_in_memory() {
+    check_owned_guards(repository::app_data(InMemoryRepository::new())).await;
+}
+
+#[actix_rt::test]
+async fn test_owned_guards_in_postgres() {
+    let ctx = setup_test_db().await;
+    check_owned_guards(repository::app_data(PgRepository::new(ctx.pool.clone()))).await;
+}
+
+#[actix_rt::test]
+async fn test_owned_guards_in_sqlite() {
+    let repo = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+    check_owned_guards(repository::app_data(repo)).await;
+}
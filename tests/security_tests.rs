@@ -0,0 +1,112 @@
+use actix_web::{App, HttpResponse, Responder, get, http::Method, test};
+use personal_crm::security::{cors, security_headers};
+
+#[get("/contacts")]
+async fn contacts() -> impl Responder {
+    HttpResponse::Ok().json(Vec::<i32>::new())
+}
+
+/// Test that preflight requests from an allowed origin are answered for any route
+#[actix_rt::test]
+async fn test_preflight_allowed_origin() {
+    let app = test::init_service(
+        App::new()
+            .wrap(security_headers())
+            .wrap(cors(
+                "https://app.example.com, https://other.example.com",
+                600,
+            ))
+            .service(contacts),
+    )
+    .await;
+
+    let req = test::TestRequest::default()
+        .method(Method::OPTIONS)
+        .uri("/contacts/42/tags/7")
+        .insert_header(("Origin", "https://other.example.com"))
+        .insert_header(("Access-Control-Request-Method", "DELETE"))
+        .insert_header(("Access-Control-Request-Headers", "authorization"))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+
+    assert!(res.status().is_success());
+    assert_eq!(
+        res.headers().get("Access-Control-Allow-Origin").unwrap(),
+        "https://other.example.com"
+    );
+    assert_eq!(res.headers().get("Access-Control-Max-Age").unwrap(), "600");
+    let methods = res.headers().get("Access-Control-Allow-Methods").unwrap();
+    assert!(methods.to_str().unwrap().contains("PUT"));
+}
+
+/// Test that browser clients may send the headers the API reads and see the ones it sets
+#[actix_rt::test]
+async fn test_api_headers() {
+    let app = test::init_service(
+        App::new()
+            .wrap(cors("https://app.example.com", 600))
+            .service(contacts),
+    )
+    .await;
+
+    let req = test::TestRequest::default()
+        .method(Method::OPTIONS)
+        .uri("/contacts")
+        .insert_header(("Origin", "https://app.example.com"))
+        .insert_header(("Access-Control-Request-Method", "POST"))
+        .insert_header((
+            "Access-Control-Request-Headers",
+            "authorization, content-type, api-version, x-workspace, if-none-match, \
+             if-modified-since, idempotency-key",
+        ))
+        .to_request();
+    assert!(test::call_service(&app, req).await.status().is_success());
+
+    let req = test::TestRequest::get()
+        .uri("/contacts")
+        .insert_header(("Origin", "https://app.example.com"))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    let exposed = res.headers().get("Access-Control-Expose-Headers").unwrap();
+    let exposed = exposed.to_str().unwrap().to_ascii_lowercase();
+    for header in [
+        "retry-after",
+        "api-version",
+        "deprecation",
+        "sunset",
+        "etag",
+        "last-modified",
+        "idempotent-replayed",
+        "link",
+    ] {
+        assert!(exposed.contains(header), "{} is not exposed", header);
+    }
+}
+
+/// Test that unknown origins are refused and security headers are always set
+#[actix_rt::test]
+async fn test_disallowed_origin_and_security_headers() {
+    let app = test::init_service(
+        App::new()
+            .wrap(security_headers())
+            .wrap(cors("https://app.example.com", 600))
+            .service(contacts),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/contacts")
+        .insert_header(("Origin", "https://evil.example.com"))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert!(res.headers().get("Access-Control-Allow-Origin").is_none());
+
+    let req = test::TestRequest::get().uri("/contacts").to_request();
+    let res = test::call_service(&app, req).await;
+    assert!(res.status().is_success());
+    assert_eq!(
+        res.headers().get("X-Content-Type-Options").unwrap(),
+        "nosniff"
+    );
+    assert_eq!(res.headers().get("X-Frame-Options").unwrap(), "DENY");
+}
@@ -0,0 +1,46 @@
+use personal_crm::repository::{InMemoryRepository, Repository};
+use personal_crm::seed::{SeedConfig, seed};
+
+async fn seeded(config: &SeedConfig) -> (InMemoryRepository, i32) {
+    let repo = InMemoryRepository::new();
+    let user = repo
+        .get_or_create_user("test|seed", "seed@example.com", "Seed")
+        .await
+        .unwrap();
+    let report = seed(&repo, user.workspace_id, config).await.unwrap();
+    assert_eq!(report.contacts, config.contacts);
+    (repo, user.workspace_id)
+}
+
+/// Test that the same seed produces the same contacts and another seed different ones
+#[tokio::test]
+async fn test_seed_is_deterministic() {
+    let config = SeedConfig {
+        email: "seed@example.com".to_string(),
+        contacts: 30,
+        seed: 7,
+    };
+    let (first, workspace_id) = seeded(&config).await;
+    let (second, _) = seeded(&config).await;
+    let (other, _) = seeded(&SeedConfig { seed: 8, ..config }).await;
+
+    let emails = |contacts: Vec<personal_crm::models::Contact>| -> Vec<Option<String>> {
+        contacts.into_iter().map(|c| c.email).collect()
+    };
+    let contacts = first.list_contacts(workspace_id).await.unwrap();
+    let ids: Vec<i32> = contacts.iter().map(|c| c.contact_id).collect();
+    assert_eq!(contacts.len(), 30);
+    assert_eq!(
+        emails(contacts),
+        emails(second.list_contacts(workspace_id).await.unwrap())
+    );
+    assert_eq!(
+        first.interactions_for_contacts(&ids).await.unwrap().len(),
+        second.interactions_for_contacts(&ids).await.unwrap().len()
+    );
+    assert_eq!(first.list_tags(workspace_id).await.unwrap().len(), 6);
+    assert_ne!(
+        emails(first.list_contacts(workspace_id).await.unwrap()),
+        emails(other.list_contacts(workspace_id).await.unwrap())
+    );
+}
@@ -0,0 +1,208 @@
+mod common;
+
+use actix_web::{App, test, web};
+use common::*;
+use personal_crm::Auth0Claims;
+use personal_crm::repository::{self, PgRepository};
+use personal_crm::routes::v1_routes;
+use personal_crm::test_support::register_claims;
+use serde_json::{Value, json};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Test sharing by tag and by contact: the grantee sees the contacts, writes only with the
+/// write permission, and loses access once the share is deleted
+#[actix_rt::test]
+async fn test_shares() {
+    let test_ctx = setup_test_db().await;
+    let app = test::init_service(
+        App::new()
+            .app_data(repository::app_data(PgRepository::new(
+                test_ctx.pool.clone(),
+            )))
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let owner_email = format!("owner-{}@example.com", nanos);
+    let friend_email = format!("friend-{}@example.com", nanos);
+    for (token, email) in [("owner", &owner_email), ("friend", &friend_email)] {
+        register_claims(
+            &format!("token-shares-{}-{}", token, nanos),
+            Auth0Claims {
+                sub: format!("test|shares-{}-{}", token, nanos),
+                email: Some(email.clone()),
+                name: Some(token.to_string()),
+                iss: None,
+                aud: None,
+                exp: None,
+                iat: None,
+                auth_time: None,
+                scope: None,
+                permissions: None,
+            },
+        )
+        .await;
+    }
+    let owner_token = format!("Bearer token-shares-owner-{}", nanos);
+    let friend_token = format!("Bearer token-shares-friend-{}", nanos);
+    let owner = ("Authorization", owner_token.as_str());
+    let friend = ("Authorization", friend_token.as_str());
+
+    // The friend's first request creates their account
+    let req = test::TestRequest::get()
+        .uri("/v1/shares/contacts")
+        .insert_header(friend)
+        .to_request();
+    let shared: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(shared, json!([]));
+
+    let req = test::TestRequest::post()
+        .uri("/v1/contacts/bulk")
+        .insert_header(owner)
+        .set_json(json!([{"first_name": "Ada"}, {"first_name": "Grace"}]))
+        .to_request();
+    let created: Value = test::call_and_read_body_json(&app, req).await;
+    let ids: Vec<i32> = serde_json::from_value(created["created_contact_ids"].clone()).unwrap();
+
+    let req = test::TestRequest::post()
+        .uri("/v1/tags")
+        .insert_header(owner)
+        .set_json(json!({"name": "Family"}))
+        .to_request();
+    let tag: Value = test::call_and_read_body_json(&app, req).await;
+    let tag_id = tag["tag_id"].as_i64().unwrap();
+    let req = test::TestRequest::post()
+        .uri(&format!("/v1/contacts/{}/tags/{}", ids[0], tag_id))
+        .insert_header(owner)
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+
+    // A tag or contacts, not both; and never with yourself
+    for body in [
+        json!({"email": friend_email, "permission": "read"}),
+        json!({"email": friend_email, "tag_id": tag_id, "contact_ids": [ids[1]], "permission": "read"}),
+        json!({"email": owner_email.to_uppercase(), "tag_id": tag_id, "permission": "read"}),
+    ] {
+        let req = test::TestRequest::post()
+            .uri("/v1/shares")
+            .insert_header(owner)
+            .set_json(body)
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 400);
+    }
+    let req = test::TestRequest::post()
+        .uri("/v1/shares")
+        .insert_header(owner)
+        .set_json(json!({"email": "nobody@example.com", "tag_id": tag_id, "permission": "read"}))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 404);
+
+    let req = test::TestRequest::post()
+        .uri("/v1/shares")
+        .insert_header(owner)
+        .set_json(json!({"email": friend_email, "tag_id": tag_id, "permission": "read"}))
+        .to_request();
+    let share: Value = test::call_and_read_body_json(&app, req).await;
+    let tag_share_id = share[0]["share_id"].as_i64().unwrap();
+
+    // Only the tagged contact is shared, and only for reading
+    let req = test::TestRequest::get()
+        .uri("/v1/shares/contacts")
+        .insert_header(friend)
+        .to_request();
+    let shared: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(shared.as_array().unwrap().len(), 1);
+    assert_eq!(shared[0]["contact"]["first_name"], "Ada");
+    assert_eq!(shared[0]["owner_email"], owner_email.as_str());
+    assert_eq!(shared[0]["permission"], "read");
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/v1/contacts/{}", ids[0]))
+        .insert_header(friend)
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+    let req = test::TestRequest::get()
+        .uri(&format!("/v1/contacts/{}", ids[1]))
+        .insert_header(friend)
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 404);
+
+    let log =
+        json!({"contact_id": ids[0], "interaction_date": "2024-01-01T12:00:00", "notes": "Lunch"});
+    let req = test::TestRequest::post()
+        .uri("/v1/interactions")
+        .insert_header(friend)
+        .set_json(&log)
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 403);
+    let req = test::TestRequest::delete()
+        .uri(&format!("/v1/contacts/{}", ids[0]))
+        .insert_header(friend)
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 404);
+
+    // Sharing again upgrades the permission; the interaction is stored under the owner
+    let req = test::TestRequest::post()
+        .uri("/v1/shares")
+        .insert_header(owner)
+        .set_json(json!({"email": friend_email, "tag_id": tag_id, "permission": "write"}))
+        .to_request();
+    let share: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(share[0]["share_id"].as_i64(), Some(tag_share_id));
+    let req = test::TestRequest::post()
+        .uri("/v1/interactions")
+        .insert_header(friend)
+        .set_json(&log)
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 201);
+    let req = test::TestRequest::get()
+        .uri(&format!("/v1/contacts/{}/interactions", ids[0]))
+        .insert_header(owner)
+        .to_request();
+    let interactions: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(interactions[0]["notes"], "Lunch");
+
+    // A single contact can be shared too
+    let req = test::TestRequest::post()
+        .uri("/v1/shares")
+        .insert_header(owner)
+        .set_json(json!({"email": friend_email, "contact_ids": [ids[1]], "permission": "read"}))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 201);
+    let req = test::TestRequest::get()
+        .uri("/v1/shares")
+        .insert_header(friend)
+        .to_request();
+    let shares: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(shares["given"], json!([]));
+    assert_eq!(shares["received"].as_array().unwrap().len(), 2);
+    let req = test::TestRequest::patch()
+        .uri(&format!("/v1/contacts/{}", ids[1]))
+        .insert_header(friend)
+        .set_json(json!({"first_name": "Grace"}))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 403);
+
+    // The grantee can leave a share; the contact is then out of reach
+    let req = test::TestRequest::delete()
+        .uri(&format!("/v1/shares/{}", tag_share_id))
+        .insert_header(friend)
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+    let req = test::TestRequest::get()
+        .uri(&format!("/v1/contacts/{}", ids[0]))
+        .insert_header(friend)
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 404);
+    let req = test::TestRequest::get()
+        .uri("/v1/shares/contacts")
+        .insert_header(friend)
+        .to_request();
+    let shared: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(shared.as_array().unwrap().len(), 1);
+    assert_eq!(shared[0]["contact"]["first_name"], "Grace");
+}
@@ -0,0 +1,192 @@
+use actix_web::{App, test as actix_test, web};
+use personal_crm::link_preview::{PublicResolver, is_fetchable, is_public_ip, parse_preview};
+use personal_crm::models::LinkPreview;
+use personal_crm::repository::{self, InMemoryRepository};
+use personal_crm::routes::v1_routes;
+use personal_crm::test_support::provision;
+use reqwest::Url;
+use reqwest::dns::Resolve;
+use serde_json::{Value, json};
+
+/// Test adding, listing, changing and removing a contact's social profiles
+#[actix_rt::test]
+async fn test_social_profile_crud() {
+    let repo = repository::app_data(InMemoryRepository::new());
+    let owner = provision(repo.get_ref(), "social").await.unwrap();
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(repo.clone())
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let auth = ("Authorization", format!("Bearer {}", owner.token));
+    let create = |body: Value| {
+        actix_test::TestRequest::post()
+            .uri("/v1/social-profiles")
+            .insert_header(auth.clone())
+            .set_json(body)
+            .to_request()
+    };
+    let list = || {
+        actix_test::TestRequest::get()
+            .uri(&format!(
+                "/v1/contacts/{}/social-profiles",
+                owner.contact_id
+            ))
+            .insert_header(auth.clone())
+            .to_request()
+    };
+
+    let res = actix_test::call_service(
+        &app,
+        create(json!({
+            "contact_id": owner.contact_id,
+            "platform": "linkedin",
+            "url": "https://www.linkedin.com/in/ada"
+        })),
+    )
+    .await;
+    assert_eq!(res.status(), 201);
+    let created: Value = actix_test::read_body_json(res).await;
+    let id = created["social_profile_id"].as_i64().unwrap();
+
+    // The same URL twice on one contact
+    let res = actix_test::call_service(
+        &app,
+        create(json!({
+            "contact_id": owner.contact_id,
+            "platform": "other",
+            "url": "https://www.linkedin.com/in/ada"
+        })),
+    )
+    .await;
+    assert_eq!(res.status(), 409);
+
+    for url in [
+        "linkedin.com/in/ada",
+        "javascript:alert(1)",
+        "ftp://example.com/ada",
+    ] {
+        let res = actix_test::call_service(
+            &app,
+            create(json!({ "contact_id": owner.contact_id, "platform": "other", "url": url })),
+        )
+        .await;
+        assert_eq!(res.status(), 400, "{} was accepted", url);
+    }
+
+    // Listed without previews, which are off unless configured
+    let profiles: Vec<Value> =
+        actix_test::read_body_json(actix_test::call_service(&app, list()).await).await;
+    assert_eq!(profiles.len(), 2);
+    assert_eq!(profiles[1]["platform"], "linkedin");
+    assert_eq!(profiles[1]["preview"], Value::Null);
+
+    let req = actix_test::TestRequest::patch()
+        .uri(&format!("/v1/social-profiles/{}", id))
+        .insert_header(auth.clone())
+        .set_json(json!({
+            "contact_id": owner.contact_id,
+            "platform": "github",
+            "url": "https://github.com/ada"
+        }))
+        .to_request();
+    assert_eq!(actix_test::call_service(&app, req).await.status(), 200);
+    let profiles: Vec<Value> =
+        actix_test::read_body_json(actix_test::call_service(&app, list()).await).await;
+    assert_eq!(profiles[1]["platform"], "github");
+    assert_eq!(profiles[1]["url"], "https://github.com/ada");
+
+    let req = actix_test::TestRequest::get()
+        .uri("/v1/export")
+        .insert_header(auth.clone())
+        .to_request();
+    let archive: Value =
+        actix_test::read_body_json(actix_test::call_service(&app, req).await).await;
+    assert_eq!(archive["social_profiles"].as_array().unwrap().len(), 2);
+
+    let delete = || {
+        actix_test::TestRequest::delete()
+            .uri(&format!("/v1/social-profiles/{}", id))
+            .insert_header(auth.clone())
+            .to_request()
+    };
+    assert_eq!(actix_test::call_service(&app, delete()).await.status(), 200);
+    assert_eq!(actix_test::call_service(&app, delete()).await.status(), 404);
+    let profiles: Vec<Value> =
+        actix_test::read_body_json(actix_test::call_service(&app, list()).await).await;
+    assert_eq!(profiles.len(), 1);
+}
+
+/// Test which profile links the server is willing to fetch for previews
+#[test]
+fn test_is_fetchable() {
+    let fetchable = |url: &str| is_fetchable(&Url::parse(url).unwrap());
+    assert!(fetchable("https://www.linkedin.com/in/ada"));
+    assert!(fetchable("https://mastodon.social/@ada"));
+
+    assert!(!fetchable("http://github.com/ada"));
+    assert!(!fetchable("https://github.com:8443/ada"));
+    assert!(!fetchable("https://127.0.0.1/ada"));
+    assert!(!fetchable("https://[::1]/ada"));
+    assert!(!fetchable("https://localhost/ada"));
+    assert!(!fetchable("https://metadata.google.internal/"));
+    assert!(!fetchable("https://printer.local/"));
+}
+
+/// Test which addresses count as public
+#[test]
+fn test_is_public_ip() {
+    let public = |ip: &str| is_public_ip(ip.parse().unwrap());
+    assert!(public("93.184.216.34"));
+    assert!(public("2606:2800:220:1:248:1893:25c8:1946"));
+
+    for ip in [
+        "127.0.0.1",
+        "0.0.0.0",
+        "10.1.2.3",
+        "172.16.0.1",
+        "192.168.1.1",
+        "169.254.169.254",
+        "100.64.0.1",
+        "::1",
+        "::",
+        "fd00::1",
+        "fe80::1",
+        "::ffff:127.0.0.1",
+        "::ffff:169.254.169.254",
+    ] {
+        assert!(!public(ip), "{}", ip);
+    }
+}
+
+/// Test that names resolving only to local addresses are not resolved
+#[actix_rt::test]
+async fn test_public_resolver() {
+    let resolved = PublicResolver.resolve("localhost".parse().unwrap()).await;
+    assert!(resolved.is_err());
+}
+
+/// Test reading Open Graph tags out of a page
+#[test]
+fn test_parse_preview() {
+    let base = Url::parse("https://github.com/ada").unwrap();
+    let html = r#"<html><head>
+        <title>Ignored</title>
+        <META name="description" content="Not Open Graph">
+        <meta content="ada &amp; friends" property="og:title" />
+        <meta property='og:image' content='/avatars/ada.png'>
+        <meta property="og:image" content="https://example.com/second.png">
+        </head><body>...</body></html>"#;
+    assert_eq!(
+        parse_preview(html, &base),
+        Some(LinkPreview {
+            title: Some("ada & friends".to_string()),
+            image_url: Some("https://github.com/avatars/ada.png".to_string()),
+        })
+    );
+
+    let html = r#"<meta property=og:image content=data:image/png;base64,AAAA>"#;
+    assert_eq!(parse_preview(html, &base), None);
+    assert_eq!(parse_preview("<p>No metadata</p>", &base), None);
+}
@@ -0,0 +1,273 @@
+mod common;
+
+use actix_web::{App, test as actix_test, web};
+use common::*;
+use personal_crm::models::{Granularity, NewInteractionRequest, StatsMetric, TimeSeriesPoint};
+use personal_crm::reconnect::week_start;
+use personal_crm::repository::{
+    self, InMemoryRepository, PgRepository, Repository, SqliteRepository,
+};
+use personal_crm::routes::v1_routes;
+use personal_crm::stats::{bucket_start, fill, next_bucket};
+use personal_crm::test_support::provision;
+use personal_crm::timezone::LocalDates;
+use serde_json::Value;
+use time::macros::{date, datetime};
+use time::{Duration, OffsetDateTime};
+
+fn interaction(contact_id: i32, interaction_date: OffsetDateTime) -> NewInteractionRequest {
+    NewInteractionRequest {
+        contact_id,
+        interaction_date,
+        notes: None,
+        follow_up_priority: None,
+        interaction_type: None,
+        contact_ids: Vec::new(),
+        follow_up_in_days: None,
+    }
+}
+
+#[test]
+fn test_buckets() {
+    // A Wednesday
+    let day = date!(2024 - 03 - 06);
+    assert_eq!(bucket_start(Granularity::Day, day), day);
+    assert_eq!(bucket_start(Granularity::Week, day), date!(2024 - 03 - 04));
+    assert_eq!(bucket_start(Granularity::Month, day), date!(2024 - 03 - 01));
+
+    assert_eq!(
+        next_bucket(Granularity::Day, date!(2024 - 02 - 29)),
+        date!(2024 - 03 - 01)
+    );
+    assert_eq!(
+        next_bucket(Granularity::Week, date!(2024 - 12 - 30)),
+        date!(2025 - 01 - 06)
+    );
+    assert_eq!(
+        next_bucket(Granularity::Month, date!(2024 - 12 - 01)),
+        date!(2025 - 01 - 01)
+    );
+}
+
+#[test]
+fn test_fill_lists_empty_buckets() {
+    let points = fill(
+        Granularity::Month,
+        date!(2024 - 01 - 01),
+        date!(2024 - 04 - 15),
+        &[(date!(2024 - 02 - 01), 3), (date!(2024 - 04 - 01), 1)],
+    );
+    assert_eq!(
+        points,
+        vec![
+            TimeSeriesPoint {
+                bucket: date!(2024 - 01 - 01),
+                count: 0,
+            },
+            TimeSeriesPoint {
+                bucket: date!(2024 - 02 - 01),
+                count: 3,
+            },
+            TimeSeriesPoint {
+                bucket: date!(2024 - 03 - 01),
+                count: 0,
+            },
+            TimeSeriesPoint {
+                bucket: date!(2024 - 04 - 01),
+                count: 1,
+            },
+        ]
+    );
+}
+
+/// Check that interactions are counted by the user's day they fall on, within the workspace
+async fn check_count_by_period(repo: web::Data<dyn Repository>) {
+    let owner = provision(repo.get_ref(), "stats-owner").await.unwrap();
+    let other = provision(repo.get_ref(), "stats-other").await.unwrap();
+    for at in [
+        // Sunday evening in New York, so the week before
+        datetime!(2024-03-04 03:00 UTC),
+        datetime!(2024-03-05 12:00 UTC),
+        datetime!(2024-03-10 12:00 UTC),
+        datetime!(2024-01-15 12:00 UTC),
+    ] {
+        repo.create_interaction(owner.workspace_id, &interaction(owner.contact_id, at), None)
+            .await
+            .unwrap();
+    }
+    repo.create_interaction(
+        other.workspace_id,
+        &interaction(other.contact_id, datetime!(2024-03-05 12:00 UTC)),
+        None,
+    )
+    .await
+    .unwrap();
+
+    let new_york = LocalDates::new(Some("America/New_York"));
+    let weeks = repo
+        .count_by_period(
+            owner.workspace_id,
+            StatsMetric::Interactions,
+            Granularity::Week,
+            date!(2024 - 02 - 26),
+            date!(2024 - 03 - 10),
+            &new_york,
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        weeks,
+        vec![(date!(2024 - 02 - 26), 1), (date!(2024 - 03 - 04), 2)]
+    );
+    let months = repo
+        .count_by_period(
+            owner.workspace_id,
+            StatsMetric::Interactions,
+            Granularity::Month,
+            date!(2024 - 01 - 01),
+            date!(2024 - 03 - 31),
+            &new_york,
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        months,
+        vec![(date!(2024 - 01 - 01), 1), (date!(2024 - 03 - 01), 3)]
+    );
+    // `to` is the last day counted
+    let days = repo
+        .count_by_period(
+            owner.workspace_id,
+            StatsMetric::Interactions,
+            Granularity::Day,
+            date!(2024 - 03 - 05),
+            date!(2024 - 03 - 09),
+            &LocalDates::utc(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(days, vec![(date!(2024 - 03 - 05), 1)]);
+
+    repo.create_contact(owner.workspace_id, &contact("Newcomer"))
+        .await
+        .unwrap();
+    let today = OffsetDateTime::now_utc().date();
+    let contacts = repo
+        .count_by_period(
+            owner.workspace_id,
+            StatsMetric::Contacts,
+            Granularity::Week,
+            week_start(today) - Duration::weeks(1),
+            today,
+            &LocalDates::utc(),
+        )
+        .await
+        .unwrap();
+    // The provisioned contact and the newcomer
+    assert_eq!(contacts, vec![(week_start(today), 2)]);
+}
+
+#[actix_rt::test]
+async fn test_count_by_period_in_memory() {
+    check_count_by_period(repository::app_data(InMemoryRepository::new())).await;
+}
+
+#[actix_rt::test]
+async fn test_count_by_period_in_postgres() {
+    let ctx = setup_test_db().await;
+    check_count_by_period(repository::app_data(PgRepository::new(ctx.pool.clone()))).await;
+}
+
+#[actix_rt::test]
+async fn test_count_by_period_in_sqlite() {
+    let repo = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+    check_count_by_period(repository::app_data(repo)).await;
+}
+
+/// Test the endpoint's defaults, the filled-in buckets and the rejected ranges
+#[actix_rt::test]
+async fn test_timeseries_api() {
+    let ctx = setup_test_db().await;
+    let repo = repository::app_data(PgRepository::new(ctx.pool.clone()));
+    let owner = provision(repo.get_ref(), "stats-api").await.unwrap();
+    repo.create_interaction(
+        owner.workspace_id,
+        &interaction(owner.contact_id, datetime!(2024-03-05 12:00 UTC)),
+        None,
+    )
+    .await
+    .unwrap();
+
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(repo.clone())
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let get = |query: &str| {
+        actix_test::TestRequest::get()
+            .uri(&format!("/v1/stats/timeseries?{}", query))
+            .insert_header(("Authorization", format!("Bearer {}", owner.token)))
+            .to_request()
+    };
+
+    // Twelve weeks through this one by default, the provisioned interaction in the last
+    let res = actix_test::call_service(&app, get("metric=interactions")).await;
+    assert_eq!(res.status(), 200);
+    let series: Value = actix_test::read_body_json(res).await;
+    let today = OffsetDateTime::now_utc().date();
+    assert_eq!(series["metric"], "interactions");
+    assert_eq!(series["granularity"], "week");
+    assert_eq!(series["timezone"], "UTC");
+    assert_eq!(series["to"], today.to_string());
+    assert_eq!(
+        series["from"],
+        (week_start(today) - Duration::weeks(11)).to_string()
+    );
+    let points = series["points"].as_array().unwrap();
+    assert_eq!(points.len(), 12);
+    assert_eq!(points[11]["bucket"], week_start(today).to_string());
+    assert_eq!(points[11]["count"], 1);
+    assert_eq!(points[0]["count"], 0);
+
+    // `from` moves back to the start of its month
+    let res = actix_test::call_service(
+        &app,
+        get("metric=interactions&granularity=month&from=2024-02-10&to=2024-04-30"),
+    )
+    .await;
+    assert_eq!(res.status(), 200);
+    let series: Value = actix_test::read_body_json(res).await;
+    assert_eq!(series["from"], "2024-02-01");
+    let counts: Vec<(String, i64)> = series["points"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|p| {
+            (
+                p["bucket"].as_str().unwrap().to_string(),
+                p["count"].as_i64().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        counts,
+        vec![
+            ("2024-02-01".to_string(), 0),
+            ("2024-03-01".to_string(), 1),
+            ("2024-04-01".to_string(), 0)
+        ]
+    );
+
+    for query in [
+        "metric=interactions&from=2024-03-10&to=2024-03-01",
+        "metric=interactions&granularity=day&from=2020-01-01&to=2024-01-01",
+        "metric=interactions&from=0001-01-01",
+        "",
+        "metric=emails",
+        "metric=contacts&granularity=year",
+    ] {
+        let res = actix_test::call_service(&app, get(query)).await;
+        assert_eq!(res.status(), 400, "{}", query);
+    }
+}
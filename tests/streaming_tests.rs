@@ -0,0 +1,72 @@
+mod common;
+
+use actix_web::http::header;
+use actix_web::middleware::Compress;
+use actix_web::{App, test, web};
+use common::*;
+use personal_crm::models::NewContactRequest;
+use personal_crm::repository::{self, InMemoryRepository, Repository};
+use personal_crm::routes::v1_routes;
+use personal_crm::test_support::register_token;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+const CONTACTS: usize = 2000;
+
+/// Test that a contact list many chunks long streams whole, matches its ETag and compresses
+#[actix_rt::test]
+async fn test_large_responses_stream_and_compress() {
+    register_token("token-streaming", "test|streaming").await;
+    let repo = InMemoryRepository::new();
+    let user = repo
+        .get_or_create_user("test|streaming", "streaming@example.com", "Streaming")
+        .await
+        .unwrap();
+    let contacts: Vec<NewContactRequest> = (0..CONTACTS)
+        .map(|n| NewContactRequest {
+            last_name: Some("Streamed".to_string()),
+            email: Some(format!("contact{}@example.com", n)),
+            notes: Some("Long enough notes to spread the list over many chunks. ".repeat(4)),
+            ..contact(&format!("Contact {}", n))
+        })
+        .collect();
+    repo.create_contacts(user.workspace_id, &contacts, true)
+        .await
+        .unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(repository::app_data(repo))
+            .wrap(Compress::default())
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let get = |uri: &str, encoding: &str| {
+        test::TestRequest::get()
+            .uri(uri)
+            .insert_header(("Authorization", "Bearer token-streaming"))
+            .insert_header((header::ACCEPT_ENCODING, encoding))
+            .to_request()
+    };
+
+    let res = test::call_service(&app, get("/v1/contacts", "identity")).await;
+    assert_eq!(res.status(), 200);
+    let etag = res.headers().get(header::ETAG).unwrap().clone();
+    let plain = test::read_body(res).await;
+    assert_eq!(
+        etag.to_str().unwrap(),
+        format!("\"{:x}\"", Sha256::digest(&plain))
+    );
+    let listed: Vec<Value> = serde_json::from_slice(&plain).unwrap();
+    assert_eq!(listed.len(), CONTACTS);
+
+    let res = test::call_service(&app, get("/v1/contacts", "gzip")).await;
+    assert_eq!(res.headers().get(header::CONTENT_ENCODING).unwrap(), "gzip");
+    assert_eq!(res.headers().get(header::ETAG), Some(&etag));
+    let compressed = test::read_body(res).await;
+    assert!(compressed.len() < plain.len() / 4);
+
+    let res = test::call_service(&app, get("/v1/export", "identity")).await;
+    assert_eq!(res.status(), 200);
+    let archive: Value = serde_json::from_slice(&test::read_body(res).await).unwrap();
+    assert_eq!(archive["contacts"].as_array().unwrap().len(), CONTACTS);
+}
@@ -0,0 +1,401 @@
+mod common;
+
+use actix_web::{App, test as actix_test, web};
+use common::*;
+use personal_crm::models::{
+    NewContactRequest, NewTagRequest, SharePermission, TagArchive, TagImportReport,
+};
+use personal_crm::repository::{
+    self, InMemoryRepository, PgRepository, Repository, SqliteRepository,
+};
+use personal_crm::routes::v1_routes;
+use personal_crm::tags::{PALETTE, normalize_color, palette_color};
+use personal_crm::test_support::{Tenant, provision};
+use serde_json::{Value, json};
+
+fn new_tag(name: &str) -> NewTagRequest {
+    NewTagRequest {
+        name: name.to_string(),
+        color: None,
+        details: None,
+    }
+}
+
+/// Tags list their contact counts, and only tags with no contacts and no shares are cleaned up
+async fn check_tag_usage(repo: web::Data<dyn Repository>) {
+    let owner = provision(repo.get_ref(), "tag-usage").await.unwrap();
+    let other = provision(repo.get_ref(), "tag-usage-other").await.unwrap();
+    let unused = repo
+        .create_tag(
+            owner.workspace_id,
+            &new_tag(&format!("unused-{}", owner.user_id)),
+        )
+        .await
+        .unwrap();
+    let shared = repo
+        .create_tag(
+            owner.workspace_id,
+            &new_tag(&format!("shared-{}", owner.user_id)),
+        )
+        .await
+        .unwrap();
+    repo.create_shares(
+        owner.user_id,
+        other.user_id,
+        Some(shared),
+        &[],
+        SharePermission::Read,
+    )
+    .await
+    .unwrap();
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(repo.clone())
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let auth = ("Authorization", format!("Bearer {}", owner.token));
+
+    let req = actix_test::TestRequest::get()
+        .uri("/v1/tags")
+        .insert_header(auth.clone())
+        .to_request();
+    let body: Value = actix_test::call_and_read_body_json(&app, req).await;
+    let counts: Vec<(i64, i64)> = body["tags"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|t| {
+            (
+                t["tag_id"].as_i64().unwrap(),
+                t["contact_count"].as_i64().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        counts,
+        [
+            (owner.tag_id as i64, 1),
+            (unused as i64, 0),
+            (shared as i64, 0)
+        ]
+    );
+
+    let req = actix_test::TestRequest::delete()
+        .uri("/v1/tags/unused")
+        .insert_header(auth.clone())
+        .to_request();
+    let res = actix_test::call_service(&app, req).await;
+    assert_eq!(res.status(), 200);
+    let body: Value = actix_test::read_body_json(res).await;
+    assert_eq!(body["deleted_ids"], json!([unused]));
+
+    let remaining: Vec<i32> = repo
+        .list_tags(owner.workspace_id)
+        .await
+        .unwrap()
+        .iter()
+        .map(|t| t.tag_id)
+        .collect();
+    assert_eq!(remaining, [owner.tag_id, shared]);
+    assert_eq!(repo.list_tags(other.workspace_id).await.unwrap().len(), 1);
+}
+
+/// Test tag usage on the in-memory repository
+#[actix_rt::test]
+async fn test_tag_usage_in_memory() {
+    check_tag_usage(repository::app_data(InMemoryRepository::new())).await;
+}
+
+/// Test tag usage on Postgres
+#[actix_rt::test]
+async fn test_tag_usage_in_postgres() {
+    let ctx = setup_test_db().await;
+    check_tag_usage(repository::app_data(PgRepository::new(ctx.pool.clone()))).await;
+}
+
+#[actix_rt::test]
+async fn test_tag_usage_in_sqlite() {
+    let repo = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+    check_tag_usage(repository::app_data(repo)).await;
+}
+
+/// Test that colors are normalized to lowercase `#rrggbb` and names pick a stable palette color
+#[test]
+fn test_colors() {
+    assert_eq!(normalize_color("#FF0000").as_deref(), Some("#ff0000"));
+    assert_eq!(normalize_color(" #f0a ").as_deref(), Some("#ff00aa"));
+    assert_eq!(normalize_color("red"), None);
+    assert_eq!(normalize_color("ff0000"), None);
+    assert_eq!(normalize_color("#ff00"), None);
+    assert_eq!(normalize_color("#gg0000"), None);
+
+    assert!(PALETTE.contains(&palette_color("Family")));
+    assert_eq!(palette_color("Family"), palette_color(" family"));
+}
+
+/// Test that tags are saved with a hex color, picked from the palette when left out
+#[actix_rt::test]
+async fn test_tag_colors() {
+    let repo = repository::app_data(InMemoryRepository::new());
+    let owner = provision(repo.get_ref(), "tag-colors").await.unwrap();
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(repo.clone())
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let auth = ("Authorization", format!("Bearer {}", owner.token));
+    let create = |body: Value| {
+        actix_test::TestRequest::post()
+            .uri("/v1/tags")
+            .insert_header(auth.clone())
+            .set_json(body)
+            .to_request()
+    };
+
+    let res =
+        actix_test::call_service(&app, create(json!({"name": "Climbing", "color": "red"}))).await;
+    assert_eq!(res.status(), 400);
+
+    let res =
+        actix_test::call_service(&app, create(json!({"name": "Work", "color": "#ABC"}))).await;
+    assert_eq!(res.status(), 201);
+    let created: Value = actix_test::read_body_json(res).await;
+    let work = created["tag_id"].as_i64().unwrap() as i32;
+    let res = actix_test::call_service(&app, create(json!({"name": "Climbing"}))).await;
+    let created: Value = actix_test::read_body_json(res).await;
+    let climbing = created["tag_id"].as_i64().unwrap() as i32;
+
+    let tags = repo.list_tags(owner.workspace_id).await.unwrap();
+    let color = |id: i32| {
+        tags.iter()
+            .find(|t| t.tag_id == id)
+            .and_then(|t| t.color.clone())
+    };
+    assert_eq!(color(work).as_deref(), Some("#aabbcc"));
+    assert_eq!(color(climbing).as_deref(), Some(palette_color("Climbing")));
+
+    let req = actix_test::TestRequest::get()
+        .uri("/v1/tags/palette")
+        .insert_header(auth.clone())
+        .to_request();
+    let palette: Value = actix_test::call_and_read_body_json(&app, req).await;
+    assert_eq!(palette["colors"], json!(PALETTE));
+}
+
+/// Check that exported tags are restored after being deleted, that another workspace reuses
+/// its tag of the same name and tags its contacts with the same email, and that importing again
+/// changes nothing
+async fn check_tag_export_import(repo: web::Data<dyn Repository>) {
+    let owner = provision(repo.get_ref(), "tag-export").await.unwrap();
+    let other = provision(repo.get_ref(), "tag-import").await.unwrap();
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(repo.clone())
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let climbing_name = format!("Climbing-{}", owner.user_id);
+    let ada_email = format!("ada-{}@example.com", owner.user_id);
+
+    let ada = repo
+        .create_contact(
+            owner.workspace_id,
+            &NewContactRequest {
+                email: Some(ada_email.clone()),
+                ..contact("Ada")
+            },
+        )
+        .await
+        .unwrap();
+    let climbing = repo
+        .create_tag(
+            owner.workspace_id,
+            &NewTagRequest {
+                name: climbing_name.clone(),
+                color: Some("#aabbcc".to_string()),
+                details: Some("Weekends".to_string()),
+            },
+        )
+        .await
+        .unwrap();
+    repo.add_tag_to_contacts(owner.workspace_id, climbing, &[ada, owner.contact_id])
+        .await
+        .unwrap();
+    let empty = repo
+        .create_tag(
+            owner.workspace_id,
+            &new_tag(&format!("Empty-{}", owner.user_id)),
+        )
+        .await
+        .unwrap();
+    let owner_contact_email = repo
+        .get_contact(owner.workspace_id, owner.contact_id)
+        .await
+        .unwrap()
+        .unwrap()
+        .email
+        .unwrap();
+
+    let req = actix_test::TestRequest::get()
+        .uri("/v1/tags/export")
+        .insert_header(("Authorization", format!("Bearer {}", owner.token)))
+        .to_request();
+    let archive: TagArchive = actix_test::call_and_read_body_json(&app, req).await;
+    let names: Vec<&str> = archive.tags.iter().map(|t| t.name.as_str()).collect();
+    assert_eq!(names.len(), 3);
+    assert!(names.is_sorted(), "{:?}", names);
+    let exported = archive
+        .tags
+        .iter()
+        .find(|t| t.name == climbing_name)
+        .unwrap();
+    assert_eq!(exported.color.as_deref(), Some("#aabbcc"));
+    assert_eq!(exported.details.as_deref(), Some("Weekends"));
+    let mut emails = vec![ada_email.clone(), owner_contact_email.clone()];
+    emails.sort();
+    assert_eq!(exported.contact_emails, emails);
+    let exported_empty = archive.tags.iter().find(|t| t.name.starts_with("Empty"));
+    assert!(exported_empty.unwrap().contact_emails.is_empty());
+    let body = serde_json::to_value(&archive).unwrap();
+
+    let import = |tenant: &Tenant, body: Value| {
+        actix_test::TestRequest::post()
+            .uri("/v1/tags/import")
+            .insert_header(("Authorization", format!("Bearer {}", tenant.token)))
+            .set_json(body)
+            .to_request()
+    };
+
+    // A mishap, a dry run of the restore, then the restore
+    repo.delete_tag(owner.workspace_id, climbing).await.unwrap();
+    repo.delete_tag(owner.workspace_id, empty).await.unwrap();
+    let dry_run = actix_test::TestRequest::post()
+        .uri("/v1/tags/import?dry_run=true")
+        .insert_header(("Authorization", format!("Bearer {}", owner.token)))
+        .set_json(body.clone())
+        .to_request();
+    let preview: TagImportReport = actix_test::call_and_read_body_json(&app, dry_run).await;
+    assert!(preview.dry_run);
+    assert!(preview.created_tag_ids.is_empty());
+    assert_eq!(preview.existing_tag_ids, vec![owner.tag_id]);
+    let mut new_tags = preview.new_tags.clone();
+    new_tags.sort();
+    let mut deleted = vec![climbing_name.clone(), format!("Empty-{}", owner.user_id)];
+    deleted.sort();
+    assert_eq!(new_tags, deleted);
+    assert_eq!(preview.tagged_count, 3);
+    assert_eq!(repo.list_tags(owner.workspace_id).await.unwrap().len(), 1);
+
+    let report: TagImportReport =
+        actix_test::call_and_read_body_json(&app, import(&owner, body.clone())).await;
+    assert!(!report.dry_run);
+    assert_eq!(report.created_tag_ids.len(), 2);
+    assert_eq!(report.new_tags.len(), 2);
+    assert_eq!(report.existing_tag_ids, vec![owner.tag_id]);
+    assert_eq!(report.tagged_count, 3);
+    assert!(report.unmatched_emails.is_empty());
+    let restored = repo.list_tags(owner.workspace_id).await.unwrap();
+    let restored = restored.iter().find(|t| t.name == climbing_name).unwrap();
+    assert_eq!(restored.color.as_deref(), Some("#aabbcc"));
+    let mut tagged: Vec<i32> = repo
+        .tags_for_contacts(&[ada, owner.contact_id])
+        .await
+        .unwrap()
+        .into_iter()
+        .filter(|(_, t)| t.tag_id == restored.tag_id)
+        .map(|(contact_id, _)| contact_id)
+        .collect();
+    tagged.sort();
+    assert_eq!(tagged, vec![owner.contact_id, ada]);
+
+    let again: TagImportReport =
+        actix_test::call_and_read_body_json(&app, import(&owner, body.clone())).await;
+    assert!(again.created_tag_ids.is_empty());
+    assert_eq!(again.existing_tag_ids.len(), 3);
+    assert_eq!(again.tagged_count, 3);
+    assert_eq!(repo.list_tags(owner.workspace_id).await.unwrap().len(), 3);
+
+    // The other workspace has the tag under another case, and Ada under another email case
+    let their_ada = repo
+        .create_contact(
+            other.workspace_id,
+            &NewContactRequest {
+                email: Some(ada_email.to_uppercase()),
+                ..contact("Ada")
+            },
+        )
+        .await
+        .unwrap();
+    let their_climbing = repo
+        .create_tag(
+            other.workspace_id,
+            &NewTagRequest {
+                name: climbing_name.to_lowercase(),
+                color: Some("#112233".to_string()),
+                details: None,
+            },
+        )
+        .await
+        .unwrap();
+    let climbing_only = json!({ "tags": [exported] });
+    let report: TagImportReport =
+        actix_test::call_and_read_body_json(&app, import(&other, climbing_only)).await;
+    assert!(report.created_tag_ids.is_empty());
+    assert_eq!(report.existing_tag_ids, vec![their_climbing]);
+    assert_eq!(report.tagged_count, 1);
+    assert_eq!(report.unmatched_emails, vec![owner_contact_email]);
+    let tags = repo.tags_for_contacts(&[their_ada]).await.unwrap();
+    let tag_ids: Vec<i32> = tags.iter().map(|(_, t)| t.tag_id).collect();
+    assert_eq!(tag_ids, vec![their_climbing]);
+    assert_eq!(tags[0].1.color.as_deref(), Some("#112233"));
+
+    // Names the owner's workspace uses are free in this one too
+    let report: TagImportReport =
+        actix_test::call_and_read_body_json(&app, import(&other, body)).await;
+    assert_eq!(report.created_tag_ids.len(), 2);
+    assert_eq!(report.existing_tag_ids, vec![their_climbing]);
+    assert_eq!(repo.list_tags(other.workspace_id).await.unwrap().len(), 4);
+
+    // One bad color and nothing is saved
+    let tag_count = repo.list_tags(other.workspace_id).await.unwrap().len();
+    let res = actix_test::call_service(
+        &app,
+        import(
+            &other,
+            json!({"tags": [
+                {"name": format!("Chess-{}", other.user_id), "contact_emails": [ada_email]},
+                {"name": format!("Sailing-{}", other.user_id), "color": "blue"}
+            ]}),
+        ),
+    )
+    .await;
+    assert_eq!(res.status(), 400);
+    let res =
+        actix_test::call_service(&app, import(&other, json!({"tags": [{"name": " "}]}))).await;
+    assert_eq!(res.status(), 400);
+    assert_eq!(
+        repo.list_tags(other.workspace_id).await.unwrap().len(),
+        tag_count
+    );
+}
+
+/// Test tag export and import on the in-memory repository
+#[actix_rt::test]
+async fn test_tag_export_import_in_memory() {
+    check_tag_export_import(repository::app_data(InMemoryRepository::new())).await;
+}
+
+/// Test tag export and import on Postgres
+#[actix_rt::test]
+async fn test_tag_export_import_in_postgres() {
+    let ctx = setup_test_db().await;
+    check_tag_export_import(repository::app_data(PgRepository::new(ctx.pool.clone()))).await;
+}
+
+#[actix_rt::test]
+async fn test_tag_export_import_in_sqlite() {
+    let repo = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+    check_tag_export_import(repository::app_data(repo)).await;
+}
@@ -0,0 +1,98 @@
+use personal_crm::tenancy::{self, REPOSITORY_SOURCES, UNSCOPED};
+
+#[test]
+fn test_is_scoped() {
+    for sql in [
+        "SELECT * FROM contacts WHERE workspace_id = $1 AND contact_id = $2",
+        "SELECT c.contact_id FROM contacts c WHERE c.workspace_id = $1",
+        "DELETE FROM tags WHERE tag_id = ANY($2) AND workspace_id=$1",
+        "UPDATE workspaces SET name = $3 WHERE $2 = owner_id",
+        "SELECT share_id FROM shares WHERE $1 IN (owner_id, grantee_id)",
+        "SELECT 1 FROM contacts WHERE workspace_id = ANY($1::bigint[])",
+        "INSERT INTO contacts (workspace_id, first_name) VALUES ($1, $2)",
+        "WITH mine AS (SELECT * FROM contacts WHERE workspace_id = $1) SELECT * FROM mine",
+    ] {
+        assert!(tenancy::is_scoped(sql), "{} is scoped", sql);
+    }
+    for sql in [
+        "SELECT * FROM contacts WHERE contact_id = $1",
+        // Compared with a column, not a parameter
+        "SELECT * FROM contacts c JOIN workspaces w ON w.workspace_id = c.workspace_id",
+        "SELECT * FROM contacts WHERE workspace_id IS NOT NULL",
+        "SELECT * FROM contacts WHERE notes = 'workspace_id = $1'",
+        "INSERT INTO contact_tags (contact_id, tag_id) VALUES ($1, $2)",
+        "UPDATE contacts SET workspace_id = workspace_id WHERE contact_id = $1",
+    ] {
+        assert!(!tenancy::is_scoped(sql), "{} is not scoped", sql);
+    }
+}
+
+#[test]
+fn test_statements() {
+    let source = r##"
+impl Repo {
+    // "SELECT in a comment"
+    pub async fn first(&self) {
+        let quote = '"';
+        sqlx::query!("SELECT 1 FROM a WHERE user_id = $1", 1)
+    }
+
+    async fn second(&self) {
+        let label = "not sql";
+        sqlx::query(
+            r#"
+            UPDATE b SET "name" = $1
+            "#,
+        )
+    }
+}
+"##;
+    let statements = tenancy::statements(source);
+    assert_eq!(statements.len(), 2);
+    assert_eq!(statements[0].method, "first");
+    assert_eq!(statements[0].line, 6);
+    assert_eq!(statements[0].sql, "SELECT 1 FROM a WHERE user_id = $1");
+    assert_eq!(statements[1].method, "second");
+    assert_eq!(statements[1].line, 12);
+    assert!(statements[1].sql.contains("UPDATE b SET \"name\" = $1"));
+
+    let report = tenancy::audit(source);
+    assert_eq!(report.statements, 2);
+    assert_eq!(report.unscoped.len(), 1);
+    assert_eq!(report.unscoped[0].method, "second");
+    // None of the repository's exemptions apply here
+    assert_eq!(report.stale.len(), UNSCOPED.len());
+    assert!(!report.passed());
+}
+
+/// Test that every statement of both SQL repositories filters by a tenant, or is exempted
+#[test]
+fn test_repository_is_scoped() {
+    assert_eq!(REPOSITORY_SOURCES.len(), 2);
+    for repository in REPOSITORY_SOURCES {
+        let report = repository.audit();
+        assert!(
+            report.statements > 100,
+            "found {} in {}",
+            report.statements,
+            repository.path
+        );
+        for statement in &report.unscoped {
+            eprintln!(
+                "{}:{} in {}: {}",
+                repository.path, statement.line, statement.method, statement.sql
+            );
+        }
+        assert!(
+            report.unscoped.is_empty(),
+            "statements without a tenant in {}",
+            repository.path
+        );
+        assert!(
+            report.stale.is_empty(),
+            "stale exemptions in {}: {:?}",
+            repository.path,
+            report.stale
+        );
+    }
+}
@@ -0,0 +1,72 @@
+use actix_web::middleware::from_fn;
+use actix_web::{App, HttpResponse, Responder, get, test, web};
+use personal_crm::versioning::{ApiVersion, api_version};
+
+#[get("/ping")]
+async fn ping(version: ApiVersion) -> impl Responder {
+    HttpResponse::Ok().body(format!("v{}", version.0))
+}
+
+#[get("/health")]
+async fn health() -> impl Responder {
+    HttpResponse::Ok().body("healthy")
+}
+
+async fn call(uri: &str, header: Option<&str>) -> (u16, String, Option<String>) {
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(api_version))
+            .service(health)
+            .service(web::scope("/v1").service(ping)),
+    )
+    .await;
+
+    let mut req = test::TestRequest::get().uri(uri);
+    if let Some(value) = header {
+        req = req.insert_header(("Api-Version", value));
+    }
+    let res = test::call_service(&app, req.to_request()).await;
+    let status = res.status().as_u16();
+    let version = res
+        .headers()
+        .get("Api-Version")
+        .map(|v| v.to_str().unwrap().to_string());
+    let body = String::from_utf8(test::read_body(res).await.to_vec()).unwrap();
+    (status, body, version)
+}
+
+/// Test that explicitly versioned paths are routed and tagged with their version
+#[actix_rt::test]
+async fn test_versioned_path() {
+    let (status, body, version) = call("/v1/ping", None).await;
+    assert_eq!(status, 200);
+    assert_eq!(body, "v1");
+    assert_eq!(version, Some("1".to_string()));
+}
+
+/// Test that unversioned paths fall back to the Api-Version header or the default version
+#[actix_rt::test]
+async fn test_unversioned_path_fallback() {
+    let (status, body, _) = call("/ping", None).await;
+    assert_eq!(status, 200);
+    assert_eq!(body, "v1");
+
+    let (status, body, _) = call("/ping?x=1", Some("v1")).await;
+    assert_eq!(status, 200);
+    assert_eq!(body, "v1");
+}
+
+/// Test that unsupported versions are rejected and the health check is left alone
+#[actix_rt::test]
+async fn test_unsupported_version_and_health() {
+    let (status, _, _) = call("/ping", Some("7")).await;
+    assert_eq!(status, 400);
+
+    let (status, _, _) = call("/v7/ping", None).await;
+    assert_eq!(status, 404);
+
+    let (status, body, version) = call("/health", Some("7")).await;
+    assert_eq!(status, 200);
+    assert_eq!(body, "healthy");
+    assert_eq!(version, None);
+}
@@ -0,0 +1,513 @@
+mod common;
+
+use actix_web::{App, HttpRequest, HttpResponse, HttpServer, test as actix_test, web};
+use common::*;
+use personal_crm::models::{
+    HookEvent, NewInteractionRequest, NewOccasionRequest, NewRestHookRequest, Occasion,
+    OccasionType,
+};
+use personal_crm::outbox::Outbox;
+use personal_crm::repository::{
+    self, InMemoryRepository, PgRepository, Repository, SqliteRepository,
+};
+use personal_crm::routes::v1_routes;
+use personal_crm::test_support::provision;
+use personal_crm::zapier::{UpcomingHooks, upcoming_items};
+use serde_json::{Value, json};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use time::macros::date;
+use time::{Date, Duration, OffsetDateTime};
+
+fn interaction(contact_id: i32, notes: &str) -> NewInteractionRequest {
+    NewInteractionRequest {
+        contact_id,
+        interaction_date: OffsetDateTime::now_utc(),
+        notes: Some(notes.to_string()),
+        follow_up_priority: None,
+        interaction_type: None,
+        contact_ids: Vec::new(),
+        follow_up_in_days: None,
+    }
+}
+
+fn occasion(contact_id: i32, name: &str, date: Date) -> NewOccasionRequest {
+    NewOccasionRequest {
+        contact_id,
+        name: name.to_string(),
+        date,
+        recurring: false,
+        recurring_interval: None,
+        details: None,
+        occasion_type: OccasionType::Birthday,
+        birth_year: None,
+        remind_days_before: None,
+    }
+}
+
+fn hook(event: HookEvent, target_url: &str) -> NewRestHookRequest {
+    NewRestHookRequest {
+        event,
+        target_url: target_url.to_string(),
+    }
+}
+
+#[test]
+fn test_upcoming_items() {
+    let occasion = |occasion_id: i32, date: Date| Occasion {
+        occasion_id,
+        contact_id: Some(1),
+        name: "Birthday".to_string(),
+        date,
+        recurring: None,
+        recurring_interval: None,
+        details: None,
+        occasion_type: OccasionType::Birthday,
+        birth_year: None,
+        remind_days_before: None,
+        upcoming_age: None,
+        interaction_id: None,
+    };
+    let today = date!(2024 - 12 - 28);
+    let items = upcoming_items(
+        vec![
+            occasion(1, date!(1990 - 12 - 29)),
+            occasion(2, date!(1985 - 01 - 03)),
+            occasion(3, date!(1990 - 01 - 05)),
+            occasion(4, date!(1990 - 12 - 27)),
+            occasion(5, date!(1992 - 01 - 03)),
+        ],
+        today,
+        today,
+        today + Duration::days(7),
+    );
+    // Latest first, across the new year; today's and last year's are left out
+    assert_eq!(
+        items.iter().map(|i| i.id.as_str()).collect::<Vec<_>>(),
+        vec!["5-2025-01-03", "2-2025-01-03", "1-2024-12-29"]
+    );
+    assert_eq!(items[0].item.occurs_on, date!(2025 - 01 - 03));
+}
+
+/// Check hook subscriptions and the newest-first lists behind the polling triggers, scoped to
+/// the workspace
+async fn check_rest_hooks(repo: web::Data<dyn Repository>) {
+    let owner = provision(repo.get_ref(), "zapier").await.unwrap();
+    let other = provision(repo.get_ref(), "zapier-other").await.unwrap();
+    let workspace_id = owner.workspace_id;
+
+    let interactions = repo
+        .create_rest_hook(
+            workspace_id,
+            &hook(HookEvent::NewInteraction, "https://hooks.example.com/i"),
+        )
+        .await
+        .unwrap();
+    let spare = repo
+        .create_rest_hook(
+            owner.spare_workspace_id,
+            &hook(HookEvent::NewInteraction, "https://hooks.example.com/s"),
+        )
+        .await
+        .unwrap();
+    let hooks = repo.list_rest_hooks(workspace_id).await.unwrap();
+    assert_eq!(
+        hooks.iter().map(|h| h.hook_id).collect::<Vec<_>>(),
+        vec![owner.rest_hook_id, interactions]
+    );
+    assert_eq!(hooks[1].event, HookEvent::NewInteraction);
+    assert_eq!(hooks[1].user_id, owner.user_id);
+    assert!(
+        repo.owns_rest_hook(workspace_id, interactions)
+            .await
+            .unwrap()
+    );
+    assert!(!repo.owns_rest_hook(workspace_id, spare).await.unwrap());
+    assert!(
+        !repo
+            .owns_rest_hook(other.workspace_id, interactions)
+            .await
+            .unwrap()
+    );
+
+    // The user's hooks in every workspace, and no one else's
+    let for_event = repo
+        .rest_hooks_for_event(HookEvent::NewInteraction, Some(owner.user_id))
+        .await
+        .unwrap();
+    assert_eq!(
+        for_event.iter().map(|h| h.hook_id).collect::<Vec<_>>(),
+        vec![interactions, spare]
+    );
+    assert_eq!(for_event[1].workspace_id, owner.spare_workspace_id);
+    assert_eq!(for_event[0].delivered_on, None);
+    repo.record_rest_hook_delivery(interactions, date!(2024 - 03 - 15))
+        .await
+        .unwrap();
+    let for_event = repo
+        .rest_hooks_for_event(HookEvent::NewInteraction, None)
+        .await
+        .unwrap();
+    let delivered = for_event
+        .iter()
+        .find(|h| h.hook_id == interactions)
+        .unwrap();
+    assert_eq!(delivered.delivered_on, Some(date!(2024 - 03 - 15)));
+    assert!(
+        !for_event
+            .iter()
+            .any(|h| h.event != HookEvent::NewInteraction)
+    );
+
+    assert!(
+        !repo
+            .delete_rest_hook(other.workspace_id, interactions)
+            .await
+            .unwrap()
+    );
+    assert!(
+        repo.delete_rest_hook(workspace_id, interactions)
+            .await
+            .unwrap()
+    );
+    assert_eq!(repo.list_rest_hooks(workspace_id).await.unwrap().len(), 1);
+
+    let ada = repo
+        .create_contact(workspace_id, &contact("Ada"))
+        .await
+        .unwrap();
+    let bo = repo
+        .create_contact(workspace_id, &contact("Bo"))
+        .await
+        .unwrap();
+    assert!(
+        repo.set_contact_archived(workspace_id, bo, true)
+            .await
+            .unwrap()
+    );
+    let latest = repo.latest_contacts(workspace_id, 2).await.unwrap();
+    assert_eq!(
+        latest.iter().map(|c| c.contact_id).collect::<Vec<_>>(),
+        vec![bo, ada]
+    );
+
+    let (first, _) = repo
+        .create_interaction(workspace_id, &interaction(ada, "first"), None)
+        .await
+        .unwrap();
+    let (second, _) = repo
+        .create_interaction(workspace_id, &interaction(ada, "second"), None)
+        .await
+        .unwrap();
+    let latest = repo.latest_interactions(workspace_id, 2).await.unwrap();
+    assert_eq!(
+        latest.iter().map(|i| i.interaction_id).collect::<Vec<_>>(),
+        vec![second, first]
+    );
+    let by_id = repo
+        .interactions_by_id(workspace_id, &[second, first, other.interaction_id])
+        .await
+        .unwrap();
+    assert_eq!(
+        by_id.iter().map(|i| i.interaction_id).collect::<Vec<_>>(),
+        vec![first, second]
+    );
+    assert_eq!(by_id[1].notes.as_deref(), Some("second"));
+}
+
+#[actix_rt::test]
+async fn test_rest_hooks_in_memory() {
+    check_rest_hooks(repository::app_data(InMemoryRepository::new())).await;
+}
+
+#[actix_rt::test]
+async fn test_rest_hooks_in_postgres() {
+    let ctx = setup_test_db().await;
+    check_rest_hooks(repository::app_data(PgRepository::new(ctx.pool.clone()))).await;
+}
+
+#[actix_rt::test]
+async fn test_rest_hooks_in_sqlite() {
+    let repo = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+    check_rest_hooks(repository::app_data(repo)).await;
+}
+
+/// (path, body) of every request the receiver got
+type Received = Arc<Mutex<Vec<(String, Value)>>>;
+
+/// Accepts everything but /gone, which answers 410
+async fn receive(
+    req: HttpRequest,
+    body: web::Bytes,
+    received: web::Data<Received>,
+) -> HttpResponse {
+    received.lock().unwrap().push((
+        req.path().to_string(),
+        serde_json::from_slice(&body).unwrap_or_default(),
+    ));
+    if req.path() == "/gone" {
+        HttpResponse::Gone().finish()
+    } else {
+        HttpResponse::Ok().finish()
+    }
+}
+
+/// Test that new contacts and interactions reach the hooks of their workspace, that upcoming
+/// occasions are sent once as they come within reach, and that gone targets are unsubscribed
+#[actix_rt::test]
+async fn test_hook_delivery() {
+    let received: Received = Arc::default();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    let data = web::Data::new(received.clone());
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(data.clone())
+            .default_service(web::to(receive))
+    })
+    .workers(1)
+    .listen(listener)
+    .unwrap()
+    .run();
+    actix_rt::spawn(server);
+    let take = || std::mem::take(&mut *received.lock().unwrap());
+
+    let repo = InMemoryRepository::new();
+    let owner = provision(&repo, "zapier-delivery").await.unwrap();
+    let workspace_id = owner.workspace_id;
+    // The provisioned hook's target does not exist; replace it
+    assert!(
+        repo.delete_rest_hook(workspace_id, owner.rest_hook_id)
+            .await
+            .unwrap()
+    );
+    for (event, path) in [
+        (HookEvent::NewContact, "/contacts"),
+        (HookEvent::NewInteraction, "/interactions"),
+        (HookEvent::UpcomingOccasion, "/occasions"),
+    ] {
+        repo.create_rest_hook(workspace_id, &hook(event, &format!("{}{}", base, path)))
+            .await
+            .unwrap();
+    }
+    let gone = repo
+        .create_rest_hook(
+            owner.spare_workspace_id,
+            &hook(HookEvent::NewContact, &format!("{}/gone", base)),
+        )
+        .await
+        .unwrap();
+
+    let outbox = Outbox::default();
+    let hooks = UpcomingHooks;
+    let drain = || outbox.drain(&repo, OffsetDateTime::now_utc());
+    let ada = repo
+        .create_contact(workspace_id, &contact("Ada"))
+        .await
+        .unwrap();
+    assert_eq!(drain().await.unwrap(), 1);
+    let got = take();
+    assert_eq!(got.len(), 1);
+    assert_eq!(got[0].0, "/contacts");
+    assert_eq!(got[0].1[0]["id"], ada.to_string());
+    assert_eq!(got[0].1[0]["first_name"], "Ada");
+
+    // A contact in the spare workspace goes to its hook alone, which is gone
+    repo.create_contact(owner.spare_workspace_id, &contact("Bo"))
+        .await
+        .unwrap();
+    drain().await.unwrap();
+    let got = take();
+    assert_eq!(got.len(), 1);
+    assert_eq!(got[0].0, "/gone");
+    assert!(
+        !repo
+            .owns_rest_hook(owner.spare_workspace_id, gone)
+            .await
+            .unwrap()
+    );
+
+    repo.create_interaction(workspace_id, &interaction(ada, "Coffee"), None)
+        .await
+        .unwrap();
+    // Updates are not sent
+    repo.update_contact(workspace_id, ada, &contact("Ada L."))
+        .await
+        .unwrap();
+    drain().await.unwrap();
+    let got = take();
+    assert_eq!(got.len(), 1);
+    assert_eq!(got[0].0, "/interactions");
+    assert_eq!(got[0].1[0]["notes"], "Coffee");
+
+    // A new hook is sent what comes within reach today: not the provisioned occasion, which is
+    // today, nor the one in three days
+    let today = OffsetDateTime::now_utc().date();
+    let sooner = repo
+        .create_occasion(
+            workspace_id,
+            &occasion(ada, "Sooner", today + Duration::days(3)),
+        )
+        .await
+        .unwrap();
+    let soon = repo
+        .create_occasion(
+            workspace_id,
+            &occasion(ada, "Soon", today + Duration::days(7)),
+        )
+        .await
+        .unwrap();
+    repo.create_occasion(
+        workspace_id,
+        &occasion(ada, "Later", today + Duration::days(30)),
+    )
+    .await
+    .unwrap();
+    let occasion_ids = |got: &[(String, Value)]| -> Vec<String> {
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].0, "/occasions");
+        got[0]
+            .1
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|o| o["id"].as_str().unwrap().to_string())
+            .collect()
+    };
+    assert_eq!(hooks.queue_due(&repo).await.unwrap(), 1);
+    drain().await.unwrap();
+    assert_eq!(
+        occasion_ids(&take()),
+        vec![format!("{}-{}", soon, today + Duration::days(7))]
+    );
+    assert_eq!(hooks.queue_due(&repo).await.unwrap(), 0);
+    drain().await.unwrap();
+    assert!(take().is_empty());
+
+    // After days without a run, everything that came within reach meanwhile is sent
+    let upcoming_hook = repo
+        .rest_hooks_for_event(HookEvent::UpcomingOccasion, Some(owner.user_id))
+        .await
+        .unwrap()[0]
+        .hook_id;
+    repo.record_rest_hook_delivery(upcoming_hook, today - Duration::days(5))
+        .await
+        .unwrap();
+    assert_eq!(hooks.queue_due(&repo).await.unwrap(), 1);
+    drain().await.unwrap();
+    assert_eq!(
+        occasion_ids(&take()),
+        vec![
+            format!("{}-{}", soon, today + Duration::days(7)),
+            format!("{}-{}", sooner, today + Duration::days(3)),
+        ]
+    );
+}
+
+/// Test subscribing and unsubscribing hooks and polling the triggers through the API
+#[actix_rt::test]
+async fn test_zapier_api() {
+    let repo = repository::app_data(InMemoryRepository::new());
+    let owner = provision(repo.get_ref(), "zapier-api").await.unwrap();
+    let other = provision(repo.get_ref(), "zapier-api-other").await.unwrap();
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(repo.clone())
+            .service(web::scope("/v1").configure(v1_routes)),
+    )
+    .await;
+    let auth = ("Authorization", format!("Bearer {}", owner.token));
+    let get = |uri: &str| {
+        actix_test::TestRequest::get()
+            .uri(uri)
+            .insert_header(auth.clone())
+            .to_request()
+    };
+    let subscribe = |body: Value| {
+        actix_test::TestRequest::post()
+            .uri("/v1/hooks")
+            .insert_header(auth.clone())
+            .set_json(body)
+            .to_request()
+    };
+
+    let res = actix_test::call_service(
+        &app,
+        subscribe(json!({
+            "event": "upcoming_occasion",
+            "target_url": "https://hooks.zapier.com/hooks/standard/1/a/"
+        })),
+    )
+    .await;
+    assert_eq!(res.status(), 201);
+    let created: Value = actix_test::read_body_json(res).await;
+    assert_eq!(created["event"], "upcoming_occasion");
+    let hook_id = created["hook_id"].as_i64().unwrap();
+    for body in [
+        json!({ "event": "new_contact", "target_url": "ftp://hooks.example.com/" }),
+        json!({ "event": "new_contact", "target_url": "/relative" }),
+        json!({ "event": "new_contact", "target_url": "http://hooks.example.com/" }),
+        json!({ "event": "new_contact", "target_url": "https://localhost/hook" }),
+        json!({ "event": "new_contact", "target_url": "http://localhost:8080/hook" }),
+        json!({ "event": "new_contact", "target_url": "http://169.254.169.254/latest/meta-data" }),
+        json!({ "event": "new_contact", "target_url": "https://192.168.1.1/hook" }),
+        json!({ "event": "new_contact", "target_url": "https://metadata.google.internal/" }),
+    ] {
+        let res = actix_test::call_service(&app, subscribe(body.clone())).await;
+        assert_eq!(res.status(), 400, "{} was accepted", body);
+    }
+    let res = actix_test::call_service(
+        &app,
+        subscribe(json!({ "event": "deleted_contact", "target_url": "https://example.com/" })),
+    )
+    .await;
+    assert!(res.status().is_client_error());
+
+    let hooks: Vec<Value> =
+        actix_test::read_body_json(actix_test::call_service(&app, get("/v1/hooks")).await).await;
+    assert_eq!(hooks.len(), 2);
+    assert_eq!(hooks[1]["hook_id"], hook_id);
+    assert_eq!(hooks[1]["event"], "upcoming_occasion");
+    assert!(hooks[1].get("workspace_id").is_none());
+
+    let contacts: Vec<Value> = actix_test::read_body_json(
+        actix_test::call_service(&app, get("/v1/triggers/new-contact")).await,
+    )
+    .await;
+    assert_eq!(contacts.len(), 1);
+    assert_eq!(contacts[0]["id"], owner.contact_id.to_string());
+    assert_eq!(contacts[0]["contact_id"], owner.contact_id);
+    let interactions: Vec<Value> = actix_test::read_body_json(
+        actix_test::call_service(&app, get("/v1/triggers/new-interaction")).await,
+    )
+    .await;
+    assert_eq!(interactions.len(), 1);
+    assert_eq!(interactions[0]["id"], owner.interaction_id.to_string());
+    let occasions: Vec<Value> = actix_test::read_body_json(
+        actix_test::call_service(&app, get("/v1/triggers/upcoming-occasion")).await,
+    )
+    .await;
+    assert_eq!(occasions.len(), 1);
+    assert_eq!(occasions[0]["occasion_id"], owner.occasion_id);
+    assert_eq!(
+        occasions[0]["id"],
+        format!(
+            "{}-{}",
+            owner.occasion_id,
+            occasions[0]["occurs_on"].as_str().unwrap()
+        )
+    );
+
+    let unsubscribe = |id: i64, token: &str| {
+        actix_test::TestRequest::delete()
+            .uri(&format!("/v1/hooks/{}", id))
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request()
+    };
+    let res = actix_test::call_service(&app, unsubscribe(hook_id, &other.token)).await;
+    assert_eq!(res.status(), 404);
+    let res = actix_test::call_service(&app, unsubscribe(hook_id, &owner.token)).await;
+    assert_eq!(res.status(), 200);
+    let res = actix_test::call_service(&app, unsubscribe(hook_id, &owner.token)).await;
+    assert_eq!(res.status(), 404);
+}